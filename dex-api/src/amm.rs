@@ -0,0 +1,864 @@
+//! AMM pools and liquidity provider positions.
+//!
+//! Wraps dex-core's `ConstantProductAMM` with the per-provider bookkeeping the
+//! API reports: LP token balances, the amounts each provider deposited (to
+//! measure impermanent loss against simply holding them) and per-share fee
+//! accumulators, so a provider's cut of swap fees is known without replaying
+//! swaps. Values are quoted in each pool's quote token.
+//!
+//! Liquidity added within a tick range also updates the pool's ticks. Tick
+//! maps are stored as compressed blobs and reloaded on boot, but a reloaded
+//! map is only decoded when its pool's ticks are first used. The rest of a
+//! pool's state, reserves, fee accounting and positions, is stored as rows
+//! and reloaded on boot too.
+
+use crate::{
+    auth::{Claims, Scope},
+    authenticated, caching, error_reply,
+    rate_limit::RouteClass,
+    rate_limited,
+    usd_prices::UsdPrices,
+    validation, with_state, ApiState, ErrorResponse, ValidationRejection,
+};
+use dex_core::{
+    amm::{AMMError, ConstantProductAMM, Tick},
+    amm_router::{PoolSnapshot, PoolState},
+    reward_distribution::{RewardClaim, RewardDistributionManager},
+    types::{Quantity, TokenId, TraderId, TradingPair},
+};
+use dex_db::{tick_maps, PoolPosition, PoolRecord, TickMapBlob};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// Fixed-point scale of the fee-per-LP-token accumulators.
+const FEE_SCALE: u128 = 1_000_000_000_000;
+
+/// An amount of each side of a pool, as (base, quote).
+type Amounts = (u128, u128);
+
+/// One provider's stake in a pool.
+#[derive(Debug, Clone, Default)]
+struct Position {
+    lp_tokens: Quantity,
+    /// Deposits still in the pool, for impermanent loss.
+    deposited: Amounts,
+    /// Fee accumulators as of the last settlement.
+    fee_checkpoint: Amounts,
+    /// Settled fees on the liquidity still in the pool.
+    fees: Amounts,
+    /// Fees paid out with liquidity already withdrawn.
+    fees_withdrawn: Amounts,
+}
+
+impl Position {
+    fn record(&self, trader_id: &TraderId) -> PoolPosition {
+        PoolPosition {
+            trader_id: trader_id.clone(),
+            lp_tokens: self.lp_tokens,
+            deposited: self.deposited,
+            fee_checkpoint: self.fee_checkpoint,
+            fees: self.fees,
+            fees_withdrawn: self.fees_withdrawn,
+        }
+    }
+
+    fn from_record(record: &PoolPosition) -> Self {
+        Self {
+            lp_tokens: record.lp_tokens,
+            deposited: record.deposited,
+            fee_checkpoint: record.fee_checkpoint,
+            fees: record.fees,
+            fees_withdrawn: record.fees_withdrawn,
+        }
+    }
+
+    /// Credit fees accrued since the last checkpoint.
+    fn settle(&mut self, fee_growth: Amounts) {
+        let lp = u128::from(self.lp_tokens);
+        self.fees.0 += lp * (fee_growth.0 - self.fee_checkpoint.0) / FEE_SCALE;
+        self.fees.1 += lp * (fee_growth.1 - self.fee_checkpoint.1) / FEE_SCALE;
+        self.fee_checkpoint = fee_growth;
+    }
+}
+
+/// A constant product pool and its liquidity providers.
+#[derive(Debug, Clone)]
+pub struct Pool {
+    pair: TradingPair,
+    amm: ConstantProductAMM,
+    positions: HashMap<TraderId, Position>,
+    /// Swap fees earned per LP token, scaled by `FEE_SCALE`.
+    fee_growth: Amounts,
+    rewards: RewardDistributionManager,
+    /// Stored ticks not decoded yet; see [`Pool::ticks`].
+    stored_ticks: Option<TickMapBlob>,
+    /// Whether the ticks changed since they were last stored.
+    ticks_changed: bool,
+    /// Whether anything else stored changed since it was last stored.
+    changed: bool,
+}
+
+impl Pool {
+    pub fn new(pair: TradingPair, fee_bps: u32) -> Self {
+        Self {
+            pair,
+            amm: ConstantProductAMM::new(fee_bps),
+            positions: HashMap::new(),
+            fee_growth: (0, 0),
+            rewards: RewardDistributionManager::new(),
+            stored_ticks: None,
+            ticks_changed: false,
+            changed: false,
+        }
+    }
+
+    /// The stored state of the pool, taken at `sequence`.
+    fn record(&self, sequence: u64) -> PoolRecord {
+        let (reserve_base, reserve_quote) = self.reserves();
+        let mut positions: Vec<PoolPosition> = self
+            .positions
+            .iter()
+            .map(|(trader_id, position)| position.record(trader_id))
+            .collect();
+        positions.sort_unstable_by(|a, b| a.trader_id.cmp(&b.trader_id));
+        PoolRecord {
+            base_token: self.pair.base().clone(),
+            quote_token: self.pair.quote().clone(),
+            sequence,
+            fee_bps: self.amm.fee,
+            reserve_base: reserve_base as Quantity,
+            reserve_quote: reserve_quote as Quantity,
+            lp_supply: self.amm.total_supply,
+            fee_growth_base: self.fee_growth.0,
+            fee_growth_quote: self.fee_growth.1,
+            positions,
+        }
+    }
+
+    /// Take reserves, fee accounting and positions from `record`. Ticks and
+    /// rewards are left alone.
+    fn restore(&mut self, record: &PoolRecord) {
+        self.amm.fee = record.fee_bps;
+        self.amm.reserves = HashMap::from([
+            (self.pair.base().clone(), record.reserve_base),
+            (self.pair.quote().clone(), record.reserve_quote),
+        ]);
+        self.amm.total_supply = record.lp_supply;
+        self.fee_growth = (record.fee_growth_base, record.fee_growth_quote);
+        self.positions = record
+            .positions
+            .iter()
+            .map(|position| (position.trader_id.clone(), Position::from_record(position)))
+            .collect();
+    }
+
+    pub fn pair(&self) -> &TradingPair {
+        &self.pair
+    }
+
+    pub fn fee_bps(&self) -> u32 {
+        self.amm.fee
+    }
+
+    /// LP tokens outstanding.
+    pub fn lp_supply(&self) -> Quantity {
+        self.amm.total_supply
+    }
+
+    /// Current (base, quote) reserves.
+    pub fn reserves(&self) -> Amounts {
+        let reserve = |token: &TokenId| u128::from(*self.amm.reserves.get(token).unwrap_or(&0));
+        (reserve(self.pair.base()), reserve(self.pair.quote()))
+    }
+
+    /// Deposit both sides for `provider`; returns the LP tokens minted.
+    pub fn add_liquidity(
+        &mut self,
+        provider: &TraderId,
+        base_amount: Quantity,
+        quote_amount: Quantity,
+    ) -> Result<Quantity, AMMError> {
+        let minted = self.amm.add_liquidity(
+            self.pair.base().clone(),
+            base_amount,
+            self.pair.quote().clone(),
+            quote_amount,
+        )?;
+        self.credit(provider, minted, base_amount, quote_amount);
+        Ok(minted)
+    }
+
+    /// Deposit both sides for `provider`, concentrated between `tick_lower`
+    /// and `tick_upper`; returns the LP tokens minted.
+    pub fn add_liquidity_in_range(
+        &mut self,
+        provider: &TraderId,
+        base_amount: Quantity,
+        quote_amount: Quantity,
+        tick_lower: i32,
+        tick_upper: i32,
+    ) -> Result<Quantity, AMMError> {
+        if tick_lower >= tick_upper {
+            return Err(AMMError::PriceRangeNotFound);
+        }
+        self.hydrate_ticks();
+        let minted = self.amm.add_liquidity_concentrated(
+            self.pair.base().clone(),
+            self.pair.quote().clone(),
+            base_amount,
+            quote_amount,
+            tick_lower,
+            tick_upper,
+        )?;
+        self.ticks_changed = true;
+        self.credit(provider, minted, base_amount, quote_amount);
+        Ok(minted)
+    }
+
+    fn credit(
+        &mut self,
+        provider: &TraderId,
+        minted: Quantity,
+        base_amount: Quantity,
+        quote_amount: Quantity,
+    ) {
+        self.changed = true;
+        let position = self.positions.entry(provider.clone()).or_default();
+        position.settle(self.fee_growth);
+        position.lp_tokens += minted;
+        position.deposited.0 += u128::from(base_amount);
+        position.deposited.1 += u128::from(quote_amount);
+    }
+
+    /// The pool's ticks, decoding stored ones on first use.
+    pub fn ticks(&mut self) -> &HashMap<i32, Tick> {
+        self.hydrate_ticks();
+        &self.amm.ticks
+    }
+
+    /// Whether stored ticks are still waiting to be decoded.
+    pub fn has_stored_ticks(&self) -> bool {
+        self.stored_ticks.is_some()
+    }
+
+    fn hydrate_ticks(&mut self) {
+        let Some(blob) = self.stored_ticks.take() else {
+            return;
+        };
+        match tick_maps::decode(&blob) {
+            Ok(ticks) => self.amm.ticks.extend(ticks),
+            Err(err) => tracing::error!(
+                pair = %self.pair,
+                error = ?err,
+                "failed to decode stored ticks"
+            ),
+        }
+    }
+
+    /// Burn `lp_tokens` of `provider`'s stake; returns the (base, quote) paid out.
+    pub fn remove_liquidity(
+        &mut self,
+        provider: &TraderId,
+        lp_tokens: Quantity,
+    ) -> Result<(Quantity, Quantity), AMMError> {
+        let position = self
+            .positions
+            .get_mut(provider)
+            .filter(|position| position.lp_tokens >= lp_tokens && lp_tokens > 0)
+            .ok_or(AMMError::InsufficientLiquidity)?;
+        let paid = self.amm.remove_liquidity(
+            self.pair.base().clone(),
+            self.pair.quote().clone(),
+            lp_tokens,
+        )?;
+        position.settle(self.fee_growth);
+
+        // Withdrawn liquidity takes its share of deposits and fees with it.
+        let (burned, held) = (u128::from(lp_tokens), u128::from(position.lp_tokens));
+        let part = |amount: u128| amount * burned / held;
+        let fees_out = (part(position.fees.0), part(position.fees.1));
+        position.deposited.0 -= part(position.deposited.0);
+        position.deposited.1 -= part(position.deposited.1);
+        position.fees.0 -= fees_out.0;
+        position.fees.1 -= fees_out.1;
+        position.fees_withdrawn.0 += fees_out.0;
+        position.fees_withdrawn.1 += fees_out.1;
+        position.lp_tokens -= lp_tokens;
+        self.changed = true;
+        Ok(paid)
+    }
+
+    /// Swap `amount_in` of `token_in` for the other side; returns the amount out.
+    pub fn swap(&mut self, token_in: &TokenId, amount_in: Quantity) -> Result<Quantity, AMMError> {
+        let buying_quote = token_in == self.pair.base();
+        let token_out = if buying_quote {
+            self.pair.quote()
+        } else if token_in == self.pair.quote() {
+            self.pair.base()
+        } else {
+            return Err(AMMError::InvalidToken);
+        };
+        let amount_out = self
+            .amm
+            .swap(token_in.clone(), token_out.clone(), amount_in)?;
+        self.changed = true;
+
+        // The fee stays in the reserves; record each LP token's share of it.
+        let fee = u128::from(amount_in) * u128::from(self.amm.fee) / 10_000;
+        let growth = fee * FEE_SCALE / u128::from(self.amm.total_supply.max(1));
+        if buying_quote {
+            self.fee_growth.0 += growth;
+        } else {
+            self.fee_growth.1 += growth;
+        }
+        Ok(amount_out)
+    }
+
+    /// Queue a liquidity mining reward for a provider.
+    pub fn add_reward(&mut self, claim: RewardClaim) {
+        self.rewards.add_claim(claim);
+    }
+
+    /// Quote-token value of `amounts` at the current pool price.
+    fn value_of(&self, amounts: Amounts) -> u128 {
+        let (reserve_base, reserve_quote) = self.reserves();
+        let base_value = (amounts.0 * reserve_quote)
+            .checked_div(reserve_base)
+            .unwrap_or_default();
+        base_value + amounts.1
+    }
+
+    fn position_summary(&self, provider: &TraderId) -> Option<PositionSummary> {
+        let mut rewards = BTreeMap::new();
+        for claim in self.rewards.get_claims_for_provider(provider) {
+            *rewards.entry(claim.token_id.to_string()).or_default() += u128::from(claim.amount);
+        }
+        let mut position = self.positions.get(provider).cloned().unwrap_or_default();
+        if position.lp_tokens == 0 && rewards.is_empty() {
+            return None;
+        }
+        position.settle(self.fee_growth);
+
+        let (reserve_base, reserve_quote) = self.reserves();
+        let (lp, total) = (
+            u128::from(position.lp_tokens),
+            u128::from(self.amm.total_supply),
+        );
+        let share = |reserve: u128| (reserve * lp).checked_div(total).unwrap_or_default();
+        let amounts = (share(reserve_base), share(reserve_quote));
+        let value = self.value_of(amounts);
+
+        // Impermanent loss compares the stake, less the fees it earned, with
+        // holding the deposited tokens.
+        let open_fees_value = self.value_of(position.fees);
+        let held_value = self.value_of(position.deposited);
+        let impermanent_loss = held_value.saturating_sub(value.saturating_sub(open_fees_value));
+        let fees = (
+            position.fees.0 + position.fees_withdrawn.0,
+            position.fees.1 + position.fees_withdrawn.1,
+        );
+
+        Some(PositionSummary {
+            pool: self.pair.to_string(),
+            quote_token: self.pair.quote().to_string(),
+            lp_tokens: position.lp_tokens,
+            share_bps: (lp * 10_000).checked_div(total).unwrap_or_default(),
+            base_amount: amounts.0,
+            quote_amount: amounts.1,
+            value,
+            fees_earned: FeesEarned {
+                base: fees.0,
+                quote: fees.1,
+                value: self.value_of(fees),
+            },
+            rewards,
+            impermanent_loss,
+            impermanent_loss_bps: (impermanent_loss * 10_000)
+                .checked_div(held_value)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+/// Swap fees credited to a position, including those already withdrawn.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FeesEarned {
+    pub base: u128,
+    pub quote: u128,
+    /// In the pool's quote token, at the current price.
+    pub value: u128,
+}
+
+/// A provider's stake in one pool, valued at the current pool price.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PositionSummary {
+    pub pool: String,
+    pub quote_token: String,
+    #[schema(value_type = u64)]
+    pub lp_tokens: Quantity,
+    /// Share of the pool's LP supply in basis points.
+    pub share_bps: u128,
+    pub base_amount: u128,
+    pub quote_amount: u128,
+    pub value: u128,
+    pub fees_earned: FeesEarned,
+    /// Pending rewards by token.
+    pub rewards: BTreeMap<String, u128>,
+    /// Shortfall against holding the deposited tokens, in the quote token.
+    pub impermanent_loss: u128,
+    pub impermanent_loss_bps: u128,
+}
+
+/// Totals over the positions valued in one quote token.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct SummaryTotals {
+    pub value: u128,
+    pub fees_value: u128,
+    pub impermanent_loss: u128,
+    /// `value` in USD, when the quote token has a USD quote.
+    pub value_usd: Option<f64>,
+}
+
+/// Everything the liquidity provider dashboard shows, in one response.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct ProviderSummary {
+    pub trader_id: String,
+    pub positions: Vec<PositionSummary>,
+    /// Pools are valued in their own quote token, so totals are per quote token.
+    pub totals: BTreeMap<String, SummaryTotals>,
+    /// Pending rewards by token across all pools.
+    pub rewards: BTreeMap<String, u128>,
+    /// All positions in USD; absent when a quote token has no USD quote.
+    pub total_value_usd: Option<f64>,
+}
+
+impl ProviderSummary {
+    /// Fill in the USD values from the synthetic quotes.
+    pub fn value_in_usd(&mut self, prices: &UsdPrices) {
+        let mut total = Some(0.0);
+        for (token, totals) in &mut self.totals {
+            totals.value_usd = prices.value(token, totals.value);
+            total = total.zip(totals.value_usd).map(|(sum, value)| sum + value);
+        }
+        self.total_value_usd = total;
+    }
+}
+
+/// All AMM pools, keyed by `BASE-QUOTE`.
+#[derive(Debug, Default)]
+pub struct AmmPools {
+    pools: BTreeMap<String, Pool>,
+    /// Advanced before every attempted change to any pool, so clients
+    /// quoting from a `PoolSnapshot` can tell when it is stale. A failed
+    /// change may advance it too, which costs a client a needless refetch but
+    /// never hides a change.
+    sequence: u64,
+    /// Tick maps restored for pools that do not exist yet, keyed like `pools`.
+    stored_ticks: HashMap<String, TickMapBlob>,
+}
+
+impl AmmPools {
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Advance the sequence ahead of a change; returns the new value.
+    pub fn advance(&mut self) -> u64 {
+        self.sequence += 1;
+        self.sequence
+    }
+
+    /// Reserves and fee of every pool, tagged with the current sequence.
+    pub fn snapshot(&self) -> PoolSnapshot {
+        PoolSnapshot {
+            sequence: self.sequence,
+            pools: self
+                .pools
+                .values()
+                .map(|pool| {
+                    let (reserve_base, reserve_quote) = pool.reserves();
+                    PoolState {
+                        pair: pool.pair().clone(),
+                        reserve_base,
+                        reserve_quote,
+                        fee_bps: pool.fee_bps(),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// The pool for `pair`, created with `fee_bps` if it does not exist yet.
+    pub fn get_or_create(&mut self, pair: TradingPair, fee_bps: u32) -> &mut Pool {
+        let key = pair.to_string();
+        let stored_ticks = &mut self.stored_ticks;
+        self.pools.entry(key).or_insert_with_key(|key| {
+            let mut pool = Pool::new(pair, fee_bps);
+            pool.stored_ticks = stored_ticks.remove(key);
+            pool
+        })
+    }
+
+    /// Hand stored tick maps to their pools, to be decoded on first use.
+    /// The sequence moves past every stored map's, so maps taken from now on
+    /// replace them.
+    pub fn restore_tick_maps(&mut self, maps: Vec<TickMapBlob>) {
+        for map in maps {
+            let Ok(pair) = TradingPair::new(map.base_token.clone(), map.quote_token.clone()) else {
+                continue;
+            };
+            self.sequence = self.sequence.max(map.sequence);
+            match self.pools.get_mut(&pair.to_string()) {
+                // Ticks this process already changed are newer.
+                Some(pool) if pool.ticks_changed => {}
+                Some(pool) => pool.stored_ticks = Some(map),
+                None => {
+                    self.stored_ticks.insert(pair.to_string(), map);
+                }
+            }
+        }
+    }
+
+    /// Put stored pools back. The sequence moves past every stored pool's,
+    /// so pools taken from now on replace them.
+    pub fn restore_pools(&mut self, records: Vec<PoolRecord>) {
+        for record in records {
+            let Ok(pair) = TradingPair::new(record.base_token.clone(), record.quote_token.clone())
+            else {
+                continue;
+            };
+            self.sequence = self.sequence.max(record.sequence);
+            let pool = self.get_or_create(pair, record.fee_bps);
+            // A pool this process already changed is newer.
+            if !pool.changed {
+                pool.restore(&record);
+            }
+        }
+    }
+
+    /// State of the pools that changed since the last call, taken at the
+    /// current sequence.
+    pub fn take_changed_pools(&mut self) -> Vec<PoolRecord> {
+        let sequence = self.sequence;
+        self.pools
+            .values_mut()
+            .filter(|pool| pool.changed)
+            .map(|pool| {
+                pool.changed = false;
+                pool.record(sequence)
+            })
+            .collect()
+    }
+
+    /// Mark pools as changed again after they failed to store.
+    pub fn mark_pools_changed(&mut self, records: &[PoolRecord]) {
+        for record in records {
+            let Ok(pair) = TradingPair::new(record.base_token.clone(), record.quote_token.clone())
+            else {
+                continue;
+            };
+            if let Some(pool) = self.get_mut(&pair) {
+                pool.changed = true;
+            }
+        }
+    }
+
+    /// Tick maps of the pools whose ticks changed since the last call,
+    /// taken at the current sequence.
+    pub fn take_changed_tick_maps(&mut self) -> Vec<TickMapBlob> {
+        let sequence = self.sequence;
+        self.pools
+            .values_mut()
+            .filter(|pool| pool.ticks_changed)
+            .map(|pool| {
+                pool.ticks_changed = false;
+                tick_maps::encode(
+                    pool.pair.base(),
+                    pool.pair.quote(),
+                    sequence,
+                    &pool.amm.ticks,
+                )
+            })
+            .collect()
+    }
+
+    /// Mark pools as changed again after their tick maps failed to store.
+    pub fn mark_tick_maps_changed(&mut self, maps: &[TickMapBlob]) {
+        for map in maps {
+            let Ok(pair) = TradingPair::new(map.base_token.clone(), map.quote_token.clone()) else {
+                continue;
+            };
+            if let Some(pool) = self.get_mut(&pair) {
+                pool.ticks_changed = true;
+            }
+        }
+    }
+
+    pub fn get(&self, pair: &TradingPair) -> Option<&Pool> {
+        self.pools.get(&pair.to_string())
+    }
+
+    pub fn get_mut(&mut self, pair: &TradingPair) -> Option<&mut Pool> {
+        self.pools.get_mut(&pair.to_string())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Pool> {
+        self.pools.values()
+    }
+
+    /// Aggregate `provider`'s positions and rewards across every pool.
+    pub fn provider_summary(&self, provider: &TraderId) -> ProviderSummary {
+        let positions: Vec<_> = self
+            .pools
+            .values()
+            .filter_map(|pool| pool.position_summary(provider))
+            .collect();
+        let mut totals = BTreeMap::<String, SummaryTotals>::new();
+        let mut rewards = BTreeMap::<String, u128>::new();
+        for position in &positions {
+            let total = totals.entry(position.quote_token.clone()).or_default();
+            total.value += position.value;
+            total.fees_value += position.fees_earned.value;
+            total.impermanent_loss += position.impermanent_loss;
+            for (token, amount) in &position.rewards {
+                *rewards.entry(token.clone()).or_default() += amount;
+            }
+        }
+        ProviderSummary {
+            trader_id: provider.to_string(),
+            positions,
+            totals,
+            rewards,
+            total_value_usd: None,
+        }
+    }
+}
+
+/// Pool reserves and liquidity provider summaries, e.g. GET /amm/pools
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Reserves of every pool, for quoting routes client-side
+    let get_pool_snapshot = warp::path("amm")
+        .and(warp::path("pools"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and(caching::conditional())
+        .and_then(handle_get_pool_snapshot)
+        .boxed();
+
+    // Liquidity provider dashboard, e.g. /amm/providers/alice/summary
+    let get_provider_summary = warp::path("amm")
+        .and(warp::path("providers"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("summary"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_provider_summary)
+        .boxed();
+
+    get_pool_snapshot.or(get_provider_summary)
+}
+
+/// Handler for the pool snapshot. Its `sequence` advances with every change
+/// to any pool, so the ETag changes with it and an unchanged snapshot
+/// revalidates as `304`.
+#[utoipa::path(
+    get,
+    path = "/amm/pools",
+    summary = "Reserves and fees of every AMM pool, for quoting routes client-side",
+    responses(
+        (
+            status = 200,
+            description = "Every pool, tagged with the pool sequence; pool stream updates carry the same sequence, so a client knows when its snapshot is stale",
+            body = PoolSnapshot,
+        ),
+    ),
+)]
+async fn handle_get_pool_snapshot(
+    state: ApiState,
+    conditional: caching::Conditional,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let snapshot = state.amm.read().await.snapshot();
+    Ok(caching::cached_json(
+        &snapshot,
+        None,
+        caching::MARKET_DATA_MAX_AGE,
+        &conditional,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/amm/providers/{trader_id}/summary",
+    summary = "Liquidity provider positions across all pools",
+    params(("trader_id" = String, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Positions, fees, rewards and impermanent loss", body = ProviderSummary),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "trader_id does not match the token subject", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_provider_summary(
+    trader_id: String,
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != trader_id {
+        return Ok(error_reply(
+            "forbidden",
+            "requested trader does not match authenticated subject",
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    let trader_id = validation::normalize_trader_id(&trader_id)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let mut summary = state.amm.read().await.provider_summary(&trader_id);
+    summary.value_in_usd(&*state.usd_prices.read().await);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&summary),
+        StatusCode::OK,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trader(id: &str) -> TraderId {
+        TraderId::parse(id).unwrap()
+    }
+
+    fn token(id: &str) -> TokenId {
+        TokenId::parse(id).unwrap()
+    }
+
+    fn reward(provider: &str, token_id: &str, amount: Quantity) -> RewardClaim {
+        RewardClaim {
+            priority: 1,
+            provider_id: trader(provider),
+            token_id: token(token_id),
+            amount,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn splits_fees_and_measures_impermanent_loss() {
+        let mut pools = AmmPools::default();
+        let pool = pools.get_or_create("ETH-USDC".parse().unwrap(), 30);
+        pool.add_liquidity(&trader("alice"), 10_000, 10_000)
+            .unwrap();
+        pool.add_liquidity(&trader("bob"), 10_000, 10_000).unwrap();
+        assert_eq!(pool.swap(&token("ETH"), 10_000), Ok(6_653));
+        assert_eq!(pool.reserves(), (30_000, 13_347));
+        pool.add_reward(reward("alice", "DEX", 40));
+        pool.add_reward(reward("alice", "DEX", 2));
+
+        let summary = pools.provider_summary(&trader("alice"));
+        let position = &summary.positions[0];
+        assert_eq!(position.pool, "ETH-USDC");
+        assert_eq!(position.share_bps, 5_000);
+        assert_eq!(
+            (position.base_amount, position.quote_amount),
+            (15_000, 6_673)
+        );
+        assert_eq!(position.value, 13_346);
+        assert_eq!(
+            position.fees_earned,
+            FeesEarned {
+                base: 15,
+                quote: 0,
+                value: 6,
+            }
+        );
+        // Holding would be worth 4_449 + 10_000; the stake less fees is 13_340.
+        assert_eq!(position.impermanent_loss, 1_109);
+        assert_eq!(position.impermanent_loss_bps, 767);
+        assert_eq!(summary.rewards["DEX"], 42);
+        assert_eq!(summary.totals["USDC"].value, 13_346);
+    }
+
+    #[test]
+    fn withdrawals_keep_earned_fees() {
+        let mut pools = AmmPools::default();
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let pool = pools.get_or_create(pair.clone(), 30);
+        pool.add_liquidity(&trader("alice"), 10_000, 10_000)
+            .unwrap();
+        pool.swap(&token("USDC"), 1_000).unwrap();
+        pool.swap(&token("ETH"), 1_000).unwrap();
+
+        let pool = pools.get_mut(&pair).unwrap();
+        assert_eq!(
+            pool.remove_liquidity(&trader("alice"), 20_000),
+            Err(AMMError::InsufficientLiquidity)
+        );
+        pool.remove_liquidity(&trader("alice"), 5_000).unwrap();
+        let position = &pools.provider_summary(&trader("alice")).positions[0];
+        assert_eq!(position.lp_tokens, 5_000);
+        assert_eq!(
+            (position.fees_earned.base, position.fees_earned.quote),
+            (3, 3)
+        );
+
+        pools
+            .get_mut(&pair)
+            .unwrap()
+            .remove_liquidity(&trader("alice"), 5_000)
+            .unwrap();
+        assert!(pools
+            .provider_summary(&trader("alice"))
+            .positions
+            .is_empty());
+        assert!(pools.provider_summary(&trader("bob")).positions.is_empty());
+    }
+
+    #[test]
+    fn stored_ticks_are_decoded_on_first_use() {
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let mut pools = AmmPools::default();
+        pools.advance();
+        pools
+            .get_or_create(pair.clone(), 30)
+            .add_liquidity_in_range(&trader("alice"), 1_000, 1_000, -100, 100)
+            .unwrap();
+        let maps = pools.take_changed_tick_maps();
+        assert_eq!(
+            (maps.len(), maps[0].tick_count, maps[0].sequence),
+            (1, 201, 1)
+        );
+        assert!(pools.take_changed_tick_maps().is_empty());
+
+        let mut restarted = AmmPools::default();
+        restarted.restore_tick_maps(maps);
+        assert_eq!(restarted.sequence(), 1);
+        let pool = restarted.get_or_create(pair, 30);
+        assert!(pool.has_stored_ticks());
+        let ticks = pool.ticks();
+        assert_eq!(ticks.len(), 201);
+        assert_eq!(
+            (ticks[&0].liquidity, ticks[&-100].liquidity_net),
+            (1_000, 1_000)
+        );
+        assert!(!pool.has_stored_ticks());
+
+        assert_eq!(
+            pool.add_liquidity_in_range(&trader("bob"), 10, 10, 5, 5),
+            Err(AMMError::PriceRangeNotFound)
+        );
+        pool.add_liquidity_in_range(&trader("bob"), 500, 500, 0, 50)
+            .unwrap();
+        assert_eq!(pool.ticks()[&0].liquidity, 1_500);
+        restarted.advance();
+        let maps = restarted.take_changed_tick_maps();
+        assert_eq!((maps[0].tick_count, maps[0].sequence), (201, 2));
+    }
+}
@@ -0,0 +1,380 @@
+//! Real-time AMM events for the `/ws` stream.
+//!
+//! Swaps and liquidity changes go through the functions here, which update
+//! the pool and publish on `ApiState::amm_tx`: a [`SwapExecution`] for every
+//! swap, on `swaps:<PAIR>`, and a fresh [`PoolDepth`] whenever reserves
+//! move, on `pool:<PAIR>`. Pool depth lists the virtual liquidity of the
+//! constant product curve as bid and ask levels at fixed distances from the
+//! pool price, so aggregators can merge it with the order book's depth.
+//!
+//! Swaps also go on the consolidated trade tape, stored and published on
+//! `trades:<PAIR>` with the `amm` venue, so tickers and candles include them.
+//! Swaps and liquidity changes are booked in the ledger against the pool's
+//! account, which therefore tracks its reserves. Booking happens under the
+//! pool lock, and a change that cannot be booked is undone.
+
+use crate::{
+    amm::Pool,
+    ledger,
+    trade_tape::{MarketTrade, TradeEventKind},
+    ApiState,
+};
+use dex_core::{
+    amm::AMMError,
+    ledger::{Account, EntryKind, JournalEntry},
+    types::{OrderSide, Quantity, TokenId, TraderId, TradingPair},
+};
+use dex_db::{DatabaseError, SwapRecord};
+use serde::Serialize;
+use std::sync::atomic::Ordering;
+
+/// Why a swap or liquidity change did not happen.
+#[derive(Debug)]
+pub enum PoolChangeError {
+    Amm(AMMError),
+    /// Booking the change failed, so the pool was left as it was.
+    Storage(DatabaseError),
+}
+
+impl From<AMMError> for PoolChangeError {
+    fn from(err: AMMError) -> Self {
+        PoolChangeError::Amm(err)
+    }
+}
+
+impl From<DatabaseError> for PoolChangeError {
+    fn from(err: DatabaseError) -> Self {
+        PoolChangeError::Storage(err)
+    }
+}
+
+/// Distances from the pool price, in basis points, depth is reported at.
+pub const DEPTH_BANDS_BPS: [u32; 4] = [50, 100, 200, 500];
+
+/// A swap through a pool.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SwapExecution {
+    pub pool: String,
+    pub token_in: String,
+    pub amount_in: Quantity,
+    pub token_out: String,
+    pub amount_out: Quantity,
+    /// Fee kept by the pool, in `token_in`.
+    pub fee: Quantity,
+    /// Quote per base the swap executed at.
+    pub price: f64,
+    pub timestamp: u64,
+}
+
+/// Base liquidity between one band and the previous, closer one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VirtualLevel {
+    pub price: f64,
+    pub quantity: Quantity,
+}
+
+/// Reserves and virtual depth of a pool.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PoolDepth {
+    pub pool: String,
+    pub reserve_base: u128,
+    pub reserve_quote: u128,
+    /// Quote per base; absent while the pool is empty.
+    pub price: Option<f64>,
+    pub fee_bps: u32,
+    pub lp_supply: Quantity,
+    /// Pool sequence after the change this depth reflects; see
+    /// `GET /amm/pools`.
+    pub sequence: u64,
+    /// Base the pool buys before its price falls to each band, before fees.
+    pub bids: Vec<VirtualLevel>,
+    /// Base the pool sells before its price rises to each band.
+    pub asks: Vec<VirtualLevel>,
+    pub timestamp: u64,
+}
+
+impl PoolDepth {
+    pub fn of(pool: &Pool, sequence: u64, timestamp: u64) -> Self {
+        let (reserve_base, reserve_quote) = pool.reserves();
+        let price = (reserve_base > 0).then(|| reserve_quote as f64 / reserve_base as f64);
+        let base = reserve_base as f64;
+        // On x * y = k the base reserve scales with 1 / sqrt(price).
+        let levels = |moved: fn(f64) -> f64, side: f64| {
+            let mut previous = 0.0;
+            DEPTH_BANDS_BPS
+                .iter()
+                .filter_map(|&bps| {
+                    let band = f64::from(bps) / 10_000.0;
+                    let cumulative = base * moved(band);
+                    let quantity = (cumulative - previous).max(0.0) as Quantity;
+                    previous = cumulative;
+                    price.map(|price| VirtualLevel {
+                        price: price * (1.0 + side * band),
+                        quantity,
+                    })
+                })
+                .collect()
+        };
+        Self {
+            pool: pool.pair().to_string(),
+            reserve_base,
+            reserve_quote,
+            price,
+            fee_bps: pool.fee_bps(),
+            lp_supply: pool.lp_supply(),
+            sequence,
+            bids: levels(|band| 1.0 / (1.0 - band).sqrt() - 1.0, -1.0),
+            asks: levels(|band| 1.0 - 1.0 / (1.0 + band).sqrt(), 1.0),
+            timestamp,
+        }
+    }
+}
+
+/// What the AMM publishes to stream sessions.
+#[derive(Debug, Clone)]
+pub enum AmmEvent {
+    Swap(SwapExecution),
+    Depth(PoolDepth),
+}
+
+/// The tape entry for a swap: base and quote bought or sold, and the price
+/// as a whole number of quote per base.
+fn swap_record(pair: &TradingPair, execution: &SwapExecution) -> SwapRecord {
+    let (taker_side, quantity, quote_quantity) = if execution.token_in == pair.base().as_str() {
+        (OrderSide::Sell, execution.amount_in, execution.amount_out)
+    } else {
+        (OrderSide::Buy, execution.amount_out, execution.amount_in)
+    };
+    SwapRecord {
+        id: 0,
+        base_token: pair.base().clone(),
+        quote_token: pair.quote().clone(),
+        taker_side,
+        price: execution.price.round() as u64,
+        quantity,
+        quote_quantity,
+        timestamp: execution.timestamp,
+    }
+}
+
+/// Swap `amount_in` of `token_in` through `pair`'s pool for `trader`;
+/// returns the amount out.
+pub async fn swap(
+    state: &ApiState,
+    trader: &TraderId,
+    pair: &TradingPair,
+    token_in: &TokenId,
+    amount_in: Quantity,
+) -> Result<Quantity, PoolChangeError> {
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let (execution, depth, swap) = {
+        let mut pools = state.amm.write().await;
+        let sequence = pools.advance();
+        let pool = pools.get_mut(pair).ok_or(AMMError::InsufficientLiquidity)?;
+        let before = pool.clone();
+        let amount_out = pool.swap(token_in, amount_in)?;
+        let (token_out, price) = if token_in == pair.base() {
+            (pair.quote(), amount_out as f64 / amount_in as f64)
+        } else {
+            (pair.base(), amount_in as f64 / amount_out.max(1) as f64)
+        };
+        let fee = u128::from(amount_in) * u128::from(pool.fee_bps()) / 10_000;
+        let execution = SwapExecution {
+            pool: pair.to_string(),
+            token_in: token_in.to_string(),
+            amount_in,
+            token_out: token_out.to_string(),
+            amount_out,
+            fee: fee as Quantity,
+            price,
+            timestamp,
+        };
+        let swap = SwapRecord {
+            id: state.swap_id_counter.fetch_add(1, Ordering::Relaxed),
+            ..swap_record(pair, &execution)
+        };
+        // The fee stays in the reserves, so the pool takes the whole amount in.
+        let (trader, pool_account) = (
+            Account::Trader(trader.to_string()),
+            Account::Pool(pair.clone()),
+        );
+        let entry = JournalEntry::new(EntryKind::Swap, format!("swap:{}", swap.id), timestamp)
+            .transfer(
+                trader.clone(),
+                pool_account.clone(),
+                token_in,
+                u128::from(amount_in),
+            )
+            .transfer(pool_account, trader, token_out, u128::from(amount_out));
+        if let Err(err) = ledger::book(state, entry).await {
+            *pool = before;
+            return Err(err.into());
+        }
+        (execution, PoolDepth::of(pool, sequence, timestamp), swap)
+    };
+    let amount_out = execution.amount_out;
+
+    // The swap has happened, so a failed write is only logged.
+    if let Err(err) = state.swap_repo.save_swap(&swap).await {
+        tracing::error!(swap_id = swap.id, error = ?err, "failed to persist swap");
+    }
+    let public = state.trade_tape.write().await.record_swap(&swap);
+    let _ = state.trade_tx.send(MarketTrade {
+        kind: TradeEventKind::Trade,
+        pair: pair.to_string(),
+        trade: public,
+    });
+    let _ = state.amm_tx.send(AmmEvent::Swap(execution));
+    let _ = state.amm_tx.send(AmmEvent::Depth(depth));
+    Ok(amount_out)
+}
+
+/// Deposit both sides of `pair` for `provider`, creating the pool with
+/// `fee_bps` if needed; returns the LP tokens minted.
+pub async fn add_liquidity(
+    state: &ApiState,
+    pair: &TradingPair,
+    fee_bps: u32,
+    provider: &TraderId,
+    base_amount: Quantity,
+    quote_amount: Quantity,
+) -> Result<Quantity, PoolChangeError> {
+    deposit(
+        state,
+        pair,
+        fee_bps,
+        provider,
+        (base_amount, quote_amount),
+        None,
+    )
+    .await
+}
+
+/// Like [`add_liquidity`], but concentrated between the ticks of `range`,
+/// lower first.
+pub async fn add_liquidity_in_range(
+    state: &ApiState,
+    pair: &TradingPair,
+    fee_bps: u32,
+    provider: &TraderId,
+    base_amount: Quantity,
+    quote_amount: Quantity,
+    range: (i32, i32),
+) -> Result<Quantity, PoolChangeError> {
+    let amounts = (base_amount, quote_amount);
+    deposit(state, pair, fee_bps, provider, amounts, Some(range)).await
+}
+
+async fn deposit(
+    state: &ApiState,
+    pair: &TradingPair,
+    fee_bps: u32,
+    provider: &TraderId,
+    (base_amount, quote_amount): (Quantity, Quantity),
+    range: Option<(i32, i32)>,
+) -> Result<Quantity, PoolChangeError> {
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let (minted, depth) = {
+        let mut pools = state.amm.write().await;
+        let sequence = pools.advance();
+        let pool = pools.get_or_create(pair.clone(), fee_bps);
+        let before = pool.clone();
+        let minted = match range {
+            Some((lower, upper)) => {
+                pool.add_liquidity_in_range(provider, base_amount, quote_amount, lower, upper)?
+            }
+            None => pool.add_liquidity(provider, base_amount, quote_amount)?,
+        };
+        let depth = PoolDepth::of(pool, sequence, timestamp);
+        let deposit = liquidity_entry(pair, provider, (base_amount, quote_amount), true, &depth);
+        if let Err(err) = ledger::book(state, deposit).await {
+            *pool = before;
+            return Err(err.into());
+        }
+        (minted, depth)
+    };
+    let _ = state.amm_tx.send(AmmEvent::Depth(depth));
+    Ok(minted)
+}
+
+/// Burn `lp_tokens` of `provider`'s stake in `pair`'s pool; returns the
+/// (base, quote) paid out.
+pub async fn remove_liquidity(
+    state: &ApiState,
+    pair: &TradingPair,
+    provider: &TraderId,
+    lp_tokens: Quantity,
+) -> Result<(Quantity, Quantity), PoolChangeError> {
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let (paid, depth) = {
+        let mut pools = state.amm.write().await;
+        let sequence = pools.advance();
+        let pool = pools.get_mut(pair).ok_or(AMMError::InsufficientLiquidity)?;
+        let before = pool.clone();
+        let paid = pool.remove_liquidity(provider, lp_tokens)?;
+        let depth = PoolDepth::of(pool, sequence, timestamp);
+        let withdrawal = liquidity_entry(pair, provider, paid, false, &depth);
+        if let Err(err) = ledger::book(state, withdrawal).await {
+            *pool = before;
+            return Err(err.into());
+        }
+        (paid, depth)
+    };
+    let _ = state.amm_tx.send(AmmEvent::Depth(depth));
+    Ok(paid)
+}
+
+/// The entry for a deposit into, or a withdrawal from, `pair`'s pool, for
+/// the change `depth` reflects.
+fn liquidity_entry(
+    pair: &TradingPair,
+    provider: &TraderId,
+    (base, quote): (Quantity, Quantity),
+    deposit: bool,
+    depth: &PoolDepth,
+) -> JournalEntry {
+    let (provider, pool) = (
+        Account::Trader(provider.to_string()),
+        Account::Pool(pair.clone()),
+    );
+    let (from, to) = if deposit {
+        (provider, pool)
+    } else {
+        (pool, provider)
+    };
+    // Pools live in memory and their sequence restarts with the server, so
+    // the time keeps references unique across restarts.
+    JournalEntry::new(
+        EntryKind::Liquidity,
+        format!("liquidity:{}:{}", depth.timestamp, depth.sequence),
+        depth.timestamp,
+    )
+    .transfer(from.clone(), to.clone(), pair.base(), u128::from(base))
+    .transfer(from, to, pair.quote(), u128::from(quote))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depth_follows_the_constant_product_curve() {
+        let mut pool = Pool::new("ETH-USDC".parse().unwrap(), 30);
+        let empty = PoolDepth::of(&pool, 0, 0);
+        assert_eq!(empty.price, None);
+        assert!(empty.bids.is_empty() && empty.asks.is_empty());
+
+        pool.add_liquidity(&"alice".parse().unwrap(), 1_000_000, 2_000_000_000)
+            .unwrap();
+        let depth = PoolDepth::of(&pool, 1, 7);
+        assert_eq!(depth.price, Some(2_000.0));
+        assert_eq!(depth.bids.len(), DEPTH_BANDS_BPS.len());
+        assert!((depth.bids[0].price - 1_990.0).abs() < 1e-9);
+        assert!((depth.asks[3].price - 2_100.0).abs() < 1e-9);
+        // Selling 2_509 ETH into 1_000_000 moves the price down 0.5%.
+        assert_eq!(depth.bids[0].quantity, 2_509);
+        assert_eq!(depth.asks[0].quantity, 2_490);
+        let to_one_percent: Quantity = depth.bids[..2].iter().map(|level| level.quantity).sum();
+        assert!((5_037..=5_038).contains(&to_one_percent));
+    }
+}
@@ -0,0 +1,49 @@
+//! Storing the state of the AMM pools.
+//!
+//! Pools that changed are stored every flush interval, reserves, fee
+//! accounting and liquidity positions in `pools` and `pool_positions`, and
+//! put back on boot, so liquidity survives a restart. Ticks are stored
+//! separately; see [`crate::tick_maps`]. Changes since the last flush are
+//! lost if the process dies, though the ledger still has them.
+
+use crate::ApiState;
+use dex_db::DatabaseError;
+use std::time::Duration;
+
+/// Put the stored pools back; returns how many there were.
+pub async fn restore(state: &ApiState) -> Result<usize, DatabaseError> {
+    let pools = state.pool_repo.load_pools().await?;
+    let count = pools.len();
+    state.amm.write().await.restore_pools(pools);
+    Ok(count)
+}
+
+/// Store the pools that changed since the last flush.
+pub async fn flush(state: &ApiState) -> Result<(), DatabaseError> {
+    let changed = state.amm.write().await.take_changed_pools();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    // A stored pool is only replaced by one taken at a later sequence, so a
+    // flush that lands after a newer one is harmless.
+    if let Err(err) = state.pool_repo.save_pools(&changed).await {
+        state.amm.write().await.mark_pools_changed(&changed);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Flush pools every `interval`.
+pub fn spawn_flush(state: ApiState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; nothing has changed yet.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = flush(&state).await {
+                tracing::error!(error = ?err, "failed to store AMM pools");
+            }
+        }
+    });
+}
@@ -0,0 +1,726 @@
+//! Long-lived API keys that sign each request with HMAC-SHA256.
+//!
+//! Server-side bots authenticate without a JWT by sending four headers:
+//! `X-API-Key` (the key ID), `X-API-Timestamp` (Unix seconds),
+//! `X-API-Content-SHA256` (hex SHA-256 of the body, or of the empty string
+//! without one) and `X-API-Signature`, the hex HMAC-SHA256 of
+//! [`string_to_sign`] keyed with the key's secret. Requests whose timestamp
+//! is further than `API_KEY_REPLAY_WINDOW_SECONDS` from the server clock are
+//! rejected, and each signature is accepted only once within that window.
+//! Keys bound to an allowlist only accept requests from addresses in it.
+//!
+//! Secrets are stored sealed (see [`crate::sealing`]) under
+//! `API_KEY_ENCRYPTION_KEY`, bound to their key ID; without that key no API
+//! keys can be created.
+
+use crate::{
+    auth::{format_scopes, parse_scopes, Claims, Scope, ScopeRejection},
+    authenticated,
+    determinism::{self, Determinism},
+    error_reply,
+    ip_allowlist::{self, Allowlist},
+    rate_limit::RouteClass,
+    rate_limited, role_of,
+    sessions::requested_scopes,
+    storage_error_reply, validation, ApiState, ErrorResponse, ValidationRejection,
+};
+use dex_db::{ApiKeyRecord, DatabaseError};
+use ethers_core::utils::hex;
+use ring::{digest, hmac};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, net::IpAddr, sync::Mutex};
+use thiserror::Error;
+use utoipa::ToSchema;
+use warp::{
+    http::{Method, StatusCode},
+    hyper::body::Bytes,
+    path::FullPath,
+    reject::{self, Reject},
+    Filter,
+};
+
+pub const KEY_HEADER: &str = "x-api-key";
+pub const TIMESTAMP_HEADER: &str = "x-api-timestamp";
+pub const CONTENT_HEADER: &str = "x-api-content-sha256";
+pub const SIGNATURE_HEADER: &str = "x-api-signature";
+
+/// Length of the random part of key IDs.
+const KEY_ID_LEN: usize = 20;
+/// Length of key secrets; 48 alphanumerics carry about 285 bits.
+const SECRET_LEN: usize = 48;
+
+/// Why a signed request was refused.
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("missing {0} header")]
+    MissingHeader(&'static str),
+    #[error("X-API-Timestamp must be Unix seconds")]
+    InvalidTimestamp,
+    #[error("request timestamp is more than {0}s from the server clock")]
+    Expired(u64),
+    #[error("unknown or revoked API key")]
+    UnknownKey,
+    #[error("signature does not match the request")]
+    BadSignature,
+    #[error("signed request was already used")]
+    Replayed,
+    #[error("body does not match X-API-Content-SHA256")]
+    ContentMismatch,
+    #[error("API key may not be used from this address")]
+    IpNotAllowed,
+    #[error("storage error: {0}")]
+    Storage(DatabaseError),
+}
+
+#[derive(Debug)]
+pub struct SignatureRejection(pub SignatureError);
+
+impl Reject for SignatureRejection {}
+
+/// A request body that is not the JSON the route expects.
+#[derive(Debug)]
+pub struct InvalidBody(pub serde_json::Error);
+
+impl Reject for InvalidBody {}
+
+/// The signing headers of a request, with what they cover.
+#[derive(Debug, Clone)]
+pub struct SignedRequest {
+    pub key_id: String,
+    pub timestamp: String,
+    pub content_sha256: String,
+    pub signature: String,
+    pub method: Method,
+    /// Path plus the raw query string, if any.
+    pub path_and_query: String,
+}
+
+/// The message a request's signature covers.
+pub fn string_to_sign(
+    timestamp: &str,
+    method: &Method,
+    path_and_query: &str,
+    content_sha256: &str,
+) -> String {
+    format!(
+        "{}\n{}\n{}\n{}",
+        timestamp,
+        method.as_str(),
+        path_and_query,
+        content_sha256
+    )
+}
+
+/// Hex HMAC-SHA256 of `message` under `secret`, as clients compute it.
+pub fn sign(secret: &str, message: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    hex::encode(hmac::sign(&key, message.as_bytes()))
+}
+
+/// Hex SHA-256 of a request body.
+pub fn content_sha256(body: &[u8]) -> String {
+    hex::encode(digest::digest(&digest::SHA256, body))
+}
+
+/// A new key ID and secret. Only the ID follows a seeded `determinism`.
+pub fn generate_key(determinism: &Determinism) -> (String, String) {
+    (
+        format!("dk_{}", determinism.random_string(KEY_ID_LEN)),
+        determinism::secret_string(SECRET_LEN),
+    )
+}
+
+/// Signatures accepted within the replay window, so none is used twice.
+#[derive(Debug)]
+pub struct ReplayGuard {
+    window: u64,
+    /// Signature to the Unix second after which its timestamp is stale anyway.
+    seen: Mutex<HashMap<String, u64>>,
+}
+
+impl ReplayGuard {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn window(&self) -> u64 {
+        self.window
+    }
+
+    /// Reject timestamps too far from `now` on either side.
+    fn check_timestamp(&self, timestamp: u64, now: u64) -> Result<(), SignatureError> {
+        if timestamp.abs_diff(now) > self.window {
+            return Err(SignatureError::Expired(self.window));
+        }
+        Ok(())
+    }
+
+    /// Record a signature, false if it was already seen.
+    fn first_use(&self, signature: &str, timestamp: u64, now: u64) -> bool {
+        let mut seen = self.seen.lock().expect("replay guard poisoned");
+        seen.retain(|_, stale_after| *stale_after >= now);
+        seen.insert(signature.to_string(), timestamp + self.window)
+            .is_none()
+    }
+}
+
+/// The request's signing headers, or `None` when it carries no `X-API-Key`.
+pub fn signed_request(
+) -> impl Filter<Extract = (Option<SignedRequest>,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>(KEY_HEADER)
+        .and(warp::header::optional::<String>(TIMESTAMP_HEADER))
+        .and(warp::header::optional::<String>(CONTENT_HEADER))
+        .and(warp::header::optional::<String>(SIGNATURE_HEADER))
+        .and(warp::method())
+        .and(warp::path::full())
+        .and(warp::query::raw().or(warp::any().map(String::new)).unify())
+        .and_then(
+            |key_id: Option<String>,
+             timestamp: Option<String>,
+             content_sha256: Option<String>,
+             signature: Option<String>,
+             method: Method,
+             path: FullPath,
+             query: String| async move {
+                let Some(key_id) = key_id else {
+                    return Ok::<_, warp::Rejection>(None);
+                };
+                let missing = |header| {
+                    reject::custom(SignatureRejection(SignatureError::MissingHeader(header)))
+                };
+                let mut path_and_query = path.as_str().to_string();
+                if !query.is_empty() {
+                    path_and_query.push('?');
+                    path_and_query.push_str(&query);
+                }
+                Ok(Some(SignedRequest {
+                    key_id,
+                    timestamp: timestamp.ok_or_else(|| missing("X-API-Timestamp"))?,
+                    content_sha256: content_sha256
+                        .ok_or_else(|| missing("X-API-Content-SHA256"))?
+                        .to_ascii_lowercase(),
+                    signature: signature.ok_or_else(|| missing("X-API-Signature"))?,
+                    method,
+                    path_and_query,
+                }))
+            },
+        )
+}
+
+/// Check a signed request from `peer` and return claims for its key's owner.
+pub async fn verify(
+    state: &ApiState,
+    request: SignedRequest,
+    peer: Option<IpAddr>,
+) -> Result<Claims, SignatureError> {
+    let guard = &state.signed_requests;
+    let timestamp: u64 = request
+        .timestamp
+        .parse()
+        .map_err(|_| SignatureError::InvalidTimestamp)?;
+    let now = state.determinism.now().unwrap_or_default();
+    guard.check_timestamp(timestamp, now)?;
+
+    let key = match state.api_keys.load_api_key(&request.key_id).await {
+        Ok(Some(key)) if !key.revoked => key,
+        Ok(_) => return Err(SignatureError::UnknownKey),
+        Err(err) => return Err(SignatureError::Storage(err)),
+    };
+    let message = string_to_sign(
+        &request.timestamp,
+        &request.method,
+        &request.path_and_query,
+        &request.content_sha256,
+    );
+    let signature = hex::decode(&request.signature).map_err(|_| SignatureError::BadSignature)?;
+    // Without the key no secret can be opened, and no key could have been
+    // created; a secret that does not open is as good as unknown.
+    let secret = state
+        .config
+        .api_key_encryption_key
+        .as_ref()
+        .ok_or(SignatureError::UnknownKey)?
+        .open(&key.key_id, &key.sealed_secret)
+        .map_err(|err| {
+            tracing::error!(key_id = %key.key_id, error = %err, "failed to open API key secret");
+            SignatureError::UnknownKey
+        })?;
+    let hmac_key = hmac::Key::new(hmac::HMAC_SHA256, &secret);
+    hmac::verify(&hmac_key, message.as_bytes(), &signature)
+        .map_err(|_| SignatureError::BadSignature)?;
+    // Stored lists were validated on write; one that no longer parses
+    // refuses every address rather than none.
+    let permitted = Allowlist::parse(key.allowed_ips.iter().map(String::as_str))
+        .is_ok_and(|allowlist| allowlist.permits(peer));
+    if !permitted {
+        tracing::warn!(
+            target: "security",
+            key_id = %key.key_id,
+            subject = %key.subject,
+            peer = peer.map(tracing::field::display),
+            "refused API key used from an address outside its allowlist"
+        );
+        return Err(SignatureError::IpNotAllowed);
+    }
+    // Keyed on the decoded bytes: hex decoding ignores case, so the header
+    // as sent would let a replay through with its case changed.
+    let signature = hex::encode(signature);
+    if !guard.first_use(&signature, timestamp, now) {
+        return Err(SignatureError::Replayed);
+    }
+    state
+        .usage
+        .signed_request(&signature, &key.subject, &key.key_id);
+
+    Ok(Claims {
+        role: role_of(state, &key.subject),
+        sub: key.subject,
+        exp: (now + guard.window()) as usize,
+        aud: None,
+        iss: None,
+        iat: Some(timestamp as usize),
+        jti: None,
+        scope: Some(key.scope),
+    })
+}
+
+/// The raw body, up to `limit` bytes, checked against the
+/// `X-API-Content-SHA256` a signed request committed to.
+pub fn signed_body(limit: u64) -> impl Filter<Extract = (Bytes,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(limit)
+        .and(warp::header::optional::<String>(CONTENT_HEADER))
+        .and(warp::body::bytes())
+        .and_then(|expected: Option<String>, body: Bytes| async move {
+            match expected {
+                Some(expected) if !expected.eq_ignore_ascii_case(&content_sha256(&body)) => Err(
+                    reject::custom(SignatureRejection(SignatureError::ContentMismatch)),
+                ),
+                _ => Ok(body),
+            }
+        })
+}
+
+/// Like `warp::body::json`, for routes that accept signed requests.
+pub fn signed_json<T: DeserializeOwned + Send>(
+    limit: u64,
+) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    signed_body(limit).and_then(|body: Bytes| async move {
+        serde_json::from_slice(&body).map_err(|err| reject::custom(InvalidBody(err)))
+    })
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateApiKeyRequest {
+    #[serde(default)]
+    label: Option<String>,
+    /// Space-separated scopes; `read trade` when omitted.
+    #[serde(default)]
+    scope: Option<String>,
+    /// CIDR blocks the key may be used from; anywhere when omitted.
+    #[serde(default)]
+    allowed_ips: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct ApiKeyAllowedIpsRequest {
+    allowed_ips: Vec<String>,
+}
+
+/// An API key without its secret.
+#[derive(Serialize, ToSchema)]
+#[schema(as = ApiKey)]
+pub struct ApiKeyResponse {
+    pub key_id: String,
+    pub scope: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub created_at: u64,
+    pub revoked: bool,
+    pub allowed_ips: Vec<String>,
+}
+
+impl From<ApiKeyRecord> for ApiKeyResponse {
+    fn from(key: ApiKeyRecord) -> Self {
+        Self {
+            key_id: key.key_id,
+            scope: key.scope,
+            label: key.label,
+            created_at: key.created_at,
+            revoked: key.revoked,
+            allowed_ips: key.allowed_ips,
+        }
+    }
+}
+
+/// A new API key; the secret is never shown again.
+#[derive(Serialize, ToSchema)]
+#[schema(as = CreatedApiKey)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub key: ApiKeyResponse,
+    pub secret: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = ApiKeys)]
+pub struct ApiKeysResponse {
+    pub keys: Vec<ApiKeyResponse>,
+}
+
+/// Most CIDR blocks one API key may be bound to.
+const MAX_ALLOWED_IPS: usize = 32;
+
+/// Long-lived keys for HMAC-signed requests, e.g. DELETE /auth/api-keys/dk_...
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let keys = warp::path("auth").and(warp::path("api-keys"));
+
+    let create_api_key = keys
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and(signed_json(2 * 1024))
+        .and_then(handle_create_api_key)
+        .boxed();
+
+    let list_api_keys = keys
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_list_api_keys)
+        .boxed();
+
+    let revoke_api_key = keys
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_revoke_api_key)
+        .boxed();
+
+    let api_key_allowed_ips = keys
+        .and(warp::path::param::<String>())
+        .and(warp::path("allowed-ips"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and(signed_json(4 * 1024))
+        .and_then(handle_set_api_key_allowed_ips)
+        .boxed();
+
+    create_api_key
+        .or(list_api_keys)
+        .or(revoke_api_key)
+        .or(api_key_allowed_ips)
+}
+
+/// Create an API key for the caller. Keys cannot carry scopes the caller's
+/// own credentials lack, and only callers holding all of a key's scopes may
+/// revoke it or change its allowlist.
+#[utoipa::path(
+    post,
+    path = "/auth/api-keys",
+    summary = "Create an API key for signing requests; the secret is only returned here",
+    request_body = CreateApiKeyRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 201, description = "New key and its secret", body = CreatedApiKeyResponse),
+        (status = 400, description = "Invalid label or unknown scope", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Requested scopes exceed the caller's", body = ErrorResponse),
+        (status = 503, description = "Storage or API_KEY_ENCRYPTION_KEY unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_create_api_key(
+    claims: Claims,
+    state: ApiState,
+    req: CreateApiKeyRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let label = match validation::validate_label(req.label.as_deref()) {
+        Ok(label) => label,
+        Err(err) => return Err(warp::reject::custom(ValidationRejection(err))),
+    };
+    let scopes = match requested_scopes(&state, &claims.sub, req.scope.as_deref()) {
+        Ok(scopes) => scopes,
+        Err(reply) => return Ok(reply),
+    };
+    if let Some(scope) = scopes.iter().find(|scope| !claims.has_scope(**scope)) {
+        return Err(warp::reject::custom(ScopeRejection(*scope)));
+    }
+    let allowed_ips = match validate_allowed_ips(&req.allowed_ips) {
+        Ok(allowed_ips) => allowed_ips,
+        Err(reply) => return Ok(reply),
+    };
+
+    let Some(sealing_key) = &state.config.api_key_encryption_key else {
+        return Ok(error_reply(
+            "api_keys_unavailable",
+            "API keys are not configured",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    };
+    let (key_id, secret) = generate_key(&state.determinism);
+    let sealed_secret = match sealing_key.seal(&key_id, secret.as_bytes()) {
+        Ok(sealed) => sealed,
+        Err(err) => {
+            tracing::error!(subject = %claims.sub, error = %err, "failed to seal API key secret");
+            return Ok(error_reply(
+                "internal_error",
+                "failed to create API key",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+    let record = ApiKeyRecord {
+        key_id,
+        sealed_secret,
+        subject: claims.sub,
+        scope: format_scopes(&scopes),
+        label,
+        created_at: state.determinism.now().unwrap_or_default(),
+        revoked: false,
+        allowed_ips,
+    };
+    if let Err(err) = state.api_keys.save_api_key(&record).await {
+        tracing::error!(subject = %record.subject, error = ?err, "failed to store API key");
+        return Ok(storage_error_reply(&err, "failed to create API key"));
+    }
+    let response = CreatedApiKeyResponse {
+        key: record.into(),
+        secret,
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::CREATED,
+    ))
+}
+
+/// `allowed_ips` in canonical form, or the error reply for an invalid list.
+fn validate_allowed_ips(
+    allowed_ips: &[String],
+) -> Result<Vec<String>, warp::reply::WithStatus<warp::reply::Json>> {
+    if allowed_ips.len() > MAX_ALLOWED_IPS {
+        return Err(error_reply(
+            "invalid_allowed_ips",
+            format!("at most {} CIDR blocks per key", MAX_ALLOWED_IPS),
+            StatusCode::BAD_REQUEST,
+        ));
+    }
+    ip_allowlist::Allowlist::parse(allowed_ips.iter().map(String::as_str))
+        .map(|allowlist| allowlist.to_strings())
+        .map_err(|err| {
+            error_reply(
+                "invalid_allowed_ips",
+                err.to_string(),
+                StatusCode::BAD_REQUEST,
+            )
+        })
+}
+
+/// Whether the caller may change an API key.
+enum KeyAccess {
+    Allowed,
+    /// Not one of the caller's active keys.
+    NotFound,
+    /// The key carries a scope the caller's credentials lack.
+    MissingScope(Scope),
+}
+
+/// Whether the caller may revoke `key_id` or change its allowlist: like
+/// creating it, that takes every scope the key carries.
+async fn key_access(
+    state: &ApiState,
+    claims: &Claims,
+    key_id: &str,
+) -> Result<KeyAccess, DatabaseError> {
+    let Some(key) = state
+        .api_keys
+        .load_api_key(key_id)
+        .await?
+        .filter(|key| key.subject == claims.sub && !key.revoked)
+    else {
+        return Ok(KeyAccess::NotFound);
+    };
+    let scopes = parse_scopes(&key.scope).map_err(|_| DatabaseError::DataIntegrityError)?;
+    Ok(
+        match scopes.into_iter().find(|scope| !claims.has_scope(*scope)) {
+            Some(scope) => KeyAccess::MissingScope(scope),
+            None => KeyAccess::Allowed,
+        },
+    )
+}
+
+fn key_not_found() -> warp::reply::Response {
+    warp::Reply::into_response(error_reply(
+        "api_key_not_found",
+        "no active API key with this ID",
+        StatusCode::NOT_FOUND,
+    ))
+}
+
+/// Replace the CIDR blocks one of the caller's keys may be used from; an
+/// empty list lets it be used anywhere again.
+#[utoipa::path(
+    put,
+    path = "/auth/api-keys/{key_id}/allowed-ips",
+    summary = "Replace the CIDR blocks one of the caller's API keys may be used from",
+    params(("key_id" = String, Path)),
+    request_body = ApiKeyAllowedIpsRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 204, description = "Allowlist replaced"),
+        (status = 400, description = "Invalid CIDR block or too many blocks", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "The key has scopes the caller's credentials lack", body = ErrorResponse),
+        (status = 404, description = "No active key with this ID", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_set_api_key_allowed_ips(
+    key_id: String,
+    claims: Claims,
+    state: ApiState,
+    req: ApiKeyAllowedIpsRequest,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let allowed_ips = match validate_allowed_ips(&req.allowed_ips) {
+        Ok(allowed_ips) => allowed_ips,
+        Err(reply) => return Ok(warp::Reply::into_response(reply)),
+    };
+    match key_access(&state, &claims, &key_id).await {
+        Ok(KeyAccess::Allowed) => {}
+        Ok(KeyAccess::NotFound) => return Ok(key_not_found()),
+        Ok(KeyAccess::MissingScope(scope)) => {
+            return Err(warp::reject::custom(ScopeRejection(scope)))
+        }
+        Err(err) => {
+            tracing::error!(%key_id, error = ?err, "failed to load API key");
+            return Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to update API key",
+            )));
+        }
+    }
+    match state
+        .api_keys
+        .set_api_key_allowed_ips(&key_id, &claims.sub, &allowed_ips)
+        .await
+    {
+        Ok(true) => Ok(warp::Reply::into_response(StatusCode::NO_CONTENT)),
+        Ok(false) => Ok(key_not_found()),
+        Err(err) => {
+            tracing::error!(%key_id, error = ?err, "failed to update API key allowlist");
+            Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to update API key",
+            )))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/auth/api-keys",
+    summary = "List the caller's API keys, without secrets",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Keys, oldest first", body = ApiKeysResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_list_api_keys(
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match state.api_keys.list_api_keys(&claims.sub).await {
+        Ok(keys) => {
+            let response = ApiKeysResponse {
+                keys: keys.into_iter().map(ApiKeyResponse::from).collect(),
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(subject = %claims.sub, error = ?err, "failed to list API keys");
+            Ok(storage_error_reply(&err, "failed to list API keys"))
+        }
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/auth/api-keys/{key_id}",
+    summary = "Revoke one of the caller's API keys",
+    params(("key_id" = String, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "The key has scopes the caller's credentials lack", body = ErrorResponse),
+        (status = 404, description = "No active key with this ID", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_revoke_api_key(
+    key_id: String,
+    claims: Claims,
+    state: ApiState,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    match key_access(&state, &claims, &key_id).await {
+        Ok(KeyAccess::Allowed) => {}
+        Ok(KeyAccess::NotFound) => return Ok(key_not_found()),
+        Ok(KeyAccess::MissingScope(scope)) => {
+            return Err(warp::reject::custom(ScopeRejection(scope)))
+        }
+        Err(err) => {
+            tracing::error!(%key_id, error = ?err, "failed to load API key");
+            return Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to revoke API key",
+            )));
+        }
+    }
+    match state.api_keys.revoke_api_key(&key_id, &claims.sub).await {
+        Ok(true) => Ok(warp::Reply::into_response(StatusCode::NO_CONTENT)),
+        Ok(false) => Ok(key_not_found()),
+        Err(err) => {
+            tracing::error!(%key_id, error = ?err, "failed to revoke API key");
+            Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to revoke API key",
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_guard_accepts_each_signature_once_within_the_window() {
+        let guard = ReplayGuard::new(30);
+        assert!(guard.check_timestamp(1_000, 1_030).is_ok());
+        assert!(guard.check_timestamp(1_031, 1_000).is_err());
+        assert!(matches!(
+            guard.check_timestamp(969, 1_000),
+            Err(SignatureError::Expired(30))
+        ));
+
+        assert!(guard.first_use("abc", 1_000, 1_000));
+        assert!(!guard.first_use("abc", 1_000, 1_010));
+        // Once its timestamp is stale the signature is forgotten.
+        assert!(guard.first_use("def", 1_040, 1_040));
+        assert_eq!(guard.seen.lock().unwrap().len(), 1);
+    }
+}
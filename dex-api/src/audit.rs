@@ -0,0 +1,391 @@
+//! Audit log of authenticated actions, for compliance review.
+//!
+//! Every request that changes state for an account is appended to the
+//! `audit_log` table once its response is known. That covers placing and
+//! cancelling orders, issuing, refreshing and revoking tokens and sessions,
+//! managing API keys and two-factor sign-in, and operator endpoints. Each
+//! entry records the account, the client address, the time and the outcome.
+//! Orders and cancels over FIX are recorded the same way. Requests that neither carry
+//! valid credentials nor name an account are not recorded, since anyone
+//! could have sent them. Administrators read the log through
+//! `GET /admin/audit`.
+
+use crate::{
+    admin_only, auth::Claims, rate_limit::RouteClass, rate_limited, storage_error_reply, ApiState,
+    ErrorResponse,
+};
+use dex_db::{AuditEntry, AuditFilter};
+use serde::{Deserialize, Serialize};
+use std::{convert::Infallible, future::Future, net::IpAddr};
+use utoipa::{openapi::Object, IntoParams, ToSchema};
+use warp::{
+    http::{Method, StatusCode},
+    reply::Response,
+    Filter, Rejection, Reply,
+};
+
+/// Default page size of `GET /admin/audit`.
+pub const DEFAULT_PAGE: u32 = 100;
+
+/// A state-changing action worth auditing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    OrderCreate,
+    OrderCancel,
+    TokenIssue,
+    TokenRefresh,
+    TokenRevoke,
+    Logout,
+    ApiKeyCreate,
+    ApiKeyRevoke,
+    ApiKeyUpdate,
+    TotpEnroll,
+    TotpConfirm,
+    TotpDisable,
+    SessionRevoke,
+    TradeBust,
+    TradeAdjust,
+    SettlementNetting,
+    /// Any other change made through an operator endpoint.
+    Admin,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::OrderCreate => "order_create",
+            Self::OrderCancel => "order_cancel",
+            Self::TokenIssue => "token_issue",
+            Self::TokenRefresh => "token_refresh",
+            Self::TokenRevoke => "token_revoke",
+            Self::Logout => "logout",
+            Self::ApiKeyCreate => "api_key_create",
+            Self::ApiKeyRevoke => "api_key_revoke",
+            Self::ApiKeyUpdate => "api_key_update",
+            Self::TotpEnroll => "totp_enroll",
+            Self::TotpConfirm => "totp_confirm",
+            Self::TotpDisable => "totp_disable",
+            Self::SessionRevoke => "session_revoke",
+            Self::TradeBust => "trade_bust",
+            Self::TradeAdjust => "trade_adjust",
+            Self::SettlementNetting => "settlement_netting",
+            Self::Admin => "admin",
+        }
+    }
+}
+
+/// The action a request for `path` with `method` performs, if audited.
+pub fn action_for(method: &Method, path: &str) -> Option<AuditAction> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let action = match (method.as_str(), segments.as_slice()) {
+        ("POST", ["orderbook", "orders"]) => AuditAction::OrderCreate,
+        ("DELETE", ["orderbook", "orders", _]) => AuditAction::OrderCancel,
+        ("POST", ["auth", "token", "shared" | "wallet"]) => AuditAction::TokenIssue,
+        ("POST", ["auth", "token", "refresh"]) => AuditAction::TokenRefresh,
+        ("POST", ["auth", "token", "revoke"]) => AuditAction::TokenRevoke,
+        ("POST", ["auth", "logout"]) => AuditAction::Logout,
+        ("POST", ["auth", "api-keys"]) => AuditAction::ApiKeyCreate,
+        ("DELETE", ["auth", "api-keys", _]) => AuditAction::ApiKeyRevoke,
+        ("PUT", ["auth", "api-keys", _, "allowed-ips"]) => AuditAction::ApiKeyUpdate,
+        ("POST", ["auth", "totp", "enroll"]) => AuditAction::TotpEnroll,
+        ("POST", ["auth", "totp", "confirm"]) => AuditAction::TotpConfirm,
+        ("POST", ["auth", "totp", "disable"]) => AuditAction::TotpDisable,
+        ("DELETE", ["auth", "sessions", _]) => AuditAction::SessionRevoke,
+        ("POST", ["admin", "trades", _, "bust"]) => AuditAction::TradeBust,
+        ("POST", ["admin", "trades", _, "adjust"]) => AuditAction::TradeAdjust,
+        ("POST", ["admin", "settlement", "netting"]) => AuditAction::SettlementNetting,
+        (method, ["admin", ..]) if !matches!(method, "GET" | "HEAD" | "OPTIONS") => {
+            AuditAction::Admin
+        }
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// `success` for 2xx, `denied` for a refused credential or permission,
+/// `rejected` for other client errors and `error` for server errors.
+pub fn outcome_of(status: StatusCode) -> &'static str {
+    if status.is_success() {
+        "success"
+    } else if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+        "denied"
+    } else if status.is_client_error() {
+        "rejected"
+    } else {
+        "error"
+    }
+}
+
+/// The account a response is recorded against when the request carried no
+/// credential, such as the trader named in a sign-in. Kept in the response
+/// extensions, so it never reaches the client.
+#[derive(Debug, Clone)]
+pub struct AuditSubject(pub String);
+
+/// Attribute `reply` to `subject` in the audit log.
+pub fn attribute(reply: impl Reply, subject: impl Into<String>) -> Response {
+    let mut response = reply.into_response();
+    response
+        .extensions_mut()
+        .insert(AuditSubject(subject.into()));
+    response
+}
+
+/// Run a handler whose request names the account it acts for, attributing
+/// its response to that account. Rejections are turned into their error
+/// responses here so that failures are attributed too.
+pub async fn attributed<R: Reply>(
+    subject: String,
+    reply: impl Future<Output = Result<R, Rejection>>,
+) -> Result<Response, Infallible> {
+    let response = match reply.await {
+        Ok(reply) => reply.into_response(),
+        Err(rejection) => crate::handle_rejection(rejection).await?,
+    };
+    Ok(attribute(response, subject))
+}
+
+/// Append an entry. A failed write is logged with the entry, so the action
+/// is not lost even while storage is down.
+pub async fn record(state: &ApiState, entry: AuditEntry) {
+    if let Err(err) = state.audit_repo.append_audit(&entry).await {
+        tracing::error!(
+            error = ?err,
+            action = %entry.action,
+            target = %entry.target,
+            subject = %entry.subject,
+            ip = entry.ip.as_deref().unwrap_or("unknown"),
+            occurred_at = entry.occurred_at,
+            outcome = %entry.outcome,
+            "failed to record audit entry"
+        );
+    }
+}
+
+/// Record a REST request by `subject` if it performs an audited action.
+pub async fn record_request(
+    state: &ApiState,
+    subject: String,
+    ip: Option<IpAddr>,
+    method: &Method,
+    path: &str,
+    status: StatusCode,
+) {
+    let Some(action) = action_for(method, path) else {
+        return;
+    };
+    let entry = AuditEntry {
+        id: 0,
+        occurred_at: state.determinism.now().unwrap_or_default(),
+        subject,
+        ip: ip.map(|ip| ip.to_string()),
+        action: action.as_str().to_string(),
+        target: format!("{} {}", method, path),
+        outcome: outcome_of(status).to_string(),
+        status: Some(status.as_u16()),
+    };
+    record(state, entry).await;
+}
+
+/// Record an order or cancel received over a FIX session or a gRPC call.
+pub async fn record_gateway(
+    state: &ApiState,
+    subject: &str,
+    ip: Option<IpAddr>,
+    action: AuditAction,
+    target: String,
+    accepted: bool,
+) {
+    let entry = AuditEntry {
+        id: 0,
+        occurred_at: state.determinism.now().unwrap_or_default(),
+        subject: subject.to_string(),
+        ip: ip.map(|ip| ip.to_string()),
+        action: action.as_str().to_string(),
+        target,
+        outcome: if accepted { "success" } else { "rejected" }.to_string(),
+        status: None,
+    };
+    record(state, entry).await;
+}
+
+/// Query parameters of `GET /admin/audit`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// Cursor from `next_cursor`
+    pub after_id: Option<u64>,
+    /// Page size, 1-1000 (default 100)
+    pub limit: Option<u32>,
+    /// Only entries for this account
+    pub subject: Option<String>,
+    /// Only this action, e.g. order_create
+    pub action: Option<String>,
+    /// Unix timestamp, inclusive
+    pub from: Option<u64>,
+    /// Unix timestamp, exclusive
+    pub to: Option<u64>,
+}
+
+impl AuditQuery {
+    pub fn into_filter(self) -> AuditFilter {
+        let nonempty = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        AuditFilter {
+            after_id: self.after_id,
+            limit: self.limit.unwrap_or(DEFAULT_PAGE),
+            subject: nonempty(self.subject),
+            action: nonempty(self.action),
+            from: self.from,
+            to: self.to,
+        }
+    }
+}
+
+/// One entry of `GET /admin/audit`.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AuditRecord {
+    pub id: u64,
+    /// Unix seconds.
+    pub occurred_at: u64,
+    pub subject: String,
+    pub ip: Option<String>,
+    pub action: String,
+    pub target: String,
+    #[schema(schema_with = outcomes)]
+    pub outcome: String,
+    pub status: Option<u16>,
+}
+
+impl From<AuditEntry> for AuditRecord {
+    fn from(entry: AuditEntry) -> Self {
+        Self {
+            id: entry.id,
+            occurred_at: entry.occurred_at,
+            subject: entry.subject,
+            ip: entry.ip,
+            action: entry.action,
+            target: entry.target,
+            outcome: entry.outcome,
+            status: entry.status,
+        }
+    }
+}
+
+fn outcomes() -> Object {
+    crate::openapi::string_enum(&["success", "denied", "rejected", "error"])
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = AuditLog)]
+pub struct AuditLogResponse {
+    pub entries: Vec<AuditRecord>,
+    /// Pass as `after_id` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// The audit log, e.g. GET /admin/audit?subject=alice
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("admin")
+        .and(warp::path("audit"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<AuditQuery>())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_audit_log)
+        .boxed()
+}
+
+/// Page through the audit log, oldest first.
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    summary = "Audit log of authenticated actions, oldest first (administrators only)",
+    params(AuditQuery),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "One page of the audit log", body = AuditLogResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_audit_log(
+    query: AuditQuery,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let filter = query.into_filter();
+    match state.audit_repo.query_audit(&filter).await {
+        Ok(entries) => {
+            let next_cursor = if entries.len() as u32 == filter.page_size() {
+                entries.last().map(|entry| entry.id)
+            } else {
+                None
+            };
+            let response = AuditLogResponse {
+                entries: entries.into_iter().map(Into::into).collect(),
+                next_cursor,
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load audit log");
+            Ok(storage_error_reply(&err, "failed to load audit log"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutating_routes_map_to_actions() {
+        let action =
+            |method: Method, path: &str| action_for(&method, path).map(AuditAction::as_str);
+        assert_eq!(
+            action(Method::POST, "/orderbook/orders"),
+            Some("order_create")
+        );
+        assert_eq!(
+            action(Method::DELETE, "/orderbook/orders/42"),
+            Some("order_cancel")
+        );
+        assert_eq!(
+            action(Method::POST, "/auth/token/wallet"),
+            Some("token_issue")
+        );
+        assert_eq!(
+            action(Method::DELETE, "/auth/api-keys/dk_1"),
+            Some("api_key_revoke")
+        );
+        assert_eq!(
+            action(Method::POST, "/admin/trades/7/bust"),
+            Some("trade_bust")
+        );
+        assert_eq!(
+            action(Method::DELETE, "/auth/sessions/abc"),
+            Some("session_revoke")
+        );
+        assert_eq!(action(Method::POST, "/admin/markets/halt"), Some("admin"));
+        assert_eq!(action(Method::GET, "/admin/audit"), None);
+        assert_eq!(action(Method::GET, "/orderbook/orders/42/trades"), None);
+        assert_eq!(action(Method::POST, "/risk/check"), None);
+
+        assert_eq!(outcome_of(StatusCode::CREATED), "success");
+        assert_eq!(outcome_of(StatusCode::FORBIDDEN), "denied");
+        assert_eq!(outcome_of(StatusCode::UNPROCESSABLE_ENTITY), "rejected");
+        assert_eq!(outcome_of(StatusCode::SERVICE_UNAVAILABLE), "error");
+    }
+}
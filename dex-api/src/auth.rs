@@ -1,41 +1,314 @@
 //! JWT authentication helpers for Warp filters.
 
+use crate::determinism::{self, Determinism};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use ethers_core::{
-    types::{Address, Signature},
-    utils::hash_message,
+    types::{transaction::eip712::TypedData, Address, Signature},
+    utils::{hash_message, hex, keccak256},
+};
+use jsonwebtoken::{
+    decode, decode_header, encode,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::{
+    rsa::PublicKeyComponents,
+    signature::{Ed25519KeyPair, KeyPair, RsaKeyPair},
 };
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fmt,
     str::FromStr,
-    sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    sync::{Arc, Mutex, RwLock},
+    time::Duration,
 };
 use thiserror::Error;
+use utoipa::ToSchema;
 use warp::reject::Reject;
 
+/// Length of refresh tokens; 48 alphanumerics carry about 285 bits.
+const REFRESH_TOKEN_LEN: usize = 48;
+/// Length of the `jti` given to each access token.
+const TOKEN_ID_LEN: usize = 24;
+
+/// Key ID given to a bare `JWT_SECRET`.
+pub const DEFAULT_KEY_ID: &str = "default";
+
+/// What a token may be used for. Tokens list their scopes, space separated,
+/// in the `scope` claim, and each protected route requires one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scope {
+    /// Private order, trade and position data; every token has it.
+    Read,
+    /// Placing and cancelling orders.
+    Trade,
+    /// Moving funds off the exchange.
+    Withdraw,
+    /// Operator endpoints, for subjects in `ADMIN_SUBJECTS` only.
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Trade => "trade",
+            Scope::Withdraw => "withdraw",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = AuthError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "read" => Ok(Scope::Read),
+            "trade" => Ok(Scope::Trade),
+            "withdraw" => Ok(Scope::Withdraw),
+            "admin" => Ok(Scope::Admin),
+            other => Err(AuthError::UnknownScope(other.to_string())),
+        }
+    }
+}
+
+/// Who a token was issued to, beyond what its scopes allow. Only subjects in
+/// `ADMIN_SUBJECTS` are issued a role, so a token that merely lists the
+/// `admin` scope does not open the operator endpoints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+}
+
+/// Scopes of tokens issued without an explicit request.
+pub const DEFAULT_SCOPES: &[Scope] = &[Scope::Read, Scope::Trade];
+
+/// Parse a space-separated scope list. `read` is always included.
+pub fn parse_scopes(raw: &str) -> Result<Vec<Scope>, AuthError> {
+    let mut scopes = vec![Scope::Read];
+    for scope in raw.split_whitespace() {
+        scopes.push(scope.parse()?);
+    }
+    scopes.sort_unstable();
+    scopes.dedup();
+    Ok(scopes)
+}
+
+/// The `scope` claim for `scopes`.
+pub fn format_scopes(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Secret half of a signing key.
+#[derive(Debug, Clone)]
+pub enum KeyMaterial {
+    /// HS256 shared secret; verifiers need the secret too.
+    Hmac(SecretString),
+    /// RS256 private key, PKCS#1 or PKCS#8 PEM.
+    Rsa(SecretString),
+    /// EdDSA (Ed25519) private key, PKCS#8 PEM.
+    Ed25519(SecretString),
+}
+
+/// A JWT signing key and the window in which it is used.
+#[derive(Debug, Clone)]
+pub struct SigningKey {
+    /// Sent as the token's `kid` header.
+    pub kid: String,
+    pub material: KeyMaterial,
+    /// Unix seconds from which new tokens are signed with this key. The
+    /// newest active key signs; older ones only verify.
+    pub active_from: u64,
+    /// Unix seconds after which tokens signed with this key are rejected.
+    pub expires_at: Option<u64>,
+}
+
+impl SigningKey {
+    /// A key that is always active, for a single static secret.
+    pub fn static_secret(secret: SecretString) -> Self {
+        Self {
+            kid: DEFAULT_KEY_ID.to_string(),
+            material: KeyMaterial::Hmac(secret),
+            active_from: 0,
+            expires_at: None,
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| now >= expires_at)
+    }
+}
+
+struct LoadedKey {
+    schedule: SigningKey,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    /// Only accepts tokens signed with the key's own algorithm.
+    validation: Validation,
+    /// Public half, for asymmetric keys.
+    jwk: Option<Jwk>,
+}
+
+impl LoadedKey {
+    fn load(schedule: SigningKey) -> Result<Self, AuthError> {
+        let invalid =
+            |err: &dyn fmt::Display| AuthError::InvalidKey(format!("{}: {}", schedule.kid, err));
+        let (algorithm, encoding, decoding, params) = match &schedule.material {
+            KeyMaterial::Hmac(secret) => {
+                let secret = secret.expose_secret().as_bytes();
+                (
+                    Algorithm::HS256,
+                    EncodingKey::from_secret(secret),
+                    DecodingKey::from_secret(secret),
+                    None,
+                )
+            }
+            KeyMaterial::Rsa(pem) => {
+                let pem = pem.expose_secret().as_bytes();
+                let parsed = pem::parse(pem).map_err(|err| invalid(&err))?;
+                let key_pair = match parsed.tag() {
+                    "RSA PRIVATE KEY" => RsaKeyPair::from_der(parsed.contents()),
+                    _ => RsaKeyPair::from_pkcs8(parsed.contents()),
+                }
+                .map_err(|err| invalid(&err))?;
+                let public = PublicKeyComponents::<Vec<u8>>::from(key_pair.public());
+                let params = AlgorithmParameters::RSA(RSAKeyParameters {
+                    key_type: RSAKeyType::RSA,
+                    n: URL_SAFE_NO_PAD.encode(&public.n),
+                    e: URL_SAFE_NO_PAD.encode(&public.e),
+                });
+                (
+                    Algorithm::RS256,
+                    EncodingKey::from_rsa_pem(pem).map_err(|err| invalid(&err))?,
+                    DecodingKey::from_rsa_raw_components(&public.n, &public.e),
+                    Some((KeyAlgorithm::RS256, params)),
+                )
+            }
+            KeyMaterial::Ed25519(pem) => {
+                let pem = pem.expose_secret().as_bytes();
+                let parsed = pem::parse(pem).map_err(|err| invalid(&err))?;
+                let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(parsed.contents())
+                    .map_err(|err| invalid(&err))?;
+                let public = key_pair.public_key().as_ref();
+                let params = AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+                    key_type: OctetKeyPairType::OctetKeyPair,
+                    curve: EllipticCurve::Ed25519,
+                    x: URL_SAFE_NO_PAD.encode(public),
+                });
+                (
+                    Algorithm::EdDSA,
+                    EncodingKey::from_ed_pem(pem).map_err(|err| invalid(&err))?,
+                    DecodingKey::from_ed_der(public),
+                    Some((KeyAlgorithm::EdDSA, params)),
+                )
+            }
+        };
+        let jwk = params.map(|(key_algorithm, algorithm)| Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(schedule.kid.clone()),
+                ..CommonParameters::default()
+            },
+            algorithm,
+        });
+        // Expiry is checked against the manager's clock, which may be seeded.
+        let mut validation = Validation::new(algorithm);
+        validation.validate_exp = false;
+        Ok(Self {
+            schedule,
+            encoding,
+            decoding,
+            validation,
+            jwk,
+        })
+    }
+}
+
 /// Shared authentication manager that validates bearer tokens.
 #[derive(Clone)]
 pub struct AuthManager {
-    decoding_key: Arc<DecodingKey>,
-    encoding_key: Arc<EncodingKey>,
-    validation: Validation,
+    /// Swapped whole when the keys are reloaded from a secrets manager.
+    keys: Arc<RwLock<Arc<Vec<LoadedKey>>>>,
     issuer: Arc<String>,
+    /// Revoked token IDs and when the token would have expired anyway;
+    /// entries are dropped after that. Revocations are stored, and this
+    /// copy is filled from storage; see `sessions::spawn_revocation_sync`.
+    revoked: Arc<Mutex<HashMap<String, u64>>>,
+    determinism: Arc<Determinism>,
 }
 
 impl AuthManager {
     pub fn new(secret: &SecretString, issuer: impl Into<String>) -> Self {
-        let decoding_key = DecodingKey::from_secret(secret.expose_secret().as_bytes());
-        let encoding_key = EncodingKey::from_secret(secret.expose_secret().as_bytes());
-        let mut validation = Validation::default();
-        validation.validate_exp = true;
-        Self {
-            decoding_key: Arc::new(decoding_key),
-            encoding_key: Arc::new(encoding_key),
-            validation,
+        Self::with_keys(vec![SigningKey::static_secret(secret.clone())], issuer)
+            .expect("HMAC keys always load")
+    }
+
+    /// Sign with the newest active key of a rotation schedule and accept
+    /// tokens from any key that has not expired. Fails if a private key
+    /// cannot be parsed.
+    pub fn with_keys(keys: Vec<SigningKey>, issuer: impl Into<String>) -> Result<Self, AuthError> {
+        Ok(Self {
+            keys: Arc::new(RwLock::new(Arc::new(Self::load_keys(keys)?))),
             issuer: Arc::new(issuer.into()),
+            revoked: Arc::new(Mutex::new(HashMap::new())),
+            determinism: Arc::default(),
+        })
+    }
+
+    fn load_keys(keys: Vec<SigningKey>) -> Result<Vec<LoadedKey>, AuthError> {
+        keys.into_iter().map(LoadedKey::load).collect()
+    }
+
+    /// Replace the signing keys, e.g. after a rotation in the secrets
+    /// manager. Tokens signed with a key that is no longer listed stop
+    /// verifying. Every clone of this manager sees the new keys; on error
+    /// the old ones stay in place.
+    pub fn replace_keys(&self, keys: Vec<SigningKey>) -> Result<(), AuthError> {
+        let loaded = Arc::new(Self::load_keys(keys)?);
+        *self.keys.write().expect("signing keys poisoned") = loaded;
+        Ok(())
+    }
+
+    fn keys(&self) -> Arc<Vec<LoadedKey>> {
+        self.keys.read().expect("signing keys poisoned").clone()
+    }
+
+    /// Read the clock and draw token IDs from `determinism`.
+    pub fn with_determinism(mut self, determinism: Arc<Determinism>) -> Self {
+        self.determinism = determinism;
+        self
+    }
+
+    /// Public keys of the asymmetric keys that have not expired, including
+    /// scheduled ones so verifiers can fetch them ahead of rotation. HMAC
+    /// keys are never published.
+    pub fn jwks(&self) -> JwkSet {
+        let now = self.determinism.now().unwrap_or_default();
+        JwkSet {
+            keys: self
+                .keys()
+                .iter()
+                .filter(|key| !key.schedule.is_expired(now))
+                .filter_map(|key| key.jwk.clone())
+                .collect(),
         }
     }
 
@@ -43,11 +316,86 @@ impl AuthManager {
         let token = header_value
             .strip_prefix("Bearer ")
             .ok_or(AuthError::MissingBearer)?;
+        self.verify_token(token)
+    }
 
-        let token_data = decode::<Claims>(token, &self.decoding_key, &self.validation)
-            .map_err(|err| AuthError::InvalidToken(err.to_string()))?;
+    /// Verify a bare token, e.g. one sent over an established WebSocket.
+    pub fn verify_token(&self, token: &str) -> Result<Claims, AuthError> {
+        let header =
+            decode_header(token).map_err(|err| AuthError::InvalidToken(err.to_string()))?;
+        let now = self.determinism.now().map_err(|_| AuthError::TimeSource)?;
+        // Tokens from before rotation have no `kid`; try every live key.
+        let keys = self.keys();
+        let candidates: Vec<&LoadedKey> = match &header.kid {
+            Some(kid) => {
+                let key = keys
+                    .iter()
+                    .find(|key| &key.schedule.kid == kid)
+                    .ok_or_else(|| AuthError::InvalidToken(format!("unknown key id {}", kid)))?;
+                if key.schedule.is_expired(now) {
+                    return Err(AuthError::InvalidToken(format!(
+                        "signing key {} has expired",
+                        kid
+                    )));
+                }
+                vec![key]
+            }
+            None => keys
+                .iter()
+                .filter(|key| !key.schedule.is_expired(now))
+                .collect(),
+        };
+
+        let mut last_error = AuthError::InvalidToken("no signing key is valid".into());
+        let mut verified = None;
+        for key in candidates {
+            match decode::<Claims>(token, &key.decoding, &key.validation) {
+                Ok(token_data) => {
+                    verified = Some((token_data.claims, key.validation.leeway));
+                    break;
+                }
+                Err(err) => last_error = AuthError::InvalidToken(err.to_string()),
+            }
+        }
+        let (claims, leeway) = verified.ok_or(last_error)?;
+        if (claims.exp as u64).saturating_add(leeway) < now {
+            return Err(AuthError::InvalidToken("ExpiredSignature".into()));
+        }
+        if let Some(jti) = &claims.jti {
+            if self.is_revoked(jti) {
+                return Err(AuthError::Revoked);
+            }
+        }
+        Ok(claims)
+    }
+
+    /// Reject the token with ID `jti`, which expires at `expires_at`, on
+    /// this instance. The caller stores the revocation for the others.
+    pub fn revoke_id(&self, jti: &str, expires_at: u64) {
+        self.restore_revoked([(jti.to_string(), expires_at)]);
+    }
 
-        Ok(token_data.claims)
+    /// Reject every token in `revoked`, as (token ID, expiry) pairs, e.g.
+    /// the revocations read back from storage.
+    pub fn restore_revoked(&self, revoked: impl IntoIterator<Item = (String, u64)>) {
+        let now = self.determinism.now().unwrap_or_default();
+        let mut list = self.revoked.lock().expect("revocation list poisoned");
+        list.retain(|_, expires_at| *expires_at > now);
+        list.extend(revoked);
+    }
+
+    fn is_revoked(&self, jti: &str) -> bool {
+        self.revoked
+            .lock()
+            .expect("revocation list poisoned")
+            .contains_key(jti)
+    }
+
+    /// The key new tokens are signed with at `now`.
+    fn signing_key(keys: &[LoadedKey], now: u64) -> Option<&LoadedKey> {
+        keys.iter()
+            .filter(|key| key.schedule.active_from <= now && !key.schedule.is_expired(now))
+            .max_by_key(|key| key.schedule.active_from)
     }
 
     pub fn issue_token(
@@ -55,13 +403,18 @@ impl AuthManager {
         subject: impl Into<String>,
         ttl: Duration,
         audience: Option<String>,
+        scopes: &[Scope],
+        role: Option<Role>,
     ) -> Result<IssuedToken, AuthError> {
         let ttl = if ttl.is_zero() {
             Duration::from_secs(60)
         } else {
             ttl
         };
-        let now = current_unix_timestamp().map_err(|_| AuthError::TimeSource)?;
+        let now = self.determinism.now().map_err(|_| AuthError::TimeSource)?;
+        let keys = self.keys();
+        let key = Self::signing_key(&keys, now)
+            .ok_or_else(|| AuthError::TokenIssuance("no active signing key".into()))?;
         let expires_at = now + ttl.as_secs();
         let claims = Claims {
             sub: subject.into(),
@@ -69,10 +422,21 @@ impl AuthManager {
             aud: audience,
             iss: Some((*self.issuer).clone()),
             iat: Some(now as usize),
+            jti: Some(self.determinism.random_string(TOKEN_ID_LEN)),
+            scope: Some(format_scopes(scopes)),
+            role,
         };
-        let token = encode(&Header::default(), &claims, &self.encoding_key)
+        let header = Header {
+            kid: Some(key.schedule.kid.clone()),
+            ..Header::new(key.validation.algorithms[0])
+        };
+        let token = encode(&header, &claims, &key.encoding)
             .map_err(|err| AuthError::TokenIssuance(err.to_string()))?;
-        Ok(IssuedToken { token, expires_at })
+        Ok(IssuedToken {
+            token,
+            jti: claims.jti.unwrap_or_default(),
+            expires_at,
+        })
     }
 }
 
@@ -87,16 +451,41 @@ pub struct Claims {
     pub iss: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub iat: Option<usize>,
+    /// Unique token ID, used to revoke a single token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Space-separated [`Scope`]s; a token without one grants none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+
+impl Claims {
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scope
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .any(|granted| granted == scope.as_str())
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.role == Some(Role::Admin)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct IssuedToken {
     pub token: String,
+    pub jti: String,
     pub expires_at: u64,
 }
 
 #[derive(Debug, Error)]
 pub enum AuthError {
+    #[error("authorization header is required")]
+    MissingCredentials,
     #[error("authorization header must use the Bearer scheme")]
     MissingBearer,
     #[error("invalid token: {0}")]
@@ -111,6 +500,12 @@ pub enum AuthError {
     SignatureMismatch,
     #[error("system clock unavailable")]
     TimeSource,
+    #[error("invalid signing key {0}")]
+    InvalidKey(String),
+    #[error("token has been revoked")]
+    Revoked,
+    #[error("unknown scope `{0}`")]
+    UnknownScope(String),
 }
 
 #[derive(Debug)]
@@ -124,6 +519,40 @@ impl fmt::Display for AuthRejection {
     }
 }
 
+/// A valid token without the scope the route requires.
+#[derive(Debug)]
+pub struct ScopeRejection(pub Scope);
+
+impl Reject for ScopeRejection {}
+
+/// A valid token on an operator endpoint that was not issued to an
+/// administrator.
+#[derive(Debug)]
+pub struct AdminRejection;
+
+impl Reject for AdminRejection {}
+
+/// An opaque refresh token and the hash it is stored under.
+#[derive(Debug, Clone)]
+pub struct NewRefreshToken {
+    pub token: String,
+    pub hash: String,
+}
+
+/// Generate a refresh token. Only the hash should be persisted.
+pub fn generate_refresh_token() -> NewRefreshToken {
+    let token = determinism::secret_string(REFRESH_TOKEN_LEN);
+    NewRefreshToken {
+        hash: hash_refresh_token(&token),
+        token,
+    }
+}
+
+/// Hash a refresh token presented by a client for lookup.
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(keccak256(token.as_bytes()))
+}
+
 pub fn clamp_ttl(requested: Option<u64>, default_ttl: u64, max_ttl: u64) -> Duration {
     let default = default_ttl.max(60);
     let max_allowed = max_ttl.max(default);
@@ -131,9 +560,16 @@ pub fn clamp_ttl(requested: Option<u64>, default_ttl: u64, max_ttl: u64) -> Dura
     Duration::from_secs(requested)
 }
 
+/// What a wallet signed: a `personal_sign` message or EIP-712 typed data.
+#[derive(Debug, Clone, Copy)]
+pub enum WalletPayload<'a> {
+    Message(&'a str),
+    TypedData(&'a TypedData),
+}
+
 pub fn verify_wallet_signature(
     address: &str,
-    message: &str,
+    payload: WalletPayload<'_>,
     signature: &str,
 ) -> Result<(), AuthError> {
     let normalized_address = normalize_address(address)?;
@@ -141,10 +577,11 @@ pub fn verify_wallet_signature(
     let signature = signature.trim_start_matches("0x");
     let sig = Signature::from_str(signature)
         .map_err(|err| AuthError::InvalidSignature(err.to_string()))?;
-    let hash = hash_message(message);
-    let recovered = sig
-        .recover(hash)
-        .map_err(|err| AuthError::InvalidSignature(err.to_string()))?;
+    let recovered = match payload {
+        WalletPayload::Message(message) => sig.recover(hash_message(message)),
+        WalletPayload::TypedData(typed_data) => sig.recover_typed_data(typed_data),
+    }
+    .map_err(|err| AuthError::InvalidSignature(err.to_string()))?;
     if recovered != target {
         return Err(AuthError::SignatureMismatch);
     }
@@ -162,8 +599,199 @@ pub fn normalize_address(input: &str) -> Result<String, AuthError> {
     Ok(trimmed)
 }
 
-fn current_unix_timestamp() -> Result<u64, std::time::SystemTimeError> {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|duration| duration.as_secs())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(kid: &str, active_from: u64, expires_at: Option<u64>) -> SigningKey {
+        SigningKey {
+            kid: kid.into(),
+            material: KeyMaterial::Hmac(SecretString::from(format!("{}-secret", kid))),
+            active_from,
+            expires_at,
+        }
+    }
+
+    fn kid_of(token: &str) -> Option<String> {
+        decode_header(token).unwrap().kid
+    }
+
+    #[test]
+    fn rotated_keys_sign_newest_and_verify_until_expiry() {
+        let now = Determinism::live().now().unwrap();
+        let ttl = Duration::from_secs(60);
+        let old_only = AuthManager::with_keys(vec![key("old", 0, None)], "test").unwrap();
+        let old_token = old_only
+            .issue_token("alice", ttl, None, DEFAULT_SCOPES, None)
+            .unwrap()
+            .token;
+        assert_eq!(kid_of(&old_token).as_deref(), Some("old"));
+        // Tokens minted before rotation carry no `kid`.
+        let legacy = encode(
+            &Header::default(),
+            &Claims {
+                sub: "bob".into(),
+                exp: (now + 60) as usize,
+                aud: None,
+                iss: None,
+                iat: None,
+                jti: None,
+                scope: None,
+                role: None,
+            },
+            &EncodingKey::from_secret(b"old-secret"),
+        )
+        .unwrap();
+
+        let rotated = AuthManager::with_keys(
+            vec![
+                key("old", 0, None),
+                key("new", now - 10, None),
+                key("next", now + 3600, None),
+            ],
+            "test",
+        )
+        .unwrap();
+        let new_token = rotated
+            .issue_token("alice", ttl, None, DEFAULT_SCOPES, None)
+            .unwrap()
+            .token;
+        assert_eq!(kid_of(&new_token).as_deref(), Some("new"));
+        assert_eq!(rotated.verify_token(&old_token).unwrap().sub, "alice");
+        assert_eq!(rotated.verify_token(&legacy).unwrap().sub, "bob");
+        assert!(old_only.verify_token(&new_token).is_err());
+
+        let retired = AuthManager::with_keys(
+            vec![key("old", 0, Some(now - 1)), key("new", now - 10, None)],
+            "test",
+        )
+        .unwrap();
+        assert!(retired.verify_token(&new_token).is_ok());
+        assert!(matches!(
+            retired.verify_token(&old_token),
+            Err(AuthError::InvalidToken(_))
+        ));
+        assert!(retired.verify_token(&legacy).is_err());
+    }
+
+    #[test]
+    fn replaced_keys_reach_every_clone() {
+        let now = Determinism::live().now().unwrap();
+        let ttl = Duration::from_secs(60);
+        let auth = AuthManager::with_keys(vec![key("old", 0, None)], "test").unwrap();
+        let clone = auth.clone();
+        let old_token = clone
+            .issue_token("alice", ttl, None, DEFAULT_SCOPES, None)
+            .unwrap()
+            .token;
+
+        auth.replace_keys(vec![key("old", 0, None), key("new", now - 10, None)])
+            .unwrap();
+        let new_token = clone
+            .issue_token("alice", ttl, None, DEFAULT_SCOPES, None)
+            .unwrap()
+            .token;
+        assert_eq!(kid_of(&new_token).as_deref(), Some("new"));
+        assert!(clone.verify_token(&old_token).is_ok());
+
+        auth.replace_keys(vec![key("new", now - 10, None)]).unwrap();
+        assert!(clone.verify_token(&old_token).is_err());
+        assert!(clone.verify_token(&new_token).is_ok());
+    }
+
+    #[test]
+    fn revoked_tokens_are_rejected_until_they_expire() {
+        let determinism = Arc::new(Determinism::seeded(7));
+        let auth = AuthManager::with_keys(vec![key("k", 0, None)], "test")
+            .unwrap()
+            .with_determinism(determinism.clone());
+        let issue = |ttl: u64| {
+            auth.issue_token(
+                "alice",
+                Duration::from_secs(ttl),
+                None,
+                DEFAULT_SCOPES,
+                None,
+            )
+            .unwrap()
+        };
+        let first = issue(600);
+        let second = issue(60);
+        auth.restore_revoked([(first.jti.clone(), first.expires_at)]);
+        assert!(matches!(
+            auth.verify_token(&first.token),
+            Err(AuthError::Revoked)
+        ));
+        assert!(auth.verify_token(&second.token).is_ok());
+
+        // Entries are dropped once their token has expired.
+        determinism.advance(601);
+        auth.revoke_id(&second.jti, second.expires_at);
+        assert!(!auth.revoked.lock().unwrap().contains_key(&first.jti));
+    }
+
+    #[test]
+    fn asymmetric_keys_are_published_as_jwks() {
+        let pem = |raw: &str| SecretString::from(raw.to_string());
+        let keys = vec![
+            key("hmac", 0, None),
+            SigningKey {
+                kid: "rsa".into(),
+                material: KeyMaterial::Rsa(pem(include_str!("../testdata/jwt-rs256.pem"))),
+                active_from: 1,
+                expires_at: None,
+            },
+            SigningKey {
+                kid: "ed".into(),
+                material: KeyMaterial::Ed25519(pem(include_str!("../testdata/jwt-ed25519.pem"))),
+                active_from: 2,
+                expires_at: None,
+            },
+        ];
+        let signer = AuthManager::with_keys(keys.clone(), "test").unwrap();
+        let token = signer
+            .issue_token("alice", Duration::from_secs(60), None, DEFAULT_SCOPES, None)
+            .unwrap()
+            .token;
+        let header = decode_header(&token).unwrap();
+        assert_eq!(header.alg, Algorithm::EdDSA);
+        assert_eq!(header.kid.as_deref(), Some("ed"));
+        assert_eq!(signer.verify_token(&token).unwrap().sub, "alice");
+
+        let jwks = signer.jwks();
+        let kids: Vec<_> = jwks
+            .keys
+            .iter()
+            .map(|jwk| jwk.common.key_id.as_deref().unwrap())
+            .collect();
+        assert_eq!(kids, ["rsa", "ed"]);
+        // A verifier holding only the published key accepts the token.
+        let published = DecodingKey::from_jwk(jwks.find("ed").unwrap()).unwrap();
+        let claims = decode::<Claims>(&token, &published, &Validation::new(Algorithm::EdDSA))
+            .unwrap()
+            .claims;
+        assert_eq!(claims.sub, "alice");
+
+        // Before the Ed25519 key, RSA signs.
+        let rsa_only = AuthManager::with_keys(keys[..2].to_vec(), "test").unwrap();
+        let token = rsa_only
+            .issue_token("bob", Duration::from_secs(60), None, DEFAULT_SCOPES, None)
+            .unwrap()
+            .token;
+        assert_eq!(decode_header(&token).unwrap().alg, Algorithm::RS256);
+        let published = DecodingKey::from_jwk(jwks.find("rsa").unwrap()).unwrap();
+        assert!(decode::<Claims>(&token, &published, &Validation::new(Algorithm::RS256)).is_ok());
+        assert_eq!(signer.verify_token(&token).unwrap().sub, "bob");
+
+        let broken = SigningKey {
+            kid: "broken".into(),
+            material: KeyMaterial::Rsa(pem("not a key")),
+            active_from: 0,
+            expires_at: None,
+        };
+        assert!(matches!(
+            AuthManager::with_keys(vec![broken], "test"),
+            Err(AuthError::InvalidKey(_))
+        ));
+    }
 }
@@ -1,10 +1,10 @@
 use clap::Parser;
 use dex_api::{
-    auth::{clamp_ttl, AuthManager},
+    auth::{clamp_ttl, format_scopes, parse_scopes, AuthManager, Role, DEFAULT_SCOPES},
     Config,
 };
 
-/// Utility binary that mints JWTs using the same secret/config as the API server.
+/// Utility binary that mints JWTs using the same keys/config as the API server.
 #[derive(Debug, Parser)]
 #[command(author, version, about = "Issue short-lived JWTs for DEX-OS traders", long_about = None)]
 struct Args {
@@ -17,19 +17,36 @@ struct Args {
     /// Optional audience claim
     #[arg(long)]
     audience: Option<String>,
+    /// Space-separated scopes, e.g. "read trade"; defaults to read and trade
+    #[arg(long)]
+    scope: Option<String>,
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
-    let config = Config::from_env()?;
-    let auth = AuthManager::new(&config.jwt_secret, config.jwt_issuer.clone());
+    let config = Config::load().await?;
+    let auth = AuthManager::with_keys(config.jwt_keys.clone(), config.jwt_issuer.clone())?;
     let ttl = clamp_ttl(
         args.ttl_seconds,
         config.jwt_default_ttl_seconds,
         config.jwt_max_ttl_seconds,
     );
-    let issued = auth.issue_token(args.trader_id, ttl, args.audience)?;
+    let scopes = match args.scope {
+        Some(raw) => parse_scopes(&raw)?,
+        None => DEFAULT_SCOPES.to_vec(),
+    };
+    // Same rule as the sign-in endpoints: only ADMIN_SUBJECTS get the role.
+    let role = config
+        .admin_subjects
+        .contains(&args.trader_id)
+        .then_some(Role::Admin);
+    let issued = auth.issue_token(args.trader_id, ttl, args.audience, &scopes, role)?;
     println!("token={}", issued.token);
     println!("expires_at={}", issued.expires_at);
+    println!("scope={}", format_scopes(&scopes));
+    if role.is_some() {
+        println!("role=admin");
+    }
     Ok(())
 }
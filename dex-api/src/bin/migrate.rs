@@ -0,0 +1,63 @@
+use clap::Parser;
+use dex_api::{config::StorageBackend, Config};
+use dex_db::{sqlite::SqliteStorage, DatabaseManager};
+use secrecy::ExposeSecret;
+
+/// Applies pending schema migrations to the configured database, or lists
+/// them with `--dry-run`. The server applies them on boot as well; this lets
+/// a deploy check or run them first.
+#[derive(Debug, Parser)]
+#[command(author, version, about = "Apply or list pending DEX-OS schema migrations", long_about = None)]
+struct Args {
+    /// Print pending migrations without applying them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// The database whose schema is migrated.
+enum Schema {
+    Postgres(DatabaseManager),
+    Sqlite(SqliteStorage),
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args = Args::parse();
+    let (config, _) = Config::load_with_secrets().await?;
+    let (schema, pending) = match &config.storage {
+        StorageBackend::Postgres(url) => {
+            let database =
+                DatabaseManager::connect_with(url.expose_secret(), config.db_pool).await?;
+            let pending = database.pending_migrations().await?;
+            (Schema::Postgres(database), pending)
+        }
+        StorageBackend::Sqlite(path) => {
+            let storage = SqliteStorage::open(path).await?;
+            let pending = storage.pending_migrations().await?;
+            (Schema::Sqlite(storage), pending)
+        }
+        StorageBackend::Memory => {
+            return Err("STORAGE_BACKEND is memory; there is no schema to migrate".into());
+        }
+    };
+    for migration in &pending {
+        println!(
+            "{} {} {}",
+            migration.version,
+            &migration.checksum()[..12],
+            migration.description
+        );
+    }
+    if pending.is_empty() {
+        println!("schema is up to date");
+    } else if args.dry_run {
+        println!("{} pending, not applied (dry run)", pending.len());
+    } else {
+        match schema {
+            Schema::Postgres(database) => database.initialize().await?,
+            Schema::Sqlite(storage) => storage.initialize().await?,
+        }
+        println!("applied {}", pending.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,1428 @@
+//! Fast engine restarts from local book snapshots.
+//!
+//! With `BOOK_SNAPSHOT_DIR` set, every order the book accepts or cancels is
+//! appended to a write-ahead log, and the whole book is checkpointed to
+//! `snapshot.json` every `BOOK_SNAPSHOT_INTERVAL_SECONDS`, starting a new log
+//! segment. On startup the snapshot and the log written after it rebuild the
+//! book without scanning the order and trade tables, provided the order and
+//! trade sequence they end at agrees with the database. Otherwise, or without
+//! a snapshot, the book is rebuilt from Postgres.
+//!
+//! Log records are numbered. Segments also roll over once they reach
+//! `BOOK_JOURNAL_SEGMENT_BYTES`, and with `BOOK_JOURNAL_ARCHIVE_DIR` set the
+//! segments a snapshot covers are compressed there with zstd instead of
+//! deleted, listed in `index.jsonl` by the record numbers they hold, so
+//! [`replay_from`] can replay the full history from any record.
+//!
+//! Deep books can be checkpointed in bands. With `BOOK_FULL_SNAPSHOT_EVERY`
+//! above 1, only every that many checkpoints writes the whole book; the ones
+//! between write `bands.json`, holding the levels near each market's touch in
+//! full and, for the tail beyond, only the orders added, changed or removed
+//! since the last full snapshot. Far levels rarely change, so this stays
+//! small however deep the book is. The band around the touch widens with the
+//! market's recent moves between checkpoints, from
+//! `BOOK_SNAPSHOT_BAND_MIN_BPS` up to `BOOK_SNAPSHOT_BAND_MAX_BPS`. A restart
+//! applies the bands to the full snapshot and checks the result against a
+//! fingerprint of the book taken at the banded checkpoint.
+
+use dex_core::{
+    matching::MatchingRegistry,
+    orderbook::OrderBook,
+    types::{Order, OrderId, OrderSide, Price, Quantity, Trade, TradeId},
+};
+use dex_db::{DatabaseError, OrderRepo, TradeRepo};
+use ethers_core::utils::{hex, keccak256};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::RwLock;
+use zstd::stream::{read::Decoder, write::Encoder};
+
+const SNAPSHOT_VERSION: u32 = 1;
+const SNAPSHOT_FILE: &str = "snapshot.json";
+const BANDS_FILE: &str = "bands.json";
+const INDEX_FILE: &str = "index.jsonl";
+/// Default size at which a log segment is closed and a new one started.
+pub const DEFAULT_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+/// Band half-width, as a multiple of the market's smoothed move between
+/// checkpoints.
+const VOLATILITY_MULTIPLIER: f64 = 4.0;
+/// Weight of the latest move in the smoothed move.
+const VOLATILITY_SMOOTHING: f64 = 0.3;
+
+/// Highest order and trade IDs a book reflects.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sequence {
+    pub last_order_id: OrderId,
+    pub last_trade_id: TradeId,
+}
+
+impl Sequence {
+    /// Whether a book at this sequence has everything the database at
+    /// `database` has. Trades must match exactly; orders may run ahead, since
+    /// the newest orders may have been rejected or cancelled and deleted.
+    fn agrees_with(&self, database: &Sequence) -> bool {
+        self.last_trade_id == database.last_trade_id && self.last_order_id >= database.last_order_id
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    /// First log segment written after the snapshot was taken.
+    segment: u64,
+    sequence: Sequence,
+    /// Hex keccak-256 of the JSON-encoded `orders`.
+    checksum: String,
+    /// Resting orders with their remaining quantity, in queue order.
+    orders: Vec<Order>,
+}
+
+/// A banded checkpoint: the levels near the touch in full, and the rest of
+/// the book as changes to the full snapshot it builds on.
+#[derive(Serialize, Deserialize)]
+struct BandSnapshot {
+    version: u32,
+    /// `segment` of the full snapshot the tail changes apply to.
+    base_segment: u64,
+    /// First log segment written after this checkpoint was taken.
+    segment: u64,
+    sequence: Sequence,
+    /// Band half-width used for each market, in basis points.
+    bands: BTreeMap<String, f64>,
+    /// Hex keccak-256 of the JSON-encoded `near`, `tail` and `removed`.
+    checksum: String,
+    /// [`fingerprint`] of the whole book at this checkpoint.
+    book: String,
+    /// Orders inside the bands, in queue order.
+    near: Vec<Order>,
+    /// Orders outside the bands that are new or have a different quantity
+    /// than in the full snapshot, in queue order.
+    tail: Vec<Order>,
+    /// Orders of the full snapshot that have left the book.
+    removed: Vec<OrderId>,
+}
+
+impl BandSnapshot {
+    fn content_checksum(&self) -> Result<String, serde_json::Error> {
+        Ok(hex::encode(keccak256(serde_json::to_vec(&(
+            &self.near,
+            &self.tail,
+            &self.removed,
+        ))?)))
+    }
+}
+
+enum Checkpoint {
+    Full(Snapshot),
+    Banded(BandSnapshot),
+}
+
+impl Checkpoint {
+    fn segment(&self) -> u64 {
+        match self {
+            Checkpoint::Full(snapshot) => snapshot.segment,
+            Checkpoint::Banded(bands) => bands.segment,
+        }
+    }
+}
+
+/// A book change in the write-ahead log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum JournalEntry {
+    /// An order as submitted, and how many trades matching it produced.
+    Add {
+        order: Order,
+        trades: u64,
+    },
+    Cancel {
+        order_id: OrderId,
+    },
+    /// Trades were stored, the highest with this ID. Logged apart from the
+    /// order, since trades are given IDs once the book has moved on, and the
+    /// IDs the database hands out may skip values.
+    Trades {
+        last_trade_id: TradeId,
+    },
+}
+
+/// One write-ahead log line: a change and its position in the log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalRecord {
+    /// Numbers records across segments and restarts, starting at 1. Records
+    /// logged before numbering was introduced read as 0.
+    #[serde(default)]
+    pub seq: u64,
+    #[serde(flatten)]
+    pub entry: JournalEntry,
+}
+
+/// Archive index line: the records an archived segment holds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct IndexEntry {
+    segment: u64,
+    first_seq: u64,
+    last_seq: u64,
+}
+
+/// How the journal rotates and retires log segments.
+#[derive(Debug, Clone)]
+pub struct JournalOptions {
+    /// Start a new segment once the current one reaches this size.
+    pub segment_bytes: u64,
+    /// Keep segments covered by a snapshot here rather than deleting them.
+    pub archive_dir: Option<PathBuf>,
+    pub bands: BandOptions,
+}
+
+impl Default for JournalOptions {
+    fn default() -> Self {
+        Self {
+            segment_bytes: DEFAULT_SEGMENT_BYTES,
+            archive_dir: None,
+            bands: BandOptions::default(),
+        }
+    }
+}
+
+/// How often the whole book is checkpointed, and how wide the bands of the
+/// checkpoints between are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BandOptions {
+    /// Write the whole book every this many checkpoints; 1 writes every one
+    /// in full.
+    pub full_every: u32,
+    /// Narrowest band half-width around the touch, in basis points.
+    pub min_bps: u32,
+    /// Widest band half-width around the touch, in basis points.
+    pub max_bps: u32,
+}
+
+impl Default for BandOptions {
+    fn default() -> Self {
+        Self {
+            full_every: 1,
+            min_bps: 50,
+            max_bps: 2000,
+        }
+    }
+}
+
+/// A market's mid price at the last checkpoint and its smoothed move
+/// between checkpoints.
+#[derive(Debug, Clone, Copy)]
+struct Volatility {
+    mid: f64,
+    move_bps: f64,
+}
+
+/// Best bid and ask of each market in `orders`.
+fn touches(orders: &[Order]) -> HashMap<String, (Option<Price>, Option<Price>)> {
+    let mut touches: HashMap<String, (Option<Price>, Option<Price>)> = HashMap::new();
+    for order in orders {
+        let Some(price) = order.price else {
+            continue;
+        };
+        let (bid, ask) = touches.entry(order.pair.to_string()).or_default();
+        match order.side {
+            OrderSide::Buy => *bid = Some(bid.map_or(price, |bid| bid.max(price))),
+            OrderSide::Sell => *ask = Some(ask.map_or(price, |ask| ask.min(price))),
+        }
+    }
+    touches
+}
+
+/// Whether a resting order lies within `width_bps` of its side's touch.
+fn in_band(order: &Order, touch: (Option<Price>, Option<Price>), width_bps: f64) -> bool {
+    let (Some(price), (bid, ask)) = (order.price, touch) else {
+        return false;
+    };
+    let width = width_bps / 10_000.0;
+    match order.side {
+        OrderSide::Buy => bid.is_some_and(|bid| price as f64 >= bid as f64 * (1.0 - width)),
+        OrderSide::Sell => ask.is_some_and(|ask| price as f64 <= ask as f64 * (1.0 + width)),
+    }
+}
+
+/// Band half-width of each market in `touches`, in basis points, after
+/// folding its move since the last checkpoint into its volatility.
+fn band_widths(
+    volatility: &mut HashMap<String, Volatility>,
+    touches: &HashMap<String, (Option<Price>, Option<Price>)>,
+    options: &BandOptions,
+) -> BTreeMap<String, f64> {
+    volatility.retain(|pair, _| touches.contains_key(pair));
+    let (min, max) = (f64::from(options.min_bps), f64::from(options.max_bps));
+    touches
+        .iter()
+        .map(|(pair, touch)| {
+            let mid = match *touch {
+                (Some(bid), Some(ask)) => (bid as f64 + ask as f64) / 2.0,
+                (Some(price), None) | (None, Some(price)) => price as f64,
+                (None, None) => 0.0,
+            };
+            let market = volatility
+                .entry(pair.clone())
+                .and_modify(|market| {
+                    if market.mid > 0.0 {
+                        let moved = (mid - market.mid).abs() / market.mid * 10_000.0;
+                        market.move_bps = VOLATILITY_SMOOTHING * moved
+                            + (1.0 - VOLATILITY_SMOOTHING) * market.move_bps;
+                    }
+                    market.mid = mid;
+                })
+                .or_insert(Volatility { mid, move_bps: 0.0 });
+            let width = (VOLATILITY_MULTIPLIER * market.move_bps).clamp(min, max.max(min));
+            (pair.clone(), width)
+        })
+        .collect()
+}
+
+/// Cheap digest of a book's orders and remaining quantities in queue order.
+fn fingerprint(orders: &[Order]) -> String {
+    let mut bytes = Vec::with_capacity(orders.len() * 24);
+    for order in orders {
+        bytes.extend_from_slice(&order.id.to_le_bytes());
+        bytes.extend_from_slice(&order.price.unwrap_or_default().to_le_bytes());
+        bytes.extend_from_slice(&order.quantity.to_le_bytes());
+    }
+    hex::encode(keccak256(bytes))
+}
+
+/// The book a banded checkpoint describes: the full snapshot's orders that
+/// are still in the tail, with their current quantities, then the tail
+/// orders added since, then the orders near the touch. A level lies wholly
+/// inside or outside a band, and orders added to a tail level since the full
+/// snapshot queue behind the ones it holds, so every level keeps its order.
+fn apply_bands(base: Vec<Order>, bands: &BandSnapshot) -> Vec<Order> {
+    let removed: HashSet<OrderId> = bands.removed.iter().copied().collect();
+    let near: HashSet<OrderId> = bands.near.iter().map(|order| order.id).collect();
+    let mut changed: HashMap<OrderId, &Order> =
+        bands.tail.iter().map(|order| (order.id, order)).collect();
+    let mut orders: Vec<Order> = base
+        .into_iter()
+        .filter(|order| !removed.contains(&order.id) && !near.contains(&order.id))
+        .map(|order| match changed.remove(&order.id) {
+            Some(current) => current.clone(),
+            None => order,
+        })
+        .collect();
+    orders.extend(
+        bands
+            .tail
+            .iter()
+            .filter(|order| changed.contains_key(&order.id))
+            .cloned(),
+    );
+    orders.extend(bands.near.iter().cloned());
+    orders
+}
+
+/// Why a snapshot could not be used.
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("no snapshot")]
+    Missing,
+    #[error("i/o error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed snapshot or log: {0}")]
+    Format(#[from] serde_json::Error),
+    #[error("unsupported snapshot version {0}")]
+    Version(u32),
+    #[error("snapshot checksum mismatch")]
+    Checksum,
+    #[error("log replay diverged: {0}")]
+    Replay(String),
+    #[error("snapshot ends at {snapshot:?} but the database is at {database:?}")]
+    Stale {
+        snapshot: Sequence,
+        database: Sequence,
+    },
+}
+
+/// Where the starting book came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarmStartSource {
+    Snapshot,
+    Postgres,
+}
+
+/// The book and ID sequence to start the engine from.
+pub struct WarmStart {
+    pub orderbook: OrderBook,
+    pub sequence: Sequence,
+    pub source: WarmStartSource,
+}
+
+/// Load the book from the snapshot in `dir` when it agrees with the
+/// database, falling back to rebuilding it from the orders and trades tables.
+/// The log is replayed with the pairs' `matching` policies, so it only agrees
+/// when they are the ones it was written under.
+pub async fn warm_start(
+    dir: Option<&Path>,
+    orders: &dyn OrderRepo,
+    trades: &dyn TradeRepo,
+    matching: &MatchingRegistry,
+) -> Result<WarmStart, DatabaseError> {
+    let database = Sequence {
+        last_order_id: orders.last_order_id().await?,
+        last_trade_id: trades.last_trade_id().await?,
+    };
+    if let Some(dir) = dir {
+        let loaded = load(dir, matching).and_then(|(orderbook, snapshot)| {
+            if snapshot.agrees_with(&database) {
+                Ok((orderbook, snapshot))
+            } else {
+                Err(SnapshotError::Stale { snapshot, database })
+            }
+        });
+        match loaded {
+            Ok((orderbook, snapshot)) => {
+                return Ok(WarmStart {
+                    orderbook,
+                    sequence: Sequence {
+                        last_order_id: snapshot.last_order_id,
+                        last_trade_id: database.last_trade_id,
+                    },
+                    source: WarmStartSource::Snapshot,
+                })
+            }
+            Err(SnapshotError::Missing) => {}
+            Err(err) => tracing::warn!(
+                error = %err,
+                "book snapshot not used, rebuilding from Postgres"
+            ),
+        }
+    }
+
+    let mut orderbook = OrderBook::with_matching(matching.clone());
+    for order in orders.load_open_orders().await? {
+        let order_id = order.id;
+        if let Err(err) = orderbook.restore_order(order) {
+            tracing::error!(order_id, error = ?err, "failed to restore order");
+        }
+    }
+    Ok(WarmStart {
+        orderbook,
+        sequence: database,
+        source: WarmStartSource::Postgres,
+    })
+}
+
+/// Rebuild the book from the snapshot, the banded checkpoint built on it if
+/// any, and the log segments after them.
+fn load(dir: &Path, matching: &MatchingRegistry) -> Result<(OrderBook, Sequence), SnapshotError> {
+    let raw = match fs::read(dir.join(SNAPSHOT_FILE)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(SnapshotError::Missing),
+        raw => raw?,
+    };
+    let snapshot: Snapshot = serde_json::from_slice(&raw)?;
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::Version(snapshot.version));
+    }
+    if checksum(&snapshot.orders)? != snapshot.checksum {
+        return Err(SnapshotError::Checksum);
+    }
+
+    let (orders, mut sequence, first_segment, book) = match read_bands(dir, snapshot.segment)? {
+        Some(bands) => (
+            apply_bands(snapshot.orders, &bands),
+            bands.sequence,
+            bands.segment,
+            Some(bands.book),
+        ),
+        None => (snapshot.orders, snapshot.sequence, snapshot.segment, None),
+    };
+
+    let mut orderbook = OrderBook::with_matching(matching.clone());
+    for order in orders {
+        orderbook
+            .restore_order(order)
+            .map_err(|err| SnapshotError::Replay(err.to_string()))?;
+    }
+    if book.is_some_and(|book| fingerprint(&orderbook.resting_orders()) != book) {
+        return Err(SnapshotError::Checksum);
+    }
+    let mut next_segment = first_segment;
+    for segment in segments(dir)? {
+        if segment < first_segment {
+            continue;
+        }
+        // Segments a checkpoint covers are deleted, so a gap means the log
+        // cannot bring this checkpoint up to date.
+        if segment != next_segment {
+            return Err(SnapshotError::Replay(format!(
+                "log segment {} is missing",
+                next_segment
+            )));
+        }
+        next_segment += 1;
+        for record in read_segment(&segment_path(dir, segment))? {
+            match record?.entry {
+                JournalEntry::Add { order, trades } => {
+                    let order_id = order.id;
+                    sequence.last_order_id = sequence.last_order_id.max(order_id);
+                    let produced = orderbook
+                        .add_order(order)
+                        .map_err(|err| SnapshotError::Replay(err.to_string()))?
+                        .len() as u64;
+                    if produced != trades {
+                        return Err(SnapshotError::Replay(format!(
+                            "order {} produced {} trades, {} were logged",
+                            order_id, produced, trades
+                        )));
+                    }
+                }
+                JournalEntry::Cancel { order_id } => {
+                    orderbook.remove_order(order_id).map_err(|err| {
+                        SnapshotError::Replay(format!("cancel of order {}: {}", order_id, err))
+                    })?;
+                }
+                JournalEntry::Trades { last_trade_id } => {
+                    sequence.last_trade_id = sequence.last_trade_id.max(last_trade_id);
+                }
+            }
+        }
+    }
+    Ok((orderbook, sequence))
+}
+
+/// The banded checkpoint built on the full snapshot starting at
+/// `base_segment`, if there is one.
+fn read_bands(dir: &Path, base_segment: u64) -> Result<Option<BandSnapshot>, SnapshotError> {
+    let raw = match fs::read(dir.join(BANDS_FILE)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        raw => raw?,
+    };
+    let bands: BandSnapshot = serde_json::from_slice(&raw)?;
+    if bands.version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::Version(bands.version));
+    }
+    // Left over from before the full snapshot replaced its base.
+    if bands.base_segment != base_segment {
+        return Ok(None);
+    }
+    if bands.content_checksum()? != bands.checksum {
+        return Err(SnapshotError::Checksum);
+    }
+    Ok(Some(bands))
+}
+
+fn checksum(orders: &[Order]) -> Result<String, serde_json::Error> {
+    Ok(hex::encode(keccak256(serde_json::to_vec(orders)?)))
+}
+
+fn segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("wal-{:020}.jsonl", segment))
+}
+
+fn compressed_segment_path(dir: &Path, segment: u64) -> PathBuf {
+    dir.join(format!("wal-{:020}.jsonl.zst", segment))
+}
+
+/// Path of an archived segment: compressed, unless it was archived before
+/// archives were compressed.
+fn archived_segment_path(archive_dir: &Path, segment: u64) -> PathBuf {
+    let path = compressed_segment_path(archive_dir, segment);
+    if path.exists() {
+        path
+    } else {
+        segment_path(archive_dir, segment)
+    }
+}
+
+/// The records of one log segment, in order, decompressed when its name
+/// ends in `.zst`.
+fn read_segment(
+    path: &Path,
+) -> io::Result<impl Iterator<Item = Result<JournalRecord, SnapshotError>>> {
+    let file = File::open(path)?;
+    let log: Box<dyn Read> = match path.extension() {
+        Some(extension) if extension == "zst" => Box::new(Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+    Ok(BufReader::new(log)
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?)))
+}
+
+/// Number of the last record in `dir`'s live segments or its archive.
+fn last_seq(dir: &Path, archive_dir: Option<&Path>) -> Result<u64, SnapshotError> {
+    for segment in segments(dir)?.into_iter().rev() {
+        if let Some(record) = read_segment(&segment_path(dir, segment))?.last() {
+            return Ok(record?.seq);
+        }
+    }
+    Ok(match archive_dir {
+        Some(archive_dir) => read_index(archive_dir)?
+            .iter()
+            .map(|entry| entry.last_seq)
+            .max()
+            .unwrap_or(0),
+        None => 0,
+    })
+}
+
+fn read_index(archive_dir: &Path) -> Result<Vec<IndexEntry>, SnapshotError> {
+    let index = match File::open(archive_dir.join(INDEX_FILE)) {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        index => BufReader::new(index?),
+    };
+    index
+        .lines()
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Compress a segment covered by a snapshot into the archive, index it and
+/// drop the live copy. Empty segments are dropped.
+///
+/// The compressed copy is complete before the live one goes, so a crash
+/// in between leaves the segment to be archived again at the next
+/// checkpoint.
+fn archive(dir: &Path, archive_dir: &Path, segment: u64) -> Result<(), SnapshotError> {
+    let path = segment_path(dir, segment);
+    let mut records = read_segment(&path)?;
+    let Some(first) = records.next() else {
+        fs::remove_file(path)?;
+        return Ok(());
+    };
+    let first_seq = first?.seq;
+    let last_seq = match records.last() {
+        Some(last) => last?.seq,
+        None => first_seq,
+    };
+    let archived = compressed_segment_path(archive_dir, segment);
+    let tmp = archived.with_extension("zst.tmp");
+    let mut encoder = Encoder::new(File::create(&tmp)?, zstd::DEFAULT_COMPRESSION_LEVEL)?;
+    io::copy(&mut File::open(&path)?, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    fs::rename(&tmp, &archived)?;
+    fs::remove_file(&path)?;
+    let mut line = serde_json::to_vec(&IndexEntry {
+        segment,
+        first_seq,
+        last_seq,
+    })?;
+    line.push(b'\n');
+    let mut index = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(archive_dir.join(INDEX_FILE))?;
+    index.write_all(&line)?;
+    index.sync_all()?;
+    Ok(())
+}
+
+/// Feed every logged record numbered `from` or later to `apply`, oldest
+/// first, from the archive and then the live log. Returns how many were
+/// replayed.
+pub fn replay_from(
+    dir: &Path,
+    archive_dir: Option<&Path>,
+    from: u64,
+    mut apply: impl FnMut(JournalRecord),
+) -> Result<u64, SnapshotError> {
+    let mut paths = Vec::new();
+    if let Some(archive_dir) = archive_dir {
+        // The index finds the first segment worth opening; segments archived
+        // after it are read whether or not their index line was written.
+        let start = read_index(archive_dir)?
+            .iter()
+            .filter(|entry| entry.last_seq >= from)
+            .map(|entry| entry.segment)
+            .min();
+        if let Some(start) = start {
+            for segment in segments(archive_dir)? {
+                if segment >= start {
+                    paths.push(archived_segment_path(archive_dir, segment));
+                }
+            }
+        }
+    }
+    for segment in segments(dir)? {
+        paths.push(segment_path(dir, segment));
+    }
+
+    let mut replayed = 0;
+    for path in paths {
+        for record in read_segment(&path)? {
+            let record = record?;
+            if record.seq >= from {
+                apply(record);
+                replayed += 1;
+            }
+        }
+    }
+    Ok(replayed)
+}
+
+/// Log segment numbers in `dir`, compressed or not, ascending.
+fn segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut segments: Vec<u64> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let name = name.strip_suffix(".zst").unwrap_or(&name);
+            name.strip_prefix("wal-")?
+                .strip_suffix(".jsonl")?
+                .parse()
+                .ok()
+        })
+        .collect();
+    segments.sort_unstable();
+    segments.dedup();
+    Ok(segments)
+}
+
+fn open_segment(dir: &Path, segment: u64) -> io::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(segment_path(dir, segment))
+}
+
+fn write_atomically(dir: &Path, name: &str, contents: &[u8]) -> io::Result<()> {
+    let tmp = dir.join(format!("{}.tmp", name));
+    let mut file = File::create(&tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, dir.join(name))
+}
+
+/// Write the checkpoint atomically, then archive or drop the segments it
+/// covers. A full snapshot retires the banded checkpoint built on the one
+/// before it.
+fn persist(
+    dir: &Path,
+    archive_dir: Option<&Path>,
+    checkpoint: &Checkpoint,
+) -> Result<(), SnapshotError> {
+    match checkpoint {
+        Checkpoint::Full(snapshot) => {
+            write_atomically(dir, SNAPSHOT_FILE, &serde_json::to_vec(snapshot)?)?;
+            match fs::remove_file(dir.join(BANDS_FILE)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            }
+        }
+        Checkpoint::Banded(bands) => {
+            write_atomically(dir, BANDS_FILE, &serde_json::to_vec(bands)?)?;
+        }
+    }
+    for segment in segments(dir)? {
+        if segment >= checkpoint.segment() {
+            continue;
+        }
+        match archive_dir {
+            Some(archive_dir) => archive(dir, archive_dir, segment)?,
+            None => fs::remove_file(segment_path(dir, segment))?,
+        }
+    }
+    Ok(())
+}
+
+struct JournalState {
+    segment: u64,
+    log: File,
+    /// Bytes written to the current segment.
+    written: u64,
+    /// Number of the last record written.
+    seq: u64,
+    sequence: Sequence,
+    /// Checkpoints taken since the journal was opened.
+    checkpoints: u64,
+    /// `segment` of the last full snapshot.
+    base_segment: u64,
+    /// Remaining quantity of each order in the last full snapshot.
+    base: HashMap<OrderId, Quantity>,
+    volatility: HashMap<String, Volatility>,
+}
+
+/// Write-ahead log of book changes and the snapshots that compact it.
+pub struct BookJournal {
+    dir: PathBuf,
+    options: JournalOptions,
+    state: Mutex<JournalState>,
+}
+
+impl BookJournal {
+    /// Start journaling into `dir` from a book at `sequence`, checkpointing
+    /// it straight away. Record numbers continue from the existing log.
+    pub fn open(
+        dir: &Path,
+        orderbook: &OrderBook,
+        sequence: Sequence,
+        options: JournalOptions,
+    ) -> Result<Self, SnapshotError> {
+        fs::create_dir_all(dir)?;
+        if let Some(archive_dir) = &options.archive_dir {
+            fs::create_dir_all(archive_dir)?;
+        }
+        // Segment numbers must keep rising past archived ones, or replay
+        // would read them out of order.
+        let mut existing = segments(dir)?;
+        if let Some(archive_dir) = &options.archive_dir {
+            existing.extend(segments(archive_dir)?);
+        }
+        let segment = existing.into_iter().max().map_or(0, |last| last + 1);
+        let journal = Self {
+            dir: dir.to_path_buf(),
+            state: Mutex::new(JournalState {
+                segment,
+                log: open_segment(dir, segment)?,
+                written: 0,
+                seq: last_seq(dir, options.archive_dir.as_deref())?,
+                sequence,
+                checkpoints: 0,
+                base_segment: 0,
+                base: HashMap::new(),
+                volatility: HashMap::new(),
+            }),
+            options,
+        };
+        let checkpoint = journal.rotate(orderbook)?;
+        persist(dir, journal.options.archive_dir.as_deref(), &checkpoint)?;
+        Ok(journal)
+    }
+
+    /// Log an order the book accepted and the number of trades it produced.
+    /// Call while holding the book's write lock, so the log follows the book.
+    /// The trades' IDs are logged once they are stored; see
+    /// [`BookJournal::record_trades`].
+    pub fn record_add(&self, order: &Order, trades: usize) {
+        let entry = JournalEntry::Add {
+            order: order.clone(),
+            trades: trades as u64,
+        };
+        self.append(entry, |sequence| {
+            sequence.last_order_id = sequence.last_order_id.max(order.id);
+        });
+    }
+
+    /// Log that `trades` were stored, so the sequence follows the highest
+    /// trade ID rather than a count.
+    pub fn record_trades(&self, trades: &[Trade]) {
+        let Some(last_trade_id) = trades.iter().map(|trade| trade.id).max() else {
+            return;
+        };
+        self.append(JournalEntry::Trades { last_trade_id }, |sequence| {
+            sequence.last_trade_id = sequence.last_trade_id.max(last_trade_id);
+        });
+    }
+
+    /// Log an order pulled from the book.
+    pub fn record_cancel(&self, order_id: OrderId) {
+        self.append(JournalEntry::Cancel { order_id }, |_| {});
+    }
+
+    fn append(&self, entry: JournalEntry, advance: impl FnOnce(&mut Sequence)) {
+        let mut state = self.state.lock().expect("book journal poisoned");
+        advance(&mut state.sequence);
+        state.seq += 1;
+        let record = JournalRecord {
+            seq: state.seq,
+            entry,
+        };
+        let written = serde_json::to_vec(&record)
+            .map_err(io::Error::from)
+            .and_then(|mut line| {
+                line.push(b'\n');
+                state.log.write_all(&line)?;
+                state.written += line.len() as u64;
+                if state.written >= self.options.segment_bytes {
+                    // Later segments are replayed after this one, so
+                    // rolling over keeps the log in order.
+                    let segment = state.segment + 1;
+                    state.log = open_segment(&self.dir, segment)?;
+                    state.segment = segment;
+                    state.written = 0;
+                }
+                Ok(())
+            });
+        if let Err(err) = written {
+            // The log now misses a change, so the snapshot must not be
+            // trusted; the next restart rebuilds from Postgres instead.
+            tracing::error!(error = %err, "failed to write book journal");
+            let _ = fs::remove_file(self.dir.join(SNAPSHOT_FILE));
+        }
+    }
+
+    /// Capture the book, in full or in bands, and move logging to a new
+    /// segment. The caller holds the book lock, so no change lands between
+    /// the two.
+    fn rotate(&self, orderbook: &OrderBook) -> Result<Checkpoint, SnapshotError> {
+        let mut state = self.state.lock().expect("book journal poisoned");
+        let segment = state.segment + 1;
+        state.log = open_segment(&self.dir, segment)?;
+        state.segment = segment;
+        state.written = 0;
+        let orders = orderbook.resting_orders();
+        let touches = touches(&orders);
+        let bands = band_widths(&mut state.volatility, &touches, &self.options.bands);
+        let full_every = u64::from(self.options.bands.full_every.max(1));
+        let full = state.checkpoints.is_multiple_of(full_every);
+        state.checkpoints += 1;
+
+        if full {
+            state.base_segment = segment;
+            state.base = orders
+                .iter()
+                .map(|order| (order.id, order.quantity))
+                .collect();
+            return Ok(Checkpoint::Full(Snapshot {
+                version: SNAPSHOT_VERSION,
+                segment,
+                sequence: state.sequence,
+                checksum: checksum(&orders)?,
+                orders,
+            }));
+        }
+
+        let book = fingerprint(&orders);
+        let mut present = HashSet::with_capacity(orders.len());
+        let (mut near, mut tail) = (Vec::new(), Vec::new());
+        for order in orders {
+            present.insert(order.id);
+            let pair = order.pair.to_string();
+            if in_band(&order, touches[&pair], bands[&pair]) {
+                near.push(order);
+            } else if state.base.get(&order.id) != Some(&order.quantity) {
+                tail.push(order);
+            }
+        }
+        let mut removed: Vec<OrderId> = state
+            .base
+            .keys()
+            .filter(|order_id| !present.contains(order_id))
+            .copied()
+            .collect();
+        removed.sort_unstable();
+        let mut bands = BandSnapshot {
+            version: SNAPSHOT_VERSION,
+            base_segment: state.base_segment,
+            segment,
+            sequence: state.sequence,
+            bands,
+            checksum: String::new(),
+            book,
+            near,
+            tail,
+            removed,
+        };
+        bands.checksum = bands.content_checksum()?;
+        Ok(Checkpoint::Banded(bands))
+    }
+
+    /// Checkpoint the book. Order entry only waits while the book is copied;
+    /// the file is written afterwards.
+    pub async fn checkpoint(&self, orderbook: &RwLock<OrderBook>) -> Result<(), SnapshotError> {
+        let checkpoint = {
+            let orderbook = orderbook.read().await;
+            self.rotate(&orderbook)?
+        };
+        let dir = self.dir.clone();
+        let archive_dir = self.options.archive_dir.clone();
+        tokio::task::spawn_blocking(move || persist(&dir, archive_dir.as_deref(), &checkpoint))
+            .await
+            .map_err(|err| SnapshotError::Io(io::Error::other(err)))?
+    }
+}
+
+/// Checkpoint the book every `interval`.
+pub fn spawn_checkpoints(
+    journal: Arc<BookJournal>,
+    orderbook: Arc<RwLock<OrderBook>>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The journal was checkpointed when it was opened.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = journal.checkpoint(&orderbook).await {
+                tracing::error!(error = %err, "failed to checkpoint the book");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MemoryStorage;
+    use dex_core::types::{OrderSide, OrderType, Trade};
+    use dex_db::{OrderFill, OrderRepo, OrderStatus, TradeRepo};
+
+    fn order(id: OrderId, side: OrderSide, price: u64, quantity: u64) -> Order {
+        Order {
+            id,
+            trader_id: "alice".parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(price),
+            quantity,
+            timestamp: 1_700_000_000 + id,
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dex-book-snapshot-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// Apply `order` to both the live book and the journal, as `submit_order` does.
+    fn submit(book: &mut OrderBook, journal: &BookJournal, order: Order) -> Vec<Trade> {
+        let trades = book.add_order(order.clone()).unwrap();
+        journal.record_add(&order, trades.len());
+        trades
+    }
+
+    #[tokio::test]
+    async fn restarts_from_snapshot_and_log() {
+        let dir = temp_dir("replay");
+        let storage = MemoryStorage::default();
+        let mut book = OrderBook::new();
+        let journal =
+            BookJournal::open(&dir, &book, Sequence::default(), JournalOptions::default()).unwrap();
+
+        let mut trades = Vec::new();
+        for next in [
+            order(1, OrderSide::Sell, 1000, 5),
+            order(2, OrderSide::Sell, 1010, 5),
+        ] {
+            trades.extend(submit(&mut book, &journal, next));
+        }
+        let shared = RwLock::new(book);
+        journal.checkpoint(&shared).await.unwrap();
+        let mut book = shared.into_inner();
+        trades.extend(submit(
+            &mut book,
+            &journal,
+            order(3, OrderSide::Buy, 1000, 2),
+        ));
+        book.remove_order(2).unwrap();
+        journal.record_cancel(2);
+
+        for (id, trade) in trades.iter_mut().enumerate() {
+            trade.id = id as u64 + 1;
+            storage.trades.lock().unwrap().push(trade.clone());
+        }
+        journal.record_trades(&trades);
+        for id in 1..=3 {
+            storage
+                .orders
+                .lock()
+                .unwrap()
+                .insert(id, order(id, OrderSide::Sell, 1000, 5));
+        }
+
+        let warm = warm_start(Some(&dir), &storage, &storage, &MatchingRegistry::default())
+            .await
+            .unwrap();
+        assert_eq!(warm.source, WarmStartSource::Snapshot);
+        assert_eq!(
+            warm.sequence,
+            Sequence {
+                last_order_id: 3,
+                last_trade_id: 1,
+            }
+        );
+        assert_eq!(warm.orderbook.get_order(1).unwrap().quantity, 3);
+        assert!(warm.orderbook.get_order(2).is_none());
+
+        // A trade the log never saw makes the snapshot stale.
+        let mut extra = trades[0].clone();
+        extra.id = 2;
+        storage.trades.lock().unwrap().push(extra);
+        let warm = warm_start(Some(&dir), &storage, &storage, &MatchingRegistry::default())
+            .await
+            .unwrap();
+        assert_eq!(warm.source, WarmStartSource::Postgres);
+        assert_eq!(warm.sequence.last_trade_id, 2);
+
+        // So does a tampered snapshot.
+        let path = dir.join(SNAPSHOT_FILE);
+        let tampered = fs::read_to_string(&path).unwrap().replace("1010", "1011");
+        fs::write(&path, tampered).unwrap();
+        assert!(matches!(
+            load(&dir, &MatchingRegistry::default()),
+            Err(SnapshotError::Checksum)
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn trade_ids_with_gaps_agree_with_the_database() {
+        let dir = temp_dir("trade-gaps");
+        let storage = MemoryStorage::default();
+        let mut book = OrderBook::new();
+        let journal =
+            BookJournal::open(&dir, &book, Sequence::default(), JournalOptions::default()).unwrap();
+
+        submit(&mut book, &journal, order(1, OrderSide::Sell, 1000, 5));
+        // Sequence values lost to rolled-back inserts leave gaps in the IDs.
+        let mut trades = submit(&mut book, &journal, order(2, OrderSide::Buy, 1000, 2));
+        trades[0].id = 5;
+        journal.record_trades(&trades);
+        storage.trades.lock().unwrap().extend(trades);
+        let shared = RwLock::new(book);
+        journal.checkpoint(&shared).await.unwrap();
+        let mut book = shared.into_inner();
+        let mut trades = submit(&mut book, &journal, order(3, OrderSide::Buy, 1000, 1));
+        trades[0].id = 9;
+        journal.record_trades(&trades);
+        storage.trades.lock().unwrap().extend(trades);
+        for id in 1..=3 {
+            storage
+                .orders
+                .lock()
+                .unwrap()
+                .insert(id, order(id, OrderSide::Sell, 1000, 5));
+        }
+
+        let warm = warm_start(Some(&dir), &storage, &storage, &MatchingRegistry::default())
+            .await
+            .unwrap();
+        assert_eq!(warm.source, WarmStartSource::Snapshot);
+        assert_eq!(
+            warm.sequence,
+            Sequence {
+                last_order_id: 3,
+                last_trade_id: 9,
+            }
+        );
+        assert_eq!(warm.orderbook.get_order(1).unwrap().quantity, 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn order_ids_resume_past_cancelled_orders() {
+        let storage = MemoryStorage::default();
+        storage
+            .orders
+            .lock()
+            .unwrap()
+            .insert(1, order(1, OrderSide::Sell, 1000, 5));
+        // Order 2 was accepted and then cancelled, which deleted its row.
+        storage
+            .receipts
+            .lock()
+            .unwrap()
+            .push(dex_db::SequencingReceipt {
+                sequence: 2,
+                order_id: 2,
+                command_hash: String::new(),
+                previous_hash: String::new(),
+                timestamp: 1_700_000_002,
+                region: "local".to_string(),
+                signature: String::new(),
+            });
+
+        let warm = warm_start(None, &storage, &storage, &MatchingRegistry::default())
+            .await
+            .unwrap();
+        assert_eq!(warm.source, WarmStartSource::Postgres);
+        assert_eq!(warm.sequence.last_order_id, 2);
+        assert_eq!(warm.orderbook.resting_orders().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_open_orders_at_their_remaining_quantity() {
+        let storage = MemoryStorage::default();
+        {
+            let mut orders = storage.orders.lock().unwrap();
+            orders.insert(1, order(1, OrderSide::Sell, 1000, 5));
+            orders.insert(2, order(2, OrderSide::Sell, 1010, 5));
+            orders.insert(3, order(3, OrderSide::Buy, 990, 5));
+        }
+        storage
+            .update_order_fills(&[
+                OrderFill {
+                    order_id: 1,
+                    status: OrderStatus::Filled,
+                    remaining_quantity: 0,
+                },
+                OrderFill {
+                    order_id: 2,
+                    status: OrderStatus::PartiallyFilled,
+                    remaining_quantity: 3,
+                },
+            ])
+            .await
+            .unwrap();
+
+        let warm = warm_start(None, &storage, &storage, &MatchingRegistry::default())
+            .await
+            .unwrap();
+        let mut resting: Vec<_> = warm
+            .orderbook
+            .resting_orders()
+            .iter()
+            .map(|order| (order.id, order.quantity))
+            .collect();
+        resting.sort();
+        assert_eq!(resting, [(2, 3), (3, 5)]);
+    }
+
+    #[tokio::test]
+    async fn rebuilds_open_orders_from_trades_stored_without_fills() {
+        let storage = MemoryStorage::default();
+        {
+            let mut orders = storage.orders.lock().unwrap();
+            orders.insert(1, order(1, OrderSide::Sell, 1000, 5));
+            orders.insert(2, order(2, OrderSide::Buy, 1000, 2));
+            orders.insert(3, order(3, OrderSide::Sell, 1010, 4));
+        }
+        // Trades written by a release that stored fills separately and
+        // crashed before it did.
+        let trade = Trade {
+            id: 1,
+            maker_order_id: 1,
+            taker_order_id: 2,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 1000,
+            quantity: 2,
+            timestamp: 1_700_000_002,
+        };
+        storage.save_trades(&[trade], &[], &[]).await.unwrap();
+
+        let warm = warm_start(None, &storage, &storage, &MatchingRegistry::default())
+            .await
+            .unwrap();
+        let mut resting: Vec<_> = warm
+            .orderbook
+            .resting_orders()
+            .iter()
+            .map(|order| (order.id, order.quantity))
+            .collect();
+        resting.sort();
+        assert_eq!(resting, [(1, 3), (3, 4)]);
+    }
+
+    #[tokio::test]
+    async fn banded_checkpoints_restart_the_same_book() {
+        let dir = temp_dir("bands");
+        let options = JournalOptions {
+            bands: BandOptions {
+                full_every: 3,
+                min_bps: 100,
+                max_bps: 100,
+            },
+            ..JournalOptions::default()
+        };
+        let mut book = OrderBook::new();
+        for resting in [
+            order(1, OrderSide::Sell, 1000, 5),
+            order(2, OrderSide::Sell, 1005, 5),
+            order(3, OrderSide::Sell, 1100, 5),
+            order(4, OrderSide::Sell, 1100, 5),
+            order(5, OrderSide::Buy, 990, 5),
+            order(6, OrderSide::Buy, 900, 5),
+        ] {
+            book.restore_order(resting).unwrap();
+        }
+        let journal = BookJournal::open(&dir, &book, Sequence::default(), options).unwrap();
+
+        // Within 1% of the touch: a new bid and a new ask. In the tail: a
+        // cancel and an order queued behind one from the full snapshot.
+        submit(&mut book, &journal, order(7, OrderSide::Buy, 995, 2));
+        submit(&mut book, &journal, order(8, OrderSide::Sell, 1003, 1));
+        book.remove_order(3).unwrap();
+        journal.record_cancel(3);
+        submit(&mut book, &journal, order(9, OrderSide::Sell, 1100, 5));
+        let shared = RwLock::new(book);
+        journal.checkpoint(&shared).await.unwrap();
+        let mut book = shared.into_inner();
+
+        let bands: BandSnapshot =
+            serde_json::from_slice(&fs::read(dir.join(BANDS_FILE)).unwrap()).unwrap();
+        let ids = |orders: &[Order]| orders.iter().map(|order| order.id).collect::<Vec<_>>();
+        assert_eq!(bands.bands["ETH-USDC"], 100.0);
+        assert_eq!(ids(&bands.near), [5, 7, 1, 8, 2]);
+        assert_eq!(ids(&bands.tail), [9]);
+        assert_eq!(bands.removed, [3]);
+
+        book.remove_order(6).unwrap();
+        journal.record_cancel(6);
+        let (restored, sequence) = load(&dir, &MatchingRegistry::default()).unwrap();
+        assert_eq!(
+            fingerprint(&restored.resting_orders()),
+            fingerprint(&book.resting_orders())
+        );
+        assert_eq!(sequence.last_order_id, 9);
+
+        // A tampered tail is caught, and a stale one ignored once the next
+        // full snapshot replaces its base.
+        let path = dir.join(BANDS_FILE);
+        let original = fs::read_to_string(&path).unwrap();
+        fs::write(&path, original.replace("\"quantity\":5", "\"quantity\":6")).unwrap();
+        assert!(matches!(
+            load(&dir, &MatchingRegistry::default()),
+            Err(SnapshotError::Checksum)
+        ));
+        fs::write(&path, original).unwrap();
+        let shared = RwLock::new(book);
+        journal.checkpoint(&shared).await.unwrap();
+        journal.checkpoint(&shared).await.unwrap();
+        assert!(!path.exists());
+        let (restored, _) = load(&dir, &MatchingRegistry::default()).unwrap();
+        assert_eq!(
+            fingerprint(&restored.resting_orders()),
+            fingerprint(&shared.read().await.resting_orders())
+        );
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn bands_widen_with_volatility() {
+        let options = BandOptions {
+            full_every: 2,
+            min_bps: 50,
+            max_bps: 1000,
+        };
+        let mut volatility = HashMap::new();
+        let mut width_at = |bid: Price, ask: Price| {
+            let touches = [("ETH-USDC".to_string(), (Some(bid), Some(ask)))].into();
+            band_widths(&mut volatility, &touches, &options)["ETH-USDC"]
+        };
+        assert_eq!(width_at(995, 1005), 50.0);
+        // A quiet market keeps the narrowest band.
+        assert_eq!(width_at(996, 1004), 50.0);
+        // A 10% jump: 4 × 0.3 × 1000 bps.
+        assert!((width_at(1095, 1105) - 1000.0).abs() < 1e-9);
+        // Calm returns slowly.
+        assert!((width_at(1095, 1105) - 840.0).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn archived_segments_are_compressed_and_replay_unchanged() {
+        let dir = temp_dir("compressed");
+        let archive_dir = dir.join("archive");
+        let options = JournalOptions {
+            segment_bytes: 1,
+            archive_dir: Some(archive_dir.clone()),
+            ..JournalOptions::default()
+        };
+        let mut book = OrderBook::new();
+        let journal = BookJournal::open(&dir, &book, Sequence::default(), options).unwrap();
+        for id in 1..=3 {
+            submit(
+                &mut book,
+                &journal,
+                order(id, OrderSide::Sell, 1000 + id, 1),
+            );
+        }
+        let live: Vec<String> = segments(&dir)
+            .unwrap()
+            .into_iter()
+            .flat_map(|segment| {
+                fs::read_to_string(segment_path(&dir, segment))
+                    .unwrap()
+                    .lines()
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let shared = RwLock::new(book);
+        journal.checkpoint(&shared).await.unwrap();
+
+        // Every segment the checkpoint covers left the live log as zstd.
+        let mut archived = Vec::new();
+        for segment in segments(&archive_dir).unwrap() {
+            let path = archived_segment_path(&archive_dir, segment);
+            assert_eq!(path, compressed_segment_path(&archive_dir, segment));
+            assert!(!segment_path(&dir, segment).exists());
+            let mut text = String::new();
+            Decoder::new(File::open(path).unwrap())
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            archived.extend(text.lines().map(str::to_owned));
+        }
+        assert_eq!(archived, live);
+
+        // Segments archived before compression still replay.
+        let first = segments(&archive_dir).unwrap()[0];
+        let mut text = Vec::new();
+        Decoder::new(File::open(compressed_segment_path(&archive_dir, first)).unwrap())
+            .unwrap()
+            .read_to_end(&mut text)
+            .unwrap();
+        fs::write(segment_path(&archive_dir, first), text).unwrap();
+        fs::remove_file(compressed_segment_path(&archive_dir, first)).unwrap();
+        let mut replayed = Vec::new();
+        replay_from(&dir, Some(&archive_dir), 1, |record| {
+            replayed.push(serde_json::to_string(&record).unwrap())
+        })
+        .unwrap();
+        assert_eq!(replayed, live);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn archived_log_replays_from_any_record() {
+        let dir = temp_dir("archive");
+        let archive_dir = dir.join("archive");
+        let options = JournalOptions {
+            // Every record closes its segment.
+            segment_bytes: 1,
+            archive_dir: Some(archive_dir.clone()),
+            ..JournalOptions::default()
+        };
+        let mut book = OrderBook::new();
+        let journal = BookJournal::open(&dir, &book, Sequence::default(), options.clone()).unwrap();
+        for id in 1..=3 {
+            submit(
+                &mut book,
+                &journal,
+                order(id, OrderSide::Sell, 1000 + id, 1),
+            );
+        }
+        let shared = RwLock::new(book);
+        journal.checkpoint(&shared).await.unwrap();
+        let mut book = shared.into_inner();
+        book.remove_order(3).unwrap();
+        journal.record_cancel(3);
+
+        let index = read_index(&archive_dir).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!((index[1].first_seq, index[1].last_seq), (2, 2));
+
+        let mut replayed = Vec::new();
+        let count = replay_from(&dir, Some(&archive_dir), 2, |record| {
+            replayed.push(record.seq)
+        })
+        .unwrap();
+        assert_eq!(count, 3);
+        assert_eq!(replayed, vec![2, 3, 4]);
+
+        // Numbering carries on across a restart.
+        drop(journal);
+        let journal = BookJournal::open(&dir, &book, Sequence::default(), options).unwrap();
+        journal.record_cancel(1);
+        let mut last = None;
+        replay_from(&dir, Some(&archive_dir), 5, |record| last = Some(record)).unwrap();
+        assert!(matches!(
+            last,
+            Some(JournalRecord {
+                seq: 5,
+                entry: JournalEntry::Cancel { order_id: 1 },
+            })
+        ));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
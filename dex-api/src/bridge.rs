@@ -0,0 +1,695 @@
+//! Crediting deposits bridged in from other chains.
+//!
+//! Anyone may relay a deposit to `POST /bridge/deposits`, but it is only
+//! credited once the source chain's [`MessageVerifier`] has checked a proof
+//! of it; an operator's word is not enough. On Ethereum the proof is a
+//! receipt inclusion proof: the block header, whose hash a light client (or
+//! an operator, for a checkpoint) has marked trusted through
+//! `POST /admin/bridge/blocks`, and the receipts trie nodes leading from the
+//! header's receipts root to the receipt holding the bridge contract's
+//! `Deposit` log. Verified deposits are converted through the configured
+//! asset mappings and booked in the ledger from the chain's `bridge:<chain>`
+//! account to the recipient, referenced by the event, so no event is
+//! credited twice. A deposit minting a wrapped asset is only credited while
+//! the asset's custody wallet backs it; see [`crate::wrapped`].
+
+use crate::{
+    admin_only, api_keys, auth::Claims, error_reply, funding, rate_limit::RouteClass, rate_limited,
+    storage_error_reply, with_state, wrapped, ApiState, ErrorResponse,
+};
+use dex_core::{
+    bridge_verification::{MessageVerifier, VerificationError, VerifiedDeposit},
+    cross_chain_asset_mapping::{
+        BridgeCredit, CrossChainAssetError, CrossChainAssetMapper, CrossChainAssetMapping,
+    },
+    ledger::{Account, EntryKind, JournalEntry},
+    types::{TokenId, TraderId},
+    wrapped_assets::{WrappedAsset, WrappedAssetError},
+};
+use dex_db::DatabaseError;
+use ethers_core::{
+    abi::{self, ParamType, Token},
+    types::U256,
+    utils::{
+        hex, keccak256,
+        rlp::{self, Rlp},
+    },
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, RwLock},
+};
+use tokio::sync::{Mutex, MutexGuard};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// Chain deposits are credited on, as named in asset mappings.
+pub const LOCAL_CHAIN: &str = "dex";
+
+/// Name of Ethereum in asset mappings.
+pub const ETHEREUM: &str = "ethereum";
+
+/// The log the bridge contract emits for each deposit.
+pub const DEPOSIT_EVENT: &str = "Deposit(string,string,uint256)";
+
+/// Which chains deposits are accepted from and which assets they map to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BridgeConfig {
+    /// Address of the bridge contract on Ethereum; unset refuses Ethereum
+    /// deposits.
+    pub ethereum_contract: Option<[u8; 20]>,
+    /// `(chain, asset on that chain, asset credited here)`.
+    pub assets: Vec<(String, TokenId, TokenId)>,
+    /// Tokens credited here that custody wallets must back.
+    pub wrapped_assets: Vec<WrappedAsset>,
+}
+
+/// Ethereum deposit proof, as sent in a deposit request.
+#[derive(Debug, Clone, Deserialize)]
+struct EthereumLogProof {
+    /// RLP of the block header, hex.
+    block_header: String,
+    /// Receipts trie nodes from the root to the receipt, hex.
+    receipt_proof: Vec<String>,
+    /// Position of the transaction in the block.
+    tx_index: u64,
+    /// Position of the deposit log among the receipt's logs.
+    log_index: u64,
+}
+
+/// Verifies Ethereum deposits by proving the bridge contract's `Deposit`
+/// log is in the receipts of a trusted block.
+#[derive(Debug)]
+pub struct EthereumLogVerifier {
+    contract: [u8; 20],
+    trusted_blocks: RwLock<HashSet<[u8; 32]>>,
+}
+
+impl EthereumLogVerifier {
+    pub fn new(contract: [u8; 20]) -> Self {
+        Self {
+            contract,
+            trusted_blocks: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Accept proofs against the block with hash `block_hash` from now on.
+    pub fn trust_block(&self, block_hash: [u8; 32]) {
+        self.trusted_blocks.write().unwrap().insert(block_hash);
+    }
+
+    fn deposit_log<'a>(
+        &self,
+        receipt: &'a [u8],
+        log_index: u64,
+    ) -> Result<&'a [u8], VerificationError> {
+        let malformed = |_| VerificationError::Malformed("invalid receipt".to_string());
+        // Typed receipts (EIP-2718) start with their type byte.
+        let receipt = match receipt.first() {
+            Some(&kind) if kind < 0x80 => &receipt[1..],
+            _ => receipt,
+        };
+        let receipt = Rlp::new(receipt);
+        let status: Vec<u8> = receipt.val_at(0).map_err(malformed)?;
+        if status != [1] {
+            return Err(VerificationError::NotABridgeEvent);
+        }
+        let log = receipt
+            .at(3)
+            .and_then(|logs| logs.at(log_index as usize))
+            .map_err(|_| VerificationError::NotABridgeEvent)?;
+        let address = log
+            .at(0)
+            .and_then(|address| address.data())
+            .map_err(malformed)?;
+        let topic = log
+            .at(1)
+            .and_then(|topics| topics.at(0))
+            .and_then(|topic| topic.data())
+            .map_err(|_| VerificationError::NotABridgeEvent)?;
+        if address != self.contract || topic != keccak256(DEPOSIT_EVENT) {
+            return Err(VerificationError::NotABridgeEvent);
+        }
+        log.at(2).and_then(|data| data.data()).map_err(malformed)
+    }
+}
+
+fn decode_hex(raw: &str, what: &str) -> Result<Vec<u8>, VerificationError> {
+    hex::decode(raw.trim_start_matches("0x"))
+        .map_err(|_| VerificationError::Malformed(format!("{} is not hex", what)))
+}
+
+impl MessageVerifier for EthereumLogVerifier {
+    fn chain(&self) -> &str {
+        ETHEREUM
+    }
+
+    fn verify(&self, proof: &Value) -> Result<VerifiedDeposit, VerificationError> {
+        let proof: EthereumLogProof = serde_json::from_value(proof.clone())
+            .map_err(|err| VerificationError::Malformed(err.to_string()))?;
+        let header = decode_hex(&proof.block_header, "block_header")?;
+        let block_hash = keccak256(&header);
+        if !self.trusted_blocks.read().unwrap().contains(&block_hash) {
+            return Err(VerificationError::UntrustedBlock);
+        }
+        let receipts_root: [u8; 32] = Rlp::new(&header)
+            .at(5)
+            .and_then(|root| root.data())
+            .ok()
+            .and_then(|root| root.try_into().ok())
+            .ok_or_else(|| VerificationError::Malformed("invalid block_header".to_string()))?;
+
+        let nodes = proof
+            .receipt_proof
+            .iter()
+            .map(|node| decode_hex(node, "receipt_proof"))
+            .collect::<Result<Vec<_>, _>>()?;
+        let key = rlp::encode(&proof.tx_index);
+        let receipt =
+            trie_get(receipts_root, &key, &nodes).ok_or(VerificationError::InvalidProof)?;
+        let data = self.deposit_log(receipt, proof.log_index)?;
+
+        let params = [ParamType::String, ParamType::String, ParamType::Uint(256)];
+        let (asset, recipient, amount) = match abi::decode(&params, data).as_deref() {
+            Ok([Token::String(asset), Token::String(recipient), Token::Uint(amount)]) => {
+                (asset.clone(), recipient.clone(), *amount)
+            }
+            _ => {
+                return Err(VerificationError::Malformed(
+                    "invalid Deposit log".to_string(),
+                ))
+            }
+        };
+        if amount.is_zero() || amount > U256::from(u128::MAX) {
+            return Err(VerificationError::InvalidDeposit("amount"));
+        }
+        Ok(VerifiedDeposit {
+            chain: ETHEREUM.to_string(),
+            event_id: format!(
+                "0x{}:{}:{}",
+                hex::encode(block_hash),
+                proof.tx_index,
+                proof.log_index
+            ),
+            asset_id: TokenId::parse(&asset)
+                .map_err(|_| VerificationError::InvalidDeposit("asset"))?,
+            recipient: TraderId::parse(&recipient)
+                .map_err(|_| VerificationError::InvalidDeposit("recipient"))?,
+            amount: amount.as_u128(),
+        })
+    }
+}
+
+/// Nibbles of `bytes`, high first.
+fn nibbles(bytes: &[u8]) -> Vec<u8> {
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// The value under `key` in the Merkle Patricia trie with root hash `root`,
+/// when `proof` holds every node on the way to it.
+fn trie_get<'a>(root: [u8; 32], key: &[u8], proof: &'a [Vec<u8>]) -> Option<&'a [u8]> {
+    let nodes: HashMap<[u8; 32], &[u8]> = proof
+        .iter()
+        .map(|node| (keccak256(node), node.as_slice()))
+        .collect();
+    // A child is embedded in its parent when its RLP is under 32 bytes, and
+    // referenced by hash otherwise.
+    let child = |reference: Rlp<'a>| -> Option<&'a [u8]> {
+        if reference.is_list() {
+            return Some(reference.as_raw());
+        }
+        let hash: [u8; 32] = reference.data().ok()?.try_into().ok()?;
+        nodes.get(&hash).copied()
+    };
+
+    let path = nibbles(key);
+    let mut at = 0;
+    let mut node = *nodes.get(&root)?;
+    loop {
+        let rlp = Rlp::new(node);
+        match rlp.item_count().ok()? {
+            // Branch: one child per nibble, then the value ending here.
+            17 => match path.get(at) {
+                Some(&nibble) => {
+                    node = child(rlp.at(nibble as usize).ok()?)?;
+                    at += 1;
+                }
+                None => {
+                    return rlp
+                        .at(16)
+                        .ok()?
+                        .data()
+                        .ok()
+                        .filter(|value| !value.is_empty())
+                }
+            },
+            // Leaf or extension: a hex-prefixed path, then the value or child.
+            2 => {
+                let encoded = nibbles(rlp.at(0).ok()?.data().ok()?);
+                let flag = *encoded.first()?;
+                let partial = &encoded[if flag & 1 == 1 { 1 } else { 2 }..];
+                if !path[at..].starts_with(partial) {
+                    return None;
+                }
+                at += partial.len();
+                if flag & 2 == 2 {
+                    return (at == path.len()).then(|| rlp.at(1).ok()?.data().ok())?;
+                }
+                node = child(rlp.at(1).ok()?)?;
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Verifiers by chain, the mappings deposits are credited through and the
+/// wrapped assets they mint.
+pub struct Bridge {
+    mapper: CrossChainAssetMapper,
+    verifiers: HashMap<String, Arc<dyn MessageVerifier>>,
+    ethereum: Option<Arc<EthereumLogVerifier>>,
+    wrapped_assets: Vec<WrappedAsset>,
+    /// Held while checking and booking a change to wrapped supply.
+    supply: Mutex<()>,
+}
+
+impl Bridge {
+    pub fn new(config: &BridgeConfig) -> Self {
+        let mut mapper = CrossChainAssetMapper::new();
+        for (chain, source, local) in &config.assets {
+            let mapping = CrossChainAssetMapping {
+                source_asset_id: source.clone(),
+                source_chain: chain.clone(),
+                destination_asset_id: local.clone(),
+                destination_chain: LOCAL_CHAIN.to_string(),
+                conversion_rate: None,
+            };
+            // Config parsing already refused duplicates.
+            let _ = mapper.add_mapping(mapping);
+        }
+        let mut bridge = Self {
+            mapper,
+            verifiers: HashMap::new(),
+            ethereum: None,
+            wrapped_assets: config.wrapped_assets.clone(),
+            supply: Mutex::new(()),
+        };
+        if let Some(contract) = config.ethereum_contract {
+            let ethereum = Arc::new(EthereumLogVerifier::new(contract));
+            bridge = bridge.with_verifier(ethereum.clone());
+            bridge.ethereum = Some(ethereum);
+        }
+        bridge
+    }
+
+    /// Accept deposits from `verifier`'s chain, replacing any earlier
+    /// verifier for it.
+    pub fn with_verifier(mut self, verifier: Arc<dyn MessageVerifier>) -> Self {
+        self.verifiers
+            .insert(verifier.chain().to_string(), verifier);
+        self
+    }
+
+    pub fn ethereum(&self) -> Option<&EthereumLogVerifier> {
+        self.ethereum.as_deref()
+    }
+
+    pub fn wrapped_assets(&self) -> &[WrappedAsset] {
+        &self.wrapped_assets
+    }
+
+    pub fn wrapped_asset(&self, token: &TokenId) -> Option<&WrappedAsset> {
+        self.wrapped_assets
+            .iter()
+            .find(|asset| asset.token == *token)
+    }
+
+    /// Whether `token` is bridged from `chain`, so it can be withdrawn there.
+    pub fn withdraws_to(&self, chain: &str, token: &TokenId) -> bool {
+        self.mapper
+            .get_mappings_for_destination_chain(LOCAL_CHAIN)
+            .iter()
+            .any(|mapping| mapping.source_chain == chain && mapping.destination_asset_id == *token)
+    }
+
+    /// Serialize mints and burns, so two of them cannot both pass their
+    /// checks against the same supply.
+    pub async fn lock_supply(&self) -> MutexGuard<'_, ()> {
+        self.supply.lock().await
+    }
+
+    /// What the deposit `proof` shows on `chain` credits here.
+    pub fn verify(&self, chain: &str, proof: &Value) -> Result<BridgeCredit, DepositError> {
+        let verifier = self
+            .verifiers
+            .get(chain)
+            .ok_or_else(|| DepositError::UnknownChain(chain.to_string()))?;
+        Ok(self.mapper.credit_deposit(verifier.as_ref(), proof)?)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DepositError {
+    #[error("deposits from {0} are not accepted")]
+    UnknownChain(String),
+    #[error(transparent)]
+    Rejected(#[from] CrossChainAssetError),
+    #[error("deposit {0} was already credited")]
+    AlreadyCredited(String),
+    #[error(transparent)]
+    Unbacked(#[from] WrappedAssetError),
+    #[error("failed to book the deposit: {0}")]
+    Storage(#[from] DatabaseError),
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(as = BridgeDepositRequest)]
+pub struct DepositRequest {
+    /// Chain the deposit was made on, e.g. `ethereum`.
+    pub chain: String,
+    /// On Ethereum: `block_header` (RLP, hex), `receipt_proof` (receipts
+    /// trie nodes, hex), `tx_index` and `log_index`.
+    #[schema(value_type = Object)]
+    pub proof: Value,
+    /// Transaction of the deposit on its chain, kept in the funding history.
+    #[serde(default)]
+    pub tx_hash: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = BridgeDeposit)]
+pub struct DepositResponse {
+    /// Ledger entry the credit was booked in.
+    pub entry_id: u64,
+    pub chain: String,
+    /// On Ethereum, `0x<block hash>:<tx index>:<log index>`.
+    pub event_id: String,
+    pub recipient: String,
+    pub token: String,
+    pub amount: u128,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TrustBlockRequest {
+    pub chain: String,
+    /// 32-byte hash, hex.
+    pub block_hash: String,
+}
+
+/// Verify a relayed deposit and credit its recipient, once per event.
+pub async fn credit(
+    state: &ApiState,
+    request: &DepositRequest,
+) -> Result<DepositResponse, DepositError> {
+    let credit = state.bridge.verify(&request.chain, &request.proof)?;
+    let _supply = state.bridge.lock_supply().await;
+    if let Some(asset) = state.bridge.wrapped_asset(&credit.asset_id) {
+        let balances = state.ledger_repo.ledger_balances().await?;
+        let reserves = wrapped::reserves(state, asset).await?;
+        asset.check_mint(&balances, reserves, credit.amount)?;
+    }
+    let deposit = &credit.deposit;
+    let reference = format!("{}:{}", deposit.chain, deposit.event_id);
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let entry = JournalEntry::new(EntryKind::Deposit, reference, timestamp).transfer(
+        Account::Bridge(deposit.chain.clone()),
+        Account::Trader(deposit.recipient.to_string()),
+        &credit.asset_id,
+        credit.amount,
+    );
+    let entry_id = state
+        .ledger_repo
+        .save_entry(&entry)
+        .await?
+        .ok_or_else(|| DepositError::AlreadyCredited(deposit.event_id.clone()))?;
+    funding::record_deposit(state, &credit, request.tx_hash.as_deref(), timestamp).await;
+    Ok(DepositResponse {
+        entry_id,
+        chain: deposit.chain.clone(),
+        event_id: deposit.event_id.clone(),
+        recipient: deposit.recipient.to_string(),
+        token: credit.asset_id.to_string(),
+        amount: credit.amount,
+    })
+}
+
+/// Deposits relayed from other chains, POST /bridge/deposits
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Deposits relayed from other chains, credited only with a valid proof
+    warp::path("bridge")
+        .and(warp::path("deposits"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(with_state(state.clone()))
+        .and(warp::body::content_length_limit(64 * 1024))
+        .and(warp::body::json())
+        .and_then(handle_bridge_deposit)
+        .boxed()
+}
+
+/// Source chain blocks the bridge trusts, POST /admin/bridge/blocks
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("admin")
+        .and(warp::path("bridge"))
+        .and(warp::path("blocks"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_trust_bridge_block)
+        .boxed()
+}
+
+/// Verify a deposit relayed from another chain and credit its recipient.
+#[utoipa::path(
+    post,
+    path = "/bridge/deposits",
+    summary = "Credit a deposit made on another chain, given a proof of it",
+    request_body = DepositRequest,
+    responses(
+        (status = 201, description = "Deposit credited", body = DepositResponse),
+        (status = 400, description = "Deposits from this chain are not accepted", body = ErrorResponse),
+        (status = 409, description = "Deposit already credited, or custody does not back the wrapped asset it mints", body = ErrorResponse),
+        (status = 422, description = "Proof rejected, or the asset is not mapped", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_bridge_deposit(
+    state: ApiState,
+    req: DepositRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    use DepositError;
+    match credit(&state, &req).await {
+        Ok(response) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            StatusCode::CREATED,
+        )),
+        Err(err @ DepositError::UnknownChain(_)) => Ok(error_reply(
+            "unsupported_chain",
+            err.to_string(),
+            StatusCode::BAD_REQUEST,
+        )),
+        Err(DepositError::Rejected(err)) => Ok(error_reply(
+            "deposit_rejected",
+            err.to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )),
+        Err(DepositError::Unbacked(err)) => Ok(error_reply(
+            "deposit_unbacked",
+            err.to_string(),
+            StatusCode::CONFLICT,
+        )),
+        Err(err @ DepositError::AlreadyCredited(_)) => Ok(error_reply(
+            "deposit_already_credited",
+            err.to_string(),
+            StatusCode::CONFLICT,
+        )),
+        Err(DepositError::Storage(err)) => {
+            tracing::error!(error = ?err, "failed to book bridge deposit");
+            Ok(storage_error_reply(&err, "failed to book the deposit"))
+        }
+    }
+}
+
+/// Trust a block on another chain, e.g. a light client checkpoint, so
+/// deposits can be proven against it.
+#[utoipa::path(
+    post,
+    path = "/admin/bridge/blocks",
+    summary = "Trust a block of another chain, so deposits can be proven against it (administrators only)",
+    request_body = TrustBlockRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Block trusted"),
+        (status = 400, description = "Unsupported chain or invalid block hash", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+    ),
+)]
+async fn handle_trust_bridge_block(
+    _claims: Claims,
+    state: ApiState,
+    req: TrustBlockRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(ethereum) = state.bridge.ethereum().filter(|_| req.chain == ETHEREUM) else {
+        return Ok(error_reply(
+            "unsupported_chain",
+            format!("blocks of {} cannot be trusted", req.chain),
+            StatusCode::BAD_REQUEST,
+        ));
+    };
+    let hash = ethers_core::utils::hex::decode(req.block_hash.trim_start_matches("0x"))
+        .ok()
+        .and_then(|hash| <[u8; 32]>::try_from(hash).ok());
+    let Some(hash) = hash else {
+        return Ok(error_reply(
+            "invalid_block_hash",
+            "block_hash must be 32 bytes of hex",
+            StatusCode::BAD_REQUEST,
+        ));
+    };
+    ethereum.trust_block(hash);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "chain": req.chain, "block_hash": req.block_hash })),
+        StatusCode::OK,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::utils::rlp::RlpStream;
+    use serde_json::json;
+
+    const CONTRACT: [u8; 20] = [0xb7; 20];
+
+    fn deposit_receipt(contract: [u8; 20], asset: &str, recipient: &str, amount: u64) -> Vec<u8> {
+        let data = abi::encode(&[
+            Token::String(asset.to_string()),
+            Token::String(recipient.to_string()),
+            Token::Uint(amount.into()),
+        ]);
+        let mut log = RlpStream::new_list(3);
+        log.append(&contract.as_slice());
+        log.begin_list(1)
+            .append(&keccak256(DEPOSIT_EVENT).as_slice());
+        log.append(&data);
+        let mut receipt = RlpStream::new_list(4);
+        receipt.append(&1u8);
+        receipt.append(&21_000u64);
+        receipt.append(&[0u8; 256].as_slice());
+        receipt.begin_list(1).append_raw(&log.out(), 1);
+        // An EIP-1559 receipt.
+        [vec![2], receipt.out().to_vec()].concat()
+    }
+
+    /// Hex-prefixed leaf holding `value` under the remaining `path` nibbles.
+    fn leaf(path: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut encoded = if path.len() % 2 == 1 {
+            vec![0x30 | path[0]]
+        } else {
+            vec![0x20]
+        };
+        let rest = if path.len() % 2 == 1 {
+            &path[1..]
+        } else {
+            path
+        };
+        encoded.extend(rest.chunks(2).map(|pair| pair[0] << 4 | pair[1]));
+        let mut node = RlpStream::new_list(2);
+        node.append(&encoded).append(&value);
+        node.out().to_vec()
+    }
+
+    /// A block with two receipts; returns its header and the proofs of the
+    /// receipts of transactions 0 and 1.
+    fn block(receipts: [&[u8]; 2]) -> (Vec<u8>, Vec<String>, Vec<String>) {
+        // Keys rlp(0) = 0x80 and rlp(1) = 0x01 part at the first nibble.
+        let first = leaf(&[0], receipts[0]);
+        let second = leaf(&[1], receipts[1]);
+        let mut branch = RlpStream::new_list(17);
+        for nibble in 0..16 {
+            match nibble {
+                0 => branch.append(&keccak256(&second).as_slice()),
+                8 => branch.append(&keccak256(&first).as_slice()),
+                _ => branch.append_empty_data(),
+            };
+        }
+        branch.append_empty_data();
+        let branch = branch.out().to_vec();
+
+        let mut header = RlpStream::new_list(15);
+        for field in 0..15 {
+            match field {
+                5 => header.append(&keccak256(&branch).as_slice()),
+                8 => header.append(&19_000_000u64),
+                _ => header.append(&[field as u8; 32].as_slice()),
+            };
+        }
+        let proof = |node: &[u8]| vec![hex::encode(&branch), hex::encode(node)];
+        (header.out().to_vec(), proof(&first), proof(&second))
+    }
+
+    fn proof(header: &[u8], nodes: &[String], tx_index: u64) -> Value {
+        json!({
+            "block_header": format!("0x{}", hex::encode(header)),
+            "receipt_proof": nodes,
+            "tx_index": tx_index,
+            "log_index": 0,
+        })
+    }
+
+    #[test]
+    fn deposits_are_proven_against_trusted_receipts() {
+        let verifier = EthereumLogVerifier::new(CONTRACT);
+        let honest = deposit_receipt(CONTRACT, "WETH", "alice", 5_000);
+        let impostor = deposit_receipt([0x11; 20], "WETH", "mallory", 9_000);
+        let (header, first, second) = block([&honest, &impostor]);
+
+        let deposit = proof(&header, &first, 0);
+        assert_eq!(
+            verifier.verify(&deposit),
+            Err(VerificationError::UntrustedBlock)
+        );
+
+        verifier.trust_block(keccak256(&header));
+        let verified = verifier.verify(&deposit).unwrap();
+        assert_eq!(verified.asset_id.as_str(), "WETH");
+        assert_eq!(verified.recipient.as_str(), "alice");
+        assert_eq!(verified.amount, 5_000);
+        assert_eq!(
+            verified.event_id,
+            format!("0x{}:0:0", hex::encode(keccak256(&header)))
+        );
+
+        // A log from another contract is not a deposit.
+        assert_eq!(
+            verifier.verify(&proof(&header, &second, 1)),
+            Err(VerificationError::NotABridgeEvent)
+        );
+        // Nor is a receipt the proof does not lead to.
+        assert_eq!(
+            verifier.verify(&proof(&header, &first, 1)),
+            Err(VerificationError::InvalidProof)
+        );
+        // A tampered receipt no longer hashes to the trusted root.
+        let forged = deposit_receipt(CONTRACT, "WETH", "alice", 5_000_000);
+        let mut nodes = first.clone();
+        nodes[1] = hex::encode(leaf(&[0], &forged));
+        assert_eq!(
+            verifier.verify(&proof(&header, &nodes, 0)),
+            Err(VerificationError::InvalidProof)
+        );
+    }
+}
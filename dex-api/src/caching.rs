@@ -0,0 +1,170 @@
+//! HTTP caching for public, unauthenticated reads.
+//!
+//! Replies carry an `ETag` over the body, a `Cache-Control` lifetime and, when
+//! the data has a meaningful modification time, `Last-Modified`. Requests with
+//! a matching `If-None-Match` (or, without one, an `If-Modified-Since` no older
+//! than the data) get an empty `304 Not Modified`, so CDNs and browsers can
+//! revalidate cheaply.
+
+use serde::Serialize;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use warp::{
+    http::{header, HeaderValue, StatusCode},
+    reply::Response,
+    Filter,
+};
+
+/// Lifetime of order book and ticker data, which changes constantly.
+pub const MARKET_DATA_MAX_AGE: u64 = 1;
+/// Lifetime of data that only changes on deploy, such as the API document.
+pub const STATIC_MAX_AGE: u64 = 300;
+
+/// Validators sent by the client.
+#[derive(Debug, Default, Clone)]
+pub struct Conditional {
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+}
+
+impl Conditional {
+    /// Whether the client's cached copy, identified by `etag` and last
+    /// modified at `last_modified` (Unix seconds), is still current.
+    fn is_fresh(&self, etag: &str, last_modified: Option<u64>) -> bool {
+        if let Some(tags) = &self.if_none_match {
+            // Weak comparison: `W/"x"` matches `"x"`.
+            let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+            let etag = strip(etag);
+            return tags
+                .split(',')
+                .any(|tag| tag.trim() == "*" || strip(tag) == etag);
+        }
+        match (&self.if_modified_since, last_modified) {
+            (Some(since), Some(modified)) => httpdate::parse_http_date(since)
+                .map(|since| unix_time(modified) <= since)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+}
+
+/// Extract the conditional request headers.
+pub fn conditional() -> impl Filter<Extract = (Conditional,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("if-none-match")
+        .and(warp::header::optional::<String>("if-modified-since"))
+        .map(|if_none_match, if_modified_since| Conditional {
+            if_none_match,
+            if_modified_since,
+        })
+}
+
+fn unix_time(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn etag_of(body: &[u8]) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    format!("\"{:016x}\"", hasher.finish())
+}
+
+/// Reply with `value` as JSON, or `304 Not Modified` when the client's copy
+/// is current. `last_modified` is the newest data in `value`, in Unix seconds.
+pub fn cached_json<T: Serialize>(
+    value: &T,
+    last_modified: Option<u64>,
+    max_age: u64,
+    conditional: &Conditional,
+) -> Response {
+    let body = serde_json::to_vec(value).unwrap_or_default();
+    let etag = etag_of(&body);
+    let fresh = conditional.is_fresh(&etag, last_modified);
+
+    let mut response = if fresh {
+        let mut response = Response::new(Vec::new().into());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        response
+    } else {
+        let mut response = Response::new(body.into());
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/json"),
+        );
+        response
+    };
+    let headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_str(&etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    if let Ok(cache_control) = HeaderValue::from_str(&format!("public, max-age={}", max_age)) {
+        headers.insert(header::CACHE_CONTROL, cache_control);
+    }
+    if let Some(modified) = last_modified {
+        if let Ok(date) = HeaderValue::from_str(&httpdate::fmt_http_date(unix_time(modified))) {
+            headers.insert(header::LAST_MODIFIED, date);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn conditional(if_none_match: Option<&str>, if_modified_since: Option<&str>) -> Conditional {
+        Conditional {
+            if_none_match: if_none_match.map(String::from),
+            if_modified_since: if_modified_since.map(String::from),
+        }
+    }
+
+    #[test]
+    fn revalidates_with_etags_and_dates() {
+        let value = json!({ "price": 1000 });
+        let fresh = cached_json(&value, Some(1_700_000_000), 1, &Conditional::default());
+        assert_eq!(fresh.status(), StatusCode::OK);
+        assert_eq!(fresh.headers()[header::CACHE_CONTROL], "public, max-age=1");
+        assert_eq!(
+            fresh.headers()[header::LAST_MODIFIED],
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+        let etag = fresh.headers()[header::ETAG].to_str().unwrap().to_string();
+
+        let weak = format!("\"other\", W/{}", etag);
+        let not_modified = cached_json(&value, None, 1, &conditional(Some(&weak), None));
+        assert_eq!(not_modified.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(not_modified.headers()[header::ETAG], etag.as_str());
+
+        let changed = json!({ "price": 1001 });
+        let stale = cached_json(&changed, None, 1, &conditional(Some(&etag), None));
+        assert_eq!(stale.status(), StatusCode::OK);
+
+        let since = "Tue, 14 Nov 2023 22:13:20 GMT";
+        let by_date = cached_json(
+            &value,
+            Some(1_700_000_000),
+            1,
+            &conditional(None, Some(since)),
+        );
+        assert_eq!(by_date.status(), StatusCode::NOT_MODIFIED);
+        let newer = cached_json(
+            &value,
+            Some(1_700_000_001),
+            1,
+            &conditional(None, Some(since)),
+        );
+        assert_eq!(newer.status(), StatusCode::OK);
+        // If-None-Match wins over If-Modified-Since.
+        let both = cached_json(
+            &changed,
+            Some(1_700_000_000),
+            1,
+            &conditional(Some(&etag), Some(since)),
+        );
+        assert_eq!(both.status(), StatusCode::OK);
+    }
+}
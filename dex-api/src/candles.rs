@@ -0,0 +1,885 @@
+//! OHLCV candles built from the persisted trade history.
+//!
+//! Bars cover both venues: order book fills and AMM swaps on the pair are
+//! merged by time, with volume in the base token. `GET /orderbook/candles`
+//! streams bars as newline-delimited JSON while it pages through the history,
+//! so ranges of any length are served without holding them in memory. After
+//! `limit` bars the response ends with a `{"next_cursor": ..}` line; passing
+//! it back as `cursor` continues the range.
+//!
+//! The leader aggregates every market's one-minute bars into the `candles`
+//! table every `CANDLE_AGGREGATION_INTERVAL_SECONDS`, a minute after each
+//! minute ends. Requests roll the stored bars up to their interval and only
+//! read raw trades and swaps after the last aggregated minute. A bust or
+//! price adjustment rebuilds the stored bar of the trade's minute.
+
+use crate::{ApiState, ErrorResponse};
+use dex_core::types::{Price, Quantity, TokenId, Trade, TradingPair};
+use dex_db::{
+    repository::MAX_TRADE_PAGE, CandleRecord, CandleRepo, DatabaseError, SwapRecord, SwapRepo,
+    TradeFilter, TradeRepo,
+};
+use futures_util::stream;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    convert::Infallible,
+    sync::Arc,
+    time::Duration,
+};
+use utoipa::ToSchema;
+use warp::{
+    http::{header, HeaderValue},
+    hyper::Body,
+    reply::Response,
+};
+
+/// Bars per response when no `limit` is given.
+pub const DEFAULT_CANDLE_LIMIT: usize = 1000;
+/// Most bars a single response may stream.
+pub const MAX_CANDLE_LIMIT: usize = 100_000;
+
+/// Bar width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    OneMinute,
+    FiveMinutes,
+    FifteenMinutes,
+    OneHour,
+    FourHours,
+    OneDay,
+}
+
+impl Interval {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "1m" => Some(Interval::OneMinute),
+            "5m" => Some(Interval::FiveMinutes),
+            "15m" => Some(Interval::FifteenMinutes),
+            "1h" => Some(Interval::OneHour),
+            "4h" => Some(Interval::FourHours),
+            "1d" => Some(Interval::OneDay),
+            _ => None,
+        }
+    }
+
+    pub fn as_secs(self) -> u64 {
+        match self {
+            Interval::OneMinute => 60,
+            Interval::FiveMinutes => 5 * 60,
+            Interval::FifteenMinutes => 15 * 60,
+            Interval::OneHour => 60 * 60,
+            Interval::FourHours => 4 * 60 * 60,
+            Interval::OneDay => 24 * 60 * 60,
+        }
+    }
+
+    /// Start of the bar containing `timestamp`.
+    pub fn open_time(self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.as_secs()
+    }
+}
+
+/// One bar. Intervals without trades have no bar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Candle {
+    pub open_time: u64,
+    #[schema(value_type = u64)]
+    pub open: Price,
+    #[schema(value_type = u64)]
+    pub high: Price,
+    #[schema(value_type = u64)]
+    pub low: Price,
+    #[schema(value_type = u64)]
+    pub close: Price,
+    #[schema(value_type = u64)]
+    pub volume: Quantity,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn new(open_time: u64, print: &Print) -> Self {
+        Self {
+            open_time,
+            open: print.price,
+            high: print.price,
+            low: print.price,
+            close: print.price,
+            volume: print.quantity,
+            trades: 1,
+        }
+    }
+
+    fn add(&mut self, print: &Print) {
+        self.high = self.high.max(print.price);
+        self.low = self.low.min(print.price);
+        self.close = print.price;
+        self.volume = self.volume.saturating_add(print.quantity);
+        self.trades += 1;
+    }
+
+    fn from_stored(open_time: u64, bar: &CandleRecord) -> Self {
+        Self {
+            open_time,
+            open: bar.open,
+            high: bar.high,
+            low: bar.low,
+            close: bar.close,
+            volume: bar.volume,
+            trades: bar.trades,
+        }
+    }
+
+    /// Fold in a stored one-minute bar that follows every print so far.
+    fn add_stored(&mut self, bar: &CandleRecord) {
+        self.high = self.high.max(bar.high);
+        self.low = self.low.min(bar.low);
+        self.close = bar.close;
+        self.volume = self.volume.saturating_add(bar.volume);
+        self.trades += bar.trades;
+    }
+}
+
+/// A fill or swap as a bar sees it.
+struct Print {
+    timestamp: u64,
+    price: Price,
+    quantity: Quantity,
+}
+
+impl From<Trade> for Print {
+    fn from(trade: Trade) -> Self {
+        Self {
+            timestamp: trade.timestamp,
+            price: trade.price,
+            quantity: trade.quantity,
+        }
+    }
+}
+
+impl From<SwapRecord> for Print {
+    fn from(swap: SwapRecord) -> Self {
+        Self {
+            timestamp: swap.timestamp,
+            price: swap.price,
+            quantity: swap.quantity,
+        }
+    }
+}
+
+/// How long after a minute ends its bars are aggregated, so that trades
+/// still being written when it ended are included.
+const SETTLE_SECONDS: u64 = 60;
+/// Most history aggregated between two stores of the bars and watermark.
+const AGGREGATION_WINDOW_SECONDS: u64 = 60 * 60;
+
+/// Where a print falls among the others of its minute: by time, then order
+/// book fills before swaps, then by ID.
+type PrintOrder = (u64, u8, u64);
+
+/// A one-minute bar being aggregated.
+struct MinuteBar {
+    record: CandleRecord,
+    first: PrintOrder,
+    last: PrintOrder,
+}
+
+/// One-minute bars of every market in a window of history.
+#[derive(Default)]
+struct MinuteBars {
+    bars: BTreeMap<(TokenId, TokenId, u64), MinuteBar>,
+}
+
+impl MinuteBars {
+    fn add(&mut self, base: &TokenId, quote: &TokenId, order: PrintOrder, print: Print) {
+        let open_time = Interval::OneMinute.open_time(print.timestamp);
+        let key = (base.clone(), quote.clone(), open_time);
+        let Some(bar) = self.bars.get_mut(&key) else {
+            let record = CandleRecord {
+                base_token: base.clone(),
+                quote_token: quote.clone(),
+                open_time,
+                open: print.price,
+                high: print.price,
+                low: print.price,
+                close: print.price,
+                volume: print.quantity,
+                trades: 1,
+            };
+            self.bars.insert(
+                key,
+                MinuteBar {
+                    record,
+                    first: order,
+                    last: order,
+                },
+            );
+            return;
+        };
+        let record = &mut bar.record;
+        record.high = record.high.max(print.price);
+        record.low = record.low.min(print.price);
+        record.volume = record.volume.saturating_add(print.quantity);
+        record.trades += 1;
+        if order < bar.first {
+            bar.first = order;
+            record.open = print.price;
+        }
+        if order > bar.last {
+            bar.last = order;
+            record.close = print.price;
+        }
+    }
+
+    fn into_records(self) -> Vec<CandleRecord> {
+        self.bars.into_values().map(|bar| bar.record).collect()
+    }
+}
+
+/// Aggregate the one-minute bars of `filter`'s pair, or of every market,
+/// over its time range.
+async fn aggregate_window(
+    trades: &dyn TradeRepo,
+    swaps: &dyn SwapRepo,
+    mut filter: TradeFilter,
+) -> Result<Vec<CandleRecord>, DatabaseError> {
+    let mut bars = MinuteBars::default();
+    filter.limit = MAX_TRADE_PAGE;
+    filter.after_id = None;
+    loop {
+        let page = trades.get_trades(&filter).await?;
+        let full = page.len() == filter.page_size() as usize;
+        filter.after_id = page.last().map(|trade| trade.id);
+        for trade in page {
+            let order = (trade.timestamp, 0, trade.id);
+            let (base, quote) = (trade.base_token.clone(), trade.quote_token.clone());
+            bars.add(&base, &quote, order, trade.into());
+        }
+        if !full {
+            break;
+        }
+    }
+    filter.after_id = None;
+    loop {
+        let page = swaps.get_swaps(&filter).await?;
+        let full = page.len() == filter.page_size() as usize;
+        filter.after_id = page.last().map(|swap| swap.id);
+        for swap in page {
+            let order = (swap.timestamp, 1, swap.id);
+            let (base, quote) = (swap.base_token.clone(), swap.quote_token.clone());
+            bars.add(&base, &quote, order, swap.into());
+        }
+        if !full {
+            break;
+        }
+    }
+    Ok(bars.into_records())
+}
+
+/// Timestamp of the earliest trade or swap, if there is one.
+async fn first_print(
+    trades: &dyn TradeRepo,
+    swaps: &dyn SwapRepo,
+) -> Result<Option<u64>, DatabaseError> {
+    let filter = TradeFilter {
+        limit: 1,
+        ..TradeFilter::default()
+    };
+    let trade = trades
+        .get_trades(&filter)
+        .await?
+        .first()
+        .map(|t| t.timestamp);
+    let swap = swaps.get_swaps(&filter).await?.first().map(|s| s.timestamp);
+    Ok(trade.into_iter().chain(swap).min())
+}
+
+/// Store the bars of every minute that ended at least `SETTLE_SECONDS`
+/// before `now` and is not stored yet, and advance the watermark past them.
+pub async fn aggregate(
+    trades: &dyn TradeRepo,
+    swaps: &dyn SwapRepo,
+    candles: &dyn CandleRepo,
+    now: u64,
+) -> Result<(), DatabaseError> {
+    let until = Interval::OneMinute.open_time(now.saturating_sub(SETTLE_SECONDS));
+    let mut through = candles.candle_watermark().await?;
+    if through == 0 {
+        // Start from the first print rather than paging through empty
+        // windows since 1970.
+        match first_print(trades, swaps).await? {
+            Some(first) => through = Interval::OneMinute.open_time(first),
+            None => return candles.advance_candle_watermark(until).await,
+        }
+    }
+    while through < until {
+        let end = until.min(through + AGGREGATION_WINDOW_SECONDS);
+        let filter = TradeFilter {
+            from: Some(through),
+            to: Some(end),
+            ..TradeFilter::default()
+        };
+        let bars = aggregate_window(trades, swaps, filter).await?;
+        candles.save_candles(&bars).await?;
+        candles.advance_candle_watermark(end).await?;
+        through = end;
+    }
+    Ok(())
+}
+
+/// Rebuild the stored bar of the minute `trade` executed in, after it was
+/// busted or re-priced. Minutes not aggregated yet are left to the next run.
+pub async fn rebuild_minute(state: &ApiState, trade: &Trade) -> Result<(), DatabaseError> {
+    let open_time = Interval::OneMinute.open_time(trade.timestamp);
+    if open_time >= state.candle_repo.candle_watermark().await? {
+        return Ok(());
+    }
+    let Ok(pair) = TradingPair::new(trade.base_token.clone(), trade.quote_token.clone()) else {
+        return Ok(());
+    };
+    let filter = TradeFilter {
+        pair: Some(pair.clone()),
+        from: Some(open_time),
+        to: Some(open_time + Interval::OneMinute.as_secs()),
+        ..TradeFilter::default()
+    };
+    let bars = aggregate_window(state.trades.as_ref(), state.swap_repo.as_ref(), filter).await?;
+    if bars.is_empty() {
+        state.candle_repo.delete_candle(&pair, open_time).await
+    } else {
+        state.candle_repo.save_candles(&bars).await
+    }
+}
+
+/// Aggregate new bars every `interval`.
+pub fn spawn_aggregation(state: ApiState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let now = state.determinism.now().unwrap_or_default();
+            if let Err(err) = aggregate(
+                state.trades.as_ref(),
+                state.swap_repo.as_ref(),
+                state.candle_repo.as_ref(),
+                now,
+            )
+            .await
+            {
+                tracing::error!(error = ?err, "failed to aggregate candles");
+            }
+        }
+    });
+}
+
+/// Validated candle query.
+#[derive(Debug)]
+pub struct CandleRequest {
+    /// Pair, time range and trade page size used to read the history.
+    pub filter: TradeFilter,
+    pub interval: Interval,
+    pub limit: usize,
+}
+
+/// Last line of a response cut short by its limit.
+#[derive(Serialize, ToSchema)]
+#[schema(as = CandleContinuation)]
+pub struct Continuation {
+    next_cursor: u64,
+}
+
+/// One line of the NDJSON candle stream.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum CandleLine {
+    Candle(Candle),
+    Continuation(Continuation),
+}
+
+/// One venue's history, read a page at a time.
+struct Pages<T> {
+    filter: TradeFilter,
+    page: VecDeque<T>,
+    /// Whether the last page was full, so another may follow.
+    more: bool,
+}
+
+impl<T> Pages<T> {
+    fn new(filter: TradeFilter, page: Vec<T>, id: fn(&T) -> u64) -> Self {
+        let mut pages = Self {
+            filter,
+            page: VecDeque::new(),
+            more: false,
+        };
+        pages.turn(page, id);
+        pages
+    }
+
+    fn turn(&mut self, page: Vec<T>, id: fn(&T) -> u64) {
+        self.more = page.len() == self.filter.page_size() as usize;
+        self.filter.after_id = page.last().map(id);
+        self.page = page.into();
+    }
+
+    fn needs_page(&self) -> bool {
+        self.page.is_empty() && self.more
+    }
+}
+
+/// Stored one-minute bars of the part of the range already aggregated, read
+/// a page at a time.
+struct StoredPages {
+    /// Bars are only read for a single market.
+    pair: Option<TradingPair>,
+    /// Open time of the next bar to read.
+    from: u64,
+    to: u64,
+    page_size: u32,
+    page: VecDeque<CandleRecord>,
+    more: bool,
+}
+
+impl StoredPages {
+    fn turn(&mut self, page: Vec<CandleRecord>) {
+        self.more = page.len() == self.page_size as usize;
+        if let Some(last) = page.last() {
+            self.from = last.open_time + Interval::OneMinute.as_secs();
+        }
+        self.page = page.into();
+    }
+
+    fn needs_page(&self) -> bool {
+        self.page.is_empty() && self.more
+    }
+}
+
+/// What a bar is built from: a stored one-minute bar or a raw print.
+enum Item {
+    Stored(CandleRecord),
+    Print(Print),
+}
+
+impl Item {
+    fn timestamp(&self) -> u64 {
+        match self {
+            Item::Stored(bar) => bar.open_time,
+            Item::Print(print) => print.timestamp,
+        }
+    }
+
+    fn start(&self, open_time: u64) -> Candle {
+        match self {
+            Item::Stored(bar) => Candle::from_stored(open_time, bar),
+            Item::Print(print) => Candle::new(open_time, print),
+        }
+    }
+
+    fn add_to(&self, candle: &mut Candle) {
+        match self {
+            Item::Stored(bar) => candle.add_stored(bar),
+            Item::Print(print) => candle.add(print),
+        }
+    }
+}
+
+/// Reads stored bars, then pages through order book trades and AMM swaps,
+/// merging them by time and folding them into bars.
+struct CandleStream {
+    trades: Arc<dyn TradeRepo>,
+    swaps: Arc<dyn SwapRepo>,
+    candles: Arc<dyn CandleRepo>,
+    stored: StoredPages,
+    trade_pages: Pages<Trade>,
+    swap_pages: Pages<SwapRecord>,
+    interval: Interval,
+    limit: usize,
+    current: Option<Candle>,
+    emitted: usize,
+    finished: bool,
+}
+
+fn write_line<T: Serialize>(chunk: &mut Vec<u8>, value: &T) {
+    if serde_json::to_writer(&mut *chunk, value).is_ok() {
+        chunk.push(b'\n');
+    }
+}
+
+impl CandleStream {
+    /// Whether a page must be read before the next item can be picked.
+    fn needs_page(&self) -> bool {
+        self.stored.needs_page()
+            || (self.stored.page.is_empty()
+                && (self.trade_pages.needs_page() || self.swap_pages.needs_page()))
+    }
+
+    /// Refill the stored bars while more remain, or else whichever venue
+    /// ran dry while more of its history remains.
+    async fn load(&mut self) -> Result<(), DatabaseError> {
+        if self.stored.needs_page() {
+            let stored = &self.stored;
+            if let Some(pair) = &stored.pair {
+                let page = self
+                    .candles
+                    .get_candles(pair, stored.from, stored.to, stored.page_size)
+                    .await?;
+                self.stored.turn(page);
+            }
+            return Ok(());
+        }
+        if self.trade_pages.needs_page() {
+            let page = self.trades.get_trades(&self.trade_pages.filter).await?;
+            self.trade_pages.turn(page, |trade| trade.id);
+        }
+        if self.swap_pages.needs_page() {
+            let page = self.swaps.get_swaps(&self.swap_pages.filter).await?;
+            self.swap_pages.turn(page, |swap| swap.id);
+        }
+        Ok(())
+    }
+
+    /// The earliest unread print across both venues; a trade goes first on a
+    /// tie. Within a venue IDs follow execution order, so timestamps never
+    /// go backwards.
+    fn next_print(&mut self) -> Option<Print> {
+        let trades = &mut self.trade_pages.page;
+        let swaps = &mut self.swap_pages.page;
+        match (trades.front(), swaps.front()) {
+            (Some(trade), Some(swap)) if swap.timestamp < trade.timestamp => {
+                swaps.pop_front().map(Print::from)
+            }
+            (Some(_), _) => trades.pop_front().map(Print::from),
+            (None, _) => swaps.pop_front().map(Print::from),
+        }
+    }
+
+    /// Stored bars all come before the raw prints, which start at the
+    /// first minute not aggregated.
+    fn next_item(&mut self) -> Option<Item> {
+        match self.stored.page.pop_front() {
+            Some(bar) => Some(Item::Stored(bar)),
+            None => self.next_print().map(Item::Print),
+        }
+    }
+
+    /// Lines for the bars completed by the next pages of history, or `None`
+    /// once the stream is done.
+    async fn next_chunk(&mut self) -> Option<Vec<u8>> {
+        let mut chunk = Vec::new();
+        while !self.finished {
+            // Both venues must have a print in hand, or be exhausted, before
+            // the earlier one can be picked.
+            if self.needs_page() {
+                if !chunk.is_empty() {
+                    return Some(chunk);
+                }
+                if let Err(err) = self.load().await {
+                    tracing::error!(error = ?err, "failed to load trades for candles");
+                    let error = ErrorResponse {
+                        code: "storage_unavailable",
+                        message: "failed to load trades".into(),
+                    };
+                    write_line(&mut chunk, &error);
+                    self.finished = true;
+                }
+                continue;
+            }
+
+            let Some(item) = self.next_item() else {
+                if let Some(last) = self.current.take() {
+                    write_line(&mut chunk, &CandleLine::Candle(last));
+                }
+                self.finished = true;
+                break;
+            };
+            let open_time = self.interval.open_time(item.timestamp());
+            match &mut self.current {
+                Some(candle) if candle.open_time == open_time => item.add_to(candle),
+                current => {
+                    if let Some(done) = current.replace(item.start(open_time)) {
+                        write_line(&mut chunk, &CandleLine::Candle(done));
+                        self.emitted += 1;
+                        if self.emitted == self.limit {
+                            let next_cursor = open_time;
+                            let continuation = Continuation { next_cursor };
+                            write_line(&mut chunk, &CandleLine::Continuation(continuation));
+                            self.finished = true;
+                        }
+                    }
+                }
+            }
+        }
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(chunk)
+        }
+    }
+}
+
+/// Stream the bars for `request` as NDJSON: stored bars for the minutes
+/// already aggregated, then the raw trades and swaps after them. The first
+/// page of each source is read here, so a storage failure before any output
+/// can still get an error status.
+pub async fn ndjson_response(
+    trades: Arc<dyn TradeRepo>,
+    swaps: Arc<dyn SwapRepo>,
+    candles: Arc<dyn CandleRepo>,
+    request: CandleRequest,
+) -> Result<Response, DatabaseError> {
+    let CandleRequest {
+        mut filter,
+        interval,
+        limit,
+    } = request;
+    let from = filter.from.unwrap_or(0);
+    // Stored bars cover whole minutes, so a range ending within one reads
+    // that minute raw.
+    let to = filter
+        .to
+        .map_or(u64::MAX, |to| Interval::OneMinute.open_time(to));
+    let stored_to = candles.candle_watermark().await?.min(to);
+    let mut stored = StoredPages {
+        pair: filter.pair.clone(),
+        from,
+        to: stored_to,
+        page_size: filter.page_size(),
+        page: VecDeque::new(),
+        more: false,
+    };
+    if let (Some(pair), true) = (&stored.pair, from < stored_to) {
+        let page = candles
+            .get_candles(pair, from, stored_to, stored.page_size)
+            .await?;
+        stored.turn(page);
+        filter.from = Some(stored_to);
+    }
+    let first_trades = trades.get_trades(&filter).await?;
+    let first_swaps = swaps.get_swaps(&filter).await?;
+
+    let state = CandleStream {
+        trades,
+        swaps,
+        candles,
+        stored,
+        trade_pages: Pages::new(filter.clone(), first_trades, |trade| trade.id),
+        swap_pages: Pages::new(filter, first_swaps, |swap| swap.id),
+        interval,
+        limit,
+        current: None,
+        emitted: 0,
+        finished: false,
+    };
+    let chunks = stream::unfold(state, |mut state| async move {
+        let chunk = state.next_chunk().await?;
+        Some((Ok::<_, Infallible>(chunk), state))
+    });
+    let mut response = Response::new(Body::wrap_stream(chunks));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MemoryStorage;
+
+    fn trade(id: u64, timestamp: u64, price: Price) -> Trade {
+        Trade {
+            id,
+            maker_order_id: 1,
+            taker_order_id: 2,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price,
+            quantity: id,
+            timestamp,
+        }
+    }
+
+    fn swap(id: u64, timestamp: u64, price: Price, quantity: Quantity) -> SwapRecord {
+        SwapRecord {
+            id,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            taker_side: dex_core::types::OrderSide::Buy,
+            price,
+            quantity,
+            quote_quantity: price * quantity,
+            timestamp,
+        }
+    }
+
+    async fn lines(
+        storage: Arc<MemoryStorage>,
+        from: Option<u64>,
+        limit: usize,
+    ) -> Vec<serde_json::Value> {
+        let filter = TradeFilter {
+            // Tiny pages so bars straddle page boundaries.
+            limit: 2,
+            pair: Some("ETH-USDC".parse().unwrap()),
+            from,
+            ..TradeFilter::default()
+        };
+        let request = CandleRequest {
+            filter,
+            interval: Interval::OneMinute,
+            limit,
+        };
+        let response = ndjson_response(storage.clone(), storage.clone(), storage, request)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/x-ndjson"
+        );
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn streams_bars_and_continues_from_cursor() {
+        let storage = Arc::new(MemoryStorage::default());
+        {
+            let mut trades = storage.trades.lock().unwrap();
+            trades.push(trade(1, 600, 100));
+            trades.push(trade(2, 610, 120));
+            trades.push(trade(3, 659, 90));
+            trades.push(trade(4, 720, 95));
+            // No trades in [780, 840).
+            trades.push(trade(5, 845, 101));
+            trades.push(trade(6, 850, 102));
+        }
+
+        let first = lines(storage.clone(), None, 2).await;
+        assert_eq!(first.len(), 3);
+        assert_eq!(
+            first[0],
+            serde_json::json!({
+                "open_time": 600, "open": 100, "high": 120, "low": 90, "close": 90,
+                "volume": 6, "trades": 3,
+            })
+        );
+        assert_eq!(first[1]["open_time"], 720);
+        assert_eq!(first[2], serde_json::json!({ "next_cursor": 840 }));
+
+        let rest = lines(storage, Some(840), 2).await;
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0]["open_time"], 840);
+        assert_eq!(rest[0]["close"], 102);
+        assert_eq!(rest[0]["volume"], 11);
+    }
+
+    #[tokio::test]
+    async fn merges_amm_swaps_into_bars() {
+        let storage = Arc::new(MemoryStorage::default());
+        {
+            let mut trades = storage.trades.lock().unwrap();
+            trades.push(trade(1, 600, 100));
+            trades.push(trade(2, 650, 110));
+            trades.push(trade(3, 700, 105));
+        }
+        {
+            let mut swaps = storage.swaps.lock().unwrap();
+            // Opens the first bar, then sets its high between the trades.
+            swaps.push(swap(1, 590, 99, 4));
+            swaps.push(swap(2, 620, 130, 5));
+            // Closes the first bar on a tie with trade 2, which goes first.
+            swaps.push(swap(3, 650, 108, 1));
+            // A bar with only a swap.
+            swaps.push(swap(4, 790, 104, 2));
+        }
+
+        let bars = lines(storage, None, 10).await;
+        assert_eq!(
+            bars,
+            vec![
+                serde_json::json!({
+                    "open_time": 540, "open": 99, "high": 99, "low": 99, "close": 99,
+                    "volume": 4, "trades": 1,
+                }),
+                serde_json::json!({
+                    "open_time": 600, "open": 100, "high": 130, "low": 100, "close": 108,
+                    "volume": 9, "trades": 4,
+                }),
+                serde_json::json!({
+                    "open_time": 660, "open": 105, "high": 105, "low": 105, "close": 105,
+                    "volume": 3, "trades": 1,
+                }),
+                serde_json::json!({
+                    "open_time": 780, "open": 104, "high": 104, "low": 104, "close": 104,
+                    "volume": 2, "trades": 1,
+                }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stored_bars_are_read_up_to_the_watermark() {
+        let storage = Arc::new(MemoryStorage::default());
+        {
+            let mut trades = storage.trades.lock().unwrap();
+            trades.push(trade(1, 600, 100));
+            trades.push(trade(2, 650, 110));
+            trades.push(trade(3, 700, 105));
+        }
+        {
+            let mut swaps = storage.swaps.lock().unwrap();
+            swaps.push(swap(1, 590, 99, 4));
+            swaps.push(swap(2, 620, 130, 5));
+            swaps.push(swap(3, 650, 108, 1));
+            swaps.push(swap(4, 790, 104, 2));
+        }
+        let raw = lines(storage.clone(), None, 10).await;
+
+        // Minutes before 720 have ended a settling period ago.
+        aggregate(
+            storage.as_ref(),
+            storage.as_ref(),
+            storage.as_ref(),
+            720 + SETTLE_SECONDS,
+        )
+        .await
+        .unwrap();
+        assert_eq!(storage.candle_watermark().await.unwrap(), 720);
+        assert_eq!(storage.candles.lock().unwrap().len(), 3);
+        // Served from the stored bars from now on.
+        storage.trades.lock().unwrap().retain(|trade| trade.id == 3);
+        storage.swaps.lock().unwrap().retain(|swap| swap.id == 4);
+        assert_eq!(lines(storage.clone(), None, 10).await, raw);
+
+        // Rolled up into a wider bar that straddles the watermark.
+        let request = CandleRequest {
+            filter: TradeFilter {
+                limit: 2,
+                pair: Some("ETH-USDC".parse().unwrap()),
+                ..TradeFilter::default()
+            },
+            interval: Interval::OneHour,
+            limit: 10,
+        };
+        let response = ndjson_response(storage.clone(), storage.clone(), storage, request)
+            .await
+            .unwrap();
+        let body = warp::hyper::body::to_bytes(response.into_body())
+            .await
+            .unwrap();
+        let bar: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            bar,
+            serde_json::json!({
+                "open_time": 0, "open": 99, "high": 130, "low": 99, "close": 104,
+                "volume": 18, "trades": 7,
+            })
+        );
+    }
+}
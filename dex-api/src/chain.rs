@@ -0,0 +1,306 @@
+//! Block explorer for the internal chain.
+//!
+//! Blocks the consensus engine accepts are stored with their transactions,
+//! so `GET /chain/blocks/{height}` and `GET /chain/txs/{hash}` answer from
+//! storage and survive restarts. Once a shard finalizes blocks they are
+//! marked finalized and pushed to subscribers of the `blocks` channel on
+//! `/ws`.
+
+use crate::{
+    error_reply, rate_limit::RouteClass, rate_limited, storage_error_reply, with_state, ApiState,
+    ErrorResponse,
+};
+use dex_core::{
+    quantum_consensus::{ConsensusMetrics, QuantumConsensusError},
+    types::Block,
+};
+use dex_db::{ChainBlock, ChainTransaction, DatabaseError};
+use ethers_core::utils::hex;
+use serde::Serialize;
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(as = ChainBlock)]
+pub struct BlockResponse {
+    pub shard_id: u64,
+    pub height: u64,
+    pub hash: String,
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub finalized: bool,
+    /// Hashes of the block's transactions, in order.
+    pub transactions: Vec<String>,
+}
+
+impl From<ChainBlock> for BlockResponse {
+    fn from(block: ChainBlock) -> Self {
+        Self {
+            shard_id: block.shard_id,
+            height: block.height,
+            hash: block.hash,
+            previous_hash: block.previous_hash,
+            timestamp: block.timestamp,
+            finalized: block.finalized,
+            transactions: block.transactions.into_iter().map(|t| t.hash).collect(),
+        }
+    }
+}
+
+/// Every shard's block at one height.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = ChainBlocks)]
+pub struct BlocksResponse {
+    pub height: u64,
+    pub blocks: Vec<BlockResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = ChainTransaction)]
+pub struct TransactionResponse {
+    pub hash: String,
+    pub shard_id: u64,
+    /// Height of the including block.
+    pub height: u64,
+    /// Position in the block.
+    pub index: u32,
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+    pub nonce: u64,
+    pub finalized: bool,
+}
+
+impl From<ChainTransaction> for TransactionResponse {
+    fn from(transaction: ChainTransaction) -> Self {
+        Self {
+            hash: transaction.hash,
+            shard_id: transaction.shard_id,
+            height: transaction.height,
+            index: transaction.index,
+            from: transaction.from,
+            to: transaction.to,
+            amount: transaction.amount,
+            nonce: transaction.nonce,
+            finalized: transaction.finalized,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChainError {
+    #[error("block rejected: {0}")]
+    Rejected(#[from] QuantumConsensusError),
+    #[error("failed to store the block: {0}")]
+    Storage(#[from] DatabaseError),
+}
+
+/// Run `block` through consensus on `shard_id` and store it once accepted.
+pub async fn commit_block(state: &ApiState, shard_id: u64, block: Block) -> Result<(), ChainError> {
+    let (block, finalized) = {
+        let mut consensus = state.consensus.write().await;
+        let block = consensus.process_block_with_sharding(shard_id, block)?;
+        let finalized = consensus
+            .get_shard_finalized_height(shard_id)
+            .is_some_and(|height| block.height <= height);
+        (block, finalized)
+    };
+    let transactions = block
+        .transactions
+        .iter()
+        .enumerate()
+        .map(|(index, transaction)| ChainTransaction {
+            hash: hex::encode(transaction.hash()),
+            shard_id,
+            height: block.height,
+            index: index as u32,
+            from: transaction.from.clone(),
+            to: transaction.to.clone(),
+            amount: transaction.amount,
+            nonce: transaction.nonce,
+            finalized,
+        })
+        .collect();
+    state
+        .chain_repo
+        .save_block(&ChainBlock {
+            shard_id,
+            height: block.height,
+            hash: hex::encode(&block.hash),
+            previous_hash: hex::encode(&block.previous_hash),
+            timestamp: block.timestamp,
+            transactions,
+            finalized,
+        })
+        .await?;
+    Ok(())
+}
+
+/// Finalize `shard_id` up to `height`, publishing each block that became
+/// final on the `blocks` channel.
+pub async fn finalize(
+    state: &ApiState,
+    shard_id: u64,
+    height: u64,
+) -> Result<Vec<BlockResponse>, DatabaseError> {
+    state
+        .consensus
+        .write()
+        .await
+        .update_shard_finality(shard_id, height);
+    let blocks: Vec<BlockResponse> = state
+        .chain_repo
+        .finalize_blocks(shard_id, height)
+        .await?
+        .into_iter()
+        .map(BlockResponse::from)
+        .collect();
+    for block in &blocks {
+        // Nobody listening is fine.
+        let _ = state.block_tx.send(block.clone());
+    }
+    Ok(blocks)
+}
+
+/// Every shard's block at `height`; `None` when no shard has reached it.
+pub async fn blocks_at(
+    state: &ApiState,
+    height: u64,
+) -> Result<Option<BlocksResponse>, DatabaseError> {
+    let blocks = state.chain_repo.load_blocks_at(height).await?;
+    Ok((!blocks.is_empty()).then(|| BlocksResponse {
+        height,
+        blocks: blocks.into_iter().map(BlockResponse::from).collect(),
+    }))
+}
+
+/// A transaction by hash, with or without a `0x` prefix.
+pub async fn transaction(
+    state: &ApiState,
+    hash: &str,
+) -> Result<Option<TransactionResponse>, DatabaseError> {
+    let hash = hash.strip_prefix("0x").unwrap_or(hash).to_ascii_lowercase();
+    Ok(state
+        .chain_repo
+        .load_transaction(&hash)
+        .await?
+        .map(TransactionResponse::from))
+}
+
+/// Explorer reads of the internal chain, e.g. GET /chain/blocks/12
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Validator telemetry summary for explorers
+    let chain_metrics = warp::path("chain")
+        .and(warp::path("metrics"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and_then(handle_get_chain_metrics)
+        .boxed();
+
+    // Explorer reads of the internal chain
+    let chain_block = warp::path("chain")
+        .and(warp::path("blocks"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and_then(handle_get_chain_blocks)
+        .boxed();
+
+    let chain_transaction = warp::path("chain")
+        .and(warp::path("txs"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and_then(handle_get_chain_transaction)
+        .boxed();
+
+    chain_metrics.or(chain_block).or(chain_transaction)
+}
+
+/// Round durations, leader fairness, vote participation and finality lag.
+#[utoipa::path(
+    get,
+    path = "/chain/metrics",
+    summary = "Validator telemetry: round durations, leader fairness, vote participation and finality lag per shard",
+    responses(
+        (status = 200, description = "Consensus metrics", body = ConsensusMetrics),
+    ),
+)]
+async fn handle_get_chain_metrics(state: ApiState) -> Result<impl warp::Reply, warp::Rejection> {
+    let metrics = state.consensus.read().await.metrics();
+    Ok(warp::reply::json(&metrics))
+}
+
+/// Every shard's block at a height, with its finality.
+#[utoipa::path(
+    get,
+    path = "/chain/blocks/{height}",
+    summary = "Every shard's block at a height, with its finality",
+    params(("height" = u64, Path)),
+    responses(
+        (status = 200, description = "Blocks at the height", body = BlocksResponse),
+        (status = 404, description = "No shard has reached the height", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_chain_blocks(
+    height: u64,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match blocks_at(&state, height).await {
+        Ok(Some(blocks)) => Ok(warp::reply::with_status(
+            warp::reply::json(&blocks),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(error_reply(
+            "block_not_found",
+            format!("no block at height {}", height),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load blocks");
+            Ok(storage_error_reply(&err, "failed to load blocks"))
+        }
+    }
+}
+
+/// A transaction of the internal chain, with its block and finality.
+#[utoipa::path(
+    get,
+    path = "/chain/txs/{hash}",
+    summary = "A transaction of the internal chain, with its block and finality",
+    params(("hash" = String, Path)),
+    responses(
+        (status = 200, description = "The transaction", body = TransactionResponse),
+        (status = 404, description = "Unknown transaction", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_chain_transaction(
+    hash: String,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match transaction(&state, &hash).await {
+        Ok(Some(transaction)) => Ok(warp::reply::with_status(
+            warp::reply::json(&transaction),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(error_reply(
+            "transaction_not_found",
+            format!("transaction {} not found", hash),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load transaction");
+            Ok(storage_error_reply(&err, "failed to load transaction"))
+        }
+    }
+}
@@ -1,24 +1,64 @@
-use rand::{distributions::Alphanumeric, Rng};
-use std::{
-    collections::HashMap,
-    sync::Arc,
-    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+//! Sign-in challenges for wallets.
+//!
+//! Each challenge is offered both as a Sign-In with Ethereum message for
+//! `personal_sign` and as EIP-712 typed data for `eth_signTypedData_v4`,
+//! which wallets render as labelled fields rather than an opaque string. Both
+//! carry the same nonce, and either signature redeems the challenge once.
+//! Wallets of other chains get the same text in its CAIP-122 form, naming
+//! their chain and without the EVM chain ID.
+//!
+//! Pending challenges live in a `ChallengeRepo`, Postgres in production, so
+//! a challenge issued by one API instance can be redeemed on any other and
+//! survives restarts.
+
+use crate::{
+    determinism::Determinism,
+    siwe::{self, SiweMessage},
+    wallets::WalletChain,
 };
-use tokio::sync::RwLock;
+use dex_db::{ChallengeRecord, ChallengeRepo, DatabaseError};
+use ethers_core::types::transaction::eip712::TypedData;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+
+/// EIP-712 domain name wallets show the signer.
+pub const DOMAIN_NAME: &str = "DEX-OS";
+pub const DOMAIN_VERSION: &str = "1";
+/// Statement shown in SIWE messages.
+pub const STATEMENT: &str = "Sign in to DEX-OS";
+
+/// Who asks wallets to sign in, as SIWE messages name it.
+#[derive(Debug, Clone)]
+pub struct SignInDomain {
+    /// Host the sign-in is for, e.g. `dex.example`.
+    pub domain: String,
+    /// URI of the API being signed in to.
+    pub uri: String,
+    pub chain_id: u64,
+}
 
 #[derive(Clone)]
 pub struct ChallengeStore {
     ttl: Duration,
-    inner: Arc<RwLock<HashMap<String, ChallengeEntry>>>,
+    domain: SignInDomain,
+    repo: Arc<dyn ChallengeRepo>,
+    determinism: Arc<Determinism>,
 }
 
-struct ChallengeEntry {
-    message: String,
-    expires_at: Instant,
+/// A challenge in each of the forms a wallet may sign.
+#[derive(Debug, Clone)]
+pub struct PendingChallenge {
+    /// The SIWE message, for Ethereum wallets.
+    pub siwe: Option<SiweMessage>,
+    /// The text a message signature covers.
+    pub message: String,
+    /// The EIP-712 form, for Ethereum wallets.
+    pub typed_data: Option<TypedData>,
 }
 
 pub struct IssuedChallenge {
     pub challenge: String,
+    pub typed_data: Option<TypedData>,
     pub expires_at: u64,
 }
 
@@ -28,57 +68,181 @@ pub enum ChallengeError {
     Missing,
     #[error("challenge expired")]
     Expired,
+    #[error("challenge storage failed: {0}")]
+    Storage(#[from] DatabaseError),
 }
 
 impl ChallengeStore {
-    pub fn new(ttl_seconds: u64) -> Self {
+    pub fn new(ttl_seconds: u64, domain: SignInDomain, repo: Arc<dyn ChallengeRepo>) -> Self {
         Self {
             ttl: Duration::from_secs(ttl_seconds.max(60)),
-            inner: Arc::new(RwLock::new(HashMap::new())),
+            domain,
+            repo,
+            determinism: Arc::default(),
         }
     }
 
-    pub async fn issue(&self, address: &str) -> IssuedChallenge {
-        let nonce: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(24)
-            .map(char::from)
-            .collect();
-        let message = format!(
-            "Sign in to DEX-OS\nAddress: {}\nNonce: {}\nIssued At: {}",
-            address,
-            nonce,
-            current_unix_timestamp().unwrap_or(0)
-        );
-        let expires_at = Instant::now() + self.ttl;
-        let expires_epoch = current_unix_timestamp().unwrap_or(0) + self.ttl.as_secs();
-        let mut guard = self.inner.write().await;
-        guard.insert(
-            address.to_string(),
-            ChallengeEntry {
-                message: message.clone(),
-                expires_at,
+    /// Read the clock and draw nonces from `determinism`.
+    pub fn with_determinism(mut self, determinism: Arc<Determinism>) -> Self {
+        self.determinism = determinism;
+        self
+    }
+
+    /// Challenge the wallet with the normalized `address` on `chain`.
+    pub async fn issue(
+        &self,
+        chain: WalletChain,
+        address: &str,
+    ) -> Result<IssuedChallenge, DatabaseError> {
+        let nonce = self.determinism.random_string(24);
+        let issued_at = self.determinism.now().unwrap_or(0);
+        let expires_at = issued_at + self.ttl.as_secs();
+        let challenge = match chain {
+            WalletChain::Ethereum => self.ethereum(address, nonce, issued_at, expires_at),
+            _ => PendingChallenge {
+                message: self.caip122(chain, address, &nonce, issued_at, expires_at),
+                siwe: None,
+                typed_data: None,
             },
-        );
-        drop(guard);
-        IssuedChallenge {
-            challenge: message,
-            expires_at: expires_epoch,
+        };
+        // Abandoned challenges are swept as new ones are issued.
+        if let Err(err) = self.repo.purge_expired_challenges(issued_at).await {
+            tracing::warn!(error = ?err, "failed to purge expired wallet challenges");
         }
+        let typed_data = challenge
+            .typed_data
+            .as_ref()
+            .map(|typed_data| serde_json::to_string(typed_data).expect("typed data is JSON"));
+        self.repo
+            .save_challenge(&ChallengeRecord {
+                subject: chain.scheme().subject(address),
+                message: challenge.message.clone(),
+                typed_data,
+                expires_at,
+            })
+            .await?;
+        Ok(IssuedChallenge {
+            challenge: challenge.message,
+            typed_data: challenge.typed_data,
+            expires_at,
+        })
+    }
+
+    fn ethereum(
+        &self,
+        address: &str,
+        nonce: String,
+        issued_at: u64,
+        expires_at: u64,
+    ) -> PendingChallenge {
+        let typed_data = sign_in_typed_data(self.domain.chain_id, address, &nonce, expires_at);
+        let siwe = SiweMessage {
+            domain: self.domain.domain.clone(),
+            address: siwe::checksum_address(address).unwrap_or_else(|| address.to_string()),
+            statement: Some(STATEMENT.to_string()),
+            uri: self.domain.uri.clone(),
+            version: siwe::VERSION.to_string(),
+            chain_id: self.domain.chain_id,
+            nonce,
+            issued_at,
+            expiration_time: Some(expires_at),
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        };
+        PendingChallenge {
+            message: siwe.to_string(),
+            siwe: Some(siwe),
+            typed_data: Some(typed_data),
+        }
+    }
+
+    /// The SIWE layout generalized to other chains (CAIP-122).
+    fn caip122(
+        &self,
+        chain: WalletChain,
+        address: &str,
+        nonce: &str,
+        issued_at: u64,
+        expires_at: u64,
+    ) -> String {
+        format!(
+            "{} wants you to sign in with your {} account:\n{}\n\n{}\n\n\
+             URI: {}\nVersion: {}\nNonce: {}\nIssued At: {}\nExpiration Time: {}",
+            self.domain.domain,
+            chain.scheme().blockchain(),
+            address,
+            STATEMENT,
+            self.domain.uri,
+            siwe::VERSION,
+            nonce,
+            siwe::format_time(issued_at),
+            siwe::format_time(expires_at),
+        )
     }
 
-    pub async fn take(&self, address: &str) -> Result<String, ChallengeError> {
-        let mut guard = self.inner.write().await;
-        let entry = guard.remove(address).ok_or(ChallengeError::Missing)?;
-        if Instant::now() > entry.expires_at {
+    /// Redeem the challenge issued to `subject`, the wallet's token subject.
+    pub async fn take(&self, subject: &str) -> Result<PendingChallenge, ChallengeError> {
+        let record = self
+            .repo
+            .take_challenge(subject)
+            .await?
+            .ok_or(ChallengeError::Missing)?;
+        if self.determinism.now().unwrap_or(u64::MAX) >= record.expires_at {
             return Err(ChallengeError::Expired);
         }
-        Ok(entry.message)
+        // Only Ethereum challenges have a typed form, and their text is the
+        // SIWE message.
+        let corrupt = |err: String| {
+            tracing::error!(%subject, error = %err, "stored challenge is corrupt");
+            ChallengeError::Storage(DatabaseError::DataIntegrityError)
+        };
+        let (siwe, typed_data) = match record.typed_data {
+            Some(raw) => (
+                Some(
+                    record
+                        .message
+                        .parse::<SiweMessage>()
+                        .map_err(|err| corrupt(err.to_string()))?,
+                ),
+                Some(serde_json::from_str(&raw).map_err(|err| corrupt(err.to_string()))?),
+            ),
+            None => (None, None),
+        };
+        Ok(PendingChallenge {
+            siwe,
+            message: record.message,
+            typed_data,
+        })
     }
 }
 
-fn current_unix_timestamp() -> Result<u64, std::time::SystemTimeError> {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
+/// The `SignIn` typed data for `address`, bound to this chain and expiry.
+pub fn sign_in_typed_data(chain_id: u64, address: &str, nonce: &str, expires_at: u64) -> TypedData {
+    serde_json::from_value(json!({
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+            ],
+            "SignIn": [
+                { "name": "address", "type": "address" },
+                { "name": "nonce", "type": "string" },
+                { "name": "expiresAt", "type": "uint256" },
+            ],
+        },
+        "primaryType": "SignIn",
+        "domain": {
+            "name": DOMAIN_NAME,
+            "version": DOMAIN_VERSION,
+            "chainId": chain_id,
+        },
+        "message": {
+            "address": address,
+            "nonce": nonce,
+            "expiresAt": expires_at,
+        },
+    }))
+    .expect("sign-in typed data is well formed")
 }
@@ -0,0 +1,378 @@
+//! Failure injection for chaos testing.
+//!
+//! Hooks randomly fail storage writes, delay stream broadcasts and drop
+//! WebSocket frames so tests can check that the book, the store and the
+//! streams stay consistent and recover once the faults stop. Everything is
+//! off by default; the `chaos` feature lets the `CHAOS_*` environment
+//! variables switch it on for test deployments.
+
+use async_trait::async_trait;
+use dex_core::{
+    ledger::JournalEntry,
+    types::{Order, OrderId, Trade, TradeId, TraderId},
+};
+use dex_db::{DatabaseError, OrderFill, OrderRepo, TradeAdjustment, TradeFilter, TradeRepo};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// Fault rates and delays; rates are probabilities in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChaosConfig {
+    pub db_write_failure_rate: f64,
+    pub broadcast_delay_ms: u64,
+    pub ws_drop_rate: f64,
+    pub seed: u64,
+}
+
+impl ChaosConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.db_write_failure_rate > 0.0 || self.broadcast_delay_ms > 0 || self.ws_drop_rate > 0.0
+    }
+}
+
+/// Shared fault injector consulted by the storage wrapper and the streams.
+#[derive(Debug)]
+pub struct Chaos {
+    config: ChaosConfig,
+    active: AtomicBool,
+    rng: Mutex<StdRng>,
+}
+
+impl Chaos {
+    pub fn new(config: ChaosConfig) -> Self {
+        Self {
+            active: AtomicBool::new(config.is_enabled()),
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            config,
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(ChaosConfig::default())
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Stop injecting faults, e.g. to check that the system recovers.
+    pub fn stop(&self) {
+        self.active.store(false, Ordering::Relaxed);
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        if !self.is_active() || rate <= 0.0 {
+            return false;
+        }
+        self.rng
+            .lock()
+            .expect("chaos rng poisoned")
+            .gen_bool(rate.min(1.0))
+    }
+
+    /// Whether the next storage write should fail.
+    pub fn fail_db_write(&self) -> bool {
+        self.roll(self.config.db_write_failure_rate)
+    }
+
+    /// Whether the next outgoing WebSocket frame should be dropped.
+    pub fn drop_ws_frame(&self) -> bool {
+        self.roll(self.config.ws_drop_rate)
+    }
+
+    /// Hold a broadcast back by the configured delay.
+    pub async fn delay_broadcast(&self) {
+        if self.is_active() && self.config.broadcast_delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.config.broadcast_delay_ms)).await;
+        }
+    }
+}
+
+impl Default for Chaos {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+/// Order and trade storage whose writes fail at the configured rate.
+pub struct ChaosStorage {
+    orders: Arc<dyn OrderRepo>,
+    trades: Arc<dyn TradeRepo>,
+    chaos: Arc<Chaos>,
+}
+
+impl ChaosStorage {
+    pub fn new(orders: Arc<dyn OrderRepo>, trades: Arc<dyn TradeRepo>, chaos: Arc<Chaos>) -> Self {
+        Self {
+            orders,
+            trades,
+            chaos,
+        }
+    }
+
+    fn check_write(&self) -> Result<(), DatabaseError> {
+        if self.chaos.fail_db_write() {
+            return Err(DatabaseError::Timeout("chaos_write"));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl OrderRepo for ChaosStorage {
+    async fn save_order(&self, order: &Order) -> Result<(), DatabaseError> {
+        self.check_write()?;
+        self.orders.save_order(order).await
+    }
+
+    async fn load_order(&self, order_id: OrderId) -> Result<Option<Order>, DatabaseError> {
+        self.orders.load_order(order_id).await
+    }
+
+    async fn delete_order(&self, order_id: OrderId) -> Result<bool, DatabaseError> {
+        self.check_write()?;
+        self.orders.delete_order(order_id).await
+    }
+
+    async fn discard_order(&self, order_id: OrderId) -> Result<(), DatabaseError> {
+        self.check_write()?;
+        self.orders.discard_order(order_id).await
+    }
+
+    async fn load_open_orders(&self) -> Result<Vec<Order>, DatabaseError> {
+        self.orders.load_open_orders().await
+    }
+
+    async fn last_order_id(&self) -> Result<OrderId, DatabaseError> {
+        self.orders.last_order_id().await
+    }
+
+    async fn next_order_id(&self) -> Result<OrderId, DatabaseError> {
+        self.orders.next_order_id().await
+    }
+
+    async fn update_order_fills(&self, fills: &[OrderFill]) -> Result<(), DatabaseError> {
+        self.check_write()?;
+        self.orders.update_order_fills(fills).await
+    }
+}
+
+#[async_trait]
+impl TradeRepo for ChaosStorage {
+    async fn save_trade(&self, trade: &Trade) -> Result<(), DatabaseError> {
+        self.check_write()?;
+        self.trades.save_trade(trade).await
+    }
+
+    async fn save_trades(
+        &self,
+        trades: &[Trade],
+        entries: &[JournalEntry],
+        fills: &[OrderFill],
+    ) -> Result<(), DatabaseError> {
+        self.check_write()?;
+        self.trades.save_trades(trades, entries, fills).await
+    }
+
+    async fn load_trade(&self, trade_id: TradeId) -> Result<Option<Trade>, DatabaseError> {
+        self.trades.load_trade(trade_id).await
+    }
+
+    async fn get_trades_for_order(&self, order_id: OrderId) -> Result<Vec<Trade>, DatabaseError> {
+        self.trades.get_trades_for_order(order_id).await
+    }
+
+    async fn get_trades_for_trader(
+        &self,
+        trader_id: &TraderId,
+        filter: &TradeFilter,
+    ) -> Result<Vec<Trade>, DatabaseError> {
+        self.trades.get_trades_for_trader(trader_id, filter).await
+    }
+
+    async fn get_trades(&self, filter: &TradeFilter) -> Result<Vec<Trade>, DatabaseError> {
+        self.trades.get_trades(filter).await
+    }
+
+    async fn last_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        self.trades.last_trade_id().await
+    }
+
+    async fn next_trade_ids(&self, count: usize) -> Result<Vec<TradeId>, DatabaseError> {
+        self.trades.next_trade_ids(count).await
+    }
+
+    async fn adjust_trade(&self, adjustment: &TradeAdjustment) -> Result<bool, DatabaseError> {
+        self.check_write()?;
+        self.trades.adjust_trade(adjustment).await
+    }
+
+    async fn get_trade_adjustments(
+        &self,
+        trade_id: TradeId,
+    ) -> Result<Vec<TradeAdjustment>, DatabaseError> {
+        self.trades.get_trade_adjustments(trade_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes,
+        test_support::{bearer_token, next_event, place, test_state_with_chaos, MemoryStorage},
+    };
+    use dex_core::orderbook::PriceLevel;
+    use std::collections::BTreeMap;
+    use warp::http::StatusCode;
+
+    fn chaos(config: ChaosConfig) -> Arc<Chaos> {
+        Arc::new(Chaos::new(config))
+    }
+
+    /// Every resting order must be known to the store, and level totals must
+    /// match the orders they hold.
+    fn assert_levels_consistent(
+        levels: &BTreeMap<u64, PriceLevel>,
+        book: &dex_core::orderbook::OrderBook,
+        storage: &MemoryStorage,
+    ) {
+        let stored = storage.orders.lock().unwrap();
+        for level in levels.values() {
+            let mut total = 0;
+            for id in &level.orders {
+                let order = book.orders.get(id).expect("level references a live order");
+                assert!(stored.contains_key(id), "order {} missing from store", id);
+                total += order.quantity;
+            }
+            assert_eq!(level.total_quantity, total);
+        }
+    }
+
+    /// Fills are stored with the trades that made them, so no stored order
+    /// has more left than its stored trades leave. It can have less: a failed
+    /// write loses trades the book already executed.
+    fn assert_fills_match_trades(storage: &MemoryStorage) {
+        let orders = storage.orders.lock().unwrap();
+        let fills = storage.order_fills.lock().unwrap();
+        let trades = storage.trades.lock().unwrap();
+        for order in orders.values() {
+            let traded: u64 = trades
+                .iter()
+                .filter(|trade| {
+                    trade.maker_order_id == order.id || trade.taker_order_id == order.id
+                })
+                .map(|trade| trade.quantity)
+                .sum();
+            let remaining = fills
+                .get(&order.id)
+                .map_or(order.quantity, |fill| fill.remaining_quantity);
+            assert!(remaining <= order.quantity - traded, "order {}", order.id);
+        }
+    }
+
+    #[test]
+    fn inactive_chaos_injects_nothing() {
+        let quiet = Chaos::disabled();
+        assert!(!quiet.fail_db_write() && !quiet.drop_ws_frame());
+
+        let noisy = Chaos::new(ChaosConfig {
+            db_write_failure_rate: 1.0,
+            ..Default::default()
+        });
+        assert!(noisy.fail_db_write());
+        noisy.stop();
+        assert!(!noisy.fail_db_write());
+    }
+
+    #[tokio::test]
+    async fn book_and_store_stay_consistent_under_write_failures() {
+        let storage = Arc::new(MemoryStorage::default());
+        let chaos = chaos(ChaosConfig {
+            db_write_failure_rate: 0.3,
+            seed: 7,
+            ..Default::default()
+        });
+        let state = test_state_with_chaos(storage.clone(), chaos.clone());
+        let filter = routes(state.clone());
+
+        let (mut created, mut failed) = (0, 0);
+        for i in 0..60u64 {
+            let (trader, side) = if i % 2 == 0 {
+                ("alice", "buy")
+            } else {
+                ("bob", "sell")
+            };
+            let response = warp::test::request()
+                .method("POST")
+                .path("/orderbook/orders")
+                .header("authorization", bearer_token(trader, 300))
+                .json(&serde_json::json!({
+                    "trader_id": trader,
+                    "base_token": "ETH",
+                    "quote_token": "USDC",
+                    "side": side,
+                    "order_type": "limit",
+                    "price": 995 + (i * 7) % 11,
+                    "quantity": 1 + (i * 5) % 10,
+                }))
+                .reply(&filter)
+                .await;
+            match response.status() {
+                StatusCode::CREATED => created += 1,
+                StatusCode::SERVICE_UNAVAILABLE => {
+                    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+                    assert_eq!(body["code"], "storage_timeout");
+                    failed += 1;
+                }
+                other => panic!("unexpected status {}", other),
+            }
+        }
+        assert!(created > 0 && failed > 0);
+
+        {
+            let book = state.orderbook.read().await;
+            assert_levels_consistent(&book.bids, &book, &storage);
+            assert_levels_consistent(&book.asks, &book, &storage);
+            if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+                assert!(bid < ask, "book crossed at {} / {}", bid, ask);
+            }
+        }
+        assert_fills_match_trades(&storage);
+
+        // Once the faults stop, order entry works again.
+        chaos.stop();
+        place(&filter, "alice", "buy", 1).await;
+    }
+
+    #[tokio::test]
+    async fn trade_stream_survives_dropped_and_delayed_frames() {
+        let chaos = chaos(ChaosConfig {
+            broadcast_delay_ms: 5,
+            ws_drop_rate: 1.0,
+            ..Default::default()
+        });
+        let state = test_state_with_chaos(Arc::new(MemoryStorage::default()), chaos.clone());
+        let filter = routes(state);
+        let mut client = warp::test::ws()
+            .path("/ws/trades/ETH-USDC")
+            .handshake(filter.clone())
+            .await
+            .expect("handshake");
+
+        place(&filter, "bob", "sell", 5).await;
+        place(&filter, "alice", "buy", 1).await;
+        chaos.stop();
+        place(&filter, "alice", "buy", 2).await;
+
+        // The first trade was dropped; the session is still alive for the next.
+        let trade = next_event(&mut client).await;
+        assert_eq!(trade["quantity"], 2);
+    }
+}
@@ -1,56 +1,616 @@
 //! Configuration loading for the DEX API service.
 //!
-//! Centralizes environment parsing and keeps sensitive values wrapped in
-//! secrecy primitives.
+//! Centralizes parsing of environment variables and the configuration files
+//! layered under them (see [`crate::config_file`]), and keeps sensitive
+//! values wrapped in secrecy primitives.
 
-use dotenvy::dotenv;
+use crate::{
+    auth::{KeyMaterial, Scope, SigningKey},
+    book_snapshot,
+    bridge::BridgeConfig,
+    challenge::SignInDomain,
+    chaos::ChaosConfig,
+    config_file,
+    cors::{CorsConfig, InvalidCors},
+    event_stream::EventStreamConfig,
+    ip_allowlist::{Allowlist, TrustedProxies},
+    kafka::{KafkaSasl, KafkaSecurity, KafkaTls, SaslMechanism},
+    leader::ElectionConfig,
+    lockout::LockoutConfig,
+    margin::MarginConfig,
+    market_feed::{FeedConfig, FeedRole},
+    messaging_policy::MessagingPolicy,
+    object_store::{S3Config, StoreLocation},
+    pubsub::PubSubUrl,
+    rate_limit::{Budget, RateLimitConfig},
+    retention::RetentionConfig,
+    sealing::SealingKey,
+    secrets::{SecretError, SecretSource, SecretValues, TraderSecrets},
+    sequencing::SequencerKey,
+    settings::{FeeRates, FeeSchedule, Settings},
+    telemetry::LogFormat,
+    tls::{ClientAuth, ClientIdentity, TlsConfig, DEFAULT_RELOAD_INTERVAL_SECONDS},
+    ws_outbox::WsBackpressure,
+};
+use dex_core::{
+    matching::{MatchingAlgorithm, MatchingRegistry},
+    types::{TokenId, TradingPair},
+    wrapped_assets::WrappedAsset,
+};
+use dex_db::{
+    instrument::QueryLimits,
+    pool::PoolConfig,
+    resilience::{BreakerConfig, ResilienceConfig, RetryPolicy},
+};
+use dotenvy::{dotenv, dotenv_override};
+use ethers_core::utils::hex;
 use secrecy::SecretString;
-use std::{collections::HashMap, env, num::ParseIntError};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    num::ParseIntError,
+    path::PathBuf,
+    time::Duration,
+};
 use thiserror::Error;
+use tracing_subscriber::EnvFilter;
 
 /// Runtime configuration for the API service.
 #[derive(Clone)]
 pub struct Config {
-    pub database_url: SecretString,
-    pub jwt_secret: SecretString,
+    /// Where orders, trades and everything else are stored.
+    pub storage: StorageBackend,
+    /// Read replica for trade history, candles and ticker counters; Postgres
+    /// storage only.
+    pub database_replica_url: Option<SecretString>,
+    /// Signing keys and their rotation schedule; never empty.
+    pub jwt_keys: Vec<SigningKey>,
     pub jwt_issuer: String,
     pub jwt_default_ttl_seconds: u64,
     pub jwt_max_ttl_seconds: u64,
     pub wallet_challenge_ttl_seconds: u64,
-    pub trader_secrets: HashMap<String, SecretString>,
+    /// Chain ID in the EIP-712 domain and SIWE message of wallet sign-in
+    /// challenges.
+    pub wallet_chain_id: u64,
+    /// Domain and URI SIWE sign-in messages name.
+    pub siwe_domain: String,
+    pub siwe_uri: String,
+    /// Lifetime of a refresh token; each refresh issues a new one.
+    pub refresh_token_ttl_seconds: u64,
+    /// Shared with every clone of the config, so a secrets refresh
+    /// reaches them all.
+    pub trader_secrets: TraderSecrets,
+    /// Secrets manager that overrides the credentials above; the
+    /// environment alone when unset.
+    pub secrets: Option<SecretSource>,
+    pub secrets_refresh_seconds: u64,
+    /// Key TOTP secrets are sealed with; two-factor enrollment is off
+    /// without it.
+    pub totp_key: Option<SealingKey>,
+    /// Key API key secrets are sealed with; API keys cannot be created
+    /// without it.
+    pub api_key_encryption_key: Option<SealingKey>,
+    /// Address the API listens on; loopback unless it faces clients directly.
+    pub server_host: IpAddr,
     pub server_port: u16,
+    /// Address of a second listener serving only the admin endpoints and
+    /// metrics, which the public listener then leaves out; plain HTTP, for
+    /// a private network.
+    pub internal_addr: Option<SocketAddr>,
+    /// Certificate and key for serving HTTPS; plain HTTP when unset.
+    pub tls: Option<TlsConfig>,
+    pub db_resilience: ResilienceConfig,
+    pub db_probe_interval_seconds: u64,
+    pub db_query_limits: QueryLimits,
+    pub db_pool: PoolConfig,
+    pub chaos: ChaosConfig,
+    pub ws_heartbeat: WsHeartbeat,
+    pub ws_backpressure: WsBackpressure,
+    /// Key sequencing receipts are signed with; a key generated at start,
+    /// which receipts issued before a restart no longer verify against,
+    /// when unset.
+    pub sequencer_key: Option<SequencerKey>,
+    /// Region named in sequencing receipts.
+    pub sequencer_region: String,
+    /// Port for the FIX order-entry gateway; disabled when unset.
+    pub fix_port: Option<u16>,
+    /// Address the FIX gateway listens on; loopback by default.
+    pub fix_host: IpAddr,
+    /// SenderCompID the FIX gateway uses in its messages.
+    pub fix_comp_id: String,
+    /// Port for the gRPC `dex.v1.Exchange` service; disabled when unset.
+    pub grpc_port: Option<u16>,
+    /// Address the gRPC service listens on; loopback by default.
+    pub grpc_host: IpAddr,
+    pub rate_limits: RateLimitConfig,
+    /// Maker and taker fees booked on order book fills.
+    pub fees: FeeSchedule,
+    /// Pairs that accept no new orders.
+    pub trading_halts: BTreeSet<TradingPair>,
+    /// Backoff and lockout after failed sign-ins.
+    pub auth_lockout: LockoutConfig,
+    /// Stable that synthetic USD quotes are priced against.
+    pub usd_reference_token: TokenId,
+    pub usd_price_refresh_seconds: u64,
+    /// How often API usage counts are added to the daily rollups.
+    pub usage_flush_interval_seconds: u64,
+    /// How often cumulative market counters are stored.
+    pub counter_flush_interval_seconds: u64,
+    /// How often new one-minute candles are aggregated.
+    pub candle_aggregation_interval_seconds: u64,
+    /// How often changed AMM tick maps are stored.
+    pub tick_map_flush_interval_seconds: u64,
+    /// How often changed AMM pools are stored.
+    pub pool_flush_interval_seconds: u64,
+    /// How often access tokens revoked on other instances are read back.
+    pub revocation_sync_interval_seconds: u64,
+    /// When old trades and orders move to the archive tables.
+    pub retention: RetentionConfig,
+    /// How long shutdown waits for in-flight orders and closing sockets.
+    pub shutdown_grace_seconds: u64,
+    /// Order-to-trade ratio limits and the penalties for breaching them.
+    pub messaging_policy: MessagingPolicy,
+    /// Margin rates, correlations and per-trader limits on resting orders.
+    pub margin: MarginConfig,
+    /// Refuse orders the trader's ledger balance does not cover.
+    pub require_funded_orders: bool,
+    /// How each pair splits a price level's fill across its makers.
+    pub matching: MatchingRegistry,
+    /// JWT subjects allowed to use the `/admin` endpoints.
+    pub admin_subjects: HashSet<String>,
+    /// Networks each subject's credentials may be used from; subjects
+    /// without one may use theirs anywhere.
+    pub ip_allowlists: HashMap<String, Allowlist>,
+    /// Proxies whose `X-Forwarded-For` names the client; none by default.
+    pub trusted_proxies: TrustedProxies,
+    /// Browser origins that may call the API; CORS is off by default.
+    pub cors: CorsConfig,
+    /// Chains deposits are accepted from and the assets they credit.
+    pub bridge: BridgeConfig,
+    /// How long after execution a trade may still be busted or re-priced.
+    pub trade_adjust_window_seconds: u64,
+    /// Directory for book snapshots and their write-ahead log; without it the
+    /// book is rebuilt from Postgres on every start.
+    pub book_snapshot_dir: Option<PathBuf>,
+    pub book_snapshot_interval_seconds: u64,
+    /// Size at which a write-ahead log segment is closed.
+    pub book_journal_segment_bytes: u64,
+    /// Where log segments covered by a snapshot are kept; without it they
+    /// are deleted.
+    pub book_journal_archive_dir: Option<PathBuf>,
+    /// How often the whole book is checkpointed, and the bands near the
+    /// touch the checkpoints between capture.
+    pub book_snapshot_bands: book_snapshot::BandOptions,
+    /// How far a signed request's timestamp may be from the server clock.
+    pub api_key_replay_window_seconds: u64,
+    /// Where the market data recorder archives segments; off when unset.
+    pub market_data_archive: Option<StoreLocation>,
+    /// Depth levels per side the recorder tracks for each pair.
+    pub market_data_archive_depth_levels: usize,
+    /// Pub/sub backend that book changes and trades are fanned out over to
+    /// replicas, and which side of it this instance is; off when unset.
+    pub market_feed: Option<FeedConfig>,
+    /// Kafka cluster that accepted orders, cancels and trades are streamed
+    /// to through the outbox; off when unset.
+    pub event_stream: Option<EventStreamConfig>,
+    /// Advisory lock instances contend for, so that only one matches while
+    /// the others stand by; every matching instance leads when unset.
+    pub leader_election: Option<ElectionConfig>,
+    /// Seed that fixes the clock, random identifiers and, unless
+    /// `CHAOS_SEED` is set, chaos faults, for reproducible simulations. Only
+    /// builds with the `deterministic` feature accept one.
+    pub deterministic_seed: Option<u64>,
+    /// OTLP/HTTP collector that spans are exported to; tracing is off when
+    /// unset.
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` reported with exported spans.
+    pub otel_service_name: String,
+    /// Share of new traces sampled; requests that arrive with a sampled
+    /// `traceparent` are always traced.
+    pub trace_sample_ratio: f64,
+    /// Filter directives for log lines and spans, e.g. `info` or
+    /// `info,dex_db=debug`.
+    pub log_level: String,
+    /// How log lines are written to stdout.
+    pub log_format: LogFormat,
+}
+
+/// Backend chosen by `STORAGE_BACKEND`.
+#[derive(Clone)]
+pub enum StorageBackend {
+    /// Postgres at `DATABASE_URL`; the default.
+    Postgres(SecretString),
+    /// One SQLite file at `SQLITE_PATH`, for a single instance without a
+    /// database server.
+    Sqlite(PathBuf),
+    /// Kept in memory and lost on restart, for tests, simulations and trying
+    /// the exchange out without a database.
+    Memory,
+}
+
+/// Keepalive settings for WebSocket sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsHeartbeat {
+    /// How often the server pings each connection.
+    pub ping_interval: Duration,
+    /// Connections that send nothing, not even a pong, for this long are closed.
+    pub idle_timeout: Duration,
+}
+
+impl Default for WsHeartbeat {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(30),
+            idle_timeout: Duration::from_secs(90),
+        }
+    }
 }
 
 impl Config {
+    /// Whether this instance mirrors another's book instead of matching.
+    pub fn is_replica(&self) -> bool {
+        self.market_feed
+            .as_ref()
+            .is_some_and(|feed| feed.role == FeedRole::Replica)
+    }
+
+    /// How wallet sign-in challenges name this server.
+    pub fn sign_in_domain(&self) -> SignInDomain {
+        SignInDomain {
+            domain: self.siwe_domain.clone(),
+            uri: self.siwe_uri.clone(),
+            chain_id: self.wallet_chain_id,
+        }
+    }
+
     /// Load configuration from environment variables, honoring values supplied
-    /// via a `.env` file when present.
-    pub fn from_env() -> Result<Self, ConfigError> {
+    /// via a `.env` file when present and falling back to the files named by
+    /// `CONFIG_FILES`, with credentials from `SECRETS_SOURCE` when one is
+    /// configured.
+    pub async fn load() -> Result<Self, ConfigError> {
+        Ok(Self::load_with_secrets().await?.0)
+    }
+
+    /// Like [`Config::load`], also returning the values read from the
+    /// secrets source, for refreshing them later.
+    pub async fn load_with_secrets() -> Result<(Self, SecretValues), ConfigError> {
         dotenv().ok();
+        config_file::reload()?;
+
+        let source = parse_secret_source().map_err(config_file::locate)?;
+        let values = match &source {
+            Some(source) => source.fetch().await.map_err(|err| ConfigError::Secrets {
+                from: source.to_string(),
+                err: Box::new(err),
+            })?,
+            None => SecretValues::default(),
+        };
+        let config = Self::from_vars(source, &values).map_err(config_file::locate)?;
+        Ok((config, values))
+    }
+
+    /// Read the settings that can change without a restart again, with
+    /// values in `.env` taking precedence over the ones read at start and
+    /// the configuration files read again.
+    pub fn reload_settings() -> Result<Settings, ConfigError> {
+        dotenv_override().ok();
+        config_file::reload()?;
+        let settings = || {
+            let (log_level, _) = parse_logging()?;
+            Ok(Settings {
+                rate_limits: parse_rate_limits()?,
+                fees: parse_fees()?,
+                trading_halts: parse_trading_halts(lookup("TRADING_HALTS").ok())?,
+                log_level,
+            })
+        };
+        settings().map_err(config_file::locate)
+    }
 
-        let database_url =
-            env::var("DATABASE_URL").map_err(|_| ConfigError::Missing("DATABASE_URL"))?;
-        let jwt_secret = env::var("JWT_SECRET").map_err(|_| ConfigError::Missing("JWT_SECRET"))?;
+    fn from_vars(
+        secrets: Option<SecretSource>,
+        values: &SecretValues,
+    ) -> Result<Self, ConfigError> {
+        let storage = match lookup("STORAGE_BACKEND")
+            .ok()
+            .map(|raw| raw.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            None | Some("") | Some("postgres") => {
+                StorageBackend::Postgres(secret_database_url(values)?)
+            }
+            Some("sqlite") => StorageBackend::Sqlite(
+                lookup("SQLITE_PATH")
+                    .ok()
+                    .filter(|path| !path.trim().is_empty())
+                    .map_or_else(|| PathBuf::from("dex-os.db"), PathBuf::from),
+            ),
+            Some("memory") => StorageBackend::Memory,
+            Some(other) => return Err(ConfigError::InvalidStorageBackend(other.to_string())),
+        };
+        let database_replica_url = secret_database_replica_url(values);
+        let jwt_keys = secret_jwt_keys(values)?;
 
-        let server_port = parse_server_port(env::var("SERVER_PORT").ok())?;
-        let jwt_issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| "dex-os-api".to_string());
+        let server_host = parse_host("SERVER_HOST")?;
+        let server_port = parse_server_port(lookup("SERVER_PORT").ok())?;
+        let internal_addr = lookup("INTERNAL_ADDR")
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<SocketAddr>()
+                    .map_err(|_| ConfigError::InvalidListenAddress {
+                        var: "INTERNAL_ADDR",
+                        value,
+                    })
+            })
+            .transpose()?;
+        let tls = parse_tls()?;
+        let siwe_domain =
+            lookup("SIWE_DOMAIN").unwrap_or_else(|_| format!("localhost:{}", server_port));
+        let siwe_uri = lookup("SIWE_URI").unwrap_or_else(|_| format!("http://{}", siwe_domain));
+        let jwt_issuer = lookup("JWT_ISSUER").unwrap_or_else(|_| "dex-os-api".to_string());
         let jwt_default_ttl_seconds = parse_u64("JWT_TTL_SECONDS", 900)?;
         let jwt_max_ttl_seconds = parse_u64("JWT_MAX_TTL_SECONDS", 3600)?;
         let wallet_challenge_ttl_seconds = parse_u64("WALLET_CHALLENGE_TTL_SECONDS", 300)?;
-        let trader_secrets = parse_trader_secrets(env::var("TRADER_SECRETS").ok())?;
+        let wallet_chain_id = parse_u64("WALLET_CHAIN_ID", 1)?;
+        let refresh_token_ttl_seconds = parse_u64("REFRESH_TOKEN_TTL_SECONDS", 30 * 24 * 3600)?;
+        let trader_secrets = secret_trader_secrets(values)?;
+        let secrets_refresh_seconds = parse_u64("SECRETS_REFRESH_SECONDS", 300)?;
+        let parse_sealing_key = |var: &'static str| {
+            lookup(var)
+                .ok()
+                .filter(|raw| !raw.trim().is_empty())
+                .map(|raw| SealingKey::parse(&raw).ok_or(ConfigError::InvalidSealingKey { var }))
+                .transpose()
+        };
+        let totp_key = parse_sealing_key("TOTP_ENCRYPTION_KEY")?;
+        let api_key_encryption_key = parse_sealing_key("API_KEY_ENCRYPTION_KEY")?;
+        let db_resilience = parse_db_resilience()?;
+        let db_probe_interval_seconds = parse_u64("DB_PROBE_INTERVAL_SECONDS", 5)?;
+        let db_query_timeout_ms = parse_u64("DB_QUERY_TIMEOUT_MS", 5000)?;
+        let db_slow_query_ms = parse_u64("DB_SLOW_QUERY_MS", 200)?;
+        let db_pool = parse_db_pool()?;
+        let ws_ping_interval_seconds = parse_u64("WS_PING_INTERVAL_SECONDS", 30)?.max(1);
+        let ws_idle_timeout_seconds = parse_u64("WS_IDLE_TIMEOUT_SECONDS", 90)?;
+        let ws_backpressure = parse_ws_backpressure()?;
+        let fix_port = lookup("FIX_PORT")
+            .ok()
+            .map(|port| port.parse::<u16>())
+            .transpose()
+            .map_err(|err| ConfigError::InvalidNumber {
+                var: "FIX_PORT",
+                err,
+            })?;
+        let sequencer_key = lookup("SEQUENCER_SIGNING_KEY")
+            .ok()
+            .filter(|raw| !raw.trim().is_empty())
+            .map(|raw| SequencerKey::parse(&raw).ok_or(ConfigError::InvalidSequencerKey))
+            .transpose()?;
+        let sequencer_region = match lookup("SEQUENCER_REGION") {
+            Ok(region) if region.trim().is_empty() || region.trim().contains(char::is_control) => {
+                return Err(ConfigError::InvalidSequencerRegion(region))
+            }
+            Ok(region) => region.trim().to_string(),
+            Err(_) => "default".to_string(),
+        };
+        let fix_host = parse_host("FIX_HOST")?;
+        let fix_comp_id = lookup("FIX_COMP_ID").unwrap_or_else(|_| "DEXOS".to_string());
+        let grpc_port = lookup("GRPC_PORT")
+            .ok()
+            .map(|port| port.parse::<u16>())
+            .transpose()
+            .map_err(|err| ConfigError::InvalidNumber {
+                var: "GRPC_PORT",
+                err,
+            })?;
+        let grpc_host = parse_host("GRPC_HOST")?;
+        let rate_limits = parse_rate_limits()?;
+        let fees = parse_fees()?;
+        let trading_halts = parse_trading_halts(lookup("TRADING_HALTS").ok())?;
+        let lockout_defaults = LockoutConfig::default();
+        let auth_lockout_threshold =
+            parse_u64("AUTH_LOCKOUT_THRESHOLD", lockout_defaults.threshold.into())?;
+        let auth_lockout_base_seconds =
+            parse_u64("AUTH_LOCKOUT_BASE_SECONDS", lockout_defaults.base_seconds)?;
+        let auth_lockout_max_seconds =
+            parse_u64("AUTH_LOCKOUT_MAX_SECONDS", lockout_defaults.max_seconds)?;
+        let usd_reference_token = match lookup("USD_REFERENCE_TOKEN") {
+            Ok(value) => TokenId::parse(&value).map_err(|_| ConfigError::InvalidToken {
+                var: "USD_REFERENCE_TOKEN",
+                value,
+            })?,
+            Err(_) => TokenId::parse("USDC").expect("valid token"),
+        };
+        let usd_price_refresh_seconds = parse_u64("USD_PRICE_REFRESH_SECONDS", 5)?;
+        let usage_flush_interval_seconds = parse_u64("USAGE_FLUSH_INTERVAL_SECONDS", 60)?;
+        let counter_flush_interval_seconds = parse_u64("COUNTER_FLUSH_INTERVAL_SECONDS", 30)?;
+        let candle_aggregation_interval_seconds =
+            parse_u64("CANDLE_AGGREGATION_INTERVAL_SECONDS", 60)?;
+        let tick_map_flush_interval_seconds = parse_u64("TICK_MAP_FLUSH_INTERVAL_SECONDS", 30)?;
+        let pool_flush_interval_seconds = parse_u64("POOL_FLUSH_INTERVAL_SECONDS", 5)?;
+        let revocation_sync_interval_seconds = parse_u64("REVOCATION_SYNC_INTERVAL_SECONDS", 5)?;
+        let retention = parse_retention()?;
+        let shutdown_grace_seconds = parse_u64("SHUTDOWN_GRACE_SECONDS", 30)?;
+        let messaging_policy = parse_messaging_policy()?;
+        let margin = parse_margin()?;
+        let require_funded_orders = parse_flag("REQUIRE_FUNDED_ORDERS", false)?;
+        let matching = parse_matching(lookup("MATCHING_POLICIES").ok())?;
+        let admin_subjects = parse_admin_subjects(lookup("ADMIN_SUBJECTS").ok());
+        let ip_allowlists = parse_ip_allowlists(lookup("IP_ALLOWLISTS").ok())?;
+        let trusted_proxies =
+            TrustedProxies::parse(lookup("TRUSTED_PROXIES").unwrap_or_default().split(','))
+                .map_err(|err| ConfigError::InvalidTrustedProxy(err.0))?;
+        let cors = CorsConfig::parse(
+            &lookup("CORS_ALLOWED_ORIGINS").unwrap_or_default(),
+            &lookup("CORS_ALLOWED_HEADERS").unwrap_or_default(),
+            parse_u64("CORS_MAX_AGE_SECONDS", crate::cors::DEFAULT_MAX_AGE_SECONDS)?,
+        )?;
+        let bridge = parse_bridge()?;
+        let trade_adjust_window_seconds = parse_u64("TRADE_ADJUST_WINDOW_SECONDS", 3600)?;
+        let book_snapshot_dir = lookup("BOOK_SNAPSHOT_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from);
+        let book_snapshot_interval_seconds = parse_u64("BOOK_SNAPSHOT_INTERVAL_SECONDS", 60)?;
+        let book_journal_segment_bytes = parse_u64(
+            "BOOK_JOURNAL_SEGMENT_BYTES",
+            book_snapshot::DEFAULT_SEGMENT_BYTES,
+        )?;
+        let book_journal_archive_dir = lookup("BOOK_JOURNAL_ARCHIVE_DIR")
+            .ok()
+            .filter(|dir| !dir.trim().is_empty())
+            .map(PathBuf::from);
+        let band_defaults = book_snapshot::BandOptions::default();
+        let book_full_snapshot_every =
+            parse_u64("BOOK_FULL_SNAPSHOT_EVERY", band_defaults.full_every.into())?;
+        let book_snapshot_band_min_bps =
+            parse_u64("BOOK_SNAPSHOT_BAND_MIN_BPS", band_defaults.min_bps.into())?;
+        let book_snapshot_band_max_bps =
+            parse_u64("BOOK_SNAPSHOT_BAND_MAX_BPS", band_defaults.max_bps.into())?;
+        let api_key_replay_window_seconds = parse_u64("API_KEY_REPLAY_WINDOW_SECONDS", 30)?;
+        let market_data_archive = parse_market_data_archive()?;
+        let market_data_archive_depth_levels = parse_u64("MARKET_DATA_ARCHIVE_DEPTH_LEVELS", 50)?;
+        let market_feed = parse_market_feed()?;
+        let event_stream = parse_event_stream()?;
+        let leader_election = parse_leader_election()?;
+        if leader_election.is_some() && !matches!(storage, StorageBackend::Postgres(_)) {
+            return Err(ConfigError::ElectionWithoutDatabase);
+        }
+        let deterministic_seed = parse_deterministic_seed()?;
+        let otlp_endpoint = lookup("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .ok()
+            .map(|endpoint| endpoint.trim().trim_end_matches('/').to_string())
+            .filter(|endpoint| !endpoint.is_empty());
+        let otel_service_name = lookup("OTEL_SERVICE_NAME")
+            .ok()
+            .filter(|name| !name.trim().is_empty())
+            .unwrap_or_else(|| "dex-api".to_string());
+        let trace_sample_ratio = parse_rate("TRACE_SAMPLE_RATIO", 1.0)?;
+        let (log_level, log_format) = parse_logging()?;
+        #[cfg(feature = "chaos")]
+        let chaos = parse_chaos(deterministic_seed)?;
+        #[cfg(not(feature = "chaos"))]
+        let chaos = ChaosConfig::default();
 
         Ok(Self {
-            database_url: SecretString::from(database_url),
-            jwt_secret: SecretString::from(jwt_secret),
+            storage,
+            database_replica_url,
+            jwt_keys,
             jwt_issuer,
             jwt_default_ttl_seconds: jwt_default_ttl_seconds.max(60),
             jwt_max_ttl_seconds: jwt_max_ttl_seconds.max(jwt_default_ttl_seconds),
             wallet_challenge_ttl_seconds: wallet_challenge_ttl_seconds.max(60),
-            trader_secrets,
+            wallet_chain_id,
+            siwe_domain,
+            siwe_uri,
+            refresh_token_ttl_seconds: refresh_token_ttl_seconds.max(60),
+            trader_secrets: trader_secrets.into(),
+            secrets,
+            secrets_refresh_seconds: secrets_refresh_seconds.max(1),
+            totp_key,
+            api_key_encryption_key,
+            server_host,
             server_port,
+            internal_addr,
+            tls,
+            db_resilience,
+            db_probe_interval_seconds: db_probe_interval_seconds.max(1),
+            db_query_limits: QueryLimits {
+                timeout: Duration::from_millis(db_query_timeout_ms.max(1)),
+                slow_threshold: Duration::from_millis(db_slow_query_ms),
+            },
+            db_pool,
+            chaos,
+            ws_heartbeat: WsHeartbeat {
+                ping_interval: Duration::from_secs(ws_ping_interval_seconds),
+                // Leave room for at least one ping round trip.
+                idle_timeout: Duration::from_secs(
+                    ws_idle_timeout_seconds.max(ws_ping_interval_seconds * 2),
+                ),
+            },
+            ws_backpressure,
+            sequencer_key,
+            sequencer_region,
+            fix_port,
+            fix_host,
+            fix_comp_id,
+            grpc_port,
+            grpc_host,
+            rate_limits,
+            fees,
+            trading_halts,
+            auth_lockout: LockoutConfig {
+                threshold: auth_lockout_threshold.min(u32::MAX.into()) as u32,
+                base_seconds: auth_lockout_base_seconds.max(1),
+                max_seconds: auth_lockout_max_seconds.max(auth_lockout_base_seconds),
+            },
+            usd_reference_token,
+            usd_price_refresh_seconds: usd_price_refresh_seconds.max(1),
+            usage_flush_interval_seconds: usage_flush_interval_seconds.max(1),
+            counter_flush_interval_seconds: counter_flush_interval_seconds.max(1),
+            candle_aggregation_interval_seconds: candle_aggregation_interval_seconds.max(1),
+            tick_map_flush_interval_seconds: tick_map_flush_interval_seconds.max(1),
+            pool_flush_interval_seconds: pool_flush_interval_seconds.max(1),
+            revocation_sync_interval_seconds: revocation_sync_interval_seconds.max(1),
+            retention,
+            shutdown_grace_seconds,
+            messaging_policy,
+            margin,
+            require_funded_orders,
+            matching,
+            admin_subjects,
+            ip_allowlists,
+            trusted_proxies,
+            cors,
+            bridge,
+            trade_adjust_window_seconds,
+            book_snapshot_dir,
+            book_snapshot_interval_seconds: book_snapshot_interval_seconds.max(1),
+            book_journal_segment_bytes: book_journal_segment_bytes.max(4096),
+            book_journal_archive_dir,
+            book_snapshot_bands: book_snapshot::BandOptions {
+                full_every: book_full_snapshot_every.clamp(1, u32::MAX.into()) as u32,
+                min_bps: book_snapshot_band_min_bps.min(10_000) as u32,
+                max_bps: book_snapshot_band_max_bps
+                    .clamp(book_snapshot_band_min_bps.min(10_000), 10_000)
+                    as u32,
+            },
+            api_key_replay_window_seconds: api_key_replay_window_seconds.max(1),
+            market_data_archive,
+            market_data_archive_depth_levels: market_data_archive_depth_levels.clamp(1, 1000)
+                as usize,
+            market_feed,
+            event_stream,
+            leader_election,
+            deterministic_seed,
+            otlp_endpoint,
+            otel_service_name,
+            trace_sample_ratio,
+            log_level,
+            log_format,
         })
     }
 }
 
+/// `name` from the environment, falling back to the configuration files.
+fn lookup(name: &str) -> Result<String, env::VarError> {
+    env::var(name).or_else(|err| config_file::get(name).ok_or(err))
+}
+
+/// An IP address to listen on, loopback unless `var` is set.
+fn parse_host(var: &'static str) -> Result<IpAddr, ConfigError> {
+    match lookup(var) {
+        Ok(value) => value
+            .trim()
+            .parse::<IpAddr>()
+            .map_err(|_| ConfigError::InvalidAddress { var, value }),
+        Err(_) => Ok(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+    }
+}
+
 fn parse_server_port(raw: Option<String>) -> Result<u16, ConfigError> {
     match raw {
         Some(value) => value
@@ -71,12 +631,152 @@ pub enum ConfigError {
         var: &'static str,
         err: ParseIntError,
     },
+    #[error("invalid value for {var}: {value}, expected a rate between 0 and 1")]
+    InvalidRate { var: &'static str, value: String },
+    #[error(
+        "invalid JWT_KEYS entry for key '{kid}', expected kid:active_from[-expires_at]:secret"
+    )]
+    InvalidJwtKey { kid: String },
+    #[error("failed to read signing key {path}: {err}")]
+    KeyFile { path: String, err: std::io::Error },
     #[error("invalid TRADER_SECRETS entry '{entry}', expected trader:secret")]
     InvalidTraderSecret { entry: String },
+    #[error("invalid token for {var}: {value}")]
+    InvalidToken { var: &'static str, value: String },
+    #[error("invalid MARKET_DATA_ARCHIVE {0}, expected s3://bucket/prefix or a directory")]
+    InvalidArchive(String),
+    #[error("invalid MARKET_FEED_URL {0}, expected redis://host:port or nats://host:port")]
+    InvalidMarketFeedUrl(String),
+    #[error("invalid MARKET_FEED_ROLE {0}, expected publisher or replica")]
+    InvalidMarketFeedRole(String),
+    #[error("invalid MARKET_FEED_PREFIX {0}, expected letters, digits, '.', '-' or '_'")]
+    InvalidMarketFeedPrefix(String),
+    #[error("invalid KAFKA_BROKERS {0}, expected comma-separated host:port")]
+    InvalidKafkaBrokers(String),
+    #[error("invalid KAFKA_TOPIC_PREFIX {0}, expected letters, digits, '.', '-' or '_'")]
+    InvalidKafkaTopicPrefix(String),
+    #[error("invalid KAFKA_SASL_MECHANISM {0}, expected plain, scram-sha-256 or scram-sha-512")]
+    InvalidKafkaSaslMechanism(String),
+    #[error("invalid {var}, expected 32 bytes in base64")]
+    InvalidSealingKey { var: &'static str },
+    #[error("invalid value for {var}: {value}, expected a positive order-to-trade ratio")]
+    InvalidRatio { var: &'static str, value: String },
+    #[error("invalid {var} entry '{value}', expected {expected}")]
+    InvalidMargin {
+        var: &'static str,
+        value: String,
+        expected: &'static str,
+    },
+    #[error("invalid MATCHING_POLICIES entry '{entry}', expected pair=price_time|pro_rata|pro_rata_top_order")]
+    InvalidMatching { entry: String },
+    #[error("invalid {var} value '{value}', expected basis points between 0 and 10000")]
+    InvalidFee { var: &'static str, value: String },
+    #[error("invalid TRADING_HALTS entry '{entry}', expected a pair such as ETH-USDC")]
+    InvalidTradingHalt { entry: String },
+    #[error("invalid TLS_CLIENT_IDENTITIES entry '{entry}', expected name=subject:scope|scope")]
+    InvalidClientIdentity { entry: String },
+    #[error("invalid IP_ALLOWLISTS entry '{entry}', expected subject=cidr|cidr")]
+    InvalidAllowlist { entry: String },
+    #[error("invalid TRUSTED_PROXIES entry '{0}', expected an address or address/prefix")]
+    InvalidTrustedProxy(String),
+    #[error("invalid SEQUENCER_SIGNING_KEY, expected a 32-byte Ed25519 seed in base64")]
+    InvalidSequencerKey,
+    #[error("invalid SEQUENCER_REGION {0:?}, expected a name on one line")]
+    InvalidSequencerRegion(String),
+    #[error("invalid value for {var}: {value}, expected true or false")]
+    InvalidFlag { var: &'static str, value: String },
+    #[error("invalid value for {var}: {value}, expected an IP address")]
+    InvalidAddress { var: &'static str, value: String },
+    #[error(
+        "invalid value for {var}: {value}, expected an address and port such as 127.0.0.1:9090"
+    )]
+    InvalidListenAddress { var: &'static str, value: String },
+    #[error("invalid STORAGE_BACKEND {0}, expected postgres, sqlite or memory")]
+    InvalidStorageBackend(String),
+    #[error("LEADER_ELECTION_KEY needs instances sharing a database, so STORAGE_BACKEND=postgres")]
+    ElectionWithoutDatabase,
+    #[error("DETERMINISTIC_SEED needs a build with --features deterministic")]
+    SeedWithoutFeature,
+    #[error("invalid SECRETS_SOURCE {0}, expected file, vault or aws")]
+    InvalidSecretsSource(String),
+    #[error("failed to read secrets from {from}: {err}")]
+    Secrets { from: String, err: Box<SecretError> },
+    #[error("invalid BRIDGE_ETHEREUM_CONTRACT {0}, expected a 20-byte hex address")]
+    InvalidBridgeContract(String),
+    #[error("invalid or repeated BRIDGE_ASSETS entry '{entry}', expected chain:ASSET=TOKEN")]
+    InvalidBridgeAsset { entry: String },
+    #[error("invalid, repeated or unbridged WRAPPED_ASSETS entry '{entry}', expected TOKEN=chain:wallet")]
+    InvalidWrappedAsset { entry: String },
+    #[error("invalid LOG_LEVEL {0}, expected filter directives such as info or info,dex_db=debug")]
+    InvalidLogLevel(String),
+    #[error("invalid LOG_FORMAT {0}, expected json or text")]
+    InvalidLogFormat(String),
+    #[error("invalid WS_SLOW_CONSUMER_POLICY {0}, expected disconnect or drop")]
+    InvalidSlowConsumerPolicy(String),
+    #[error("invalid CORS setting: {0}")]
+    InvalidCors(#[from] InvalidCors),
+    #[error("failed to read config file {path}: {err}")]
+    File { path: String, err: String },
+    #[error("{path}: unknown setting {field}")]
+    UnknownField { path: String, field: String },
+    #[error("{path}: invalid {field}, expected a value or a list of values")]
+    InvalidField { path: String, field: String },
+    #[error("{path}: invalid {field}: {err}")]
+    InFile {
+        path: String,
+        field: String,
+        err: Box<ConfigError>,
+    },
+}
+
+impl ConfigError {
+    /// The variable whose value was rejected, if the error is about one.
+    pub fn var(&self) -> Option<&'static str> {
+        match self {
+            Self::InvalidPort { .. } => Some("SERVER_PORT"),
+            Self::InvalidNumber { var, .. }
+            | Self::InvalidRate { var, .. }
+            | Self::InvalidToken { var, .. }
+            | Self::InvalidRatio { var, .. }
+            | Self::InvalidSealingKey { var }
+            | Self::InvalidMargin { var, .. }
+            | Self::InvalidFee { var, .. }
+            | Self::InvalidFlag { var, .. }
+            | Self::InvalidAddress { var, .. }
+            | Self::InvalidListenAddress { var, .. } => Some(var),
+            Self::InvalidJwtKey { .. } => Some("JWT_KEYS"),
+            Self::InvalidTraderSecret { .. } => Some("TRADER_SECRETS"),
+            Self::InvalidArchive(_) => Some("MARKET_DATA_ARCHIVE"),
+            Self::InvalidMarketFeedUrl(_) => Some("MARKET_FEED_URL"),
+            Self::InvalidMarketFeedRole(_) => Some("MARKET_FEED_ROLE"),
+            Self::InvalidMarketFeedPrefix(_) => Some("MARKET_FEED_PREFIX"),
+            Self::InvalidKafkaBrokers(_) => Some("KAFKA_BROKERS"),
+            Self::InvalidKafkaTopicPrefix(_) => Some("KAFKA_TOPIC_PREFIX"),
+            Self::InvalidKafkaSaslMechanism(_) => Some("KAFKA_SASL_MECHANISM"),
+            Self::InvalidSequencerKey => Some("SEQUENCER_SIGNING_KEY"),
+            Self::InvalidSequencerRegion(_) => Some("SEQUENCER_REGION"),
+            Self::InvalidMatching { .. } => Some("MATCHING_POLICIES"),
+            Self::InvalidTradingHalt { .. } => Some("TRADING_HALTS"),
+            Self::InvalidClientIdentity { .. } => Some("TLS_CLIENT_IDENTITIES"),
+            Self::InvalidAllowlist { .. } => Some("IP_ALLOWLISTS"),
+            Self::InvalidTrustedProxy(_) => Some("TRUSTED_PROXIES"),
+            Self::InvalidStorageBackend(_) => Some("STORAGE_BACKEND"),
+            Self::ElectionWithoutDatabase => Some("LEADER_ELECTION_KEY"),
+            Self::SeedWithoutFeature => Some("DETERMINISTIC_SEED"),
+            Self::InvalidSecretsSource(_) => Some("SECRETS_SOURCE"),
+            Self::InvalidBridgeContract(_) => Some("BRIDGE_ETHEREUM_CONTRACT"),
+            Self::InvalidBridgeAsset { .. } => Some("BRIDGE_ASSETS"),
+            Self::InvalidWrappedAsset { .. } => Some("WRAPPED_ASSETS"),
+            Self::InvalidLogLevel(_) => Some("LOG_LEVEL"),
+            Self::InvalidLogFormat(_) => Some("LOG_FORMAT"),
+            Self::InvalidSlowConsumerPolicy(_) => Some("WS_SLOW_CONSUMER_POLICY"),
+            _ => None,
+        }
+    }
 }
 
 fn parse_u64(var: &'static str, default: u64) -> Result<u64, ConfigError> {
-    match env::var(var) {
+    match lookup(var) {
         Ok(value) => value
             .parse::<u64>()
             .map_err(|err| ConfigError::InvalidNumber { var, err }),
@@ -84,6 +784,833 @@ fn parse_u64(var: &'static str, default: u64) -> Result<u64, ConfigError> {
     }
 }
 
+fn parse_flag(var: &'static str, default: bool) -> Result<bool, ConfigError> {
+    match lookup(var) {
+        Ok(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(ConfigError::InvalidFlag { var, value }),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+fn parse_rate(var: &'static str, default: f64) -> Result<f64, ConfigError> {
+    match lookup(var) {
+        Ok(value) => match value.parse::<f64>() {
+            Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+            _ => Err(ConfigError::InvalidRate { var, value }),
+        },
+        Err(_) => Ok(default),
+    }
+}
+
+/// `LOG_LEVEL`, falling back to `RUST_LOG` and then `info`, and
+/// `LOG_FORMAT`, JSON unless set to `text`.
+fn parse_logging() -> Result<(String, LogFormat), ConfigError> {
+    let log_level = lookup("LOG_LEVEL")
+        .or_else(|_| lookup("RUST_LOG"))
+        .ok()
+        .map(|level| level.trim().to_string())
+        .filter(|level| !level.is_empty())
+        .unwrap_or_else(|| "info".to_string());
+    if EnvFilter::try_new(&log_level).is_err() {
+        return Err(ConfigError::InvalidLogLevel(log_level));
+    }
+    let log_format = match lookup("LOG_FORMAT") {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| ConfigError::InvalidLogFormat(value))?,
+        Err(_) => LogFormat::Json,
+    };
+    Ok((log_level, log_format))
+}
+
+/// `WS_SEND_QUEUE_FRAMES`, at least one, and `WS_SLOW_CONSUMER_POLICY`,
+/// disconnecting unless set to `drop`.
+fn parse_ws_backpressure() -> Result<WsBackpressure, ConfigError> {
+    let defaults = WsBackpressure::default();
+    let queue_frames = parse_u64("WS_SEND_QUEUE_FRAMES", defaults.queue_frames as u64)?.max(1);
+    let policy = match lookup("WS_SLOW_CONSUMER_POLICY") {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| ConfigError::InvalidSlowConsumerPolicy(value))?,
+        Err(_) => defaults.policy,
+    };
+    Ok(WsBackpressure {
+        queue_frames: queue_frames as usize,
+        policy,
+    })
+}
+
+/// Seeded mode makes every identifier and the clock predictable, so only
+/// builds with the `deterministic` feature accept a seed; others refuse to
+/// start with one rather than run live against a harness expecting a replay.
+fn parse_deterministic_seed() -> Result<Option<u64>, ConfigError> {
+    let Ok(seed) = lookup("DETERMINISTIC_SEED") else {
+        return Ok(None);
+    };
+    if !cfg!(feature = "deterministic") {
+        return Err(ConfigError::SeedWithoutFeature);
+    }
+    seed.parse::<u64>()
+        .map(Some)
+        .map_err(|err| ConfigError::InvalidNumber {
+            var: "DETERMINISTIC_SEED",
+            err,
+        })
+}
+
+#[cfg(feature = "chaos")]
+fn parse_chaos(deterministic_seed: Option<u64>) -> Result<ChaosConfig, ConfigError> {
+    Ok(ChaosConfig {
+        db_write_failure_rate: parse_rate("CHAOS_DB_WRITE_FAILURE_RATE", 0.0)?,
+        broadcast_delay_ms: parse_u64("CHAOS_BROADCAST_DELAY_MS", 0)?,
+        ws_drop_rate: parse_rate("CHAOS_WS_DROP_RATE", 0.0)?,
+        seed: parse_u64("CHAOS_SEED", deterministic_seed.unwrap_or(0))?,
+    })
+}
+
+fn parse_tls() -> Result<Option<TlsConfig>, ConfigError> {
+    let path = |var| {
+        lookup(var)
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .map(PathBuf::from)
+    };
+    let (cert_path, key_path) = match (path("TLS_CERT_PATH"), path("TLS_KEY_PATH")) {
+        (Some(cert_path), Some(key_path)) => (cert_path, key_path),
+        (None, None) => return Ok(None),
+        (Some(_), None) => return Err(ConfigError::Missing("TLS_KEY_PATH")),
+        (None, Some(_)) => return Err(ConfigError::Missing("TLS_CERT_PATH")),
+    };
+    let reload_interval_seconds = parse_u64(
+        "TLS_RELOAD_INTERVAL_SECONDS",
+        DEFAULT_RELOAD_INTERVAL_SECONDS,
+    )?;
+    let acme_webroot = path("TLS_ACME_WEBROOT");
+    let http_port = lookup("TLS_HTTP_PORT")
+        .ok()
+        .map(|port| port.parse::<u16>())
+        .transpose()
+        .map_err(|err| ConfigError::InvalidNumber {
+            var: "TLS_HTTP_PORT",
+            err,
+        })?
+        // HTTP-01 challenges always arrive on port 80.
+        .or(acme_webroot.as_ref().map(|_| 80));
+    let identities = parse_client_identities(lookup("TLS_CLIENT_IDENTITIES").ok())?;
+    let client_auth = match path("TLS_CLIENT_CA_PATH") {
+        Some(ca_path) => Some(ClientAuth {
+            ca_path,
+            identities,
+        }),
+        None if identities.is_empty() => None,
+        None => return Err(ConfigError::Missing("TLS_CLIENT_CA_PATH")),
+    };
+    Ok(Some(TlsConfig {
+        cert_path,
+        key_path,
+        reload_interval: Duration::from_secs(reload_interval_seconds.max(1)),
+        http_port,
+        acme_webroot,
+        client_auth,
+    }))
+}
+
+/// `TLS_CLIENT_IDENTITIES` maps client certificate names to subjects and
+/// their scopes, e.g. `settlement.internal=svc-settlement:read|trade`. The
+/// scopes default to `read`.
+fn parse_client_identities(
+    raw: Option<String>,
+) -> Result<BTreeMap<String, ClientIdentity>, ConfigError> {
+    let mut identities = BTreeMap::new();
+    for entry in raw.unwrap_or_default().split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let invalid = || ConfigError::InvalidClientIdentity {
+            entry: entry.to_string(),
+        };
+        // Names may be URIs, which contain colons but no equals sign.
+        let (name, identity) = entry.rsplit_once('=').ok_or_else(invalid)?;
+        let (subject, scopes) = identity.split_once(':').unwrap_or((identity, "read"));
+        let scopes = scopes
+            .split('|')
+            .map(|scope| scope.trim().parse::<Scope>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, _>>()?;
+        if name.trim().is_empty() || subject.trim().is_empty() {
+            return Err(invalid());
+        }
+        let scope = scopes
+            .iter()
+            .map(|scope| scope.as_str())
+            .collect::<Vec<_>>()
+            .join(" ");
+        identities.insert(
+            name.trim().to_string(),
+            ClientIdentity {
+                subject: subject.trim().to_string(),
+                scope,
+            },
+        );
+    }
+    Ok(identities)
+}
+
+/// `MARKET_DATA_ARCHIVE` is `s3://bucket/prefix` or a local directory. S3
+/// archives are signed with `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`
+/// and sent to `MARKET_DATA_ARCHIVE_S3_ENDPOINT`, by default AWS in
+/// `MARKET_DATA_ARCHIVE_S3_REGION`.
+fn parse_market_data_archive() -> Result<Option<StoreLocation>, ConfigError> {
+    let Some(raw) = lookup("MARKET_DATA_ARCHIVE")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let Some(location) = raw.strip_prefix("s3://") else {
+        return Ok(Some(StoreLocation::Local(PathBuf::from(raw))));
+    };
+    let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+    if bucket.is_empty() {
+        return Err(ConfigError::InvalidArchive(raw));
+    }
+    let region =
+        lookup("MARKET_DATA_ARCHIVE_S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    let endpoint = lookup("MARKET_DATA_ARCHIVE_S3_ENDPOINT")
+        .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region));
+    Ok(Some(StoreLocation::S3(S3Config {
+        endpoint,
+        region,
+        bucket: bucket.to_string(),
+        prefix: prefix.to_string(),
+        access_key_id: lookup("AWS_ACCESS_KEY_ID")
+            .map_err(|_| ConfigError::Missing("AWS_ACCESS_KEY_ID"))?,
+        secret_access_key: SecretString::from(
+            lookup("AWS_SECRET_ACCESS_KEY")
+                .map_err(|_| ConfigError::Missing("AWS_SECRET_ACCESS_KEY"))?,
+        ),
+    })))
+}
+
+/// `MARKET_FEED_URL` names a Redis or NATS server. The instance publishes to
+/// it unless `MARKET_FEED_ROLE` is `replica`.
+fn parse_market_feed() -> Result<Option<FeedConfig>, ConfigError> {
+    let Some(raw) = lookup("MARKET_FEED_URL")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let url = PubSubUrl::parse(&raw).ok_or_else(|| ConfigError::InvalidMarketFeedUrl(raw))?;
+    let role = match lookup("MARKET_FEED_ROLE") {
+        Err(_) => FeedRole::Publisher,
+        Ok(role) => match role.trim() {
+            "" | "publisher" => FeedRole::Publisher,
+            "replica" => FeedRole::Replica,
+            _ => return Err(ConfigError::InvalidMarketFeedRole(role)),
+        },
+    };
+    // Channel names go into NATS subjects, which cannot hold spaces.
+    let prefix = lookup("MARKET_FEED_PREFIX").unwrap_or_else(|_| "dex.market".to_string());
+    if !is_channel_name(&prefix) {
+        return Err(ConfigError::InvalidMarketFeedPrefix(prefix));
+    }
+    Ok(Some(FeedConfig {
+        url,
+        role,
+        prefix,
+        snapshot_interval: Duration::from_secs(
+            parse_u64("MARKET_FEED_SNAPSHOT_SECONDS", 5)?.max(1),
+        ),
+    }))
+}
+
+/// `KAFKA_BROKERS` lists the brokers to bootstrap from; order flow is
+/// streamed to `KAFKA_TOPIC_PREFIX`'s `.orders`, `.cancels` and `.trades`
+/// topics. Connections use TLS when `KAFKA_TLS` is set or a `KAFKA_TLS_*`
+/// path is given, and sign in with SASL when `KAFKA_SASL_MECHANISM` is.
+fn parse_event_stream() -> Result<Option<EventStreamConfig>, ConfigError> {
+    let Some(raw) = lookup("KAFKA_BROKERS")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let brokers: Vec<String> = raw
+        .split(',')
+        .map(|broker| broker.trim().to_string())
+        .filter(|broker| !broker.is_empty())
+        .collect();
+    let valid = brokers.iter().all(|broker| {
+        broker
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+    });
+    if !valid {
+        return Err(ConfigError::InvalidKafkaBrokers(raw));
+    }
+    let topic_prefix = lookup("KAFKA_TOPIC_PREFIX").unwrap_or_else(|_| "dex".to_string());
+    if !is_channel_name(&topic_prefix) {
+        return Err(ConfigError::InvalidKafkaTopicPrefix(topic_prefix));
+    }
+    Ok(Some(EventStreamConfig {
+        brokers,
+        security: parse_kafka_security()?,
+        topic_prefix,
+        client_id: lookup("KAFKA_CLIENT_ID").unwrap_or_else(|_| "dex-api".to_string()),
+        batch_size: parse_u64("KAFKA_RELAY_BATCH_SIZE", 500)?.clamp(1, 10_000) as u32,
+        interval: Duration::from_millis(parse_u64("KAFKA_RELAY_INTERVAL_MS", 500)?.max(10)),
+    }))
+}
+
+fn parse_kafka_security() -> Result<KafkaSecurity, ConfigError> {
+    let path = |var| lookup(var).ok().map(PathBuf::from);
+    let tls = KafkaTls {
+        ca_path: path("KAFKA_TLS_CA_PATH"),
+        cert_path: path("KAFKA_TLS_CERT_PATH"),
+        key_path: path("KAFKA_TLS_KEY_PATH"),
+    };
+    match (&tls.cert_path, &tls.key_path) {
+        (Some(_), None) => return Err(ConfigError::Missing("KAFKA_TLS_KEY_PATH")),
+        (None, Some(_)) => return Err(ConfigError::Missing("KAFKA_TLS_CERT_PATH")),
+        _ => {}
+    }
+    let tls = (parse_flag("KAFKA_TLS", false)? || tls != KafkaTls::default()).then_some(tls);
+    let sasl = match lookup("KAFKA_SASL_MECHANISM") {
+        Err(_) => None,
+        Ok(raw) => Some(KafkaSasl {
+            mechanism: SaslMechanism::parse(&raw)
+                .ok_or(ConfigError::InvalidKafkaSaslMechanism(raw))?,
+            username: lookup("KAFKA_SASL_USERNAME")
+                .map_err(|_| ConfigError::Missing("KAFKA_SASL_USERNAME"))?,
+            password: SecretString::from(
+                lookup("KAFKA_SASL_PASSWORD")
+                    .map_err(|_| ConfigError::Missing("KAFKA_SASL_PASSWORD"))?,
+            ),
+        }),
+    };
+    Ok(KafkaSecurity { tls, sasl })
+}
+
+/// `LEADER_ELECTION_KEY` names the advisory lock the leader holds; it is
+/// tried for, and once held checked, every `LEADER_CHECK_INTERVAL_MS`.
+fn parse_leader_election() -> Result<Option<ElectionConfig>, ConfigError> {
+    let Some(raw) = lookup("LEADER_ELECTION_KEY")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let key = raw
+        .trim()
+        .parse::<i64>()
+        .map_err(|err| ConfigError::InvalidNumber {
+            var: "LEADER_ELECTION_KEY",
+            err,
+        })?;
+    Ok(Some(ElectionConfig {
+        key,
+        check_interval: Duration::from_millis(parse_u64("LEADER_CHECK_INTERVAL_MS", 1000)?.max(10)),
+    }))
+}
+
+/// Letters, digits, '.', '-' and '_', which pub/sub subjects and Kafka topics
+/// all accept.
+fn is_channel_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+fn parse_ratio(var: &'static str, value: &str) -> Result<f64, ConfigError> {
+    match value.trim().parse::<f64>() {
+        Ok(ratio) if ratio.is_finite() && ratio > 0.0 => Ok(ratio),
+        _ => Err(ConfigError::InvalidRatio {
+            var,
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// `ORDER_TO_TRADE_MAX_RATIO` applies to every trader and
+/// `ORDER_TO_TRADE_RATIO_OVERRIDES`, comma-separated `trader:ratio` entries,
+/// to the ones named; without either the policy is off.
+fn parse_messaging_policy() -> Result<MessagingPolicy, ConfigError> {
+    let defaults = MessagingPolicy::default();
+    let max_ratio = lookup("ORDER_TO_TRADE_MAX_RATIO")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+        .map(|raw| parse_ratio("ORDER_TO_TRADE_MAX_RATIO", &raw))
+        .transpose()?;
+    let mut overrides = HashMap::new();
+    if let Ok(raw) = lookup("ORDER_TO_TRADE_RATIO_OVERRIDES") {
+        for entry in raw.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let (trader, ratio) = entry
+                .split_once(':')
+                .filter(|(trader, _)| !trader.trim().is_empty())
+                .ok_or_else(|| ConfigError::InvalidRatio {
+                    var: "ORDER_TO_TRADE_RATIO_OVERRIDES",
+                    value: entry.to_string(),
+                })?;
+            overrides.insert(
+                trader.trim().to_string(),
+                parse_ratio("ORDER_TO_TRADE_RATIO_OVERRIDES", ratio)?,
+            );
+        }
+    }
+    let min_orders = parse_u64("ORDER_TO_TRADE_MIN_ORDERS", defaults.min_orders)?;
+    let fee = parse_u64("ORDER_TO_TRADE_PENALTY_FEE", defaults.fee)?;
+    let rate_limit_percent = parse_u64(
+        "ORDER_TO_TRADE_RATE_LIMIT_PERCENT",
+        defaults.rate_limit_percent.into(),
+    )?;
+    let penalty_days = parse_u64("ORDER_TO_TRADE_PENALTY_DAYS", defaults.penalty_days)?;
+    Ok(MessagingPolicy {
+        max_ratio,
+        overrides,
+        min_orders: min_orders.max(1),
+        fee,
+        rate_limit_percent: rate_limit_percent.clamp(1, 100) as u32,
+        penalty_days: penalty_days.max(1),
+    })
+}
+
+/// `BRIDGE_ETHEREUM_CONTRACT` is the bridge contract Ethereum deposits are
+/// proven against, `BRIDGE_ASSETS` (`chain:ASSET=TOKEN`) maps each bridged
+/// asset to the token credited here, and `WRAPPED_ASSETS`
+/// (`TOKEN=chain:wallet`) names the custody wallet backing each token
+/// minted from a chain.
+fn parse_bridge() -> Result<BridgeConfig, ConfigError> {
+    let mut bridge = BridgeConfig::default();
+    if let Some(raw) = lookup("BRIDGE_ETHEREUM_CONTRACT")
+        .ok()
+        .filter(|raw| !raw.trim().is_empty())
+    {
+        let address = hex::decode(raw.trim().trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| <[u8; 20]>::try_from(bytes).ok())
+            .ok_or(ConfigError::InvalidBridgeContract(raw))?;
+        bridge.ethereum_contract = Some(address);
+    }
+    let raw = lookup("BRIDGE_ASSETS").unwrap_or_default();
+    for entry in raw.split(',').filter(|entry| !entry.trim().is_empty()) {
+        let invalid = || ConfigError::InvalidBridgeAsset {
+            entry: entry.to_string(),
+        };
+        let (chain, assets) = entry.trim().split_once(':').ok_or_else(invalid)?;
+        let (source, local) = assets.split_once('=').ok_or_else(invalid)?;
+        let chain = chain.trim().to_lowercase();
+        let source = TokenId::parse(source.trim()).map_err(|_| invalid())?;
+        let local = TokenId::parse(local.trim()).map_err(|_| invalid())?;
+        let repeated = bridge
+            .assets
+            .iter()
+            .any(|(c, s, _)| *c == chain && *s == source);
+        if chain.is_empty() || repeated {
+            return Err(invalid());
+        }
+        bridge.assets.push((chain, source, local));
+    }
+    let raw = lookup("WRAPPED_ASSETS").unwrap_or_default();
+    for entry in raw.split(',').filter(|entry| !entry.trim().is_empty()) {
+        let invalid = || ConfigError::InvalidWrappedAsset {
+            entry: entry.to_string(),
+        };
+        let (token, custody) = entry.trim().split_once('=').ok_or_else(invalid)?;
+        let (chain, wallet) = custody.split_once(':').ok_or_else(invalid)?;
+        let token = TokenId::parse(token.trim()).map_err(|_| invalid())?;
+        let chain = chain.trim().to_lowercase();
+        let wallet = wallet.trim();
+        // Only a token deposits from the chain are credited in can be minted.
+        let bridged = bridge
+            .assets
+            .iter()
+            .any(|(c, _, local)| *c == chain && *local == token);
+        let repeated = bridge.wrapped_assets.iter().any(|a| a.token == token);
+        if wallet.is_empty() || !bridged || repeated {
+            return Err(invalid());
+        }
+        bridge.wrapped_assets.push(WrappedAsset {
+            token,
+            chain,
+            custody_wallet: wallet.to_string(),
+        });
+    }
+    Ok(bridge)
+}
+
+/// Comma-separated `key:value` entries of `var`, with an empty key or an
+/// unparseable value reported as `expected`.
+fn parse_margin_entries(
+    var: &'static str,
+    expected: &'static str,
+    valid: fn(f64) -> bool,
+) -> Result<Vec<(String, f64)>, ConfigError> {
+    let mut entries = Vec::new();
+    let Ok(raw) = lookup(var) else {
+        return Ok(entries);
+    };
+    for entry in raw.split(',').filter(|entry| !entry.trim().is_empty()) {
+        let invalid = || ConfigError::InvalidMargin {
+            var,
+            value: entry.to_string(),
+            expected,
+        };
+        let (key, value) = entry.rsplit_once(':').ok_or_else(invalid)?;
+        let value = value.trim().parse::<f64>().map_err(|_| invalid())?;
+        if key.trim().is_empty() || !value.is_finite() || !valid(value) {
+            return Err(invalid());
+        }
+        entries.push((key.trim().to_string(), value));
+    }
+    Ok(entries)
+}
+
+/// `MARGIN_LIMITS` (`trader:usd`) names the traders whose orders are held to
+/// a margin limit, `MARGIN_RATE` and `MARGIN_PAIR_RATES` (`PAIR:rate`) set
+/// the margin rates, and `PORTFOLIO_MARGIN_ACCOUNTS` lists the traders whose
+/// correlated markets are netted with `MARGIN_CORRELATIONS`
+/// (`PAIR/PAIR:correlation`).
+fn parse_margin() -> Result<MarginConfig, ConfigError> {
+    let mut margin = MarginConfig::default();
+    if lookup("MARGIN_RATE").is_ok() {
+        margin.default_rate = parse_rate("MARGIN_RATE", 0.0)?;
+    }
+    margin.pair_rates = parse_margin_entries(
+        "MARGIN_PAIR_RATES",
+        "PAIR:rate with a rate between 0 and 1",
+        |rate| (0.0..=1.0).contains(&rate),
+    )?
+    .into_iter()
+    .collect();
+    margin.limits = parse_margin_entries(
+        "MARGIN_LIMITS",
+        "trader:usd with a non-negative limit",
+        |limit| limit >= 0.0,
+    )?
+    .into_iter()
+    .collect();
+    const CORRELATION: &str = "PAIR/PAIR:correlation with a correlation between -1 and 1";
+    for (pairs, correlation) in parse_margin_entries("MARGIN_CORRELATIONS", CORRELATION, |rho| {
+        (-1.0..=1.0).contains(&rho)
+    })? {
+        let (a, b) = pairs
+            .split_once('/')
+            .filter(|(a, b)| !a.trim().is_empty() && !b.trim().is_empty())
+            .ok_or_else(|| ConfigError::InvalidMargin {
+                var: "MARGIN_CORRELATIONS",
+                value: format!("{}:{}", pairs, correlation),
+                expected: CORRELATION,
+            })?;
+        margin
+            .correlations
+            .insert((a.trim().to_string(), b.trim().to_string()), correlation);
+    }
+    margin.portfolio_accounts = parse_admin_subjects(lookup("PORTFOLIO_MARGIN_ACCOUNTS").ok());
+    Ok(margin)
+}
+
+fn parse_db_resilience() -> Result<ResilienceConfig, ConfigError> {
+    let max_attempts = parse_u64("DB_RETRY_MAX_ATTEMPTS", 3)?;
+    let base_delay_ms = parse_u64("DB_RETRY_BASE_DELAY_MS", 50)?;
+    let max_delay_ms = parse_u64("DB_RETRY_MAX_DELAY_MS", 1000)?;
+    let failure_threshold = parse_u64("DB_BREAKER_FAILURE_THRESHOLD", 5)?;
+    let open_seconds = parse_u64("DB_BREAKER_OPEN_SECONDS", 10)?;
+    Ok(ResilienceConfig {
+        retry: RetryPolicy {
+            max_attempts: max_attempts.clamp(1, 10) as u32,
+            base_delay: Duration::from_millis(base_delay_ms),
+            max_delay: Duration::from_millis(max_delay_ms.max(base_delay_ms)),
+        },
+        breaker: BreakerConfig {
+            failure_threshold: failure_threshold.clamp(1, u32::MAX as u64) as u32,
+            open_duration: Duration::from_secs(open_seconds.max(1)),
+        },
+    })
+}
+
+/// Pool size and timeouts; an idle or statement timeout of zero turns it off.
+fn parse_db_pool() -> Result<PoolConfig, ConfigError> {
+    let defaults = PoolConfig::default();
+    let max_connections = parse_u64("DB_POOL_MAX_CONNECTIONS", defaults.max_connections.into())?;
+    let min_connections = parse_u64("DB_POOL_MIN_CONNECTIONS", defaults.min_connections.into())?;
+    let acquire_timeout_ms = parse_u64(
+        "DB_POOL_ACQUIRE_TIMEOUT_MS",
+        defaults.acquire_timeout.as_millis() as u64,
+    )?;
+    let idle_timeout_seconds = parse_u64(
+        "DB_POOL_IDLE_TIMEOUT_SECONDS",
+        defaults.idle_timeout.map_or(0, |idle| idle.as_secs()),
+    )?;
+    let statement_timeout_ms = parse_u64("DB_STATEMENT_TIMEOUT_MS", 0)?;
+    let max_connections = max_connections.clamp(1, u32::MAX as u64) as u32;
+    Ok(PoolConfig {
+        max_connections,
+        min_connections: min_connections.min(max_connections.into()) as u32,
+        acquire_timeout: Duration::from_millis(acquire_timeout_ms.max(1)),
+        idle_timeout: (idle_timeout_seconds > 0).then(|| Duration::from_secs(idle_timeout_seconds)),
+        statement_timeout: (statement_timeout_ms > 0)
+            .then(|| Duration::from_millis(statement_timeout_ms)),
+    })
+}
+
+fn parse_retention() -> Result<RetentionConfig, ConfigError> {
+    let defaults = RetentionConfig::default();
+    let interval_seconds = parse_u64("RETENTION_INTERVAL_SECONDS", defaults.interval.as_secs())?;
+    let batch_size = parse_u64("RETENTION_BATCH_SIZE", defaults.batch_size.into())?;
+    Ok(RetentionConfig {
+        max_age_days: parse_u64("RETENTION_DAYS", defaults.max_age_days)?,
+        interval: Duration::from_secs(interval_seconds.max(1)),
+        batch_size: batch_size.clamp(1, u32::MAX.into()) as u32,
+    })
+}
+
+/// Budgets from `RATE_LIMIT_<CLASS>_PER_SECOND` and `RATE_LIMIT_<CLASS>_BURST`;
+/// a rate of zero turns limiting off for the class.
+fn parse_rate_limits() -> Result<RateLimitConfig, ConfigError> {
+    fn budget(
+        per_second_var: &'static str,
+        burst_var: &'static str,
+        default: Budget,
+    ) -> Result<Budget, ConfigError> {
+        let per_second = parse_u64(per_second_var, default.per_second.into())?;
+        let burst = parse_u64(burst_var, default.burst.into())?;
+        let per_second = per_second.min(u32::MAX.into()) as u32;
+        Ok(Budget {
+            per_second,
+            // A bucket must hold at least one request.
+            burst: (burst.min(u32::MAX.into()) as u32).max(per_second.min(1)),
+        })
+    }
+
+    let defaults = RateLimitConfig::default();
+    Ok(RateLimitConfig {
+        order_entry: budget(
+            "RATE_LIMIT_ORDER_ENTRY_PER_SECOND",
+            "RATE_LIMIT_ORDER_ENTRY_BURST",
+            defaults.order_entry,
+        )?,
+        market_data: budget(
+            "RATE_LIMIT_MARKET_DATA_PER_SECOND",
+            "RATE_LIMIT_MARKET_DATA_BURST",
+            defaults.market_data,
+        )?,
+        auth: budget(
+            "RATE_LIMIT_AUTH_PER_SECOND",
+            "RATE_LIMIT_AUTH_BURST",
+            defaults.auth,
+        )?,
+    })
+}
+
+fn parse_bps(var: &'static str, value: &str) -> Result<u32, ConfigError> {
+    match value.trim().parse::<u32>() {
+        Ok(bps) if bps <= 10_000 => Ok(bps),
+        _ => Err(ConfigError::InvalidFee {
+            var,
+            value: value.to_string(),
+        }),
+    }
+}
+
+/// `FEE_MAKER_BPS` and `FEE_TAKER_BPS` apply to every pair and
+/// `FEE_PAIR_RATES`, comma-separated `PAIR=maker:taker` entries, to the
+/// pairs named. Fills are free by default.
+fn parse_fees() -> Result<FeeSchedule, ConfigError> {
+    let rate = |var: &'static str| match lookup(var) {
+        Ok(value) => parse_bps(var, &value),
+        Err(_) => Ok(0),
+    };
+    let mut fees = FeeSchedule {
+        default: FeeRates {
+            maker_bps: rate("FEE_MAKER_BPS")?,
+            taker_bps: rate("FEE_TAKER_BPS")?,
+        },
+        pairs: BTreeMap::new(),
+    };
+    for entry in lookup("FEE_PAIR_RATES").unwrap_or_default().split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let invalid = || ConfigError::InvalidFee {
+            var: "FEE_PAIR_RATES",
+            value: entry.to_string(),
+        };
+        let (pair, rates) = entry.split_once('=').ok_or_else(invalid)?;
+        let (maker, taker) = rates.split_once(':').ok_or_else(invalid)?;
+        let pair: TradingPair = pair.trim().parse().map_err(|_| invalid())?;
+        let rates = FeeRates {
+            maker_bps: parse_bps("FEE_PAIR_RATES", maker).map_err(|_| invalid())?,
+            taker_bps: parse_bps("FEE_PAIR_RATES", taker).map_err(|_| invalid())?,
+        };
+        fees.pairs.insert(pair, rates);
+    }
+    Ok(fees)
+}
+
+/// `TRADING_HALTS` lists the pairs that accept no new orders.
+fn parse_trading_halts(raw: Option<String>) -> Result<BTreeSet<TradingPair>, ConfigError> {
+    raw.unwrap_or_default()
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            entry
+                .trim()
+                .parse()
+                .map_err(|_| ConfigError::InvalidTradingHalt {
+                    entry: entry.to_string(),
+                })
+        })
+        .collect()
+}
+
+/// Keys from `JWT_KEYS`, comma-separated `kid:active_from[-expires_at]:secret`
+/// entries in Unix seconds, plus `JWT_SECRET` as an always-active key named
+/// `default`. At least one of the two is required. A secret of the form
+/// `RS256:<path>` or `EdDSA:<path>` names a PEM private key file instead of
+/// an HMAC secret.
+fn parse_jwt_keys(
+    secret: Option<String>,
+    schedule: Option<String>,
+) -> Result<Vec<SigningKey>, ConfigError> {
+    let mut keys: Vec<SigningKey> = secret
+        .map(|secret| SigningKey::static_secret(SecretString::from(secret)))
+        .into_iter()
+        .collect();
+    for entry in schedule.iter().flat_map(|raw| raw.split(',')) {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        // The entry holds a secret, so only its key ID goes into errors.
+        let invalid = || ConfigError::InvalidJwtKey {
+            kid: entry
+                .trim()
+                .split(':')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+        };
+        let mut parts = entry.trim().splitn(3, ':');
+        let (Some(kid), Some(window), Some(secret)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        let (active_from, expires_at) = match window.split_once('-') {
+            Some((from, until)) => (from, Some(until)),
+            None => (window, None),
+        };
+        let active_from = active_from.parse::<u64>().map_err(|_| invalid())?;
+        let expires_at = expires_at
+            .map(|until| until.parse::<u64>())
+            .transpose()
+            .map_err(|_| invalid())?;
+        if kid.is_empty()
+            || secret.is_empty()
+            || expires_at.is_some_and(|until| until <= active_from)
+            || keys.iter().any(|key| key.kid == kid)
+        {
+            return Err(invalid());
+        }
+        let read_pem = |path: &str| {
+            std::fs::read_to_string(path)
+                .map(SecretString::from)
+                .map_err(|err| ConfigError::KeyFile {
+                    path: path.to_string(),
+                    err,
+                })
+        };
+        let material = if let Some(path) = secret.strip_prefix("RS256:") {
+            KeyMaterial::Rsa(read_pem(path)?)
+        } else if let Some(path) = secret.strip_prefix("EdDSA:") {
+            KeyMaterial::Ed25519(read_pem(path)?)
+        } else {
+            KeyMaterial::Hmac(SecretString::from(secret.to_string()))
+        };
+        keys.push(SigningKey {
+            kid: kid.to_string(),
+            material,
+            active_from,
+            expires_at,
+        });
+    }
+    if keys.is_empty() {
+        return Err(ConfigError::Missing("JWT_SECRET"));
+    }
+    Ok(keys)
+}
+
+/// `SECRETS_SOURCE` is `file` (a JSON object in `SECRETS_FILE`), `vault`
+/// (the KV secret at `VAULT_SECRET_PATH`, read from `VAULT_ADDR` with
+/// `VAULT_TOKEN`) or `aws` (the Secrets Manager secret
+/// `AWS_SECRETS_MANAGER_SECRET_ID` in `AWS_REGION`, signed with
+/// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`). Unset or `env` reads
+/// credentials from the environment only.
+fn parse_secret_source() -> Result<Option<SecretSource>, ConfigError> {
+    let required = |var: &'static str| {
+        lookup(var)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+            .ok_or(ConfigError::Missing(var))
+    };
+    let raw = lookup("SECRETS_SOURCE").unwrap_or_default();
+    match raw.trim() {
+        "" | "env" => Ok(None),
+        "file" => Ok(Some(SecretSource::File(PathBuf::from(required(
+            "SECRETS_FILE",
+        )?)))),
+        "vault" => Ok(Some(SecretSource::Vault {
+            address: required("VAULT_ADDR")?,
+            token: SecretString::from(required("VAULT_TOKEN")?),
+            path: required("VAULT_SECRET_PATH")?,
+        })),
+        "aws" => {
+            let region = lookup("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+            Ok(Some(SecretSource::Aws {
+                endpoint: lookup("AWS_SECRETS_MANAGER_ENDPOINT")
+                    .unwrap_or_else(|_| format!("https://secretsmanager.{}.amazonaws.com", region)),
+                region,
+                secret_id: required("AWS_SECRETS_MANAGER_SECRET_ID")?,
+                access_key_id: required("AWS_ACCESS_KEY_ID")?,
+                secret_access_key: SecretString::from(required("AWS_SECRET_ACCESS_KEY")?),
+            }))
+        }
+        _ => Err(ConfigError::InvalidSecretsSource(raw)),
+    }
+}
+
+/// `var` from the secrets source, falling back to the environment.
+fn secret_var(values: &SecretValues, var: &'static str) -> Option<String> {
+    values.get(var).or_else(|| lookup(var).ok())
+}
+
+pub(crate) fn secret_database_url(values: &SecretValues) -> Result<SecretString, ConfigError> {
+    secret_var(values, "DATABASE_URL")
+        .map(SecretString::from)
+        .ok_or(ConfigError::Missing("DATABASE_URL"))
+}
+
+pub(crate) fn secret_database_replica_url(values: &SecretValues) -> Option<SecretString> {
+    secret_var(values, "DATABASE_REPLICA_URL")
+        .filter(|url| !url.trim().is_empty())
+        .map(SecretString::from)
+}
+
+pub(crate) fn secret_jwt_keys(values: &SecretValues) -> Result<Vec<SigningKey>, ConfigError> {
+    parse_jwt_keys(
+        secret_var(values, "JWT_SECRET"),
+        secret_var(values, "JWT_KEYS"),
+    )
+}
+
+pub(crate) fn secret_trader_secrets(
+    values: &SecretValues,
+) -> Result<HashMap<String, SecretString>, ConfigError> {
+    parse_trader_secrets(secret_var(values, "TRADER_SECRETS"))
+}
+
 fn parse_trader_secrets(raw: Option<String>) -> Result<HashMap<String, SecretString>, ConfigError> {
     let mut map = HashMap::new();
     if let Some(raw) = raw {
@@ -108,3 +1635,50 @@ fn parse_trader_secrets(raw: Option<String>) -> Result<HashMap<String, SecretStr
     }
     Ok(map)
 }
+
+/// `IP_ALLOWLISTS` binds subjects to networks, e.g.
+/// `alice=203.0.113.0/24|2001:db8::/32,bob=198.51.100.7`.
+fn parse_ip_allowlists(raw: Option<String>) -> Result<HashMap<String, Allowlist>, ConfigError> {
+    let mut allowlists = HashMap::new();
+    for entry in raw.unwrap_or_default().split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let invalid = || ConfigError::InvalidAllowlist {
+            entry: entry.to_string(),
+        };
+        let (subject, blocks) = entry.split_once('=').ok_or_else(invalid)?;
+        let allowlist = Allowlist::parse(blocks.split('|')).map_err(|_| invalid())?;
+        if subject.trim().is_empty() || allowlist.is_empty() {
+            return Err(invalid());
+        }
+        allowlists.insert(subject.trim().to_string(), allowlist);
+    }
+    Ok(allowlists)
+}
+
+fn parse_matching(raw: Option<String>) -> Result<MatchingRegistry, ConfigError> {
+    let mut matching = MatchingRegistry::default();
+    for entry in raw.unwrap_or_default().split(',') {
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let invalid = || ConfigError::InvalidMatching {
+            entry: entry.to_string(),
+        };
+        let (pair, algorithm) = entry.split_once('=').ok_or_else(invalid)?;
+        let pair: TradingPair = pair.trim().parse().map_err(|_| invalid())?;
+        let algorithm: MatchingAlgorithm = algorithm.trim().parse().map_err(|_| invalid())?;
+        matching.set(pair, algorithm.policy());
+    }
+    Ok(matching)
+}
+
+fn parse_admin_subjects(raw: Option<String>) -> HashSet<String> {
+    raw.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|subject| !subject.is_empty())
+        .map(String::from)
+        .collect()
+}
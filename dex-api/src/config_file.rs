@@ -0,0 +1,412 @@
+//! TOML and YAML configuration files.
+//!
+//! `CONFIG_FILES` names files to read settings from, comma-separated and
+//! applied in order, e.g. `config/base.toml,config/production.yaml`; a
+//! setting in a later file replaces the same setting in an earlier one, and
+//! environment variables, including `.env`, replace both. Files use the
+//! names of the environment variables, lower-cased and optionally nested at
+//! any underscore, so `server_port = 8080` and
+//!
+//! ```toml
+//! [rate_limit.order_entry]
+//! per_second = 20
+//! ```
+//!
+//! set `SERVER_PORT` and `RATE_LIMIT_ORDER_ENTRY_PER_SECOND`. Settings that
+//! take comma-separated entries also take lists. Unknown settings are
+//! rejected, and a value that fails to parse is reported by the file and
+//! field it came from.
+
+use crate::config::ConfigError;
+use serde_json::Value;
+use std::{collections::BTreeMap, env, path::Path, sync::RwLock};
+
+/// Every setting the configuration reads.
+pub const KNOWN_VARS: &[&str] = &[
+    "ADMIN_SUBJECTS",
+    "API_KEY_ENCRYPTION_KEY",
+    "API_KEY_REPLAY_WINDOW_SECONDS",
+    "AUTH_LOCKOUT_BASE_SECONDS",
+    "AUTH_LOCKOUT_MAX_SECONDS",
+    "AUTH_LOCKOUT_THRESHOLD",
+    "AWS_ACCESS_KEY_ID",
+    "AWS_REGION",
+    "AWS_SECRETS_MANAGER_ENDPOINT",
+    "AWS_SECRETS_MANAGER_SECRET_ID",
+    "AWS_SECRET_ACCESS_KEY",
+    "BOOK_FULL_SNAPSHOT_EVERY",
+    "BOOK_JOURNAL_ARCHIVE_DIR",
+    "BOOK_JOURNAL_SEGMENT_BYTES",
+    "BOOK_SNAPSHOT_BAND_MAX_BPS",
+    "BOOK_SNAPSHOT_BAND_MIN_BPS",
+    "BOOK_SNAPSHOT_DIR",
+    "BOOK_SNAPSHOT_INTERVAL_SECONDS",
+    "BRIDGE_ASSETS",
+    "BRIDGE_ETHEREUM_CONTRACT",
+    "CANDLE_AGGREGATION_INTERVAL_SECONDS",
+    "CHAOS_BROADCAST_DELAY_MS",
+    "CHAOS_DB_WRITE_FAILURE_RATE",
+    "CHAOS_SEED",
+    "CHAOS_WS_DROP_RATE",
+    "CORS_ALLOWED_HEADERS",
+    "CORS_ALLOWED_ORIGINS",
+    "CORS_MAX_AGE_SECONDS",
+    "COUNTER_FLUSH_INTERVAL_SECONDS",
+    "DATABASE_REPLICA_URL",
+    "DATABASE_URL",
+    "DB_BREAKER_FAILURE_THRESHOLD",
+    "DB_BREAKER_OPEN_SECONDS",
+    "DB_POOL_ACQUIRE_TIMEOUT_MS",
+    "DB_POOL_IDLE_TIMEOUT_SECONDS",
+    "DB_POOL_MAX_CONNECTIONS",
+    "DB_POOL_MIN_CONNECTIONS",
+    "DB_PROBE_INTERVAL_SECONDS",
+    "DB_QUERY_TIMEOUT_MS",
+    "DB_RETRY_BASE_DELAY_MS",
+    "DB_RETRY_MAX_ATTEMPTS",
+    "DB_RETRY_MAX_DELAY_MS",
+    "DB_SLOW_QUERY_MS",
+    "DB_STATEMENT_TIMEOUT_MS",
+    "DETERMINISTIC_SEED",
+    "FEE_MAKER_BPS",
+    "FEE_PAIR_RATES",
+    "FEE_TAKER_BPS",
+    "FIX_COMP_ID",
+    "FIX_HOST",
+    "FIX_PORT",
+    "GRPC_HOST",
+    "GRPC_PORT",
+    "INTERNAL_ADDR",
+    "IP_ALLOWLISTS",
+    "JWT_ISSUER",
+    "JWT_KEYS",
+    "JWT_MAX_TTL_SECONDS",
+    "JWT_SECRET",
+    "JWT_TTL_SECONDS",
+    "KAFKA_BROKERS",
+    "KAFKA_CLIENT_ID",
+    "KAFKA_RELAY_BATCH_SIZE",
+    "KAFKA_RELAY_INTERVAL_MS",
+    "KAFKA_SASL_MECHANISM",
+    "KAFKA_SASL_PASSWORD",
+    "KAFKA_SASL_USERNAME",
+    "KAFKA_TLS",
+    "KAFKA_TLS_CA_PATH",
+    "KAFKA_TLS_CERT_PATH",
+    "KAFKA_TLS_KEY_PATH",
+    "KAFKA_TOPIC_PREFIX",
+    "LEADER_CHECK_INTERVAL_MS",
+    "LEADER_ELECTION_KEY",
+    "LOG_FORMAT",
+    "LOG_LEVEL",
+    "MARGIN_CORRELATIONS",
+    "MARGIN_LIMITS",
+    "MARGIN_PAIR_RATES",
+    "MARGIN_RATE",
+    "MARKET_DATA_ARCHIVE",
+    "MARKET_DATA_ARCHIVE_DEPTH_LEVELS",
+    "MARKET_DATA_ARCHIVE_S3_ENDPOINT",
+    "MARKET_DATA_ARCHIVE_S3_REGION",
+    "MARKET_FEED_PREFIX",
+    "MARKET_FEED_ROLE",
+    "MARKET_FEED_SNAPSHOT_SECONDS",
+    "MARKET_FEED_URL",
+    "MATCHING_POLICIES",
+    "ORDER_TO_TRADE_MAX_RATIO",
+    "ORDER_TO_TRADE_MIN_ORDERS",
+    "ORDER_TO_TRADE_PENALTY_DAYS",
+    "ORDER_TO_TRADE_PENALTY_FEE",
+    "ORDER_TO_TRADE_RATE_LIMIT_PERCENT",
+    "ORDER_TO_TRADE_RATIO_OVERRIDES",
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_SERVICE_NAME",
+    "POOL_FLUSH_INTERVAL_SECONDS",
+    "PORTFOLIO_MARGIN_ACCOUNTS",
+    "RATE_LIMIT_AUTH_BURST",
+    "RATE_LIMIT_AUTH_PER_SECOND",
+    "RATE_LIMIT_MARKET_DATA_BURST",
+    "RATE_LIMIT_MARKET_DATA_PER_SECOND",
+    "RATE_LIMIT_ORDER_ENTRY_BURST",
+    "RATE_LIMIT_ORDER_ENTRY_PER_SECOND",
+    "REFRESH_TOKEN_TTL_SECONDS",
+    "REQUIRE_FUNDED_ORDERS",
+    "RETENTION_BATCH_SIZE",
+    "RETENTION_DAYS",
+    "RETENTION_INTERVAL_SECONDS",
+    "REVOCATION_SYNC_INTERVAL_SECONDS",
+    "RUST_LOG",
+    "SECRETS_FILE",
+    "SECRETS_REFRESH_SECONDS",
+    "SECRETS_SOURCE",
+    "SEQUENCER_REGION",
+    "SEQUENCER_SIGNING_KEY",
+    "SERVER_HOST",
+    "SERVER_PORT",
+    "SHUTDOWN_GRACE_SECONDS",
+    "SIWE_DOMAIN",
+    "SIWE_URI",
+    "SQLITE_PATH",
+    "STORAGE_BACKEND",
+    "TICK_MAP_FLUSH_INTERVAL_SECONDS",
+    "TLS_ACME_WEBROOT",
+    "TLS_CERT_PATH",
+    "TLS_CLIENT_CA_PATH",
+    "TLS_CLIENT_IDENTITIES",
+    "TLS_HTTP_PORT",
+    "TLS_KEY_PATH",
+    "TLS_RELOAD_INTERVAL_SECONDS",
+    "TOTP_ENCRYPTION_KEY",
+    "TRACE_SAMPLE_RATIO",
+    "TRADER_SECRETS",
+    "TRADE_ADJUST_WINDOW_SECONDS",
+    "TRADING_HALTS",
+    "TRUSTED_PROXIES",
+    "USAGE_FLUSH_INTERVAL_SECONDS",
+    "USD_PRICE_REFRESH_SECONDS",
+    "USD_REFERENCE_TOKEN",
+    "VAULT_ADDR",
+    "VAULT_SECRET_PATH",
+    "VAULT_TOKEN",
+    "WALLET_CHAIN_ID",
+    "WALLET_CHALLENGE_TTL_SECONDS",
+    "WRAPPED_ASSETS",
+    "WS_IDLE_TIMEOUT_SECONDS",
+    "WS_PING_INTERVAL_SECONDS",
+    "WS_SEND_QUEUE_FRAMES",
+    "WS_SLOW_CONSUMER_POLICY",
+];
+
+/// The files in force; read by every configuration lookup.
+static LOADED: RwLock<ConfigFiles> = RwLock::new(ConfigFiles {
+    values: BTreeMap::new(),
+});
+
+/// A setting and where it was read from.
+#[derive(Debug, Clone)]
+struct Sourced {
+    value: String,
+    path: String,
+    field: String,
+}
+
+/// Settings read from configuration files, by environment variable name.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFiles {
+    values: BTreeMap<String, Sourced>,
+}
+
+impl ConfigFiles {
+    /// Read the comma-separated `paths` in order.
+    pub fn load(paths: &str) -> Result<Self, ConfigError> {
+        let mut files = Self::default();
+        for path in paths.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let contents = std::fs::read_to_string(path).map_err(|err| ConfigError::File {
+                path: path.to_string(),
+                err: err.to_string(),
+            })?;
+            files.merge(path, &contents)?;
+        }
+        Ok(files)
+    }
+
+    /// Layer the file at `path` with `contents` over the files read so far.
+    pub fn merge(&mut self, path: &str, contents: &str) -> Result<(), ConfigError> {
+        let invalid = |err: String| ConfigError::File {
+            path: path.to_string(),
+            err,
+        };
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default();
+        let root: Value = match extension {
+            "toml" => toml::from_str(contents).map_err(|err| invalid(err.to_string()))?,
+            "yaml" | "yml" => {
+                serde_yaml::from_str(contents).map_err(|err| invalid(err.to_string()))?
+            }
+            _ => return Err(invalid("expected a .toml, .yaml or .yml file".to_string())),
+        };
+        match root {
+            Value::Object(_) => self.flatten(path, &mut Vec::new(), &root),
+            Value::Null => Ok(()),
+            _ => Err(invalid("expected a table of settings".to_string())),
+        }
+    }
+
+    fn flatten<'a>(
+        &mut self,
+        path: &str,
+        at: &mut Vec<&'a str>,
+        value: &'a Value,
+    ) -> Result<(), ConfigError> {
+        let value = match value {
+            Value::Object(table) => {
+                for (key, value) in table {
+                    at.push(key);
+                    self.flatten(path, at, value)?;
+                    at.pop();
+                }
+                return Ok(());
+            }
+            Value::Null => return Ok(()),
+            Value::Array(items) => items
+                .iter()
+                .map(scalar)
+                .collect::<Option<Vec<_>>>()
+                .map(|items| items.join(",")),
+            value => scalar(value),
+        };
+        let field = at.join(".");
+        let var = at.join("_").replace('-', "_").to_uppercase();
+        if !KNOWN_VARS.contains(&var.as_str()) {
+            return Err(ConfigError::UnknownField {
+                path: path.to_string(),
+                field,
+            });
+        }
+        let value = value.ok_or_else(|| ConfigError::InvalidField {
+            path: path.to_string(),
+            field: field.clone(),
+        })?;
+        self.values.insert(
+            var,
+            Sourced {
+                value,
+                path: path.to_string(),
+                field,
+            },
+        );
+        Ok(())
+    }
+
+    /// The value the files give `var`.
+    pub fn get(&self, var: &str) -> Option<&str> {
+        self.values.get(var).map(|sourced| sourced.value.as_str())
+    }
+
+    /// Point `err` at the file and field its value came from, unless it came
+    /// from the environment.
+    fn locate(&self, err: ConfigError) -> ConfigError {
+        let Some(var) = err.var() else {
+            return err;
+        };
+        match self.values.get(var) {
+            Some(sourced) if env::var(var).is_err() => ConfigError::InFile {
+                path: sourced.path.clone(),
+                field: sourced.field.clone(),
+                err: Box::new(err),
+            },
+            _ => err,
+        }
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Bool(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Read the files named by `CONFIG_FILES` and put them in force. The files
+/// in force are kept if one fails to read.
+pub(crate) fn reload() -> Result<(), ConfigError> {
+    let files = ConfigFiles::load(&env::var("CONFIG_FILES").unwrap_or_default())?;
+    *LOADED.write().unwrap_or_else(|err| err.into_inner()) = files;
+    Ok(())
+}
+
+/// The value the files in force give `var`.
+pub(crate) fn get(var: &str) -> Option<String> {
+    LOADED
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(var)
+        .map(String::from)
+}
+
+/// [`ConfigFiles::locate`] against the files in force.
+pub(crate) fn locate(err: ConfigError) -> ConfigError {
+    LOADED
+        .read()
+        .unwrap_or_else(|err| err.into_inner())
+        .locate(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn later_files_override_earlier_ones_and_nest_at_underscores() {
+        let mut files = ConfigFiles::default();
+        files
+            .merge(
+                "base.toml",
+                r#"
+                server_port = 3030
+                trading_halts = ["ETH-USDC", "BTC-USDC"]
+
+                [rate_limit.order_entry]
+                per_second = 20
+                burst = 40
+                "#,
+            )
+            .unwrap();
+        files
+            .merge(
+                "production.yaml",
+                "server:\n  port: 8443\nrate_limit:\n  order_entry:\n    burst: 80\n",
+            )
+            .unwrap();
+        assert_eq!(files.get("SERVER_PORT"), Some("8443"));
+        assert_eq!(files.get("RATE_LIMIT_ORDER_ENTRY_PER_SECOND"), Some("20"));
+        assert_eq!(files.get("RATE_LIMIT_ORDER_ENTRY_BURST"), Some("80"));
+        assert_eq!(files.get("TRADING_HALTS"), Some("ETH-USDC,BTC-USDC"));
+    }
+
+    #[test]
+    fn errors_name_the_file_and_field() {
+        let mut files = ConfigFiles::default();
+        let err = files
+            .merge("prod.toml", "[server]\nprot = 8443\n")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "prod.toml: unknown setting server.prot");
+        let err = files
+            .merge("prod.toml", "[fix]\nport = [[1]]\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid fix.port"));
+        assert!(files.merge("prod.ini", "").is_err());
+
+        files
+            .merge("prod.yaml", "tls:\n  http_port: not-a-port\n")
+            .unwrap();
+        let err = files.locate(ConfigError::InvalidNumber {
+            var: "TLS_HTTP_PORT",
+            err: "x".parse::<u16>().unwrap_err(),
+        });
+        assert!(err
+            .to_string()
+            .starts_with("prod.yaml: invalid tls.http_port: invalid numeric value"));
+    }
+
+    #[test]
+    fn every_setting_the_configuration_reads_is_known() {
+        let source = include_str!("config.rs");
+        for literal in source.split('"').skip(1).step_by(2) {
+            let is_var = literal.len() > 2
+                && literal.contains('_')
+                && literal
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+            if is_var && literal != "CONFIG_FILES" {
+                assert!(
+                    KNOWN_VARS.contains(&literal),
+                    "{literal} is not in KNOWN_VARS"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Cross-origin access for browser clients.
+//!
+//! CORS is off unless `CORS_ALLOWED_ORIGINS` lists the origins web apps may
+//! call the API from, or `*` for any. Requests without an `Origin` header,
+//! which is every client other than a browser, are never affected. Once it
+//! is on, preflights from listed origins are answered directly and their
+//! responses cached for `max_age_seconds`, and requests from any other
+//! origin are refused with `403 cors_forbidden`. WebSocket upgrades carry an
+//! `Origin` but no preflight, so only their origin is checked.
+
+use crate::api_keys;
+use std::{fmt, time::Duration};
+use thiserror::Error;
+use warp::http::{header::HeaderName, uri::Authority, Method};
+
+/// Request headers the API reads, always allowed.
+pub const DEFAULT_ALLOWED_HEADERS: &[&str] = &[
+    "authorization",
+    "content-type",
+    "if-none-match",
+    "if-modified-since",
+    api_keys::KEY_HEADER,
+    api_keys::SIGNATURE_HEADER,
+    api_keys::TIMESTAMP_HEADER,
+    api_keys::CONTENT_HEADER,
+];
+
+/// Response headers scripts may read besides the CORS-safelisted ones.
+const EXPOSED_HEADERS: &[&str] = &["etag", "last-modified", "retry-after"];
+
+const ALLOWED_METHODS: &[Method] = &[Method::GET, Method::POST, Method::PUT, Method::DELETE];
+
+/// Default of `CORS_MAX_AGE_SECONDS`.
+pub const DEFAULT_MAX_AGE_SECONDS: u64 = 600;
+
+/// Which browser origins may call the API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// `scheme://host[:port]` origins; empty disables CORS.
+    pub allowed_origins: AllowedOrigins,
+    /// Request headers a preflight may ask for, including the defaults.
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache a preflight response.
+    pub max_age_seconds: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    Any,
+    List(Vec<String>),
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: AllowedOrigins::List(Vec::new()),
+            allowed_headers: DEFAULT_ALLOWED_HEADERS
+                .iter()
+                .map(|header| header.to_string())
+                .collect(),
+            max_age_seconds: DEFAULT_MAX_AGE_SECONDS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvalidCors {
+    #[error("invalid origin '{0}', expected http(s)://host[:port]")]
+    Origin(String),
+    #[error("invalid header name '{0}'")]
+    Header(String),
+}
+
+impl CorsConfig {
+    /// Build the policy from comma-separated origins and extra headers.
+    pub fn parse(
+        origins: &str,
+        extra_headers: &str,
+        max_age_seconds: u64,
+    ) -> Result<Self, InvalidCors> {
+        let origins: Vec<&str> = entries(origins).collect();
+        let allowed_origins = if origins.contains(&"*") {
+            AllowedOrigins::Any
+        } else {
+            AllowedOrigins::List(
+                origins
+                    .into_iter()
+                    .map(parse_origin)
+                    .collect::<Result<_, _>>()?,
+            )
+        };
+        let mut allowed_headers = Self::default().allowed_headers;
+        for header in entries(extra_headers) {
+            let name = HeaderName::from_bytes(header.as_bytes())
+                .map_err(|_| InvalidCors::Header(header.to_string()))?;
+            if !allowed_headers
+                .iter()
+                .any(|allowed| allowed == name.as_str())
+            {
+                allowed_headers.push(name.as_str().to_string());
+            }
+        }
+        Ok(Self {
+            allowed_origins,
+            allowed_headers,
+            max_age_seconds,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.allowed_origins != AllowedOrigins::List(Vec::new())
+    }
+
+    /// The warp layer enforcing the policy, or `None` when CORS is off.
+    pub fn layer(&self) -> Option<warp::cors::Builder> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let cors = warp::cors()
+            .allow_methods(ALLOWED_METHODS.iter().cloned())
+            .allow_headers(self.allowed_headers.iter().map(String::as_str))
+            .expose_headers(EXPOSED_HEADERS.iter().copied())
+            .max_age(Duration::from_secs(self.max_age_seconds));
+        Some(match &self.allowed_origins {
+            AllowedOrigins::Any => cors.allow_any_origin(),
+            AllowedOrigins::List(origins) => cors.allow_origins(origins.iter().map(String::as_str)),
+        })
+    }
+}
+
+impl fmt::Display for AllowedOrigins {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Any => f.write_str("*"),
+            Self::List(origins) => f.write_str(&origins.join(", ")),
+        }
+    }
+}
+
+fn entries(raw: &str) -> impl Iterator<Item = &str> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+/// An origin in the form browsers send it: lower-case scheme and host, no
+/// path. A trailing slash is dropped.
+fn parse_origin(raw: &str) -> Result<String, InvalidCors> {
+    let invalid = || InvalidCors::Origin(raw.to_string());
+    let (scheme, rest) = raw.split_once("://").ok_or_else(invalid)?;
+    let scheme = scheme.to_ascii_lowercase();
+    if scheme != "http" && scheme != "https" {
+        return Err(invalid());
+    }
+    let host = rest.strip_suffix('/').unwrap_or(rest);
+    let authority: Authority = host.parse().map_err(|_| invalid())?;
+    if host.is_empty() || host.contains(['/', '@', '?', '#']) {
+        return Err(invalid());
+    }
+    Ok(format!(
+        "{}://{}",
+        scheme,
+        authority.as_str().to_ascii_lowercase()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn origins_and_headers_are_validated() {
+        let cors = CorsConfig::parse(
+            "https://App.example.com/, http://localhost:3000",
+            "X-Request-Id, authorization",
+            60,
+        )
+        .unwrap();
+        assert_eq!(
+            cors.allowed_origins,
+            AllowedOrigins::List(vec![
+                "https://app.example.com".into(),
+                "http://localhost:3000".into()
+            ])
+        );
+        assert_eq!(
+            cors.allowed_headers.len(),
+            DEFAULT_ALLOWED_HEADERS.len() + 1
+        );
+        assert_eq!(cors.allowed_headers.last().unwrap(), "x-request-id");
+        assert!(cors.layer().is_some());
+
+        let any = CorsConfig::parse("https://a.example, *", "", 60).unwrap();
+        assert_eq!(any.allowed_origins, AllowedOrigins::Any);
+        assert!(!CorsConfig::parse(" ", "", 60).unwrap().is_enabled());
+        assert!(CorsConfig::default().layer().is_none());
+
+        for origin in [
+            "app.example.com",
+            "ftp://app.example.com",
+            "https://app.example.com/path",
+            "https://user@app.example.com",
+            "https://",
+        ] {
+            assert!(
+                matches!(
+                    CorsConfig::parse(origin, "", 60),
+                    Err(InvalidCors::Origin(_))
+                ),
+                "{}",
+                origin
+            );
+        }
+        assert!(matches!(
+            CorsConfig::parse("*", "bad header", 60),
+            Err(InvalidCors::Header(_))
+        ));
+    }
+}
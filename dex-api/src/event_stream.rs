@@ -0,0 +1,350 @@
+//! Order flow published to Kafka for analytics and risk systems.
+//!
+//! Every accepted order, cancel and trade is written to the `event_outbox`
+//! table as it happens, one topic per event type and keyed by pair. A relay
+//! publishes pending events in order and marks them published only once
+//! Kafka has acknowledged them, so delivery is at least once: consumers
+//! should drop repeats by the `event-id` header.
+
+use crate::{
+    kafka::{KafkaError, KafkaProducer, KafkaSecurity, Record},
+    ApiState,
+};
+use async_trait::async_trait;
+use dex_core::types::{Order, OrderSide, Trade};
+use dex_db::OutboxEvent;
+use serde::Serialize;
+use std::{sync::Arc, time::Duration};
+use tokio::task::JoinHandle;
+
+/// Where and how often events are relayed.
+#[derive(Debug, Clone)]
+pub struct EventStreamConfig {
+    /// `host:port` of the brokers to bootstrap from.
+    pub brokers: Vec<String>,
+    pub security: KafkaSecurity,
+    /// Topics are `<prefix>.orders`, `<prefix>.cancels` and `<prefix>.trades`.
+    pub topic_prefix: String,
+    pub client_id: String,
+    /// Most events published per round.
+    pub batch_size: u32,
+    /// Pause between rounds once the outbox is drained.
+    pub interval: Duration,
+}
+
+impl EventStreamConfig {
+    pub fn orders_topic(&self) -> String {
+        format!("{}.orders", self.topic_prefix)
+    }
+
+    pub fn cancels_topic(&self) -> String {
+        format!("{}.cancels", self.topic_prefix)
+    }
+
+    pub fn trades_topic(&self) -> String {
+        format!("{}.trades", self.topic_prefix)
+    }
+}
+
+/// Where the relay publishes outbox events.
+#[async_trait]
+pub trait OutboxPublisher: Send + Sync {
+    /// Publish `records` to `topic`, returning once all are acknowledged.
+    async fn publish(&self, topic: &str, records: &[Record]) -> Result<(), KafkaError>;
+}
+
+#[async_trait]
+impl OutboxPublisher for KafkaProducer {
+    async fn publish(&self, topic: &str, records: &[Record]) -> Result<(), KafkaError> {
+        self.send(topic, records).await
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent<'a> {
+    OrderAccepted {
+        order: &'a Order,
+    },
+    OrderCancelled {
+        order: &'a Order,
+        timestamp: u64,
+    },
+    Trade {
+        pair: String,
+        taker_side: OrderSide,
+        trade: &'a Trade,
+    },
+}
+
+fn outbox_event(topic: String, pair: String, event: &StreamEvent, created_at: u64) -> OutboxEvent {
+    OutboxEvent {
+        id: 0,
+        topic,
+        key: pair,
+        payload: serde_json::to_string(event).expect("stream events always encode"),
+        created_at,
+    }
+}
+
+async fn enqueue(state: &ApiState, events: Vec<OutboxEvent>) {
+    if let Err(err) = state.outbox_repo.enqueue_events(&events).await {
+        tracing::error!(events = events.len(), error = ?err, "failed to queue stream events");
+    }
+}
+
+/// Queue the acceptance of `order` and the trades it took part in.
+pub async fn record_order(state: &ApiState, order: &Order, trades: &[Trade]) {
+    let Some(config) = &state.config.event_stream else {
+        return;
+    };
+    let pair = order.pair.to_string();
+    let mut events = vec![outbox_event(
+        config.orders_topic(),
+        pair.clone(),
+        &StreamEvent::OrderAccepted { order },
+        order.timestamp,
+    )];
+    for trade in trades {
+        let event = StreamEvent::Trade {
+            pair: pair.clone(),
+            taker_side: order.side,
+            trade,
+        };
+        events.push(outbox_event(
+            config.trades_topic(),
+            pair.clone(),
+            &event,
+            trade.timestamp,
+        ));
+    }
+    enqueue(state, events).await;
+}
+
+/// Queue the cancellation of `order`.
+pub async fn record_cancel(state: &ApiState, order: &Order, timestamp: u64) {
+    let Some(config) = &state.config.event_stream else {
+        return;
+    };
+    let event = outbox_event(
+        config.cancels_topic(),
+        order.pair.to_string(),
+        &StreamEvent::OrderCancelled { order, timestamp },
+        timestamp,
+    );
+    enqueue(state, vec![event]).await;
+}
+
+/// Publish one round of pending events, returning how many were published.
+/// Each topic's events go out oldest first; a topic that fails is retried
+/// from its oldest pending event next round, so no event overtakes another
+/// with the same key.
+async fn relay_round(
+    state: &ApiState,
+    publisher: &dyn OutboxPublisher,
+    config: &EventStreamConfig,
+) -> Result<usize, String> {
+    let pending = state
+        .outbox_repo
+        .pending_events(config.batch_size)
+        .await
+        .map_err(|err| err.to_string())?;
+
+    let mut topics: Vec<(String, Vec<OutboxEvent>)> = Vec::new();
+    for event in pending {
+        match topics.iter_mut().find(|(topic, _)| *topic == event.topic) {
+            Some((_, events)) => events.push(event),
+            None => topics.push((event.topic.clone(), vec![event])),
+        }
+    }
+
+    let mut published = 0;
+    let mut failure = None;
+    for (topic, events) in topics {
+        let records: Vec<Record> = events
+            .iter()
+            .map(|event| Record {
+                key: event.key.clone().into_bytes(),
+                value: event.payload.clone().into_bytes(),
+                headers: vec![("event-id".to_string(), event.id.to_string().into_bytes())],
+                timestamp_ms: event.created_at as i64 * 1000,
+            })
+            .collect();
+        if let Err(err) = publisher.publish(&topic, &records).await {
+            failure.get_or_insert(format!("{topic}: {err}"));
+            continue;
+        }
+        let ids: Vec<u64> = events.iter().map(|event| event.id).collect();
+        let now = state.determinism.now().unwrap_or_default();
+        state
+            .outbox_repo
+            .mark_published(&ids, now)
+            .await
+            .map_err(|err| err.to_string())?;
+        published += ids.len();
+    }
+    match failure {
+        Some(err) => Err(err),
+        None => Ok(published),
+    }
+}
+
+/// Relay the outbox to `publisher` until the process exits.
+pub fn spawn_relay(
+    state: ApiState,
+    config: EventStreamConfig,
+    publisher: Arc<dyn OutboxPublisher>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let brokers = config.brokers.join(",");
+        let mut failing = false;
+        loop {
+            match relay_round(&state, &*publisher, &config).await {
+                Ok(published) => {
+                    if failing {
+                        tracing::info!(%brokers, "event stream publishing again");
+                        failing = false;
+                    }
+                    // A full batch suggests more is waiting.
+                    if published >= config.batch_size as usize {
+                        continue;
+                    }
+                }
+                Err(err) => {
+                    if !failing {
+                        tracing::warn!(%brokers, error = %err, "event stream relay failed");
+                        failing = true;
+                    }
+                }
+            }
+            tokio::time::sleep(config.interval).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes,
+        test_support::{bearer_token, place, test_state_with_memory, MemoryStorage},
+    };
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn orders_cancels_and_trades_are_queued_per_topic_keyed_by_pair() {
+        let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+        state.config.event_stream = Some(EventStreamConfig {
+            brokers: vec!["127.0.0.1:9092".to_string()],
+            security: KafkaSecurity::default(),
+            topic_prefix: "dex".to_string(),
+            client_id: "dex-api".to_string(),
+            batch_size: 100,
+            interval: Duration::from_millis(10),
+        });
+        let filter = routes(state.clone());
+        place(&filter, "bob", "sell", 5).await;
+        let resting = place(&filter, "carol", "sell", 1).await;
+        place(&filter, "alice", "buy", 2).await;
+        let response = warp::test::request()
+            .method("DELETE")
+            .path(&format!("/orderbook/orders/{}", resting["order_id"]))
+            .header("authorization", bearer_token("carol", 300))
+            .reply(&filter)
+            .await;
+        assert!(response.status().is_success());
+
+        let pending = state.outbox_repo.pending_events(10).await.unwrap();
+        let topics: Vec<&str> = pending.iter().map(|event| event.topic.as_str()).collect();
+        assert_eq!(
+            topics,
+            [
+                "dex.orders",
+                "dex.orders",
+                "dex.orders",
+                "dex.trades",
+                "dex.cancels"
+            ]
+        );
+        assert!(pending.iter().all(|event| event.key == "ETH-USDC"));
+        let trade: serde_json::Value = serde_json::from_str(&pending[3].payload).unwrap();
+        assert_eq!(trade["type"], "trade");
+        assert_eq!(trade["taker_side"], "Buy");
+        assert_eq!(trade["trade"]["quantity"], 2);
+
+        // Only what Kafka acknowledged leaves the outbox.
+        let ids: Vec<u64> = pending.iter().map(|event| event.id).collect();
+        state
+            .outbox_repo
+            .mark_published(&ids[..4], 1)
+            .await
+            .unwrap();
+        let pending = state.outbox_repo.pending_events(10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, ids[4]);
+    }
+
+    /// Records what it is sent, refusing one topic.
+    #[derive(Default)]
+    struct FakePublisher {
+        refused_topic: String,
+        published: std::sync::Mutex<Vec<(String, Record)>>,
+    }
+
+    #[async_trait]
+    impl OutboxPublisher for FakePublisher {
+        async fn publish(&self, topic: &str, records: &[Record]) -> Result<(), KafkaError> {
+            if topic == self.refused_topic {
+                return Err(KafkaError::Cancelled);
+            }
+            let mut published = self.published.lock().unwrap();
+            published.extend(
+                records
+                    .iter()
+                    .map(|record| (topic.to_string(), record.clone())),
+            );
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn relay_marks_only_acknowledged_topics_published() {
+        let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+        let config = EventStreamConfig {
+            brokers: vec!["127.0.0.1:9092".to_string()],
+            security: KafkaSecurity::default(),
+            topic_prefix: "dex".to_string(),
+            client_id: "dex-api".to_string(),
+            batch_size: 100,
+            interval: Duration::from_millis(10),
+        };
+        state.config.event_stream = Some(config.clone());
+        let filter = routes(state.clone());
+        place(&filter, "bob", "sell", 5).await;
+        place(&filter, "alice", "buy", 2).await;
+
+        let publisher = FakePublisher {
+            refused_topic: config.trades_topic(),
+            ..FakePublisher::default()
+        };
+        assert!(relay_round(&state, &publisher, &config).await.is_err());
+        let published = publisher.published.lock().unwrap().clone();
+        assert_eq!(published.len(), 2);
+        assert!(published.iter().all(|(topic, record)| topic == "dex.orders"
+            && record.key == b"ETH-USDC"
+            && record.headers[0].0 == "event-id"));
+
+        // The refused trade is retried next round.
+        let pending = state.outbox_repo.pending_events(10).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].topic, "dex.trades");
+        let publisher = FakePublisher::default();
+        assert_eq!(relay_round(&state, &publisher, &config).await, Ok(1));
+        assert!(state
+            .outbox_repo
+            .pending_events(10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,1105 @@
+//! FIX 4.4 order-entry gateway.
+//!
+//! Market makers log on with their API token in Password (554) and enter
+//! orders with NewOrderSingle (D) and OrderCancelRequest (F). Orders run
+//! through the same pipeline as `POST /orderbook/orders`, and
+//! ExecutionReports (8) are built from the trader's private order events, so
+//! fills against REST or WebSocket flow are reported as well. Only orders
+//! entered on a session are reported on it.
+//!
+//! Sequence numbers are checked but not recovered: a gap ends the session,
+//! and clients log on again with ResetSeqNumFlag (141=Y).
+
+use crate::{
+    audit::{self, AuditAction},
+    auth::Scope,
+    ip_allowlist,
+    order_events::{OrderEvent, OrderStatus, UserEvent},
+    orders::{
+        cancel_order, insufficient_funds_message, margin_limit_message, submit_order, CancelError,
+        CreateOrderRequest, SubmitError,
+    },
+    validation, ApiState,
+};
+use dex_core::types::{OrderId, Price, Quantity, TradeId};
+use std::{collections::HashMap, fmt::Write as _, net::IpAddr, time::Duration};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpListener,
+    sync::broadcast,
+    time::{Instant, Interval},
+};
+
+pub const BEGIN_STRING: &str = "FIX.4.4";
+const SOH: u8 = 0x01;
+/// Largest message body accepted from a client.
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+/// Time a new connection has to log on.
+const LOGON_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEARTBEAT_SECONDS: u64 = 300;
+
+/// Tag numbers used by the gateway.
+pub mod tag {
+    pub const AVG_PX: u32 = 6;
+    pub const CL_ORD_ID: u32 = 11;
+    pub const CUM_QTY: u32 = 14;
+    pub const EXEC_ID: u32 = 17;
+    pub const LAST_PX: u32 = 31;
+    pub const LAST_QTY: u32 = 32;
+    pub const MSG_SEQ_NUM: u32 = 34;
+    pub const MSG_TYPE: u32 = 35;
+    pub const ORDER_ID: u32 = 37;
+    pub const ORDER_QTY: u32 = 38;
+    pub const ORD_STATUS: u32 = 39;
+    pub const ORD_TYPE: u32 = 40;
+    pub const ORIG_CL_ORD_ID: u32 = 41;
+    pub const PRICE: u32 = 44;
+    pub const REF_SEQ_NUM: u32 = 45;
+    pub const SENDER_COMP_ID: u32 = 49;
+    pub const SENDING_TIME: u32 = 52;
+    pub const SIDE: u32 = 54;
+    pub const SYMBOL: u32 = 55;
+    pub const TARGET_COMP_ID: u32 = 56;
+    pub const TEXT: u32 = 58;
+    pub const TRANSACT_TIME: u32 = 60;
+    pub const ENCRYPT_METHOD: u32 = 98;
+    pub const CXL_REJ_REASON: u32 = 102;
+    pub const ORD_REJ_REASON: u32 = 103;
+    pub const HEART_BT_INT: u32 = 108;
+    pub const TEST_REQ_ID: u32 = 112;
+    pub const RESET_SEQ_NUM_FLAG: u32 = 141;
+    pub const EXEC_TYPE: u32 = 150;
+    pub const LEAVES_QTY: u32 = 151;
+    pub const REF_TAG_ID: u32 = 371;
+    pub const REF_MSG_TYPE: u32 = 372;
+    pub const SESSION_REJECT_REASON: u32 = 373;
+    pub const CXL_REJ_RESPONSE_TO: u32 = 434;
+    pub const PASSWORD: u32 = 554;
+}
+
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum FixError {
+    #[error("message must start with 8={}", BEGIN_STRING)]
+    BeginString,
+    #[error("malformed or oversized BodyLength (9)")]
+    BodyLength,
+    #[error("malformed field at byte {0}")]
+    Field(usize),
+    #[error("checksum mismatch: computed {computed:03}, received {received}")]
+    Checksum { computed: u8, received: String },
+    #[error("MsgType (35) must follow BodyLength (9)")]
+    MsgType,
+}
+
+/// A FIX message: its MsgType and the fields after it, in order. The
+/// BeginString, BodyLength and CheckSum framing is handled by `encode` and
+/// `decode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixMessage {
+    msg_type: String,
+    fields: Vec<(u32, String)>,
+}
+
+impl FixMessage {
+    pub fn new(msg_type: impl Into<String>) -> Self {
+        Self {
+            msg_type: msg_type.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, tag: u32, value: impl ToString) -> Self {
+        self.fields.push((tag, value.to_string()));
+        self
+    }
+
+    pub fn msg_type(&self) -> &str {
+        &self.msg_type
+    }
+
+    /// First value of `tag`, if present.
+    pub fn get(&self, tag: u32) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| *field == tag)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = format!("{}={}\x01", tag::MSG_TYPE, self.msg_type);
+        for (tag, value) in &self.fields {
+            let _ = write!(body, "{}={}\x01", tag, value);
+        }
+        let mut out = format!("8={}\x019={}\x01{}", BEGIN_STRING, body.len(), body).into_bytes();
+        let checksum = checksum(&out);
+        out.extend_from_slice(format!("10={:03}\x01", checksum).as_bytes());
+        out
+    }
+
+    /// Decode one complete message, as delimited by [`frame_len`].
+    pub fn decode(frame: &[u8]) -> Result<Self, FixError> {
+        let trailer_start = frame.len().checked_sub(7).ok_or(FixError::BodyLength)?;
+        let (head, trailer) = frame.split_at(trailer_start);
+        let computed = checksum(head);
+        let received = trailer
+            .strip_prefix(b"10=")
+            .and_then(|rest| rest.strip_suffix(&[SOH]))
+            .map(|digits| String::from_utf8_lossy(digits).into_owned())
+            .unwrap_or_default();
+        if received != format!("{:03}", computed) {
+            return Err(FixError::Checksum { computed, received });
+        }
+
+        // BeginString and BodyLength were checked while framing.
+        let mut fields = parse_fields(head)?.into_iter().skip(2);
+        let msg_type = match fields.next() {
+            Some((tag::MSG_TYPE, msg_type)) => msg_type,
+            _ => return Err(FixError::MsgType),
+        };
+        Ok(Self {
+            msg_type,
+            fields: fields.collect(),
+        })
+    }
+}
+
+fn checksum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn parse_fields(bytes: &[u8]) -> Result<Vec<(u32, String)>, FixError> {
+    let bytes = bytes
+        .strip_suffix(&[SOH])
+        .ok_or(FixError::Field(bytes.len()))?;
+    let mut fields = Vec::new();
+    let mut offset = 0;
+    for raw in bytes.split(|&byte| byte == SOH) {
+        let field = std::str::from_utf8(raw)
+            .ok()
+            .and_then(|field| field.split_once('='))
+            .and_then(|(tag, value)| Some((tag.parse::<u32>().ok()?, value.to_string())))
+            .ok_or(FixError::Field(offset))?;
+        fields.push(field);
+        offset += raw.len() + 1;
+    }
+    Ok(fields)
+}
+
+/// Length of the first message in `buf`, or `None` until all of it has
+/// arrived.
+pub fn frame_len(buf: &[u8]) -> Result<Option<usize>, FixError> {
+    let prefix = format!("8={}\x019=", BEGIN_STRING);
+    let prefix = prefix.as_bytes();
+    let seen = buf.len().min(prefix.len());
+    if buf[..seen] != prefix[..seen] {
+        return Err(FixError::BeginString);
+    }
+    let rest = &buf[seen..];
+    let Some(end) = rest.iter().position(|&byte| byte == SOH) else {
+        // Still waiting for the BodyLength digits.
+        return if rest.len() > 6 {
+            Err(FixError::BodyLength)
+        } else {
+            Ok(None)
+        };
+    };
+    let body_len = std::str::from_utf8(&rest[..end])
+        .ok()
+        .filter(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()))
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&len| len <= MAX_BODY_LENGTH)
+        .ok_or(FixError::BodyLength)?;
+    // Header, body, then the seven byte `10=NNN<SOH>` trailer.
+    let total = prefix.len() + end + 1 + body_len + 7;
+    Ok((buf.len() >= total).then_some(total))
+}
+
+/// FIX UTCTimestamp (`YYYYMMDD-HH:MM:SS`) for Unix seconds.
+fn utc_timestamp(secs: u64) -> String {
+    // Civil date from days since the epoch (Howard Hinnant's algorithm).
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let doe = days.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    let secs_of_day = secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}-{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    )
+}
+
+/// An order entered on the session, tracked to fill in ExecutionReports.
+#[derive(Debug)]
+struct SessionOrder {
+    cl_ord_id: String,
+    symbol: String,
+    side: String,
+    quantity: Quantity,
+    price: Option<Price>,
+    cum_qty: Quantity,
+    notional: u128,
+    /// ClOrdID of a pending OrderCancelRequest.
+    cancel_cl_ord_id: Option<String>,
+}
+
+enum Execution {
+    New,
+    Trade {
+        trade_id: TradeId,
+        price: Price,
+        quantity: Quantity,
+    },
+    Canceled,
+}
+
+/// State of one FIX connection.
+struct Session {
+    state: ApiState,
+    /// Address of the counterparty, for the audit log.
+    peer: Option<IpAddr>,
+    comp_id: String,
+    target_comp_id: String,
+    trader_id: Option<String>,
+    heartbeat: Duration,
+    next_in: u64,
+    next_out: u64,
+    orders: HashMap<OrderId, SessionOrder>,
+    /// Set once the session has logged out or must be dropped.
+    closed: bool,
+}
+
+impl Session {
+    fn new(state: ApiState, peer: Option<IpAddr>) -> Self {
+        Self {
+            comp_id: state.config.fix_comp_id.clone(),
+            state,
+            peer,
+            target_comp_id: String::new(),
+            trader_id: None,
+            heartbeat: LOGON_TIMEOUT,
+            next_in: 1,
+            next_out: 1,
+            orders: HashMap::new(),
+            closed: false,
+        }
+    }
+
+    /// Add the standard header to an outgoing message.
+    fn stamp(&mut self, msg: FixMessage) -> FixMessage {
+        let now = self.state.determinism.now().unwrap_or_default();
+        let mut stamped = FixMessage::new(msg.msg_type)
+            .with(tag::SENDER_COMP_ID, &self.comp_id)
+            .with(tag::TARGET_COMP_ID, &self.target_comp_id)
+            .with(tag::MSG_SEQ_NUM, self.next_out)
+            .with(tag::SENDING_TIME, utc_timestamp(now));
+        self.next_out += 1;
+        stamped.fields.extend(msg.fields);
+        stamped
+    }
+
+    fn logout(&mut self, text: impl Into<String>) -> Vec<FixMessage> {
+        self.closed = true;
+        vec![FixMessage::new("5").with(tag::TEXT, text.into())]
+    }
+
+    async fn on_frame(&mut self, frame: &[u8]) -> Vec<FixMessage> {
+        let msg = match FixMessage::decode(frame) {
+            Ok(msg) => msg,
+            // Garbled messages are dropped without consuming a sequence number.
+            Err(err) => {
+                tracing::warn!(error = %err, "fix: dropping garbled message");
+                return Vec::new();
+            }
+        };
+        if self.target_comp_id.is_empty() {
+            if let Some(sender) = msg.get(tag::SENDER_COMP_ID) {
+                self.target_comp_id = sender.to_string();
+            }
+        }
+
+        let Some(seq) = msg
+            .get(tag::MSG_SEQ_NUM)
+            .and_then(|seq| seq.parse::<u64>().ok())
+        else {
+            return self.logout("MsgSeqNum (34) is missing or invalid");
+        };
+        if msg.msg_type() == "A" && msg.get(tag::RESET_SEQ_NUM_FLAG) == Some("Y") {
+            self.next_in = seq;
+            self.next_out = 1;
+        }
+        if seq != self.next_in {
+            return self.logout(format!(
+                "MsgSeqNum {} received, {} expected; resend is not supported",
+                seq, self.next_in
+            ));
+        }
+        self.next_in += 1;
+
+        if self.trader_id.is_none() && msg.msg_type() != "A" {
+            return self.logout("first message must be Logon");
+        }
+        match msg.msg_type() {
+            "A" => self.on_logon(&msg, seq),
+            "0" => Vec::new(),
+            "1" => vec![FixMessage::new("0").with(
+                tag::TEST_REQ_ID,
+                msg.get(tag::TEST_REQ_ID).unwrap_or_default(),
+            )],
+            "5" => {
+                self.closed = true;
+                vec![FixMessage::new("5")]
+            }
+            // Both answer nothing right away when the request is accepted.
+            "D" => {
+                let replies = self.on_new_order(&msg, seq).await;
+                self.audit(AuditAction::OrderCreate, &msg, replies.is_empty())
+                    .await;
+                replies
+            }
+            "F" => {
+                let replies = self.on_cancel(&msg, seq).await;
+                self.audit(AuditAction::OrderCancel, &msg, replies.is_empty())
+                    .await;
+                replies
+            }
+            other => vec![session_reject(seq, other, None, 11, "unsupported MsgType")],
+        }
+    }
+
+    async fn audit(&self, action: AuditAction, msg: &FixMessage, accepted: bool) {
+        let target = format!(
+            "FIX {} ClOrdID={}",
+            msg.msg_type(),
+            msg.get(tag::CL_ORD_ID).unwrap_or_default()
+        );
+        audit::record_gateway(
+            &self.state,
+            self.trader_id.as_deref().unwrap_or_default(),
+            self.peer,
+            action,
+            target,
+            accepted,
+        )
+        .await;
+    }
+
+    fn on_logon(&mut self, msg: &FixMessage, seq: u64) -> Vec<FixMessage> {
+        if self.trader_id.is_some() {
+            return vec![session_reject(seq, "A", None, 99, "already logged on")];
+        }
+        let claims = match msg
+            .get(tag::PASSWORD)
+            .map(|token| self.state.auth.verify_token(token))
+        {
+            Some(Ok(claims)) if claims.has_scope(Scope::Trade) => claims,
+            Some(Ok(_)) => return self.logout("API token lacks the `trade` scope"),
+            _ => return self.logout("Password (554) must carry a valid API token"),
+        };
+        if ip_allowlist::check(&self.state, &claims.sub, self.peer).is_err() {
+            return self.logout("API token may not be used from this address");
+        }
+        let heartbeat = match msg
+            .get(tag::HEART_BT_INT)
+            .and_then(|secs| secs.parse::<u64>().ok())
+        {
+            Some(secs @ 1..=MAX_HEARTBEAT_SECONDS) => secs,
+            _ => {
+                return self.logout(format!(
+                    "HeartBtInt (108) must be between 1 and {} seconds",
+                    MAX_HEARTBEAT_SECONDS
+                ))
+            }
+        };
+        self.heartbeat = Duration::from_secs(heartbeat);
+        self.trader_id = Some(claims.sub);
+        let mut logon = FixMessage::new("A")
+            .with(tag::ENCRYPT_METHOD, 0)
+            .with(tag::HEART_BT_INT, heartbeat);
+        if msg.get(tag::RESET_SEQ_NUM_FLAG) == Some("Y") {
+            logon = logon.with(tag::RESET_SEQ_NUM_FLAG, "Y");
+        }
+        vec![logon]
+    }
+
+    async fn on_new_order(&mut self, msg: &FixMessage, seq: u64) -> Vec<FixMessage> {
+        let Some(cl_ord_id) = msg.get(tag::CL_ORD_ID) else {
+            return vec![session_reject(
+                seq,
+                "D",
+                Some(tag::CL_ORD_ID),
+                1,
+                "ClOrdID is required",
+            )];
+        };
+        if self
+            .orders
+            .values()
+            .any(|order| order.cl_ord_id == cl_ord_id)
+        {
+            return vec![order_rejected(msg, 6, "duplicate ClOrdID")];
+        }
+        let trader_id = self.trader_id.clone().unwrap_or_default();
+        let validated = match new_order_request(msg, trader_id)
+            .and_then(|req| validation::validate_create_order(req).map_err(|err| err.to_string()))
+        {
+            Ok(validated) => validated,
+            Err(text) => return vec![order_rejected(msg, 99, text)],
+        };
+
+        let outcome = match submit_order(&self.state, validated).await {
+            // A failed trade write is logged by the pipeline; the fills
+            // still happened and are reported from the order events.
+            Ok(outcome) => outcome,
+            Err(SubmitError::TradeWrite(outcome, _)) => *outcome,
+            Err(SubmitError::Degraded) => {
+                return vec![order_rejected(
+                    msg,
+                    99,
+                    "order entry is suspended while storage is unavailable",
+                )]
+            }
+            Err(SubmitError::ShuttingDown) => {
+                return vec![order_rejected(msg, 99, "the server is shutting down")]
+            }
+            Err(SubmitError::Replica) => {
+                return vec![order_rejected(
+                    msg,
+                    99,
+                    "this instance serves market data only",
+                )]
+            }
+            // 2: exchange closed.
+            Err(SubmitError::Halted) => {
+                return vec![order_rejected(msg, 2, "trading in this pair is halted")]
+            }
+            Err(SubmitError::Clock) => return vec![order_rejected(msg, 99, "internal error")],
+            Err(SubmitError::Storage(_)) => {
+                return vec![order_rejected(msg, 99, "failed to persist order")]
+            }
+            Err(SubmitError::Rejected(err)) => {
+                return vec![order_rejected(msg, 99, err.to_string())]
+            }
+            // 3: order exceeds limit.
+            Err(SubmitError::MarginLimit(breach)) => {
+                return vec![order_rejected(msg, 3, margin_limit_message(&breach))]
+            }
+            Err(SubmitError::Unfunded(shortfall)) => {
+                return vec![order_rejected(
+                    msg,
+                    3,
+                    insufficient_funds_message(&shortfall),
+                )]
+            }
+        };
+        // Reports follow from the order events, which are read after this.
+        self.orders.insert(
+            outcome.order.id,
+            SessionOrder {
+                cl_ord_id: cl_ord_id.to_string(),
+                symbol: msg.get(tag::SYMBOL).unwrap_or_default().to_string(),
+                side: msg.get(tag::SIDE).unwrap_or_default().to_string(),
+                quantity: outcome.order.quantity,
+                price: outcome.order.price,
+                cum_qty: 0,
+                notional: 0,
+                cancel_cl_ord_id: None,
+            },
+        );
+        Vec::new()
+    }
+
+    async fn on_cancel(&mut self, msg: &FixMessage, seq: u64) -> Vec<FixMessage> {
+        let (Some(cl_ord_id), Some(orig_cl_ord_id)) =
+            (msg.get(tag::CL_ORD_ID), msg.get(tag::ORIG_CL_ORD_ID))
+        else {
+            return vec![session_reject(
+                seq,
+                "F",
+                None,
+                1,
+                "ClOrdID and OrigClOrdID are required",
+            )];
+        };
+        let order_id = msg
+            .get(tag::ORDER_ID)
+            .and_then(|id| id.parse::<OrderId>().ok())
+            .filter(|id| self.orders.contains_key(id))
+            .or_else(|| {
+                self.orders
+                    .iter()
+                    .find(|(_, order)| order.cl_ord_id == orig_cl_ord_id)
+                    .map(|(id, _)| *id)
+            });
+        let Some(order) = order_id.and_then(|id| self.orders.get_mut(&id)) else {
+            return vec![cancel_rejected(msg, None, "8", 1, "unknown order")];
+        };
+        let order_id = order_id.unwrap_or_default();
+        order.cancel_cl_ord_id = Some(cl_ord_id.to_string());
+
+        let trader_id = self.trader_id.clone().unwrap_or_default();
+        let (reason, text) = match cancel_order(&self.state, order_id, &trader_id).await {
+            // The cancelled event produces the ExecutionReport.
+            Ok(_) => return Vec::new(),
+            Err(CancelError::NotFound) => (0, "too late to cancel".to_string()),
+            Err(CancelError::Replica) => (99, "this instance serves market data only".to_string()),
+            Err(CancelError::Book(err)) => (99, err.to_string()),
+        };
+        let status = match self.orders.get_mut(&order_id) {
+            Some(order) => {
+                order.cancel_cl_ord_id = None;
+                if order.cum_qty > 0 {
+                    "1"
+                } else {
+                    "0"
+                }
+            }
+            None => "2",
+        };
+        vec![cancel_rejected(msg, Some(order_id), status, reason, text)]
+    }
+
+    /// Turn the trader's order events into ExecutionReports for orders
+    /// entered on this session.
+    fn on_event(&mut self, event: &UserEvent) -> Vec<FixMessage> {
+        if self.trader_id.as_deref() != Some(event.trader_id.as_str()) {
+            return Vec::new();
+        }
+        let (order_id, execution, timestamp) = match &event.event {
+            OrderEvent::OrderUpdate {
+                order_id,
+                status,
+                timestamp,
+                ..
+            } => {
+                let execution = match status {
+                    OrderStatus::Accepted => Execution::New,
+                    OrderStatus::Cancelled => Execution::Canceled,
+                    // Fills are reported as they happen; just stop tracking.
+                    OrderStatus::Filled => {
+                        self.orders.remove(order_id);
+                        return Vec::new();
+                    }
+                    OrderStatus::PartiallyFilled => return Vec::new(),
+                };
+                (*order_id, execution, *timestamp)
+            }
+            OrderEvent::Fill {
+                order_id,
+                trade_id,
+                price,
+                quantity,
+                timestamp,
+                ..
+            } => (
+                *order_id,
+                Execution::Trade {
+                    trade_id: *trade_id,
+                    price: *price,
+                    quantity: *quantity,
+                },
+                *timestamp,
+            ),
+            // Corrections come after the fact and are only published on the
+            // WebSocket streams.
+            OrderEvent::TradeAdjusted { .. } => return Vec::new(),
+        };
+        let report = self.execution_report(order_id, execution, timestamp);
+        report.into_iter().collect()
+    }
+
+    fn execution_report(
+        &mut self,
+        order_id: OrderId,
+        execution: Execution,
+        timestamp: u64,
+    ) -> Option<FixMessage> {
+        let order = self.orders.get_mut(&order_id)?;
+        if let Execution::Trade {
+            price, quantity, ..
+        } = execution
+        {
+            order.cum_qty = (order.cum_qty + quantity).min(order.quantity);
+            order.notional += price as u128 * quantity as u128;
+        }
+        let (exec_id, exec_type, ord_status) = match execution {
+            Execution::New => (format!("{}-0", order_id), "0", "0"),
+            Execution::Trade { trade_id, .. } => {
+                let status = if order.cum_qty == order.quantity {
+                    "2"
+                } else {
+                    "1"
+                };
+                (format!("{}-T{}", order_id, trade_id), "F", status)
+            }
+            Execution::Canceled => (format!("{}-C", order_id), "4", "4"),
+        };
+        let leaves = match execution {
+            Execution::Canceled => 0,
+            _ => order.quantity - order.cum_qty,
+        };
+        let avg_px = order
+            .notional
+            .checked_div(order.cum_qty as u128)
+            .unwrap_or_default();
+
+        let mut report = FixMessage::new("8").with(tag::ORDER_ID, order_id);
+        report = match (&execution, &order.cancel_cl_ord_id) {
+            (Execution::Canceled, Some(cancel_cl_ord_id)) => report
+                .with(tag::CL_ORD_ID, cancel_cl_ord_id)
+                .with(tag::ORIG_CL_ORD_ID, &order.cl_ord_id),
+            _ => report.with(tag::CL_ORD_ID, &order.cl_ord_id),
+        };
+        report = report
+            .with(tag::EXEC_ID, exec_id)
+            .with(tag::EXEC_TYPE, exec_type)
+            .with(tag::ORD_STATUS, ord_status)
+            .with(tag::SYMBOL, &order.symbol)
+            .with(tag::SIDE, &order.side)
+            .with(tag::ORDER_QTY, order.quantity);
+        if let Some(price) = order.price {
+            report = report.with(tag::PRICE, price);
+        }
+        if let Execution::Trade {
+            price, quantity, ..
+        } = execution
+        {
+            report = report
+                .with(tag::LAST_PX, price)
+                .with(tag::LAST_QTY, quantity);
+        }
+        let report = report
+            .with(tag::LEAVES_QTY, leaves)
+            .with(tag::CUM_QTY, order.cum_qty)
+            .with(tag::AVG_PX, avg_px)
+            .with(tag::TRANSACT_TIME, utc_timestamp(timestamp));
+        if matches!(execution, Execution::Canceled) {
+            self.orders.remove(&order_id);
+        }
+        Some(report)
+    }
+}
+
+/// Map a NewOrderSingle onto the REST order request.
+fn new_order_request(msg: &FixMessage, trader_id: String) -> Result<CreateOrderRequest, String> {
+    let (base_token, quote_token) = msg
+        .get(tag::SYMBOL)
+        .and_then(|symbol| symbol.split_once(['/', '-']))
+        .ok_or("Symbol (55) must be BASE/QUOTE, e.g. ETH/USDC")?;
+    let side = match msg.get(tag::SIDE) {
+        Some("1") => "buy",
+        Some("2") => "sell",
+        _ => return Err("Side (54) must be 1 (buy) or 2 (sell)".into()),
+    };
+    let order_type = match msg.get(tag::ORD_TYPE) {
+        Some("1") => "market",
+        Some("2") => "limit",
+        _ => return Err("OrdType (40) must be 1 (market) or 2 (limit)".into()),
+    };
+    let quantity = msg
+        .get(tag::ORDER_QTY)
+        .and_then(|qty| qty.parse::<Quantity>().ok())
+        .ok_or("OrderQty (38) must be a whole number")?;
+    let price = match msg.get(tag::PRICE) {
+        Some(price) => Some(
+            price
+                .parse::<Price>()
+                .map_err(|_| "Price (44) must be a whole number of ticks")?,
+        ),
+        None => None,
+    };
+    Ok(CreateOrderRequest {
+        trader_id,
+        base_token: base_token.to_string(),
+        quote_token: quote_token.to_string(),
+        side: side.to_string(),
+        order_type: order_type.to_string(),
+        price,
+        quantity,
+    })
+}
+
+/// Session-level Reject (3) for a message that could not be processed.
+fn session_reject(
+    ref_seq: u64,
+    ref_msg_type: &str,
+    ref_tag: Option<u32>,
+    reason: u32,
+    text: &str,
+) -> FixMessage {
+    let mut reject = FixMessage::new("3").with(tag::REF_SEQ_NUM, ref_seq);
+    if let Some(ref_tag) = ref_tag {
+        reject = reject.with(tag::REF_TAG_ID, ref_tag);
+    }
+    reject
+        .with(tag::REF_MSG_TYPE, ref_msg_type)
+        .with(tag::SESSION_REJECT_REASON, reason)
+        .with(tag::TEXT, text)
+}
+
+/// ExecutionReport (8) rejecting a NewOrderSingle.
+fn order_rejected(msg: &FixMessage, reason: u32, text: impl Into<String>) -> FixMessage {
+    let cl_ord_id = msg.get(tag::CL_ORD_ID).unwrap_or_default();
+    FixMessage::new("8")
+        .with(tag::ORDER_ID, "NONE")
+        .with(tag::CL_ORD_ID, cl_ord_id)
+        .with(tag::EXEC_ID, format!("{}-R", cl_ord_id))
+        .with(tag::EXEC_TYPE, "8")
+        .with(tag::ORD_STATUS, "8")
+        .with(tag::SYMBOL, msg.get(tag::SYMBOL).unwrap_or_default())
+        .with(tag::SIDE, msg.get(tag::SIDE).unwrap_or_default())
+        .with(tag::LEAVES_QTY, 0)
+        .with(tag::CUM_QTY, 0)
+        .with(tag::AVG_PX, 0)
+        .with(tag::ORD_REJ_REASON, reason)
+        .with(tag::TEXT, text.into())
+}
+
+/// OrderCancelReject (9) answering an OrderCancelRequest.
+fn cancel_rejected(
+    msg: &FixMessage,
+    order_id: Option<OrderId>,
+    ord_status: &str,
+    reason: u32,
+    text: impl Into<String>,
+) -> FixMessage {
+    let order_id = order_id.map_or_else(|| "NONE".to_string(), |id| id.to_string());
+    FixMessage::new("9")
+        .with(tag::ORDER_ID, order_id)
+        .with(tag::CL_ORD_ID, msg.get(tag::CL_ORD_ID).unwrap_or_default())
+        .with(
+            tag::ORIG_CL_ORD_ID,
+            msg.get(tag::ORIG_CL_ORD_ID).unwrap_or_default(),
+        )
+        .with(tag::ORD_STATUS, ord_status)
+        .with(tag::CXL_REJ_RESPONSE_TO, 1)
+        .with(tag::CXL_REJ_REASON, reason)
+        .with(tag::TEXT, text.into())
+}
+
+fn heartbeat_ticker(period: Duration) -> Interval {
+    tokio::time::interval_at(Instant::now() + period, period)
+}
+
+/// Accept FIX connections on `listener`, one session per connection.
+pub async fn serve(listener: TcpListener, state: ApiState) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    run_session(stream, state, Some(peer.ip())).await;
+                    tracing::info!(%peer, "fix: session closed");
+                });
+            }
+            Err(err) => tracing::error!(error = %err, "fix: failed to accept connection"),
+        }
+    }
+}
+
+/// Drive one FIX session with the counterparty at `peer` until either side
+/// logs out or the connection drops.
+pub async fn run_session<S>(stream: S, state: ApiState, peer: Option<IpAddr>)
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut events = state.user_tx.subscribe();
+    let mut session = Session::new(state, peer);
+    let mut heartbeat = session.heartbeat;
+    let mut ticker = heartbeat_ticker(heartbeat);
+    let mut last_seen = Instant::now();
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let replies = tokio::select! {
+            read = reader.read(&mut chunk) => match read {
+                Ok(0) | Err(_) => break,
+                Ok(n) => {
+                    last_seen = Instant::now();
+                    buffer.extend_from_slice(&chunk[..n]);
+                    let mut replies = Vec::new();
+                    while !session.closed {
+                        match frame_len(&buffer) {
+                            Ok(Some(len)) => {
+                                let frame: Vec<u8> = buffer.drain(..len).collect();
+                                replies.extend(session.on_frame(&frame).await);
+                            }
+                            Ok(None) => break,
+                            // Without framing there is no way to find the next message.
+                            Err(err) => {
+                                tracing::warn!(error = %err, "fix: closing connection");
+                                return;
+                            }
+                        }
+                    }
+                    replies
+                }
+            },
+            event = events.recv() => match event {
+                Ok(event) => session.on_event(&event),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => session.logout(format!(
+                    "{} order events were dropped; log on again and check open orders",
+                    skipped
+                )),
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                if session.trader_id.is_none() {
+                    break;
+                }
+                if last_seen.elapsed() > heartbeat * 2 {
+                    session.logout("heartbeat timeout")
+                } else {
+                    vec![FixMessage::new("0")]
+                }
+            }
+        };
+
+        for reply in replies {
+            let bytes = session.stamp(reply).encode();
+            if writer.write_all(&bytes).await.is_err() {
+                return;
+            }
+        }
+        if session.closed {
+            break;
+        }
+        if session.heartbeat != heartbeat {
+            heartbeat = session.heartbeat;
+            ticker = heartbeat_ticker(heartbeat);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes,
+        test_support::{bearer_token, place, test_state_with_memory, MemoryStorage},
+    };
+    use std::sync::Arc;
+    use tokio::io::DuplexStream;
+
+    struct Client {
+        stream: DuplexStream,
+        seq: u64,
+        buffer: Vec<u8>,
+    }
+
+    impl Client {
+        fn connect(state: ApiState) -> Self {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            tokio::spawn(run_session(server, state, None));
+            Self {
+                stream: client,
+                seq: 1,
+                buffer: Vec::new(),
+            }
+        }
+
+        async fn send(&mut self, msg: FixMessage) {
+            let mut stamped = FixMessage::new(msg.msg_type)
+                .with(tag::SENDER_COMP_ID, "MM1")
+                .with(tag::TARGET_COMP_ID, "DEXOS")
+                .with(tag::MSG_SEQ_NUM, self.seq)
+                .with(tag::SENDING_TIME, utc_timestamp(1_700_000_000));
+            stamped.fields.extend(msg.fields);
+            self.seq += 1;
+            self.stream.write_all(&stamped.encode()).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> FixMessage {
+            loop {
+                if let Some(len) = frame_len(&self.buffer).unwrap() {
+                    let frame: Vec<u8> = self.buffer.drain(..len).collect();
+                    return FixMessage::decode(&frame).unwrap();
+                }
+                let mut chunk = [0u8; 4096];
+                let read =
+                    tokio::time::timeout(Duration::from_secs(5), self.stream.read(&mut chunk))
+                        .await
+                        .expect("fix reply")
+                        .unwrap();
+                assert!(read > 0, "session closed");
+                self.buffer.extend_from_slice(&chunk[..read]);
+            }
+        }
+
+        async fn logon(&mut self, trader: &str) -> FixMessage {
+            let token = bearer_token(trader, 300).replace("Bearer ", "");
+            self.send(
+                FixMessage::new("A")
+                    .with(tag::ENCRYPT_METHOD, 0)
+                    .with(tag::HEART_BT_INT, 30)
+                    .with(tag::PASSWORD, token),
+            )
+            .await;
+            self.recv().await
+        }
+    }
+
+    fn new_order(cl_ord_id: &str, side: &str, quantity: u64) -> FixMessage {
+        FixMessage::new("D")
+            .with(tag::CL_ORD_ID, cl_ord_id)
+            .with(tag::SYMBOL, "ETH/USDC")
+            .with(tag::SIDE, side)
+            .with(tag::ORD_TYPE, 2)
+            .with(tag::ORDER_QTY, quantity)
+            .with(tag::PRICE, 1000)
+    }
+
+    #[test]
+    fn encodes_and_frames_messages() {
+        let msg = FixMessage::new("D")
+            .with(tag::CL_ORD_ID, "abc")
+            .with(tag::ORDER_QTY, 5);
+        let bytes = msg.encode();
+        assert!(bytes.starts_with(b"8=FIX.4.4\x019=17\x0135=D\x01"));
+        assert_eq!(frame_len(&bytes[..12]), Ok(None));
+        assert_eq!(frame_len(&bytes[..bytes.len() - 1]), Ok(None));
+
+        let mut stream = bytes.clone();
+        stream.extend_from_slice(b"8=FIX");
+        assert_eq!(frame_len(&stream), Ok(Some(bytes.len())));
+        assert_eq!(FixMessage::decode(&bytes), Ok(msg));
+
+        let mut tampered = bytes.clone();
+        let qty = tampered.len() - 9;
+        tampered[qty] = b'6';
+        assert!(matches!(
+            FixMessage::decode(&tampered),
+            Err(FixError::Checksum { .. })
+        ));
+        assert_eq!(frame_len(b"8=FIX.4.2\x01"), Err(FixError::BeginString));
+        assert_eq!(
+            frame_len(b"8=FIX.4.4\x019=1x\x01"),
+            Err(FixError::BodyLength)
+        );
+    }
+
+    #[test]
+    fn formats_utc_timestamps() {
+        assert_eq!(utc_timestamp(0), "19700101-00:00:00");
+        assert_eq!(utc_timestamp(1_700_000_000), "20231114-22:13:20");
+        assert_eq!(utc_timestamp(951_827_696), "20000229-12:34:56");
+    }
+
+    #[tokio::test]
+    async fn logon_requires_a_valid_token_and_sequence() {
+        let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+
+        let mut client = Client::connect(state.clone());
+        client
+            .send(
+                FixMessage::new("A")
+                    .with(tag::HEART_BT_INT, 30)
+                    .with(tag::PASSWORD, "not-a-jwt"),
+            )
+            .await;
+        let reply = client.recv().await;
+        assert_eq!(reply.msg_type(), "5");
+
+        let mut client = Client::connect(state.clone());
+        client.send(new_order("1", "1", 1)).await;
+        assert_eq!(
+            client.recv().await.get(tag::TEXT),
+            Some("first message must be Logon")
+        );
+
+        let mut client = Client::connect(state);
+        let logon = client.logon("bob").await;
+        assert_eq!(logon.msg_type(), "A");
+        assert_eq!(logon.get(tag::TARGET_COMP_ID), Some("MM1"));
+        assert_eq!(logon.get(tag::HEART_BT_INT), Some("30"));
+
+        client
+            .send(FixMessage::new("1").with(tag::TEST_REQ_ID, "ping-1"))
+            .await;
+        assert_eq!(client.recv().await.get(tag::TEST_REQ_ID), Some("ping-1"));
+
+        client.seq += 1;
+        client.send(FixMessage::new("0")).await;
+        let logout = client.recv().await;
+        assert_eq!(logout.msg_type(), "5");
+        assert!(logout
+            .get(tag::TEXT)
+            .unwrap()
+            .contains("resend is not supported"));
+    }
+
+    #[tokio::test]
+    async fn orders_run_through_the_shared_pipeline() {
+        let storage = Arc::new(MemoryStorage::default());
+        let state = test_state_with_memory(storage.clone());
+        let filter = routes(state.clone());
+        let mut client = Client::connect(state);
+        client.logon("bob").await;
+
+        client.send(new_order("sell-1", "2", 5)).await;
+        let new = client.recv().await;
+        assert_eq!(new.msg_type(), "8");
+        assert_eq!(new.get(tag::EXEC_TYPE), Some("0"));
+        assert_eq!(new.get(tag::CL_ORD_ID), Some("sell-1"));
+        let order_id = new.get(tag::ORDER_ID).unwrap().to_string();
+        assert_eq!(storage.orders.lock().unwrap().len(), 1);
+
+        // A REST taker fills part of the FIX order.
+        place(&filter, "alice", "buy", 2).await;
+        let fill = client.recv().await;
+        assert_eq!(fill.get(tag::EXEC_TYPE), Some("F"));
+        assert_eq!(fill.get(tag::ORD_STATUS), Some("1"));
+        assert_eq!(fill.get(tag::LAST_QTY), Some("2"));
+        assert_eq!(fill.get(tag::LAST_PX), Some("1000"));
+        assert_eq!(fill.get(tag::LEAVES_QTY), Some("3"));
+
+        client.send(new_order("sell-1", "2", 1)).await;
+        let duplicate = client.recv().await;
+        assert_eq!(duplicate.get(tag::EXEC_TYPE), Some("8"));
+        assert_eq!(duplicate.get(tag::TEXT), Some("duplicate ClOrdID"));
+
+        client.send(new_order("sell-2", "3", 1)).await;
+        let bad_side = client.recv().await;
+        assert_eq!(bad_side.get(tag::ORD_STATUS), Some("8"));
+
+        client
+            .send(
+                FixMessage::new("F")
+                    .with(tag::ORIG_CL_ORD_ID, "sell-1")
+                    .with(tag::CL_ORD_ID, "cancel-1")
+                    .with(tag::SYMBOL, "ETH/USDC")
+                    .with(tag::SIDE, 2),
+            )
+            .await;
+        let cancelled = client.recv().await;
+        assert_eq!(cancelled.get(tag::EXEC_TYPE), Some("4"));
+        assert_eq!(cancelled.get(tag::ORDER_ID), Some(order_id.as_str()));
+        assert_eq!(cancelled.get(tag::CL_ORD_ID), Some("cancel-1"));
+        assert_eq!(cancelled.get(tag::ORIG_CL_ORD_ID), Some("sell-1"));
+        assert_eq!(cancelled.get(tag::CUM_QTY), Some("2"));
+        assert_eq!(cancelled.get(tag::LEAVES_QTY), Some("0"));
+
+        client
+            .send(
+                FixMessage::new("F")
+                    .with(tag::ORIG_CL_ORD_ID, "sell-1")
+                    .with(tag::CL_ORD_ID, "cancel-2"),
+            )
+            .await;
+        let rejected = client.recv().await;
+        assert_eq!(rejected.msg_type(), "9");
+        assert_eq!(rejected.get(tag::CXL_REJ_REASON), Some("1"));
+    }
+}
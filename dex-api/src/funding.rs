@@ -0,0 +1,600 @@
+//! Deposits and withdrawals of traders' funds.
+//!
+//! Every movement of funds in or out of the exchange is kept in
+//! `funding_repo` next to the ledger entry that moved the balance. Bridge
+//! deposits are recorded as confirmed once credited (see [`crate::bridge`]).
+//! A trader requests a withdrawal through `POST /account/withdrawals`: it is
+//! accepted while their balance covers it on top of what their resting
+//! orders commit, and debited at once from `trader:<id>` to the chain's
+//! `bridge:<chain>` account, so the funds cannot be spent twice while the
+//! withdrawal is pending. Operators send it and report the outcome through
+//! `POST /admin/withdrawals/{id}`; a failed withdrawal's funds are returned.
+
+use crate::{
+    admin_only, api_keys,
+    auth::{Claims, Scope},
+    authenticated, bridge, error_reply, funds,
+    orders::replica_reply,
+    rate_limit::RouteClass,
+    rate_limited, storage_error_reply, ApiState, ErrorResponse,
+};
+use dex_core::{
+    cross_chain_asset_mapping::BridgeCredit,
+    ledger::{Account, EntryKind, JournalEntry},
+    types::TokenId,
+};
+use dex_db::{DatabaseError, FundingFilter, FundingKind, FundingRecord, FundingStatus};
+use serde::{Deserialize, Serialize};
+use utoipa::{openapi::Object, IntoParams, ToSchema};
+use warp::{http::StatusCode, Filter};
+
+/// Default page size of funding history.
+pub const DEFAULT_PAGE: u32 = 100;
+
+/// Longest withdrawal address accepted.
+const MAX_ADDRESS_LEN: usize = 128;
+
+/// Longest transaction hash accepted.
+const MAX_TX_HASH_LEN: usize = 128;
+
+/// Record a credited bridge deposit, logging rather than returning failures:
+/// the ledger already holds the credit.
+pub async fn record_deposit(
+    state: &ApiState,
+    credit: &BridgeCredit,
+    tx_hash: Option<&str>,
+    timestamp: u64,
+) {
+    let deposit = &credit.deposit;
+    let record = FundingRecord {
+        id: 0,
+        trader_id: deposit.recipient.to_string(),
+        kind: FundingKind::Deposit,
+        chain: deposit.chain.clone(),
+        token: credit.asset_id.clone(),
+        amount: credit.amount,
+        address: None,
+        reference: Some(deposit.event_id.clone()),
+        tx_hash: tx_hash
+            .map(str::trim)
+            .filter(|hash| !hash.is_empty() && hash.len() <= MAX_TX_HASH_LEN)
+            .map(str::to_string),
+        status: FundingStatus::Confirmed,
+        created_at: timestamp,
+        updated_at: timestamp,
+    };
+    if let Err(err) = state.funding_repo.save_funding(&record).await {
+        tracing::error!(
+            chain = %deposit.chain,
+            event_id = %deposit.event_id,
+            error = ?err,
+            "failed to record deposit"
+        );
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WithdrawalRequest {
+    pub chain: String,
+    pub token: String,
+    pub amount: u128,
+    /// Where to send the funds on `chain`.
+    pub address: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SettleWithdrawalRequest {
+    /// `confirmed` or `failed`.
+    #[schema(schema_with = settled_statuses)]
+    pub status: String,
+    /// Transaction that sent the funds; required to confirm.
+    pub tx_hash: Option<String>,
+}
+
+fn settled_statuses() -> Object {
+    crate::openapi::string_enum(&["confirmed", "failed"])
+}
+
+/// A deposit or withdrawal, as returned by the API.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(as = FundingTransfer)]
+pub struct FundingResponse {
+    pub id: u64,
+    pub trader_id: String,
+    /// `deposit` or `withdrawal`.
+    #[schema(schema_with = funding_kinds)]
+    pub kind: &'static str,
+    pub chain: String,
+    pub token: String,
+    pub amount: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    /// `pending`, `confirmed` or `failed`.
+    #[schema(schema_with = funding_statuses)]
+    pub status: &'static str,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl From<FundingRecord> for FundingResponse {
+    fn from(record: FundingRecord) -> Self {
+        Self {
+            id: record.id,
+            trader_id: record.trader_id,
+            kind: record.kind.as_str(),
+            chain: record.chain,
+            token: record.token.to_string(),
+            amount: record.amount,
+            address: record.address,
+            tx_hash: record.tx_hash,
+            status: record.status.as_str(),
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+fn funding_kinds() -> Object {
+    crate::openapi::string_enum(&["deposit", "withdrawal"])
+}
+
+fn funding_statuses() -> Object {
+    crate::openapi::string_enum(&["pending", "confirmed", "failed"])
+}
+
+/// Query parameters of `GET /account/funding` and `GET /admin/funding`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FundingQuery {
+    /// Cursor from `next_cursor`
+    pub after_id: Option<u64>,
+    /// Page size, 1-1000 (default 100)
+    pub limit: Option<u32>,
+    /// `deposit` or `withdrawal`.
+    pub kind: Option<String>,
+    /// `pending`, `confirmed` or `failed`.
+    pub status: Option<String>,
+    /// Only honoured for administrators.
+    pub trader_id: Option<String>,
+}
+
+impl FundingQuery {
+    /// The filter, or a message naming the parameter that did not parse.
+    pub fn into_filter(self) -> Result<FundingFilter, String> {
+        let nonempty = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        Ok(FundingFilter {
+            after_id: self.after_id,
+            limit: self.limit.unwrap_or(DEFAULT_PAGE),
+            trader_id: nonempty(self.trader_id),
+            kind: nonempty(self.kind)
+                .map(|kind| kind.parse())
+                .transpose()
+                .map_err(|_| "kind must be deposit or withdrawal")?,
+            status: nonempty(self.status)
+                .map(|status| status.parse())
+                .transpose()
+                .map_err(|_| "status must be pending, confirmed or failed")?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = FundingHistory)]
+pub struct FundingHistoryResponse {
+    pub transfers: Vec<FundingResponse>,
+    /// Pass as `after_id` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// One page of records matching `filter`.
+pub async fn history(
+    state: &ApiState,
+    filter: &FundingFilter,
+) -> Result<FundingHistoryResponse, DatabaseError> {
+    let records = state.funding_repo.query_funding(filter).await?;
+    let next_cursor = if records.len() == filter.page_size() as usize {
+        records.last().map(|record| record.id)
+    } else {
+        None
+    };
+    Ok(FundingHistoryResponse {
+        transfers: records.into_iter().map(FundingResponse::from).collect(),
+        next_cursor,
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum WithdrawalError {
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+    #[error("{token} cannot be withdrawn to {chain}")]
+    Unsupported { chain: String, token: String },
+    #[error("withdrawal and resting orders need {} {}, more than the balance of {}", .0.required, .0.token, .0.available)]
+    Insufficient(funds::InsufficientFunds),
+    #[error("withdrawal {0} not found")]
+    NotFound(u64),
+    #[error("withdrawal {0} is no longer pending")]
+    AlreadySettled(u64),
+    #[error("failed to store the withdrawal: {0}")]
+    Storage(#[from] DatabaseError),
+}
+
+impl From<funds::FundsError> for WithdrawalError {
+    fn from(err: funds::FundsError) -> Self {
+        match err {
+            funds::FundsError::Insufficient(shortfall) => WithdrawalError::Insufficient(shortfall),
+            funds::FundsError::Overflow => WithdrawalError::Invalid("amount"),
+            funds::FundsError::Storage(err) => WithdrawalError::Storage(err),
+        }
+    }
+}
+
+fn withdrawal_entry(
+    record: &FundingRecord,
+    reference: String,
+    returned: bool,
+    timestamp: u64,
+) -> JournalEntry {
+    let trader = Account::Trader(record.trader_id.clone());
+    let bridge = Account::Bridge(record.chain.clone());
+    let (from, to) = if returned {
+        (bridge, trader)
+    } else {
+        (trader, bridge)
+    };
+    JournalEntry::new(EntryKind::Withdrawal, reference, timestamp).transfer(
+        from,
+        to,
+        &record.token,
+        record.amount,
+    )
+}
+
+/// Accept `trader`'s withdrawal and debit it, or refuse it when their free
+/// balance does not cover it.
+pub async fn request_withdrawal(
+    state: &ApiState,
+    trader: &str,
+    request: &WithdrawalRequest,
+) -> Result<FundingResponse, WithdrawalError> {
+    let token =
+        TokenId::parse(request.token.trim()).map_err(|_| WithdrawalError::Invalid("token"))?;
+    let chain = request.chain.trim();
+    if chain.is_empty() || chain == bridge::LOCAL_CHAIN {
+        return Err(WithdrawalError::Invalid("chain"));
+    }
+    let address = request.address.trim();
+    if address.is_empty() || address.len() > MAX_ADDRESS_LEN {
+        return Err(WithdrawalError::Invalid("address"));
+    }
+    if request.amount == 0 {
+        return Err(WithdrawalError::Invalid("amount"));
+    }
+    if !state.bridge.withdraws_to(chain, &token) {
+        return Err(WithdrawalError::Unsupported {
+            chain: chain.to_string(),
+            token: token.to_string(),
+        });
+    }
+
+    // Orders hold their trader's lock from their funds check until they
+    // rest, so holding it here keeps them from committing the same funds
+    // while the balance is checked and debited. Commitments are read before
+    // the balance, so a fill in between is counted twice rather than missed.
+    let _trader_lock = state.trader_locks.lock(trader).await;
+    let fees = state.settings.borrow().fees.clone();
+    let resting = funds::committed(&*state.orderbook.read().await, &fees, trader, &token);
+    let required = resting
+        .and_then(|resting| request.amount.checked_add(resting))
+        .ok_or(WithdrawalError::Invalid("amount"))?;
+    funds::cover(state, trader, token.clone(), required).await?;
+
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let mut record = FundingRecord {
+        id: 0,
+        trader_id: trader.to_string(),
+        kind: FundingKind::Withdrawal,
+        chain: chain.to_string(),
+        token,
+        amount: request.amount,
+        address: Some(address.to_string()),
+        reference: None,
+        tx_hash: None,
+        status: FundingStatus::Pending,
+        created_at: timestamp,
+        updated_at: timestamp,
+    };
+    let debit = |id| withdrawal_entry(&record, format!("withdrawal:{}", id), false, timestamp);
+    record.id = state.funding_repo.save_withdrawal(&record, &debit).await?;
+    Ok(record.into())
+}
+
+/// Record the outcome of a pending withdrawal, returning the funds of one
+/// that failed.
+pub async fn settle_withdrawal(
+    state: &ApiState,
+    id: u64,
+    request: &SettleWithdrawalRequest,
+) -> Result<FundingResponse, WithdrawalError> {
+    let status = match request.status.trim().parse() {
+        Ok(status @ (FundingStatus::Confirmed | FundingStatus::Failed)) => status,
+        _ => return Err(WithdrawalError::Invalid("status")),
+    };
+    let tx_hash = request
+        .tx_hash
+        .as_deref()
+        .map(str::trim)
+        .filter(|hash| !hash.is_empty());
+    if tx_hash.is_some_and(|hash| hash.len() > MAX_TX_HASH_LEN)
+        || (status == FundingStatus::Confirmed && tx_hash.is_none())
+    {
+        return Err(WithdrawalError::Invalid("tx_hash"));
+    }
+
+    let record = state
+        .funding_repo
+        .get_funding(id)
+        .await?
+        .filter(|record| record.kind == FundingKind::Withdrawal)
+        .ok_or(WithdrawalError::NotFound(id))?;
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let refund = (status == FundingStatus::Failed).then(|| {
+        withdrawal_entry(
+            &record,
+            format!("withdrawal:{}:returned", id),
+            true,
+            timestamp,
+        )
+    });
+    if !state
+        .funding_repo
+        .settle_funding(id, status, tx_hash, timestamp, refund.as_ref())
+        .await?
+    {
+        return Err(WithdrawalError::AlreadySettled(id));
+    }
+    Ok(FundingRecord {
+        status,
+        tx_hash: tx_hash.map(str::to_string).or(record.tx_hash.clone()),
+        updated_at: timestamp,
+        ..record
+    }
+    .into())
+}
+
+/// Withdrawals and funding history of the caller, e.g. POST /account/withdrawals
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let withdraw = warp::path("account")
+        .and(warp::path("withdrawals"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(authenticated(state.clone(), Scope::Withdraw))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_request_withdrawal)
+        .boxed();
+
+    let get_account_funding = warp::path("account")
+        .and(warp::path("funding"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<FundingQuery>())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_account_funding)
+        .boxed();
+
+    withdraw.or(get_account_funding)
+}
+
+/// Funding history of every trader and withdrawal settlement, e.g.
+/// POST /admin/withdrawals/7
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let admin_funding = warp::path("admin")
+        .and(warp::path("funding"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<FundingQuery>())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_admin_funding)
+        .boxed();
+
+    let settle = warp::path("admin")
+        .and(warp::path("withdrawals"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_settle_withdrawal)
+        .boxed();
+
+    admin_funding.or(settle)
+}
+
+fn withdrawal_error_reply(err: WithdrawalError) -> warp::reply::WithStatus<warp::reply::Json> {
+    use WithdrawalError;
+    match err {
+        err @ WithdrawalError::Invalid(_) => {
+            error_reply("invalid_request", err.to_string(), StatusCode::BAD_REQUEST)
+        }
+        err @ WithdrawalError::Unsupported { .. } => error_reply(
+            "unsupported_withdrawal",
+            err.to_string(),
+            StatusCode::BAD_REQUEST,
+        ),
+        err @ WithdrawalError::Insufficient(_) => error_reply(
+            "insufficient_balance",
+            err.to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+        err @ WithdrawalError::NotFound(_) => error_reply(
+            "withdrawal_not_found",
+            err.to_string(),
+            StatusCode::NOT_FOUND,
+        ),
+        err @ WithdrawalError::AlreadySettled(_) => error_reply(
+            "withdrawal_already_settled",
+            err.to_string(),
+            StatusCode::CONFLICT,
+        ),
+        WithdrawalError::Storage(err) => {
+            tracing::error!(error = ?err, "failed to store withdrawal");
+            storage_error_reply(&err, "failed to store the withdrawal")
+        }
+    }
+}
+
+/// Withdraw part of the caller's balance to another chain.
+#[utoipa::path(
+    post,
+    path = "/account/withdrawals",
+    summary = "Withdraw part of the caller's balance to another chain; needs the withdraw scope",
+    description = "The amount is debited at once and the withdrawal stays pending until an operator reports it sent or failed; a failed withdrawal's funds are returned.",
+    request_body = WithdrawalRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 201, description = "Pending withdrawal", body = FundingResponse),
+        (status = 400, description = "Invalid request, or the token is not bridged from that chain", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 403, description = "Token lacks the withdraw scope", body = ErrorResponse),
+        (status = 422, description = "Balance does not cover the withdrawal and resting orders (insufficient_balance)", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable, or this instance takes no orders", body = ErrorResponse),
+    ),
+)]
+async fn handle_request_withdrawal(
+    claims: Claims,
+    state: ApiState,
+    req: WithdrawalRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if !state.storage.is_available() {
+        return Ok(error_reply(
+            "degraded_mode",
+            "withdrawals are suspended while storage is unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ));
+    }
+    if state.is_read_only() {
+        return Ok(replica_reply());
+    }
+    match request_withdrawal(&state, &claims.sub, &req).await {
+        Ok(response) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            StatusCode::CREATED,
+        )),
+        Err(err) => Ok(withdrawal_error_reply(err)),
+    }
+}
+
+async fn funding_history_reply(
+    state: &ApiState,
+    filter: Result<dex_db::FundingFilter, String>,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    let filter = match filter {
+        Ok(filter) => filter,
+        Err(message) => return error_reply("invalid_query", message, StatusCode::BAD_REQUEST),
+    };
+    match history(state, &filter).await {
+        Ok(history) => warp::reply::with_status(warp::reply::json(&history), StatusCode::OK),
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to query funding history");
+            storage_error_reply(&err, "failed to query funding history")
+        }
+    }
+}
+
+/// The caller's deposits and withdrawals, oldest first.
+#[utoipa::path(
+    get,
+    path = "/account/funding",
+    summary = "The caller's deposits and withdrawals, oldest first",
+    params(FundingQuery),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "One page of funding history", body = FundingHistoryResponse),
+        (status = 400, description = "Unknown kind or status", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_account_funding(
+    query: FundingQuery,
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let filter = query.into_filter().map(|filter| dex_db::FundingFilter {
+        trader_id: Some(claims.sub.clone()),
+        ..filter
+    });
+    Ok(funding_history_reply(&state, filter).await)
+}
+
+/// Every trader's deposits and withdrawals, e.g. the pending withdrawals
+/// waiting to be sent.
+#[utoipa::path(
+    get,
+    path = "/admin/funding",
+    summary = "Every trader's deposits and withdrawals, oldest first (administrators only)",
+    params(FundingQuery),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "One page of funding history", body = FundingHistoryResponse),
+        (status = 400, description = "Unknown kind or status", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_admin_funding(
+    query: FundingQuery,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(funding_history_reply(&state, query.into_filter()).await)
+}
+
+/// Record whether a pending withdrawal was sent.
+#[utoipa::path(
+    post,
+    path = "/admin/withdrawals/{withdrawal_id}",
+    summary = "Report a pending withdrawal as sent or failed (administrators only)",
+    params(("withdrawal_id" = u64, Path)),
+    request_body = SettleWithdrawalRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Settled withdrawal", body = FundingResponse),
+        (status = 400, description = "Unknown status, or a confirmation without tx_hash", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 404, description = "No withdrawal with this id", body = ErrorResponse),
+        (status = 409, description = "The withdrawal is no longer pending", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_settle_withdrawal(
+    id: u64,
+    _claims: Claims,
+    state: ApiState,
+    req: SettleWithdrawalRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match settle_withdrawal(&state, id, &req).await {
+        Ok(response) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            StatusCode::OK,
+        )),
+        Err(err) => Ok(withdrawal_error_reply(err)),
+    }
+}
@@ -0,0 +1,290 @@
+//! Refusing orders their trader cannot pay for.
+//!
+//! With `REQUIRE_FUNDED_ORDERS` set, an order is only accepted while the
+//! trader's ledger balance covers it together with their other resting
+//! orders. A sell commits its base quantity and a limit buy its notional in
+//! the quote token, plus the fee on it. A market buy has no price of its
+//! own, so it commits what the asks it would take cost, plus the fee. A
+//! sell's fee is taken from its proceeds, so it needs no quote. Fills are booked in the ledger as they
+//! happen, so what an order has already spent or earned is reflected in the
+//! balance it is checked against. Orders and withdrawals hold their trader's
+//! lock in [`TraderLocks`] from the check until the funds are committed, so
+//! neither is checked against funds the other is about to take.
+
+use crate::{
+    auth::{Claims, Scope},
+    authenticated,
+    rate_limit::RouteClass,
+    rate_limited,
+    settings::{FeeRates, FeeSchedule},
+    storage_error_reply, ApiState, ErrorResponse,
+};
+use dex_core::{
+    ledger::Account,
+    orderbook::OrderBook,
+    types::{Notional, Order, OrderSide, OrderType, TokenId},
+};
+use dex_db::DatabaseError;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+use tokio::sync::OwnedMutexGuard;
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// An order the trader's balance does not cover.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsufficientFunds {
+    pub token: TokenId,
+    /// What the order and the trader's resting orders commit.
+    pub required: u128,
+    /// The trader's ledger balance, or zero when they owe the token.
+    pub available: u128,
+}
+
+#[derive(Debug)]
+pub enum FundsError {
+    Insufficient(InsufficientFunds),
+    /// What the order and the trader's resting orders commit overflows u128.
+    Overflow,
+    Storage(DatabaseError),
+}
+
+impl From<DatabaseError> for FundsError {
+    fn from(err: DatabaseError) -> Self {
+        FundsError::Storage(err)
+    }
+}
+
+/// One lock per trader with funds being checked. Entries nobody holds are
+/// dropped as new ones are added.
+#[derive(Debug, Default)]
+pub struct TraderLocks {
+    locks: Mutex<HashMap<String, Weak<tokio::sync::Mutex<()>>>>,
+}
+
+impl TraderLocks {
+    /// Wait for `trader`'s lock.
+    pub async fn lock(&self, trader: &str) -> OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            match locks.get(trader).and_then(Weak::upgrade) {
+                Some(lock) => lock,
+                None => {
+                    locks.retain(|_, lock| lock.strong_count() > 0);
+                    let lock = Arc::new(tokio::sync::Mutex::new(()));
+                    locks.insert(trader.to_string(), Arc::downgrade(&lock));
+                    lock
+                }
+            }
+        };
+        lock.lock_owned().await
+    }
+}
+
+/// One token of a trader's balance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TokenBalance {
+    pub token: String,
+    /// Ledger debits minus credits; negative while the trader owes it.
+    pub balance: i128,
+}
+
+/// Response of `GET /account/balances`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct AccountBalances {
+    pub trader_id: String,
+    pub balances: Vec<TokenBalance>,
+}
+
+/// `trader`'s ledger balance in each token they hold or owe.
+pub async fn balances(state: &ApiState, trader: &str) -> Result<AccountBalances, DatabaseError> {
+    let balances = state
+        .ledger_repo
+        .account_balances(&Account::Trader(trader.to_string()))
+        .await?;
+    Ok(AccountBalances {
+        trader_id: trader.to_string(),
+        balances: balances
+            .into_iter()
+            .map(|balance| TokenBalance {
+                token: balance.token.to_string(),
+                balance: balance.balance,
+            })
+            .collect(),
+    })
+}
+
+/// Token `order` spends and how much of it, given the book it would meet and
+/// its pair's fee rates; `None` when the amount overflows. A buy pays its fee
+/// in the quote token on top of its notional, taken at the higher of the
+/// maker and taker rates since it can fill as either. A sell's fee comes out
+/// of its proceeds, so it commits only its base quantity.
+fn commitment<'a>(
+    order: &Order,
+    resting: impl Iterator<Item = &'a Order>,
+    rates: FeeRates,
+) -> (TokenId, Option<u128>) {
+    let notional = match (order.side, order.order_type, order.price) {
+        (OrderSide::Sell, _, _) => {
+            return (order.pair.base().clone(), Some(u128::from(order.quantity)))
+        }
+        (OrderSide::Buy, OrderType::Limit, Some(price)) => {
+            Some(Notional::of(price, order.quantity).value())
+        }
+        (OrderSide::Buy, _, _) => {
+            let mut asks: Vec<&Order> = resting
+                .filter(|ask| ask.pair == order.pair && ask.side == OrderSide::Sell)
+                .collect();
+            asks.sort_by_key(|ask| (ask.price, ask.timestamp, ask.id));
+            let mut remaining = order.quantity;
+            let mut cost = Some(0u128);
+            for ask in asks {
+                if remaining == 0 {
+                    break;
+                }
+                let taken = remaining.min(ask.quantity);
+                let notional = Notional::of(ask.price.unwrap_or_default(), taken).value();
+                cost = cost.and_then(|cost| cost.checked_add(notional));
+                remaining -= taken;
+            }
+            cost
+        }
+    };
+    let bps = u128::from(rates.maker_bps.max(rates.taker_bps));
+    let required = notional.and_then(|notional| {
+        let fee = notional.checked_mul(bps)? / 10_000;
+        notional.checked_add(fee)
+    });
+    (order.pair.quote().clone(), required)
+}
+
+/// What `trader`'s resting orders in `orderbook` commit of `token` under the
+/// fee schedule `fees`, or `None` when the total overflows.
+pub fn committed(
+    orderbook: &OrderBook,
+    fees: &FeeSchedule,
+    trader: &str,
+    token: &TokenId,
+) -> Option<u128> {
+    orderbook
+        .orders
+        .values()
+        .filter(|resting| resting.trader_id.as_str() == trader)
+        .map(|resting| commitment(resting, std::iter::empty(), fees.rates(&resting.pair)))
+        .filter(|(spent, _)| spent == token)
+        .try_fold(0u128, |total, (_, amount)| total.checked_add(amount?))
+}
+
+/// Check that `trader`'s ledger balance in `token` covers `required`.
+pub async fn cover(
+    state: &ApiState,
+    trader: &str,
+    token: TokenId,
+    required: u128,
+) -> Result<(), FundsError> {
+    let balance = state
+        .ledger_repo
+        .account_balances(&Account::Trader(trader.to_string()))
+        .await?
+        .into_iter()
+        .find(|balance| balance.token == token)
+        .map_or(0, |balance| balance.balance);
+    let available = u128::try_from(balance).unwrap_or(0);
+    if required > available {
+        return Err(FundsError::Insufficient(InsufficientFunds {
+            token,
+            required,
+            available,
+        }));
+    }
+    Ok(())
+}
+
+/// Check that `order`'s trader can fund it alongside their resting orders
+/// in `orderbook`. An order is only counted once it rests, so callers placing
+/// it hold the book's write lock and the trader's lock from this check until
+/// it is added and its fills are booked.
+pub async fn check(
+    state: &ApiState,
+    orderbook: &OrderBook,
+    order: &Order,
+) -> Result<(), FundsError> {
+    if !state.config.require_funded_orders {
+        return Ok(());
+    }
+    let fees = state.settings.borrow().fees.clone();
+    let (token, required) = commitment(order, orderbook.orders.values(), fees.rates(&order.pair));
+    let required = required
+        .zip(committed(
+            orderbook,
+            &fees,
+            order.trader_id.as_str(),
+            &token,
+        ))
+        .and_then(|(required, resting)| required.checked_add(resting))
+        .ok_or(FundsError::Overflow)?;
+    cover(state, order.trader_id.as_str(), token, required).await
+}
+
+/// The caller's balances, GET /account/balances
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("account")
+        .and(warp::path("balances"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_account_balances)
+        .boxed()
+}
+
+#[utoipa::path(
+    get,
+    path = "/account/balances",
+    summary = "The caller's ledger balance in each token",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Balances per token; negative where the caller owes it", body = AccountBalances),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_account_balances(
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match balances(&state, &claims.sub).await {
+        Ok(balances) => Ok(warp::reply::with_status(
+            warp::reply::json(&balances),
+            StatusCode::OK,
+        )),
+        Err(err) => Ok(storage_error_reply(&err, "failed to load balances")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn traders_wait_only_for_their_own_lock() {
+        let locks = TraderLocks::default();
+        let alice = locks.lock("alice").await;
+        assert!(
+            tokio::time::timeout(std::time::Duration::from_millis(10), locks.lock("alice"))
+                .await
+                .is_err()
+        );
+        drop(locks.lock("bob").await);
+        drop(alice);
+        drop(locks.lock("alice").await);
+        // Released locks are dropped once another trader takes one.
+        let _carol = locks.lock("carol").await;
+        assert_eq!(locks.locks.lock().unwrap().len(), 1);
+    }
+}
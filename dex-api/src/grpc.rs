@@ -0,0 +1,700 @@
+//! gRPC order entry and market data: the `dex.v1.Exchange` service.
+//!
+//! Orders and cancels run through the same pipeline as `POST
+//! /orderbook/orders` and `DELETE /orderbook/orders/{order_id}`, depth comes
+//! from the same snapshots as `GET /orderbook/depth`, and the streams
+//! forward the broadcasts behind the `/ws` channels. Authenticated calls
+//! carry the REST API's bearer token in `authorization` metadata; every
+//! call counts against the REST rate limits, and orders and cancels are
+//! audited like FIX ones.
+
+use crate::{
+    audit::{self, AuditAction},
+    auth::{Claims, Scope},
+    ip_allowlist,
+    market_data::{requested_depth, DepthQuery, DepthSnapshot},
+    order_events::{Liquidity, OrderEvent, OrderStatus, UserEvent},
+    orders::{
+        self, insufficient_funds_message, margin_limit_message, CancelError, CreateOrderRequest,
+        SubmitError,
+    },
+    rate_limit::{ClientKey, RouteClass},
+    trade_tape::{MarketTrade, TradeEventKind, Venue},
+    validation, ApiState,
+};
+use dex_core::types::Trade;
+use dex_grpc::v1::{
+    self,
+    exchange_server::{Exchange, ExchangeServer},
+};
+use futures_util::Stream;
+use std::{future::Future, net::IpAddr, pin::Pin, sync::Arc};
+use tokio::{
+    net::TcpListener,
+    sync::{broadcast, mpsc},
+};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{transport::Server, Request, Response, Status};
+
+/// Messages queued for a streaming call before events wait on the client.
+const STREAM_BUFFER: usize = 64;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// The `dex.v1.Exchange` service on the API's shared state.
+pub struct ExchangeService {
+    state: ApiState,
+}
+
+// The helpers' errors are returned as-is from tonic handlers, which must
+// produce a `Status`; boxing them would only be undone at every call site.
+#[allow(clippy::result_large_err)]
+impl ExchangeService {
+    pub fn new(state: ApiState) -> Self {
+        Self { state }
+    }
+
+    /// Charge the call to the caller's budget for `class`: the trader when it
+    /// carries a valid bearer token, the client address otherwise.
+    fn rate_limit<T>(&self, request: &Request<T>, class: RouteClass) -> Result<(), Status> {
+        let key =
+            match bearer(request).and_then(|header| self.state.auth.verify_bearer(header).ok()) {
+                Some(claims) => ClientKey::Trader(claims.sub),
+                None => peer(request).map_or(ClientKey::Anonymous, ClientKey::Ip),
+            };
+        self.state
+            .rate_limiter
+            .check(class, key)
+            .map_err(|retry_after| {
+                let retry_after = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+                Status::resource_exhausted(format!(
+                    "rate limit exceeded, retry in {}s",
+                    retry_after
+                ))
+            })
+    }
+
+    /// Claims of the call's bearer token, which must grant `scope`.
+    fn authenticate<T>(&self, request: &Request<T>, scope: Scope) -> Result<Claims, Status> {
+        let header =
+            bearer(request).ok_or_else(|| Status::unauthenticated("a bearer token is required"))?;
+        let claims = self
+            .state
+            .auth
+            .verify_bearer(header)
+            .map_err(|err| Status::unauthenticated(err.to_string()))?;
+        ip_allowlist::check(&self.state, &claims.sub, peer(request)).map_err(|_| {
+            Status::permission_denied("credentials may not be used from this address")
+        })?;
+        if !claims.has_scope(scope) {
+            return Err(Status::permission_denied(format!(
+                "token lacks the `{}` scope",
+                scope
+            )));
+        }
+        Ok(claims)
+    }
+
+    async fn place(
+        &self,
+        claims: &Claims,
+        req: v1::PlaceOrderRequest,
+    ) -> Result<v1::PlaceOrderResponse, Status> {
+        let side = match req.side() {
+            v1::Side::Buy => "buy",
+            v1::Side::Sell => "sell",
+            v1::Side::Unspecified => return Err(Status::invalid_argument("side is required")),
+        };
+        let order_type = match req.order_type() {
+            v1::OrderType::Market => "market",
+            v1::OrderType::Limit => "limit",
+            v1::OrderType::Unspecified => {
+                return Err(Status::invalid_argument("order_type is required"))
+            }
+        };
+        let validated = validation::validate_create_order(CreateOrderRequest {
+            trader_id: req.trader_id,
+            base_token: req.base_token,
+            quote_token: req.quote_token,
+            side: side.to_string(),
+            order_type: order_type.to_string(),
+            price: req.price,
+            quantity: req.quantity,
+        })
+        .map_err(|err| Status::invalid_argument(err.to_string()))?;
+        if validated.trader_id != claims.sub {
+            return Err(Status::permission_denied(
+                "trader_id does not match authenticated subject",
+            ));
+        }
+        let outcome = orders::submit_order(&self.state, validated)
+            .await
+            .map_err(submit_status)?;
+        Ok(v1::PlaceOrderResponse {
+            order_id: outcome.order.id,
+            trades: outcome.trades.iter().map(trade_message).collect(),
+        })
+    }
+
+    fn depth(&self, req: v1::DepthRequest) -> Result<Arc<validation::DepthRequest>, Status> {
+        let query = DepthQuery {
+            levels: req.levels.map(|levels| levels as usize),
+            pair: req.pair,
+            grouping: req.grouping,
+            encoding: None,
+        };
+        validation::validate_depth_query(query)
+            .map(Arc::new)
+            .map_err(|err| Status::invalid_argument(err.to_string()))
+    }
+
+    async fn depth_snapshot(&self, request: &validation::DepthRequest) -> v1::DepthSnapshot {
+        let now = self.state.determinism.now().unwrap_or_default();
+        depth_message(requested_depth(
+            &*self.state.orderbook.read().await,
+            request,
+            now,
+        ))
+    }
+}
+
+#[tonic::async_trait]
+impl Exchange for ExchangeService {
+    async fn place_order(
+        &self,
+        request: Request<v1::PlaceOrderRequest>,
+    ) -> Result<Response<v1::PlaceOrderResponse>, Status> {
+        self.rate_limit(&request, RouteClass::OrderEntry)?;
+        let claims = self.authenticate(&request, Scope::Trade)?;
+        let ip = peer(&request);
+        let req = request.into_inner();
+        let target = format!("gRPC PlaceOrder {}-{}", req.base_token, req.quote_token);
+        let placed = self.place(&claims, req).await;
+        audit::record_gateway(
+            &self.state,
+            &claims.sub,
+            ip,
+            AuditAction::OrderCreate,
+            target,
+            placed.is_ok(),
+        )
+        .await;
+        placed.map(Response::new)
+    }
+
+    async fn cancel_order(
+        &self,
+        request: Request<v1::CancelOrderRequest>,
+    ) -> Result<Response<v1::CancelOrderResponse>, Status> {
+        self.rate_limit(&request, RouteClass::OrderEntry)?;
+        let claims = self.authenticate(&request, Scope::Trade)?;
+        let ip = peer(&request);
+        let order_id = request.into_inner().order_id;
+        let cancelled = orders::cancel_order(&self.state, order_id, &claims.sub).await;
+        audit::record_gateway(
+            &self.state,
+            &claims.sub,
+            ip,
+            AuditAction::OrderCancel,
+            format!("gRPC CancelOrder {}", order_id),
+            cancelled.is_ok(),
+        )
+        .await;
+        match cancelled {
+            Ok(order) => Ok(Response::new(v1::CancelOrderResponse {
+                order_id,
+                cancelled_quantity: order.quantity,
+            })),
+            Err(CancelError::NotFound) => Err(Status::not_found("no open order with this id")),
+            Err(CancelError::Replica) => Err(Status::unavailable(REPLICA_MESSAGE)),
+            Err(CancelError::Book(err)) => Err(Status::failed_precondition(err.to_string())),
+        }
+    }
+
+    async fn get_depth(
+        &self,
+        request: Request<v1::DepthRequest>,
+    ) -> Result<Response<v1::DepthSnapshot>, Status> {
+        self.rate_limit(&request, RouteClass::MarketData)?;
+        let depth = self.depth(request.into_inner())?;
+        Ok(Response::new(self.depth_snapshot(&depth).await))
+    }
+
+    type StreamDepthStream = ResponseStream<v1::DepthSnapshot>;
+
+    async fn stream_depth(
+        &self,
+        request: Request<v1::DepthRequest>,
+    ) -> Result<Response<Self::StreamDepthStream>, Status> {
+        self.rate_limit(&request, RouteClass::MarketData)?;
+        let depth = self.depth(request.into_inner())?;
+        let events = self.state.market_tx.subscribe();
+        let first = self.depth_snapshot(&depth).await;
+        let state = self.state.clone();
+        // Each book change is read again at the requested levels and pair;
+        // snapshots missed by a slow client are covered by the next one.
+        let stream = relay(&self.state, events, Some(first), move |_| {
+            let service = ExchangeService::new(state.clone());
+            let depth = depth.clone();
+            async move { Ok(Some(service.depth_snapshot(&depth).await)) }
+        });
+        Ok(Response::new(stream))
+    }
+
+    type StreamTradesStream = ResponseStream<v1::PublicTrade>;
+
+    async fn stream_trades(
+        &self,
+        request: Request<v1::StreamTradesRequest>,
+    ) -> Result<Response<Self::StreamTradesStream>, Status> {
+        self.rate_limit(&request, RouteClass::MarketData)?;
+        let pair = validation::parse_pair(&request.into_inner().pair)
+            .map_err(|err| Status::invalid_argument(err.to_string()))?
+            .to_string();
+        let events = self.state.trade_tx.subscribe();
+        let stream = relay(
+            &self.state,
+            events,
+            None,
+            move |trade: Option<MarketTrade>| {
+                let forwarded = match trade {
+                    Some(trade) if trade.pair == pair => Ok(Some(public_trade(trade))),
+                    Some(_) => Ok(None),
+                    None => Err(lagged()),
+                };
+                async move { forwarded }
+            },
+        );
+        Ok(Response::new(stream))
+    }
+
+    type StreamOrderEventsStream = ResponseStream<v1::OrderEvent>;
+
+    async fn stream_order_events(
+        &self,
+        request: Request<v1::StreamOrderEventsRequest>,
+    ) -> Result<Response<Self::StreamOrderEventsStream>, Status> {
+        self.rate_limit(&request, RouteClass::MarketData)?;
+        let trader_id = self.authenticate(&request, Scope::Read)?.sub;
+        let events = self.state.user_tx.subscribe();
+        let stream = relay(
+            &self.state,
+            events,
+            None,
+            move |update: Option<UserEvent>| {
+                let forwarded = match update {
+                    Some(update) if update.trader_id == trader_id => {
+                        Ok(Some(order_event(update.event)))
+                    }
+                    Some(_) => Ok(None),
+                    None => Err(lagged()),
+                };
+                async move { forwarded }
+            },
+        );
+        Ok(Response::new(stream))
+    }
+}
+
+/// Serve the `dex.v1.Exchange` service on `listener` until the server drains.
+pub async fn serve(listener: TcpListener, state: ApiState) -> Result<(), tonic::transport::Error> {
+    let shutdown = state.shutdown.clone();
+    Server::builder()
+        .add_service(ExchangeServer::new(ExchangeService::new(state)))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), async move {
+            shutdown.draining().await
+        })
+        .await
+}
+
+const REPLICA_MESSAGE: &str =
+    "this instance serves market data only; send orders to the matching instance";
+
+fn bearer<T>(request: &Request<T>) -> Option<&str> {
+    request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+}
+
+fn peer<T>(request: &Request<T>) -> Option<IpAddr> {
+    request.remote_addr().map(|addr| addr.ip())
+}
+
+/// Forward `events` to a streaming call, after `first`, until the client goes
+/// away or the server drains. `forward` picks and converts the events meant
+/// for the call; it is passed `None` when the call fell behind the broadcast
+/// and events were dropped, and an error it returns ends the call.
+fn relay<E, T, F, Fut>(
+    state: &ApiState,
+    mut events: broadcast::Receiver<E>,
+    first: Option<T>,
+    mut forward: F,
+) -> ResponseStream<T>
+where
+    E: Clone + Send + 'static,
+    T: Send + 'static,
+    F: FnMut(Option<E>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Option<T>, Status>> + Send,
+{
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    let state = state.clone();
+    tokio::spawn(async move {
+        let _open = state.shutdown.track();
+        if let Some(first) = first {
+            if tx.send(Ok(first)).await.is_err() {
+                return;
+            }
+        }
+        loop {
+            let event = tokio::select! {
+                _ = state.shutdown.draining() => {
+                    let _ = tx.send(Err(Status::unavailable("the server is shutting down"))).await;
+                    break;
+                }
+                _ = tx.closed() => break,
+                event = events.recv() => match event {
+                    Ok(event) => Some(event),
+                    Err(broadcast::error::RecvError::Lagged(_)) => None,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            let message = match forward(event).await {
+                Ok(Some(message)) => Ok(message),
+                Ok(None) => continue,
+                Err(status) => Err(status),
+            };
+            let ended = message.is_err();
+            if tx.send(message).await.is_err() || ended {
+                break;
+            }
+        }
+    });
+    Box::pin(ReceiverStream::new(rx))
+}
+
+fn lagged() -> Status {
+    Status::data_loss("events were dropped; call again and resync over REST")
+}
+
+/// The status a refused order is reported with, following the REST codes.
+fn submit_status(err: SubmitError) -> Status {
+    match err {
+        SubmitError::Degraded => {
+            Status::unavailable("order entry is suspended while storage is unavailable")
+        }
+        SubmitError::ShuttingDown => {
+            Status::unavailable("the server is shutting down and no longer accepts orders")
+        }
+        SubmitError::Replica => Status::unavailable(REPLICA_MESSAGE),
+        SubmitError::Halted => Status::failed_precondition("trading in this pair is halted"),
+        SubmitError::Clock => Status::internal("internal error"),
+        SubmitError::Storage(_) => Status::unavailable("failed to persist order"),
+        SubmitError::Rejected(err) => Status::failed_precondition(err.to_string()),
+        SubmitError::MarginLimit(breach) => {
+            Status::failed_precondition(margin_limit_message(&breach))
+        }
+        SubmitError::Unfunded(shortfall) => {
+            Status::failed_precondition(insufficient_funds_message(&shortfall))
+        }
+        SubmitError::TradeWrite(_, _) => Status::unavailable("failed to persist trade"),
+    }
+}
+
+fn side(label: &str) -> i32 {
+    match label {
+        "buy" => v1::Side::Buy,
+        "sell" => v1::Side::Sell,
+        _ => v1::Side::Unspecified,
+    }
+    .into()
+}
+
+fn liquidity(liquidity: Liquidity) -> i32 {
+    match liquidity {
+        Liquidity::Maker => v1::Liquidity::Maker,
+        Liquidity::Taker => v1::Liquidity::Taker,
+    }
+    .into()
+}
+
+fn trade_message(trade: &Trade) -> v1::Trade {
+    v1::Trade {
+        id: trade.id,
+        maker_order_id: trade.maker_order_id,
+        taker_order_id: trade.taker_order_id,
+        price: trade.price,
+        quantity: trade.quantity,
+        timestamp: trade.timestamp,
+    }
+}
+
+fn depth_message(snapshot: DepthSnapshot) -> v1::DepthSnapshot {
+    let levels = |levels: Vec<crate::market_data::DepthLevel>| {
+        levels
+            .into_iter()
+            .map(|level| v1::DepthLevel {
+                price: level.price,
+                quantity: level.quantity,
+            })
+            .collect()
+    };
+    v1::DepthSnapshot {
+        bids: levels(snapshot.bids),
+        asks: levels(snapshot.asks),
+        best_bid: snapshot.best_bid,
+        best_ask: snapshot.best_ask,
+        timestamp: snapshot.timestamp,
+    }
+}
+
+fn public_trade(trade: MarketTrade) -> v1::PublicTrade {
+    let kind = match trade.kind {
+        TradeEventKind::Trade => v1::TradeEventKind::Trade,
+        TradeEventKind::TradeBust => v1::TradeEventKind::TradeBust,
+        TradeEventKind::TradeCorrection => v1::TradeEventKind::TradeCorrection,
+    };
+    let venue = match trade.trade.venue {
+        Venue::Orderbook => v1::Venue::Orderbook,
+        Venue::Amm => v1::Venue::Amm,
+    };
+    v1::PublicTrade {
+        pair: trade.pair,
+        id: trade.trade.id,
+        price: trade.trade.price,
+        quantity: trade.trade.quantity,
+        timestamp: trade.trade.timestamp,
+        side: side(trade.trade.side),
+        is_buyer_maker: trade.trade.is_buyer_maker,
+        kind: kind.into(),
+        venue: venue.into(),
+    }
+}
+
+fn order_event(event: OrderEvent) -> v1::OrderEvent {
+    use v1::order_event::Event;
+    let event = match event {
+        OrderEvent::OrderUpdate {
+            order_id,
+            status,
+            pair,
+            side: label,
+            price,
+            quantity,
+            filled_quantity,
+            remaining_quantity,
+            timestamp,
+        } => {
+            let status = match status {
+                OrderStatus::Accepted => v1::OrderStatus::Accepted,
+                OrderStatus::PartiallyFilled => v1::OrderStatus::PartiallyFilled,
+                OrderStatus::Filled => v1::OrderStatus::Filled,
+                OrderStatus::Cancelled => v1::OrderStatus::Cancelled,
+            };
+            Event::OrderUpdate(v1::OrderUpdate {
+                order_id,
+                status: status.into(),
+                pair,
+                side: side(label),
+                price,
+                quantity,
+                filled_quantity,
+                remaining_quantity,
+                timestamp,
+            })
+        }
+        OrderEvent::Fill {
+            order_id,
+            trade_id,
+            pair,
+            side: label,
+            price,
+            quantity,
+            liquidity: role,
+            timestamp,
+        } => Event::Fill(v1::Fill {
+            order_id,
+            trade_id,
+            pair,
+            side: side(label),
+            price,
+            quantity,
+            liquidity: liquidity(role),
+            timestamp,
+        }),
+        OrderEvent::TradeAdjusted {
+            order_id,
+            trade_id,
+            pair,
+            side: label,
+            adjustment,
+            previous_price,
+            price,
+            quantity,
+            liquidity: role,
+            reason,
+            timestamp,
+        } => Event::TradeAdjusted(v1::TradeAdjusted {
+            order_id,
+            trade_id,
+            pair,
+            side: side(label),
+            adjustment: adjustment.to_string(),
+            previous_price,
+            price,
+            quantity,
+            liquidity: liquidity(role),
+            reason,
+            timestamp,
+        }),
+    };
+    v1::OrderEvent { event: Some(event) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{bearer_token, test_state_with_memory, MemoryStorage};
+    use dex_grpc::v1::exchange_client::ExchangeClient;
+    use tonic::{transport::Channel, Code};
+
+    async fn connect(state: ApiState) -> ExchangeClient<Channel> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, state));
+        ExchangeClient::connect(format!("http://{}", addr))
+            .await
+            .unwrap()
+    }
+
+    fn signed<T>(message: T, trader: &str) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert("authorization", bearer_token(trader, 300).parse().unwrap());
+        request
+    }
+
+    fn limit(trader: &str, side: v1::Side, quantity: u64) -> v1::PlaceOrderRequest {
+        v1::PlaceOrderRequest {
+            trader_id: trader.to_string(),
+            base_token: "ETH".to_string(),
+            quote_token: "USDC".to_string(),
+            side: side.into(),
+            order_type: v1::OrderType::Limit.into(),
+            price: Some(1000),
+            quantity,
+        }
+    }
+
+    #[tokio::test]
+    async fn orders_depth_and_streams_round_trip() {
+        let mut client = connect(test_state_with_memory(Arc::new(MemoryStorage::default()))).await;
+        let mut trades = client
+            .stream_trades(v1::StreamTradesRequest {
+                pair: "ETH-USDC".to_string(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let mut events = client
+            .stream_order_events(signed(v1::StreamOrderEventsRequest {}, "bob"))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let resting = client
+            .place_order(signed(limit("bob", v1::Side::Sell, 5), "bob"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(resting.trades.is_empty());
+        let depth = client
+            .get_depth(v1::DepthRequest {
+                pair: Some("ETH-USDC".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            depth.asks,
+            vec![v1::DepthLevel {
+                price: 1000,
+                quantity: 5
+            }]
+        );
+        assert_eq!(depth.best_ask, Some(1000));
+
+        let taker = client
+            .place_order(signed(limit("alice", v1::Side::Buy, 2), "alice"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(taker.trades.len(), 1);
+        assert_eq!(taker.trades[0].maker_order_id, resting.order_id);
+        assert_eq!(taker.trades[0].quantity, 2);
+        let trade = trades.message().await.unwrap().unwrap();
+        assert_eq!(trade.pair, "ETH-USDC");
+        assert_eq!(trade.id, taker.trades[0].id);
+        assert_eq!(trade.side(), v1::Side::Buy);
+        assert_eq!(trade.kind(), v1::TradeEventKind::Trade);
+
+        let cancelled = client
+            .cancel_order(signed(
+                v1::CancelOrderRequest {
+                    order_id: resting.order_id,
+                },
+                "bob",
+            ))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(cancelled.cancelled_quantity, 3);
+
+        // Bob hears of his order being accepted, filled and cancelled, and
+        // nothing of Alice's.
+        let mut statuses = Vec::new();
+        let mut fills = Vec::new();
+        while statuses.last() != Some(&v1::OrderStatus::Cancelled) {
+            match events.message().await.unwrap().unwrap().event.unwrap() {
+                v1::order_event::Event::OrderUpdate(update) => {
+                    assert_eq!(update.order_id, resting.order_id);
+                    statuses.push(update.status());
+                }
+                v1::order_event::Event::Fill(fill) => fills.push(fill),
+                v1::order_event::Event::TradeAdjusted(_) => panic!("no trade was adjusted"),
+            }
+        }
+        assert_eq!(statuses.first(), Some(&v1::OrderStatus::Accepted));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].liquidity(), v1::Liquidity::Maker);
+        assert_eq!(fills[0].quantity, 2);
+    }
+
+    #[tokio::test]
+    async fn order_entry_requires_the_trader_s_token() {
+        let mut client = connect(test_state_with_memory(Arc::new(MemoryStorage::default()))).await;
+        let missing = client
+            .place_order(limit("bob", v1::Side::Sell, 5))
+            .await
+            .unwrap_err();
+        assert_eq!(missing.code(), Code::Unauthenticated);
+        let other_trader = client
+            .place_order(signed(limit("bob", v1::Side::Sell, 5), "alice"))
+            .await
+            .unwrap_err();
+        assert_eq!(other_trader.code(), Code::PermissionDenied);
+        let invalid = client
+            .place_order(signed(
+                v1::PlaceOrderRequest {
+                    side: v1::Side::Unspecified.into(),
+                    ..limit("bob", v1::Side::Sell, 5)
+                },
+                "bob",
+            ))
+            .await
+            .unwrap_err();
+        assert_eq!(invalid.code(), Code::InvalidArgument);
+    }
+}
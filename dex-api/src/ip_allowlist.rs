@@ -0,0 +1,284 @@
+//! Network allowlists that bind credentials to the addresses they may be
+//! used from.
+//!
+//! `IP_ALLOWLISTS` gives a trader's allowlist, which covers every credential
+//! issued to them: bearer tokens, API keys, stream tokens and FIX logons. An
+//! API key can also carry an allowlist of its own, stored with the key, and
+//! requests signed with it must then come from an address in both. An empty
+//! allowlist allows any address; a non-empty one refuses requests whose peer
+//! address is unknown.
+//!
+//! Behind a proxy the TCP peer is the proxy. Peers in `TRUSTED_PROXIES` may
+//! name the client in `X-Forwarded-For`; see [`client_ip`].
+
+use crate::{tls, ApiState};
+use std::{
+    convert::Infallible,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+use thiserror::Error;
+use warp::{http::HeaderMap, reject::Reject, Filter};
+
+/// A block of addresses such as `10.0.0.0/8` or `2001:db8::/32`. A bare
+/// address is a block of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("invalid CIDR block '{0}', expected an address or address/prefix")]
+pub struct InvalidCidr(pub String);
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                mask(u32::from(ip).into(), 32, self.prefix) == u32::from(network).into()
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                mask(u128::from(ip), 128, self.prefix) == u128::from(network)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// `bits` with everything after the first `prefix` of `width` cleared.
+fn mask(bits: u128, width: u8, prefix: u8) -> u128 {
+    match width - prefix {
+        0 => bits,
+        host if host >= 128 => 0,
+        host => bits >> host << host,
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidCidr(raw.to_string());
+        let (address, prefix) = match raw.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (raw.trim(), None),
+        };
+        let address = address
+            .parse::<IpAddr>()
+            .map_err(|_| invalid())?
+            .to_canonical();
+        let width = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| invalid())?,
+            None => width,
+        };
+        if prefix > width {
+            return Err(invalid());
+        }
+        // Host bits are dropped, so `10.1.2.3/8` is `10.0.0.0/8`.
+        let network = match address {
+            IpAddr::V4(v4) => IpAddr::V4((mask(u32::from(v4).into(), 32, prefix) as u32).into()),
+            IpAddr::V6(v6) => IpAddr::V6(mask(u128::from(v6), 128, prefix).into()),
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// The blocks a credential may be used from; empty allows any address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Allowlist(Vec<Cidr>);
+
+impl Allowlist {
+    pub fn parse<'a>(blocks: impl IntoIterator<Item = &'a str>) -> Result<Self, InvalidCidr> {
+        blocks
+            .into_iter()
+            .filter(|block| !block.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn permits(&self, peer: Option<IpAddr>) -> bool {
+        self.is_empty() || peer.is_some_and(|ip| self.0.iter().any(|block| block.contains(ip)))
+    }
+
+    /// The blocks in canonical form, as stored.
+    pub fn to_strings(&self) -> Vec<String> {
+        self.0.iter().map(ToString::to_string).collect()
+    }
+}
+
+/// Proxies whose `X-Forwarded-For` is believed; empty believes none.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<Cidr>);
+
+impl TrustedProxies {
+    pub fn parse<'a>(blocks: impl IntoIterator<Item = &'a str>) -> Result<Self, InvalidCidr> {
+        Allowlist::parse(blocks).map(|allowlist| Self(allowlist.0))
+    }
+
+    fn trusts(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|block| block.contains(ip))
+    }
+
+    /// The client behind `peer`. `forwarded` holds the `X-Forwarded-For`
+    /// values in the order received; each proxy appends the address it got
+    /// the request from, so they are read from the right, for as long as the
+    /// hop that appended them is trusted. Anything left of the first
+    /// untrusted hop could have been written by the client.
+    pub fn client(&self, peer: Option<IpAddr>, forwarded: &[&str]) -> Option<IpAddr> {
+        let mut client = peer?.to_canonical();
+        for hop in forwarded.iter().flat_map(|value| value.split(',')).rev() {
+            if !self.trusts(client) {
+                break;
+            }
+            let hop = hop.trim();
+            match hop
+                .parse::<IpAddr>()
+                .or_else(|_| hop.parse::<SocketAddr>().map(|addr| addr.ip()))
+            {
+                Ok(ip) => client = ip.to_canonical(),
+                // A garbled entry names no one; the proxy that passed it on
+                // is the last address known.
+                Err(_) => break,
+            }
+        }
+        Some(client)
+    }
+}
+
+/// The client's address: the TCP peer, or, when that is a trusted proxy, the
+/// address it forwarded. Allowlists, the audit log and per-address rate
+/// limits all use this.
+pub fn client_ip(
+    state: ApiState,
+) -> impl Filter<Extract = (Option<IpAddr>,), Error = Infallible> + Clone {
+    tls::remote_addr().and(warp::header::headers_cloned()).map(
+        move |peer: Option<SocketAddr>, headers: HeaderMap| {
+            let forwarded: Vec<&str> = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .collect();
+            state
+                .config
+                .trusted_proxies
+                .client(peer.map(|peer| peer.ip()), &forwarded)
+        },
+    )
+}
+
+/// A credential used from outside its allowlist.
+#[derive(Debug)]
+pub struct IpRejection;
+
+impl Reject for IpRejection {}
+
+/// Refuse credentials of `subject` used from outside their allowlist.
+pub fn check(state: &ApiState, subject: &str, peer: Option<IpAddr>) -> Result<(), IpRejection> {
+    match state.config.ip_allowlists.get(subject) {
+        Some(allowlist) if !allowlist.permits(peer) => {
+            tracing::warn!(
+                target: "security",
+                subject,
+                peer = peer.map(tracing::field::display),
+                "refused credential used from an address outside its allowlist"
+            );
+            Err(IpRejection)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_match_addresses_by_prefix() {
+        let block: Cidr = "10.1.2.3/8".parse().unwrap();
+        assert_eq!(block.to_string(), "10.0.0.0/8");
+        assert!(block.contains("10.200.0.1".parse().unwrap()));
+        assert!(!block.contains("11.0.0.1".parse().unwrap()));
+        // IPv4-mapped IPv6 peers are matched as IPv4.
+        assert!(block.contains("::ffff:10.0.0.1".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:1::5".parse().unwrap()));
+        assert!(!v6.contains("10.0.0.1".parse().unwrap()));
+        assert_eq!(
+            "192.0.2.7".parse::<Cidr>().unwrap().to_string(),
+            "192.0.2.7/32"
+        );
+        assert_eq!("::/0".parse::<Cidr>().unwrap().to_string(), "::/0");
+        assert!("0.0.0.0/0"
+            .parse::<Cidr>()
+            .unwrap()
+            .contains("203.0.113.9".parse().unwrap()));
+
+        for invalid in ["10.0.0.0/33", "example.com", "10.0.0.0/", "::/129"] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn empty_allowlists_allow_any_address() {
+        let open = Allowlist::default();
+        assert!(open.permits(None));
+
+        let office = Allowlist::parse(["192.0.2.0/24", "2001:db8::/32"]).unwrap();
+        assert!(office.permits(Some("192.0.2.10".parse().unwrap())));
+        assert!(!office.permits(Some("198.51.100.1".parse().unwrap())));
+        // An unknown peer cannot be shown to be inside the list.
+        assert!(!office.permits(None));
+        assert_eq!(office.to_strings(), ["192.0.2.0/24", "2001:db8::/32"]);
+    }
+
+    #[test]
+    fn forwarded_addresses_are_believed_only_from_trusted_proxies() {
+        let ip = |raw: &str| Some(raw.parse::<IpAddr>().unwrap());
+        let proxies = TrustedProxies::parse(["10.0.0.0/8"]).unwrap();
+
+        // Untrusted peers are the client, whatever they claim.
+        assert_eq!(
+            proxies.client(ip("198.51.100.9"), &["203.0.113.5"]),
+            ip("198.51.100.9")
+        );
+        assert_eq!(
+            TrustedProxies::default().client(ip("10.0.0.1"), &["203.0.113.5"]),
+            ip("10.0.0.1")
+        );
+        // A trusted proxy names the client.
+        assert_eq!(
+            proxies.client(ip("10.0.0.1"), &["203.0.113.5"]),
+            ip("203.0.113.5")
+        );
+        // Hops are read from the right, through every trusted proxy, and
+        // entries left of the first untrusted one are ignored.
+        assert_eq!(
+            proxies.client(ip("10.0.0.1"), &["192.0.2.1, 203.0.113.5", "10.0.0.2"]),
+            ip("203.0.113.5")
+        );
+        assert_eq!(
+            proxies.client(ip("::ffff:10.0.0.1"), &["[2001:db8::5]:443"]),
+            ip("2001:db8::5")
+        );
+        // With nothing forwarded, or nothing legible, the proxy is all that
+        // is known.
+        assert_eq!(proxies.client(ip("10.0.0.1"), &[]), ip("10.0.0.1"));
+        assert_eq!(proxies.client(ip("10.0.0.1"), &["unknown"]), ip("10.0.0.1"));
+        assert_eq!(proxies.client(None, &["203.0.113.5"]), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! Active/standby failover between instances sharing a database.
+//!
+//! With `LEADER_ELECTION_KEY` set, instances contend for a Postgres advisory
+//! lock under that key. The holder leads: it matches orders and runs the
+//! background jobs that write, such as the Kafka relay, webhook delivery and
+//! the market feed publisher. The others stand by: they serve market data
+//! and reject orders as replicas do, following the market feed when one is
+//! configured, and keep trying for the lock. When the leader exits or loses
+//! its database connection, Postgres releases the lock and a standby takes
+//! over, reloading the book, ID counters and receipt chain from the database
+//! before it accepts orders. A leader that can no longer confirm it holds the
+//! lock must stop at once, so that two instances never match together.
+
+use crate::{book_snapshot, matching_stats::MatchingStats, ApiState, OrderTracker};
+use dex_db::{leader::LeaderLease, DatabaseError, DatabaseManager};
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::sync::watch;
+
+/// Lock the instances contend for, and how often it is checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElectionConfig {
+    pub key: i64,
+    /// Pause between attempts while standing by, and between checks that
+    /// the lock is still held while leading.
+    pub check_interval: Duration,
+}
+
+/// Whether this instance currently leads.
+pub struct Leadership {
+    leader: watch::Sender<bool>,
+}
+
+impl Default for Leadership {
+    /// Leading, as every matching instance does without elections.
+    fn default() -> Self {
+        Self {
+            leader: watch::Sender::new(true),
+        }
+    }
+}
+
+impl Leadership {
+    /// Standing by until an election is won.
+    pub fn standby() -> Self {
+        Self {
+            leader: watch::Sender::new(false),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.leader.borrow()
+    }
+
+    /// Start taking orders.
+    pub fn promote(&self) {
+        self.leader.send_replace(true);
+    }
+}
+
+/// Try for the lock until it is ours.
+pub async fn campaign(database: &DatabaseManager, config: ElectionConfig) -> LeaderLease {
+    let mut failing = false;
+    loop {
+        match database.try_lead(config.key).await {
+            Ok(Some(lease)) => return lease,
+            Ok(None) => failing = false,
+            Err(err) => {
+                if !failing {
+                    tracing::warn!(error = %err, "leader election attempt failed");
+                    failing = true;
+                }
+            }
+        }
+        tokio::time::sleep(config.check_interval).await;
+    }
+}
+
+/// Returns once the lease can no longer be confirmed.
+pub async fn hold(lease: &LeaderLease, config: ElectionConfig) {
+    let mut checks = tokio::time::interval(config.check_interval);
+    loop {
+        checks.tick().await;
+        if !lease.held().await {
+            return;
+        }
+    }
+}
+
+/// Bring a standby's state up to date with what the previous leader
+/// stored, so it can take orders. The book, swap IDs, receipt chain and
+/// trade counters are reloaded from the database; order and trade IDs are
+/// handed out by the database already.
+pub async fn take_over(state: &ApiState) -> Result<(), DatabaseError> {
+    let warm = book_snapshot::warm_start(
+        None,
+        state.orders.as_ref(),
+        state.trades.as_ref(),
+        &state.config.matching,
+    )
+    .await?;
+    let last_swap_id = state.swap_repo.last_swap_id().await?;
+    let latest_receipt = state.receipt_repo.latest_receipt().await?;
+    let counters = state.counter_repo.load_counters().await?;
+
+    let resting = warm.orderbook.resting_orders();
+    *state.orderbook.write().await = warm.orderbook;
+    state
+        .swap_id_counter
+        .store(last_swap_id + 1, Ordering::Relaxed);
+    state.sequencer.resume(latest_receipt.as_ref());
+
+    let mut order_tracker = OrderTracker::new();
+    for order in &resting {
+        order_tracker.accept(order);
+    }
+    *state.order_tracker.write().await = order_tracker;
+    let mut matching_stats = MatchingStats::default();
+    matching_stats.seed(&resting);
+    *state.matching_stats.write().await = matching_stats;
+    state
+        .trade_tape
+        .write()
+        .await
+        .counters_mut()
+        .restore(counters);
+    tracing::info!(orders = resting.len(), "took over the book");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes,
+        test_support::{bearer_token, place, test_state_with_memory, MemoryStorage},
+    };
+    use dex_core::types::{Order, OrderSide, OrderType};
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+
+    #[tokio::test]
+    async fn standbys_refuse_orders_until_they_take_over() {
+        let storage = Arc::new(MemoryStorage::default());
+        let mut state = test_state_with_memory(storage.clone());
+        state.leadership = Arc::new(Leadership::standby());
+        let filter = routes(state.clone());
+
+        let response = warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token("bob", 300))
+            .json(&serde_json::json!({
+                "trader_id": "bob",
+                "base_token": "ETH",
+                "quote_token": "USDC",
+                "side": "sell",
+                "order_type": "limit",
+                "price": 1000,
+                "quantity": 5
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["code"], "read_only_replica");
+
+        // An order the previous leader stored.
+        storage.orders.lock().unwrap().insert(
+            41,
+            Order {
+                id: 41,
+                trader_id: "carol".parse().unwrap(),
+                pair: "ETH-USDC".parse().unwrap(),
+                side: OrderSide::Sell,
+                order_type: OrderType::Limit,
+                price: Some(1010),
+                quantity: 3,
+                timestamp: 1_700_000_000,
+            },
+        );
+        take_over(&state).await.unwrap();
+        state.leadership.promote();
+
+        assert!(state.orderbook.read().await.get_order(41).is_some());
+        let placed = place(&filter, "bob", "sell", 5).await;
+        assert_eq!(placed["order_id"], 42);
+    }
+}
@@ -0,0 +1,568 @@
+//! Double-entry bookkeeping of fills, swaps, liquidity, fees and settlement.
+//!
+//! Every event that moves value is booked in `ledger_repo` as a balanced
+//! journal entry (see [`dex_core::ledger`]), referenced by the event it came
+//! from so that booking it twice is a no-op. Fills are stored in the same
+//! transaction as their trades; other events book after the fact and fail
+//! the request when the write fails. Administrators export the journal
+//! through `GET /admin/ledger/entries` and reconcile it through
+//! `GET /admin/ledger/balances`, which checks that every token sums to zero,
+//! that each pool's account matches its reserves and that no wrapped asset
+//! outstrips its custody wallet.
+
+use crate::{
+    admin_only, amm::AmmPools, auth::Claims, error_reply, rate_limit::RouteClass, rate_limited,
+    settings::FeeRates, storage_error_reply, wrapped, ApiState, ErrorResponse,
+};
+use dex_core::{
+    ledger::{Account, AccountBalance, EntryKind, JournalEntry},
+    types::{Order, OrderId, OrderSide, TokenId, Trade, TraderId, TradingPair},
+    wrapped_assets::Backing,
+};
+use dex_db::{DatabaseError, LedgerFilter, MessagingPenalty, NettingSet, TradeAdjustment};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use utoipa::{IntoParams, ToSchema};
+use warp::{http::StatusCode, Filter};
+
+/// Default page size of `GET /admin/ledger/entries`.
+pub const DEFAULT_PAGE: u32 = 100;
+
+/// Token messaging penalties are booked in.
+pub const PENALTY_TOKEN: &str = "USD";
+
+/// Store `entry`. An entry that does not balance is refused as a data
+/// integrity error.
+pub async fn book(state: &ApiState, entry: JournalEntry) -> Result<(), DatabaseError> {
+    if let Err(err) = entry.validate() {
+        tracing::error!(
+            kind = entry.kind.as_str(),
+            reference = %entry.reference,
+            error = %err,
+            "refused to book ledger entry"
+        );
+        return Err(DatabaseError::DataIntegrityError);
+    }
+    state.ledger_repo.save_entry(&entry).await?;
+    Ok(())
+}
+
+/// The buyer and seller of a trade between the order of `maker`, a trader,
+/// and `taker`.
+fn buyer_and_seller<'a>(maker: &'a str, taker: &'a Order) -> (&'a str, &'a str) {
+    match taker.side {
+        OrderSide::Buy => (taker.trader_id.as_str(), maker),
+        OrderSide::Sell => (maker, taker.trader_id.as_str()),
+    }
+}
+
+/// The entry for an order book trade between `maker`, the trader whose
+/// order rested, and `taker`.
+pub fn fill(trade: &Trade, maker: &str, taker: &Order) -> JournalEntry {
+    let (buyer, seller) = buyer_and_seller(maker, taker);
+    JournalEntry::fill(trade, buyer, seller)
+}
+
+/// The entry for the fees on an order book trade: maker and taker each pay
+/// their rate of the notional, in the quote token, to the exchange. A
+/// buyer's fee is paid on top of the notional. A seller's is deducted from
+/// the proceeds [`fill`] credits it in the same transaction, so a seller
+/// needs no quote balance of its own and nets the notional less its fee.
+pub fn fill_fees(trade: &Trade, maker: &str, taker: &Order, rates: FeeRates) -> JournalEntry {
+    let notional = trade.notional().value();
+    JournalEntry::new(
+        EntryKind::Fee,
+        format!("trade:{}", trade.id),
+        trade.timestamp,
+    )
+    .transfer(
+        Account::Trader(maker.to_string()),
+        Account::Fees,
+        &trade.quote_token,
+        FeeRates::fee(rates.maker_bps, notional),
+    )
+    .transfer(
+        Account::Trader(taker.trader_id.as_str().to_string()),
+        Account::Fees,
+        &trade.quote_token,
+        FeeRates::fee(rates.taker_bps, notional),
+    )
+}
+
+/// The entries booking the trades `taker` executed, with their fees under
+/// the schedule in force. `makers` names the trader of each maker order, as
+/// the book knew them when it matched; a maker missing from it is looked up
+/// in storage, and one missing there too is a data integrity error.
+pub async fn fill_entries(
+    state: &ApiState,
+    taker: &Order,
+    trades: &[Trade],
+    makers: &HashMap<OrderId, TraderId>,
+) -> Result<Vec<JournalEntry>, DatabaseError> {
+    let rates = state.settings.borrow().fees.rates(&taker.pair);
+    let mut entries = Vec::with_capacity(2 * trades.len());
+    for trade in trades {
+        let maker = match makers.get(&trade.maker_order_id) {
+            Some(maker) => maker.clone(),
+            None => match state.orders.load_order(trade.maker_order_id).await? {
+                Some(maker) => maker.trader_id,
+                None => {
+                    tracing::error!(
+                        trade_id = trade.id,
+                        maker_order_id = trade.maker_order_id,
+                        "cannot book trade: maker order is not stored"
+                    );
+                    return Err(DatabaseError::DataIntegrityError);
+                }
+            },
+        };
+        entries.push(fill(trade, maker.as_str(), taker));
+        let fees = fill_fees(trade, maker.as_str(), taker, rates);
+        if !fees.postings.is_empty() {
+            entries.push(fees);
+        }
+    }
+    if let Some(err) = entries.iter().find_map(|entry| entry.validate().err()) {
+        tracing::error!(taker_order_id = taker.id, error = %err, "refused to book fills");
+        return Err(DatabaseError::DataIntegrityError);
+    }
+    Ok(entries)
+}
+
+/// The entry undoing or re-pricing a fill, for the trade's `sequence`th
+/// adjustment. `trade` carries the price before the adjustment. A price
+/// adjustment moves the difference in quote between buyer and seller.
+pub fn correction(
+    trade: &Trade,
+    maker: &Order,
+    taker: &Order,
+    adjustment: &TradeAdjustment,
+    sequence: usize,
+) -> JournalEntry {
+    let reference = format!("trade:{}:{}", trade.id, sequence);
+    let booked = fill(trade, maker.trader_id.as_str(), taker);
+    let Some(new_price) = adjustment.new_price else {
+        return booked.reversal(EntryKind::Correction, reference, adjustment.adjusted_at);
+    };
+    let (buyer, seller) = buyer_and_seller(maker.trader_id.as_str(), taker);
+    let (buyer, seller) = (
+        Account::Trader(buyer.to_string()),
+        Account::Trader(seller.to_string()),
+    );
+    let quantity = u128::from(trade.quantity);
+    let (from, to, difference) = if new_price > trade.price {
+        (buyer, seller, new_price - trade.price)
+    } else {
+        (seller, buyer, trade.price - new_price)
+    };
+    JournalEntry::new(EntryKind::Correction, reference, adjustment.adjusted_at).transfer(
+        from,
+        to,
+        &trade.quote_token,
+        u128::from(difference) * quantity,
+    )
+}
+
+/// The entry for a messaging penalty: the fee moves from the trader to the
+/// exchange.
+pub fn penalty(penalty: &MessagingPenalty, timestamp: u64) -> JournalEntry {
+    let token = PENALTY_TOKEN.parse().expect("valid token symbol");
+    JournalEntry::new(
+        EntryKind::Fee,
+        format!("penalty:{}:{}", penalty.subject, penalty.day),
+        timestamp,
+    )
+    .transfer(
+        Account::Trader(penalty.subject.clone()),
+        Account::Fees,
+        &token,
+        u128::from(penalty.fee),
+    )
+}
+
+/// The entry paying out a netting set. Each transfer squares what its
+/// sender owed and its recipient was owed, bringing both accounts back
+/// towards zero.
+pub fn settlement(set: &NettingSet) -> Option<JournalEntry> {
+    let mut entry = JournalEntry::new(
+        EntryKind::Settlement,
+        format!("netting:{}", set.id),
+        set.created_at,
+    );
+    for transfer in &set.transfers {
+        let token = transfer.token.parse().ok()?;
+        entry = entry.transfer(
+            Account::Trader(transfer.to_trader.clone()),
+            Account::Trader(transfer.from_trader.clone()),
+            &token,
+            transfer.amount,
+        );
+    }
+    Some(entry)
+}
+
+/// Query parameters of `GET /admin/ledger/entries`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LedgerQuery {
+    /// Cursor from `next_cursor`
+    pub after_id: Option<u64>,
+    /// Page size, 1-1000 (default 100)
+    pub limit: Option<u32>,
+    /// Only entries posting to `trader:<id>`, `pool:<BASE-QUOTE>`,
+    /// `bridge:<chain>` or `fees`
+    pub account: Option<String>,
+    /// `fill`, `swap`, `liquidity`, `fee`, `correction`, `settlement`,
+    /// `deposit`, `burn` or `withdrawal`
+    pub kind: Option<String>,
+}
+
+impl LedgerQuery {
+    /// The filter, or a message naming the parameter that did not parse.
+    pub fn into_filter(self) -> Result<LedgerFilter, String> {
+        let nonempty = |value: Option<String>| {
+            value
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        Ok(LedgerFilter {
+            after_id: self.after_id,
+            limit: self.limit.unwrap_or(DEFAULT_PAGE),
+            account: nonempty(self.account)
+                .map(|account| account.parse())
+                .transpose()
+                .map_err(|_| "account must be trader:<id>, pool:<BASE-QUOTE> or fees")?,
+            kind: nonempty(self.kind)
+                .map(|kind| kind.parse())
+                .transpose()
+                .map_err(|_| {
+                    "kind must be fill, swap, liquidity, fee, correction, settlement, deposit, burn or withdrawal"
+                })?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = LedgerEntries)]
+pub struct LedgerEntriesResponse {
+    pub entries: Vec<JournalEntry>,
+    /// Pass as `after_id` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<u64>,
+}
+
+/// A pool's ledger account against its actual reserves, in one token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PoolReconciliation {
+    pub pool: String,
+    pub token: String,
+    pub ledger: i128,
+    pub reserves: u128,
+    /// Reserves minus ledger balance; zero when every change was booked.
+    pub difference: i128,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = LedgerBalances)]
+pub struct LedgerBalancesResponse {
+    /// Whether every token sums to zero, every pool matches its reserves and
+    /// every wrapped asset is backed.
+    pub reconciled: bool,
+    pub balances: Vec<AccountBalance>,
+    /// Sum of all balances per token.
+    pub trial_balance: BTreeMap<String, i128>,
+    pub pools: Vec<PoolReconciliation>,
+    /// Supply of each wrapped asset against its custody balance.
+    pub wrapped: Vec<Backing>,
+}
+
+/// Compare each pool's reserves with its ledger account.
+pub fn reconcile_pools(balances: &[AccountBalance], pools: &AmmPools) -> Vec<PoolReconciliation> {
+    let booked = |pair: &TradingPair, token: &TokenId| {
+        balances
+            .iter()
+            .find(|balance| {
+                balance.token == *token && balance.account == Account::Pool(pair.clone())
+            })
+            .map_or(0, |balance| balance.balance)
+    };
+    pools
+        .iter()
+        .flat_map(|pool| {
+            let pair = pool.pair();
+            let (base, quote) = pool.reserves();
+            [(pair.base(), base), (pair.quote(), quote)].map(|(token, reserves)| {
+                let ledger = booked(pair, token);
+                PoolReconciliation {
+                    pool: pair.to_string(),
+                    token: token.to_string(),
+                    ledger,
+                    reserves,
+                    difference: reserves as i128 - ledger,
+                }
+            })
+        })
+        .collect()
+}
+
+/// Balances, trial balance and pool reconciliation from stored balances,
+/// with the backing of wrapped assets computed from them.
+pub fn balances_response(
+    balances: Vec<AccountBalance>,
+    pools: &AmmPools,
+    wrapped: Vec<Backing>,
+) -> LedgerBalancesResponse {
+    let trial_balance: BTreeMap<String, i128> =
+        dex_core::ledger::trial_balance(balances.iter().map(|b| (&b.token, b.balance)))
+            .into_iter()
+            .map(|(token, total)| (token.to_string(), total))
+            .collect();
+    let pools = reconcile_pools(&balances, pools);
+    LedgerBalancesResponse {
+        reconciled: trial_balance.values().all(|total| *total == 0)
+            && pools.iter().all(|pool| pool.difference == 0)
+            && wrapped.iter().all(|backing| backing.backed),
+        balances,
+        trial_balance,
+        pools,
+        wrapped,
+    }
+}
+
+/// Ledger reads, e.g. GET /admin/ledger/balances
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let ledger = warp::path("admin").and(warp::path("ledger"));
+
+    let ledger_entries = ledger
+        .and(warp::path("entries"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(warp::query::<LedgerQuery>())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_ledger_entries)
+        .boxed();
+
+    let ledger_balances = ledger
+        .and(warp::path("balances"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_ledger_balances)
+        .boxed();
+
+    ledger_entries.or(ledger_balances)
+}
+
+/// Page through the ledger's journal entries, oldest first.
+#[utoipa::path(
+    get,
+    path = "/admin/ledger/entries",
+    summary = "Double-entry journal of fills, swaps, liquidity, fees and settlement, oldest first (administrators only)",
+    params(LedgerQuery),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "One page of the journal", body = LedgerEntriesResponse),
+        (status = 400, description = "Unknown account or kind", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_ledger_entries(
+    query: LedgerQuery,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let filter = match query.into_filter() {
+        Ok(filter) => filter,
+        Err(message) => {
+            return Ok(error_reply(
+                "invalid_query",
+                message,
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    match state.ledger_repo.query_ledger(&filter).await {
+        Ok(entries) => {
+            let next_cursor = if entries.len() as u32 == filter.page_size() {
+                entries.last().map(|entry| entry.id)
+            } else {
+                None
+            };
+            let response = LedgerEntriesResponse {
+                entries,
+                next_cursor,
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load ledger entries");
+            Ok(storage_error_reply(&err, "failed to load ledger entries"))
+        }
+    }
+}
+
+/// Every account's ledger balance, reconciled against the pools' reserves
+/// and the custody wallets backing wrapped assets.
+#[utoipa::path(
+    get,
+    path = "/admin/ledger/balances",
+    summary = "Ledger balances, trial balance and pool reconciliation (administrators only)",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Balances and reconciliation", body = LedgerBalancesResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_ledger_balances(
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let balances = match state.ledger_repo.ledger_balances().await {
+        Ok(balances) => balances,
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load ledger balances");
+            return Ok(storage_error_reply(&err, "failed to load ledger balances"));
+        }
+    };
+    match wrapped::backing(&state, &balances).await {
+        Ok(backing) => {
+            let response = balances_response(balances, &*state.amm.read().await, backing);
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load custody balances");
+            Ok(storage_error_reply(&err, "failed to load custody balances"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::netting;
+    use dex_core::{ledger::Ledger, types::OrderType};
+    use dex_db::AdjustmentKind;
+
+    fn order(id: u64, trader: &str, side: OrderSide) -> Order {
+        Order {
+            id,
+            trader_id: trader.parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(100),
+            quantity: 5,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    fn trade() -> Trade {
+        Trade {
+            id: 9,
+            maker_order_id: 1,
+            taker_order_id: 2,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 100,
+            quantity: 5,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    fn adjustment(new_price: Option<u64>) -> TradeAdjustment {
+        TradeAdjustment {
+            trade_id: 9,
+            kind: match new_price {
+                Some(_) => AdjustmentKind::PriceAdjust,
+                None => AdjustmentKind::Bust,
+            },
+            previous_price: 100,
+            new_price,
+            reason: "fat finger".to_string(),
+            adjusted_by: "admin".to_string(),
+            adjusted_at: 1_700_000_010,
+        }
+    }
+
+    #[test]
+    fn settling_a_repriced_fill_squares_both_traders() {
+        let (maker, taker) = (
+            order(1, "alice", OrderSide::Sell),
+            order(2, "bob", OrderSide::Buy),
+        );
+        let trade = trade();
+        let mut ledger = Ledger::new();
+        ledger
+            .post(&fill(&trade, maker.trader_id.as_str(), &taker))
+            .unwrap();
+        ledger
+            .post(&correction(
+                &trade,
+                &maker,
+                &taker,
+                &adjustment(Some(90)),
+                1,
+            ))
+            .unwrap();
+        let usdc = "USDC".parse().unwrap();
+        let bob = Account::Trader("bob".to_string());
+        assert_eq!(ledger.balance(&bob, &usdc), -450);
+
+        let mut repriced = trade.clone();
+        repriced.price = 90;
+        let positions = netting::positions([netting::Fill {
+            trade: &repriced,
+            buyer: "bob",
+            seller: "alice",
+        }]);
+        let set = NettingSet {
+            id: 1,
+            created_at: 1_700_000_100,
+            created_by: "admin".to_string(),
+            after_trade_id: 0,
+            last_trade_id: 9,
+            trade_count: 1,
+            gross_transfers: 2,
+            unattributed_trade_ids: Vec::new(),
+            transfers: netting::transfers(&positions),
+            positions,
+        };
+        ledger.post(&settlement(&set).unwrap()).unwrap();
+        assert!(ledger.balances().is_empty());
+    }
+
+    #[test]
+    fn busts_reverse_the_fill() {
+        let (maker, taker) = (
+            order(1, "alice", OrderSide::Buy),
+            order(2, "bob", OrderSide::Sell),
+        );
+        let trade = trade();
+        let mut ledger = Ledger::new();
+        ledger
+            .post(&fill(&trade, maker.trader_id.as_str(), &taker))
+            .unwrap();
+        assert_eq!(
+            ledger.balance(&Account::Trader("alice".to_string()), &trade.base_token),
+            5
+        );
+        let bust = correction(&trade, &maker, &taker, &adjustment(None), 1);
+        assert_eq!(bust.kind, EntryKind::Correction);
+        ledger.post(&bust).unwrap();
+        assert!(ledger.balances().is_empty());
+    }
+}
@@ -0,0 +1,172 @@
+//! Brute-force protection for the sign-in endpoints.
+//!
+//! Failed attempts are counted per trader ID or wallet subject. Once an
+//! account reaches `threshold` consecutive failures it is locked for
+//! `base_seconds`, doubling with every further failure up to `max_seconds`;
+//! attempts during a lockout are refused without being checked and do not
+//! extend it. A successful sign-in clears the count. Every failure and
+//! refusal is logged as a structured security event.
+
+use std::{collections::HashMap, sync::Mutex};
+
+/// Accounts tracked before ones without an active lockout are dropped.
+const MAX_TRACKED_ACCOUNTS: usize = 4096;
+
+/// When failed sign-ins lock an account, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutConfig {
+    /// Consecutive failures before the first lockout; zero disables lockouts.
+    pub threshold: u32,
+    pub base_seconds: u64,
+    pub max_seconds: u64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 5,
+            base_seconds: 30,
+            max_seconds: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Failures {
+    count: u32,
+    /// Unix seconds.
+    locked_until: u64,
+}
+
+/// Failure counters of every account that recently failed to sign in.
+#[derive(Debug)]
+pub struct AuthLockout {
+    config: LockoutConfig,
+    accounts: Mutex<HashMap<String, Failures>>,
+}
+
+impl AuthLockout {
+    pub fn new(config: LockoutConfig) -> Self {
+        Self {
+            config,
+            accounts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Seconds until `account` may try again, if it is locked out at `now`.
+    pub fn locked_for(&self, account: &str, now: u64) -> Option<u64> {
+        let accounts = self.accounts.lock().expect("lockout poisoned");
+        accounts
+            .get(account)
+            .map(|failures| failures.locked_until.saturating_sub(now))
+            .filter(|&remaining| remaining > 0)
+    }
+
+    /// Count a failed attempt, returning the failures so far and when the
+    /// lockout it triggered, if any, ends.
+    pub fn record_failure(&self, account: &str, now: u64) -> (u32, Option<u64>) {
+        let mut accounts = self.accounts.lock().expect("lockout poisoned");
+        if accounts.len() >= MAX_TRACKED_ACCOUNTS {
+            accounts.retain(|_, failures| failures.locked_until > now);
+        }
+        let failures = accounts.entry(account.to_string()).or_default();
+        failures.count = failures.count.saturating_add(1);
+        if self.config.threshold == 0 || failures.count < self.config.threshold {
+            return (failures.count, None);
+        }
+        let doublings = (failures.count - self.config.threshold).min(32);
+        let seconds = self
+            .config
+            .base_seconds
+            .saturating_mul(1 << doublings)
+            .min(self.config.max_seconds);
+        failures.locked_until = now + seconds;
+        (failures.count, Some(failures.locked_until))
+    }
+
+    /// Forget the failures of an account that signed in.
+    pub fn record_success(&self, account: &str) {
+        self.accounts
+            .lock()
+            .expect("lockout poisoned")
+            .remove(account);
+    }
+}
+
+/// What happened in a sign-in attempt worth auditing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityEventKind {
+    AuthFailed,
+    AuthLockedOut,
+}
+
+impl SecurityEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SecurityEventKind::AuthFailed => "auth_failed",
+            SecurityEventKind::AuthLockedOut => "auth_locked_out",
+        }
+    }
+}
+
+/// One line of the security log.
+#[derive(Debug)]
+pub struct SecurityEvent<'a> {
+    pub event: SecurityEventKind,
+    /// Endpoint the attempt was made on.
+    pub endpoint: &'static str,
+    /// Trader ID or wallet subject.
+    pub account: &'a str,
+    pub reason: &'a str,
+    /// Consecutive failures, this one included.
+    pub failures: Option<u32>,
+    /// Unix seconds; set when the account is locked out.
+    pub locked_until: Option<u64>,
+    pub timestamp: u64,
+}
+
+impl SecurityEvent<'_> {
+    /// Log the event under the `security` target, for log shippers.
+    pub fn emit(&self) {
+        tracing::warn!(
+            target: "security",
+            event = self.event.as_str(),
+            endpoint = self.endpoint,
+            account = self.account,
+            reason = self.reason,
+            failures = self.failures,
+            locked_until = self.locked_until,
+            timestamp = self.timestamp,
+            "security event"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_after_the_threshold() {
+        let lockout = AuthLockout::new(LockoutConfig {
+            threshold: 3,
+            base_seconds: 10,
+            max_seconds: 25,
+        });
+        assert_eq!(lockout.record_failure("alice", 100), (1, None));
+        assert_eq!(lockout.record_failure("alice", 100), (2, None));
+        assert_eq!(lockout.locked_for("alice", 100), None);
+        assert_eq!(lockout.record_failure("alice", 100), (3, Some(110)));
+        assert_eq!(lockout.locked_for("alice", 104), Some(6));
+        assert_eq!(lockout.locked_for("bob", 104), None);
+
+        assert_eq!(lockout.locked_for("alice", 110), None);
+        assert_eq!(lockout.record_failure("alice", 110), (4, Some(130)));
+        // Capped at max_seconds.
+        assert_eq!(lockout.record_failure("alice", 130), (5, Some(155)));
+
+        lockout.record_success("alice");
+        assert_eq!(lockout.locked_for("alice", 131), None);
+        assert_eq!(lockout.record_failure("alice", 131), (1, None));
+    }
+}
@@ -1,50 +1,591 @@
 //! Main entry point for the DEX-OS API server
 
-use dex_api::{auth::AuthManager, challenge::ChallengeStore, routes, ApiState, Config};
-use dex_core::orderbook::OrderBook;
-use dex_db::DatabaseManager;
+use dex_api::{
+    amm_state,
+    api_keys::ReplayGuard,
+    auth::AuthManager,
+    book_snapshot::{self, BookJournal, JournalOptions},
+    bridge::Bridge,
+    candles,
+    challenge::ChallengeStore,
+    chaos::{Chaos, ChaosStorage},
+    config::StorageBackend,
+    event_stream, fix, grpc, kafka,
+    leader::{self, Leadership},
+    lockout::AuthLockout,
+    market_counters,
+    market_feed::{self, FeedRole, MarketFeed},
+    matching_stats::MatchingStats,
+    messaging_policy,
+    rate_limit::RateLimiter,
+    recorder::{self, Recorder},
+    retention, secrets,
+    sequencing::{Sequencer, SequencerKey},
+    sessions,
+    settings::{self, Settings},
+    shutdown, surface_routes, telemetry, tick_maps,
+    tls::{self, CertStore},
+    usage,
+    usd_prices::{self, UsdPrices},
+    webhooks, AmmPools, ApiState, Config, Determinism, OrderTracker, Surface, TradeTape,
+};
+use dex_db::{
+    memory::MemoryStorage, sqlite::SqliteStorage, ApiKeyRepo, AuditRepo, CandleRepo, ChainRepo,
+    CounterRepo, CustodyRepo, DatabaseManager, FundingRepo, LedgerRepo, OrderRepo, OutboxRepo,
+    PoolRepo, ReceiptRepo, RefreshTokenRepo, RetentionRepo, SettlementRepo, Storage, SwapRepo,
+    TickMapRepo, TotpRepo, TradeRepo, UsageRepo, WebhookRepo,
+};
 use secrecy::ExposeSecret;
-use std::sync::{atomic::AtomicU64, Arc};
-use tokio::sync::{broadcast, RwLock};
+use std::{
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, watch, RwLock};
 
 #[tokio::main]
 async fn main() {
     if let Err(err) = bootstrap().await {
+        // Logging may not be set up yet, or set up wrongly.
         eprintln!("Failed to start DEX-OS API server: {}", err);
         std::process::exit(1);
     }
 }
 
 async fn bootstrap() -> Result<(), Box<dyn std::error::Error>> {
-    let config = Config::from_env()?;
+    let (config, secret_values) = Config::load_with_secrets().await?;
+    let telemetry = telemetry::init(&config)?;
+    if let Some(endpoint) = &config.otlp_endpoint {
+        tracing::info!(
+            url = %telemetry::traces_url(endpoint),
+            "exporting traces"
+        );
+    }
+
+    let (storage, database): (Arc<dyn Storage>, _) = match &config.storage {
+        StorageBackend::Postgres(url) => {
+            let mut database = DatabaseManager::connect_with(url.expose_secret(), config.db_pool)
+                .await?
+                .with_resilience(config.db_resilience)
+                .with_query_limits(config.db_query_limits);
+            if let Some(replica_url) = &config.database_replica_url {
+                database = database.with_replica(replica_url.expose_secret())?;
+                tracing::info!("reading trade history and candles from the read replica");
+            }
+            let database = Arc::new(database);
+            database.initialize().await?;
+            database
+                .clone()
+                .spawn_recovery_probe(Duration::from_secs(config.db_probe_interval_seconds));
+            (database.clone(), Some(database))
+        }
+        StorageBackend::Sqlite(path) => {
+            let storage = SqliteStorage::open(path).await?;
+            storage.initialize().await?;
+            tracing::info!(path = %path.display(), "SQLite storage");
+            (Arc::new(storage), None)
+        }
+        StorageBackend::Memory => {
+            tracing::warn!("in-memory storage; nothing is kept across restarts");
+            (Arc::new(MemoryStorage::default()), None)
+        }
+    };
 
-    let database = Arc::new(DatabaseManager::connect(config.database_url.expose_secret()).await?);
-    database.initialize().await?;
+    // Replicas never lead; they follow whichever instance does. Config
+    // refuses election without Postgres.
+    let election = config.leader_election.filter(|_| !config.is_replica());
+    let lease = match (election, &database) {
+        (Some(election), Some(database)) => {
+            let lease = database.try_lead(election.key).await?;
+            tracing::info!(
+                key = election.key,
+                leading = lease.is_some(),
+                "leader election"
+            );
+            lease
+        }
+        _ => None,
+    };
+    let standby = election.is_some() && lease.is_none();
+    let leads = !config.is_replica() && !standby;
 
-    let auth = Arc::new(AuthManager::new(
-        &config.jwt_secret,
-        config.jwt_issuer.clone(),
-    ));
-    let wallet_challenges = Arc::new(ChallengeStore::new(config.wallet_challenge_ttl_seconds));
+    let determinism = Arc::new(Determinism::from_seed(config.deterministic_seed));
+    if let Some(seed) = config.deterministic_seed {
+        tracing::warn!(seed, "deterministic mode enabled");
+    }
+    let auth = Arc::new(
+        AuthManager::with_keys(config.jwt_keys.clone(), config.jwt_issuer.clone())?
+            .with_determinism(determinism.clone()),
+    );
+    let wallet_challenges = Arc::new(
+        ChallengeStore::new(
+            config.wallet_challenge_ttl_seconds,
+            config.sign_in_domain(),
+            storage.clone(),
+        )
+        .with_determinism(determinism.clone()),
+    );
     let (market_tx, _) = broadcast::channel(64);
+    let (user_tx, _) = broadcast::channel(1024);
+    let (trade_tx, _) = broadcast::channel(1024);
+    let (amm_tx, _) = broadcast::channel(1024);
+    let (block_tx, _) = broadcast::channel(1024);
+
+    let chaos = Arc::new(Chaos::new(config.chaos));
+    let (orders, trades): (Arc<dyn OrderRepo>, Arc<dyn TradeRepo>) = if chaos.is_active() {
+        tracing::warn!(chaos = ?config.chaos, "chaos fault injection enabled");
+        let faulty = Arc::new(ChaosStorage::new(
+            storage.clone(),
+            storage.clone(),
+            chaos.clone(),
+        ));
+        (faulty.clone(), faulty)
+    } else {
+        (storage.clone(), storage.clone())
+    };
+
+    let refresh_tokens: Arc<dyn RefreshTokenRepo> = storage.clone();
+    let api_keys: Arc<dyn ApiKeyRepo> = storage.clone();
+    let usage_repo: Arc<dyn UsageRepo> = storage.clone();
+    let totp_repo: Arc<dyn TotpRepo> = storage.clone();
+    let audit_repo: Arc<dyn AuditRepo> = storage.clone();
+    let settlement_repo: Arc<dyn SettlementRepo> = storage.clone();
+    let swap_repo: Arc<dyn SwapRepo> = storage.clone();
+    let candle_repo: Arc<dyn CandleRepo> = storage.clone();
+    let last_swap_id = swap_repo.last_swap_id().await?;
+    let counter_repo: Arc<dyn CounterRepo> = storage.clone();
+    let ledger_repo: Arc<dyn LedgerRepo> = storage.clone();
+    let funding_repo: Arc<dyn FundingRepo> = storage.clone();
+    let retention_repo: Arc<dyn RetentionRepo> = storage.clone();
+    let tick_map_repo: Arc<dyn TickMapRepo> = storage.clone();
+    let pool_repo: Arc<dyn PoolRepo> = storage.clone();
+    let custody_repo: Arc<dyn CustodyRepo> = storage.clone();
+    let chain_repo: Arc<dyn ChainRepo> = storage.clone();
+    let receipt_repo: Arc<dyn ReceiptRepo> = storage.clone();
+    let latest_receipt = receipt_repo.latest_receipt().await?;
+    let outbox_repo: Arc<dyn OutboxRepo> = storage.clone();
+    let webhook_repo: Arc<dyn WebhookRepo> = storage.clone();
+    let sequencer_key = config.sequencer_key.clone().unwrap_or_else(|| {
+        tracing::warn!(
+            "SEQUENCER_SIGNING_KEY not set; using an ephemeral key, so receipts will not verify after a restart"
+        );
+        let seed = dex_api::determinism::secret_bytes(32);
+        SequencerKey::new(seed.try_into().expect("32 random bytes"))
+    });
+    let sequencer = Sequencer::new(
+        &sequencer_key,
+        &config.sequencer_region,
+        latest_receipt.as_ref(),
+    );
+    tracing::info!(
+        public_key = %sequencer.public_key(),
+        next_sequence = latest_receipt.as_ref().map_or(1, |r| r.sequence + 1),
+        "sequencer ready"
+    );
+    let mut trade_tape = TradeTape::default();
+    let counters = counter_repo.load_counters().await?;
+    tracing::info!(markets = counters.len(), "restored trade counters");
+    trade_tape.counters_mut().restore(counters);
+
+    let started = Instant::now();
+    let snapshot_dir = config.book_snapshot_dir.as_deref();
+    for (pair, policy) in config.matching.configured() {
+        tracing::info!(%pair, %policy, "matching policy");
+    }
+    let warm = book_snapshot::warm_start(
+        snapshot_dir,
+        orders.as_ref(),
+        trades.as_ref(),
+        &config.matching,
+    )
+    .await?;
+    let resting = warm.orderbook.resting_orders();
+    tracing::info!(
+        orders = resting.len(),
+        source = ?warm.source,
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "loaded resting orders"
+    );
+    let mut order_tracker = OrderTracker::new();
+    for order in &resting {
+        order_tracker.accept(order);
+    }
+    let mut matching_stats = MatchingStats::default();
+    matching_stats.seed(&resting);
+    // Replicas take their book from the feed, so they leave the log alone.
+    // So do standbys: one that takes over runs without it until restarted.
+    let journal = match snapshot_dir.filter(|_| leads) {
+        Some(dir) => Some(Arc::new(BookJournal::open(
+            dir,
+            &warm.orderbook,
+            warm.sequence,
+            JournalOptions {
+                segment_bytes: config.book_journal_segment_bytes,
+                archive_dir: config.book_journal_archive_dir.clone(),
+                bands: config.book_snapshot_bands,
+            },
+        )?)),
+        None => None,
+    };
+
+    let (market_feed, feed_outbox) = match config.market_feed.as_ref().map(|feed| feed.role) {
+        Some(FeedRole::Publisher) => {
+            let (feed, outbox) = MarketFeed::new();
+            (Some(Arc::new(feed)), Some(outbox))
+        }
+        _ => (None, None),
+    };
 
     let state = ApiState {
-        orderbook: Arc::new(RwLock::new(OrderBook::new())),
-        order_id_counter: Arc::new(AtomicU64::new(1)),
-        trade_id_counter: Arc::new(AtomicU64::new(1)),
+        orderbook: Arc::new(RwLock::new(warm.orderbook)),
+        swap_id_counter: Arc::new(AtomicU64::new(last_swap_id + 1)),
+        storage,
         database,
+        orders,
+        trades,
         auth,
+        refresh_tokens,
+        api_keys,
+        signed_requests: Arc::new(ReplayGuard::new(config.api_key_replay_window_seconds)),
         config: config.clone(),
         wallet_challenges,
         market_tx,
+        trade_tape: Arc::new(RwLock::new(trade_tape)),
+        order_tracker: Arc::new(RwLock::new(order_tracker)),
+        user_tx,
+        trade_tx,
+        amm_tx,
+        block_tx,
+        chaos,
+        amm: Arc::new(RwLock::new(AmmPools::default())),
+        rate_limiter: Arc::new(RateLimiter::new(config.rate_limits)),
+        settings: Arc::new(watch::Sender::new(Settings::from(&config))),
+        auth_lockout: Arc::new(AuthLockout::new(config.auth_lockout)),
+        usd_prices: Arc::new(RwLock::new(UsdPrices::new(&config.usd_reference_token))),
+        journal: journal.clone(),
+        market_feed,
+        matching_stats: Arc::new(RwLock::new(matching_stats)),
+        trader_locks: Default::default(),
+        determinism,
+        usage: Default::default(),
+        usage_repo,
+        totp_repo,
+        audit_repo,
+        settlement_repo,
+        swap_repo,
+        candle_repo,
+        counter_repo,
+        ledger_repo,
+        funding_repo,
+        retention_repo,
+        tick_map_repo,
+        pool_repo,
+        custody_repo,
+        chain_repo,
+        receipt_repo,
+        outbox_repo,
+        webhook_repo,
+        sequencer: Arc::new(sequencer),
+        bridge: Arc::new(Bridge::new(&config.bridge)),
+        shutdown: Default::default(),
+        consensus: Default::default(),
+        leadership: Arc::new(if standby {
+            Leadership::standby()
+        } else {
+            Leadership::default()
+        }),
     };
+    telemetry.follow_log_level(state.settings.subscribe());
+    settings::spawn_reload_on_hangup(state.clone());
+    let restored = amm_state::restore(&state).await?;
+    tracing::info!(pools = restored, "restored AMM pools");
+    let restored = tick_maps::restore(&state).await?;
+    tracing::info!(pools = restored, "restored tick maps");
+    let restored = sessions::sync_revocations(&state).await?;
+    tracing::info!(tokens = restored, "restored token revocations");
+    sessions::spawn_revocation_sync(
+        state.clone(),
+        Duration::from_secs(config.revocation_sync_interval_seconds),
+    );
+    let orderbook = state.orderbook.clone();
 
-    let routes = routes(state);
+    if let Some(journal) = &journal {
+        book_snapshot::spawn_checkpoints(
+            journal.clone(),
+            orderbook.clone(),
+            Duration::from_secs(config.book_snapshot_interval_seconds),
+        );
+    }
 
-    println!("Starting DEX-OS API server on port {}", config.server_port);
-    warp::serve(routes)
-        .run(([127, 0, 0, 1], config.server_port))
-        .await;
+    if let Some(feed_config) = &config.market_feed {
+        tracing::info!(url = %feed_config.url, role = ?feed_config.role, "market data feed");
+    }
+    // Standbys mirror the leader until they take over from it.
+    let mirror = match &config.market_feed {
+        Some(feed_config) if !leads => Some(market_feed::spawn_replica(
+            state.clone(),
+            feed_config.url.client(),
+            feed_config.clone(),
+        )),
+        _ => None,
+    };
+    match (lease, election) {
+        (Some(lease), Some(election)) => {
+            spawn_leader_jobs(&state, feed_outbox);
+            tokio::spawn(async move {
+                leader::hold(&lease, election).await;
+                step_down();
+            });
+        }
+        (None, Some(election)) => {
+            let state = state.clone();
+            tokio::spawn(async move {
+                let database = state.database.clone().expect("elections run on Postgres");
+                let lease = leader::campaign(&database, election).await;
+                tracing::info!(key = election.key, "won leader election, taking over");
+                if let Some(mirror) = mirror {
+                    mirror.abort();
+                }
+                if let Err(err) = leader::take_over(&state).await {
+                    tracing::error!(error = %err, "failed to take over; stopping");
+                    std::process::exit(1);
+                }
+                spawn_leader_jobs(&state, feed_outbox);
+                state.leadership.promote();
+                leader::hold(&lease, election).await;
+                step_down();
+            });
+        }
+        _ if leads => spawn_leader_jobs(&state, feed_outbox),
+        _ => {}
+    }
+
+    let recorder = match &config.market_data_archive {
+        Some(location) => {
+            tracing::info!(%location, "archiving market data");
+            let recorder = Arc::new(tokio::sync::Mutex::new(Recorder::new(
+                location.open()?,
+                config.market_data_archive_depth_levels,
+            )));
+            recorder::spawn(state.clone(), recorder.clone());
+            Some(recorder)
+        }
+        None => None,
+    };
 
+    usage::spawn_flush(
+        state.clone(),
+        Duration::from_secs(config.usage_flush_interval_seconds),
+    );
+    if let Some(source) = &config.secrets {
+        tracing::info!(
+            %source,
+            interval_seconds = config.secrets_refresh_seconds,
+            "refreshing secrets"
+        );
+        secrets::spawn_refresh(state.clone(), source.clone(), secret_values);
+    }
+    tick_maps::spawn_flush(
+        state.clone(),
+        Duration::from_secs(config.tick_map_flush_interval_seconds),
+    );
+    amm_state::spawn_flush(
+        state.clone(),
+        Duration::from_secs(config.pool_flush_interval_seconds),
+    );
+    messaging_policy::spawn_evaluation(state.clone());
+
+    usd_prices::spawn_refresh(
+        state.clone(),
+        Duration::from_secs(config.usd_price_refresh_seconds),
+    );
+
+    if let Some(fix_port) = config.fix_port {
+        let listener = tokio::net::TcpListener::bind((config.fix_host, fix_port)).await?;
+        tracing::info!(host = %config.fix_host, port = fix_port, "starting FIX gateway");
+        tokio::spawn(fix::serve(listener, state.clone()));
+    }
+
+    if let Some(grpc_port) = config.grpc_port {
+        let listener = tokio::net::TcpListener::bind((config.grpc_host, grpc_port)).await?;
+        tracing::info!(host = %config.grpc_host, port = grpc_port, "starting gRPC service");
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = grpc::serve(listener, state).await {
+                tracing::error!(error = %err, "gRPC service stopped");
+            }
+        });
+    }
+
+    if config.cors.is_enabled() {
+        tracing::info!(
+            origins = %config.cors.allowed_origins,
+            "allowing browser requests"
+        );
+    }
+    // The internal listener stays up while the public one drains, so
+    // shutdown can be watched through the metrics.
+    let routes = match config.internal_addr {
+        Some(addr) => {
+            let internal = surface_routes(state.clone(), Surface::Internal);
+            let (addr, server) = warp::serve(internal).try_bind_ephemeral(addr)?;
+            tracing::info!(%addr, "serving admin endpoints and metrics");
+            tokio::spawn(server);
+            surface_routes(state.clone(), Surface::Public)
+        }
+        None => surface_routes(state.clone(), Surface::All),
+    };
+
+    let shutdown = {
+        let state = state.clone();
+        async move {
+            shutdown::signal().await;
+            tracing::info!("shutting down");
+            state.shutdown.begin();
+        }
+    };
+    match &config.tls {
+        Some(tls_config) => {
+            let certs = Arc::new(CertStore::load(
+                &tls_config.cert_path,
+                &tls_config.key_path,
+            )?);
+            tls::spawn_reload(certs.clone(), tls_config.reload_interval);
+            if let Some(http_port) = tls_config.http_port {
+                let http = tls::http_routes(tls_config.acme_webroot.clone(), config.server_port);
+                let (_, server) =
+                    warp::serve(http).try_bind_ephemeral((config.server_host, http_port))?;
+                tracing::info!(port = http_port, "redirecting HTTP to HTTPS");
+                tokio::spawn(server);
+            }
+            let listener =
+                tokio::net::TcpListener::bind((config.server_host, config.server_port)).await?;
+            tracing::info!(
+                host = %config.server_host,
+                port = config.server_port,
+                "starting DEX-OS API server with TLS"
+            );
+            let acceptor = tls::acceptor(certs, tls_config.client_auth.as_ref())?;
+            if let Some(client_auth) = &tls_config.client_auth {
+                tracing::info!(
+                    ca = %client_auth.ca_path.display(),
+                    identities = client_auth.identities.len(),
+                    "requiring client certificates"
+                );
+            }
+            tls::serve(listener, acceptor, routes, shutdown).await;
+        }
+        None => {
+            tracing::info!(
+                host = %config.server_host,
+                port = config.server_port,
+                "starting DEX-OS API server"
+            );
+            let (_, server) = warp::serve(routes).try_bind_with_graceful_shutdown(
+                (config.server_host, config.server_port),
+                shutdown,
+            )?;
+            server.await;
+        }
+    }
+
+    // The listener is closed; let orders being stored finish and sockets
+    // send their close frames.
+    let grace = Duration::from_secs(config.shutdown_grace_seconds);
+    if tokio::time::timeout(grace, state.shutdown.drained())
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            in_flight = state.shutdown.in_flight(),
+            "gave up waiting for in-flight work"
+        );
+    }
+
+    if let Err(err) = usage::flush(&state).await {
+        tracing::error!(error = ?err, "failed to store API usage");
+    }
+    if let Err(err) = market_counters::flush(&state).await {
+        tracing::error!(error = ?err, "failed to store market counters");
+    }
+    if let Err(err) = tick_maps::flush(&state).await {
+        tracing::error!(error = ?err, "failed to store tick maps");
+    }
+    if let Err(err) = amm_state::flush(&state).await {
+        tracing::error!(error = ?err, "failed to store AMM pools");
+    }
+
+    // Upload the hour in progress rather than lose it.
+    if let Some(recorder) = recorder {
+        if let Err(err) = recorder.lock().await.flush().await {
+            tracing::error!(
+                error = %err,
+                "failed to archive the last market data segment"
+            );
+        }
+    }
+
+    // A final checkpoint lets the next start skip replaying the log.
+    if let Some(journal) = journal {
+        journal.checkpoint(&orderbook).await?;
+    }
+
+    telemetry.shutdown();
     Ok(())
 }
+
+/// Start the jobs that only the instance matching orders runs.
+fn spawn_leader_jobs(state: &ApiState, feed_outbox: Option<market_feed::Outbox>) {
+    let config = &state.config;
+    if let (Some(feed_config), Some(feed), Some(outbox)) =
+        (&config.market_feed, &state.market_feed, feed_outbox)
+    {
+        market_feed::spawn_publisher(
+            state.clone(),
+            feed.clone(),
+            outbox,
+            feed_config.url.client(),
+            feed_config.clone(),
+        );
+    }
+    if let Some(stream_config) = &config.event_stream {
+        tracing::info!(
+            brokers = %stream_config.brokers.join(","),
+            prefix = %stream_config.topic_prefix,
+            "streaming order flow to Kafka"
+        );
+        match kafka::KafkaProducer::new(
+            &stream_config.brokers,
+            &stream_config.client_id,
+            &stream_config.security,
+        ) {
+            Ok(producer) => {
+                event_stream::spawn_relay(state.clone(), stream_config.clone(), Arc::new(producer));
+            }
+            Err(err) => tracing::error!(error = %err, "cannot create Kafka producer"),
+        }
+    }
+    webhooks::spawn(state.clone());
+    // Other instances count the leader's trades; only it writes them.
+    market_counters::spawn_flush(
+        state.clone(),
+        Duration::from_secs(config.counter_flush_interval_seconds),
+    );
+    candles::spawn_aggregation(
+        state.clone(),
+        Duration::from_secs(config.candle_aggregation_interval_seconds),
+    );
+    if config.retention.is_enabled() {
+        tracing::info!(
+            days = config.retention.max_age_days,
+            "archiving old trades and orders"
+        );
+    }
+    retention::spawn(state.clone(), config.retention);
+}
+
+/// Stop a leader that can no longer confirm its lock, before a standby
+/// that has taken it matches alongside.
+fn step_down() {
+    tracing::error!("lost leadership; stopping so that a standby takes over");
+    std::process::exit(1);
+}
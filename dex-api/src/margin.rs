@@ -0,0 +1,370 @@
+//! Margin requirements on resting orders, with portfolio margin for approved
+//! accounts.
+//!
+//! A resting order commits its trader to a position should it fill. Per
+//! market, a trader's exposure is the larger of their resting buys and
+//! resting sells, valued in USD through the synthetic quotes and signed long
+//! for buys, short for sells. The market's margin is its rate times that
+//! exposure. Standard accounts need the sum of their per-market margins.
+//! Accounts approved for portfolio margin net correlated markets against each
+//! other: the requirement is `sqrt(m · C · m)` over the per-market margins
+//! `m` and the configured correlation matrix `C`, so a long in one market is
+//! partly offset by a short in a correlated one. Markets without a configured
+//! correlation are not netted, which keeps the requirement at or below the
+//! gross sum.
+//!
+//! Traders with a margin limit cannot place an order that takes their
+//! requirement over it, unless the order lowers the requirement.
+
+use crate::{
+    auth::{Claims, Scope},
+    authenticated,
+    rate_limit::RouteClass,
+    rate_limited,
+    usd_prices::UsdPrices,
+    ApiState, ErrorResponse,
+};
+use dex_core::types::{Notional, Order, OrderSide, OrderType, Quantity};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// Margin rates, correlations and limits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginConfig {
+    /// Share of a market's exposure held as margin, unless overridden.
+    pub default_rate: f64,
+    /// Rates for individual markets, keyed by `BASE-QUOTE`.
+    pub pair_rates: HashMap<String, f64>,
+    /// Correlations between markets, between -1 and 1. Each pair of markets
+    /// is listed once, in either order.
+    pub correlations: HashMap<(String, String), f64>,
+    /// Largest requirement each trader may carry, in USD. Traders without a
+    /// limit are reported but never refused.
+    pub limits: HashMap<String, f64>,
+    /// Traders approved for portfolio margin.
+    pub portfolio_accounts: HashSet<String>,
+}
+
+impl Default for MarginConfig {
+    fn default() -> Self {
+        Self {
+            default_rate: 0.1,
+            pair_rates: HashMap::new(),
+            correlations: HashMap::new(),
+            limits: HashMap::new(),
+            portfolio_accounts: HashSet::new(),
+        }
+    }
+}
+
+/// Margin held for one market.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct PairMargin {
+    pub pair: String,
+    /// USD value of the larger side of the resting orders; negative when
+    /// the sells are larger.
+    pub exposure_usd: f64,
+    pub rate: f64,
+    pub margin_usd: f64,
+}
+
+/// A trader's margin requirement.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct MarginReport {
+    pub trader_id: String,
+    /// Whether correlated markets are netted for this trader.
+    pub portfolio_margin: bool,
+    pub pairs: Vec<PairMargin>,
+    /// Sum of the per-market margins.
+    pub gross_usd: f64,
+    /// Margin the trader needs: the gross sum, or the netted requirement
+    /// with portfolio margin.
+    pub required_usd: f64,
+    pub limit_usd: Option<f64>,
+}
+
+/// An order refused for taking its trader over their margin limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginBreach {
+    pub required_usd: f64,
+    pub limit_usd: f64,
+}
+
+impl MarginConfig {
+    pub fn rate_for(&self, pair: &str) -> f64 {
+        self.pair_rates
+            .get(pair)
+            .copied()
+            .unwrap_or(self.default_rate)
+    }
+
+    /// The configured correlation between two markets; one for a market with
+    /// itself.
+    pub fn correlation(&self, a: &str, b: &str) -> Option<f64> {
+        if a == b {
+            return Some(1.0);
+        }
+        self.correlations
+            .get(&(a.to_string(), b.to_string()))
+            .or_else(|| self.correlations.get(&(b.to_string(), a.to_string())))
+            .copied()
+    }
+
+    /// The requirement for `trader` holding the signed USD `exposures`,
+    /// keyed by market.
+    pub fn report(&self, trader: &str, exposures: &BTreeMap<String, f64>) -> MarginReport {
+        let pairs: Vec<PairMargin> = exposures
+            .iter()
+            .filter(|(_, exposure)| **exposure != 0.0)
+            .map(|(pair, &exposure_usd)| {
+                let rate = self.rate_for(pair);
+                PairMargin {
+                    pair: pair.clone(),
+                    exposure_usd,
+                    rate,
+                    margin_usd: (rate * exposure_usd).abs(),
+                }
+            })
+            .collect();
+        let gross_usd = pairs.iter().map(|pair| pair.margin_usd).sum();
+        let portfolio_margin = self.portfolio_accounts.contains(trader);
+        let required_usd = if portfolio_margin {
+            self.netted(&pairs)
+        } else {
+            gross_usd
+        };
+        MarginReport {
+            trader_id: trader.to_string(),
+            portfolio_margin,
+            pairs,
+            gross_usd,
+            required_usd,
+            limit_usd: self.limits.get(trader).copied(),
+        }
+    }
+
+    /// `sqrt(m · C · m)` over the signed per-market margins. Markets without
+    /// a configured correlation add their margins in full, as if moving
+    /// against the trader together.
+    fn netted(&self, pairs: &[PairMargin]) -> f64 {
+        let signed: Vec<f64> = pairs
+            .iter()
+            .map(|pair| pair.rate * pair.exposure_usd)
+            .collect();
+        let mut variance = 0.0;
+        for (i, a) in pairs.iter().enumerate() {
+            for (j, b) in pairs.iter().enumerate() {
+                let product = signed[i] * signed[j];
+                variance += match self.correlation(&a.pair, &b.pair) {
+                    Some(correlation) => correlation * product,
+                    None => product.abs(),
+                };
+            }
+        }
+        variance.max(0.0).sqrt()
+    }
+}
+
+/// Signed USD exposure per market of `orders`. Markets whose quote token has
+/// no USD quote yet are valued at face value in the quote token.
+pub fn exposures<'a>(
+    orders: impl IntoIterator<Item = &'a Order>,
+    prices: &UsdPrices,
+) -> BTreeMap<String, f64> {
+    let mut sides: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    for order in orders {
+        let Some(price) = order.price else {
+            continue;
+        };
+        let notional = Notional::of(price, order.quantity).value();
+        let value = prices
+            .value(order.pair.quote().as_str(), notional)
+            .unwrap_or(notional as f64);
+        let (long, short) = sides.entry(order.pair.to_string()).or_default();
+        match order.side {
+            OrderSide::Buy => *long += value,
+            OrderSide::Sell => *short += value,
+        }
+    }
+    sides
+        .into_iter()
+        .map(|(pair, (long, short))| {
+            let exposure = if long >= short { long } else { -short };
+            (pair, exposure)
+        })
+        .collect()
+}
+
+/// The current requirement for `trader`, from their resting orders.
+pub async fn report(state: &ApiState, trader: &str) -> MarginReport {
+    let orderbook = state.orderbook.read().await;
+    let prices = state.usd_prices.read().await;
+    let resting = orderbook
+        .orders
+        .values()
+        .filter(|order| order.trader_id.as_str() == trader);
+    state
+        .config
+        .margin
+        .report(trader, &exposures(resting, &prices))
+}
+
+/// Refuse `order` if resting `resting` of it would take its trader over their
+/// margin limit without lowering their requirement.
+pub async fn check(state: &ApiState, order: &Order, resting: Quantity) -> Result<(), MarginBreach> {
+    let margin = &state.config.margin;
+    let trader = order.trader_id.as_str();
+    let Some(&limit_usd) = margin.limits.get(trader) else {
+        return Ok(());
+    };
+    // Unfilled market orders are dropped, so only a limit remainder rests.
+    if order.order_type != OrderType::Limit || resting == 0 {
+        return Ok(());
+    }
+    let new_order = Order {
+        quantity: resting,
+        ..order.clone()
+    };
+
+    let orderbook = state.orderbook.read().await;
+    let prices = state.usd_prices.read().await;
+    let current: Vec<&Order> = orderbook
+        .orders
+        .values()
+        .filter(|resting| resting.trader_id == order.trader_id)
+        .collect();
+    let before = margin.report(trader, &exposures(current.iter().copied(), &prices));
+    let after = margin.report(
+        trader,
+        &exposures(current.iter().copied().chain([&new_order]), &prices),
+    );
+    if after.required_usd > limit_usd && after.required_usd > before.required_usd {
+        return Err(MarginBreach {
+            required_usd: after.required_usd,
+            limit_usd,
+        });
+    }
+    Ok(())
+}
+
+/// The caller's margin requirement, GET /account/margin
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("account")
+        .and(warp::path("margin"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_account_margin)
+        .boxed()
+}
+
+/// Handler for the caller's margin requirement on their resting orders
+#[utoipa::path(
+    get,
+    path = "/account/margin",
+    summary = "The caller's margin requirement on their resting orders",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Per-market margin, gross and required totals, and the limit", body = MarginReport),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_account_margin(
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let report = report(&state, &claims.sub).await;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&report),
+        StatusCode::OK,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exposures(entries: &[(&str, f64)]) -> BTreeMap<String, f64> {
+        entries
+            .iter()
+            .map(|(pair, exposure)| (pair.to_string(), *exposure))
+            .collect()
+    }
+
+    #[test]
+    fn correlated_markets_are_netted_for_portfolio_accounts() {
+        let mut config = MarginConfig::default();
+        config
+            .correlations
+            .insert(("BTC-USDC".into(), "ETH-USDC".into()), 0.8);
+        config.portfolio_accounts.insert("mm".into());
+        let hedged = exposures(&[("BTC-USDC", 10_000.0), ("ETH-USDC", -10_000.0)]);
+
+        let standard = config.report("alice", &hedged);
+        assert_eq!(standard.gross_usd, 2000.0);
+        assert_eq!(standard.required_usd, 2000.0);
+
+        // sqrt(1000² + 1000² - 2 · 0.8 · 1000²)
+        let portfolio = config.report("mm", &hedged);
+        assert!(portfolio.portfolio_margin);
+        assert_eq!(portfolio.gross_usd, 2000.0);
+        assert!((portfolio.required_usd - 632.455).abs() < 0.001);
+
+        // Longs in both still benefit, less so.
+        let both_long = config.report(
+            "mm",
+            &exposures(&[("BTC-USDC", 10_000.0), ("ETH-USDC", 10_000.0)]),
+        );
+        assert!((both_long.required_usd - 1897.367).abs() < 0.001);
+    }
+
+    #[test]
+    fn uncorrelated_markets_add_up_in_full() {
+        let mut config = MarginConfig::default();
+        config.portfolio_accounts.insert("mm".into());
+        config.pair_rates.insert("SOL-USDC".into(), 0.2);
+        let report = config.report(
+            "mm",
+            &exposures(&[("BTC-USDC", 10_000.0), ("SOL-USDC", -5_000.0)]),
+        );
+        assert_eq!(report.pairs[1].margin_usd, 1000.0);
+        assert!((report.required_usd - report.gross_usd).abs() < 1e-9);
+        assert_eq!(config.correlation("SOL-USDC", "SOL-USDC"), Some(1.0));
+        assert_eq!(config.correlation("BTC-USDC", "SOL-USDC"), None);
+    }
+
+    #[test]
+    fn exposure_is_the_larger_side_of_the_resting_orders() {
+        let order = |id, side, price, quantity| Order {
+            id,
+            trader_id: "alice".parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(price),
+            quantity,
+            timestamp: 1_700_000_000,
+        };
+        let orders = [
+            order(1, OrderSide::Buy, 100, 5),
+            order(2, OrderSide::Sell, 110, 3),
+            order(3, OrderSide::Sell, 120, 2),
+        ];
+        let mut prices = UsdPrices::new(&"USDC".parse().unwrap());
+        assert_eq!(super::exposures(&orders, &prices)["ETH-USDC"], -570.0);
+
+        prices.prices.insert(
+            "USDC".into(),
+            crate::usd_prices::UsdQuote {
+                price: 0.5,
+                route: Vec::new(),
+            },
+        );
+        assert_eq!(super::exposures(&orders[..1], &prices)["ETH-USDC"], 250.0);
+    }
+}
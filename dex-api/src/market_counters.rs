@@ -0,0 +1,205 @@
+//! Cumulative trade counters per market.
+//!
+//! Each market counts its trades and base and quote volume since it first
+//! traded, over order book fills and AMM swaps alike, and remembers its last
+//! trade. The counters feed the tickers. They are stored every flush
+//! interval and reloaded on boot, so a deploy does not reset them.
+//! Busted or corrected trades stay counted as executed.
+
+use crate::ApiState;
+use dex_core::types::{Price, Quantity, TokenId, TradingPair};
+use dex_db::{DatabaseError, MarketCounters};
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+type MarketKey = (TokenId, TokenId);
+
+/// Running counters of every market, and which changed since the last flush.
+#[derive(Debug, Default)]
+pub struct CounterBook {
+    totals: HashMap<MarketKey, MarketCounters>,
+    dirty: HashSet<MarketKey>,
+}
+
+impl CounterBook {
+    /// Count a trade of `quantity` base for `quote_quantity` quote at `price`.
+    pub fn record(
+        &mut self,
+        base: &TokenId,
+        quote: &TokenId,
+        price: Price,
+        quantity: Quantity,
+        quote_quantity: u128,
+        timestamp: u64,
+    ) {
+        let key = (base.clone(), quote.clone());
+        let totals = self
+            .totals
+            .entry(key.clone())
+            .or_insert_with(|| MarketCounters {
+                base_token: base.clone(),
+                quote_token: quote.clone(),
+                trade_count: 0,
+                base_volume: 0,
+                quote_volume: 0,
+                last_price: None,
+                last_trade_time: None,
+            });
+        totals.trade_count += 1;
+        totals.base_volume = totals.base_volume.saturating_add(quantity as u128);
+        totals.quote_volume = totals.quote_volume.saturating_add(quote_quantity);
+        totals.last_price = Some(price);
+        totals.last_trade_time = Some(timestamp);
+        self.dirty.insert(key);
+    }
+
+    /// Seed the counters with stored totals, keeping whichever count is
+    /// ahead where a market has already traded.
+    pub fn restore(&mut self, stored: Vec<MarketCounters>) {
+        for counters in stored {
+            let key = (counters.base_token.clone(), counters.quote_token.clone());
+            match self.totals.get_mut(&key) {
+                Some(totals) => {
+                    totals.trade_count = totals.trade_count.max(counters.trade_count);
+                    totals.base_volume = totals.base_volume.max(counters.base_volume);
+                    totals.quote_volume = totals.quote_volume.max(counters.quote_volume);
+                    if totals.last_trade_time < counters.last_trade_time {
+                        totals.last_price = counters.last_price;
+                        totals.last_trade_time = counters.last_trade_time;
+                    }
+                }
+                None => {
+                    self.totals.insert(key, counters);
+                }
+            }
+        }
+    }
+
+    /// Counters of one market, if it has ever traded.
+    pub fn get(&self, pair: &TradingPair) -> Option<&MarketCounters> {
+        self.totals
+            .get(&(pair.base().clone(), pair.quote().clone()))
+    }
+
+    /// Markets that have ever traded.
+    pub fn pairs(&self) -> impl Iterator<Item = TradingPair> + '_ {
+        self.totals
+            .keys()
+            .filter_map(|(base, quote)| TradingPair::new(base.clone(), quote.clone()).ok())
+    }
+
+    /// Current counters of the markets that changed since the last call.
+    pub fn take_dirty(&mut self) -> Vec<MarketCounters> {
+        self.dirty
+            .drain()
+            .filter_map(|key| self.totals.get(&key).cloned())
+            .collect()
+    }
+
+    /// Mark markets as changed again after their counters failed to store.
+    pub fn mark_dirty(&mut self, counters: &[MarketCounters]) {
+        self.dirty.extend(
+            counters
+                .iter()
+                .map(|c| (c.base_token.clone(), c.quote_token.clone())),
+        );
+    }
+}
+
+/// Store the counters of markets that traded since the last flush.
+pub async fn flush(state: &ApiState) -> Result<(), DatabaseError> {
+    let changed = state.trade_tape.write().await.counters_mut().take_dirty();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    // Stored counters never go backwards, so a snapshot that lands after a
+    // newer one is harmless.
+    if let Err(err) = state.counter_repo.save_counters(&changed).await {
+        state
+            .trade_tape
+            .write()
+            .await
+            .counters_mut()
+            .mark_dirty(&changed);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Flush market counters every `interval`.
+pub fn spawn_flush(state: ApiState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; nothing has traded yet.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = flush(&state).await {
+                tracing::error!(error = ?err, "failed to store market counters");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(symbol: &str) -> TokenId {
+        symbol.parse().unwrap()
+    }
+
+    fn stored(trade_count: u64, last_trade_time: u64) -> MarketCounters {
+        MarketCounters {
+            base_token: token("ETH"),
+            quote_token: token("USDC"),
+            trade_count,
+            base_volume: trade_count as u128 * 10,
+            quote_volume: trade_count as u128 * 1_000,
+            last_price: Some(100),
+            last_trade_time: Some(last_trade_time),
+        }
+    }
+
+    #[test]
+    fn counts_trades_and_reports_changed_markets_once() {
+        let mut book = CounterBook::default();
+        book.record(&token("ETH"), &token("USDC"), 100, 5, 500, 10);
+        book.record(&token("ETH"), &token("USDC"), 102, 2, 204, 11);
+
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let totals = book.get(&pair).unwrap().clone();
+        assert_eq!(totals.trade_count, 2);
+        assert_eq!((totals.base_volume, totals.quote_volume), (7, 704));
+        assert_eq!(totals.last_price, Some(102));
+        assert_eq!(totals.last_trade_time, Some(11));
+
+        assert_eq!(book.take_dirty().len(), 1);
+        assert!(book.take_dirty().is_empty());
+        book.mark_dirty(&[totals]);
+        assert_eq!(book.take_dirty()[0].trade_count, 2);
+    }
+
+    #[test]
+    fn restore_never_moves_counters_backwards() {
+        let mut book = CounterBook::default();
+        book.restore(vec![stored(40, 1_000)]);
+        book.record(&token("ETH"), &token("USDC"), 90, 1, 90, 2_000);
+        // A late reload of older totals changes nothing.
+        book.restore(vec![stored(40, 1_000)]);
+
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let totals = book.get(&pair).unwrap();
+        assert_eq!(totals.trade_count, 41);
+        assert_eq!(totals.base_volume, 401);
+        assert_eq!(totals.last_price, Some(90));
+
+        book.restore(vec![stored(50, 3_000)]);
+        let totals = book.get(&pair).unwrap();
+        assert_eq!(totals.trade_count, 50);
+        assert_eq!(totals.last_price, Some(100));
+        assert_eq!(book.pairs().count(), 1);
+    }
+}
@@ -0,0 +1,653 @@
+//! Public market data: prices, depth, recent trades, candles and matching
+//! statistics.
+
+use crate::{
+    caching, candles,
+    rate_limit::RouteClass,
+    rate_limited, storage_error_reply,
+    streams::STREAM_DEPTH_LEVELS,
+    trade_tape::{self, PublicTrade, TradeTape},
+    validation::{self, DepthRequest},
+    with_state, ApiState, ErrorResponse, ValidationRejection,
+};
+use dex_core::{
+    orderbook::{OrderBook, PriceLevel},
+    types::{Price, Quantity, TradingPair},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use utoipa::{openapi::Object, IntoParams, ToSchema};
+use warp::{http::StatusCode, Filter};
+/// Prices for every market. The top-level best bid and ask span the whole
+/// book, as before pairs were reported separately.
+#[derive(Serialize, ToSchema)]
+pub struct PriceResponse {
+    pub best_bid: Option<u64>,
+    pub best_ask: Option<u64>,
+    pub pairs: Vec<Ticker>,
+}
+
+/// Optional market filter for `/orderbook/prices`, e.g. `?pair=ETH-USDC`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct PricesQuery {
+    /// Market as BASE-QUOTE, e.g. ETH-USDC
+    #[param(example = "ETH-USDC")]
+    pair: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DepthLevel {
+    #[schema(value_type = u64)]
+    pub price: Price,
+    #[schema(value_type = u64)]
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DepthSnapshot {
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+    #[schema(value_type = Option<u64>)]
+    pub best_bid: Option<Price>,
+    #[schema(value_type = Option<u64>)]
+    pub best_ask: Option<Price>,
+    pub timestamp: u64,
+}
+
+/// Top of book and last execution for one pair, served by
+/// `/orderbook/prices` and pushed on `ticker:` channels.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Ticker {
+    pub pair: String,
+    #[schema(value_type = Option<u64>)]
+    pub best_bid: Option<Price>,
+    #[schema(value_type = Option<u64>)]
+    pub best_ask: Option<Price>,
+    /// Midpoint of the best bid and ask, rounded down.
+    #[schema(value_type = Option<u64>)]
+    pub mid_price: Option<Price>,
+    #[schema(value_type = Option<u64>)]
+    pub last_price: Option<Price>,
+    pub last_trade_time: Option<u64>,
+    /// Trades since the market opened, over the order book and the AMM.
+    pub trade_count: u64,
+    /// Base traded since the market opened.
+    pub volume: u128,
+    /// Quote traded since the market opened.
+    pub quote_volume: u128,
+    pub timestamp: u64,
+}
+
+/// Depth filters, e.g. `?levels=20&pair=ETH-USDC&grouping=10&encoding=compact`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct DepthQuery {
+    /// Levels per side, 1-100 (default 10)
+    pub(crate) levels: Option<usize>,
+    /// Market as BASE-QUOTE, e.g. ETH-USDC
+    #[param(example = "ETH-USDC")]
+    pub(crate) pair: Option<String>,
+    /// Price bucket size; bids round down and asks round up to a multiple.
+    pub(crate) grouping: Option<u64>,
+    /// `json` (default) or `compact`.
+    #[param(schema_with = depth_encodings)]
+    pub(crate) encoding: Option<String>,
+}
+
+fn depth_encodings() -> Object {
+    crate::openapi::string_enum(&["json", "compact"])
+}
+
+/// How depth levels are written in a REST response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DepthEncoding {
+    /// `{"price":..,"quantity":..}` objects.
+    #[default]
+    Json,
+    /// `[price, quantity]` arrays.
+    Compact,
+}
+
+/// Depth snapshot with levels packed as `[price, quantity]` pairs.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CompactDepthSnapshot {
+    pub bids: Vec<(Price, Quantity)>,
+    pub asks: Vec<(Price, Quantity)>,
+    #[schema(value_type = Option<u64>)]
+    pub best_bid: Option<Price>,
+    #[schema(value_type = Option<u64>)]
+    pub best_ask: Option<Price>,
+    pub timestamp: u64,
+}
+
+impl From<DepthSnapshot> for CompactDepthSnapshot {
+    fn from(snapshot: DepthSnapshot) -> Self {
+        let pack = |levels: Vec<DepthLevel>| {
+            levels
+                .into_iter()
+                .map(|level| (level.price, level.quantity))
+                .collect()
+        };
+        Self {
+            bids: pack(snapshot.bids),
+            asks: pack(snapshot.asks),
+            best_bid: snapshot.best_bid,
+            best_ask: snapshot.best_ask,
+            timestamp: snapshot.timestamp,
+        }
+    }
+}
+
+/// Body of `GET /orderbook/depth`, in the requested encoding.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum DepthResponse {
+    Json(DepthSnapshot),
+    Compact(CompactDepthSnapshot),
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct CandlesQuery {
+    /// Market as BASE-QUOTE, e.g. ETH-USDC
+    #[param(example = "ETH-USDC")]
+    pub(crate) pair: Option<String>,
+    /// `1m` (default), `5m`, `15m`, `1h`, `4h` or `1d`.
+    #[param(schema_with = intervals)]
+    pub(crate) interval: Option<String>,
+    /// Inclusive start, Unix seconds
+    pub(crate) from: Option<u64>,
+    /// Exclusive end, Unix seconds
+    pub(crate) to: Option<u64>,
+    /// Bars per response, 1-100000 (default 1000)
+    pub(crate) limit: Option<usize>,
+    /// `next_cursor` from a previous response; replaces `from`.
+    pub(crate) cursor: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RecentTradesQuery {
+    /// Number of trades (default 100)
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct MatchingStatsQuery {
+    /// Market as BASE-QUOTE, e.g. ETH-USDC
+    #[param(example = "ETH-USDC")]
+    pub(crate) pair: Option<String>,
+    /// `1m`, `5m`, `15m`, `1h` (default), `4h` or `1d`.
+    #[param(schema_with = intervals)]
+    pub(crate) interval: Option<String>,
+}
+
+fn intervals() -> Object {
+    crate::openapi::string_enum(&["1m", "5m", "15m", "1h", "4h", "1d"])
+}
+
+/// Public trade tape for a single market
+#[derive(Serialize, ToSchema)]
+pub struct RecentTradesResponse {
+    pub pair: String,
+    pub trades: Vec<PublicTrade>,
+}
+
+const DEFAULT_RECENT_TRADES: usize = 100;
+
+pub(crate) const DEFAULT_DEPTH_LEVELS: usize = 10;
+
+pub(crate) const MAX_DEPTH_LEVELS: usize = 100;
+
+/// Public market data, e.g. GET /orderbook/depth?levels=10
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let orderbook = warp::path("orderbook");
+
+    // Get prices endpoint
+    let get_prices = orderbook
+        .and(warp::path("prices"))
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and(warp::query::<PricesQuery>())
+        .and(caching::conditional())
+        .and_then(handle_get_prices)
+        .boxed();
+
+    let get_depth = orderbook
+        .and(warp::path("depth"))
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and(warp::query::<DepthQuery>())
+        .and(caching::conditional())
+        .and_then(handle_get_depth)
+        .boxed();
+
+    // Public recent trades for a market, e.g. /markets/ETH-USDC/trades?limit=100
+    let get_recent_trades = warp::path("markets")
+        .and(warp::path::param::<String>())
+        .and(warp::path("trades"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(warp::query::<RecentTradesQuery>())
+        .and(with_state(state.clone()))
+        .and(caching::conditional())
+        .and_then(handle_get_recent_trades)
+        .boxed();
+
+    // OHLCV bars streamed as NDJSON, e.g. /orderbook/candles?pair=ETH-USDC&interval=1h
+    let get_candles = orderbook
+        .and(warp::path("candles"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(warp::query::<CandlesQuery>())
+        .and(with_state(state.clone()))
+        .and_then(handle_get_candles)
+        .boxed();
+
+    // Fill ratios, cancels and resting time, e.g. /stats/matching?pair=ETH-USDC&interval=1h
+    let get_matching_stats = warp::path("stats")
+        .and(warp::path("matching"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(warp::query::<MatchingStatsQuery>())
+        .and(with_state(state.clone()))
+        .and_then(handle_get_matching_stats)
+        .boxed();
+
+    // Synthetic USD price of every traded token
+    let get_usd_prices = warp::path("prices")
+        .and(warp::path("usd"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and(caching::conditional())
+        .and_then(handle_get_usd_prices)
+        .boxed();
+
+    get_prices
+        .or(get_depth)
+        .or(get_recent_trades)
+        .or(get_candles)
+        .or(get_matching_stats)
+        .or(get_usd_prices)
+}
+
+/// Handler for best prices: one market with `?pair=`, otherwise every market
+/// with resting orders or recent trades.
+#[utoipa::path(
+    get,
+    path = "/orderbook/prices",
+    summary = "Best bid, ask, mid and last price per market",
+    params(PricesQuery),
+    responses(
+        (status = 200, description = "Prices", body = PriceResponse),
+        (status = 304, description = "Unchanged since the If-None-Match ETag or If-Modified-Since date"),
+        (status = 400, description = "Invalid pair", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_prices(
+    state: ApiState,
+    query: PricesQuery,
+    conditional: caching::Conditional,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pair = query
+        .pair
+        .as_deref()
+        .map(validation::parse_pair)
+        .transpose()
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let now = state.determinism.now().unwrap_or_default();
+    let orderbook = state.orderbook.read().await;
+    let tape = state.trade_tape.read().await;
+    if let Some(pair) = pair {
+        return Ok(caching::cached_json(
+            &ticker(&orderbook, &tape, &pair, now),
+            None,
+            caching::MARKET_DATA_MAX_AGE,
+            &conditional,
+        ));
+    }
+
+    let pairs: HashSet<TradingPair> = orderbook
+        .orders
+        .values()
+        .map(|order| order.pair.clone())
+        .chain(tape.pairs())
+        .collect();
+    let mut pairs: Vec<Ticker> = pairs
+        .iter()
+        .map(|pair| ticker(&orderbook, &tape, pair, now))
+        .collect();
+    pairs.sort_by(|a, b| a.pair.cmp(&b.pair));
+    let response = PriceResponse {
+        best_bid: orderbook.best_bid(),
+        best_ask: orderbook.best_ask(),
+        pairs,
+    };
+    Ok(caching::cached_json(
+        &response,
+        None,
+        caching::MARKET_DATA_MAX_AGE,
+        &conditional,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/orderbook/depth",
+    summary = "Aggregated order book depth",
+    params(DepthQuery),
+    responses(
+        (
+            status = 200,
+            description = "Depth snapshot; compact encoding packs levels as [price, quantity]",
+            body = DepthResponse,
+        ),
+        (status = 304, description = "Unchanged since the If-None-Match ETag or If-Modified-Since date"),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_depth(
+    state: ApiState,
+    query: DepthQuery,
+    conditional: caching::Conditional,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let request = validation::validate_depth_query(query)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let now = state.determinism.now().unwrap_or_default();
+    let snapshot = requested_depth(&*state.orderbook.read().await, &request, now);
+    let response = match request.encoding {
+        DepthEncoding::Json => DepthResponse::Json(snapshot),
+        DepthEncoding::Compact => DepthResponse::Compact(snapshot.into()),
+    };
+    Ok(caching::cached_json(
+        &response,
+        None,
+        caching::MARKET_DATA_MAX_AGE,
+        &conditional,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/markets/{pair}/trades",
+    summary = "Recent public trades for a market",
+    params(
+        ("pair" = String, Path, description = "Market as BASE-QUOTE, e.g. ETH-USDC", example = "ETH-USDC"),
+        RecentTradesQuery,
+    ),
+    responses(
+        (status = 200, description = "Recent trades, newest first", body = RecentTradesResponse),
+        (status = 304, description = "Unchanged since the If-None-Match ETag or If-Modified-Since date"),
+        (status = 400, description = "Invalid pair", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_recent_trades(
+    raw_pair: String,
+    query: RecentTradesQuery,
+    state: ApiState,
+    conditional: caching::Conditional,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pair = validation::parse_pair(&raw_pair)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_RECENT_TRADES)
+        .clamp(1, trade_tape::DEFAULT_TAPE_CAPACITY);
+    let trades = state.trade_tape.read().await.recent(&pair, limit);
+    // The tape only grows, so its newest trade dates the response.
+    let last_modified = trades.iter().map(|trade| trade.timestamp).max();
+    let response = RecentTradesResponse {
+        pair: pair.to_string(),
+        trades,
+    };
+    Ok(caching::cached_json(
+        &response,
+        last_modified,
+        caching::MARKET_DATA_MAX_AGE,
+        &conditional,
+    ))
+}
+
+/// Handler for streaming candles
+#[utoipa::path(
+    get,
+    path = "/orderbook/candles",
+    summary = "OHLCV bars for a market, streamed as NDJSON",
+    params(CandlesQuery),
+    responses(
+        (
+            status = 200,
+            description = "One Candle per line, oldest first; a final CandleContinuation line when more bars remain",
+            body = candles::CandleLine,
+            content_type = "application/x-ndjson",
+        ),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_candles(
+    query: CandlesQuery,
+    state: ApiState,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let request = validation::validate_candles_query(query)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let response = candles::ndjson_response(
+        state.trades.clone(),
+        state.swap_repo.clone(),
+        state.candle_repo.clone(),
+        request,
+    )
+    .await;
+    match response {
+        Ok(response) => Ok(response),
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load trades for candles");
+            Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to load trades",
+            )))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/stats/matching",
+    summary = "Fill ratios, cancel-to-trade ratios, resting time and volume of a market over the last day",
+    params(MatchingStatsQuery),
+    responses(
+        (status = 200, description = "Matching statistics, oldest interval first", body = crate::matching_stats::MatchingReport),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_matching_stats(
+    query: MatchingStatsQuery,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let (pair, interval) = validation::validate_matching_stats_query(query)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let report = state.matching_stats.read().await.report(&pair, interval);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&report),
+        StatusCode::OK,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/prices/usd",
+    summary = "USD price of every traded token, bridged through the best market path",
+    responses(
+        (status = 200, description = "Synthetic USD quotes", body = crate::usd_prices::UsdPrices),
+        (status = 304, description = "Unchanged since the If-None-Match ETag or If-Modified-Since date"),
+    ),
+)]
+async fn handle_get_usd_prices(
+    state: ApiState,
+    conditional: caching::Conditional,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let prices = state.usd_prices.read().await;
+    let last_modified = (prices.updated_at > 0).then_some(prices.updated_at);
+    Ok(caching::cached_json(
+        &*prices,
+        last_modified,
+        caching::MARKET_DATA_MAX_AGE,
+        &conditional,
+    ))
+}
+
+/// Depth for a validated depth query, grouped when it asks for buckets.
+pub(crate) fn requested_depth(
+    orderbook: &OrderBook,
+    request: &DepthRequest,
+    timestamp: u64,
+) -> DepthSnapshot {
+    match request.grouping {
+        None => depth_snapshot_for(orderbook, request.pair.as_ref(), request.levels, timestamp),
+        Some(step) => {
+            let full = depth_snapshot_for(orderbook, request.pair.as_ref(), usize::MAX, timestamp);
+            group_depth(full, step, request.levels)
+        }
+    }
+}
+
+pub(crate) fn clamp_depth_levels(levels: Option<usize>) -> usize {
+    let requested = levels.unwrap_or(DEFAULT_DEPTH_LEVELS);
+    requested.clamp(1, MAX_DEPTH_LEVELS)
+}
+
+/// Merge levels into `step`-wide price buckets, keeping the best `levels`.
+/// Bids round down and asks round up so a bucket never looks better than
+/// the orders in it.
+fn group_depth(snapshot: DepthSnapshot, step: u64, levels: usize) -> DepthSnapshot {
+    let bucket = |levels_in: Vec<DepthLevel>, round: fn(Price, u64) -> Price| {
+        let mut grouped: Vec<DepthLevel> = Vec::new();
+        for level in levels_in {
+            let price = round(level.price, step);
+            match grouped.last_mut() {
+                Some(last) if last.price == price => {
+                    last.quantity = last.quantity.saturating_add(level.quantity)
+                }
+                _ => grouped.push(DepthLevel {
+                    price,
+                    quantity: level.quantity,
+                }),
+            }
+        }
+        grouped.truncate(levels);
+        grouped
+    };
+    DepthSnapshot {
+        bids: bucket(snapshot.bids, |price, step| price - price % step),
+        asks: bucket(snapshot.asks, |price, step| {
+            price.div_ceil(step).saturating_mul(step)
+        }),
+        ..snapshot
+    }
+}
+
+fn depth_snapshot(orderbook: &OrderBook, levels: usize, timestamp: u64) -> DepthSnapshot {
+    depth_snapshot_for(orderbook, None, levels, timestamp)
+}
+
+/// Depth for the whole book, or only the resting orders of `pair`.
+pub(crate) fn depth_snapshot_for(
+    orderbook: &OrderBook,
+    pair: Option<&TradingPair>,
+    levels: usize,
+    timestamp: u64,
+) -> DepthSnapshot {
+    let level_quantity = |level: &PriceLevel| match pair {
+        None => level.total_quantity,
+        Some(pair) => level
+            .orders
+            .iter()
+            .filter_map(|id| orderbook.orders.get(id))
+            .filter(|order| &order.pair == pair)
+            .map(|order| order.quantity)
+            .sum(),
+    };
+    let depth = |(&price, level): (&Price, &PriceLevel)| {
+        let quantity = level_quantity(level);
+        (quantity > 0).then_some(DepthLevel { price, quantity })
+    };
+    let bids: Vec<DepthLevel> = orderbook
+        .bids
+        .iter()
+        .rev()
+        .filter_map(depth)
+        .take(levels)
+        .collect();
+    let asks: Vec<DepthLevel> = orderbook
+        .asks
+        .iter()
+        .filter_map(depth)
+        .take(levels)
+        .collect();
+    let (best_bid, best_ask) = match pair {
+        None => (orderbook.best_bid(), orderbook.best_ask()),
+        Some(_) => (
+            bids.first().map(|level| level.price),
+            asks.first().map(|level| level.price),
+        ),
+    };
+    DepthSnapshot {
+        bids,
+        asks,
+        best_bid,
+        best_ask,
+        timestamp,
+    }
+}
+
+pub(crate) fn ticker(
+    orderbook: &OrderBook,
+    tape: &TradeTape,
+    pair: &TradingPair,
+    timestamp: u64,
+) -> Ticker {
+    let top = depth_snapshot_for(orderbook, Some(pair), 1, timestamp);
+    let last = tape.last(pair);
+    // The counters remember the last trade after a restart empties the tape.
+    let counters = tape.counters().get(pair);
+    let mid_price = match (top.best_bid, top.best_ask) {
+        (Some(bid), Some(ask)) => Some(((bid as u128 + ask as u128) / 2) as Price),
+        _ => None,
+    };
+    Ticker {
+        pair: pair.to_string(),
+        best_bid: top.best_bid,
+        best_ask: top.best_ask,
+        mid_price,
+        last_price: last
+            .map(|trade| trade.price)
+            .or_else(|| counters.and_then(|c| c.last_price)),
+        last_trade_time: last
+            .map(|trade| trade.timestamp)
+            .or_else(|| counters.and_then(|c| c.last_trade_time)),
+        trade_count: counters.map_or(0, |c| c.trade_count),
+        volume: counters.map_or(0, |c| c.base_volume),
+        quote_volume: counters.map_or(0, |c| c.quote_volume),
+        timestamp: top.timestamp,
+    }
+}
+
+pub(crate) async fn broadcast_depth_snapshot(state: &ApiState) {
+    state.chaos.delay_broadcast().await;
+    let now = state.determinism.now().unwrap_or_default();
+    let snapshot = {
+        let orderbook = state.orderbook.read().await;
+        depth_snapshot(&orderbook, STREAM_DEPTH_LEVELS, now)
+    };
+    let _ = state.market_tx.send(snapshot);
+}
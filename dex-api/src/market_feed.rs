@@ -0,0 +1,394 @@
+//! Market data fan-out from the matching instance to read replicas.
+//!
+//! The depth and trade broadcasts behind `/ws` only reach sessions in the
+//! process that matched the order. With `MARKET_FEED_URL` set, the matching
+//! instance publishes every change to its book and every trade on a
+//! [`PubSub`] backend. Replicas (`MARKET_FEED_ROLE=replica`) subscribe,
+//! mirror the book and the trade tape, and serve the same depth, ticker and
+//! trade streams from them. Replicas refuse order entry, since only one
+//! instance may match.
+//!
+//! Book updates are numbered. Pub/sub delivery is at most once, so a replica
+//! that sees a gap, because it reconnected, fell behind or the matching
+//! instance restarted, stops applying updates until the next full snapshot,
+//! which the matching instance publishes every
+//! `MARKET_FEED_SNAPSHOT_SECONDS`.
+
+use crate::{
+    market_data::broadcast_depth_snapshot,
+    pubsub::{Message, PubSub, PubSubUrl},
+    trade_tape::{MarketTrade, TradeEventKind},
+    ApiState,
+};
+use dex_core::{
+    orderbook::OrderBook,
+    types::{Order, OrderId, OrderSide, Trade, TradingPair},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+/// Longest wait between attempts to resubscribe.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedRole {
+    /// Matches orders and publishes the results.
+    Publisher,
+    /// Mirrors the publisher and serves market data only.
+    Replica,
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedConfig {
+    pub url: PubSubUrl,
+    pub role: FeedRole,
+    /// Channels are `<prefix>.book` and `<prefix>.trades`.
+    pub prefix: String,
+    /// How often the publisher sends the whole book.
+    pub snapshot_interval: Duration,
+}
+
+impl FeedConfig {
+    fn book_channel(&self) -> String {
+        format!("{}.book", self.prefix)
+    }
+
+    fn trade_channel(&self) -> String {
+        format!("{}.trades", self.prefix)
+    }
+}
+
+/// A change to the book, numbered in the order the book took it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BookUpdate {
+    /// Every resting order once update `sequence` was applied.
+    Snapshot {
+        sequence: u64,
+        orders: Vec<Order>,
+    },
+    Add {
+        sequence: u64,
+        order: Order,
+    },
+    Cancel {
+        sequence: u64,
+        order_id: OrderId,
+    },
+}
+
+/// An execution as the matching instance filed it on its tape.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedTrade {
+    pub trade: Trade,
+    pub taker_side: OrderSide,
+}
+
+#[derive(Debug)]
+enum Outgoing {
+    Book(BookUpdate),
+    Trade(FeedTrade),
+}
+
+/// Updates waiting for [`spawn_publisher`] to send them.
+#[derive(Debug)]
+pub struct Outbox(mpsc::UnboundedReceiver<Outgoing>);
+
+/// The publishing side, kept in the matching instance's [`ApiState`].
+#[derive(Debug)]
+pub struct MarketFeed {
+    /// Number of the last book update.
+    sequence: AtomicU64,
+    tx: mpsc::UnboundedSender<Outgoing>,
+}
+
+impl MarketFeed {
+    pub fn new() -> (Self, Outbox) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let feed = Self {
+            sequence: AtomicU64::new(0),
+            tx,
+        };
+        (feed, Outbox(rx))
+    }
+
+    /// Publish an order the book took. Called with the book locked, so
+    /// update numbers follow the book.
+    pub fn order_added(&self, order: &Order) {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = self.tx.send(Outgoing::Book(BookUpdate::Add {
+            sequence,
+            order: order.clone(),
+        }));
+    }
+
+    /// Publish a cancel. Called with the book locked.
+    pub fn order_cancelled(&self, order_id: OrderId) {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed) + 1;
+        let _ = self
+            .tx
+            .send(Outgoing::Book(BookUpdate::Cancel { sequence, order_id }));
+    }
+
+    /// Publish the whole book. Called with the book locked.
+    pub fn snapshot(&self, book: &OrderBook) {
+        let _ = self.tx.send(Outgoing::Book(BookUpdate::Snapshot {
+            sequence: self.sequence.load(Ordering::Relaxed),
+            orders: book.resting_orders(),
+        }));
+    }
+
+    /// Publish an execution as it is filed on the tape.
+    pub fn trade(&self, trade: &Trade, taker_side: OrderSide) {
+        let _ = self.tx.send(Outgoing::Trade(FeedTrade {
+            trade: trade.clone(),
+            taker_side,
+        }));
+    }
+}
+
+/// Send queued updates, and a snapshot every `snapshot_interval`, until
+/// the feed is dropped. Updates that cannot be sent are dropped; replicas
+/// catch up from the next snapshot.
+pub fn spawn_publisher(
+    state: ApiState,
+    feed: Arc<MarketFeed>,
+    mut outbox: Outbox,
+    bus: Arc<dyn PubSub>,
+    config: FeedConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let (book_channel, trade_channel) = (config.book_channel(), config.trade_channel());
+        let mut snapshots = tokio::time::interval(config.snapshot_interval);
+        let mut failing = false;
+        loop {
+            let outgoing = tokio::select! {
+                outgoing = outbox.0.recv() => match outgoing {
+                    Some(outgoing) => outgoing,
+                    None => break,
+                },
+                _ = snapshots.tick() => {
+                    feed.snapshot(&*state.orderbook.read().await);
+                    continue;
+                }
+            };
+            let (channel, payload) = match &outgoing {
+                Outgoing::Book(update) => (&book_channel, serde_json::to_vec(update)),
+                Outgoing::Trade(trade) => (&trade_channel, serde_json::to_vec(trade)),
+            };
+            let payload = payload.expect("feed updates always encode");
+            match bus.publish(channel, &payload).await {
+                Ok(()) if failing => {
+                    tracing::info!(url = %config.url, "market feed publishing again");
+                    failing = false;
+                }
+                Ok(()) => {}
+                Err(err) if !failing => {
+                    tracing::warn!(url = %config.url, error = %err, "market feed publish failed");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    })
+}
+
+/// Mirror the publisher into `state`, resubscribing with backoff whenever
+/// the subscription drops.
+pub fn spawn_replica(state: ApiState, bus: Arc<dyn PubSub>, config: FeedConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let channels = [config.book_channel(), config.trade_channel()];
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match bus.subscribe(&channels).await {
+                Ok(mut subscription) => {
+                    tracing::info!(url = %config.url, "following market feed");
+                    delay = Duration::from_secs(1);
+                    // Anything may have been missed while unsubscribed.
+                    let mut mirror = Mirror::default();
+                    while let Some(message) = subscription.next().await {
+                        mirror.apply(&state, &config, message).await;
+                    }
+                    tracing::warn!(url = %config.url, "market feed subscription ended");
+                }
+                Err(err) => {
+                    tracing::warn!(url = %config.url, error = %err, "market feed subscribe failed")
+                }
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+        }
+    })
+}
+
+/// A replica's position in the publisher's book updates.
+#[derive(Debug, Default)]
+pub struct Mirror {
+    /// The last update applied; `None` until a snapshot brings the book in
+    /// line.
+    applied: Option<u64>,
+}
+
+impl Mirror {
+    pub async fn apply(&mut self, state: &ApiState, config: &FeedConfig, message: Message) {
+        if message.channel == config.book_channel() {
+            match serde_json::from_slice(&message.payload) {
+                Ok(update) => self.book_update(state, update).await,
+                Err(err) => tracing::warn!(error = %err, "unreadable market feed book update"),
+            }
+        } else if message.channel == config.trade_channel() {
+            match serde_json::from_slice(&message.payload) {
+                Ok(trade) => file_trade(state, trade).await,
+                Err(err) => tracing::warn!(error = %err, "unreadable market feed trade"),
+            }
+        }
+    }
+
+    async fn book_update(&mut self, state: &ApiState, update: BookUpdate) {
+        let mut book = state.orderbook.write().await;
+        let next = self.applied.map(|applied| applied + 1);
+        let applied = match update {
+            BookUpdate::Snapshot { sequence, .. } if self.applied == Some(sequence) => return,
+            BookUpdate::Snapshot { sequence, orders } => {
+                let mut rebuilt = OrderBook::with_matching(state.config.matching.clone());
+                for order in orders {
+                    if let Err(err) = rebuilt.restore_order(order) {
+                        tracing::warn!(error = %err, "unusable market feed snapshot");
+                        return;
+                    }
+                }
+                *book = rebuilt;
+                if self.applied.is_none() {
+                    tracing::info!(sequence, "replica book in line with the market feed");
+                }
+                Ok(sequence)
+            }
+            BookUpdate::Add { sequence, order } if next == Some(sequence) => book
+                .add_order(order)
+                .map(|_| sequence)
+                .map_err(|err| (sequence, err)),
+            BookUpdate::Cancel { sequence, order_id } if next == Some(sequence) => book
+                .remove_order(order_id)
+                .map(|_| sequence)
+                .map_err(|err| (sequence, err)),
+            BookUpdate::Add { sequence, .. } | BookUpdate::Cancel { sequence, .. } => {
+                if self.applied.take().is_some() {
+                    tracing::warn!(
+                        sequence,
+                        "market feed update missed; waiting for a snapshot"
+                    );
+                }
+                return;
+            }
+        };
+        match applied {
+            Ok(sequence) => self.applied = Some(sequence),
+            Err((sequence, err)) => {
+                tracing::warn!(sequence, error = %err, "replica book diverged; waiting for a snapshot");
+                self.applied = None;
+            }
+        }
+        drop(book);
+        broadcast_depth_snapshot(state).await;
+    }
+}
+
+/// File a trade on the replica's tape and stream it, as the matching
+/// instance did.
+async fn file_trade(state: &ApiState, FeedTrade { trade, taker_side }: FeedTrade) {
+    let Ok(pair) = TradingPair::new(trade.base_token.clone(), trade.quote_token.clone()) else {
+        return;
+    };
+    let public = state.trade_tape.write().await.record(&trade, taker_side);
+    let _ = state.trade_tx.send(MarketTrade {
+        kind: TradeEventKind::Trade,
+        pair: pair.to_string(),
+        trade: public,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        pubsub::MemoryPubSub,
+        routes,
+        test_support::{place, test_state_with_memory, MemoryStorage},
+    };
+
+    fn config() -> FeedConfig {
+        FeedConfig {
+            url: PubSubUrl::parse("nats://localhost").unwrap(),
+            role: FeedRole::Publisher,
+            prefix: "test.market".to_string(),
+            snapshot_interval: Duration::from_secs(3600),
+        }
+    }
+
+    async fn depth(state: &ApiState) -> Vec<(u64, u64)> {
+        let book = state.orderbook.read().await;
+        book.resting_orders()
+            .iter()
+            .map(|order| (order.id, order.quantity))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn replicas_mirror_the_book_and_tape_and_resync_after_a_gap() {
+        let bus = Arc::new(MemoryPubSub::default());
+        let (feed, outbox) = MarketFeed::new();
+        let feed = Arc::new(feed);
+        let mut primary = test_state_with_memory(Arc::new(MemoryStorage::default()));
+        primary.market_feed = Some(feed.clone());
+        let replica = test_state_with_memory(Arc::new(MemoryStorage::default()));
+        let mut subscription = bus
+            .subscribe(&[config().book_channel(), config().trade_channel()])
+            .await
+            .unwrap();
+        spawn_publisher(primary.clone(), feed.clone(), outbox, bus.clone(), config());
+
+        let filter = routes(primary.clone());
+        place(&filter, "bob", "sell", 5).await;
+        place(&filter, "alice", "buy", 2).await;
+
+        // The first snapshot, taken on start, lines the replica up; the two
+        // orders and the trade follow.
+        let mut mirror = Mirror::default();
+        let mut trades = replica.trade_tx.subscribe();
+        for _ in 0..4 {
+            let message = subscription.next().await.unwrap();
+            mirror.apply(&replica, &config(), message).await;
+        }
+        assert_eq!(mirror.applied, Some(2));
+        assert_eq!(depth(&replica).await, depth(&primary).await);
+        let trade = trades.try_recv().unwrap();
+        assert_eq!((trade.pair.as_str(), trade.trade.quantity), ("ETH-USDC", 2));
+        let pair = "ETH-USDC".parse().unwrap();
+        assert_eq!(replica.trade_tape.read().await.recent(&pair, 10).len(), 1);
+
+        // Update 3 is lost; 4 must not be applied on top of the gap.
+        {
+            let book = primary.orderbook.read().await;
+            feed.order_cancelled(99);
+            feed.order_cancelled(98);
+            drop(book);
+        }
+        let _lost = subscription.next().await.unwrap();
+        let after_gap = subscription.next().await.unwrap();
+        mirror.apply(&replica, &config(), after_gap).await;
+        assert_eq!(mirror.applied, None);
+
+        feed.snapshot(&*primary.orderbook.read().await);
+        let snapshot = subscription.next().await.unwrap();
+        mirror.apply(&replica, &config(), snapshot).await;
+        assert_eq!(mirror.applied, Some(4));
+        assert_eq!(depth(&replica).await, depth(&primary).await);
+    }
+}
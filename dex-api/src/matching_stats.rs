@@ -0,0 +1,324 @@
+//! Per-pair matching statistics for venue-quality monitoring.
+//!
+//! Built from the same accept, fill and cancel events the book journals, in
+//! one-minute buckets kept for a day, and rolled up into the requested
+//! interval by `GET /stats/matching`. Fills are credited to the bucket the
+//! order was submitted in, so an interval's fill ratio is the share of the
+//! quantity submitted then that has traded since.
+
+use crate::candles::Interval;
+use dex_core::types::{Order, OrderId, OrderType, Quantity, Trade, TradingPair};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use utoipa::ToSchema;
+
+/// Width of the buckets statistics are kept in.
+pub const BUCKET_SECONDS: u64 = 60;
+/// Buckets retained per pair: one day.
+pub const RETAINED_BUCKETS: u64 = 24 * 60;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    orders: u64,
+    submitted_quantity: Quantity,
+    /// Quantity since filled of the orders submitted in this bucket.
+    filled_quantity: Quantity,
+    trades: u64,
+    volume: Quantity,
+    cancels: u64,
+    /// Orders that left the book, fully filled or cancelled, and the total
+    /// time they rested.
+    exits: u64,
+    resting_seconds: u64,
+}
+
+impl Bucket {
+    fn merge(&mut self, other: &Bucket) {
+        self.orders += other.orders;
+        self.submitted_quantity = self
+            .submitted_quantity
+            .saturating_add(other.submitted_quantity);
+        self.filled_quantity = self.filled_quantity.saturating_add(other.filled_quantity);
+        self.trades += other.trades;
+        self.volume = self.volume.saturating_add(other.volume);
+        self.cancels += other.cancels;
+        self.exits += other.exits;
+        self.resting_seconds = self.resting_seconds.saturating_add(other.resting_seconds);
+    }
+}
+
+/// An order resting in the book.
+#[derive(Debug, Clone)]
+struct OpenOrder {
+    pair: TradingPair,
+    submitted_at: u64,
+    remaining: Quantity,
+}
+
+/// Matching statistics for every pair.
+#[derive(Debug, Default)]
+pub struct MatchingStats {
+    by_pair: HashMap<TradingPair, BTreeMap<u64, Bucket>>,
+    open: HashMap<OrderId, OpenOrder>,
+}
+
+impl MatchingStats {
+    /// Track orders already resting when the engine starts, so their fills
+    /// and cancels report how long they rested.
+    pub fn seed(&mut self, resting: &[Order]) {
+        for order in resting {
+            self.open.insert(
+                order.id,
+                OpenOrder {
+                    pair: order.pair.clone(),
+                    submitted_at: order.timestamp,
+                    remaining: order.quantity,
+                },
+            );
+        }
+    }
+
+    /// Record an order the book accepted and the trades it produced.
+    pub fn record_add(&mut self, order: &Order, trades: &[Trade]) {
+        let submitted = self.bucket(&order.pair, order.timestamp);
+        submitted.orders += 1;
+        submitted.submitted_quantity = submitted.submitted_quantity.saturating_add(order.quantity);
+
+        let mut remaining = order.quantity;
+        for trade in trades {
+            let executed = self.bucket(&order.pair, trade.timestamp);
+            executed.trades += 1;
+            executed.volume = executed.volume.saturating_add(trade.quantity);
+            remaining = remaining.saturating_sub(trade.quantity);
+            self.credit_fill(&order.pair, order.timestamp, trade.quantity);
+            self.fill_maker(trade);
+        }
+        if order.order_type == OrderType::Limit && remaining > 0 {
+            self.open.insert(
+                order.id,
+                OpenOrder {
+                    pair: order.pair.clone(),
+                    submitted_at: order.timestamp,
+                    remaining,
+                },
+            );
+        }
+    }
+
+    /// Record a cancel of a resting order.
+    pub fn record_cancel(&mut self, order: &Order, timestamp: u64) {
+        let submitted_at = self
+            .open
+            .remove(&order.id)
+            .map_or(order.timestamp, |open| open.submitted_at);
+        let bucket = self.bucket(&order.pair, timestamp);
+        bucket.cancels += 1;
+        bucket.exits += 1;
+        bucket.resting_seconds += timestamp.saturating_sub(submitted_at);
+    }
+
+    fn fill_maker(&mut self, trade: &Trade) {
+        let Some(maker) = self.open.get_mut(&trade.maker_order_id) else {
+            return;
+        };
+        maker.remaining = maker.remaining.saturating_sub(trade.quantity);
+        let (pair, submitted_at) = (maker.pair.clone(), maker.submitted_at);
+        if maker.remaining == 0 {
+            self.open.remove(&trade.maker_order_id);
+            let bucket = self.bucket(&pair, trade.timestamp);
+            bucket.exits += 1;
+            bucket.resting_seconds += trade.timestamp.saturating_sub(submitted_at);
+        }
+        self.credit_fill(&pair, submitted_at, trade.quantity);
+    }
+
+    /// Credit a fill to the bucket its order was submitted in, if retained.
+    fn credit_fill(&mut self, pair: &TradingPair, submitted_at: u64, quantity: Quantity) {
+        if let Some(bucket) = self
+            .by_pair
+            .get_mut(pair)
+            .and_then(|buckets| buckets.get_mut(&bucket_start(submitted_at)))
+        {
+            bucket.filled_quantity = bucket.filled_quantity.saturating_add(quantity);
+        }
+    }
+
+    fn bucket(&mut self, pair: &TradingPair, timestamp: u64) -> &mut Bucket {
+        let buckets = self.by_pair.entry(pair.clone()).or_default();
+        let start = bucket_start(timestamp);
+        let oldest = start.saturating_sub((RETAINED_BUCKETS - 1) * BUCKET_SECONDS);
+        while buckets
+            .first_key_value()
+            .is_some_and(|(first, _)| *first < oldest)
+        {
+            buckets.pop_first();
+        }
+        buckets.entry(start).or_default()
+    }
+
+    /// Statistics for `pair` per `interval`, oldest first. Intervals without
+    /// activity are left out.
+    pub fn report(&self, pair: &TradingPair, interval: Interval) -> MatchingReport {
+        let mut rolled: BTreeMap<u64, Bucket> = BTreeMap::new();
+        let mut total = Bucket::default();
+        for (start, bucket) in self.by_pair.get(pair).into_iter().flatten() {
+            rolled
+                .entry(interval.open_time(*start))
+                .or_default()
+                .merge(bucket);
+            total.merge(bucket);
+        }
+        MatchingReport {
+            pair: pair.to_string(),
+            interval_seconds: interval.as_secs(),
+            intervals: rolled
+                .into_iter()
+                .map(|(start, bucket)| IntervalStats::new(start, &bucket))
+                .collect(),
+            total: IntervalStats::new(
+                self.by_pair
+                    .get(pair)
+                    .and_then(|buckets| buckets.keys().next().copied())
+                    .unwrap_or_default(),
+                &total,
+            ),
+        }
+    }
+}
+
+fn bucket_start(timestamp: u64) -> u64 {
+    timestamp - timestamp % BUCKET_SECONDS
+}
+
+/// Matching quality of one pair over one interval.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct IntervalStats {
+    pub start: u64,
+    pub orders: u64,
+    #[schema(value_type = u64)]
+    pub submitted_quantity: Quantity,
+    #[schema(value_type = u64)]
+    pub filled_quantity: Quantity,
+    /// Share of the quantity submitted in the interval that has filled.
+    pub fill_ratio: Option<f64>,
+    pub trades: u64,
+    /// Base quantity matched in the interval.
+    #[schema(value_type = u64)]
+    pub volume: Quantity,
+    pub cancels: u64,
+    pub cancel_to_trade_ratio: Option<f64>,
+    /// Mean time orders leaving the book in the interval had rested.
+    pub average_resting_seconds: Option<f64>,
+}
+
+impl IntervalStats {
+    fn new(start: u64, bucket: &Bucket) -> Self {
+        let ratio = |numerator: u64, denominator: u64| {
+            (denominator > 0).then(|| numerator as f64 / denominator as f64)
+        };
+        Self {
+            start,
+            orders: bucket.orders,
+            submitted_quantity: bucket.submitted_quantity,
+            filled_quantity: bucket.filled_quantity,
+            fill_ratio: ratio(bucket.filled_quantity, bucket.submitted_quantity),
+            trades: bucket.trades,
+            volume: bucket.volume,
+            cancels: bucket.cancels,
+            cancel_to_trade_ratio: ratio(bucket.cancels, bucket.trades),
+            average_resting_seconds: ratio(bucket.resting_seconds, bucket.exits),
+        }
+    }
+}
+
+/// Response of `GET /stats/matching`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MatchingReport {
+    pub pair: String,
+    pub interval_seconds: u64,
+    pub intervals: Vec<IntervalStats>,
+    /// Everything retained, starting at the oldest bucket.
+    pub total: IntervalStats,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dex_core::types::OrderSide;
+
+    const T0: u64 = 1_700_000_040;
+
+    fn order(id: OrderId, side: OrderSide, quantity: Quantity, timestamp: u64) -> Order {
+        Order {
+            id,
+            trader_id: "alice".parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(1000),
+            quantity,
+            timestamp,
+        }
+    }
+
+    fn trade(maker: OrderId, taker: OrderId, quantity: Quantity, timestamp: u64) -> Trade {
+        Trade {
+            id: 0,
+            maker_order_id: maker,
+            taker_order_id: taker,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 1000,
+            quantity,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn rolls_fills_cancels_and_resting_time_into_intervals() {
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let mut stats = MatchingStats::default();
+        stats.record_add(&order(1, OrderSide::Sell, 10, T0), &[]);
+        stats.record_add(&order(2, OrderSide::Sell, 5, T0), &[]);
+        // Ninety seconds later a buyer takes all of order 1.
+        let buy = order(3, OrderSide::Buy, 10, T0 + 90);
+        stats.record_add(&buy, &[trade(1, 3, 10, T0 + 90)]);
+        stats.record_cancel(&order(2, OrderSide::Sell, 5, T0), T0 + 30);
+
+        let report = stats.report(&pair, Interval::OneMinute);
+        assert_eq!(report.intervals.len(), 2);
+        let first = &report.intervals[0];
+        assert_eq!(first.orders, 2);
+        // Order 1 filled in the next minute, order 2 was cancelled.
+        assert_eq!(first.fill_ratio, Some(10.0 / 15.0));
+        assert_eq!(first.cancels, 1);
+        assert_eq!(first.cancel_to_trade_ratio, None);
+        assert_eq!(first.average_resting_seconds, Some(30.0));
+        let second = &report.intervals[1];
+        assert_eq!((second.trades, second.volume), (1, 10));
+        assert_eq!(second.fill_ratio, Some(1.0));
+        assert_eq!(second.average_resting_seconds, Some(90.0));
+
+        let hourly = stats.report(&pair, Interval::OneHour);
+        assert_eq!(hourly.intervals.len(), 1);
+        let hour = IntervalStats {
+            start: hourly.total.start,
+            ..hourly.intervals[0].clone()
+        };
+        assert_eq!(hour, hourly.total);
+        assert_eq!(hourly.total.fill_ratio, Some(20.0 / 25.0));
+        assert_eq!(hourly.total.cancel_to_trade_ratio, Some(1.0));
+        assert_eq!(hourly.total.average_resting_seconds, Some(60.0));
+    }
+
+    #[test]
+    fn keeps_a_day_of_buckets() {
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let mut stats = MatchingStats::default();
+        stats.record_add(&order(1, OrderSide::Sell, 1, T0), &[]);
+        stats.record_add(&order(2, OrderSide::Sell, 1, T0 + 24 * 3600), &[]);
+        let report = stats.report(&pair, Interval::OneMinute);
+        assert_eq!(report.intervals.len(), 1);
+        assert_eq!(report.total.orders, 1);
+    }
+}
@@ -0,0 +1,228 @@
+//! Order-to-trade ratio limits.
+//!
+//! Once a UTC day is over, each trader's accepted orders are compared with
+//! their fills. A trader who placed at least `min_orders` orders and more
+//! than their limit per fill is penalised: a fee is recorded for billing and
+//! their rate limits are cut to a share of the usual budget for a few days.
+//! Penalties are stored, logged as security events and listed by
+//! `/account/usage`.
+
+use crate::{
+    ledger,
+    usage::{self, SECONDS_PER_DAY},
+    ApiState,
+};
+use dex_db::{DatabaseError, MessagingPenalty};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+
+/// Time past midnight before the previous day is evaluated, on top of the
+/// usage flush interval, so every instance has stored its counts.
+const EVALUATION_GRACE_SECONDS: u64 = 60;
+
+/// Who the order-to-trade ratio applies to, and what breaching it costs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessagingPolicy {
+    /// Orders per fill allowed to every trader; `None` leaves traders
+    /// without an override unlimited.
+    pub max_ratio: Option<f64>,
+    /// Limits for individual traders, such as market makers.
+    pub overrides: HashMap<String, f64>,
+    /// Orders a day below which the ratio is not enforced.
+    pub min_orders: u64,
+    /// Fee per breach, in USD.
+    pub fee: u64,
+    /// Share of the usual rate limits, in percent, left to a penalised
+    /// trader; 100 leaves them untouched.
+    pub rate_limit_percent: u32,
+    /// Days the rate limit reduction lasts.
+    pub penalty_days: u64,
+}
+
+impl Default for MessagingPolicy {
+    fn default() -> Self {
+        Self {
+            max_ratio: None,
+            overrides: HashMap::new(),
+            min_orders: 1000,
+            fee: 0,
+            rate_limit_percent: 50,
+            penalty_days: 1,
+        }
+    }
+}
+
+impl MessagingPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.max_ratio.is_some() || !self.overrides.is_empty()
+    }
+
+    /// Orders per fill `trader` is allowed, if limited.
+    pub fn limit_for(&self, trader: &str) -> Option<f64> {
+        self.overrides.get(trader).copied().or(self.max_ratio)
+    }
+
+    /// The penalty for `trader` placing `orders` and getting `fills` on
+    /// `day`, if that breaches their limit.
+    pub fn assess(
+        &self,
+        trader: &str,
+        day: u64,
+        orders: u64,
+        fills: u64,
+    ) -> Option<MessagingPenalty> {
+        let max_ratio = self.limit_for(trader)?;
+        if orders < self.min_orders {
+            return None;
+        }
+        // Without a single fill any limit is breached.
+        if fills > 0 && orders as f64 / fills as f64 <= max_ratio {
+            return None;
+        }
+        Some(MessagingPenalty {
+            subject: trader.to_string(),
+            day,
+            orders,
+            fills,
+            max_ratio,
+            fee: self.fee,
+            rate_limit_percent: self.rate_limit_percent.min(100),
+            // The reduction starts when the day is evaluated, at its end.
+            expires_at: day + SECONDS_PER_DAY * (1 + self.penalty_days),
+        })
+    }
+}
+
+fn emit(penalty: &MessagingPenalty, now: u64) {
+    tracing::warn!(
+        target: "security",
+        event = "messaging_penalty",
+        account = %penalty.subject,
+        date = %usage::date_of(penalty.day),
+        orders = penalty.orders,
+        fills = penalty.fills,
+        max_ratio = penalty.max_ratio,
+        fee = penalty.fee,
+        rate_limit_percent = penalty.rate_limit_percent,
+        expires_at = penalty.expires_at,
+        timestamp = now,
+        "messaging penalty"
+    );
+}
+
+/// Cut the penalised trader's rate limits until the penalty expires.
+fn apply(state: &ApiState, penalty: &MessagingPenalty, now: u64) {
+    if penalty.rate_limit_percent < 100 && penalty.expires_at > now {
+        state.rate_limiter.reduce(
+            &penalty.subject,
+            penalty.rate_limit_percent,
+            Duration::from_secs(penalty.expires_at - now),
+        );
+    }
+}
+
+/// Penalise every trader who breached their limit on `day`. Traders already
+/// penalised for the day are skipped, so a day may be evaluated again.
+pub async fn evaluate(state: &ApiState, day: u64) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+    let policy = &state.config.messaging_policy;
+    if !policy.is_enabled() {
+        return Ok(Vec::new());
+    }
+    let mut totals: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+    for rollup in state.usage_repo.load_day_usage(day).await? {
+        let (orders, fills) = totals.entry(rollup.subject).or_default();
+        *orders += rollup.orders;
+        *fills += rollup.fills;
+    }
+
+    let now = state.determinism.now().unwrap_or_default();
+    let mut penalties = Vec::new();
+    for (trader, (orders, fills)) in totals {
+        let Some(penalty) = policy.assess(&trader, day, orders, fills) else {
+            continue;
+        };
+        // Booked first: the entry is keyed by trader and day, so a retry
+        // after a failed save books nothing twice, while a saved penalty is
+        // skipped on the next evaluation.
+        ledger::book(state, ledger::penalty(&penalty, now)).await?;
+        if !state.usage_repo.save_penalty(&penalty).await? {
+            continue;
+        }
+        apply(state, &penalty, now);
+        emit(&penalty, now);
+        penalties.push(penalty);
+    }
+    Ok(penalties)
+}
+
+/// Reapply the rate limit reductions still in force, after a restart.
+pub async fn restore(state: &ApiState) -> Result<usize, DatabaseError> {
+    let now = state.determinism.now().unwrap_or_default();
+    let penalties = state.usage_repo.load_active_penalties(now).await?;
+    for penalty in &penalties {
+        apply(state, penalty, now);
+    }
+    Ok(penalties.len())
+}
+
+/// Restore active penalties, then evaluate each day shortly after it ends.
+/// The previous day is evaluated on start in case the server was down at
+/// midnight.
+pub fn spawn_evaluation(state: ApiState) {
+    if !state.config.messaging_policy.is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        if let Err(err) = restore(&state).await {
+            tracing::error!(error = ?err, "failed to restore messaging penalties");
+        }
+        let grace = state.config.usage_flush_interval_seconds + EVALUATION_GRACE_SECONDS;
+        loop {
+            let now = state.determinism.now().unwrap_or_default();
+            let yesterday =
+                usage::day_of(now.saturating_sub(grace)).saturating_sub(SECONDS_PER_DAY);
+            if let Err(err) = usage::flush(&state).await {
+                tracing::error!(error = ?err, "failed to store API usage");
+            }
+            if let Err(err) = evaluate(&state, yesterday).await {
+                tracing::error!(error = ?err, "failed to evaluate order-to-trade ratios");
+            }
+            let next = yesterday + 2 * SECONDS_PER_DAY + grace;
+            tokio::time::sleep(Duration::from_secs(next.saturating_sub(now).max(1))).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breaches_need_enough_orders_and_too_few_fills() {
+        let mut policy = MessagingPolicy {
+            max_ratio: Some(20.0),
+            min_orders: 100,
+            fee: 250,
+            ..MessagingPolicy::default()
+        };
+        policy.overrides.insert("mm".into(), 500.0);
+        let day = 1_700_006_400;
+
+        assert_eq!(policy.assess("alice", day, 99, 0), None);
+        assert_eq!(policy.assess("alice", day, 200, 10), None);
+        let penalty = policy.assess("alice", day, 210, 10).expect("breach");
+        assert_eq!((penalty.max_ratio, penalty.fee), (20.0, 250));
+        assert_eq!(penalty.rate_limit_percent, 50);
+        assert_eq!(penalty.expires_at, day + 2 * SECONDS_PER_DAY);
+        assert!(policy.assess("alice", day, 100, 0).is_some());
+
+        assert_eq!(policy.assess("mm", day, 4000, 10), None);
+        assert!(policy.assess("mm", day, 6000, 10).is_some());
+
+        policy.max_ratio = None;
+        assert_eq!(policy.assess("alice", day, 1000, 0), None);
+        assert!(policy.is_enabled());
+    }
+}
@@ -0,0 +1,382 @@
+//! Prometheus text exposition for operational metrics.
+
+use crate::{rate_limit::RateLimitCounters, with_state, ApiState};
+use dex_core::quantum_consensus::{ConsensusMetrics, ShardMetrics};
+use dex_db::{
+    pool::PoolStats,
+    resilience::{BreakerState, DbMetricsSnapshot},
+};
+use std::fmt::Write;
+use warp::Filter;
+
+/// Content type for the Prometheus text exposition format.
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Append a single metric with its HELP/TYPE header.
+pub fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Render database retry and circuit breaker metrics.
+pub fn render_db_metrics(out: &mut String, db: &DbMetricsSnapshot) {
+    let _ = writeln!(
+        out,
+        "# HELP dex_db_breaker_state Circuit breaker state (1 for the active state)."
+    );
+    let _ = writeln!(out, "# TYPE dex_db_breaker_state gauge");
+    for state in [
+        BreakerState::Closed,
+        BreakerState::Open,
+        BreakerState::HalfOpen,
+    ] {
+        let _ = writeln!(
+            out,
+            "dex_db_breaker_state{{state=\"{}\"}} {}",
+            state.as_str(),
+            u64::from(db.breaker_state == state)
+        );
+    }
+    write_metric(
+        out,
+        "dex_db_operations_total",
+        "counter",
+        "Database operation attempts.",
+        db.operations,
+    );
+    write_metric(
+        out,
+        "dex_db_failures_total",
+        "counter",
+        "Database operations that failed after retries.",
+        db.failures,
+    );
+    write_metric(
+        out,
+        "dex_db_retries_total",
+        "counter",
+        "Retries of transient database failures.",
+        db.retries,
+    );
+    write_metric(
+        out,
+        "dex_db_rejected_total",
+        "counter",
+        "Operations rejected while the circuit breaker was open.",
+        db.rejected,
+    );
+    write_metric(
+        out,
+        "dex_db_breaker_opened_total",
+        "counter",
+        "Times the circuit breaker opened.",
+        db.breaker_opened,
+    );
+    write_metric(
+        out,
+        "dex_db_breaker_closed_total",
+        "counter",
+        "Times the circuit breaker closed after recovery.",
+        db.breaker_closed,
+    );
+    write_metric(
+        out,
+        "dex_db_probes_total",
+        "counter",
+        "Database recovery probes executed.",
+        db.probes,
+    );
+    write_metric(
+        out,
+        "dex_db_probe_failures_total",
+        "counter",
+        "Database recovery probes that failed.",
+        db.probe_failures,
+    );
+    write_metric(
+        out,
+        "dex_db_timeouts_total",
+        "counter",
+        "Database query attempts that exceeded the query timeout.",
+        db.timeouts,
+    );
+    write_metric(
+        out,
+        "dex_db_slow_queries_total",
+        "counter",
+        "Database queries slower than the slow-query threshold.",
+        db.slow_queries,
+    );
+    write_metric(
+        out,
+        "dex_db_pool_timeouts_total",
+        "counter",
+        "Database query attempts that found no free pooled connection in time.",
+        db.pool_timeouts,
+    );
+    write_metric(
+        out,
+        "dex_db_replica_fallbacks_total",
+        "counter",
+        "Reads the read replica failed that were retried on the primary.",
+        db.replica_fallbacks,
+    );
+}
+
+/// Render how many pooled connections are busy, idle and allowed.
+pub fn render_pool_metrics(out: &mut String, pool: &PoolStats) {
+    let _ = writeln!(
+        out,
+        "# HELP dex_db_pool_connections Open pooled connections by state."
+    );
+    let _ = writeln!(out, "# TYPE dex_db_pool_connections gauge");
+    for (state, count) in [("in_use", pool.in_use()), ("idle", pool.idle)] {
+        let _ = writeln!(
+            out,
+            "dex_db_pool_connections{{state=\"{}\"}} {}",
+            state, count
+        );
+    }
+    write_metric(
+        out,
+        "dex_db_pool_max_connections",
+        "gauge",
+        "Connections the pool may open.",
+        pool.max_connections.into(),
+    );
+}
+
+/// Render allowed and rate-limited request counts per route class.
+pub fn render_rate_limit_metrics(out: &mut String, counters: &[RateLimitCounters]) {
+    write_class_counters(
+        out,
+        "dex_api_rate_limit_allowed_total",
+        "Requests admitted by the rate limiter.",
+        counters,
+        |counter| counter.allowed,
+    );
+    write_class_counters(
+        out,
+        "dex_api_rate_limited_total",
+        "Requests refused with 429 by the rate limiter.",
+        counters,
+        |counter| counter.limited,
+    );
+}
+
+fn write_class_counters(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    counters: &[RateLimitCounters],
+    value: impl Fn(&RateLimitCounters) -> u64,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    for counter in counters {
+        let _ = writeln!(
+            out,
+            "{}{{class=\"{}\"}} {}",
+            name,
+            counter.class.as_str(),
+            value(counter)
+        );
+    }
+}
+
+/// Render validator telemetry: rounds and leader distribution overall,
+/// round durations, vote participation and finality lag per shard.
+pub fn render_consensus_metrics(out: &mut String, consensus: &ConsensusMetrics) {
+    write_metric(
+        out,
+        "dex_consensus_rounds_total",
+        "counter",
+        "Consensus rounds started across all shards.",
+        consensus.rounds,
+    );
+    write_metric(
+        out,
+        "dex_consensus_global_finalized_height",
+        "gauge",
+        "Height finalized on every shard.",
+        consensus.global_finalized_height,
+    );
+    let _ = writeln!(
+        out,
+        "# HELP dex_consensus_leader_fairness Jain's fairness index of rounds led per validator (1 is perfectly even)."
+    );
+    let _ = writeln!(out, "# TYPE dex_consensus_leader_fairness gauge");
+    let _ = writeln!(
+        out,
+        "dex_consensus_leader_fairness {}",
+        consensus.leader_fairness
+    );
+    let _ = writeln!(
+        out,
+        "# HELP dex_consensus_leader_rounds_total Rounds led by each validator."
+    );
+    let _ = writeln!(out, "# TYPE dex_consensus_leader_rounds_total counter");
+    for (validator, rounds) in &consensus.leaders {
+        let _ = writeln!(
+            out,
+            "dex_consensus_leader_rounds_total{{validator=\"{}\"}} {}",
+            escape_label(validator),
+            rounds
+        );
+    }
+
+    write_shard_series(
+        out,
+        "dex_consensus_shard_rounds_total",
+        "counter",
+        "Consensus rounds completed on each shard.",
+        &consensus.shards,
+        |shard| shard.rounds as f64,
+    );
+    write_shard_series(
+        out,
+        "dex_consensus_round_duration_ms",
+        "gauge",
+        "Duration of each shard's latest round in milliseconds.",
+        &consensus.shards,
+        |shard| shard.last_round_ms as f64,
+    );
+    write_shard_series(
+        out,
+        "dex_consensus_round_duration_mean_ms",
+        "gauge",
+        "Mean round duration on each shard in milliseconds.",
+        &consensus.shards,
+        |shard| shard.mean_round_ms,
+    );
+    write_shard_series(
+        out,
+        "dex_consensus_vote_participation",
+        "gauge",
+        "Share of each shard's validators that voted, over its rounds.",
+        &consensus.shards,
+        |shard| shard.vote_participation,
+    );
+    write_shard_series(
+        out,
+        "dex_consensus_finality_lag_blocks",
+        "gauge",
+        "Blocks produced on each shard but not yet finalized.",
+        &consensus.shards,
+        |shard| shard.finality_lag as f64,
+    );
+}
+
+fn write_shard_series(
+    out: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    shards: &[ShardMetrics],
+    value: impl Fn(&ShardMetrics) -> f64,
+) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    for shard in shards {
+        let _ = writeln!(
+            out,
+            "{}{{shard=\"{}\"}} {}",
+            name,
+            shard.shard_id,
+            value(shard)
+        );
+    }
+}
+
+/// Escape a label value for the text exposition format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Prometheus metrics, GET /metrics
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("metrics")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and_then(handle_metrics)
+        .boxed()
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    summary = "Prometheus metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+    ),
+)]
+async fn handle_metrics(state: ApiState) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut body = String::new();
+    write_metric(
+        &mut body,
+        "dex_api_degraded",
+        "gauge",
+        "1 while order entry is suspended because storage is unavailable.",
+        u64::from(!state.storage.is_available()),
+    );
+    if let Some(database) = &state.database {
+        render_db_metrics(&mut body, &database.metrics());
+        render_pool_metrics(&mut body, &database.pool_stats());
+    }
+    render_rate_limit_metrics(&mut body, &state.rate_limiter.counters());
+    render_consensus_metrics(&mut body, &state.consensus.read().await.metrics());
+    Ok(warp::reply::with_header(body, "content-type", CONTENT_TYPE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_breaker_state_and_counters() {
+        let snapshot = DbMetricsSnapshot {
+            breaker_state: BreakerState::Open,
+            operations: 10,
+            failures: 3,
+            retries: 4,
+            rejected: 2,
+            breaker_opened: 1,
+            breaker_closed: 0,
+            probes: 5,
+            probe_failures: 5,
+            timeouts: 0,
+            slow_queries: 0,
+            pool_timeouts: 2,
+            replica_fallbacks: 0,
+        };
+        let mut out = String::new();
+        render_db_metrics(&mut out, &snapshot);
+        assert!(out.contains("dex_db_breaker_state{state=\"open\"} 1"));
+        assert!(out.contains("dex_db_breaker_state{state=\"closed\"} 0"));
+        assert!(out.contains("dex_db_retries_total 4"));
+        assert!(out.contains("dex_db_pool_timeouts_total 2"));
+    }
+
+    #[test]
+    fn renders_pool_saturation() {
+        let mut out = String::new();
+        render_pool_metrics(
+            &mut out,
+            &PoolStats {
+                size: 5,
+                idle: 1,
+                max_connections: 5,
+            },
+        );
+        assert!(out.contains("dex_db_pool_connections{state=\"in_use\"} 4"));
+        assert!(out.contains("dex_db_pool_connections{state=\"idle\"} 1"));
+        assert!(out.contains("dex_db_pool_max_connections 5"));
+    }
+}
@@ -0,0 +1,526 @@
+//! Multilateral netting of settlement batches.
+//!
+//! Settling trades gross takes two transfers per trade: the base token from
+//! seller to buyer and the quote token back. Netting collapses a batch into
+//! each trader's net position per token, then pays the traders owed a token
+//! from those who owe it, largest amounts first. That takes at most one
+//! transfer fewer than the traders with a non-zero position in the token,
+//! however many trades they made with each other.
+//!
+//! A batch covers the trades after the previous batch that are older than
+//! `trade_adjust_window_seconds`, since those can no longer be busted or
+//! re-priced. Each batch is stored with its positions and transfers so that
+//! settlement can be audited against the trades it came from.
+
+use crate::{
+    admin_only, auth::Claims, error_reply, ledger, rate_limit::RouteClass, rate_limited,
+    storage_error_reply, ApiState, ErrorResponse,
+};
+use dex_core::types::{Order, OrderId, OrderSide, Trade};
+use dex_db::{
+    repository::MAX_TRADE_PAGE, DatabaseError, NetPosition, NetTransfer, NettingSet, TradeFilter,
+};
+use serde::Serialize;
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// Most trades netted in one batch; later trades wait for the next.
+pub const MAX_BATCH_TRADES: usize = 100_000;
+
+/// Why [`net_batch`] failed.
+#[derive(Debug)]
+pub enum NettingError {
+    /// No trades old enough to settle since the previous batch.
+    NothingToNet,
+    /// Another batch after the same trade was stored first.
+    Conflict,
+    Storage(DatabaseError),
+}
+
+impl From<DatabaseError> for NettingError {
+    fn from(err: DatabaseError) -> Self {
+        NettingError::Storage(err)
+    }
+}
+
+/// A trade with the traders on each side.
+#[derive(Debug, Clone, Copy)]
+pub struct Fill<'a> {
+    pub trade: &'a Trade,
+    pub buyer: &'a str,
+    pub seller: &'a str,
+}
+
+/// Gross flows per trader and token, ordered by token then trader.
+pub fn positions<'a>(fills: impl IntoIterator<Item = Fill<'a>>) -> Vec<NetPosition> {
+    let mut flows: BTreeMap<(&str, &str), (u128, u128)> = BTreeMap::new();
+    let mut add = |token, trader, delivered: u128, received: u128| {
+        let flow = flows.entry((token, trader)).or_default();
+        flow.0 += delivered;
+        flow.1 += received;
+    };
+    for fill in fills {
+        let (base, quote) = (
+            fill.trade.base_token.as_str(),
+            fill.trade.quote_token.as_str(),
+        );
+        let quantity = u128::from(fill.trade.quantity);
+        let notional = fill.trade.notional().value();
+        add(base, fill.seller, quantity, 0);
+        add(base, fill.buyer, 0, quantity);
+        add(quote, fill.buyer, notional, 0);
+        add(quote, fill.seller, 0, notional);
+    }
+    flows
+        .into_iter()
+        .map(|((token, trader), (delivered, received))| NetPosition {
+            trader_id: trader.to_string(),
+            token: token.to_string(),
+            delivered,
+            received,
+        })
+        .collect()
+}
+
+/// Traders and the amounts they owe or are owed.
+type Balances<'a> = Vec<(&'a str, u128)>;
+
+/// Transfers settling `positions`, token by token. The largest debtor pays
+/// the largest creditor until one of them is square, so every transfer
+/// settles at least one trader.
+pub fn transfers(positions: &[NetPosition]) -> Vec<NetTransfer> {
+    let mut by_token: BTreeMap<&str, (Balances, Balances)> = BTreeMap::new();
+    for position in positions {
+        let (debtors, creditors) = by_token.entry(position.token.as_str()).or_default();
+        let net = position.net();
+        if net < 0 {
+            debtors.push((&position.trader_id, net.unsigned_abs()));
+        } else if net > 0 {
+            creditors.push((&position.trader_id, net.unsigned_abs()));
+        }
+    }
+
+    let mut transfers = Vec::new();
+    for (token, (mut debtors, mut creditors)) in by_token {
+        let largest_first = |a: &(&str, u128), b: &(&str, u128)| b.1.cmp(&a.1).then(a.0.cmp(b.0));
+        debtors.sort_by(largest_first);
+        creditors.sort_by(largest_first);
+        let (mut debtors, mut creditors) = (debtors.into_iter(), creditors.into_iter());
+        let (mut debtor, mut creditor) = (debtors.next(), creditors.next());
+        while let (Some((from, owed)), Some((to, due))) = (debtor, creditor) {
+            let amount = owed.min(due);
+            transfers.push(NetTransfer {
+                token: token.to_string(),
+                from_trader: from.to_string(),
+                to_trader: to.to_string(),
+                amount,
+            });
+            debtor = if owed > amount {
+                Some((from, owed - amount))
+            } else {
+                debtors.next()
+            };
+            creditor = if due > amount {
+                Some((to, due - amount))
+            } else {
+                creditors.next()
+            };
+        }
+    }
+    transfers
+}
+
+/// The trades after `after` that are old enough to settle, oldest first.
+async fn settleable_trades(
+    state: &ApiState,
+    after: u64,
+    cutoff: u64,
+) -> Result<Vec<Trade>, DatabaseError> {
+    let mut trades = Vec::new();
+    loop {
+        let filter = TradeFilter {
+            after_id: Some(trades.last().map_or(after, |trade: &Trade| trade.id)),
+            limit: MAX_TRADE_PAGE,
+            ..TradeFilter::default()
+        };
+        let page = state.trades.get_trades(&filter).await?;
+        let full = page.len() as u32 == filter.page_size();
+        for trade in page {
+            // Trade IDs follow execution order, so the batch ends at the
+            // first trade still inside the adjustment window.
+            if trade.timestamp >= cutoff || trades.len() == MAX_BATCH_TRADES {
+                return Ok(trades);
+            }
+            trades.push(trade);
+        }
+        if !full {
+            return Ok(trades);
+        }
+    }
+}
+
+/// Net the trades since the previous batch on behalf of the administrator
+/// `created_by`, and store the result.
+pub async fn net_batch(state: &ApiState, created_by: &str) -> Result<NettingSet, NettingError> {
+    let after = state.settlement_repo.last_netted_trade_id().await?;
+    let now = state.determinism.now().unwrap_or_default();
+    let cutoff = now.saturating_sub(state.config.trade_adjust_window_seconds);
+    let trades = settleable_trades(state, after, cutoff).await?;
+    let Some(last_trade_id) = trades.last().map(|trade| trade.id) else {
+        return Err(NettingError::NothingToNet);
+    };
+
+    let mut orders: HashMap<OrderId, Option<Order>> = HashMap::new();
+    for trade in &trades {
+        for order_id in [trade.maker_order_id, trade.taker_order_id] {
+            if let Entry::Vacant(entry) = orders.entry(order_id) {
+                entry.insert(state.orders.load_order(order_id).await?);
+            }
+        }
+    }
+    let mut fills = Vec::with_capacity(trades.len());
+    let mut unattributed_trade_ids = Vec::new();
+    for trade in &trades {
+        let maker = orders[&trade.maker_order_id].as_ref();
+        let taker = orders[&trade.taker_order_id].as_ref();
+        match (maker, taker) {
+            (Some(maker), Some(taker)) => {
+                let (buyer, seller) = match maker.side {
+                    OrderSide::Buy => (maker, taker),
+                    OrderSide::Sell => (taker, maker),
+                };
+                fills.push(Fill {
+                    trade,
+                    buyer: buyer.trader_id.as_str(),
+                    seller: seller.trader_id.as_str(),
+                });
+            }
+            _ => unattributed_trade_ids.push(trade.id),
+        }
+    }
+
+    let positions = positions(fills.iter().copied());
+    let mut set = NettingSet {
+        id: 0,
+        created_at: now,
+        created_by: created_by.to_string(),
+        after_trade_id: after,
+        last_trade_id,
+        trade_count: fills.len() as u64,
+        gross_transfers: 2 * fills.len() as u64,
+        unattributed_trade_ids,
+        transfers: transfers(&positions),
+        positions,
+    };
+    set.id = state
+        .settlement_repo
+        .save_netting_set(&set)
+        .await?
+        .ok_or(NettingError::Conflict)?;
+    match ledger::settlement(&set) {
+        // A set of trades that all cancel out moves nothing.
+        Some(entry) if entry.postings.is_empty() => {}
+        Some(entry) => ledger::book(state, entry).await?,
+        None => tracing::error!(
+            netting_set_id = set.id,
+            "netting set left out of the ledger"
+        ),
+    }
+    if !set.unattributed_trade_ids.is_empty() {
+        tracing::warn!(
+            netting_set_id = set.id,
+            trade_ids = ?set.unattributed_trade_ids,
+            "netting set left out trades without stored orders"
+        );
+    }
+    Ok(set)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = NettingPosition)]
+pub struct PositionResponse {
+    pub trader_id: String,
+    pub token: String,
+    pub delivered: u128,
+    pub received: u128,
+    /// Positive when the trader is owed the token.
+    pub net: i128,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = NettingTransfer)]
+pub struct TransferResponse {
+    pub token: String,
+    pub from_trader: String,
+    pub to_trader: String,
+    pub amount: u128,
+}
+
+/// A stored netting set, as returned by the settlement endpoints.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = NettingSet)]
+pub struct NettingSetResponse {
+    pub id: u64,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub created_by: String,
+    pub after_trade_id: u64,
+    pub last_trade_id: u64,
+    pub trade_count: u64,
+    pub gross_transfers: u64,
+    pub net_transfers: u64,
+    pub unattributed_trade_ids: Vec<u64>,
+    pub positions: Vec<PositionResponse>,
+    pub transfers: Vec<TransferResponse>,
+}
+
+impl From<NettingSet> for NettingSetResponse {
+    fn from(set: NettingSet) -> Self {
+        Self {
+            id: set.id,
+            created_at: set.created_at,
+            created_by: set.created_by,
+            after_trade_id: set.after_trade_id,
+            last_trade_id: set.last_trade_id,
+            trade_count: set.trade_count,
+            gross_transfers: set.gross_transfers,
+            net_transfers: set.transfers.len() as u64,
+            unattributed_trade_ids: set.unattributed_trade_ids,
+            positions: set
+                .positions
+                .into_iter()
+                .map(|position| PositionResponse {
+                    net: position.net(),
+                    trader_id: position.trader_id,
+                    token: position.token,
+                    delivered: position.delivered,
+                    received: position.received,
+                })
+                .collect(),
+            transfers: set
+                .transfers
+                .into_iter()
+                .map(|transfer| TransferResponse {
+                    token: transfer.token,
+                    from_trader: transfer.from_trader,
+                    to_trader: transfer.to_trader,
+                    amount: transfer.amount,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Settlement netting, e.g. POST /admin/settlement/netting
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let netting = warp::path("admin")
+        .and(warp::path("settlement"))
+        .and(warp::path("netting"));
+
+    let net_batch = netting
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and_then(handle_net_settlement)
+        .boxed();
+
+    let netting_set = netting
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_netting_set)
+        .boxed();
+
+    net_batch.or(netting_set)
+}
+
+/// Net the trades settled since the previous batch and store the result.
+#[utoipa::path(
+    post,
+    path = "/admin/settlement/netting",
+    summary = "Net the trades since the previous settlement batch into transfers (administrators only)",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 201, description = "Netting set stored", body = NettingSetResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 409, description = "No trades outside the adjustment window, or another run netted them first", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_net_settlement(
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    use NettingError;
+
+    Ok(match net_batch(&state, &claims.sub).await {
+        Ok(set) => warp::reply::with_status(
+            warp::reply::json(&NettingSetResponse::from(set)),
+            StatusCode::CREATED,
+        ),
+        Err(NettingError::NothingToNet) => error_reply(
+            "nothing_to_net",
+            "no trades outside the adjustment window since the previous batch",
+            StatusCode::CONFLICT,
+        ),
+        Err(NettingError::Conflict) => error_reply(
+            "netting_conflict",
+            "another run netted this batch first; retry to net the next one",
+            StatusCode::CONFLICT,
+        ),
+        Err(NettingError::Storage(err)) => {
+            tracing::error!(error = ?err, "failed to net settlement batch");
+            storage_error_reply(&err, "failed to net settlement batch")
+        }
+    })
+}
+
+/// Handler for a stored netting set
+#[utoipa::path(
+    get,
+    path = "/admin/settlement/netting/{netting_set_id}",
+    summary = "A stored netting set with its positions and transfers (administrators only)",
+    params(("netting_set_id" = u64, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "The netting set", body = NettingSetResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 404, description = "No netting set with this id", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_netting_set(
+    id: u64,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match state.settlement_repo.load_netting_set(id).await {
+        Ok(Some(set)) => Ok(warp::reply::with_status(
+            warp::reply::json(&NettingSetResponse::from(set)),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(error_reply(
+            "netting_set_not_found",
+            "no netting set with this id",
+            StatusCode::NOT_FOUND,
+        )),
+        Err(err) => {
+            tracing::error!(netting_set_id = id, error = ?err, "failed to load netting set");
+            Ok(storage_error_reply(&err, "failed to load netting set"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(id: u64, price: u64, quantity: u64) -> Trade {
+        Trade {
+            id,
+            maker_order_id: id,
+            taker_order_id: id + 100,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price,
+            quantity,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn circular_trades_net_to_fewer_transfers() {
+        let trades = [trade(1, 100, 5), trade(2, 100, 5), trade(3, 110, 2)];
+        let fills = [
+            Fill {
+                trade: &trades[0],
+                buyer: "alice",
+                seller: "bob",
+            },
+            Fill {
+                trade: &trades[1],
+                buyer: "bob",
+                seller: "carol",
+            },
+            Fill {
+                trade: &trades[2],
+                buyer: "carol",
+                seller: "alice",
+            },
+        ];
+        let positions = positions(fills);
+        let net: Vec<(&str, &str, i128)> = positions
+            .iter()
+            .map(|p| (p.token.as_str(), p.trader_id.as_str(), p.net()))
+            .collect();
+        assert_eq!(
+            net,
+            [
+                ("ETH", "alice", 3),
+                ("ETH", "bob", 0),
+                ("ETH", "carol", -3),
+                ("USDC", "alice", -280),
+                ("USDC", "bob", 0),
+                ("USDC", "carol", 280),
+            ]
+        );
+        // Bob passed the same 5 ETH on, so he settles nothing.
+        assert_eq!(positions[1].delivered, 5);
+        assert_eq!(positions[1].received, 5);
+
+        let transfers = transfers(&positions);
+        assert_eq!(
+            transfers,
+            [
+                NetTransfer {
+                    token: "ETH".into(),
+                    from_trader: "carol".into(),
+                    to_trader: "alice".into(),
+                    amount: 3,
+                },
+                NetTransfer {
+                    token: "USDC".into(),
+                    from_trader: "alice".into(),
+                    to_trader: "carol".into(),
+                    amount: 280,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn largest_debts_are_paid_to_largest_credits_first() {
+        let position = |trader: &str, delivered, received| NetPosition {
+            trader_id: trader.into(),
+            token: "USDC".into(),
+            delivered,
+            received,
+        };
+        let positions = [
+            position("alice", 70, 0),
+            position("bob", 30, 0),
+            position("carol", 0, 60),
+            position("dave", 0, 40),
+        ];
+        let paid: Vec<(String, String, u128)> = transfers(&positions)
+            .into_iter()
+            .map(|t| (t.from_trader, t.to_trader, t.amount))
+            .collect();
+        assert_eq!(
+            paid,
+            [
+                ("alice".into(), "carol".into(), 60),
+                ("alice".into(), "dave".into(), 10),
+                ("bob".into(), "dave".into(), 30),
+            ]
+        );
+    }
+}
@@ -0,0 +1,428 @@
+//! OpenAPI description of the REST API.
+//!
+//! Served as `GET /openapi.json`, with a Swagger UI page at `GET /docs`, so
+//! client SDKs can be generated from it. The document is generated from the
+//! `#[utoipa::path]` annotations on the handlers and the `ToSchema` derives
+//! on the types they take and return; the tests check that every documented
+//! route exists and that the response types serialize to the documented
+//! schemas.
+
+use crate::caching;
+use serde_json::Value;
+use std::sync::Arc;
+use utoipa::{
+    openapi::{
+        security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+        ContentBuilder, OpenApi as Document, Ref, RefOr, Response, ResponseBuilder,
+    },
+    Modify, OpenApi,
+};
+use warp::Filter;
+
+/// Swagger UI page for `/docs`, loading the viewer from a CDN.
+pub const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>DEX-OS API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Schema of a string field that takes one of `values`.
+pub(crate) fn string_enum(values: &[&str]) -> utoipa::openapi::Object {
+    utoipa::openapi::ObjectBuilder::new()
+        .schema_type(utoipa::openapi::Type::String)
+        .enum_values(Some(values.iter().copied()))
+        .build()
+}
+
+/// Every documented operation. Routes not listed here are missing from the
+/// document: the document itself and its viewer are left out on purpose.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "DEX-OS API",
+        description = "Order entry, market data and account endpoints. Prices and quantities are integer ticks. Messages on the WebSocket streams (/ws, /ws/trades/{pair}, /ws/orders) are described in the README.",
+    ),
+    paths(
+        crate::amm::handle_get_pool_snapshot,
+        crate::amm::handle_get_provider_summary,
+        crate::api_keys::handle_create_api_key,
+        crate::api_keys::handle_set_api_key_allowed_ips,
+        crate::api_keys::handle_list_api_keys,
+        crate::api_keys::handle_revoke_api_key,
+        crate::audit::handle_get_audit_log,
+        crate::bridge::handle_bridge_deposit,
+        crate::bridge::handle_trust_bridge_block,
+        crate::chain::handle_get_chain_metrics,
+        crate::chain::handle_get_chain_blocks,
+        crate::chain::handle_get_chain_transaction,
+        crate::funding::handle_request_withdrawal,
+        crate::funding::handle_get_account_funding,
+        crate::funding::handle_get_admin_funding,
+        crate::funding::handle_settle_withdrawal,
+        crate::funds::handle_get_account_balances,
+        crate::ledger::handle_get_ledger_entries,
+        crate::ledger::handle_get_ledger_balances,
+        crate::margin::handle_get_account_margin,
+        crate::market_data::handle_get_prices,
+        crate::market_data::handle_get_depth,
+        crate::market_data::handle_get_recent_trades,
+        crate::market_data::handle_get_candles,
+        crate::market_data::handle_get_matching_stats,
+        crate::market_data::handle_get_usd_prices,
+        crate::metrics::handle_metrics,
+        crate::netting::handle_net_settlement,
+        crate::netting::handle_get_netting_set,
+        crate::orders::handle_create_order,
+        crate::orders::handle_risk_check,
+        crate::orders::handle_cancel_order,
+        crate::orders::handle_get_trades_for_order,
+        crate::orders::handle_get_order_receipt,
+        crate::orders::handle_get_trades_for_trader,
+        crate::sequencing::handle_get_sequencing_key,
+        crate::sessions::handle_get_jwks,
+        crate::sessions::handle_shared_token,
+        crate::sessions::handle_totp_enroll,
+        crate::sessions::handle_totp_confirm,
+        crate::sessions::handle_totp_disable,
+        crate::sessions::handle_wallet_challenge,
+        crate::sessions::handle_wallet_token,
+        crate::sessions::handle_refresh_token,
+        crate::sessions::handle_revoke_token,
+        crate::sessions::handle_logout,
+        crate::sessions::handle_list_sessions,
+        crate::sessions::handle_revoke_session,
+        crate::settings::handle_get_settings,
+        crate::settings::handle_reload_settings,
+        crate::streams::handle_stream_ws,
+        crate::streams::handle_trades_ws,
+        crate::streams::handle_orders_ws,
+        crate::trade_corrections::handle_bust_trade,
+        crate::trade_corrections::handle_adjust_trade,
+        crate::trade_corrections::handle_get_trade_adjustments,
+        crate::usage::handle_get_account_usage,
+        crate::webhooks::handle_create_webhook,
+        crate::webhooks::handle_list_webhooks,
+        crate::webhooks::handle_delete_webhook,
+        crate::wrapped::handle_get_wrapped_supply,
+        crate::wrapped::handle_report_custody_balance,
+        crate::wrapped::handle_burn_wrapped,
+    ),
+    modifiers(&CommonResponses, &SecuritySchemes),
+)]
+struct ApiDoc;
+
+/// Responses every operation may give that the handlers do not produce
+/// themselves: rate limiting and IP allowlists are enforced by filters in
+/// front of them. Also names operations after their handlers, without the
+/// `handle_` prefix.
+struct CommonResponses;
+
+impl Modify for CommonResponses {
+    fn modify(&self, openapi: &mut Document) {
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            let operations = [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.patch,
+            ];
+            for operation in operations.into_iter().flatten() {
+                if let Some(id) = &mut operation.operation_id {
+                    if let Some(name) = id.strip_prefix("handle_") {
+                        *id = name.to_string();
+                    }
+                }
+                let responses = &mut operation.responses.responses;
+                // Scrapers are not rate limited.
+                if path != "/metrics" {
+                    responses.entry("429".to_string()).or_insert_with(|| {
+                        error_response("Rate limit exceeded; retry after Retry-After seconds")
+                    });
+                }
+                if operation.security.is_some() {
+                    match responses.get_mut("403") {
+                        Some(RefOr::T(forbidden)) => forbidden.description.push_str(
+                            ", or credentials used from outside their IP allowlist (ip_not_allowed)",
+                        ),
+                        _ => {
+                            responses.insert(
+                                "403".to_string(),
+                                error_response(
+                                    "Credentials used from outside their IP allowlist (ip_not_allowed)",
+                                ),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A response carrying an `ErrorResponse` body.
+fn error_response(description: &str) -> RefOr<Response> {
+    ResponseBuilder::new()
+        .description(description)
+        .content(
+            "application/json",
+            ContentBuilder::new()
+                .schema(Some(Ref::from_schema_name("ErrorResponse")))
+                .build(),
+        )
+        .build()
+        .into()
+}
+
+/// The ways a caller can authenticate, named by the operations' `security`.
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut Document) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearerAuth",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some(
+                        "Tokens carry space-separated scopes (read, trade, withdraw, admin) in the `scope` claim; a route answers 403 insufficient_scope when its scope is missing.",
+                    ))
+                    .build(),
+            ),
+        );
+        components.add_security_scheme(
+            "apiKeySignature",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                "X-API-Key",
+                "Instead of a bearer token, send X-API-Key, X-API-Timestamp (Unix seconds), X-API-Content-SHA256 (hex SHA-256 of the body) and X-API-Signature: hex HMAC-SHA256 under the key's secret of timestamp, method, path with query, and body hash, joined by newlines.",
+            ))),
+        );
+        components.add_security_scheme(
+            "clientCertificate",
+            SecurityScheme::MutualTls {
+                description: Some(
+                    "On deployments requiring client certificates, a certificate whose name is mapped in TLS_CLIENT_IDENTITIES authenticates as that subject with its configured scopes.".to_string(),
+                ),
+                extensions: None,
+            },
+        );
+    }
+}
+
+/// The OpenAPI 3.1 document served at `/openapi.json`.
+pub fn document() -> Value {
+    serde_json::to_value(ApiDoc::openapi()).expect("OpenAPI document serializes")
+}
+
+/// Machine-readable API description and a Swagger UI page for it
+pub(crate) fn routes() -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone
+{
+    let spec = Arc::new(document());
+
+    let openapi_json = warp::path("openapi.json")
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(caching::conditional())
+        .map(move |conditional: caching::Conditional| {
+            caching::cached_json(&*spec, None, caching::STATIC_MAX_AGE, &conditional)
+        })
+        .boxed();
+
+    let docs = warp::path("docs")
+        .and(warp::path::end())
+        .and(warp::get())
+        .map(|| warp::reply::html(SWAGGER_UI_HTML))
+        .boxed();
+
+    openapi_json.or(docs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        routes,
+        test_support::{bearer_token, place, test_state_with_memory, MemoryStorage},
+    };
+    use std::sync::Arc;
+    use warp::http::StatusCode;
+
+    /// Check that `value` has every required property of `name` and no
+    /// undocumented ones.
+    fn assert_matches_schema(value: &Value, name: &str) {
+        let document = document();
+        let schema = &document["components"]["schemas"][name];
+        let properties = schema["properties"].as_object().expect("object schema");
+        let fields = value.as_object().expect("object value");
+        for key in fields.keys() {
+            assert!(
+                properties.contains_key(key),
+                "{}.{} is undocumented",
+                name,
+                key
+            );
+        }
+        for key in schema["required"].as_array().into_iter().flatten() {
+            let key = key.as_str().unwrap();
+            assert!(fields.contains_key(key), "{}.{} is missing", name, key);
+        }
+    }
+
+    #[tokio::test]
+    async fn documented_routes_exist() {
+        let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+        // Wrapped-asset routes only answer for a configured token.
+        let config = crate::bridge::BridgeConfig {
+            assets: vec![(
+                "ethereum".to_string(),
+                "WETH".parse().unwrap(),
+                "ETH".parse().unwrap(),
+            )],
+            wrapped_assets: vec![dex_core::wrapped_assets::WrappedAsset {
+                token: "ETH".parse().unwrap(),
+                chain: "ethereum".to_string(),
+                custody_wallet: "eth-custody".to_string(),
+            }],
+            ..Default::default()
+        };
+        state.bridge = Arc::new(crate::bridge::Bridge::new(&config));
+        // Explorer routes only answer for stored blocks.
+        state
+            .chain_repo
+            .save_block(&dex_db::ChainBlock {
+                shard_id: 0,
+                height: 1,
+                hash: "ab".to_string(),
+                previous_hash: "00".to_string(),
+                timestamp: 1,
+                transactions: vec![dex_db::ChainTransaction {
+                    hash: "cd".to_string(),
+                    shard_id: 0,
+                    height: 1,
+                    index: 0,
+                    from: "alice".to_string(),
+                    to: "bob".to_string(),
+                    amount: 1,
+                    nonce: 0,
+                    finalized: false,
+                }],
+                finalized: false,
+            })
+            .await
+            .unwrap();
+        let filter = routes(state);
+        let document = document();
+        for (path, operations) in document["paths"].as_object().unwrap() {
+            let path = path
+                .replace("{token}", "ETH")
+                .replace("{wallet_id}", "eth-custody")
+                .replace("{height}", "1")
+                .replace("{hash}", "cd")
+                .replace("{order_id}", "1")
+                .replace("{trade_id}", "1")
+                .replace("{trader_id}", "alice")
+                .replace("{key_id}", "dk_1")
+                .replace("{jti}", "abc")
+                .replace("{netting_set_id}", "1")
+                .replace("{withdrawal_id}", "1")
+                .replace("{pair}", "ETH-USDC");
+            for method in operations.as_object().unwrap().keys() {
+                let response = warp::test::request()
+                    .method(&method.to_uppercase())
+                    .path(&path)
+                    .reply(&filter)
+                    .await;
+                assert!(
+                    response.status() != StatusCode::NOT_FOUND
+                        && response.status() != StatusCode::METHOD_NOT_ALLOWED,
+                    "{} {} is documented but not routed",
+                    method,
+                    path
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_match_documented_schemas() {
+        let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+        place(&filter, "bob", "sell", 5).await;
+        place(&filter, "alice", "buy", 2).await;
+
+        let get = |path: &'static str| {
+            let filter = filter.clone();
+            async move {
+                let response = warp::test::request()
+                    .path(path)
+                    .header("authorization", bearer_token("alice", 300))
+                    .reply(&filter)
+                    .await;
+                serde_json::from_slice::<Value>(response.body()).unwrap()
+            }
+        };
+
+        let prices = get("/orderbook/prices").await;
+        assert_matches_schema(&prices, "PriceResponse");
+        assert_matches_schema(&prices["pairs"][0], "Ticker");
+        let depth = get("/orderbook/depth").await;
+        assert_matches_schema(&depth, "DepthSnapshot");
+        assert_matches_schema(&depth["asks"][0], "DepthLevel");
+        let trades = get("/orderbook/traders/alice/trades").await;
+        assert_matches_schema(&trades, "GetTradesResponse");
+        assert_matches_schema(&trades["trades"][0], "TradeResponse");
+        let recent = get("/markets/ETH-USDC/trades").await;
+        assert_matches_schema(&recent, "RecentTradesResponse");
+        assert_matches_schema(&recent["trades"][0], "PublicTrade");
+        let candle = get("/orderbook/candles?pair=ETH-USDC").await;
+        assert_matches_schema(&candle, "Candle");
+        let matching = get("/stats/matching?pair=ETH-USDC").await;
+        assert_matches_schema(&matching, "MatchingReport");
+        assert_matches_schema(&matching["intervals"][0], "IntervalStats");
+        assert_eq!(matching["total"]["trades"], 1);
+        let usd = get("/prices/usd").await;
+        assert_matches_schema(&usd, "UsdPrices");
+        let pools = get("/amm/pools").await;
+        assert_matches_schema(&pools, "PoolSnapshot");
+        let summary = get("/amm/providers/alice/summary").await;
+        assert_matches_schema(&summary, "ProviderSummary");
+        let usage = get("/account/usage").await;
+        assert_matches_schema(&usage, "AccountUsage");
+        assert_matches_schema(&usage["days"][0], "DailyUsage");
+        let margin = get("/account/margin").await;
+        assert_matches_schema(&margin, "MarginReport");
+        let balances = get("/account/balances").await;
+        assert_matches_schema(&balances, "AccountBalances");
+        let missing = get("/orderbook/prices?pair=nope").await;
+        assert_matches_schema(&missing, "ErrorResponse");
+    }
+
+    #[tokio::test]
+    async fn serves_document_and_swagger_ui() {
+        let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+        let response = warp::test::request()
+            .path("/openapi.json")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["openapi"], "3.1.0");
+        assert!(body["paths"]["/orderbook/orders"]["post"].is_object());
+
+        let response = warp::test::request().path("/docs").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(String::from_utf8_lossy(response.body()).contains("/openapi.json"));
+    }
+}
@@ -0,0 +1,346 @@
+//! Private order lifecycle events for the per-trader WebSocket channel.
+//!
+//! The tracker follows orders accepted through the API and turns matching
+//! results into `order_update` and `fill` events addressed to the owning
+//! trader, so clients can follow their orders without polling.
+
+use dex_core::types::{
+    Order, OrderId, OrderSide, OrderType, Price, Quantity, Trade, TradeId, TraderId, TradingPair,
+};
+use dex_db::{OrderFill, TradeAdjustment};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Lifecycle state reported in `order_update` events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderStatus {
+    Accepted,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+}
+
+/// Whether the trader's order rested in the book or crossed it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+/// Event payload pushed to a trader's private stream.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OrderEvent {
+    OrderUpdate {
+        order_id: OrderId,
+        status: OrderStatus,
+        pair: String,
+        side: &'static str,
+        price: Option<Price>,
+        quantity: Quantity,
+        filled_quantity: Quantity,
+        remaining_quantity: Quantity,
+        timestamp: u64,
+    },
+    Fill {
+        order_id: OrderId,
+        trade_id: TradeId,
+        pair: String,
+        side: &'static str,
+        price: Price,
+        quantity: Quantity,
+        liquidity: Liquidity,
+        timestamp: u64,
+    },
+    /// An administrator busted or re-priced one of the trader's fills.
+    TradeAdjusted {
+        order_id: OrderId,
+        trade_id: TradeId,
+        pair: String,
+        side: &'static str,
+        /// `bust` or `price_adjust`.
+        adjustment: &'static str,
+        previous_price: Price,
+        /// Corrected price; absent for a bust.
+        price: Option<Price>,
+        quantity: Quantity,
+        liquidity: Liquidity,
+        reason: String,
+        timestamp: u64,
+    },
+}
+
+impl OrderEvent {
+    /// The state to store for the order an `order_update` is about.
+    pub fn order_fill(&self) -> Option<OrderFill> {
+        let OrderEvent::OrderUpdate {
+            order_id,
+            status,
+            remaining_quantity,
+            ..
+        } = self
+        else {
+            return None;
+        };
+        let status = match status {
+            OrderStatus::Accepted => dex_db::OrderStatus::Open,
+            OrderStatus::PartiallyFilled => dex_db::OrderStatus::PartiallyFilled,
+            OrderStatus::Filled => dex_db::OrderStatus::Filled,
+            OrderStatus::Cancelled => dex_db::OrderStatus::Cancelled,
+        };
+        Some(OrderFill {
+            order_id: *order_id,
+            status,
+            remaining_quantity: *remaining_quantity,
+        })
+    }
+}
+
+/// An event together with the trader it belongs to.
+#[derive(Debug, Clone)]
+pub struct UserEvent {
+    pub trader_id: TraderId,
+    pub event: OrderEvent,
+}
+
+/// Tell the owner of `order` that its fill in `trade` was busted or re-priced.
+pub fn trade_adjusted(order: &Order, trade: &Trade, adjustment: &TradeAdjustment) -> UserEvent {
+    let liquidity = if trade.maker_order_id == order.id {
+        Liquidity::Maker
+    } else {
+        Liquidity::Taker
+    };
+    UserEvent {
+        trader_id: order.trader_id.clone(),
+        event: OrderEvent::TradeAdjusted {
+            order_id: order.id,
+            trade_id: trade.id,
+            pair: order.pair.to_string(),
+            side: side_label(order.side),
+            adjustment: adjustment.kind.as_str(),
+            previous_price: adjustment.previous_price,
+            price: adjustment.new_price,
+            quantity: trade.quantity,
+            liquidity,
+            reason: adjustment.reason.clone(),
+            timestamp: adjustment.adjusted_at,
+        },
+    }
+}
+
+fn side_label(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Buy => "buy",
+        OrderSide::Sell => "sell",
+    }
+}
+
+#[derive(Debug)]
+struct TrackedOrder {
+    trader_id: TraderId,
+    pair: TradingPair,
+    side: OrderSide,
+    order_type: OrderType,
+    price: Option<Price>,
+    quantity: Quantity,
+    filled: Quantity,
+}
+
+impl TrackedOrder {
+    fn update(&self, order_id: OrderId, status: OrderStatus, timestamp: u64) -> UserEvent {
+        UserEvent {
+            trader_id: self.trader_id.clone(),
+            event: OrderEvent::OrderUpdate {
+                order_id,
+                status,
+                pair: self.pair.to_string(),
+                side: side_label(self.side),
+                price: self.price,
+                quantity: self.quantity,
+                filled_quantity: self.filled,
+                remaining_quantity: self.quantity - self.filled,
+                timestamp,
+            },
+        }
+    }
+}
+
+/// Open orders accepted through the API, with their filled quantity.
+#[derive(Debug, Default)]
+pub struct OrderTracker {
+    open: HashMap<OrderId, TrackedOrder>,
+}
+
+impl OrderTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start tracking a newly accepted order.
+    pub fn accept(&mut self, order: &Order) -> UserEvent {
+        let tracked = TrackedOrder {
+            trader_id: order.trader_id.clone(),
+            pair: order.pair.clone(),
+            side: order.side,
+            order_type: order.order_type,
+            price: order.price,
+            quantity: order.quantity,
+            filled: 0,
+        };
+        let event = tracked.update(order.id, OrderStatus::Accepted, order.timestamp);
+        self.open.insert(order.id, tracked);
+        event
+    }
+
+    /// Apply the trades produced by matching `taker_id`, returning fills and
+    /// status changes for both sides. Market orders are immediate-or-cancel,
+    /// so any unfilled remainder is reported as cancelled.
+    pub fn apply_trades(&mut self, taker_id: OrderId, trades: &[Trade]) -> Vec<UserEvent> {
+        let mut events = Vec::new();
+        for trade in trades {
+            for (order_id, liquidity) in [
+                (trade.maker_order_id, Liquidity::Maker),
+                (trade.taker_order_id, Liquidity::Taker),
+            ] {
+                let Some(order) = self.open.get_mut(&order_id) else {
+                    continue;
+                };
+                order.filled = (order.filled + trade.quantity).min(order.quantity);
+                events.push(UserEvent {
+                    trader_id: order.trader_id.clone(),
+                    event: OrderEvent::Fill {
+                        order_id,
+                        trade_id: trade.id,
+                        pair: order.pair.to_string(),
+                        side: side_label(order.side),
+                        price: trade.price,
+                        quantity: trade.quantity,
+                        liquidity,
+                        timestamp: trade.timestamp,
+                    },
+                });
+                // The taker's final state is reported once, after all its trades.
+                if liquidity == Liquidity::Maker {
+                    events.extend(self.settle(order_id, trade.timestamp, false));
+                }
+            }
+        }
+        let timestamp = trades
+            .last()
+            .map(|trade| trade.timestamp)
+            .unwrap_or_default();
+        events.extend(self.settle(taker_id, timestamp, true));
+        events
+    }
+
+    /// Stop tracking a cancelled order.
+    pub fn cancel(&mut self, order_id: OrderId, timestamp: u64) -> Option<UserEvent> {
+        self.open
+            .remove(&order_id)
+            .map(|order| order.update(order_id, OrderStatus::Cancelled, timestamp))
+    }
+
+    /// Whether the order is still open.
+    pub fn is_open(&self, order_id: OrderId) -> bool {
+        self.open.contains_key(&order_id)
+    }
+
+    fn settle(&mut self, order_id: OrderId, timestamp: u64, is_taker: bool) -> Option<UserEvent> {
+        let order = self.open.get(&order_id)?;
+        let status = if order.filled == order.quantity {
+            OrderStatus::Filled
+        } else if is_taker && order.order_type == OrderType::Market {
+            OrderStatus::Cancelled
+        } else if order.filled > 0 {
+            OrderStatus::PartiallyFilled
+        } else {
+            return None;
+        };
+        let event = order.update(order_id, status, timestamp);
+        if status != OrderStatus::PartiallyFilled {
+            self.open.remove(&order_id);
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: OrderId, trader: &str, side: OrderSide, quantity: Quantity) -> Order {
+        Order {
+            id,
+            trader_id: trader.parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(1000),
+            quantity,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    fn trade(id: TradeId, maker: OrderId, taker: OrderId, quantity: Quantity) -> Trade {
+        Trade {
+            id,
+            maker_order_id: maker,
+            taker_order_id: taker,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 1000,
+            quantity,
+            timestamp: 1_700_000_001,
+        }
+    }
+
+    fn status(event: &UserEvent) -> Option<OrderStatus> {
+        match event.event {
+            OrderEvent::OrderUpdate { status, .. } => Some(status),
+            OrderEvent::Fill { .. } | OrderEvent::TradeAdjusted { .. } => None,
+        }
+    }
+
+    #[test]
+    fn reports_fills_and_status_for_both_sides() {
+        let mut tracker = OrderTracker::new();
+        tracker.accept(&order(1, "bob", OrderSide::Sell, 10));
+        tracker.accept(&order(2, "alice", OrderSide::Buy, 4));
+
+        let events = tracker.apply_trades(2, &[trade(7, 1, 2, 4)]);
+        let summary: Vec<_> = events
+            .iter()
+            .map(|e| (e.trader_id.as_str(), status(e)))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("bob", None),
+                ("bob", Some(OrderStatus::PartiallyFilled)),
+                ("alice", None),
+                ("alice", Some(OrderStatus::Filled)),
+            ]
+        );
+        assert!(tracker.is_open(1));
+        assert!(!tracker.is_open(2));
+
+        let cancelled = tracker.cancel(1, 1_700_000_002).unwrap();
+        assert_eq!(status(&cancelled), Some(OrderStatus::Cancelled));
+        assert!(tracker.cancel(1, 1_700_000_003).is_none());
+    }
+
+    #[test]
+    fn unfilled_market_remainder_is_cancelled() {
+        let mut tracker = OrderTracker::new();
+        let mut market = order(3, "alice", OrderSide::Buy, 5);
+        market.order_type = OrderType::Market;
+        market.price = None;
+        tracker.accept(&market);
+
+        let events = tracker.apply_trades(3, &[]);
+        assert_eq!(status(&events[0]), Some(OrderStatus::Cancelled));
+        assert!(!tracker.is_open(3));
+    }
+}
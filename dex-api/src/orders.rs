@@ -0,0 +1,976 @@
+//! Order entry: placing, checking and cancelling orders, and the trades
+//! and sequencing receipts they produced.
+
+use crate::{
+    api_keys,
+    auth::{Claims, Scope},
+    authenticated, error_reply, event_stream,
+    funds::{self},
+    ledger, margin,
+    market_data::broadcast_depth_snapshot,
+    order_events::{Liquidity, OrderEvent},
+    rate_limit::RouteClass,
+    rate_limited,
+    sequencing::{self},
+    storage_error_reply,
+    trade_tape::{MarketTrade, TradeEventKind},
+    validation, ApiState, ErrorResponse, InternalError, ValidationRejection,
+};
+use dex_core::{
+    orderbook::OrderBookError,
+    types::{Order, OrderId, OrderType, Quantity, TokenId, Trade, TradeId, TraderId},
+};
+use dex_db::{DatabaseError, OrderFill, SequencingReceipt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{openapi::Object, IntoParams, ToSchema};
+use warp::{http::StatusCode, Filter};
+/// Request to create a new order
+#[derive(Deserialize, ToSchema)]
+pub struct CreateOrderRequest {
+    pub trader_id: String,
+    pub base_token: String,
+    pub quote_token: String,
+    #[schema(schema_with = order_sides)]
+    pub side: String,
+    #[schema(schema_with = order_types)]
+    pub order_type: String,
+    /// Required for limit orders
+    pub price: Option<u64>,
+    pub quantity: u64,
+}
+
+fn order_sides() -> Object {
+    crate::openapi::string_enum(&["buy", "sell"])
+}
+
+fn order_types() -> Object {
+    crate::openapi::string_enum(&["limit", "market"])
+}
+
+/// Response for order creation
+#[derive(Serialize, ToSchema)]
+pub struct CreateOrderResponse {
+    #[schema(value_type = u64)]
+    pub order_id: OrderId,
+    pub success: bool,
+    pub message: Option<String>,
+    /// Signed proof of the order's place in the sequence.
+    pub receipt: sequencing::ReceiptResponse,
+}
+
+/// Outcome of a pre-trade check. A refused order carries the `code` and
+/// `message` that placing it would have returned.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RiskCheckResponse {
+    pub accepted: bool,
+    /// HTTP status placing the order would get.
+    pub status: u16,
+    /// Error code placing the order would return.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Quantity that would execute against the book right away.
+    #[schema(value_type = u64)]
+    pub fill_quantity: Quantity,
+    /// Quantity that would rest on the book afterwards.
+    #[schema(value_type = u64)]
+    pub resting_quantity: Quantity,
+}
+
+impl RiskCheckResponse {
+    fn refused(code: &'static str, message: impl Into<String>, status: StatusCode) -> Self {
+        Self {
+            accepted: false,
+            status: status.as_u16(),
+            code: Some(code),
+            message: Some(message.into()),
+            fill_quantity: 0,
+            resting_quantity: 0,
+        }
+    }
+}
+
+/// Response for order cancellation
+#[derive(Serialize, ToSchema)]
+pub struct CancelOrderResponse {
+    #[schema(value_type = u64)]
+    pub order_id: OrderId,
+    pub success: bool,
+    #[schema(value_type = u64)]
+    pub cancelled_quantity: Quantity,
+}
+
+/// Response for trade information
+#[derive(Serialize, ToSchema)]
+pub struct TradeResponse {
+    pub id: u64,
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    #[schema(value_type = String)]
+    pub base_token: TokenId,
+    #[schema(value_type = String)]
+    pub quote_token: TokenId,
+    pub price: u64,
+    pub quantity: u64,
+    pub timestamp: u64,
+}
+
+impl From<Trade> for TradeResponse {
+    fn from(trade: Trade) -> Self {
+        Self {
+            id: trade.id,
+            maker_order_id: trade.maker_order_id,
+            taker_order_id: trade.taker_order_id,
+            base_token: trade.base_token,
+            quote_token: trade.quote_token,
+            price: trade.price,
+            quantity: trade.quantity,
+            timestamp: trade.timestamp,
+        }
+    }
+}
+
+/// Response for getting trades
+#[derive(Serialize, ToSchema)]
+pub struct GetTradesResponse {
+    pub trades: Vec<TradeResponse>,
+    pub success: bool,
+    pub message: Option<String>,
+    /// Pass as `after_id` to fetch the next page; absent on the last page.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<u64>)]
+    pub next_cursor: Option<TradeId>,
+}
+
+/// Cursor and filters for a trader's trade history,
+/// e.g. `?after_id=42&limit=100&pair=ETH-USDC&from=1700000000&to=1700086400`.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct TradeHistoryQuery {
+    /// Cursor from `next_cursor`
+    #[param(value_type = Option<u64>)]
+    pub(crate) after_id: Option<TradeId>,
+    /// Page size, 1-1000 (default 100)
+    pub(crate) limit: Option<u32>,
+    /// Market as BASE-QUOTE, e.g. ETH-USDC
+    #[param(example = "ETH-USDC")]
+    pub(crate) pair: Option<String>,
+    /// Unix timestamp, inclusive
+    pub(crate) from: Option<u64>,
+    /// Unix timestamp, exclusive
+    pub(crate) to: Option<u64>,
+}
+
+/// An order that went through [`submit_order`], with the trades it executed.
+#[derive(Debug)]
+pub(crate) struct OrderOutcome {
+    pub(crate) order: Order,
+    pub(crate) trades: Vec<Trade>,
+    pub(crate) receipt: SequencingReceipt,
+}
+
+/// Why [`submit_order`] failed.
+#[derive(Debug)]
+pub(crate) enum SubmitError {
+    /// Storage is unavailable, so order entry is suspended.
+    Degraded,
+    /// The server is draining before it exits.
+    ShuttingDown,
+    /// This instance mirrors the matching instance and takes no orders.
+    Replica,
+    /// Trading in the order's pair is halted.
+    Halted,
+    /// The system clock could not be read.
+    Clock,
+    /// The order could not be stored; it never reached the book.
+    Storage(DatabaseError),
+    /// The book refused the order.
+    Rejected(OrderBookError),
+    /// Resting the order would take its trader over their margin limit.
+    MarginLimit(margin::MarginBreach),
+    /// The trader's balance does not cover the order.
+    Unfunded(funds::InsufficientFunds),
+    /// The order was matched and published, but a trade could not be stored.
+    TradeWrite(Box<OrderOutcome>, DatabaseError),
+}
+
+/// Why [`cancel_order`] failed.
+#[derive(Debug)]
+pub(crate) enum CancelError {
+    /// No open order with this id belongs to the trader.
+    NotFound,
+    /// This instance mirrors the matching instance and takes no cancels.
+    Replica,
+    Book(OrderBookError),
+}
+
+/// Order entry and per-order reads, e.g. POST /orderbook/orders
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let orderbook = warp::path("orderbook");
+
+    // Create order endpoint
+    let create_order = orderbook
+        .and(warp::path("orders"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(authenticated(state.clone(), Scope::Trade))
+        .and(api_keys::signed_json(8 * 1024))
+        .and_then(handle_create_order)
+        .boxed();
+
+    // Dry run of order entry for upstream brokers
+    let risk_check = warp::path("risk")
+        .and(warp::path("check"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(authenticated(state.clone(), Scope::Trade))
+        .and(api_keys::signed_json(8 * 1024))
+        .and_then(handle_risk_check)
+        .boxed();
+
+    // Cancel a resting order, e.g. DELETE /orderbook/orders/42
+    let cancel_order = orderbook
+        .and(warp::path("orders"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(authenticated(state.clone(), Scope::Trade))
+        .and_then(handle_cancel_order)
+        .boxed();
+
+    // Get trades for order endpoint
+    let get_trades_for_order = orderbook
+        .and(warp::path("orders"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path("trades"))
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_trades_for_order)
+        .boxed();
+
+    // Signed sequencing receipt of an order, e.g. GET /orderbook/orders/42/receipt
+    let get_order_receipt = orderbook
+        .and(warp::path("orders"))
+        .and(warp::path::param::<u64>())
+        .and(warp::path("receipt"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_order_receipt)
+        .boxed();
+
+    // Get trades for trader endpoint
+    let get_trades_for_trader = orderbook
+        .and(warp::path("traders"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("trades"))
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(warp::query::<TradeHistoryQuery>())
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_get_trades_for_trader)
+        .boxed();
+
+    create_order
+        .or(risk_check)
+        .or(cancel_order)
+        .or(get_trades_for_order)
+        .or(get_order_receipt)
+        .or(get_trades_for_trader)
+}
+
+/// Handler for creating orders
+#[utoipa::path(
+    post,
+    path = "/orderbook/orders",
+    summary = "Place an order",
+    request_body = CreateOrderRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 201, description = "Order accepted and matched", body = CreateOrderResponse),
+        (status = 400, description = "Invalid order", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "trader_id does not match the token subject", body = ErrorResponse),
+        (status = 409, description = "Order conflicts with the book, or trading in the pair is halted (trading_halted)", body = ErrorResponse),
+        (status = 422, description = "Order rejected by the book, or over the trader's margin limit (margin_limit_exceeded), or not covered by their balance (insufficient_balance)", body = ErrorResponse),
+        (status = 503, description = "Order entry suspended while storage is unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_create_order(
+    claims: Claims,
+    state: ApiState,
+    req: CreateOrderRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let validated = validation::validate_create_order(req)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    if validated.trader_id != claims.sub {
+        return Ok(error_reply(
+            "forbidden",
+            "trader_id does not match authenticated subject",
+            StatusCode::FORBIDDEN,
+        ));
+    }
+
+    let outcome = match submit_order(&state, validated).await {
+        Ok(outcome) => outcome,
+        Err(SubmitError::Degraded) => {
+            return Ok(error_reply(
+                "degraded_mode",
+                "order entry is suspended while storage is unavailable",
+                StatusCode::SERVICE_UNAVAILABLE,
+            ))
+        }
+        Err(SubmitError::ShuttingDown) => {
+            return Ok(error_reply(
+                "shutting_down",
+                "the server is shutting down and no longer accepts orders",
+                StatusCode::SERVICE_UNAVAILABLE,
+            ))
+        }
+        Err(SubmitError::Replica) => return Ok(replica_reply()),
+        Err(SubmitError::Halted) => {
+            return Ok(error_reply(
+                "trading_halted",
+                "trading in this pair is halted",
+                StatusCode::CONFLICT,
+            ))
+        }
+        Err(SubmitError::Clock) => return Err(warp::reject::custom(InternalError)),
+        Err(SubmitError::Storage(err)) => {
+            return Ok(storage_error_reply(&err, "failed to persist order"))
+        }
+        Err(SubmitError::Rejected(err)) => {
+            let (code, status) = book_error_status(&err);
+            return Ok(error_reply(code, err.to_string(), status));
+        }
+        Err(SubmitError::MarginLimit(breach)) => {
+            return Ok(error_reply(
+                "margin_limit_exceeded",
+                margin_limit_message(&breach),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ))
+        }
+        Err(SubmitError::Unfunded(shortfall)) => {
+            return Ok(error_reply(
+                "insufficient_balance",
+                insufficient_funds_message(&shortfall),
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ))
+        }
+        Err(SubmitError::TradeWrite(_, err)) => {
+            return Ok(storage_error_reply(&err, "failed to persist trade"))
+        }
+    };
+
+    let message = if outcome.trades.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "Order created and matched, {} trades executed",
+            outcome.trades.len()
+        ))
+    };
+    let response = CreateOrderResponse {
+        order_id: outcome.order.id,
+        success: true,
+        message,
+        receipt: outcome.receipt.into(),
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::CREATED,
+    ))
+}
+
+pub(crate) fn replica_reply() -> warp::reply::WithStatus<warp::reply::Json> {
+    error_reply(
+        "read_only_replica",
+        "this instance serves market data only; send orders to the matching instance",
+        StatusCode::SERVICE_UNAVAILABLE,
+    )
+}
+
+fn book_error_status(err: &OrderBookError) -> (&'static str, StatusCode) {
+    if err.is_rejection() {
+        ("order_rejected", StatusCode::UNPROCESSABLE_ENTITY)
+    } else {
+        ("order_book_error", StatusCode::CONFLICT)
+    }
+}
+
+/// Run an order through the same checks as `POST /orderbook/orders` without
+/// placing it. Refusals are reported in the body with a 200, so only a bad
+/// token fails the request itself.
+#[utoipa::path(
+    post,
+    path = "/risk/check",
+    summary = "Run an order through the order entry checks without placing it",
+    request_body = CreateOrderRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Whether the order would be accepted", body = RiskCheckResponse),
+        (status = 400, description = "Malformed request body", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn handle_risk_check(
+    claims: Claims,
+    state: ApiState,
+    req: CreateOrderRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let response = match validation::validate_create_order(req) {
+        Err(err) => {
+            RiskCheckResponse::refused("validation_error", err.to_string(), StatusCode::BAD_REQUEST)
+        }
+        Ok(validated) if validated.trader_id != claims.sub => RiskCheckResponse::refused(
+            "forbidden",
+            "trader_id does not match authenticated subject",
+            StatusCode::FORBIDDEN,
+        ),
+        Ok(_) if !state.storage.is_available() => RiskCheckResponse::refused(
+            "degraded_mode",
+            "order entry is suspended while storage is unavailable",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        Ok(validated) if state.settings.borrow().is_halted(&validated.pair) => {
+            RiskCheckResponse::refused(
+                "trading_halted",
+                "trading in this pair is halted",
+                StatusCode::CONFLICT,
+            )
+        }
+        Ok(validated) => {
+            let timestamp = state.determinism.now().unwrap_or_default();
+            let order = validated.into_order(0, timestamp);
+            let checked = state.orderbook.read().await.check_order(&order);
+            match checked {
+                Ok(fill_quantity) => match check_margin(&state, &order).await {
+                    Err(breach) => RiskCheckResponse::refused(
+                        "margin_limit_exceeded",
+                        margin_limit_message(&breach),
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                    ),
+                    Ok(()) => {
+                        match funds::check(&state, &*state.orderbook.read().await, &order).await {
+                            Err(funds::FundsError::Insufficient(shortfall)) => {
+                                RiskCheckResponse::refused(
+                                    "insufficient_balance",
+                                    insufficient_funds_message(&shortfall),
+                                    StatusCode::UNPROCESSABLE_ENTITY,
+                                )
+                            }
+                            Err(funds::FundsError::Overflow) => RiskCheckResponse::refused(
+                                "order_rejected",
+                                OrderBookError::NotionalOverflow.to_string(),
+                                StatusCode::UNPROCESSABLE_ENTITY,
+                            ),
+                            Err(funds::FundsError::Storage(_)) => RiskCheckResponse::refused(
+                                "storage_unavailable",
+                                "balances could not be read",
+                                StatusCode::SERVICE_UNAVAILABLE,
+                            ),
+                            Ok(()) => RiskCheckResponse {
+                                accepted: true,
+                                status: StatusCode::CREATED.as_u16(),
+                                code: None,
+                                message: None,
+                                fill_quantity,
+                                // Unfilled market orders are dropped, not rested.
+                                resting_quantity: match order.order_type {
+                                    OrderType::Limit => order.quantity - fill_quantity,
+                                    OrderType::Market => 0,
+                                },
+                            },
+                        }
+                    }
+                },
+                Err(err) => {
+                    let (code, status) = book_error_status(&err);
+                    RiskCheckResponse::refused(code, err.to_string(), status)
+                }
+            }
+        }
+    };
+    Ok(warp::reply::json(&response))
+}
+
+/// Persist, match and publish an order. This is the order pipeline shared by
+/// every entry point; callers check that the trader may place it.
+#[tracing::instrument(
+    skip_all,
+    fields(
+        trader = %validated.trader_id.as_str(),
+        pair = %validated.pair,
+        order_id = tracing::field::Empty,
+        trades = tracing::field::Empty,
+    )
+)]
+pub(crate) async fn submit_order(
+    state: &ApiState,
+    validated: validation::ValidatedCreateOrder,
+) -> Result<OrderOutcome, SubmitError> {
+    // While the database is down the API runs read-only: matching without
+    // persistence would leave the book and the store out of sync.
+    if !state.storage.is_available() {
+        return Err(SubmitError::Degraded);
+    }
+    if state.is_read_only() {
+        return Err(SubmitError::Replica);
+    }
+    if state.settings.borrow().is_halted(&validated.pair) {
+        return Err(SubmitError::Halted);
+    }
+    // Held until the order is stored and matched, so shutdown waits for it.
+    let _in_flight = state.shutdown.track().ok_or(SubmitError::ShuttingDown)?;
+    let order_id = state
+        .orders
+        .next_order_id()
+        .await
+        .map_err(SubmitError::Storage)?;
+    tracing::Span::current().record("order_id", order_id);
+    let timestamp = state.determinism.now().map_err(|_| SubmitError::Clock)?;
+    let order = validated.into_order(order_id, timestamp);
+    check_margin(state, &order)
+        .await
+        .map_err(SubmitError::MarginLimit)?;
+
+    // Held from the funds check until the order rests and its fills are
+    // booked, so two orders, or an order and a withdrawal of its trader,
+    // cannot both be checked against the same funds.
+    let trader_lock = state.trader_locks.lock(order.trader_id.as_str()).await;
+    let mut orderbook = state.orderbook.write().await;
+    match funds::check(state, &orderbook, &order).await {
+        Ok(()) => {}
+        Err(funds::FundsError::Insufficient(shortfall)) => {
+            return Err(SubmitError::Unfunded(shortfall))
+        }
+        Err(funds::FundsError::Overflow) => {
+            return Err(SubmitError::Rejected(OrderBookError::NotionalOverflow))
+        }
+        Err(funds::FundsError::Storage(err)) => return Err(SubmitError::Storage(err)),
+    }
+
+    // Persist before matching so a failed write never leaves an order in the
+    // book that storage does not know about.
+    if let Err(err) = state.orders.save_order(&order).await {
+        tracing::error!(order_id, error = ?err, "failed to persist order");
+        return Err(SubmitError::Storage(err));
+    }
+
+    let result = tracing::info_span!("match").in_scope(|| orderbook.add_order(order.clone()));
+    // Sequenced under the book lock so sequence numbers follow match order.
+    let mut result = result.map(|trades| (trades, state.sequencer.stamp(&order)));
+    if let Ok((trades, _)) = &result {
+        tracing::Span::current().record("trades", trades.len());
+    }
+    if let (Ok((trades, _)), Some(journal)) = (&result, &state.journal) {
+        journal.record_add(&order, trades.len());
+    }
+    if let (Ok(_), Some(feed)) = (&result, &state.market_feed) {
+        feed.order_added(&order);
+    }
+    if let Ok((trades, _)) = &result {
+        state
+            .matching_stats
+            .write()
+            .await
+            .record_add(&order, trades);
+    }
+    // Stored with their ledger entries and the fills of the orders they
+    // executed before the book is released, so the next funds check sees the
+    // balances these trades move and a book rebuilt from storage rests only
+    // what is left. The book has already moved, so a failed write must not
+    // skip the stream updates below; it is returned once they are published.
+    let (trade_write_error, events) = match &mut result {
+        Ok((trades, _)) => {
+            let ids = assign_trade_ids(state, trades).await;
+            let events = {
+                let mut tracker = state.order_tracker.write().await;
+                let accepted = tracker.accept(&order);
+                let updates = tracker.apply_trades(order_id, trades);
+                std::iter::once(accepted).chain(updates).collect::<Vec<_>>()
+            };
+            let fills: Vec<OrderFill> = events
+                .iter()
+                .filter_map(|update| update.event.order_fill())
+                .collect();
+            let makers: HashMap<OrderId, TraderId> = events
+                .iter()
+                .filter_map(|update| match update.event {
+                    OrderEvent::Fill {
+                        order_id,
+                        liquidity: Liquidity::Maker,
+                        ..
+                    } => Some((order_id, update.trader_id.clone())),
+                    _ => None,
+                })
+                .collect();
+            let stored = match ids {
+                Ok(()) => store_trades(state, &order, trades, &fills, &makers).await,
+                Err(err) => Err(err),
+            };
+            (stored.err(), events)
+        }
+        Err(_) => (None, Vec::new()),
+    };
+    drop(orderbook);
+    drop(trader_lock);
+
+    let (trades, receipt) = match result {
+        Ok(accepted) => accepted,
+        Err(err) => {
+            // The order never reached the book, so drop its stored copy.
+            if let Err(db_err) = state.orders.discard_order(order_id).await {
+                tracing::error!(order_id, error = ?db_err, "failed to discard rejected order");
+            }
+            return Err(SubmitError::Rejected(err));
+        }
+    };
+
+    if let Err(err) = state.receipt_repo.save_receipt(&receipt).await {
+        tracing::error!(order_id, error = ?err, "failed to persist sequencing receipt");
+    }
+
+    match (&trade_write_error, &state.journal) {
+        (Some(err), _) => tracing::error!(
+            order_id,
+            trades = trades.len(),
+            error = ?err,
+            "failed to persist trades"
+        ),
+        (None, Some(journal)) => journal.record_trades(&trades),
+        (None, None) => {}
+    }
+    event_stream::record_order(state, &order, &trades).await;
+
+    state.chaos.delay_broadcast().await;
+    if !trades.is_empty() {
+        let mut tape = state.trade_tape.write().await;
+        let pair = order.pair.to_string();
+        for trade in &trades {
+            let public = tape.record(trade, order.side);
+            if let Some(feed) = &state.market_feed {
+                feed.trade(trade, order.side);
+            }
+            let _ = state.trade_tx.send(MarketTrade {
+                kind: TradeEventKind::Trade,
+                pair: pair.clone(),
+                trade: public,
+            });
+        }
+    }
+
+    state.usage.order_events(&events, timestamp);
+    for event in events {
+        let _ = state.user_tx.send(event);
+    }
+
+    broadcast_depth_snapshot(state).await;
+
+    let outcome = OrderOutcome {
+        order,
+        trades,
+        receipt,
+    };
+    match trade_write_error {
+        Some(err) => Err(SubmitError::TradeWrite(Box::new(outcome), err)),
+        None => Ok(outcome),
+    }
+}
+
+/// Give the trades of a match their IDs. Trades that could not be given IDs
+/// keep ID 0 and are not stored.
+async fn assign_trade_ids(state: &ApiState, trades: &mut [Trade]) -> Result<(), DatabaseError> {
+    let ids = state.trades.next_trade_ids(trades.len()).await?;
+    for (trade, id) in trades.iter_mut().zip(ids) {
+        trade.id = id;
+    }
+    Ok(())
+}
+
+/// Store the trades `taker` executed together with the ledger entries
+/// booking them and the `fills` of the orders they executed, whose traders
+/// are `makers`.
+async fn store_trades(
+    state: &ApiState,
+    taker: &Order,
+    trades: &[Trade],
+    fills: &[OrderFill],
+    makers: &HashMap<OrderId, TraderId>,
+) -> Result<(), DatabaseError> {
+    let entries = ledger::fill_entries(state, taker, trades, makers).await?;
+    state.trades.save_trades(trades, &entries, fills).await
+}
+
+/// Check the part of `order` that would rest against its trader's margin
+/// limit. Orders the book would refuse are left for it to reject.
+async fn check_margin(state: &ApiState, order: &Order) -> Result<(), margin::MarginBreach> {
+    if !state
+        .config
+        .margin
+        .limits
+        .contains_key(order.trader_id.as_str())
+    {
+        return Ok(());
+    }
+    let checked = state.orderbook.read().await.check_order(order);
+    match checked {
+        Ok(fill_quantity) => margin::check(state, order, order.quantity - fill_quantity).await,
+        Err(_) => Ok(()),
+    }
+}
+
+pub(crate) fn margin_limit_message(breach: &margin::MarginBreach) -> String {
+    format!(
+        "order would raise the margin requirement to {:.2} USD, over the limit of {:.2} USD",
+        breach.required_usd, breach.limit_usd
+    )
+}
+
+pub(crate) fn insufficient_funds_message(shortfall: &funds::InsufficientFunds) -> String {
+    format!(
+        "orders would commit {} {}, more than the balance of {}",
+        shortfall.required, shortfall.token, shortfall.available
+    )
+}
+
+/// Handler for cancelling a resting order. Allowed in degraded mode so
+/// traders can pull liquidity while storage is unavailable.
+#[utoipa::path(
+    delete,
+    path = "/orderbook/orders/{order_id}",
+    summary = "Cancel a resting order",
+    params(("order_id" = u64, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Order cancelled", body = CancelOrderResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No open order with this id belongs to the trader", body = ErrorResponse),
+    ),
+)]
+async fn handle_cancel_order(
+    order_id: u64,
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let cancelled = match cancel_order(&state, order_id, &claims.sub).await {
+        Ok(order) => order,
+        Err(CancelError::NotFound) => {
+            return Ok(error_reply(
+                "order_not_found",
+                "no open order with this id",
+                StatusCode::NOT_FOUND,
+            ))
+        }
+        Err(CancelError::Replica) => return Ok(replica_reply()),
+        Err(CancelError::Book(err)) => {
+            return Ok(error_reply(
+                "order_book_error",
+                err.to_string(),
+                StatusCode::CONFLICT,
+            ))
+        }
+    };
+
+    let response = CancelOrderResponse {
+        order_id,
+        success: true,
+        cancelled_quantity: cancelled.quantity,
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::OK,
+    ))
+}
+
+/// Pull `trader_id`'s resting order from the book and publish the change.
+pub(crate) async fn cancel_order(
+    state: &ApiState,
+    order_id: OrderId,
+    trader_id: &str,
+) -> Result<Order, CancelError> {
+    if state.is_read_only() {
+        return Err(CancelError::Replica);
+    }
+    let mut orderbook = state.orderbook.write().await;
+    match orderbook.get_order(order_id) {
+        Some(order) if order.trader_id == trader_id => {}
+        // Other traders' orders are reported as missing to avoid leaking IDs.
+        _ => return Err(CancelError::NotFound),
+    }
+    let cancelled = orderbook.remove_order(order_id);
+    let timestamp = state.determinism.now().unwrap_or_default();
+    if let (Ok(_), Some(journal)) = (&cancelled, &state.journal) {
+        journal.record_cancel(order_id);
+    }
+    if let (Ok(_), Some(feed)) = (&cancelled, &state.market_feed) {
+        feed.order_cancelled(order_id);
+    }
+    if let Ok(order) = &cancelled {
+        state
+            .matching_stats
+            .write()
+            .await
+            .record_cancel(order, timestamp);
+    }
+    drop(orderbook);
+    let cancelled = cancelled.map_err(CancelError::Book)?;
+
+    state.chaos.delay_broadcast().await;
+    if let Some(event) = state
+        .order_tracker
+        .write()
+        .await
+        .cancel(order_id, timestamp)
+    {
+        let _ = state.user_tx.send(event);
+    }
+    if let Err(err) = state.orders.delete_order(order_id).await {
+        tracing::error!(order_id, error = ?err, "failed to delete cancelled order");
+    }
+    event_stream::record_cancel(state, &cancelled, timestamp).await;
+    broadcast_depth_snapshot(state).await;
+    Ok(cancelled)
+}
+
+/// Handler for getting trades for an order
+#[utoipa::path(
+    get,
+    path = "/orderbook/orders/{order_id}/trades",
+    summary = "Trades executed by an order",
+    params(("order_id" = u64, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Trades for the order", body = GetTradesResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_trades_for_order(
+    order_id: u64,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match state.trades.get_trades_for_order(order_id).await {
+        Ok(trades) => {
+            let response = GetTradesResponse {
+                trades: trades.into_iter().map(TradeResponse::from).collect(),
+                success: true,
+                message: None,
+                next_cursor: None,
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(order_id, error = ?err, "failed to load trades for order");
+            Ok(storage_error_reply(&err, "failed to load trades"))
+        }
+    }
+}
+
+/// Handler for an order's sequencing receipt
+#[utoipa::path(
+    get,
+    path = "/orderbook/orders/{order_id}/receipt",
+    summary = "Signed sequencing receipt of an accepted order",
+    params(("order_id" = u64, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "The order's receipt", body = sequencing::ReceiptResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 404, description = "No receipt for this order", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_order_receipt(
+    order_id: u64,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match state.receipt_repo.load_receipt(order_id).await {
+        Ok(Some(receipt)) => Ok(warp::reply::with_status(
+            warp::reply::json(&sequencing::ReceiptResponse::from(receipt)),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(error_reply(
+            "not_found",
+            "no receipt for this order",
+            StatusCode::NOT_FOUND,
+        )),
+        Err(err) => {
+            tracing::error!(order_id, error = ?err, "failed to load sequencing receipt");
+            Ok(storage_error_reply(&err, "failed to load receipt"))
+        }
+    }
+}
+
+/// Handler for getting trades for a trader
+#[utoipa::path(
+    get,
+    path = "/orderbook/traders/{trader_id}/trades",
+    summary = "The authenticated trader's fills, oldest first",
+    params(("trader_id" = String, Path), TradeHistoryQuery),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "A page of trades", body = GetTradesResponse),
+        (status = 400, description = "Invalid filters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "trader_id does not match the token subject", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_trades_for_trader(
+    trader_id: String,
+    query: TradeHistoryQuery,
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if claims.sub != trader_id {
+        return Ok(error_reply(
+            "forbidden",
+            "requested trader does not match authenticated subject",
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    let trader_id = validation::normalize_trader_id(&trader_id)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    let filter = validation::validate_trade_history(query)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    match state
+        .trades
+        .get_trades_for_trader(&trader_id, &filter)
+        .await
+    {
+        Ok(trades) => {
+            // A full page means there may be more; hand back the cursor.
+            let next_cursor = if trades.len() as u32 == filter.page_size() {
+                trades.last().map(|trade| trade.id)
+            } else {
+                None
+            };
+            let response = GetTradesResponse {
+                trades: trades.into_iter().map(TradeResponse::from).collect(),
+                success: true,
+                message: None,
+                next_cursor,
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(%trader_id, error = ?err, "failed to load trades for trader");
+            Ok(storage_error_reply(&err, "failed to load trades"))
+        }
+    }
+}
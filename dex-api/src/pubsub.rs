@@ -0,0 +1,690 @@
+//! Publish/subscribe transports for fanning data out across API instances.
+//!
+//! [`PubSub`] carries opaque payloads on named channels. Delivery is at most
+//! once: a subscriber misses whatever is published while it is disconnected,
+//! so anything built on it has to notice gaps. [`RedisPubSub`] speaks RESP
+//! (`PUBLISH`/`SUBSCRIBE`) and [`NatsPubSub`] the NATS client protocol, both
+//! over plain TCP; [`MemoryPubSub`] stays in the process.
+
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use std::{fmt, io, sync::Arc, time::Duration};
+use thiserror::Error;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::{broadcast, mpsc, Mutex},
+};
+
+/// Largest payload accepted from a server.
+const MAX_PAYLOAD: usize = 64 * 1024 * 1024;
+/// How long connecting, or waiting for a server to confirm a command, may take.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Messages a subscription buffers before the reader waits for the consumer.
+const SUBSCRIPTION_BUFFER: usize = 1024;
+
+#[derive(Debug, Error)]
+pub enum PubSubError {
+    #[error("pub/sub connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("pub/sub server did not answer in time")]
+    Timeout,
+    #[error("pub/sub server refused the command: {0}")]
+    Server(String),
+    #[error("unexpected reply from pub/sub server: {0}")]
+    Protocol(String),
+}
+
+/// A payload received on a channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub channel: String,
+    pub payload: Vec<u8>,
+}
+
+/// Messages on the subscribed channels, until the connection drops.
+#[derive(Debug)]
+pub struct Subscription {
+    rx: mpsc::Receiver<Message>,
+}
+
+impl Subscription {
+    /// The next message, or `None` once the subscription has ended.
+    pub async fn next(&mut self) -> Option<Message> {
+        self.rx.recv().await
+    }
+}
+
+/// Named channels that instances publish to and subscribe on.
+#[async_trait]
+pub trait PubSub: Send + Sync {
+    /// Send `payload` to everyone subscribed to `channel` right now.
+    async fn publish(&self, channel: &str, payload: &[u8]) -> Result<(), PubSubError>;
+
+    /// Start receiving what is published on `channels`. Returns once the
+    /// server has registered the subscription.
+    async fn subscribe(&self, channels: &[String]) -> Result<Subscription, PubSubError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Redis,
+    Nats,
+}
+
+/// Where a pub/sub server is: `redis://[[user]:password@]host[:port]` or
+/// `nats://[user:password@ | token@]host[:port]`.
+#[derive(Clone)]
+pub struct PubSubUrl {
+    pub backend: Backend,
+    pub host: String,
+    pub port: u16,
+    user: Option<String>,
+    password: Option<SecretString>,
+}
+
+impl fmt::Debug for PubSubUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PubSubUrl({})", self)
+    }
+}
+
+/// Credentials are left out.
+impl fmt::Display for PubSubUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scheme = match self.backend {
+            Backend::Redis => "redis",
+            Backend::Nats => "nats",
+        };
+        write!(f, "{}://{}:{}", scheme, self.host, self.port)
+    }
+}
+
+impl PubSubUrl {
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        let (backend, rest, default_port) = if let Some(rest) = raw.strip_prefix("redis://") {
+            (Backend::Redis, rest, 6379)
+        } else if let Some(rest) = raw.strip_prefix("nats://") {
+            (Backend::Nats, rest, 4222)
+        } else {
+            return None;
+        };
+        let rest = rest.trim_end_matches('/');
+        let (userinfo, address) = match rest.rsplit_once('@') {
+            Some((userinfo, address)) => (Some(userinfo), address),
+            None => (None, rest),
+        };
+        let (user, password) = match userinfo {
+            None => (None, None),
+            Some(userinfo) => match userinfo.split_once(':') {
+                Some((user, password)) => (
+                    Some(user.to_string()).filter(|user| !user.is_empty()),
+                    Some(password.to_string()),
+                ),
+                // A bare NATS token, or a Redis password without a user.
+                None => (None, Some(userinfo.to_string())),
+            },
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !host.ends_with(':') => (host, port.parse().ok()?),
+            _ => (address, default_port),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() || host.contains(['/', '@', ' ']) {
+            return None;
+        }
+        Some(Self {
+            backend,
+            host: host.to_string(),
+            port,
+            user,
+            password: password.filter(|p| !p.is_empty()).map(SecretString::new),
+        })
+    }
+
+    /// A client for the server; nothing connects until it is used.
+    pub fn client(&self) -> Arc<dyn PubSub> {
+        match self.backend {
+            Backend::Redis => Arc::new(RedisPubSub::new(self.clone())),
+            Backend::Nats => Arc::new(NatsPubSub::new(self.clone())),
+        }
+    }
+}
+
+/// A TCP connection read a line or a sized payload at a time.
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    async fn open(url: &PubSubUrl) -> Result<Self, PubSubError> {
+        let stream = tokio::time::timeout(
+            REPLY_TIMEOUT,
+            TcpStream::connect((url.host.as_str(), url.port)),
+        )
+        .await
+        .map_err(|_| PubSubError::Timeout)??;
+        stream.set_nodelay(true)?;
+        let (reader, writer) = stream.into_split();
+        Ok(Self {
+            reader: BufReader::new(reader),
+            writer,
+        })
+    }
+
+    /// The next line, without its CRLF.
+    async fn line(&mut self) -> Result<String, PubSubError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        line.truncate(line.trim_end_matches(['\r', '\n']).len());
+        Ok(line)
+    }
+
+    /// `len` bytes and the CRLF after them.
+    async fn payload(&mut self, len: usize) -> Result<Vec<u8>, PubSubError> {
+        if len > MAX_PAYLOAD {
+            return Err(PubSubError::Protocol(format!("{} byte payload", len)));
+        }
+        let mut payload = vec![0; len + 2];
+        self.reader.read_exact(&mut payload).await?;
+        payload.truncate(len);
+        Ok(payload)
+    }
+
+    async fn send(&mut self, bytes: &[u8]) -> Result<(), PubSubError> {
+        Ok(self.writer.write_all(bytes).await?)
+    }
+}
+
+async fn within<T>(
+    work: impl std::future::Future<Output = Result<T, PubSubError>>,
+) -> Result<T, PubSubError> {
+    tokio::time::timeout(REPLY_TIMEOUT, work)
+        .await
+        .map_err(|_| PubSubError::Timeout)?
+}
+
+/// Redis pub/sub over RESP.
+pub struct RedisPubSub {
+    url: PubSubUrl,
+    /// Kept open between publishes.
+    publisher: Mutex<Option<Connection>>,
+}
+
+/// A RESP reply; the commands used here never nest arrays.
+#[derive(Debug, PartialEq, Eq)]
+enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+}
+
+impl RedisPubSub {
+    pub fn new(url: PubSubUrl) -> Self {
+        Self {
+            url,
+            publisher: Mutex::new(None),
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection, PubSubError> {
+        let mut conn = Connection::open(&self.url).await?;
+        if let Some(password) = &self.url.password {
+            let password = password.expose_secret().as_bytes();
+            let auth = match &self.url.user {
+                Some(user) => redis_command(&[b"AUTH", user.as_bytes(), password]),
+                None => redis_command(&[b"AUTH", password]),
+            };
+            conn.send(&auth).await?;
+            within(read_reply(&mut conn)).await?;
+        }
+        Ok(conn)
+    }
+}
+
+/// A command as a RESP array of bulk strings.
+fn redis_command(args: &[&[u8]]) -> Vec<u8> {
+    let mut command = format!("*{}\r\n", args.len()).into_bytes();
+    for arg in args {
+        command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        command.extend_from_slice(arg);
+        command.extend_from_slice(b"\r\n");
+    }
+    command
+}
+
+async fn read_reply(conn: &mut Connection) -> Result<Reply, PubSubError> {
+    let line = conn.line().await?;
+    match line.strip_prefix('*') {
+        Some(len) => {
+            let len: usize = len
+                .parse()
+                .map_err(|_| PubSubError::Protocol(line.clone()))?;
+            let mut items = Vec::with_capacity(len.min(16));
+            for _ in 0..len {
+                let line = conn.line().await?;
+                items.push(read_scalar(conn, line).await?);
+            }
+            Ok(Reply::Array(items))
+        }
+        None => read_scalar(conn, line).await,
+    }
+}
+
+async fn read_scalar(conn: &mut Connection, line: String) -> Result<Reply, PubSubError> {
+    let protocol = || PubSubError::Protocol(line.clone());
+    let rest = line.get(1..).unwrap_or_default();
+    match line.as_bytes().first() {
+        Some(b'+') => Ok(Reply::Status(rest.to_string())),
+        Some(b'-') => Err(PubSubError::Server(rest.to_string())),
+        Some(b':') => rest.parse().map(Reply::Integer).map_err(|_| protocol()),
+        Some(b'$') => match rest.parse::<i64>().map_err(|_| protocol())? {
+            len if len < 0 => Ok(Reply::Bulk(None)),
+            len => Ok(Reply::Bulk(Some(conn.payload(len as usize).await?))),
+        },
+        _ => Err(protocol()),
+    }
+}
+
+#[async_trait]
+impl PubSub for RedisPubSub {
+    async fn publish(&self, channel: &str, payload: &[u8]) -> Result<(), PubSubError> {
+        let command = redis_command(&[b"PUBLISH", channel.as_bytes(), payload]);
+        let mut publisher = self.publisher.lock().await;
+        if let Some(conn) = publisher.as_mut() {
+            match redis_publish(conn, &command).await {
+                Ok(()) => return Ok(()),
+                // The server may have closed the kept connection while idle.
+                Err(PubSubError::Io(_)) => *publisher = None,
+                Err(err) => {
+                    *publisher = None;
+                    return Err(err);
+                }
+            }
+        }
+        let conn = publisher.insert(self.connect().await?);
+        let result = redis_publish(conn, &command).await;
+        if result.is_err() {
+            *publisher = None;
+        }
+        result
+    }
+
+    async fn subscribe(&self, channels: &[String]) -> Result<Subscription, PubSubError> {
+        let mut conn = self.connect().await?;
+        let mut command: Vec<&[u8]> = vec![b"SUBSCRIBE"];
+        command.extend(channels.iter().map(|channel| channel.as_bytes()));
+        conn.send(&redis_command(&command)).await?;
+        for _ in channels {
+            match within(read_reply(&mut conn)).await? {
+                Reply::Array(items) if items.first() == Some(&bulk("subscribe")) => {}
+                other => return Err(PubSubError::Protocol(format!("{:?}", other))),
+            }
+        }
+
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        tokio::spawn(async move {
+            loop {
+                let reply = match read_reply(&mut conn).await {
+                    Ok(reply) => reply,
+                    Err(err) => {
+                        tracing::warn!(error = %err, "redis subscription closed");
+                        return;
+                    }
+                };
+                let Reply::Array(items) = reply else { continue };
+                let mut items = items.into_iter();
+                let (
+                    Some(kind),
+                    Some(Reply::Bulk(Some(channel))),
+                    Some(Reply::Bulk(Some(payload))),
+                ) = (items.next(), items.next(), items.next())
+                else {
+                    continue;
+                };
+                if kind != bulk("message") {
+                    continue;
+                }
+                let message = Message {
+                    channel: String::from_utf8_lossy(&channel).into_owned(),
+                    payload,
+                };
+                if tx.send(message).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Subscription { rx })
+    }
+}
+
+async fn redis_publish(conn: &mut Connection, command: &[u8]) -> Result<(), PubSubError> {
+    conn.send(command).await?;
+    match within(read_reply(conn)).await? {
+        Reply::Integer(_) => Ok(()),
+        other => Err(PubSubError::Protocol(format!("{:?}", other))),
+    }
+}
+
+fn bulk(text: &str) -> Reply {
+    Reply::Bulk(Some(text.as_bytes().to_vec()))
+}
+
+/// NATS core pub/sub.
+pub struct NatsPubSub {
+    url: PubSubUrl,
+    /// Kept open between publishes.
+    publisher: Mutex<Option<Connection>>,
+}
+
+impl NatsPubSub {
+    pub fn new(url: PubSubUrl) -> Self {
+        Self {
+            url,
+            publisher: Mutex::new(None),
+        }
+    }
+
+    /// Open a connection, identify and wait for the server to accept it.
+    async fn connect(&self) -> Result<Connection, PubSubError> {
+        let mut conn = Connection::open(&self.url).await?;
+        let info = within(conn.line()).await?;
+        if !info.starts_with("INFO ") {
+            return Err(PubSubError::Protocol(info));
+        }
+        let mut options = json!({
+            "verbose": false,
+            "pedantic": false,
+            "name": "dex-api",
+            "lang": "rust",
+            "version": env!("CARGO_PKG_VERSION"),
+        });
+        match (&self.url.user, &self.url.password) {
+            (Some(user), Some(password)) => {
+                options["user"] = json!(user);
+                options["pass"] = json!(password.expose_secret());
+            }
+            (None, Some(token)) => options["auth_token"] = json!(token.expose_secret()),
+            _ => {}
+        }
+        conn.send(format!("CONNECT {}\r\nPING\r\n", options).as_bytes())
+            .await?;
+        nats_flush(&mut conn).await?;
+        Ok(conn)
+    }
+}
+
+/// Read until the PONG answering our PING, answering the server's own pings
+/// on the way.
+async fn nats_flush(conn: &mut Connection) -> Result<(), PubSubError> {
+    within(nats_await_pong(conn)).await
+}
+
+async fn nats_await_pong(conn: &mut Connection) -> Result<(), PubSubError> {
+    loop {
+        let line = conn.line().await?;
+        match line.as_str() {
+            "PONG" => return Ok(()),
+            "PING" => conn.send(b"PONG\r\n").await?,
+            "+OK" => {}
+            _ if line.starts_with("INFO ") => {}
+            _ => return Err(nats_error(line)),
+        }
+    }
+}
+
+/// `-ERR 'reason'` as the server's refusal; anything else is unexpected.
+fn nats_error(line: String) -> PubSubError {
+    match line.strip_prefix("-ERR") {
+        Some(err) => PubSubError::Server(err.trim().trim_matches('\'').to_string()),
+        None => PubSubError::Protocol(line),
+    }
+}
+
+async fn nats_publish(conn: &mut Connection, command: &[u8]) -> Result<(), PubSubError> {
+    conn.send(command).await?;
+    nats_flush(conn).await
+}
+
+/// Forward messages until the connection fails or the subscription is
+/// dropped.
+async fn nats_deliver(mut conn: Connection, tx: mpsc::Sender<Message>) -> Result<(), PubSubError> {
+    loop {
+        let line = conn.line().await?;
+        if line == "PING" {
+            conn.send(b"PONG\r\n").await?;
+            continue;
+        }
+        if line.starts_with("-ERR") {
+            return Err(nats_error(line));
+        }
+        // MSG <subject> <sid> [reply-to] <#bytes>
+        let Some(args) = line.strip_prefix("MSG ") else {
+            continue;
+        };
+        let args: Vec<&str> = args.split_whitespace().collect();
+        let (Some(channel), Some(Ok(len))) =
+            (args.first(), args.last().map(|len| len.parse::<usize>()))
+        else {
+            return Err(PubSubError::Protocol(line));
+        };
+        let message = Message {
+            channel: channel.to_string(),
+            payload: conn.payload(len).await?,
+        };
+        if tx.send(message).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+#[async_trait]
+impl PubSub for NatsPubSub {
+    async fn publish(&self, channel: &str, payload: &[u8]) -> Result<(), PubSubError> {
+        let mut command = format!("PUB {} {}\r\n", channel, payload.len()).into_bytes();
+        command.extend_from_slice(payload);
+        // The PING makes the server confirm it processed the PUB.
+        command.extend_from_slice(b"\r\nPING\r\n");
+        let mut publisher = self.publisher.lock().await;
+        if let Some(conn) = publisher.as_mut() {
+            match nats_publish(conn, &command).await {
+                Ok(()) => return Ok(()),
+                // Servers close connections that leave their pings
+                // unanswered, as an idle publisher does.
+                Err(PubSubError::Io(_)) => *publisher = None,
+                Err(err) => {
+                    *publisher = None;
+                    return Err(err);
+                }
+            }
+        }
+        let conn = publisher.insert(self.connect().await?);
+        let result = nats_publish(conn, &command).await;
+        if result.is_err() {
+            *publisher = None;
+        }
+        result
+    }
+
+    async fn subscribe(&self, channels: &[String]) -> Result<Subscription, PubSubError> {
+        let mut conn = self.connect().await?;
+        let mut command = String::new();
+        for (sid, channel) in channels.iter().enumerate() {
+            command.push_str(&format!("SUB {} {}\r\n", channel, sid + 1));
+        }
+        command.push_str("PING\r\n");
+        conn.send(command.as_bytes()).await?;
+        nats_flush(&mut conn).await?;
+
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        tokio::spawn(async move {
+            if let Err(err) = nats_deliver(conn, tx).await {
+                tracing::warn!(error = %err, "NATS subscription closed");
+            }
+        });
+        Ok(Subscription { rx })
+    }
+}
+
+/// Channels within one process.
+#[derive(Debug, Clone)]
+pub struct MemoryPubSub {
+    tx: broadcast::Sender<Message>,
+}
+
+impl Default for MemoryPubSub {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(SUBSCRIPTION_BUFFER).0,
+        }
+    }
+}
+
+#[async_trait]
+impl PubSub for MemoryPubSub {
+    async fn publish(&self, channel: &str, payload: &[u8]) -> Result<(), PubSubError> {
+        let _ = self.tx.send(Message {
+            channel: channel.to_string(),
+            payload: payload.to_vec(),
+        });
+        Ok(())
+    }
+
+    async fn subscribe(&self, channels: &[String]) -> Result<Subscription, PubSubError> {
+        let mut messages = self.tx.subscribe();
+        let channels = channels.to_vec();
+        let (tx, rx) = mpsc::channel(SUBSCRIPTION_BUFFER);
+        tokio::spawn(async move {
+            // A subscriber that falls behind is dropped, as a network one
+            // would be, so it sees the gap.
+            while let Ok(message) = messages.recv().await {
+                if channels.contains(&message.channel) && tx.send(message).await.is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Subscription { rx })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn urls_name_the_backend_address_and_credentials() {
+        let url = PubSubUrl::parse("redis://:hunter2@cache.internal").unwrap();
+        assert_eq!(url.backend, Backend::Redis);
+        assert_eq!(url.to_string(), "redis://cache.internal:6379");
+        assert_eq!(url.user, None);
+        assert_eq!(url.password.unwrap().expose_secret(), "hunter2");
+
+        let url = PubSubUrl::parse("nats://feed:pw@10.0.0.7:4333").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("10.0.0.7", 4333));
+        assert_eq!(url.user.as_deref(), Some("feed"));
+
+        assert!(PubSubUrl::parse("kafka://broker:9092").is_none());
+        assert!(PubSubUrl::parse("nats://broker:port").is_none());
+    }
+
+    #[tokio::test]
+    async fn redis_publishes_and_delivers_messages() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            // The subscriber connects first, then the publisher.
+            let (subscriber, _) = listener.accept().await.unwrap();
+            let (mut sub_read, mut sub_write) = subscriber.into_split();
+            let mut buf = vec![0; 256];
+            let n = sub_read.read(&mut buf).await.unwrap();
+            assert_eq!(
+                &buf[..n],
+                redis_command(&[b"SUBSCRIBE", b"feed"]).as_slice()
+            );
+            sub_write
+                .write_all(b"*3\r\n$9\r\nsubscribe\r\n$4\r\nfeed\r\n:1\r\n")
+                .await
+                .unwrap();
+
+            let (publisher, _) = listener.accept().await.unwrap();
+            let (mut pub_read, mut pub_write) = publisher.into_split();
+            let n = pub_read.read(&mut buf).await.unwrap();
+            assert_eq!(
+                &buf[..n],
+                redis_command(&[b"PUBLISH", b"feed", b"hi"]).as_slice()
+            );
+            pub_write.write_all(b":1\r\n").await.unwrap();
+            sub_write
+                .write_all(b"*3\r\n$7\r\nmessage\r\n$4\r\nfeed\r\n$2\r\nhi\r\n")
+                .await
+                .unwrap();
+            (sub_write, pub_write)
+        });
+
+        let url = PubSubUrl::parse(&format!("redis://127.0.0.1:{}", port)).unwrap();
+        let client = url.client();
+        let mut subscription = client.subscribe(&["feed".to_string()]).await.unwrap();
+        client.publish("feed", b"hi").await.unwrap();
+        let message = subscription.next().await.unwrap();
+        assert_eq!(message.channel, "feed");
+        assert_eq!(message.payload, b"hi");
+        drop(server.await.unwrap());
+        assert!(subscription.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn nats_answers_pings_and_reports_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            write
+                .write_all(b"INFO {\"max_payload\":1048576}\r\n")
+                .await
+                .unwrap();
+            assert!(lines
+                .next_line()
+                .await
+                .unwrap()
+                .unwrap()
+                .starts_with("CONNECT {"));
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "PING");
+            write.write_all(b"PING\r\nPONG\r\n").await.unwrap();
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "PONG");
+
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "PUB feed 2");
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "hi");
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "PING");
+            write.write_all(b"PONG\r\n").await.unwrap();
+
+            assert_eq!(lines.next_line().await.unwrap().unwrap(), "PUB feed 2");
+            write
+                .write_all(b"-ERR 'Permissions Violation for Publish to feed'\r\n")
+                .await
+                .unwrap();
+            // Keep the connection open until the client gives up on it.
+            let _ = lines.next_line().await;
+        });
+
+        let client =
+            NatsPubSub::new(PubSubUrl::parse(&format!("nats://127.0.0.1:{}", port)).unwrap());
+        client.publish("feed", b"hi").await.unwrap();
+        let err = client.publish("feed", b"no").await.unwrap_err();
+        assert!(
+            matches!(&err, PubSubError::Server(reason) if reason == "Permissions Violation for Publish to feed"),
+            "{err}"
+        );
+    }
+}
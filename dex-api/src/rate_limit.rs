@@ -0,0 +1,403 @@
+//! Token-bucket rate limiting for the HTTP and WebSocket routes.
+//!
+//! Order entry, market data and auth routes each have their own budget.
+//! Requests with a valid bearer token are charged to the trader, anything else
+//! to the client IP, so traders sharing an address do not starve each other
+//! and anonymous clients are still bounded. A trader penalised under the
+//! messaging policy gets a reduced share of every budget until it expires.
+//! Budgets can be swapped at runtime; buckets keep their tokens across the
+//! change and are capped to the new burst on their next refill.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+/// Buckets tracked before full (idle) ones are dropped.
+const MAX_TRACKED_CLIENTS: usize = 4096;
+
+/// Routes that share a budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteClass {
+    OrderEntry,
+    MarketData,
+    Auth,
+}
+
+impl RouteClass {
+    pub const ALL: [RouteClass; 3] = [
+        RouteClass::OrderEntry,
+        RouteClass::MarketData,
+        RouteClass::Auth,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RouteClass::OrderEntry => "order_entry",
+            RouteClass::MarketData => "market_data",
+            RouteClass::Auth => "auth",
+        }
+    }
+
+    /// Schema of a class as named in responses.
+    pub(crate) fn schema() -> utoipa::openapi::Object {
+        crate::openapi::string_enum(&Self::ALL.map(Self::as_str))
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Sustained rate and burst size of one bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Budget {
+    /// Tokens added per second; zero disables limiting for the class.
+    pub per_second: u32,
+    /// Bucket capacity, the most requests allowed back to back.
+    pub burst: u32,
+}
+
+impl Budget {
+    pub const UNLIMITED: Budget = Budget {
+        per_second: 0,
+        burst: 0,
+    };
+
+    pub fn is_unlimited(&self) -> bool {
+        self.per_second == 0
+    }
+
+    /// `percent` of this budget, keeping at least one request per second.
+    pub fn scaled(self, percent: u32) -> Budget {
+        if self.is_unlimited() {
+            return self;
+        }
+        let scale = |value: u32| (u64::from(value) * u64::from(percent) / 100).max(1) as u32;
+        Budget {
+            per_second: scale(self.per_second),
+            burst: scale(self.burst),
+        }
+    }
+}
+
+/// Budgets per route class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitConfig {
+    pub order_entry: Budget,
+    pub market_data: Budget,
+    pub auth: Budget,
+}
+
+impl RateLimitConfig {
+    pub fn unlimited() -> Self {
+        Self {
+            order_entry: Budget::UNLIMITED,
+            market_data: Budget::UNLIMITED,
+            auth: Budget::UNLIMITED,
+        }
+    }
+
+    pub fn budget(&self, class: RouteClass) -> Budget {
+        match class {
+            RouteClass::OrderEntry => self.order_entry,
+            RouteClass::MarketData => self.market_data,
+            RouteClass::Auth => self.auth,
+        }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            order_entry: Budget {
+                per_second: 10,
+                burst: 20,
+            },
+            market_data: Budget {
+                per_second: 20,
+                burst: 50,
+            },
+            auth: Budget {
+                per_second: 1,
+                burst: 10,
+            },
+        }
+    }
+}
+
+/// Who a request is charged to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ClientKey {
+    Trader(String),
+    Ip(IpAddr),
+    /// No token and no peer address, e.g. in-process requests.
+    Anonymous,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, budget: Budget, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * f64::from(budget.per_second)).min(f64::from(budget.burst));
+        self.updated = now;
+    }
+}
+
+/// Allowed and refused requests for one route class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitCounters {
+    pub class: RouteClass,
+    pub allowed: u64,
+    pub limited: u64,
+}
+
+/// Shared token buckets, keyed by route class and client.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RwLock<RateLimitConfig>,
+    buckets: Mutex<HashMap<(RouteClass, ClientKey), Bucket>>,
+    /// Percentage of each budget left to penalised traders, and until when.
+    reductions: Mutex<HashMap<String, (u32, Instant)>>,
+    allowed: [AtomicU64; 3],
+    limited: [AtomicU64; 3],
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            buckets: Mutex::new(HashMap::new()),
+            reductions: Mutex::new(HashMap::new()),
+            allowed: Default::default(),
+            limited: Default::default(),
+        }
+    }
+
+    /// The budgets in force.
+    pub fn config(&self) -> RateLimitConfig {
+        *self.config.read().expect("rate limiter poisoned")
+    }
+
+    /// Replace the budgets, e.g. on a configuration reload.
+    pub fn set_config(&self, config: RateLimitConfig) {
+        *self.config.write().expect("rate limiter poisoned") = config;
+    }
+
+    /// Charge one request to `key`; when refused, returns how long until the
+    /// next token.
+    pub fn check(&self, class: RouteClass, key: ClientKey) -> Result<(), Duration> {
+        self.check_at(class, key, Instant::now())
+    }
+
+    /// Limit `trader` to `percent` of every budget for `duration`.
+    pub fn reduce(&self, trader: &str, percent: u32, duration: Duration) {
+        self.reduce_at(trader, percent, duration, Instant::now());
+    }
+
+    fn reduce_at(&self, trader: &str, percent: u32, duration: Duration, now: Instant) {
+        self.reductions
+            .lock()
+            .expect("rate limiter poisoned")
+            .insert(trader.to_string(), (percent.min(100), now + duration));
+    }
+
+    /// The percentage of its budgets `trader` is limited to, if penalised.
+    pub fn reduction(&self, trader: &str) -> Option<u32> {
+        self.reduction_at(trader, Instant::now())
+    }
+
+    fn reduction_at(&self, trader: &str, now: Instant) -> Option<u32> {
+        let mut reductions = self.reductions.lock().expect("rate limiter poisoned");
+        match reductions.get(trader) {
+            Some(&(percent, until)) if until > now => Some(percent),
+            Some(_) => {
+                reductions.remove(trader);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn check_at(&self, class: RouteClass, key: ClientKey, now: Instant) -> Result<(), Duration> {
+        let config = self.config();
+        let mut budget = config.budget(class);
+        if let ClientKey::Trader(trader) = &key {
+            if let Some(percent) = self.reduction_at(trader, now) {
+                budget = budget.scaled(percent);
+            }
+        }
+        let outcome = if budget.is_unlimited() {
+            Ok(())
+        } else {
+            let mut buckets = self.buckets.lock().expect("rate limiter poisoned");
+            if buckets.len() >= MAX_TRACKED_CLIENTS {
+                // A full bucket is the same as a fresh one, so it can go.
+                buckets.retain(|(class, _), bucket| {
+                    let budget = config.budget(*class);
+                    bucket.refill(budget, now);
+                    bucket.tokens < f64::from(budget.burst)
+                });
+            }
+            let bucket = buckets.entry((class, key)).or_insert(Bucket {
+                tokens: f64::from(budget.burst),
+                updated: now,
+            });
+            bucket.refill(budget, now);
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                Ok(())
+            } else {
+                let wait = (1.0 - bucket.tokens) / f64::from(budget.per_second);
+                Err(Duration::from_secs_f64(wait))
+            }
+        };
+        let counter = match outcome {
+            Ok(()) => &self.allowed[class.index()],
+            Err(_) => &self.limited[class.index()],
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        outcome
+    }
+
+    pub fn counters(&self) -> Vec<RateLimitCounters> {
+        RouteClass::ALL
+            .iter()
+            .map(|&class| RateLimitCounters {
+                class,
+                allowed: self.allowed[class.index()].load(Ordering::Relaxed),
+                limited: self.limited[class.index()].load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_second: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            order_entry: Budget { per_second, burst },
+            ..RateLimitConfig::unlimited()
+        })
+    }
+
+    #[test]
+    fn buckets_allow_bursts_then_refill() {
+        let limiter = limiter(2, 3);
+        let alice = || ClientKey::Trader("alice".into());
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter
+                .check_at(RouteClass::OrderEntry, alice(), start)
+                .is_ok());
+        }
+        let wait = limiter
+            .check_at(RouteClass::OrderEntry, alice(), start)
+            .unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        // Other clients and classes have their own budgets.
+        let bob = ClientKey::Trader("bob".into());
+        assert!(limiter.check_at(RouteClass::OrderEntry, bob, start).is_ok());
+        assert!(limiter
+            .check_at(RouteClass::MarketData, alice(), start)
+            .is_ok());
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter
+            .check_at(RouteClass::OrderEntry, alice(), later)
+            .is_ok());
+        assert!(limiter
+            .check_at(RouteClass::OrderEntry, alice(), later)
+            .is_err());
+
+        let counters = limiter.counters();
+        assert_eq!(counters[0].class, RouteClass::OrderEntry);
+        assert_eq!((counters[0].allowed, counters[0].limited), (5, 2));
+        assert_eq!((counters[1].allowed, counters[1].limited), (1, 0));
+    }
+
+    #[test]
+    fn penalised_traders_get_a_share_of_the_budget() {
+        let limiter = limiter(10, 10);
+        let start = Instant::now();
+        limiter.reduce_at("alice", 20, Duration::from_secs(60), start);
+        assert_eq!(limiter.reduction_at("alice", start), Some(20));
+        let alice = || ClientKey::Trader("alice".into());
+        for _ in 0..2 {
+            assert!(limiter
+                .check_at(RouteClass::OrderEntry, alice(), start)
+                .is_ok());
+        }
+        assert!(limiter
+            .check_at(RouteClass::OrderEntry, alice(), start)
+            .is_err());
+        let bob = ClientKey::Trader("bob".into());
+        assert!(limiter.check_at(RouteClass::OrderEntry, bob, start).is_ok());
+
+        let expired = start + Duration::from_secs(61);
+        assert_eq!(limiter.reduction_at("alice", expired), None);
+        for _ in 0..10 {
+            assert!(limiter
+                .check_at(RouteClass::OrderEntry, alice(), expired)
+                .is_ok());
+        }
+    }
+
+    #[test]
+    fn new_budgets_apply_to_existing_buckets() {
+        let limiter = limiter(1, 5);
+        let start = Instant::now();
+        let alice = || ClientKey::Trader("alice".into());
+        assert!(limiter
+            .check_at(RouteClass::OrderEntry, alice(), start)
+            .is_ok());
+        limiter.set_config(RateLimitConfig {
+            order_entry: Budget {
+                per_second: 1,
+                burst: 2,
+            },
+            ..RateLimitConfig::unlimited()
+        });
+        assert_eq!(limiter.config().order_entry.burst, 2);
+        // The bucket held four tokens, capped to the new burst of two.
+        for _ in 0..2 {
+            assert!(limiter
+                .check_at(RouteClass::OrderEntry, alice(), start)
+                .is_ok());
+        }
+        assert!(limiter
+            .check_at(RouteClass::OrderEntry, alice(), start)
+            .is_err());
+    }
+
+    #[test]
+    fn idle_buckets_are_dropped_when_full() {
+        let limiter = limiter(1, 1);
+        let start = Instant::now();
+        for i in 0..MAX_TRACKED_CLIENTS {
+            let key = ClientKey::Trader(i.to_string());
+            limiter
+                .check_at(RouteClass::OrderEntry, key, start)
+                .unwrap();
+        }
+        let later = start + Duration::from_secs(2);
+        limiter
+            .check_at(RouteClass::OrderEntry, ClientKey::Anonymous, later)
+            .unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+}
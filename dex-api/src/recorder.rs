@@ -0,0 +1,587 @@
+//! Long-term archive of public market data.
+//!
+//! The recorder follows the same broadcasts as the `/ws` stream and writes
+//! every depth change, trade and ticker change as one JSON line, in gzip
+//! segments of one hour each, to an [`ObjectStore`]. Depth is recorded as
+//! deltas: changed levels with their new quantity, removed ones with zero.
+//! Each segment opens with a depth snapshot and ticker per pair, so it can be
+//! replayed on its own.
+//!
+//! Closed segments are listed in `manifest.json` with their hour, time range,
+//! record count, size, SHA-256 and channels, so readers find the files they
+//! need without listing the bucket. Segments that fail to upload are kept
+//! and retried at the next roll; the manifest is only written after its
+//! segments are.
+
+use crate::{
+    market_data::{depth_snapshot_for, ticker, DepthLevel, Ticker},
+    object_store::{self, ObjectStore, ObjectStoreError},
+    trade_tape::MarketTrade,
+    ApiState,
+};
+use chrono::DateTime;
+use dex_core::types::TradingPair;
+use flate2::{write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io::{self, Write},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::sync::{broadcast, Mutex};
+
+/// Key of the segment index.
+pub const MANIFEST_KEY: &str = "manifest.json";
+const SEGMENT_SECONDS: u64 = 3600;
+/// How often an idle recorder checks whether its segment's hour is over.
+const ROLL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum RecorderError {
+    #[error(transparent)]
+    Store(#[from] ObjectStoreError),
+    #[error("failed to compress segment: {0}")]
+    Compression(#[from] io::Error),
+    #[error("invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+}
+
+/// What a record holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    DepthSnapshot,
+    DepthDelta,
+    Trade,
+    Ticker,
+}
+
+/// One line of a segment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub timestamp: u64,
+    /// The `/ws` channel the message belongs to, e.g. `depth:ETH-USDC`.
+    pub channel: String,
+    #[serde(rename = "type")]
+    pub kind: RecordKind,
+    pub data: Value,
+}
+
+/// Index of every archived segment, oldest first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub segments: Vec<SegmentEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentEntry {
+    pub key: String,
+    /// Start of the hour the segment covers, in Unix seconds.
+    pub hour: u64,
+    pub first_timestamp: u64,
+    pub last_timestamp: u64,
+    pub records: u64,
+    /// Compressed size.
+    pub bytes: u64,
+    /// Hex SHA-256 of the compressed segment.
+    pub sha256: String,
+    pub channels: Vec<String>,
+}
+
+/// Read the manifest of an archive; empty when nothing was archived yet.
+pub async fn load_manifest(store: &dyn ObjectStore) -> Result<Manifest, RecorderError> {
+    match store.get(MANIFEST_KEY).await? {
+        Some(raw) => Ok(serde_json::from_slice(&raw)?),
+        None => Ok(Manifest::default()),
+    }
+}
+
+struct Segment {
+    hour: u64,
+    encoder: GzEncoder<Vec<u8>>,
+    first_timestamp: u64,
+    last_timestamp: u64,
+    records: u64,
+    channels: BTreeSet<String>,
+}
+
+struct ClosedSegment {
+    entry: SegmentEntry,
+    body: Vec<u8>,
+}
+
+/// Per-pair depth as last recorded.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Book {
+    bids: Vec<DepthLevel>,
+    asks: Vec<DepthLevel>,
+}
+
+#[derive(Serialize)]
+struct DepthData<'a> {
+    bids: &'a [DepthLevel],
+    asks: &'a [DepthLevel],
+}
+
+pub struct Recorder {
+    store: Arc<dyn ObjectStore>,
+    depth_levels: usize,
+    books: HashMap<String, Book>,
+    tickers: HashMap<String, Ticker>,
+    segment: Option<Segment>,
+    pending: Vec<ClosedSegment>,
+    /// Loaded before the first upload, so a store that cannot be read is
+    /// never overwritten with an empty index.
+    manifest: Option<Manifest>,
+}
+
+impl Recorder {
+    pub fn new(store: Arc<dyn ObjectStore>, depth_levels: usize) -> Self {
+        Self {
+            store,
+            depth_levels: depth_levels.max(1),
+            books: HashMap::new(),
+            tickers: HashMap::new(),
+            segment: None,
+            pending: Vec::new(),
+            manifest: None,
+        }
+    }
+
+    /// Record what changed in the book and on the tape since the last call.
+    pub async fn book_changed(&mut self, state: &ApiState, now: u64) -> Result<(), RecorderError> {
+        let (books, tickers) = {
+            let orderbook = state.orderbook.read().await;
+            let tape = state.trade_tape.read().await;
+            let pairs: HashSet<TradingPair> = orderbook
+                .orders
+                .values()
+                .map(|order| order.pair.clone())
+                .chain(tape.pairs())
+                .collect();
+            let mut books = HashMap::new();
+            let mut tickers = Vec::new();
+            for pair in pairs {
+                let depth = depth_snapshot_for(&orderbook, Some(&pair), self.depth_levels, now);
+                let book = Book {
+                    bids: depth.bids,
+                    asks: depth.asks,
+                };
+                books.insert(pair.to_string(), book);
+                tickers.push(ticker(&orderbook, &tape, &pair, now));
+            }
+            (books, tickers)
+        };
+
+        self.roll(now).await?;
+        // Pairs that left the book are recorded as emptied.
+        let gone: Vec<String> = self
+            .books
+            .keys()
+            .filter(|pair| !books.contains_key(*pair))
+            .cloned()
+            .collect();
+        let mut changes: Vec<(String, Book)> = books.into_iter().collect();
+        changes.extend(gone.into_iter().map(|pair| (pair, Book::default())));
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        for (pair, book) in changes {
+            let previous = self.books.get(&pair).cloned();
+            match previous {
+                None => {
+                    let data = DepthData {
+                        bids: &book.bids,
+                        asks: &book.asks,
+                    };
+                    self.write(
+                        &format!("depth:{}", pair),
+                        RecordKind::DepthSnapshot,
+                        data,
+                        now,
+                    )?;
+                }
+                Some(previous) if previous != book => {
+                    let bids = level_delta(&previous.bids, &book.bids);
+                    let asks = level_delta(&previous.asks, &book.asks);
+                    let data = DepthData {
+                        bids: &bids,
+                        asks: &asks,
+                    };
+                    self.write(
+                        &format!("depth:{}", pair),
+                        RecordKind::DepthDelta,
+                        data,
+                        now,
+                    )?;
+                }
+                Some(_) => {}
+            }
+            if book == Book::default() {
+                self.books.remove(&pair);
+            } else {
+                self.books.insert(pair, book);
+            }
+        }
+
+        for ticker in tickers {
+            let changed = self.tickers.get(&ticker.pair).is_none_or(|previous| {
+                (
+                    previous.best_bid,
+                    previous.best_ask,
+                    previous.last_price,
+                    previous.last_trade_time,
+                ) != (
+                    ticker.best_bid,
+                    ticker.best_ask,
+                    ticker.last_price,
+                    ticker.last_trade_time,
+                )
+            });
+            if changed {
+                let channel = format!("ticker:{}", ticker.pair);
+                self.write(&channel, RecordKind::Ticker, &ticker, now)?;
+                self.tickers.insert(ticker.pair.clone(), ticker);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record an execution, bust or correction from the trade broadcast.
+    pub async fn trade(&mut self, trade: &MarketTrade, now: u64) -> Result<(), RecorderError> {
+        self.roll(now).await?;
+        self.write(
+            &format!("trades:{}", trade.pair),
+            RecordKind::Trade,
+            trade,
+            now,
+        )
+    }
+
+    /// Close the segment once its hour is over and upload what is waiting.
+    pub async fn roll(&mut self, now: u64) -> Result<(), RecorderError> {
+        if self
+            .segment
+            .as_ref()
+            .is_some_and(|segment| segment.hour != hour_of(now))
+        {
+            self.close()?;
+        }
+        self.upload_pending().await
+    }
+
+    /// Close and upload the open segment, e.g. on shutdown.
+    pub async fn flush(&mut self) -> Result<(), RecorderError> {
+        self.close()?;
+        self.upload_pending().await
+    }
+
+    fn write(
+        &mut self,
+        channel: &str,
+        kind: RecordKind,
+        data: impl Serialize,
+        now: u64,
+    ) -> Result<(), RecorderError> {
+        if self.segment.is_none() {
+            self.open(now)?;
+        }
+        let record = Record {
+            timestamp: now,
+            channel: channel.to_string(),
+            kind,
+            data: serde_json::to_value(data)?,
+        };
+        self.append(&record)
+    }
+
+    fn append(&mut self, record: &Record) -> Result<(), RecorderError> {
+        let segment = self.segment.as_mut().expect("segment is open");
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        segment.encoder.write_all(&line)?;
+        segment.last_timestamp = record.timestamp;
+        segment.records += 1;
+        segment.channels.insert(record.channel.clone());
+        Ok(())
+    }
+
+    /// Start a segment with the state every later delta builds on.
+    fn open(&mut self, now: u64) -> Result<(), RecorderError> {
+        self.segment = Some(Segment {
+            hour: hour_of(now),
+            encoder: GzEncoder::new(Vec::new(), Compression::default()),
+            first_timestamp: now,
+            last_timestamp: now,
+            records: 0,
+            channels: BTreeSet::new(),
+        });
+        let mut pairs: Vec<&String> = self.books.keys().collect();
+        pairs.sort();
+        let mut records = Vec::new();
+        for pair in pairs {
+            let book = &self.books[pair];
+            let data = DepthData {
+                bids: &book.bids,
+                asks: &book.asks,
+            };
+            records.push(Record {
+                timestamp: now,
+                channel: format!("depth:{}", pair),
+                kind: RecordKind::DepthSnapshot,
+                data: serde_json::to_value(data)?,
+            });
+        }
+        let mut tickers: Vec<&Ticker> = self.tickers.values().collect();
+        tickers.sort_by(|a, b| a.pair.cmp(&b.pair));
+        for ticker in tickers {
+            records.push(Record {
+                timestamp: now,
+                channel: format!("ticker:{}", ticker.pair),
+                kind: RecordKind::Ticker,
+                data: serde_json::to_value(ticker)?,
+            });
+        }
+        for record in &records {
+            self.append(record)?;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), RecorderError> {
+        let Some(segment) = self.segment.take() else {
+            return Ok(());
+        };
+        let body = segment.encoder.finish()?;
+        let hour = DateTime::from_timestamp(segment.hour as i64, 0).unwrap_or_default();
+        self.pending.push(ClosedSegment {
+            entry: SegmentEntry {
+                key: format!(
+                    "segments/{}-{}.jsonl.gz",
+                    hour.format("%Y-%m-%d/%H"),
+                    segment.first_timestamp
+                ),
+                hour: segment.hour,
+                first_timestamp: segment.first_timestamp,
+                last_timestamp: segment.last_timestamp,
+                records: segment.records,
+                bytes: body.len() as u64,
+                sha256: object_store::sha256_hex(&body),
+                channels: segment.channels.into_iter().collect(),
+            },
+            body,
+        });
+        Ok(())
+    }
+
+    async fn upload_pending(&mut self) -> Result<(), RecorderError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut manifest = match self.manifest.take() {
+            Some(manifest) => manifest,
+            None => load_manifest(self.store.as_ref()).await?,
+        };
+        let mut result = Ok(());
+        let mut uploaded = 0;
+        for segment in &self.pending {
+            if let Err(err) = self
+                .store
+                .put(&segment.entry.key, segment.body.clone())
+                .await
+            {
+                result = Err(err.into());
+                break;
+            }
+            manifest.segments.push(segment.entry.clone());
+            uploaded += 1;
+        }
+        if uploaded > 0 {
+            let body = serde_json::to_vec_pretty(&manifest)?;
+            match self.store.put(MANIFEST_KEY, body).await {
+                Ok(()) => {
+                    self.pending.drain(..uploaded);
+                }
+                Err(err) => {
+                    // Upload the segments again with the next manifest.
+                    manifest
+                        .segments
+                        .truncate(manifest.segments.len() - uploaded);
+                    result = Err(err.into());
+                }
+            }
+        }
+        self.manifest = Some(manifest);
+        result
+    }
+}
+
+/// Levels of `current` that differ from `previous`, and removed ones at zero.
+fn level_delta(previous: &[DepthLevel], current: &[DepthLevel]) -> Vec<DepthLevel> {
+    let old: HashMap<_, _> = previous
+        .iter()
+        .map(|level| (level.price, level.quantity))
+        .collect();
+    let new: HashSet<_> = current.iter().map(|level| level.price).collect();
+    let mut delta: Vec<DepthLevel> = current
+        .iter()
+        .filter(|level| old.get(&level.price) != Some(&level.quantity))
+        .cloned()
+        .collect();
+    delta.extend(
+        previous
+            .iter()
+            .filter(|level| !new.contains(&level.price))
+            .map(|level| DepthLevel {
+                price: level.price,
+                quantity: 0,
+            }),
+    );
+    delta
+}
+
+fn hour_of(timestamp: u64) -> u64 {
+    timestamp - timestamp % SEGMENT_SECONDS
+}
+
+/// Feed the recorder from the market and trade broadcasts until they close.
+pub fn spawn(state: ApiState, recorder: Arc<Mutex<Recorder>>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut market_rx = state.market_tx.subscribe();
+        let mut trade_rx = state.trade_tx.subscribe();
+        let mut roll_check = tokio::time::interval(ROLL_CHECK_INTERVAL);
+        loop {
+            let result = tokio::select! {
+                update = market_rx.recv() => match update {
+                    // Depth is diffed against the book, so missed updates lose nothing.
+                    Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                        let now = state.determinism.now().unwrap_or_default();
+                        recorder.lock().await.book_changed(&state, now).await
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                trade = trade_rx.recv() => match trade {
+                    Ok(trade) => {
+                        let now = state.determinism.now().unwrap_or_default();
+                        recorder.lock().await.trade(&trade, now).await
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!(skipped, "market data recorder missed trades");
+                        Ok(())
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+                _ = roll_check.tick() => {
+                    let now = state.determinism.now().unwrap_or_default();
+                    recorder.lock().await.roll(now).await
+                }
+            };
+            if let Err(err) = result {
+                tracing::error!(error = %err, "market data recorder failed");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        determinism::SEEDED_EPOCH,
+        object_store::LocalObjectStore,
+        routes,
+        test_support::{place, test_state_with_seed, MemoryStorage},
+    };
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    fn read_segment(body: &[u8]) -> Vec<Record> {
+        let mut text = String::new();
+        GzDecoder::new(body).read_to_string(&mut text).unwrap();
+        text.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn archives_hourly_segments_with_a_manifest() {
+        let root = std::env::temp_dir().join(format!("dex-recorder-{}", std::process::id()));
+        let store = Arc::new(LocalObjectStore::new(&root));
+        let state = test_state_with_seed(Arc::new(MemoryStorage::default()), 3);
+        let filter = routes(state.clone());
+        let mut trades = state.trade_tx.subscribe();
+        let mut recorder = Recorder::new(store.clone(), 10);
+        let now = SEEDED_EPOCH;
+
+        place(&filter, "bob", "sell", 5).await;
+        recorder.book_changed(&state, now).await.unwrap();
+        place(&filter, "alice", "buy", 2).await;
+        let trade = trades.recv().await.unwrap();
+        recorder.trade(&trade, now + 1).await.unwrap();
+        recorder.book_changed(&state, now + 1).await.unwrap();
+        // Nothing is uploaded until the hour is over.
+        recorder.roll(now + 2).await.unwrap();
+        assert_eq!(
+            load_manifest(store.as_ref()).await.unwrap(),
+            Manifest::default()
+        );
+
+        let next_hour = hour_of(now) + SEGMENT_SECONDS;
+        recorder.roll(next_hour).await.unwrap();
+        place(&filter, "bob", "sell", 1).await;
+        recorder.book_changed(&state, next_hour + 5).await.unwrap();
+        recorder.flush().await.unwrap();
+
+        let manifest = load_manifest(store.as_ref()).await.unwrap();
+        assert_eq!(manifest.segments.len(), 2);
+        let first = &manifest.segments[0];
+        assert_eq!(first.hour, hour_of(now));
+        assert_eq!(
+            first.key,
+            format!("segments/2023-11-14/22-{}.jsonl.gz", now)
+        );
+        assert_eq!(
+            first.channels,
+            ["depth:ETH-USDC", "ticker:ETH-USDC", "trades:ETH-USDC"]
+        );
+        let body = store.get(&first.key).await.unwrap().unwrap();
+        assert_eq!(object_store::sha256_hex(&body), first.sha256);
+        let records = read_segment(&body);
+        assert_eq!(records.len() as u64, first.records);
+        let kinds: Vec<_> = records.iter().map(|record| record.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                RecordKind::DepthSnapshot,
+                RecordKind::Ticker,
+                RecordKind::Trade,
+                RecordKind::DepthDelta,
+                RecordKind::Ticker,
+            ]
+        );
+        assert_eq!(records[0].data["asks"][0]["quantity"], 5);
+        assert_eq!(records[2].data["quantity"], 2);
+        assert_eq!(records[3].data["asks"][0]["quantity"], 3);
+        assert_eq!(records[4].data["last_price"], 1000);
+
+        // The next segment starts from a snapshot of the book it inherited.
+        let records = read_segment(&store.get(&manifest.segments[1].key).await.unwrap().unwrap());
+        assert_eq!(records[0].kind, RecordKind::DepthSnapshot);
+        assert_eq!(records[0].timestamp, next_hour + 5);
+        assert_eq!(records[0].data["asks"][0]["quantity"], 3);
+        assert_eq!(records[2].kind, RecordKind::DepthDelta);
+        assert_eq!(records[2].data["asks"][0]["quantity"], 4);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn deltas_zero_removed_levels() {
+        let level = |price, quantity| DepthLevel { price, quantity };
+        let delta = level_delta(
+            &[level(100, 5), level(99, 2)],
+            &[level(100, 3), level(98, 1)],
+        );
+        assert_eq!(delta, [level(100, 3), level(98, 1), level(99, 0)]);
+    }
+}
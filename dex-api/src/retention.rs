@@ -0,0 +1,203 @@
+//! Moving old trades and orders out of the hot tables.
+//!
+//! With `RETENTION_DAYS` set, the leader moves trades and orders older than
+//! that to `trades_archive` and `orders_archive` every interval, in batches,
+//! so the tables orders and history queries hit stop growing with volume.
+//! Trades are only moved once a settlement netting batch covers them, since
+//! netting reads them from the hot table; orders stay while they are open
+//! or a trade still in the hot table refers to them.
+
+use crate::ApiState;
+use dex_db::DatabaseError;
+use std::time::Duration;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// What is archived, and how often.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionConfig {
+    /// Age in days after which rows are archived; zero keeps everything.
+    pub max_age_days: u64,
+    pub interval: Duration,
+    /// Rows moved per statement.
+    pub batch_size: u32,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            max_age_days: 0,
+            interval: Duration::from_secs(3600),
+            batch_size: 1000,
+        }
+    }
+}
+
+impl RetentionConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.max_age_days > 0
+    }
+}
+
+/// Rows moved by one run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Archived {
+    pub trades: u64,
+    pub orders: u64,
+}
+
+/// Archive everything that has aged out, a batch at a time.
+pub async fn run(state: &ApiState, config: &RetentionConfig) -> Result<Archived, DatabaseError> {
+    let now = state.determinism.now().unwrap_or_default();
+    let before = now.saturating_sub(config.max_age_days.saturating_mul(SECONDS_PER_DAY));
+    let netted = state.settlement_repo.last_netted_trade_id().await?;
+    let batch = config.batch_size.max(1);
+
+    let mut archived = Archived::default();
+    loop {
+        let moved = state
+            .retention_repo
+            .archive_trades(before, netted, batch)
+            .await?;
+        archived.trades += moved;
+        if moved < u64::from(batch) {
+            break;
+        }
+    }
+    // After the trades, so orders whose trades just moved go too.
+    loop {
+        let moved = state.retention_repo.archive_orders(before, batch).await?;
+        archived.orders += moved;
+        if moved < u64::from(batch) {
+            break;
+        }
+    }
+    Ok(archived)
+}
+
+/// Archive aged-out rows every `config.interval`, if retention is on.
+pub fn spawn(state: ApiState, config: RetentionConfig) {
+    if !config.is_enabled() {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval);
+        loop {
+            ticker.tick().await;
+            match run(&state, &config).await {
+                Ok(archived) if archived != Archived::default() => tracing::info!(
+                    trades = archived.trades,
+                    orders = archived.orders,
+                    "archived old trades and orders"
+                ),
+                Ok(_) => {}
+                Err(err) => tracing::error!(error = ?err, "failed to archive old rows"),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{test_state_with_memory, MemoryStorage};
+    use dex_core::types::{Order, OrderSide, OrderType, Trade, TradingPair};
+    use dex_db::{NettingSet, OrderFill, OrderRepo, OrderStatus, SettlementRepo};
+    use std::sync::Arc;
+
+    const DAY: u64 = SECONDS_PER_DAY;
+
+    fn order(id: u64, timestamp: u64) -> Order {
+        Order {
+            id,
+            trader_id: "alice".parse().unwrap(),
+            pair: TradingPair::new("ETH".parse().unwrap(), "USDC".parse().unwrap()).unwrap(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            price: Some(1000),
+            quantity: 1,
+            timestamp,
+        }
+    }
+
+    fn trade(id: u64, maker: u64, taker: u64, timestamp: u64) -> Trade {
+        Trade {
+            id,
+            maker_order_id: maker,
+            taker_order_id: taker,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 1000,
+            quantity: 1,
+            timestamp,
+        }
+    }
+
+    #[tokio::test]
+    async fn archives_netted_trades_and_finished_orders_past_the_age() {
+        let storage = Arc::new(MemoryStorage::default());
+        let state = test_state_with_memory(storage.clone());
+        let now = state.determinism.now().unwrap();
+        let old = now - 40 * DAY;
+        for (id, timestamp) in [(1, old), (2, old), (3, old), (4, old), (5, now)] {
+            storage.save_order(&order(id, timestamp)).await.unwrap();
+        }
+        // Order 4 is still resting; the others are filled.
+        let fills: Vec<OrderFill> = [1, 2, 3, 5]
+            .into_iter()
+            .map(|order_id| OrderFill {
+                order_id,
+                status: OrderStatus::Filled,
+                remaining_quantity: 0,
+            })
+            .collect();
+        storage.update_order_fills(&fills).await.unwrap();
+        storage.trades.lock().unwrap().extend([
+            trade(1, 1, 2, old),
+            // Not netted yet, so it and order 3 stay.
+            trade(2, 3, 5, old),
+        ]);
+        storage
+            .save_netting_set(&NettingSet {
+                id: 0,
+                created_at: old,
+                created_by: "admin".into(),
+                after_trade_id: 0,
+                last_trade_id: 1,
+                trade_count: 1,
+                gross_transfers: 2,
+                unattributed_trade_ids: Vec::new(),
+                positions: Vec::new(),
+                transfers: Vec::new(),
+            })
+            .await
+            .unwrap();
+
+        let config = RetentionConfig {
+            max_age_days: 30,
+            batch_size: 1,
+            ..RetentionConfig::default()
+        };
+        let archived = run(&state, &config).await.unwrap();
+
+        assert_eq!(
+            archived,
+            Archived {
+                trades: 1,
+                orders: 2
+            }
+        );
+        let hot_trades: Vec<u64> = storage
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(hot_trades, [2]);
+        let mut hot_orders: Vec<u64> = storage.orders.lock().unwrap().keys().copied().collect();
+        hot_orders.sort_unstable();
+        assert_eq!(hot_orders, [3, 4, 5]);
+        assert_eq!(storage.last_order_id().await.unwrap(), 5);
+    }
+}
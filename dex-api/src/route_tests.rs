@@ -0,0 +1,4258 @@
+//! Tests of the composed routes, driven through `warp::test`.
+
+use crate::{
+    amm_events, api_keys,
+    auth::{AuthManager, KeyMaterial, SigningKey},
+    config::WsHeartbeat,
+    cors, ip_allowlist,
+    messaging_policy::{self, MessagingPolicy},
+    rate_limit::{Budget, RateLimitConfig, RateLimiter},
+    routes, sequencing, sessions,
+    siwe::SiweMessage,
+    surface_routes,
+    test_support::{
+        admin_token, bearer_token, next_event, place, scoped_token, test_config,
+        test_state_with_memory, test_state_with_seed, MemoryStorage,
+    },
+    totp, usage, Determinism, Surface,
+};
+use dex_core::{
+    ledger::{Account, EntryKind, JournalEntry},
+    types::{Order, OrderSide, OrderType, Trade, TradingPair},
+};
+use dex_db::{OrderFill, OrderRepo, OrderStatus, SequencingReceipt};
+use ethers_core::{
+    k256::ecdsa::SigningKey as WalletKey,
+    types::{
+        transaction::eip712::{Eip712, TypedData},
+        Signature, U256,
+    },
+    utils::{hash_message, secret_key_to_address},
+};
+use secrecy::SecretString;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use warp::http::{Method, StatusCode};
+
+fn order(id: u64, trader: &str, side: OrderSide) -> Order {
+    Order {
+        id,
+        trader_id: trader.parse().unwrap(),
+        pair: "ETH-USDC".parse().unwrap(),
+        side,
+        order_type: OrderType::Limit,
+        price: Some(1000),
+        quantity: 5,
+        timestamp: 1_700_000_000,
+    }
+}
+
+#[tokio::test]
+async fn storage_failures_are_told_apart() {
+    use warp::Reply;
+    for (err, code, status) in [
+        (
+            dex_db::DatabaseError::Unavailable,
+            "storage_circuit_open",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            dex_db::DatabaseError::Timeout("load_order"),
+            "storage_timeout",
+            StatusCode::SERVICE_UNAVAILABLE,
+        ),
+        (
+            dex_db::DatabaseError::DataIntegrityError,
+            "storage_error",
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ),
+    ] {
+        let response = super::storage_error_reply(&err, "failed").into_response();
+        assert_eq!(response.status(), status);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], code);
+    }
+}
+
+#[tokio::test]
+async fn matches_store_the_status_and_remaining_quantity_of_each_order() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let filter = routes(state.clone());
+
+    let maker = place(&filter, "bob", "sell", 5).await["order_id"]
+        .as_u64()
+        .unwrap();
+    let taker = place(&filter, "alice", "buy", 2).await["order_id"]
+        .as_u64()
+        .unwrap();
+
+    // The book rests what storage reports as open, and nothing of the taker.
+    let resting: Vec<(u64, u64)> = state
+        .orderbook
+        .read()
+        .await
+        .resting_orders()
+        .iter()
+        .map(|order| (order.id, order.quantity))
+        .collect();
+    assert_eq!(resting, vec![(maker, 3)]);
+    let open: Vec<(u64, u64)> = storage
+        .load_open_orders()
+        .await
+        .unwrap()
+        .iter()
+        .map(|order| (order.id, order.quantity))
+        .collect();
+    assert_eq!(open, resting);
+    let depth = warp::test::request()
+        .path("/orderbook/depth?levels=10")
+        .reply(&filter)
+        .await;
+    let depth: serde_json::Value = serde_json::from_slice(depth.body()).unwrap();
+    assert!(depth["bids"].as_array().unwrap().is_empty());
+    assert_eq!(
+        depth["asks"],
+        serde_json::json!([{ "price": 1000, "quantity": 3 }])
+    );
+
+    let fills = storage.order_fills.lock().unwrap();
+    assert_eq!(
+        fills.get(&taker),
+        Some(&OrderFill {
+            order_id: taker,
+            status: OrderStatus::Filled,
+            remaining_quantity: 0,
+        })
+    );
+    assert_eq!(
+        fills.get(&maker),
+        Some(&OrderFill {
+            order_id: maker,
+            status: OrderStatus::PartiallyFilled,
+            remaining_quantity: 3,
+        })
+    );
+}
+
+#[tokio::test]
+async fn trader_trades_are_served_from_the_trade_repo() {
+    let storage = Arc::new(MemoryStorage::default());
+    {
+        let mut orders = storage.orders.lock().unwrap();
+        orders.insert(1, order(1, "bob", OrderSide::Sell));
+        orders.insert(2, order(2, "alice", OrderSide::Buy));
+    }
+    storage.trades.lock().unwrap().push(Trade {
+        id: 7,
+        maker_order_id: 1,
+        taker_order_id: 2,
+        base_token: "ETH".parse().unwrap(),
+        quote_token: "USDC".parse().unwrap(),
+        price: 1000,
+        quantity: 5,
+        timestamp: 1_700_000_001,
+    });
+    let filter = routes(test_state_with_memory(storage));
+
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trades"][0]["id"], 7);
+}
+
+#[tokio::test]
+async fn trader_trades_are_paginated_by_cursor() {
+    let storage = Arc::new(MemoryStorage::default());
+    {
+        let mut orders = storage.orders.lock().unwrap();
+        orders.insert(1, order(1, "bob", OrderSide::Sell));
+        orders.insert(2, order(2, "alice", OrderSide::Buy));
+    }
+    storage
+        .trades
+        .lock()
+        .unwrap()
+        .extend((1..=3).map(|id| Trade {
+            id,
+            maker_order_id: 1,
+            taker_order_id: 2,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 1000,
+            quantity: 1,
+            timestamp: 1_700_000_000 + id,
+        }));
+    let filter = routes(test_state_with_memory(storage));
+
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades?limit=2&pair=ETH-USDC")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trades"].as_array().unwrap().len(), 2);
+    assert_eq!(body["next_cursor"], 2);
+
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades?limit=2&after_id=2")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trades"][0]["id"], 3);
+    assert!(body.get("next_cursor").is_none());
+
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades?from=20&to=10")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn pool_snapshot_tracks_the_pool_sequence() {
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let pair: TradingPair = "ETH-USDC".parse().unwrap();
+    let alice = "alice".parse().unwrap();
+    amm_events::add_liquidity(&state, &pair, 30, &alice, 1_000, 2_000_000)
+        .await
+        .unwrap();
+    let filter = routes(state.clone());
+
+    let response = warp::test::request()
+        .path("/amm/pools")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()["etag"].clone();
+    let snapshot: dex_core::amm_router::PoolSnapshot =
+        serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(snapshot.sequence, 1);
+    assert_eq!(snapshot.pools[0].reserve_base, 1_000);
+    assert_eq!(snapshot.pools[0].reserve_quote, 2_000_000);
+
+    // The snapshot quotes exactly what the pool pays.
+    let router = dex_core::amm_router::AmmRouter::new(snapshot);
+    let quote = router.quote(pair.base(), pair.quote(), 10, 3).unwrap();
+    let paid = amm_events::swap(&state, &alice, &pair, pair.base(), 10)
+        .await
+        .unwrap();
+    assert_eq!(quote.amount_out, paid);
+
+    // The swap moved the sequence, so the cached copy is stale.
+    let response = warp::test::request()
+        .path("/amm/pools")
+        .header("if-none-match", etag.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let snapshot: dex_core::amm_router::PoolSnapshot =
+        serde_json::from_slice(response.body()).unwrap();
+    assert!(!router.is_fresh(snapshot.sequence));
+    let etag = response.headers()["etag"].clone();
+    let response = warp::test::request()
+        .path("/amm/pools")
+        .header("if-none-match", etag)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn provider_summary_aggregates_pools() {
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    {
+        let mut pools = state.amm.write().await;
+        let alice = "alice".parse().unwrap();
+        pools
+            .get_or_create("ETH-USDC".parse().unwrap(), 30)
+            .add_liquidity(&alice, 1_000, 2_000_000)
+            .unwrap();
+        pools
+            .get_or_create("BTC-USDC".parse().unwrap(), 30)
+            .add_liquidity(&alice, 10, 300_000)
+            .unwrap();
+    }
+    let filter = routes(state.clone());
+
+    let response = warp::test::request()
+        .path("/amm/providers/alice/summary")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["positions"][0]["pool"], "BTC-USDC");
+    assert_eq!(body["positions"][1]["pool"], "ETH-USDC");
+    assert_eq!(body["positions"][1]["value"], 4_000_000);
+    assert_eq!(body["totals"]["USDC"]["value"], 4_600_000);
+    assert_eq!(body["totals"]["USDC"]["impermanent_loss"], 0);
+    assert_eq!(body["totals"]["USDC"]["value_usd"], serde_json::Value::Null);
+
+    // Once priced, totals are also reported in USD.
+    crate::usd_prices::refresh(&state).await;
+    let response = warp::test::request()
+        .path("/amm/providers/alice/summary")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["totals"]["USDC"]["value_usd"], 4_600_000.0);
+    assert_eq!(body["total_value_usd"], 4_600_000.0);
+    let response = warp::test::request()
+        .path("/prices/usd")
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["prices"]["ETH"]["price"], 2_000.0);
+    assert_eq!(
+        body["prices"]["BTC"]["route"],
+        serde_json::json!(["BTC-USDC"])
+    );
+
+    let response = warp::test::request()
+        .path("/amm/providers/alice/summary")
+        .header("authorization", bearer_token("bob", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn requests_over_budget_are_refused() {
+    let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    state.rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+        market_data: Budget {
+            per_second: 1,
+            burst: 2,
+        },
+        ..RateLimitConfig::unlimited()
+    }));
+    let filter = routes(state);
+    let get_prices = |peer: [u8; 4], token: Option<String>| {
+        let mut request = warp::test::request()
+            .path("/orderbook/prices")
+            .remote_addr(SocketAddr::from((peer, 40_000)));
+        if let Some(token) = token {
+            request = request.header("authorization", token);
+        }
+        request.reply(&filter)
+    };
+
+    for _ in 0..2 {
+        assert_eq!(
+            get_prices([10, 0, 0, 1], None).await.status(),
+            StatusCode::OK
+        );
+    }
+    let refused = get_prices([10, 0, 0, 1], None).await;
+    assert_eq!(refused.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(refused.headers()["retry-after"], "1");
+    let body: serde_json::Value = serde_json::from_slice(refused.body()).unwrap();
+    assert_eq!(body["code"], "rate_limited");
+
+    // Other addresses, and traders behind the same one, have their own budgets.
+    assert_eq!(
+        get_prices([10, 0, 0, 2], None).await.status(),
+        StatusCode::OK
+    );
+    let alice = Some(bearer_token("alice", 300));
+    assert_eq!(
+        get_prices([10, 0, 0, 1], alice).await.status(),
+        StatusCode::OK
+    );
+    // Order entry has a separate (here unlimited) budget.
+    place(&filter, "alice", "buy", 1).await;
+
+    let metrics = warp::test::request().path("/metrics").reply(&filter).await;
+    let metrics = String::from_utf8_lossy(metrics.body()).into_owned();
+    assert!(metrics.contains("dex_api_rate_limited_total{class=\"market_data\"} 1"));
+    assert!(metrics.contains("dex_api_rate_limit_allowed_total{class=\"market_data\"} 4"));
+}
+
+#[tokio::test]
+async fn private_stream_delivers_only_own_order_events() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let mut alice = warp::test::ws()
+        .path("/ws/orders")
+        .header("authorization", bearer_token("alice", 300))
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+
+    place(&filter, "bob", "sell", 5).await;
+    let created = place(&filter, "alice", "buy", 3).await;
+
+    let accepted = next_event(&mut alice).await;
+    assert_eq!(accepted["type"], "order_update");
+    assert_eq!(accepted["status"], "accepted");
+    assert_eq!(accepted["order_id"], created["order_id"]);
+    let fill = next_event(&mut alice).await;
+    assert_eq!(fill["type"], "fill");
+    assert_eq!(fill["liquidity"], "taker");
+    assert_eq!(fill["quantity"], 3);
+    let filled = next_event(&mut alice).await;
+    assert_eq!(filled["status"], "filled");
+    assert_eq!(filled["remaining_quantity"], 0);
+}
+
+#[tokio::test]
+async fn trade_stream_is_scoped_to_its_market() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let mut eth = warp::test::ws()
+        .path("/ws/trades/ETH-USDC")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+    let mut btc = warp::test::ws()
+        .path("/ws/trades/BTC-USDC")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 3).await;
+
+    let trade = next_event(&mut eth).await;
+    assert_eq!(trade["type"], "trade");
+    assert_eq!(trade["pair"], "ETH-USDC");
+    assert_eq!(trade["price"], 1000);
+    assert_eq!(trade["quantity"], 3);
+    assert_eq!(trade["side"], "buy");
+    let quiet = tokio::time::timeout(std::time::Duration::from_millis(50), btc.recv()).await;
+    assert!(quiet.is_err());
+
+    let invalid = warp::test::ws()
+        .path("/ws/trades/ETH")
+        .handshake(filter)
+        .await;
+    assert!(invalid.is_err());
+}
+
+async fn send_op(client: &mut warp::test::WsClient, op: &str, channel: &str) {
+    let msg = serde_json::json!({ "op": op, "channel": channel });
+    client.send_text(msg.to_string()).await;
+}
+
+/// Skip frames until one of `kind` arrives on `channel`.
+async fn next_on(
+    client: &mut warp::test::WsClient,
+    kind: &str,
+    channel: &str,
+) -> serde_json::Value {
+    loop {
+        let frame = next_event(client).await;
+        if frame["type"] == kind && frame["channel"] == channel {
+            return frame;
+        }
+    }
+}
+
+#[tokio::test]
+async fn multiplexed_stream_serves_public_channels() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let mut client = warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+
+    for channel in ["depth:ETH-USDC", "trades:ETH-USDC", "ticker:ETH-USDC"] {
+        send_op(&mut client, "subscribe", channel).await;
+        next_on(&mut client, "subscribed", channel).await;
+    }
+
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 2).await;
+
+    // Channels are served concurrently, so frames may interleave in any order.
+    let (mut trade, mut ticker, mut depth) = (None, None, None);
+    while trade.is_none() || ticker.is_none() || depth.is_none() {
+        let frame = next_event(&mut client).await;
+        match frame["channel"].as_str() {
+            Some("trades:ETH-USDC") => trade = Some(frame),
+            Some("ticker:ETH-USDC") if frame["data"]["last_price"] == 1000 => ticker = Some(frame),
+            Some("depth:ETH-USDC") if frame["data"]["asks"][0]["quantity"] == 3 => {
+                depth = Some(frame)
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(trade.unwrap()["data"]["quantity"], 2);
+    assert_eq!(ticker.unwrap()["data"]["best_ask"], 1000);
+    assert_eq!(depth.unwrap()["data"]["asks"][0]["price"], 1000);
+
+    send_op(&mut client, "unsubscribe", "trades:ETH-USDC").await;
+    next_on(&mut client, "unsubscribed", "trades:ETH-USDC").await;
+    send_op(&mut client, "subscribe", "candles:ETH-USDC").await;
+    let error = loop {
+        let frame = next_event(&mut client).await;
+        if frame["type"] == "error" {
+            break frame;
+        }
+    };
+    assert_eq!(error["code"], "invalid_channel");
+}
+
+#[tokio::test]
+async fn multiplexed_stream_serves_amm_channels() {
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let filter = routes(state.clone());
+    let pair: TradingPair = "ETH-USDC".parse().unwrap();
+    let provider = "alice".parse().unwrap();
+    amm_events::add_liquidity(&state, &pair, 30, &provider, 1_000_000, 2_000_000_000)
+        .await
+        .unwrap();
+    let mut client = warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+
+    send_op(&mut client, "subscribe", "swaps:ETH-USDC").await;
+    next_on(&mut client, "subscribed", "swaps:ETH-USDC").await;
+    send_op(&mut client, "subscribe", "pool:ETH-USDC").await;
+    let snapshot = next_on(&mut client, "update", "pool:ETH-USDC").await;
+    assert_eq!(snapshot["data"]["reserve_base"], 1_000_000);
+    assert_eq!(snapshot["data"]["price"], 2_000.0);
+    assert_eq!(snapshot["data"]["bids"][0]["quantity"], 2_509);
+
+    let out = amm_events::swap(&state, &provider, &pair, pair.base(), 10_000)
+        .await
+        .unwrap();
+    let swap = next_on(&mut client, "update", "swaps:ETH-USDC").await;
+    assert_eq!(swap["data"]["token_in"], "ETH");
+    assert_eq!(swap["data"]["amount_in"], 10_000);
+    assert_eq!(swap["data"]["amount_out"], out);
+    assert_eq!(swap["data"]["fee"], 30);
+    let depth = next_on(&mut client, "update", "pool:ETH-USDC").await;
+    assert_eq!(depth["data"]["reserve_base"], 1_010_000);
+    assert!(depth["data"]["price"].as_f64().unwrap() < 2_000.0);
+
+    // Liquidity changes move the pool without a swap.
+    amm_events::remove_liquidity(&state, &pair, &provider, 1_000)
+        .await
+        .unwrap();
+    let depth = next_on(&mut client, "update", "pool:ETH-USDC").await;
+    assert!(depth["data"]["reserve_base"].as_u64().unwrap() < 1_010_000);
+
+    // The swap is on the pair's consolidated tape and in its history.
+    let response = warp::test::request()
+        .path("/markets/ETH-USDC/trades")
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trades"][0]["venue"], "amm");
+    assert_eq!(body["trades"][0]["side"], "sell");
+    assert_eq!(body["trades"][0]["quantity"], 10_000);
+    let swaps = state
+        .swap_repo
+        .get_swaps(&dex_db::TradeFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(swaps.len(), 1);
+    assert_eq!(swaps[0].quote_quantity, out);
+}
+
+#[tokio::test]
+async fn explorer_serves_blocks_transactions_and_finality() {
+    use dex_core::types::{Block, Transaction, Validator};
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    {
+        let mut consensus = state.consensus.write().await;
+        consensus
+            .add_validator(Validator {
+                id: "v1".to_string(),
+                public_key: vec![1],
+                stake: 100,
+            })
+            .unwrap();
+        consensus.initialize_shards(2).unwrap();
+    }
+    let transaction = Transaction {
+        from: "alice".to_string(),
+        to: "bob".to_string(),
+        amount: 25,
+        nonce: 7,
+        signature: vec![],
+    };
+    let tx_hash = ethers_core::utils::hex::encode(transaction.hash());
+    for (shard_id, height, transactions) in
+        [(0, 1, vec![transaction]), (1, 1, vec![]), (0, 2, vec![])]
+    {
+        let block = Block {
+            id: height,
+            height,
+            timestamp: 1_700_000_000 + height,
+            transactions,
+            previous_hash: vec![0; 32],
+            hash: vec![shard_id as u8 + 1; 32],
+            signature: vec![],
+        };
+        crate::chain::commit_block(&state, shard_id, block)
+            .await
+            .unwrap();
+    }
+    let filter = routes(state.clone());
+
+    let response = warp::test::request()
+        .path("/chain/blocks/1")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["blocks"].as_array().unwrap().len(), 2);
+    assert_eq!(body["blocks"][0]["shard_id"], 0);
+    assert_eq!(body["blocks"][0]["hash"], "01".repeat(32));
+    assert_eq!(body["blocks"][0]["transactions"][0], tx_hash.as_str());
+    assert_eq!(body["blocks"][0]["finalized"], false);
+    let missing = warp::test::request()
+        .path("/chain/blocks/9")
+        .reply(&filter)
+        .await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+    let response = warp::test::request()
+        .path(&format!("/chain/txs/0x{}", tx_hash))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["height"], 1);
+    assert_eq!(body["amount"], 25);
+    assert_eq!(body["finalized"], false);
+
+    let mut client = warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+    send_op(&mut client, "subscribe", "blocks").await;
+    next_on(&mut client, "subscribed", "blocks").await;
+
+    let finalized = crate::chain::finalize(&state, 0, 2).await.unwrap();
+    assert_eq!(finalized.len(), 2);
+    for height in [1, 2] {
+        let update = next_on(&mut client, "update", "blocks").await;
+        assert_eq!(update["data"]["shard_id"], 0);
+        assert_eq!(update["data"]["height"], height);
+        assert_eq!(update["data"]["finalized"], true);
+    }
+    // Finalizing again reports nothing new.
+    assert!(crate::chain::finalize(&state, 0, 2)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let response = warp::test::request()
+        .path(&format!("/chain/txs/{}", tx_hash))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["finalized"], true);
+}
+
+#[tokio::test]
+async fn multiplexed_stream_gates_private_channel() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let mut anonymous = warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+    send_op(&mut anonymous, "subscribe", "orders").await;
+    assert_eq!(next_event(&mut anonymous).await["code"], "unauthorized");
+
+    let mut alice = warp::test::ws()
+        .path("/ws")
+        .header("authorization", bearer_token("alice", 300))
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+    send_op(&mut alice, "subscribe", "orders").await;
+    assert_eq!(next_event(&mut alice).await["type"], "subscribed");
+
+    place(&filter, "bob", "sell", 5).await;
+    let created = place(&filter, "alice", "buy", 1).await;
+    let update = next_on(&mut alice, "update", "orders").await;
+    assert_eq!(update["data"]["type"], "order_update");
+    assert_eq!(update["data"]["order_id"], created["order_id"]);
+}
+
+#[tokio::test]
+async fn stream_accepts_tokens_in_query_and_auth_messages() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let token = |sub: &str| bearer_token(sub, 300).replace("Bearer ", "");
+
+    let mut client = warp::test::ws()
+        .path(&format!("/ws?token={}", token("alice")))
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+    send_op(&mut client, "subscribe", "orders").await;
+    assert_eq!(next_event(&mut client).await["type"], "subscribed");
+
+    let rejected = warp::test::ws()
+        .path("/ws?token=not-a-jwt")
+        .handshake(filter.clone())
+        .await;
+    assert!(rejected.is_err());
+
+    let mut client = warp::test::ws()
+        .path("/ws")
+        .handshake(filter.clone())
+        .await
+        .expect("handshake");
+    let auth = |token: String| serde_json::json!({ "op": "auth", "token": token }).to_string();
+    client.send_text(auth("not-a-jwt".into())).await;
+    assert_eq!(next_event(&mut client).await["code"], "unauthorized");
+
+    client.send_text(auth(token("alice"))).await;
+    let ack = next_event(&mut client).await;
+    assert_eq!(ack["type"], "authenticated");
+    assert_eq!(ack["trader_id"], "alice");
+    send_op(&mut client, "subscribe", "orders").await;
+    assert_eq!(next_event(&mut client).await["type"], "subscribed");
+
+    // Switching traders closes the previous trader's private channels.
+    client.send_text(auth(token("bob"))).await;
+    let dropped = next_event(&mut client).await;
+    assert_eq!(dropped["type"], "unsubscribed");
+    assert_eq!(dropped["channel"], "orders");
+    assert_eq!(next_event(&mut client).await["trader_id"], "bob");
+}
+
+#[tokio::test]
+async fn trusted_proxies_forward_the_client_address() {
+    let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    state.config.ip_allowlists.insert(
+        "alice".into(),
+        ip_allowlist::Allowlist::parse(["203.0.113.0/24"]).unwrap(),
+    );
+    state.config.trusted_proxies = ip_allowlist::TrustedProxies::parse(["10.0.0.0/8"]).unwrap();
+    state.rate_limiter = Arc::new(RateLimiter::new(RateLimitConfig {
+        market_data: Budget {
+            per_second: 1,
+            burst: 1,
+        },
+        ..RateLimitConfig::unlimited()
+    }));
+    let filter = routes(state);
+    let request = |path: &str, peer: &str, forwarded: &str| {
+        warp::test::request()
+            .path(path)
+            .remote_addr(peer.parse().unwrap())
+            .header("x-forwarded-for", forwarded)
+    };
+    let list_keys = |peer: &str, forwarded: &str| {
+        request("/auth/api-keys", peer, forwarded)
+            .header("authorization", bearer_token("alice", 300))
+            .reply(&filter)
+    };
+
+    // The allowlist sees the client the proxy forwarded, not the proxy.
+    assert_eq!(
+        list_keys("10.0.0.1:4000", "203.0.113.5").await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        list_keys("10.0.0.1:4000", "198.51.100.9").await.status(),
+        StatusCode::FORBIDDEN
+    );
+    // Anyone else claiming an address is not believed.
+    assert_eq!(
+        list_keys("198.51.100.9:4000", "203.0.113.5").await.status(),
+        StatusCode::FORBIDDEN
+    );
+
+    // Clients behind the same proxy have their own budgets.
+    let get_prices =
+        |forwarded: &str| request("/orderbook/prices", "10.0.0.1:4000", forwarded).reply(&filter);
+    assert_eq!(get_prices("192.0.2.1").await.status(), StatusCode::OK);
+    assert_eq!(
+        get_prices("192.0.2.1").await.status(),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(get_prices("192.0.2.2").await.status(), StatusCode::OK);
+}
+
+async fn heartbeat_client(path: &str, idle_timeout: Duration) -> warp::test::WsClient {
+    let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    state.config.ws_heartbeat = WsHeartbeat {
+        ping_interval: Duration::from_millis(10),
+        idle_timeout,
+    };
+    warp::test::ws()
+        .path(path)
+        .header("authorization", bearer_token("alice", 300))
+        .handshake(routes(state))
+        .await
+        .expect("handshake")
+}
+
+#[tokio::test]
+async fn sockets_are_pinged_and_idle_peers_closed() {
+    for path in ["/ws", "/ws/trades/ETH-USDC", "/ws/orders"] {
+        // The test client answers pings, which keeps the session alive.
+        let mut client = heartbeat_client(path, Duration::from_secs(3600)).await;
+        for _ in 0..3 {
+            let msg = client.recv().await.expect("ping");
+            assert!(msg.is_ping(), "{}: expected a ping, got {:?}", path, msg);
+        }
+
+        // A peer that is already idle is closed on the first tick, which
+        // the client may report as a close frame or as the end of the stream.
+        let mut client = heartbeat_client(path, Duration::ZERO).await;
+        if let Ok(msg) = client.recv().await {
+            assert!(msg.is_close(), "{}: expected a close, got {:?}", path, msg);
+        }
+    }
+}
+
+#[tokio::test]
+async fn shutdown_refuses_orders_and_closes_sockets() {
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let shutdown = state.shutdown.clone();
+    let filter = routes(state);
+    place(&filter, "bob", "sell", 5).await;
+    let mut clients = Vec::new();
+    for path in ["/ws", "/ws/trades/ETH-USDC", "/ws/orders"] {
+        let client = warp::test::ws()
+            .path(path)
+            .header("authorization", bearer_token("alice", 300))
+            .handshake(filter.clone())
+            .await
+            .expect("handshake");
+        clients.push((path, client));
+    }
+
+    shutdown.begin();
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("alice", 300))
+        .json(&serde_json::json!({
+            "trader_id": "alice",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "buy",
+            "order_type": "limit",
+            "price": 1000,
+            "quantity": 5,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "shutting_down");
+
+    // As with idle peers, the client may report the close frame as the
+    // end of the stream.
+    for (path, mut client) in clients {
+        if let Ok(msg) = client.recv().await {
+            assert!(msg.is_close(), "{}: expected a close, got {:?}", path, msg);
+        }
+    }
+    tokio::time::timeout(Duration::from_secs(1), shutdown.drained())
+        .await
+        .expect("sessions finished");
+}
+
+#[tokio::test]
+async fn owner_can_cancel_resting_order() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let created = place(&filter, "bob", "sell", 5).await;
+    let path = format!("/orderbook/orders/{}", created["order_id"]);
+
+    let response = warp::test::request()
+        .method("DELETE")
+        .path(&path)
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = warp::test::request()
+        .method("DELETE")
+        .path(&path)
+        .header("authorization", bearer_token("bob", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["cancelled_quantity"], 5);
+
+    let depth = warp::test::request()
+        .path("/orderbook/depth?levels=10")
+        .reply(&filter)
+        .await;
+    let depth: serde_json::Value = serde_json::from_slice(depth.body()).unwrap();
+    assert!(depth["asks"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn order_overflowing_a_price_level_is_rejected() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    place(&filter, "bob", "sell", u64::MAX).await;
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("bob", 300))
+        .json(&serde_json::json!({
+            "trader_id": "bob",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "sell",
+            "order_type": "limit",
+            "price": 1000,
+            "quantity": 1,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "order_rejected");
+    // Never accepted, so not kept as a cancelled order.
+    assert_eq!(storage.orders.lock().unwrap().len(), 1);
+    assert!(storage.deleted_orders.lock().unwrap().is_empty());
+    assert_eq!(storage.load_open_orders().await.unwrap().len(), 1);
+    assert!(storage.load_order(2).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn matched_orders_are_persisted_through_the_repos() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+
+    for (trader, side) in [("bob", "sell"), ("alice", "buy")] {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token(trader, 300))
+            .json(&serde_json::json!({
+                "trader_id": trader,
+                "base_token": "ETH",
+                "quote_token": "USDC",
+                "side": side,
+                "order_type": "limit",
+                "price": 1000,
+                "quantity": 5,
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    assert_eq!(storage.orders.lock().unwrap().len(), 2);
+    assert_eq!(storage.trades.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn a_taker_sweeping_several_makers_stores_every_trade() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+
+    let orders = [
+        ("bob", "sell", 1000, 1),
+        ("carol", "sell", 1001, 1),
+        ("dave", "sell", 1002, 1),
+        ("alice", "buy", 1002, 3),
+    ];
+    for (trader, side, price, quantity) in orders {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token(trader, 300))
+            .json(&serde_json::json!({
+                "trader_id": trader,
+                "base_token": "ETH",
+                "quote_token": "USDC",
+                "side": side,
+                "order_type": "limit",
+                "price": price,
+                "quantity": quantity,
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let trades = storage.trades.lock().unwrap();
+    let prices: Vec<u64> = trades.iter().map(|trade| trade.price).collect();
+    assert_eq!(prices, [1000, 1001, 1002]);
+    assert_eq!(trades[1].id, trades[0].id + 1);
+    assert_eq!(trades[2].id, trades[1].id + 1);
+}
+
+#[tokio::test]
+async fn ids_continue_after_those_already_stored() {
+    let storage = Arc::new(MemoryStorage::default());
+    storage.trades.lock().unwrap().push(Trade {
+        id: 9,
+        maker_order_id: 40,
+        taker_order_id: 41,
+        base_token: "ETH".parse().unwrap(),
+        quote_token: "USDC".parse().unwrap(),
+        price: 1000,
+        quantity: 1,
+        timestamp: 1,
+    });
+    let filter = routes(test_state_with_memory(storage.clone()));
+
+    for (trader, side) in [("bob", "sell"), ("alice", "buy")] {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token(trader, 300))
+            .json(&serde_json::json!({
+                "trader_id": trader,
+                "base_token": "ETH",
+                "quote_token": "USDC",
+                "side": side,
+                "order_type": "limit",
+                "price": 1000,
+                "quantity": 1,
+            }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    let mut order_ids: Vec<_> = storage.orders.lock().unwrap().keys().copied().collect();
+    order_ids.sort();
+    assert_eq!(order_ids, [42, 43]);
+    assert_eq!(storage.trades.lock().unwrap()[1].id, 10);
+}
+
+#[tokio::test]
+async fn internal_surface_splits_admin_and_metrics_off_the_public_one() {
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let public = surface_routes(state.clone(), Surface::Public);
+    let internal = surface_routes(state, Surface::Internal);
+    let get = |path: &'static str| {
+        warp::test::request()
+            .path(path)
+            .header("authorization", admin_token("admin", 300))
+    };
+
+    assert_eq!(
+        get("/metrics").reply(&public).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        get("/admin/config").reply(&public).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        get("/orderbook/prices").reply(&public).await.status(),
+        StatusCode::OK
+    );
+
+    assert_eq!(
+        get("/metrics").reply(&internal).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        get("/admin/config").reply(&internal).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        get("/orderbook/prices").reply(&internal).await.status(),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn accepted_orders_carry_verifiable_chained_receipts() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let first = place(&filter, "bob", "sell", 5).await;
+    let second = place(&filter, "alice", "buy", 2).await;
+
+    let key = warp::test::request()
+        .path("/sequencing/key")
+        .reply(&filter)
+        .await;
+    let key: serde_json::Value = serde_json::from_slice(key.body()).unwrap();
+    assert_eq!(key["algorithm"], "Ed25519");
+    let public_key = key["public_key"].as_str().unwrap();
+
+    let receipt = |body: &serde_json::Value| SequencingReceipt {
+        sequence: body["sequence"].as_u64().unwrap(),
+        order_id: body["order_id"].as_u64().unwrap(),
+        command_hash: body["command_hash"].as_str().unwrap().to_string(),
+        previous_hash: body["previous_hash"].as_str().unwrap().to_string(),
+        timestamp: body["timestamp"].as_u64().unwrap(),
+        region: body["region"].as_str().unwrap().to_string(),
+        signature: body["signature"].as_str().unwrap().to_string(),
+    };
+    let first_receipt = receipt(&first["receipt"]);
+    let second_receipt = receipt(&second["receipt"]);
+    assert_eq!(second_receipt.sequence, first_receipt.sequence + 1);
+    assert_eq!(
+        second_receipt.order_id,
+        second["order_id"].as_u64().unwrap()
+    );
+    assert_eq!(
+        second_receipt.previous_hash,
+        sequencing::receipt_hash(&first_receipt)
+    );
+    assert!(sequencing::verify(public_key, &second_receipt));
+
+    let response = warp::test::request()
+        .path(&format!(
+            "/orderbook/orders/{}/receipt",
+            second_receipt.order_id
+        ))
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let stored: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(receipt(&stored), second_receipt);
+    assert_eq!(storage.receipts.lock().unwrap().len(), 2);
+
+    let missing = warp::test::request()
+        .path("/orderbook/orders/999/receipt")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn prices_are_reported_per_pair() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 1).await;
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("alice", 300))
+        .json(&serde_json::json!({
+            "trader_id": "alice",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "buy",
+            "order_type": "limit",
+            "price": 990,
+            "quantity": 1,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("bob", 300))
+        .json(&serde_json::json!({
+            "trader_id": "bob",
+            "base_token": "BTC",
+            "quote_token": "USDC",
+            "side": "sell",
+            "order_type": "limit",
+            "price": 30000,
+            "quantity": 1,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = warp::test::request()
+        .path("/orderbook/prices?pair=ETH-USDC")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let eth: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(eth["pair"], "ETH-USDC");
+    assert_eq!(eth["best_bid"], 990);
+    assert_eq!(eth["best_ask"], 1000);
+    assert_eq!(eth["mid_price"], 995);
+    assert_eq!(eth["last_price"], 1000);
+    assert!(eth["last_trade_time"].is_u64());
+
+    let response = warp::test::request()
+        .path("/orderbook/prices")
+        .reply(&filter)
+        .await;
+    let all: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(all["best_ask"], 1000);
+    let btc = &all["pairs"][0];
+    assert_eq!(btc["pair"], "BTC-USDC");
+    assert_eq!(btc["best_ask"], 30000);
+    assert!(btc["mid_price"].is_null() && btc["last_price"].is_null());
+    assert_eq!(all["pairs"][1]["pair"], "ETH-USDC");
+
+    let response = warp::test::request()
+        .path("/orderbook/prices?pair=ETH")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn market_counters_survive_a_restart() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let filter = routes(state.clone());
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 2).await;
+    crate::market_counters::flush(&state).await.unwrap();
+    assert_eq!(storage.counters.lock().unwrap()[0].trade_count, 1);
+
+    let restarted = test_state_with_memory(storage.clone());
+    let stored = restarted.counter_repo.load_counters().await.unwrap();
+    restarted
+        .trade_tape
+        .write()
+        .await
+        .counters_mut()
+        .restore(stored);
+    let response = warp::test::request()
+        .path("/orderbook/prices?pair=ETH-USDC")
+        .reply(&routes(restarted))
+        .await;
+    let eth: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(eth["trade_count"], 1);
+    assert_eq!(eth["volume"], 2);
+    assert_eq!(eth["quote_volume"], 2000);
+    // The tape is empty, but the counters remember the last trade.
+    assert_eq!(eth["last_price"], 1000);
+    assert!(eth["best_ask"].is_null());
+}
+
+#[tokio::test]
+async fn tick_maps_survive_a_restart_compressed() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let pair: TradingPair = "ETH-USDC".parse().unwrap();
+    let alice = "alice".parse().unwrap();
+    crate::amm_events::add_liquidity_in_range(
+        &state,
+        &pair,
+        30,
+        &alice,
+        1_000,
+        2_000_000,
+        (-2_000, 2_000),
+    )
+    .await
+    .unwrap();
+    crate::tick_maps::flush(&state).await.unwrap();
+    {
+        let stored = storage.tick_maps.lock().unwrap();
+        assert_eq!((stored[0].tick_count, stored[0].sequence), (4_001, 1));
+        assert!(stored[0].data.len() < 1_000);
+    }
+
+    let restarted = test_state_with_memory(storage.clone());
+    assert_eq!(crate::tick_maps::restore(&restarted).await.unwrap(), 1);
+    crate::amm_events::add_liquidity_in_range(
+        &restarted,
+        &pair,
+        30,
+        &alice,
+        500,
+        1_000_000,
+        (0, 10),
+    )
+    .await
+    .unwrap();
+    crate::tick_maps::flush(&restarted).await.unwrap();
+    let mut pools = restarted.amm.write().await;
+    let ticks = pools.get_mut(&pair).unwrap().ticks();
+    assert_eq!(ticks.len(), 4_001);
+    assert_eq!((ticks[&0].liquidity, ticks[&10].liquidity), (1_500, 1_000));
+    // Restoring moved the sequence on, so the newer map replaced the old.
+    let stored = storage.tick_maps.lock().unwrap();
+    assert_eq!((stored[0].tick_count, stored[0].sequence), (4_001, 2));
+}
+
+#[tokio::test]
+async fn amm_pools_survive_a_restart() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let pair: TradingPair = "ETH-USDC".parse().unwrap();
+    let (alice, bob) = ("alice".parse().unwrap(), "bob".parse().unwrap());
+    let eth: dex_core::types::TokenId = "ETH".parse().unwrap();
+    crate::amm_events::add_liquidity(&state, &pair, 30, &alice, 10_000, 10_000)
+        .await
+        .unwrap();
+    crate::amm_events::add_liquidity(&state, &pair, 30, &bob, 10_000, 10_000)
+        .await
+        .unwrap();
+    crate::amm_events::swap(&state, &bob, &pair, &eth, 1_000)
+        .await
+        .unwrap();
+    crate::amm_events::remove_liquidity(&state, &pair, &bob, 5_000)
+        .await
+        .unwrap();
+    crate::amm_state::flush(&state).await.unwrap();
+    assert_eq!(storage.pools.lock().unwrap()[0].positions.len(), 2);
+
+    let restarted = test_state_with_memory(storage.clone());
+    assert_eq!(crate::amm_state::restore(&restarted).await.unwrap(), 1);
+    {
+        let (before, after) = (state.amm.read().await, restarted.amm.read().await);
+        assert_eq!(after.snapshot(), before.snapshot());
+        for provider in [&alice, &bob] {
+            assert_eq!(
+                after.provider_summary(provider),
+                before.provider_summary(provider)
+            );
+        }
+    }
+    let out = crate::amm_events::swap(&state, &alice, &pair, &eth, 2_000)
+        .await
+        .unwrap();
+    assert_eq!(
+        crate::amm_events::swap(&restarted, &alice, &pair, &eth, 2_000)
+            .await
+            .unwrap(),
+        out
+    );
+    // The restored sequence carries on, so the newer state replaces the old.
+    crate::amm_state::flush(&restarted).await.unwrap();
+    assert_eq!(storage.pools.lock().unwrap()[0].sequence, 5);
+}
+
+async fn post_json(
+    filter: &(impl warp::Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible>
+          + Clone
+          + 'static),
+    path: &str,
+    body: serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let response = warp::test::request()
+        .method("POST")
+        .path(path)
+        .json(&body)
+        .reply(filter)
+        .await;
+    let body = serde_json::from_slice(response.body()).unwrap_or_default();
+    (response.status(), body)
+}
+
+#[tokio::test]
+async fn failed_sign_ins_lock_the_account_out() {
+    let state = test_state_with_seed(Arc::new(MemoryStorage::default()), 11);
+    let filter = routes(state.clone());
+    let sign_in = |secret: &str| {
+        post_json(
+            &filter,
+            "/auth/token/shared",
+            serde_json::json!({ "trader_id": "alice", "secret": secret }),
+        )
+    };
+
+    for _ in 0..4 {
+        let (status, _) = sign_in("guess").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+    // The fifth failure locks alice out, even with the right secret.
+    let (status, _) = sign_in("guess").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/token/shared")
+        .json(&serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["retry-after"], "30");
+
+    // Each failure after the lockout doubles it.
+    state.determinism.advance(30);
+    let (status, _) = sign_in("guess").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    state.determinism.advance(30);
+    let (status, body) = sign_in("shared-secret").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "locked_out");
+
+    state.determinism.advance(30);
+    let (status, _) = sign_in("shared-secret").await;
+    assert_eq!(status, StatusCode::OK);
+    // Success clears the count.
+    let (status, _) = sign_in("guess").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = sign_in("shared-secret").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn refresh_tokens_rotate_and_detect_reuse() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let (status, issued) = post_json(
+        &filter,
+        "/auth/token/shared",
+        serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let first = issued["refresh_token"].as_str().unwrap().to_string();
+    // Only the hash is stored.
+    assert!(!storage.refresh_tokens.lock().unwrap().contains_key(&first));
+
+    let (status, refreshed) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": first }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let second = refreshed["refresh_token"].as_str().unwrap().to_string();
+    assert_ne!(second, first);
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades")
+        .header(
+            "authorization",
+            format!("Bearer {}", refreshed["token"].as_str().unwrap()),
+        )
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Replaying the rotated token revokes the whole family.
+    let (status, body) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": first }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_refresh_token");
+    let (status, _) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": second }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Signing out revokes a fresh session.
+    let (_, issued) = post_json(
+        &filter,
+        "/auth/token/shared",
+        serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }),
+    )
+    .await;
+    let token = issued["refresh_token"].clone();
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/token/revoke")
+        .json(&serde_json::json!({ "refresh_token": token }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let (status, _) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": token }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn tokens_only_reach_routes_their_scopes_allow() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let sign_in = |scope: Option<&str>| {
+        let mut body = serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" });
+        if let Some(scope) = scope {
+            body["scope"] = scope.into();
+        }
+        post_json(&filter, "/auth/token/shared", body)
+    };
+    let order = serde_json::json!({
+        "trader_id": "alice",
+        "base_token": "ETH",
+        "quote_token": "USDC",
+        "side": "buy",
+        "order_type": "limit",
+        "price": 1000,
+        "quantity": 1,
+    });
+
+    let (status, default) = sign_in(None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(default["scope"], "read trade");
+
+    let (status, read_only) = sign_in(Some("read")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(read_only["scope"], "read");
+    let bearer = format!("Bearer {}", read_only["token"].as_str().unwrap());
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer.clone())
+        .json(&order)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "insufficient_scope");
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades")
+        .header("authorization", bearer)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Refreshed tokens keep the narrower scope.
+    let (status, refreshed) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": read_only["refresh_token"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(refreshed["scope"], "read");
+
+    let (status, body) = sign_in(Some("trade admin")).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "forbidden");
+    let (status, body) = sign_in(Some("everything")).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_scope");
+
+    // Non-admin scopes do not open the operator endpoints, even to admins.
+    let response = warp::test::request()
+        .path("/admin/trades/1/adjustments")
+        .header("authorization", scoped_token("admin", "read trade", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn traders_register_list_and_revoke_webhooks() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let register = |body: serde_json::Value| {
+        warp::test::request()
+            .method("POST")
+            .path("/account/webhooks")
+            .header("authorization", bearer_token("alice", 300))
+            .json(&body)
+            .reply(&filter)
+    };
+
+    let response = register(serde_json::json!({ "url": "http://hooks.example.com/" })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = register(serde_json::json!({ "url": "https://10.0.0.5/hook" })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = register(serde_json::json!({
+        "url": "https://hooks.example.com/dex",
+        "events": ["fill", "order_update"],
+    }))
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let id = created["id"].as_str().unwrap().to_string();
+    assert!(id.starts_with("wh_"));
+    assert_eq!(created["secret"].as_str().unwrap().len(), 32);
+    assert_eq!(
+        created["events"],
+        serde_json::json!(["fill", "order_update"])
+    );
+
+    let list = |trader: &str| {
+        warp::test::request()
+            .path("/account/webhooks")
+            .header("authorization", bearer_token(trader, 300))
+            .reply(&filter)
+    };
+    let listed: serde_json::Value = serde_json::from_slice(list("alice").await.body()).unwrap();
+    assert_eq!(listed["webhooks"][0]["id"], id.as_str());
+    assert!(listed["webhooks"][0].get("secret").is_none());
+    let listed: serde_json::Value = serde_json::from_slice(list("bob").await.body()).unwrap();
+    assert_eq!(listed["webhooks"], serde_json::json!([]));
+
+    let revoke = |trader: &str| {
+        warp::test::request()
+            .method("DELETE")
+            .path(&format!("/account/webhooks/{}", id))
+            .header("authorization", bearer_token(trader, 300))
+            .reply(&filter)
+    };
+    assert_eq!(revoke("bob").await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(revoke("alice").await.status(), StatusCode::NO_CONTENT);
+    let listed: serde_json::Value = serde_json::from_slice(list("alice").await.body()).unwrap();
+    assert_eq!(listed["webhooks"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn api_keys_sign_requests_once() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/api-keys")
+        .header("authorization", scoped_token("alice", "read trade", 300))
+        .json(&serde_json::json!({ "label": "bot", "scope": "trade withdraw" }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/api-keys")
+        .header("authorization", scoped_token("alice", "read trade", 300))
+        .json(&serde_json::json!({ "label": "bot", "scope": "trade" }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(created["scope"], "read trade");
+    let key_id = created["key_id"].as_str().unwrap().to_string();
+    let secret = created["secret"].as_str().unwrap().to_string();
+    // Only the sealed secret is stored, and it opens for this key alone.
+    let sealed = storage.api_keys.lock().unwrap()[&key_id]
+        .sealed_secret
+        .clone();
+    assert!(!sealed.contains(&secret));
+    let sealing_key = test_config().api_key_encryption_key.unwrap();
+    assert_eq!(
+        sealing_key.open(&key_id, &sealed).unwrap(),
+        secret.as_bytes()
+    );
+    assert!(sealing_key.open("dk_other", &sealed).is_err());
+
+    let signed = |method: &str, path: &str, body: &str, timestamp: u64| {
+        let hash = api_keys::content_sha256(body.as_bytes());
+        let message = api_keys::string_to_sign(
+            &timestamp.to_string(),
+            &method.parse().unwrap(),
+            path,
+            &hash,
+        );
+        warp::test::request()
+            .method(method)
+            .path(path)
+            .header("x-api-key", key_id.as_str())
+            .header("x-api-timestamp", timestamp.to_string())
+            .header("x-api-content-sha256", hash)
+            .header("x-api-signature", api_keys::sign(&secret, &message))
+            .body(body)
+    };
+    let now = Determinism::live().now().unwrap();
+    let order = serde_json::json!({
+        "trader_id": "alice",
+        "base_token": "ETH",
+        "quote_token": "USDC",
+        "side": "buy",
+        "order_type": "limit",
+        "price": 1000,
+        "quantity": 1,
+    })
+    .to_string();
+    let request = signed("POST", "/orderbook/orders", &order, now);
+    let response = request.reply(&filter).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    // The same signed request is refused a second time.
+    let response = signed("POST", "/orderbook/orders", &order, now)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert!(body["message"].as_str().unwrap().contains("already used"));
+    // Changing the signature's case does not make it a new one.
+    let message = api_keys::string_to_sign(
+        &now.to_string(),
+        &Method::POST,
+        "/orderbook/orders",
+        &api_keys::content_sha256(order.as_bytes()),
+    );
+    let response = signed("POST", "/orderbook/orders", &order, now)
+        .header(
+            "x-api-signature",
+            api_keys::sign(&secret, &message).to_ascii_uppercase(),
+        )
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    // So are stale timestamps and bodies swapped after signing.
+    let response = signed("POST", "/orderbook/orders", &order, now - 60)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = signed("POST", "/orderbook/orders", &order, now + 1)
+        .body(order.replace("\"quantity\":1", "\"quantity\":9"))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    // Keys carry only their own scopes.
+    let response = signed("GET", "/admin/trades/1/adjustments", "", now)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = signed("GET", "/auth/api-keys", "", now + 2)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let listed: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(listed["keys"][0]["key_id"], key_id.as_str());
+    assert_eq!(listed["keys"][0]["label"], "bot");
+    assert!(listed["keys"][0].get("secret").is_none());
+
+    // Changing or revoking the key takes every scope it carries.
+    let response = warp::test::request()
+        .method("PUT")
+        .path(&format!("/auth/api-keys/{}/allowed-ips", key_id))
+        .header("authorization", scoped_token("alice", "read", 300))
+        .json(&serde_json::json!({ "allowed_ips": ["10.0.0.0/8"] }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let revoke = |token: String| {
+        warp::test::request()
+            .method("DELETE")
+            .path(&format!("/auth/api-keys/{}", key_id))
+            .header("authorization", token)
+            .reply(&filter)
+    };
+    let response = revoke(scoped_token("alice", "read", 300)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!storage.api_keys.lock().unwrap()[&key_id].revoked);
+    let response = revoke(bearer_token("bob", 300)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = revoke(bearer_token("alice", 300)).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = signed("GET", "/auth/api-keys", "", now + 3)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn logout_revokes_the_session() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let (_, issued) = post_json(
+        &filter,
+        "/auth/token/shared",
+        serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }),
+    )
+    .await;
+    let bearer = format!("Bearer {}", issued["token"].as_str().unwrap());
+    let trades = || {
+        warp::test::request()
+            .path("/orderbook/traders/alice/trades")
+            .header("authorization", bearer.clone())
+            .reply(&filter)
+    };
+    assert_eq!(trades().await.status(), StatusCode::OK);
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/logout")
+        .header("authorization", bearer.clone())
+        .json(&serde_json::json!({ "refresh_token": issued["refresh_token"] }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let response = trades().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["message"], "token has been revoked");
+    let (status, _) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": issued["refresh_token"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Other sessions are unaffected, and the body is optional.
+    let other = bearer_token("alice", 300);
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades")
+        .header("authorization", other.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/logout")
+        .header("authorization", other.clone())
+        .body("")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Revocations are stored, so another instance, or this one after a
+    // restart, refuses the token once it has read them back.
+    assert_eq!(storage.revoked_tokens.lock().unwrap().len(), 1);
+    let restarted = test_state_with_memory(storage.clone());
+    assert!(restarted.auth.verify_bearer(&bearer).is_ok());
+    assert_eq!(sessions::sync_revocations(&restarted).await.unwrap(), 1);
+    assert!(restarted.auth.verify_bearer(&bearer).is_err());
+}
+
+#[tokio::test]
+async fn cors_allows_listed_browser_origins() {
+    let storage = Arc::new(MemoryStorage::default());
+    let prices = |filter, origin: Option<&str>| {
+        let mut request = warp::test::request().path("/orderbook/prices");
+        if let Some(origin) = origin {
+            request = request.header("origin", origin);
+        }
+        async move { request.reply(&filter).await }
+    };
+
+    // Off by default: browser requests get no CORS headers.
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let response = prices(filter, Some("https://app.example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+
+    let mut state = test_state_with_memory(storage);
+    state.config.cors =
+        cors::CorsConfig::parse("https://app.example.com", "x-request-id", 120).unwrap();
+    let filter = routes(state);
+    let response = warp::test::request()
+        .method("OPTIONS")
+        .path("/orderbook/orders")
+        .header("origin", "https://app.example.com")
+        .header("access-control-request-method", "POST")
+        .header(
+            "access-control-request-headers",
+            "authorization, content-type, x-request-id",
+        )
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let header = |response: &warp::http::Response<warp::hyper::body::Bytes>, name: &str| {
+        response
+            .headers()
+            .get(name)
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+    assert_eq!(
+        header(&response, "access-control-allow-origin").as_deref(),
+        Some("https://app.example.com")
+    );
+    assert_eq!(
+        header(&response, "access-control-max-age").as_deref(),
+        Some("120")
+    );
+
+    let response = prices(filter.clone(), Some("https://app.example.com")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(header(&response, "access-control-expose-headers")
+        .unwrap()
+        .contains("etag"));
+    // Errors can be read by the page as well.
+    let response = warp::test::request()
+        .path("/account/usage")
+        .header("origin", "https://app.example.com")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(header(&response, "access-control-allow-origin").is_some());
+
+    let response = prices(filter.clone(), Some("https://evil.example")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "cors_forbidden");
+    let response = warp::test::request()
+        .method("OPTIONS")
+        .path("/orderbook/orders")
+        .header("origin", "https://app.example.com")
+        .header("access-control-request-method", "POST")
+        .header("access-control-request-headers", "x-unlisted")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    // Clients that are not browsers send no origin and are unaffected.
+    assert_eq!(prices(filter, None).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn sessions_are_listed_and_revoked_one_by_one() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let sign_in = || {
+        post_json(
+            &filter,
+            "/auth/token/shared",
+            serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }),
+        )
+    };
+    let (_, first) = sign_in().await;
+    let (_, second) = sign_in().await;
+    let bearer =
+        |issued: &serde_json::Value| format!("Bearer {}", issued["token"].as_str().unwrap());
+    let list = |bearer: String| {
+        let filter = filter.clone();
+        async move {
+            let response = warp::test::request()
+                .path("/auth/sessions")
+                .header("authorization", bearer)
+                .reply(&filter)
+                .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            serde_json::from_slice::<serde_json::Value>(response.body()).unwrap()
+        }
+    };
+
+    let body = list(bearer(&second)).await;
+    let sessions = body["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    let (current, other): (Vec<_>, Vec<_>) = sessions
+        .iter()
+        .partition(|session| session["current"] == true);
+    assert_eq!((current.len(), other.len()), (1, 1));
+    assert_eq!(other[0]["scope"], "read trade");
+    assert!(current[0]["last_seen"].as_u64().unwrap() >= current[0]["issued_at"].as_u64().unwrap());
+    // Another trader's sessions are not listed.
+    let body = list(bearer_token("bob", 300)).await;
+    assert!(body["sessions"].as_array().unwrap().is_empty());
+    // Sessions are read from storage, so every instance lists them.
+    let elsewhere = routes(test_state_with_memory(storage.clone()));
+    let response = warp::test::request()
+        .path("/auth/sessions")
+        .header("authorization", bearer(&first))
+        .reply(&elsewhere)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["sessions"].as_array().unwrap().len(), 2);
+
+    // The second sign-in ends the first, refresh token included.
+    let jti = other[0]["jti"].as_str().unwrap().to_string();
+    let revoke = |jti: String, bearer: String| {
+        let filter = filter.clone();
+        async move {
+            warp::test::request()
+                .method("DELETE")
+                .path(&format!("/auth/sessions/{}", jti))
+                .header("authorization", bearer)
+                .reply(&filter)
+                .await
+                .status()
+        }
+    };
+    assert_eq!(
+        revoke(jti.clone(), bearer_token("bob", 300)).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        revoke(jti.clone(), bearer(&second)).await,
+        StatusCode::NO_CONTENT
+    );
+    let response = warp::test::request()
+        .path("/auth/sessions")
+        .header("authorization", bearer(&first))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let (status, _) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": first["refresh_token"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(revoke(jti, bearer(&second)).await, StatusCode::NOT_FOUND);
+
+    let body = list(bearer(&second)).await;
+    assert_eq!(body["sessions"].as_array().unwrap().len(), 1);
+    let signed_in_at = body["sessions"][0]["issued_at"].clone();
+    let (status, refreshed) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": second["refresh_token"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Refreshing keeps the session, now named by the new access token.
+    let body = list(bearer(&refreshed)).await;
+    let sessions = body["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0]["current"], true);
+    assert_eq!(sessions[0]["issued_at"], signed_in_at);
+}
+
+#[tokio::test]
+async fn margin_limits_net_correlated_markets_for_portfolio_accounts() {
+    let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let margin = &mut state.config.margin;
+    margin.limits.insert("alice".into(), 150.0);
+    margin.limits.insert("maker".into(), 150.0);
+    margin.portfolio_accounts.insert("maker".into());
+    margin
+        .correlations
+        .insert(("ETH-USDC".into(), "BTC-USDC".into()), 0.9);
+    let filter = routes(state);
+    let submit = |trader: &str, base: &str, side: &str, price: u64| {
+        warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token(trader, 300))
+            .json(&serde_json::json!({
+                "trader_id": trader, "base_token": base, "quote_token": "USDC",
+                "side": side, "order_type": "limit", "price": price, "quantity": 1,
+            }))
+            .reply(&filter)
+    };
+
+    // Without USD quotes, USDC markets are valued at face value.
+    for trader in ["alice", "maker"] {
+        assert_eq!(
+            submit(trader, "ETH", "buy", 1000).await.status(),
+            StatusCode::CREATED
+        );
+    }
+    let refused = submit("alice", "BTC", "sell", 1100).await;
+    assert_eq!(refused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = serde_json::from_slice(refused.body()).unwrap();
+    assert_eq!(body["code"], "margin_limit_exceeded");
+    // The short is netted against the correlated long.
+    assert_eq!(
+        submit("maker", "BTC", "sell", 1100).await.status(),
+        StatusCode::CREATED
+    );
+
+    let response = warp::test::request()
+        .path("/account/margin")
+        .header("authorization", bearer_token("maker", 300))
+        .reply(&filter)
+        .await;
+    let report: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(report["portfolio_margin"], true);
+    assert_eq!(report["gross_usd"], 210.0);
+    assert_eq!(report["limit_usd"], 150.0);
+    let required = report["required_usd"].as_f64().unwrap();
+    assert!((required - 2300f64.sqrt()).abs() < 1e-9);
+    assert_eq!(report["pairs"][0]["pair"], "BTC-USDC");
+    assert_eq!(report["pairs"][0]["exposure_usd"], -1100.0);
+}
+
+#[tokio::test]
+async fn unfunded_orders_are_refused_when_balances_are_required() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    state.config.require_funded_orders = true;
+    storage.ledger.lock().unwrap().push(
+        JournalEntry::new(EntryKind::Deposit, "ethereum:0xabc:0", 1).transfer(
+            Account::Bridge("ethereum".into()),
+            Account::Trader("alice".into()),
+            &"USDC".parse().unwrap(),
+            2_500,
+        ),
+    );
+    let filter = routes(state);
+    let submit = |side: &str, quantity: u64| {
+        warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token("alice", 300))
+            .json(&serde_json::json!({
+                "trader_id": "alice", "base_token": "ETH", "quote_token": "USDC",
+                "side": side, "order_type": "limit", "price": 1000, "quantity": quantity,
+            }))
+            .reply(&filter)
+    };
+
+    assert_eq!(submit("buy", 2).await.status(), StatusCode::CREATED);
+    // The resting buy already commits 2000 of the 2500 USDC.
+    let refused = submit("buy", 1).await;
+    assert_eq!(refused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = serde_json::from_slice(refused.body()).unwrap();
+    assert_eq!(body["code"], "insufficient_balance");
+    let body: serde_json::Value = serde_json::from_slice(submit("sell", 1).await.body()).unwrap();
+    assert_eq!(body["code"], "insufficient_balance");
+
+    let response = warp::test::request()
+        .path("/account/balances")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "trader_id": "alice",
+            "balances": [{ "token": "USDC", "balance": 2500 }],
+        })
+    );
+}
+
+#[tokio::test]
+async fn funded_buys_cover_their_fee_and_sells_pay_it_from_proceeds() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    state.config.require_funded_orders = true;
+    let mut settings = state.settings.borrow().clone();
+    settings.fees.default = crate::settings::FeeRates {
+        maker_bps: 10,
+        taker_bps: 10,
+    };
+    assert!(crate::settings::apply(&state, settings));
+    let deposit = |trader: &str, token: &str, amount: u128| {
+        JournalEntry::new(EntryKind::Deposit, format!("ethereum:{trader}:{token}"), 1).transfer(
+            Account::Bridge("ethereum".into()),
+            Account::Trader(trader.into()),
+            &token.parse().unwrap(),
+            amount,
+        )
+    };
+    storage
+        .ledger
+        .lock()
+        .unwrap()
+        .extend([deposit("alice", "USDC", 1_000), deposit("bob", "ETH", 1)]);
+    let filter = routes(state);
+    let submit = |trader: &str, side: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token(trader, 300))
+            .json(&serde_json::json!({
+                "trader_id": trader, "base_token": "ETH", "quote_token": "USDC",
+                "side": side, "order_type": "limit", "price": 1000, "quantity": 1,
+            }))
+            .reply(&filter)
+    };
+    let balances = |trader: &str| {
+        let request = warp::test::request()
+            .path("/account/balances")
+            .header("authorization", bearer_token(trader, 300))
+            .reply(&filter);
+        async move {
+            let body: serde_json::Value = serde_json::from_slice(request.await.body()).unwrap();
+            body["balances"].clone()
+        }
+    };
+
+    // Alice's balance covers the notional, but not the 1 USDC fee on it.
+    let refused = submit("alice", "buy").await;
+    assert_eq!(refused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = serde_json::from_slice(refused.body()).unwrap();
+    assert_eq!(body["code"], "insufficient_balance");
+    // Bob holds no USDC, but his fee comes out of what the sale pays him.
+    assert_eq!(submit("bob", "sell").await.status(), StatusCode::CREATED);
+
+    storage
+        .ledger
+        .lock()
+        .unwrap()
+        .push(deposit("alice", "USDC", 1));
+    assert_eq!(submit("alice", "buy").await.status(), StatusCode::CREATED);
+    assert_eq!(
+        balances("bob").await,
+        serde_json::json!([{ "token": "USDC", "balance": 999 }])
+    );
+    assert_eq!(
+        balances("alice").await,
+        serde_json::json!([{ "token": "ETH", "balance": 1 }])
+    );
+}
+
+#[tokio::test]
+async fn orders_whose_commitments_overflow_are_rejected() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    state.config.require_funded_orders = true;
+    storage.ledger.lock().unwrap().push(
+        JournalEntry::new(EntryKind::Deposit, "ethereum:0xabc:0", 1).transfer(
+            Account::Bridge("ethereum".into()),
+            Account::Trader("alice".into()),
+            &"USDC".parse().unwrap(),
+            i128::MAX as u128,
+        ),
+    );
+    let filter = routes(state);
+    let submit = |price: u64, quantity: u64| {
+        warp::test::request()
+            .method("POST")
+            .path("/orderbook/orders")
+            .header("authorization", bearer_token("alice", 300))
+            .json(&serde_json::json!({
+                "trader_id": "alice", "base_token": "ETH", "quote_token": "USDC",
+                "side": "buy", "order_type": "limit", "price": price, "quantity": quantity,
+            }))
+            .reply(&filter)
+    };
+
+    // Commits just under 2^127 USDC, which the balance covers.
+    assert_eq!(
+        submit(u64::MAX, u64::MAX / 2).await.status(),
+        StatusCode::CREATED
+    );
+    // Together with the resting buy this commits more than a u128 holds.
+    let refused = submit(u64::MAX - 1, u64::MAX).await;
+    assert_eq!(refused.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = serde_json::from_slice(refused.body()).unwrap();
+    assert_eq!(body["code"], "order_rejected");
+    assert_eq!(storage.orders.lock().unwrap().len(), 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn concurrent_orders_cannot_commit_the_same_funds() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    state.config.require_funded_orders = true;
+    storage.ledger.lock().unwrap().push(
+        JournalEntry::new(EntryKind::Deposit, "ethereum:0xabc:0", 1).transfer(
+            Account::Bridge("ethereum".into()),
+            Account::Trader("alice".into()),
+            &"USDC".parse().unwrap(),
+            1_500,
+        ),
+    );
+    let filter = routes(state);
+    let start = Arc::new(tokio::sync::Barrier::new(8));
+    let submits: Vec<_> = (0..8)
+        .map(|_| {
+            let (filter, start) = (filter.clone(), start.clone());
+            tokio::spawn(async move {
+                start.wait().await;
+                warp::test::request()
+                    .method("POST")
+                    .path("/orderbook/orders")
+                    .header("authorization", bearer_token("alice", 300))
+                    .json(&serde_json::json!({
+                        "trader_id": "alice", "base_token": "ETH", "quote_token": "USDC",
+                        "side": "buy", "order_type": "limit", "price": 1000, "quantity": 1,
+                    }))
+                    .reply(&filter)
+                    .await
+                    .status()
+            })
+        })
+        .collect();
+
+    // Each covers 1000 of the 1500 USDC on its own, but not both.
+    let mut statuses = Vec::new();
+    for submit in submits {
+        statuses.push(submit.await.unwrap());
+    }
+    statuses.sort_unstable();
+    let mut expected = vec![StatusCode::UNPROCESSABLE_ENTITY; 7];
+    expected.insert(0, StatusCode::CREATED);
+    assert_eq!(statuses, expected);
+    assert_eq!(storage.orders.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn risk_check_reports_rejections_without_placing() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    place(&filter, "bob", "sell", 5).await;
+    let check = |trader: &str, body: serde_json::Value| {
+        warp::test::request()
+            .method("POST")
+            .path("/risk/check")
+            .header("authorization", bearer_token(trader, 300))
+            .json(&body)
+            .reply(&filter)
+    };
+    let order = |side: &str, price: u64, quantity: u64| {
+        serde_json::json!({
+            "trader_id": "alice", "base_token": "ETH", "quote_token": "USDC",
+            "side": side, "order_type": "limit", "price": price, "quantity": quantity,
+        })
+    };
+
+    let response = check("alice", order("buy", 1000, 8)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(
+        body,
+        serde_json::json!({
+            "accepted": true, "status": 201, "fill_quantity": 5, "resting_quantity": 3,
+        })
+    );
+
+    let body: serde_json::Value =
+        serde_json::from_slice(check("bob", order("buy", 1000, 8)).await.body()).unwrap();
+    assert_eq!(body["accepted"], false);
+    assert_eq!(body["status"], 403);
+    assert_eq!(body["code"], "forbidden");
+    let body: serde_json::Value =
+        serde_json::from_slice(check("alice", order("buy", 1000, 0)).await.body()).unwrap();
+    assert_eq!(body["code"], "validation_error");
+    assert_eq!(body["message"], "quantity must be greater than zero");
+    // Resting on bob's level would overflow it.
+    let body: serde_json::Value =
+        serde_json::from_slice(check("alice", order("sell", 1000, u64::MAX)).await.body()).unwrap();
+    assert_eq!(body["status"], 422);
+    assert_eq!(body["code"], "order_rejected");
+
+    // Nothing was placed: bob's ask is still whole.
+    let response = warp::test::request()
+        .path("/orderbook/depth")
+        .reply(&filter)
+        .await;
+    let depth: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(depth["asks"][0]["quantity"], 5);
+}
+
+#[tokio::test]
+async fn jwks_publishes_asymmetric_signing_keys() {
+    let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let ed25519 = SigningKey {
+        kid: "ed-1".into(),
+        material: KeyMaterial::Ed25519(SecretString::from(
+            include_str!("../testdata/jwt-ed25519.pem").to_string(),
+        )),
+        active_from: 1,
+        expires_at: None,
+    };
+    let keys = vec![state.config.jwt_keys[0].clone(), ed25519];
+    state.auth = Arc::new(AuthManager::with_keys(keys, "test-issuer").unwrap());
+    let filter = routes(state);
+
+    let response = warp::test::request()
+        .path("/.well-known/jwks.json")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["cache-control"], "public, max-age=300");
+    let jwks: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let keys = jwks["keys"].as_array().unwrap();
+    assert_eq!(keys.len(), 1, "HMAC keys stay private");
+    assert_eq!(keys[0]["kid"], "ed-1");
+    assert_eq!(keys[0]["kty"], "OKP");
+    assert_eq!(keys[0]["alg"], "EdDSA");
+
+    // Sessions are now signed with the Ed25519 key.
+    let (_, issued) = post_json(
+        &filter,
+        "/auth/token/shared",
+        serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }),
+    )
+    .await;
+    let token = issued["token"].as_str().unwrap();
+    let header = jsonwebtoken::decode_header(token).unwrap();
+    assert_eq!(header.alg, jsonwebtoken::Algorithm::EdDSA);
+    let response = warp::test::request()
+        .path("/orderbook/traders/alice/trades")
+        .header("authorization", format!("Bearer {}", token))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn market_data_supports_conditional_requests() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    place(&filter, "bob", "sell", 5).await;
+
+    let response = warp::test::request()
+        .path("/orderbook/depth")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["cache-control"], "public, max-age=1");
+    let etag = response.headers()["etag"].clone();
+
+    let response = warp::test::request()
+        .path("/orderbook/depth")
+        .header("if-none-match", etag.clone())
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert!(response.body().is_empty());
+
+    place(&filter, "bob", "sell", 2).await;
+    let response = warp::test::request()
+        .path("/orderbook/depth")
+        .header("if-none-match", etag)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    place(&filter, "alice", "buy", 1).await;
+    let response = warp::test::request()
+        .path("/markets/ETH-USDC/trades")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let last_modified = response.headers()["last-modified"].clone();
+    let response = warp::test::request()
+        .path("/markets/ETH-USDC/trades")
+        .header("if-modified-since", last_modified)
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn candles_are_streamed_as_ndjson() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 2).await;
+    place(&filter, "alice", "buy", 1).await;
+
+    let response = warp::test::request()
+        .path("/orderbook/candles?pair=ETH-USDC&interval=1d")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = String::from_utf8(response.body().to_vec()).unwrap();
+    let bars: Vec<serde_json::Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(bars.len(), 1);
+    assert_eq!(bars[0]["close"], 1000);
+    assert_eq!(bars[0]["volume"], 3);
+    assert_eq!(bars[0]["trades"], 2);
+
+    for query in [
+        "interval=1d",
+        "pair=ETH-USDC&interval=2m",
+        "pair=ETH-USDC&limit=0",
+    ] {
+        let response = warp::test::request()
+            .path(&format!("/orderbook/candles?{}", query))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn depth_query_is_typed_and_validated() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    place(&filter, "bob", "sell", 5).await;
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("bob", 300))
+        .json(&serde_json::json!({
+            "trader_id": "bob",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "sell",
+            "order_type": "limit",
+            "price": 1003,
+            "quantity": 2,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = warp::test::request()
+        .path("/orderbook/depth")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["asks"].as_array().unwrap().len(), 2);
+
+    let response = warp::test::request()
+        .path("/orderbook/depth?pair=ETH-USDC&grouping=5&encoding=compact")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["asks"], serde_json::json!([[1000, 5], [1005, 2]]));
+    assert_eq!(body["best_ask"], 1000);
+
+    for (query, code) in [
+        ("levels=0", "validation_error"),
+        ("grouping=0", "validation_error"),
+        ("encoding=xml", "validation_error"),
+        ("pair=ETH", "validation_error"),
+        ("levels=ten", "invalid_query"),
+    ] {
+        let response = warp::test::request()
+            .path(&format!("/orderbook/depth?{}", query))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["code"], code, "{}", query);
+    }
+}
+
+#[tokio::test]
+async fn order_entry_is_traced_from_request_through_matching() {
+    use std::sync::Mutex;
+    use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+
+    /// Span names, each with its parent's.
+    type Names = Vec<(String, Option<String>)>;
+
+    /// Records each span's name with its parent's.
+    struct SpanTree(Arc<Mutex<Names>>);
+
+    impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanTree {
+        fn on_new_span(
+            &self,
+            _attrs: &tracing::span::Attributes<'_>,
+            id: &tracing::span::Id,
+            ctx: Context<'_, S>,
+        ) {
+            let span = ctx.span(id).unwrap();
+            let parent = span.parent().map(|parent| parent.name().to_string());
+            self.0
+                .lock()
+                .unwrap()
+                .push((span.name().to_string(), parent));
+        }
+    }
+
+    let spans = Arc::new(Mutex::new(Vec::new()));
+    let _guard = tracing_subscriber::registry()
+        .with(SpanTree(spans.clone()))
+        .set_default();
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    place(&filter, "alice", "buy", 5).await;
+
+    let spans = spans.lock().unwrap().clone();
+    let parent_of = |name: &str| {
+        spans
+            .iter()
+            .find(|(span, _)| span == name)
+            .unwrap_or_else(|| panic!("no {} span in {:?}", name, spans))
+            .1
+            .clone()
+    };
+    assert_eq!(parent_of("request"), None);
+    assert_eq!(parent_of("submit_order").as_deref(), Some("request"));
+    assert_eq!(parent_of("match").as_deref(), Some("submit_order"));
+}
+
+#[tokio::test]
+async fn log_lines_carry_the_request_id() {
+    use std::{io, sync::Mutex};
+    use tracing_subscriber::prelude::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let _guard = tracing_subscriber::registry()
+        .with(
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_current_span(false)
+                .with_span_list(true)
+                .with_writer(move || writer.clone()),
+        )
+        .set_default();
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let sign_in = |request_id: Option<&str>| {
+        let request = warp::test::request()
+            .method("POST")
+            .path("/auth/token/shared")
+            .json(&serde_json::json!({ "trader_id": "alice", "secret": "guess" }));
+        match request_id {
+            Some(id) => request.header("x-request-id", id),
+            None => request,
+        }
+        .reply(&filter)
+    };
+
+    let response = sign_in(Some("req-42")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["x-request-id"], "req-42");
+    let logged = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let failure = logged
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .find(|line| line["fields"]["event"] == "auth_failed")
+        .unwrap_or_else(|| panic!("no auth_failed line in {}", logged));
+    assert_eq!(failure["level"], "WARN");
+    assert_eq!(failure["target"], "security");
+    assert_eq!(failure["fields"]["account"], "alice");
+    assert_eq!(failure["spans"][0]["name"], "request");
+    assert_eq!(failure["spans"][0]["request_id"], "req-42");
+
+    // Callers without a usable ID are given one.
+    for request_id in [None, Some("two words")] {
+        let response = sign_in(request_id).await;
+        let generated = response.headers()["x-request-id"].to_str().unwrap();
+        assert_eq!(generated.len(), 32);
+        assert!(generated.bytes().all(|byte| byte.is_ascii_hexdigit()));
+    }
+}
+
+#[tokio::test]
+async fn ledger_books_fills_swaps_and_corrections() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let filter = routes(state.clone());
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 5).await;
+    let trade_id = storage.trades.lock().unwrap()[0].id;
+    let pair: TradingPair = "ETH-USDC".parse().unwrap();
+    let carol = "carol".parse().unwrap();
+    amm_events::add_liquidity(&state, &pair, 30, &carol, 1_000, 2_000_000)
+        .await
+        .unwrap();
+    amm_events::swap(&state, &carol, &pair, pair.quote(), 20_000)
+        .await
+        .unwrap();
+    let response = warp::test::request()
+        .method("POST")
+        .path(&format!("/admin/trades/{}/bust", trade_id))
+        .header("authorization", admin_token("admin", 300))
+        .json(&serde_json::json!({ "reason": "fat finger" }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let get = |path: &str| {
+        warp::test::request()
+            .path(path)
+            .header("authorization", admin_token("admin", 300))
+            .reply(&filter)
+    };
+    let response = get("/admin/ledger/entries?account=trader:alice").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let kinds: Vec<_> = body["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["kind"].as_str().unwrap())
+        .collect();
+    assert_eq!(kinds, ["fill", "correction"]);
+    assert_eq!(body["entries"][0]["postings"][0]["account"], "trader:alice");
+    assert_eq!(body["entries"][0]["postings"][0]["direction"], "debit");
+    assert_eq!(body["entries"][0]["postings"][0]["amount"], 5);
+
+    let response = get("/admin/ledger/balances").await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["reconciled"], true);
+    assert_eq!(body["trial_balance"]["ETH"], 0);
+    // The bust squared alice and bob; only the pool and its provider
+    // hold balances.
+    let accounts: Vec<_> = body["balances"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|balance| balance["account"].as_str().unwrap())
+        .collect();
+    assert!(accounts
+        .iter()
+        .all(|account| *account == "trader:carol" || *account == "pool:ETH-USDC"));
+    assert_eq!(body["pools"][1]["ledger"], 2_020_000);
+    assert_eq!(body["pools"][1]["difference"], 0);
+
+    let response = get("/admin/ledger/entries?account=vault").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn trades_are_stored_only_with_their_ledger_entries() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let filter = routes(state.clone());
+    let maker = place(&filter, "bob", "sell", 5).await;
+    // With the maker neither tracked nor stored the fill cannot be booked,
+    // so the trade is not stored either.
+    let maker_id = maker["order_id"].as_u64().unwrap();
+    storage.orders.lock().unwrap().remove(&maker_id);
+    *state.order_tracker.write().await = crate::order_events::OrderTracker::new();
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("alice", 300))
+        .json(&serde_json::json!({
+            "trader_id": "alice",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "buy",
+            "order_type": "limit",
+            "price": 1000,
+            "quantity": 5,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(storage.trades.lock().unwrap().is_empty());
+    assert!(storage.ledger.lock().unwrap().is_empty());
+
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 5).await;
+    assert_eq!(storage.trades.lock().unwrap().len(), 1);
+    assert_eq!(storage.ledger.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn applied_settings_halt_pairs_and_charge_fees() {
+    use dex_core::ledger::{Direction, EntryKind};
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let filter = routes(state.clone());
+    let pair: TradingPair = "ETH-USDC".parse().unwrap();
+    let mut settings = state.settings.borrow().clone();
+    settings.trading_halts.insert(pair.clone());
+    settings.log_level = "debug".to_string();
+    assert!(crate::settings::apply(&state, settings.clone()));
+    assert!(!crate::settings::apply(&state, settings.clone()));
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("bob", 300))
+        .json(&serde_json::json!({
+            "trader_id": "bob",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "sell",
+            "order_type": "limit",
+            "price": 1000,
+            "quantity": 5,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "trading_halted");
+    assert!(storage.orders.lock().unwrap().is_empty());
+
+    let response = warp::test::request()
+        .path("/admin/config")
+        .header("authorization", admin_token("admin", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trading_halts"][0], "ETH-USDC");
+    assert_eq!(body["log_level"], "debug");
+
+    settings.trading_halts.clear();
+    settings.fees.pairs.insert(
+        pair,
+        crate::settings::FeeRates {
+            maker_bps: 10,
+            taker_bps: 20,
+        },
+    );
+    settings.rate_limits.order_entry = crate::rate_limit::Budget {
+        per_second: 100,
+        burst: 100,
+    };
+    crate::settings::apply(&state, settings);
+    assert_eq!(state.rate_limiter.config().order_entry.burst, 100);
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 5).await;
+    let ledger = storage.ledger.lock().unwrap();
+    let fees = ledger
+        .iter()
+        .find(|entry| entry.kind == EntryKind::Fee)
+        .expect("fees booked");
+    // 5 ETH at 1000 USDC: bob made and pays 0.1%, alice took and pays 0.2%.
+    let paid: Vec<_> = fees
+        .postings
+        .iter()
+        .filter(|posting| posting.direction == Direction::Credit)
+        .map(|posting| (posting.account.to_string(), posting.amount))
+        .collect();
+    assert_eq!(
+        paid,
+        [
+            ("trader:bob".to_string(), 5),
+            ("trader:alice".to_string(), 10)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn bridge_deposits_need_a_verified_proof() {
+    use dex_core::bridge_verification::{MockVerifier, VerifiedDeposit};
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage);
+    let mut verifier = MockVerifier::new("solana");
+    verifier.attest(VerifiedDeposit {
+        chain: "solana".to_string(),
+        event_id: "sig-1".to_string(),
+        asset_id: "SOL".parse().unwrap(),
+        recipient: "alice".parse().unwrap(),
+        amount: 250,
+    });
+    let config = crate::bridge::BridgeConfig {
+        ethereum_contract: Some([0xb7; 20]),
+        assets: vec![(
+            "solana".to_string(),
+            "SOL".parse().unwrap(),
+            "WSOL".parse().unwrap(),
+        )],
+        wrapped_assets: Vec::new(),
+    };
+    state.bridge = Arc::new(crate::bridge::Bridge::new(&config).with_verifier(Arc::new(verifier)));
+    let filter = routes(state);
+
+    let deposit = |chain: &str, event_id: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/bridge/deposits")
+            .json(&serde_json::json!({ "chain": chain, "proof": { "event_id": event_id } }))
+            .reply(&filter)
+    };
+    let response = deposit("solana", "sig-2").await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = deposit("bitcoin", "sig-1").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = deposit("solana", "sig-1").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["recipient"], "alice");
+    assert_eq!(body["token"], "WSOL");
+    assert_eq!(body["amount"], 250);
+    let response = deposit("solana", "sig-1").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = warp::test::request()
+        .path("/admin/ledger/entries?kind=deposit")
+        .header("authorization", admin_token("admin", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let entries = body["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["reference"], "solana:sig-1");
+    assert_eq!(entries[0]["postings"][0]["account"], "trader:alice");
+    assert_eq!(entries[0]["postings"][0]["direction"], "debit");
+    assert_eq!(entries[0]["postings"][1]["account"], "bridge:solana");
+
+    let trust = |subject: &str, block_hash: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/admin/bridge/blocks")
+            .header("authorization", admin_token(subject, 300))
+            .json(&serde_json::json!({ "chain": "ethereum", "block_hash": block_hash }))
+            .reply(&filter)
+    };
+    let hash = format!("0x{}", "ab".repeat(32));
+    assert_eq!(trust("alice", &hash).await.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        trust("admin", "0xab").await.status(),
+        StatusCode::BAD_REQUEST
+    );
+    assert_eq!(trust("admin", &hash).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn chain_metrics_summarize_consensus() {
+    use dex_core::types::{Block, Validator};
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    {
+        let mut consensus = state.consensus.write().await;
+        for id in ["v1", "v2"] {
+            consensus
+                .add_validator(Validator {
+                    id: id.to_string(),
+                    public_key: vec![1],
+                    stake: 100,
+                })
+                .unwrap();
+        }
+        consensus.initialize_shards(2).unwrap();
+        for height in 1..=4 {
+            let block = Block {
+                id: height,
+                height,
+                timestamp: 1,
+                transactions: vec![],
+                previous_hash: vec![0; 32],
+                hash: vec![0; 32],
+                signature: vec![],
+            };
+            consensus.process_block_with_sharding(1, block).unwrap();
+        }
+        let voters = consensus.get_shard(1).unwrap().validators[..1].to_vec();
+        consensus
+            .record_round(1, Duration::from_millis(250), &voters)
+            .unwrap();
+        consensus.update_shard_finality(1, 3);
+    }
+    let filter = routes(state);
+
+    let response = warp::test::request()
+        .path("/chain/metrics")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["rounds"], 4);
+    assert_eq!(body["leaders"]["v1"], 2);
+    assert_eq!(body["leaders"]["v2"], 2);
+    assert_eq!(body["leader_fairness"], 1.0);
+    assert_eq!(body["shards"].as_array().unwrap().len(), 1);
+    assert_eq!(body["shards"][0]["shard_id"], 1);
+    assert_eq!(body["shards"][0]["last_round_ms"], 250);
+    assert_eq!(body["shards"][0]["vote_participation"], 0.5);
+    assert_eq!(body["shards"][0]["finality_lag"], 1);
+
+    let metrics = warp::test::request().path("/metrics").reply(&filter).await;
+    let metrics = String::from_utf8_lossy(metrics.body()).into_owned();
+    assert!(metrics.contains("dex_consensus_rounds_total 4"));
+    assert!(metrics.contains("dex_consensus_leader_rounds_total{validator=\"v1\"} 2"));
+    assert!(metrics.contains("dex_consensus_vote_participation{shard=\"1\"} 0.5"));
+    assert!(metrics.contains("dex_consensus_finality_lag_blocks{shard=\"1\"} 1"));
+}
+
+#[tokio::test]
+async fn withdrawals_debit_free_balance_until_settled() {
+    use dex_core::bridge_verification::{MockVerifier, VerifiedDeposit};
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    let mut verifier = MockVerifier::new("solana");
+    verifier.attest(VerifiedDeposit {
+        chain: "solana".to_string(),
+        event_id: "sig-1".to_string(),
+        asset_id: "SOL".parse().unwrap(),
+        recipient: "alice".parse().unwrap(),
+        amount: 250,
+    });
+    let config = crate::bridge::BridgeConfig {
+        ethereum_contract: None,
+        assets: vec![(
+            "solana".to_string(),
+            "SOL".parse().unwrap(),
+            "WSOL".parse().unwrap(),
+        )],
+        wrapped_assets: Vec::new(),
+    };
+    state.bridge = Arc::new(crate::bridge::Bridge::new(&config).with_verifier(Arc::new(verifier)));
+    let filter = routes(state);
+
+    let response = warp::test::request()
+        .method("POST")
+        .path("/bridge/deposits")
+        .json(&serde_json::json!({
+            "chain": "solana", "proof": { "event_id": "sig-1" }, "tx_hash": "5xSig",
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    // A resting sell commits 100 of the 250 WSOL.
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("alice", 300))
+        .json(&serde_json::json!({
+            "trader_id": "alice", "base_token": "WSOL", "quote_token": "USDC",
+            "side": "sell", "order_type": "limit", "price": 20, "quantity": 100,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let withdraw = |token: String, amount: u128| {
+        warp::test::request()
+            .method("POST")
+            .path("/account/withdrawals")
+            .header("authorization", token)
+            .json(&serde_json::json!({
+                "chain": "solana", "token": "WSOL", "amount": amount, "address": "AliceSol",
+            }))
+            .reply(&filter)
+    };
+    let response = withdraw(scoped_token("alice", "read trade", 300), 10).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = withdraw(bearer_token("alice", 300), 200).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "insufficient_balance");
+    // With the resting 100 this would overflow rather than be covered.
+    let response = warp::test::request()
+        .method("POST")
+        .path("/account/withdrawals")
+        .header("authorization", bearer_token("alice", 300))
+        .header("content-type", "application/json")
+        .body(format!(
+            r#"{{"chain": "solana", "token": "WSOL", "amount": {}, "address": "AliceSol"}}"#,
+            u128::MAX - 50
+        ))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = withdraw(bearer_token("alice", 300), 150).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["status"], "pending");
+    let withdrawal_id = body["id"].as_u64().unwrap();
+
+    let balance = || async {
+        let response = warp::test::request()
+            .path("/account/balances")
+            .header("authorization", bearer_token("alice", 300))
+            .reply(&filter)
+            .await;
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        body["balances"][0]["balance"].as_i64().unwrap()
+    };
+    assert_eq!(balance().await, 100);
+
+    let settle = |id: u64, body: serde_json::Value| {
+        warp::test::request()
+            .method("POST")
+            .path(&format!("/admin/withdrawals/{}", id))
+            .header("authorization", admin_token("admin", 300))
+            .json(&body)
+            .reply(&filter)
+    };
+    let response = settle(withdrawal_id, serde_json::json!({ "status": "confirmed" })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = settle(withdrawal_id, serde_json::json!({ "status": "failed" })).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(balance().await, 250);
+    let response = settle(withdrawal_id, serde_json::json!({ "status": "failed" })).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = withdraw(bearer_token("alice", 300), 50).await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let response = settle(
+        body["id"].as_u64().unwrap(),
+        serde_json::json!({ "status": "confirmed", "tx_hash": "3xOut" }),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(balance().await, 200);
+
+    let response = warp::test::request()
+        .path("/account/funding")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let transfers = body["transfers"].as_array().unwrap();
+    let summary: Vec<_> = transfers
+        .iter()
+        .map(|t| (t["kind"].clone(), t["status"].clone(), t["tx_hash"].clone()))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            ("deposit".into(), "confirmed".into(), "5xSig".into()),
+            (
+                "withdrawal".into(),
+                "failed".into(),
+                serde_json::Value::Null
+            ),
+            ("withdrawal".into(), "confirmed".into(), "3xOut".into()),
+        ]
+    );
+    let response = warp::test::request()
+        .path("/admin/funding?status=pending")
+        .header("authorization", admin_token("admin", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["transfers"], serde_json::json!([]));
+}
+
+#[tokio::test]
+async fn wrapped_supply_stays_within_custody() {
+    use dex_core::{
+        bridge_verification::{MockVerifier, VerifiedDeposit},
+        wrapped_assets::WrappedAsset,
+    };
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage);
+    let mut verifier = MockVerifier::new("solana");
+    for (event_id, amount) in [("sig-1", 250), ("sig-2", 100)] {
+        verifier.attest(VerifiedDeposit {
+            chain: "solana".to_string(),
+            event_id: event_id.to_string(),
+            asset_id: "SOL".parse().unwrap(),
+            recipient: "alice".parse().unwrap(),
+            amount,
+        });
+    }
+    let config = crate::bridge::BridgeConfig {
+        ethereum_contract: None,
+        assets: vec![(
+            "solana".to_string(),
+            "SOL".parse().unwrap(),
+            "WSOL".parse().unwrap(),
+        )],
+        wrapped_assets: vec![WrappedAsset {
+            token: "WSOL".parse().unwrap(),
+            chain: "solana".to_string(),
+            custody_wallet: "sol-vault".to_string(),
+        }],
+    };
+    state.bridge = Arc::new(crate::bridge::Bridge::new(&config).with_verifier(Arc::new(verifier)));
+    let filter = routes(state);
+
+    let deposit = |event_id: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/bridge/deposits")
+            .json(&serde_json::json!({ "chain": "solana", "proof": { "event_id": event_id } }))
+            .reply(&filter)
+    };
+    let report = |wallet: &str, token: &str, balance: u128| {
+        warp::test::request()
+            .method("PUT")
+            .path(&format!("/admin/custody/{}/balances", wallet))
+            .header("authorization", admin_token("admin", 300))
+            .json(&serde_json::json!({ "token": token, "balance": balance }))
+            .reply(&filter)
+    };
+    let supply = || {
+        warp::test::request()
+            .path("/wrapped/WSOL/supply")
+            .reply(&filter)
+    };
+
+    // Nothing is in custody yet, so nothing can be minted.
+    let response = deposit("sig-1").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "deposit_unbacked");
+
+    assert_eq!(
+        report("eth-vault", "WSOL", 300).await.status(),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    assert_eq!(
+        report("sol-vault", "WSOL", 300).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(deposit("sig-1").await.status(), StatusCode::CREATED);
+    // A second deposit would take the supply past what custody holds.
+    assert_eq!(deposit("sig-2").await.status(), StatusCode::CONFLICT);
+
+    let response = supply().await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["custody_wallet"], "sol-vault");
+    assert_eq!(body["supply"], 250);
+    assert_eq!(body["reserves"], 300);
+    assert_eq!(body["backed"], true);
+    let response = warp::test::request()
+        .path("/wrapped/SOL/supply")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let burn = |amount: u128, reference: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/admin/wrapped/WSOL/burns")
+            .header("authorization", admin_token("admin", 300))
+            .json(&serde_json::json!({
+                "trader_id": "alice",
+                "amount": amount,
+                "reference": reference,
+            }))
+            .reply(&filter)
+    };
+    assert_eq!(
+        burn(300, "w-1").await.status(),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    let response = burn(200, "w-1").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["supply"], 50);
+    assert_eq!(burn(10, "w-1").await.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        report("sol-vault", "WSOL", 100).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(deposit("sig-2").await.status(), StatusCode::CONFLICT);
+
+    let balances = || {
+        warp::test::request()
+            .path("/admin/ledger/balances")
+            .header("authorization", admin_token("admin", 300))
+            .reply(&filter)
+    };
+    let body: serde_json::Value = serde_json::from_slice(balances().await.body()).unwrap();
+    assert_eq!(body["reconciled"], true);
+    assert_eq!(body["wrapped"][0]["supply"], 50);
+    assert_eq!(body["wrapped"][0]["reserves"], 100);
+
+    // Custody losing what backs the supply breaks reconciliation.
+    assert_eq!(
+        report("sol-vault", "WSOL", 40).await.status(),
+        StatusCode::OK
+    );
+    let body: serde_json::Value = serde_json::from_slice(balances().await.body()).unwrap();
+    assert_eq!(body["reconciled"], false);
+    assert_eq!(body["wrapped"][0]["backed"], false);
+}
+
+#[tokio::test]
+async fn admins_bust_and_adjust_trades() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let mut trade_rx = state.trade_tx.subscribe();
+    let mut user_rx = state.user_tx.subscribe();
+    let filter = routes(state);
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 5).await;
+    let trade_id = storage.trades.lock().unwrap()[0].id;
+    while trade_rx.try_recv().is_ok() {}
+    while user_rx.try_recv().is_ok() {}
+
+    let correct = |subject: &str, action: &str, body: serde_json::Value| {
+        warp::test::request()
+            .method("POST")
+            .path(&format!("/admin/trades/{}/{}", trade_id, action))
+            .header("authorization", admin_token(subject, 300))
+            .json(&body)
+            .reply(&filter)
+    };
+    let reason = serde_json::json!({ "reason": "fat finger" });
+    let response = correct("alice", "bust", reason.clone()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = correct("admin", "bust", serde_json::json!({ "reason": " " })).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let adjusted = serde_json::json!({ "price": 990, "reason": "off-market print" });
+    let response = correct("admin", "adjust", adjusted).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["kind"], "price_adjust");
+    assert_eq!(body["previous_price"], 1000);
+    assert_eq!(body["new_price"], 990);
+    assert_eq!(body["adjusted_by"], "admin");
+    assert_eq!(storage.trades.lock().unwrap()[0].price, 990);
+    let correction = serde_json::to_value(trade_rx.try_recv().unwrap()).unwrap();
+    assert_eq!(correction["type"], "trade_correction");
+    assert_eq!(correction["price"], 990);
+    let mut notified: Vec<_> = std::iter::from_fn(|| user_rx.try_recv().ok())
+        .map(|event| event.trader_id.to_string())
+        .collect();
+    notified.sort();
+    assert_eq!(notified, ["alice", "bob"]);
+
+    let response = correct("admin", "bust", reason.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let bust = serde_json::to_value(trade_rx.try_recv().unwrap()).unwrap();
+    assert_eq!(bust["type"], "trade_bust");
+    let response = warp::test::request()
+        .path("/markets/ETH-USDC/trades")
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trades"], serde_json::json!([]));
+
+    let response = correct("admin", "bust", reason).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "trade_busted");
+
+    let response = warp::test::request()
+        .path(&format!("/admin/trades/{}/adjustments", trade_id))
+        .header("authorization", admin_token("admin", 300))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["busted"], true);
+    let kinds: Vec<_> = body["adjustments"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|adjustment| adjustment["kind"].clone())
+        .collect();
+    assert_eq!(kinds, ["price_adjust", "bust"]);
+
+    // Outside the window.
+    let stale = Trade {
+        id: 99,
+        timestamp: 1_700_000_000,
+        ..storage.trades.lock().unwrap()[0].clone()
+    };
+    storage.trades.lock().unwrap().push(stale);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/admin/trades/99/bust")
+        .header("authorization", admin_token("admin", 300))
+        .json(&serde_json::json!({ "reason": "late" }))
+        .reply(&filter)
+        .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "adjustment_window_closed");
+}
+
+#[tokio::test]
+async fn settlement_batches_net_trades_into_transfers() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_seed(storage.clone(), 3);
+    state.config.trade_adjust_window_seconds = 60;
+    let filter = routes(state.clone());
+    let net = |subject: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/admin/settlement/netting")
+            .header("authorization", admin_token(subject, 300))
+            .reply(&filter)
+    };
+
+    let fill = |id: u64, seller: &str, buyer: &str| {
+        let order = |id, trader: &str, side| Order {
+            id,
+            trader_id: trader.parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(1000),
+            quantity: 5,
+            timestamp: 1_700_000_000,
+        };
+        let mut orders = storage.orders.lock().unwrap();
+        orders.insert(2 * id, order(2 * id, seller, OrderSide::Sell));
+        orders.insert(2 * id + 1, order(2 * id + 1, buyer, OrderSide::Buy));
+        storage.trades.lock().unwrap().push(Trade {
+            id,
+            maker_order_id: 2 * id,
+            taker_order_id: 2 * id + 1,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price: 1000,
+            quantity: 5,
+            timestamp: state.determinism.now().unwrap(),
+        });
+    };
+
+    // Alice buys from bob and passes the same ETH on to carol.
+    fill(1, "bob", "alice");
+    fill(2, "alice", "carol");
+    assert_eq!(net("alice").await.status(), StatusCode::FORBIDDEN);
+    // Both trades can still be busted.
+    let response = net("admin").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "nothing_to_net");
+
+    state.determinism.advance(61);
+    let response = net("admin").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["after_trade_id"], 0);
+    assert_eq!(body["last_trade_id"], 2);
+    assert_eq!(body["trade_count"], 2);
+    assert_eq!(body["gross_transfers"], 4);
+    assert_eq!(body["net_transfers"], 2);
+    assert_eq!(body["created_by"], "admin");
+    assert_eq!(
+        body["transfers"],
+        serde_json::json!([
+            { "token": "ETH", "from_trader": "bob", "to_trader": "carol", "amount": 5 },
+            { "token": "USDC", "from_trader": "carol", "to_trader": "bob", "amount": 5000 },
+        ])
+    );
+    let alice = &body["positions"][0];
+    assert_eq!(alice["trader_id"], "alice");
+    assert_eq!(alice["token"], "ETH");
+    assert_eq!(alice["delivered"], 5);
+    assert_eq!(alice["received"], 5);
+    assert_eq!(alice["net"], 0);
+
+    let stored = warp::test::request()
+        .path(&format!("/admin/settlement/netting/{}", body["id"]))
+        .header("authorization", admin_token("admin", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(stored.status(), StatusCode::OK);
+    let stored: serde_json::Value = serde_json::from_slice(stored.body()).unwrap();
+    assert_eq!(stored, body);
+    let missing = warp::test::request()
+        .path("/admin/settlement/netting/99")
+        .header("authorization", admin_token("admin", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+    // The next batch starts after the last netted trade. A trade whose
+    // order was cancelled away cannot be attributed.
+    fill(3, "bob", "dave");
+    storage.orders.lock().unwrap().remove(&6);
+    state.determinism.advance(61);
+    let response = net("admin").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["after_trade_id"], 2);
+    assert_eq!(body["trade_count"], 0);
+    assert_eq!(body["unattributed_trade_ids"], serde_json::json!([3]));
+    assert_eq!(net("admin").await.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn mapped_client_certificates_authenticate_without_tokens() {
+    let mut state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let identity = |subject: &str, scope: &str| crate::tls::ClientIdentity {
+        subject: subject.into(),
+        scope: scope.into(),
+    };
+    state.config.tls = Some(crate::tls::TlsConfig {
+        cert_path: "server.pem".into(),
+        key_path: "server.key".into(),
+        reload_interval: Duration::from_secs(300),
+        http_port: None,
+        acme_webroot: None,
+        client_auth: Some(crate::tls::ClientAuth {
+            ca_path: "clients-ca.pem".into(),
+            identities: [
+                (
+                    "settlement-bot".to_string(),
+                    identity("svc-settlement", "read"),
+                ),
+                ("mm.internal".to_string(), identity("alice", "read trade")),
+            ]
+            .into_iter()
+            .collect(),
+        }),
+    });
+    let filter = routes(state);
+    let request = |method: &str, path: &str, names: &[&str]| {
+        let cert = crate::tls::ClientCert {
+            names: names.iter().map(|name| name.to_string()).collect(),
+        };
+        warp::test::request()
+            .method(method)
+            .path(path)
+            .extension(cert)
+            .reply(&filter)
+    };
+
+    // The common name maps to a read-only service identity.
+    let response = request(
+        "GET",
+        "/auth/api-keys",
+        &["unmapped.internal", "settlement-bot"],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = request("DELETE", "/orderbook/orders/42", &["settlement-bot"]).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    // A trader's certificate may trade as them.
+    let response = request("DELETE", "/orderbook/orders/42", &["mm.internal"]).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Certificates without a mapping still need credentials.
+    let response = request("GET", "/auth/api-keys", &["unmapped.internal"]).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = warp::test::request()
+        .path("/auth/api-keys")
+        .extension(crate::tls::ClientCert {
+            names: vec!["unmapped.internal".into()],
+        })
+        .header("authorization", bearer_token("bob", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn ip_allowlists_bind_credentials_to_networks() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    state.config.ip_allowlists.insert(
+        "alice".into(),
+        ip_allowlist::Allowlist::parse(["203.0.113.0/24"]).unwrap(),
+    );
+    let filter = routes(state);
+    let office: SocketAddr = "203.0.113.5:4000".parse().unwrap();
+    let elsewhere: SocketAddr = "198.51.100.9:4000".parse().unwrap();
+    let list_keys = |trader: &str, peer: SocketAddr| {
+        warp::test::request()
+            .path("/auth/api-keys")
+            .header("authorization", bearer_token(trader, 300))
+            .remote_addr(peer)
+            .reply(&filter)
+    };
+
+    // Subjects bound in config may only use their tokens from there.
+    assert_eq!(list_keys("alice", office).await.status(), StatusCode::OK);
+    let response = list_keys("alice", elsewhere).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "ip_not_allowed");
+    assert_eq!(list_keys("bob", elsewhere).await.status(), StatusCode::OK);
+
+    // Keys can be bound on their own.
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/api-keys")
+        .header("authorization", bearer_token("bob", 300))
+        .json(
+            &serde_json::json!({ "label": "bot", "allowed_ips": ["10.1.2.3/8", "2001:db8::/32"] }),
+        )
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let created: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(
+        created["allowed_ips"],
+        serde_json::json!(["10.0.0.0/8", "2001:db8::/32"])
+    );
+    let key_id = created["key_id"].as_str().unwrap().to_string();
+    let secret = created["secret"].as_str().unwrap().to_string();
+    let now = Determinism::live().now().unwrap();
+    let signed = |peer: &str, timestamp: u64| {
+        let hash = api_keys::content_sha256(b"");
+        let message = api_keys::string_to_sign(
+            &timestamp.to_string(),
+            &Method::GET,
+            "/auth/api-keys",
+            &hash,
+        );
+        warp::test::request()
+            .path("/auth/api-keys")
+            .header("x-api-key", key_id.as_str())
+            .header("x-api-timestamp", timestamp.to_string())
+            .header("x-api-content-sha256", hash)
+            .header("x-api-signature", api_keys::sign(&secret, &message))
+            .remote_addr(peer.parse().unwrap())
+            .reply(&filter)
+    };
+    assert_eq!(signed("10.9.9.9:1", now).await.status(), StatusCode::OK);
+    let response = signed("198.51.100.9:1", now + 1).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "ip_not_allowed");
+
+    let set_allowed_ips = |allowed_ips: serde_json::Value| {
+        warp::test::request()
+            .method("PUT")
+            .path(&format!("/auth/api-keys/{}/allowed-ips", key_id))
+            .header("authorization", bearer_token("bob", 300))
+            .json(&serde_json::json!({ "allowed_ips": allowed_ips }))
+            .reply(&filter)
+    };
+    let response = set_allowed_ips(serde_json::json!(["10.0.0.0/33"])).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = set_allowed_ips(serde_json::json!([])).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert!(storage.api_keys.lock().unwrap()[&key_id]
+        .allowed_ips
+        .is_empty());
+    assert_eq!(
+        signed("198.51.100.9:1", now + 2).await.status(),
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn audit_log_records_authenticated_actions() {
+    let storage = Arc::new(MemoryStorage::default());
+    let filter = routes(test_state_with_memory(storage.clone()));
+    let created = place(&filter, "alice", "sell", 1).await;
+    let response = warp::test::request()
+        .method("DELETE")
+        .path(&format!("/orderbook/orders/{}", created["order_id"]))
+        .header("authorization", bearer_token("alice", 300))
+        .remote_addr("203.0.113.7:4000".parse().unwrap())
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // A failed sign-in counts against the account it names.
+    let (status, _) = post_json(
+        &filter,
+        "/auth/token/shared",
+        serde_json::json!({ "trader_id": "alice", "secret": "guess" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    // Unattributable and read-only requests are not recorded.
+    let (status, _) = post_json(
+        &filter,
+        "/auth/token/refresh",
+        serde_json::json!({ "refresh_token": "unknown" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let response = warp::test::request()
+        .path("/orderbook/depth")
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/admin/trades/1/bust")
+        .header("authorization", bearer_token("bob", 300))
+        .json(&serde_json::json!({ "reason": "fat finger" }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let recorded: Vec<_> = storage
+        .audit
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|entry| {
+            (
+                entry.subject.clone(),
+                entry.action.clone(),
+                entry.outcome.clone(),
+            )
+        })
+        .collect();
+    let expected = [
+        ("alice", "order_create", "success"),
+        ("alice", "order_cancel", "success"),
+        ("alice", "token_issue", "denied"),
+        ("bob", "trade_bust", "denied"),
+    ];
+    assert_eq!(
+        recorded,
+        expected.map(|(subject, action, outcome)| (
+            subject.to_string(),
+            action.to_string(),
+            outcome.to_string()
+        ))
+    );
+    assert_eq!(
+        storage.audit.lock().unwrap()[1].ip.as_deref(),
+        Some("203.0.113.7")
+    );
+
+    let audit_log = |path: &str, token: String| {
+        warp::test::request()
+            .path(path)
+            .header("authorization", token)
+            .reply(&filter)
+    };
+    let response = audit_log("/admin/audit", bearer_token("alice", 300)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = audit_log(
+        "/admin/audit?subject=alice&limit=2",
+        admin_token("admin", 300),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["entries"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        body["entries"][1]["target"],
+        format!("DELETE /orderbook/orders/{}", created["order_id"])
+    );
+    assert_eq!(body["next_cursor"], 2);
+    let response = audit_log(
+        "/admin/audit?subject=alice&after_id=2",
+        admin_token("admin", 300),
+    )
+    .await;
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["entries"][0]["action"], "token_issue");
+    assert_eq!(body["entries"][0]["status"], 401);
+    assert!(body.get("next_cursor").is_none());
+}
+
+#[tokio::test]
+async fn account_usage_counts_requests_per_credential() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_memory(storage.clone());
+    let filter = routes(state.clone());
+    let response = warp::test::request()
+        .method("POST")
+        .path("/auth/api-keys")
+        .header("authorization", bearer_token("alice", 300))
+        .json(&serde_json::json!({ "label": "bot", "scope": "trade" }))
+        .reply(&filter)
+        .await;
+    let created: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let key_id = created["key_id"].as_str().unwrap().to_string();
+    let secret = created["secret"].as_str().unwrap().to_string();
+    let signed = |method: &str, path: &str, timestamp: u64| {
+        let hash = api_keys::content_sha256(b"");
+        let message = api_keys::string_to_sign(
+            &timestamp.to_string(),
+            &method.parse().unwrap(),
+            path,
+            &hash,
+        );
+        warp::test::request()
+            .method(method)
+            .path(path)
+            .header("x-api-key", key_id.as_str())
+            .header("x-api-timestamp", timestamp.to_string())
+            .header("x-api-content-sha256", hash)
+            .header("x-api-signature", api_keys::sign(&secret, &message))
+            .reply(&filter)
+    };
+
+    place(&filter, "bob", "sell", 5).await;
+    place(&filter, "alice", "buy", 2).await;
+    place(&filter, "alice", "buy", 2).await;
+    let now = Determinism::live().now().unwrap();
+    assert_eq!(
+        signed("GET", "/auth/api-keys", now).await.status(),
+        StatusCode::OK
+    );
+    assert_eq!(
+        signed("DELETE", "/orderbook/orders/999", now + 1)
+            .await
+            .status(),
+        StatusCode::NOT_FOUND
+    );
+    usage::flush(&state).await.unwrap();
+    assert!(!storage.usage.lock().unwrap().is_empty());
+
+    // Stored and pending counts are merged; anonymous requests are not counted.
+    let (status, _) = post_json(
+        &filter,
+        "/orderbook/orders",
+        serde_json::json!({ "trader_id": "alice" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let response = warp::test::request()
+        .method("POST")
+        .path("/orderbook/orders")
+        .header("authorization", bearer_token("alice", 300))
+        .json(&serde_json::json!({
+            "trader_id": "bob",
+            "base_token": "ETH",
+            "quote_token": "USDC",
+            "side": "buy",
+            "order_type": "limit",
+            "price": 1000,
+            "quantity": 1,
+        }))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = warp::test::request()
+        .path("/account/usage?days=3")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["trader_id"], "alice");
+    assert_eq!(body["days"].as_array().unwrap().len(), 1);
+    let today = &body["days"][0];
+    // Key creation, two orders and the forbidden one, plus two signed.
+    assert_eq!(
+        (today["requests"].as_u64(), today["errors"].as_u64()),
+        (Some(6), Some(2))
+    );
+    assert_eq!(
+        (today["orders"].as_u64(), today["fills"].as_u64()),
+        (Some(2), Some(2))
+    );
+    assert_eq!(today["order_to_trade_ratio"], 1.0);
+    assert_eq!(today["api_keys"][0]["key_id"], key_id.as_str());
+    assert_eq!(today["api_keys"][0]["requests"], 2);
+    assert_eq!(today["api_keys"][0]["errors"], 1);
+    assert_eq!(body["rate_limits"].as_array().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn order_to_trade_breaches_are_penalised() {
+    let storage = Arc::new(MemoryStorage::default());
+    let mut state = test_state_with_memory(storage.clone());
+    state.config.messaging_policy = MessagingPolicy {
+        max_ratio: Some(2.0),
+        min_orders: 3,
+        fee: 25,
+        ..Default::default()
+    };
+    state.config.rate_limits = RateLimitConfig {
+        market_data: Budget {
+            per_second: 4,
+            burst: 4,
+        },
+        ..RateLimitConfig::unlimited()
+    };
+    state.rate_limiter = Arc::new(RateLimiter::new(state.config.rate_limits));
+    let today = usage::day_of(state.determinism.now().unwrap());
+    let yesterday = today - usage::SECONDS_PER_DAY;
+    let rollup = |subject: &str, credential: &str, orders, fills| dex_db::UsageRollup {
+        subject: subject.into(),
+        day: yesterday,
+        credential: credential.into(),
+        orders,
+        fills,
+        ..Default::default()
+    };
+    storage.usage.lock().unwrap().extend([
+        rollup("alice", "", 5, 1),
+        rollup("alice", "key-1", 2, 1),
+        rollup("bob", "", 6, 3),
+        rollup("carol", "", 2, 0),
+    ]);
+
+    let penalties = messaging_policy::evaluate(&state, yesterday).await.unwrap();
+    assert_eq!(penalties.len(), 1);
+    assert_eq!(
+        (
+            penalties[0].subject.as_str(),
+            penalties[0].orders,
+            penalties[0].fills
+        ),
+        ("alice", 7, 2)
+    );
+    assert_eq!(state.rate_limiter.reduction("alice"), Some(50));
+    assert_eq!(state.rate_limiter.reduction("bob"), None);
+    // A day is penalised once, however often it is evaluated.
+    assert!(messaging_policy::evaluate(&state, yesterday)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let filter = routes(state);
+    let response = warp::test::request()
+        .path("/account/usage")
+        .header("authorization", bearer_token("alice", 300))
+        .reply(&filter)
+        .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    let policy = &body["messaging_policy"];
+    assert_eq!(policy["max_order_to_trade_ratio"], 2.0);
+    assert_eq!(policy["penalties"][0]["fee"], 25);
+    assert_eq!(policy["penalties"][0]["rate_limit_percent"], 50);
+    assert_eq!(body["rate_limits"][1]["class"], "market_data");
+    assert_eq!(body["rate_limits"][1]["burst"], 2);
+
+    // Half the burst of four, one of which the usage request spent.
+    let get_prices = || {
+        warp::test::request()
+            .path("/orderbook/prices")
+            .header("authorization", bearer_token("alice", 300))
+            .reply(&filter)
+    };
+    assert_eq!(get_prices().await.status(), StatusCode::OK);
+    assert_eq!(get_prices().await.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn shared_secret_sign_in_needs_a_totp_code_once_enrolled() {
+    let storage = Arc::new(MemoryStorage::default());
+    let state = test_state_with_seed(storage.clone(), 5);
+    let filter = routes(state.clone());
+    let credentials = |extra: serde_json::Value| {
+        let mut body = serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" });
+        body.as_object_mut()
+            .unwrap()
+            .extend(extra.as_object().unwrap().clone());
+        body
+    };
+
+    let (status, enrolled) = post_json(
+        &filter,
+        "/auth/totp/enroll",
+        credentials(serde_json::json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(enrolled["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/test-issuer:alice?secret="));
+    let record = storage.totp.lock().unwrap()["alice"].clone();
+    assert!(!record
+        .sealed_secret
+        .contains(enrolled["secret"].as_str().unwrap()));
+    let secret = state
+        .config
+        .totp_key
+        .as_ref()
+        .unwrap()
+        .open("alice", &record.sealed_secret)
+        .unwrap();
+    assert_eq!(totp::base32(&secret), enrolled["secret"]);
+    let code = || {
+        let step = totp::step_of(state.determinism.now().unwrap());
+        format!("{:06}", totp::code_at(&secret, step))
+    };
+    let sign_in = |extra| post_json(&filter, "/auth/token/shared", credentials(extra));
+
+    // Not enforced until confirmed.
+    let (status, _) = sign_in(serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let wrong = format!("{:06}", (code().parse::<u32>().unwrap() + 1) % 1_000_000);
+    let (status, body) = post_json(
+        &filter,
+        "/auth/totp/confirm",
+        credentials(serde_json::json!({ "code": wrong })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_totp");
+    let (status, body) = post_json(
+        &filter,
+        "/auth/totp/confirm",
+        credentials(serde_json::json!({ "code": code() })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["enabled"], true);
+
+    let (status, body) = sign_in(serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "totp_required");
+    // The confirming code was spent.
+    let (status, body) = sign_in(serde_json::json!({ "totp_code": code() })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "invalid_totp");
+    state.determinism.advance(totp::PERIOD);
+    let (status, body) = sign_in(serde_json::json!({ "totp_code": code() })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["token"].is_string());
+    let (status, _) = sign_in(serde_json::json!({ "secret": "wrong", "totp_code": code() })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = post_json(
+        &filter,
+        "/auth/totp/enroll",
+        credentials(serde_json::json!({})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["code"], "totp_enabled");
+
+    state.determinism.advance(totp::PERIOD);
+    let (status, body) = post_json(
+        &filter,
+        "/auth/totp/disable",
+        credentials(serde_json::json!({ "code": code() })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["enabled"], false);
+    let (status, _) = sign_in(serde_json::json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn only_administrators_are_issued_the_admin_role() {
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    state.config.trader_secrets.insert(
+        "admin".into(),
+        SecretString::from("shared-secret".to_string()),
+    );
+    let filter = routes(state);
+    let sign_in = |trader_id: &str, scope: &str| {
+        post_json(
+            &filter,
+            "/auth/token/shared",
+            serde_json::json!({
+                "trader_id": trader_id,
+                "secret": "shared-secret",
+                "scope": scope,
+            }),
+        )
+    };
+    let adjustments = |authorization: String| {
+        warp::test::request()
+            .path("/admin/trades/1/adjustments")
+            .header("authorization", authorization)
+            .reply(&filter)
+    };
+
+    let (status, alice) = sign_in("alice", "trade").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(alice.get("role").is_none());
+    let (status, admin) = sign_in("admin", "admin").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(admin["role"], "admin");
+    let token = format!("Bearer {}", admin["token"].as_str().unwrap());
+    assert_eq!(adjustments(token).await.status(), StatusCode::OK);
+
+    // The admin scope alone, without the role claim, is not enough.
+    let response = adjustments(bearer_token("admin", 300)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "forbidden");
+    // Nor is a role claim for a subject outside ADMIN_SUBJECTS.
+    let response = adjustments(admin_token("alice", 300)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+fn wallet_sign(key: &WalletKey, hash: [u8; 32]) -> String {
+    let (signature, recovery_id) = key.sign_prehash_recoverable(&hash).unwrap();
+    Signature {
+        r: U256::from_big_endian(&signature.r().to_bytes()),
+        s: U256::from_big_endian(&signature.s().to_bytes()),
+        v: u64::from(recovery_id.to_byte()) + 27,
+    }
+    .to_string()
+}
+
+#[tokio::test]
+async fn wallets_sign_in_with_typed_data_or_a_message() {
+    let filter = routes(test_state_with_memory(Arc::new(MemoryStorage::default())));
+    let key = WalletKey::from_slice(&[7; 32]).unwrap();
+    let address = format!("{:?}", secret_key_to_address(&key));
+    let challenge = || async {
+        let response = warp::test::request()
+            .method("POST")
+            .path("/auth/challenge")
+            .json(&serde_json::json!({ "address": address }))
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        serde_json::from_slice::<serde_json::Value>(response.body()).unwrap()
+    };
+    let sign_in = |signature: String, signature_type: &str| {
+        warp::test::request()
+            .method("POST")
+            .path("/auth/token/wallet")
+            .json(&serde_json::json!({
+                "address": address,
+                "signature": signature,
+                "signature_type": signature_type,
+            }))
+            .reply(&filter)
+    };
+
+    let issued = challenge().await;
+    assert_eq!(issued["typed_data"]["primaryType"], "SignIn");
+    assert_eq!(issued["typed_data"]["message"]["address"], address);
+    assert_eq!(
+        issued["typed_data"]["message"]["expiresAt"],
+        issued["expires_at"]
+    );
+    let typed: TypedData = serde_json::from_value(issued["typed_data"].clone()).unwrap();
+    let typed_signature = wallet_sign(&key, typed.encode_eip712().unwrap());
+    // A typed-data signature does not pass as a signed message.
+    let response = sign_in(typed_signature.clone(), "personal_sign").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let typed: TypedData = serde_json::from_value(challenge().await["typed_data"].clone()).unwrap();
+    let response = sign_in(wallet_sign(&key, typed.encode_eip712().unwrap()), "eip712").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // Each challenge is redeemed once.
+    let response = sign_in(typed_signature, "eip712").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let issued = challenge().await;
+    let message = issued["challenge"].as_str().unwrap();
+    assert!(message.starts_with("localhost:3030 wants you to sign in with your Ethereum"));
+    let response = sign_in(wallet_sign(&key, hash_message(message).0), "personal_sign").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // SIWE tooling may render the challenge itself; its fields must match.
+    let with_message = |signed: SiweMessage| {
+        let text = signed.to_string();
+        warp::test::request()
+            .method("POST")
+            .path("/auth/token/wallet")
+            .json(&serde_json::json!({
+                "address": address,
+                "signature": wallet_sign(&key, hash_message(&text).0),
+                "message": text,
+            }))
+            .reply(&filter)
+    };
+    let mut signed: SiweMessage = challenge().await["challenge"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    signed.domain = "phish.example".into();
+    let response = with_message(signed.clone()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+    assert_eq!(body["code"], "invalid_siwe_message");
+
+    let mut signed: SiweMessage = challenge().await["challenge"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    signed.statement = None;
+    signed.resources = vec!["https://localhost:3030/terms".into()];
+    let response = with_message(signed).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn wallet_challenges_are_redeemable_on_any_instance() {
+    let storage = Arc::new(MemoryStorage::default());
+    let issuer = routes(test_state_with_memory(storage.clone()));
+    let redeemer = routes(test_state_with_memory(storage.clone()));
+    let key = WalletKey::from_slice(&[7; 32]).unwrap();
+    let address = format!("{:?}", secret_key_to_address(&key));
+
+    let (status, issued) = post_json(
+        &issuer,
+        "/auth/challenge",
+        serde_json::json!({ "address": address }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(storage.challenges.lock().unwrap().len(), 1);
+    let message = issued["challenge"].as_str().unwrap();
+    let (status, body) = post_json(
+        &redeemer,
+        "/auth/token/wallet",
+        serde_json::json!({
+            "address": address,
+            "signature": wallet_sign(&key, hash_message(message).0),
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["token"].is_string());
+    assert!(storage.challenges.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn solana_wallets_sign_in_with_chain_qualified_subjects() {
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    let state = test_state_with_memory(Arc::new(MemoryStorage::default()));
+    let auth = state.auth.clone();
+    let filter = routes(state);
+    let pair = Ed25519KeyPair::from_seed_unchecked(&[9; 32]).unwrap();
+    let address = bs58::encode(pair.public_key().as_ref()).into_string();
+
+    let (status, issued) = post_json(
+        &filter,
+        "/auth/challenge",
+        serde_json::json!({ "address": address, "chain": "solana" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(issued.get("typed_data").is_none());
+    let message = issued["challenge"].as_str().unwrap();
+    assert!(message.starts_with(&format!(
+        "localhost:3030 wants you to sign in with your Solana account:\n{}\n",
+        address
+    )));
+    let signature = bs58::encode(pair.sign(message.as_bytes())).into_string();
+
+    // The challenge belongs to the Solana account alone.
+    let sign_in = |chain: &str, signature_type: &str| {
+        post_json(
+            &filter,
+            "/auth/token/wallet",
+            serde_json::json!({
+                "address": address,
+                "chain": chain,
+                "signature": signature,
+                "signature_type": signature_type,
+            }),
+        )
+    };
+    let (status, body) = sign_in("ethereum", "personal_sign").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_address");
+    let (status, body) = sign_in("solana", "eip712").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "invalid_request");
+
+    let (status, _) = post_json(
+        &filter,
+        "/auth/challenge",
+        serde_json::json!({ "address": address, "chain": "solana" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = sign_in("solana", "personal_sign").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (_, issued) = post_json(
+        &filter,
+        "/auth/challenge",
+        serde_json::json!({ "address": address, "chain": "solana" }),
+    )
+    .await;
+    let message = issued["challenge"].as_str().unwrap();
+    let signature = bs58::encode(pair.sign(message.as_bytes())).into_string();
+    let (status, body) = post_json(
+        &filter,
+        "/auth/token/wallet",
+        serde_json::json!({ "address": address, "chain": "solana", "signature": signature }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let claims = auth.verify_token(body["token"].as_str().unwrap()).unwrap();
+    assert_eq!(claims.sub, format!("solana:{}", address));
+}
+
+#[tokio::test]
+async fn seeded_states_replay_the_same_trace() {
+    async fn trace(seed: u64) -> serde_json::Value {
+        let storage = Arc::new(MemoryStorage::default());
+        let state = test_state_with_seed(storage.clone(), seed);
+        let determinism = state.determinism.clone();
+        let filter = routes(state);
+        let (status, session) = post_json(
+            &filter,
+            "/auth/token/shared",
+            serde_json::json!({ "trader_id": "alice", "secret": "shared-secret" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, challenge) = post_json(
+            &filter,
+            "/auth/challenge",
+            serde_json::json!({ "address": "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed" }),
+        )
+        .await;
+        place(&filter, "alice", "sell", 5).await;
+        determinism.advance(90);
+        place(&filter, "bob", "buy", 2).await;
+        let depth = warp::test::request()
+            .path("/orderbook/depth")
+            .reply(&filter)
+            .await;
+        let mut orders: Vec<(u64, u64)> = storage
+            .orders
+            .lock()
+            .unwrap()
+            .values()
+            .map(|order| (order.id, order.timestamp))
+            .collect();
+        orders.sort();
+        serde_json::json!({
+            "token": session["token"],
+            "refresh_token": session["refresh_token"],
+            "challenge": challenge["challenge"],
+            "orders": orders,
+            "trades": storage.trades.lock().unwrap().iter().map(|trade| trade.timestamp).collect::<Vec<_>>(),
+            "depth": serde_json::from_slice::<serde_json::Value>(depth.body()).unwrap(),
+        })
+    }
+
+    let mut first = trace(7).await;
+    let mut again = trace(7).await;
+    // Refresh tokens are credentials, so the seed never decides them.
+    assert_ne!(again["refresh_token"].take(), first["refresh_token"].take());
+    assert!(first["refresh_token"].is_null());
+    assert_eq!(again, first);
+    assert_eq!(
+        first["orders"],
+        serde_json::json!([[1, 1_700_000_000], [2, 1_700_000_090]])
+    );
+    assert_eq!(first["trades"], serde_json::json!([1_700_000_090]));
+    assert_eq!(first["depth"]["timestamp"], 1_700_000_090);
+    assert!(first["token"].is_string());
+    let other = trace(8).await;
+    assert_ne!(other["token"], first["token"]);
+    assert_ne!(other["challenge"], first["challenge"]);
+    assert_eq!(other["orders"], first["orders"]);
+}
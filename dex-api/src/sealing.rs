@@ -0,0 +1,104 @@
+//! Encrypting secrets the server must read back, for storage.
+//!
+//! TOTP secrets and API key secrets cannot be stored hashed: the server
+//! recomputes codes and signatures with them. They are sealed with
+//! AES-256-GCM instead, each bound to the record it belongs to, so a
+//! database dump alone does not reveal them and a sealed value copied to
+//! another record does not open.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand::{SecureRandom, SystemRandom},
+};
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SealError {
+    #[error("failed to seal secret")]
+    Seal,
+    #[error("stored secret cannot be opened with the configured key")]
+    Open,
+}
+
+/// AES-256 key that secrets are sealed with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SealingKey([u8; 32]);
+
+impl fmt::Debug for SealingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SealingKey(..)")
+    }
+}
+
+impl SealingKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// A key from 32 bytes in standard base64.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let bytes = STANDARD.decode(raw.trim()).ok()?;
+        Some(Self(bytes.try_into().ok()?))
+    }
+
+    fn aead(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).expect("32 byte key"))
+    }
+
+    /// Encrypt `secret` for the record named `owner`, as base64 of the
+    /// nonce, ciphertext and tag.
+    pub fn seal(&self, owner: &str, secret: &[u8]) -> Result<String, SealError> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| SealError::Seal)?;
+        let mut sealed = secret.to_vec();
+        self.aead()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(owner.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| SealError::Seal)?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        Ok(STANDARD.encode(out))
+    }
+
+    /// Decrypt a secret sealed for `owner`.
+    pub fn open(&self, owner: &str, sealed: &str) -> Result<Vec<u8>, SealError> {
+        let bytes = STANDARD.decode(sealed).map_err(|_| SealError::Open)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(SealError::Open);
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| SealError::Open)?;
+        let mut in_out = ciphertext.to_vec();
+        let secret = self
+            .aead()
+            .open_in_place(nonce, Aad::from(owner.as_bytes()), &mut in_out)
+            .map_err(|_| SealError::Open)?;
+        Ok(secret.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_secrets_open_only_for_their_owner() {
+        let key = SealingKey::new([7; 32]);
+        let sealed = key.seal("alice", b"12345678901234567890").unwrap();
+        assert_eq!(key.open("alice", &sealed).unwrap(), b"12345678901234567890");
+        assert!(key.open("bob", &sealed).is_err());
+        assert!(SealingKey::new([8; 32]).open("alice", &sealed).is_err());
+        assert_ne!(key.seal("alice", b"12345678901234567890").unwrap(), sealed);
+
+        let encoded = STANDARD.encode([7; 32]);
+        assert_eq!(SealingKey::parse(&encoded), Some(key));
+        assert_eq!(SealingKey::parse("c2hvcnQ="), None);
+    }
+}
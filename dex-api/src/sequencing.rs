@@ -0,0 +1,274 @@
+//! Signed sequencing receipts.
+//!
+//! Every order the engine accepts takes the next sequence number while the
+//! book is locked, so the sequence is the order in which orders reached the
+//! book. The engine signs a receipt binding that number to a hash of the
+//! order as sequenced and to the hash of the receipt before it, returns it
+//! with the order and stores it for `GET /orderbook/orders/{id}/receipt`.
+//!
+//! Traders verify receipts without trusting the API: the signed message is
+//! the text [`signed_message`] builds, signed with the Ed25519 key published
+//! at `GET /sequencing/key`. A valid signature proves the engine committed
+//! to the position, and since each receipt names the hash of the previous
+//! one, two receipts with consecutive sequence numbers show that nothing was
+//! sequenced between them.
+
+use crate::{caching, with_state, ApiState};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use dex_core::{state_machine::ExchangeCommand, types::Order};
+use dex_db::SequencingReceipt;
+use ethers_core::utils::hex;
+use ring::{
+    digest,
+    signature::{self, Ed25519KeyPair, KeyPair, UnparsedPublicKey},
+};
+use serde::Serialize;
+use std::{fmt, sync::Mutex};
+use utoipa::{openapi::Object, ToSchema};
+use warp::Filter;
+
+/// Seed of the Ed25519 key receipts are signed with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SequencerKey([u8; 32]);
+
+impl fmt::Debug for SequencerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SequencerKey(..)")
+    }
+}
+
+impl SequencerKey {
+    pub fn new(seed: [u8; 32]) -> Self {
+        Self(seed)
+    }
+
+    /// A key from a 32-byte seed in standard base64.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let bytes = STANDARD.decode(raw.trim()).ok()?;
+        Some(Self(bytes.try_into().ok()?))
+    }
+}
+
+/// Hands out sequence numbers and signs their receipts.
+pub struct Sequencer {
+    key_pair: Ed25519KeyPair,
+    region: String,
+    /// Next sequence number and the hash the next receipt names.
+    head: Mutex<(u64, String)>,
+}
+
+impl fmt::Debug for Sequencer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sequencer")
+            .field("public_key", &self.public_key())
+            .field("region", &self.region)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Sequencer {
+    /// A sequencer continuing after `latest`, the last receipt issued.
+    pub fn new(key: &SequencerKey, region: &str, latest: Option<&SequencingReceipt>) -> Self {
+        Self {
+            key_pair: Ed25519KeyPair::from_seed_unchecked(&key.0).expect("32 byte seed"),
+            region: region.to_string(),
+            head: Mutex::new(head_after(latest)),
+        }
+    }
+
+    /// Continue after `latest` instead, e.g. once another instance's
+    /// receipts precede this one's.
+    pub fn resume(&self, latest: Option<&SequencingReceipt>) {
+        *self.head.lock().unwrap_or_else(|err| err.into_inner()) = head_after(latest);
+    }
+
+    /// Hex of the public key receipts verify against.
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn region(&self) -> &str {
+        &self.region
+    }
+
+    /// Sequence `order`. Called with the book locked, so the sequence
+    /// follows the order in which the book took orders.
+    pub fn stamp(&self, order: &Order) -> SequencingReceipt {
+        let command = ExchangeCommand::PlaceOrder(order.clone());
+        let mut head = self.head.lock().unwrap_or_else(|err| err.into_inner());
+        let mut receipt = SequencingReceipt {
+            sequence: head.0,
+            order_id: order.id,
+            command_hash: hex::encode(command.hash()),
+            previous_hash: head.1.clone(),
+            timestamp: order.timestamp,
+            region: self.region.clone(),
+            signature: String::new(),
+        };
+        receipt.signature = hex::encode(self.key_pair.sign(&signed_message(&receipt)));
+        *head = (receipt.sequence + 1, receipt_hash(&receipt));
+        receipt
+    }
+}
+
+/// Next sequence number and previous hash after `latest`.
+fn head_after(latest: Option<&SequencingReceipt>) -> (u64, String) {
+    match latest {
+        Some(latest) => (latest.sequence + 1, receipt_hash(latest)),
+        None => (1, hex::encode([0; 32])),
+    }
+}
+
+/// The text a receipt's signature covers: a version line, then the
+/// sequence number, order ID, command hash, previous hash, timestamp and
+/// region, one per line.
+pub fn signed_message(receipt: &SequencingReceipt) -> Vec<u8> {
+    format!(
+        "dex-os-sequencing-receipt/v1\n{}\n{}\n{}\n{}\n{}\n{}",
+        receipt.sequence,
+        receipt.order_id,
+        receipt.command_hash,
+        receipt.previous_hash,
+        receipt.timestamp,
+        receipt.region,
+    )
+    .into_bytes()
+}
+
+/// Hex SHA-256 of the receipt's signed message, which the next receipt
+/// names as its previous hash.
+pub fn receipt_hash(receipt: &SequencingReceipt) -> String {
+    hex::encode(digest::digest(&digest::SHA256, &signed_message(receipt)))
+}
+
+/// Whether `receipt` is signed by the hex Ed25519 `public_key`.
+pub fn verify(public_key: &str, receipt: &SequencingReceipt) -> bool {
+    let (Ok(public_key), Ok(signature)) =
+        (hex::decode(public_key), hex::decode(&receipt.signature))
+    else {
+        return false;
+    };
+    UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(&signed_message(receipt), &signature)
+        .is_ok()
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[schema(as = SequencingReceipt)]
+pub struct ReceiptResponse {
+    pub sequence: u64,
+    pub order_id: u64,
+    /// Hex SHA-256 of the order as sequenced
+    pub command_hash: String,
+    /// Hex SHA-256 of the previous receipt's signed message
+    pub previous_hash: String,
+    pub timestamp: u64,
+    pub region: String,
+    /// Hex Ed25519 signature over the receipt's signed message
+    pub signature: String,
+}
+
+impl From<SequencingReceipt> for ReceiptResponse {
+    fn from(receipt: SequencingReceipt) -> Self {
+        Self {
+            sequence: receipt.sequence,
+            order_id: receipt.order_id,
+            command_hash: receipt.command_hash,
+            previous_hash: receipt.previous_hash,
+            timestamp: receipt.timestamp,
+            region: receipt.region,
+            signature: receipt.signature,
+        }
+    }
+}
+
+/// Body of `GET /sequencing/key`.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = SequencingKey)]
+pub struct SequencingKeyResponse {
+    #[schema(schema_with = key_algorithms)]
+    pub algorithm: &'static str,
+    /// Hex Ed25519 public key
+    pub public_key: String,
+    pub region: String,
+}
+
+fn key_algorithms() -> Object {
+    crate::openapi::string_enum(&["Ed25519"])
+}
+
+/// Public key that signs sequencing receipts, GET /sequencing/key
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("sequencing")
+        .and(warp::path("key"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and(caching::conditional())
+        .map(handle_get_sequencing_key)
+        .boxed()
+}
+
+#[utoipa::path(
+    get,
+    path = "/sequencing/key",
+    summary = "Public key sequencing receipts are signed with",
+    responses(
+        (status = 200, description = "The sequencer's key", body = SequencingKeyResponse),
+        (status = 304, description = "Not modified"),
+    ),
+)]
+fn handle_get_sequencing_key(
+    state: ApiState,
+    conditional: caching::Conditional,
+) -> warp::reply::Response {
+    let key = SequencingKeyResponse {
+        algorithm: "Ed25519",
+        public_key: state.sequencer.public_key(),
+        region: state.sequencer.region().to_string(),
+    };
+    caching::cached_json(&key, None, caching::STATIC_MAX_AGE, &conditional)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u64) -> Order {
+        Order {
+            id,
+            trader_id: "alice".parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side: dex_core::types::OrderSide::Buy,
+            order_type: dex_core::types::OrderType::Limit,
+            price: Some(1_000),
+            quantity: 5,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn receipts_verify_and_chain_across_restarts() {
+        let key = SequencerKey::new([4; 32]);
+        let sequencer = Sequencer::new(&key, "eu-west", None);
+        let first = sequencer.stamp(&order(1));
+        let second = sequencer.stamp(&order(2));
+        assert_eq!((first.sequence, second.sequence), (1, 2));
+        assert_eq!(first.previous_hash, hex::encode([0; 32]));
+        assert_eq!(second.previous_hash, receipt_hash(&first));
+        assert!(verify(&sequencer.public_key(), &second));
+
+        let mut forged = second.clone();
+        forged.sequence = 1;
+        assert!(!verify(&sequencer.public_key(), &forged));
+        let other = Sequencer::new(&SequencerKey::new([5; 32]), "eu-west", None);
+        assert!(!verify(&other.public_key(), &second));
+
+        let restarted = Sequencer::new(&key, "eu-west", Some(&second));
+        let third = restarted.stamp(&order(3));
+        assert_eq!(third.sequence, 3);
+        assert_eq!(third.previous_hash, receipt_hash(&second));
+    }
+}
@@ -0,0 +1,1456 @@
+//! Sign-in: shared-secret, TOTP and wallet sign-in, refresh tokens, and the
+//! sessions they issue.
+
+use crate::{
+    api_keys, audit,
+    auth::{
+        clamp_ttl, format_scopes, generate_refresh_token, hash_refresh_token, parse_scopes,
+        AuthError, Claims, Role, Scope, WalletPayload, DEFAULT_SCOPES,
+    },
+    authenticated, caching,
+    challenge::ChallengeError,
+    determinism, error_reply,
+    lockout::{SecurityEvent, SecurityEventKind},
+    rate_limit::RouteClass,
+    rate_limited, role_of,
+    siwe::SiweMessage,
+    storage_error_reply, totp,
+    wallets::WalletChain,
+    with_state, ApiState, ErrorResponse, LockedOut,
+};
+use dex_db::{DatabaseError, RefreshTokenRecord, RevokedToken, TotpRecord};
+use ethers_core::types::transaction::eip712::TypedData;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use utoipa::{openapi::Object, ToSchema};
+use warp::{http::StatusCode, hyper::body::Bytes, Filter};
+#[derive(Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub token: String,
+    pub expires_at: u64,
+    /// Space-separated scopes the token grants.
+    pub scope: String,
+    /// `admin` for administrators; omitted for everyone else.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(inline)]
+    pub role: Option<Role>,
+    /// Omitted when the refresh token could not be stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_expires_at: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WalletChallengeResponse {
+    /// Message for `personal_sign`, or for other chains' message signing.
+    pub challenge: String,
+    /// The same challenge as EIP-712 typed data for `eth_signTypedData_v4`;
+    /// Ethereum wallets only.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub typed_data: Option<TypedData>,
+    pub expires_at: u64,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SharedTokenRequest {
+    trader_id: String,
+    secret: String,
+    #[serde(default)]
+    ttl_seconds: Option<u64>,
+    #[serde(default)]
+    audience: Option<String>,
+    /// Space-separated scopes; `read trade` when omitted.
+    #[serde(default)]
+    scope: Option<String>,
+    /// Current authenticator code; required once two-factor sign-in is on.
+    #[serde(default)]
+    totp_code: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct TotpEnrollRequest {
+    trader_id: String,
+    secret: String,
+}
+
+/// Shared-secret credentials plus an authenticator code.
+#[derive(Deserialize, ToSchema)]
+struct TotpCodeRequest {
+    trader_id: String,
+    secret: String,
+    code: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct TotpEnrollResponse {
+    /// Base32 secret to type into an authenticator app.
+    pub secret: String,
+    /// The same secret as an `otpauth://` URI, for a QR code.
+    pub otpauth_uri: String,
+    pub digits: u32,
+    pub period: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = TotpStatus)]
+pub struct TotpStatusResponse {
+    /// Whether token issuance now needs a code.
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RefreshTokenRequest {
+    refresh_token: String,
+    #[serde(default)]
+    ttl_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct RevokeTokenRequest {
+    refresh_token: String,
+}
+
+#[derive(Default, Deserialize, ToSchema)]
+struct LogoutRequest {
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+/// A sign-in of the caller whose refresh token is neither used, revoked nor
+/// expired.
+#[derive(Serialize, ToSchema)]
+#[schema(as = Session)]
+pub struct SessionResponse {
+    /// ID of the latest access token of the sign-in.
+    pub jti: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audience: Option<String>,
+    pub scope: String,
+    /// When the sign-in happened.
+    pub issued_at: u64,
+    /// When the session ends unless it is refreshed.
+    pub expires_at: u64,
+    /// When its tokens were last issued, at sign-in or refresh.
+    pub last_seen: u64,
+    /// Whether this is the token the request was made with.
+    pub current: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = Sessions)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionResponse>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct WalletChallengeRequest {
+    address: String,
+    #[serde(default)]
+    #[schema(inline)]
+    chain: WalletChain,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct WalletTokenRequest {
+    address: String,
+    #[serde(default)]
+    #[schema(inline)]
+    chain: WalletChain,
+    signature: String,
+    /// Base64 compressed secp256k1 key, which Cosmos signatures need.
+    #[serde(default)]
+    public_key: Option<String>,
+    /// Which form of the challenge was signed.
+    #[serde(default)]
+    #[schema(inline)]
+    signature_type: WalletSignatureType,
+    /// The SIWE message a `personal_sign` signature covers, when the wallet
+    /// signed its own rendering of the challenge rather than the issued text.
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    ttl_seconds: Option<u64>,
+    #[serde(default)]
+    audience: Option<String>,
+    /// Space-separated scopes; `read trade` when omitted.
+    #[serde(default)]
+    scope: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+enum WalletSignatureType {
+    #[default]
+    PersonalSign,
+    #[serde(rename = "eip712")]
+    TypedData,
+}
+
+/// A public signing key as `jsonwebtoken` serializes it; only describes the
+/// OpenAPI document.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+#[schema(as = Jwk)]
+struct JwkSchema {
+    #[schema(schema_with = key_types)]
+    kty: String,
+    #[serde(rename = "use")]
+    #[schema(schema_with = key_uses)]
+    key_use: String,
+    #[schema(schema_with = key_algorithms)]
+    alg: String,
+    kid: String,
+    /// RSA modulus, base64url
+    n: Option<String>,
+    /// RSA exponent, base64url
+    e: Option<String>,
+    #[schema(schema_with = key_curves)]
+    crv: Option<String>,
+    /// Ed25519 public key, base64url
+    x: Option<String>,
+}
+
+/// The key set served at `/.well-known/jwks.json`; only describes the
+/// OpenAPI document.
+#[allow(dead_code)]
+#[derive(ToSchema)]
+#[schema(as = JwkSet)]
+struct JwkSetSchema {
+    keys: Vec<JwkSchema>,
+}
+
+fn key_types() -> Object {
+    crate::openapi::string_enum(&["RSA", "OKP"])
+}
+
+fn key_uses() -> Object {
+    crate::openapi::string_enum(&["sig"])
+}
+
+fn key_algorithms() -> Object {
+    crate::openapi::string_enum(&["RS256", "EdDSA"])
+}
+
+fn key_curves() -> Object {
+    crate::openapi::string_enum(&["Ed25519"])
+}
+
+/// Sign-in and session endpoints, e.g. POST /auth/token/refresh
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Public halves of the asymmetric signing keys
+    let jwks = warp::path(".well-known")
+        .and(warp::path("jwks.json"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(with_state(state.clone()))
+        .and(caching::conditional())
+        .map(handle_get_jwks)
+        .boxed();
+
+    let shared = warp::path("auth")
+        .and(warp::path("token"))
+        .and(warp::path("shared"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(with_state(state.clone()))
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and_then(|state: ApiState, req: SharedTokenRequest| {
+            audit::attributed(
+                req.trader_id.trim().to_string(),
+                handle_shared_token(state, req),
+            )
+        })
+        .boxed();
+
+    // Two-factor sign-in for shared-secret accounts, e.g. POST /auth/totp/enroll
+    let totp = |action: &'static str| {
+        warp::path("auth")
+            .and(warp::path("totp"))
+            .and(warp::path(action))
+            .and(warp::path::end())
+            .and(warp::post())
+            .and(rate_limited(state.clone(), RouteClass::Auth))
+            .and(with_state(state.clone()))
+            .and(warp::body::content_length_limit(2 * 1024))
+    };
+
+    let totp_enroll = totp("enroll")
+        .and(warp::body::json())
+        .and_then(|state: ApiState, req: TotpEnrollRequest| {
+            audit::attributed(
+                req.trader_id.trim().to_string(),
+                handle_totp_enroll(state, req),
+            )
+        })
+        .boxed();
+
+    let totp_confirm = totp("confirm")
+        .and(warp::body::json())
+        .and_then(|state: ApiState, req: TotpCodeRequest| {
+            audit::attributed(
+                req.trader_id.trim().to_string(),
+                handle_totp_confirm(state, req),
+            )
+        })
+        .boxed();
+
+    let totp_disable = totp("disable")
+        .and(warp::body::json())
+        .and_then(|state: ApiState, req: TotpCodeRequest| {
+            audit::attributed(
+                req.trader_id.trim().to_string(),
+                handle_totp_disable(state, req),
+            )
+        })
+        .boxed();
+
+    let challenge = warp::path("auth")
+        .and(warp::path("challenge"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(with_state(state.clone()))
+        .and(warp::body::content_length_limit(2 * 1024))
+        .and(warp::body::json())
+        .and_then(handle_wallet_challenge)
+        .boxed();
+
+    let wallet_token = warp::path("auth")
+        .and(warp::path("token"))
+        .and(warp::path("wallet"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(with_state(state.clone()))
+        .and(warp::body::content_length_limit(4 * 1024))
+        .and(warp::body::json())
+        .and_then(|state: ApiState, req: WalletTokenRequest| {
+            audit::attributed(wallet_subject(&req), handle_wallet_token(state, req))
+        })
+        .boxed();
+
+    let refresh = warp::path("auth")
+        .and(warp::path("token"))
+        .and(warp::path("refresh"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(with_state(state.clone()))
+        .and(warp::body::content_length_limit(2 * 1024))
+        .and(warp::body::json())
+        .and_then(handle_refresh_token)
+        .boxed();
+
+    let revoke = warp::path("auth")
+        .and(warp::path("token"))
+        .and(warp::path("revoke"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(with_state(state.clone()))
+        .and(warp::body::content_length_limit(2 * 1024))
+        .and(warp::body::json())
+        .and_then(handle_revoke_token)
+        .boxed();
+
+    let logout = warp::path("auth")
+        .and(warp::path("logout"))
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and(api_keys::signed_body(2 * 1024))
+        .and_then(handle_logout)
+        .boxed();
+
+    // Access tokens of the caller, e.g. DELETE /auth/sessions/{jti}
+    let sessions = warp::path("auth").and(warp::path("sessions"));
+
+    let list_sessions = sessions
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_list_sessions)
+        .boxed();
+
+    let revoke_session = sessions
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state, Scope::Read))
+        .and_then(handle_revoke_session)
+        .boxed();
+
+    jwks.or(shared)
+        .or(totp_enroll)
+        .or(totp_confirm)
+        .or(totp_disable)
+        .or(challenge)
+        .or(wallet_token)
+        .or(refresh)
+        .or(revoke)
+        .or(logout)
+        .or(list_sessions)
+        .or(revoke_session)
+}
+
+/// Public keys for verifying RS256 and EdDSA tokens.
+#[utoipa::path(
+    get,
+    path = "/.well-known/jwks.json",
+    summary = "Public keys for verifying RS256 and EdDSA tokens",
+    responses(
+        (status = 200, description = "Signing keys that have not expired", body = JwkSetSchema),
+        (status = 304, description = "Not modified"),
+    ),
+)]
+fn handle_get_jwks(state: ApiState, conditional: caching::Conditional) -> warp::reply::Response {
+    let jwks = state.auth.jwks();
+    caching::cached_json(&jwks, None, caching::STATIC_MAX_AGE, &conditional)
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/token/shared",
+    summary = "Issue a token for a trader's shared secret",
+    request_body = SharedTokenRequest,
+    responses(
+        (status = 200, description = "Signed token", body = TokenResponse),
+        (status = 400, description = "Invalid request or unknown scope", body = ErrorResponse),
+        (status = 401, description = "Unknown trader, wrong secret, or a missing (totp_required) or invalid two-factor code", body = ErrorResponse),
+        (status = 403, description = "The admin scope was requested by a non-administrator", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded, or locked_out after repeated failed sign-ins; retry after Retry-After seconds", body = ErrorResponse),
+    ),
+)]
+async fn handle_shared_token(
+    state: ApiState,
+    req: SharedTokenRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if state.config.trader_secrets.is_empty() {
+        return Ok(error_reply(
+            "unauthorized",
+            "shared secret token issuance is disabled",
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    check_lockout(&state, "shared", &req.trader_id)?;
+    if let Err(reply) = check_shared_secret(&state, "shared", &req.trader_id, &req.secret) {
+        return Ok(reply);
+    }
+    let enrollment = match state.totp_repo.load_totp(&req.trader_id).await {
+        Ok(enrollment) => enrollment.filter(|enrollment| enrollment.confirmed),
+        Err(err) => {
+            return Ok(storage_error_reply(
+                &err,
+                "failed to load two-factor enrollment",
+            ))
+        }
+    };
+    if let Some(enrollment) = enrollment {
+        let Some(code) = req.totp_code.as_deref() else {
+            return Ok(error_reply(
+                "totp_required",
+                "a two-factor code is required",
+                StatusCode::UNAUTHORIZED,
+            ));
+        };
+        if let Err(reply) = verify_totp(&state, "shared", &enrollment, code).await {
+            return Ok(reply);
+        }
+    }
+    state.auth_lockout.record_success(&req.trader_id);
+
+    let ttl = clamp_ttl(
+        req.ttl_seconds,
+        state.config.jwt_default_ttl_seconds,
+        state.config.jwt_max_ttl_seconds,
+    );
+    let scopes = match requested_scopes(&state, &req.trader_id, req.scope.as_deref()) {
+        Ok(scopes) => scopes,
+        Err(reply) => return Ok(reply),
+    };
+    let response =
+        match issue_session(&state, req.trader_id, ttl, req.audience, &scopes, None).await {
+            Ok(response) => response,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to issue shared token");
+                return Ok(error_reply(
+                    "internal_error",
+                    "failed to issue token",
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                ));
+            }
+        };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::OK,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/totp/enroll",
+    summary = "Start two-factor enrollment for a shared-secret account",
+    request_body = TotpEnrollRequest,
+    responses(
+        (status = 200, description = "Secret for an authenticator app, pending confirmation", body = TotpEnrollResponse),
+        (status = 401, description = "Unknown trader or wrong secret", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication is already enabled", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded, or locked_out after repeated failed sign-ins; retry after Retry-After seconds", body = ErrorResponse),
+        (status = 503, description = "TOTP_ENCRYPTION_KEY is not configured", body = ErrorResponse),
+    ),
+)]
+async fn handle_totp_enroll(
+    state: ApiState,
+    req: TotpEnrollRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let Some(key) = &state.config.totp_key else {
+        return Ok(totp_unavailable());
+    };
+    check_lockout(&state, "totp", &req.trader_id)?;
+    if let Err(reply) = check_shared_secret(&state, "totp", &req.trader_id, &req.secret) {
+        return Ok(reply);
+    }
+    state.auth_lockout.record_success(&req.trader_id);
+
+    let secret = determinism::secret_bytes(totp::SECRET_LEN);
+    let sealed_secret = match key.seal(&req.trader_id, &secret) {
+        Ok(sealed) => sealed,
+        Err(err) => {
+            tracing::error!(trader_id = %req.trader_id, error = %err, "failed to seal TOTP secret");
+            return Ok(error_reply(
+                "internal_error",
+                "failed to enroll two-factor authentication",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+    let record = TotpRecord {
+        subject: req.trader_id.clone(),
+        sealed_secret,
+        confirmed: false,
+        last_step: 0,
+        created_at: state.determinism.now().unwrap_or_default(),
+    };
+    match state.totp_repo.save_totp(&record).await {
+        Ok(true) => {}
+        Ok(false) => return Ok(totp_already_enabled()),
+        Err(err) => {
+            return Ok(storage_error_reply(
+                &err,
+                "failed to save two-factor enrollment",
+            ))
+        }
+    }
+    let response = TotpEnrollResponse {
+        secret: totp::base32(&secret),
+        otpauth_uri: totp::otpauth_uri(&state.config.jwt_issuer, &req.trader_id, &secret),
+        digits: totp::DIGITS,
+        period: totp::PERIOD,
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::OK,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/totp/confirm",
+    summary = "Turn two-factor sign-in on with a first code",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "Two-factor sign-in enabled", body = TotpStatusResponse),
+        (status = 400, description = "No pending enrollment", body = ErrorResponse),
+        (status = 401, description = "Wrong secret or invalid code", body = ErrorResponse),
+        (status = 409, description = "Two-factor authentication is already enabled", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded, or locked_out after repeated failed sign-ins; retry after Retry-After seconds", body = ErrorResponse),
+    ),
+)]
+async fn handle_totp_confirm(
+    state: ApiState,
+    req: TotpCodeRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    check_lockout(&state, "totp", &req.trader_id)?;
+    if let Err(reply) = check_shared_secret(&state, "totp", &req.trader_id, &req.secret) {
+        return Ok(reply);
+    }
+    let enrollment = match state.totp_repo.load_totp(&req.trader_id).await {
+        Ok(Some(enrollment)) if enrollment.confirmed => return Ok(totp_already_enabled()),
+        Ok(Some(enrollment)) => enrollment,
+        Ok(None) => return Ok(totp_not_enrolled()),
+        Err(err) => {
+            return Ok(storage_error_reply(
+                &err,
+                "failed to load two-factor enrollment",
+            ))
+        }
+    };
+    if let Err(reply) = verify_totp(&state, "totp", &enrollment, &req.code).await {
+        return Ok(reply);
+    }
+    state.auth_lockout.record_success(&req.trader_id);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&TotpStatusResponse { enabled: true }),
+        StatusCode::OK,
+    ))
+}
+
+/// Turn two-factor sign-in off; a confirmed enrollment takes a current code.
+#[utoipa::path(
+    post,
+    path = "/auth/totp/disable",
+    summary = "Turn two-factor sign-in off",
+    request_body = TotpCodeRequest,
+    responses(
+        (status = 200, description = "Two-factor sign-in disabled", body = TotpStatusResponse),
+        (status = 400, description = "No enrollment", body = ErrorResponse),
+        (status = 401, description = "Wrong secret or invalid code", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded, or locked_out after repeated failed sign-ins; retry after Retry-After seconds", body = ErrorResponse),
+    ),
+)]
+async fn handle_totp_disable(
+    state: ApiState,
+    req: TotpCodeRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    check_lockout(&state, "totp", &req.trader_id)?;
+    if let Err(reply) = check_shared_secret(&state, "totp", &req.trader_id, &req.secret) {
+        return Ok(reply);
+    }
+    let enrollment = match state.totp_repo.load_totp(&req.trader_id).await {
+        Ok(Some(enrollment)) => enrollment,
+        Ok(None) => return Ok(totp_not_enrolled()),
+        Err(err) => {
+            return Ok(storage_error_reply(
+                &err,
+                "failed to load two-factor enrollment",
+            ))
+        }
+    };
+    if enrollment.confirmed {
+        if let Err(reply) = verify_totp(&state, "totp", &enrollment, &req.code).await {
+            return Ok(reply);
+        }
+    }
+    state.auth_lockout.record_success(&req.trader_id);
+    if let Err(err) = state.totp_repo.delete_totp(&req.trader_id).await {
+        return Ok(storage_error_reply(
+            &err,
+            "failed to remove two-factor enrollment",
+        ));
+    }
+    Ok(warp::reply::with_status(
+        warp::reply::json(&TotpStatusResponse { enabled: false }),
+        StatusCode::OK,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/challenge",
+    summary = "Request a challenge for a wallet to sign",
+    request_body = WalletChallengeRequest,
+    responses(
+        (status = 200, description = "Challenge to sign", body = WalletChallengeResponse),
+        (status = 400, description = "Invalid wallet address", body = ErrorResponse),
+    ),
+)]
+async fn handle_wallet_challenge(
+    state: ApiState,
+    req: WalletChallengeRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let address = match normalize_wallet_address(req.chain, &req.address) {
+        Ok(address) => address,
+        Err(reply) => return Ok(reply),
+    };
+    let issued = match state.wallet_challenges.issue(req.chain, &address).await {
+        Ok(issued) => issued,
+        Err(err) => return Ok(storage_error_reply(&err, "failed to issue challenge")),
+    };
+    let response = WalletChallengeResponse {
+        challenge: issued.challenge,
+        typed_data: issued.typed_data,
+        expires_at: issued.expires_at,
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::OK,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/auth/token/wallet",
+    summary = "Exchange a signed challenge for a token",
+    request_body = WalletTokenRequest,
+    responses(
+        (status = 200, description = "Signed token", body = TokenResponse),
+        (status = 400, description = "Invalid request or unknown scope", body = ErrorResponse),
+        (status = 401, description = "Unknown, expired or wrongly signed challenge", body = ErrorResponse),
+        (status = 403, description = "The admin scope was requested by a non-administrator", body = ErrorResponse),
+        (status = 429, description = "Rate limit exceeded, or locked_out after repeated failed sign-ins; retry after Retry-After seconds", body = ErrorResponse),
+    ),
+)]
+async fn handle_wallet_token(
+    state: ApiState,
+    req: WalletTokenRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let scheme = req.chain.scheme();
+    let address = match normalize_wallet_address(req.chain, &req.address) {
+        Ok(address) => address,
+        Err(reply) => return Ok(reply),
+    };
+    let subject = scheme.subject(&address);
+    check_lockout(&state, "wallet", &subject)?;
+
+    let challenge = match state.wallet_challenges.take(&subject).await {
+        Ok(challenge) => challenge,
+        Err(err) => {
+            let (code, status, msg) = match err {
+                ChallengeError::Missing => (
+                    "challenge_missing",
+                    StatusCode::BAD_REQUEST,
+                    "no pending challenge for this address",
+                ),
+                ChallengeError::Expired => (
+                    "challenge_expired",
+                    StatusCode::BAD_REQUEST,
+                    "challenge expired, request a new one",
+                ),
+                ChallengeError::Storage(err) => {
+                    return Ok(storage_error_reply(&err, "failed to load challenge"))
+                }
+            };
+            return Ok(error_reply(code, msg, status));
+        }
+    };
+
+    let payload = match (req.signature_type, req.message.as_deref()) {
+        (WalletSignatureType::PersonalSign, None) => WalletPayload::Message(&challenge.message),
+        (WalletSignatureType::PersonalSign, Some(message)) => {
+            let Some(issued) = &challenge.siwe else {
+                return Ok(error_reply(
+                    "invalid_request",
+                    "message only applies to Ethereum wallets",
+                    StatusCode::BAD_REQUEST,
+                ));
+            };
+            let now = state.determinism.now().unwrap_or_default();
+            let checked = message
+                .parse::<SiweMessage>()
+                .map_err(|err| (StatusCode::BAD_REQUEST, err))
+                .and_then(|signed| {
+                    signed
+                        .check(issued, now)
+                        .map_err(|err| (StatusCode::UNAUTHORIZED, err))
+                });
+            if let Err((status, err)) = checked {
+                record_auth_failure(&state, "wallet", &subject, "invalid_siwe_message");
+                return Ok(error_reply("invalid_siwe_message", err.to_string(), status));
+            }
+            WalletPayload::Message(message)
+        }
+        (WalletSignatureType::TypedData, None) => match &challenge.typed_data {
+            Some(typed_data) => WalletPayload::TypedData(typed_data),
+            None => {
+                return Ok(error_reply(
+                    "invalid_request",
+                    "eip712 signatures only apply to Ethereum wallets",
+                    StatusCode::BAD_REQUEST,
+                ))
+            }
+        },
+        (WalletSignatureType::TypedData, Some(_)) => {
+            return Ok(error_reply(
+                "invalid_request",
+                "message only applies to personal_sign signatures",
+                StatusCode::BAD_REQUEST,
+            ))
+        }
+    };
+    if let Err(err) = scheme.verify(&address, payload, &req.signature, req.public_key.as_deref()) {
+        record_auth_failure(&state, "wallet", &subject, "invalid_signature");
+        return Ok(error_reply(
+            "invalid_signature",
+            err.to_string(),
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    state.auth_lockout.record_success(&subject);
+
+    let ttl = clamp_ttl(
+        req.ttl_seconds,
+        state.config.jwt_default_ttl_seconds,
+        state.config.jwt_max_ttl_seconds,
+    );
+
+    let scopes = match requested_scopes(&state, &subject, req.scope.as_deref()) {
+        Ok(scopes) => scopes,
+        Err(reply) => return Ok(reply),
+    };
+    let response = match issue_session(&state, subject, ttl, req.audience, &scopes, None).await {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to issue wallet token");
+            return Ok(error_reply(
+                "internal_error",
+                "failed to issue token",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ));
+        }
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::OK,
+    ))
+}
+
+/// Refuse a sign-in as `account` while it is locked out.
+fn check_lockout(
+    state: &ApiState,
+    endpoint: &'static str,
+    account: &str,
+) -> Result<(), warp::Rejection> {
+    let now = state.determinism.now().unwrap_or_default();
+    let Some(retry_after) = state.auth_lockout.locked_for(account, now) else {
+        return Ok(());
+    };
+    SecurityEvent {
+        event: SecurityEventKind::AuthLockedOut,
+        endpoint,
+        account,
+        reason: "locked_out",
+        failures: None,
+        locked_until: Some(now + retry_after),
+        timestamp: now,
+    }
+    .emit();
+    Err(warp::reject::custom(LockedOut { retry_after }))
+}
+
+/// Count a failed sign-in as `account` and log it.
+fn record_auth_failure(state: &ApiState, endpoint: &'static str, account: &str, reason: &str) {
+    let now = state.determinism.now().unwrap_or_default();
+    let (failures, locked_until) = state.auth_lockout.record_failure(account, now);
+    SecurityEvent {
+        event: SecurityEventKind::AuthFailed,
+        endpoint,
+        account,
+        reason,
+        failures: Some(failures),
+        locked_until,
+        timestamp: now,
+    }
+    .emit();
+}
+
+/// Check shared-secret credentials, counting a mismatch toward lockout.
+fn check_shared_secret(
+    state: &ApiState,
+    endpoint: &'static str,
+    trader_id: &str,
+    secret: &str,
+) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    let reason = match state.config.trader_secrets.get(trader_id) {
+        None => "unknown_trader",
+        Some(expected) if expected.expose_secret() != secret => "wrong_secret",
+        Some(_) => return Ok(()),
+    };
+    record_auth_failure(state, endpoint, trader_id, reason);
+    Err(error_reply(
+        "unauthorized",
+        "invalid trader credentials",
+        StatusCode::UNAUTHORIZED,
+    ))
+}
+
+/// Check an authenticator code against an enrollment, confirming a pending
+/// one, and spend its time step so the code cannot be replayed.
+async fn verify_totp(
+    state: &ApiState,
+    endpoint: &'static str,
+    enrollment: &TotpRecord,
+    code: &str,
+) -> Result<(), warp::reply::WithStatus<warp::reply::Json>> {
+    let Some(key) = &state.config.totp_key else {
+        return Err(totp_unavailable());
+    };
+    let secret = key
+        .open(&enrollment.subject, &enrollment.sealed_secret)
+        .map_err(|err| {
+            tracing::error!(
+                subject = %enrollment.subject,
+                error = %err,
+                "failed to open TOTP secret"
+            );
+            error_reply(
+                "internal_error",
+                "failed to verify two-factor code",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            )
+        })?;
+    let now = state.determinism.now().unwrap_or_default();
+    let accepted = match totp::verify(&secret, code, now, enrollment.last_step) {
+        Some(step) if enrollment.confirmed => {
+            state
+                .totp_repo
+                .use_totp_step(&enrollment.subject, step)
+                .await
+        }
+        Some(step) => {
+            state
+                .totp_repo
+                .confirm_totp(&enrollment.subject, step)
+                .await
+        }
+        None => Ok(false),
+    }
+    .map_err(|err| storage_error_reply(&err, "failed to verify two-factor code"))?;
+    if !accepted {
+        record_auth_failure(state, endpoint, &enrollment.subject, "wrong_totp");
+        return Err(error_reply(
+            "invalid_totp",
+            "invalid or already used two-factor code",
+            StatusCode::UNAUTHORIZED,
+        ));
+    }
+    Ok(())
+}
+
+fn totp_unavailable() -> warp::reply::WithStatus<warp::reply::Json> {
+    error_reply(
+        "totp_unavailable",
+        "two-factor authentication is not configured",
+        StatusCode::SERVICE_UNAVAILABLE,
+    )
+}
+
+fn totp_already_enabled() -> warp::reply::WithStatus<warp::reply::Json> {
+    error_reply(
+        "totp_enabled",
+        "two-factor authentication is already enabled",
+        StatusCode::CONFLICT,
+    )
+}
+
+fn totp_not_enrolled() -> warp::reply::WithStatus<warp::reply::Json> {
+    error_reply(
+        "totp_not_enrolled",
+        "no two-factor enrollment for this account",
+        StatusCode::BAD_REQUEST,
+    )
+}
+
+/// Canonical form of a wallet address on `chain`, or the reply refusing it.
+/// The subject a wallet sign-in is for, or the address as sent when it is
+/// not valid for its chain.
+fn wallet_subject(req: &WalletTokenRequest) -> String {
+    let scheme = req.chain.scheme();
+    match scheme.normalize_address(&req.address) {
+        Ok(address) => scheme.subject(&address),
+        Err(_) => req.address.trim().to_string(),
+    }
+}
+
+fn normalize_wallet_address(
+    chain: WalletChain,
+    address: &str,
+) -> Result<String, warp::reply::WithStatus<warp::reply::Json>> {
+    let scheme = chain.scheme();
+    scheme.normalize_address(address).map_err(|_| {
+        error_reply(
+            "invalid_address",
+            format!(
+                "{} wallet address must be {}",
+                scheme.blockchain(),
+                scheme.address_format()
+            ),
+            StatusCode::BAD_REQUEST,
+        )
+    })
+}
+
+/// Scopes for a sign-in by `subject`: those requested, or the defaults. Only
+/// administrators may ask for `admin`.
+pub(crate) fn requested_scopes(
+    state: &ApiState,
+    subject: &str,
+    requested: Option<&str>,
+) -> Result<Vec<Scope>, warp::reply::WithStatus<warp::reply::Json>> {
+    let scopes = match requested {
+        None => DEFAULT_SCOPES.to_vec(),
+        Some(raw) => parse_scopes(raw).map_err(|err| {
+            error_reply("invalid_scope", err.to_string(), StatusCode::BAD_REQUEST)
+        })?,
+    };
+    if scopes.contains(&Scope::Admin) && !state.config.admin_subjects.contains(subject) {
+        return Err(error_reply(
+            "forbidden",
+            "the admin scope is limited to administrators",
+            StatusCode::FORBIDDEN,
+        ));
+    }
+    Ok(scopes)
+}
+
+/// Issue an access token plus a refresh token, in the family of the token
+/// `rotated_from` when refreshing, or a new family for a fresh sign-in. The
+/// stored refresh token is the session: it records the access token issued
+/// with it. If it cannot be stored the access token is still returned; the
+/// client signs in again when it expires.
+async fn issue_session(
+    state: &ApiState,
+    subject: String,
+    ttl: Duration,
+    audience: Option<String>,
+    scopes: &[Scope],
+    rotated_from: Option<&RefreshTokenRecord>,
+) -> Result<TokenResponse, AuthError> {
+    let role = role_of(state, &subject);
+    let issued = state
+        .auth
+        .issue_token(subject.clone(), ttl, audience.clone(), scopes, role)?;
+    let now = state.determinism.now().map_err(|_| AuthError::TimeSource)?;
+    let refresh = generate_refresh_token();
+    let refresh_expires_at = now + state.config.refresh_token_ttl_seconds;
+    let record = RefreshTokenRecord {
+        token_hash: refresh.hash,
+        family_id: rotated_from.map_or_else(
+            || state.determinism.random_string(24),
+            |token| token.family_id.clone(),
+        ),
+        subject,
+        audience,
+        scope: format_scopes(scopes),
+        expires_at: refresh_expires_at,
+        revoked: false,
+        // Tokens stored before sign-in times were recorded have none.
+        signed_in_at: rotated_from
+            .map(|token| token.signed_in_at)
+            .filter(|signed_in_at| *signed_in_at != 0)
+            .unwrap_or(now),
+        issued_at: now,
+        access_jti: Some(issued.jti),
+        access_expires_at: issued.expires_at,
+    };
+    let stored = match state.refresh_tokens.save_refresh_token(&record).await {
+        Ok(()) => Some((refresh.token, refresh_expires_at)),
+        Err(err) => {
+            tracing::error!(
+                subject = %record.subject,
+                error = ?err,
+                "failed to store refresh token"
+            );
+            None
+        }
+    };
+    Ok(TokenResponse {
+        token: issued.token,
+        expires_at: issued.expires_at,
+        scope: record.scope,
+        role,
+        refresh_token: stored.as_ref().map(|(token, _)| token.clone()),
+        refresh_expires_at: stored.map(|(_, expires_at)| expires_at),
+    })
+}
+
+/// Exchange a refresh token for a new access token and a new refresh token.
+/// Each refresh token works once; presenting a used one revokes its family,
+/// since it means the token was copied.
+#[utoipa::path(
+    post,
+    path = "/auth/token/refresh",
+    summary = "Exchange a refresh token for a new token pair",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "Signed token and its replacement refresh token", body = TokenResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Unknown, expired, used or revoked refresh token", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_refresh_token(
+    state: ApiState,
+    req: RefreshTokenRequest,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let token_hash = hash_refresh_token(&req.refresh_token);
+    let record = match state.refresh_tokens.revoke_refresh_token(&token_hash).await {
+        Ok(Some(record)) => record,
+        Ok(None) => {
+            return Ok(warp::Reply::into_response(error_reply(
+                "invalid_refresh_token",
+                "unknown refresh token",
+                StatusCode::UNAUTHORIZED,
+            )))
+        }
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to redeem refresh token");
+            return Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to redeem refresh token",
+            )));
+        }
+    };
+    let subject = record.subject.clone();
+    let reply = refresh_redeemed(&state, req.ttl_seconds, record).await;
+    Ok(audit::attribute(reply, subject))
+}
+
+/// Issue the tokens for a redeemed refresh token, unless it had already been
+/// used or has expired.
+async fn refresh_redeemed(
+    state: &ApiState,
+    ttl_seconds: Option<u64>,
+    record: RefreshTokenRecord,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    if record.revoked {
+        tracing::warn!(
+            target: "security",
+            subject = %record.subject,
+            "refresh token reused; revoking its family"
+        );
+        if let Err(err) = state
+            .refresh_tokens
+            .revoke_refresh_family(&record.family_id)
+            .await
+        {
+            tracing::error!(error = ?err, "failed to revoke refresh token family");
+        }
+        return error_reply(
+            "invalid_refresh_token",
+            "refresh token was already used or revoked; sign in again",
+            StatusCode::UNAUTHORIZED,
+        );
+    }
+    if state.determinism.now().unwrap_or(u64::MAX) >= record.expires_at {
+        return error_reply(
+            "refresh_token_expired",
+            "refresh token expired; sign in again",
+            StatusCode::UNAUTHORIZED,
+        );
+    }
+
+    let ttl = clamp_ttl(
+        ttl_seconds,
+        state.config.jwt_default_ttl_seconds,
+        state.config.jwt_max_ttl_seconds,
+    );
+    // Refreshed tokens keep the sign-in's scopes, re-checked in case the
+    // subject has since lost administrator access.
+    let scopes = match requested_scopes(state, &record.subject, Some(&record.scope)) {
+        Ok(scopes) => scopes,
+        Err(reply) => return reply,
+    };
+    let session = issue_session(
+        state,
+        record.subject.clone(),
+        ttl,
+        record.audience.clone(),
+        &scopes,
+        Some(&record),
+    )
+    .await;
+    let response = match session {
+        Ok(response) => response,
+        Err(err) => {
+            tracing::error!(error = %err, "failed to issue refreshed token");
+            return error_reply(
+                "internal_error",
+                "failed to issue token",
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    };
+    warp::reply::with_status(warp::reply::json(&response), StatusCode::OK)
+}
+
+/// Revoke a refresh token and every token rotated from the same sign-in.
+/// Unknown tokens are accepted too, so the response reveals nothing.
+#[utoipa::path(
+    post,
+    path = "/auth/token/revoke",
+    summary = "Revoke a refresh token and every token rotated from its sign-in",
+    request_body = RevokeTokenRequest,
+    responses(
+        (status = 204, description = "Revoked, or not a known token"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_revoke_token(
+    state: ApiState,
+    req: RevokeTokenRequest,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    match revoke_refresh_session(&state, &req.refresh_token).await {
+        Ok(Some(subject)) => Ok(audit::attribute(StatusCode::NO_CONTENT, subject)),
+        Ok(None) => Ok(warp::Reply::into_response(StatusCode::NO_CONTENT)),
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to revoke refresh token");
+            Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to revoke refresh token",
+            )))
+        }
+    }
+}
+
+/// Revoke a refresh token's family, returning the subject it was issued to.
+async fn revoke_refresh_session(
+    state: &ApiState,
+    refresh_token: &str,
+) -> Result<Option<String>, DatabaseError> {
+    let token_hash = hash_refresh_token(refresh_token);
+    match state
+        .refresh_tokens
+        .revoke_refresh_token(&token_hash)
+        .await?
+    {
+        Some(record) => state
+            .refresh_tokens
+            .revoke_refresh_family(&record.family_id)
+            .await
+            .map(|_| Some(record.subject)),
+        None => Ok(None),
+    }
+}
+
+/// Sign out: revoke the bearer token for the rest of its lifetime and, when
+/// the body carries one, the refresh token and its family. The body is
+/// optional.
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    summary = "Revoke the bearer token and, if given, its refresh token",
+    request_body = Option<LogoutRequest>,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 204, description = "Signed out"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 401, description = "Missing, invalid or already revoked token", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_logout(
+    claims: Claims,
+    state: ApiState,
+    body: Bytes,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let req: LogoutRequest = if body.is_empty() {
+        LogoutRequest::default()
+    } else {
+        match serde_json::from_slice(&body) {
+            Ok(req) => req,
+            Err(err) => {
+                return Ok(warp::Reply::into_response(error_reply(
+                    "invalid_payload",
+                    format!("invalid request body: {}", err),
+                    StatusCode::BAD_REQUEST,
+                )))
+            }
+        }
+    };
+
+    match claims.jti {
+        Some(jti) => {
+            let token = RevokedToken {
+                jti,
+                expires_at: claims.exp as u64,
+            };
+            if let Err(err) = revoke_access_token(&state, token).await {
+                tracing::error!(error = ?err, "failed to revoke access token on logout");
+                return Ok(warp::Reply::into_response(storage_error_reply(
+                    &err,
+                    "failed to revoke token",
+                )));
+            }
+        }
+        None => tracing::warn!(
+            subject = %claims.sub,
+            "token has no jti and stays valid until it expires"
+        ),
+    }
+    if let Some(refresh_token) = req.refresh_token {
+        if let Err(err) = revoke_refresh_session(&state, &refresh_token).await {
+            tracing::error!(error = ?err, "failed to revoke refresh token on logout");
+            return Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to revoke refresh token",
+            )));
+        }
+    }
+    Ok(warp::Reply::into_response(StatusCode::NO_CONTENT))
+}
+
+/// The caller's sessions, read from the refresh tokens in storage, so every
+/// instance lists the same ones.
+#[utoipa::path(
+    get,
+    path = "/auth/sessions",
+    summary = "List the caller's sign-ins whose refresh token is still valid",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Sessions, oldest first", body = SessionsResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_list_sessions(
+    claims: Claims,
+    state: ApiState,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let tokens = match refresh_sessions(&state, &claims.sub).await {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to list sessions");
+            return Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to list sessions",
+            )));
+        }
+    };
+    // Tokens stored before access token IDs were recorded cannot be named
+    // and are left out.
+    let sessions = tokens
+        .into_iter()
+        .filter_map(|token| {
+            let jti = token.access_jti?;
+            Some(SessionResponse {
+                current: claims.jti.as_deref() == Some(jti.as_str()),
+                jti,
+                audience: token.audience,
+                scope: token.scope,
+                issued_at: token.signed_in_at,
+                expires_at: token.expires_at,
+                last_seen: token.issued_at,
+            })
+        })
+        .collect();
+    Ok(warp::Reply::into_response(warp::reply::json(
+        &SessionsResponse { sessions },
+    )))
+}
+
+/// End one of the caller's sessions: its latest access token is rejected
+/// from now on, and the refresh tokens of its sign-in stop working.
+#[utoipa::path(
+    delete,
+    path = "/auth/sessions/{jti}",
+    summary = "End one of the caller's sign-ins: its latest access token and its refresh tokens",
+    params(("jti" = String, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 404, description = "No active session with this ID", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_revoke_session(
+    jti: String,
+    claims: Claims,
+    state: ApiState,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    let session = match refresh_sessions(&state, &claims.sub).await {
+        Ok(tokens) => tokens
+            .into_iter()
+            .find(|token| token.access_jti.as_deref() == Some(jti.as_str())),
+        Err(err) => {
+            tracing::error!(session = %jti, error = ?err, "failed to look up the session");
+            return Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to look up the session",
+            )));
+        }
+    };
+    let Some(session) = session else {
+        return Ok(warp::Reply::into_response(error_reply(
+            "session_not_found",
+            "no active session with this ID",
+            StatusCode::NOT_FOUND,
+        )));
+    };
+    // The access token goes first, so a failure leaves the session listed
+    // and the request can be retried.
+    let token = RevokedToken {
+        jti: jti.clone(),
+        expires_at: session.access_expires_at,
+    };
+    if let Err(err) = revoke_access_token(&state, token).await {
+        tracing::error!(session = %jti, error = ?err, "failed to revoke the session's token");
+        return Ok(warp::Reply::into_response(storage_error_reply(
+            &err,
+            "failed to revoke the session's token",
+        )));
+    }
+    if let Err(err) = state
+        .refresh_tokens
+        .revoke_refresh_family(&session.family_id)
+        .await
+    {
+        tracing::error!(
+            session = %jti,
+            error = ?err,
+            "failed to revoke the session's refresh tokens"
+        );
+        return Ok(warp::Reply::into_response(storage_error_reply(
+            &err,
+            "failed to revoke the session's refresh tokens",
+        )));
+    }
+    Ok(warp::Reply::into_response(StatusCode::NO_CONTENT))
+}
+
+/// `subject`'s sign-ins that can still be refreshed, oldest first.
+async fn refresh_sessions(
+    state: &ApiState,
+    subject: &str,
+) -> Result<Vec<RefreshTokenRecord>, DatabaseError> {
+    let now = state.determinism.now().unwrap_or_default();
+    state
+        .refresh_tokens
+        .list_refresh_sessions(subject, now)
+        .await
+}
+
+/// Reject an access token from now until it expires. The revocation is
+/// stored first, so a failure leaves the token working and the request can
+/// be retried; other instances pick it up at their next sync.
+async fn revoke_access_token(state: &ApiState, token: RevokedToken) -> Result<(), DatabaseError> {
+    state.refresh_tokens.revoke_access_token(&token).await?;
+    state.auth.revoke_id(&token.jti, token.expires_at);
+    Ok(())
+}
+
+/// Read back the stored revocations of unexpired tokens, including those
+/// made on other instances; returns how many there were.
+pub async fn sync_revocations(state: &ApiState) -> Result<usize, DatabaseError> {
+    let now = state.determinism.now().unwrap_or_default();
+    let revoked = state.refresh_tokens.load_revoked_tokens(now).await?;
+    let count = revoked.len();
+    state.auth.restore_revoked(
+        revoked
+            .into_iter()
+            .map(|token| (token.jti, token.expires_at)),
+    );
+    Ok(count)
+}
+
+/// Sync revocations every `interval`, dropping those of expired tokens.
+pub fn spawn_revocation_sync(state: ApiState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let now = state.determinism.now().unwrap_or_default();
+            if let Err(err) = state.refresh_tokens.purge_expired_revocations(now).await {
+                tracing::error!(error = ?err, "failed to purge expired token revocations");
+            }
+            if let Err(err) = sync_revocations(&state).await {
+                tracing::error!(error = ?err, "failed to read token revocations");
+            }
+        }
+    });
+}
@@ -0,0 +1,298 @@
+//! Settings that change without a restart.
+//!
+//! Rate limits, the fee schedule, trading halts and the log level are read
+//! with the rest of the configuration at start, and read again on SIGHUP or
+//! `POST /admin/config/reload`. A reload that fails to parse leaves the
+//! running settings as they were. The settings in force live in a watch
+//! channel on `ApiState`: order entry and the ledger read the latest value,
+//! the rate limiter is updated in place and the log filter follows its own
+//! subscription.
+
+use crate::{
+    admin_only,
+    auth::Claims,
+    config::{Config, ConfigError},
+    error_reply,
+    rate_limit::{RateLimitConfig, RouteClass},
+    rate_limited, ApiState, ErrorResponse,
+};
+use dex_core::types::TradingPair;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// Maker and taker fees, in basis points of a fill's notional.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FeeRates {
+    pub maker_bps: u32,
+    pub taker_bps: u32,
+}
+
+impl FeeRates {
+    /// The fee at `bps` on `notional`, rounded down.
+    pub fn fee(bps: u32, notional: u128) -> u128 {
+        notional * u128::from(bps) / 10_000
+    }
+}
+
+/// Fees charged on order book fills: one rate for every pair, with
+/// overrides for some.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeeSchedule {
+    pub default: FeeRates,
+    pub pairs: BTreeMap<TradingPair, FeeRates>,
+}
+
+impl FeeSchedule {
+    pub fn rates(&self, pair: &TradingPair) -> FeeRates {
+        self.pairs.get(pair).copied().unwrap_or(self.default)
+    }
+}
+
+/// The part of [`Config`] that can be reloaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub rate_limits: RateLimitConfig,
+    pub fees: FeeSchedule,
+    /// Pairs that accept no new orders.
+    pub trading_halts: BTreeSet<TradingPair>,
+    pub log_level: String,
+}
+
+impl Settings {
+    pub fn is_halted(&self, pair: &TradingPair) -> bool {
+        self.trading_halts.contains(pair)
+    }
+}
+
+impl From<&Config> for Settings {
+    fn from(config: &Config) -> Self {
+        Self {
+            rate_limits: config.rate_limits,
+            fees: config.fees.clone(),
+            trading_halts: config.trading_halts.clone(),
+            log_level: config.log_level.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = RateLimit)]
+pub struct BudgetResponse {
+    #[schema(schema_with = RouteClass::schema)]
+    pub class: &'static str,
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = Fees)]
+pub struct FeesResponse {
+    pub maker_bps: u32,
+    pub taker_bps: u32,
+    /// Overrides by pair.
+    pub pairs: BTreeMap<String, FeeRates>,
+}
+
+/// Body of `GET /admin/config` and `POST /admin/config/reload`.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = Settings)]
+pub struct SettingsResponse {
+    pub rate_limits: Vec<BudgetResponse>,
+    pub fees: FeesResponse,
+    /// Pairs that accept no new orders.
+    pub trading_halts: Vec<String>,
+    pub log_level: String,
+}
+
+impl From<&Settings> for SettingsResponse {
+    fn from(settings: &Settings) -> Self {
+        Self {
+            rate_limits: RouteClass::ALL
+                .iter()
+                .map(|&class| {
+                    let budget = settings.rate_limits.budget(class);
+                    BudgetResponse {
+                        class: class.as_str(),
+                        per_second: budget.per_second,
+                        burst: budget.burst,
+                    }
+                })
+                .collect(),
+            fees: FeesResponse {
+                maker_bps: settings.fees.default.maker_bps,
+                taker_bps: settings.fees.default.taker_bps,
+                pairs: settings
+                    .fees
+                    .pairs
+                    .iter()
+                    .map(|(pair, rates)| (pair.to_string(), *rates))
+                    .collect(),
+            },
+            trading_halts: settings
+                .trading_halts
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            log_level: settings.log_level.clone(),
+        }
+    }
+}
+
+/// Put `settings` in force; returns whether anything changed.
+pub fn apply(state: &ApiState, settings: Settings) -> bool {
+    state.rate_limiter.set_config(settings.rate_limits);
+    state.settings.send_if_modified(|current| {
+        if *current == settings {
+            return false;
+        }
+        let halted: Vec<String> = settings
+            .trading_halts
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        tracing::info!(
+            log_level = %settings.log_level,
+            trading_halts = ?halted,
+            "applied new settings"
+        );
+        *current = settings;
+        true
+    })
+}
+
+/// Read the settings again and put them in force.
+pub fn reload(state: &ApiState) -> Result<Settings, ConfigError> {
+    let settings = Config::reload_settings()?;
+    apply(state, settings.clone());
+    Ok(settings)
+}
+
+/// Reload on every SIGHUP.
+pub fn spawn_reload_on_hangup(state: ApiState) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(err) => {
+                tracing::error!(error = %err, "failed to listen for SIGHUP");
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            if let Err(err) = reload(&state) {
+                tracing::error!(error = %err, "kept the current settings");
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Runtime settings, e.g. POST /admin/config/reload
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let admin_config = warp::path("admin").and(warp::path("config"));
+
+    let current_settings = admin_config
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_settings)
+        .boxed();
+
+    let reload_settings = admin_config
+        .and(warp::path("reload"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state))
+        .and_then(handle_reload_settings)
+        .boxed();
+
+    current_settings.or(reload_settings)
+}
+
+/// The reloadable settings in force.
+#[utoipa::path(
+    get,
+    path = "/admin/config",
+    summary = "Rate limits, fees, trading halts and log level in force (administrators only)",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "The reloadable settings", body = SettingsResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_settings(
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let response = SettingsResponse::from(&*state.settings.borrow());
+    Ok(warp::reply::json(&response))
+}
+
+/// Read the reloadable settings again, as on SIGHUP.
+#[utoipa::path(
+    post,
+    path = "/admin/config/reload",
+    summary = "Read the reloadable settings again, as on SIGHUP (administrators only)",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "The settings now in force", body = SettingsResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 422, description = "Configuration invalid; the settings in force are kept", body = ErrorResponse),
+    ),
+)]
+async fn handle_reload_settings(
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(match reload(&state) {
+        Ok(reloaded) => {
+            tracing::info!(admin = %claims.sub, "reloaded settings");
+            warp::reply::with_status(
+                warp::reply::json(&SettingsResponse::from(&reloaded)),
+                StatusCode::OK,
+            )
+        }
+        Err(err) => error_reply(
+            "invalid_config",
+            err.to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pairs_override_the_default_rates() {
+        let pair: TradingPair = "ETH-USDC".parse().unwrap();
+        let schedule = FeeSchedule {
+            default: FeeRates {
+                maker_bps: 2,
+                taker_bps: 5,
+            },
+            pairs: BTreeMap::from([(
+                pair.clone(),
+                FeeRates {
+                    maker_bps: 0,
+                    taker_bps: 3,
+                },
+            )]),
+        };
+        assert_eq!(schedule.rates(&pair).taker_bps, 3);
+        let other: TradingPair = "BTC-USDC".parse().unwrap();
+        assert_eq!(schedule.rates(&other), schedule.default);
+        assert_eq!(FeeRates::fee(5, 1_999_999), 999);
+    }
+}
@@ -0,0 +1,139 @@
+//! Graceful shutdown.
+//!
+//! On SIGTERM or Ctrl-C the server drains instead of stopping dead. The
+//! coordinator is switched to draining, after which order entry is refused
+//! with `503 shutting_down`, the listener stops accepting connections and
+//! WebSocket sessions end with a close frame. Orders already being stored
+//! and sessions still closing are tracked as in flight, and the server waits
+//! for them (up to `SHUTDOWN_GRACE_SECONDS`) before flushing its buffers and
+//! checkpointing the order book.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{watch, Notify};
+
+/// Tells every part of the server when to start draining, and keeps count
+/// of the work that must finish before it exits.
+#[derive(Debug)]
+pub struct Shutdown {
+    draining: watch::Sender<bool>,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+/// Work the server waits for before exiting; released on drop.
+#[derive(Debug)]
+pub struct InFlight<'a>(&'a Shutdown);
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            draining: watch::Sender::new(false),
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Stop taking on new work. Idempotent.
+    pub fn begin(&self) {
+        self.draining.send_replace(true);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.draining.borrow()
+    }
+
+    /// Resolves once draining has begun.
+    pub async fn draining(&self) {
+        let mut draining = self.draining.subscribe();
+        // The sender lives as long as `self`, so this cannot fail.
+        let _ = draining.wait_for(|draining| *draining).await;
+    }
+
+    /// Register work the server must wait for; `None` once draining, when
+    /// no new work may start.
+    pub fn track(&self) -> Option<InFlight<'_>> {
+        // Counted before the check, so `drained` cannot miss work that
+        // started just before draining began.
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight(self);
+        (!self.is_draining()).then_some(guard)
+    }
+
+    /// How much tracked work is still running.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Resolves once no tracked work is running.
+    pub async fn drained(&self) {
+        loop {
+            let idle = self.idle.notified();
+            tokio::pin!(idle);
+            idle.as_mut().enable();
+            if self.in_flight() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        if self.0.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Resolves on Ctrl-C or, on Unix, SIGTERM.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => tracing::error!(error = %err, "failed to listen for SIGTERM"),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, time::Duration};
+
+    #[tokio::test]
+    async fn draining_refuses_new_work_and_waits_for_the_rest() {
+        let shutdown = Arc::new(Shutdown::default());
+        let order = shutdown.track().expect("accepting work");
+        assert_eq!(shutdown.in_flight(), 1);
+
+        shutdown.begin();
+        assert!(shutdown.is_draining());
+        assert!(shutdown.track().is_none());
+        assert_eq!(shutdown.in_flight(), 1);
+        shutdown.draining().await;
+
+        let waiter = tokio::spawn({
+            let shutdown = shutdown.clone();
+            async move { shutdown.drained().await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiter.is_finished());
+        drop(order);
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("drained once the order finished")
+            .unwrap();
+    }
+}
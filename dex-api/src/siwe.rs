@@ -0,0 +1,294 @@
+//! Sign-In with Ethereum (EIP-4361) messages.
+//!
+//! Wallet challenges are issued as SIWE messages, and clients built on SIWE
+//! tooling may send back the message their wallet signed instead of the
+//! issued text. [`SiweMessage::check`] then holds its fields to the issued
+//! challenge before the signature is verified.
+
+use chrono::{DateTime, SecondsFormat};
+use ethers_core::{types::Address, utils::to_checksum};
+use std::{fmt, str::FromStr};
+use thiserror::Error;
+
+const PREAMBLE: &str = " wants you to sign in with your Ethereum account:";
+pub const VERSION: &str = "1";
+/// How far a client clock may run ahead of the server's.
+pub const MAX_CLOCK_SKEW_SECONDS: u64 = 60;
+
+/// Why a SIWE message was refused.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SiweError {
+    #[error("malformed SIWE message: {0}")]
+    Malformed(&'static str),
+    #[error("SIWE {0} does not match the challenge")]
+    Mismatch(&'static str),
+    #[error("SIWE message is expired")]
+    Expired,
+    #[error("SIWE message is not valid yet")]
+    NotYetValid,
+}
+
+/// An EIP-4361 message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SiweMessage {
+    /// Host, with an optional scheme, requesting the sign-in.
+    pub domain: String,
+    /// EIP-55 checksummed address.
+    pub address: String,
+    pub statement: Option<String>,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    /// Unix seconds.
+    pub issued_at: u64,
+    pub expiration_time: Option<u64>,
+    pub not_before: Option<u64>,
+    pub request_id: Option<String>,
+    pub resources: Vec<String>,
+}
+
+impl SiweMessage {
+    /// Check a message a client signed against the challenge it answers.
+    pub fn check(&self, challenge: &SiweMessage, now: u64) -> Result<(), SiweError> {
+        let fields = [
+            ("domain", self.domain == challenge.domain),
+            ("address", self.address == challenge.address),
+            ("URI", self.uri == challenge.uri),
+            ("version", self.version == VERSION),
+            ("chain ID", self.chain_id == challenge.chain_id),
+            ("nonce", self.nonce == challenge.nonce),
+        ];
+        if let Some((field, _)) = fields.iter().find(|(_, matches)| !matches) {
+            return Err(SiweError::Mismatch(field));
+        }
+        let issued_after = challenge.issued_at.saturating_sub(MAX_CLOCK_SKEW_SECONDS);
+        if self.issued_at < issued_after || self.issued_at > now + MAX_CLOCK_SKEW_SECONDS {
+            return Err(SiweError::Mismatch("issued-at"));
+        }
+        if self.expiration_time.is_some_and(|expires| expires <= now) {
+            return Err(SiweError::Expired);
+        }
+        if self.not_before.is_some_and(|not_before| not_before > now) {
+            return Err(SiweError::NotYetValid);
+        }
+        Ok(())
+    }
+}
+
+/// EIP-55 form of a lowercase `0x` address.
+pub fn checksum_address(address: &str) -> Option<String> {
+    Address::from_str(address)
+        .ok()
+        .map(|address| to_checksum(&address, None))
+}
+
+/// RFC 3339 form of Unix seconds, as SIWE messages write times.
+pub(crate) fn format_time(timestamp: u64) -> String {
+    DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn parse_time(raw: &str) -> Result<u64, SiweError> {
+    DateTime::parse_from_rfc3339(raw)
+        .ok()
+        .and_then(|time| u64::try_from(time.timestamp()).ok())
+        .ok_or(SiweError::Malformed("timestamps must be RFC 3339"))
+}
+
+impl fmt::Display for SiweMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}{}", self.domain, PREAMBLE)?;
+        writeln!(f, "{}", self.address)?;
+        writeln!(f)?;
+        if let Some(statement) = &self.statement {
+            writeln!(f, "{}", statement)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "URI: {}", self.uri)?;
+        writeln!(f, "Version: {}", self.version)?;
+        writeln!(f, "Chain ID: {}", self.chain_id)?;
+        writeln!(f, "Nonce: {}", self.nonce)?;
+        write!(f, "Issued At: {}", format_time(self.issued_at))?;
+        if let Some(expiration_time) = self.expiration_time {
+            write!(f, "\nExpiration Time: {}", format_time(expiration_time))?;
+        }
+        if let Some(not_before) = self.not_before {
+            write!(f, "\nNot Before: {}", format_time(not_before))?;
+        }
+        if let Some(request_id) = &self.request_id {
+            write!(f, "\nRequest ID: {}", request_id)?;
+        }
+        if !self.resources.is_empty() {
+            write!(f, "\nResources:")?;
+            for resource in &self.resources {
+                write!(f, "\n- {}", resource)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SiweMessage {
+    type Err = SiweError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let mut lines = raw.split('\n').peekable();
+        let mut next = |what| lines.next().ok_or(SiweError::Malformed(what));
+
+        let domain = next("missing domain")?
+            .strip_suffix(PREAMBLE)
+            .ok_or(SiweError::Malformed("missing preamble"))?
+            .to_string();
+        let address = next("missing address")?.to_string();
+        if checksum_address(&address).as_deref() != Some(address.as_str()) {
+            return Err(SiweError::Malformed("address must be EIP-55 checksummed"));
+        }
+        if !next("missing blank line")?.is_empty() {
+            return Err(SiweError::Malformed(
+                "expected a blank line after the address",
+            ));
+        }
+        let statement = match next("missing URI")? {
+            "" => None,
+            statement => {
+                if !next("missing blank line")?.is_empty() {
+                    return Err(SiweError::Malformed(
+                        "expected a blank line after the statement",
+                    ));
+                }
+                Some(statement.to_string())
+            }
+        };
+        let mut field = |tag: &'static str| {
+            next(tag)?
+                .strip_prefix(tag)
+                .map(str::to_string)
+                .ok_or(SiweError::Malformed(tag))
+        };
+        let uri = field("URI: ")?;
+        let version = field("Version: ")?;
+        let chain_id = field("Chain ID: ")?
+            .parse()
+            .map_err(|_| SiweError::Malformed("chain ID must be a number"))?;
+        let nonce = field("Nonce: ")?;
+        if nonce.len() < 8 || !nonce.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(SiweError::Malformed(
+                "nonce must be at least 8 alphanumerics",
+            ));
+        }
+        let issued_at = parse_time(&field("Issued At: ")?)?;
+
+        let mut message = SiweMessage {
+            domain,
+            address,
+            statement,
+            uri,
+            version,
+            chain_id,
+            nonce,
+            issued_at,
+            expiration_time: None,
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        };
+        while let Some(line) = lines.next() {
+            if let Some(raw) = line.strip_prefix("Expiration Time: ") {
+                message.expiration_time = Some(parse_time(raw)?);
+            } else if let Some(raw) = line.strip_prefix("Not Before: ") {
+                message.not_before = Some(parse_time(raw)?);
+            } else if let Some(raw) = line.strip_prefix("Request ID: ") {
+                message.request_id = Some(raw.to_string());
+            } else if line == "Resources:" {
+                while let Some(resource) = lines.next_if(|line| line.starts_with("- ")) {
+                    message.resources.push(resource[2..].to_string());
+                }
+            } else {
+                return Err(SiweError::Malformed("unexpected line"));
+            }
+        }
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn challenge() -> SiweMessage {
+        SiweMessage {
+            domain: "dex.example".into(),
+            address: checksum_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap(),
+            statement: Some("Sign in to DEX-OS".into()),
+            uri: "https://dex.example".into(),
+            version: VERSION.into(),
+            chain_id: 1,
+            nonce: "abcdefgh1234".into(),
+            issued_at: NOW,
+            expiration_time: Some(NOW + 300),
+            not_before: None,
+            request_id: None,
+            resources: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn round_trips_the_eip_4361_format() {
+        let message = challenge();
+        let text = message.to_string();
+        assert!(text.starts_with(
+            "dex.example wants you to sign in with your Ethereum account:\n\
+             0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\n\nSign in to DEX-OS\n\n\
+             URI: https://dex.example\nVersion: 1\nChain ID: 1\n"
+        ));
+        assert!(text
+            .ends_with("Issued At: 2023-11-14T22:13:20Z\nExpiration Time: 2023-11-14T22:18:20Z"));
+        assert_eq!(text.parse::<SiweMessage>().unwrap(), message);
+
+        // Messages from other tools may omit the statement and add resources.
+        let mut other = message.clone();
+        other.statement = None;
+        other.request_id = Some("req-1".into());
+        other.resources = vec!["ipfs://bafy".into(), "https://dex.example/terms".into()];
+        assert_eq!(other.to_string().parse::<SiweMessage>().unwrap(), other);
+
+        let lowercase = text.replace(&message.address, &message.address.to_lowercase());
+        assert!(matches!(
+            lowercase.parse::<SiweMessage>(),
+            Err(SiweError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn holds_fields_to_the_challenge() {
+        let issued = challenge();
+        assert_eq!(issued.check(&issued, NOW + 10), Ok(()));
+
+        let mut signed = issued.clone();
+        signed.domain = "evil.example".into();
+        assert_eq!(
+            signed.check(&issued, NOW),
+            Err(SiweError::Mismatch("domain"))
+        );
+        let mut signed = issued.clone();
+        signed.chain_id = 10;
+        assert_eq!(
+            signed.check(&issued, NOW),
+            Err(SiweError::Mismatch("chain ID"))
+        );
+        let mut signed = issued.clone();
+        signed.issued_at = NOW + 3600;
+        assert_eq!(
+            signed.check(&issued, NOW),
+            Err(SiweError::Mismatch("issued-at"))
+        );
+        assert_eq!(issued.check(&issued, NOW + 300), Err(SiweError::Expired));
+        let mut signed = issued.clone();
+        signed.not_before = Some(NOW + 60);
+        assert_eq!(signed.check(&issued, NOW), Err(SiweError::NotYetValid));
+    }
+}
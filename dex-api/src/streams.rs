@@ -0,0 +1,755 @@
+//! WebSocket streams: the multiplexed `/ws` feed, public executions per
+//! market and the private order feed.
+
+use crate::{
+    amm_events::{AmmEvent, PoolDepth},
+    auth::{AuthRejection, Claims, Scope},
+    authenticated, chain,
+    config::WsHeartbeat,
+    determinism::Determinism,
+    ip_allowlist,
+    market_data::{clamp_depth_levels, depth_snapshot_for, ticker},
+    optional_claims,
+    order_events::UserEvent,
+    rate_limit::RouteClass,
+    rate_limited,
+    subscriptions::{Channel, ClientMessage, ServerMessage, MAX_SUBSCRIPTIONS},
+    trade_tape::MarketTrade,
+    validation, with_state,
+    ws_outbox::{slow_consumer_close_message, WsOutbox, WsWriter},
+    ApiState, ErrorResponse, ValidationRejection,
+};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use tokio::{
+    sync::broadcast,
+    time::{Instant, Interval},
+};
+use utoipa::IntoParams;
+use warp::{
+    ws::{Message, WebSocket, Ws},
+    Filter,
+};
+pub(crate) const STREAM_DEPTH_LEVELS: usize = 20;
+
+/// Token for the `/ws` handshake. Browsers cannot set headers on a
+/// WebSocket upgrade, so it may be passed as `?token=` instead.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct StreamAuthQuery {
+    /// Access token, for clients that cannot set the Authorization header
+    token: Option<String>,
+}
+
+/// Server-initiated keepalive for a WebSocket session.
+struct Heartbeat {
+    idle_timeout: std::time::Duration,
+    ticker: Interval,
+    last_seen: Instant,
+}
+
+enum Beat {
+    Ping,
+    /// Nothing, not even a pong, arrived within the idle timeout.
+    Idle,
+}
+
+impl Heartbeat {
+    fn new(config: WsHeartbeat) -> Self {
+        let now = Instant::now();
+        Self {
+            idle_timeout: config.idle_timeout,
+            ticker: tokio::time::interval_at(now + config.ping_interval, config.ping_interval),
+            last_seen: now,
+        }
+    }
+
+    /// Record a frame from the client.
+    fn seen(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    async fn tick(&mut self) -> Beat {
+        self.ticker.tick().await;
+        if self.last_seen.elapsed() >= self.idle_timeout {
+            Beat::Idle
+        } else {
+            Beat::Ping
+        }
+    }
+}
+
+/// Per-connection state of the multiplexed stream.
+struct StreamSession {
+    /// Set by the handshake or an `auth` message; private channels need it.
+    claims: Option<Claims>,
+    /// Subscribed channels, with the depth levels requested for depth channels.
+    channels: HashMap<Channel, usize>,
+    /// Clock token expiry is checked against.
+    determinism: Arc<Determinism>,
+    /// Address of the client, checked against allowlists on `auth`.
+    peer: Option<IpAddr>,
+}
+
+impl StreamSession {
+    /// Apply a client message, returning the acknowledgement and, for market
+    /// data channels, an initial snapshot.
+    async fn handle(&mut self, state: &ApiState, raw: &str) -> Vec<ServerMessage> {
+        let request = match serde_json::from_str::<ClientMessage>(raw) {
+            Ok(request) => request,
+            Err(err) => return vec![ServerMessage::error("invalid_message", err.to_string())],
+        };
+        match request {
+            ClientMessage::Subscribe { channel, levels } => {
+                let channel = match channel.parse::<Channel>() {
+                    Ok(channel) => channel,
+                    Err(err) => {
+                        return vec![ServerMessage::error("invalid_channel", err.to_string())]
+                    }
+                };
+                if channel.is_private() && self.trader_id().is_none() {
+                    return vec![ServerMessage::error(
+                        "unauthorized",
+                        format!("{} requires an authenticated connection", channel),
+                    )];
+                }
+                if !self.channels.contains_key(&channel) && self.channels.len() >= MAX_SUBSCRIPTIONS
+                {
+                    return vec![ServerMessage::error(
+                        "too_many_subscriptions",
+                        format!("at most {} channels per connection", MAX_SUBSCRIPTIONS),
+                    )];
+                }
+                let levels = levels
+                    .map(|levels| clamp_depth_levels(Some(levels)))
+                    .unwrap_or(STREAM_DEPTH_LEVELS);
+                self.channels.insert(channel.clone(), levels);
+                let mut replies = vec![ServerMessage::Subscribed {
+                    channel: channel.to_string(),
+                }];
+                replies.extend(market_update(state, &channel, levels).await);
+                replies
+            }
+            ClientMessage::Unsubscribe { channel } => match channel.parse::<Channel>() {
+                Ok(parsed) if self.channels.remove(&parsed).is_some() => {
+                    vec![ServerMessage::Unsubscribed {
+                        channel: parsed.to_string(),
+                    }]
+                }
+                _ => vec![ServerMessage::error(
+                    "not_subscribed",
+                    format!("not subscribed to {}", channel),
+                )],
+            },
+            ClientMessage::Auth { token } => self.authenticate(state, &token),
+        }
+    }
+
+    /// The authenticated trader, while their token is still valid.
+    fn trader_id(&self) -> Option<&str> {
+        let now = self.determinism.now().unwrap_or_default();
+        self.claims
+            .as_ref()
+            .filter(|claims| claims.exp as u64 > now && claims.has_scope(Scope::Read))
+            .map(|claims| claims.sub.as_str())
+    }
+
+    /// Authenticate the connection, or refresh its token before it expires.
+    fn authenticate(&mut self, state: &ApiState, token: &str) -> Vec<ServerMessage> {
+        let claims = match state.auth.verify_token(token) {
+            Ok(claims) => claims,
+            Err(err) => return vec![ServerMessage::error("unauthorized", err.to_string())],
+        };
+        if ip_allowlist::check(state, &claims.sub, self.peer).is_err() {
+            return vec![ServerMessage::error(
+                "ip_not_allowed",
+                "credentials may not be used from this address",
+            )];
+        }
+        // A different trader must not inherit the previous one's private streams.
+        let mut replies = match &self.claims {
+            Some(current) if current.sub != claims.sub => self.drop_private(),
+            _ => Vec::new(),
+        };
+        replies.push(ServerMessage::Authenticated {
+            trader_id: claims.sub.clone(),
+            expires_at: claims.exp as u64,
+        });
+        self.claims = Some(claims);
+        replies
+    }
+
+    /// Close private channels once the token lapses; the client can resume
+    /// them by sending a fresh token.
+    fn check_expiry(&mut self) -> Vec<ServerMessage> {
+        if self.claims.is_none() || self.trader_id().is_some() {
+            return Vec::new();
+        }
+        self.claims = None;
+        let mut replies = vec![ServerMessage::error(
+            "token_expired",
+            "authentication expired; send a new token with an auth message",
+        )];
+        replies.extend(self.drop_private());
+        replies
+    }
+
+    fn drop_private(&mut self) -> Vec<ServerMessage> {
+        let private: Vec<Channel> = self
+            .channels
+            .keys()
+            .filter(|channel| channel.is_private())
+            .cloned()
+            .collect();
+        private
+            .into_iter()
+            .map(|channel| {
+                self.channels.remove(&channel);
+                ServerMessage::Unsubscribed {
+                    channel: channel.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Refresh every snapshot subscription `matches` selects.
+    async fn market_updates(
+        &self,
+        state: &ApiState,
+        matches: impl Fn(&Channel) -> bool,
+    ) -> Vec<ServerMessage> {
+        let mut updates = Vec::new();
+        for (channel, &levels) in &self.channels {
+            if matches(channel) {
+                updates.extend(market_update(state, channel, levels).await);
+            }
+        }
+        updates
+    }
+
+    fn trade_update(&self, trade: &MarketTrade) -> Option<ServerMessage> {
+        self.channels
+            .keys()
+            .find(|channel| matches!(channel, Channel::Trades(pair) if pair.to_string() == trade.pair))
+            .and_then(|channel| ServerMessage::update(channel, trade))
+    }
+
+    fn amm_update(&self, event: &AmmEvent) -> Option<ServerMessage> {
+        let (channel, data) = match event {
+            AmmEvent::Swap(swap) => (
+                self.channels
+                    .keys()
+                    .find(|channel| matches!(channel, Channel::Swaps(pair) if pair.to_string() == swap.pool)),
+                serde_json::to_value(swap),
+            ),
+            AmmEvent::Depth(depth) => (
+                self.channels
+                    .keys()
+                    .find(|channel| matches!(channel, Channel::Pool(pair) if pair.to_string() == depth.pool)),
+                serde_json::to_value(depth),
+            ),
+        };
+        channel
+            .zip(data.ok())
+            .and_then(|(channel, data)| ServerMessage::update(channel, data))
+    }
+
+    fn block_update(&self, block: &chain::BlockResponse) -> Option<ServerMessage> {
+        if self.channels.contains_key(&Channel::Blocks) {
+            ServerMessage::update(&Channel::Blocks, block)
+        } else {
+            None
+        }
+    }
+
+    fn order_update(&self, event: &UserEvent) -> Option<ServerMessage> {
+        let own = self.trader_id() == Some(event.trader_id.as_str());
+        if own && self.channels.contains_key(&Channel::Orders) {
+            ServerMessage::update(&Channel::Orders, &event.event)
+        } else {
+            None
+        }
+    }
+
+    /// Tell subscribers of `matches` channels that events were dropped.
+    fn lagged(&self, skipped: u64, matches: impl Fn(&Channel) -> bool) -> Vec<ServerMessage> {
+        self.channels
+            .keys()
+            .filter(|channel| matches(channel))
+            .map(|channel| ServerMessage::Lagged {
+                channel: channel.to_string(),
+                skipped,
+            })
+            .collect()
+    }
+}
+
+/// WebSocket endpoints, e.g. /ws/trades/ETH-USDC
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Multiplexed market data and private channels over one connection
+    let stream_ws = warp::path("ws")
+        .and(warp::path::end())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(stream_claims(state.clone()))
+        .and(ip_allowlist::client_ip(state.clone()))
+        .and(with_state(state.clone()))
+        .and(warp::ws())
+        .and_then(handle_stream_ws)
+        .boxed();
+
+    // Public executions for one market, e.g. /ws/trades/ETH-USDC
+    let trades_ws = warp::path("ws")
+        .and(warp::path("trades"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and(warp::ws())
+        .and_then(handle_trades_ws)
+        .boxed();
+
+    // Private order and fill events for the authenticated trader
+    let orders_ws = warp::path("ws")
+        .and(warp::path("orders"))
+        .and(warp::path::end())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and(warp::ws())
+        .and_then(handle_orders_ws)
+        .boxed();
+
+    stream_ws.or(trades_ws).or(orders_ws)
+}
+
+/// Optional credentials for `/ws`, from the `Authorization` header or the
+/// `token` query parameter. A token that is present but invalid is rejected.
+fn stream_claims(
+    state: ApiState,
+) -> impl Filter<Extract = (Option<Claims>,), Error = warp::Rejection> + Clone {
+    optional_claims(state.clone())
+        .and(warp::query::<StreamAuthQuery>())
+        .and(ip_allowlist::client_ip(state.clone()))
+        .and(with_state(state))
+        .and_then(
+            |claims: Option<Claims>,
+             query: StreamAuthQuery,
+             peer: Option<IpAddr>,
+             state: ApiState| async move {
+                match (claims, query.token) {
+                    (Some(claims), _) => Ok::<_, warp::Rejection>(Some(claims)),
+                    (None, Some(token)) => {
+                        let claims = state
+                            .auth
+                            .verify_token(&token)
+                            .map_err(|err| warp::reject::custom(AuthRejection(err)))?;
+                        ip_allowlist::check(&state, &claims.sub, peer)
+                            .map_err(warp::reject::custom)?;
+                        Ok(Some(claims))
+                    }
+                    (None, None) => Ok(None),
+                }
+            },
+        )
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws",
+    summary = "Multiplexed market data and, with a token, private channels",
+    params(StreamAuthQuery),
+    security((), ("bearerAuth" = [])),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "Not a WebSocket upgrade request", body = ErrorResponse),
+        (status = 401, description = "Invalid token", body = ErrorResponse),
+    ),
+)]
+async fn handle_stream_ws(
+    claims: Option<Claims>,
+    peer: Option<IpAddr>,
+    state: ApiState,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(ws.on_upgrade(move |socket| stream_ws_session(socket, state, claims, peer)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws/trades/{pair}",
+    summary = "Public executions for one market",
+    params(("pair" = String, Path, description = "Market as BASE-QUOTE, e.g. ETH-USDC", example = "ETH-USDC")),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "Invalid pair, or not a WebSocket upgrade request", body = ErrorResponse),
+    ),
+)]
+async fn handle_trades_ws(
+    raw_pair: String,
+    state: ApiState,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let pair = validation::parse_pair(&raw_pair)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    Ok(ws.on_upgrade(move |socket| trades_ws_session(socket, state, pair.to_string())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ws/orders",
+    summary = "Order and fill events for the authenticated trader",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 101, description = "Switched to the WebSocket protocol"),
+        (status = 400, description = "Not a WebSocket upgrade request", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+    ),
+)]
+async fn handle_orders_ws(
+    claims: Claims,
+    state: ApiState,
+    ws: Ws,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(ws.on_upgrade(move |socket| orders_ws_session(socket, state, claims.sub)))
+}
+
+fn ping_message() -> Message {
+    Message::ping(Vec::new())
+}
+
+fn idle_close_message() -> Message {
+    // 1001 "going away": the server is dropping an unresponsive peer.
+    Message::close_with(1001u16, "idle timeout")
+}
+
+fn shutdown_close_message() -> Message {
+    // 1001 "going away": the server is shutting down.
+    Message::close_with(1001u16, "server shutting down")
+}
+
+/// Current snapshot for a depth, ticker or pool channel; `None` for event
+/// channels.
+async fn market_update(
+    state: &ApiState,
+    channel: &Channel,
+    levels: usize,
+) -> Option<ServerMessage> {
+    let now = state.determinism.now().unwrap_or_default();
+    match channel {
+        Channel::Depth(pair) => {
+            let orderbook = state.orderbook.read().await;
+            ServerMessage::update(
+                channel,
+                depth_snapshot_for(&orderbook, pair.as_ref(), levels, now),
+            )
+        }
+        Channel::Ticker(pair) => {
+            let ticker = {
+                let orderbook = state.orderbook.read().await;
+                let tape = state.trade_tape.read().await;
+                ticker(&orderbook, &tape, pair, now)
+            };
+            ServerMessage::update(channel, ticker)
+        }
+        Channel::Pool(pair) => {
+            let pools = state.amm.read().await;
+            let depth = pools
+                .get(pair)
+                .map(|pool| PoolDepth::of(pool, pools.sequence(), now))?;
+            ServerMessage::update(channel, depth)
+        }
+        Channel::Trades(_) | Channel::Orders | Channel::Swaps(_) | Channel::Blocks => None,
+    }
+}
+
+async fn stream_ws_session(
+    socket: WebSocket,
+    state: ApiState,
+    claims: Option<Claims>,
+    peer: Option<IpAddr>,
+) {
+    let (sender, mut receiver) = socket.split();
+    let outbox = Arc::new(WsOutbox::new(state.config.ws_backpressure));
+    let mut writer = WsWriter::spawn(outbox.clone(), sender);
+    let mut market_rx = state.market_tx.subscribe();
+    let mut trade_rx = state.trade_tx.subscribe();
+    let mut user_rx = state.user_tx.subscribe();
+    let mut amm_rx = state.amm_tx.subscribe();
+    let mut block_rx = state.block_tx.subscribe();
+    let mut heartbeat = Heartbeat::new(state.config.ws_heartbeat);
+    let mut session = StreamSession {
+        claims,
+        channels: HashMap::new(),
+        determinism: state.determinism.clone(),
+        peer,
+    };
+    let _open = state.shutdown.track();
+
+    'session: loop {
+        let outgoing = tokio::select! {
+            _ = state.shutdown.draining() => {
+                outbox.close(shutdown_close_message());
+                break;
+            }
+            // The client is gone.
+            _ = writer.stopped() => break,
+            msg = receiver.next() => match msg {
+                Some(Ok(msg)) if msg.is_close() => break,
+                Some(Ok(msg)) => {
+                    heartbeat.seen();
+                    match msg.to_str() {
+                        Ok(text) => session.handle(&state, text).await,
+                        // Pongs and binary frames carry no requests
+                        Err(()) => continue,
+                    }
+                }
+                _ => break,
+            },
+            beat = heartbeat.tick() => match beat {
+                Beat::Ping => {
+                    outbox.control(ping_message());
+                    session.check_expiry()
+                }
+                Beat::Idle => {
+                    outbox.close(idle_close_message());
+                    break;
+                }
+            },
+            update = market_rx.recv() => match update {
+                // Depth frames are snapshots, so a missed one needs no notice.
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {
+                    session.market_updates(&state, |channel| !channel.is_amm()).await
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            trade = trade_rx.recv() => match trade {
+                Ok(trade) => session.trade_update(&trade).into_iter().collect(),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    session.lagged(skipped, |channel| matches!(channel, Channel::Trades(_)))
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            event = user_rx.recv() => match event {
+                Ok(event) => session.order_update(&event).into_iter().collect(),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    session.lagged(skipped, Channel::is_private)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            event = amm_rx.recv() => match event {
+                Ok(event) => session.amm_update(&event).into_iter().collect(),
+                // Missed swaps are reported; pool frames are resent whole.
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    let mut replies =
+                        session.lagged(skipped, |channel| matches!(channel, Channel::Swaps(_)));
+                    replies.extend(
+                        session
+                            .market_updates(&state, |channel| matches!(channel, Channel::Pool(_)))
+                            .await,
+                    );
+                    replies
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            block = block_rx.recv() => match block {
+                Ok(block) => session.block_update(&block).into_iter().collect(),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    session.lagged(skipped, |channel| *channel == Channel::Blocks)
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
+
+        for message in outgoing {
+            if matches!(message, ServerMessage::Update { .. }) && state.chaos.drop_ws_frame() {
+                continue;
+            }
+            let text = match serde_json::to_string(&message) {
+                Ok(text) => text,
+                Err(_) => continue,
+            };
+            if let Some(claims) = &session.claims {
+                let now = state.determinism.now().unwrap_or_default();
+                state.usage.ws_sent(&claims.sub, text.len(), now);
+            }
+            let channel = match &message {
+                ServerMessage::Update { channel, .. } => channel.parse::<Channel>().ok(),
+                _ => None,
+            };
+            match channel {
+                Some(channel) if channel.is_snapshot() => {
+                    outbox.snapshot(&channel.to_string(), text)
+                }
+                Some(channel) => {
+                    if outbox.event(&channel.to_string(), text).is_err() {
+                        outbox.close(slow_consumer_close_message());
+                        break 'session;
+                    }
+                }
+                // Acks, errors and notices answer the client or warn it.
+                None => outbox.control(Message::text(text)),
+            }
+        }
+    }
+    writer.finish().await;
+}
+
+async fn trades_ws_session(socket: WebSocket, state: ApiState, pair: String) {
+    let (sender, mut receiver) = socket.split();
+    let outbox = Arc::new(WsOutbox::new(state.config.ws_backpressure));
+    let mut writer = WsWriter::spawn(outbox.clone(), sender);
+    let channel = format!("trades:{pair}");
+    let mut subscriber = state.trade_tx.subscribe();
+    let mut heartbeat = Heartbeat::new(state.config.ws_heartbeat);
+    let _open = state.shutdown.track();
+
+    loop {
+        let trade = tokio::select! {
+            _ = state.shutdown.draining() => {
+                outbox.close(shutdown_close_message());
+                break;
+            }
+            _ = writer.stopped() => break,
+            // Client frames are only pongs and keepalives; watch for disconnects.
+            msg = receiver.next() => match msg {
+                Some(Ok(msg)) if !msg.is_close() => {
+                    heartbeat.seen();
+                    continue;
+                }
+                _ => break,
+            },
+            beat = heartbeat.tick() => match beat {
+                Beat::Ping => {
+                    outbox.control(ping_message());
+                    continue;
+                }
+                Beat::Idle => {
+                    outbox.close(idle_close_message());
+                    break;
+                }
+            },
+            trade = subscriber.recv() => trade,
+        };
+        match trade {
+            Ok(trade) if trade.pair == pair => {
+                if state.chaos.drop_ws_frame() {
+                    continue;
+                }
+                let text = match serde_json::to_string(&trade) {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                };
+                if outbox.event(&channel, text).is_err() {
+                    outbox.close(slow_consumer_close_message());
+                    break;
+                }
+            }
+            Ok(_) => continue,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                // Missed trades can be backfilled from /markets/{pair}/trades.
+                let notice = serde_json::json!({ "type": "lagged", "skipped": skipped });
+                outbox.control(Message::text(notice.to_string()));
+            }
+            Err(_) => break,
+        }
+    }
+    writer.finish().await;
+}
+
+async fn orders_ws_session(socket: WebSocket, state: ApiState, trader_id: String) {
+    let (sender, mut receiver) = socket.split();
+    let outbox = Arc::new(WsOutbox::new(state.config.ws_backpressure));
+    let mut writer = WsWriter::spawn(outbox.clone(), sender);
+    let mut subscriber = state.user_tx.subscribe();
+    let mut heartbeat = Heartbeat::new(state.config.ws_heartbeat);
+    let _open = state.shutdown.track();
+
+    loop {
+        let update = tokio::select! {
+            _ = state.shutdown.draining() => {
+                outbox.close(shutdown_close_message());
+                break;
+            }
+            _ = writer.stopped() => break,
+            // Client frames are only pongs and keepalives; watch for disconnects.
+            msg = receiver.next() => match msg {
+                Some(Ok(msg)) if !msg.is_close() => {
+                    heartbeat.seen();
+                    continue;
+                }
+                _ => break,
+            },
+            beat = heartbeat.tick() => match beat {
+                Beat::Ping => {
+                    outbox.control(ping_message());
+                    continue;
+                }
+                Beat::Idle => {
+                    outbox.close(idle_close_message());
+                    break;
+                }
+            },
+            update = subscriber.recv() => update,
+        };
+        match update {
+            Ok(update) if update.trader_id == trader_id => {
+                if state.chaos.drop_ws_frame() {
+                    continue;
+                }
+                let text = match serde_json::to_string(&update.event) {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                };
+                let now = state.determinism.now().unwrap_or_default();
+                state.usage.ws_sent(&trader_id, text.len(), now);
+                if outbox.event("orders", text).is_err() {
+                    outbox.close(slow_consumer_close_message());
+                    break;
+                }
+            }
+            Ok(_) => continue,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                // Private events are not replayable; tell the client to resync over REST.
+                let notice = serde_json::json!({ "type": "lagged", "skipped": skipped });
+                outbox.control(Message::text(notice.to_string()));
+            }
+            Err(_) => break,
+        }
+    }
+    writer.finish().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_stream_auth_closes_private_channels() {
+        let expired = Claims {
+            sub: "alice".into(),
+            exp: 1,
+            aud: None,
+            iss: None,
+            iat: None,
+            jti: None,
+            scope: Some("read".into()),
+            role: None,
+        };
+        let mut session = StreamSession {
+            claims: Some(expired),
+            channels: [(Channel::Orders, 0), (Channel::Depth(None), 10)].into(),
+            determinism: Arc::default(),
+            peer: None,
+        };
+        assert!(session.trader_id().is_none());
+
+        let replies = serde_json::to_value(session.check_expiry()).unwrap();
+        assert_eq!(replies[0]["code"], "token_expired");
+        assert_eq!(replies[1]["channel"], "orders");
+        assert!(session.claims.is_none());
+        assert_eq!(session.channels.len(), 1);
+        assert!(session.check_expiry().is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+//! Message protocol for the multiplexed `/ws` stream.
+//!
+//! Clients send `{"op":"subscribe","channel":"depth:ETH-USDC"}` and
+//! `{"op":"unsubscribe",...}` messages; the server answers with acks and
+//! pushes `update` frames tagged with the channel they belong to. A JWT can
+//! be sent (and later refreshed) with `{"op":"auth","token":"..."}` to unlock
+//! private channels on a connection opened without one.
+
+use crate::validation::{self, ValidationError};
+use dex_core::types::TradingPair;
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// Most channels a single connection may follow at once.
+pub const MAX_SUBSCRIPTIONS: usize = 32;
+
+/// A stream a client can subscribe to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// Aggregated depth, for the whole book (`depth`) or one pair (`depth:ETH-USDC`).
+    Depth(Option<TradingPair>),
+    /// Public executions for one pair.
+    Trades(TradingPair),
+    /// Best bid/ask and last trade for one pair.
+    Ticker(TradingPair),
+    /// The authenticated trader's order and fill events.
+    Orders,
+    /// Swaps through one AMM pool.
+    Swaps(TradingPair),
+    /// Reserves and virtual depth of one AMM pool.
+    Pool(TradingPair),
+    /// Blocks of the internal chain as each shard finalizes them.
+    Blocks,
+}
+
+impl Channel {
+    /// Whether the channel carries the subscriber's private data.
+    pub fn is_private(&self) -> bool {
+        matches!(self, Channel::Orders)
+    }
+
+    /// Whether each update replaces the last, so only the latest is worth
+    /// sending.
+    pub fn is_snapshot(&self) -> bool {
+        matches!(
+            self,
+            Channel::Depth(_) | Channel::Ticker(_) | Channel::Pool(_)
+        )
+    }
+
+    /// Whether the channel reports on the AMM rather than the order book.
+    pub fn is_amm(&self) -> bool {
+        matches!(self, Channel::Swaps(_) | Channel::Pool(_))
+    }
+}
+
+impl FromStr for Channel {
+    type Err = ValidationError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let (name, pair) = match raw.split_once(':') {
+            Some((name, pair)) => (name, Some(validation::parse_pair(pair)?)),
+            None => (raw, None),
+        };
+        match (name, pair) {
+            ("depth", pair) => Ok(Channel::Depth(pair)),
+            ("trades", Some(pair)) => Ok(Channel::Trades(pair)),
+            ("ticker", Some(pair)) => Ok(Channel::Ticker(pair)),
+            ("orders", None) => Ok(Channel::Orders),
+            ("swaps", Some(pair)) => Ok(Channel::Swaps(pair)),
+            ("pool", Some(pair)) => Ok(Channel::Pool(pair)),
+            ("blocks", None) => Ok(Channel::Blocks),
+            _ => Err(ValidationError::InvalidChannel),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Depth(None) => f.write_str("depth"),
+            Channel::Depth(Some(pair)) => write!(f, "depth:{}", pair),
+            Channel::Trades(pair) => write!(f, "trades:{}", pair),
+            Channel::Ticker(pair) => write!(f, "ticker:{}", pair),
+            Channel::Orders => f.write_str("orders"),
+            Channel::Swaps(pair) => write!(f, "swaps:{}", pair),
+            Channel::Pool(pair) => write!(f, "pool:{}", pair),
+            Channel::Blocks => f.write_str("blocks"),
+        }
+    }
+}
+
+/// Requests sent by the client.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe {
+        channel: String,
+        /// Depth levels per side; only used by depth channels.
+        #[serde(default)]
+        levels: Option<usize>,
+    },
+    Unsubscribe {
+        channel: String,
+    },
+    Auth {
+        token: String,
+    },
+}
+
+/// Frames sent by the server.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Subscribed {
+        channel: String,
+    },
+    Unsubscribed {
+        channel: String,
+    },
+    Authenticated {
+        trader_id: String,
+        expires_at: u64,
+    },
+    Update {
+        channel: String,
+        data: serde_json::Value,
+    },
+    /// Events on the channel were dropped; resync over REST.
+    Lagged {
+        channel: String,
+        skipped: u64,
+    },
+    Error {
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn update(channel: &Channel, data: impl Serialize) -> Option<Self> {
+        serde_json::to_value(data)
+            .ok()
+            .map(|data| ServerMessage::Update {
+                channel: channel.to_string(),
+                data,
+            })
+    }
+
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_formats_channels() {
+        for raw in [
+            "depth",
+            "depth:ETH-USDC",
+            "trades:ETH-USDC",
+            "ticker:BTC-USDC",
+            "orders",
+            "swaps:ETH-USDC",
+            "pool:ETH-USDC",
+            "blocks",
+        ] {
+            let channel: Channel = raw.parse().unwrap();
+            assert_eq!(channel.to_string(), raw);
+        }
+        assert!("orders".parse::<Channel>().unwrap().is_private());
+        assert!("pool:ETH-USDC".parse::<Channel>().unwrap().is_amm());
+        assert!(!"depth:ETH-USDC".parse::<Channel>().unwrap().is_amm());
+        assert_eq!(
+            "pool".parse::<Channel>(),
+            Err(ValidationError::InvalidChannel)
+        );
+        assert_eq!(
+            "trades".parse::<Channel>(),
+            Err(ValidationError::InvalidChannel)
+        );
+        assert_eq!(
+            "candles:ETH-USDC".parse::<Channel>(),
+            Err(ValidationError::InvalidChannel)
+        );
+        assert_eq!(
+            "depth:ETH".parse::<Channel>(),
+            Err(ValidationError::InvalidPair)
+        );
+    }
+
+    #[test]
+    fn decodes_client_messages() {
+        let msg: ClientMessage =
+            serde_json::from_str(r#"{"op":"subscribe","channel":"depth","levels":5}"#).unwrap();
+        assert!(matches!(
+            msg,
+            ClientMessage::Subscribe { ref channel, levels: Some(5) } if channel == "depth"
+        ));
+        let msg: ClientMessage = serde_json::from_str(r#"{"op":"auth","token":"abc"}"#).unwrap();
+        assert!(matches!(msg, ClientMessage::Auth { ref token } if token == "abc"));
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"op":"publish"}"#).is_err());
+    }
+}
@@ -0,0 +1,257 @@
+//! Structured logs and distributed tracing over OpenTelemetry.
+//!
+//! Requests, order matching and storage run inside `tracing` spans: every
+//! HTTP request opens a `request` span, continuing the caller's trace when it
+//! sends a W3C `traceparent` header; order entry opens `submit_order`, with
+//! `match` around the book; and every statement runs in a `db` span opened
+//! by `dex-db`. With `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are batched
+//! and exported to that collector over OTLP/HTTP, so an operator can follow
+//! an order from ingress through matching to persistence.
+//!
+//! Log lines are `tracing` events written to stdout, as JSON by default, at
+//! the levels `LOG_LEVEL` allows. Each line carries the fields of the spans
+//! it was logged in, so everything logged while handling a request carries
+//! its `request_id`: the caller's `x-request-id`, or a fresh one, returned
+//! in the response either way. Failures are logged with the error's
+//! `Display` under `error`; `DatabaseError` and `OrderBookError` are logged
+//! with their `Debug` form instead, which keeps the variant and, for the
+//! database, the driver's own details. The filter follows the log level of
+//! the settings in force, so a reload changes it without a restart.
+
+use crate::{settings::Settings, Config};
+use opentelemetry::{
+    propagation::{Extractor, TextMapPropagator},
+    trace::TracerProvider as _,
+};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider},
+    Resource,
+};
+use std::{convert::Infallible, str::FromStr};
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::{field, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
+use warp::{http::HeaderMap, Filter};
+
+/// Path OTLP/HTTP collectors accept spans on.
+const TRACES_PATH: &str = "/v1/traces";
+
+/// Header carrying the ID a request is logged under.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest caller-chosen request ID that is kept.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// How log lines are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// One JSON object per line, listing the spans it was logged in.
+    Json,
+    /// Plain text, for reading in a terminal.
+    Text,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "json" => Ok(LogFormat::Json),
+            "text" => Ok(LogFormat::Text),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TelemetryError {
+    #[error("failed to build the OTLP exporter: {0}")]
+    Exporter(#[from] ExporterBuildError),
+    #[error("failed to install the tracing subscriber: {0}")]
+    Subscriber(#[from] tracing_subscriber::util::TryInitError),
+}
+
+/// The installed subscriber; shut it down on exit to flush pending spans.
+pub struct Telemetry {
+    provider: Option<SdkTracerProvider>,
+    filter: reload::Handle<EnvFilter, Registry>,
+}
+
+impl Telemetry {
+    /// Swap the filter whenever the log level in `settings` changes.
+    pub fn follow_log_level(&self, mut settings: watch::Receiver<Settings>) {
+        let filter = self.filter.clone();
+        tokio::spawn(async move {
+            let mut current = settings.borrow_and_update().log_level.clone();
+            while settings.changed().await.is_ok() {
+                let level = settings.borrow_and_update().log_level.clone();
+                if level == current {
+                    continue;
+                }
+                // Config parsing already refused filters that do not parse.
+                let Ok(new_filter) = EnvFilter::try_new(&level) else {
+                    continue;
+                };
+                match filter.reload(new_filter) {
+                    Ok(()) => {
+                        tracing::info!(log_level = %level, "changed log level");
+                        current = level;
+                    }
+                    Err(err) => tracing::error!(error = %err, "failed to change log level"),
+                }
+            }
+        });
+    }
+
+    pub fn shutdown(self) {
+        if let Some(provider) = self.provider {
+            if let Err(err) = provider.shutdown() {
+                tracing::error!(error = %err, "failed to flush traces");
+            }
+        }
+    }
+}
+
+/// Where spans are sent for a collector at `endpoint`, which may name the
+/// traces path itself or just the collector.
+pub fn traces_url(endpoint: &str) -> String {
+    if endpoint.ends_with(TRACES_PATH) {
+        endpoint.to_string()
+    } else {
+        format!("{}{}", endpoint, TRACES_PATH)
+    }
+}
+
+/// Install the log writer, and the OTLP exporter when
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` is set. `LOG_LEVEL` filters both.
+pub fn init(config: &Config) -> Result<Telemetry, TelemetryError> {
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .with_endpoint(traces_url(endpoint))
+                .build()?;
+            let provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                    config.trace_sample_ratio,
+                ))))
+                .with_resource(
+                    Resource::builder()
+                        .with_service_name(config.otel_service_name.clone())
+                        .build(),
+                )
+                .build();
+            Some(provider)
+        }
+        None => None,
+    };
+    let logs = match config.log_format {
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+        LogFormat::Text => fmt::layer().with_ansi(false).boxed(),
+    };
+    let traces = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("dex-api")));
+    // Config parsing already refused filters that do not parse.
+    let filter = EnvFilter::try_new(&config.log_level).unwrap_or_else(|_| EnvFilter::new("info"));
+    let (filter, handle) = reload::Layer::new(filter);
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(logs)
+        .with(traces)
+        .try_init()?;
+    Ok(Telemetry {
+        provider,
+        filter: handle,
+    })
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|name| name.as_str()).collect()
+    }
+}
+
+/// The span a request is handled in, a child of the caller's span when the
+/// request carries a `traceparent` header.
+pub fn request_span(info: warp::trace::Info<'_>) -> Span {
+    let span = tracing::info_span!(
+        "request",
+        otel.kind = "server",
+        http.request.method = %info.method(),
+        url.path = %info.path(),
+        request_id = field::Empty,
+    );
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(info.request_headers()));
+    let _ = span.set_parent(parent);
+    span
+}
+
+/// The ID the request is logged under: the caller's `x-request-id` when it
+/// is short and printable, and a random one otherwise. Recorded on the
+/// request span, so it must run inside it.
+pub fn request_id() -> impl Filter<Extract = (String,), Error = Infallible> + Clone {
+    warp::header::headers_cloned().map(|headers: HeaderMap| {
+        let id = headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| is_safe_request_id(id))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{:032x}", rand::random::<u128>()));
+        Span::current().record("request_id", id.as_str());
+        id
+    })
+}
+
+/// Whether a caller's request ID can be logged and echoed as it is.
+fn is_safe_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_traces_path_is_added_once() {
+        assert_eq!(
+            traces_url("http://collector:4318"),
+            "http://collector:4318/v1/traces"
+        );
+        assert_eq!(
+            traces_url("http://collector:4318/v1/traces"),
+            "http://collector:4318/v1/traces"
+        );
+    }
+
+    #[test]
+    fn only_printable_request_ids_are_kept() {
+        assert!(is_safe_request_id("req-7f3a:1"));
+        assert!(!is_safe_request_id(""));
+        assert!(!is_safe_request_id("two words"));
+        assert!(!is_safe_request_id("forged\nline"));
+        assert!(!is_safe_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+        assert_eq!("JSON".parse(), Ok(LogFormat::Json));
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert_eq!("xml".parse::<LogFormat>(), Err(()));
+    }
+}
@@ -0,0 +1,48 @@
+//! Storing the tick maps of concentrated-liquidity pools.
+//!
+//! Pools whose ticks changed are stored every flush interval, each as one
+//! compressed blob rather than a row per tick, and the blobs are reloaded on
+//! boot. A reloaded map stays compressed until its pool's ticks are first
+//! used, so pools nobody trades do not slow down a restart.
+
+use crate::ApiState;
+use dex_db::DatabaseError;
+use std::time::Duration;
+
+/// Hand the stored tick maps to the pools; returns how many there were.
+pub async fn restore(state: &ApiState) -> Result<usize, DatabaseError> {
+    let maps = state.tick_map_repo.load_tick_maps().await?;
+    let count = maps.len();
+    state.amm.write().await.restore_tick_maps(maps);
+    Ok(count)
+}
+
+/// Store the tick maps of pools whose ticks changed since the last flush.
+pub async fn flush(state: &ApiState) -> Result<(), DatabaseError> {
+    let changed = state.amm.write().await.take_changed_tick_maps();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    // A stored map is only replaced by one taken at a later sequence, so a
+    // flush that lands after a newer one is harmless.
+    if let Err(err) = state.tick_map_repo.save_tick_maps(&changed).await {
+        state.amm.write().await.mark_tick_maps_changed(&changed);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Flush tick maps every `interval`.
+pub fn spawn_flush(state: ApiState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; nothing has changed yet.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = flush(&state).await {
+                tracing::error!(error = ?err, "failed to store tick maps");
+            }
+        }
+    });
+}
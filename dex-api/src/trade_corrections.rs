@@ -0,0 +1,410 @@
+//! Administrative busts and price adjustments of executed trades.
+//!
+//! An operator may bust an erroneous trade, or correct its price, within
+//! `trade_adjust_window_seconds` of execution. The store applies the change
+//! and appends it to the trade's adjustment history; the trade tape, and with
+//! it tickers and recent trades, is corrected; both traders get a
+//! `trade_adjusted` event and the market's trade stream a `trade_bust` or
+//! `trade_correction` message. The correction is booked in the ledger,
+//! reversing or re-pricing the fill.
+
+use crate::{
+    admin_only, api_keys,
+    auth::Claims,
+    candles, error_reply, ledger,
+    market_data::broadcast_depth_snapshot,
+    order_events,
+    rate_limit::RouteClass,
+    rate_limited, storage_error_reply,
+    trade_tape::{MarketTrade, PublicTrade, TradeEventKind},
+    validation::{self},
+    ApiState, ErrorResponse, ValidationRejection,
+};
+use dex_core::types::{Order, Price, Trade, TradeId, TradingPair};
+use dex_db::{AdjustmentKind, DatabaseError, TradeAdjustment};
+use serde::{Deserialize, Serialize};
+use utoipa::{openapi::Object, ToSchema};
+use warp::{http::StatusCode, Filter};
+
+/// Why [`correct_trade`] failed.
+#[derive(Debug)]
+pub enum CorrectionError {
+    NotFound,
+    /// The trade was busted earlier and can no longer change.
+    AlreadyBusted,
+    /// The trade is older than the adjustment window, in seconds.
+    WindowClosed(u64),
+    /// A price adjustment to the price the trade already has.
+    UnchangedPrice,
+    /// Another correction landed between loading the trade and applying this one.
+    Conflict,
+    Storage(DatabaseError),
+}
+
+impl From<DatabaseError> for CorrectionError {
+    fn from(err: DatabaseError) -> Self {
+        CorrectionError::Storage(err)
+    }
+}
+
+/// Bust the trade (`new_price` of `None`) or re-price it, on behalf of the
+/// administrator `adjusted_by`, and publish the correction.
+pub async fn correct_trade(
+    state: &ApiState,
+    trade_id: TradeId,
+    new_price: Option<Price>,
+    reason: String,
+    adjusted_by: &str,
+) -> Result<TradeAdjustment, CorrectionError> {
+    let trade = state
+        .trades
+        .load_trade(trade_id)
+        .await?
+        .ok_or(CorrectionError::NotFound)?;
+    let history = state.trades.get_trade_adjustments(trade_id).await?;
+    if history
+        .iter()
+        .any(|adjustment| adjustment.kind == AdjustmentKind::Bust)
+    {
+        return Err(CorrectionError::AlreadyBusted);
+    }
+    let now = state.determinism.now().unwrap_or_default();
+    let window = state.config.trade_adjust_window_seconds;
+    if now.saturating_sub(trade.timestamp) > window {
+        return Err(CorrectionError::WindowClosed(window));
+    }
+    if new_price == Some(trade.price) {
+        return Err(CorrectionError::UnchangedPrice);
+    }
+
+    let adjustment = TradeAdjustment {
+        trade_id,
+        kind: match new_price {
+            Some(_) => AdjustmentKind::PriceAdjust,
+            None => AdjustmentKind::Bust,
+        },
+        previous_price: trade.price,
+        new_price,
+        reason,
+        adjusted_by: adjusted_by.to_string(),
+        adjusted_at: now,
+    };
+    // Both orders are needed to book the correction, so a trade whose
+    // orders are gone cannot be corrected.
+    let (Some(maker), Some(taker)) = (
+        state.orders.load_order(trade.maker_order_id).await?,
+        state.orders.load_order(trade.taker_order_id).await?,
+    ) else {
+        tracing::error!(trade_id, "cannot correct trade: its orders are not stored");
+        return Err(CorrectionError::Storage(DatabaseError::DataIntegrityError));
+    };
+    if !state.trades.adjust_trade(&adjustment).await? {
+        return Err(CorrectionError::Conflict);
+    }
+    let entry = ledger::correction(&trade, &maker, &taker, &adjustment, history.len() + 1);
+    ledger::book(state, entry).await?;
+    publish(state, &trade, &adjustment, maker, taker).await;
+    if let Err(err) = candles::rebuild_minute(state, &trade).await {
+        tracing::error!(trade_id, error = ?err, "failed to rebuild the candle of a corrected trade");
+    }
+    Ok(adjustment)
+}
+
+/// Correct the tape and notify the market and both traders.
+async fn publish(
+    state: &ApiState,
+    trade: &Trade,
+    adjustment: &TradeAdjustment,
+    maker: Order,
+    taker: Order,
+) {
+    let Ok(pair) = TradingPair::new(trade.base_token.clone(), trade.quote_token.clone()) else {
+        return;
+    };
+
+    state.chaos.delay_broadcast().await;
+    let retained = state
+        .trade_tape
+        .write()
+        .await
+        .correct(&pair, trade.id, adjustment.new_price);
+    // Trades evicted from the tape are rebuilt from the taker's side.
+    let public = retained.unwrap_or_else(|| {
+        let mut corrected = trade.clone();
+        corrected.price = adjustment.new_price.unwrap_or(trade.price);
+        PublicTrade::from_trade(&corrected, taker.side)
+    });
+    let kind = match adjustment.kind {
+        AdjustmentKind::Bust => TradeEventKind::TradeBust,
+        AdjustmentKind::PriceAdjust => TradeEventKind::TradeCorrection,
+    };
+    let _ = state.trade_tx.send(MarketTrade {
+        kind,
+        pair: pair.to_string(),
+        trade: public,
+    });
+
+    for order in [maker, taker] {
+        let _ = state
+            .user_tx
+            .send(order_events::trade_adjusted(&order, trade, adjustment));
+    }
+    // Tickers follow the tape; push them to stream subscribers.
+    broadcast_depth_snapshot(state).await;
+}
+
+/// One entry in a trade's adjustment history.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = TradeAdjustment)]
+pub struct TradeAdjustmentResponse {
+    #[schema(value_type = u64)]
+    pub trade_id: TradeId,
+    /// `bust` or `price_adjust`.
+    #[schema(schema_with = adjustment_kinds)]
+    pub kind: &'static str,
+    #[schema(value_type = u64)]
+    pub previous_price: Price,
+    /// Corrected price; absent for a bust.
+    #[schema(value_type = Option<u64>)]
+    pub new_price: Option<Price>,
+    pub reason: String,
+    pub adjusted_by: String,
+    pub adjusted_at: u64,
+}
+
+impl From<TradeAdjustment> for TradeAdjustmentResponse {
+    fn from(adjustment: TradeAdjustment) -> Self {
+        Self {
+            trade_id: adjustment.trade_id,
+            kind: adjustment.kind.as_str(),
+            previous_price: adjustment.previous_price,
+            new_price: adjustment.new_price,
+            reason: adjustment.reason,
+            adjusted_by: adjustment.adjusted_by,
+            adjusted_at: adjustment.adjusted_at,
+        }
+    }
+}
+
+fn adjustment_kinds() -> Object {
+    crate::openapi::string_enum(&["bust", "price_adjust"])
+}
+
+/// A trade's adjustment history, oldest first.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = TradeAdjustments)]
+pub struct TradeAdjustmentsResponse {
+    #[schema(value_type = u64)]
+    pub trade_id: TradeId,
+    pub busted: bool,
+    pub adjustments: Vec<TradeAdjustmentResponse>,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct BustTradeRequest {
+    reason: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+struct AdjustTradeRequest {
+    #[schema(value_type = u64)]
+    price: Price,
+    reason: String,
+}
+
+/// Busts and adjustments of trades, e.g. POST /admin/trades/42/bust
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let trades = warp::path("admin").and(warp::path("trades"));
+
+    let bust = trades
+        .and(warp::path::param::<TradeId>())
+        .and(warp::path("bust"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_bust_trade)
+        .boxed();
+
+    let adjust = trades
+        .and(warp::path::param::<TradeId>())
+        .and(warp::path("adjust"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_adjust_trade)
+        .boxed();
+
+    let history = trades
+        .and(warp::path::param::<TradeId>())
+        .and(warp::path("adjustments"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(admin_only(state.clone()))
+        .and_then(handle_get_trade_adjustments)
+        .boxed();
+
+    bust.or(adjust).or(history)
+}
+
+/// Reply for a bust or price adjustment.
+async fn correction_reply(
+    state: &ApiState,
+    trade_id: TradeId,
+    new_price: Option<Price>,
+    reason: String,
+    claims: &Claims,
+) -> warp::reply::WithStatus<warp::reply::Json> {
+    use CorrectionError;
+
+    match correct_trade(state, trade_id, new_price, reason, &claims.sub).await {
+        Ok(adjustment) => warp::reply::with_status(
+            warp::reply::json(&TradeAdjustmentResponse::from(adjustment)),
+            StatusCode::OK,
+        ),
+        Err(CorrectionError::NotFound) => error_reply(
+            "trade_not_found",
+            "no trade with this id",
+            StatusCode::NOT_FOUND,
+        ),
+        Err(CorrectionError::AlreadyBusted) => error_reply(
+            "trade_busted",
+            "trade has already been busted",
+            StatusCode::CONFLICT,
+        ),
+        Err(CorrectionError::WindowClosed(window)) => error_reply(
+            "adjustment_window_closed",
+            format!(
+                "trades can only be adjusted within {}s of execution",
+                window
+            ),
+            StatusCode::CONFLICT,
+        ),
+        Err(CorrectionError::UnchangedPrice) => error_reply(
+            "validation_error",
+            "price must differ from the current trade price",
+            StatusCode::BAD_REQUEST,
+        ),
+        Err(CorrectionError::Conflict) => error_reply(
+            "trade_changed",
+            "trade was adjusted concurrently; reload it and retry",
+            StatusCode::CONFLICT,
+        ),
+        Err(CorrectionError::Storage(err)) => {
+            tracing::error!(trade_id, error = ?err, "failed to adjust trade");
+            storage_error_reply(&err, "failed to adjust trade")
+        }
+    }
+}
+
+/// Handler for busting an erroneous trade
+#[utoipa::path(
+    post,
+    path = "/admin/trades/{trade_id}/bust",
+    summary = "Bust an erroneous trade (administrators only)",
+    params(("trade_id" = u64, Path)),
+    request_body = BustTradeRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Trade busted", body = TradeAdjustmentResponse),
+        (status = 400, description = "Invalid reason", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 404, description = "No trade with this id", body = ErrorResponse),
+        (status = 409, description = "Trade already busted, outside the adjustment window or changed concurrently", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_bust_trade(
+    trade_id: TradeId,
+    claims: Claims,
+    state: ApiState,
+    req: BustTradeRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let reason = validation::validate_reason(&req.reason)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    Ok(correction_reply(&state, trade_id, None, reason, &claims).await)
+}
+
+/// Handler for correcting the price of a trade
+#[utoipa::path(
+    post,
+    path = "/admin/trades/{trade_id}/adjust",
+    summary = "Correct the price of a trade (administrators only)",
+    params(("trade_id" = u64, Path)),
+    request_body = AdjustTradeRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Trade re-priced", body = TradeAdjustmentResponse),
+        (status = 400, description = "Invalid or unchanged price, or invalid reason", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 404, description = "No trade with this id", body = ErrorResponse),
+        (status = 409, description = "Trade already busted, outside the adjustment window or changed concurrently", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_adjust_trade(
+    trade_id: TradeId,
+    claims: Claims,
+    state: ApiState,
+    req: AdjustTradeRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    if req.price == 0 {
+        return Err(warp::reject::custom(ValidationRejection(
+            validation::ValidationError::InvalidPrice,
+        )));
+    }
+    let reason = validation::validate_reason(&req.reason)
+        .map_err(|err| warp::reject::custom(ValidationRejection(err)))?;
+    Ok(correction_reply(&state, trade_id, Some(req.price), reason, &claims).await)
+}
+
+/// Handler for a trade's adjustment history
+#[utoipa::path(
+    get,
+    path = "/admin/trades/{trade_id}/adjustments",
+    summary = "Adjustment history of a trade (administrators only)",
+    params(("trade_id" = u64, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Adjustments, oldest first", body = TradeAdjustmentsResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_trade_adjustments(
+    trade_id: TradeId,
+    _claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match state.trades.get_trade_adjustments(trade_id).await {
+        Ok(adjustments) => {
+            let response = TradeAdjustmentsResponse {
+                trade_id,
+                busted: adjustments
+                    .iter()
+                    .any(|adjustment| adjustment.kind == AdjustmentKind::Bust),
+                adjustments: adjustments.into_iter().map(Into::into).collect(),
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(trade_id, error = ?err, "failed to load trade adjustments");
+            Ok(storage_error_reply(
+                &err,
+                "failed to load trade adjustments",
+            ))
+        }
+    }
+}
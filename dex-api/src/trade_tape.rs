@@ -0,0 +1,336 @@
+//! In-memory tape of recent executions per trading pair.
+//!
+//! Feeds the public trade endpoints without hitting the database. Each pair
+//! keeps a bounded ring buffer of its latest trades. The tape is
+//! consolidated: order book fills and AMM swaps on the same pair are filed
+//! together in execution order, each flagged with its venue, so tickers and
+//! trade streams cover all activity on the pair. The tape also keeps each
+//! pair's cumulative counters, which outlive the trades it retains.
+
+use crate::market_counters::CounterBook;
+use dex_core::types::{OrderSide, Price, Quantity, TokenId, Trade, TradeId, TradingPair};
+use dex_db::SwapRecord;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use utoipa::{openapi::Object, ToSchema};
+
+/// Number of trades retained per pair.
+pub const DEFAULT_TAPE_CAPACITY: usize = 1000;
+
+/// Where an execution took place: order book fill or AMM swap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Venue {
+    Orderbook,
+    Amm,
+}
+
+/// Public view of an execution, with the aggressor side inferred from the taker.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct PublicTrade {
+    /// Unique within the venue: AMM swaps are numbered separately.
+    #[schema(value_type = u64)]
+    pub id: TradeId,
+    #[schema(inline)]
+    pub venue: Venue,
+    #[schema(value_type = u64)]
+    pub price: Price,
+    #[schema(value_type = u64)]
+    pub quantity: Quantity,
+    pub timestamp: u64,
+    /// Side of the taker order (`buy` means the buyer lifted an ask).
+    #[schema(schema_with = sides)]
+    pub side: &'static str,
+    /// True when the resting (maker) order was the buy side.
+    pub is_buyer_maker: bool,
+}
+
+fn sides() -> Object {
+    crate::openapi::string_enum(&["buy", "sell"])
+}
+
+impl PublicTrade {
+    pub fn from_trade(trade: &Trade, taker_side: OrderSide) -> Self {
+        let (side, is_buyer_maker) = taker(taker_side);
+        Self {
+            id: trade.id,
+            venue: Venue::Orderbook,
+            price: trade.price,
+            quantity: trade.quantity,
+            timestamp: trade.timestamp,
+            side,
+            is_buyer_maker,
+        }
+    }
+
+    /// A swap, with the pool as the maker.
+    pub fn from_swap(swap: &SwapRecord) -> Self {
+        let (side, is_buyer_maker) = taker(swap.taker_side);
+        Self {
+            id: swap.id,
+            venue: Venue::Amm,
+            price: swap.price,
+            quantity: swap.quantity,
+            timestamp: swap.timestamp,
+            side,
+            is_buyer_maker,
+        }
+    }
+}
+
+fn taker(side: OrderSide) -> (&'static str, bool) {
+    match side {
+        OrderSide::Buy => ("buy", false),
+        OrderSide::Sell => ("sell", true),
+    }
+}
+
+/// What a trade stream message reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeEventKind {
+    /// A new execution.
+    Trade,
+    /// An earlier execution was busted and no longer counts.
+    TradeBust,
+    /// An earlier execution stands at a corrected price.
+    TradeCorrection,
+}
+
+/// A public trade tagged with its market, as pushed over `/ws/trades`.
+#[derive(Debug, Clone, Serialize)]
+pub struct MarketTrade {
+    #[serde(rename = "type")]
+    pub kind: TradeEventKind,
+    pub pair: String,
+    #[serde(flatten)]
+    pub trade: PublicTrade,
+}
+
+/// Bounded per-pair buffer of recent trades.
+#[derive(Debug)]
+pub struct TradeTape {
+    capacity: usize,
+    /// Keyed by (base, quote) so trades can be filed without rebuilding a pair.
+    by_pair: HashMap<(TokenId, TokenId), VecDeque<PublicTrade>>,
+    counters: CounterBook,
+}
+
+impl TradeTape {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            by_pair: HashMap::new(),
+            counters: CounterBook::default(),
+        }
+    }
+
+    /// Append an execution, evicting the oldest trade once the pair is full.
+    pub fn record(&mut self, trade: &Trade, taker_side: OrderSide) -> PublicTrade {
+        self.counters.record(
+            &trade.base_token,
+            &trade.quote_token,
+            trade.price,
+            trade.quantity,
+            trade.price as u128 * trade.quantity as u128,
+            trade.timestamp,
+        );
+        let key = (trade.base_token.clone(), trade.quote_token.clone());
+        self.push(key, PublicTrade::from_trade(trade, taker_side))
+    }
+
+    /// Append an AMM swap on the pool's pair.
+    pub fn record_swap(&mut self, swap: &SwapRecord) -> PublicTrade {
+        self.counters.record(
+            &swap.base_token,
+            &swap.quote_token,
+            swap.price,
+            swap.quantity,
+            swap.quote_quantity as u128,
+            swap.timestamp,
+        );
+        let key = (swap.base_token.clone(), swap.quote_token.clone());
+        self.push(key, PublicTrade::from_swap(swap))
+    }
+
+    fn push(&mut self, key: (TokenId, TokenId), public: PublicTrade) -> PublicTrade {
+        let buffer = self.by_pair.entry(key).or_default();
+        if buffer.len() == self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(public.clone());
+        public
+    }
+
+    /// Apply an administrative correction to an order book trade: drop a
+    /// busted trade (`new_price` of `None`) or re-price an adjusted one.
+    /// Returns the trade as the tape held it, after any re-pricing, or
+    /// `None` if it has been evicted.
+    pub fn correct(
+        &mut self,
+        pair: &TradingPair,
+        trade_id: TradeId,
+        new_price: Option<Price>,
+    ) -> Option<PublicTrade> {
+        let buffer = self.by_pair.get_mut(&Self::key(pair))?;
+        let index = buffer
+            .iter()
+            .position(|trade| trade.venue == Venue::Orderbook && trade.id == trade_id)?;
+        match new_price {
+            Some(price) => {
+                buffer[index].price = price;
+                Some(buffer[index].clone())
+            }
+            None => buffer.remove(index),
+        }
+    }
+
+    /// Most recent trades for a pair, newest first.
+    pub fn recent(&self, pair: &TradingPair, limit: usize) -> Vec<PublicTrade> {
+        self.by_pair
+            .get(&Self::key(pair))
+            .map(|buffer| buffer.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Latest trade for a pair.
+    pub fn last(&self, pair: &TradingPair) -> Option<&PublicTrade> {
+        self.by_pair
+            .get(&Self::key(pair))
+            .and_then(|buffer| buffer.back())
+    }
+
+    /// Pairs that have ever traded, including before a restart once the
+    /// stored counters are restored.
+    pub fn pairs(&self) -> impl Iterator<Item = TradingPair> + '_ {
+        self.counters.pairs()
+    }
+
+    /// Cumulative counters per pair.
+    pub fn counters(&self) -> &CounterBook {
+        &self.counters
+    }
+
+    pub fn counters_mut(&mut self) -> &mut CounterBook {
+        &mut self.counters
+    }
+
+    fn key(pair: &TradingPair) -> (TokenId, TokenId) {
+        (pair.base().clone(), pair.quote().clone())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Default for TradeTape {
+    fn default() -> Self {
+        Self::new(DEFAULT_TAPE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(id: TradeId, base: &str, price: Price) -> Trade {
+        Trade {
+            id,
+            maker_order_id: 1,
+            taker_order_id: 2,
+            base_token: base.parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            price,
+            quantity: 5,
+            timestamp: 1_700_000_000 + id,
+        }
+    }
+
+    fn pair(base: &str) -> TradingPair {
+        format!("{}-USDC", base).parse().unwrap()
+    }
+
+    #[test]
+    fn returns_newest_first_and_evicts_oldest() {
+        let mut tape = TradeTape::new(2);
+        tape.record(&trade(1, "ETH", 100), OrderSide::Buy);
+        tape.record(&trade(2, "ETH", 101), OrderSide::Sell);
+        tape.record(&trade(3, "ETH", 102), OrderSide::Buy);
+
+        let recent = tape.recent(&pair("ETH"), 10);
+        let ids: Vec<_> = recent.iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![3, 2]);
+        assert_eq!(tape.last(&pair("ETH")).map(|t| t.price), Some(102));
+    }
+
+    #[test]
+    fn infers_side_from_taker_and_separates_pairs() {
+        let mut tape = TradeTape::default();
+        let sold = tape.record(&trade(1, "ETH", 100), OrderSide::Sell);
+        assert_eq!(sold.side, "sell");
+        assert!(sold.is_buyer_maker);
+        let bought = tape.record(&trade(2, "BTC", 100), OrderSide::Buy);
+        assert!(!bought.is_buyer_maker);
+
+        assert_eq!(tape.recent(&pair("ETH"), 10).len(), 1);
+        assert!(tape.recent(&pair("SOL"), 10).is_empty());
+
+        let mut pairs: Vec<String> = tape.pairs().map(|pair| pair.to_string()).collect();
+        pairs.sort();
+        assert_eq!(pairs, ["BTC-USDC", "ETH-USDC"]);
+    }
+
+    #[test]
+    fn corrections_reprice_and_drop_trades() {
+        let mut tape = TradeTape::default();
+        tape.record(&trade(1, "ETH", 100), OrderSide::Buy);
+        tape.record(&trade(2, "ETH", 101), OrderSide::Buy);
+
+        let adjusted = tape.correct(&pair("ETH"), 2, Some(99)).unwrap();
+        assert_eq!(adjusted.price, 99);
+        assert_eq!(tape.last(&pair("ETH")).map(|t| t.price), Some(99));
+
+        let busted = tape.correct(&pair("ETH"), 2, None).unwrap();
+        assert_eq!(busted.id, 2);
+        assert_eq!(tape.last(&pair("ETH")).map(|t| t.id), Some(1));
+        assert!(tape.correct(&pair("ETH"), 2, None).is_none());
+        assert!(tape.correct(&pair("BTC"), 1, None).is_none());
+    }
+
+    #[test]
+    fn swaps_share_the_pair_tape_but_not_corrections() {
+        let mut tape = TradeTape::default();
+        tape.record(&trade(1, "ETH", 100), OrderSide::Buy);
+        let swap = SwapRecord {
+            id: 1,
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
+            taker_side: OrderSide::Sell,
+            price: 98,
+            quantity: 3,
+            quote_quantity: 294,
+            timestamp: 1_700_000_005,
+        };
+        let public = tape.record_swap(&swap);
+        assert_eq!(public.venue, Venue::Amm);
+        assert!(public.is_buyer_maker);
+        assert_eq!(
+            serde_json::to_value(&public).unwrap()["venue"],
+            serde_json::json!("amm")
+        );
+
+        let venues: Vec<_> = tape
+            .recent(&pair("ETH"), 10)
+            .iter()
+            .map(|t| (t.venue, t.id))
+            .collect();
+        assert_eq!(venues, vec![(Venue::Amm, 1), (Venue::Orderbook, 1)]);
+
+        // Busting trade 1 leaves the swap numbered 1 alone.
+        let busted = tape.correct(&pair("ETH"), 1, None).unwrap();
+        assert_eq!(busted.venue, Venue::Orderbook);
+        assert_eq!(tape.last(&pair("ETH")).map(|t| t.venue), Some(Venue::Amm));
+        assert!(tape.correct(&pair("ETH"), 1, None).is_none());
+    }
+}
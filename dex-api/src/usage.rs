@@ -0,0 +1,492 @@
+//! Per-trader API usage, for `GET /account/usage`.
+//!
+//! Every authenticated request is counted against the credential it used:
+//! the API key that signed it, or the trader's bearer token. Errors and
+//! throttled requests are counted alongside, and the private WebSocket
+//! streams add the messages and bytes sent to the trader. Orders accepted
+//! and fills are counted per account, for the order-to-trade ratio.
+//!
+//! Counts accumulate in memory and are added to daily rollups in storage
+//! every flush interval; reads merge the stored days with what is pending.
+//! The response also lists the trader's messaging policy penalties.
+
+use crate::{
+    auth::{Claims, Scope},
+    authenticated,
+    order_events::{OrderEvent, OrderStatus, UserEvent},
+    rate_limit::RouteClass,
+    rate_limited, storage_error_reply, ApiState, ErrorResponse,
+};
+use chrono::DateTime;
+use dex_db::{DatabaseError, MessagingPenalty, UsageRollup};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+    time::Duration,
+};
+use utoipa::{IntoParams, ToSchema};
+use warp::{http::StatusCode, Filter};
+
+pub const SECONDS_PER_DAY: u64 = 86_400;
+
+/// Signed requests awaiting their response; past this many, ones whose
+/// response was never counted are forgotten.
+const MAX_PENDING_SIGNATURES: usize = 4096;
+
+/// Unix seconds at the start of the UTC day containing `timestamp`.
+pub fn day_of(timestamp: u64) -> u64 {
+    timestamp - timestamp % SECONDS_PER_DAY
+}
+
+/// `YYYY-MM-DD` of the UTC day starting at `day`.
+pub fn date_of(day: u64) -> String {
+    DateTime::from_timestamp(day as i64, 0)
+        .map(|date| date.format("%Y-%m-%d").to_string())
+        .unwrap_or_default()
+}
+
+type UsageKey = (String, u64, String);
+
+/// Counts not yet added to the stored rollups.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    pending: Mutex<HashMap<UsageKey, UsageRollup>>,
+    /// Key owner and ID of signed requests in flight, by signature, so the
+    /// response can be counted against the key that was verified.
+    signed: Mutex<HashMap<String, (String, String)>>,
+}
+
+impl UsageTracker {
+    /// Note that `signature` was verified as a request by `subject` with
+    /// API key `key_id`.
+    pub fn signed_request(&self, signature: &str, subject: &str, key_id: &str) {
+        let mut signed = self.signed.lock().expect("usage poisoned");
+        if signed.len() >= MAX_PENDING_SIGNATURES {
+            signed.clear();
+        }
+        signed.insert(
+            signature.to_string(),
+            (subject.to_string(), key_id.to_string()),
+        );
+    }
+
+    /// Owner and key ID of a verified signed request.
+    pub fn take_signed(&self, signature: &str) -> Option<(String, String)> {
+        self.signed
+            .lock()
+            .expect("usage poisoned")
+            .remove(signature)
+    }
+
+    /// Count a request by `subject` with `credential` that got `status`.
+    pub fn request(&self, subject: &str, credential: &str, status: StatusCode, now: u64) {
+        self.add(subject, credential, now, |rollup| {
+            rollup.requests += 1;
+            if status.is_client_error() || status.is_server_error() {
+                rollup.errors += 1;
+            }
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                rollup.throttled += 1;
+            }
+        });
+    }
+
+    /// Count accepted orders and fills among order events.
+    pub fn order_events(&self, events: &[UserEvent], now: u64) {
+        for event in events {
+            let trader = event.trader_id.to_string();
+            match event.event {
+                OrderEvent::OrderUpdate {
+                    status: OrderStatus::Accepted,
+                    ..
+                } => self.add(&trader, "", now, |rollup| rollup.orders += 1),
+                OrderEvent::Fill { .. } => self.add(&trader, "", now, |rollup| rollup.fills += 1),
+                _ => {}
+            }
+        }
+    }
+
+    /// Count a WebSocket message of `bytes` sent to `subject`.
+    pub fn ws_sent(&self, subject: &str, bytes: usize, now: u64) {
+        self.add(subject, "", now, |rollup| {
+            rollup.ws_messages += 1;
+            rollup.ws_bytes += bytes as u64;
+        });
+    }
+
+    /// The counts of `subject` not yet stored.
+    pub fn pending(&self, subject: &str) -> Vec<UsageRollup> {
+        self.pending
+            .lock()
+            .expect("usage poisoned")
+            .values()
+            .filter(|rollup| rollup.subject == subject)
+            .cloned()
+            .collect()
+    }
+
+    /// Take every pending count, to be stored.
+    pub fn drain(&self) -> Vec<UsageRollup> {
+        let mut pending = self.pending.lock().expect("usage poisoned");
+        pending.drain().map(|(_, rollup)| rollup).collect()
+    }
+
+    /// Put back counts that could not be stored.
+    pub fn restore(&self, rollups: Vec<UsageRollup>) {
+        let mut pending = self.pending.lock().expect("usage poisoned");
+        for rollup in rollups {
+            let key = (
+                rollup.subject.clone(),
+                rollup.day,
+                rollup.credential.clone(),
+            );
+            let entry = pending.entry(key).or_default();
+            merge(entry, &rollup);
+        }
+    }
+
+    fn add(&self, subject: &str, credential: &str, now: u64, count: impl FnOnce(&mut UsageRollup)) {
+        let day = day_of(now);
+        let mut pending = self.pending.lock().expect("usage poisoned");
+        let rollup = pending
+            .entry((subject.to_string(), day, credential.to_string()))
+            .or_insert_with(|| UsageRollup {
+                subject: subject.to_string(),
+                day,
+                credential: credential.to_string(),
+                ..UsageRollup::default()
+            });
+        count(rollup);
+    }
+}
+
+fn merge(into: &mut UsageRollup, from: &UsageRollup) {
+    if into.subject.is_empty() {
+        into.subject = from.subject.clone();
+        into.day = from.day;
+        into.credential = from.credential.clone();
+    }
+    into.requests += from.requests;
+    into.errors += from.errors;
+    into.throttled += from.throttled;
+    into.orders += from.orders;
+    into.fills += from.fills;
+    into.ws_messages += from.ws_messages;
+    into.ws_bytes += from.ws_bytes;
+}
+
+/// Add the pending counts to the stored rollups; on failure they stay
+/// pending for the next flush.
+pub async fn flush(state: &ApiState) -> Result<(), DatabaseError> {
+    let rollups = state.usage.drain();
+    if rollups.is_empty() {
+        return Ok(());
+    }
+    if let Err(err) = state.usage_repo.add_usage(&rollups).await {
+        state.usage.restore(rollups);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Flush usage counts every `interval`.
+pub fn spawn_flush(state: ApiState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // The first tick completes immediately; nothing is pending yet.
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Err(err) = flush(&state).await {
+                tracing::error!(error = ?err, "failed to store API usage");
+            }
+        }
+    });
+}
+
+/// Requests made with one API key on one day.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KeyUsage {
+    pub key_id: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub throttled: u64,
+}
+
+/// A trader's usage on one UTC day, over every credential.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DailyUsage {
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub requests: u64,
+    pub errors: u64,
+    /// Errors per request; absent without requests.
+    pub error_rate: Option<f64>,
+    pub throttled: u64,
+    pub orders: u64,
+    pub fills: u64,
+    /// Orders per fill; absent without fills.
+    pub order_to_trade_ratio: Option<f64>,
+    pub ws_messages: u64,
+    pub ws_bytes: u64,
+    /// Requests signed with each API key, included in the totals above.
+    pub api_keys: Vec<KeyUsage>,
+}
+
+/// Sustained rate and burst a route class allows each trader.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RateLimitUsage {
+    #[schema(schema_with = RouteClass::schema)]
+    pub class: &'static str,
+    /// Requests per second; zero when the class is not limited.
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+/// A day on which the trader breached their order-to-trade ratio.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = MessagingPenalty)]
+pub struct PenaltyUsage {
+    /// `YYYY-MM-DD` of the day evaluated.
+    pub date: String,
+    pub orders: u64,
+    pub fills: u64,
+    pub max_ratio: f64,
+    /// Fee charged, in USD.
+    pub fee: u64,
+    /// Share of the usual rate limits left while the penalty lasts.
+    pub rate_limit_percent: u32,
+    /// Unix seconds.
+    pub expires_at: u64,
+}
+
+/// The order-to-trade ratio the trader is held to.
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = MessagingPolicy)]
+pub struct MessagingPolicyUsage {
+    /// Orders per fill allowed; absent when the trader is not limited.
+    pub max_order_to_trade_ratio: Option<f64>,
+    /// Orders a day below which the ratio is not enforced.
+    pub min_orders: u64,
+    /// Penalties for days in the window, oldest first.
+    pub penalties: Vec<PenaltyUsage>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = AccountUsage)]
+pub struct AccountUsageResponse {
+    pub trader_id: String,
+    /// Oldest day first; days without activity are omitted.
+    pub days: Vec<DailyUsage>,
+    /// Budgets currently in force, reduced while a penalty lasts.
+    pub rate_limits: Vec<RateLimitUsage>,
+    pub messaging_policy: MessagingPolicyUsage,
+}
+
+/// Group stored and pending rollups into the usage of each day.
+pub fn account_usage(
+    state: &ApiState,
+    subject: &str,
+    rollups: &[UsageRollup],
+    penalties: &[MessagingPenalty],
+) -> AccountUsageResponse {
+    let mut merged: BTreeMap<(u64, &str), UsageRollup> = BTreeMap::new();
+    for rollup in rollups {
+        merge(
+            merged.entry((rollup.day, &rollup.credential)).or_default(),
+            rollup,
+        );
+    }
+
+    let mut days: BTreeMap<u64, DailyUsage> = BTreeMap::new();
+    for ((day, credential), rollup) in merged {
+        let usage = days.entry(day).or_insert_with(|| DailyUsage {
+            date: date_of(day),
+            requests: 0,
+            errors: 0,
+            error_rate: None,
+            throttled: 0,
+            orders: 0,
+            fills: 0,
+            order_to_trade_ratio: None,
+            ws_messages: 0,
+            ws_bytes: 0,
+            api_keys: Vec::new(),
+        });
+        usage.requests += rollup.requests;
+        usage.errors += rollup.errors;
+        usage.throttled += rollup.throttled;
+        usage.orders += rollup.orders;
+        usage.fills += rollup.fills;
+        usage.ws_messages += rollup.ws_messages;
+        usage.ws_bytes += rollup.ws_bytes;
+        if !credential.is_empty() {
+            usage.api_keys.push(KeyUsage {
+                key_id: credential.to_string(),
+                requests: rollup.requests,
+                errors: rollup.errors,
+                throttled: rollup.throttled,
+            });
+        }
+    }
+    for usage in days.values_mut() {
+        usage.error_rate =
+            (usage.requests > 0).then(|| usage.errors as f64 / usage.requests as f64);
+        usage.order_to_trade_ratio =
+            (usage.fills > 0).then(|| usage.orders as f64 / usage.fills as f64);
+    }
+
+    let policy = &state.config.messaging_policy;
+    let reduction = state.rate_limiter.reduction(subject);
+    AccountUsageResponse {
+        trader_id: subject.to_string(),
+        days: days.into_values().collect(),
+        rate_limits: RouteClass::ALL
+            .iter()
+            .map(|&class| {
+                let mut budget = state.rate_limiter.config().budget(class);
+                if let Some(percent) = reduction {
+                    budget = budget.scaled(percent);
+                }
+                RateLimitUsage {
+                    class: class.as_str(),
+                    per_second: budget.per_second,
+                    burst: budget.burst,
+                }
+            })
+            .collect(),
+        messaging_policy: MessagingPolicyUsage {
+            max_order_to_trade_ratio: policy.limit_for(subject),
+            min_orders: policy.min_orders,
+            penalties: penalties
+                .iter()
+                .map(|penalty| PenaltyUsage {
+                    date: date_of(penalty.day),
+                    orders: penalty.orders,
+                    fills: penalty.fills,
+                    max_ratio: penalty.max_ratio,
+                    fee: penalty.fee,
+                    rate_limit_percent: penalty.rate_limit_percent,
+                    expires_at: penalty.expires_at,
+                })
+                .collect(),
+        },
+    }
+}
+
+/// Most days of history `/account/usage` returns.
+const MAX_USAGE_DAYS: u64 = 90;
+
+/// Usage history length in days, e.g. `?days=30`; a week by default.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct UsageQuery {
+    /// Days of history, today included (default 7, max 90)
+    days: Option<u64>,
+}
+
+/// The caller's own usage, e.g. GET /account/usage?days=7
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    warp::path("account")
+        .and(warp::path("usage"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and(warp::query::<UsageQuery>())
+        .and_then(handle_get_account_usage)
+        .boxed()
+}
+
+/// Handler for the caller's usage over the last `days` days
+#[utoipa::path(
+    get,
+    path = "/account/usage",
+    summary = "The caller's API usage per day, with its rate limits",
+    params(UsageQuery),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Requests, errors, orders, fills and stream traffic per day", body = AccountUsageResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_account_usage(
+    claims: Claims,
+    state: ApiState,
+    query: UsageQuery,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let days = query.days.unwrap_or(7).clamp(1, MAX_USAGE_DAYS);
+    let today = day_of(state.determinism.now().unwrap_or_default());
+    let since = today.saturating_sub((days - 1) * SECONDS_PER_DAY);
+    let mut rollups = match state.usage_repo.load_usage(&claims.sub, since).await {
+        Ok(rollups) => rollups,
+        Err(err) => {
+            tracing::error!(subject = %claims.sub, error = ?err, "failed to load usage");
+            return Ok(storage_error_reply(&err, "failed to load usage"));
+        }
+    };
+    rollups.extend(
+        state
+            .usage
+            .pending(&claims.sub)
+            .into_iter()
+            .filter(|rollup| rollup.day >= since),
+    );
+    let penalties = match state.usage_repo.load_penalties(&claims.sub, since).await {
+        Ok(penalties) => penalties,
+        Err(err) => {
+            tracing::error!(subject = %claims.sub, error = ?err, "failed to load penalties");
+            return Ok(storage_error_reply(&err, "failed to load usage"));
+        }
+    };
+    let response = account_usage(&state, &claims.sub, &rollups, &penalties);
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::OK,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_kept_per_day_and_credential() {
+        let tracker = UsageTracker::default();
+        let monday = 1_700_006_400;
+        tracker.request("alice", "", StatusCode::OK, monday);
+        tracker.request("alice", "key-1", StatusCode::BAD_REQUEST, monday + 5);
+        tracker.request("alice", "key-1", StatusCode::TOO_MANY_REQUESTS, monday + 6);
+        tracker.request("alice", "", StatusCode::OK, monday + SECONDS_PER_DAY);
+        tracker.ws_sent("alice", 120, monday);
+        tracker.request("bob", "", StatusCode::OK, monday);
+
+        let mut pending = tracker.pending("alice");
+        pending.sort_by(|a, b| (a.day, &a.credential).cmp(&(b.day, &b.credential)));
+        assert_eq!(pending.len(), 3);
+        assert_eq!((pending[0].requests, pending[0].ws_bytes), (1, 120));
+        assert_eq!(pending[1].credential, "key-1");
+        assert_eq!(
+            (pending[1].requests, pending[1].errors, pending[1].throttled),
+            (2, 2, 1)
+        );
+        assert_eq!(pending[2].day, monday + SECONDS_PER_DAY);
+
+        // Counts that fail to store are merged back.
+        let drained = tracker.drain();
+        assert!(tracker.pending("alice").is_empty());
+        tracker.request("alice", "", StatusCode::OK, monday);
+        tracker.restore(drained);
+        let today: u64 = tracker
+            .pending("alice")
+            .iter()
+            .filter(|rollup| rollup.day == monday && rollup.credential.is_empty())
+            .map(|rollup| rollup.requests)
+            .sum();
+        assert_eq!(today, 2);
+    }
+}
@@ -0,0 +1,222 @@
+//! Synthetic USD quotes for every traded token.
+//!
+//! Order book markets and AMM pools form a token graph, each priced at its
+//! mid (or last trade) or pool spot price. A token's USD price is its rate to
+//! the reference stable along the best path dex-core's `PathRouter` finds, so
+//! tokens without a direct stable market are still quoted. A background task
+//! rebuilds the snapshot; `GET /prices/usd` and valuation endpoints read it.
+
+use crate::{
+    market_data::{depth_snapshot_for, ticker},
+    ApiState,
+};
+use dex_core::{
+    path_routing::{PathRouter, TradingEdge},
+    types::{Quantity, TokenId, TradingPair},
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::Duration,
+};
+use utoipa::ToSchema;
+
+/// Price of one market, in quote per base.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarketPrice {
+    pub pair: TradingPair,
+    pub price: f64,
+    /// Fraction of the traded amount charged as a fee.
+    pub fee: f64,
+    /// Base quantity behind the price; deeper markets are preferred.
+    pub liquidity: Quantity,
+}
+
+/// USD price of one token.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct UsdQuote {
+    pub price: f64,
+    /// Markets the quote was bridged through, e.g. `["ABC-ETH", "ETH-USDC"]`.
+    pub route: Vec<String>,
+}
+
+/// Latest quotes against the reference stable.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct UsdPrices {
+    pub reference: String,
+    /// Unix seconds of the last refresh; zero before the first.
+    pub updated_at: u64,
+    pub prices: BTreeMap<String, UsdQuote>,
+}
+
+impl UsdPrices {
+    pub fn new(reference: &TokenId) -> Self {
+        Self {
+            reference: reference.to_string(),
+            updated_at: 0,
+            prices: BTreeMap::new(),
+        }
+    }
+
+    /// Quote every token reachable from `markets` against `reference`.
+    pub fn compute(reference: &TokenId, markets: &[MarketPrice], updated_at: u64) -> Self {
+        let mut router = PathRouter::new();
+        for market in markets.iter().filter(|market| market.price > 0.0) {
+            let edge = |from: &TokenId, to: &TokenId, exchange_rate: f64| TradingEdge {
+                from_token: from.clone(),
+                to_token: to.clone(),
+                dex_name: market.pair.to_string(),
+                exchange_rate,
+                fee: market.fee,
+                liquidity: market.liquidity,
+            };
+            let (base, quote) = (market.pair.base(), market.pair.quote());
+            router.add_edge(edge(base, quote, market.price));
+            router.add_edge(edge(quote, base, 1.0 / market.price));
+        }
+
+        let mut prices = BTreeMap::new();
+        for token in router.get_tokens() {
+            let quote = if token == reference {
+                UsdQuote {
+                    price: 1.0,
+                    route: Vec::new(),
+                }
+            } else {
+                match router.find_best_path_dijkstra(token, reference, 1.0) {
+                    Some(path) => UsdQuote {
+                        price: path.edges.iter().map(|edge| edge.exchange_rate).product(),
+                        route: path.edges.into_iter().map(|edge| edge.dex_name).collect(),
+                    },
+                    None => continue,
+                }
+            };
+            prices.insert(token.to_string(), quote);
+        }
+        Self {
+            reference: reference.to_string(),
+            updated_at,
+            prices,
+        }
+    }
+
+    pub fn price(&self, token: &str) -> Option<f64> {
+        self.prices.get(token).map(|quote| quote.price)
+    }
+
+    /// USD value of `amount` of `token`, when it has a quote.
+    pub fn value(&self, token: &str, amount: u128) -> Option<f64> {
+        self.price(token).map(|price| price * amount as f64)
+    }
+}
+
+/// Current prices of every order book market and AMM pool.
+pub async fn market_prices(state: &ApiState) -> Vec<MarketPrice> {
+    let mut markets = Vec::new();
+    let now = state.determinism.now().unwrap_or_default();
+    {
+        let orderbook = state.orderbook.read().await;
+        let tape = state.trade_tape.read().await;
+        let pairs: HashSet<TradingPair> = orderbook
+            .orders
+            .values()
+            .map(|order| order.pair.clone())
+            .chain(tape.pairs())
+            .collect();
+        for pair in pairs {
+            let ticker = ticker(&orderbook, &tape, &pair, now);
+            let Some(price) = ticker.mid_price.or(ticker.last_price) else {
+                continue;
+            };
+            let top = depth_snapshot_for(&orderbook, Some(&pair), 1, now);
+            let resting: Quantity = top
+                .bids
+                .iter()
+                .chain(&top.asks)
+                .map(|level| level.quantity)
+                .sum();
+            markets.push(MarketPrice {
+                pair,
+                price: price as f64,
+                fee: 0.0,
+                // A price from the last trade alone still counts, barely.
+                liquidity: resting.max(1),
+            });
+        }
+    }
+
+    let pools = state.amm.read().await;
+    for pool in pools.iter() {
+        let (base, quote) = pool.reserves();
+        if base == 0 || quote == 0 {
+            continue;
+        }
+        markets.push(MarketPrice {
+            pair: pool.pair().clone(),
+            price: quote as f64 / base as f64,
+            fee: f64::from(pool.fee_bps()) / 10_000.0,
+            liquidity: Quantity::try_from(base).unwrap_or(Quantity::MAX),
+        });
+    }
+    markets
+}
+
+/// Rebuild the USD quotes from the current markets.
+pub async fn refresh(state: &ApiState) {
+    let markets = market_prices(state).await;
+    let now = state.determinism.now().unwrap_or_default();
+    let prices = UsdPrices::compute(&state.config.usd_reference_token, &markets, now);
+    *state.usd_prices.write().await = prices;
+}
+
+/// Refresh the USD quotes every `interval`.
+pub fn spawn_refresh(state: ApiState, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            refresh(&state).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(pair: &str, price: f64, liquidity: Quantity) -> MarketPrice {
+        MarketPrice {
+            pair: pair.parse().unwrap(),
+            price,
+            fee: 0.0,
+            liquidity,
+        }
+    }
+
+    #[test]
+    fn bridges_tokens_to_the_reference_stable() {
+        let usdc: TokenId = "USDC".parse().unwrap();
+        let markets = [
+            market("ETH-USDC", 2000.0, 1_000),
+            market("ABC-ETH", 0.5, 1_000),
+            // Inverted and shallow: USDC priced in DAI.
+            market("USDC-DAI", 1.0, 500),
+            // A thin direct market loses to the deep bridge.
+            market("ABC-USDC", 900.0, 1),
+            // Never reaches USDC.
+            market("FOO-BAR", 3.0, 100),
+        ];
+        let prices = UsdPrices::compute(&usdc, &markets, 42);
+
+        assert_eq!(prices.reference, "USDC");
+        assert_eq!(prices.updated_at, 42);
+        assert_eq!(prices.price("USDC"), Some(1.0));
+        assert_eq!(prices.price("ETH"), Some(2000.0));
+        assert_eq!(prices.price("DAI"), Some(1.0));
+        let abc = &prices.prices["ABC"];
+        assert_eq!(abc.price, 1000.0);
+        assert_eq!(abc.route, ["ABC-ETH", "ETH-USDC"]);
+        assert_eq!(prices.price("FOO"), None);
+        assert_eq!(prices.value("ETH", 3), Some(6000.0));
+    }
+}
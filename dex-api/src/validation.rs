@@ -0,0 +1,583 @@
+//! Validation of order requests and market data queries.
+
+use crate::{
+    candles::{CandleRequest, Interval, DEFAULT_CANDLE_LIMIT, MAX_CANDLE_LIMIT},
+    market_data::{
+        CandlesQuery, DepthEncoding, DepthQuery, MatchingStatsQuery, DEFAULT_DEPTH_LEVELS,
+        MAX_DEPTH_LEVELS,
+    },
+    orders::{CreateOrderRequest, TradeHistoryQuery},
+};
+use dex_core::types::{Order, OrderId, OrderSide, OrderType, TokenId, TraderId, TradingPair};
+use dex_db::{repository::MAX_TRADE_PAGE, TradeFilter};
+use thiserror::Error;
+
+/// Result of validating a `CreateOrderRequest`.
+#[derive(Debug)]
+pub struct ValidatedCreateOrder {
+    pub trader_id: TraderId,
+    pub pair: TradingPair,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub price: Option<u64>,
+    pub quantity: u64,
+}
+
+impl ValidatedCreateOrder {
+    /// Convert the validated payload into a fully formed `Order`.
+    pub fn into_order(self, order_id: OrderId, timestamp: u64) -> Order {
+        Order {
+            id: order_id,
+            trader_id: self.trader_id,
+            pair: self.pair,
+            side: self.side,
+            order_type: self.order_type,
+            price: self.price,
+            quantity: self.quantity,
+            timestamp,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Error)]
+pub enum ValidationError {
+    #[error("trader_id must be between 3 and 64 visible characters")]
+    InvalidTraderId,
+    #[error("base_token must be 2-16 characters from [A-Za-z0-9_-]")]
+    InvalidBaseToken,
+    #[error("quote_token must be 2-16 characters from [A-Za-z0-9_-]")]
+    InvalidQuoteToken,
+    #[error("base_token and quote_token must differ")]
+    IdenticalTokens,
+    #[error("quantity must be greater than zero")]
+    InvalidQuantity,
+    #[error("limit orders require a positive price")]
+    MissingLimitPrice,
+    #[error("price must be greater than zero")]
+    InvalidPrice,
+    #[error("side must be `buy` or `sell`")]
+    InvalidSide,
+    #[error("order_type must be `market` or `limit`")]
+    InvalidOrderType,
+    #[error("pair must be formatted as BASE-QUOTE, e.g. ETH-USDC")]
+    InvalidPair,
+    #[error("limit must be between 1 and 1000")]
+    InvalidLimit,
+    #[error("from must be earlier than to")]
+    InvalidTimeRange,
+    #[error("channel must be depth, depth:PAIR, trades:PAIR, ticker:PAIR or orders")]
+    InvalidChannel,
+    #[error("levels must be between 1 and 100")]
+    InvalidDepthLevels,
+    #[error("grouping must be greater than zero")]
+    InvalidGrouping,
+    #[error("encoding must be `json` or `compact`")]
+    InvalidEncoding,
+    #[error("pair is required")]
+    MissingPair,
+    #[error("interval must be 1m, 5m, 15m, 1h, 4h or 1d")]
+    InvalidInterval,
+    #[error("limit must be between 1 and 100000")]
+    InvalidCandleLimit,
+    #[error("reason must be between 1 and 256 characters")]
+    InvalidReason,
+    #[error("label must be at most 64 characters")]
+    InvalidLabel,
+}
+
+/// Validate a create order request.
+pub fn validate_create_order(
+    req: CreateOrderRequest,
+) -> Result<ValidatedCreateOrder, ValidationError> {
+    let trader_id = normalize_trader_id(&req.trader_id)?;
+    let base_token = normalize_token(&req.base_token, TokenRole::Base)?;
+    let quote_token = normalize_token(&req.quote_token, TokenRole::Quote)?;
+
+    if base_token == quote_token {
+        return Err(ValidationError::IdenticalTokens);
+    }
+
+    let side = parse_side(&req.side)?;
+    let order_type = parse_order_type(&req.order_type)?;
+
+    if req.quantity == 0 {
+        return Err(ValidationError::InvalidQuantity);
+    }
+
+    let price = match order_type {
+        OrderType::Limit => match req.price {
+            Some(p) if p > 0 => Some(p),
+            Some(_) => return Err(ValidationError::InvalidPrice),
+            None => return Err(ValidationError::MissingLimitPrice),
+        },
+        OrderType::Market => {
+            if let Some(p) = req.price {
+                if p == 0 {
+                    return Err(ValidationError::InvalidPrice);
+                }
+            }
+            None
+        }
+    };
+
+    Ok(ValidatedCreateOrder {
+        trader_id,
+        pair: TradingPair::new(base_token, quote_token)
+            .map_err(|_| ValidationError::IdenticalTokens)?,
+        side,
+        order_type,
+        price,
+        quantity: req.quantity,
+    })
+}
+
+/// Parse a `BASE-QUOTE` market identifier from a URL path segment.
+pub fn parse_pair(raw: &str) -> Result<TradingPair, ValidationError> {
+    let (base, quote) = raw.split_once('-').ok_or(ValidationError::InvalidPair)?;
+    let base = normalize_token(base, TokenRole::Base).map_err(|_| ValidationError::InvalidPair)?;
+    let quote =
+        normalize_token(quote, TokenRole::Quote).map_err(|_| ValidationError::InvalidPair)?;
+    TradingPair::new(base, quote).map_err(|_| ValidationError::IdenticalTokens)
+}
+
+/// Default page size for trade history queries.
+pub const DEFAULT_TRADE_PAGE: u32 = 100;
+
+/// Validate trade history query parameters into a storage filter.
+pub fn validate_trade_history(query: TradeHistoryQuery) -> Result<TradeFilter, ValidationError> {
+    let limit = query.limit.unwrap_or(DEFAULT_TRADE_PAGE);
+    if limit == 0 || limit > MAX_TRADE_PAGE {
+        return Err(ValidationError::InvalidLimit);
+    }
+    let pair = query.pair.as_deref().map(parse_pair).transpose()?;
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from >= to {
+            return Err(ValidationError::InvalidTimeRange);
+        }
+    }
+    Ok(TradeFilter {
+        after_id: query.after_id,
+        limit,
+        pair,
+        from: query.from,
+        to: query.to,
+    })
+}
+
+/// Validate candle query parameters. `from` (or `cursor`) is rounded down
+/// to the start of its bar.
+pub fn validate_candles_query(query: CandlesQuery) -> Result<CandleRequest, ValidationError> {
+    let pair = parse_pair(query.pair.as_deref().ok_or(ValidationError::MissingPair)?)?;
+    let interval = match query.interval.as_deref() {
+        None => Interval::OneMinute,
+        Some(raw) => Interval::parse(raw).ok_or(ValidationError::InvalidInterval)?,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_CANDLE_LIMIT);
+    if limit == 0 || limit > MAX_CANDLE_LIMIT {
+        return Err(ValidationError::InvalidCandleLimit);
+    }
+    let from = query
+        .cursor
+        .or(query.from)
+        .map(|from| interval.open_time(from));
+    if let (Some(from), Some(to)) = (from, query.to) {
+        if from >= to {
+            return Err(ValidationError::InvalidTimeRange);
+        }
+    }
+    Ok(CandleRequest {
+        filter: TradeFilter {
+            after_id: None,
+            limit: MAX_TRADE_PAGE,
+            pair: Some(pair),
+            from,
+            to: query.to,
+        },
+        interval,
+        limit,
+    })
+}
+
+/// Pair and roll-up interval of a matching statistics query.
+pub fn validate_matching_stats_query(
+    query: MatchingStatsQuery,
+) -> Result<(TradingPair, Interval), ValidationError> {
+    let pair = parse_pair(query.pair.as_deref().ok_or(ValidationError::MissingPair)?)?;
+    let interval = match query.interval.as_deref() {
+        None => Interval::OneHour,
+        Some(raw) => Interval::parse(raw).ok_or(ValidationError::InvalidInterval)?,
+    };
+    Ok((pair, interval))
+}
+
+/// Validated depth query.
+#[derive(Debug)]
+pub struct DepthRequest {
+    pub levels: usize,
+    pub pair: Option<TradingPair>,
+    pub grouping: Option<u64>,
+    pub encoding: DepthEncoding,
+}
+
+/// Validate depth query parameters.
+pub fn validate_depth_query(query: DepthQuery) -> Result<DepthRequest, ValidationError> {
+    let levels = query.levels.unwrap_or(DEFAULT_DEPTH_LEVELS);
+    if levels == 0 || levels > MAX_DEPTH_LEVELS {
+        return Err(ValidationError::InvalidDepthLevels);
+    }
+    let pair = query.pair.as_deref().map(parse_pair).transpose()?;
+    if query.grouping == Some(0) {
+        return Err(ValidationError::InvalidGrouping);
+    }
+    let encoding = match query.encoding.as_deref().map(str::to_ascii_lowercase) {
+        None => DepthEncoding::Json,
+        Some(raw) => match raw.as_str() {
+            "json" => DepthEncoding::Json,
+            "compact" => DepthEncoding::Compact,
+            _ => return Err(ValidationError::InvalidEncoding),
+        },
+    };
+    Ok(DepthRequest {
+        levels,
+        pair,
+        grouping: query.grouping,
+        encoding,
+    })
+}
+
+enum TokenRole {
+    Base,
+    Quote,
+}
+
+/// Trim the reason recorded with a trade adjustment.
+pub fn validate_reason(raw: &str) -> Result<String, ValidationError> {
+    let reason = raw.trim();
+    if reason.is_empty() || reason.chars().count() > 256 {
+        return Err(ValidationError::InvalidReason);
+    }
+    Ok(reason.to_string())
+}
+
+/// Trim an API key label; blank labels are dropped.
+pub fn validate_label(raw: Option<&str>) -> Result<Option<String>, ValidationError> {
+    match raw.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(label) if label.chars().count() > 64 => Err(ValidationError::InvalidLabel),
+        Some(label) => Ok(Some(label.to_string())),
+    }
+}
+
+pub fn normalize_trader_id(raw: &str) -> Result<TraderId, ValidationError> {
+    TraderId::parse(raw.trim()).map_err(|_| ValidationError::InvalidTraderId)
+}
+
+fn normalize_token(raw: &str, role: TokenRole) -> Result<TokenId, ValidationError> {
+    TokenId::parse(raw.trim()).map_err(|_| match role {
+        TokenRole::Base => ValidationError::InvalidBaseToken,
+        TokenRole::Quote => ValidationError::InvalidQuoteToken,
+    })
+}
+
+fn parse_side(raw: &str) -> Result<OrderSide, ValidationError> {
+    match raw.to_ascii_lowercase().as_str() {
+        "buy" => Ok(OrderSide::Buy),
+        "sell" => Ok(OrderSide::Sell),
+        _ => Err(ValidationError::InvalidSide),
+    }
+}
+
+fn parse_order_type(raw: &str) -> Result<OrderType, ValidationError> {
+    match raw.to_ascii_lowercase().as_str() {
+        "limit" => Ok(OrderType::Limit),
+        "market" => Ok(OrderType::Market),
+        _ => Err(ValidationError::InvalidOrderType),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_request() -> CreateOrderRequest {
+        CreateOrderRequest {
+            trader_id: "alice".into(),
+            base_token: "ETH".into(),
+            quote_token: "USDC".into(),
+            side: "buy".into(),
+            order_type: "limit".into(),
+            price: Some(1000),
+            quantity: 10,
+        }
+    }
+
+    #[test]
+    fn validates_happy_path() {
+        let req = base_request();
+        let validated = validate_create_order(req).expect("valid request");
+        assert_eq!(validated.trader_id, "alice");
+        assert_eq!(validated.pair.base(), "ETH");
+        assert_eq!(validated.pair.quote(), "USDC");
+        assert!(validated.price.is_some());
+    }
+
+    #[test]
+    fn rejects_identical_tokens() {
+        let mut req = base_request();
+        req.quote_token = "ETH".into();
+        let err = validate_create_order(req).unwrap_err();
+        assert!(matches!(err, ValidationError::IdenticalTokens));
+    }
+
+    #[test]
+    fn requires_price_for_limit_order() {
+        let mut req = base_request();
+        req.price = None;
+        let err = validate_create_order(req).unwrap_err();
+        assert!(matches!(err, ValidationError::MissingLimitPrice));
+    }
+
+    #[test]
+    fn rejects_zero_quantity() {
+        let mut req = base_request();
+        req.quantity = 0;
+        let err = validate_create_order(req).unwrap_err();
+        assert!(matches!(err, ValidationError::InvalidQuantity));
+    }
+
+    #[test]
+    fn parses_market_pair() {
+        let pair = parse_pair("ETH-USDC").expect("valid pair");
+        assert_eq!(pair.base(), "ETH");
+        assert_eq!(pair.quote(), "USDC");
+        assert!(matches!(
+            parse_pair("ETHUSDC"),
+            Err(ValidationError::InvalidPair)
+        ));
+        assert!(matches!(
+            parse_pair("E-USDC"),
+            Err(ValidationError::InvalidPair)
+        ));
+    }
+
+    #[test]
+    fn validates_trade_history_query() {
+        let filter = validate_trade_history(TradeHistoryQuery {
+            after_id: Some(7),
+            pair: Some("ETH-USDC".into()),
+            ..Default::default()
+        })
+        .expect("valid query");
+        assert_eq!(filter.after_id, Some(7));
+        assert_eq!(filter.limit, DEFAULT_TRADE_PAGE);
+        assert_eq!(
+            filter.pair.map(|pair| pair.to_string()),
+            Some("ETH-USDC".into())
+        );
+
+        let err = validate_trade_history(TradeHistoryQuery {
+            from: Some(10),
+            to: Some(10),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(err, ValidationError::InvalidTimeRange));
+
+        let err = validate_trade_history(TradeHistoryQuery {
+            limit: Some(MAX_TRADE_PAGE + 1),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(err, ValidationError::InvalidLimit));
+    }
+
+    #[test]
+    fn validates_depth_query() {
+        let request = validate_depth_query(DepthQuery::default()).expect("defaults");
+        assert_eq!(request.levels, DEFAULT_DEPTH_LEVELS);
+        assert!(request.pair.is_none() && request.grouping.is_none());
+        assert_eq!(request.encoding, DepthEncoding::Json);
+
+        let request = validate_depth_query(DepthQuery {
+            pair: Some("ETH-USDC".into()),
+            grouping: Some(5),
+            encoding: Some("COMPACT".into()),
+            ..Default::default()
+        })
+        .expect("valid query");
+        assert_eq!(
+            request.pair.map(|pair| pair.to_string()),
+            Some("ETH-USDC".into())
+        );
+        assert_eq!(request.encoding, DepthEncoding::Compact);
+
+        for (query, expected) in [
+            (
+                DepthQuery {
+                    levels: Some(0),
+                    ..Default::default()
+                },
+                ValidationError::InvalidDepthLevels,
+            ),
+            (
+                DepthQuery {
+                    levels: Some(MAX_DEPTH_LEVELS + 1),
+                    ..Default::default()
+                },
+                ValidationError::InvalidDepthLevels,
+            ),
+            (
+                DepthQuery {
+                    grouping: Some(0),
+                    ..Default::default()
+                },
+                ValidationError::InvalidGrouping,
+            ),
+            (
+                DepthQuery {
+                    encoding: Some("xml".into()),
+                    ..Default::default()
+                },
+                ValidationError::InvalidEncoding,
+            ),
+            (
+                DepthQuery {
+                    pair: Some("ETH".into()),
+                    ..Default::default()
+                },
+                ValidationError::InvalidPair,
+            ),
+        ] {
+            assert_eq!(validate_depth_query(query).unwrap_err(), expected);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_token_chars() {
+        let mut req = base_request();
+        req.base_token = "E TH".into();
+        let err = validate_create_order(req).unwrap_err();
+        assert!(matches!(err, ValidationError::InvalidBaseToken));
+    }
+}
+
+#[cfg(test)]
+mod auth_filter_tests {
+    use crate::{authenticated, handle_rejection, ApiState, Claims, Scope};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use secrecy::{ExposeSecret, SecretString};
+    use std::time::{SystemTime, UNIX_EPOCH};
+    use warp::http::StatusCode;
+    use warp::Filter;
+
+    const TEST_SECRET: &str = crate::test_support::TEST_SECRET;
+
+    #[tokio::test]
+    async fn missing_token_returns_401() {
+        let state = test_state();
+        let filter = protected_filter(state, Scope::Read);
+
+        let response = warp::test::request().reply(&filter).await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn invalid_token_returns_401() {
+        let state = test_state();
+        let filter = protected_filter(state, Scope::Read);
+
+        let response = warp::test::request()
+            .header("authorization", "Bearer totally-invalid")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn expired_token_returns_401() {
+        let secret = SecretString::from(TEST_SECRET.to_string());
+        let token = build_token(&secret, -3600);
+        let state = test_state();
+        let filter = protected_filter(state, Scope::Read);
+
+        let response = warp::test::request()
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn valid_token_returns_200() {
+        let secret = SecretString::from(TEST_SECRET.to_string());
+        let token = build_token(&secret, 300);
+        let state = test_state();
+        let filter = protected_filter(state, Scope::Read);
+
+        let response = warp::test::request()
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn token_without_scope_returns_403() {
+        let secret = SecretString::from(TEST_SECRET.to_string());
+        let token = build_token(&secret, 300);
+        let filter = protected_filter(test_state(), Scope::Trade);
+
+        let response = warp::test::request()
+            .header("authorization", format!("Bearer {}", token))
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["code"], "insufficient_scope");
+    }
+
+    fn protected_filter(
+        state: ApiState,
+        scope: Scope,
+    ) -> impl Filter<Extract = (impl warp::Reply,), Error = std::convert::Infallible> + Clone {
+        authenticated(state, scope)
+            .and_then(|claims: Claims, _state: ApiState| async move {
+                let reply =
+                    warp::reply::with_status(warp::reply::json(&claims.sub), StatusCode::OK);
+                Ok::<_, warp::Rejection>(reply)
+            })
+            .recover(handle_rejection)
+    }
+
+    fn test_state() -> ApiState {
+        crate::test_support::test_state()
+    }
+
+    fn build_token(secret: &SecretString, offset_seconds: i64) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("time went backwards")
+            .as_secs() as i64;
+        let claims = Claims {
+            sub: "alice".into(),
+            exp: (now + offset_seconds) as usize,
+            aud: None,
+            iss: None,
+            iat: None,
+            jti: None,
+            scope: Some("read".into()),
+            role: None,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(secret.expose_secret().as_bytes()),
+        )
+        .expect("token encoding")
+    }
+}
@@ -0,0 +1,287 @@
+//! Address and signature schemes wallets sign in with.
+//!
+//! Ethereum wallets sign the challenge with `personal_sign` or as EIP-712
+//! typed data and keep their bare `0x` address as token subject. Solana
+//! wallets sign the challenge text with ed25519 and send the signature in
+//! base58. Cosmos wallets sign it as an ADR-036 `sign/MsgSignData` document
+//! with secp256k1 and send the signature and compressed public key in
+//! base64, as Keplr's `signArbitrary` returns them. Non-EVM subjects carry
+//! their namespace, e.g. `solana:<address>`, so an address string can never
+//! name accounts on two chains.
+
+use crate::auth::{normalize_address, verify_wallet_signature, AuthError, WalletPayload};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bech32::{Bech32, Hrp};
+use ethers_core::k256::ecdsa::{signature::Verifier, Signature, VerifyingKey};
+use ring::{digest, signature::UnparsedPublicKey, signature::ED25519};
+use ripemd::{Digest, Ripemd160};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+/// How one family of wallets names accounts and signs.
+pub trait WalletScheme: Send + Sync {
+    /// Chain name sign-in messages show, e.g. `Solana`.
+    fn blockchain(&self) -> &'static str;
+
+    /// What a valid address looks like, for error messages.
+    fn address_format(&self) -> &'static str;
+
+    /// Canonical form of `address`.
+    fn normalize_address(&self, address: &str) -> Result<String, AuthError>;
+
+    /// Token subject for a normalized address.
+    fn subject(&self, address: &str) -> String;
+
+    /// Check that `signature` over `payload` was made with the key behind
+    /// the normalized `address`. Schemes whose addresses are hashes of the
+    /// key need the `public_key` too.
+    fn verify(
+        &self,
+        address: &str,
+        payload: WalletPayload<'_>,
+        signature: &str,
+        public_key: Option<&str>,
+    ) -> Result<(), AuthError>;
+}
+
+/// Chains whose wallets may sign in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletChain {
+    #[default]
+    Ethereum,
+    Solana,
+    Cosmos,
+}
+
+impl WalletChain {
+    pub fn scheme(self) -> &'static dyn WalletScheme {
+        match self {
+            WalletChain::Ethereum => &EthereumScheme,
+            WalletChain::Solana => &SolanaScheme,
+            WalletChain::Cosmos => &CosmosScheme,
+        }
+    }
+}
+
+/// `0x` addresses recovered from secp256k1 signatures.
+pub struct EthereumScheme;
+
+impl WalletScheme for EthereumScheme {
+    fn blockchain(&self) -> &'static str {
+        "Ethereum"
+    }
+
+    fn address_format(&self) -> &'static str {
+        "a 0x-prefixed hex string"
+    }
+
+    fn normalize_address(&self, address: &str) -> Result<String, AuthError> {
+        normalize_address(address)
+    }
+
+    /// Bare addresses, as subjects were before other chains were added.
+    fn subject(&self, address: &str) -> String {
+        address.to_string()
+    }
+
+    fn verify(
+        &self,
+        address: &str,
+        payload: WalletPayload<'_>,
+        signature: &str,
+        _public_key: Option<&str>,
+    ) -> Result<(), AuthError> {
+        verify_wallet_signature(address, payload, signature)
+    }
+}
+
+/// Base58 ed25519 public keys.
+pub struct SolanaScheme;
+
+impl WalletScheme for SolanaScheme {
+    fn blockchain(&self) -> &'static str {
+        "Solana"
+    }
+
+    fn address_format(&self) -> &'static str {
+        "a base58 ed25519 public key"
+    }
+
+    fn normalize_address(&self, address: &str) -> Result<String, AuthError> {
+        let key = bs58::decode(address.trim())
+            .into_vec()
+            .map_err(|_| AuthError::InvalidAddress)?;
+        if key.len() != 32 {
+            return Err(AuthError::InvalidAddress);
+        }
+        Ok(bs58::encode(key).into_string())
+    }
+
+    fn subject(&self, address: &str) -> String {
+        format!("solana:{}", address)
+    }
+
+    fn verify(
+        &self,
+        address: &str,
+        payload: WalletPayload<'_>,
+        signature: &str,
+        _public_key: Option<&str>,
+    ) -> Result<(), AuthError> {
+        let message = signed_message(payload)?;
+        let key = bs58::decode(address)
+            .into_vec()
+            .map_err(|_| AuthError::InvalidAddress)?;
+        let signature = bs58::decode(signature)
+            .into_vec()
+            .map_err(|err| AuthError::InvalidSignature(err.to_string()))?;
+        UnparsedPublicKey::new(&ED25519, key)
+            .verify(message.as_bytes(), &signature)
+            .map_err(|_| AuthError::SignatureMismatch)
+    }
+}
+
+/// Bech32 hashes of secp256k1 keys, under any chain's prefix.
+pub struct CosmosScheme;
+
+impl WalletScheme for CosmosScheme {
+    fn blockchain(&self) -> &'static str {
+        "Cosmos"
+    }
+
+    fn address_format(&self) -> &'static str {
+        "a bech32 account address"
+    }
+
+    fn normalize_address(&self, address: &str) -> Result<String, AuthError> {
+        let (hrp, data) = bech32::decode(address.trim()).map_err(|_| AuthError::InvalidAddress)?;
+        if data.len() != 20 {
+            return Err(AuthError::InvalidAddress);
+        }
+        bech32::encode::<Bech32>(hrp, &data).map_err(|_| AuthError::InvalidAddress)
+    }
+
+    fn subject(&self, address: &str) -> String {
+        format!("cosmos:{}", address)
+    }
+
+    fn verify(
+        &self,
+        address: &str,
+        payload: WalletPayload<'_>,
+        signature: &str,
+        public_key: Option<&str>,
+    ) -> Result<(), AuthError> {
+        let message = signed_message(payload)?;
+        let public_key = public_key.ok_or_else(|| {
+            AuthError::InvalidSignature("public_key is required for Cosmos wallets".into())
+        })?;
+        let public_key = STANDARD
+            .decode(public_key)
+            .map_err(|err| AuthError::InvalidSignature(err.to_string()))?;
+        let key = VerifyingKey::from_sec1_bytes(&public_key)
+            .map_err(|_| AuthError::InvalidSignature("invalid secp256k1 public key".into()))?;
+        let (hrp, _) = bech32::decode(address).map_err(|_| AuthError::InvalidAddress)?;
+        if cosmos_address(hrp, &public_key)? != address {
+            return Err(AuthError::SignatureMismatch);
+        }
+        let signature = STANDARD
+            .decode(signature)
+            .ok()
+            .and_then(|bytes| Signature::from_slice(&bytes).ok())
+            .ok_or_else(|| AuthError::InvalidSignature("expected a 64-byte signature".into()))?;
+        key.verify(adr036_sign_doc(address, message).as_bytes(), &signature)
+            .map_err(|_| AuthError::SignatureMismatch)
+    }
+}
+
+/// The challenge text; typed data is Ethereum's alone.
+fn signed_message(payload: WalletPayload<'_>) -> Result<&str, AuthError> {
+    match payload {
+        WalletPayload::Message(message) => Ok(message),
+        WalletPayload::TypedData(_) => Err(AuthError::InvalidSignature(
+            "only Ethereum wallets sign typed data".into(),
+        )),
+    }
+}
+
+/// Account address of a compressed secp256k1 key: RIPEMD-160 of its SHA-256.
+fn cosmos_address(hrp: Hrp, public_key: &[u8]) -> Result<String, AuthError> {
+    let sha = digest::digest(&digest::SHA256, public_key);
+    let hash = Ripemd160::digest(sha.as_ref());
+    bech32::encode::<Bech32>(hrp, &hash).map_err(|_| AuthError::InvalidAddress)
+}
+
+/// The ADR-036 amino sign document for `message`, in canonical form: keys
+/// sorted, no whitespace.
+fn adr036_sign_doc(signer: &str, message: &str) -> String {
+    format!(
+        r#"{{"account_number":"0","chain_id":"","fee":{{"amount":[],"gas":"0"}},"memo":"","msgs":[{{"type":"sign/MsgSignData","value":{{"data":"{}","signer":"{}"}}}}],"sequence":"0"}}"#,
+        STANDARD.encode(message),
+        signer
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::k256::ecdsa::{signature::Signer, SigningKey};
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+
+    const MESSAGE: &str = "Sign in to DEX-OS";
+
+    #[test]
+    fn solana_wallets_sign_the_challenge_text() {
+        let pair = Ed25519KeyPair::from_seed_unchecked(&[3; 32]).unwrap();
+        let address = bs58::encode(pair.public_key().as_ref()).into_string();
+        let scheme = WalletChain::Solana.scheme();
+        assert_eq!(scheme.normalize_address(&address).unwrap(), address);
+        assert_eq!(scheme.subject(&address), format!("solana:{}", address));
+        assert!(scheme
+            .normalize_address("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+            .is_err());
+
+        let signature = bs58::encode(pair.sign(MESSAGE.as_bytes())).into_string();
+        let payload = WalletPayload::Message(MESSAGE);
+        assert!(scheme.verify(&address, payload, &signature, None).is_ok());
+        let other = WalletPayload::Message("Sign in to something else");
+        assert!(matches!(
+            scheme.verify(&address, other, &signature, None),
+            Err(AuthError::SignatureMismatch)
+        ));
+    }
+
+    #[test]
+    fn cosmos_wallets_sign_adr036_documents() {
+        let key = SigningKey::from_slice(&[5; 32]).unwrap();
+        let public_key = key.verifying_key().to_sec1_bytes();
+        let address = cosmos_address(Hrp::parse("osmo").unwrap(), &public_key).unwrap();
+        let scheme = WalletChain::Cosmos.scheme();
+        assert!(address.starts_with("osmo1"));
+        assert_eq!(
+            scheme.normalize_address(&address.to_uppercase()).unwrap(),
+            address
+        );
+
+        let signature: Signature = key.sign(adr036_sign_doc(&address, MESSAGE).as_bytes());
+        let signature = STANDARD.encode(signature.to_bytes());
+        let public_key = STANDARD.encode(&public_key);
+        let payload = WalletPayload::Message(MESSAGE);
+        assert!(scheme
+            .verify(&address, payload, &signature, Some(&public_key))
+            .is_ok());
+        assert!(matches!(
+            scheme.verify(&address, payload, &signature, None),
+            Err(AuthError::InvalidSignature(_))
+        ));
+
+        // A valid signature by a key that does not own the address.
+        let stranger = SigningKey::from_slice(&[6; 32]).unwrap();
+        let stranger_key = STANDARD.encode(stranger.verifying_key().to_sec1_bytes());
+        assert!(matches!(
+            scheme.verify(&address, payload, &signature, Some(&stranger_key)),
+            Err(AuthError::SignatureMismatch)
+        ));
+    }
+}
@@ -0,0 +1,851 @@
+//! Webhook notifications of order events, for traders who cannot keep a
+//! WebSocket open.
+//!
+//! Traders register HTTPS endpoints under `/account/webhooks`. The
+//! dispatcher turns each private order event into a queued delivery for
+//! every webhook of its trader that takes that event type, and the delivery
+//! worker POSTs them signed with the webhook's secret, retrying with
+//! exponential backoff until the endpoint answers 2xx or attempts run out.
+//! The host is resolved again for every attempt and the request sent to the
+//! address that was checked, so a name cannot be pointed at an internal
+//! service once registered.
+
+use crate::{
+    api_keys,
+    auth::{Claims, Scope},
+    authenticated, determinism, error_reply,
+    object_store::{HttpEndpoint, Signed},
+    order_events::{OrderEvent, UserEvent},
+    rate_limit::RouteClass,
+    rate_limited, storage_error_reply, ApiState, ErrorResponse,
+};
+use dex_db::{DeliveryStatus, WebhookDelivery, WebhookRecord};
+use futures_util::future::join_all;
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::{
+    sync::{broadcast::error::RecvError, RwLock},
+    task::JoinHandle,
+};
+use utoipa::{openapi::Array, ToSchema};
+use warp::{http::StatusCode, Filter};
+
+/// Hex HMAC-SHA256 of `<timestamp>.<body>` under the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "x-dex-signature";
+/// Unix seconds at which the delivery was signed.
+pub const TIMESTAMP_HEADER: &str = "x-dex-timestamp";
+/// Same for every attempt at one delivery, so receivers can drop repeats.
+pub const DELIVERY_HEADER: &str = "x-dex-delivery";
+/// Event types a webhook can take, as in the private WebSocket stream.
+pub const EVENT_TYPES: &[&str] = &["order_update", "fill", "trade_adjusted"];
+/// Most webhooks one trader may have.
+pub const MAX_WEBHOOKS: usize = 10;
+const MAX_URL_LEN: usize = 2048;
+/// Attempts before a delivery is given up on.
+pub const MAX_ATTEMPTS: u32 = 8;
+/// Wait before the first retry; doubled after each failed attempt.
+const FIRST_RETRY: Duration = Duration::from_secs(10);
+const MAX_RETRY: Duration = Duration::from_secs(3600);
+/// How long an endpoint may take to answer.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often registered webhooks are reloaded, so ones added or revoked
+/// through any instance take effect.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Most deliveries attempted at once.
+const DELIVERY_BATCH: u32 = 100;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum InvalidWebhook {
+    #[error("webhook URL must be an https:// URL of at most {MAX_URL_LEN} characters")]
+    Url,
+    #[error("webhook URL must not point at a loopback, private or other internal address")]
+    InternalHost,
+    #[error("unknown event type {0}, expected one of order_update, fill, trade_adjusted")]
+    EventType(String),
+}
+
+/// `url` if it is an HTTPS URL that does not name an internal host.
+pub fn validate_url(url: &str) -> Result<String, InvalidWebhook> {
+    let url = url.trim();
+    if url.len() > MAX_URL_LEN || !url.starts_with("https://") {
+        return Err(InvalidWebhook::Url);
+    }
+    let (base, _) = split_url(url).ok_or(InvalidWebhook::Url)?;
+    let authority = &base["https://".len()..];
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.parse::<u16>().is_ok() => host,
+        _ => authority,
+    };
+    // IPv6 literals are refused outright rather than classified.
+    if host.is_empty() || host.contains(['@', '[', ']']) {
+        return Err(InvalidWebhook::Url);
+    }
+    let host = host.to_ascii_lowercase();
+    let internal = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => true,
+        Ok(ip) => !is_public(ip),
+        Err(_) => host == "localhost" || host.ends_with(".localhost"),
+    };
+    if internal {
+        return Err(InvalidWebhook::InternalHost);
+    }
+    Ok(url.to_string())
+}
+
+/// Whether webhooks may be delivered to `ip`: it is not loopback, private,
+/// link-local, shared (100.64.0.0/10), in 0.0.0.0/8, multicast, broadcast or
+/// reserved, nor an IPv6 address of those kinds.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [first, second, ..] = ip.octets();
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_multicast()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || first == 0
+                || (first == 100 && (64..128).contains(&second))
+                || first >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let [first, second, ..] = ip.segments();
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // Unique local, link-local and NAT64 addresses.
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+                    || (first, second) == (0x64, 0xff9b))
+            }
+        },
+    }
+}
+
+/// The requested event types, deduplicated, or every type when none are.
+pub fn validate_events(events: &[String]) -> Result<Vec<String>, InvalidWebhook> {
+    if events.is_empty() {
+        return Ok(EVENT_TYPES.iter().map(|event| event.to_string()).collect());
+    }
+    let mut valid = Vec::new();
+    for event in events {
+        if !EVENT_TYPES.contains(&event.as_str()) {
+            return Err(InvalidWebhook::EventType(event.clone()));
+        }
+        if !valid.contains(event) {
+            valid.push(event.clone());
+        }
+    }
+    Ok(valid)
+}
+
+/// `scheme://authority` and the path with query of `url`.
+fn split_url(url: &str) -> Option<(&str, String)> {
+    let (_, rest) = url.split_once("://")?;
+    let scheme_len = url.len() - rest.len();
+    let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (base, path) = url.split_at(scheme_len + end);
+    let path = path.split('#').next().unwrap_or_default();
+    let path = match path {
+        "" => "/".to_string(),
+        path if path.starts_with('?') => format!("/{}", path),
+        path => path.to_string(),
+    };
+    Some((base, path))
+}
+
+/// The signature a receiver recomputes to check a delivery.
+pub fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    api_keys::sign(secret, &format!("{}.{}", timestamp, body))
+}
+
+fn event_type(event: &OrderEvent) -> &'static str {
+    match event {
+        OrderEvent::OrderUpdate { .. } => "order_update",
+        OrderEvent::Fill { .. } => "fill",
+        OrderEvent::TradeAdjusted { .. } => "trade_adjusted",
+    }
+}
+
+#[derive(Serialize)]
+struct Notification<'a> {
+    webhook_id: &'a str,
+    trader_id: &'a str,
+    event: &'a OrderEvent,
+}
+
+/// Active webhooks, by trader and by ID.
+#[derive(Default)]
+struct Registry {
+    by_trader: HashMap<String, Vec<WebhookRecord>>,
+    by_id: HashMap<String, WebhookRecord>,
+}
+
+impl Registry {
+    fn new(webhooks: Vec<WebhookRecord>) -> Self {
+        let mut registry = Self::default();
+        for webhook in webhooks {
+            registry
+                .by_trader
+                .entry(webhook.trader_id.clone())
+                .or_default()
+                .push(webhook.clone());
+            registry.by_id.insert(webhook.id.clone(), webhook);
+        }
+        registry
+    }
+}
+
+async fn refresh(state: &ApiState, registry: &RwLock<Registry>) {
+    match state.webhook_repo.load_active_webhooks().await {
+        Ok(webhooks) => *registry.write().await = Registry::new(webhooks),
+        Err(err) => tracing::warn!(error = ?err, "failed to reload webhooks"),
+    }
+}
+
+/// Queue a delivery of `event` to each of its trader's webhooks that takes
+/// its type.
+async fn dispatch(state: &ApiState, registry: &RwLock<Registry>, event: &UserEvent) {
+    let kind = event_type(&event.event);
+    let now = state.determinism.now().unwrap_or_default();
+    let deliveries: Vec<WebhookDelivery> = {
+        let registry = registry.read().await;
+        let Some(webhooks) = registry.by_trader.get(event.trader_id.as_str()) else {
+            return;
+        };
+        webhooks
+            .iter()
+            .filter(|webhook| webhook.events.iter().any(|wanted| wanted == kind))
+            .map(|webhook| {
+                let notification = Notification {
+                    webhook_id: &webhook.id,
+                    trader_id: event.trader_id.as_str(),
+                    event: &event.event,
+                };
+                WebhookDelivery {
+                    id: 0,
+                    webhook_id: webhook.id.clone(),
+                    payload: serde_json::to_string(&notification)
+                        .expect("notifications always encode"),
+                    status: DeliveryStatus::Pending,
+                    attempts: 0,
+                    next_attempt_at: now,
+                    last_error: None,
+                }
+            })
+            .collect()
+    };
+    if let Err(err) = state.webhook_repo.enqueue_deliveries(&deliveries).await {
+        tracing::error!(
+            trader = %event.trader_id.as_str(),
+            error = ?err,
+            "failed to queue webhook deliveries"
+        );
+    }
+}
+
+/// The address to deliver to `endpoint` at. Every address its host
+/// resolves to must pass `allowed`.
+async fn resolve(
+    endpoint: &HttpEndpoint,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<SocketAddr, String> {
+    let (host, port) = endpoint.address();
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|err| format!("cannot resolve {}: {}", host, err))?
+        .collect();
+    if let Some(addr) = addrs.iter().find(|addr| !allowed(addr.ip())) {
+        return Err(format!(
+            "{} resolves to internal address {}",
+            host,
+            addr.ip()
+        ));
+    }
+    addrs
+        .first()
+        .copied()
+        .ok_or_else(|| format!("{} has no address", host))
+}
+
+/// POST a delivery to its webhook at an address `allowed` accepts,
+/// returning why it failed.
+async fn post(
+    webhook: &WebhookRecord,
+    delivery: &WebhookDelivery,
+    now: u64,
+    allowed: fn(IpAddr) -> bool,
+) -> Result<(), String> {
+    let (base, path) = split_url(&webhook.url).ok_or("invalid webhook URL")?;
+    let endpoint = HttpEndpoint::parse(base).map_err(|err| err.to_string())?;
+    let request = Signed {
+        path,
+        headers: vec![
+            ("host".into(), endpoint.host_header()),
+            ("content-type".into(), "application/json".into()),
+            ("user-agent".into(), "dex-os-webhooks".into()),
+            (DELIVERY_HEADER.into(), delivery.id.to_string()),
+            (TIMESTAMP_HEADER.into(), now.to_string()),
+            (
+                SIGNATURE_HEADER.into(),
+                sign(&webhook.secret, now, &delivery.payload),
+            ),
+        ],
+    };
+    let body = delivery.payload.clone().into_bytes();
+    let exchange = async {
+        let addr = resolve(&endpoint, allowed).await?;
+        endpoint
+            .send_to(addr, Method::POST, request, body)
+            .await
+            .map_err(|err| err.to_string())
+    };
+    match tokio::time::timeout(DELIVERY_TIMEOUT, exchange).await {
+        Err(_) => Err(format!("no answer within {}s", DELIVERY_TIMEOUT.as_secs())),
+        Ok(Err(err)) => Err(err),
+        Ok(Ok((status, _))) if status.is_success() => Ok(()),
+        Ok(Ok((status, _))) => Err(format!("endpoint answered {}", status)),
+    }
+}
+
+/// Wait before the attempt after `attempts` failed ones.
+fn retry_delay(attempts: u32) -> Duration {
+    FIRST_RETRY
+        .saturating_mul(1 << attempts.saturating_sub(1).min(16))
+        .min(MAX_RETRY)
+}
+
+/// Make one attempt at a delivery and store its outcome.
+async fn attempt(
+    state: &ApiState,
+    webhook: Option<WebhookRecord>,
+    mut delivery: WebhookDelivery,
+    allowed: fn(IpAddr) -> bool,
+) {
+    let now = state.determinism.now().unwrap_or_default();
+    let result = match &webhook {
+        Some(webhook) => post(webhook, &delivery, now, allowed).await,
+        None => Err("webhook revoked".to_string()),
+    };
+    delivery.attempts += 1;
+    match result {
+        Ok(()) => {
+            delivery.status = DeliveryStatus::Delivered;
+            delivery.last_error = None;
+        }
+        Err(err) => {
+            delivery.status = if webhook.is_none() || delivery.attempts >= MAX_ATTEMPTS {
+                DeliveryStatus::Failed
+            } else {
+                DeliveryStatus::Pending
+            };
+            delivery.next_attempt_at = now + retry_delay(delivery.attempts).as_secs();
+            tracing::debug!(
+                delivery = delivery.id,
+                webhook = %delivery.webhook_id,
+                attempts = delivery.attempts,
+                error = %err,
+                "webhook delivery failed"
+            );
+            delivery.last_error = Some(err);
+        }
+    }
+    if let Err(err) = state.webhook_repo.update_delivery(&delivery).await {
+        tracing::error!(delivery = delivery.id, error = ?err, "failed to record webhook attempt");
+    }
+}
+
+/// Attempt every due delivery to addresses `allowed` accepts, returning how
+/// many there were.
+async fn deliver_due(
+    state: &ApiState,
+    registry: &RwLock<Registry>,
+    allowed: fn(IpAddr) -> bool,
+) -> usize {
+    let now = state.determinism.now().unwrap_or_default();
+    let due = match state.webhook_repo.due_deliveries(now, DELIVERY_BATCH).await {
+        Ok(due) => due,
+        Err(err) => {
+            tracing::warn!(error = ?err, "failed to load due webhook deliveries");
+            return 0;
+        }
+    };
+    let count = due.len();
+    let attempts: Vec<_> = {
+        let registry = registry.read().await;
+        due.into_iter()
+            .map(|delivery| {
+                let webhook = registry.by_id.get(&delivery.webhook_id).cloned();
+                attempt(state, webhook, delivery, allowed)
+            })
+            .collect()
+    };
+    join_all(attempts).await;
+    count
+}
+
+/// Queue order events for webhooks and deliver them until the process
+/// exits.
+pub fn spawn(state: ApiState) -> JoinHandle<()> {
+    let registry = Arc::new(RwLock::new(Registry::default()));
+
+    let delivery_state = state.clone();
+    let delivery_registry = registry.clone();
+    tokio::spawn(async move {
+        loop {
+            let due = deliver_due(&delivery_state, &delivery_registry, is_public).await;
+            if due < DELIVERY_BATCH as usize {
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut events = state.user_tx.subscribe();
+        let mut refreshes = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = refreshes.tick() => refresh(&state, &registry).await,
+                event = events.recv() => match event {
+                    Ok(event) => dispatch(&state, &registry, &event).await,
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "webhook dispatcher fell behind; events dropped");
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            }
+        }
+    })
+}
+
+#[derive(Deserialize, ToSchema)]
+struct CreateWebhookRequest {
+    url: String,
+    /// Event types to deliver; all of them when omitted.
+    #[serde(default)]
+    #[schema(schema_with = event_types)]
+    events: Vec<String>,
+}
+
+fn event_types() -> Array {
+    Array::new(crate::openapi::string_enum(EVENT_TYPES))
+}
+
+/// A webhook without its secret.
+#[derive(Serialize, ToSchema)]
+#[schema(as = Webhook)]
+pub struct WebhookResponse {
+    pub id: String,
+    pub url: String,
+    #[schema(schema_with = event_types)]
+    pub events: Vec<String>,
+    pub created_at: u64,
+}
+
+impl From<WebhookRecord> for WebhookResponse {
+    fn from(webhook: WebhookRecord) -> Self {
+        Self {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events,
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+/// A new webhook; the signing secret is never shown again.
+#[derive(Serialize, ToSchema)]
+#[schema(as = CreatedWebhook)]
+pub struct CreatedWebhookResponse {
+    #[serde(flatten)]
+    pub webhook: WebhookResponse,
+    pub secret: String,
+}
+
+#[derive(Serialize, ToSchema)]
+#[schema(as = Webhooks)]
+pub struct WebhooksResponse {
+    pub webhooks: Vec<WebhookResponse>,
+}
+
+/// Notifications of the caller's order events, e.g. DELETE /account/webhooks/wh_...
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let webhooks = warp::path("account").and(warp::path("webhooks"));
+
+    let create_webhook = webhooks
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and(api_keys::signed_json(4 * 1024))
+        .and_then(handle_create_webhook)
+        .boxed();
+
+    let list_webhooks = webhooks
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_list_webhooks)
+        .boxed();
+
+    let delete_webhook = webhooks
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(warp::delete())
+        .and(rate_limited(state.clone(), RouteClass::Auth))
+        .and(authenticated(state.clone(), Scope::Read))
+        .and_then(handle_delete_webhook)
+        .boxed();
+
+    create_webhook.or(list_webhooks).or(delete_webhook)
+}
+
+/// Register a webhook for the caller's order events.
+#[utoipa::path(
+    post,
+    path = "/account/webhooks",
+    summary = "Register an HTTPS webhook for the caller's order events; the signing secret is only returned here",
+    description = "Each delivery is a POST of a Webhook notification, signed in X-Dex-Signature with hex HMAC-SHA256 of `<X-Dex-Timestamp>.<body>` under the secret. Failed deliveries are retried with exponential backoff; X-Dex-Delivery stays the same across retries.",
+    request_body = CreateWebhookRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 201, description = "New webhook and its secret", body = CreatedWebhookResponse),
+        (status = 400, description = "URL is not public HTTPS, or unknown event type", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 409, description = "The caller already has the most webhooks allowed", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_create_webhook(
+    claims: Claims,
+    state: ApiState,
+    req: CreateWebhookRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let invalid = |err: InvalidWebhook| {
+        error_reply("invalid_webhook", err.to_string(), StatusCode::BAD_REQUEST)
+    };
+    let url = match validate_url(&req.url) {
+        Ok(url) => url,
+        Err(err) => return Ok(invalid(err)),
+    };
+    let events = match validate_events(&req.events) {
+        Ok(events) => events,
+        Err(err) => return Ok(invalid(err)),
+    };
+    match state.webhook_repo.list_webhooks(&claims.sub).await {
+        Ok(existing) if existing.len() >= MAX_WEBHOOKS => {
+            return Ok(error_reply(
+                "too_many_webhooks",
+                format!("at most {} webhooks per trader", MAX_WEBHOOKS),
+                StatusCode::CONFLICT,
+            ));
+        }
+        Ok(_) => {}
+        Err(err) => {
+            tracing::error!(subject = %claims.sub, error = ?err, "failed to list webhooks");
+            return Ok(storage_error_reply(&err, "failed to create webhook"));
+        }
+    }
+
+    let secret = determinism::secret_string(32);
+    let record = WebhookRecord {
+        id: format!("wh_{}", state.determinism.random_string(16)),
+        trader_id: claims.sub,
+        url,
+        secret: secret.clone(),
+        events,
+        created_at: state.determinism.now().unwrap_or_default(),
+        revoked: false,
+    };
+    if let Err(err) = state.webhook_repo.save_webhook(&record).await {
+        tracing::error!(subject = %record.trader_id, error = ?err, "failed to store webhook");
+        return Ok(storage_error_reply(&err, "failed to create webhook"));
+    }
+    let response = CreatedWebhookResponse {
+        webhook: record.into(),
+        secret,
+    };
+    Ok(warp::reply::with_status(
+        warp::reply::json(&response),
+        StatusCode::CREATED,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/account/webhooks",
+    summary = "List the caller's webhooks, without secrets",
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Webhooks, oldest first", body = WebhooksResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_list_webhooks(
+    claims: Claims,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    match state.webhook_repo.list_webhooks(&claims.sub).await {
+        Ok(webhooks) => {
+            let response = WebhooksResponse {
+                webhooks: webhooks.into_iter().map(WebhookResponse::from).collect(),
+            };
+            Ok(warp::reply::with_status(
+                warp::reply::json(&response),
+                StatusCode::OK,
+            ))
+        }
+        Err(err) => {
+            tracing::error!(subject = %claims.sub, error = ?err, "failed to list webhooks");
+            Ok(storage_error_reply(&err, "failed to list webhooks"))
+        }
+    }
+}
+
+/// Revoke one of the caller's webhooks; deliveries still queued for it are
+/// dropped.
+#[utoipa::path(
+    delete,
+    path = "/account/webhooks/{webhook_id}",
+    summary = "Revoke one of the caller's webhooks and drop its queued deliveries",
+    params(("webhook_id" = String, Path)),
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 204, description = "Revoked"),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 404, description = "No webhook with this ID", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_delete_webhook(
+    webhook_id: String,
+    claims: Claims,
+    state: ApiState,
+) -> Result<warp::reply::Response, warp::Rejection> {
+    match state
+        .webhook_repo
+        .revoke_webhook(&webhook_id, &claims.sub)
+        .await
+    {
+        Ok(true) => Ok(warp::Reply::into_response(StatusCode::NO_CONTENT)),
+        Ok(false) => Ok(warp::Reply::into_response(error_reply(
+            "webhook_not_found",
+            "no webhook with this ID",
+            StatusCode::NOT_FOUND,
+        ))),
+        Err(err) => {
+            tracing::error!(%webhook_id, error = ?err, "failed to revoke webhook");
+            Ok(warp::Reply::into_response(storage_error_reply(
+                &err,
+                "failed to revoke webhook",
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        order_events::OrderStatus,
+        test_support::{test_state_with_seed, MemoryStorage},
+    };
+    use dex_core::types::TraderId;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn only_public_https_urls_are_accepted() {
+        assert_eq!(
+            validate_url("https://hooks.example.com/dex?team=1").as_deref(),
+            Ok("https://hooks.example.com/dex?team=1")
+        );
+        assert!(validate_url("https://hooks.example.com:8443").is_ok());
+        assert_eq!(
+            validate_url("http://hooks.example.com/"),
+            Err(InvalidWebhook::Url)
+        );
+        assert_eq!(
+            validate_url("https://user@evil.example/"),
+            Err(InvalidWebhook::Url)
+        );
+        for internal in [
+            "https://localhost/hook",
+            "https://127.0.0.1/hook",
+            "https://10.1.2.3:8443/hook",
+            "https://169.254.169.254/latest",
+            "https://100.100.100.200/latest",
+            "https://0.1.2.3/hook",
+            "https://[::1]/hook",
+        ] {
+            assert!(validate_url(internal).is_err(), "{internal}");
+        }
+        for (ip, public) in [
+            ("93.184.215.14", true),
+            ("100.64.0.1", false),
+            ("100.128.0.1", true),
+            ("224.0.0.1", false),
+            ("2606:2800:21f:cb07::1", true),
+            ("::ffff:10.0.0.1", false),
+            ("fd00::1", false),
+            ("fe80::1", false),
+            ("64:ff9b::a00:1", false),
+        ] {
+            assert_eq!(is_public(ip.parse().unwrap()), public, "{ip}");
+        }
+        assert_eq!(
+            split_url("https://a.example:8443?x=1").unwrap(),
+            ("https://a.example:8443", "/?x=1".to_string())
+        );
+        assert_eq!(
+            validate_events(&["fill".into(), "fill".into()]).unwrap(),
+            ["fill"]
+        );
+        assert!(validate_events(&["trade".into()]).is_err());
+    }
+
+    #[test]
+    fn retries_back_off_exponentially_up_to_an_hour() {
+        assert_eq!(retry_delay(1), Duration::from_secs(10));
+        assert_eq!(retry_delay(2), Duration::from_secs(20));
+        assert_eq!(retry_delay(4), Duration::from_secs(80));
+        assert_eq!(retry_delay(30), MAX_RETRY);
+    }
+
+    /// Accept one request, reply with `status` and return the request.
+    async fn endpoint(listener: &TcpListener, status: &str) -> String {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|line| {
+                        line.to_ascii_lowercase()
+                            .strip_prefix("content-length: ")
+                            .map(str::to_string)
+                    })
+                    .and_then(|length| length.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+        let reply = format!("HTTP/1.1 {status}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+        stream.write_all(reply.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    }
+
+    #[tokio::test]
+    async fn events_are_delivered_signed_and_retried_on_failure() {
+        let storage = Arc::new(MemoryStorage::default());
+        let state = test_state_with_seed(storage.clone(), 7);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Registered directly: the routes only accept public HTTPS URLs.
+        let webhook = WebhookRecord {
+            id: "wh_1".to_string(),
+            trader_id: "alice".to_string(),
+            url: format!("http://127.0.0.1:{port}/hooks/dex"),
+            secret: "s3cret".to_string(),
+            events: vec!["order_update".to_string()],
+            created_at: 0,
+            revoked: false,
+        };
+        state.webhook_repo.save_webhook(&webhook).await.unwrap();
+        let registry = RwLock::new(Registry::default());
+        refresh(&state, &registry).await;
+
+        let update = |trader: &str| UserEvent {
+            trader_id: TraderId::parse(trader).unwrap(),
+            event: OrderEvent::OrderUpdate {
+                order_id: 7,
+                status: OrderStatus::Cancelled,
+                pair: "ETH-USDC".to_string(),
+                side: "buy",
+                price: Some(100),
+                quantity: 5,
+                filled_quantity: 0,
+                remaining_quantity: 0,
+                timestamp: 1,
+            },
+        };
+        dispatch(&state, &registry, &update("alice")).await;
+        dispatch(&state, &registry, &update("bob")).await;
+
+        // The worker never connects to loopback addresses.
+        assert_eq!(deliver_due(&state, &registry, is_public).await, 1);
+        let pending = storage.webhook_deliveries.lock().unwrap()[0].clone();
+        assert_eq!(pending.attempts, 1);
+        assert_eq!(
+            pending.last_error.as_deref(),
+            Some("127.0.0.1 resolves to internal address 127.0.0.1")
+        );
+
+        // An attempt the endpoint refuses is rescheduled.
+        let anywhere = |_| true;
+        state.determinism.advance(retry_delay(1).as_secs());
+        let (refused, _) = tokio::join!(
+            endpoint(&listener, "503 Service Unavailable"),
+            deliver_due(&state, &registry, anywhere)
+        );
+        assert!(refused.starts_with("POST /hooks/dex HTTP/1.1"));
+        let pending = storage.webhook_deliveries.lock().unwrap()[0].clone();
+        assert_eq!(pending.status, DeliveryStatus::Pending);
+        assert_eq!(pending.attempts, 2);
+        assert_eq!(
+            pending.last_error.as_deref(),
+            Some("endpoint answered 503 Service Unavailable")
+        );
+        assert_eq!(
+            deliver_due(&state, &registry, anywhere).await,
+            0,
+            "not due yet"
+        );
+
+        state.determinism.advance(retry_delay(2).as_secs());
+        let (request, delivered) = tokio::join!(
+            endpoint(&listener, "204 No Content"),
+            deliver_due(&state, &registry, anywhere)
+        );
+        assert_eq!(delivered, 1, "bob has no webhook");
+        let delivery = storage.webhook_deliveries.lock().unwrap()[0].clone();
+        assert_eq!(delivery.status, DeliveryStatus::Delivered);
+
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        let header = |name: &str| {
+            head.lines()
+                .find_map(|line| line.strip_prefix(&format!("{name}: ")))
+                .unwrap()
+                .to_string()
+        };
+        let timestamp: u64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(header(SIGNATURE_HEADER), sign("s3cret", timestamp, body));
+        assert_eq!(header(DELIVERY_HEADER), delivery.id.to_string());
+        let notification: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(notification["webhook_id"], "wh_1");
+        assert_eq!(notification["event"]["status"], "cancelled");
+    }
+}
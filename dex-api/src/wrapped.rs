@@ -0,0 +1,383 @@
+//! Wrapped assets and the custody balances backing them.
+//!
+//! A token listed in `WRAPPED_ASSETS` is minted by bridge deposits only
+//! while its custody wallet holds the outstanding supply plus the deposit.
+//! Operators report custody balances through
+//! `PUT /admin/custody/{wallet_id}/balances` and book the burn of tokens
+//! whose backing was released through `POST /admin/wrapped/{token}/burns`.
+//! Each token's supply against its reserves is public at
+//! `GET /wrapped/{token}/supply` and part of ledger reconciliation.
+
+use crate::{
+    admin_only, api_keys, auth::Claims, error_reply, rate_limit::RouteClass, rate_limited,
+    storage_error_reply, with_state, ApiState, ErrorResponse,
+};
+use dex_core::{
+    ledger::{Account, AccountBalance, EntryKind, JournalEntry},
+    types::{TokenId, TraderId},
+    wrapped_assets::{Backing, WrappedAsset, WrappedAssetError},
+};
+use dex_db::{CustodyBalance, DatabaseError};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use warp::{http::StatusCode, Filter};
+
+/// What `asset`'s custody wallet was last reported to hold of it; nothing
+/// until a balance is reported.
+fn held(custody: &[CustodyBalance], asset: &WrappedAsset) -> u128 {
+    custody
+        .iter()
+        .find(|b| b.wallet_id == asset.custody_wallet && b.token == asset.token)
+        .map_or(0, |b| b.balance)
+}
+
+/// What `asset`'s custody wallet holds of it, as stored.
+pub async fn reserves(state: &ApiState, asset: &WrappedAsset) -> Result<u128, DatabaseError> {
+    let custody = state.custody_repo.load_custody_balances().await?;
+    Ok(held(&custody, asset))
+}
+
+/// Every wrapped asset's supply according to `balances`, against its
+/// custody wallet's balance.
+pub async fn backing(
+    state: &ApiState,
+    balances: &[AccountBalance],
+) -> Result<Vec<Backing>, DatabaseError> {
+    let custody = state.custody_repo.load_custody_balances().await?;
+    Ok(state
+        .bridge
+        .wrapped_assets()
+        .iter()
+        .map(|asset| asset.backing(balances, held(&custody, asset)))
+        .collect())
+}
+
+/// `token`'s supply against its reserves; `None` when it is not wrapped.
+pub async fn supply(state: &ApiState, token: &TokenId) -> Result<Option<Backing>, DatabaseError> {
+    let Some(asset) = state.bridge.wrapped_asset(token) else {
+        return Ok(None);
+    };
+    let balances = state.ledger_repo.ledger_balances().await?;
+    let reserves = reserves(state, asset).await?;
+    Ok(Some(asset.backing(&balances, reserves)))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CustodyBalanceRequest {
+    /// Wrapped token the wallet backs.
+    pub token: String,
+    pub balance: u128,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = CustodyBalance)]
+pub struct CustodyBalanceResponse {
+    pub wallet_id: String,
+    pub token: String,
+    pub balance: u128,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CustodyError {
+    #[error("wallet {wallet_id} does not back {token}")]
+    NotCustodian { wallet_id: String, token: String },
+    #[error("failed to store the custody balance: {0}")]
+    Storage(#[from] DatabaseError),
+}
+
+/// Record what custody wallet `wallet_id` holds of the token it backs.
+pub async fn report_custody(
+    state: &ApiState,
+    wallet_id: &str,
+    request: &CustodyBalanceRequest,
+) -> Result<CustodyBalanceResponse, CustodyError> {
+    let asset = TokenId::parse(&request.token)
+        .ok()
+        .and_then(|token| state.bridge.wrapped_asset(&token))
+        .filter(|asset| asset.custody_wallet == wallet_id)
+        .ok_or_else(|| CustodyError::NotCustodian {
+            wallet_id: wallet_id.to_string(),
+            token: request.token.clone(),
+        })?;
+    let balance = CustodyBalance {
+        wallet_id: wallet_id.to_string(),
+        token: asset.token.clone(),
+        balance: request.balance,
+        updated_at: state.determinism.now().unwrap_or_default(),
+    };
+    state.custody_repo.save_custody_balance(&balance).await?;
+    Ok(CustodyBalanceResponse {
+        wallet_id: balance.wallet_id,
+        token: balance.token.to_string(),
+        balance: balance.balance,
+        updated_at: balance.updated_at,
+    })
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[schema(as = WrappedBurnRequest)]
+pub struct BurnRequest {
+    /// Holder the tokens are burned from.
+    pub trader_id: String,
+    pub amount: u128,
+    /// Identifies the release from custody, so it is booked only once.
+    pub reference: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = WrappedBurn)]
+pub struct BurnResponse {
+    /// Ledger entry the burn was booked in.
+    pub entry_id: u64,
+    pub token: String,
+    pub trader_id: String,
+    pub amount: u128,
+    /// Supply left after the burn.
+    pub supply: u128,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BurnError {
+    #[error("{0} is not a wrapped asset")]
+    UnknownToken(String),
+    #[error("invalid {0}")]
+    Invalid(&'static str),
+    #[error(transparent)]
+    Rejected(#[from] WrappedAssetError),
+    #[error("burn {0} was already booked")]
+    AlreadyBooked(String),
+    #[error("failed to book the burn: {0}")]
+    Storage(#[from] DatabaseError),
+}
+
+/// Book `request.amount` of `token` from the trader back to the bridge,
+/// once per reference.
+pub async fn burn(
+    state: &ApiState,
+    token: &str,
+    request: &BurnRequest,
+) -> Result<BurnResponse, BurnError> {
+    let asset = TokenId::parse(token)
+        .ok()
+        .and_then(|token| state.bridge.wrapped_asset(&token))
+        .ok_or_else(|| BurnError::UnknownToken(token.to_string()))?;
+    let trader =
+        TraderId::parse(&request.trader_id).map_err(|_| BurnError::Invalid("trader_id"))?;
+    if request.amount == 0 {
+        return Err(BurnError::Invalid("amount"));
+    }
+    if request.reference.trim().is_empty() {
+        return Err(BurnError::Invalid("reference"));
+    }
+
+    let _supply = state.bridge.lock_supply().await;
+    let balances = state.ledger_repo.ledger_balances().await?;
+    let holder = Account::Trader(trader.to_string());
+    asset.check_burn(&balances, &holder, request.amount)?;
+    let reference = format!("{}:{}", asset.token, request.reference);
+    let timestamp = state.determinism.now().unwrap_or_default();
+    let entry = JournalEntry::new(EntryKind::Burn, reference, timestamp).transfer(
+        holder,
+        Account::Bridge(asset.chain.clone()),
+        &asset.token,
+        request.amount,
+    );
+    let entry_id = state
+        .ledger_repo
+        .save_entry(&entry)
+        .await?
+        .ok_or_else(|| BurnError::AlreadyBooked(request.reference.clone()))?;
+    Ok(BurnResponse {
+        entry_id,
+        token: asset.token.to_string(),
+        trader_id: trader.to_string(),
+        amount: request.amount,
+        supply: asset.supply(&balances) - request.amount,
+    })
+}
+
+/// Supply of a wrapped asset, e.g. GET /wrapped/WETH/supply
+pub(crate) fn routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    // Supply of a wrapped asset against the custody balance backing it
+    warp::path("wrapped")
+        .and(warp::path::param::<String>())
+        .and(warp::path("supply"))
+        .and(warp::path::end())
+        .and(warp::get())
+        .and(rate_limited(state.clone(), RouteClass::MarketData))
+        .and(with_state(state.clone()))
+        .and_then(handle_get_wrapped_supply)
+        .boxed()
+}
+
+/// Custody balances and burns of wrapped assets, e.g. POST /admin/wrapped/WETH/burns
+pub(crate) fn admin_routes(
+    state: ApiState,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let custody_balance = warp::path("admin")
+        .and(warp::path("custody"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("balances"))
+        .and(warp::path::end())
+        .and(warp::put())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_report_custody_balance)
+        .boxed();
+
+    let wrapped_burn = warp::path("admin")
+        .and(warp::path("wrapped"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("burns"))
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(rate_limited(state.clone(), RouteClass::OrderEntry))
+        .and(admin_only(state.clone()))
+        .and(api_keys::signed_json(2 * 1024))
+        .and_then(handle_burn_wrapped)
+        .boxed();
+
+    custody_balance.or(wrapped_burn)
+}
+
+/// Supply of a wrapped asset and the custody balance backing it.
+#[utoipa::path(
+    get,
+    path = "/wrapped/{token}/supply",
+    summary = "Supply of a wrapped asset against the custody balance backing it",
+    params(("token" = String, Path)),
+    responses(
+        (status = 200, description = "Supply and reserves", body = Backing),
+        (status = 404, description = "Not a wrapped asset", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_get_wrapped_supply(
+    token: String,
+    state: ApiState,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let backing = match TokenId::parse(&token) {
+        Ok(token) => supply(&state, &token).await,
+        Err(_) => Ok(None),
+    };
+    match backing {
+        Ok(Some(backing)) => Ok(warp::reply::with_status(
+            warp::reply::json(&backing),
+            StatusCode::OK,
+        )),
+        Ok(None) => Ok(error_reply(
+            "wrapped_asset_not_found",
+            format!("{} is not a wrapped asset", token),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to load wrapped supply");
+            Ok(storage_error_reply(&err, "failed to load wrapped supply"))
+        }
+    }
+}
+
+/// Record what a custody wallet holds of the wrapped asset it backs.
+#[utoipa::path(
+    put,
+    path = "/admin/custody/{wallet_id}/balances",
+    summary = "Record what a custody wallet holds of the wrapped asset it backs (administrators only)",
+    params(("wallet_id" = String, Path)),
+    request_body = CustodyBalanceRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 200, description = "Balance recorded", body = CustodyBalanceResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 422, description = "The wallet does not back this token", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_report_custody_balance(
+    wallet_id: String,
+    _claims: Claims,
+    state: ApiState,
+    req: CustodyBalanceRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    use CustodyError;
+    match report_custody(&state, &wallet_id, &req).await {
+        Ok(response) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            StatusCode::OK,
+        )),
+        Err(err @ CustodyError::NotCustodian { .. }) => Ok(error_reply(
+            "not_custodian",
+            err.to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )),
+        Err(CustodyError::Storage(err)) => {
+            tracing::error!(error = ?err, "failed to store custody balance");
+            Ok(storage_error_reply(
+                &err,
+                "failed to store the custody balance",
+            ))
+        }
+    }
+}
+
+/// Book the burn of wrapped tokens whose backing left custody.
+#[utoipa::path(
+    post,
+    path = "/admin/wrapped/{token}/burns",
+    summary = "Burn wrapped tokens whose backing was released from custody (administrators only)",
+    params(("token" = String, Path)),
+    request_body = BurnRequest,
+    security(("bearerAuth" = []), ("apiKeySignature" = []), ("clientCertificate" = [])),
+    responses(
+        (status = 201, description = "Burn booked", body = BurnResponse),
+        (status = 400, description = "Invalid trader, amount or reference", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid token", body = ErrorResponse),
+        (status = 403, description = "Caller is not an administrator", body = ErrorResponse),
+        (status = 404, description = "Not a wrapped asset", body = ErrorResponse),
+        (status = 409, description = "Burn already booked", body = ErrorResponse),
+        (status = 422, description = "Burn exceeds the supply or the trader's balance", body = ErrorResponse),
+        (status = 503, description = "Storage unavailable", body = ErrorResponse),
+    ),
+)]
+async fn handle_burn_wrapped(
+    token: String,
+    _claims: Claims,
+    state: ApiState,
+    req: BurnRequest,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    use BurnError;
+    match burn(&state, &token, &req).await {
+        Ok(response) => Ok(warp::reply::with_status(
+            warp::reply::json(&response),
+            StatusCode::CREATED,
+        )),
+        Err(err @ BurnError::UnknownToken(_)) => Ok(error_reply(
+            "wrapped_asset_not_found",
+            err.to_string(),
+            StatusCode::NOT_FOUND,
+        )),
+        Err(err @ BurnError::Invalid(_)) => Ok(error_reply(
+            "invalid_request",
+            err.to_string(),
+            StatusCode::BAD_REQUEST,
+        )),
+        Err(BurnError::Rejected(err)) => Ok(error_reply(
+            "burn_rejected",
+            err.to_string(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )),
+        Err(err @ BurnError::AlreadyBooked(_)) => Ok(error_reply(
+            "burn_already_booked",
+            err.to_string(),
+            StatusCode::CONFLICT,
+        )),
+        Err(BurnError::Storage(err)) => {
+            tracing::error!(error = ?err, "failed to book wrapped burn");
+            Ok(storage_error_reply(&err, "failed to book the burn"))
+        }
+    }
+}
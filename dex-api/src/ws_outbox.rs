@@ -0,0 +1,337 @@
+//! Bounded send queues between WebSocket sessions and their clients.
+//!
+//! A session queues its frames here and a writer task sends them, so a
+//! client that reads slowly no longer holds up the session and makes it lag
+//! behind the broadcast channels. Snapshot frames (depth, ticker and pool)
+//! are conflated: a newer snapshot replaces the one still queued for the same
+//! channel. Event frames each take a slot, and once `WS_SEND_QUEUE_FRAMES` of
+//! them are waiting the client is too slow: by default it is disconnected,
+//! and with `WS_SLOW_CONSUMER_POLICY=drop` it loses the new events instead
+//! and is sent a `lagged` notice for each channel once there is room again.
+
+use crate::subscriptions::ServerMessage;
+use futures_util::{stream::SplitSink, SinkExt};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{sync::Notify, task::JoinHandle};
+use warp::ws::{Message, WebSocket};
+
+/// How long a closing connection gets to take its close frame.
+const CLOSE_GRACE: Duration = Duration::from_secs(5);
+
+/// What happens to a client whose send queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    /// Close the connection with 1008 "slow consumer".
+    Disconnect,
+    /// Drop new events and report how many with `lagged` notices.
+    Drop,
+}
+
+impl FromStr for SlowConsumerPolicy {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "disconnect" => Ok(SlowConsumerPolicy::Disconnect),
+            "drop" => Ok(SlowConsumerPolicy::Drop),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Send queue limits for WebSocket sessions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsBackpressure {
+    /// Most event frames waiting for one client.
+    pub queue_frames: usize,
+    pub policy: SlowConsumerPolicy,
+}
+
+impl Default for WsBackpressure {
+    fn default() -> Self {
+        Self {
+            queue_frames: 256,
+            policy: SlowConsumerPolicy::Disconnect,
+        }
+    }
+}
+
+/// The client fell too far behind and must be disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlowConsumer;
+
+enum Queued {
+    /// The latest snapshot of a channel; replaced rather than queued behind.
+    Snapshot {
+        channel: String,
+        text: String,
+    },
+    Event(String),
+    Control(Message),
+}
+
+#[derive(Default)]
+struct Queue {
+    frames: VecDeque<Queued>,
+    /// Event frames in `frames`.
+    events: usize,
+    /// Events dropped per channel and not yet reported.
+    skipped: BTreeMap<String, u64>,
+    /// Set once the connection is closing; nothing more is queued.
+    closing: bool,
+}
+
+impl Queue {
+    /// Queue a `lagged` notice for each channel that lost events, while
+    /// there is room.
+    fn report_skipped(&mut self, capacity: usize) {
+        while self.events < capacity {
+            let Some((channel, skipped)) = self.skipped.pop_first() else {
+                break;
+            };
+            let notice = ServerMessage::Lagged { channel, skipped };
+            if let Ok(text) = serde_json::to_string(&notice) {
+                self.frames.push_back(Queued::Event(text));
+                self.events += 1;
+            }
+        }
+    }
+}
+
+/// Frames waiting to be sent to one client.
+pub struct WsOutbox {
+    config: WsBackpressure,
+    queue: Mutex<Queue>,
+    ready: Notify,
+}
+
+impl WsOutbox {
+    pub fn new(config: WsBackpressure) -> Self {
+        Self {
+            config: WsBackpressure {
+                queue_frames: config.queue_frames.max(1),
+                ..config
+            },
+            queue: Mutex::default(),
+            ready: Notify::new(),
+        }
+    }
+
+    /// Queue a snapshot of `channel`, replacing one still waiting.
+    pub fn snapshot(&self, channel: &str, text: String) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closing {
+            return;
+        }
+        let waiting = queue.frames.iter_mut().find_map(|frame| match frame {
+            Queued::Snapshot {
+                channel: queued,
+                text,
+            } if queued == channel => Some(text),
+            _ => None,
+        });
+        match waiting {
+            Some(waiting) => *waiting = text,
+            None => {
+                queue.frames.push_back(Queued::Snapshot {
+                    channel: channel.to_string(),
+                    text,
+                });
+                self.ready.notify_one();
+            }
+        }
+    }
+
+    /// Queue an event of `channel`, or fail when the client is too slow to
+    /// keep and must be disconnected.
+    pub fn event(&self, channel: &str, text: String) -> Result<(), SlowConsumer> {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closing {
+            return Ok(());
+        }
+        let capacity = self.config.queue_frames;
+        queue.report_skipped(capacity);
+        if queue.events >= capacity {
+            match self.config.policy {
+                SlowConsumerPolicy::Disconnect => return Err(SlowConsumer),
+                SlowConsumerPolicy::Drop => {
+                    *queue.skipped.entry(channel.to_string()).or_default() += 1;
+                    return Ok(());
+                }
+            }
+        }
+        queue.frames.push_back(Queued::Event(text));
+        queue.events += 1;
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    /// Queue a frame that is never dropped, such as a ping or an ack.
+    pub fn control(&self, message: Message) {
+        let mut queue = self.queue.lock().unwrap();
+        if !queue.closing {
+            queue.frames.push_back(Queued::Control(message));
+            self.ready.notify_one();
+        }
+    }
+
+    /// Discard whatever is still queued and send `message` as the last frame.
+    pub fn close(&self, message: Message) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.closing {
+            return;
+        }
+        queue.frames.clear();
+        queue.events = 0;
+        queue.skipped.clear();
+        queue.frames.push_back(Queued::Control(message));
+        queue.closing = true;
+        self.ready.notify_one();
+    }
+
+    /// The next frame to send, once there is one.
+    async fn next(&self) -> Message {
+        loop {
+            {
+                let mut queue = self.queue.lock().unwrap();
+                if queue.frames.is_empty() {
+                    queue.report_skipped(self.config.queue_frames);
+                }
+                if let Some(frame) = queue.frames.pop_front() {
+                    return match frame {
+                        Queued::Snapshot { text, .. } => Message::text(text),
+                        Queued::Event(text) => {
+                            queue.events -= 1;
+                            Message::text(text)
+                        }
+                        Queued::Control(message) => message,
+                    };
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+}
+
+/// Task sending an outbox's frames to its client.
+pub struct WsWriter {
+    outbox: Arc<WsOutbox>,
+    handle: JoinHandle<()>,
+    stopped: bool,
+}
+
+impl WsWriter {
+    /// Start sending `outbox` to `sink`, until a close frame has been sent or
+    /// the client is gone.
+    pub fn spawn(outbox: Arc<WsOutbox>, mut sink: SplitSink<WebSocket, Message>) -> Self {
+        let queued = outbox.clone();
+        let handle = tokio::spawn(async move {
+            loop {
+                let message = queued.next().await;
+                let close = message.is_close();
+                if sink.send(message).await.is_err() || close {
+                    break;
+                }
+            }
+        });
+        Self {
+            outbox,
+            handle,
+            stopped: false,
+        }
+    }
+
+    /// Resolves once the writer has stopped, such as when the client
+    /// disconnects.
+    pub async fn stopped(&mut self) {
+        if !self.stopped {
+            let _ = (&mut self.handle).await;
+            self.stopped = true;
+        }
+    }
+
+    /// Close the connection, unless a close frame is already queued, and
+    /// give it a moment to go out before dropping the connection.
+    pub async fn finish(mut self) {
+        if self.stopped {
+            return;
+        }
+        self.outbox.close(Message::close());
+        if tokio::time::timeout(CLOSE_GRACE, &mut self.handle)
+            .await
+            .is_err()
+        {
+            self.handle.abort();
+        }
+    }
+}
+
+/// Close frame for a client that fell too far behind.
+pub fn slow_consumer_close_message() -> Message {
+    // 1008 "policy violation": the client did not keep up with its stream.
+    Message::close_with(1008u16, "slow consumer")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(message: Message) -> String {
+        message.to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn snapshots_are_conflated_and_events_disconnect_when_full() {
+        let outbox = WsOutbox::new(WsBackpressure {
+            queue_frames: 2,
+            policy: SlowConsumerPolicy::Disconnect,
+        });
+        outbox.snapshot("depth:ETH-USDC", "depth 1".into());
+        outbox.event("trades:ETH-USDC", "trade 1".into()).unwrap();
+        outbox.snapshot("depth:ETH-USDC", "depth 2".into());
+        outbox.snapshot("ticker:ETH-USDC", "ticker 1".into());
+        outbox.event("trades:ETH-USDC", "trade 2".into()).unwrap();
+        assert_eq!(
+            outbox.event("trades:ETH-USDC", "trade 3".into()),
+            Err(SlowConsumer)
+        );
+
+        let mut sent = Vec::new();
+        for _ in 0..4 {
+            sent.push(text(outbox.next().await));
+        }
+        assert_eq!(sent, ["depth 2", "trade 1", "ticker 1", "trade 2"]);
+
+        // Sending made room again.
+        outbox.event("trades:ETH-USDC", "trade 3".into()).unwrap();
+        assert_eq!(text(outbox.next().await), "trade 3");
+    }
+
+    #[tokio::test]
+    async fn dropped_events_are_reported_once_there_is_room() {
+        let outbox = WsOutbox::new(WsBackpressure {
+            queue_frames: 1,
+            policy: SlowConsumerPolicy::Drop,
+        });
+        outbox.event("orders", "fill 1".into()).unwrap();
+        outbox.event("orders", "fill 2".into()).unwrap();
+        outbox.event("orders", "fill 3".into()).unwrap();
+        outbox.control(Message::ping(Vec::new()));
+
+        assert_eq!(text(outbox.next().await), "fill 1");
+        assert!(outbox.next().await.is_ping());
+        let notice: serde_json::Value = serde_json::from_str(&text(outbox.next().await)).unwrap();
+        assert_eq!(notice["type"], "lagged");
+        assert_eq!(notice["channel"], "orders");
+        assert_eq!(notice["skipped"], 2);
+
+        outbox.close(slow_consumer_close_message());
+        outbox.event("orders", "fill 4".into()).unwrap();
+        assert!(outbox.next().await.is_close());
+    }
+}
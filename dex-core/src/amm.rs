@@ -507,8 +507,8 @@ mod tests {
     #[test]
     fn test_add_liquidity() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "BTC".to_string();
-        let token_b = "USD".to_string();
+        let token_a: TokenId = "BTC".parse().unwrap();
+        let token_b: TokenId = "USD".parse().unwrap();
 
         let liquidity_tokens = amm
             .add_liquidity(
@@ -528,8 +528,8 @@ mod tests {
     #[test]
     fn test_add_liquidity_concentrated() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "BTC".to_string();
-        let token_b = "USD".to_string();
+        let token_a: TokenId = "BTC".parse().unwrap();
+        let token_b: TokenId = "USD".parse().unwrap();
 
         let liquidity_tokens = amm
             .add_liquidity_concentrated(
@@ -560,8 +560,8 @@ mod tests {
     #[test]
     fn test_remove_liquidity_concentrated() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "BTC".to_string();
-        let token_b = "USD".to_string();
+        let token_a: TokenId = "BTC".parse().unwrap();
+        let token_b: TokenId = "USD".parse().unwrap();
 
         // Add liquidity first
         let liquidity_tokens = amm
@@ -588,8 +588,8 @@ mod tests {
     #[test]
     fn test_get_liquidity_at_tick() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "BTC".to_string();
-        let token_b = "USD".to_string();
+        let token_a: TokenId = "BTC".parse().unwrap();
+        let token_b: TokenId = "USD".parse().unwrap();
 
         // Add liquidity to specific ticks
         amm.add_liquidity_concentrated(token_a, token_b, 1000, 50000000, -50, 50)
@@ -606,8 +606,8 @@ mod tests {
     #[test]
     fn test_get_active_ticks() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "BTC".to_string();
-        let token_b = "USD".to_string();
+        let token_a: TokenId = "BTC".parse().unwrap();
+        let token_b: TokenId = "USD".parse().unwrap();
 
         // Add liquidity to specific ticks
         amm.add_liquidity_concentrated(token_a, token_b, 1000, 50000000, -10, 10)
@@ -626,8 +626,8 @@ mod tests {
     #[test]
     fn test_find_price_in_range() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "DAI".to_string();
-        let token_b = "USDC".to_string();
+        let token_a: TokenId = "DAI".parse().unwrap();
+        let token_b: TokenId = "USDC".parse().unwrap();
 
         // Add initial liquidity
         amm.add_liquidity(
@@ -650,8 +650,8 @@ mod tests {
     #[test]
     fn test_find_price_in_range_not_found() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "DAI".to_string();
-        let token_b = "USDC".to_string();
+        let token_a: TokenId = "DAI".parse().unwrap();
+        let token_b: TokenId = "USDC".parse().unwrap();
 
         // Add initial liquidity
         amm.add_liquidity(
@@ -673,8 +673,8 @@ mod tests {
     #[test]
     fn test_is_price_within_slippage() {
         let mut amm = ConstantProductAMM::new(30);
-        let token_a = "DAI".to_string();
-        let token_b = "USDC".to_string();
+        let token_a: TokenId = "DAI".parse().unwrap();
+        let token_b: TokenId = "USDC".parse().unwrap();
 
         // Add initial liquidity
         amm.add_liquidity(
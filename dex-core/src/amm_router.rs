@@ -0,0 +1,473 @@
+//! Multi-hop and split route quoting over constant product pools
+//!
+//! Unlike `path_routing`, which ranks paths by spot exchange rates, this
+//! module walks each candidate path through the pools' actual reserves with
+//! the same integer curve `ConstantProductAMM::swap` uses, so a quote is
+//! exactly what the server would pay out against the same reserves. It works
+//! on a `PoolSnapshot`, a plain serializable copy of every pool tagged with the
+//! server's pool sequence, so frontends can quote offline from a synced
+//! snapshot and tell when it has gone stale.
+
+use crate::types::{Quantity, TokenId, TradingPair};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Reserves and fee of one pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PoolState {
+    #[cfg_attr(feature = "openapi", schema(inline))]
+    pub pair: TradingPair,
+    pub reserve_base: u128,
+    pub reserve_quote: u128,
+    pub fee_bps: u32,
+}
+
+impl PoolState {
+    /// Reserves as (in, out) when swapping `token_in`, or `None` if the pool
+    /// does not hold it.
+    fn reserves_from(&self, token_in: &TokenId) -> Option<(u128, u128)> {
+        if token_in == self.pair.base() {
+            Some((self.reserve_base, self.reserve_quote))
+        } else if token_in == self.pair.quote() {
+            Some((self.reserve_quote, self.reserve_base))
+        } else {
+            None
+        }
+    }
+
+    /// What swapping `amount_in` of `token_in` would pay out, or `None` if the
+    /// pool cannot fill it.
+    pub fn amount_out(&self, token_in: &TokenId, amount_in: Quantity) -> Option<Quantity> {
+        let (reserve_in, reserve_out) = self.reserves_from(token_in)?;
+        if reserve_in == 0 || reserve_out == 0 || self.fee_bps > 10_000 {
+            return None;
+        }
+        let amount_in_with_fee = u128::from(amount_in) * u128::from(10_000 - self.fee_bps);
+        let numerator = amount_in_with_fee.checked_mul(reserve_out)?;
+        let denominator = reserve_in
+            .checked_mul(10_000)?
+            .checked_add(amount_in_with_fee)?;
+        let amount_out = numerator / denominator;
+        if amount_out >= reserve_out {
+            return None;
+        }
+        Quantity::try_from(amount_out).ok()
+    }
+
+    /// Apply a swap already quoted with `amount_out`.
+    fn apply(&mut self, token_in: &TokenId, amount_in: Quantity, amount_out: Quantity) {
+        let (amount_in, amount_out) = (u128::from(amount_in), u128::from(amount_out));
+        if token_in == self.pair.base() {
+            self.reserve_base += amount_in;
+            self.reserve_quote -= amount_out;
+        } else {
+            self.reserve_quote += amount_in;
+            self.reserve_base -= amount_out;
+        }
+    }
+}
+
+/// Every pool at one point of the server's pool sequence.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct PoolSnapshot {
+    /// Advances whenever any pool may have changed.
+    pub sequence: u64,
+    pub pools: Vec<PoolState>,
+}
+
+/// The outcome of sending `amount_in` along one path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RouteQuote {
+    /// Tokens visited, from the input to the output token.
+    pub tokens: Vec<TokenId>,
+    /// Pool swapped through at each hop.
+    pub pools: Vec<TradingPair>,
+    pub amount_in: Quantity,
+    pub amount_out: Quantity,
+}
+
+/// An order split across several paths.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitQuote {
+    /// Each path with its share of the input, in execution order.
+    pub routes: Vec<RouteQuote>,
+    pub amount_in: Quantity,
+    pub amount_out: Quantity,
+    /// Pool sequence of the snapshot the quote was computed from.
+    pub sequence: u64,
+}
+
+/// Why a route could not be quoted.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum RouteError {
+    #[error("Amount must be positive")]
+    ZeroAmount,
+    #[error("Input and output token are the same")]
+    SameToken,
+    #[error("No route from {0} to {1}")]
+    NoRoute(TokenId, TokenId),
+}
+
+/// One hop of a path: the pool index and the token sent into it.
+type Hop = (usize, TokenId);
+
+/// Quotes multi-hop and split routes against a pool snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct AmmRouter {
+    snapshot: PoolSnapshot,
+}
+
+impl AmmRouter {
+    pub fn new(snapshot: PoolSnapshot) -> Self {
+        Self { snapshot }
+    }
+
+    /// Pool sequence of the snapshot being quoted against.
+    pub fn sequence(&self) -> u64 {
+        self.snapshot.sequence
+    }
+
+    /// Whether quotes still match a server whose pools are at
+    /// `server_sequence`; if not, the snapshot should be fetched again.
+    pub fn is_fresh(&self, server_sequence: u64) -> bool {
+        self.snapshot.sequence == server_sequence
+    }
+
+    pub fn snapshot(&self) -> &PoolSnapshot {
+        &self.snapshot
+    }
+
+    /// Every path from `from` to `to` of at most `max_hops` pools that visits
+    /// no token twice, shortest first.
+    fn paths(&self, from: &TokenId, to: &TokenId, max_hops: usize) -> Vec<Vec<Hop>> {
+        let mut paths = Vec::new();
+        let mut visited = vec![from.clone()];
+        let mut path = Vec::new();
+        self.extend_paths(to, max_hops, &mut visited, &mut path, &mut paths);
+        paths.sort_by_key(|path| path.len());
+        paths
+    }
+
+    fn extend_paths(
+        &self,
+        to: &TokenId,
+        max_hops: usize,
+        visited: &mut Vec<TokenId>,
+        path: &mut Vec<Hop>,
+        paths: &mut Vec<Vec<Hop>>,
+    ) {
+        if path.len() == max_hops {
+            return;
+        }
+        let token = visited.last().cloned().expect("path starts at a token");
+        for (index, pool) in self.snapshot.pools.iter().enumerate() {
+            let next = if &token == pool.pair.base() {
+                pool.pair.quote()
+            } else if &token == pool.pair.quote() {
+                pool.pair.base()
+            } else {
+                continue;
+            };
+            if visited.contains(next) {
+                continue;
+            }
+            path.push((index, token.clone()));
+            if next == to {
+                paths.push(path.clone());
+            } else {
+                visited.push(next.clone());
+                self.extend_paths(to, max_hops, visited, path, paths);
+                visited.pop();
+            }
+            path.pop();
+        }
+    }
+
+    /// Output of `amount_in` along `path` through `pools`, without changing
+    /// them. A path never visits a token twice, so it uses each pool once.
+    fn amount_out(pools: &[PoolState], path: &[Hop], amount_in: Quantity) -> Option<Quantity> {
+        path.iter()
+            .try_fold(amount_in, |amount, (index, token_in)| {
+                pools[*index].amount_out(token_in, amount)
+            })
+    }
+
+    /// Swap `amount_in` along `path` through `pools`, returning the output.
+    fn execute(pools: &mut [PoolState], path: &[Hop], amount_in: Quantity) -> Option<Quantity> {
+        let mut amount = amount_in;
+        for (index, token_in) in path {
+            let out = pools[*index].amount_out(token_in, amount)?;
+            pools[*index].apply(token_in, amount, out);
+            amount = out;
+        }
+        Some(amount)
+    }
+
+    fn route_quote(&self, path: &[Hop], amount_in: Quantity, amount_out: Quantity) -> RouteQuote {
+        let pools = &self.snapshot.pools;
+        let mut tokens: Vec<TokenId> = path.iter().map(|(_, token)| token.clone()).collect();
+        if let Some((index, token_in)) = path.last() {
+            let pair = &pools[*index].pair;
+            let out = if token_in == pair.base() {
+                pair.quote()
+            } else {
+                pair.base()
+            };
+            tokens.push(out.clone());
+        }
+        RouteQuote {
+            tokens,
+            pools: path
+                .iter()
+                .map(|(index, _)| pools[*index].pair.clone())
+                .collect(),
+            amount_in,
+            amount_out,
+        }
+    }
+
+    fn check(from: &TokenId, to: &TokenId, amount_in: Quantity) -> Result<(), RouteError> {
+        if amount_in == 0 {
+            return Err(RouteError::ZeroAmount);
+        }
+        if from == to {
+            return Err(RouteError::SameToken);
+        }
+        Ok(())
+    }
+
+    /// The single path of at most `max_hops` pools that pays the most for
+    /// `amount_in` of `from`; ties go to the shorter path.
+    pub fn quote(
+        &self,
+        from: &TokenId,
+        to: &TokenId,
+        amount_in: Quantity,
+        max_hops: usize,
+    ) -> Result<RouteQuote, RouteError> {
+        Self::check(from, to, amount_in)?;
+        let no_route = || RouteError::NoRoute(from.clone(), to.clone());
+        let mut best: Option<(Vec<Hop>, Quantity)> = None;
+        for path in self.paths(from, to, max_hops) {
+            if let Some(out) = Self::amount_out(&self.snapshot.pools, &path, amount_in) {
+                if best.as_ref().is_none_or(|(_, best_out)| out > *best_out) {
+                    best = Some((path, out));
+                }
+            }
+        }
+        let (path, out) = best.ok_or_else(no_route)?;
+        Ok(self.route_quote(&path, amount_in, out))
+    }
+
+    /// Split `amount_in` of `from` into `parts` equal chunks and send each
+    /// down whichever path pays most for it given the chunks already sent,
+    /// so deep paths absorb more of a large order than shallow ones. Falls
+    /// back to the best single path when splitting does not pay more.
+    pub fn quote_split(
+        &self,
+        from: &TokenId,
+        to: &TokenId,
+        amount_in: Quantity,
+        max_hops: usize,
+        parts: u32,
+    ) -> Result<SplitQuote, RouteError> {
+        let single = self.quote(from, to, amount_in, max_hops)?;
+        let single = SplitQuote {
+            amount_in,
+            amount_out: single.amount_out,
+            routes: vec![single],
+            sequence: self.snapshot.sequence,
+        };
+        let paths = self.paths(from, to, max_hops);
+        let parts = Quantity::from(parts.max(1)).min(amount_in);
+        if paths.len() < 2 || parts < 2 {
+            return Ok(single);
+        }
+
+        let chunk = amount_in / parts;
+        let mut pools = self.snapshot.pools.clone();
+        let mut allocated = vec![0 as Quantity; paths.len()];
+        for part in 0..parts {
+            // The remainder rides with the first chunk.
+            let amount = if part == 0 {
+                chunk + amount_in % parts
+            } else {
+                chunk
+            };
+            let best = paths
+                .iter()
+                .enumerate()
+                .filter_map(|(i, path)| Some((i, Self::amount_out(&pools, path, amount)?)))
+                .fold(
+                    None,
+                    |best: Option<(usize, Quantity)>, (i, out)| match best {
+                        Some((_, best_out)) if best_out >= out => best,
+                        _ => Some((i, out)),
+                    },
+                );
+            let Some((i, _)) = best else {
+                return Ok(single);
+            };
+            Self::execute(&mut pools, &paths[i], amount);
+            allocated[i] += amount;
+        }
+
+        // Quote each path's whole share in one swap, as it would execute.
+        let mut pools = self.snapshot.pools.clone();
+        let mut routes = Vec::new();
+        for (path, amount) in paths.iter().zip(allocated) {
+            if amount == 0 {
+                continue;
+            }
+            let Some(out) = Self::execute(&mut pools, path, amount) else {
+                return Ok(single);
+            };
+            routes.push(self.route_quote(path, amount, out));
+        }
+        let amount_out = routes.iter().map(|route| route.amount_out).sum();
+        if amount_out <= single.amount_out {
+            return Ok(single);
+        }
+        Ok(SplitQuote {
+            routes,
+            amount_in,
+            amount_out,
+            sequence: self.snapshot.sequence,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::amm::ConstantProductAMM;
+
+    fn token(symbol: &str) -> TokenId {
+        TokenId::parse(symbol).unwrap()
+    }
+
+    fn pool(pair: &str, reserve_base: u128, reserve_quote: u128) -> PoolState {
+        PoolState {
+            pair: pair.parse().unwrap(),
+            reserve_base,
+            reserve_quote,
+            fee_bps: 30,
+        }
+    }
+
+    fn router(pools: Vec<PoolState>) -> AmmRouter {
+        AmmRouter::new(PoolSnapshot { sequence: 7, pools })
+    }
+
+    #[test]
+    fn single_hop_matches_the_amm_curve() {
+        let mut amm = ConstantProductAMM::new(30);
+        amm.add_liquidity(token("ETH"), 1_000, token("USDC"), 2_000_000)
+            .unwrap();
+        let router = router(vec![pool("ETH-USDC", 1_000, 2_000_000)]);
+
+        let quote = router.quote(&token("ETH"), &token("USDC"), 10, 3).unwrap();
+        let paid = amm.swap(token("ETH"), token("USDC"), 10).unwrap();
+        assert_eq!(quote.amount_out, paid);
+        assert_eq!(quote.tokens, vec![token("ETH"), token("USDC")]);
+
+        let back = router
+            .quote(&token("USDC"), &token("ETH"), 20_000, 3)
+            .unwrap();
+        assert_eq!(back.amount_out, 9);
+    }
+
+    #[test]
+    fn finds_the_better_multi_hop_path() {
+        // BTC is cheap against USDC in the BTC pools, so ETH -> BTC -> USDC
+        // pays more than the shallow direct pool.
+        let router = router(vec![
+            pool("ETH-USDC", 100, 150_000),
+            pool("ETH-BTC", 100_000, 5_000),
+            pool("BTC-USDC", 10_000, 400_000_000),
+        ]);
+        let quote = router.quote(&token("ETH"), &token("USDC"), 50, 3).unwrap();
+        assert_eq!(
+            quote.tokens,
+            vec![token("ETH"), token("BTC"), token("USDC")]
+        );
+        assert_eq!(quote.pools.len(), 2);
+
+        let direct = router.quote(&token("ETH"), &token("USDC"), 50, 1).unwrap();
+        assert_eq!(direct.tokens, vec![token("ETH"), token("USDC")]);
+        assert!(direct.amount_out < quote.amount_out);
+    }
+
+    #[test]
+    fn splits_large_orders_across_paths() {
+        let router = router(vec![
+            pool("ETH-USDC", 1_000, 2_000_000),
+            pool("ETH-DAI", 1_000, 2_000_000),
+            pool("DAI-USDC", 10_000_000, 10_000_000),
+        ]);
+        let single = router.quote(&token("ETH"), &token("USDC"), 500, 2).unwrap();
+        let split = router
+            .quote_split(&token("ETH"), &token("USDC"), 500, 2, 10)
+            .unwrap();
+        assert_eq!(split.sequence, 7);
+        assert_eq!(split.routes.len(), 2);
+        assert_eq!(
+            split
+                .routes
+                .iter()
+                .map(|route| route.amount_in)
+                .sum::<Quantity>(),
+            500
+        );
+        assert_eq!(
+            split
+                .routes
+                .iter()
+                .map(|route| route.amount_out)
+                .sum::<Quantity>(),
+            split.amount_out
+        );
+        assert!(split.amount_out > single.amount_out);
+
+        // A small order is not worth splitting.
+        let small = router
+            .quote_split(&token("ETH"), &token("USDC"), 1, 2, 10)
+            .unwrap();
+        assert_eq!(small.routes.len(), 1);
+    }
+
+    #[test]
+    fn reports_unroutable_requests() {
+        let router = router(vec![
+            pool("ETH-USDC", 1_000, 2_000_000),
+            pool("BTC-DAI", 0, 0),
+        ]);
+        assert_eq!(
+            router.quote(&token("ETH"), &token("DAI"), 10, 4),
+            Err(RouteError::NoRoute(token("ETH"), token("DAI")))
+        );
+        assert_eq!(
+            router.quote(&token("ETH"), &token("ETH"), 10, 4),
+            Err(RouteError::SameToken)
+        );
+        assert_eq!(
+            router.quote(&token("ETH"), &token("USDC"), 0, 4),
+            Err(RouteError::ZeroAmount)
+        );
+        // An empty pool pays nothing.
+        assert_eq!(
+            router.quote(&token("BTC"), &token("DAI"), 10, 4),
+            Err(RouteError::NoRoute(token("BTC"), token("DAI")))
+        );
+    }
+
+    #[test]
+    fn freshness_follows_the_server_sequence() {
+        let router = router(vec![]);
+        assert_eq!(router.sequence(), 7);
+        assert!(router.is_fresh(7));
+        assert!(!router.is_fresh(8));
+    }
+}
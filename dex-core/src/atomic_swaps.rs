@@ -362,11 +362,11 @@ mod tests {
         
         let result = manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             secret_hash,
             3600, // 1 hour timeout
@@ -391,11 +391,11 @@ mod tests {
         
         let result = manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             vec![1, 2, 3], // Invalid hash length
             3600, // 1 hour timeout
@@ -415,11 +415,11 @@ mod tests {
         // Initiate swap
         manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             secret_hash,
             3600, // 1 hour timeout
@@ -444,11 +444,11 @@ mod tests {
         // Initiate and fund swap
         manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             secret_hash.clone(),
             3600, // 1 hour timeout
@@ -480,11 +480,11 @@ mod tests {
         // Initiate and fund swap
         manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             secret_hash,
             3600, // 1 hour timeout
@@ -512,11 +512,11 @@ mod tests {
         // Initiate and fund swap
         manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             secret_hash,
             1, // 1 second timeout for testing
@@ -541,11 +541,11 @@ mod tests {
         // Initiate swap
         manager.initiate_swap(
             "swap1".to_string(),
-            "initiator1".to_string(),
-            "participant1".to_string(),
-            "BTC".to_string(),
+            "initiator1".parse().unwrap(),
+            "participant1".parse().unwrap(),
+            "BTC".parse().unwrap(),
             1000,
-            "ETH".to_string(),
+            "ETH".parse().unwrap(),
             20000,
             secret_hash,
             3600, // 1 hour timeout
@@ -0,0 +1,89 @@
+//! Verification of inbound bridge messages
+//!
+//! A deposit made on another chain is only credited here once a
+//! [`MessageVerifier`] for that chain has checked cryptographic evidence of
+//! it: a light-client proof that the event is in a trusted block, or a
+//! threshold of attestation signatures. Each chain's verifier decides what a
+//! proof looks like; all of them return the same [`VerifiedDeposit`].
+//! [`MockVerifier`] stands in for a real chain in tests.
+
+use crate::types::{TokenId, TraderId};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VerificationError {
+    #[error("malformed proof: {0}")]
+    Malformed(String),
+    #[error("proof is not anchored in a trusted block")]
+    UntrustedBlock,
+    #[error("proof does not match the trusted root")]
+    InvalidProof,
+    #[error("event was not emitted by the bridge")]
+    NotABridgeEvent,
+    #[error("deposit names an invalid {0}")]
+    InvalidDeposit(&'static str),
+}
+
+/// A deposit on another chain, as proven by its verifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifiedDeposit {
+    /// Chain the deposit was made on.
+    pub chain: String,
+    /// Identifies the event on its chain, so it is credited only once.
+    pub event_id: String,
+    /// Asset deposited, as named on its chain.
+    pub asset_id: TokenId,
+    pub recipient: TraderId,
+    pub amount: u128,
+}
+
+/// Checks proofs of deposits made on one chain.
+pub trait MessageVerifier: Send + Sync {
+    /// The chain this verifier trusts, as named in asset mappings.
+    fn chain(&self) -> &str;
+
+    /// The deposit `proof` shows, or why it does not.
+    fn verify(&self, proof: &Value) -> Result<VerifiedDeposit, VerificationError>;
+}
+
+/// A verifier that accepts only the deposits it was told to, by event ID.
+/// Proofs are `{"event_id": "..."}`.
+#[derive(Debug, Clone, Default)]
+pub struct MockVerifier {
+    chain: String,
+    deposits: HashMap<String, VerifiedDeposit>,
+}
+
+impl MockVerifier {
+    pub fn new(chain: &str) -> Self {
+        Self {
+            chain: chain.to_string(),
+            deposits: HashMap::new(),
+        }
+    }
+
+    /// Accept proofs of `deposit` from now on.
+    pub fn attest(&mut self, deposit: VerifiedDeposit) {
+        self.deposits.insert(deposit.event_id.clone(), deposit);
+    }
+}
+
+impl MessageVerifier for MockVerifier {
+    fn chain(&self) -> &str {
+        &self.chain
+    }
+
+    fn verify(&self, proof: &Value) -> Result<VerifiedDeposit, VerificationError> {
+        let event_id = proof
+            .get("event_id")
+            .and_then(Value::as_str)
+            .ok_or_else(|| VerificationError::Malformed("missing event_id".to_string()))?;
+        self.deposits
+            .get(event_id)
+            .cloned()
+            .ok_or(VerificationError::InvalidProof)
+    }
+}
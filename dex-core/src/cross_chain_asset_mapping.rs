@@ -5,8 +5,13 @@
 //!
 //! It provides functionality for mapping assets across different blockchain networks,
 //! enabling seamless cross-chain trading and asset transfers.
+//!
+//! Deposits are only credited against a mapping once the source chain's
+//! `MessageVerifier` has verified a proof of them.
 
+use crate::bridge_verification::{MessageVerifier, VerificationError, VerifiedDeposit};
 use crate::types::{TokenId, TraderId};
+use serde_json::Value;
 use std::collections::HashMap;
 use thiserror::Error;
 
@@ -25,6 +30,19 @@ pub struct CrossChainAssetMapping {
     pub conversion_rate: Option<f64>,
 }
 
+/// A verified deposit and what it credits on the destination chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct BridgeCredit {
+    /// The deposit as proven on its source chain
+    pub deposit: VerifiedDeposit,
+    /// The asset credited
+    pub asset_id: TokenId,
+    /// The chain the asset is credited on
+    pub chain: String,
+    /// The amount credited, after the mapping's conversion rate
+    pub amount: u128,
+}
+
 /// Manages cross-chain asset mappings
 #[derive(Debug, Clone)]
 pub struct CrossChainAssetMapper {
@@ -177,6 +195,34 @@ impl CrossChainAssetMapper {
             Err(CrossChainAssetError::NoConversionRate)
         }
     }
+
+    /// Verify a deposit proof with its source chain's verifier and work out the credit.
+    /// A mapping without a conversion rate credits the amount deposited. Callers must
+    /// still credit each deposit's event ID only once.
+    pub fn credit_deposit(
+        &self,
+        verifier: &dyn MessageVerifier,
+        proof: &Value,
+    ) -> Result<BridgeCredit, CrossChainAssetError> {
+        let deposit = verifier.verify(proof)?;
+        if deposit.chain != verifier.chain() {
+            return Err(VerificationError::InvalidDeposit("chain").into());
+        }
+        let mapping = self.get_mapping(&deposit.asset_id, &deposit.chain)
+            .ok_or(CrossChainAssetError::MappingNotFound)?;
+
+        let amount = match mapping.conversion_rate {
+            None => deposit.amount,
+            Some(rate) if rate.is_finite() && rate > 0.0 => (deposit.amount as f64 * rate) as u128,
+            Some(_) => return Err(CrossChainAssetError::NoConversionRate),
+        };
+        Ok(BridgeCredit {
+            asset_id: mapping.destination_asset_id.clone(),
+            chain: mapping.destination_chain.clone(),
+            amount,
+            deposit,
+        })
+    }
 }
 
 impl Default for CrossChainAssetMapper {
@@ -194,11 +240,15 @@ pub enum CrossChainAssetError {
     MappingNotFound,
     #[error("No conversion rate available for this mapping")]
     NoConversionRate,
+    #[error("Deposit could not be verified: {0}")]
+    Verification(#[from] VerificationError),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::bridge_verification::MockVerifier;
+    use serde_json::json;
 
     #[test]
     fn test_cross_chain_asset_mapper_creation() {
@@ -212,9 +262,9 @@ mod tests {
         let mut mapper = CrossChainAssetMapper::new();
         
         let mapping = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "WBTC".to_string(),
+            destination_asset_id: "WBTC".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: Some(1.0),
         };
@@ -229,17 +279,17 @@ mod tests {
         let mut mapper = CrossChainAssetMapper::new();
         
         let mapping1 = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "WBTC".to_string(),
+            destination_asset_id: "WBTC".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: Some(1.0),
         };
         
         let mapping2 = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "BTCB".to_string(),
+            destination_asset_id: "BTCB".parse().unwrap(),
             destination_chain: "BinanceSmartChain".to_string(),
             conversion_rate: Some(1.0),
         };
@@ -261,9 +311,9 @@ mod tests {
         let mut mapper = CrossChainAssetMapper::new();
         
         let mapping = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "WBTC".to_string(),
+            destination_asset_id: "WBTC".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: Some(1.0),
         };
@@ -271,12 +321,12 @@ mod tests {
         mapper.add_mapping(mapping.clone()).unwrap();
         
         // Test forward lookup
-        let retrieved = mapper.get_mapping(&"BTC".to_string(), "Bitcoin");
+        let retrieved = mapper.get_mapping(&"BTC".parse().unwrap(), "Bitcoin");
         assert!(retrieved.is_some());
         assert_eq!(retrieved.unwrap(), &mapping);
         
         // Test reverse lookup
-        let retrieved_reverse = mapper.get_mapping_by_destination(&"WBTC".to_string(), "Ethereum");
+        let retrieved_reverse = mapper.get_mapping_by_destination(&"WBTC".parse().unwrap(), "Ethereum");
         assert!(retrieved_reverse.is_some());
         assert_eq!(retrieved_reverse.unwrap(), &mapping);
     }
@@ -286,9 +336,9 @@ mod tests {
         let mut mapper = CrossChainAssetMapper::new();
         
         let mapping = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "WBTC".to_string(),
+            destination_asset_id: "WBTC".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: Some(1.0),
         };
@@ -297,12 +347,12 @@ mod tests {
         assert_eq!(mapper.mapping_count(), 1);
         
         // Remove the mapping
-        assert!(mapper.remove_mapping(&"BTC".to_string(), "Bitcoin").is_ok());
+        assert!(mapper.remove_mapping(&"BTC".parse().unwrap(), "Bitcoin").is_ok());
         assert_eq!(mapper.mapping_count(), 0);
         assert!(mapper.is_empty());
         
         // Try to remove non-existent mapping
-        let result = mapper.remove_mapping(&"BTC".to_string(), "Bitcoin");
+        let result = mapper.remove_mapping(&"BTC".parse().unwrap(), "Bitcoin");
         assert!(result.is_err());
         assert!(matches!(
             result.unwrap_err(),
@@ -316,25 +366,25 @@ mod tests {
         
         // Add mappings from different source chains
         let mapping1 = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "WBTC".to_string(),
+            destination_asset_id: "WBTC".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: Some(1.0),
         };
         
         let mapping2 = CrossChainAssetMapping {
-            source_asset_id: "ETH".to_string(),
+            source_asset_id: "ETH".parse().unwrap(),
             source_chain: "Ethereum".to_string(),
-            destination_asset_id: "WETH".to_string(),
+            destination_asset_id: "WETH".parse().unwrap(),
             destination_chain: "BinanceSmartChain".to_string(),
             conversion_rate: Some(1.0),
         };
         
         let mapping3 = CrossChainAssetMapping {
-            source_asset_id: "SOL".to_string(),
+            source_asset_id: "SOL".parse().unwrap(),
             source_chain: "Bitcoin".to_string(), // Same source chain as mapping1
-            destination_asset_id: "WSOL".to_string(),
+            destination_asset_id: "WSOL".parse().unwrap(),
             destination_chain: "Polygon".to_string(),
             conversion_rate: Some(1.0),
         };
@@ -360,35 +410,35 @@ mod tests {
         let mut mapper = CrossChainAssetMapper::new();
         
         let mapping = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "WBTC".to_string(),
+            destination_asset_id: "WBTC".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: Some(1.0),
         };
         
         mapper.add_mapping(mapping).unwrap();
         
-        let trader_id = "trader1".to_string();
+        let trader_id: TraderId = "trader1".parse().unwrap();
         
         // Grant access
         mapper.grant_trader_access(
             trader_id.clone(),
-            "BTC".to_string(),
+            "BTC".parse().unwrap(),
             "Bitcoin".to_string(),
         );
         
         // Check access
-        assert!(mapper.has_trader_access(&trader_id, &"BTC".to_string(), "Bitcoin"));
-        assert!(!mapper.has_trader_access(&trader_id, &"ETH".to_string(), "Ethereum"));
+        assert!(mapper.has_trader_access(&trader_id, &"BTC".parse().unwrap(), "Bitcoin"));
+        assert!(!mapper.has_trader_access(&trader_id, &"ETH".parse().unwrap(), "Ethereum"));
         
         // Get trader mappings
         let trader_mappings = mapper.get_trader_mappings(&trader_id);
         assert_eq!(trader_mappings.len(), 1);
         
         // Revoke access
-        mapper.revoke_trader_access(&trader_id, &"BTC".to_string(), "Bitcoin");
-        assert!(!mapper.has_trader_access(&trader_id, &"BTC".to_string(), "Bitcoin"));
+        mapper.revoke_trader_access(&trader_id, &"BTC".parse().unwrap(), "Bitcoin");
+        assert!(!mapper.has_trader_access(&trader_id, &"BTC".parse().unwrap(), "Bitcoin"));
         
         // Get trader mappings after revocation
         let trader_mappings = mapper.get_trader_mappings(&trader_id);
@@ -401,18 +451,18 @@ mod tests {
         
         // Add mapping with conversion rate
         let mapping_with_rate = CrossChainAssetMapping {
-            source_asset_id: "BTC".to_string(),
+            source_asset_id: "BTC".parse().unwrap(),
             source_chain: "Bitcoin".to_string(),
-            destination_asset_id: "USD".to_string(),
+            destination_asset_id: "USD".parse().unwrap(),
             destination_chain: "Fiat".to_string(),
             conversion_rate: Some(50000.0), // 1 BTC = 50000 USD
         };
         
         // Add mapping without conversion rate
         let mapping_without_rate = CrossChainAssetMapping {
-            source_asset_id: "ETH".to_string(),
+            source_asset_id: "ETH".parse().unwrap(),
             source_chain: "Ethereum".to_string(),
-            destination_asset_id: "WETH".to_string(),
+            destination_asset_id: "WETH".parse().unwrap(),
             destination_chain: "Ethereum".to_string(),
             conversion_rate: None, // Wrapped token, 1:1 but no explicit rate
         };
@@ -421,12 +471,12 @@ mod tests {
         mapper.add_mapping(mapping_without_rate).unwrap();
         
         // Test conversion with rate
-        let converted_amount = mapper.convert_amount(&"BTC".to_string(), "Bitcoin", 2.5);
+        let converted_amount = mapper.convert_amount(&"BTC".parse().unwrap(), "Bitcoin", 2.5);
         assert!(converted_amount.is_ok());
         assert_eq!(converted_amount.unwrap(), 125000.0); // 2.5 * 50000
         
         // Test conversion without rate
-        let no_rate_result = mapper.convert_amount(&"ETH".to_string(), "Ethereum", 1.0);
+        let no_rate_result = mapper.convert_amount(&"ETH".parse().unwrap(), "Ethereum", 1.0);
         assert!(no_rate_result.is_err());
         assert!(matches!(
             no_rate_result.unwrap_err(),
@@ -434,11 +484,57 @@ mod tests {
         ));
         
         // Test conversion with non-existent mapping
-        let not_found_result = mapper.convert_amount(&"DOGE".to_string(), "Dogecoin", 1000.0);
+        let not_found_result = mapper.convert_amount(&"DOGE".parse().unwrap(), "Dogecoin", 1000.0);
         assert!(not_found_result.is_err());
         assert!(matches!(
             not_found_result.unwrap_err(),
             CrossChainAssetError::MappingNotFound
         ));
     }
+
+    #[test]
+    fn test_credit_deposit_requires_a_verified_proof() {
+        let mut mapper = CrossChainAssetMapper::new();
+        mapper.add_mapping(CrossChainAssetMapping {
+            source_asset_id: "WETH".parse().unwrap(),
+            source_chain: "ethereum".to_string(),
+            destination_asset_id: "ETH".parse().unwrap(),
+            destination_chain: "dex".to_string(),
+            conversion_rate: None,
+        }).unwrap();
+
+        let deposit = VerifiedDeposit {
+            chain: "ethereum".to_string(),
+            event_id: "0xabc:0:1".to_string(),
+            asset_id: "WETH".parse().unwrap(),
+            recipient: "alice".parse().unwrap(),
+            amount: 5_000,
+        };
+        let mut verifier = MockVerifier::new("ethereum");
+        let proof = json!({ "event_id": "0xabc:0:1" });
+
+        // Nothing is credited on say-so.
+        assert!(matches!(
+            mapper.credit_deposit(&verifier, &proof),
+            Err(CrossChainAssetError::Verification(VerificationError::InvalidProof))
+        ));
+        assert!(matches!(
+            mapper.credit_deposit(&verifier, &json!({})),
+            Err(CrossChainAssetError::Verification(VerificationError::Malformed(_)))
+        ));
+
+        verifier.attest(deposit.clone());
+        let credit = mapper.credit_deposit(&verifier, &proof).unwrap();
+        assert_eq!(credit.asset_id, "ETH".parse::<TokenId>().unwrap());
+        assert_eq!((credit.chain.as_str(), credit.amount), ("dex", 5_000));
+        assert_eq!(credit.deposit, deposit);
+
+        // A deposit of an unmapped asset is refused.
+        let mut verifier = MockVerifier::new("ethereum");
+        verifier.attest(VerifiedDeposit { asset_id: "DAI".parse().unwrap(), ..deposit });
+        assert!(matches!(
+            mapper.credit_deposit(&verifier, &proof),
+            Err(CrossChainAssetError::MappingNotFound)
+        ));
+    }
 }
\ No newline at end of file
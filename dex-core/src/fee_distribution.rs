@@ -111,7 +111,7 @@ impl FeeDistributionManager {
     /// Get traders in a range (useful for pagination or batch processing)
     pub fn get_traders_in_range(&self, start: &TraderId, end: &TraderId) -> Vec<&FeeDistribution> {
         self.distributions
-            .range::<std::string::String, _>((
+            .range::<TraderId, _>((
                 std::ops::Bound::Included(start),
                 std::ops::Bound::Included(end),
             ))
@@ -159,22 +159,22 @@ mod tests {
 
         // Add some fee distributions
         let dist1 = FeeDistribution {
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let dist2 = FeeDistribution {
-            trader_id: "trader2".to_string(),
-            token_id: "ETH".to_string(),
+            trader_id: "trader2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let dist3 = FeeDistribution {
-            trader_id: "trader3".to_string(),
-            token_id: "USDC".to_string(),
+            trader_id: "trader3".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
@@ -188,17 +188,17 @@ mod tests {
         assert!(manager.has_distributions());
 
         // Get specific distributions
-        let retrieved_dist = manager.get_distribution(&"trader1".to_string()).unwrap();
+        let retrieved_dist = manager.get_distribution(&"trader1".parse().unwrap()).unwrap();
         assert_eq!(retrieved_dist.amount, 1000);
 
-        let retrieved_dist = manager.get_distribution(&"trader2".to_string()).unwrap();
+        let retrieved_dist = manager.get_distribution(&"trader2".parse().unwrap()).unwrap();
         assert_eq!(retrieved_dist.amount, 2000);
 
-        let retrieved_dist = manager.get_distribution(&"trader3".to_string()).unwrap();
+        let retrieved_dist = manager.get_distribution(&"trader3".parse().unwrap()).unwrap();
         assert_eq!(retrieved_dist.amount, 1500);
 
         // Non-existent trader
-        assert!(manager.get_distribution(&"trader4".to_string()).is_none());
+        assert!(manager.get_distribution(&"trader4".parse().unwrap()).is_none());
     }
 
     #[test]
@@ -206,8 +206,8 @@ mod tests {
         let mut manager = FeeDistributionManager::new();
 
         let dist = FeeDistribution {
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
@@ -217,14 +217,14 @@ mod tests {
         assert_eq!(manager.trader_count(), 1);
 
         // Remove the distribution
-        let removed = manager.remove_distribution(&"trader1".to_string()).unwrap();
+        let removed = manager.remove_distribution(&"trader1".parse().unwrap()).unwrap();
         assert_eq!(removed.amount, 1000);
         assert_eq!(manager.total_fees(), 0);
         assert_eq!(manager.trader_count(), 0);
 
         // Try to remove non-existent distribution
         assert!(manager
-            .remove_distribution(&"trader2".to_string())
+            .remove_distribution(&"trader2".parse().unwrap())
             .is_none());
     }
 
@@ -233,8 +233,8 @@ mod tests {
         let mut manager = FeeDistributionManager::new();
 
         let dist = FeeDistribution {
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
@@ -244,16 +244,16 @@ mod tests {
 
         // Update the amount
         assert!(manager
-            .update_distribution_amount(&"trader1".to_string(), 1500)
+            .update_distribution_amount(&"trader1".parse().unwrap(), 1500)
             .is_ok());
         assert_eq!(manager.total_fees(), 1500);
 
-        let updated_dist = manager.get_distribution(&"trader1".to_string()).unwrap();
+        let updated_dist = manager.get_distribution(&"trader1".parse().unwrap()).unwrap();
         assert_eq!(updated_dist.amount, 1500);
 
         // Try to update non-existent trader
         assert!(manager
-            .update_distribution_amount(&"trader2".to_string(), 2000)
+            .update_distribution_amount(&"trader2".parse().unwrap(), 2000)
             .is_err());
     }
 
@@ -263,22 +263,22 @@ mod tests {
 
         // Add distributions in non-alphabetical order
         let dist_c = FeeDistribution {
-            trader_id: "traderC".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "traderC".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let dist_a = FeeDistribution {
-            trader_id: "traderA".to_string(),
-            token_id: "ETH".to_string(),
+            trader_id: "traderA".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let dist_b = FeeDistribution {
-            trader_id: "traderB".to_string(),
-            token_id: "USDC".to_string(),
+            trader_id: "traderB".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
@@ -300,8 +300,8 @@ mod tests {
         let mut manager = FeeDistributionManager::new();
 
         let dist = FeeDistribution {
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
@@ -323,10 +323,10 @@ mod tests {
 
         // Add distributions with different trader IDs
         for i in 1..=5 {
-            let trader_id = format!("trader{}", i);
+            let trader_id: TraderId = format!("trader{}", i).parse().unwrap();
             let dist = FeeDistribution {
                 trader_id: trader_id.clone(),
-                token_id: "BTC".to_string(),
+                token_id: "BTC".parse().unwrap(),
                 amount: 1000 * i as u64,
                 timestamp: 1000000 + i as u64,
             };
@@ -335,7 +335,7 @@ mod tests {
 
         // Get traders in a range
         let ranged_distributions =
-            manager.get_traders_in_range(&"trader2".to_string(), &"trader4".to_string());
+            manager.get_traders_in_range(&"trader2".parse().unwrap(), &"trader4".parse().unwrap());
         assert_eq!(ranged_distributions.len(), 3);
         assert_eq!(ranged_distributions[0].trader_id, "trader2");
         assert_eq!(ranged_distributions[1].trader_id, "trader3");
@@ -348,10 +348,10 @@ mod tests {
 
         // Add distributions
         for i in 1..=5 {
-            let trader_id = format!("trader{}", i);
+            let trader_id: TraderId = format!("trader{}", i).parse().unwrap();
             let dist = FeeDistribution {
                 trader_id: trader_id.clone(),
-                token_id: "BTC".to_string(),
+                token_id: "BTC".parse().unwrap(),
                 amount: 1000 * i as u64,
                 timestamp: 1000000 + i as u64,
             };
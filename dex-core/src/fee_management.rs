@@ -113,24 +113,24 @@ mod tests {
         // Add some fee claims
         let claim1 = FeeClaim {
             priority: 10,
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = FeeClaim {
             priority: 20,
-            trader_id: "trader2".to_string(),
-            token_id: "ETH".to_string(),
+            trader_id: "trader2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let claim3 = FeeClaim {
             priority: 15,
-            trader_id: "trader3".to_string(),
-            token_id: "USDC".to_string(),
+            trader_id: "trader3".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
@@ -170,8 +170,8 @@ mod tests {
         // Add a claim
         let claim = FeeClaim {
             priority: 10,
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
@@ -192,16 +192,16 @@ mod tests {
         // Add claims with same priority but different timestamps
         let claim1 = FeeClaim {
             priority: 10,
-            trader_id: "trader1".to_string(),
-            token_id: "BTC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000, // Earlier timestamp
         };
 
         let claim2 = FeeClaim {
             priority: 10,
-            trader_id: "trader2".to_string(),
-            token_id: "ETH".to_string(),
+            trader_id: "trader2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001, // Later timestamp
         };
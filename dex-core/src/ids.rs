@@ -0,0 +1,247 @@
+//! Validated identifier newtypes
+//!
+//! Token and trader identifiers are parsed once at the system boundary (API
+//! requests, database rows, WASM input) and carried through the engine as
+//! cheap-to-clone `Arc<str>` handles. Token symbols come from a small, closed
+//! set and are interned so every clone of `ETH` shares one allocation. They
+//! also arrive from unauthenticated requests, so the table is capped: symbols
+//! past [`MAX_INTERNED_TOKENS`] are allocated per parse instead.
+
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    borrow::Borrow,
+    collections::HashSet,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+};
+use thiserror::Error;
+
+/// Errors raised when constructing identifiers from untrusted input.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum IdError {
+    #[error("token must be 2-16 characters from [A-Za-z0-9_-]")]
+    InvalidToken,
+    #[error("trader_id must be between 3 and 64 visible characters")]
+    InvalidTrader,
+    #[error("base and quote tokens must differ")]
+    IdenticalTokens,
+    #[error("pair must be formatted as BASE-QUOTE, e.g. ETH-USDC")]
+    InvalidPair,
+}
+
+/// Most distinct token symbols the interner holds.
+pub const MAX_INTERNED_TOKENS: usize = 4096;
+
+fn intern(raw: &str) -> Arc<str> {
+    static TOKENS: OnceLock<Mutex<HashSet<Arc<str>>>> = OnceLock::new();
+    let mut tokens = TOKENS
+        .get_or_init(Default::default)
+        .lock()
+        .expect("token interner poisoned");
+    intern_in(&mut tokens, raw)
+}
+
+/// The symbol `tokens` already holds, or a new one, added while the table
+/// has room.
+fn intern_in(tokens: &mut HashSet<Arc<str>>, raw: &str) -> Arc<str> {
+    if let Some(existing) = tokens.get(raw) {
+        return existing.clone();
+    }
+    let token: Arc<str> = Arc::from(raw);
+    if tokens.len() < MAX_INTERNED_TOKENS {
+        tokens.insert(token.clone());
+    }
+    token
+}
+
+/// Token symbol, e.g. `ETH` or `USDC`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TokenId(Arc<str>);
+
+impl TokenId {
+    /// Validate and intern a token symbol.
+    pub fn parse(raw: &str) -> Result<Self, IdError> {
+        let valid = (2..=16).contains(&raw.len())
+            && raw
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            return Err(IdError::InvalidToken);
+        }
+        Ok(Self(intern(raw)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Trader identifier: an account name or normalized wallet address.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TraderId(Arc<str>);
+
+impl TraderId {
+    /// Validate a trader identifier.
+    pub fn parse(raw: &str) -> Result<Self, IdError> {
+        let valid = (3..=64).contains(&raw.len()) && raw.chars().all(|c| c.is_ascii_graphic());
+        if !valid {
+            return Err(IdError::InvalidTrader);
+        }
+        Ok(Self(Arc::from(raw)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+macro_rules! string_id_impls {
+    ($ty:ident) => {
+        impl FromStr for $ty {
+            type Err = IdError;
+
+            fn from_str(raw: &str) -> Result<Self, Self::Err> {
+                Self::parse(raw)
+            }
+        }
+
+        impl TryFrom<String> for $ty {
+            type Error = IdError;
+
+            fn try_from(raw: String) -> Result<Self, Self::Error> {
+                Self::parse(&raw)
+            }
+        }
+
+        impl TryFrom<&str> for $ty {
+            type Error = IdError;
+
+            fn try_from(raw: &str) -> Result<Self, Self::Error> {
+                Self::parse(raw)
+            }
+        }
+
+        impl From<$ty> for String {
+            fn from(id: $ty) -> Self {
+                id.0.to_string()
+            }
+        }
+
+        impl AsRef<str> for $ty {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl Borrow<str> for $ty {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl PartialEq<str> for $ty {
+            fn eq(&self, other: &str) -> bool {
+                &*self.0 == other
+            }
+        }
+
+        impl PartialEq<&str> for $ty {
+            fn eq(&self, other: &&str) -> bool {
+                &*self.0 == *other
+            }
+        }
+
+        impl PartialEq<String> for $ty {
+            fn eq(&self, other: &String) -> bool {
+                *self.0 == **other
+            }
+        }
+
+        impl fmt::Display for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(&self.0)
+            }
+        }
+
+        impl fmt::Debug for $ty {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&*self.0, f)
+            }
+        }
+
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(&self.0)
+            }
+        }
+    };
+}
+
+string_id_impls!(TokenId);
+string_id_impls!(TraderId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TradingPair;
+
+    #[test]
+    fn test_token_validation_and_interning() {
+        let a = TokenId::parse("ETH").unwrap();
+        let b: TokenId = "ETH".parse().unwrap();
+        assert!(Arc::ptr_eq(&a.0, &b.0));
+        assert_eq!(a, "ETH");
+        assert!(TokenId::parse("USDC_E").is_ok());
+        assert_eq!(TokenId::parse("USDC-E").unwrap(), "USDC-E");
+        assert_eq!(TokenId::parse("E"), Err(IdError::InvalidToken));
+        assert_eq!(TokenId::parse("E TH"), Err(IdError::InvalidToken));
+        assert_eq!(
+            TokenId::parse("ABCDEFGHIJKLMNOPQ"),
+            Err(IdError::InvalidToken)
+        );
+    }
+
+    #[test]
+    fn test_interner_is_bounded() {
+        let mut tokens = HashSet::new();
+        for n in 0..MAX_INTERNED_TOKENS {
+            intern_in(&mut tokens, &format!("T{n}"));
+        }
+        let a = intern_in(&mut tokens, "T0");
+        let b = intern_in(&mut tokens, "T0");
+        assert!(Arc::ptr_eq(&a, &b));
+        let c = intern_in(&mut tokens, "LATE");
+        let d = intern_in(&mut tokens, "LATE");
+        assert_eq!(c, d);
+        assert!(!Arc::ptr_eq(&c, &d));
+        assert_eq!(tokens.len(), MAX_INTERNED_TOKENS);
+    }
+
+    #[test]
+    fn test_pairs_round_trip() {
+        let pair: TradingPair = "BTC-USDC-E".parse().unwrap();
+        assert_eq!(pair.base(), "BTC");
+        assert_eq!(pair.quote(), "USDC-E");
+        assert_eq!(pair.to_string().parse::<TradingPair>().unwrap(), pair);
+        assert_eq!("ETH-".parse::<TradingPair>(), Err(IdError::InvalidPair));
+    }
+
+    #[test]
+    fn test_trader_validation() {
+        assert!(TraderId::parse("alice").is_ok());
+        assert_eq!(TraderId::parse("al"), Err(IdError::InvalidTrader));
+        assert_eq!(TraderId::parse("al ice"), Err(IdError::InvalidTrader));
+    }
+
+    #[test]
+    fn test_serde_round_trip_rejects_invalid() {
+        let token = TokenId::parse("USDC").unwrap();
+        let json = serde_json::to_string(&token).unwrap();
+        assert_eq!(json, "\"USDC\"");
+        assert_eq!(serde_json::from_str::<TokenId>(&json).unwrap(), token);
+        assert!(serde_json::from_str::<TokenId>("\"U\"").is_err());
+    }
+}
@@ -0,0 +1,496 @@
+//! Double-entry ledger
+//!
+//! Value only moves between accounts through journal entries. An entry is a
+//! list of postings, each debiting or crediting one account in one token, and
+//! is rejected unless its debits equal its credits in every token. The ledger
+//! therefore sums to zero per token at all times, and any account's balance
+//! can be reproduced exactly from the entries that touched it.
+//!
+//! An account's balance is its debits minus its credits: what it has taken
+//! in. A trader's account holds their unsettled position, positive where
+//! they are owed a token and negative where they owe it; settlement brings it
+//! back to zero. A pool's account holds its reserves, and the fee account
+//! the fees charged.
+
+use crate::types::{TokenId, Trade, TradingPair};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LedgerError {
+    #[error("journal entry has no postings")]
+    Empty,
+    #[error("posting of zero {0}")]
+    ZeroAmount(TokenId),
+    #[error("journal entry does not balance in {token}: debits {debits}, credits {credits}")]
+    Unbalanced {
+        token: TokenId,
+        debits: u128,
+        credits: u128,
+    },
+    #[error("invalid ledger account {0}")]
+    InvalidAccount(String),
+    #[error("invalid journal entry kind {0}")]
+    InvalidKind(String),
+    #[error("invalid posting direction {0}")]
+    InvalidDirection(String),
+}
+
+/// Who holds a balance.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Account {
+    /// A trader's unsettled position.
+    Trader(String),
+    /// An AMM pool's reserves.
+    Pool(TradingPair),
+    /// Fees and penalties charged by the exchange.
+    Fees,
+    /// Tokens locked in the bridge on another chain, by chain.
+    Bridge(String),
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Account::Trader(trader) => write!(f, "trader:{}", trader),
+            Account::Pool(pair) => write!(f, "pool:{}", pair),
+            Account::Fees => f.write_str("fees"),
+            Account::Bridge(chain) => write!(f, "bridge:{}", chain),
+        }
+    }
+}
+
+impl FromStr for Account {
+    type Err = LedgerError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let invalid = || LedgerError::InvalidAccount(raw.to_string());
+        match raw.split_once(':') {
+            Some(("trader", trader)) if !trader.is_empty() => {
+                Ok(Account::Trader(trader.to_string()))
+            }
+            Some(("pool", pair)) => pair.parse().map(Account::Pool).map_err(|_| invalid()),
+            Some(("bridge", chain)) if !chain.is_empty() => Ok(Account::Bridge(chain.to_string())),
+            _ if raw == "fees" => Ok(Account::Fees),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Serialize for Account {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Account {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// What a journal entry records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// An order book trade.
+    Fill,
+    /// A swap through an AMM pool.
+    Swap,
+    /// Liquidity added to or removed from a pool.
+    Liquidity,
+    /// A fee or penalty charged to a trader.
+    Fee,
+    /// A busted or re-priced trade.
+    Correction,
+    /// Net transfers paying out a settlement batch.
+    Settlement,
+    /// A verified deposit bridged in from another chain.
+    Deposit,
+    /// A wrapped asset burned to release what backs it.
+    Burn,
+    /// Funds a trader withdrew to another chain, or their return when the
+    /// withdrawal failed.
+    Withdrawal,
+}
+
+impl EntryKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryKind::Fill => "fill",
+            EntryKind::Swap => "swap",
+            EntryKind::Liquidity => "liquidity",
+            EntryKind::Fee => "fee",
+            EntryKind::Correction => "correction",
+            EntryKind::Settlement => "settlement",
+            EntryKind::Deposit => "deposit",
+            EntryKind::Burn => "burn",
+            EntryKind::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+impl FromStr for EntryKind {
+    type Err = LedgerError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        Ok(match raw {
+            "fill" => EntryKind::Fill,
+            "swap" => EntryKind::Swap,
+            "liquidity" => EntryKind::Liquidity,
+            "fee" => EntryKind::Fee,
+            "correction" => EntryKind::Correction,
+            "settlement" => EntryKind::Settlement,
+            "deposit" => EntryKind::Deposit,
+            "burn" => EntryKind::Burn,
+            "withdrawal" => EntryKind::Withdrawal,
+            _ => return Err(LedgerError::InvalidKind(raw.to_string())),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Debit,
+    Credit,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Debit => "debit",
+            Direction::Credit => "credit",
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = LedgerError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "debit" => Ok(Direction::Debit),
+            "credit" => Ok(Direction::Credit),
+            _ => Err(LedgerError::InvalidDirection(raw.to_string())),
+        }
+    }
+}
+
+/// One line of a journal entry.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(as = LedgerPosting))]
+pub struct Posting {
+    /// `trader:<id>`, `pool:<BASE-QUOTE>`, `bridge:<chain>` or `fees`.
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub account: Account,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub token: TokenId,
+    #[cfg_attr(feature = "openapi", schema(inline))]
+    pub direction: Direction,
+    pub amount: u128,
+}
+
+impl Posting {
+    /// The posting's effect on the account's balance.
+    pub fn signed_amount(&self) -> i128 {
+        match self.direction {
+            Direction::Debit => self.amount as i128,
+            Direction::Credit => -(self.amount as i128),
+        }
+    }
+}
+
+/// A balanced set of postings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(as = LedgerEntry))]
+pub struct JournalEntry {
+    /// Assigned by the store; ignored on save.
+    pub id: u64,
+    #[cfg_attr(feature = "openapi", schema(inline))]
+    pub kind: EntryKind,
+    /// What the entry books, e.g. `trade:42`; unique per kind, so an event
+    /// is never booked twice.
+    pub reference: String,
+    /// Unix seconds.
+    pub timestamp: u64,
+    pub postings: Vec<Posting>,
+}
+
+impl JournalEntry {
+    pub fn new(kind: EntryKind, reference: impl Into<String>, timestamp: u64) -> Self {
+        Self {
+            id: 0,
+            kind,
+            reference: reference.into(),
+            timestamp,
+            postings: Vec::new(),
+        }
+    }
+
+    /// Move `amount` of `token` from `from` to `to`: credit one, debit the
+    /// other. Zero amounts add nothing.
+    pub fn transfer(mut self, from: Account, to: Account, token: &TokenId, amount: u128) -> Self {
+        if amount == 0 {
+            return self;
+        }
+        self.postings.push(Posting {
+            account: to,
+            token: token.clone(),
+            direction: Direction::Debit,
+            amount,
+        });
+        self.postings.push(Posting {
+            account: from,
+            token: token.clone(),
+            direction: Direction::Credit,
+            amount,
+        });
+        self
+    }
+
+    /// An order book trade: the seller delivers the base and the buyer the
+    /// quote, both owed until settlement.
+    pub fn fill(trade: &Trade, buyer: &str, seller: &str) -> Self {
+        let buyer = Account::Trader(buyer.to_string());
+        let seller = Account::Trader(seller.to_string());
+        Self::new(
+            EntryKind::Fill,
+            format!("trade:{}", trade.id),
+            trade.timestamp,
+        )
+        .transfer(
+            seller.clone(),
+            buyer.clone(),
+            &trade.base_token,
+            u128::from(trade.quantity),
+        )
+        .transfer(buyer, seller, &trade.quote_token, trade.notional().value())
+    }
+
+    /// The same postings with debits and credits swapped, undoing this
+    /// entry under a new kind and reference.
+    pub fn reversal(&self, kind: EntryKind, reference: impl Into<String>, timestamp: u64) -> Self {
+        let mut reversal = Self::new(kind, reference, timestamp);
+        reversal.postings = self
+            .postings
+            .iter()
+            .map(|posting| Posting {
+                direction: match posting.direction {
+                    Direction::Debit => Direction::Credit,
+                    Direction::Credit => Direction::Debit,
+                },
+                ..posting.clone()
+            })
+            .collect();
+        reversal
+    }
+
+    /// Check that the entry has postings, none of them zero, and that its
+    /// debits equal its credits in every token.
+    pub fn validate(&self) -> Result<(), LedgerError> {
+        if self.postings.is_empty() {
+            return Err(LedgerError::Empty);
+        }
+        let mut totals: BTreeMap<&TokenId, (u128, u128)> = BTreeMap::new();
+        for posting in &self.postings {
+            if posting.amount == 0 {
+                return Err(LedgerError::ZeroAmount(posting.token.clone()));
+            }
+            let (debits, credits) = totals.entry(&posting.token).or_default();
+            match posting.direction {
+                Direction::Debit => *debits = debits.saturating_add(posting.amount),
+                Direction::Credit => *credits = credits.saturating_add(posting.amount),
+            }
+        }
+        match totals
+            .into_iter()
+            .find(|(_, (debits, credits))| debits != credits)
+        {
+            Some((token, (debits, credits))) => Err(LedgerError::Unbalanced {
+                token: token.clone(),
+                debits,
+                credits,
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An account's balance in one token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(as = LedgerBalance))]
+pub struct AccountBalance {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub account: Account,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub token: TokenId,
+    /// Debits minus credits.
+    pub balance: i128,
+}
+
+/// Balances built from journal entries.
+#[derive(Debug, Clone, Default)]
+pub struct Ledger {
+    balances: BTreeMap<(Account, TokenId), i128>,
+    entries: u64,
+}
+
+impl Ledger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply an entry, refusing it unless it balances.
+    pub fn post(&mut self, entry: &JournalEntry) -> Result<(), LedgerError> {
+        entry.validate()?;
+        for posting in &entry.postings {
+            *self
+                .balances
+                .entry((posting.account.clone(), posting.token.clone()))
+                .or_default() += posting.signed_amount();
+        }
+        self.entries += 1;
+        Ok(())
+    }
+
+    /// Entries posted.
+    pub fn entries(&self) -> u64 {
+        self.entries
+    }
+
+    pub fn balance(&self, account: &Account, token: &TokenId) -> i128 {
+        self.balances
+            .get(&(account.clone(), token.clone()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Every non-zero balance, by account then token.
+    pub fn balances(&self) -> Vec<AccountBalance> {
+        self.balances
+            .iter()
+            .filter(|(_, balance)| **balance != 0)
+            .map(|((account, token), balance)| AccountBalance {
+                account: account.clone(),
+                token: token.clone(),
+                balance: *balance,
+            })
+            .collect()
+    }
+
+    /// Sum of every account's balance per token; zero throughout while the
+    /// books balance.
+    pub fn trial_balance(&self) -> BTreeMap<TokenId, i128> {
+        trial_balance(
+            self.balances
+                .iter()
+                .map(|((_, token), balance)| (token, *balance)),
+        )
+    }
+}
+
+/// Sum `balances` per token.
+pub fn trial_balance<'a>(
+    balances: impl IntoIterator<Item = (&'a TokenId, i128)>,
+) -> BTreeMap<TokenId, i128> {
+    let mut totals = BTreeMap::new();
+    for (token, balance) in balances {
+        *totals.entry(token.clone()).or_insert(0) += balance;
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(symbol: &str) -> TokenId {
+        symbol.parse().unwrap()
+    }
+
+    fn trader(name: &str) -> Account {
+        Account::Trader(name.to_string())
+    }
+
+    fn trade() -> Trade {
+        Trade {
+            id: 7,
+            maker_order_id: 1,
+            taker_order_id: 2,
+            base_token: token("ETH"),
+            quote_token: token("USDC"),
+            price: 2_000,
+            quantity: 3,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn fills_move_both_legs_and_keep_the_books_balanced() {
+        let mut ledger = Ledger::new();
+        let fill = JournalEntry::fill(&trade(), "alice", "bob");
+        assert_eq!(fill.reference, "trade:7");
+        ledger.post(&fill).unwrap();
+
+        assert_eq!(ledger.balance(&trader("alice"), &token("ETH")), 3);
+        assert_eq!(ledger.balance(&trader("alice"), &token("USDC")), -6_000);
+        assert_eq!(ledger.balance(&trader("bob"), &token("ETH")), -3);
+        assert_eq!(ledger.balance(&trader("bob"), &token("USDC")), 6_000);
+        assert!(ledger.trial_balance().values().all(|total| *total == 0));
+
+        ledger
+            .post(&fill.reversal(EntryKind::Correction, "bust:7", 1_700_000_100))
+            .unwrap();
+        assert!(ledger.balances().is_empty());
+        assert_eq!(ledger.entries(), 2);
+    }
+
+    #[test]
+    fn unbalanced_or_empty_entries_are_refused() {
+        let mut ledger = Ledger::new();
+        let mut entry = JournalEntry::new(EntryKind::Fee, "penalty", 0).transfer(
+            trader("alice"),
+            Account::Fees,
+            &token("USD"),
+            25,
+        );
+        entry.postings[0].amount = 30;
+        assert_eq!(
+            ledger.post(&entry),
+            Err(LedgerError::Unbalanced {
+                token: token("USD"),
+                debits: 30,
+                credits: 25,
+            })
+        );
+        let empty = JournalEntry::new(EntryKind::Fee, "nothing", 0).transfer(
+            trader("alice"),
+            Account::Fees,
+            &token("USD"),
+            0,
+        );
+        assert_eq!(ledger.post(&empty), Err(LedgerError::Empty));
+        assert_eq!(ledger.entries(), 0);
+    }
+
+    #[test]
+    fn accounts_round_trip_through_text() {
+        for account in [
+            trader("solana:abc"),
+            Account::Pool("ETH-USDC".parse().unwrap()),
+            Account::Fees,
+            Account::Bridge("ethereum".to_string()),
+        ] {
+            assert_eq!(account.to_string().parse::<Account>().unwrap(), account);
+        }
+        assert!("pool:ETH".parse::<Account>().is_err());
+        assert!("vault".parse::<Account>().is_err());
+        assert!("bridge:".parse::<Account>().is_err());
+    }
+}
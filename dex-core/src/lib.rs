@@ -1,12 +1,17 @@
 //! DEX-OS core engine library
 
 pub mod amm;
+pub mod amm_router;
 pub mod atomic_swaps;
 pub mod avl_tree;
+pub mod bridge_verification;
 pub mod cross_chain_asset_mapping;
 pub mod fee_distribution;
 pub mod fee_management;
+pub mod ids;
+pub mod ledger;
 pub mod lending;
+pub mod matching;
 pub mod merkle_tree;
 pub mod multisig_wallet;
 pub mod orderbook;
@@ -16,9 +21,11 @@ pub mod price_prediction;
 pub mod quantum_consensus;
 pub mod reward_distribution;
 pub mod stableswap;
+pub mod state_machine;
 pub mod trade_prevention;
 pub mod treasury;
 pub mod types;
+pub mod wrapped_assets;
 pub mod payments;
 
 #[cfg(test)]
@@ -0,0 +1,380 @@
+//! Matching algorithms for the DEX-OS order book
+//!
+//! Price priority always holds: an incoming order clears better price levels
+//! before worse ones. Within a level, the pair's [`MatchingPolicy`] decides
+//! how the quantity the level can take is split across its resting makers:
+//! strict time priority (FIFO), pure pro-rata by resting size, or pro-rata
+//! after the oldest order at the level is filled first. Pairs are mapped to
+//! policies in a [`MatchingRegistry`]; pairs without an entry match FIFO.
+
+use crate::types::{OrderId, Quantity, TradingPair};
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+use thiserror::Error;
+
+/// How a price level's fill is split across the makers resting on it.
+pub trait MatchingPolicy: fmt::Debug + Send + Sync {
+    /// Name used in configuration and logs.
+    fn name(&self) -> &'static str;
+
+    /// Split `quantity` across `makers`, given in time priority as order ID
+    /// and remaining quantity. `quantity` never exceeds the makers' total.
+    /// Returns one allocation per maker that receives anything, in the
+    /// makers' order, summing to exactly `quantity`, none above what the
+    /// maker has left.
+    fn allocate(
+        &self,
+        makers: &[(OrderId, Quantity)],
+        quantity: Quantity,
+    ) -> Vec<(OrderId, Quantity)>;
+}
+
+/// Strict time priority: the oldest maker is filled in full before the next.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PriceTime;
+
+impl MatchingPolicy for PriceTime {
+    fn name(&self) -> &'static str {
+        "price_time"
+    }
+
+    fn allocate(
+        &self,
+        makers: &[(OrderId, Quantity)],
+        quantity: Quantity,
+    ) -> Vec<(OrderId, Quantity)> {
+        let mut remaining = quantity;
+        let mut allocations = Vec::new();
+        for &(order_id, size) in makers {
+            if remaining == 0 {
+                break;
+            }
+            let fill = remaining.min(size);
+            remaining -= fill;
+            allocations.push((order_id, fill));
+        }
+        allocations
+    }
+}
+
+/// Every maker gets a share proportional to its resting size.
+///
+/// Shares are rounded down and the units left over go one each to the makers
+/// with the largest rounding remainders, older makers first on a tie. Each
+/// maker thus ends within one unit of its exact share, and a maker too small
+/// for a whole unit can still be filled when it has the larger remainder.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProRata;
+
+impl MatchingPolicy for ProRata {
+    fn name(&self) -> &'static str {
+        "pro_rata"
+    }
+
+    fn allocate(
+        &self,
+        makers: &[(OrderId, Quantity)],
+        quantity: Quantity,
+    ) -> Vec<(OrderId, Quantity)> {
+        pro_rata(makers, quantity)
+    }
+}
+
+/// The oldest maker at the level, the one that set it, is filled first; what
+/// is left is split pro-rata across the others as [`ProRata`] does.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProRataTopOrder;
+
+impl MatchingPolicy for ProRataTopOrder {
+    fn name(&self) -> &'static str {
+        "pro_rata_top_order"
+    }
+
+    fn allocate(
+        &self,
+        makers: &[(OrderId, Quantity)],
+        quantity: Quantity,
+    ) -> Vec<(OrderId, Quantity)> {
+        let Some((&(top_id, top_size), rest)) = makers.split_first() else {
+            return Vec::new();
+        };
+        let top_fill = quantity.min(top_size);
+        let mut allocations = Vec::with_capacity(makers.len());
+        if top_fill > 0 {
+            allocations.push((top_id, top_fill));
+        }
+        allocations.extend(pro_rata(rest, quantity - top_fill));
+        allocations
+    }
+}
+
+/// Largest-remainder apportionment of `quantity` by maker size.
+fn pro_rata(makers: &[(OrderId, Quantity)], quantity: Quantity) -> Vec<(OrderId, Quantity)> {
+    let total: u128 = makers.iter().map(|&(_, size)| u128::from(size)).sum();
+    if quantity == 0 || total == 0 {
+        return Vec::new();
+    }
+    if u128::from(quantity) >= total {
+        return makers
+            .iter()
+            .filter(|&&(_, size)| size > 0)
+            .copied()
+            .collect();
+    }
+
+    // Floor of each exact share, with the remainder of the division kept to
+    // rank makers for the leftover units.
+    let mut shares: Vec<(Quantity, u128)> = makers
+        .iter()
+        .map(|&(_, size)| {
+            let product = u128::from(quantity) * u128::from(size);
+            // Below `size` as `quantity` is below `total`
+            ((product / total) as Quantity, product % total)
+        })
+        .collect();
+    let assigned: u128 = shares.iter().map(|&(share, _)| u128::from(share)).sum();
+    // Each share is short of exact by under one unit, so fewer units are
+    // left than there are makers with a remainder.
+    let leftover = (u128::from(quantity) - assigned) as usize;
+    let mut ranked: Vec<usize> = (0..makers.len())
+        .filter(|&index| shares[index].1 > 0)
+        .collect();
+    ranked.sort_by(|&a, &b| shares[b].1.cmp(&shares[a].1).then(a.cmp(&b)));
+    for &index in ranked.iter().take(leftover) {
+        shares[index].0 += 1;
+    }
+
+    makers
+        .iter()
+        .zip(shares)
+        .filter(|(_, (share, _))| *share > 0)
+        .map(|(&(order_id, _), (share, _))| (order_id, share))
+        .collect()
+}
+
+/// The built-in policies, as named in configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchingAlgorithm {
+    PriceTime,
+    ProRata,
+    ProRataTopOrder,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("unknown matching algorithm '{0}', expected price_time, pro_rata or pro_rata_top_order")]
+pub struct UnknownAlgorithm(pub String);
+
+impl MatchingAlgorithm {
+    pub fn policy(self) -> Arc<dyn MatchingPolicy> {
+        match self {
+            Self::PriceTime => Arc::new(PriceTime),
+            Self::ProRata => Arc::new(ProRata),
+            Self::ProRataTopOrder => Arc::new(ProRataTopOrder),
+        }
+    }
+}
+
+impl FromStr for MatchingAlgorithm {
+    type Err = UnknownAlgorithm;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "price_time" | "fifo" => Ok(Self::PriceTime),
+            "pro_rata" => Ok(Self::ProRata),
+            "pro_rata_top_order" => Ok(Self::ProRataTopOrder),
+            _ => Err(UnknownAlgorithm(raw.to_string())),
+        }
+    }
+}
+
+/// Matching policy of each pair.
+#[derive(Debug, Clone, Default)]
+pub struct MatchingRegistry {
+    by_pair: HashMap<TradingPair, Arc<dyn MatchingPolicy>>,
+}
+
+impl MatchingRegistry {
+    /// Match `pair` with `policy` from now on.
+    pub fn set(&mut self, pair: TradingPair, policy: Arc<dyn MatchingPolicy>) {
+        self.by_pair.insert(pair, policy);
+    }
+
+    /// The policy `pair` matches with, FIFO unless configured otherwise.
+    pub fn policy(&self, pair: &TradingPair) -> &dyn MatchingPolicy {
+        match self.by_pair.get(pair) {
+            Some(policy) => policy.as_ref(),
+            None => &PriceTime,
+        }
+    }
+
+    /// Pairs with a policy other than the default, with its name.
+    pub fn configured(&self) -> impl Iterator<Item = (&TradingPair, &'static str)> {
+        self.by_pair
+            .iter()
+            .map(|(pair, policy)| (pair, policy.name()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allocate(
+        policy: &dyn MatchingPolicy,
+        sizes: &[Quantity],
+        quantity: Quantity,
+    ) -> Vec<Quantity> {
+        let makers: Vec<(OrderId, Quantity)> = sizes
+            .iter()
+            .enumerate()
+            .map(|(index, &size)| (index as OrderId + 1, size))
+            .collect();
+        let allocations = policy.allocate(&makers, quantity);
+        let mut per_maker = vec![0; sizes.len()];
+        for (order_id, fill) in allocations {
+            per_maker[order_id as usize - 1] = fill;
+        }
+        per_maker
+    }
+
+    #[test]
+    fn price_time_fills_oldest_first() {
+        assert_eq!(allocate(&PriceTime, &[30, 50, 20], 60), vec![30, 30, 0]);
+        assert_eq!(allocate(&PriceTime, &[30, 50, 20], 100), vec![30, 50, 20]);
+        assert_eq!(PriceTime.allocate(&[(1, 30), (2, 50)], 30), vec![(1, 30)]);
+        assert!(PriceTime.allocate(&[(1, 30)], 0).is_empty());
+    }
+
+    #[test]
+    fn pro_rata_splits_by_size() {
+        assert_eq!(
+            allocate(&ProRata, &[100, 300, 600], 500),
+            vec![50, 150, 300]
+        );
+        assert_eq!(
+            allocate(&ProRata, &[100, 300, 600], 1000),
+            vec![100, 300, 600]
+        );
+        // Allocations come back in time priority.
+        assert_eq!(
+            ProRata.allocate(&[(7, 10), (3, 30)], 20),
+            vec![(7, 5), (3, 15)]
+        );
+    }
+
+    #[test]
+    fn pro_rata_leftover_goes_to_largest_remainders() {
+        // Exact shares 0.5, 0.5, 1: the tie on the first two goes to the older.
+        assert_eq!(allocate(&ProRata, &[1, 1, 2], 2), vec![1, 0, 1]);
+        // Exact shares 1.25, 1.25, 2.5: the unit left goes to the half.
+        assert_eq!(allocate(&ProRata, &[10, 10, 20], 5), vec![1, 1, 3]);
+        // Exact shares 1.17, 2.33, 3.5: likewise.
+        assert_eq!(allocate(&ProRata, &[10, 20, 30, 0], 7), vec![1, 2, 4, 0]);
+        // Exact shares 0.33 each: one unit to the oldest.
+        assert_eq!(allocate(&ProRata, &[5, 5, 5], 1), vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn pro_rata_fills_small_makers_on_remainder() {
+        // Exact shares 0.9 and 9.1: the small maker's larger remainder wins the unit.
+        assert_eq!(allocate(&ProRata, &[9, 91], 10), vec![1, 9]);
+        // One-lot makers never get more than they have.
+        assert_eq!(allocate(&ProRata, &[1, 1, 1, 1], 3), vec![1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn pro_rata_handles_extreme_sizes() {
+        let huge = Quantity::MAX;
+        assert_eq!(
+            allocate(&ProRata, &[huge, huge], huge),
+            vec![huge / 2 + 1, huge / 2]
+        );
+        assert_eq!(allocate(&ProRata, &[huge, 1], 2), vec![2, 0]);
+        assert!(ProRata.allocate(&[], 0).is_empty());
+        assert!(ProRata.allocate(&[(1, 0)], 0).is_empty());
+    }
+
+    #[test]
+    fn top_order_is_filled_before_the_rest() {
+        assert_eq!(
+            allocate(&ProRataTopOrder, &[30, 100, 300], 30),
+            vec![30, 0, 0]
+        );
+        assert_eq!(
+            allocate(&ProRataTopOrder, &[30, 100, 300], 230),
+            vec![30, 50, 150]
+        );
+        assert_eq!(
+            allocate(&ProRataTopOrder, &[30, 100, 300], 20),
+            vec![20, 0, 0]
+        );
+        assert_eq!(
+            allocate(&ProRataTopOrder, &[30, 100, 300], 430),
+            vec![30, 100, 300]
+        );
+        // Leftover units of the pro-rata part follow the same rule.
+        assert_eq!(
+            allocate(&ProRataTopOrder, &[1, 1, 1, 2], 3),
+            vec![1, 1, 0, 1]
+        );
+        assert!(ProRataTopOrder.allocate(&[], 0).is_empty());
+    }
+
+    /// Allocations across many pseudo-random levels conserve quantity, stay
+    /// within each maker's size and within one unit of the exact share.
+    #[test]
+    fn allocations_are_conservative_and_fair() {
+        let mut seed: u64 = 0x5eed;
+        let mut next = |bound: u64| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed >> 33) % bound
+        };
+        let policies: [&dyn MatchingPolicy; 3] = [&PriceTime, &ProRata, &ProRataTopOrder];
+        for _ in 0..2000 {
+            let sizes: Vec<Quantity> = (0..next(8) + 1).map(|_| next(1000) + 1).collect();
+            let total: Quantity = sizes.iter().sum();
+            let quantity = next(total + 1);
+            for policy in policies {
+                let fills = allocate(policy, &sizes, quantity);
+                assert_eq!(fills.iter().sum::<Quantity>(), quantity, "{:?}", policy);
+                assert!(fills.iter().zip(&sizes).all(|(fill, size)| fill <= size));
+            }
+            let fills = allocate(&ProRata, &sizes, quantity);
+            for (&fill, &size) in fills.iter().zip(&sizes) {
+                let exact = u128::from(quantity) * u128::from(size);
+                let total = u128::from(total);
+                let floor = exact / total;
+                let fill = u128::from(fill);
+                assert!(
+                    fill == floor || fill == floor + 1,
+                    "{:?} {}",
+                    sizes,
+                    quantity
+                );
+                if exact % total == 0 {
+                    assert_eq!(fill, floor);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn registry_defaults_to_price_time() {
+        let eth: TradingPair = "ETH-USDC".parse().unwrap();
+        let btc: TradingPair = "BTC-USDC".parse().unwrap();
+        let mut registry = MatchingRegistry::default();
+        registry.set(
+            eth.clone(),
+            "pro_rata".parse::<MatchingAlgorithm>().unwrap().policy(),
+        );
+        assert_eq!(registry.policy(&eth).name(), "pro_rata");
+        assert_eq!(registry.policy(&btc).name(), "price_time");
+        assert_eq!(
+            registry.configured().collect::<Vec<_>>(),
+            vec![(&eth, "pro_rata")]
+        );
+        assert_eq!("fifo".parse(), Ok(MatchingAlgorithm::PriceTime));
+        assert!("lifo".parse::<MatchingAlgorithm>().is_err());
+    }
+}
@@ -394,11 +394,11 @@ mod tests {
     fn test_multisig_wallet_creation() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -420,7 +420,7 @@ mod tests {
 
         // Test with required signatures greater than participants
         let participants = vec![WalletParticipant {
-            id: "participant1".to_string(),
+            id: "participant1".parse().unwrap(),
             public_key: "pubkey1".to_string(),
         }];
 
@@ -433,7 +433,7 @@ mod tests {
 
         // Test with zero required signatures
         let participants = vec![WalletParticipant {
-            id: "participant1".to_string(),
+            id: "participant1".parse().unwrap(),
             public_key: "pubkey1".to_string(),
         }];
 
@@ -449,11 +449,11 @@ mod tests {
     fn test_asset_management() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -461,33 +461,33 @@ mod tests {
         let mut wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Test deposit
-        wallet.deposit("BTC".to_string(), 1000);
-        assert_eq!(wallet.get_balance(&"BTC".to_string()), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
+        assert_eq!(wallet.get_balance(&"BTC".parse().unwrap()), 1000);
 
         // Test get all balances
         let balances = wallet.get_all_balances();
         assert_eq!(balances.len(), 1);
-        assert_eq!(balances.get(&"BTC".to_string()), Some(&1000));
+        assert_eq!(balances.get("BTC"), Some(&1000));
 
         // Test deposit more of the same token
-        wallet.deposit("BTC".to_string(), 500);
-        assert_eq!(wallet.get_balance(&"BTC".to_string()), 1500);
+        wallet.deposit("BTC".parse().unwrap(), 500);
+        assert_eq!(wallet.get_balance(&"BTC".parse().unwrap()), 1500);
 
         // Test deposit different token
-        wallet.deposit("ETH".to_string(), 100);
-        assert_eq!(wallet.get_balance(&"BTC".to_string()), 1500);
-        assert_eq!(wallet.get_balance(&"ETH".to_string()), 100);
+        wallet.deposit("ETH".parse().unwrap(), 100);
+        assert_eq!(wallet.get_balance(&"BTC".parse().unwrap()), 1500);
+        assert_eq!(wallet.get_balance(&"ETH".parse().unwrap()), 100);
     }
 
     #[test]
     fn test_transaction_creation() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -495,21 +495,21 @@ mod tests {
         let mut wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Deposit some assets
-        wallet.deposit("BTC".to_string(), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
 
         // Create a transaction
         let transaction_id = wallet
-            .create_transaction("recipient1".to_string(), "BTC".to_string(), 500)
+            .create_transaction("recipient1".to_string(), "BTC".parse().unwrap(), 500)
             .unwrap();
         assert_eq!(transaction_id, 1);
         assert_eq!(wallet.pending_transaction_count(), 1);
         assert_eq!(wallet.executed_transaction_count(), 0);
 
         // Check that the funds were deducted from the wallet
-        assert_eq!(wallet.get_balance(&"BTC".to_string()), 500);
+        assert_eq!(wallet.get_balance(&"BTC".parse().unwrap()), 500);
 
         // Try to create a transaction with insufficient funds
-        let result = wallet.create_transaction("recipient2".to_string(), "BTC".to_string(), 1000);
+        let result = wallet.create_transaction("recipient2".to_string(), "BTC".parse().unwrap(), 1000);
         assert!(result.is_err());
         assert!(matches!(
             result.unwrap_err(),
@@ -521,15 +521,15 @@ mod tests {
     fn test_transaction_signing() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
             WalletParticipant {
-                id: "participant3".to_string(),
+                id: "participant3".parse().unwrap(),
                 public_key: "pubkey3".to_string(),
             },
         ];
@@ -537,11 +537,11 @@ mod tests {
         let mut wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Deposit some assets
-        wallet.deposit("BTC".to_string(), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
 
         // Create a transaction
         let transaction_id = wallet
-            .create_transaction("recipient1".to_string(), "BTC".to_string(), 500)
+            .create_transaction("recipient1".to_string(), "BTC".parse().unwrap(), 500)
             .unwrap();
 
         // Get the transaction
@@ -551,24 +551,24 @@ mod tests {
 
         // Sign the transaction with participant1
         wallet
-            .sign_transaction(transaction_id, "participant1".to_string())
+            .sign_transaction(transaction_id, "participant1".parse().unwrap())
             .unwrap();
 
         // Check that the signature was added
         let transaction = wallet.get_pending_transaction(transaction_id).unwrap();
         assert_eq!(transaction.signatures.len(), 1);
-        assert!(transaction.has_signature_from(&"participant1".to_string()));
+        assert!(transaction.has_signature_from(&"participant1".parse().unwrap()));
         assert!(!transaction.is_ready_for_execution()); // Still need one more signature
 
         // Sign the transaction with participant2
         wallet
-            .sign_transaction(transaction_id, "participant2".to_string())
+            .sign_transaction(transaction_id, "participant2".parse().unwrap())
             .unwrap();
 
         // Check that the signature was added
         let transaction = wallet.get_pending_transaction(transaction_id).unwrap();
         assert_eq!(transaction.signatures.len(), 2);
-        assert!(transaction.has_signature_from(&"participant2".to_string()));
+        assert!(transaction.has_signature_from(&"participant2".parse().unwrap()));
         assert!(transaction.is_ready_for_execution()); // Now has enough signatures
     }
 
@@ -576,11 +576,11 @@ mod tests {
     fn test_transaction_execution() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -588,19 +588,19 @@ mod tests {
         let mut wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Deposit some assets
-        wallet.deposit("BTC".to_string(), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
 
         // Create a transaction
         let transaction_id = wallet
-            .create_transaction("recipient1".to_string(), "BTC".to_string(), 500)
+            .create_transaction("recipient1".to_string(), "BTC".parse().unwrap(), 500)
             .unwrap();
 
         // Sign the transaction with both participants
         wallet
-            .sign_transaction(transaction_id, "participant1".to_string())
+            .sign_transaction(transaction_id, "participant1".parse().unwrap())
             .unwrap();
         wallet
-            .sign_transaction(transaction_id, "participant2".to_string())
+            .sign_transaction(transaction_id, "participant2".parse().unwrap())
             .unwrap();
 
         // Execute the transaction
@@ -620,11 +620,11 @@ mod tests {
     fn test_transaction_cancellation() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -632,15 +632,15 @@ mod tests {
         let mut wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Deposit some assets
-        wallet.deposit("BTC".to_string(), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
 
         // Create a transaction
         let transaction_id = wallet
-            .create_transaction("recipient1".to_string(), "BTC".to_string(), 500)
+            .create_transaction("recipient1".to_string(), "BTC".parse().unwrap(), 500)
             .unwrap();
 
         // Check that the funds were deducted from the wallet
-        assert_eq!(wallet.get_balance(&"BTC".to_string()), 500);
+        assert_eq!(wallet.get_balance(&"BTC".parse().unwrap()), 500);
 
         // Cancel the transaction
         wallet.cancel_transaction(transaction_id).unwrap();
@@ -650,7 +650,7 @@ mod tests {
         assert_eq!(wallet.executed_transaction_count(), 0);
 
         // Check that the funds were returned to the wallet
-        assert_eq!(wallet.get_balance(&"BTC".to_string()), 1000);
+        assert_eq!(wallet.get_balance(&"BTC".parse().unwrap()), 1000);
     }
 
     #[test]
@@ -660,11 +660,11 @@ mod tests {
         // Create a wallet
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -695,11 +695,11 @@ mod tests {
     fn test_participant_verification() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -707,20 +707,20 @@ mod tests {
         let wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Test participant verification
-        assert!(wallet.is_participant(&"participant1".to_string()));
-        assert!(wallet.is_participant(&"participant2".to_string()));
-        assert!(!wallet.is_participant(&"participant3".to_string()));
+        assert!(wallet.is_participant(&"participant1".parse().unwrap()));
+        assert!(wallet.is_participant(&"participant2".parse().unwrap()));
+        assert!(!wallet.is_participant(&"participant3".parse().unwrap()));
     }
 
     #[test]
     fn test_invalid_transaction_signing() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -728,7 +728,7 @@ mod tests {
         let mut wallet = MultiSigWallet::new("wallet1".to_string(), participants, 2).unwrap();
 
         // Try to sign a non-existent transaction
-        let result = wallet.sign_transaction(999, "participant1".to_string());
+        let result = wallet.sign_transaction(999, "participant1".parse().unwrap());
         assert!(result.is_err());
         assert!(matches!(
             result.unwrap_err(),
@@ -736,13 +736,13 @@ mod tests {
         ));
 
         // Create a transaction
-        wallet.deposit("BTC".to_string(), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
         let transaction_id = wallet
-            .create_transaction("recipient1".to_string(), "BTC".to_string(), 500)
+            .create_transaction("recipient1".to_string(), "BTC".parse().unwrap(), 500)
             .unwrap();
 
         // Try to sign with a non-participant
-        let result = wallet.sign_transaction(transaction_id, "participant3".to_string());
+        let result = wallet.sign_transaction(transaction_id, "participant3".parse().unwrap());
         assert!(result.is_err());
         assert!(matches!(result.unwrap_err(), MultiSigError::NotParticipant));
     }
@@ -751,11 +751,11 @@ mod tests {
     fn test_invalid_transaction_execution() {
         let participants = vec![
             WalletParticipant {
-                id: "participant1".to_string(),
+                id: "participant1".parse().unwrap(),
                 public_key: "pubkey1".to_string(),
             },
             WalletParticipant {
-                id: "participant2".to_string(),
+                id: "participant2".parse().unwrap(),
                 public_key: "pubkey2".to_string(),
             },
         ];
@@ -771,9 +771,9 @@ mod tests {
         ));
 
         // Create a transaction
-        wallet.deposit("BTC".to_string(), 1000);
+        wallet.deposit("BTC".parse().unwrap(), 1000);
         let transaction_id = wallet
-            .create_transaction("recipient1".to_string(), "BTC".to_string(), 500)
+            .create_transaction("recipient1".to_string(), "BTC".parse().unwrap(), 500)
             .unwrap();
 
         // Try to execute without enough signatures
@@ -786,10 +786,10 @@ mod tests {
 
         // Sign the transaction
         wallet
-            .sign_transaction(transaction_id, "participant1".to_string())
+            .sign_transaction(transaction_id, "participant1".parse().unwrap())
             .unwrap();
         wallet
-            .sign_transaction(transaction_id, "participant2".to_string())
+            .sign_transaction(transaction_id, "participant2".parse().unwrap())
             .unwrap();
 
         // Execute the transaction
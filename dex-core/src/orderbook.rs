@@ -2,7 +2,8 @@
 
 use crate::merkle_tree::MerkleTree;
 use crate::avl_tree::AvlPriceLevelTree;
-use crate::types::{Order, OrderId, OrderSide, Price, Quantity, Trade};
+use crate::matching::MatchingRegistry;
+use crate::types::{Notional, Order, OrderId, OrderSide, OrderType, Price, Quantity, Trade};
 use std::cmp::Reverse;
 use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
 
@@ -14,6 +15,14 @@ pub struct PriceLevel {
     pub total_quantity: Quantity,
 }
 
+/// A planned match against one resting maker order
+#[derive(Debug, Clone, Copy)]
+struct Fill {
+    maker_order_id: OrderId,
+    price: Price,
+    quantity: Quantity,
+}
+
 /// Wrapper for OrderId to implement Ord trait for time priority queue
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) struct TimePriorityOrder {
@@ -64,11 +73,18 @@ pub struct OrderBook {
     /// This implements the Priority 1 feature from DEX-OS-V1.csv:
     /// "Core Trading,Orderbook,Orderbook,Queue,Transaction Mempool,High"
     pub transaction_mempool: VecDeque<Order>,
+    /// How each pair splits a price level's fill across its makers
+    pub matching: MatchingRegistry,
 }
 
 impl OrderBook {
-    /// Create a new empty orderbook
+    /// Create a new empty orderbook matching every pair in price-time priority
     pub fn new() -> Self {
+        Self::with_matching(MatchingRegistry::default())
+    }
+
+    /// Create a new empty orderbook matching pairs with the policies in `matching`
+    pub fn with_matching(matching: MatchingRegistry) -> Self {
         Self {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
@@ -77,16 +93,22 @@ impl OrderBook {
             orders: HashMap::new(),
             time_priority_queue: BinaryHeap::new(),
             transaction_mempool: VecDeque::new(),
+            matching,
         }
     }
 
     /// Add an order to the orderbook and match it against existing orders
     /// This implements the Priority 1 feature from DEX-OS-V1.csv:
     /// "Core Trading,Orderbook,Orderbook,Price-Time Priority,Order Matching,High"
+    ///
+    /// Overflow is detected before the book is touched, so a rejected order
+    /// leaves the book unchanged.
     pub fn add_order(&mut self, order: Order) -> Result<Vec<Trade>, OrderBookError> {
-        // Store the order
-        let order_id = order.id;
-        self.orders.insert(order_id, order.clone());
+        let fills = self.plan_fills(&order)?;
+        let resting = Self::resting_part(&order, &fills);
+        if let Some(resting) = &resting {
+            self.check_level_capacity(resting)?;
+        }
 
         // Add order to time priority queue
         // This implements the Priority 1 feature from DEX-OS-V1.csv:
@@ -96,21 +118,79 @@ impl OrderBook {
             order_id: order.id,
         }));
 
-        // Try to match the order
-        let trades = self.match_order(&order);
+        // Execute the matched fills
+        let trades = self.execute_fills(&order, fills);
 
-        // Add remaining order to the book if it wasn't fully filled
-        if order.quantity > 0 {
-            // Add to the appropriate side of the book
-            match order.side {
-                OrderSide::Buy => self.add_bid(order),
-                OrderSide::Sell => self.add_ask(order),
+        // Store and rest whatever is left of a limit order
+        if let Some(resting) = resting {
+            self.orders.insert(resting.id, resting.clone());
+            match resting.side {
+                OrderSide::Buy => self.add_bid(resting),
+                OrderSide::Sell => self.add_ask(resting),
             }
         }
 
         Ok(trades)
     }
 
+    /// Rest a previously accepted order on the book without matching it, e.g.
+    /// when rebuilding the book after a restart. `order.quantity` is what is
+    /// left of the order; orders without a limit price never rest and are
+    /// ignored.
+    pub fn restore_order(&mut self, order: Order) -> Result<(), OrderBookError> {
+        if order.price.is_none() || order.quantity == 0 {
+            return Ok(());
+        }
+        self.check_level_capacity(&order)?;
+        self.orders.insert(order.id, order.clone());
+        self.time_priority_queue.push(Reverse(TimePriorityOrder {
+            timestamp: order.timestamp,
+            order_id: order.id,
+        }));
+        match order.side {
+            OrderSide::Buy => self.add_bid(order),
+            OrderSide::Sell => self.add_ask(order),
+        }
+        Ok(())
+    }
+
+    /// Orders resting on the book with their remaining quantity, bids then
+    /// asks, each level in queue order. Restoring them in this order rebuilds
+    /// the same book.
+    pub fn resting_orders(&self) -> Vec<Order> {
+        self.bids
+            .values()
+            .chain(self.asks.values())
+            .flat_map(|level| &level.orders)
+            .filter_map(|order_id| self.orders.get(order_id).cloned())
+            .collect()
+    }
+
+    /// Run the checks `add_order` makes without touching the book, returning
+    /// the quantity that would execute immediately
+    pub fn check_order(&self, order: &Order) -> Result<Quantity, OrderBookError> {
+        let fills = self.plan_fills(order)?;
+        if let Some(resting) = Self::resting_part(order, &fills) {
+            self.check_level_capacity(&resting)?;
+        }
+        Ok(fills.iter().map(|fill| fill.quantity).sum())
+    }
+
+    /// What is left of `order` to rest on the book once `fills` execute.
+    /// Market orders and fully filled orders leave nothing behind.
+    fn resting_part(order: &Order, fills: &[Fill]) -> Option<Order> {
+        if order.order_type == OrderType::Market {
+            return None;
+        }
+        // Planned fills never exceed the order's quantity
+        let filled: Quantity = fills.iter().map(|fill| fill.quantity).sum();
+        let remaining = order.quantity - filled;
+        (remaining > 0).then(|| Order {
+            quantity: remaining,
+            ..order.clone()
+        })
+    }
+
     /// Add an order to the transaction mempool
     /// This implements the Priority 1 feature from DEX-OS-V1.csv:
     /// "Core Trading,Orderbook,Orderbook,Queue,Transaction Mempool,High"
@@ -134,172 +214,113 @@ impl OrderBook {
         self.transaction_mempool.len()
     }
 
-    /// Match an order against existing orders in the book using price-time priority
+    /// Match an order against existing orders in the book using price priority,
+    /// splitting each level across its makers with the pair's matching policy
     /// This implements the Priority 1 feature from DEX-OS-V1.csv:
     /// "Core Trading,Orderbook,Orderbook,Price-Time Priority,Order Matching,High"
-    fn match_order(&mut self, order: &Order) -> Vec<Trade> {
-        let mut trades = Vec::new();
+    ///
+    /// Only reads the book: the fills are computed with checked arithmetic and
+    /// applied afterwards by `execute_fills`.
+    fn plan_fills(&self, order: &Order) -> Result<Vec<Fill>, OrderBookError> {
+        // Buys walk asks in ascending price order, sells walk bids in descending order
+        let levels: Box<dyn Iterator<Item = (&Price, &PriceLevel)>> = match order.side {
+            OrderSide::Buy => Box::new(self.asks.iter()),
+            OrderSide::Sell => Box::new(self.bids.iter().rev()),
+        };
+
+        let policy = self.matching.policy(&order.pair);
+        let mut fills = Vec::new();
         let mut remaining_quantity = order.quantity;
+        let mut notional = Notional::ZERO;
 
-        match order.side {
-            OrderSide::Buy => {
-                // Match against asks (sell orders)
-                let mut asks_to_remove = Vec::new();
-
-                // Iterate through asks in ascending price order
-                for (&ask_price, ask_level) in self.asks.iter_mut() {
-                    // If this is a limit order and the ask price is higher than our limit, stop matching
-                    if let Some(limit_price) = order.price {
-                        if ask_price > limit_price {
-                            break;
-                        }
-                    }
-
-                    // Match against orders at this price level in time priority (FIFO)
-                    // Orders are processed in the order they were added to the vector (FIFO)
-                    let mut orders_to_remove = Vec::new();
-                    for &ask_order_id in &ask_level.orders {
-                        if let Some(ask_order) = self.orders.get(&ask_order_id) {
-                            let trade_quantity =
-                                std::cmp::min(remaining_quantity, ask_order.quantity);
-
-                            // Create a trade
-                            let trade = Trade {
-                                id: 0, // This will be set by the trade ID counter in the API
-                                maker_order_id: ask_order_id,
-                                taker_order_id: order.id,
-                                base_token: ask_order.pair.base.clone(),
-                                quote_token: ask_order.pair.quote.clone(),
-                                price: ask_price,
-                                quantity: trade_quantity,
-                                timestamp: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                            };
-
-                            trades.push(trade);
-                            remaining_quantity -= trade_quantity;
-
-                            // If the ask order is fully filled, mark it for removal
-                            if trade_quantity >= ask_order.quantity {
-                                orders_to_remove.push(ask_order_id);
-                            } else {
-                                // Update the remaining quantity of the ask order
-                                if let Some(ask_order_mut) = self.orders.get_mut(&ask_order_id) {
-                                    ask_order_mut.quantity -= trade_quantity;
-                                }
-                            }
-
-                            // If the taker order is fully filled, stop matching
-                            if remaining_quantity == 0 {
-                                break;
-                            }
-                        }
-                    }
-
-                    // Remove fully filled ask orders
-                    for order_id in orders_to_remove {
-                        ask_level.orders.retain(|&id| id != order_id);
-                        ask_level.total_quantity -=
-                            self.orders.get(&order_id).map(|o| o.quantity).unwrap_or(0);
-                        self.orders.remove(&order_id);
-                    }
-
-                    // If the price level is empty, mark it for removal
-                    if ask_level.orders.is_empty() {
-                        asks_to_remove.push(ask_price);
-                    }
-
-                    // If the taker order is fully filled, stop matching
-                    if remaining_quantity == 0 {
-                        break;
-                    }
-                }
+        for (&level_price, level) in levels {
+            if remaining_quantity == 0 {
+                break;
+            }
 
-                // Remove empty ask price levels
-                for price in asks_to_remove {
-                    self.asks.remove(&price);
-                }
+            // If this is a limit order and the level is beyond our limit, stop matching
+            let crosses = match (order.side, order.price) {
+                (_, None) => true,
+                (OrderSide::Buy, Some(limit_price)) => level_price <= limit_price,
+                (OrderSide::Sell, Some(limit_price)) => level_price >= limit_price,
+            };
+            if !crosses {
+                break;
             }
-            OrderSide::Sell => {
-                // Match against bids (buy orders)
-                let mut bids_to_remove = Vec::new();
-
-                // Iterate through bids in descending price order
-                for (&bid_price, bid_level) in self.bids.iter_mut().rev() {
-                    // If this is a limit order and the bid price is lower than our limit, stop matching
-                    if let Some(limit_price) = order.price {
-                        if bid_price < limit_price {
-                            break;
-                        }
-                    }
-
-                    // Match against orders at this price level in time priority (FIFO)
-                    // Orders are processed in the order they were added to the vector (FIFO)
-                    let mut orders_to_remove = Vec::new();
-                    for &bid_order_id in &bid_level.orders {
-                        if let Some(bid_order) = self.orders.get(&bid_order_id) {
-                            let trade_quantity =
-                                std::cmp::min(remaining_quantity, bid_order.quantity);
-
-                            // Create a trade
-                            let trade = Trade {
-                                id: 0, // This will be set by the trade ID counter in the API
-                                maker_order_id: bid_order_id,
-                                taker_order_id: order.id,
-                                base_token: bid_order.pair.base.clone(),
-                                quote_token: bid_order.pair.quote.clone(),
-                                price: bid_price,
-                                quantity: trade_quantity,
-                                timestamp: std::time::SystemTime::now()
-                                    .duration_since(std::time::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_secs(),
-                            };
-
-                            trades.push(trade);
-                            remaining_quantity -= trade_quantity;
-
-                            // If the bid order is fully filled, mark it for removal
-                            if trade_quantity >= bid_order.quantity {
-                                orders_to_remove.push(bid_order_id);
-                            } else {
-                                // Update the remaining quantity of the bid order
-                                if let Some(bid_order_mut) = self.orders.get_mut(&bid_order_id) {
-                                    bid_order_mut.quantity -= trade_quantity;
-                                }
-                            }
-
-                            // If the taker order is fully filled, stop matching
-                            if remaining_quantity == 0 {
-                                break;
-                            }
-                        }
-                    }
-
-                    // Remove fully filled bid orders
-                    for order_id in orders_to_remove {
-                        bid_level.orders.retain(|&id| id != order_id);
-                        bid_level.total_quantity -=
-                            self.orders.get(&order_id).map(|o| o.quantity).unwrap_or(0);
-                        self.orders.remove(&order_id);
-                    }
-
-                    // If the price level is empty, mark it for removal
-                    if bid_level.orders.is_empty() {
-                        bids_to_remove.push(bid_price);
-                    }
-
-                    // If the taker order is fully filled, stop matching
-                    if remaining_quantity == 0 {
-                        break;
-                    }
-                }
 
-                // Remove empty bid price levels
-                for price in bids_to_remove {
-                    self.bids.remove(&price);
+            // Makers of the order's pair at this price level in time priority,
+            // with what they have left; levels are shared by every pair
+            let makers: Vec<(OrderId, Quantity)> = level
+                .orders
+                .iter()
+                .filter_map(|&maker_order_id| {
+                    let maker_order = self.orders.get(&maker_order_id)?;
+                    (maker_order.pair == order.pair)
+                        .then_some((maker_order_id, maker_order.quantity))
+                })
+                .collect();
+            let available = makers
+                .iter()
+                .fold(0, |total: Quantity, &(_, quantity)| total.saturating_add(quantity));
+
+            for (maker_order_id, quantity) in
+                policy.allocate(&makers, remaining_quantity.min(available))
+            {
+                notional = notional
+                    .checked_add(Notional::of(level_price, quantity))
+                    .ok_or(OrderBookError::NotionalOverflow)?;
+                remaining_quantity -= quantity;
+                fills.push(Fill {
+                    maker_order_id,
+                    price: level_price,
+                    quantity,
+                });
+            }
+        }
+
+        Ok(fills)
+    }
+
+    /// Apply fills produced by `plan_fills`, updating makers and price levels
+    fn execute_fills(&mut self, order: &Order, fills: Vec<Fill>) -> Vec<Trade> {
+        let book = match order.side {
+            OrderSide::Buy => &mut self.asks,
+            OrderSide::Sell => &mut self.bids,
+        };
+
+        let mut trades = Vec::with_capacity(fills.len());
+        for fill in fills {
+            let Some(maker_order) = self.orders.get_mut(&fill.maker_order_id) else {
+                continue;
+            };
+            // The planned quantity never exceeds what the maker has left
+            maker_order.quantity -= fill.quantity;
+            let fully_filled = maker_order.quantity == 0;
+
+            trades.push(Trade {
+                id: 0, // This will be set by the trade ID counter in the API
+                maker_order_id: fill.maker_order_id,
+                taker_order_id: order.id,
+                base_token: maker_order.pair.base().clone(),
+                quote_token: maker_order.pair.quote().clone(),
+                price: fill.price,
+                quantity: fill.quantity,
+                // Trades carry the taker's timestamp so replaying the same orders is deterministic
+                timestamp: order.timestamp,
+            });
+
+            if fully_filled {
+                self.orders.remove(&fill.maker_order_id);
+            }
+
+            if let Some(level) = book.get_mut(&fill.price) {
+                level.total_quantity = level.total_quantity.saturating_sub(fill.quantity);
+                if fully_filled {
+                    level.orders.retain(|&id| id != fill.maker_order_id);
+                }
+                // Remove empty price levels
+                if level.orders.is_empty() {
+                    book.remove(&fill.price);
                 }
             }
         }
@@ -307,6 +328,23 @@ impl OrderBook {
         trades
     }
 
+    /// Reject a limit order whose resting quantity would overflow its price level.
+    /// `order.quantity` is the part that would rest, not what was submitted.
+    fn check_level_capacity(&self, order: &Order) -> Result<(), OrderBookError> {
+        let Some(price) = order.price else {
+            return Ok(());
+        };
+        let book = match order.side {
+            OrderSide::Buy => &self.bids,
+            OrderSide::Sell => &self.asks,
+        };
+        let resting = book.get(&price).map_or(0, |level| level.total_quantity);
+        resting
+            .checked_add(order.quantity)
+            .map(|_| ())
+            .ok_or(OrderBookError::QuantityOverflow { price })
+    }
+
     /// Add a bid order to the orderbook
     /// This implements the Priority 1 feature from DEX-OS-V1.csv:
     /// "Core Trading,Orderbook,Orderbook,Vector,Order Queue,High"
@@ -322,7 +360,8 @@ impl OrderBook {
                 .entry(price)
                 .and_modify(|level| {
                     level.orders.push(order.id);
-                    level.total_quantity += order.quantity;
+                    // Guarded by check_level_capacity in add_order
+                    level.total_quantity = level.total_quantity.saturating_add(order.quantity);
                 })
                 .or_insert(PriceLevel {
                     price,
@@ -347,7 +386,8 @@ impl OrderBook {
                 .entry(price)
                 .and_modify(|level| {
                     level.orders.push(order.id);
-                    level.total_quantity += order.quantity;
+                    // Guarded by check_level_capacity in add_order
+                    level.total_quantity = level.total_quantity.saturating_add(order.quantity);
                 })
                 .or_insert(PriceLevel {
                     price,
@@ -364,37 +404,38 @@ impl OrderBook {
             .remove(&order_id)
             .ok_or(OrderBookError::OrderNotFound)?;
 
+        let price = order.price.unwrap_or(0);
         match order.side {
-            OrderSide::Buy => self.remove_bid(order_id, order.price.unwrap_or(0)),
-            OrderSide::Sell => self.remove_ask(order_id, order.price.unwrap_or(0)),
+            OrderSide::Buy => self.remove_bid(order_id, price, order.quantity),
+            OrderSide::Sell => self.remove_ask(order_id, price, order.quantity),
         }
 
         Ok(order)
     }
 
     /// Remove a bid order from the orderbook
-    fn remove_bid(&mut self, order_id: OrderId, price: Price) {
+    fn remove_bid(&mut self, order_id: OrderId, price: Price, quantity: Quantity) {
         if let Some(level) = self.bids.get_mut(&price) {
             level.orders.retain(|&id| id != order_id);
-            // If the price level is now empty, remove it from the AVL tree as well
+            level.total_quantity = level.total_quantity.saturating_sub(quantity);
+            // If the price level is now empty, drop it from the book and the AVL tree
             if level.orders.is_empty() {
+                self.bids.remove(&price);
                 self.bid_price_levels.remove_price_level(&price);
             }
-            // Note: In a production implementation, we would recalculate total_quantity
-            // based on remaining orders to avoid floating point errors
         }
     }
 
     /// Remove an ask order from the orderbook
-    fn remove_ask(&mut self, order_id: OrderId, price: Price) {
+    fn remove_ask(&mut self, order_id: OrderId, price: Price, quantity: Quantity) {
         if let Some(level) = self.asks.get_mut(&price) {
             level.orders.retain(|&id| id != order_id);
-            // If the price level is now empty, remove it from the AVL tree as well
+            level.total_quantity = level.total_quantity.saturating_sub(quantity);
+            // If the price level is now empty, drop it from the book and the AVL tree
             if level.orders.is_empty() {
+                self.asks.remove(&price);
                 self.ask_price_levels.remove_price_level(&price);
             }
-            // Note: In a production implementation, we would recalculate total_quantity
-            // based on remaining orders to avoid floating point errors
         }
     }
 
@@ -500,12 +541,23 @@ impl OrderBook {
 pub enum OrderBookError {
     #[error("Order not found")]
     OrderNotFound,
+    #[error("Resting quantity at price {price} would overflow")]
+    QuantityOverflow { price: Price },
+    #[error("Order notional would overflow")]
+    NotionalOverflow,
+}
+
+impl OrderBookError {
+    /// Whether the error rejects the incoming order rather than reporting book state
+    pub fn is_rejection(&self) -> bool {
+        matches!(self, Self::QuantityOverflow { .. } | Self::NotionalOverflow)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::{OrderType, TradingPair};
+    use crate::types::TradingPair;
 
     #[test]
     fn test_orderbook_creation() {
@@ -518,14 +570,11 @@ mod tests {
     #[test]
     fn test_add_order() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         let order = Order {
             id: 1,
-            trader_id: "trader1".to_string(),
+            trader_id: "trader1".parse().unwrap(),
             pair,
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -543,15 +592,12 @@ mod tests {
     #[test]
     fn test_batch_proof_generation() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         // Add multiple orders
         let order1 = Order {
             id: 1,
-            trader_id: "trader1".to_string(),
+            trader_id: "trader1".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -562,7 +608,7 @@ mod tests {
 
         let order2 = Order {
             id: 2,
-            trader_id: "trader2".to_string(),
+            trader_id: "trader2".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -586,16 +632,169 @@ mod tests {
     }
 
     #[test]
-    fn test_order_lookup() {
+    fn test_remove_order_updates_price_level() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
+        for (id, quantity) in [(1, 10), (2, 5)] {
+            let order = Order {
+                id,
+                trader_id: "trader1".parse().unwrap(),
+                pair: pair.clone(),
+                side: OrderSide::Buy,
+                order_type: OrderType::Limit,
+                price: Some(50000),
+                quantity,
+                timestamp: 1000 + id,
+            };
+            orderbook.add_order(order).unwrap();
+        }
+
+        let removed = orderbook.remove_order(1).unwrap();
+        assert_eq!(removed.quantity, 10);
+        assert_eq!(orderbook.bids.get(&50000).map(|l| l.total_quantity), Some(5));
+
+        orderbook.remove_order(2).unwrap();
+        assert!(orderbook.bids.is_empty());
+        assert_eq!(orderbook.best_bid(), None);
+    }
+
+    #[test]
+    fn test_partial_fill_updates_price_level() {
+        let mut orderbook = OrderBook::new();
+        let mut ask = limit_order(1, OrderSide::Sell, 50000, 10);
+        ask.trader_id = "seller".parse().unwrap();
+        orderbook.add_order(ask).unwrap();
+        orderbook.add_order(limit_order(2, OrderSide::Buy, 50000, 4)).unwrap();
+
+        assert_eq!(orderbook.orders.get(&1).unwrap().quantity, 6);
+        assert_eq!(orderbook.asks.get(&50000).map(|l| l.total_quantity), Some(6));
+    }
+
+    #[test]
+    fn test_fully_crossing_order_leaves_book_empty() {
+        let mut orderbook = OrderBook::new();
+        orderbook.add_order(limit_order(1, OrderSide::Sell, 100, 10)).unwrap();
+        let trades = orderbook.add_order(limit_order(2, OrderSide::Buy, 100, 10)).unwrap();
+
+        assert_eq!(trades.len(), 1);
+        assert!(orderbook.bids.is_empty());
+        assert!(orderbook.asks.is_empty());
+        assert!(orderbook.orders.is_empty());
+        assert!(orderbook.resting_orders().is_empty());
+    }
+
+    #[test]
+    fn test_taker_rests_only_its_remainder() {
+        let mut orderbook = OrderBook::new();
+        orderbook.add_order(limit_order(1, OrderSide::Sell, 100, 4)).unwrap();
+        orderbook.add_order(limit_order(2, OrderSide::Buy, 100, 10)).unwrap();
+
+        assert!(orderbook.asks.is_empty());
+        assert_eq!(orderbook.get_order(2).unwrap().quantity, 6);
+        assert_eq!(orderbook.bids.get(&100).map(|l| l.total_quantity), Some(6));
+
+        // An unfilled market order is dropped rather than stored.
+        let market = Order {
+            order_type: OrderType::Market,
+            price: None,
+            ..limit_order(3, OrderSide::Sell, 0, 10)
         };
+        orderbook.add_order(market).unwrap();
+        assert!(orderbook.bids.is_empty());
+        assert!(orderbook.orders.is_empty());
+    }
+
+    #[test]
+    fn test_level_quantity_overflow_is_rejected() {
+        let mut orderbook = OrderBook::new();
+        orderbook.add_order(limit_order(1, OrderSide::Buy, 50000, u64::MAX)).unwrap();
+
+        let result = orderbook.add_order(limit_order(2, OrderSide::Buy, 50000, 1));
+        assert!(matches!(
+            result,
+            Err(OrderBookError::QuantityOverflow { price: 50000 })
+        ));
+        assert!(result.unwrap_err().is_rejection());
+        assert!(!orderbook.orders.contains_key(&2));
+        assert_eq!(orderbook.bids.get(&50000).unwrap().orders, vec![1]);
+        assert_eq!(orderbook.bids.get(&50000).unwrap().total_quantity, u64::MAX);
+    }
+
+    #[test]
+    fn test_check_order_leaves_book_unchanged() {
+        let mut orderbook = OrderBook::new();
+        orderbook.add_order(limit_order(1, OrderSide::Sell, 50000, 10)).unwrap();
+        orderbook.add_order(limit_order(2, OrderSide::Buy, 49000, u64::MAX)).unwrap();
+
+        assert_eq!(orderbook.check_order(&limit_order(3, OrderSide::Buy, 50000, 4)).unwrap(), 4);
+        assert_eq!(orderbook.check_order(&limit_order(4, OrderSide::Buy, 51000, 25)).unwrap(), 10);
+        assert!(matches!(
+            orderbook.check_order(&limit_order(5, OrderSide::Buy, 49000, 1)),
+            Err(OrderBookError::QuantityOverflow { price: 49000 })
+        ));
+        assert_eq!(orderbook.orders.len(), 2);
+        assert_eq!(orderbook.asks.get(&50000).unwrap().total_quantity, 10);
+    }
+
+    #[test]
+    fn test_restore_order_rebuilds_book_without_matching() {
+        let mut orderbook = OrderBook::new();
+        orderbook.add_order(limit_order(1, OrderSide::Sell, 50000, 10)).unwrap();
+        orderbook.add_order(limit_order(2, OrderSide::Sell, 50000, 5)).unwrap();
+        orderbook.add_order(limit_order(3, OrderSide::Buy, 49000, 7)).unwrap();
+        orderbook.add_order(limit_order(4, OrderSide::Buy, 50000, 4)).unwrap();
+
+        let resting = orderbook.resting_orders();
+        let mut restored = OrderBook::new();
+        for order in resting.clone() {
+            restored.restore_order(order).unwrap();
+        }
+        assert_eq!(restored.resting_orders().len(), resting.len());
+        assert_eq!(restored.asks.get(&50000).unwrap().orders, vec![1, 2]);
+        assert_eq!(restored.get_order(1).unwrap().quantity, 6);
+
+        // A crossing order rests as given instead of trading.
+        restored.restore_order(limit_order(5, OrderSide::Buy, 51000, 3)).unwrap();
+        assert_eq!(restored.bids.get(&51000).unwrap().total_quantity, 3);
+        assert_eq!(restored.asks.get(&50000).unwrap().total_quantity, 11);
+    }
+
+    #[test]
+    fn test_notional_does_not_wrap() {
+        let mut orderbook = OrderBook::new();
+        orderbook.add_order(limit_order(1, OrderSide::Sell, u64::MAX, u64::MAX)).unwrap();
+        let trades = orderbook
+            .add_order(limit_order(2, OrderSide::Buy, u64::MAX, u64::MAX))
+            .unwrap();
+
+        assert_eq!(trades.len(), 1);
+        let expected = u128::from(u64::MAX) * u128::from(u64::MAX);
+        assert_eq!(trades[0].notional().value(), expected);
+        assert_eq!(trades[0].notional().to_u64(), None);
+        assert_eq!(Notional::of(3, 4).to_u64(), Some(12));
+    }
+
+    fn limit_order(id: OrderId, side: OrderSide, price: Price, quantity: Quantity) -> Order {
+        Order {
+            id,
+            trader_id: "trader1".parse().unwrap(),
+            pair: "BTC-USD".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(price),
+            quantity,
+            timestamp: 1234567890 + id,
+        }
+    }
+
+    #[test]
+    fn test_order_lookup() {
+        let mut orderbook = OrderBook::new();
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         let order = Order {
             id: 1,
-            trader_id: "trader1".to_string(),
+            trader_id: "trader1".parse().unwrap(),
             pair,
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -622,15 +821,12 @@ mod tests {
     #[test]
     fn test_order_matching() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         // Add a sell order
         let sell_order = Order {
             id: 1,
-            trader_id: "seller".to_string(),
+            trader_id: "seller".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -644,7 +840,7 @@ mod tests {
         // Add a buy order that matches
         let buy_order = Order {
             id: 2,
-            trader_id: "buyer".to_string(),
+            trader_id: "buyer".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -673,16 +869,13 @@ mod tests {
     #[test]
     fn test_price_time_priority_matching() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         // Add multiple sell orders at the same price (50000) but different times
         // Order 1 (timestamp 1000) should be matched first due to FIFO
         let sell_order1 = Order {
             id: 1,
-            trader_id: "seller1".to_string(),
+            trader_id: "seller1".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -694,7 +887,7 @@ mod tests {
         // Order 2 (timestamp 2000) should be matched second
         let sell_order2 = Order {
             id: 2,
-            trader_id: "seller2".to_string(),
+            trader_id: "seller2".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -706,7 +899,7 @@ mod tests {
         // Order 3 (timestamp 3000) should be matched third
         let sell_order3 = Order {
             id: 3,
-            trader_id: "seller3".to_string(),
+            trader_id: "seller3".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -728,7 +921,7 @@ mod tests {
         // Add a large buy order that will match all sell orders
         let buy_order = Order {
             id: 4,
-            trader_id: "buyer".to_string(),
+            trader_id: "buyer".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -759,10 +952,10 @@ mod tests {
         assert_eq!(trades[2].price, 50000);
         assert_eq!(trades[2].quantity, 50);
 
-        // Verify all sell orders have been removed from the orderbook
-        assert_eq!(orderbook.orders.len(), 1); // Only the buy order remains
-        assert_eq!(orderbook.orders.get(&4).unwrap().id, 4); // The buy order
+        // Verify the sell orders and the fully filled buy order are gone
+        assert!(orderbook.orders.is_empty());
         assert_eq!(orderbook.asks.len(), 0); // No more asks
+        assert_eq!(orderbook.bids.len(), 0); // Nothing left of the buy to rest
     }
 
     /// Test price priority matching
@@ -772,16 +965,13 @@ mod tests {
     #[test]
     fn test_price_priority_matching() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         // Add sell orders at different prices
         // Better price (lower) should be matched first
         let sell_order_high_price = Order {
             id: 1,
-            trader_id: "seller1".to_string(),
+            trader_id: "seller1".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -792,7 +982,7 @@ mod tests {
 
         let sell_order_low_price = Order {
             id: 2,
-            trader_id: "seller2".to_string(),
+            trader_id: "seller2".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -812,7 +1002,7 @@ mod tests {
         // Add a buy order that will match sell orders
         let buy_order = Order {
             id: 3,
-            trader_id: "buyer".to_string(),
+            trader_id: "buyer".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -839,6 +1029,111 @@ mod tests {
         assert_eq!(trades[1].quantity, 50);
     }
 
+    #[test]
+    fn test_pro_rata_matching_per_pair() {
+        let mut matching = MatchingRegistry::default();
+        matching.set(
+            "BTC-USD".parse().unwrap(),
+            crate::matching::MatchingAlgorithm::ProRata.policy(),
+        );
+        let mut orderbook = OrderBook::with_matching(matching);
+        orderbook.add_order(limit_order(1, OrderSide::Sell, 100, 10)).unwrap();
+        orderbook.add_order(limit_order(2, OrderSide::Sell, 100, 30)).unwrap();
+        orderbook.add_order(limit_order(3, OrderSide::Sell, 101, 50)).unwrap();
+
+        // Price priority still holds: the better level is cleared first and
+        // the 5 left over go to the next one.
+        let sweep = limit_order(4, OrderSide::Buy, 101, 45);
+        assert_eq!(orderbook.check_order(&sweep).unwrap(), 45);
+        let trades = orderbook.add_order(sweep).unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_order_id, trade.price, trade.quantity))
+            .collect();
+        assert_eq!(fills, vec![(1, 100, 10), (2, 100, 30), (3, 101, 5)]);
+
+        orderbook.add_order(limit_order(5, OrderSide::Sell, 101, 15)).unwrap();
+        // Makers of 45 and 15 at 101 split 20 as 15 and 5.
+        let trades = orderbook
+            .add_order(limit_order(6, OrderSide::Buy, 101, 20))
+            .unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|trade| (trade.maker_order_id, trade.quantity))
+            .collect();
+        assert_eq!(fills, vec![(3, 15), (5, 5)]);
+        assert_eq!(orderbook.get_order(3).unwrap().quantity, 30);
+        assert_eq!(orderbook.get_order(5).unwrap().quantity, 10);
+        assert_eq!(orderbook.asks[&101].total_quantity, 40);
+
+        // Other pairs keep price-time priority.
+        let mut eth_sell = limit_order(7, OrderSide::Sell, 100, 10);
+        eth_sell.pair = "ETH-USD".parse().unwrap();
+        let mut eth_sell_later = limit_order(8, OrderSide::Sell, 100, 30);
+        eth_sell_later.pair = eth_sell.pair.clone();
+        let mut fifo = OrderBook::with_matching(orderbook.matching.clone());
+        fifo.add_order(eth_sell.clone()).unwrap();
+        fifo.add_order(eth_sell_later).unwrap();
+        let mut eth_buy = limit_order(9, OrderSide::Buy, 100, 10);
+        eth_buy.pair = eth_sell.pair;
+        let trades = fifo.add_order(eth_buy).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].maker_order_id, 7);
+    }
+
+    #[test]
+    fn test_makers_of_other_pairs_are_never_matched() {
+        let mut matching = MatchingRegistry::default();
+        matching.set(
+            "BTC-USD".parse().unwrap(),
+            crate::matching::MatchingAlgorithm::ProRata.policy(),
+        );
+        let mut orderbook = OrderBook::with_matching(matching);
+        let eth: TradingPair = "ETH-USD".parse().unwrap();
+        // Both pairs rest makers at 100, ETH-USD first.
+        let mut eth_sell = limit_order(1, OrderSide::Sell, 100, 10);
+        eth_sell.pair = eth.clone();
+        orderbook.add_order(eth_sell).unwrap();
+        orderbook
+            .add_order(limit_order(2, OrderSide::Sell, 100, 10))
+            .unwrap();
+        orderbook
+            .add_order(limit_order(3, OrderSide::Sell, 100, 30))
+            .unwrap();
+        let mut eth_sell_later = limit_order(4, OrderSide::Sell, 100, 30);
+        eth_sell_later.pair = eth.clone();
+        orderbook.add_order(eth_sell_later).unwrap();
+
+        let fills = |trades: Vec<Trade>| -> Vec<(OrderId, Quantity)> {
+            trades
+                .iter()
+                .map(|trade| (trade.maker_order_id, trade.quantity))
+                .collect()
+        };
+
+        // BTC-USD splits its own makers pro-rata, skipping the older ETH-USD
+        // maker at the same price.
+        let trades = orderbook
+            .add_order(limit_order(5, OrderSide::Buy, 100, 20))
+            .unwrap();
+        assert_eq!(fills(trades), vec![(2, 5), (3, 15)]);
+
+        // ETH-USD keeps price-time priority over its own makers.
+        let mut eth_buy = limit_order(6, OrderSide::Buy, 100, 20);
+        eth_buy.pair = eth;
+        let trades = orderbook.add_order(eth_buy).unwrap();
+        assert_eq!(fills(trades), vec![(1, 10), (4, 10)]);
+
+        // A BTC-USD buy larger than what BTC-USD makers have left rests the
+        // rest rather than trading with the ETH-USD maker still at 100.
+        let sweep = limit_order(7, OrderSide::Buy, 100, 60);
+        assert_eq!(orderbook.check_order(&sweep).unwrap(), 20);
+        let trades = orderbook.add_order(sweep).unwrap();
+        assert_eq!(fills(trades), vec![(2, 5), (3, 15)]);
+        assert_eq!(orderbook.get_order(7).unwrap().quantity, 40);
+        assert_eq!(orderbook.get_order(4).unwrap().quantity, 20);
+    }
+
     /// Test Heap Time Priority Queue functionality
     /// This test verifies that orders can be retrieved in time priority order
     /// Implements the Priority 1 feature from DEX-OS-V1.csv:
@@ -846,15 +1141,12 @@ mod tests {
     #[test]
     fn test_heap_time_priority_queue() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         // Add orders with different timestamps
         let order1 = Order {
             id: 1,
-            trader_id: "trader1".to_string(),
+            trader_id: "trader1".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -865,7 +1157,7 @@ mod tests {
 
         let order2 = Order {
             id: 2,
-            trader_id: "trader2".to_string(),
+            trader_id: "trader2".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -876,7 +1168,7 @@ mod tests {
 
         let order3 = Order {
             id: 3,
-            trader_id: "trader3".to_string(),
+            trader_id: "trader3".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -918,15 +1210,12 @@ mod tests {
     #[test]
     fn test_queue_transaction_mempool() {
         let mut orderbook = OrderBook::new();
-        let pair = TradingPair {
-            base: "BTC".to_string(),
-            quote: "USD".to_string(),
-        };
+        let pair: TradingPair = "BTC-USD".parse().unwrap();
 
         // Create orders
         let order1 = Order {
             id: 1,
-            trader_id: "trader1".to_string(),
+            trader_id: "trader1".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -937,7 +1226,7 @@ mod tests {
 
         let order2 = Order {
             id: 2,
-            trader_id: "trader2".to_string(),
+            trader_id: "trader2".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Sell,
             order_type: OrderType::Limit,
@@ -948,7 +1237,7 @@ mod tests {
 
         let order3 = Order {
             id: 3,
-            trader_id: "trader3".to_string(),
+            trader_id: "trader3".parse().unwrap(),
             pair: pair.clone(),
             side: OrderSide::Buy,
             order_type: OrderType::Limit,
@@ -303,8 +303,8 @@ mod tests {
         let mut explorer = PartialFillExplorer::new();
 
         let opportunity = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             available_liquidity: 1000000,
             exchange_rate: 13.5,
@@ -317,7 +317,7 @@ mod tests {
         assert_eq!(explorer.opportunity_count(), 1);
 
         let opportunities = explorer
-            .get_opportunities_from_token(&"BTC".to_string())
+            .get_opportunities_from_token(&"BTC".parse().unwrap())
             .unwrap();
         assert_eq!(opportunities.len(), 1);
         assert_eq!(opportunities[0], opportunity);
@@ -329,8 +329,8 @@ mod tests {
 
         // Add a simple opportunity: BTC -> ETH
         let opportunity = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             available_liquidity: 1000000,
             exchange_rate: 13.5,
@@ -341,7 +341,7 @@ mod tests {
 
         // Explore partial fills from BTC to ETH
         let plans = explorer
-            .explore_partial_fills(&"BTC".to_string(), &"ETH".to_string(), 500000)
+            .explore_partial_fills(&"BTC".parse().unwrap(), &"ETH".parse().unwrap(), 500000)
             .unwrap();
         assert_eq!(plans.len(), 1);
 
@@ -358,8 +358,8 @@ mod tests {
 
         // Add opportunities: BTC -> ETH -> USDC
         let opportunity1 = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             available_liquidity: 1000000,
             exchange_rate: 13.5,
@@ -367,8 +367,8 @@ mod tests {
         };
 
         let opportunity2 = PartialFillOpportunity {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             available_liquidity: 50000000,
             exchange_rate: 3200.0,
@@ -380,7 +380,7 @@ mod tests {
 
         // Explore partial fills from BTC to USDC
         let plans = explorer
-            .explore_partial_fills(&"BTC".to_string(), &"USDC".to_string(), 500000)
+            .explore_partial_fills(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 500000)
             .unwrap();
         assert_eq!(plans.len(), 1);
 
@@ -396,8 +396,8 @@ mod tests {
 
         // Add two opportunities for BTC -> ETH with different exchange rates
         let opportunity1 = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             available_liquidity: 1000000,
             exchange_rate: 13.5, // Lower rate
@@ -405,8 +405,8 @@ mod tests {
         };
 
         let opportunity2 = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             available_liquidity: 500000,
             exchange_rate: 13.8, // Higher rate
@@ -418,7 +418,7 @@ mod tests {
 
         // Find the best plan
         let best_plan = explorer
-            .find_best_plan(&"BTC".to_string(), &"ETH".to_string(), 500000)
+            .find_best_plan(&"BTC".parse().unwrap(), &"ETH".parse().unwrap(), 500000)
             .unwrap();
         assert!(best_plan.is_some());
 
@@ -434,8 +434,8 @@ mod tests {
 
         // Add opportunities from different DEXes
         let opportunity1 = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             available_liquidity: 1000000,
             exchange_rate: 13.5,
@@ -443,8 +443,8 @@ mod tests {
         };
 
         let opportunity2 = PartialFillOpportunity {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             available_liquidity: 50000000,
             exchange_rate: 3200.0,
@@ -462,13 +462,13 @@ mod tests {
         assert_eq!(explorer.opportunity_count(), 1);
         assert_eq!(
             explorer
-                .get_opportunities_from_token(&"BTC".to_string())
+                .get_opportunities_from_token(&"BTC".parse().unwrap())
                 .map_or(0, |opportunities| opportunities.len()),
             0
         );
         assert_eq!(
             explorer
-                .get_opportunities_from_token(&"ETH".to_string())
+                .get_opportunities_from_token(&"ETH".parse().unwrap())
                 .unwrap()
                 .len(),
             1
@@ -480,8 +480,8 @@ mod tests {
         let mut explorer = PartialFillExplorer::new();
 
         let opportunity = PartialFillOpportunity {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             available_liquidity: 1000000,
             exchange_rate: 13.5,
@@ -629,8 +629,8 @@ mod tests {
         let mut router = PathRouter::new();
 
         let edge = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -641,10 +641,10 @@ mod tests {
 
         assert_eq!(router.token_count(), 2);
         assert_eq!(router.edge_count(), 1);
-        assert!(router.get_tokens().contains(&"BTC".to_string()));
-        assert!(router.get_tokens().contains(&"ETH".to_string()));
+        assert!(router.get_tokens().contains(&"BTC".parse().unwrap()));
+        assert!(router.get_tokens().contains(&"ETH".parse().unwrap()));
 
-        let edges = router.get_edges_from_token(&"BTC".to_string()).unwrap();
+        let edges = router.get_edges_from_token(&"BTC".parse().unwrap()).unwrap();
         assert_eq!(edges.len(), 1);
         assert_eq!(edges[0], edge);
     }
@@ -655,8 +655,8 @@ mod tests {
 
         // Add a simple path: BTC -> ETH
         let edge = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -667,7 +667,7 @@ mod tests {
 
         // Find path from BTC to ETH
         let result = router
-            .find_best_path(&"BTC".to_string(), &"ETH".to_string(), 1.0)
+            .find_best_path(&"BTC".parse().unwrap(), &"ETH".parse().unwrap(), 1.0)
             .unwrap();
         assert!(result.is_some());
 
@@ -685,8 +685,8 @@ mod tests {
 
         // Add path: BTC -> ETH -> USDC
         let edge1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -694,8 +694,8 @@ mod tests {
         };
 
         let edge2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -707,7 +707,7 @@ mod tests {
 
         // Find path from BTC to USDC
         let result = router
-            .find_best_path(&"BTC".to_string(), &"USDC".to_string(), 1.0)
+            .find_best_path(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 1.0)
             .unwrap();
         assert!(result.is_some());
 
@@ -727,8 +727,8 @@ mod tests {
         // Add multiple paths from BTC to USDC
         // Path 1: BTC -> ETH -> USDC
         let edge1_1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -736,8 +736,8 @@ mod tests {
         };
 
         let edge1_2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -746,8 +746,8 @@ mod tests {
 
         // Path 2: BTC -> USDC (direct)
         let edge2 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "Curve".to_string(),
             exchange_rate: 42000.0, // Better rate than the multi-hop path
             fee: 0.001,
@@ -759,7 +759,7 @@ mod tests {
         router.add_edge(edge2.clone());
 
         // Find best path using heap-based selection
-        let result = router.find_best_path_with_heap(&"BTC".to_string(), &"USDC".to_string(), 3);
+        let result = router.find_best_path_with_heap(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 3);
         assert!(result.is_some());
 
         let path = result.unwrap();
@@ -778,8 +778,8 @@ mod tests {
         // Add multiple paths from BTC to USDC
         // Path 1: BTC -> ETH -> USDC
         let edge1_1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -787,8 +787,8 @@ mod tests {
         };
 
         let edge1_2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -797,8 +797,8 @@ mod tests {
 
         // Path 2: BTC -> USDC (direct)
         let edge2 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "Curve".to_string(),
             exchange_rate: 42000.0, // Better rate than the multi-hop path
             fee: 0.001,
@@ -811,7 +811,7 @@ mod tests {
 
         // Find best path using enhanced selection
         let result = router
-            .find_best_path_enhanced(&"BTC".to_string(), &"USDC".to_string(), 1.0)
+            .find_best_path_enhanced(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 1.0)
             .unwrap();
         assert!(result.is_some());
 
@@ -830,8 +830,8 @@ mod tests {
 
         // Add path: BTC -> ETH
         let edge = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -842,7 +842,7 @@ mod tests {
 
         // Try to find path from BTC to USDC (no path exists)
         let result = router
-            .find_best_path(&"BTC".to_string(), &"USDC".to_string(), 1.0)
+            .find_best_path(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 1.0)
             .unwrap();
         assert!(result.is_none());
     }
@@ -853,8 +853,8 @@ mod tests {
 
         // Add edges from different DEXes
         let edge1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -862,8 +862,8 @@ mod tests {
         };
 
         let edge2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -880,11 +880,11 @@ mod tests {
 
         assert_eq!(router.edge_count(), 1);
         assert!(router
-            .get_edges_from_token(&"BTC".to_string())
+            .get_edges_from_token(&"BTC".parse().unwrap())
             .map_or(true, |edges| edges.is_empty()));
         assert_eq!(
             router
-                .get_edges_from_token(&"ETH".to_string())
+                .get_edges_from_token(&"ETH".parse().unwrap())
                 .unwrap()
                 .len(),
             1
@@ -897,8 +897,8 @@ mod tests {
 
         // Add multiple paths: BTC -> ETH -> USDC and BTC -> USDC
         let edge1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -906,8 +906,8 @@ mod tests {
         };
 
         let edge2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -915,8 +915,8 @@ mod tests {
         };
 
         let edge3 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "Curve".to_string(),
             exchange_rate: 45000.0,
             fee: 0.004,
@@ -928,7 +928,7 @@ mod tests {
         router.add_edge(edge3.clone());
 
         // Find all paths from BTC to USDC with max 2 hops
-        let paths = router.find_all_paths(&"BTC".to_string(), &"USDC".to_string(), 2);
+        let paths = router.find_all_paths(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 2);
         assert_eq!(paths.len(), 2);
 
         // One path should be direct (BTC -> USDC)
@@ -947,8 +947,8 @@ mod tests {
 
         // Add path: BTC -> ETH -> USDC
         let edge1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -956,8 +956,8 @@ mod tests {
         };
 
         let edge2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -969,17 +969,17 @@ mod tests {
 
         // First call should compute the path
         let result1 = router
-            .find_best_path(&"BTC".to_string(), &"USDC".to_string(), 1.0)
+            .find_best_path(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 1.0)
             .unwrap();
         assert!(result1.is_some());
         
         // Check that the path was cached
         assert_eq!(router.route_cache.len(), 1);
-        assert!(router.route_cache.contains_key(&("BTC".to_string(), "USDC".to_string())));
+        assert!(router.route_cache.contains_key(&("BTC".parse().unwrap(), "USDC".parse().unwrap())));
 
         // Second call should use the cache
         let result2 = router
-            .find_best_path(&"BTC".to_string(), &"USDC".to_string(), 2.0)
+            .find_best_path(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 2.0)
             .unwrap();
         assert!(result2.is_some());
         
@@ -998,8 +998,8 @@ mod tests {
 
         // Add initial path: BTC -> ETH
         let edge1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -1008,13 +1008,13 @@ mod tests {
         router.add_edge(edge1.clone());
 
         // Find and cache path
-        let _ = router.find_best_path(&"BTC".to_string(), &"ETH".to_string(), 1.0).unwrap();
+        let _ = router.find_best_path(&"BTC".parse().unwrap(), &"ETH".parse().unwrap(), 1.0).unwrap();
         assert_eq!(router.route_cache.len(), 1);
 
         // Add another edge that could affect routing
         let edge2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -1041,8 +1041,8 @@ mod tests {
         
         // Add path: BTC -> ETH -> USDC
         let edge1 = TradingEdge {
-            from_token: "BTC".to_string(),
-            to_token: "ETH".to_string(),
+            from_token: "BTC".parse().unwrap(),
+            to_token: "ETH".parse().unwrap(),
             dex_name: "Uniswap".to_string(),
             exchange_rate: 13.5,
             fee: 0.003,
@@ -1050,8 +1050,8 @@ mod tests {
         };
         
         let edge2 = TradingEdge {
-            from_token: "ETH".to_string(),
-            to_token: "USDC".to_string(),
+            from_token: "ETH".parse().unwrap(),
+            to_token: "USDC".parse().unwrap(),
             dex_name: "SushiSwap".to_string(),
             exchange_rate: 3200.0,
             fee: 0.003,
@@ -1062,7 +1062,7 @@ mod tests {
         router.add_edge(edge2.clone());
         
         // Test Dijkstra's algorithm
-        let dijkstra_result = router.find_best_path_dijkstra(&"BTC".to_string(), &"USDC".to_string(), 1.0);
+        let dijkstra_result = router.find_best_path_dijkstra(&"BTC".parse().unwrap(), &"USDC".parse().unwrap(), 1.0);
         assert!(dijkstra_result.is_some());
         
         let path = dijkstra_result.unwrap();
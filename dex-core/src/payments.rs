@@ -136,7 +136,7 @@ impl UniversalPayments {
 
         // Create transaction in sender's wallet
         let transaction_id = sender_wallet.create_transaction(
-            transfer.to_user.clone(),
+            transfer.to_user.to_string(),
             transfer.token_id.clone(),
             transfer.amount
         ).map_err(|_| PaymentError::InsufficientFunds)?;
@@ -278,12 +278,12 @@ mod tests {
 
         // Create wallet participants
         let participant1 = WalletParticipant {
-            id: "user1".to_string(),
+            id: "user1".parse().unwrap(),
             public_key: "pubkey1".to_string(),
         };
         
         let participant2 = WalletParticipant {
-            id: "user2".to_string(),
+            id: "user2".parse().unwrap(),
             public_key: "pubkey2".to_string(),
         };
 
@@ -292,17 +292,17 @@ mod tests {
         let wallet2 = MultiSigWallet::new("wallet2".to_string(), vec![participant2.clone()], 1).unwrap();
         
         // Deposit funds
-        wallet1.deposit("BTC".to_string(), 1000);
+        wallet1.deposit("BTC".parse().unwrap(), 1000);
         
         // Register wallets
-        payments.register_wallet("user1".to_string(), wallet1);
-        payments.register_wallet("user2".to_string(), wallet2);
+        payments.register_wallet("user1".parse().unwrap(), wallet1);
+        payments.register_wallet("user2".parse().unwrap(), wallet2);
 
         // Create one-tap transfer
         let transfer = OneTapTransfer {
-            from_user: "user1".to_string(),
-            to_user: "user2".to_string(),
-            token_id: "BTC".to_string(),
+            from_user: "user1".parse().unwrap(),
+            to_user: "user2".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 500,
             payment_method: None,
             fiat_currency: None,
@@ -337,9 +337,9 @@ mod tests {
         let mut payments = UniversalPayments::new(config);
         
         let transfer = OneTapTransfer {
-            from_user: "user1".to_string(),
-            to_user: "user2".to_string(),
-            token_id: "BTC".to_string(),
+            from_user: "user1".parse().unwrap(),
+            to_user: "user2".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 0, // Invalid amount
             payment_method: None,
             fiat_currency: None,
@@ -372,18 +372,18 @@ mod tests {
         // Create transfers
         let transfers = vec![
             OneTapTransfer {
-                from_user: "user1".to_string(),
-                to_user: "user2".to_string(),
-                token_id: "BTC".to_string(),
+                from_user: "user1".parse().unwrap(),
+                to_user: "user2".parse().unwrap(),
+                token_id: "BTC".parse().unwrap(),
                 amount: 100,
                 payment_method: None,
                 fiat_currency: None,
                 timestamp: get_current_timestamp(),
             },
             OneTapTransfer {
-                from_user: "user1".to_string(),
-                to_user: "user3".to_string(),
-                token_id: "BTC".to_string(),
+                from_user: "user1".parse().unwrap(),
+                to_user: "user3".parse().unwrap(),
+                token_id: "BTC".parse().unwrap(),
                 amount: 200,
                 payment_method: None,
                 fiat_currency: None,
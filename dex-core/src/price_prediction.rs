@@ -436,31 +436,31 @@ mod tests {
 
         // Add a predictor
         let predictor = KalmanPricePredictor::new(50000.0, 10.0, 50.0);
-        manager.add_predictor("BTC".to_string(), "USD".to_string(), predictor);
+        manager.add_predictor("BTC".parse().unwrap(), "USD".parse().unwrap(), predictor);
 
         assert_eq!(manager.predictor_count(), 1);
         assert!(manager.has_predictors());
 
         // Update a price
         let state = manager
-            .update_price(&"BTC".to_string(), &"USD".to_string(), 51000.0, 1000)
+            .update_price(&"BTC".parse().unwrap(), &"USD".parse().unwrap(), 51000.0, 1000)
             .unwrap();
         assert_ne!(state.price, 50000.0);
 
         // Predict next price
         let prediction = manager
-            .predict_price(&"BTC".to_string(), &"USD".to_string())
+            .predict_price(&"BTC".parse().unwrap(), &"USD".parse().unwrap())
             .unwrap();
         assert_ne!(prediction.price, state.price);
 
         // Get estimated price
         let estimated = manager
-            .get_estimated_price(&"BTC".to_string(), &"USD".to_string())
+            .get_estimated_price(&"BTC".parse().unwrap(), &"USD".parse().unwrap())
             .unwrap();
         assert_eq!(estimated, state.price);
 
         // Remove predictor
-        assert!(manager.remove_predictor(&"BTC".to_string(), &"USD".to_string()));
+        assert!(manager.remove_predictor(&"BTC".parse().unwrap(), &"USD".parse().unwrap()));
         assert_eq!(manager.predictor_count(), 0);
     }
 
@@ -9,8 +9,10 @@
 //! "Core Components,Quantum Consensus (QBFT),Consensus,Lattice BFT Core,BFT Core,High"
 
 use crate::types::{Block, Transaction, Validator};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::result::Result;
+use std::time::Duration;
 
 /// Custom error types for quantum consensus operations
 #[derive(Debug, thiserror::Error)]
@@ -40,6 +42,8 @@ pub struct QuantumConsensusEngine {
     /// This implements the Priority 2 feature from DEX-OS-V1.csv:
     /// "2,Core Components,Quantum Consensus (QBFT),Consensus,Global Finality,Finality,High"
     finality_tracker: GlobalFinalityTracker,
+    /// Round, leader and vote statistics exported as validator telemetry
+    telemetry: ConsensusTelemetry,
 }
 
 /// Represents a shard in the sharded consensus system
@@ -96,6 +100,70 @@ impl GlobalFinalityTracker {
     }
 }
 
+/// Statistics the engine gathers as rounds complete
+#[derive(Debug, Clone, Default)]
+struct ConsensusTelemetry {
+    /// How many rounds each validator led
+    leader_counts: HashMap<String, u64>,
+    shards: HashMap<u64, ShardTelemetry>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct ShardTelemetry {
+    rounds: u64,
+    total_round_ms: u64,
+    last_round_ms: u64,
+    /// Votes cast by the shard's validators, summed over its rounds
+    votes_cast: u64,
+    /// Votes its validators could have cast, summed over its rounds
+    votes_expected: u64,
+}
+
+/// Snapshot of consensus health for explorers and the metrics endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ConsensusMetrics {
+    /// Rounds started across all shards
+    pub rounds: u64,
+    pub validators: usize,
+    /// Rounds led by each validator, including those that never led
+    pub leaders: BTreeMap<String, u64>,
+    /// Jain's fairness index of the leader counts: 1 when every validator
+    /// led equally often, approaching 1/n when one validator leads them all
+    pub leader_fairness: f64,
+    pub global_finalized_height: u64,
+    /// Shards that have produced a block, completed a round or finalized
+    pub shards: Vec<ShardMetrics>,
+}
+
+/// Round and finality statistics of one shard
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct ShardMetrics {
+    pub shard_id: u64,
+    pub rounds: u64,
+    pub last_round_ms: u64,
+    pub mean_round_ms: f64,
+    /// Share of the shard's validators that voted, over all its rounds
+    pub vote_participation: f64,
+    /// Height of the shard's newest block
+    pub head_height: u64,
+    pub finalized_height: u64,
+    /// Blocks produced but not yet finalized
+    pub finality_lag: u64,
+}
+
+/// Jain's fairness index over `counts`; 1 when there is nothing to compare
+fn jain_fairness(counts: &[u64]) -> f64 {
+    let sum: f64 = counts.iter().map(|&count| count as f64).sum();
+    let squares: f64 = counts.iter().map(|&count| (count as f64).powi(2)).sum();
+    if squares == 0.0 {
+        1.0
+    } else {
+        sum * sum / (counts.len() as f64 * squares)
+    }
+}
+
 impl QuantumConsensusEngine {
     /// Create a new quantum consensus engine
     pub fn new() -> Self {
@@ -105,6 +173,7 @@ impl QuantumConsensusEngine {
             current_leader: None,
             shards: HashMap::new(),
             finality_tracker: GlobalFinalityTracker::new(),
+            telemetry: ConsensusTelemetry::default(),
         }
     }
 
@@ -267,6 +336,7 @@ impl QuantumConsensusEngine {
         
         // Validate the block
         let leader = self.get_current_leader()?;
+        *self.telemetry.leader_counts.entry(leader.clone()).or_insert(0) += 1;
         if !self.validate_block_proposal(&block, &leader)? {
             return Err(QuantumConsensusError::BlockProposalFailed);
         }
@@ -277,6 +347,70 @@ impl QuantumConsensusEngine {
         // In a real implementation, we would add lattice-based consensus here
         Ok(block)
     }
+
+    /// Record a completed round on a shard: how long it took and which
+    /// validators voted. Votes from validators outside the shard, and
+    /// repeated votes, do not count towards participation.
+    pub fn record_round(&mut self, shard_id: u64, duration: Duration, voters: &[String]) -> Result<(), QuantumConsensusError> {
+        let shard = self.shards.get(&shard_id)
+            .ok_or_else(|| QuantumConsensusError::NetworkError("Shard not found".to_string()))?;
+        let voted: HashSet<&String> = voters.iter()
+            .filter(|voter| shard.validators.contains(voter))
+            .collect();
+
+        let stats = self.telemetry.shards.entry(shard_id).or_default();
+        let duration_ms = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        stats.rounds += 1;
+        stats.total_round_ms = stats.total_round_ms.saturating_add(duration_ms);
+        stats.last_round_ms = duration_ms;
+        stats.votes_cast += voted.len() as u64;
+        stats.votes_expected += shard.validators.len() as u64;
+        Ok(())
+    }
+
+    /// Round durations, leader distribution, vote participation and
+    /// finality lag as of now
+    pub fn metrics(&self) -> ConsensusMetrics {
+        let mut leaders: BTreeMap<String, u64> = self.validators.keys()
+            .map(|id| (id.clone(), 0))
+            .collect();
+        for (leader, count) in &self.telemetry.leader_counts {
+            *leaders.entry(leader.clone()).or_insert(0) += count;
+        }
+        let counts: Vec<u64> = leaders.values().copied().collect();
+
+        let mut shards: Vec<ShardMetrics> = self.shards.values()
+            .filter_map(|shard| {
+                let stats = self.telemetry.shards.get(&shard.id).cloned().unwrap_or_default();
+                let head_height = shard.blocks.iter().map(|block| block.height).max().unwrap_or(0);
+                let finalized_height = self.get_shard_finalized_height(shard.id);
+                if stats.rounds == 0 && shard.blocks.is_empty() && finalized_height.is_none() {
+                    return None;
+                }
+                let finalized_height = finalized_height.unwrap_or(0);
+                Some(ShardMetrics {
+                    shard_id: shard.id,
+                    rounds: stats.rounds,
+                    last_round_ms: stats.last_round_ms,
+                    mean_round_ms: if stats.rounds == 0 { 0.0 } else { stats.total_round_ms as f64 / stats.rounds as f64 },
+                    vote_participation: if stats.votes_expected == 0 { 0.0 } else { stats.votes_cast as f64 / stats.votes_expected as f64 },
+                    head_height,
+                    finalized_height,
+                    finality_lag: head_height.saturating_sub(finalized_height),
+                })
+            })
+            .collect();
+        shards.sort_by_key(|shard| shard.shard_id);
+
+        ConsensusMetrics {
+            rounds: self.current_round,
+            validators: self.validators.len(),
+            leader_fairness: jain_fairness(&counts),
+            leaders,
+            global_finalized_height: self.get_global_finalized_height(),
+            shards,
+        }
+    }
 }
 
 impl Default for QuantumConsensusEngine {
@@ -721,4 +855,59 @@ mod tests {
         
         assert!(engine.process_block_with_sharding(999, block).is_err());
     }
+    #[test]
+    fn test_consensus_metrics() {
+        let mut engine = QuantumConsensusEngine::new();
+        for id in ["validator1", "validator2", "validator3"] {
+            engine.add_validator(Validator {
+                id: id.to_string(),
+                public_key: vec![1, 2, 3, 4],
+                stake: 1000,
+            }).unwrap();
+        }
+        assert!(engine.initialize_shards(4).is_ok());
+        assert_eq!(engine.metrics().leader_fairness, 1.0);
+        assert!(engine.metrics().shards.is_empty());
+
+        for height in 1..=3 {
+            let block = Block {
+                id: height,
+                height,
+                timestamp: 1234567890,
+                transactions: vec![],
+                previous_hash: vec![0; 32],
+                hash: vec![0; 32],
+                signature: vec![],
+            };
+            engine.process_block_with_sharding(2, block).unwrap();
+        }
+        let validators = engine.get_shard(2).unwrap().validators.clone();
+        engine.record_round(2, Duration::from_millis(100), &validators).unwrap();
+        engine.record_round(2, Duration::from_millis(300), &[
+            validators[0].clone(),
+            validators[0].clone(),
+            "outsider".to_string(),
+        ]).unwrap();
+        engine.update_shard_finality(2, 1);
+        assert!(engine.record_round(999, Duration::from_millis(1), &[]).is_err());
+
+        let metrics = engine.metrics();
+        assert_eq!(metrics.rounds, 3);
+        assert_eq!(metrics.validators, 3);
+        assert_eq!(metrics.leaders.values().sum::<u64>(), 3);
+        assert!(metrics.leader_fairness > 0.0 && metrics.leader_fairness <= 1.0);
+        assert_eq!(metrics.global_finalized_height, 1);
+        assert_eq!(metrics.shards, vec![ShardMetrics {
+            shard_id: 2,
+            rounds: 2,
+            last_round_ms: 300,
+            mean_round_ms: 200.0,
+            vote_participation: 4.0 / 6.0,
+            head_height: 3,
+            finalized_height: 1,
+            finality_lag: 2,
+        }]);
+
+        assert_eq!(jain_fairness(&[3, 0, 0]), 1.0 / 3.0);
+    }
 }
\ No newline at end of file
@@ -281,24 +281,24 @@ mod tests {
         // Add some reward claims
         let claim1 = RewardClaim {
             priority: 10,
-            provider_id: "oracle1".to_string(),
-            token_id: "BTC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = RewardClaim {
             priority: 20,
-            provider_id: "oracle2".to_string(),
-            token_id: "ETH".to_string(),
+            provider_id: "oracle2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let claim3 = RewardClaim {
             priority: 15,
-            provider_id: "oracle3".to_string(),
-            token_id: "USDC".to_string(),
+            provider_id: "oracle3".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
@@ -337,24 +337,24 @@ mod tests {
         // Add claims for different providers
         let claim1 = RewardClaim {
             priority: 10,
-            provider_id: "oracle1".to_string(),
-            token_id: "BTC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = RewardClaim {
             priority: 20,
-            provider_id: "oracle2".to_string(),
-            token_id: "ETH".to_string(),
+            provider_id: "oracle2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let claim3 = RewardClaim {
             priority: 15,
-            provider_id: "oracle1".to_string(),
-            token_id: "USDC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
@@ -366,7 +366,7 @@ mod tests {
         assert_eq!(manager.pending_claims_count(), 3);
 
         // Remove claims for oracle1
-        let removed_claims = manager.remove_claims_for_provider(&"oracle1".to_string());
+        let removed_claims = manager.remove_claims_for_provider(&"oracle1".parse().unwrap());
         assert_eq!(removed_claims.len(), 2);
         assert_eq!(manager.pending_claims_count(), 1);
 
@@ -382,24 +382,24 @@ mod tests {
         // Add claims for different providers
         let claim1 = RewardClaim {
             priority: 10,
-            provider_id: "oracle1".to_string(),
-            token_id: "BTC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = RewardClaim {
             priority: 20,
-            provider_id: "oracle2".to_string(),
-            token_id: "ETH".to_string(),
+            provider_id: "oracle2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let claim3 = RewardClaim {
             priority: 15,
-            provider_id: "oracle1".to_string(),
-            token_id: "USDC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
@@ -409,11 +409,11 @@ mod tests {
         manager.add_claim(claim3.clone());
 
         // Get claims for oracle1
-        let oracle1_claims = manager.get_claims_for_provider(&"oracle1".to_string());
+        let oracle1_claims = manager.get_claims_for_provider(&"oracle1".parse().unwrap());
         assert_eq!(oracle1_claims.len(), 2);
 
         // Get claims for oracle2
-        let oracle2_claims = manager.get_claims_for_provider(&"oracle2".to_string());
+        let oracle2_claims = manager.get_claims_for_provider(&"oracle2".parse().unwrap());
         assert_eq!(oracle2_claims.len(), 1);
         assert_eq!(oracle2_claims[0].provider_id, "oracle2");
     }
@@ -425,31 +425,31 @@ mod tests {
         // Add claims for different tokens
         let claim1 = RewardClaim {
             priority: 10,
-            provider_id: "oracle1".to_string(),
-            token_id: "BTC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = RewardClaim {
             priority: 20,
-            provider_id: "oracle2".to_string(),
-            token_id: "ETH".to_string(),
+            provider_id: "oracle2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
-        manager.add_claim("BTC".to_string(), claim1.clone());
-        manager.add_claim("ETH".to_string(), claim2.clone());
+        manager.add_claim("BTC".parse().unwrap(), claim1.clone());
+        manager.add_claim("ETH".parse().unwrap(), claim2.clone());
 
-        assert_eq!(manager.pending_claims_count(&"BTC".to_string()), 1);
-        assert_eq!(manager.pending_claims_count(&"ETH".to_string()), 1);
+        assert_eq!(manager.pending_claims_count(&"BTC".parse().unwrap()), 1);
+        assert_eq!(manager.pending_claims_count(&"ETH".parse().unwrap()), 1);
         assert_eq!(manager.total_pending_claims_count(), 2);
-        assert!(manager.has_pending_claims(&"BTC".to_string()));
-        assert!(manager.has_pending_claims(&"ETH".to_string()));
+        assert!(manager.has_pending_claims(&"BTC".parse().unwrap()));
+        assert!(manager.has_pending_claims(&"ETH".parse().unwrap()));
         assert!(manager.has_any_pending_claims());
-        assert_eq!(manager.total_pending_rewards(&"BTC".to_string()), 1000);
-        assert_eq!(manager.total_pending_rewards(&"ETH".to_string()), 2000);
+        assert_eq!(manager.total_pending_rewards(&"BTC".parse().unwrap()), 1000);
+        assert_eq!(manager.total_pending_rewards(&"ETH".parse().unwrap()), 2000);
         assert_eq!(manager.total_pending_rewards_global(), 3000);
     }
 
@@ -460,31 +460,31 @@ mod tests {
         // Add claims with different priorities
         let claim1 = RewardClaim {
             priority: 10,
-            provider_id: "oracle1".to_string(),
-            token_id: "BTC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = RewardClaim {
             priority: 20,
-            provider_id: "oracle2".to_string(),
-            token_id: "ETH".to_string(),
+            provider_id: "oracle2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let claim3 = RewardClaim {
             priority: 15,
-            provider_id: "oracle3".to_string(),
-            token_id: "USDC".to_string(),
+            provider_id: "oracle3".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
 
-        manager.add_claim("BTC".to_string(), claim1.clone());
-        manager.add_claim("ETH".to_string(), claim2.clone());
-        manager.add_claim("USDC".to_string(), claim3.clone());
+        manager.add_claim("BTC".parse().unwrap(), claim1.clone());
+        manager.add_claim("ETH".parse().unwrap(), claim2.clone());
+        manager.add_claim("USDC".parse().unwrap(), claim3.clone());
 
         // Process claims globally - should process highest priority first (20)
         let processed = manager.process_next_claim_global().unwrap();
@@ -512,39 +512,39 @@ mod tests {
         // Add claims for the same provider across different tokens
         let claim1 = RewardClaim {
             priority: 10,
-            provider_id: "oracle1".to_string(),
-            token_id: "BTC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "BTC".parse().unwrap(),
             amount: 1000,
             timestamp: 1000000,
         };
 
         let claim2 = RewardClaim {
             priority: 20,
-            provider_id: "oracle2".to_string(),
-            token_id: "ETH".to_string(),
+            provider_id: "oracle2".parse().unwrap(),
+            token_id: "ETH".parse().unwrap(),
             amount: 2000,
             timestamp: 1000001,
         };
 
         let claim3 = RewardClaim {
             priority: 15,
-            provider_id: "oracle1".to_string(),
-            token_id: "USDC".to_string(),
+            provider_id: "oracle1".parse().unwrap(),
+            token_id: "USDC".parse().unwrap(),
             amount: 1500,
             timestamp: 1000002,
         };
 
-        manager.add_claim("BTC".to_string(), claim1.clone());
-        manager.add_claim("ETH".to_string(), claim2.clone());
-        manager.add_claim("USDC".to_string(), claim3.clone());
+        manager.add_claim("BTC".parse().unwrap(), claim1.clone());
+        manager.add_claim("ETH".parse().unwrap(), claim2.clone());
+        manager.add_claim("USDC".parse().unwrap(), claim3.clone());
 
         assert_eq!(manager.total_pending_claims_count(), 3);
 
         // Remove all claims for oracle1
-        let removed_claims = manager.remove_claims_for_provider(&"oracle1".to_string());
+        let removed_claims = manager.remove_claims_for_provider(&"oracle1".parse().unwrap());
         assert_eq!(removed_claims.len(), 2);
-        assert!(removed_claims.contains_key(&"BTC".to_string()));
-        assert!(removed_claims.contains_key(&"USDC".to_string()));
+        assert!(removed_claims.contains_key("BTC"));
+        assert!(removed_claims.contains_key("USDC"));
         assert_eq!(manager.total_pending_claims_count(), 1);
 
         // Only oracle2's claim should remain
@@ -353,8 +353,8 @@ mod tests {
     #[test]
     fn test_add_liquidity() {
         let mut amm = StableSwapAMM::new(30, 100);
-        let token_a = "DAI".to_string();
-        let token_b = "USDC".to_string();
+        let token_a: TokenId = "DAI".parse().unwrap();
+        let token_b: TokenId = "USDC".parse().unwrap();
 
         let liquidity_tokens = amm
             .add_liquidity(
@@ -374,8 +374,8 @@ mod tests {
     #[test]
     fn test_swap() {
         let mut amm = StableSwapAMM::new(30, 100);
-        let token_a = "DAI".to_string();
-        let token_b = "USDC".to_string();
+        let token_a: TokenId = "DAI".parse().unwrap();
+        let token_b: TokenId = "USDC".parse().unwrap();
 
         // Add initial liquidity
         amm.add_liquidity(token_a.clone(), 1000000, token_b.clone(), 1000000)
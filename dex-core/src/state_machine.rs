@@ -0,0 +1,327 @@
+//! Deterministic state machine between the exchange and the chain
+//!
+//! The chain decides the order of commands; the exchange executes them.
+//! [`StateMachine`] is the contract between the two: `apply` takes one
+//! command and returns the change it made, depending on nothing but the
+//! current state and the command (no clock, no randomness, no I/O), and
+//! `state_root` commits to the whole state. Any replica that applies the
+//! same commands from the same state therefore reaches the same root.
+//!
+//! Block production runs a batch of commands through the machine and seals
+//! the root before and after into a [`CommandBlock`]. A replica replays the
+//! block against its own copy of the state and accepts it only if both
+//! roots match, without trusting, or even running, the API process that
+//! produced it.
+
+use crate::{
+    matching::MatchingRegistry,
+    orderbook::{OrderBook, OrderBookError},
+    types::{Order, OrderId, OrderSide, OrderType, Trade, TradeId, TraderId},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// A deterministic state machine.
+pub trait StateMachine {
+    type Command;
+    /// What applying a command changed.
+    type Delta;
+    /// Why a command was refused; a refused command leaves the state as it was.
+    type Error;
+
+    fn apply(&mut self, command: &Self::Command) -> Result<Self::Delta, Self::Error>;
+
+    /// Commitment to the current state.
+    fn state_root(&self) -> [u8; 32];
+}
+
+/// A command to the exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ExchangeCommand {
+    PlaceOrder(Order),
+    CancelOrder {
+        order_id: OrderId,
+        trader_id: TraderId,
+    },
+}
+
+impl ExchangeCommand {
+    /// SHA-256 of the command's canonical encoding.
+    pub fn hash(&self) -> [u8; 32] {
+        let encoded = serde_json::to_vec(self).expect("commands always encode");
+        Sha256::digest(encoded).into()
+    }
+}
+
+/// What one exchange command changed.
+#[derive(Debug, Clone, Default)]
+pub struct ExchangeDelta {
+    /// Trades executed, numbered by the machine.
+    pub trades: Vec<Trade>,
+    /// The order as it rests on the book afterwards, if it does.
+    pub rested: Option<(OrderId, u64)>,
+    /// The order taken off the book by a cancel.
+    pub cancelled: Option<OrderId>,
+}
+
+#[derive(Debug, Error)]
+pub enum ExchangeCommandError {
+    #[error("order {0} already exists")]
+    DuplicateOrder(OrderId),
+    #[error("order {0} does not belong to the trader")]
+    NotOwner(OrderId),
+    #[error(transparent)]
+    Book(#[from] OrderBookError),
+}
+
+/// The exchange engine as a state machine: the order book and the trade
+/// numbering.
+#[derive(Debug, Clone)]
+pub struct ExchangeStateMachine {
+    book: OrderBook,
+    next_trade_id: TradeId,
+}
+
+impl ExchangeStateMachine {
+    pub fn new(matching: MatchingRegistry) -> Self {
+        Self {
+            book: OrderBook::with_matching(matching),
+            next_trade_id: 1,
+        }
+    }
+
+    pub fn book(&self) -> &OrderBook {
+        &self.book
+    }
+}
+
+impl StateMachine for ExchangeStateMachine {
+    type Command = ExchangeCommand;
+    type Delta = ExchangeDelta;
+    type Error = ExchangeCommandError;
+
+    fn apply(&mut self, command: &ExchangeCommand) -> Result<ExchangeDelta, ExchangeCommandError> {
+        match command {
+            ExchangeCommand::PlaceOrder(order) => {
+                if self.book.get_order(order.id).is_some() {
+                    return Err(ExchangeCommandError::DuplicateOrder(order.id));
+                }
+                let mut trades = self.book.add_order(order.clone())?;
+                for trade in &mut trades {
+                    trade.id = self.next_trade_id;
+                    self.next_trade_id += 1;
+                }
+                let rested = self
+                    .book
+                    .get_order(order.id)
+                    .map(|resting| (resting.id, resting.quantity));
+                Ok(ExchangeDelta {
+                    trades,
+                    rested,
+                    cancelled: None,
+                })
+            }
+            ExchangeCommand::CancelOrder {
+                order_id,
+                trader_id,
+            } => {
+                let order = self
+                    .book
+                    .get_order(*order_id)
+                    .ok_or(OrderBookError::OrderNotFound)?;
+                if order.trader_id != *trader_id {
+                    return Err(ExchangeCommandError::NotOwner(*order_id));
+                }
+                self.book.remove_order(*order_id)?;
+                Ok(ExchangeDelta {
+                    cancelled: Some(*order_id),
+                    ..ExchangeDelta::default()
+                })
+            }
+        }
+    }
+
+    /// SHA-256 over the next trade ID and every resting order in book
+    /// order, each field length-prefixed or fixed-width.
+    fn state_root(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.next_trade_id.to_be_bytes());
+        for order in self.book.resting_orders() {
+            hasher.update(order.id.to_be_bytes());
+            for field in [
+                order.trader_id.as_str(),
+                order.pair.base().as_str(),
+                order.pair.quote().as_str(),
+            ] {
+                hasher.update((field.len() as u64).to_be_bytes());
+                hasher.update(field.as_bytes());
+            }
+            hasher.update([
+                match order.side {
+                    OrderSide::Buy => 0,
+                    OrderSide::Sell => 1,
+                },
+                match order.order_type {
+                    OrderType::Limit => 0,
+                    OrderType::Market => 1,
+                },
+            ]);
+            hasher.update(order.price.unwrap_or(0).to_be_bytes());
+            hasher.update(order.quantity.to_be_bytes());
+            hasher.update(order.timestamp.to_be_bytes());
+        }
+        hasher.finalize().into()
+    }
+}
+
+/// Commands sealed with the state roots before and after them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandBlock<C> {
+    pub height: u64,
+    /// Root the commands were applied to.
+    pub parent_root: [u8; 32],
+    /// Refused commands stay in the block: refusing them is deterministic too.
+    pub commands: Vec<C>,
+    pub state_root: [u8; 32],
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ReplayError {
+    #[error("block {height} builds on a different state")]
+    ParentMismatch { height: u64 },
+    #[error("block {height} leads to a different state root")]
+    RootMismatch { height: u64 },
+}
+
+/// The outcome of each command in a block, in block order.
+pub type Outcomes<M> = Vec<Result<<M as StateMachine>::Delta, <M as StateMachine>::Error>>;
+
+/// Apply `commands` and seal them into the block at `height`, returning it
+/// with the outcome of each command.
+pub fn produce_block<M: StateMachine>(
+    machine: &mut M,
+    height: u64,
+    commands: Vec<M::Command>,
+) -> (CommandBlock<M::Command>, Outcomes<M>) {
+    let parent_root = machine.state_root();
+    let outcomes = commands
+        .iter()
+        .map(|command| machine.apply(command))
+        .collect();
+    let block = CommandBlock {
+        height,
+        parent_root,
+        commands,
+        state_root: machine.state_root(),
+    };
+    (block, outcomes)
+}
+
+/// Re-execute `block` on a replica's `machine`, checking that it builds on
+/// the replica's state and ends at the root it claims. On a mismatch the
+/// machine is left in whatever state the commands produced; replicas replay
+/// onto a copy they can discard.
+pub fn replay_block<M: StateMachine>(
+    machine: &mut M,
+    block: &CommandBlock<M::Command>,
+) -> Result<Outcomes<M>, ReplayError> {
+    if machine.state_root() != block.parent_root {
+        return Err(ReplayError::ParentMismatch {
+            height: block.height,
+        });
+    }
+    let outcomes = block
+        .commands
+        .iter()
+        .map(|command| machine.apply(command))
+        .collect();
+    if machine.state_root() != block.state_root {
+        return Err(ReplayError::RootMismatch {
+            height: block.height,
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: OrderId, trader: &str, side: OrderSide, price: u64, quantity: u64) -> Order {
+        Order {
+            id,
+            trader_id: trader.parse().unwrap(),
+            pair: "ETH-USDC".parse().unwrap(),
+            side,
+            order_type: OrderType::Limit,
+            price: Some(price),
+            quantity,
+            timestamp: 1_700_000_000 + id,
+        }
+    }
+
+    fn commands() -> Vec<ExchangeCommand> {
+        vec![
+            ExchangeCommand::PlaceOrder(order(1, "bob", OrderSide::Sell, 1_000, 5)),
+            ExchangeCommand::PlaceOrder(order(2, "bob", OrderSide::Sell, 1_010, 5)),
+            ExchangeCommand::PlaceOrder(order(3, "alice", OrderSide::Buy, 1_010, 7)),
+            // Not alice's order to cancel.
+            ExchangeCommand::CancelOrder {
+                order_id: 2,
+                trader_id: "alice".parse().unwrap(),
+            },
+            ExchangeCommand::PlaceOrder(order(2, "carol", OrderSide::Buy, 900, 1)),
+        ]
+    }
+
+    #[test]
+    fn applying_commands_reports_trades_and_resting_quantity() {
+        let mut machine = ExchangeStateMachine::new(MatchingRegistry::default());
+        let (_, outcomes) = produce_block(&mut machine, 1, commands());
+        assert_eq!(outcomes[1].as_ref().unwrap().rested, Some((2, 5)));
+        let taker = outcomes[2].as_ref().unwrap();
+        let trades: Vec<_> = taker.trades.iter().map(|t| (t.id, t.quantity)).collect();
+        assert_eq!(trades, [(1, 5), (2, 2)]);
+        assert!(matches!(
+            outcomes[3],
+            Err(ExchangeCommandError::NotOwner(2))
+        ));
+        assert!(matches!(
+            outcomes[4],
+            Err(ExchangeCommandError::DuplicateOrder(2))
+        ));
+        assert_eq!(machine.book().get_order(2).unwrap().quantity, 3);
+
+        let cancel = ExchangeCommand::CancelOrder {
+            order_id: 2,
+            trader_id: "bob".parse().unwrap(),
+        };
+        assert_eq!(machine.apply(&cancel).unwrap().cancelled, Some(2));
+        assert!(machine.book().get_order(2).is_none());
+    }
+
+    #[test]
+    fn replicas_reach_the_same_root_and_catch_divergence() {
+        let mut producer = ExchangeStateMachine::new(MatchingRegistry::default());
+        let replica = producer.clone();
+        let (block, _) = produce_block(&mut producer, 1, commands());
+        assert_ne!(block.parent_root, block.state_root);
+
+        let mut honest = replica.clone();
+        assert!(replay_block(&mut honest, &block).is_ok());
+        assert_eq!(honest.state_root(), producer.state_root());
+
+        let mut forged = block.clone();
+        forged.commands.remove(1);
+        assert_eq!(
+            replay_block(&mut replica.clone(), &forged).unwrap_err(),
+            ReplayError::RootMismatch { height: 1 }
+        );
+        assert_eq!(
+            replay_block(&mut honest, &block).unwrap_err(),
+            ReplayError::ParentMismatch { height: 1 }
+        );
+        assert_ne!(commands()[0].hash(), commands()[1].hash());
+    }
+}
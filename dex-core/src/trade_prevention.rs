@@ -171,9 +171,9 @@ mod tests {
 
         let trade = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
@@ -198,9 +198,9 @@ mod tests {
 
         let trade = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
@@ -221,9 +221,9 @@ mod tests {
 
         let trade = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
@@ -249,27 +249,27 @@ mod tests {
         // Add trades for different traders
         let trade1 = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
 
         let trade2 = ProcessedTrade {
             trade_id: 2,
-            trader_id: "trader2".to_string(),
-            base_token: "ETH".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader2".parse().unwrap(),
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 3000,
             quantity: 5000,
         };
 
         let trade3 = ProcessedTrade {
             trade_id: 3,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 51000,
             quantity: 2000,
         };
@@ -279,11 +279,11 @@ mod tests {
         prevention.add_processed_trade(trade3.clone()).unwrap();
 
         // Get trades for trader1
-        let trader1_trades = prevention.get_trades_for_trader(&"trader1".to_string());
+        let trader1_trades = prevention.get_trades_for_trader(&"trader1".parse().unwrap());
         assert_eq!(trader1_trades.len(), 2);
 
         // Get trades for trader2
-        let trader2_trades = prevention.get_trades_for_trader(&"trader2".to_string());
+        let trader2_trades = prevention.get_trades_for_trader(&"trader2".parse().unwrap());
         assert_eq!(trader2_trades.len(), 1);
         assert_eq!(trader2_trades[0].trade_id, 2);
     }
@@ -295,27 +295,27 @@ mod tests {
         // Add trades for different token pairs
         let trade1 = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
 
         let trade2 = ProcessedTrade {
             trade_id: 2,
-            trader_id: "trader2".to_string(),
-            base_token: "ETH".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader2".parse().unwrap(),
+            base_token: "ETH".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 3000,
             quantity: 5000,
         };
 
         let trade3 = ProcessedTrade {
             trade_id: 3,
-            trader_id: "trader3".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader3".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 51000,
             quantity: 2000,
         };
@@ -326,12 +326,12 @@ mod tests {
 
         // Get trades for BTC/USDC pair
         let btc_usdc_trades =
-            prevention.get_trades_for_token_pair(&"BTC".to_string(), &"USDC".to_string());
+            prevention.get_trades_for_token_pair(&"BTC".parse().unwrap(), &"USDC".parse().unwrap());
         assert_eq!(btc_usdc_trades.len(), 2);
 
         // Get trades for ETH/USDC pair
         let eth_usdc_trades =
-            prevention.get_trades_for_token_pair(&"ETH".to_string(), &"USDC".to_string());
+            prevention.get_trades_for_token_pair(&"ETH".parse().unwrap(), &"USDC".parse().unwrap());
         assert_eq!(eth_usdc_trades.len(), 1);
         assert_eq!(eth_usdc_trades[0].trade_id, 2);
     }
@@ -342,9 +342,9 @@ mod tests {
 
         let trade = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
@@ -365,9 +365,9 @@ mod tests {
 
         let trade = ProcessedTrade {
             trade_id: 1,
-            trader_id: "trader1".to_string(),
-            base_token: "BTC".to_string(),
-            quote_token: "USDC".to_string(),
+            trader_id: "trader1".parse().unwrap(),
+            base_token: "BTC".parse().unwrap(),
+            quote_token: "USDC".parse().unwrap(),
             price: 50000,
             quantity: 1000,
         };
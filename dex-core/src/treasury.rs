@@ -554,7 +554,7 @@ mod tests {
     #[test]
     fn test_asset_management() {
         let mut treasury = AITreasury::new();
-        let token_id = "BTC".to_string();
+        let token_id: TokenId = "BTC".parse().unwrap();
         let amount = 1000;
         
         // Test deposit
@@ -570,7 +570,7 @@ mod tests {
     #[test]
     fn test_market_predictions() {
         let mut treasury = AITreasury::new();
-        let token_id = "BTC".to_string();
+        let token_id: TokenId = "BTC".parse().unwrap();
         
         let prediction = MarketPrediction {
             token_id: token_id.clone(),
@@ -596,8 +596,8 @@ mod tests {
     #[test]
     fn test_proposal_creation_and_voting() {
         let mut treasury = AITreasury::new();
-        let creator = "creator1".to_string();
-        let token_id = "BTC".to_string();
+        let creator: TraderId = "creator1".parse().unwrap();
+        let token_id: TokenId = "BTC".parse().unwrap();
         
         // Create a proposal
         let proposal_id = treasury.create_proposal(
@@ -636,7 +636,7 @@ mod tests {
     #[test]
     fn test_autonomous_operations() {
         let mut treasury = AITreasury::new();
-        let token_id = "BTC".to_string();
+        let token_id: TokenId = "BTC".parse().unwrap();
         
         // Create an operation
         let result = treasury.create_autonomous_operation(
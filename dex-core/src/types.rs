@@ -1,25 +1,65 @@
 //! Common types used throughout the DEX-OS core engine
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fmt, str::FromStr};
+
+pub use crate::ids::{IdError, TokenId, TraderId};
 
 /// Unique identifier for orders
 pub type OrderId = u64;
 
-/// Unique identifier for traders
-pub type TraderId = String;
-
 /// Price representation
 pub type Price = u64;
 
 /// Quantity representation
 pub type Quantity = u64;
 
-/// Token identifier
-pub type TokenId = String;
-
 /// Unique identifier for trades
 pub type TradeId = u64;
 
+/// Quote-denominated value of a fill, `price * quantity`.
+///
+/// Both factors are `u64`, so the product is carried as `u128`, which cannot
+/// overflow for a single fill. Sums of notionals still use checked addition.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Notional(u128);
+
+impl Notional {
+    pub const ZERO: Self = Self(0);
+
+    /// Notional of `quantity` units at `price`.
+    pub fn of(price: Price, quantity: Quantity) -> Self {
+        Self(u128::from(price) * u128::from(quantity))
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn value(self) -> u128 {
+        self.0
+    }
+
+    /// Narrow to `u64`, for callers that still store notionals as `u64`.
+    pub fn to_u64(self) -> Option<u64> {
+        u64::try_from(self.0).ok()
+    }
+}
+
+impl fmt::Display for Notional {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
 /// Order side (buy or sell)
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderSide {
@@ -34,11 +74,64 @@ pub enum OrderType {
     Market,
 }
 
-/// Represents a trading pair
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+/// Represents a trading pair of two distinct tokens
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(try_from = "RawTradingPair")]
 pub struct TradingPair {
-    pub base: TokenId,
-    pub quote: TokenId,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    base: TokenId,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    quote: TokenId,
+}
+
+#[derive(Deserialize)]
+struct RawTradingPair {
+    base: TokenId,
+    quote: TokenId,
+}
+
+impl TryFrom<RawTradingPair> for TradingPair {
+    type Error = IdError;
+
+    fn try_from(raw: RawTradingPair) -> Result<Self, Self::Error> {
+        Self::new(raw.base, raw.quote)
+    }
+}
+
+impl TradingPair {
+    pub fn new(base: TokenId, quote: TokenId) -> Result<Self, IdError> {
+        if base == quote {
+            return Err(IdError::IdenticalTokens);
+        }
+        Ok(Self { base, quote })
+    }
+
+    pub fn base(&self) -> &TokenId {
+        &self.base
+    }
+
+    pub fn quote(&self) -> &TokenId {
+        &self.quote
+    }
+}
+
+/// Parses the `BASE-QUOTE` market notation, e.g. `ETH-USDC`.
+impl FromStr for TradingPair {
+    type Err = IdError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        let (base, quote) = raw.split_once('-').ok_or(IdError::InvalidPair)?;
+        let base = TokenId::parse(base).map_err(|_| IdError::InvalidPair)?;
+        let quote = TokenId::parse(quote).map_err(|_| IdError::InvalidPair)?;
+        Self::new(base, quote)
+    }
+}
+
+impl fmt::Display for TradingPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.base, self.quote)
+    }
 }
 
 /// Represents an order in the system
@@ -67,6 +160,13 @@ pub struct Trade {
     pub timestamp: u64,
 }
 
+impl Trade {
+    /// Quote value exchanged in this trade.
+    pub fn notional(&self) -> Notional {
+        Notional::of(self.price, self.quantity)
+    }
+}
+
 /// Represents a blockchain block
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
@@ -89,6 +189,21 @@ pub struct Transaction {
     pub signature: Vec<u8>,
 }
 
+impl Transaction {
+    /// SHA-256 over every field, each length-prefixed so no two
+    /// transactions encode alike.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        for field in [self.from.as_bytes(), self.to.as_bytes(), &self.signature] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field);
+        }
+        hasher.update(self.amount.to_be_bytes());
+        hasher.update(self.nonce.to_be_bytes());
+        hasher.finalize().into()
+    }
+}
+
 /// Represents a validator in the consensus protocol
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Validator {
@@ -0,0 +1,208 @@
+//! Backing of wrapped assets
+//!
+//! A wrapped asset is a token credited here for one held in custody: a
+//! multisig wallet on the asset's own chain. Bridge deposits mint it, booked
+//! from the chain's `bridge:<chain>` account to the recipient, and burns
+//! book it back when the custody wallet releases what backs it. The
+//! outstanding supply is therefore what the bridge account has credited in
+//! the token net of what it took back, and it must never exceed the custody
+//! wallet's balance.
+
+use crate::{
+    ledger::{Account, AccountBalance},
+    types::TokenId,
+};
+use serde::Serialize;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum WrappedAssetError {
+    #[error("minting {amount} {token} would exceed the custody balance: supply {supply}, reserves {reserves}")]
+    Unbacked {
+        token: TokenId,
+        amount: u128,
+        supply: u128,
+        reserves: u128,
+    },
+    #[error("burning {amount} {token} exceeds the outstanding supply of {supply}")]
+    ExceedsSupply {
+        token: TokenId,
+        amount: u128,
+        supply: u128,
+    },
+    #[error("burning {amount} {token} exceeds the holder's balance of {balance}")]
+    InsufficientBalance {
+        token: TokenId,
+        amount: u128,
+        balance: i128,
+    },
+}
+
+/// A token minted here against a custody wallet's balance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedAsset {
+    /// The wrapped token, as credited here.
+    pub token: TokenId,
+    /// Chain the custody wallet is on, as named in asset mappings.
+    pub chain: String,
+    /// Multisig wallet holding what backs the token.
+    pub custody_wallet: String,
+}
+
+/// Supply of a wrapped asset against its custody balance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema), schema(as = WrappedSupply))]
+pub struct Backing {
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
+    pub token: TokenId,
+    pub chain: String,
+    pub custody_wallet: String,
+    /// Minted minus burned.
+    pub supply: u128,
+    /// The custody wallet's balance, in units of the wrapped token.
+    pub reserves: u128,
+    /// Whether every unit of supply is held in custody.
+    pub backed: bool,
+}
+
+impl WrappedAsset {
+    fn bridge(&self) -> Account {
+        Account::Bridge(self.chain.clone())
+    }
+
+    /// Outstanding supply according to `balances`. The bridge account is
+    /// credited for every mint and debited for every burn, so its balance
+    /// is the negated supply.
+    pub fn supply(&self, balances: &[AccountBalance]) -> u128 {
+        let bridge = self.bridge();
+        let balance: i128 = balances
+            .iter()
+            .filter(|balance| balance.account == bridge && balance.token == self.token)
+            .map(|balance| balance.balance)
+            .sum();
+        balance.min(0).unsigned_abs()
+    }
+
+    pub fn backing(&self, balances: &[AccountBalance], reserves: u128) -> Backing {
+        let supply = self.supply(balances);
+        Backing {
+            token: self.token.clone(),
+            chain: self.chain.clone(),
+            custody_wallet: self.custody_wallet.clone(),
+            supply,
+            reserves,
+            backed: supply <= reserves,
+        }
+    }
+
+    /// Refuse to mint `amount` unless custody already holds it on top of
+    /// the outstanding supply.
+    pub fn check_mint(
+        &self,
+        balances: &[AccountBalance],
+        reserves: u128,
+        amount: u128,
+    ) -> Result<(), WrappedAssetError> {
+        let supply = self.supply(balances);
+        match supply.checked_add(amount) {
+            Some(total) if total <= reserves => Ok(()),
+            _ => Err(WrappedAssetError::Unbacked {
+                token: self.token.clone(),
+                amount,
+                supply,
+                reserves,
+            }),
+        }
+    }
+
+    /// Refuse to burn more than is outstanding or than `holder` holds.
+    pub fn check_burn(
+        &self,
+        balances: &[AccountBalance],
+        holder: &Account,
+        amount: u128,
+    ) -> Result<(), WrappedAssetError> {
+        let supply = self.supply(balances);
+        if amount > supply {
+            return Err(WrappedAssetError::ExceedsSupply {
+                token: self.token.clone(),
+                amount,
+                supply,
+            });
+        }
+        let balance = balances
+            .iter()
+            .find(|balance| balance.account == *holder && balance.token == self.token)
+            .map_or(0, |balance| balance.balance);
+        if balance < 0 || balance.unsigned_abs() < amount {
+            return Err(WrappedAssetError::InsufficientBalance {
+                token: self.token.clone(),
+                amount,
+                balance,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::{EntryKind, JournalEntry, Ledger};
+
+    #[test]
+    fn supply_follows_mints_and_burns_and_must_stay_backed() {
+        let weth: TokenId = "WETH".parse().unwrap();
+        let asset = WrappedAsset {
+            token: weth.clone(),
+            chain: "ethereum".to_string(),
+            custody_wallet: "eth-custody".to_string(),
+        };
+        let alice = Account::Trader("alice".to_string());
+        let mut ledger = Ledger::new();
+        ledger
+            .post(
+                &JournalEntry::new(EntryKind::Deposit, "ethereum:0xabc:0:0", 1).transfer(
+                    asset.bridge(),
+                    alice.clone(),
+                    &weth,
+                    70,
+                ),
+            )
+            .unwrap();
+        assert_eq!(asset.supply(&ledger.balances()), 70);
+        assert!(asset.backing(&ledger.balances(), 100).backed);
+        assert!(!asset.backing(&ledger.balances(), 60).backed);
+
+        assert_eq!(asset.check_mint(&ledger.balances(), 100, 30), Ok(()));
+        assert_eq!(
+            asset.check_mint(&ledger.balances(), 100, 31),
+            Err(WrappedAssetError::Unbacked {
+                token: weth.clone(),
+                amount: 31,
+                supply: 70,
+                reserves: 100,
+            })
+        );
+
+        let bob = Account::Trader("bob".to_string());
+        assert!(matches!(
+            asset.check_burn(&ledger.balances(), &bob, 10),
+            Err(WrappedAssetError::InsufficientBalance { balance: 0, .. })
+        ));
+        assert!(matches!(
+            asset.check_burn(&ledger.balances(), &alice, 71),
+            Err(WrappedAssetError::ExceedsSupply { supply: 70, .. })
+        ));
+        assert_eq!(asset.check_burn(&ledger.balances(), &alice, 20), Ok(()));
+        ledger
+            .post(&JournalEntry::new(EntryKind::Burn, "WETH:w-1", 2).transfer(
+                alice,
+                asset.bridge(),
+                &weth,
+                20,
+            ))
+            .unwrap();
+        assert_eq!(asset.supply(&ledger.balances()), 50);
+    }
+}
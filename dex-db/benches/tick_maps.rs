@@ -0,0 +1,207 @@
+//! Persisting a pool's ticks as one compressed blob versus a row per tick.
+//!
+//! The encoding benchmarks always run. Set `BENCH_DATABASE_URL` to a scratch
+//! Postgres database to also time saving and restoring both layouts: the
+//! blob through `TickMapRepo`, the rows one `INSERT` each in a transaction,
+//! into a `bench_amm_ticks` table the benchmark creates and drops.
+//!
+//! ```text
+//! BENCH_DATABASE_URL=postgres://localhost/dex_bench cargo bench -p dex-db
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use dex_core::{amm::Tick, types::TokenId};
+use dex_db::{migrations::run_migrations, tick_maps, DatabaseManager, TickMapRepo};
+use sqlx_core::{query::query, raw_sql::raw_sql, row::Row};
+use sqlx_postgres::{PgPool, PgPoolOptions};
+use std::collections::HashMap;
+use tokio::runtime::Runtime;
+
+const TICK_COUNTS: [i32; 3] = [1_000, 10_000, 50_000];
+
+/// `count` ticks under positions that each span 500 ticks, staggered every
+/// 100, so liquidity changes along the range like a busy pool's.
+fn ticks(count: i32) -> HashMap<i32, Tick> {
+    let mut ticks: HashMap<i32, Tick> = HashMap::new();
+    for lower in (0..count).step_by(100) {
+        let upper = (lower + 500).min(count);
+        let liquidity = 1_000 + lower as u64;
+        for index in lower..upper {
+            let tick = ticks.entry(index).or_insert(Tick {
+                index,
+                liquidity: 0,
+                liquidity_net: 0,
+            });
+            tick.liquidity += liquidity;
+        }
+        ticks.entry(lower).and_modify(|tick| {
+            tick.liquidity_net += liquidity as i64;
+        });
+    }
+    ticks
+}
+
+fn tokens() -> (TokenId, TokenId) {
+    (
+        TokenId::parse("BENCH").unwrap(),
+        TokenId::parse("USDC").unwrap(),
+    )
+}
+
+fn codec(c: &mut Criterion) {
+    let (base, quote) = tokens();
+    let mut group = c.benchmark_group("tick_map_codec");
+    for count in TICK_COUNTS {
+        let ticks = ticks(count);
+        let blob = tick_maps::encode(&base, &quote, 1, &ticks);
+        println!(
+            "{} ticks: {} byte blob, {} bytes per tick",
+            count,
+            blob.data.len(),
+            blob.data.len() as f64 / f64::from(count)
+        );
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("encode", count), &ticks, |b, ticks| {
+            b.iter(|| tick_maps::encode(&base, &quote, 1, ticks))
+        });
+        group.bench_with_input(BenchmarkId::new("decode", count), &blob, |b, blob| {
+            b.iter(|| tick_maps::decode(blob).unwrap())
+        });
+    }
+    group.finish();
+}
+
+async fn save_rows(pool: &PgPool, base: &TokenId, quote: &TokenId, ticks: &HashMap<i32, Tick>) {
+    let mut tx = pool.begin().await.unwrap();
+    query("DELETE FROM bench_amm_ticks WHERE base_token = $1 AND quote_token = $2")
+        .bind(base.as_str())
+        .bind(quote.as_str())
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    for tick in ticks.values() {
+        query(
+            "INSERT INTO bench_amm_ticks (base_token, quote_token, tick_index, liquidity, liquidity_net) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(base.as_str())
+        .bind(quote.as_str())
+        .bind(tick.index)
+        .bind(tick.liquidity as i64)
+        .bind(tick.liquidity_net)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    }
+    tx.commit().await.unwrap();
+}
+
+async fn load_rows(pool: &PgPool, base: &TokenId, quote: &TokenId) -> HashMap<i32, Tick> {
+    query(
+        "SELECT tick_index, liquidity, liquidity_net FROM bench_amm_ticks \
+         WHERE base_token = $1 AND quote_token = $2",
+    )
+    .bind(base.as_str())
+    .bind(quote.as_str())
+    .fetch_all(pool)
+    .await
+    .unwrap()
+    .iter()
+    .map(|row| {
+        let index: i32 = row.get("tick_index");
+        let tick = Tick {
+            index,
+            liquidity: row.get::<i64, _>("liquidity") as u64,
+            liquidity_net: row.get("liquidity_net"),
+        };
+        (index, tick)
+    })
+    .collect()
+}
+
+fn storage(c: &mut Criterion) {
+    let Ok(url) = std::env::var("BENCH_DATABASE_URL") else {
+        eprintln!("BENCH_DATABASE_URL is not set; skipping the storage benchmarks");
+        return;
+    };
+    let runtime = Runtime::new().unwrap();
+    let pool = runtime.block_on(async {
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .connect(&url)
+            .await
+            .unwrap();
+        run_migrations(&pool).await.unwrap();
+        raw_sql(
+            r#"
+            CREATE TABLE IF NOT EXISTS bench_amm_ticks (
+                base_token TEXT NOT NULL,
+                quote_token TEXT NOT NULL,
+                tick_index INTEGER NOT NULL,
+                liquidity BIGINT NOT NULL,
+                liquidity_net BIGINT NOT NULL,
+                PRIMARY KEY (base_token, quote_token, tick_index)
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        pool
+    });
+    let database = DatabaseManager::new(pool.clone());
+    let (base, quote) = tokens();
+    // Stored maps are only replaced by newer ones, so every save takes the
+    // ticks at the next sequence.
+    let mut sequence = 0;
+
+    let mut group = c.benchmark_group("tick_map_storage");
+    group.sample_size(10);
+    for count in TICK_COUNTS {
+        let ticks = ticks(count);
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("save_blob", count), &ticks, |b, ticks| {
+            b.iter(|| {
+                sequence += 1;
+                let blob = tick_maps::encode(&base, &quote, sequence, ticks);
+                runtime.block_on(database.save_tick_maps(&[blob])).unwrap();
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("save_rows", count), &ticks, |b, ticks| {
+            b.iter(|| runtime.block_on(save_rows(&pool, &base, &quote, ticks)))
+        });
+        group.bench_function(BenchmarkId::new("restore_blob", count), |b| {
+            b.iter(|| {
+                let maps = runtime.block_on(database.load_tick_maps()).unwrap();
+                let blob = maps
+                    .iter()
+                    .find(|map| map.base_token == base && map.quote_token == quote)
+                    .unwrap();
+                assert_eq!(tick_maps::decode(blob).unwrap().len(), ticks.len());
+            })
+        });
+        group.bench_function(BenchmarkId::new("restore_rows", count), |b| {
+            b.iter(|| {
+                let restored = runtime.block_on(load_rows(&pool, &base, &quote));
+                assert_eq!(restored.len(), ticks.len());
+            })
+        });
+    }
+    group.finish();
+
+    runtime.block_on(async {
+        raw_sql("DROP TABLE bench_amm_ticks")
+            .execute(&pool)
+            .await
+            .unwrap();
+        query("DELETE FROM amm_tick_maps WHERE base_token = $1 AND quote_token = $2")
+            .bind(base.as_str())
+            .bind(quote.as_str())
+            .execute(&pool)
+            .await
+            .unwrap();
+    });
+}
+
+criterion_group!(benches, codec, storage);
+criterion_main!(benches);
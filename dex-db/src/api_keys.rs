@@ -0,0 +1,109 @@
+//! Postgres implementation of `ApiKeyRepo`.
+
+use crate::{
+    repository::{ApiKeyRecord, ApiKeyRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn api_key_from_row(row: &PgRow) -> ApiKeyRecord {
+    ApiKeyRecord {
+        key_id: row.get("key_id"),
+        sealed_secret: row.get("sealed_secret"),
+        subject: row.get("subject"),
+        scope: row.get("scope"),
+        label: row.get("label"),
+        created_at: row.get::<i64, _>("created_at") as u64,
+        revoked: row.get("revoked"),
+        allowed_ips: row.get("allowed_ips"),
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepo for DatabaseManager {
+    async fn save_api_key(&self, key: &ApiKeyRecord) -> Result<(), DatabaseError> {
+        // Plain insert: key IDs are unique, so a retry could hit a duplicate key.
+        self.run("save_api_key", false, || {
+            query(
+                r#"
+            INSERT INTO api_keys (key_id, sealed_secret, subject, scope, label, created_at, revoked, allowed_ips)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            )
+            .bind(key.key_id.as_str())
+            .bind(key.sealed_secret.as_str())
+            .bind(key.subject.as_str())
+            .bind(key.scope.as_str())
+            .bind(key.label.as_deref())
+            .bind(key.created_at as i64)
+            .bind(key.revoked)
+            .bind(&key.allowed_ips)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_api_key(&self, key_id: &str) -> Result<Option<ApiKeyRecord>, DatabaseError> {
+        let row = self
+            .run("load_api_key", true, || {
+                query("SELECT * FROM api_keys WHERE key_id = $1")
+                    .bind(key_id)
+                    .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        Ok(row.as_ref().map(api_key_from_row))
+    }
+
+    async fn list_api_keys(&self, subject: &str) -> Result<Vec<ApiKeyRecord>, DatabaseError> {
+        let rows = self
+            .run("list_api_keys", true, || {
+                query("SELECT * FROM api_keys WHERE subject = $1 ORDER BY created_at, key_id")
+                    .bind(subject)
+                    .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(api_key_from_row).collect())
+    }
+
+    async fn revoke_api_key(&self, key_id: &str, subject: &str) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("revoke_api_key", true, || {
+                query(
+                    "UPDATE api_keys SET revoked = TRUE WHERE key_id = $1 AND subject = $2 AND NOT revoked",
+                )
+                .bind(key_id)
+                .bind(subject)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_api_key_allowed_ips(
+        &self,
+        key_id: &str,
+        subject: &str,
+        allowed_ips: &[String],
+    ) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("set_api_key_allowed_ips", true, || {
+                query(
+                    "UPDATE api_keys SET allowed_ips = $3 WHERE key_id = $1 AND subject = $2 AND NOT revoked",
+                )
+                .bind(key_id)
+                .bind(subject)
+                .bind(allowed_ips)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
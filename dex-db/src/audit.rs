@@ -0,0 +1,82 @@
+//! Postgres implementation of `AuditRepo`.
+
+use crate::{
+    repository::{AuditEntry, AuditFilter, AuditRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn entry_from_row(row: &PgRow) -> AuditEntry {
+    AuditEntry {
+        id: row.get::<i64, _>("id") as u64,
+        occurred_at: row.get::<i64, _>("occurred_at") as u64,
+        subject: row.get("subject"),
+        ip: row.get("ip"),
+        action: row.get("action"),
+        target: row.get("target"),
+        outcome: row.get("outcome"),
+        status: row
+            .get::<Option<i32>, _>("status")
+            .map(|status| status as u16),
+    }
+}
+
+#[async_trait]
+impl AuditRepo for DatabaseManager {
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<u64, DatabaseError> {
+        // Not retried: replaying an insert whose reply was lost would record
+        // the action twice.
+        let row = self
+            .run("append_audit", false, || {
+                query(
+                    r#"
+            INSERT INTO audit_log (occurred_at, subject, ip, action, target, outcome, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+                )
+                .bind(entry.occurred_at as i64)
+                .bind(entry.subject.as_str())
+                .bind(entry.ip.as_deref())
+                .bind(entry.action.as_str())
+                .bind(entry.target.as_str())
+                .bind(entry.outcome.as_str())
+                .bind(entry.status.map(i32::from))
+                .fetch_one(&self.pool)
+            })
+            .await?;
+
+        Ok(row.get::<i64, _>("id") as u64)
+    }
+
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, DatabaseError> {
+        let rows = self
+            .run("query_audit", true, || {
+                query(
+                    r#"
+            SELECT id, occurred_at, subject, ip, action, target, outcome, status
+            FROM audit_log
+            WHERE ($1::BIGINT IS NULL OR id > $1)
+              AND ($2::TEXT IS NULL OR subject = $2)
+              AND ($3::TEXT IS NULL OR action = $3)
+              AND ($4::BIGINT IS NULL OR occurred_at >= $4)
+              AND ($5::BIGINT IS NULL OR occurred_at < $5)
+            ORDER BY id ASC
+            LIMIT $6
+            "#,
+                )
+                .bind(filter.after_id.map(|id| id as i64))
+                .bind(filter.subject.as_deref())
+                .bind(filter.action.as_deref())
+                .bind(filter.from.map(|ts| ts as i64))
+                .bind(filter.to.map(|ts| ts as i64))
+                .bind(i64::from(filter.page_size()))
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(entry_from_row).collect())
+    }
+}
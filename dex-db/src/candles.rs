@@ -0,0 +1,148 @@
+//! Postgres implementation of `CandleRepo`.
+
+use crate::{
+    parse_column,
+    repository::{CandleRecord, CandleRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::types::TradingPair;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn candle_from_row(row: &PgRow) -> Result<CandleRecord, DatabaseError> {
+    Ok(CandleRecord {
+        base_token: parse_column(row, "base_token")?,
+        quote_token: parse_column(row, "quote_token")?,
+        open_time: row.get::<i64, _>("open_time") as u64,
+        open: row.get::<i64, _>("open") as u64,
+        high: row.get::<i64, _>("high") as u64,
+        low: row.get::<i64, _>("low") as u64,
+        close: row.get::<i64, _>("close") as u64,
+        volume: row.get::<i64, _>("volume") as u64,
+        trades: row.get::<i64, _>("trades") as u64,
+    })
+}
+
+#[async_trait]
+impl CandleRepo for DatabaseManager {
+    async fn save_candles(&self, candles: &[CandleRecord]) -> Result<(), DatabaseError> {
+        if candles.is_empty() {
+            return Ok(());
+        }
+        let bases: Vec<&str> = candles.iter().map(|c| c.base_token.as_str()).collect();
+        let quotes: Vec<&str> = candles.iter().map(|c| c.quote_token.as_str()).collect();
+        let column = |field: fn(&CandleRecord) -> u64| -> Vec<i64> {
+            candles.iter().map(|c| field(c) as i64).collect()
+        };
+        let open_times = column(|c| c.open_time);
+        let opens = column(|c| c.open);
+        let highs = column(|c| c.high);
+        let lows = column(|c| c.low);
+        let closes = column(|c| c.close);
+        let volumes = column(|c| c.volume);
+        let trades = column(|c| c.trades);
+
+        // Bars are replaced whole, so a retry is harmless.
+        self.run("save_candles", true, || {
+            query(
+                r#"
+            INSERT INTO candles (
+                base_token, quote_token, open_time, open, high, low, close, volume, trades
+            )
+            SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::BIGINT[],
+                $5::BIGINT[], $6::BIGINT[], $7::BIGINT[], $8::BIGINT[], $9::BIGINT[])
+            ON CONFLICT (base_token, quote_token, open_time) DO UPDATE SET
+                open = EXCLUDED.open,
+                high = EXCLUDED.high,
+                low = EXCLUDED.low,
+                close = EXCLUDED.close,
+                volume = EXCLUDED.volume,
+                trades = EXCLUDED.trades
+            "#,
+            )
+            .bind(bases.clone())
+            .bind(quotes.clone())
+            .bind(open_times.clone())
+            .bind(opens.clone())
+            .bind(highs.clone())
+            .bind(lows.clone())
+            .bind(closes.clone())
+            .bind(volumes.clone())
+            .bind(trades.clone())
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_candle(&self, pair: &TradingPair, open_time: u64) -> Result<(), DatabaseError> {
+        self.run("delete_candle", true, || {
+            query(
+                "DELETE FROM candles \
+                 WHERE base_token = $1 AND quote_token = $2 AND open_time = $3",
+            )
+            .bind(pair.base().as_str())
+            .bind(pair.quote().as_str())
+            .bind(open_time as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn get_candles(
+        &self,
+        pair: &TradingPair,
+        from: u64,
+        to: u64,
+        limit: u32,
+    ) -> Result<Vec<CandleRecord>, DatabaseError> {
+        let rows = self
+            .run_read("get_candles", |pool| {
+                query(
+                    r#"
+            SELECT base_token, quote_token, open_time, open, high, low, close, volume, trades
+            FROM candles
+            WHERE base_token = $1 AND quote_token = $2 AND open_time >= $3 AND open_time < $4
+            ORDER BY open_time ASC
+            LIMIT $5
+            "#,
+                )
+                .bind(pair.base().as_str())
+                .bind(pair.quote().as_str())
+                .bind(from as i64)
+                .bind(to.min(i64::MAX as u64) as i64)
+                .bind(i64::from(limit))
+                .fetch_all(pool)
+            })
+            .await?;
+        rows.iter().map(candle_from_row).collect()
+    }
+
+    async fn candle_watermark(&self) -> Result<u64, DatabaseError> {
+        let row = self
+            .run("candle_watermark", true, || {
+                query("SELECT COALESCE(MAX(through), 0) AS through FROM candle_watermark")
+                    .fetch_one(&self.pool)
+            })
+            .await?;
+        Ok(row.get::<i64, _>("through") as u64)
+    }
+
+    async fn advance_candle_watermark(&self, through: u64) -> Result<(), DatabaseError> {
+        self.run("advance_candle_watermark", true, || {
+            query(
+                r#"
+            INSERT INTO candle_watermark (through) VALUES ($1)
+            ON CONFLICT (id) DO UPDATE
+                SET through = GREATEST(candle_watermark.through, EXCLUDED.through)
+            "#,
+            )
+            .bind(through as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
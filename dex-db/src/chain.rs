@@ -0,0 +1,198 @@
+//! Postgres implementation of `ChainRepo`.
+
+use crate::{
+    repository::{ChainBlock, ChainRepo, ChainTransaction},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn block_from_row(row: &PgRow) -> ChainBlock {
+    ChainBlock {
+        shard_id: row.get::<i64, _>("shard_id") as u64,
+        height: row.get::<i64, _>("height") as u64,
+        hash: row.get("hash"),
+        previous_hash: row.get("previous_hash"),
+        timestamp: row.get::<i64, _>("timestamp") as u64,
+        transactions: Vec::new(),
+        finalized: row.get("finalized"),
+    }
+}
+
+fn transaction_from_row(row: &PgRow, finalized: bool) -> ChainTransaction {
+    ChainTransaction {
+        hash: row.get("hash"),
+        shard_id: row.get::<i64, _>("shard_id") as u64,
+        height: row.get::<i64, _>("height") as u64,
+        index: row.get::<i32, _>("position") as u32,
+        from: row.get("sender"),
+        to: row.get("recipient"),
+        amount: row.get("amount"),
+        nonce: row.get::<i64, _>("nonce") as u64,
+        finalized,
+    }
+}
+
+impl DatabaseManager {
+    /// Fill in the transactions of `blocks`.
+    async fn attach_transactions(&self, blocks: &mut [ChainBlock]) -> Result<(), DatabaseError> {
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        let shards: Vec<i64> = blocks.iter().map(|b| b.shard_id as i64).collect();
+        let heights: Vec<i64> = blocks.iter().map(|b| b.height as i64).collect();
+        let rows = self
+            .run("load_chain_transactions", true, || {
+                query(
+                    r#"
+            SELECT t.hash, t.shard_id, t.height, t.position, t.sender, t.recipient, t.amount, t.nonce
+            FROM chain_transactions t
+            JOIN UNNEST($1::BIGINT[], $2::BIGINT[]) AS b (shard_id, height)
+                USING (shard_id, height)
+            ORDER BY t.shard_id ASC, t.height ASC, t.position ASC
+            "#,
+                )
+                .bind(shards.clone())
+                .bind(heights.clone())
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        for row in &rows {
+            let shard_id = row.get::<i64, _>("shard_id") as u64;
+            let height = row.get::<i64, _>("height") as u64;
+            if let Some(block) = blocks
+                .iter_mut()
+                .find(|b| b.shard_id == shard_id && b.height == height)
+            {
+                let transaction = transaction_from_row(row, block.finalized);
+                block.transactions.push(transaction);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChainRepo for DatabaseManager {
+    async fn save_block(&self, block: &ChainBlock) -> Result<(), DatabaseError> {
+        let transactions = |field: fn(&ChainTransaction) -> String| -> Vec<String> {
+            block.transactions.iter().map(field).collect()
+        };
+        let hashes = transactions(|t| t.hash.clone());
+        let senders = transactions(|t| t.from.clone());
+        let recipients = transactions(|t| t.to.clone());
+        let amounts: Vec<i64> = block.transactions.iter().map(|t| t.amount).collect();
+        let nonces: Vec<i64> = block.transactions.iter().map(|t| t.nonce as i64).collect();
+
+        // One statement, so the block and its transactions are stored
+        // together; a retry finds the block stored and changes nothing.
+        self.run("save_chain_block", true, || {
+            query(
+                r#"
+            WITH block AS (
+                INSERT INTO chain_blocks (shard_id, height, hash, previous_hash, timestamp, finalized)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (shard_id, height) DO NOTHING
+                RETURNING shard_id, height
+            )
+            INSERT INTO chain_transactions
+                (hash, shard_id, height, position, sender, recipient, amount, nonce)
+            SELECT t.hash, block.shard_id, block.height, (t.position - 1)::INTEGER,
+                t.sender, t.recipient, t.amount, t.nonce
+            FROM block, UNNEST($7::TEXT[], $8::TEXT[], $9::TEXT[], $10::BIGINT[], $11::BIGINT[])
+                WITH ORDINALITY AS t (hash, sender, recipient, amount, nonce, position)
+            ON CONFLICT (hash) DO NOTHING
+            "#,
+            )
+            .bind(block.shard_id as i64)
+            .bind(block.height as i64)
+            .bind(block.hash.as_str())
+            .bind(block.previous_hash.as_str())
+            .bind(block.timestamp as i64)
+            .bind(block.finalized)
+            .bind(hashes.clone())
+            .bind(senders.clone())
+            .bind(recipients.clone())
+            .bind(amounts.clone())
+            .bind(nonces.clone())
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_blocks_at(&self, height: u64) -> Result<Vec<ChainBlock>, DatabaseError> {
+        let rows = self
+            .run("load_chain_blocks", true, || {
+                query(
+                    r#"
+            SELECT shard_id, height, hash, previous_hash, timestamp, finalized
+            FROM chain_blocks
+            WHERE height = $1
+            ORDER BY shard_id ASC
+            "#,
+                )
+                .bind(height as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        let mut blocks: Vec<ChainBlock> = rows.iter().map(block_from_row).collect();
+        self.attach_transactions(&mut blocks).await?;
+        Ok(blocks)
+    }
+
+    async fn load_transaction(
+        &self,
+        hash: &str,
+    ) -> Result<Option<ChainTransaction>, DatabaseError> {
+        let row = self
+            .run("load_chain_transaction", true, || {
+                query(
+                    r#"
+            SELECT t.hash, t.shard_id, t.height, t.position, t.sender, t.recipient, t.amount, t.nonce,
+                b.finalized
+            FROM chain_transactions t
+            JOIN chain_blocks b USING (shard_id, height)
+            WHERE t.hash = $1
+            "#,
+                )
+                .bind(hash)
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        Ok(row.map(|row| transaction_from_row(&row, row.get("finalized"))))
+    }
+
+    async fn finalize_blocks(
+        &self,
+        shard_id: u64,
+        height: u64,
+    ) -> Result<Vec<ChainBlock>, DatabaseError> {
+        // Not retried: a retry after a lost reply would find the blocks
+        // already finalized and never report them.
+        let rows = self
+            .run("finalize_chain_blocks", false, || {
+                query(
+                    r#"
+            UPDATE chain_blocks SET finalized = TRUE
+            WHERE shard_id = $1 AND height <= $2 AND NOT finalized
+            RETURNING shard_id, height, hash, previous_hash, timestamp, finalized
+            "#,
+                )
+                .bind(shard_id as i64)
+                .bind(height as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        let mut blocks: Vec<ChainBlock> = rows.iter().map(block_from_row).collect();
+        blocks.sort_by_key(|block| block.height);
+        self.attach_transactions(&mut blocks).await?;
+        Ok(blocks)
+    }
+}
@@ -0,0 +1,73 @@
+//! Postgres implementation of `ChallengeRepo`.
+
+use crate::{
+    repository::{ChallengeRecord, ChallengeRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+
+#[async_trait]
+impl ChallengeRepo for DatabaseManager {
+    async fn save_challenge(&self, challenge: &ChallengeRecord) -> Result<(), DatabaseError> {
+        self.run("save_challenge", true, || {
+            query(
+                r#"
+            INSERT INTO wallet_challenges (subject, message, typed_data, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (subject) DO UPDATE SET
+                message = EXCLUDED.message,
+                typed_data = EXCLUDED.typed_data,
+                expires_at = EXCLUDED.expires_at
+            "#,
+            )
+            .bind(challenge.subject.as_str())
+            .bind(challenge.message.as_str())
+            .bind(challenge.typed_data.as_deref())
+            .bind(challenge.expires_at as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        subject: &str,
+    ) -> Result<Option<ChallengeRecord>, DatabaseError> {
+        // Not retried: a retry after a lost reply would find the challenge
+        // already deleted and turn a valid sign-in into a missing challenge.
+        let row = self
+            .run("take_challenge", false, || {
+                query(
+                    r#"
+            DELETE FROM wallet_challenges WHERE subject = $1
+            RETURNING subject, message, typed_data, expires_at
+            "#,
+                )
+                .bind(subject)
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        Ok(row.map(|row| ChallengeRecord {
+            subject: row.get("subject"),
+            message: row.get("message"),
+            typed_data: row.get("typed_data"),
+            expires_at: row.get::<i64, _>("expires_at") as u64,
+        }))
+    }
+
+    async fn purge_expired_challenges(&self, now: u64) -> Result<u64, DatabaseError> {
+        let result = self
+            .run("purge_expired_challenges", true, || {
+                query("DELETE FROM wallet_challenges WHERE expires_at <= $1")
+                    .bind(now as i64)
+                    .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,109 @@
+//! Postgres implementation of `CounterRepo`.
+
+use crate::{
+    parse_column,
+    repository::{CounterRepo, MarketCounters},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn counters_from_row(row: &PgRow) -> Result<MarketCounters, DatabaseError> {
+    Ok(MarketCounters {
+        base_token: parse_column(row, "base_token")?,
+        quote_token: parse_column(row, "quote_token")?,
+        trade_count: row.get::<i64, _>("trade_count") as u64,
+        base_volume: parse_column(row, "base_volume")?,
+        quote_volume: parse_column(row, "quote_volume")?,
+        last_price: row
+            .get::<Option<i64>, _>("last_price")
+            .map(|price| price as u64),
+        last_trade_time: row
+            .get::<Option<i64>, _>("last_trade_time")
+            .map(|time| time as u64),
+    })
+}
+
+#[async_trait]
+impl CounterRepo for DatabaseManager {
+    async fn save_counters(&self, counters: &[MarketCounters]) -> Result<(), DatabaseError> {
+        if counters.is_empty() {
+            return Ok(());
+        }
+        let bases: Vec<&str> = counters.iter().map(|c| c.base_token.as_str()).collect();
+        let quotes: Vec<&str> = counters.iter().map(|c| c.quote_token.as_str()).collect();
+        let trade_counts: Vec<i64> = counters.iter().map(|c| c.trade_count as i64).collect();
+        let base_volumes: Vec<String> =
+            counters.iter().map(|c| c.base_volume.to_string()).collect();
+        let quote_volumes: Vec<String> = counters
+            .iter()
+            .map(|c| c.quote_volume.to_string())
+            .collect();
+        let last_prices: Vec<Option<i64>> = counters
+            .iter()
+            .map(|c| c.last_price.map(|price| price as i64))
+            .collect();
+        let last_trade_times: Vec<Option<i64>> = counters
+            .iter()
+            .map(|c| c.last_trade_time.map(|time| time as i64))
+            .collect();
+
+        // The upsert keeps the larger of each total, so a retry is harmless.
+        self.run("save_counters", true, || {
+            query(
+                r#"
+            INSERT INTO market_counters (
+                base_token, quote_token, trade_count, base_volume, quote_volume,
+                last_price, last_trade_time
+            )
+            SELECT c.base_token, c.quote_token, c.trade_count, c.base_volume::NUMERIC,
+                c.quote_volume::NUMERIC, c.last_price, c.last_trade_time
+            FROM UNNEST($1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::TEXT[], $5::TEXT[],
+                $6::BIGINT[], $7::BIGINT[])
+                AS c (base_token, quote_token, trade_count, base_volume, quote_volume,
+                    last_price, last_trade_time)
+            ON CONFLICT (base_token, quote_token) DO UPDATE SET
+                trade_count = GREATEST(market_counters.trade_count, EXCLUDED.trade_count),
+                base_volume = GREATEST(market_counters.base_volume, EXCLUDED.base_volume),
+                quote_volume = GREATEST(market_counters.quote_volume, EXCLUDED.quote_volume),
+                last_price = CASE
+                    WHEN market_counters.last_trade_time IS NULL
+                        OR EXCLUDED.last_trade_time >= market_counters.last_trade_time
+                    THEN COALESCE(EXCLUDED.last_price, market_counters.last_price)
+                    ELSE market_counters.last_price
+                END,
+                last_trade_time = GREATEST(market_counters.last_trade_time, EXCLUDED.last_trade_time)
+            "#,
+            )
+            .bind(bases.clone())
+            .bind(quotes.clone())
+            .bind(trade_counts.clone())
+            .bind(base_volumes.clone())
+            .bind(quote_volumes.clone())
+            .bind(last_prices.clone())
+            .bind(last_trade_times.clone())
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_counters(&self) -> Result<Vec<MarketCounters>, DatabaseError> {
+        let rows = self
+            .run_read("load_counters", |pool| {
+                query(
+                    r#"
+            SELECT base_token, quote_token, trade_count, base_volume::TEXT AS base_volume,
+                quote_volume::TEXT AS quote_volume, last_price, last_trade_time
+            FROM market_counters
+            "#,
+                )
+                .fetch_all(pool)
+            })
+            .await?;
+
+        rows.iter().map(counters_from_row).collect()
+    }
+}
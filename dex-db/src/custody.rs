@@ -0,0 +1,65 @@
+//! Postgres implementation of `CustodyRepo`.
+
+use crate::{
+    parse_column,
+    repository::{CustodyBalance, CustodyRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+
+#[async_trait]
+impl CustodyRepo for DatabaseManager {
+    async fn save_custody_balance(&self, balance: &CustodyBalance) -> Result<(), DatabaseError> {
+        let amount = balance.balance.to_string();
+
+        // Only a report at least as recent replaces the stored one, so a
+        // retry is harmless.
+        self.run("save_custody_balance", true, || {
+            query(
+                r#"
+            INSERT INTO custody_balances (wallet_id, token, balance, updated_at)
+            VALUES ($1, $2, $3::NUMERIC, $4)
+            ON CONFLICT (wallet_id, token) DO UPDATE SET
+                balance = EXCLUDED.balance,
+                updated_at = EXCLUDED.updated_at
+            WHERE EXCLUDED.updated_at >= custody_balances.updated_at
+            "#,
+            )
+            .bind(balance.wallet_id.as_str())
+            .bind(balance.token.as_str())
+            .bind(amount.as_str())
+            .bind(balance.updated_at as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_custody_balances(&self) -> Result<Vec<CustodyBalance>, DatabaseError> {
+        let rows = self
+            .run("load_custody_balances", true, || {
+                query(
+                    r#"
+            SELECT wallet_id, token, balance::TEXT AS balance, updated_at
+            FROM custody_balances
+            ORDER BY wallet_id ASC, token ASC
+            "#,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(CustodyBalance {
+                    wallet_id: row.get("wallet_id"),
+                    token: parse_column(row, "token")?,
+                    balance: parse_column(row, "balance")?,
+                    updated_at: row.get::<i64, _>("updated_at") as u64,
+                })
+            })
+            .collect()
+    }
+}
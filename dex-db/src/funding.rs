@@ -0,0 +1,172 @@
+//! Postgres implementation of `FundingRepo`.
+
+use crate::{
+    ledger::insert_entry,
+    parse_column,
+    repository::{FundingFilter, FundingRecord, FundingRepo, FundingStatus},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::ledger::JournalEntry;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::{PgExecutor, PgRow};
+
+const COLUMNS: &str = "id, trader_id, kind, chain, token, amount::TEXT AS amount, address, \
+                       reference, tx_hash, status, created_at, updated_at";
+
+fn funding_from_row(row: &PgRow) -> Result<FundingRecord, DatabaseError> {
+    Ok(FundingRecord {
+        id: row.get::<i64, _>("id") as u64,
+        trader_id: row.get("trader_id"),
+        kind: parse_column(row, "kind")?,
+        chain: row.get("chain"),
+        token: parse_column(row, "token")?,
+        amount: parse_column(row, "amount")?,
+        address: row.get("address"),
+        reference: row.get("reference"),
+        tx_hash: row.get("tx_hash"),
+        status: parse_column(row, "status")?,
+        created_at: row.get::<i64, _>("created_at") as u64,
+        updated_at: row.get::<i64, _>("updated_at") as u64,
+    })
+}
+
+/// Insert `record` through `executor`, returning its new ID, or `None` when
+/// a deposit with the same chain and reference is already stored.
+async fn insert_funding<'e>(
+    executor: impl PgExecutor<'e>,
+    record: &FundingRecord,
+) -> Result<Option<u64>, sqlx_core::error::Error> {
+    let row = query(
+        r#"
+        INSERT INTO funding_transfers (
+            trader_id, kind, chain, token, amount, address, reference, tx_hash, status,
+            created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5::NUMERIC, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (kind, chain, reference) DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(record.trader_id.as_str())
+    .bind(record.kind.as_str())
+    .bind(record.chain.as_str())
+    .bind(record.token.as_str())
+    .bind(record.amount.to_string())
+    .bind(record.address.as_deref())
+    .bind(record.reference.as_deref())
+    .bind(record.tx_hash.as_deref())
+    .bind(record.status.as_str())
+    .bind(record.created_at as i64)
+    .bind(record.updated_at as i64)
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|row| row.get::<i64, _>("id") as u64))
+}
+
+#[async_trait]
+impl FundingRepo for DatabaseManager {
+    async fn save_funding(&self, record: &FundingRecord) -> Result<Option<u64>, DatabaseError> {
+        // Not retried: the ID is assigned here, so a retry after a lost reply
+        // would store a withdrawal twice.
+        self.run("save_funding", false, || insert_funding(&self.pool, record))
+            .await
+    }
+
+    async fn save_withdrawal(
+        &self,
+        record: &FundingRecord,
+        debit: &(dyn Fn(u64) -> JournalEntry + Sync),
+    ) -> Result<u64, DatabaseError> {
+        // Not retried, like `save_funding`.
+        let id = self
+            .run("save_withdrawal", false, || async {
+                let mut tx = self.pool.begin().await?;
+                let id = insert_funding(&mut *tx, record).await?;
+                if let Some(id) = id {
+                    insert_entry(&mut *tx, &debit(id)).await?;
+                    tx.commit().await?;
+                }
+                Ok(id)
+            })
+            .await?;
+        id.ok_or(DatabaseError::DataIntegrityError)
+    }
+
+    async fn get_funding(&self, id: u64) -> Result<Option<FundingRecord>, DatabaseError> {
+        let sql = format!("SELECT {} FROM funding_transfers WHERE id = $1", COLUMNS);
+        let row = self
+            .run("get_funding", true, || {
+                query(&sql).bind(id as i64).fetch_optional(&self.pool)
+            })
+            .await?;
+        row.as_ref().map(funding_from_row).transpose()
+    }
+
+    async fn settle_funding(
+        &self,
+        id: u64,
+        status: FundingStatus,
+        tx_hash: Option<&str>,
+        at: u64,
+        refund: Option<&JournalEntry>,
+    ) -> Result<bool, DatabaseError> {
+        // Only pending records move, so a retry after a lost reply reports
+        // false; callers read the record back to tell.
+        let result = self
+            .run("settle_funding", true, || async {
+                let mut tx = self.pool.begin().await?;
+                let result = query(
+                    r#"
+            UPDATE funding_transfers
+            SET status = $2, tx_hash = COALESCE($3, tx_hash), updated_at = $4
+            WHERE id = $1 AND status = 'pending'
+            "#,
+                )
+                .bind(id as i64)
+                .bind(status.as_str())
+                .bind(tx_hash)
+                .bind(at as i64)
+                .execute(&mut *tx)
+                .await?;
+                if let (1, Some(refund)) = (result.rows_affected(), refund) {
+                    insert_entry(&mut *tx, refund).await?;
+                }
+                tx.commit().await?;
+                Ok(result)
+            })
+            .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn query_funding(
+        &self,
+        filter: &FundingFilter,
+    ) -> Result<Vec<FundingRecord>, DatabaseError> {
+        let sql = format!(
+            r#"
+            SELECT {}
+            FROM funding_transfers
+            WHERE ($1::BIGINT IS NULL OR id > $1)
+              AND ($2::TEXT IS NULL OR trader_id = $2)
+              AND ($3::TEXT IS NULL OR kind = $3)
+              AND ($4::TEXT IS NULL OR status = $4)
+            ORDER BY id ASC
+            LIMIT $5
+            "#,
+            COLUMNS
+        );
+        let rows = self
+            .run("query_funding", true, || {
+                query(&sql)
+                    .bind(filter.after_id.map(|id| id as i64))
+                    .bind(filter.trader_id.as_deref())
+                    .bind(filter.kind.map(|kind| kind.as_str()))
+                    .bind(filter.status.map(|status| status.as_str()))
+                    .bind(i64::from(filter.page_size()))
+                    .fetch_all(&self.pool)
+            })
+            .await?;
+        rows.iter().map(funding_from_row).collect()
+    }
+}
@@ -0,0 +1,80 @@
+//! Per-query timeouts and slow-query logging for the database layer
+//!
+//! Every `DatabaseManager` call is bounded by a timeout and tagged with the
+//! operation name, so queries that stall order persistence show up in the
+//! logs as `slow query` warnings with their duration and row count.
+
+use sqlx_postgres::{PgQueryResult, PgRow};
+use std::time::Duration;
+
+/// Timeout and slow-query threshold applied to every database call.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryLimits {
+    /// Upper bound for a single attempt of a query.
+    pub timeout: Duration,
+    /// Queries taking at least this long are logged.
+    pub slow_threshold: Duration,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(5),
+            slow_threshold: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Number of rows produced or touched by a query result.
+pub trait RowCount {
+    fn row_count(&self) -> u64;
+}
+
+impl RowCount for PgQueryResult {
+    fn row_count(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+impl<T> RowCount for Option<T> {
+    fn row_count(&self) -> u64 {
+        u64::from(self.is_some())
+    }
+}
+
+impl<T> RowCount for Vec<T> {
+    fn row_count(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl RowCount for PgRow {
+    fn row_count(&self) -> u64 {
+        1
+    }
+}
+
+/// A single `BIGINT` read with `query_scalar!`.
+impl RowCount for i64 {
+    fn row_count(&self) -> u64 {
+        1
+    }
+}
+
+/// Outcome label for a finished query attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryOutcome {
+    Ok,
+    Error,
+    Timeout,
+}
+
+impl QueryOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryOutcome::Ok => "ok",
+            QueryOutcome::Error => "error",
+            QueryOutcome::Timeout => "timeout",
+        }
+    }
+}
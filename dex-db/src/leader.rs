@@ -0,0 +1,64 @@
+//! Leadership among instances sharing a database, held as a Postgres
+//! session advisory lock.
+//!
+//! The lock lives as long as the session that took it, so a lease keeps a
+//! connection of its own, outside the pool. When the leader exits or its
+//! connection drops, Postgres releases the lock and another instance can
+//! take it.
+
+use crate::{DatabaseError, DatabaseManager};
+use sqlx_core::{connection::Connection, query::query, row::Row};
+use sqlx_postgres::PgConnection;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Proof of leadership: the connection holding the advisory lock.
+pub struct LeaderLease {
+    key: i64,
+    connection: Mutex<PgConnection>,
+    timeout: Duration,
+}
+
+impl DatabaseManager {
+    /// Take leadership under `key` if no other session holds it.
+    pub async fn try_lead(&self, key: i64) -> Result<Option<LeaderLease>, DatabaseError> {
+        let mut connection = self.pool.acquire().await?.detach();
+        let row = query("SELECT pg_try_advisory_lock($1) AS locked")
+            .bind(key)
+            .fetch_one(&mut connection)
+            .await?;
+        if !row.get::<bool, _>("locked") {
+            let _ = connection.close().await;
+            return Ok(None);
+        }
+        Ok(Some(LeaderLease {
+            key,
+            connection: Mutex::new(connection),
+            timeout: self.query_limits.timeout,
+        }))
+    }
+}
+
+impl LeaderLease {
+    /// Whether the lock is still held. False once the connection fails or
+    /// does not answer within the query timeout; leadership may already have
+    /// passed to another instance by then.
+    pub async fn held(&self) -> bool {
+        let mut connection = self.connection.lock().await;
+        let check = query(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM pg_locks
+                WHERE locktype = 'advisory' AND objsubid = 1 AND granted AND pid = pg_backend_pid()
+                    AND ((classid::BIGINT << 32) | objid::BIGINT) = $1
+            ) AS held
+            "#,
+        )
+        .bind(self.key)
+        .fetch_one(&mut *connection);
+        match tokio::time::timeout(self.timeout, check).await {
+            Ok(Ok(row)) => row.get::<bool, _>("held"),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Postgres implementation of `LedgerRepo`.
+
+use crate::{
+    parse_column,
+    repository::{LedgerFilter, LedgerRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::ledger::{Account, AccountBalance, JournalEntry, Posting};
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::{PgExecutor, PgRow};
+use std::collections::HashMap;
+
+fn balance_from_row(row: &PgRow) -> Result<AccountBalance, DatabaseError> {
+    Ok(AccountBalance {
+        account: parse_column(row, "account")?,
+        token: parse_column(row, "token")?,
+        balance: parse_column(row, "balance")?,
+    })
+}
+
+/// Store `entry`, its postings and the balances they move, through
+/// `executor`. Returns the new entry's id, or `None` when an entry of the
+/// same kind and reference is already stored.
+///
+/// One statement, so the entry is stored whole even outside a transaction.
+/// Balances are locked in account order, so concurrent entries cannot
+/// deadlock on them.
+pub(crate) async fn insert_entry<'e>(
+    executor: impl PgExecutor<'e>,
+    entry: &JournalEntry,
+) -> Result<Option<u64>, sqlx_core::error::Error> {
+    let postings = |field: fn(&Posting) -> String| -> Vec<String> {
+        entry.postings.iter().map(field).collect()
+    };
+    let row = query(
+        r#"
+        WITH entry AS (
+            INSERT INTO ledger_entries (kind, reference, timestamp)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (kind, reference) DO NOTHING
+            RETURNING id
+        ), postings AS (
+            INSERT INTO ledger_postings (entry_id, line, account, token, direction, amount)
+            SELECT entry.id, p.line, p.account, p.token, p.direction, p.amount::NUMERIC
+            FROM entry, UNNEST($4::TEXT[], $5::TEXT[], $6::TEXT[], $7::TEXT[])
+                WITH ORDINALITY AS p (account, token, direction, amount, line)
+        ), balances AS (
+            INSERT INTO ledger_balances (account, token, balance)
+            SELECT p.account, p.token,
+                SUM(CASE p.direction WHEN 'debit' THEN p.amount::NUMERIC
+                    ELSE -p.amount::NUMERIC END)
+            FROM entry, UNNEST($4::TEXT[], $5::TEXT[], $6::TEXT[], $7::TEXT[])
+                AS p (account, token, direction, amount)
+            GROUP BY p.account, p.token
+            ORDER BY p.account, p.token
+            ON CONFLICT (account, token) DO UPDATE
+                SET balance = ledger_balances.balance + EXCLUDED.balance
+        )
+        SELECT id FROM entry
+        "#,
+    )
+    .bind(entry.kind.as_str())
+    .bind(entry.reference.as_str())
+    .bind(entry.timestamp as i64)
+    .bind(postings(|p| p.account.to_string()))
+    .bind(postings(|p| p.token.to_string()))
+    .bind(postings(|p| p.direction.as_str().to_string()))
+    .bind(postings(|p| p.amount.to_string()))
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|row| row.get::<i64, _>("id") as u64))
+}
+
+#[async_trait]
+impl LedgerRepo for DatabaseManager {
+    async fn save_entry(&self, entry: &JournalEntry) -> Result<Option<u64>, DatabaseError> {
+        // A retry after a lost reply finds the entry already stored and
+        // returns `None`, which callers treat as booked.
+        self.run("save_ledger_entry", true, || {
+            insert_entry(&self.pool, entry)
+        })
+        .await
+    }
+
+    async fn query_ledger(
+        &self,
+        filter: &LedgerFilter,
+    ) -> Result<Vec<JournalEntry>, DatabaseError> {
+        let account = filter.account.as_ref().map(ToString::to_string);
+        let rows = self
+            .run("query_ledger", true, || {
+                query(
+                    r#"
+            SELECT id, kind, reference, timestamp
+            FROM ledger_entries e
+            WHERE ($1::BIGINT IS NULL OR id > $1)
+              AND ($2::TEXT IS NULL OR kind = $2)
+              AND ($3::TEXT IS NULL OR EXISTS (
+                  SELECT 1 FROM ledger_postings p WHERE p.entry_id = e.id AND p.account = $3
+              ))
+            ORDER BY id ASC
+            LIMIT $4
+            "#,
+                )
+                .bind(filter.after_id.map(|id| id as i64))
+                .bind(filter.kind.map(|kind| kind.as_str()))
+                .bind(account.as_deref())
+                .bind(i64::from(filter.page_size()))
+                .fetch_all(&self.pool)
+            })
+            .await?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids: Vec<i64> = rows.iter().map(|row| row.get("id")).collect();
+        let posting_rows = self
+            .run("query_ledger_postings", true, || {
+                query(
+                    r#"
+            SELECT entry_id, account, token, direction, amount::TEXT AS amount
+            FROM ledger_postings
+            WHERE entry_id = ANY($1)
+            ORDER BY entry_id ASC, line ASC
+            "#,
+                )
+                .bind(ids.clone())
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        let mut postings: HashMap<i64, Vec<Posting>> = HashMap::new();
+        for row in &posting_rows {
+            postings
+                .entry(row.get("entry_id"))
+                .or_default()
+                .push(Posting {
+                    account: parse_column(row, "account")?,
+                    token: parse_column(row, "token")?,
+                    direction: parse_column(row, "direction")?,
+                    amount: parse_column(row, "amount")?,
+                });
+        }
+
+        rows.iter()
+            .map(|row| {
+                let id: i64 = row.get("id");
+                Ok(JournalEntry {
+                    id: id as u64,
+                    kind: parse_column(row, "kind")?,
+                    reference: row.get("reference"),
+                    timestamp: row.get::<i64, _>("timestamp") as u64,
+                    postings: postings.remove(&id).unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    async fn ledger_balances(&self) -> Result<Vec<AccountBalance>, DatabaseError> {
+        let rows = self
+            .run("ledger_balances", true, || {
+                query(
+                    r#"
+            SELECT account, token, balance::TEXT AS balance
+            FROM ledger_balances
+            WHERE balance <> 0
+            ORDER BY account ASC, token ASC
+            "#,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+        rows.iter().map(balance_from_row).collect()
+    }
+
+    async fn account_balances(
+        &self,
+        account: &Account,
+    ) -> Result<Vec<AccountBalance>, DatabaseError> {
+        let account = account.to_string();
+        let rows = self
+            .run("account_balances", true, || {
+                query(
+                    r#"
+            SELECT account, token, balance::TEXT AS balance
+            FROM ledger_balances
+            WHERE account = $1 AND balance <> 0
+            ORDER BY token ASC
+            "#,
+                )
+                .bind(account.as_str())
+                .fetch_all(&self.pool)
+            })
+            .await?;
+        rows.iter().map(balance_from_row).collect()
+    }
+}
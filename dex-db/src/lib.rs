@@ -3,259 +3,426 @@
 //! This module provides database functionality for persisting orders,
 //! trades, and other DEX-related data.
 
-use dex_core::types::{Order, OrderId, Trade, TradeId, TraderId, TradingPair};
+use instrument::{QueryLimits, QueryOutcome, RowCount};
+use pool::{PoolConfig, PoolStats};
+use resilience::{
+    is_transient, BreakerState, CircuitBreaker, DbMetrics, DbMetricsSnapshot, ResilienceConfig,
+};
 use sqlx_core::{query::query, row::Row};
-use sqlx_postgres::{PgPool, PgPoolOptions};
+use sqlx_postgres::{PgConnectOptions, PgPool, PgPoolOptions, PgRow};
+use std::{
+    future::Future,
+    str::FromStr,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
+};
 use thiserror::Error;
+use tracing::Instrument;
 
+mod api_keys;
+mod audit;
+mod candles;
+mod chain;
+mod challenges;
+mod counters;
+mod custody;
+mod funding;
+pub mod instrument;
+pub mod leader;
+mod ledger;
+pub mod memory;
 pub mod migrations;
+pub mod online_migration;
+mod orders;
+mod outbox;
+pub mod pool;
+mod pools;
+mod receipts;
+mod refresh_tokens;
+pub mod repository;
+pub mod resilience;
+mod retention;
+mod rows;
+mod settlement;
+pub mod sqlite;
+#[cfg(test)]
+mod storage_tests;
+mod swaps;
+pub mod tick_maps;
+mod totp;
+pub mod trade_feed;
+mod trades;
+mod usage;
+mod webhooks;
+
+pub use repository::{
+    AdjustmentKind, ApiKeyRecord, ApiKeyRepo, AuditEntry, AuditFilter, AuditRepo, CandleRecord,
+    CandleRepo, ChainBlock, ChainRepo, ChainTransaction, ChallengeRecord, ChallengeRepo,
+    CounterRepo, CustodyBalance, CustodyRepo, DeliveryStatus, FundingFilter, FundingKind,
+    FundingRecord, FundingRepo, FundingStatus, LedgerFilter, LedgerRepo, MarketCounters,
+    MessagingPenalty, NetPosition, NetTransfer, NettingSet, OrderFill, OrderRepo, OrderStatus,
+    OutboxEvent, OutboxRepo, PoolPosition, PoolRecord, PoolRepo, ReceiptRepo, RefreshTokenRecord,
+    RefreshTokenRepo, RetentionRepo, RevokedToken, SequencingReceipt, SettlementRepo, Storage,
+    SwapRecord, SwapRepo, TickMapBlob, TickMapRepo, TotpRecord, TotpRepo, TradeAdjustment,
+    TradeFilter, TradeRepo, UsageRepo, UsageRollup, WebhookDelivery, WebhookRecord, WebhookRepo,
+};
 
 /// Database manager for the DEX
 #[derive(Clone)]
 pub struct DatabaseManager {
     pool: PgPool,
+    /// Read-only pool for heavy history queries; `None` reads the primary.
+    replica: Option<PgPool>,
+    resilience: ResilienceConfig,
+    breaker: Arc<CircuitBreaker>,
+    metrics: Arc<DbMetrics>,
+    query_limits: QueryLimits,
+    pool_config: PoolConfig,
 }
 
 impl DatabaseManager {
     /// Establish a new connection pool using the provided database URL.
     pub async fn connect(database_url: &str) -> Result<Self, DatabaseError> {
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .connect(database_url)
-            .await?;
-        Ok(Self { pool })
+        Self::connect_with(database_url, PoolConfig::default()).await
     }
 
-    /// Create a new database manager with the provided connection pool
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    /// Establish a new connection pool sized and timed by `pool_config`.
+    pub async fn connect_with(
+        database_url: &str,
+        pool_config: PoolConfig,
+    ) -> Result<Self, DatabaseError> {
+        let options = pool_config.connect_options(PgConnectOptions::from_str(database_url)?);
+        let pool = pool_config.pool_options().connect_with(options).await?;
+        Ok(Self {
+            pool_config,
+            ..Self::new(pool)
+        })
     }
 
-    /// Initialize the database schema
-    pub async fn initialize(&self) -> Result<(), DatabaseError> {
-        // Run migrations
-        migrations::run_migrations(&self.pool).await?;
+    /// Point connections opened from now on at `database_url`, e.g. after
+    /// the database password was rotated. Open connections are kept until
+    /// the pool retires them.
+    pub fn set_url(&self, database_url: &str) -> Result<(), DatabaseError> {
+        self.pool.set_connect_options(
+            self.pool_config
+                .connect_options(PgConnectOptions::from_str(database_url)?),
+        );
         Ok(())
     }
 
-    /// Save an order to the database
-    pub async fn save_order(&self, order: &Order) -> Result<(), DatabaseError> {
-        query(
-            r#"
-            INSERT INTO orders (
-                id, trader_id, base_token, quote_token, side, order_type, price, quantity, timestamp
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
-            ON CONFLICT (id) DO UPDATE SET
-                trader_id = $2,
-                base_token = $3,
-                quote_token = $4,
-                side = $5,
-                order_type = $6,
-                price = $7,
-                quantity = $8,
-                timestamp = $9
-            "#,
-        )
-        .bind(order.id as i64)
-        .bind(&order.trader_id)
-        .bind(&order.pair.base)
-        .bind(&order.pair.quote)
-        .bind(match order.side {
-            dex_core::types::OrderSide::Buy => "buy",
-            dex_core::types::OrderSide::Sell => "sell",
-        })
-        .bind(match order.order_type {
-            dex_core::types::OrderType::Limit => "limit",
-            dex_core::types::OrderType::Market => "market",
-        })
-        .bind(order.price.map(|p| p as i64))
-        .bind(order.quantity as i64)
-        .bind(order.timestamp as i64)
-        .execute(&self.pool)
-        .await?;
+    /// Send trade history and candle reads to the read replica at
+    /// `replica_url`. Connections are opened on first use, so a replica that
+    /// is down at startup only costs a fallback to the primary.
+    pub fn with_replica(mut self, replica_url: &str) -> Result<Self, DatabaseError> {
+        let options = self
+            .pool_config
+            .connect_options(PgConnectOptions::from_str(replica_url)?);
+        self.replica = Some(self.pool_config.pool_options().connect_lazy_with(options));
+        Ok(self)
+    }
 
+    /// Like `set_url`, for the read replica. Does nothing without one.
+    pub fn set_replica_url(&self, replica_url: &str) -> Result<(), DatabaseError> {
+        if let Some(replica) = &self.replica {
+            replica.set_connect_options(
+                self.pool_config
+                    .connect_options(PgConnectOptions::from_str(replica_url)?),
+            );
+        }
         Ok(())
     }
 
-    /// Load an order from the database by ID
-    pub async fn load_order(&self, order_id: OrderId) -> Result<Option<Order>, DatabaseError> {
-        let row = query(
-            r#"
-            SELECT 
-                id, trader_id, base_token, quote_token, side, order_type, price, quantity, timestamp
-            FROM orders
-            WHERE id = $1
-            "#,
-        )
-        .bind(order_id as i64)
-        .fetch_optional(&self.pool)
-        .await?;
-
-        if let Some(row) = row {
-            let price: Option<i64> = row.get("price");
-            let order = Order {
-                id: row.get::<i64, _>("id") as u64,
-                trader_id: row.get("trader_id"),
-                pair: TradingPair {
-                    base: row.get("base_token"),
-                    quote: row.get("quote_token"),
-                },
-                side: match row.get::<&str, _>("side") {
-                    "buy" => dex_core::types::OrderSide::Buy,
-                    "sell" => dex_core::types::OrderSide::Sell,
-                    _ => return Err(DatabaseError::DataIntegrityError),
-                },
-                order_type: match row.get::<&str, _>("order_type") {
-                    "limit" => dex_core::types::OrderType::Limit,
-                    "market" => dex_core::types::OrderType::Market,
-                    _ => return Err(DatabaseError::DataIntegrityError),
-                },
-                price: price.map(|p| p as u64),
-                quantity: row.get::<i64, _>("quantity") as u64,
-                timestamp: row.get::<i64, _>("timestamp") as u64,
-            };
-            Ok(Some(order))
-        } else {
-            Ok(None)
+    /// Create a new database manager with the provided connection pool
+    pub fn new(pool: PgPool) -> Self {
+        let resilience = ResilienceConfig::default();
+        Self {
+            pool,
+            replica: None,
+            resilience,
+            breaker: Arc::new(CircuitBreaker::new(resilience.breaker)),
+            metrics: Arc::new(DbMetrics::default()),
+            query_limits: QueryLimits::default(),
+            pool_config: PoolConfig::default(),
         }
     }
 
-    /// Delete an order from the database
-    pub async fn delete_order(&self, order_id: OrderId) -> Result<bool, DatabaseError> {
-        let result = query("DELETE FROM orders WHERE id = $1")
-            .bind(order_id as i64)
-            .execute(&self.pool)
-            .await?;
+    /// Replace the retry and circuit breaker settings.
+    pub fn with_resilience(mut self, resilience: ResilienceConfig) -> Self {
+        self.resilience = resilience;
+        self.breaker = Arc::new(CircuitBreaker::new(resilience.breaker));
+        self
+    }
 
-        Ok(result.rows_affected() > 0)
+    /// Replace the per-query timeout and slow-query threshold.
+    pub fn with_query_limits(mut self, query_limits: QueryLimits) -> Self {
+        self.query_limits = query_limits;
+        self
     }
 
-    /// Save a trade to the database
-    pub async fn save_trade(&self, trade: &Trade) -> Result<(), DatabaseError> {
-        query(
-            r#"
-            INSERT INTO trades (
-                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
-            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
-            "#,
-        )
-        .bind(trade.id as i64)
-        .bind(trade.maker_order_id as i64)
-        .bind(trade.taker_order_id as i64)
-        .bind(&trade.base_token)
-        .bind(&trade.quote_token)
-        .bind(trade.price as i64)
-        .bind(trade.quantity as i64)
-        .bind(trade.timestamp as i64)
-        .execute(&self.pool)
-        .await?;
+    /// Current circuit breaker state.
+    pub fn breaker_state(&self) -> BreakerState {
+        self.breaker.state()
+    }
 
-        Ok(())
+    /// Whether the database is accepting work. Half-open counts as available so
+    /// a trial request can close the breaker again.
+    pub fn is_available(&self) -> bool {
+        self.breaker.state() != BreakerState::Open
     }
 
-    /// Load a trade from the database by ID
-    pub async fn load_trade(&self, trade_id: TradeId) -> Result<Option<Trade>, DatabaseError> {
-        let row = query(
-            r#"
-            SELECT 
-                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
-            FROM trades
-            WHERE id = $1
-            "#,
-        )
-        .bind(trade_id as i64)
-        .fetch_optional(&self.pool)
-        .await?;
+    /// Snapshot of retry/breaker counters for metrics export.
+    pub fn metrics(&self) -> DbMetricsSnapshot {
+        self.metrics.snapshot(self.breaker.state())
+    }
 
-        if let Some(row) = row {
-            let trade = Trade {
-                id: row.get::<i64, _>("id") as u64,
-                maker_order_id: row.get::<i64, _>("maker_order_id") as u64,
-                taker_order_id: row.get::<i64, _>("taker_order_id") as u64,
-                base_token: row.get("base_token"),
-                quote_token: row.get("quote_token"),
-                price: row.get::<i64, _>("price") as u64,
-                quantity: row.get::<i64, _>("quantity") as u64,
-                timestamp: row.get::<i64, _>("timestamp") as u64,
-            };
-            Ok(Some(trade))
-        } else {
-            Ok(None)
+    /// Busy and idle connections of the pool right now.
+    pub fn pool_stats(&self) -> PoolStats {
+        PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle() as u32,
+            max_connections: self.pool.options().get_max_connections(),
         }
     }
 
-    /// Get all trades for a specific order
-    pub async fn get_trades_for_order(
-        &self,
-        order_id: OrderId,
-    ) -> Result<Vec<Trade>, DatabaseError> {
-        let rows = query(
-            r#"
-            SELECT 
-                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
-            FROM trades
-            WHERE maker_order_id = $1 OR taker_order_id = $1
-            ORDER BY timestamp ASC
-            "#,
+    /// Run a lightweight health query, bypassing the breaker gate. A successful
+    /// probe closes the breaker.
+    pub async fn probe(&self) -> Result<(), DatabaseError> {
+        self.metrics.probes.fetch_add(1, Ordering::Relaxed);
+        let probe = tokio::time::timeout(
+            self.query_limits.timeout,
+            query("SELECT 1").execute(&self.pool),
         )
-        .bind(order_id as i64)
-        .fetch_all(&self.pool)
-        .await?;
-
-        let mut trades = Vec::new();
-        for row in rows {
-            let trade = Trade {
-                id: row.get::<i64, _>("id") as u64,
-                maker_order_id: row.get::<i64, _>("maker_order_id") as u64,
-                taker_order_id: row.get::<i64, _>("taker_order_id") as u64,
-                base_token: row.get("base_token"),
-                quote_token: row.get("quote_token"),
-                price: row.get::<i64, _>("price") as u64,
-                quantity: row.get::<i64, _>("quantity") as u64,
-                timestamp: row.get::<i64, _>("timestamp") as u64,
-            };
-            trades.push(trade);
+        .await
+        .unwrap_or(Err(sqlx_core::error::Error::PoolTimedOut));
+        match probe {
+            Ok(_) => {
+                if self.breaker.record_success() {
+                    self.metrics.breaker_closed.fetch_add(1, Ordering::Relaxed);
+                    tracing::info!("database recovered, circuit breaker closed");
+                }
+                Ok(())
+            }
+            Err(err) => {
+                self.metrics.probe_failures.fetch_add(1, Ordering::Relaxed);
+                if is_transient(&err) && self.breaker.record_failure() {
+                    self.metrics.breaker_opened.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err.into())
+            }
         }
+    }
 
-        Ok(trades)
+    /// Periodically probe the database while the breaker is not closed.
+    pub fn spawn_recovery_probe(
+        self: Arc<Self>,
+        interval: Duration,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if self.breaker.state() == BreakerState::Closed {
+                    continue;
+                }
+                if let Err(err) = self.probe().await {
+                    tracing::warn!(error = ?err, "database recovery probe failed");
+                }
+            }
+        })
     }
 
-    /// Get all trades for a specific trader
-    pub async fn get_trades_for_trader(
+    /// Execute a database operation through the circuit breaker, retrying
+    /// transient failures with backoff when the operation is idempotent. Each
+    /// attempt is bounded by the query timeout and logged when slow.
+    async fn run<T, F, Fut>(
         &self,
-        trader_id: &TraderId,
-    ) -> Result<Vec<Trade>, DatabaseError> {
-        let rows = query(
-            r#"
-            SELECT 
-                t.id, t.maker_order_id, t.taker_order_id, t.base_token, t.quote_token, t.price, t.quantity, t.timestamp
-            FROM trades t
-            JOIN orders o1 ON t.maker_order_id = o1.id
-            JOIN orders o2 ON t.taker_order_id = o2.id
-            WHERE o1.trader_id = $1 OR o2.trader_id = $1
-            ORDER BY t.timestamp ASC
-            "#,
-        )
-        .bind(trader_id)
-        .fetch_all(&self.pool)
-        .await?;
+        op: &'static str,
+        idempotent: bool,
+        mut f: F,
+    ) -> Result<T, DatabaseError>
+    where
+        T: RowCount,
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, sqlx_core::error::Error>>,
+    {
+        if !self.breaker.try_acquire() {
+            self.metrics.rejected.fetch_add(1, Ordering::Relaxed);
+            return Err(DatabaseError::Unavailable);
+        }
+
+        let max_attempts = if idempotent {
+            self.resilience.retry.max_attempts.max(1)
+        } else {
+            1
+        };
+        let mut attempt = 1;
+        loop {
+            self.metrics.operations.fetch_add(1, Ordering::Relaxed);
+            let started = Instant::now();
+            let span = tracing::info_span!(
+                "db",
+                otel.kind = "client",
+                db.system = "postgresql",
+                db.operation = op,
+                attempt,
+                otel.status_code = tracing::field::Empty,
+            );
+            let outcome = tokio::time::timeout(self.query_limits.timeout, f())
+                .instrument(span.clone())
+                .await;
+            if !matches!(outcome, Ok(Ok(_))) {
+                span.record("otel.status_code", "ERROR");
+            }
+            let elapsed = started.elapsed();
+            let err = match outcome {
+                Ok(Ok(value)) => {
+                    self.log_if_slow(op, elapsed, Some(value.row_count()), QueryOutcome::Ok);
+                    if self.breaker.record_success() {
+                        self.metrics.breaker_closed.fetch_add(1, Ordering::Relaxed);
+                        tracing::info!("database recovered, circuit breaker closed");
+                    }
+                    return Ok(value);
+                }
+                Ok(Err(err)) => {
+                    if matches!(err, sqlx_core::error::Error::PoolTimedOut) {
+                        self.metrics.pool_timeouts.fetch_add(1, Ordering::Relaxed);
+                    }
+                    self.log_if_slow(op, elapsed, None, QueryOutcome::Error);
+                    DatabaseError::from(err)
+                }
+                Err(_) => {
+                    self.metrics.timeouts.fetch_add(1, Ordering::Relaxed);
+                    self.log_if_slow(op, elapsed, None, QueryOutcome::Timeout);
+                    DatabaseError::Timeout(op)
+                }
+            };
+
+            if !err.is_unavailable() {
+                // The database answered, so it is reachable even though the
+                // statement failed.
+                self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+                self.breaker.record_success();
+                return Err(err);
+            }
+            if attempt < max_attempts {
+                self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    db.operation = op,
+                    attempt,
+                    max_attempts,
+                    error = ?err,
+                    "database operation failed, retrying"
+                );
+                tokio::time::sleep(self.resilience.retry.delay_for(attempt)).await;
+                attempt += 1;
+                continue;
+            }
+            self.metrics.failures.fetch_add(1, Ordering::Relaxed);
+            if self.breaker.record_failure() {
+                self.metrics.breaker_opened.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    db.operation = op,
+                    error = ?err,
+                    "circuit breaker opened after database operation failed"
+                );
+            }
+            return Err(err);
+        }
+    }
 
-        let mut trades = Vec::new();
-        for row in rows {
-            let trade = Trade {
-                id: row.get::<i64, _>("id") as u64,
-                maker_order_id: row.get::<i64, _>("maker_order_id") as u64,
-                taker_order_id: row.get::<i64, _>("taker_order_id") as u64,
-                base_token: row.get("base_token"),
-                quote_token: row.get("quote_token"),
-                price: row.get::<i64, _>("price") as u64,
-                quantity: row.get::<i64, _>("quantity") as u64,
-                timestamp: row.get::<i64, _>("timestamp") as u64,
+    /// Execute a read that tolerates replication lag on the read replica,
+    /// falling back to the primary through `run` when the replica is
+    /// unreachable or too slow. Statement errors are returned as they are,
+    /// since the primary would fail the same way. Replica attempts bypass the
+    /// breaker, which tracks the primary only.
+    async fn run_read<'a, T, F, Fut>(
+        &'a self,
+        op: &'static str,
+        mut f: F,
+    ) -> Result<T, DatabaseError>
+    where
+        T: RowCount,
+        F: FnMut(&'a PgPool) -> Fut,
+        Fut: Future<Output = Result<T, sqlx_core::error::Error>>,
+    {
+        if let Some(replica) = &self.replica {
+            let started = Instant::now();
+            let span = tracing::info_span!(
+                "db",
+                otel.kind = "client",
+                db.system = "postgresql",
+                db.operation = op,
+                db.replica = true,
+            );
+            let outcome = tokio::time::timeout(self.query_limits.timeout, f(replica))
+                .instrument(span)
+                .await;
+            let elapsed = started.elapsed();
+            let err = match outcome {
+                Ok(Ok(value)) => {
+                    self.log_if_slow(op, elapsed, Some(value.row_count()), QueryOutcome::Ok);
+                    return Ok(value);
+                }
+                Ok(Err(err)) => {
+                    self.log_if_slow(op, elapsed, None, QueryOutcome::Error);
+                    DatabaseError::from(err)
+                }
+                Err(_) => {
+                    self.log_if_slow(op, elapsed, None, QueryOutcome::Timeout);
+                    DatabaseError::Timeout(op)
+                }
             };
-            trades.push(trade);
+            if !err.is_unavailable() {
+                return Err(err);
+            }
+            self.metrics
+                .replica_fallbacks
+                .fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                db.operation = op,
+                error = ?err,
+                "read replica unavailable, reading from the primary"
+            );
+        }
+        self.run(op, true, || f(&self.pool)).await
+    }
+
+    fn log_if_slow(
+        &self,
+        op: &'static str,
+        elapsed: Duration,
+        rows: Option<u64>,
+        outcome: QueryOutcome,
+    ) {
+        if elapsed >= self.query_limits.slow_threshold {
+            self.metrics.slow_queries.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                db.operation = op,
+                duration_ms = elapsed.as_millis() as u64,
+                rows,
+                outcome = outcome.as_str(),
+                "slow query"
+            );
         }
+    }
+
+    /// Initialize the database schema
+    pub async fn initialize(&self) -> Result<(), DatabaseError> {
+        // Run migrations
+        migrations::run_migrations(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Migrations `initialize` would run, without running them. Fails like
+    /// `initialize` when an applied migration has changed.
+    pub async fn pending_migrations(&self) -> Result<Vec<migrations::Migration>, DatabaseError> {
+        Ok(migrations::pending_migrations(&self.pool).await?)
+    }
+}
 
-        Ok(trades)
+impl Storage for DatabaseManager {
+    fn is_available(&self) -> bool {
+        DatabaseManager::is_available(self)
     }
 }
 
@@ -266,9 +433,34 @@ pub enum DatabaseError {
     SqlxError(#[from] sqlx_core::error::Error),
     #[error("Data integrity error")]
     DataIntegrityError,
+    #[error("Database unavailable: circuit breaker is open")]
+    Unavailable,
+    #[error("Database operation {0} timed out")]
+    Timeout(&'static str),
+    #[error("Migration failed: {0}")]
+    Migration(#[from] migrations::MigrationError),
+}
+
+impl DatabaseError {
+    /// Whether the failure stems from the database being unreachable rather
+    /// than from the request itself. Callers should surface these as 503s.
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            DatabaseError::Unavailable | DatabaseError::Timeout(_) => true,
+            DatabaseError::SqlxError(err) => is_transient(err),
+            DatabaseError::DataIntegrityError | DatabaseError::Migration(_) => false,
+        }
+    }
+}
+
+/// Parse a text column into a validated identifier, treating malformed
+/// values as a data integrity failure.
+pub(crate) fn parse_column<T: FromStr>(row: &PgRow, column: &str) -> Result<T, DatabaseError> {
+    row.get::<&str, _>(column)
+        .parse()
+        .map_err(|_| DatabaseError::DataIntegrityError)
 }
 
-#[cfg(test)]
 impl DatabaseManager {
     /// Create a database manager backed by a lazily connected pool. Useful in tests that do
     /// not exercise the database but need a handle for wiring filters.
@@ -276,6 +468,6 @@ impl DatabaseManager {
         let pool = PgPoolOptions::new()
             .max_connections(1)
             .connect_lazy(database_url)?;
-        Ok(Self { pool })
+        Ok(Self::new(pool))
     }
 }
@@ -0,0 +1,1303 @@
+//! In-memory implementation of every repository.
+//!
+//! Nothing survives a restart, so it suits tests, simulations and trying the
+//! exchange out without a Postgres server, not production.
+
+use crate::{
+    AdjustmentKind, ApiKeyRecord, ApiKeyRepo, AuditEntry, AuditFilter, AuditRepo, CandleRecord,
+    CandleRepo, ChainBlock, ChainRepo, ChainTransaction, ChallengeRecord, ChallengeRepo,
+    CounterRepo, CustodyBalance, CustodyRepo, DatabaseError, DeliveryStatus, FundingFilter,
+    FundingRecord, FundingRepo, FundingStatus, LedgerFilter, LedgerRepo, MarketCounters,
+    MessagingPenalty, NettingSet, OrderFill, OrderRepo, OutboxEvent, OutboxRepo, PoolRecord,
+    PoolRepo, ReceiptRepo, RefreshTokenRecord, RefreshTokenRepo, RetentionRepo, RevokedToken,
+    SequencingReceipt, SettlementRepo, Storage, SwapRecord, SwapRepo, TickMapBlob, TickMapRepo,
+    TotpRecord, TotpRepo, TradeAdjustment, TradeFilter, TradeRepo, UsageRepo, UsageRollup,
+    WebhookDelivery, WebhookRecord, WebhookRepo,
+};
+use async_trait::async_trait;
+use dex_core::{
+    ledger::{Account, AccountBalance, JournalEntry, Ledger},
+    types::{Order, OrderId, OrderType, Trade, TradeId, TraderId, TradingPair},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+/// Every table kept in memory behind a lock of its own.
+#[derive(Default)]
+pub struct MemoryStorage {
+    pub orders: Mutex<HashMap<OrderId, Order>>,
+    /// Orders soft-deleted; they stay in `orders`.
+    pub deleted_orders: Mutex<HashSet<OrderId>>,
+    /// Latest fill state by order; orders without one are open and unfilled.
+    pub order_fills: Mutex<HashMap<OrderId, OrderFill>>,
+    pub trades: Mutex<Vec<Trade>>,
+    /// Orders and trades moved out by retention.
+    pub orders_archive: Mutex<HashMap<OrderId, Order>>,
+    pub trades_archive: Mutex<Vec<Trade>>,
+    /// Highest order and trade IDs handed out.
+    pub issued_ids: Mutex<(OrderId, TradeId)>,
+    pub trade_adjustments: Mutex<Vec<TradeAdjustment>>,
+    /// Refresh tokens by hash.
+    pub refresh_tokens: Mutex<HashMap<String, RefreshTokenRecord>>,
+    /// Revoked access tokens by ID, with when they expire.
+    pub revoked_tokens: Mutex<HashMap<String, u64>>,
+    /// API keys by ID.
+    pub api_keys: Mutex<HashMap<String, ApiKeyRecord>>,
+    /// Pending wallet challenges by subject.
+    pub challenges: Mutex<HashMap<String, ChallengeRecord>>,
+    /// Stored usage rollups.
+    pub usage: Mutex<Vec<UsageRollup>>,
+    /// Messaging policy penalties.
+    pub penalties: Mutex<Vec<MessagingPenalty>>,
+    /// TOTP enrollments by subject.
+    pub totp: Mutex<HashMap<String, TotpRecord>>,
+    /// Audit log entries, oldest first.
+    pub audit: Mutex<Vec<AuditEntry>>,
+    /// Netting sets, oldest first.
+    pub netting_sets: Mutex<Vec<NettingSet>>,
+    /// AMM swaps, oldest first.
+    pub swaps: Mutex<Vec<SwapRecord>>,
+    /// Cumulative market counters.
+    pub counters: Mutex<Vec<MarketCounters>>,
+    /// One-minute bars, oldest first.
+    pub candles: Mutex<Vec<CandleRecord>>,
+    /// Time up to which bars are stored.
+    pub candle_watermark: Mutex<u64>,
+    /// Ledger entries, oldest first.
+    pub ledger: Mutex<Vec<JournalEntry>>,
+    /// Deposits and withdrawals, oldest first.
+    pub funding: Mutex<Vec<FundingRecord>>,
+    /// Compressed tick maps by pool.
+    pub tick_maps: Mutex<Vec<TickMapBlob>>,
+    /// AMM pools with their positions.
+    pub pools: Mutex<Vec<PoolRecord>>,
+    /// Custody wallet balances by wallet and token.
+    pub custody: Mutex<Vec<CustodyBalance>>,
+    /// Blocks of the internal chain with their transactions.
+    pub chain: Mutex<Vec<ChainBlock>>,
+    /// Sequencing receipts by sequence number.
+    pub receipts: Mutex<Vec<SequencingReceipt>>,
+    /// Stream events, oldest first, with whether each was published.
+    pub outbox: Mutex<Vec<(OutboxEvent, bool)>>,
+    /// Webhooks, revoked ones included, oldest first.
+    pub webhooks: Mutex<Vec<WebhookRecord>>,
+    /// Webhook deliveries, oldest first.
+    pub webhook_deliveries: Mutex<Vec<WebhookDelivery>>,
+}
+
+#[async_trait]
+impl OrderRepo for MemoryStorage {
+    async fn save_order(&self, order: &Order) -> Result<(), DatabaseError> {
+        self.orders.lock().unwrap().insert(order.id, order.clone());
+        Ok(())
+    }
+
+    async fn load_order(&self, order_id: OrderId) -> Result<Option<Order>, DatabaseError> {
+        Ok(self.orders.lock().unwrap().get(&order_id).cloned())
+    }
+
+    async fn delete_order(&self, order_id: OrderId) -> Result<bool, DatabaseError> {
+        let stored = self.orders.lock().unwrap().contains_key(&order_id);
+        Ok(stored && self.deleted_orders.lock().unwrap().insert(order_id))
+    }
+
+    async fn discard_order(&self, order_id: OrderId) -> Result<(), DatabaseError> {
+        self.orders.lock().unwrap().remove(&order_id);
+        self.deleted_orders.lock().unwrap().remove(&order_id);
+        Ok(())
+    }
+
+    async fn load_open_orders(&self) -> Result<Vec<Order>, DatabaseError> {
+        let orders = self.orders.lock().unwrap();
+        let fills = self.order_fills.lock().unwrap();
+        let deleted = self.deleted_orders.lock().unwrap();
+        let trades = self.trades.lock().unwrap();
+        let traded = |order_id: OrderId| -> u64 {
+            trades
+                .iter()
+                .filter(|trade| {
+                    trade.maker_order_id == order_id || trade.taker_order_id == order_id
+                })
+                .map(|trade| trade.quantity)
+                .sum()
+        };
+        let mut open: Vec<Order> = orders
+            .values()
+            .filter(|order| order.order_type == OrderType::Limit && !deleted.contains(&order.id))
+            .filter_map(|order| {
+                let remaining = match fills.get(&order.id) {
+                    Some(fill) if !fill.status.is_open() => return None,
+                    Some(fill) => fill.remaining_quantity,
+                    None => order.quantity,
+                };
+                let remaining = remaining.min(order.quantity.saturating_sub(traded(order.id)));
+                (remaining > 0).then(|| Order {
+                    quantity: remaining,
+                    ..order.clone()
+                })
+            })
+            .collect();
+        open.sort_by_key(|order| order.id);
+        Ok(open)
+    }
+
+    async fn last_order_id(&self) -> Result<OrderId, DatabaseError> {
+        let stored = self.orders.lock().unwrap().keys().max().copied();
+        let archived = self.orders_archive.lock().unwrap().keys().max().copied();
+        let traded = self
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .chain(self.trades_archive.lock().unwrap().iter())
+            .map(|trade| trade.maker_order_id.max(trade.taker_order_id))
+            .max();
+        let receipted = self
+            .receipts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|receipt| receipt.order_id)
+            .max();
+        Ok(stored.max(archived).max(traded).max(receipted).unwrap_or(0))
+    }
+
+    async fn next_order_id(&self) -> Result<OrderId, DatabaseError> {
+        let last = self.last_order_id().await?;
+        let mut issued = self.issued_ids.lock().unwrap();
+        issued.0 = issued.0.max(last) + 1;
+        Ok(issued.0)
+    }
+
+    async fn update_order_fills(&self, fills: &[OrderFill]) -> Result<(), DatabaseError> {
+        let orders = self.orders.lock().unwrap();
+        store_fills(&orders, &mut self.order_fills.lock().unwrap(), fills);
+        Ok(())
+    }
+}
+
+/// Record the fills of orders that are stored; others are skipped, like
+/// an update matching no row.
+fn store_fills(
+    orders: &HashMap<OrderId, Order>,
+    stored: &mut HashMap<OrderId, OrderFill>,
+    fills: &[OrderFill],
+) {
+    for fill in fills
+        .iter()
+        .filter(|fill| orders.contains_key(&fill.order_id))
+    {
+        stored.insert(fill.order_id, *fill);
+    }
+}
+
+impl MemoryStorage {
+    fn is_busted(&self, trade_id: TradeId) -> bool {
+        self.trade_adjustments
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|adjustment| {
+                adjustment.trade_id == trade_id && adjustment.kind == AdjustmentKind::Bust
+            })
+    }
+}
+
+#[async_trait]
+impl TradeRepo for MemoryStorage {
+    async fn save_trade(&self, trade: &Trade) -> Result<(), DatabaseError> {
+        self.trades.lock().unwrap().push(trade.clone());
+        Ok(())
+    }
+
+    async fn save_trades(
+        &self,
+        trades: &[Trade],
+        entries: &[JournalEntry],
+        fills: &[OrderFill],
+    ) -> Result<(), DatabaseError> {
+        // Orders before trades, like every other method taking both.
+        let orders = self.orders.lock().unwrap();
+        let mut order_fills = self.order_fills.lock().unwrap();
+        let mut stored = self.trades.lock().unwrap();
+        let mut ledger = self.ledger.lock().unwrap();
+        for trade in trades {
+            if !stored.iter().any(|stored| stored.id == trade.id) {
+                stored.push(trade.clone());
+            }
+        }
+        for entry in entries {
+            append_entry(&mut ledger, entry);
+        }
+        store_fills(&orders, &mut order_fills, fills);
+        Ok(())
+    }
+
+    async fn load_trade(&self, trade_id: TradeId) -> Result<Option<Trade>, DatabaseError> {
+        Ok(self
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|trade| trade.id == trade_id)
+            .cloned())
+    }
+
+    async fn get_trades_for_order(&self, order_id: OrderId) -> Result<Vec<Trade>, DatabaseError> {
+        Ok(self
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|trade| trade.maker_order_id == order_id || trade.taker_order_id == order_id)
+            .filter(|trade| !self.is_busted(trade.id))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_trades_for_trader(
+        &self,
+        trader_id: &TraderId,
+        filter: &TradeFilter,
+    ) -> Result<Vec<Trade>, DatabaseError> {
+        let orders = self.orders.lock().unwrap();
+        let owned = |id: &OrderId| {
+            orders
+                .get(id)
+                .is_some_and(|order| &order.trader_id == trader_id)
+        };
+        let mut trades: Vec<Trade> = self
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|trade| owned(&trade.maker_order_id) || owned(&trade.taker_order_id))
+            .filter(|trade| filter.matches(trade) && !self.is_busted(trade.id))
+            .cloned()
+            .collect();
+        trades.sort_by_key(|trade| trade.id);
+        trades.truncate(filter.page_size() as usize);
+        Ok(trades)
+    }
+
+    async fn get_trades(&self, filter: &TradeFilter) -> Result<Vec<Trade>, DatabaseError> {
+        let mut trades: Vec<Trade> = self
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|trade| filter.matches(trade) && !self.is_busted(trade.id))
+            .cloned()
+            .collect();
+        trades.sort_by_key(|trade| trade.id);
+        trades.truncate(filter.page_size() as usize);
+        Ok(trades)
+    }
+
+    async fn last_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        let hot = self
+            .trades
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|trade| trade.id)
+            .max();
+        let archived = self
+            .trades_archive
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|trade| trade.id)
+            .max();
+        Ok(hot.max(archived).unwrap_or(0))
+    }
+
+    async fn next_trade_ids(&self, count: usize) -> Result<Vec<TradeId>, DatabaseError> {
+        let last = self.last_trade_id().await?;
+        let mut issued = self.issued_ids.lock().unwrap();
+        let first = issued.1.max(last) + 1;
+        issued.1 = first + count as u64 - 1;
+        Ok((first..=issued.1).collect())
+    }
+
+    async fn adjust_trade(&self, adjustment: &TradeAdjustment) -> Result<bool, DatabaseError> {
+        if self.is_busted(adjustment.trade_id) {
+            return Ok(false);
+        }
+        let mut trades = self.trades.lock().unwrap();
+        let Some(trade) = trades
+            .iter_mut()
+            .find(|trade| trade.id == adjustment.trade_id)
+            .filter(|trade| trade.price == adjustment.previous_price)
+        else {
+            return Ok(false);
+        };
+        if let Some(price) = adjustment.new_price {
+            trade.price = price;
+        }
+        self.trade_adjustments
+            .lock()
+            .unwrap()
+            .push(adjustment.clone());
+        Ok(true)
+    }
+
+    async fn get_trade_adjustments(
+        &self,
+        trade_id: TradeId,
+    ) -> Result<Vec<TradeAdjustment>, DatabaseError> {
+        Ok(self
+            .trade_adjustments
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|adjustment| adjustment.trade_id == trade_id)
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for MemoryStorage {
+    async fn save_refresh_token(&self, token: &RefreshTokenRecord) -> Result<(), DatabaseError> {
+        self.refresh_tokens
+            .lock()
+            .unwrap()
+            .insert(token.token_hash.clone(), token.clone());
+        Ok(())
+    }
+
+    async fn revoke_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshTokenRecord>, DatabaseError> {
+        let mut tokens = self.refresh_tokens.lock().unwrap();
+        Ok(tokens.get_mut(token_hash).map(|token| {
+            let previous = token.clone();
+            token.revoked = true;
+            previous
+        }))
+    }
+
+    async fn revoke_refresh_family(&self, family_id: &str) -> Result<u64, DatabaseError> {
+        let mut revoked = 0;
+        for token in self.refresh_tokens.lock().unwrap().values_mut() {
+            if token.family_id == family_id && !token.revoked {
+                token.revoked = true;
+                revoked += 1;
+            }
+        }
+        Ok(revoked)
+    }
+
+    async fn list_refresh_sessions(
+        &self,
+        subject: &str,
+        now: u64,
+    ) -> Result<Vec<RefreshTokenRecord>, DatabaseError> {
+        let mut tokens: Vec<_> = self
+            .refresh_tokens
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|token| token.subject == subject && !token.revoked && token.expires_at > now)
+            .cloned()
+            .collect();
+        tokens
+            .sort_by(|a, b| (a.signed_in_at, &a.token_hash).cmp(&(b.signed_in_at, &b.token_hash)));
+        Ok(tokens)
+    }
+
+    async fn revoke_access_token(&self, token: &RevokedToken) -> Result<(), DatabaseError> {
+        self.revoked_tokens
+            .lock()
+            .unwrap()
+            .entry(token.jti.clone())
+            .or_insert(token.expires_at);
+        Ok(())
+    }
+
+    async fn load_revoked_tokens(&self, now: u64) -> Result<Vec<RevokedToken>, DatabaseError> {
+        Ok(self
+            .revoked_tokens
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, expires_at)| **expires_at > now)
+            .map(|(jti, expires_at)| RevokedToken {
+                jti: jti.clone(),
+                expires_at: *expires_at,
+            })
+            .collect())
+    }
+
+    async fn purge_expired_revocations(&self, now: u64) -> Result<u64, DatabaseError> {
+        let mut revoked = self.revoked_tokens.lock().unwrap();
+        let before = revoked.len();
+        revoked.retain(|_, expires_at| *expires_at > now);
+        Ok((before - revoked.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepo for MemoryStorage {
+    async fn save_api_key(&self, key: &ApiKeyRecord) -> Result<(), DatabaseError> {
+        self.api_keys
+            .lock()
+            .unwrap()
+            .insert(key.key_id.clone(), key.clone());
+        Ok(())
+    }
+
+    async fn load_api_key(&self, key_id: &str) -> Result<Option<ApiKeyRecord>, DatabaseError> {
+        Ok(self.api_keys.lock().unwrap().get(key_id).cloned())
+    }
+
+    async fn list_api_keys(&self, subject: &str) -> Result<Vec<ApiKeyRecord>, DatabaseError> {
+        let mut keys: Vec<_> = self
+            .api_keys
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|key| key.subject == subject)
+            .cloned()
+            .collect();
+        keys.sort_by(|a, b| (a.created_at, &a.key_id).cmp(&(b.created_at, &b.key_id)));
+        Ok(keys)
+    }
+
+    async fn revoke_api_key(&self, key_id: &str, subject: &str) -> Result<bool, DatabaseError> {
+        Ok(match self.api_keys.lock().unwrap().get_mut(key_id) {
+            Some(key) if key.subject == subject && !key.revoked => {
+                key.revoked = true;
+                true
+            }
+            _ => false,
+        })
+    }
+
+    async fn set_api_key_allowed_ips(
+        &self,
+        key_id: &str,
+        subject: &str,
+        allowed_ips: &[String],
+    ) -> Result<bool, DatabaseError> {
+        Ok(match self.api_keys.lock().unwrap().get_mut(key_id) {
+            Some(key) if key.subject == subject && !key.revoked => {
+                key.allowed_ips = allowed_ips.to_vec();
+                true
+            }
+            _ => false,
+        })
+    }
+}
+
+#[async_trait]
+impl ChallengeRepo for MemoryStorage {
+    async fn save_challenge(&self, challenge: &ChallengeRecord) -> Result<(), DatabaseError> {
+        self.challenges
+            .lock()
+            .unwrap()
+            .insert(challenge.subject.clone(), challenge.clone());
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        subject: &str,
+    ) -> Result<Option<ChallengeRecord>, DatabaseError> {
+        Ok(self.challenges.lock().unwrap().remove(subject))
+    }
+
+    async fn purge_expired_challenges(&self, now: u64) -> Result<u64, DatabaseError> {
+        let mut challenges = self.challenges.lock().unwrap();
+        let before = challenges.len();
+        challenges.retain(|_, challenge| challenge.expires_at > now);
+        Ok((before - challenges.len()) as u64)
+    }
+}
+
+#[async_trait]
+impl UsageRepo for MemoryStorage {
+    async fn add_usage(&self, rollups: &[UsageRollup]) -> Result<(), DatabaseError> {
+        // Rows are summed on read, like the upsert sums them on write.
+        self.usage.lock().unwrap().extend_from_slice(rollups);
+        Ok(())
+    }
+
+    async fn load_usage(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<UsageRollup>, DatabaseError> {
+        Ok(self
+            .usage
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|rollup| rollup.subject == subject && rollup.day >= since_day)
+            .cloned()
+            .collect())
+    }
+
+    async fn load_day_usage(&self, day: u64) -> Result<Vec<UsageRollup>, DatabaseError> {
+        Ok(self
+            .usage
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|rollup| rollup.day == day)
+            .cloned()
+            .collect())
+    }
+
+    async fn save_penalty(&self, penalty: &MessagingPenalty) -> Result<bool, DatabaseError> {
+        let mut penalties = self.penalties.lock().unwrap();
+        if penalties
+            .iter()
+            .any(|saved| saved.subject == penalty.subject && saved.day == penalty.day)
+        {
+            return Ok(false);
+        }
+        penalties.push(penalty.clone());
+        Ok(true)
+    }
+
+    async fn load_penalties(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+        Ok(self
+            .penalties
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|penalty| penalty.subject == subject && penalty.day >= since_day)
+            .cloned()
+            .collect())
+    }
+
+    async fn load_active_penalties(
+        &self,
+        now: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+        Ok(self
+            .penalties
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|penalty| penalty.expires_at > now)
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TotpRepo for MemoryStorage {
+    async fn save_totp(&self, record: &TotpRecord) -> Result<bool, DatabaseError> {
+        let mut totp = self.totp.lock().unwrap();
+        if totp
+            .get(&record.subject)
+            .is_some_and(|saved| saved.confirmed)
+        {
+            return Ok(false);
+        }
+        totp.insert(record.subject.clone(), record.clone());
+        Ok(true)
+    }
+
+    async fn load_totp(&self, subject: &str) -> Result<Option<TotpRecord>, DatabaseError> {
+        Ok(self.totp.lock().unwrap().get(subject).cloned())
+    }
+
+    async fn confirm_totp(&self, subject: &str, step: u64) -> Result<bool, DatabaseError> {
+        let mut totp = self.totp.lock().unwrap();
+        match totp.get_mut(subject) {
+            Some(record) if !record.confirmed => {
+                record.confirmed = true;
+                record.last_step = step;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn use_totp_step(&self, subject: &str, step: u64) -> Result<bool, DatabaseError> {
+        let mut totp = self.totp.lock().unwrap();
+        match totp.get_mut(subject) {
+            Some(record) if record.confirmed && record.last_step < step => {
+                record.last_step = step;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn delete_totp(&self, subject: &str) -> Result<bool, DatabaseError> {
+        Ok(self.totp.lock().unwrap().remove(subject).is_some())
+    }
+}
+
+#[async_trait]
+impl AuditRepo for MemoryStorage {
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<u64, DatabaseError> {
+        let mut audit = self.audit.lock().unwrap();
+        let id = audit.len() as u64 + 1;
+        audit.push(AuditEntry {
+            id,
+            ..entry.clone()
+        });
+        Ok(id)
+    }
+
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, DatabaseError> {
+        Ok(self
+            .audit
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .take(filter.page_size() as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl SettlementRepo for MemoryStorage {
+    async fn save_netting_set(&self, set: &NettingSet) -> Result<Option<u64>, DatabaseError> {
+        let mut sets = self.netting_sets.lock().unwrap();
+        if sets
+            .iter()
+            .any(|stored| stored.after_trade_id == set.after_trade_id)
+        {
+            return Ok(None);
+        }
+        let id = sets.len() as u64 + 1;
+        sets.push(NettingSet { id, ..set.clone() });
+        Ok(Some(id))
+    }
+
+    async fn load_netting_set(&self, id: u64) -> Result<Option<NettingSet>, DatabaseError> {
+        Ok(self
+            .netting_sets
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|set| set.id == id)
+            .cloned())
+    }
+
+    async fn last_netted_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        Ok(self
+            .netting_sets
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|set| set.last_trade_id)
+            .max()
+            .unwrap_or(0))
+    }
+}
+
+#[async_trait]
+impl SwapRepo for MemoryStorage {
+    async fn save_swap(&self, swap: &SwapRecord) -> Result<(), DatabaseError> {
+        self.swaps.lock().unwrap().push(swap.clone());
+        Ok(())
+    }
+
+    async fn get_swaps(&self, filter: &TradeFilter) -> Result<Vec<SwapRecord>, DatabaseError> {
+        let mut swaps: Vec<SwapRecord> = self
+            .swaps
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|swap| swap.matches(filter))
+            .cloned()
+            .collect();
+        swaps.sort_by_key(|swap| swap.id);
+        swaps.truncate(filter.page_size() as usize);
+        Ok(swaps)
+    }
+
+    async fn last_swap_id(&self) -> Result<u64, DatabaseError> {
+        Ok(self
+            .swaps
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|swap| swap.id)
+            .max()
+            .unwrap_or(0))
+    }
+}
+
+#[async_trait]
+impl CandleRepo for MemoryStorage {
+    async fn save_candles(&self, candles: &[CandleRecord]) -> Result<(), DatabaseError> {
+        let mut stored = self.candles.lock().unwrap();
+        for candle in candles {
+            let key = |c: &CandleRecord| (c.base_token.clone(), c.quote_token.clone(), c.open_time);
+            stored.retain(|c| key(c) != key(candle));
+            stored.push(candle.clone());
+        }
+        stored.sort_by_key(|c| c.open_time);
+        Ok(())
+    }
+
+    async fn delete_candle(&self, pair: &TradingPair, open_time: u64) -> Result<(), DatabaseError> {
+        self.candles.lock().unwrap().retain(|c| {
+            c.open_time != open_time
+                || c.base_token != *pair.base()
+                || c.quote_token != *pair.quote()
+        });
+        Ok(())
+    }
+
+    async fn get_candles(
+        &self,
+        pair: &TradingPair,
+        from: u64,
+        to: u64,
+        limit: u32,
+    ) -> Result<Vec<CandleRecord>, DatabaseError> {
+        Ok(self
+            .candles
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| c.base_token == *pair.base() && c.quote_token == *pair.quote())
+            .filter(|c| c.open_time >= from && c.open_time < to)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn candle_watermark(&self) -> Result<u64, DatabaseError> {
+        Ok(*self.candle_watermark.lock().unwrap())
+    }
+
+    async fn advance_candle_watermark(&self, through: u64) -> Result<(), DatabaseError> {
+        let mut watermark = self.candle_watermark.lock().unwrap();
+        *watermark = (*watermark).max(through);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl CounterRepo for MemoryStorage {
+    async fn save_counters(&self, counters: &[MarketCounters]) -> Result<(), DatabaseError> {
+        let mut stored = self.counters.lock().unwrap();
+        for counters in counters {
+            let existing = stored.iter_mut().find(|c| {
+                c.base_token == counters.base_token && c.quote_token == counters.quote_token
+            });
+            match existing {
+                Some(existing) => {
+                    existing.trade_count = existing.trade_count.max(counters.trade_count);
+                    existing.base_volume = existing.base_volume.max(counters.base_volume);
+                    existing.quote_volume = existing.quote_volume.max(counters.quote_volume);
+                    if existing.last_trade_time <= counters.last_trade_time {
+                        existing.last_price = counters.last_price.or(existing.last_price);
+                        existing.last_trade_time = counters.last_trade_time;
+                    }
+                }
+                None => stored.push(counters.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_counters(&self) -> Result<Vec<MarketCounters>, DatabaseError> {
+        Ok(self.counters.lock().unwrap().clone())
+    }
+}
+
+#[async_trait]
+impl TickMapRepo for MemoryStorage {
+    async fn save_tick_maps(&self, maps: &[TickMapBlob]) -> Result<(), DatabaseError> {
+        let mut stored = self.tick_maps.lock().unwrap();
+        for map in maps {
+            let existing = stored
+                .iter_mut()
+                .find(|m| m.base_token == map.base_token && m.quote_token == map.quote_token);
+            match existing {
+                Some(existing) if existing.sequence < map.sequence => *existing = map.clone(),
+                Some(_) => {}
+                None => stored.push(map.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_tick_maps(&self) -> Result<Vec<TickMapBlob>, DatabaseError> {
+        Ok(self.tick_maps.lock().unwrap().clone())
+    }
+}
+
+#[async_trait]
+impl PoolRepo for MemoryStorage {
+    async fn save_pools(&self, pools: &[PoolRecord]) -> Result<(), DatabaseError> {
+        let mut stored = self.pools.lock().unwrap();
+        for pool in pools {
+            let existing = stored
+                .iter_mut()
+                .find(|p| p.base_token == pool.base_token && p.quote_token == pool.quote_token);
+            match existing {
+                Some(existing) if existing.sequence < pool.sequence => *existing = pool.clone(),
+                Some(_) => {}
+                None => stored.push(pool.clone()),
+            }
+        }
+        Ok(())
+    }
+
+    async fn load_pools(&self) -> Result<Vec<PoolRecord>, DatabaseError> {
+        Ok(self.pools.lock().unwrap().clone())
+    }
+}
+
+#[async_trait]
+impl CustodyRepo for MemoryStorage {
+    async fn save_custody_balance(&self, balance: &CustodyBalance) -> Result<(), DatabaseError> {
+        let mut stored = self.custody.lock().unwrap();
+        let existing = stored
+            .iter_mut()
+            .find(|b| b.wallet_id == balance.wallet_id && b.token == balance.token);
+        match existing {
+            Some(existing) if existing.updated_at <= balance.updated_at => {
+                *existing = balance.clone()
+            }
+            Some(_) => {}
+            None => stored.push(balance.clone()),
+        }
+        Ok(())
+    }
+
+    async fn load_custody_balances(&self) -> Result<Vec<CustodyBalance>, DatabaseError> {
+        Ok(self.custody.lock().unwrap().clone())
+    }
+}
+
+#[async_trait]
+impl ChainRepo for MemoryStorage {
+    async fn save_block(&self, block: &ChainBlock) -> Result<(), DatabaseError> {
+        let mut chain = self.chain.lock().unwrap();
+        if !chain
+            .iter()
+            .any(|b| b.shard_id == block.shard_id && b.height == block.height)
+        {
+            chain.push(block.clone());
+        }
+        Ok(())
+    }
+
+    async fn load_blocks_at(&self, height: u64) -> Result<Vec<ChainBlock>, DatabaseError> {
+        let mut blocks: Vec<ChainBlock> = self
+            .chain
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|b| b.height == height)
+            .cloned()
+            .collect();
+        blocks.sort_by_key(|b| b.shard_id);
+        Ok(blocks)
+    }
+
+    async fn load_transaction(
+        &self,
+        hash: &str,
+    ) -> Result<Option<ChainTransaction>, DatabaseError> {
+        let chain = self.chain.lock().unwrap();
+        Ok(chain.iter().find_map(|block| {
+            block
+                .transactions
+                .iter()
+                .find(|t| t.hash == hash)
+                .map(|t| ChainTransaction {
+                    finalized: block.finalized,
+                    ..t.clone()
+                })
+        }))
+    }
+
+    async fn finalize_blocks(
+        &self,
+        shard_id: u64,
+        height: u64,
+    ) -> Result<Vec<ChainBlock>, DatabaseError> {
+        let mut chain = self.chain.lock().unwrap();
+        let mut finalized: Vec<ChainBlock> = chain
+            .iter_mut()
+            .filter(|b| b.shard_id == shard_id && b.height <= height && !b.finalized)
+            .map(|b| {
+                b.finalized = true;
+                for t in &mut b.transactions {
+                    t.finalized = true;
+                }
+                b.clone()
+            })
+            .collect();
+        finalized.sort_by_key(|b| b.height);
+        Ok(finalized)
+    }
+}
+
+#[async_trait]
+impl ReceiptRepo for MemoryStorage {
+    async fn save_receipt(&self, receipt: &SequencingReceipt) -> Result<(), DatabaseError> {
+        let mut receipts = self.receipts.lock().unwrap();
+        if !receipts.iter().any(|r| r.sequence == receipt.sequence) {
+            receipts.push(receipt.clone());
+        }
+        Ok(())
+    }
+
+    async fn load_receipt(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<SequencingReceipt>, DatabaseError> {
+        let receipts = self.receipts.lock().unwrap();
+        Ok(receipts.iter().find(|r| r.order_id == order_id).cloned())
+    }
+
+    async fn latest_receipt(&self) -> Result<Option<SequencingReceipt>, DatabaseError> {
+        let receipts = self.receipts.lock().unwrap();
+        Ok(receipts.iter().max_by_key(|r| r.sequence).cloned())
+    }
+}
+
+#[async_trait]
+impl OutboxRepo for MemoryStorage {
+    async fn enqueue_events(&self, events: &[OutboxEvent]) -> Result<(), DatabaseError> {
+        let mut outbox = self.outbox.lock().unwrap();
+        for event in events {
+            let id = outbox.len() as u64 + 1;
+            outbox.push((
+                OutboxEvent {
+                    id,
+                    ..event.clone()
+                },
+                false,
+            ));
+        }
+        Ok(())
+    }
+
+    async fn pending_events(&self, limit: u32) -> Result<Vec<OutboxEvent>, DatabaseError> {
+        let outbox = self.outbox.lock().unwrap();
+        Ok(outbox
+            .iter()
+            .filter(|(_, published)| !published)
+            .take(limit as usize)
+            .map(|(event, _)| event.clone())
+            .collect())
+    }
+
+    async fn mark_published(&self, ids: &[u64], _published_at: u64) -> Result<(), DatabaseError> {
+        let mut outbox = self.outbox.lock().unwrap();
+        for (event, published) in outbox.iter_mut() {
+            if ids.contains(&event.id) {
+                *published = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WebhookRepo for MemoryStorage {
+    async fn save_webhook(&self, webhook: &WebhookRecord) -> Result<(), DatabaseError> {
+        self.webhooks.lock().unwrap().push(webhook.clone());
+        Ok(())
+    }
+
+    async fn list_webhooks(&self, trader_id: &str) -> Result<Vec<WebhookRecord>, DatabaseError> {
+        let webhooks = self.webhooks.lock().unwrap();
+        Ok(webhooks
+            .iter()
+            .filter(|webhook| webhook.trader_id == trader_id && !webhook.revoked)
+            .cloned()
+            .collect())
+    }
+
+    async fn load_active_webhooks(&self) -> Result<Vec<WebhookRecord>, DatabaseError> {
+        let webhooks = self.webhooks.lock().unwrap();
+        Ok(webhooks
+            .iter()
+            .filter(|webhook| !webhook.revoked)
+            .cloned()
+            .collect())
+    }
+
+    async fn revoke_webhook(&self, id: &str, trader_id: &str) -> Result<bool, DatabaseError> {
+        let mut webhooks = self.webhooks.lock().unwrap();
+        let Some(webhook) = webhooks
+            .iter_mut()
+            .find(|webhook| webhook.id == id && webhook.trader_id == trader_id && !webhook.revoked)
+        else {
+            return Ok(false);
+        };
+        webhook.revoked = true;
+        for delivery in self.webhook_deliveries.lock().unwrap().iter_mut() {
+            if delivery.webhook_id == id && delivery.status == DeliveryStatus::Pending {
+                delivery.status = DeliveryStatus::Failed;
+                delivery.last_error = Some("webhook revoked".to_string());
+            }
+        }
+        Ok(true)
+    }
+
+    async fn enqueue_deliveries(
+        &self,
+        deliveries: &[WebhookDelivery],
+    ) -> Result<(), DatabaseError> {
+        let mut stored = self.webhook_deliveries.lock().unwrap();
+        for delivery in deliveries {
+            let id = stored.len() as u64 + 1;
+            stored.push(WebhookDelivery {
+                id,
+                ..delivery.clone()
+            });
+        }
+        Ok(())
+    }
+
+    async fn due_deliveries(
+        &self,
+        now: u64,
+        limit: u32,
+    ) -> Result<Vec<WebhookDelivery>, DatabaseError> {
+        let stored = self.webhook_deliveries.lock().unwrap();
+        Ok(stored
+            .iter()
+            .filter(|delivery| {
+                delivery.status == DeliveryStatus::Pending && delivery.next_attempt_at <= now
+            })
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DatabaseError> {
+        let mut stored = self.webhook_deliveries.lock().unwrap();
+        if let Some(slot) = stored.iter_mut().find(|stored| stored.id == delivery.id) {
+            *slot = delivery.clone();
+        }
+        Ok(())
+    }
+}
+
+/// Append `entry` to the journal unless one of the same kind and reference
+/// is already there; returns the new entry's id.
+fn append_entry(entries: &mut Vec<JournalEntry>, entry: &JournalEntry) -> Option<u64> {
+    if entries
+        .iter()
+        .any(|stored| stored.kind == entry.kind && stored.reference == entry.reference)
+    {
+        return None;
+    }
+    let id = entries.len() as u64 + 1;
+    entries.push(JournalEntry {
+        id,
+        ..entry.clone()
+    });
+    Some(id)
+}
+
+#[async_trait]
+impl LedgerRepo for MemoryStorage {
+    async fn save_entry(&self, entry: &JournalEntry) -> Result<Option<u64>, DatabaseError> {
+        Ok(append_entry(&mut self.ledger.lock().unwrap(), entry))
+    }
+
+    async fn query_ledger(
+        &self,
+        filter: &LedgerFilter,
+    ) -> Result<Vec<JournalEntry>, DatabaseError> {
+        Ok(self
+            .ledger
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .take(filter.page_size() as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn ledger_balances(&self) -> Result<Vec<AccountBalance>, DatabaseError> {
+        let mut ledger = Ledger::new();
+        for entry in self.ledger.lock().unwrap().iter() {
+            ledger
+                .post(entry)
+                .map_err(|_| DatabaseError::DataIntegrityError)?;
+        }
+        Ok(ledger.balances())
+    }
+
+    async fn account_balances(
+        &self,
+        account: &Account,
+    ) -> Result<Vec<AccountBalance>, DatabaseError> {
+        let mut balances = self.ledger_balances().await?;
+        balances.retain(|balance| &balance.account == account);
+        Ok(balances)
+    }
+}
+
+#[async_trait]
+impl FundingRepo for MemoryStorage {
+    async fn save_funding(&self, record: &FundingRecord) -> Result<Option<u64>, DatabaseError> {
+        let mut funding = self.funding.lock().unwrap();
+        if record.reference.is_some()
+            && funding.iter().any(|stored| {
+                stored.kind == record.kind
+                    && stored.chain == record.chain
+                    && stored.reference == record.reference
+            })
+        {
+            return Ok(None);
+        }
+        let id = funding.len() as u64 + 1;
+        funding.push(FundingRecord {
+            id,
+            ..record.clone()
+        });
+        Ok(Some(id))
+    }
+
+    async fn save_withdrawal(
+        &self,
+        record: &FundingRecord,
+        debit: &(dyn Fn(u64) -> JournalEntry + Sync),
+    ) -> Result<u64, DatabaseError> {
+        let mut funding = self.funding.lock().unwrap();
+        let id = funding.len() as u64 + 1;
+        append_entry(&mut self.ledger.lock().unwrap(), &debit(id));
+        funding.push(FundingRecord {
+            id,
+            ..record.clone()
+        });
+        Ok(id)
+    }
+
+    async fn get_funding(&self, id: u64) -> Result<Option<FundingRecord>, DatabaseError> {
+        Ok(self
+            .funding
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|record| record.id == id)
+            .cloned())
+    }
+
+    async fn settle_funding(
+        &self,
+        id: u64,
+        status: FundingStatus,
+        tx_hash: Option<&str>,
+        at: u64,
+        refund: Option<&JournalEntry>,
+    ) -> Result<bool, DatabaseError> {
+        let mut funding = self.funding.lock().unwrap();
+        let Some(record) = funding
+            .iter_mut()
+            .find(|record| record.id == id && record.status == FundingStatus::Pending)
+        else {
+            return Ok(false);
+        };
+        if let Some(refund) = refund {
+            append_entry(&mut self.ledger.lock().unwrap(), refund);
+        }
+        record.status = status;
+        if let Some(tx_hash) = tx_hash {
+            record.tx_hash = Some(tx_hash.to_string());
+        }
+        record.updated_at = at;
+        Ok(true)
+    }
+
+    async fn query_funding(
+        &self,
+        filter: &FundingFilter,
+    ) -> Result<Vec<FundingRecord>, DatabaseError> {
+        Ok(self
+            .funding
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| filter.matches(record))
+            .take(filter.page_size() as usize)
+            .cloned()
+            .collect())
+    }
+}
+
+#[async_trait]
+impl RetentionRepo for MemoryStorage {
+    async fn archive_trades(
+        &self,
+        before: u64,
+        up_to_trade_id: TradeId,
+        limit: u32,
+    ) -> Result<u64, DatabaseError> {
+        let mut trades = self.trades.lock().unwrap();
+        let mut moved: Vec<TradeId> = trades
+            .iter()
+            .filter(|trade| trade.timestamp < before && trade.id <= up_to_trade_id)
+            .map(|trade| trade.id)
+            .collect();
+        moved.sort_unstable();
+        moved.truncate(limit as usize);
+        let (archived, kept) = trades
+            .drain(..)
+            .partition(|trade| moved.binary_search(&trade.id).is_ok());
+        *trades = kept;
+        self.trades_archive
+            .lock()
+            .unwrap()
+            .extend::<Vec<Trade>>(archived);
+        Ok(moved.len() as u64)
+    }
+
+    async fn archive_orders(&self, before: u64, limit: u32) -> Result<u64, DatabaseError> {
+        let mut orders = self.orders.lock().unwrap();
+        let fills = self.order_fills.lock().unwrap();
+        let mut deleted = self.deleted_orders.lock().unwrap();
+        let trades = self.trades.lock().unwrap();
+        let mut moved: Vec<OrderId> = orders
+            .values()
+            .filter(|order| order.timestamp < before)
+            .filter(|order| {
+                deleted.contains(&order.id)
+                    || fills
+                        .get(&order.id)
+                        .is_some_and(|fill| !fill.status.is_open())
+            })
+            .filter(|order| {
+                !trades.iter().any(|trade| {
+                    trade.maker_order_id == order.id || trade.taker_order_id == order.id
+                })
+            })
+            .map(|order| order.id)
+            .collect();
+        moved.sort_unstable();
+        moved.truncate(limit as usize);
+        let mut archive = self.orders_archive.lock().unwrap();
+        for id in &moved {
+            if let Some(order) = orders.remove(id) {
+                deleted.remove(id);
+                archive.insert(*id, order);
+            }
+        }
+        Ok(moved.len() as u64)
+    }
+}
+
+impl Storage for MemoryStorage {}
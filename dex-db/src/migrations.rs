@@ -1,9 +1,16 @@
 //! Database migrations for the DEX-OS core engine
 //!
 //! This module provides functionality for database schema evolution.
+//!
+//! Applied versions are recorded in the `migrations` table with a checksum of
+//! their SQL. A migration is never edited once released: startup refuses to
+//! go on when an applied migration's SQL no longer matches its checksum.
+//! `pending_migrations` lists what would run without running it.
 
-use sqlx_core::{query::query, row::Row};
+use sha2::{Digest, Sha256};
+use sqlx_core::{query::query, raw_sql::raw_sql, row::Row};
 use sqlx_postgres::PgPool;
+use std::collections::BTreeMap;
 
 /// Represents a database migration
 pub struct Migration {
@@ -12,6 +19,32 @@ pub struct Migration {
     pub sql: &'static str,
 }
 
+impl Migration {
+    /// Hex SHA-256 of the migration's SQL, recorded when it is applied.
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error(
+        "migration {version} ({description}) changed after it was applied: \
+         recorded checksum {recorded}, now {current}"
+    )]
+    ChecksumDrift {
+        version: i32,
+        description: &'static str,
+        recorded: String,
+        current: String,
+    },
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx_core::error::Error),
+}
+
 /// Get all migrations
 pub fn get_migrations() -> Vec<Migration> {
     vec![
@@ -63,53 +96,770 @@ pub fn get_migrations() -> Vec<Migration> {
                 CREATE INDEX IF NOT EXISTS idx_trades_taker_order_id ON trades (taker_order_id)
             "#,
         },
+        Migration {
+            version: 5,
+            description: "Add indexes for paginated and pair/time filtered trade history",
+            sql: r#"
+                CREATE INDEX IF NOT EXISTS idx_trades_pair_timestamp ON trades (base_token, quote_token, timestamp);
+                CREATE INDEX IF NOT EXISTS idx_trades_timestamp ON trades (timestamp)
+            "#,
+        },
+        Migration {
+            version: 6,
+            description: "Create refresh_tokens table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS refresh_tokens (
+                    token_hash TEXT PRIMARY KEY,
+                    family_id TEXT NOT NULL,
+                    subject TEXT NOT NULL,
+                    audience TEXT,
+                    expires_at BIGINT NOT NULL,
+                    revoked BOOLEAN NOT NULL DEFAULT FALSE
+                );
+                CREATE INDEX IF NOT EXISTS idx_refresh_tokens_family_id ON refresh_tokens (family_id)
+            "#,
+        },
+        Migration {
+            version: 7,
+            description: "Add trade busts and the trade_adjustments history table",
+            sql: r#"
+                ALTER TABLE trades ADD COLUMN IF NOT EXISTS busted BOOLEAN NOT NULL DEFAULT FALSE;
+                CREATE TABLE IF NOT EXISTS trade_adjustments (
+                    id BIGSERIAL PRIMARY KEY,
+                    trade_id BIGINT NOT NULL,
+                    kind TEXT NOT NULL,
+                    previous_price BIGINT NOT NULL,
+                    new_price BIGINT,
+                    reason TEXT NOT NULL,
+                    adjusted_by TEXT NOT NULL,
+                    adjusted_at BIGINT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_trade_adjustments_trade_id ON trade_adjustments (trade_id)
+            "#,
+        },
+        Migration {
+            version: 8,
+            description: "Add token scopes to refresh_tokens",
+            sql: r#"
+                ALTER TABLE refresh_tokens ADD COLUMN IF NOT EXISTS scope TEXT NOT NULL DEFAULT 'read trade'
+            "#,
+        },
+        Migration {
+            version: 9,
+            description: "Create api_keys table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS api_keys (
+                    key_id TEXT PRIMARY KEY,
+                    secret TEXT NOT NULL,
+                    subject TEXT NOT NULL,
+                    scope TEXT NOT NULL,
+                    label TEXT,
+                    created_at BIGINT NOT NULL,
+                    revoked BOOLEAN NOT NULL DEFAULT FALSE
+                );
+                CREATE INDEX IF NOT EXISTS idx_api_keys_subject ON api_keys (subject)
+            "#,
+        },
+        Migration {
+            version: 10,
+            description: "Create column_migrations table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS column_migrations (
+                    name TEXT PRIMARY KEY,
+                    phase TEXT NOT NULL,
+                    cursor BIGINT NOT NULL DEFAULT 0,
+                    rows_done BIGINT NOT NULL DEFAULT 0,
+                    rows_total BIGINT NOT NULL DEFAULT 0,
+                    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                )
+            "#,
+        },
+        Migration {
+            version: 11,
+            description: "Create wallet_challenges table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS wallet_challenges (
+                    subject TEXT PRIMARY KEY,
+                    message TEXT NOT NULL,
+                    typed_data TEXT,
+                    expires_at BIGINT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_wallet_challenges_expires_at ON wallet_challenges (expires_at)
+            "#,
+        },
+        Migration {
+            version: 12,
+            description: "Create api_usage_daily table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS api_usage_daily (
+                    subject TEXT NOT NULL,
+                    day BIGINT NOT NULL,
+                    credential TEXT NOT NULL,
+                    requests BIGINT NOT NULL DEFAULT 0,
+                    errors BIGINT NOT NULL DEFAULT 0,
+                    throttled BIGINT NOT NULL DEFAULT 0,
+                    orders BIGINT NOT NULL DEFAULT 0,
+                    fills BIGINT NOT NULL DEFAULT 0,
+                    ws_messages BIGINT NOT NULL DEFAULT 0,
+                    ws_bytes BIGINT NOT NULL DEFAULT 0,
+                    PRIMARY KEY (subject, day, credential)
+                )
+            "#,
+        },
+        Migration {
+            version: 13,
+            description: "Create messaging_penalties table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS messaging_penalties (
+                    subject TEXT NOT NULL,
+                    day BIGINT NOT NULL,
+                    orders BIGINT NOT NULL,
+                    fills BIGINT NOT NULL,
+                    max_ratio DOUBLE PRECISION NOT NULL,
+                    fee BIGINT NOT NULL,
+                    rate_limit_percent INTEGER NOT NULL,
+                    expires_at BIGINT NOT NULL,
+                    PRIMARY KEY (subject, day)
+                );
+                CREATE INDEX IF NOT EXISTS idx_messaging_penalties_expires_at ON messaging_penalties (expires_at);
+                CREATE INDEX IF NOT EXISTS idx_api_usage_daily_day ON api_usage_daily (day)
+            "#,
+        },
+        Migration {
+            version: 14,
+            description: "Create totp_secrets table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS totp_secrets (
+                    subject TEXT PRIMARY KEY,
+                    sealed_secret TEXT NOT NULL,
+                    confirmed BOOLEAN NOT NULL DEFAULT FALSE,
+                    last_step BIGINT NOT NULL DEFAULT 0,
+                    created_at BIGINT NOT NULL
+                )
+            "#,
+        },
+        Migration {
+            version: 15,
+            description: "Create append-only audit_log table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS audit_log (
+                    id BIGSERIAL PRIMARY KEY,
+                    occurred_at BIGINT NOT NULL,
+                    subject TEXT NOT NULL,
+                    ip TEXT,
+                    action TEXT NOT NULL,
+                    target TEXT NOT NULL,
+                    outcome TEXT NOT NULL,
+                    status INTEGER
+                );
+                CREATE INDEX IF NOT EXISTS idx_audit_log_subject ON audit_log (subject, id);
+                CREATE INDEX IF NOT EXISTS idx_audit_log_occurred_at ON audit_log (occurred_at);
+                CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+                BEGIN
+                    RAISE EXCEPTION 'audit_log is append-only';
+                END;
+                $$ LANGUAGE plpgsql;
+                DROP TRIGGER IF EXISTS audit_log_append_only ON audit_log;
+                CREATE TRIGGER audit_log_append_only
+                    BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_log
+                    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only()
+            "#,
+        },
+        Migration {
+            version: 16,
+            description: "Add IP allowlists to api_keys",
+            sql: r#"
+                ALTER TABLE api_keys ADD COLUMN IF NOT EXISTS allowed_ips TEXT[] NOT NULL DEFAULT '{}'
+            "#,
+        },
+        Migration {
+            version: 17,
+            description: "Create settlement netting tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS netting_sets (
+                    id BIGSERIAL PRIMARY KEY,
+                    created_at BIGINT NOT NULL,
+                    created_by TEXT NOT NULL,
+                    after_trade_id BIGINT NOT NULL UNIQUE,
+                    last_trade_id BIGINT NOT NULL,
+                    trade_count BIGINT NOT NULL,
+                    gross_transfers BIGINT NOT NULL,
+                    unattributed_trade_ids BIGINT[] NOT NULL DEFAULT '{}'
+                );
+                CREATE TABLE IF NOT EXISTS netting_positions (
+                    netting_set_id BIGINT NOT NULL REFERENCES netting_sets (id),
+                    trader_id TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    delivered NUMERIC(39, 0) NOT NULL,
+                    received NUMERIC(39, 0) NOT NULL,
+                    PRIMARY KEY (netting_set_id, trader_id, token)
+                );
+                CREATE TABLE IF NOT EXISTS netting_transfers (
+                    netting_set_id BIGINT NOT NULL REFERENCES netting_sets (id),
+                    seq INTEGER NOT NULL,
+                    token TEXT NOT NULL,
+                    from_trader TEXT NOT NULL,
+                    to_trader TEXT NOT NULL,
+                    amount NUMERIC(39, 0) NOT NULL,
+                    PRIMARY KEY (netting_set_id, seq)
+                )
+            "#,
+        },
+        Migration {
+            version: 18,
+            description: "Create amm_swaps table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS amm_swaps (
+                    id BIGINT PRIMARY KEY,
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    taker_side TEXT NOT NULL,
+                    price BIGINT NOT NULL,
+                    quantity BIGINT NOT NULL,
+                    quote_quantity BIGINT NOT NULL,
+                    timestamp BIGINT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_amm_swaps_pair_timestamp
+                    ON amm_swaps (base_token, quote_token, timestamp)
+            "#,
+        },
+        Migration {
+            version: 19,
+            description: "Create market_counters table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS market_counters (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    trade_count BIGINT NOT NULL,
+                    base_volume NUMERIC(39, 0) NOT NULL,
+                    quote_volume NUMERIC(39, 0) NOT NULL,
+                    last_price BIGINT,
+                    last_trade_time BIGINT,
+                    PRIMARY KEY (base_token, quote_token)
+                )
+            "#,
+        },
+        Migration {
+            version: 20,
+            description: "Create ledger_entries and ledger_postings tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS ledger_entries (
+                    id BIGSERIAL PRIMARY KEY,
+                    kind TEXT NOT NULL,
+                    reference TEXT NOT NULL,
+                    timestamp BIGINT NOT NULL,
+                    UNIQUE (kind, reference)
+                );
+                CREATE TABLE IF NOT EXISTS ledger_postings (
+                    entry_id BIGINT NOT NULL REFERENCES ledger_entries (id),
+                    line BIGINT NOT NULL,
+                    account TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    direction TEXT NOT NULL,
+                    amount NUMERIC(39, 0) NOT NULL,
+                    PRIMARY KEY (entry_id, line)
+                );
+                CREATE INDEX IF NOT EXISTS idx_ledger_postings_account
+                    ON ledger_postings (account, entry_id)
+            "#,
+        },
+        Migration {
+            version: 21,
+            description: "Create amm_tick_maps table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS amm_tick_maps (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    sequence BIGINT NOT NULL,
+                    tick_count BIGINT NOT NULL,
+                    data BYTEA NOT NULL,
+                    PRIMARY KEY (base_token, quote_token)
+                )
+            "#,
+        },
+        Migration {
+            version: 22,
+            description: "Create custody_balances table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS custody_balances (
+                    wallet_id TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    balance NUMERIC(39, 0) NOT NULL,
+                    updated_at BIGINT NOT NULL,
+                    PRIMARY KEY (wallet_id, token)
+                )
+            "#,
+        },
+        Migration {
+            version: 23,
+            description: "Create chain_blocks and chain_transactions tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS chain_blocks (
+                    shard_id BIGINT NOT NULL,
+                    height BIGINT NOT NULL,
+                    hash TEXT NOT NULL,
+                    previous_hash TEXT NOT NULL,
+                    timestamp BIGINT NOT NULL,
+                    finalized BOOLEAN NOT NULL DEFAULT FALSE,
+                    PRIMARY KEY (shard_id, height)
+                );
+                CREATE INDEX IF NOT EXISTS idx_chain_blocks_height ON chain_blocks (height);
+                CREATE TABLE IF NOT EXISTS chain_transactions (
+                    hash TEXT PRIMARY KEY,
+                    shard_id BIGINT NOT NULL,
+                    height BIGINT NOT NULL,
+                    position INTEGER NOT NULL,
+                    sender TEXT NOT NULL,
+                    recipient TEXT NOT NULL,
+                    amount BIGINT NOT NULL,
+                    nonce BIGINT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_chain_transactions_block
+                    ON chain_transactions (shard_id, height, position)
+            "#,
+        },
+        Migration {
+            version: 24,
+            description: "Create sequencing_receipts table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS sequencing_receipts (
+                    sequence BIGINT PRIMARY KEY,
+                    order_id BIGINT NOT NULL UNIQUE,
+                    command_hash TEXT NOT NULL,
+                    previous_hash TEXT NOT NULL,
+                    timestamp BIGINT NOT NULL,
+                    region TEXT NOT NULL,
+                    signature TEXT NOT NULL
+                )
+            "#,
+        },
+        Migration {
+            version: 25,
+            description: "Create event_outbox table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS event_outbox (
+                    id BIGSERIAL PRIMARY KEY,
+                    topic TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    payload TEXT NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    published_at BIGINT
+                );
+                CREATE INDEX IF NOT EXISTS idx_event_outbox_pending
+                    ON event_outbox (id) WHERE published_at IS NULL
+            "#,
+        },
+        Migration {
+            version: 26,
+            description: "Create webhooks and webhook_deliveries tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS webhooks (
+                    id TEXT PRIMARY KEY,
+                    trader_id TEXT NOT NULL,
+                    url TEXT NOT NULL,
+                    secret TEXT NOT NULL,
+                    events TEXT[] NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    revoked BOOLEAN NOT NULL DEFAULT FALSE
+                );
+                CREATE INDEX IF NOT EXISTS idx_webhooks_trader ON webhooks (trader_id);
+                CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                    id BIGSERIAL PRIMARY KEY,
+                    webhook_id TEXT NOT NULL REFERENCES webhooks (id),
+                    payload TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    attempts INTEGER NOT NULL DEFAULT 0,
+                    next_attempt_at BIGINT NOT NULL,
+                    last_error TEXT
+                );
+                CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_due
+                    ON webhook_deliveries (next_attempt_at) WHERE status = 'pending'
+            "#,
+        },
+        Migration {
+            version: 27,
+            description: "Track order status, filled and remaining quantity",
+            sql: r#"
+                ALTER TABLE orders
+                    ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'open',
+                    ADD COLUMN IF NOT EXISTS filled_quantity BIGINT NOT NULL DEFAULT 0,
+                    ADD COLUMN IF NOT EXISTS remaining_quantity BIGINT;
+                WITH fills AS (
+                    SELECT order_id, SUM(quantity)::BIGINT AS filled
+                    FROM (
+                        SELECT maker_order_id AS order_id, quantity FROM trades WHERE NOT busted
+                        UNION ALL
+                        SELECT taker_order_id AS order_id, quantity FROM trades WHERE NOT busted
+                    ) matched
+                    GROUP BY order_id
+                ), filled AS (
+                    SELECT o.id, LEAST(COALESCE(f.filled, 0), o.quantity) AS filled
+                    FROM orders o
+                    LEFT JOIN fills f ON f.order_id = o.id
+                )
+                UPDATE orders o
+                SET filled_quantity = filled.filled,
+                    remaining_quantity = o.quantity - filled.filled,
+                    status = CASE
+                        WHEN filled.filled = o.quantity THEN 'filled'
+                        WHEN o.order_type = 'market' THEN 'cancelled'
+                        WHEN filled.filled > 0 THEN 'partially_filled'
+                        ELSE 'open'
+                    END
+                FROM filled
+                WHERE filled.id = o.id;
+                ALTER TABLE orders ALTER COLUMN remaining_quantity SET NOT NULL;
+                CREATE INDEX IF NOT EXISTS idx_orders_open
+                    ON orders (id) WHERE status IN ('open', 'partially_filled')
+            "#,
+        },
+        Migration {
+            version: 28,
+            description: "Create candles and candle_watermark tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS candles (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    open_time BIGINT NOT NULL,
+                    open BIGINT NOT NULL,
+                    high BIGINT NOT NULL,
+                    low BIGINT NOT NULL,
+                    close BIGINT NOT NULL,
+                    volume BIGINT NOT NULL,
+                    trades BIGINT NOT NULL,
+                    PRIMARY KEY (base_token, quote_token, open_time)
+                );
+                CREATE TABLE IF NOT EXISTS candle_watermark (
+                    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+                    through BIGINT NOT NULL
+                )
+            "#,
+        },
+        Migration {
+            version: 29,
+            description: "Create ledger_balances table from the posted entries",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS ledger_balances (
+                    account TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    balance NUMERIC(40, 0) NOT NULL,
+                    PRIMARY KEY (account, token)
+                );
+                INSERT INTO ledger_balances (account, token, balance)
+                SELECT account, token,
+                    SUM(CASE direction WHEN 'debit' THEN amount ELSE -amount END)
+                FROM ledger_postings
+                GROUP BY account, token
+                ON CONFLICT (account, token) DO NOTHING
+            "#,
+        },
+        Migration {
+            version: 30,
+            description: "Create funding_transfers table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS funding_transfers (
+                    id BIGSERIAL PRIMARY KEY,
+                    trader_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    chain TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    amount NUMERIC(39, 0) NOT NULL,
+                    address TEXT,
+                    reference TEXT,
+                    tx_hash TEXT,
+                    status TEXT NOT NULL,
+                    created_at BIGINT NOT NULL,
+                    updated_at BIGINT NOT NULL,
+                    UNIQUE (kind, chain, reference)
+                );
+                CREATE INDEX IF NOT EXISTS idx_funding_transfers_trader
+                    ON funding_transfers (trader_id, id);
+                CREATE INDEX IF NOT EXISTS idx_funding_transfers_pending
+                    ON funding_transfers (id) WHERE status = 'pending'
+            "#,
+        },
+        Migration {
+            version: 31,
+            description: "Notify the trades channel of inserted trades",
+            sql: r#"
+                CREATE OR REPLACE FUNCTION notify_trade() RETURNS trigger AS $$
+                BEGIN
+                    PERFORM pg_notify('trades', json_build_object(
+                        'id', NEW.id,
+                        'maker_order_id', NEW.maker_order_id,
+                        'taker_order_id', NEW.taker_order_id,
+                        'base_token', NEW.base_token,
+                        'quote_token', NEW.quote_token,
+                        'price', NEW.price,
+                        'quantity', NEW.quantity,
+                        'timestamp', NEW.timestamp
+                    )::TEXT);
+                    RETURN NULL;
+                END;
+                $$ LANGUAGE plpgsql;
+                DROP TRIGGER IF EXISTS notify_trade ON trades;
+                CREATE TRIGGER notify_trade
+                    AFTER INSERT ON trades
+                    FOR EACH ROW EXECUTE FUNCTION notify_trade()
+            "#,
+        },
+        Migration {
+            version: 32,
+            description: "Create order and trade ID sequences",
+            sql: r#"
+                CREATE SEQUENCE IF NOT EXISTS order_ids AS BIGINT;
+                SELECT setval('order_ids', GREATEST(
+                    (SELECT MAX(id) FROM orders),
+                    (SELECT MAX(GREATEST(maker_order_id, taker_order_id)) FROM trades),
+                    (SELECT MAX(order_id) FROM sequencing_receipts),
+                    0
+                ) + 1, false);
+                CREATE SEQUENCE IF NOT EXISTS trade_ids AS BIGINT;
+                SELECT setval('trade_ids', COALESCE((SELECT MAX(id) FROM trades), 0) + 1, false)
+            "#,
+        },
+        Migration {
+            version: 33,
+            description: "Create trades_archive and orders_archive tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS trades_archive (
+                    LIKE trades INCLUDING DEFAULTS,
+                    PRIMARY KEY (id)
+                );
+                CREATE TABLE IF NOT EXISTS orders_archive (
+                    LIKE orders INCLUDING DEFAULTS,
+                    PRIMARY KEY (id)
+                );
+                CREATE INDEX IF NOT EXISTS idx_orders_terminal_timestamp
+                    ON orders (timestamp) WHERE status NOT IN ('open', 'partially_filled')
+            "#,
+        },
+        Migration {
+            version: 34,
+            description: "Add audit columns to orders and trades and soft-delete orders",
+            sql: r#"
+                ALTER TABLE orders
+                    ADD COLUMN IF NOT EXISTS created_at BIGINT,
+                    ADD COLUMN IF NOT EXISTS updated_at BIGINT,
+                    ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
+                ALTER TABLE orders_archive
+                    ADD COLUMN IF NOT EXISTS created_at BIGINT,
+                    ADD COLUMN IF NOT EXISTS updated_at BIGINT,
+                    ADD COLUMN IF NOT EXISTS deleted_at BIGINT;
+                UPDATE orders SET created_at = timestamp, updated_at = timestamp;
+                UPDATE orders_archive SET created_at = timestamp, updated_at = timestamp;
+                ALTER TABLE orders
+                    ALTER COLUMN created_at SET DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT,
+                    ALTER COLUMN created_at SET NOT NULL,
+                    ALTER COLUMN updated_at SET DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT,
+                    ALTER COLUMN updated_at SET NOT NULL;
+                ALTER TABLE trades
+                    ADD COLUMN IF NOT EXISTS created_at BIGINT,
+                    ADD COLUMN IF NOT EXISTS updated_at BIGINT;
+                ALTER TABLE trades_archive
+                    ADD COLUMN IF NOT EXISTS created_at BIGINT,
+                    ADD COLUMN IF NOT EXISTS updated_at BIGINT;
+                UPDATE trades SET created_at = timestamp, updated_at = timestamp;
+                UPDATE trades_archive SET created_at = timestamp, updated_at = timestamp;
+                ALTER TABLE trades
+                    ALTER COLUMN created_at SET DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT,
+                    ALTER COLUMN created_at SET NOT NULL,
+                    ALTER COLUMN updated_at SET DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT,
+                    ALTER COLUMN updated_at SET NOT NULL
+            "#,
+        },
+        Migration {
+            version: 35,
+            description: "Create pools and pool_positions tables",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS pools (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    sequence BIGINT NOT NULL,
+                    fee_bps INTEGER NOT NULL,
+                    reserve_base BIGINT NOT NULL,
+                    reserve_quote BIGINT NOT NULL,
+                    lp_supply BIGINT NOT NULL,
+                    fee_growth_base NUMERIC(39, 0) NOT NULL,
+                    fee_growth_quote NUMERIC(39, 0) NOT NULL,
+                    updated_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT,
+                    PRIMARY KEY (base_token, quote_token)
+                );
+                CREATE TABLE IF NOT EXISTS pool_positions (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    trader_id TEXT NOT NULL,
+                    lp_tokens BIGINT NOT NULL,
+                    deposited_base NUMERIC(39, 0) NOT NULL,
+                    deposited_quote NUMERIC(39, 0) NOT NULL,
+                    fee_checkpoint_base NUMERIC(39, 0) NOT NULL,
+                    fee_checkpoint_quote NUMERIC(39, 0) NOT NULL,
+                    fees_base NUMERIC(39, 0) NOT NULL,
+                    fees_quote NUMERIC(39, 0) NOT NULL,
+                    fees_withdrawn_base NUMERIC(39, 0) NOT NULL,
+                    fees_withdrawn_quote NUMERIC(39, 0) NOT NULL,
+                    PRIMARY KEY (base_token, quote_token, trader_id),
+                    FOREIGN KEY (base_token, quote_token)
+                        REFERENCES pools (base_token, quote_token) ON DELETE CASCADE
+                );
+                CREATE INDEX IF NOT EXISTS idx_pool_positions_trader_id
+                    ON pool_positions (trader_id)
+            "#,
+        },
+        Migration {
+            version: 36,
+            description: "Create revoked_tokens table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS revoked_tokens (
+                    jti TEXT PRIMARY KEY,
+                    expires_at BIGINT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS idx_revoked_tokens_expires_at
+                    ON revoked_tokens (expires_at)
+            "#,
+        },
+        Migration {
+            version: 37,
+            description: "Add sign-in times and access token IDs to refresh_tokens",
+            sql: r#"
+                ALTER TABLE refresh_tokens
+                    ADD COLUMN IF NOT EXISTS signed_in_at BIGINT NOT NULL DEFAULT 0,
+                    ADD COLUMN IF NOT EXISTS issued_at BIGINT NOT NULL DEFAULT 0,
+                    ADD COLUMN IF NOT EXISTS access_jti TEXT,
+                    ADD COLUMN IF NOT EXISTS access_expires_at BIGINT NOT NULL DEFAULT 0;
+                CREATE INDEX IF NOT EXISTS idx_refresh_tokens_subject
+                    ON refresh_tokens (subject) WHERE NOT revoked
+            "#,
+        },
+        Migration {
+            version: 38,
+            description: "Seal API key secrets",
+            // Secrets stored before this were plaintext and cannot be sealed
+            // without the key, so those keys are revoked and must be reissued.
+            sql: r#"
+                ALTER TABLE api_keys RENAME COLUMN secret TO sealed_secret;
+                UPDATE api_keys SET revoked = TRUE
+            "#,
+        },
     ]
 }
 
-/// Run all pending migrations
-pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx_core::error::Error> {
-    // Create migrations table if it doesn't exist
-    query(
+/// The migrations table, created on first run. `checksum` was added after
+/// the table itself, so rows recorded before it may lack one.
+async fn ensure_migrations_table(pool: &PgPool) -> Result<(), sqlx_core::error::Error> {
+    raw_sql(
         r#"
         CREATE TABLE IF NOT EXISTS migrations (
             version INTEGER PRIMARY KEY,
             description TEXT NOT NULL,
             applied_at TIMESTAMP NOT NULL DEFAULT NOW()
-        )
+        );
+        ALTER TABLE migrations ADD COLUMN IF NOT EXISTS checksum TEXT
         "#,
     )
     .execute(pool)
     .await?;
+    Ok(())
+}
 
-    // Get the current schema version
-    let current_version: i32 = match query("SELECT MAX(version) FROM migrations")
-        .fetch_optional(pool)
+/// Applied versions with the checksums recorded for them. Reads without
+/// writing, so it works on a database whose table predates checksums or that
+/// has none yet.
+async fn applied_migrations(
+    pool: &PgPool,
+) -> Result<BTreeMap<i32, Option<String>>, sqlx_core::error::Error> {
+    let exists: bool = query("SELECT to_regclass('migrations') IS NOT NULL AS present")
+        .fetch_one(pool)
         .await?
-    {
-        Some(row) => row.get::<Option<i32>, _>(0).unwrap_or(0),
-        None => 0,
-    };
-
-    // Get all migrations
-    let migrations = get_migrations();
+        .get("present");
+    if !exists {
+        return Ok(BTreeMap::new());
+    }
+    let rows = query("SELECT version, to_jsonb(m) ->> 'checksum' AS checksum FROM migrations m")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("version"), row.get("checksum")))
+        .collect())
+}
 
-    // Run pending migrations
+/// Fail on the first applied migration whose SQL no longer matches the
+/// checksum recorded when it ran. Versions this build does not know, e.g.
+/// applied by a newer release, are left alone.
+pub(crate) fn check_drift(
+    migrations: &[Migration],
+    applied: &BTreeMap<i32, Option<String>>,
+) -> Result<(), MigrationError> {
     for migration in migrations {
-        if migration.version > current_version {
-            println!(
-                "Running migration {}: {}",
-                migration.version, migration.description
-            );
+        if let Some(Some(recorded)) = applied.get(&migration.version) {
+            let current = migration.checksum();
+            if *recorded != current {
+                return Err(MigrationError::ChecksumDrift {
+                    version: migration.version,
+                    description: migration.description,
+                    recorded: recorded.clone(),
+                    current,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Migrations not yet applied, in order, after checking the applied ones for
+/// drift. Changes nothing, so it serves as a dry run.
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<Migration>, MigrationError> {
+    let applied = applied_migrations(pool).await?;
+    let migrations = get_migrations();
+    check_drift(&migrations, &applied)?;
+    Ok(migrations
+        .into_iter()
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .collect())
+}
 
-            // Run the migration SQL
-            query(migration.sql).execute(pool).await?;
+/// Run all pending migrations, refusing to when an applied one has changed.
+///
+/// Each migration runs in a transaction with the row recording it, so a
+/// failed migration leaves neither its changes nor its record behind.
+/// Migrations recorded before checksums were kept get theirs filled in.
+pub async fn run_migrations(pool: &PgPool) -> Result<(), MigrationError> {
+    ensure_migrations_table(pool).await?;
+    let applied = applied_migrations(pool).await?;
+    let migrations = get_migrations();
+    check_drift(&migrations, &applied)?;
 
-            // Record the migration
-            query("INSERT INTO migrations (version, description) VALUES ($1, $2)")
+    for migration in migrations {
+        match applied.get(&migration.version) {
+            Some(Some(_)) => {}
+            Some(None) => {
+                query("UPDATE migrations SET checksum = $2 WHERE version = $1")
+                    .bind(migration.version)
+                    .bind(migration.checksum())
+                    .execute(pool)
+                    .await?;
+            }
+            None => {
+                tracing::info!(
+                    version = migration.version,
+                    description = migration.description,
+                    "running migration"
+                );
+                let mut tx = pool.begin().await?;
+                // Migrations may contain several statements, which the
+                // prepared-statement protocol used by `query` rejects.
+                raw_sql(migration.sql).execute(&mut *tx).await?;
+                query(
+                    "INSERT INTO migrations (version, description, checksum) VALUES ($1, $2, $3)",
+                )
                 .bind(migration.version)
                 .bind(migration.description)
-                .execute(pool)
+                .bind(migration.checksum())
+                .execute(&mut *tx)
                 .await?;
+                tx.commit().await?;
+            }
         }
     }
 
@@ -126,4 +876,26 @@ mod tests {
         assert!(!migrations.is_empty());
         assert_eq!(migrations[0].version, 1);
     }
+
+    #[test]
+    fn drift_is_reported_and_unrecorded_checksums_pass() {
+        let migrations = get_migrations();
+        let mut applied: BTreeMap<i32, Option<String>> = BTreeMap::new();
+        applied.insert(1, Some(migrations[0].checksum()));
+        applied.insert(2, None);
+        applied.insert(i32::MAX, Some("from a newer release".into()));
+        assert!(check_drift(&migrations, &applied).is_ok());
+
+        applied.insert(3, Some("0".repeat(64)));
+        match check_drift(&migrations, &applied) {
+            Err(MigrationError::ChecksumDrift { version, .. }) => assert_eq!(version, 3),
+            other => panic!("expected drift, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn versions_are_unique_and_ascending() {
+        let versions: Vec<i32> = get_migrations().iter().map(|m| m.version).collect();
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
 }
@@ -0,0 +1,433 @@
+//! Zero-downtime column migrations.
+//!
+//! Changing a column's type in place rewrites and locks the table, and breaks
+//! servers still running against the old column. A [`ColumnMigration`] moves
+//! the data into a new column instead, in phases that are each safe to run
+//! while servers keep serving:
+//!
+//! 1. [`DatabaseManager::start_column_migration`] adds the new column and a
+//!    trigger that dual-writes it from the old column on every insert and
+//!    update, so new rows never need a backfill.
+//! 2. [`DatabaseManager::backfill_column_batch`], repeated until the phase is
+//!    [`Phase::Backfilled`], converts historical rows in key order. Progress
+//!    is kept in `column_migrations`, so an interrupted backfill resumes
+//!    where it stopped.
+//! 3. [`DatabaseManager::verify_column_migration`] counts rows whose new
+//!    column disagrees with the old one.
+//! 4. [`DatabaseManager::switch_column_reads`] records that reads may move to
+//!    the new column, and only once verification found no mismatch. Readers
+//!    check [`DatabaseManager::column_reads_switched`].
+//!
+//! Dropping the trigger and the old column is left to a regular migration
+//! once every server reads the new column.
+
+use crate::{DatabaseError, DatabaseManager};
+use sqlx_core::{query::query, raw_sql::raw_sql, row::Row};
+use std::{fmt, str::FromStr, time::Duration};
+use thiserror::Error;
+
+/// Moves `table.old_column` into `table.new_column`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnMigration {
+    /// Unique name progress is tracked under.
+    pub name: &'static str,
+    pub table: &'static str,
+    /// Unique BIGINT column the backfill pages by.
+    pub key: &'static str,
+    pub old_column: &'static str,
+    pub new_column: &'static str,
+    /// SQL type of the new column, e.g. `NUMERIC(38, 18)`.
+    pub new_type: &'static str,
+    /// SQL expression for the new value, with `{old}` standing for the old
+    /// column, e.g. `{old}::NUMERIC / 1000000`.
+    pub convert: &'static str,
+}
+
+/// How far a column migration has got.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// New writes fill both columns; historical rows are being backfilled.
+    DualWrite,
+    /// Every row has been converted.
+    Backfilled,
+    /// No row's columns disagree.
+    Verified,
+    /// Reads use the new column.
+    ReadsSwitched,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::DualWrite => "dual_write",
+            Phase::Backfilled => "backfilled",
+            Phase::Verified => "verified",
+            Phase::ReadsSwitched => "reads_switched",
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Phase {
+    type Err = DatabaseError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        match raw {
+            "dual_write" => Ok(Phase::DualWrite),
+            "backfilled" => Ok(Phase::Backfilled),
+            "verified" => Ok(Phase::Verified),
+            "reads_switched" => Ok(Phase::ReadsSwitched),
+            _ => Err(DatabaseError::DataIntegrityError),
+        }
+    }
+}
+
+/// Recorded state of a column migration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub phase: Phase,
+    /// Highest key backfilled so far.
+    pub cursor: i64,
+    pub rows_done: u64,
+    /// Rows in the table when the migration started.
+    pub rows_total: u64,
+}
+
+impl Progress {
+    /// Share of the rows present at the start that have been backfilled.
+    pub fn fraction_done(&self) -> f64 {
+        if self.rows_total == 0 {
+            1.0
+        } else {
+            (self.rows_done as f64 / self.rows_total as f64).min(1.0)
+        }
+    }
+}
+
+/// Why a column migration step was refused.
+#[derive(Debug, Error)]
+pub enum ColumnMigrationError {
+    #[error("column migration {0} has not been started")]
+    NotStarted(&'static str),
+    #[error("column migration {name} is {phase}, not yet {required}")]
+    NotReady {
+        name: &'static str,
+        phase: Phase,
+        required: Phase,
+    },
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+}
+
+impl From<sqlx_core::error::Error> for ColumnMigrationError {
+    fn from(err: sqlx_core::error::Error) -> Self {
+        ColumnMigrationError::Database(err.into())
+    }
+}
+
+impl ColumnMigration {
+    fn function_name(&self) -> String {
+        format!("dual_write_{}", self.name)
+    }
+
+    fn converted(&self, old: &str) -> String {
+        self.convert.replace("{old}", old)
+    }
+
+    /// Add the new column and the dual-write trigger.
+    fn start_sql(&self) -> String {
+        let function = self.function_name();
+        format!(
+            r#"
+            ALTER TABLE {table} ADD COLUMN IF NOT EXISTS {new} {new_type};
+            CREATE OR REPLACE FUNCTION {function}() RETURNS trigger AS $$
+            BEGIN
+                NEW.{new} := {converted};
+                RETURN NEW;
+            END
+            $$ LANGUAGE plpgsql;
+            DROP TRIGGER IF EXISTS {function} ON {table};
+            CREATE TRIGGER {function} BEFORE INSERT OR UPDATE ON {table}
+                FOR EACH ROW EXECUTE FUNCTION {function}()
+            "#,
+            table = self.table,
+            new = self.new_column,
+            new_type = self.new_type,
+            converted = self.converted(&format!("NEW.{}", self.old_column)),
+            function = function,
+        )
+    }
+
+    /// Convert the next `$2` rows after key `$1`, returning the keys done.
+    fn backfill_sql(&self) -> String {
+        format!(
+            r#"
+            WITH batch AS (
+                SELECT {key} FROM {table} WHERE {key} > $1 ORDER BY {key} LIMIT $2
+            )
+            UPDATE {table} t SET {new} = {converted}
+            FROM batch
+            WHERE t.{key} = batch.{key}
+            RETURNING t.{key}
+            "#,
+            key = self.key,
+            table = self.table,
+            new = self.new_column,
+            converted = self.converted(&format!("t.{}", self.old_column)),
+        )
+    }
+
+    /// Count rows whose columns disagree.
+    fn mismatch_sql(&self) -> String {
+        format!(
+            "SELECT COUNT(*) AS mismatched FROM {table} WHERE {new} IS DISTINCT FROM ({converted})",
+            table = self.table,
+            new = self.new_column,
+            converted = self.converted(self.old_column),
+        )
+    }
+}
+
+impl DatabaseManager {
+    /// Recorded progress of the named migration, if it was started.
+    pub async fn column_migration_progress(
+        &self,
+        name: &str,
+    ) -> Result<Option<Progress>, DatabaseError> {
+        let row = query(
+            "SELECT phase, cursor, rows_done, rows_total FROM column_migrations WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| {
+            Ok(Progress {
+                phase: row.get::<&str, _>("phase").parse()?,
+                cursor: row.get("cursor"),
+                rows_done: row.get::<i64, _>("rows_done") as u64,
+                rows_total: row.get::<i64, _>("rows_total") as u64,
+            })
+        })
+        .transpose()
+    }
+
+    /// Whether readers should use the migration's new column.
+    pub async fn column_reads_switched(&self, name: &str) -> Result<bool, DatabaseError> {
+        Ok(self
+            .column_migration_progress(name)
+            .await?
+            .is_some_and(|progress| progress.phase == Phase::ReadsSwitched))
+    }
+
+    /// Add the new column and start dual-writing it. Starting again is a
+    /// no-op apart from re-creating the trigger.
+    pub async fn start_column_migration(
+        &self,
+        migration: &ColumnMigration,
+    ) -> Result<Progress, ColumnMigrationError> {
+        raw_sql(&migration.start_sql()).execute(&self.pool).await?;
+        let total: i64 = query(&format!(
+            "SELECT COUNT(*) AS total FROM {}",
+            migration.table
+        ))
+        .fetch_one(&self.pool)
+        .await?
+        .get("total");
+        query(
+            r#"
+            INSERT INTO column_migrations (name, phase, cursor, rows_done, rows_total)
+            VALUES ($1, $2, 0, 0, $3)
+            ON CONFLICT (name) DO NOTHING
+            "#,
+        )
+        .bind(migration.name)
+        .bind(Phase::DualWrite.as_str())
+        .bind(total)
+        .execute(&self.pool)
+        .await?;
+        self.require_progress(migration).await
+    }
+
+    /// Backfill up to `batch_size` more rows and record the new position.
+    pub async fn backfill_column_batch(
+        &self,
+        migration: &ColumnMigration,
+        batch_size: u32,
+    ) -> Result<Progress, ColumnMigrationError> {
+        let mut progress = self.require_progress(migration).await?;
+        if progress.phase != Phase::DualWrite {
+            return Ok(progress);
+        }
+        let keys: Vec<i64> = query(&migration.backfill_sql())
+            .bind(progress.cursor)
+            .bind(i64::from(batch_size))
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| row.get(migration.key))
+            .collect();
+        if let Some(last) = keys.iter().max() {
+            progress.cursor = *last;
+        }
+        progress.rows_done += keys.len() as u64;
+        if keys.len() < batch_size as usize {
+            progress.phase = Phase::Backfilled;
+        }
+        query(
+            r#"
+            UPDATE column_migrations
+            SET phase = $2, cursor = $3, rows_done = $4, updated_at = NOW()
+            WHERE name = $1
+            "#,
+        )
+        .bind(migration.name)
+        .bind(progress.phase.as_str())
+        .bind(progress.cursor)
+        .bind(progress.rows_done as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(progress)
+    }
+
+    /// Backfill every remaining row, pausing between batches to leave room
+    /// for live traffic, and report progress after each batch.
+    pub async fn backfill_column(
+        &self,
+        migration: &ColumnMigration,
+        batch_size: u32,
+        pause: Duration,
+        mut on_progress: impl FnMut(&Progress),
+    ) -> Result<Progress, ColumnMigrationError> {
+        loop {
+            let progress = self.backfill_column_batch(migration, batch_size).await?;
+            on_progress(&progress);
+            if progress.phase != Phase::DualWrite {
+                return Ok(progress);
+            }
+            tokio::time::sleep(pause).await;
+        }
+    }
+
+    /// Count rows whose new column disagrees with the old one, marking a
+    /// fully backfilled migration verified when there are none.
+    pub async fn verify_column_migration(
+        &self,
+        migration: &ColumnMigration,
+    ) -> Result<u64, ColumnMigrationError> {
+        let progress = self.require_phase(migration, Phase::Backfilled).await?;
+        let mismatched: i64 = query(&migration.mismatch_sql())
+            .fetch_one(&self.pool)
+            .await?
+            .get("mismatched");
+        if mismatched == 0 && progress.phase == Phase::Backfilled {
+            self.set_phase(migration, Phase::Verified).await?;
+        }
+        Ok(mismatched as u64)
+    }
+
+    /// Move reads to the new column; the migration must be verified.
+    pub async fn switch_column_reads(
+        &self,
+        migration: &ColumnMigration,
+    ) -> Result<Progress, ColumnMigrationError> {
+        let mut progress = self.require_phase(migration, Phase::Verified).await?;
+        self.set_phase(migration, Phase::ReadsSwitched).await?;
+        progress.phase = Phase::ReadsSwitched;
+        Ok(progress)
+    }
+
+    async fn require_progress(
+        &self,
+        migration: &ColumnMigration,
+    ) -> Result<Progress, ColumnMigrationError> {
+        self.column_migration_progress(migration.name)
+            .await?
+            .ok_or(ColumnMigrationError::NotStarted(migration.name))
+    }
+
+    async fn require_phase(
+        &self,
+        migration: &ColumnMigration,
+        required: Phase,
+    ) -> Result<Progress, ColumnMigrationError> {
+        let progress = self.require_progress(migration).await?;
+        if progress.phase < required {
+            return Err(ColumnMigrationError::NotReady {
+                name: migration.name,
+                phase: progress.phase,
+                required,
+            });
+        }
+        Ok(progress)
+    }
+
+    async fn set_phase(
+        &self,
+        migration: &ColumnMigration,
+        phase: Phase,
+    ) -> Result<(), DatabaseError> {
+        query("UPDATE column_migrations SET phase = $2, updated_at = NOW() WHERE name = $1")
+            .bind(migration.name)
+            .bind(phase.as_str())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICE: ColumnMigration = ColumnMigration {
+        name: "orders_price_numeric",
+        table: "orders",
+        key: "id",
+        old_column: "price",
+        new_column: "price_numeric",
+        new_type: "NUMERIC(38, 18)",
+        convert: "{old}::NUMERIC",
+    };
+
+    #[test]
+    fn statements_refer_to_the_old_column_in_context() {
+        let start = PRICE.start_sql();
+        assert!(start.contains("ADD COLUMN IF NOT EXISTS price_numeric NUMERIC(38, 18)"));
+        assert!(start.contains("NEW.price_numeric := NEW.price::NUMERIC;"));
+        assert!(start.contains("CREATE TRIGGER dual_write_orders_price_numeric"));
+
+        let backfill = PRICE.backfill_sql();
+        assert!(backfill.contains("SET price_numeric = t.price::NUMERIC"));
+        assert!(backfill.contains("WHERE id > $1 ORDER BY id LIMIT $2"));
+
+        assert!(PRICE
+            .mismatch_sql()
+            .ends_with("WHERE price_numeric IS DISTINCT FROM (price::NUMERIC)"));
+    }
+
+    #[test]
+    fn phases_round_trip_and_report_progress() {
+        for phase in [
+            Phase::DualWrite,
+            Phase::Backfilled,
+            Phase::Verified,
+            Phase::ReadsSwitched,
+        ] {
+            assert_eq!(phase.as_str().parse::<Phase>().unwrap(), phase);
+        }
+        assert!(Phase::Backfilled < Phase::Verified);
+
+        let progress = Progress {
+            phase: Phase::DualWrite,
+            cursor: 40,
+            rows_done: 40,
+            rows_total: 160,
+        };
+        assert_eq!(progress.fraction_done(), 0.25);
+    }
+}
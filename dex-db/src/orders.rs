@@ -0,0 +1,234 @@
+//! Postgres implementation of `OrderRepo`.
+
+use crate::{
+    repository::{OrderFill, OrderRepo},
+    rows::{from_column, to_column, OrderRow},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::types::{Order, OrderId};
+use sqlx::{query, query_as, query_scalar};
+use sqlx_postgres::{PgExecutor, PgQueryResult};
+
+#[async_trait]
+impl OrderRepo for DatabaseManager {
+    async fn save_order(&self, order: &Order) -> Result<(), DatabaseError> {
+        let row = OrderRow::try_from(order)?;
+        // Upsert by id, so replaying it is safe. A replay leaves the fill
+        // state alone.
+        self.run("save_order", true, || {
+            query!(
+                r#"
+            INSERT INTO orders (
+                id, trader_id, base_token, quote_token, side, order_type, price, quantity, timestamp,
+                remaining_quantity
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $8)
+            ON CONFLICT (id) DO UPDATE SET
+                updated_at = EXTRACT(EPOCH FROM NOW())::BIGINT,
+                trader_id = $2,
+                base_token = $3,
+                quote_token = $4,
+                side = $5,
+                order_type = $6,
+                price = $7,
+                quantity = $8,
+                timestamp = $9
+            "#,
+                row.id,
+                row.trader_id,
+                row.base_token,
+                row.quote_token,
+                row.side,
+                row.order_type,
+                row.price,
+                row.quantity,
+                row.timestamp,
+            )
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_order(&self, order_id: OrderId) -> Result<Option<Order>, DatabaseError> {
+        let order_id = to_column(order_id)?;
+        let row = self
+            .run("load_order", true, || {
+                query_as!(
+                    OrderRow,
+                    r#"
+            SELECT
+                id, trader_id, base_token, quote_token, side, order_type, price, quantity, timestamp
+            FROM orders
+            WHERE id = $1
+            "#,
+                    order_id,
+                )
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        row.map(Order::try_from).transpose()
+    }
+
+    async fn delete_order(&self, order_id: OrderId) -> Result<bool, DatabaseError> {
+        let order_id = to_column(order_id)?;
+        // Only marks rows not yet deleted, so a replay changes nothing.
+        let result = self
+            .run("delete_order", true, || {
+                query!(
+                    r#"
+            UPDATE orders
+            SET deleted_at = EXTRACT(EPOCH FROM NOW())::BIGINT,
+                updated_at = EXTRACT(EPOCH FROM NOW())::BIGINT,
+                status = CASE
+                    WHEN status IN ('open', 'partially_filled') THEN 'cancelled'
+                    ELSE status
+                END
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+                    order_id,
+                )
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn discard_order(&self, order_id: OrderId) -> Result<(), DatabaseError> {
+        let order_id = to_column(order_id)?;
+        self.run("discard_order", true, || {
+            query!("DELETE FROM orders WHERE id = $1", order_id).execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_open_orders(&self) -> Result<Vec<Order>, DatabaseError> {
+        // Fills are stored with their trades, but rows written before that
+        // may record less than their trades executed, so the stored trades
+        // bound what is left. Busted trades count, like fills do.
+        let rows = self
+            .run("load_open_orders", true, || {
+                query_as!(
+                    OrderRow,
+                    r#"
+            SELECT
+                o.id, o.trader_id, o.base_token, o.quote_token, o.side, o.order_type, o.price,
+                LEAST(o.remaining_quantity, o.quantity - t.traded) AS "quantity!", o.timestamp
+            FROM orders o
+            CROSS JOIN LATERAL (
+                SELECT COALESCE(SUM(quantity), 0)::BIGINT AS traded
+                FROM trades
+                WHERE maker_order_id = o.id OR taker_order_id = o.id
+            ) t
+            WHERE o.status IN ('open', 'partially_filled')
+                AND o.order_type = 'limit'
+                AND LEAST(o.remaining_quantity, o.quantity - t.traded) > 0
+                AND o.deleted_at IS NULL
+            ORDER BY o.id ASC
+            "#,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        rows.into_iter().map(Order::try_from).collect()
+    }
+
+    async fn last_order_id(&self) -> Result<OrderId, DatabaseError> {
+        let last_id = self
+            .run("last_order_id", true, || {
+                query_scalar!(
+                    r#"
+            SELECT GREATEST(
+                (SELECT MAX(id) FROM orders),
+                (SELECT MAX(id) FROM orders_archive),
+                (SELECT MAX(GREATEST(maker_order_id, taker_order_id)) FROM trades),
+                (SELECT MAX(GREATEST(maker_order_id, taker_order_id)) FROM trades_archive),
+                (SELECT MAX(order_id) FROM sequencing_receipts),
+                0
+            ) AS "last_id!"
+            "#,
+                )
+                .fetch_one(&self.pool)
+            })
+            .await?;
+
+        from_column(last_id)
+    }
+
+    async fn next_order_id(&self) -> Result<OrderId, DatabaseError> {
+        // A retry at worst skips an ID.
+        let id = self
+            .run("next_order_id", true, || {
+                query_scalar!(r#"SELECT nextval('order_ids') AS "id!""#).fetch_one(&self.pool)
+            })
+            .await?;
+
+        from_column(id)
+    }
+
+    async fn update_order_fills(&self, fills: &[OrderFill]) -> Result<(), DatabaseError> {
+        if fills.is_empty() {
+            return Ok(());
+        }
+        let columns = FillColumns::new(fills)?;
+        // Sets absolute values, so replaying it is safe.
+        self.run("update_order_fills", true, || {
+            update_fills(&self.pool, &columns)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// Bound parameters of a fill update.
+pub(crate) struct FillColumns<'a> {
+    ids: Vec<i64>,
+    statuses: Vec<&'a str>,
+    remaining: Vec<i64>,
+}
+
+impl<'a> FillColumns<'a> {
+    pub(crate) fn new(fills: &'a [OrderFill]) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            ids: fills
+                .iter()
+                .map(|fill| to_column(fill.order_id))
+                .collect::<Result<_, _>>()?,
+            statuses: fills.iter().map(|fill| fill.status.as_str()).collect(),
+            remaining: fills
+                .iter()
+                .map(|fill| to_column(fill.remaining_quantity))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Set the status and remaining quantity of the orders in `columns`,
+/// through `executor`.
+pub(crate) async fn update_fills<'e>(
+    executor: impl PgExecutor<'e>,
+    columns: &FillColumns<'_>,
+) -> Result<PgQueryResult, sqlx_core::error::Error> {
+    query!(
+        r#"
+            UPDATE orders o
+            SET status = f.status,
+                remaining_quantity = f.remaining,
+                filled_quantity = o.quantity - f.remaining,
+                updated_at = EXTRACT(EPOCH FROM NOW())::BIGINT
+            FROM UNNEST($1::BIGINT[], $2::TEXT[], $3::BIGINT[]) AS f (id, status, remaining)
+            WHERE o.id = f.id
+            "#,
+        &columns.ids,
+        &columns.statuses as &[&str],
+        &columns.remaining,
+    )
+    .execute(executor)
+    .await
+}
@@ -0,0 +1,86 @@
+//! Postgres implementation of `OutboxRepo`.
+
+use crate::{
+    repository::{OutboxEvent, OutboxRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+
+#[async_trait]
+impl OutboxRepo for DatabaseManager {
+    async fn enqueue_events(&self, events: &[OutboxEvent]) -> Result<(), DatabaseError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let topics: Vec<&str> = events.iter().map(|event| event.topic.as_str()).collect();
+        let keys: Vec<&str> = events.iter().map(|event| event.key.as_str()).collect();
+        let payloads: Vec<&str> = events.iter().map(|event| event.payload.as_str()).collect();
+        let created: Vec<i64> = events.iter().map(|event| event.created_at as i64).collect();
+
+        // Retried: delivery is at least once anyway, so a replayed insert
+        // only adds a duplicate consumers already have to expect.
+        self.run("enqueue_events", true, || {
+            query(
+                r#"
+            INSERT INTO event_outbox (topic, key, payload, created_at)
+            SELECT topic, key, payload, created_at
+            FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::BIGINT[])
+                WITH ORDINALITY AS e (topic, key, payload, created_at, position)
+            ORDER BY position
+            "#,
+            )
+            .bind(&topics)
+            .bind(&keys)
+            .bind(&payloads)
+            .bind(&created)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_events(&self, limit: u32) -> Result<Vec<OutboxEvent>, DatabaseError> {
+        let rows = self
+            .run("pending_events", true, || {
+                query(
+                    r#"
+            SELECT id, topic, key, payload, created_at
+            FROM event_outbox
+            WHERE published_at IS NULL
+            ORDER BY id
+            LIMIT $1
+            "#,
+                )
+                .bind(limit as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| OutboxEvent {
+                id: row.get::<i64, _>("id") as u64,
+                topic: row.get("topic"),
+                key: row.get("key"),
+                payload: row.get("payload"),
+                created_at: row.get::<i64, _>("created_at") as u64,
+            })
+            .collect())
+    }
+
+    async fn mark_published(&self, ids: &[u64], published_at: u64) -> Result<(), DatabaseError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let ids: Vec<i64> = ids.iter().map(|&id| id as i64).collect();
+        self.run("mark_events_published", true, || {
+            query("UPDATE event_outbox SET published_at = $2 WHERE id = ANY($1)")
+                .bind(&ids)
+                .bind(published_at as i64)
+                .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
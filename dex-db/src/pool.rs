@@ -0,0 +1,72 @@
+//! Connection pool sizing and timeouts
+//!
+//! The pool is opened once at startup with these settings. How many of its
+//! connections are busy is exported with the other database metrics, so a
+//! pool that is too small shows up as connections all in use and acquire
+//! timeouts rather than as unexplained slow queries.
+
+use sqlx_postgres::{PgConnectOptions, PgPoolOptions};
+use std::time::Duration;
+
+/// Size and timeouts of the Postgres connection pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// Connections kept open even while idle.
+    pub min_connections: u32,
+    /// How long a query waits for a free connection before failing.
+    pub acquire_timeout: Duration,
+    /// Idle connections beyond `min_connections` are closed after this long;
+    /// `None` keeps them open.
+    pub idle_timeout: Option<Duration>,
+    /// Server-side `statement_timeout` of every connection; `None` leaves the
+    /// server's own setting.
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(5),
+            idle_timeout: Some(Duration::from_secs(600)),
+            statement_timeout: None,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub(crate) fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections.max(1))
+            .min_connections(self.min_connections.min(self.max_connections))
+            .acquire_timeout(self.acquire_timeout)
+            .idle_timeout(self.idle_timeout)
+    }
+
+    /// `options` with the statement timeout applied, if one is set.
+    pub(crate) fn connect_options(&self, options: PgConnectOptions) -> PgConnectOptions {
+        match self.statement_timeout {
+            Some(timeout) => {
+                options.options([("statement_timeout", format!("{}ms", timeout.as_millis()))])
+            }
+            None => options,
+        }
+    }
+}
+
+/// Connections of the pool at one moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Open connections, busy or idle.
+    pub size: u32,
+    pub idle: u32,
+    pub max_connections: u32,
+}
+
+impl PoolStats {
+    pub fn in_use(&self) -> u32 {
+        self.size.saturating_sub(self.idle)
+    }
+}
@@ -0,0 +1,196 @@
+//! Postgres implementation of `PoolRepo`.
+
+use crate::{
+    parse_column,
+    repository::{PoolPosition, PoolRecord, PoolRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+
+#[async_trait]
+impl PoolRepo for DatabaseManager {
+    async fn save_pools(&self, pools: &[PoolRecord]) -> Result<(), DatabaseError> {
+        for pool in pools {
+            let positions = |field: fn(&PoolPosition) -> String| -> Vec<String> {
+                pool.positions.iter().map(field).collect()
+            };
+            let traders = positions(|p| p.trader_id.to_string());
+            let lp_tokens: Vec<i64> = pool.positions.iter().map(|p| p.lp_tokens as i64).collect();
+            let deposited_base = positions(|p| p.deposited.0.to_string());
+            let deposited_quote = positions(|p| p.deposited.1.to_string());
+            let checkpoint_base = positions(|p| p.fee_checkpoint.0.to_string());
+            let checkpoint_quote = positions(|p| p.fee_checkpoint.1.to_string());
+            let fees_base = positions(|p| p.fees.0.to_string());
+            let fees_quote = positions(|p| p.fees.1.to_string());
+            let withdrawn_base = positions(|p| p.fees_withdrawn.0.to_string());
+            let withdrawn_quote = positions(|p| p.fees_withdrawn.1.to_string());
+            let fee_growth_base = pool.fee_growth_base.to_string();
+            let fee_growth_quote = pool.fee_growth_quote.to_string();
+
+            // One statement, so a pool and its positions are stored together,
+            // and only a newer pool replaces the stored one, so a retry is
+            // harmless. Positions the pool no longer has are dropped.
+            self.run("save_pools", true, || {
+                query(
+                    r#"
+            WITH pool AS (
+                INSERT INTO pools (
+                    base_token, quote_token, sequence, fee_bps, reserve_base, reserve_quote,
+                    lp_supply, fee_growth_base, fee_growth_quote
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8::NUMERIC, $9::NUMERIC)
+                ON CONFLICT (base_token, quote_token) DO UPDATE SET
+                    sequence = EXCLUDED.sequence,
+                    fee_bps = EXCLUDED.fee_bps,
+                    reserve_base = EXCLUDED.reserve_base,
+                    reserve_quote = EXCLUDED.reserve_quote,
+                    lp_supply = EXCLUDED.lp_supply,
+                    fee_growth_base = EXCLUDED.fee_growth_base,
+                    fee_growth_quote = EXCLUDED.fee_growth_quote,
+                    updated_at = EXTRACT(EPOCH FROM NOW())::BIGINT
+                WHERE EXCLUDED.sequence > pools.sequence
+                RETURNING base_token, quote_token
+            ), dropped AS (
+                DELETE FROM pool_positions p
+                USING pool
+                WHERE p.base_token = pool.base_token AND p.quote_token = pool.quote_token
+                  AND p.trader_id <> ALL($10::TEXT[])
+            )
+            INSERT INTO pool_positions (
+                base_token, quote_token, trader_id, lp_tokens, deposited_base, deposited_quote,
+                fee_checkpoint_base, fee_checkpoint_quote, fees_base, fees_quote,
+                fees_withdrawn_base, fees_withdrawn_quote
+            )
+            SELECT pool.base_token, pool.quote_token, p.trader_id, p.lp_tokens,
+                p.deposited_base::NUMERIC, p.deposited_quote::NUMERIC,
+                p.fee_checkpoint_base::NUMERIC, p.fee_checkpoint_quote::NUMERIC,
+                p.fees_base::NUMERIC, p.fees_quote::NUMERIC,
+                p.fees_withdrawn_base::NUMERIC, p.fees_withdrawn_quote::NUMERIC
+            FROM pool, UNNEST(
+                $10::TEXT[], $11::BIGINT[], $12::TEXT[], $13::TEXT[], $14::TEXT[],
+                $15::TEXT[], $16::TEXT[], $17::TEXT[], $18::TEXT[], $19::TEXT[]
+            ) AS p (
+                trader_id, lp_tokens, deposited_base, deposited_quote, fee_checkpoint_base,
+                fee_checkpoint_quote, fees_base, fees_quote, fees_withdrawn_base,
+                fees_withdrawn_quote
+            )
+            ON CONFLICT (base_token, quote_token, trader_id) DO UPDATE SET
+                lp_tokens = EXCLUDED.lp_tokens,
+                deposited_base = EXCLUDED.deposited_base,
+                deposited_quote = EXCLUDED.deposited_quote,
+                fee_checkpoint_base = EXCLUDED.fee_checkpoint_base,
+                fee_checkpoint_quote = EXCLUDED.fee_checkpoint_quote,
+                fees_base = EXCLUDED.fees_base,
+                fees_quote = EXCLUDED.fees_quote,
+                fees_withdrawn_base = EXCLUDED.fees_withdrawn_base,
+                fees_withdrawn_quote = EXCLUDED.fees_withdrawn_quote
+            "#,
+                )
+                .bind(pool.base_token.as_str())
+                .bind(pool.quote_token.as_str())
+                .bind(pool.sequence as i64)
+                .bind(pool.fee_bps as i32)
+                .bind(pool.reserve_base as i64)
+                .bind(pool.reserve_quote as i64)
+                .bind(pool.lp_supply as i64)
+                .bind(fee_growth_base.as_str())
+                .bind(fee_growth_quote.as_str())
+                .bind(traders.clone())
+                .bind(lp_tokens.clone())
+                .bind(deposited_base.clone())
+                .bind(deposited_quote.clone())
+                .bind(checkpoint_base.clone())
+                .bind(checkpoint_quote.clone())
+                .bind(fees_base.clone())
+                .bind(fees_quote.clone())
+                .bind(withdrawn_base.clone())
+                .bind(withdrawn_quote.clone())
+                .execute(&self.pool)
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_pools(&self) -> Result<Vec<PoolRecord>, DatabaseError> {
+        // One query, so a save landing in between cannot pair a pool with
+        // another snapshot's positions.
+        let rows = self
+            .run("load_pools", true, || {
+                query(
+                    r#"
+            SELECT
+                pl.base_token, pl.quote_token, pl.sequence, pl.fee_bps, pl.reserve_base,
+                pl.reserve_quote, pl.lp_supply,
+                pl.fee_growth_base::TEXT AS fee_growth_base,
+                pl.fee_growth_quote::TEXT AS fee_growth_quote,
+                p.trader_id, p.lp_tokens,
+                p.deposited_base::TEXT AS deposited_base,
+                p.deposited_quote::TEXT AS deposited_quote,
+                p.fee_checkpoint_base::TEXT AS fee_checkpoint_base,
+                p.fee_checkpoint_quote::TEXT AS fee_checkpoint_quote,
+                p.fees_base::TEXT AS fees_base,
+                p.fees_quote::TEXT AS fees_quote,
+                p.fees_withdrawn_base::TEXT AS fees_withdrawn_base,
+                p.fees_withdrawn_quote::TEXT AS fees_withdrawn_quote
+            FROM pools pl
+            LEFT JOIN pool_positions p
+                ON p.base_token = pl.base_token AND p.quote_token = pl.quote_token
+            ORDER BY pl.base_token ASC, pl.quote_token ASC, p.trader_id ASC
+            "#,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        let mut pools: Vec<PoolRecord> = Vec::new();
+        for row in &rows {
+            let base_token = parse_column(row, "base_token")?;
+            let quote_token = parse_column(row, "quote_token")?;
+            let same_pool = pools
+                .last()
+                .is_some_and(|p| p.base_token == base_token && p.quote_token == quote_token);
+            if !same_pool {
+                pools.push(PoolRecord {
+                    base_token,
+                    quote_token,
+                    sequence: row.get::<i64, _>("sequence") as u64,
+                    fee_bps: row.get::<i32, _>("fee_bps") as u32,
+                    reserve_base: row.get::<i64, _>("reserve_base") as u64,
+                    reserve_quote: row.get::<i64, _>("reserve_quote") as u64,
+                    lp_supply: row.get::<i64, _>("lp_supply") as u64,
+                    fee_growth_base: parse_column(row, "fee_growth_base")?,
+                    fee_growth_quote: parse_column(row, "fee_growth_quote")?,
+                    positions: Vec::new(),
+                });
+            }
+            // A pool without positions joins to a row of NULLs.
+            if row.get::<Option<&str>, _>("trader_id").is_none() {
+                continue;
+            }
+            let pool = pools.last_mut().expect("pushed above");
+            pool.positions.push(PoolPosition {
+                trader_id: parse_column(row, "trader_id")?,
+                lp_tokens: row.get::<i64, _>("lp_tokens") as u64,
+                deposited: (
+                    parse_column(row, "deposited_base")?,
+                    parse_column(row, "deposited_quote")?,
+                ),
+                fee_checkpoint: (
+                    parse_column(row, "fee_checkpoint_base")?,
+                    parse_column(row, "fee_checkpoint_quote")?,
+                ),
+                fees: (
+                    parse_column(row, "fees_base")?,
+                    parse_column(row, "fees_quote")?,
+                ),
+                fees_withdrawn: (
+                    parse_column(row, "fees_withdrawn_base")?,
+                    parse_column(row, "fees_withdrawn_quote")?,
+                ),
+            });
+        }
+        Ok(pools)
+    }
+}
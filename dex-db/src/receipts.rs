@@ -0,0 +1,85 @@
+//! Postgres implementation of `ReceiptRepo`.
+
+use crate::{
+    repository::{ReceiptRepo, SequencingReceipt},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::types::OrderId;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn receipt_from_row(row: &PgRow) -> SequencingReceipt {
+    SequencingReceipt {
+        sequence: row.get::<i64, _>("sequence") as u64,
+        order_id: row.get::<i64, _>("order_id") as u64,
+        command_hash: row.get("command_hash"),
+        previous_hash: row.get("previous_hash"),
+        timestamp: row.get::<i64, _>("timestamp") as u64,
+        region: row.get("region"),
+        signature: row.get("signature"),
+    }
+}
+
+#[async_trait]
+impl ReceiptRepo for DatabaseManager {
+    async fn save_receipt(&self, receipt: &SequencingReceipt) -> Result<(), DatabaseError> {
+        self.run("save_sequencing_receipt", true, || {
+            query(
+                r#"
+            INSERT INTO sequencing_receipts
+                (sequence, order_id, command_hash, previous_hash, timestamp, region, signature)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (sequence) DO NOTHING
+            "#,
+            )
+            .bind(receipt.sequence as i64)
+            .bind(receipt.order_id as i64)
+            .bind(receipt.command_hash.as_str())
+            .bind(receipt.previous_hash.as_str())
+            .bind(receipt.timestamp as i64)
+            .bind(receipt.region.as_str())
+            .bind(receipt.signature.as_str())
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn load_receipt(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<SequencingReceipt>, DatabaseError> {
+        let row = self
+            .run("load_sequencing_receipt", true, || {
+                query(
+                    r#"
+            SELECT sequence, order_id, command_hash, previous_hash, timestamp, region, signature
+            FROM sequencing_receipts
+            WHERE order_id = $1
+            "#,
+                )
+                .bind(order_id as i64)
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+        Ok(row.as_ref().map(receipt_from_row))
+    }
+
+    async fn latest_receipt(&self) -> Result<Option<SequencingReceipt>, DatabaseError> {
+        let row = self
+            .run("latest_sequencing_receipt", true, || {
+                query(
+                    r#"
+            SELECT sequence, order_id, command_hash, previous_hash, timestamp, region, signature
+            FROM sequencing_receipts
+            ORDER BY sequence DESC
+            LIMIT 1
+            "#,
+                )
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+        Ok(row.as_ref().map(receipt_from_row))
+    }
+}
@@ -0,0 +1,171 @@
+//! Postgres implementation of `RefreshTokenRepo`.
+
+use crate::{
+    repository::{RefreshTokenRecord, RefreshTokenRepo, RevokedToken},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn refresh_token_from_row(row: &PgRow) -> RefreshTokenRecord {
+    RefreshTokenRecord {
+        token_hash: row.get("token_hash"),
+        family_id: row.get("family_id"),
+        subject: row.get("subject"),
+        audience: row.get("audience"),
+        scope: row.get("scope"),
+        expires_at: row.get::<i64, _>("expires_at") as u64,
+        revoked: row.get("revoked"),
+        signed_in_at: row.get::<i64, _>("signed_in_at") as u64,
+        issued_at: row.get::<i64, _>("issued_at") as u64,
+        access_jti: row.get("access_jti"),
+        access_expires_at: row.get::<i64, _>("access_expires_at") as u64,
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for DatabaseManager {
+    async fn save_refresh_token(&self, token: &RefreshTokenRecord) -> Result<(), DatabaseError> {
+        // Plain insert: hashes are unique, so a retry could hit a duplicate key.
+        self.run("save_refresh_token", false, || {
+            query(
+                r#"
+            INSERT INTO refresh_tokens (
+                token_hash, family_id, subject, audience, scope, expires_at, revoked,
+                signed_in_at, issued_at, access_jti, access_expires_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+            )
+            .bind(token.token_hash.as_str())
+            .bind(token.family_id.as_str())
+            .bind(token.subject.as_str())
+            .bind(token.audience.as_deref())
+            .bind(token.scope.as_str())
+            .bind(token.expires_at as i64)
+            .bind(token.revoked)
+            .bind(token.signed_in_at as i64)
+            .bind(token.issued_at as i64)
+            .bind(token.access_jti.as_deref())
+            .bind(token.access_expires_at as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn revoke_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshTokenRecord>, DatabaseError> {
+        // Not retried: a retry after a lost reply would see the token already
+        // revoked and mistake the refresh for a replay.
+        let row = self
+            .run("revoke_refresh_token", false, || {
+                query(
+                    r#"
+            WITH previous AS (
+                SELECT token_hash, revoked FROM refresh_tokens WHERE token_hash = $1 FOR UPDATE
+            )
+            UPDATE refresh_tokens t SET revoked = TRUE
+            FROM previous
+            WHERE t.token_hash = previous.token_hash
+            RETURNING t.token_hash, t.family_id, t.subject, t.audience, t.scope, t.expires_at,
+                previous.revoked, t.signed_in_at, t.issued_at, t.access_jti, t.access_expires_at
+            "#,
+                )
+                .bind(token_hash)
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        Ok(row.as_ref().map(refresh_token_from_row))
+    }
+
+    async fn revoke_refresh_family(&self, family_id: &str) -> Result<u64, DatabaseError> {
+        let result = self
+            .run("revoke_refresh_family", true, || {
+                query(
+                    "UPDATE refresh_tokens SET revoked = TRUE WHERE family_id = $1 AND NOT revoked",
+                )
+                .bind(family_id)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn list_refresh_sessions(
+        &self,
+        subject: &str,
+        now: u64,
+    ) -> Result<Vec<RefreshTokenRecord>, DatabaseError> {
+        let rows = self
+            .run("list_refresh_sessions", true, || {
+                query(
+                    r#"
+            SELECT token_hash, family_id, subject, audience, scope, expires_at, revoked,
+                signed_in_at, issued_at, access_jti, access_expires_at
+            FROM refresh_tokens
+            WHERE subject = $1 AND NOT revoked AND expires_at > $2
+            ORDER BY signed_in_at ASC, token_hash ASC
+            "#,
+                )
+                .bind(subject)
+                .bind(now as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(refresh_token_from_row).collect())
+    }
+
+    async fn revoke_access_token(&self, token: &RevokedToken) -> Result<(), DatabaseError> {
+        self.run("revoke_access_token", true, || {
+            query(
+                r#"
+            INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2)
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+            )
+            .bind(token.jti.as_str())
+            .bind(token.expires_at as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_revoked_tokens(&self, now: u64) -> Result<Vec<RevokedToken>, DatabaseError> {
+        let rows = self
+            .run("load_revoked_tokens", true, || {
+                query("SELECT jti, expires_at FROM revoked_tokens WHERE expires_at > $1")
+                    .bind(now as i64)
+                    .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| RevokedToken {
+                jti: row.get("jti"),
+                expires_at: row.get::<i64, _>("expires_at") as u64,
+            })
+            .collect())
+    }
+
+    async fn purge_expired_revocations(&self, now: u64) -> Result<u64, DatabaseError> {
+        let result = self
+            .run("purge_expired_revocations", true, || {
+                query("DELETE FROM revoked_tokens WHERE expires_at <= $1")
+                    .bind(now as i64)
+                    .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
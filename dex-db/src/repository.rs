@@ -0,0 +1,1328 @@
+//! Storage traits for the DEX-OS persistence layer
+//!
+//! Each domain gets its own repository trait so the API can depend on the
+//! behaviour it needs rather than on `DatabaseManager`, and so storage can be
+//! mocked in tests or moved to another backend one domain at a time.
+
+use crate::DatabaseError;
+use async_trait::async_trait;
+use dex_core::ledger::{Account, AccountBalance, EntryKind, JournalEntry};
+use dex_core::types::{
+    Order, OrderId, OrderSide, Price, Quantity, TokenId, Trade, TradeId, TraderId, TradingPair,
+};
+
+/// Largest page a trade history query may return.
+pub const MAX_TRADE_PAGE: u32 = 1000;
+
+/// Largest page an audit log query may return.
+pub const MAX_AUDIT_PAGE: u32 = 1000;
+
+/// Largest page a ledger query may return.
+pub const MAX_LEDGER_PAGE: u32 = 1000;
+
+/// Largest page a funding history query may return.
+pub const MAX_FUNDING_PAGE: u32 = 1000;
+
+/// Cursor and filters for trade history queries. Results are ordered by
+/// trade ID ascending, so the last ID of a page is the cursor for the next.
+#[derive(Debug, Clone, Default)]
+pub struct TradeFilter {
+    /// Only return trades with an ID greater than this cursor.
+    pub after_id: Option<TradeId>,
+    /// Page size, capped at `MAX_TRADE_PAGE`.
+    pub limit: u32,
+    /// Restrict to a single market.
+    pub pair: Option<TradingPair>,
+    /// Inclusive lower bound on the trade timestamp.
+    pub from: Option<u64>,
+    /// Exclusive upper bound on the trade timestamp.
+    pub to: Option<u64>,
+}
+
+impl TradeFilter {
+    /// Effective page size, always between 1 and `MAX_TRADE_PAGE`.
+    pub fn page_size(&self) -> u32 {
+        self.limit.clamp(1, MAX_TRADE_PAGE)
+    }
+
+    /// Whether a trade passes the pair, time and cursor filters.
+    pub fn matches(&self, trade: &Trade) -> bool {
+        self.after_id.is_none_or(|after| trade.id > after)
+            && self.pair.as_ref().is_none_or(|pair| {
+                trade.base_token == *pair.base() && trade.quote_token == *pair.quote()
+            })
+            && self.from.is_none_or(|from| trade.timestamp >= from)
+            && self.to.is_none_or(|to| trade.timestamp < to)
+    }
+}
+
+/// Kind of administrative correction to an executed trade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjustmentKind {
+    /// The trade is cancelled and no longer counts anywhere.
+    Bust,
+    /// The trade stands at a corrected price.
+    PriceAdjust,
+}
+
+impl AdjustmentKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AdjustmentKind::Bust => "bust",
+            AdjustmentKind::PriceAdjust => "price_adjust",
+        }
+    }
+}
+
+impl std::str::FromStr for AdjustmentKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bust" => Ok(AdjustmentKind::Bust),
+            "price_adjust" => Ok(AdjustmentKind::PriceAdjust),
+            other => Err(format!("unknown adjustment kind: {}", other)),
+        }
+    }
+}
+
+/// One entry in a trade's adjustment history.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TradeAdjustment {
+    pub trade_id: TradeId,
+    pub kind: AdjustmentKind,
+    /// Price of the trade before the adjustment.
+    pub previous_price: Price,
+    /// Corrected price; `None` for a bust.
+    pub new_price: Option<Price>,
+    pub reason: String,
+    /// Subject of the administrator who made the adjustment.
+    pub adjusted_by: String,
+    /// Unix seconds.
+    pub adjusted_at: u64,
+}
+
+/// A stored refresh token. Only a hash of the token is kept, so reading the
+/// table does not hand out sessions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefreshTokenRecord {
+    /// Hex-encoded hash of the token.
+    pub token_hash: String,
+    /// Shared by every token rotated from the same sign-in, so reuse of a
+    /// rotated token can revoke the whole chain.
+    pub family_id: String,
+    pub subject: String,
+    pub audience: Option<String>,
+    /// Space-separated scopes of the sign-in, kept across rotations.
+    pub scope: String,
+    /// Unix seconds.
+    pub expires_at: u64,
+    pub revoked: bool,
+    /// When the sign-in happened, kept across rotations. Unix seconds.
+    pub signed_in_at: u64,
+    /// When this token was issued. Unix seconds.
+    pub issued_at: u64,
+    /// ID of the access token issued with this one, so ending the session
+    /// can revoke it; `None` for tokens stored before it was recorded.
+    pub access_jti: Option<String>,
+    /// When that access token expires. Unix seconds.
+    pub access_expires_at: u64,
+}
+
+/// A revoked access token, rejected until it would have expired anyway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RevokedToken {
+    pub jti: String,
+    /// Unix seconds.
+    pub expires_at: u64,
+}
+
+/// A wallet sign-in challenge waiting for its signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChallengeRecord {
+    /// Token subject of the wallet the challenge was issued to.
+    pub subject: String,
+    /// The text a message signature covers.
+    pub message: String,
+    /// JSON of the EIP-712 form, for Ethereum wallets.
+    pub typed_data: Option<String>,
+    /// Unix seconds.
+    pub expires_at: u64,
+}
+
+/// A shared-secret account's TOTP enrollment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpRecord {
+    pub subject: String,
+    /// The TOTP secret, sealed with the server's encryption key.
+    pub sealed_secret: String,
+    /// Set once a code from the authenticator app has been verified; until
+    /// then the enrollment can be replaced and is not enforced.
+    pub confirmed: bool,
+    /// Last time step a code was accepted for, so a code is used only once.
+    pub last_step: u64,
+    /// Unix seconds.
+    pub created_at: u64,
+}
+
+/// One authenticated action that changed state, or an attempt at one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Assigned by the store in order of recording; ignored on append.
+    pub id: u64,
+    /// Unix seconds.
+    pub occurred_at: u64,
+    /// Account the action was taken as.
+    pub subject: String,
+    /// Address of the client, when known.
+    pub ip: Option<String>,
+    /// What was attempted, e.g. `order_create` or `token_issue`.
+    pub action: String,
+    /// Endpoint or channel it came through, e.g. `DELETE /orderbook/orders/42`.
+    pub target: String,
+    /// `success`, `denied`, `rejected` or `error`.
+    pub outcome: String,
+    /// HTTP status of the response; `None` for FIX.
+    pub status: Option<u16>,
+}
+
+/// Cursor and filters for audit log queries. Results are ordered by entry
+/// ID ascending, so the last ID of a page is the cursor for the next.
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    /// Only return entries with an ID greater than this cursor.
+    pub after_id: Option<u64>,
+    /// Page size, capped at `MAX_AUDIT_PAGE`.
+    pub limit: u32,
+    pub subject: Option<String>,
+    pub action: Option<String>,
+    /// Inclusive lower bound on `occurred_at`.
+    pub from: Option<u64>,
+    /// Exclusive upper bound on `occurred_at`.
+    pub to: Option<u64>,
+}
+
+impl AuditFilter {
+    /// Effective page size, always between 1 and `MAX_AUDIT_PAGE`.
+    pub fn page_size(&self) -> u32 {
+        self.limit.clamp(1, MAX_AUDIT_PAGE)
+    }
+
+    /// Whether an entry passes the filters and cursor.
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.after_id.is_none_or(|after| entry.id > after)
+            && self
+                .subject
+                .as_ref()
+                .is_none_or(|subject| entry.subject == *subject)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| entry.action == *action)
+            && self.from.is_none_or(|from| entry.occurred_at >= from)
+            && self.to.is_none_or(|to| entry.occurred_at < to)
+    }
+}
+
+/// One UTC day of a trader's API usage through one credential.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageRollup {
+    pub subject: String,
+    /// Unix seconds at the start of the UTC day.
+    pub day: u64,
+    /// API key ID the requests were signed with; empty for bearer tokens,
+    /// WebSocket sessions and the account-wide order and fill counts.
+    pub credential: String,
+    pub requests: u64,
+    /// Responses with a 4xx or 5xx status, throttled ones included.
+    pub errors: u64,
+    /// Requests refused by the rate limiter.
+    pub throttled: u64,
+    pub orders: u64,
+    pub fills: u64,
+    pub ws_messages: u64,
+    pub ws_bytes: u64,
+}
+
+/// A day on which a trader breached the order-to-trade ratio, and the
+/// penalty it drew.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessagingPenalty {
+    pub subject: String,
+    /// Unix seconds at the start of the UTC day evaluated.
+    pub day: u64,
+    pub orders: u64,
+    pub fills: u64,
+    /// Orders per fill the trader was allowed.
+    pub max_ratio: f64,
+    /// Penalty fee charged, in USD.
+    pub fee: u64,
+    /// Percentage of the usual rate limits left to the trader.
+    pub rate_limit_percent: u32,
+    /// Unix seconds; when the rate limit reduction ends.
+    pub expires_at: u64,
+}
+
+/// A long-lived API key that signs requests with HMAC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyRecord {
+    /// Public identifier sent with each request.
+    pub key_id: String,
+    /// HMAC secret, sealed with the server's encryption key. Unlike refresh
+    /// tokens it cannot be stored hashed, since the server recomputes every
+    /// signature with it.
+    pub sealed_secret: String,
+    pub subject: String,
+    /// Space-separated scopes granted to requests signed with the key.
+    pub scope: String,
+    pub label: Option<String>,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub revoked: bool,
+    /// CIDR blocks requests signed with the key must come from; empty
+    /// allows any address.
+    pub allowed_ips: Vec<String>,
+}
+
+/// A trader's gross flows of one token within a netting set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetPosition {
+    pub trader_id: String,
+    pub token: String,
+    /// Total the trader owes across the netted trades.
+    pub delivered: u128,
+    /// Total the trader is owed across the netted trades.
+    pub received: u128,
+}
+
+impl NetPosition {
+    /// Positive when the trader is owed the token, negative when they owe it.
+    pub fn net(&self) -> i128 {
+        self.received as i128 - self.delivered as i128
+    }
+}
+
+/// One transfer that settles a netting set.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetTransfer {
+    pub token: String,
+    pub from_trader: String,
+    pub to_trader: String,
+    pub amount: u128,
+}
+
+/// The trades of one settlement batch collapsed into net transfers, kept for
+/// audit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NettingSet {
+    /// Assigned by the store; ignored on save.
+    pub id: u64,
+    /// Unix seconds.
+    pub created_at: u64,
+    /// Administrator who ran the netting.
+    pub created_by: String,
+    /// The set covers trades after this ID, up to `last_trade_id`.
+    pub after_trade_id: TradeId,
+    pub last_trade_id: TradeId,
+    /// Trades netted, not counting `unattributed_trade_ids`.
+    pub trade_count: u64,
+    /// Transfers settling each trade gross would take: two per trade.
+    pub gross_transfers: u64,
+    /// Trades whose orders are no longer stored, so their traders are
+    /// unknown. They are left out of the positions and settled by hand.
+    pub unattributed_trade_ids: Vec<TradeId>,
+    pub positions: Vec<NetPosition>,
+    pub transfers: Vec<NetTransfer>,
+}
+
+/// A swap through an AMM pool, stored so market data can cover the pools
+/// as well as the order book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapRecord {
+    /// Numbered separately from order book trades.
+    pub id: u64,
+    pub base_token: TokenId,
+    pub quote_token: TokenId,
+    /// `Buy` when the swapper bought base from the pool.
+    pub taker_side: OrderSide,
+    /// Quote paid or received per base, rounded to the nearest unit.
+    pub price: Price,
+    /// Base bought or sold.
+    pub quantity: Quantity,
+    /// Quote paid or received.
+    pub quote_quantity: Quantity,
+    /// Unix seconds.
+    pub timestamp: u64,
+}
+
+impl SwapRecord {
+    /// Whether the swap passes the pair, time and cursor filters, the cursor
+    /// being a swap ID.
+    pub fn matches(&self, filter: &TradeFilter) -> bool {
+        filter.after_id.is_none_or(|after| self.id > after)
+            && filter.pair.as_ref().is_none_or(|pair| {
+                self.base_token == *pair.base() && self.quote_token == *pair.quote()
+            })
+            && filter.from.is_none_or(|from| self.timestamp >= from)
+            && filter.to.is_none_or(|to| self.timestamp < to)
+    }
+}
+
+/// Cursor and filters for ledger queries. Results are ordered by entry ID
+/// ascending, so the last ID of a page is the cursor for the next.
+#[derive(Debug, Clone, Default)]
+pub struct LedgerFilter {
+    /// Only return entries with an ID greater than this cursor.
+    pub after_id: Option<u64>,
+    /// Page size, capped at `MAX_LEDGER_PAGE`.
+    pub limit: u32,
+    /// Only entries posting to this account.
+    pub account: Option<Account>,
+    pub kind: Option<EntryKind>,
+}
+
+impl LedgerFilter {
+    /// Effective page size, always between 1 and `MAX_LEDGER_PAGE`.
+    pub fn page_size(&self) -> u32 {
+        self.limit.clamp(1, MAX_LEDGER_PAGE)
+    }
+
+    /// Whether an entry passes the filters and cursor.
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        self.after_id.is_none_or(|after| entry.id > after)
+            && self.kind.is_none_or(|kind| entry.kind == kind)
+            && self.account.as_ref().is_none_or(|account| {
+                entry
+                    .postings
+                    .iter()
+                    .any(|posting| posting.account == *account)
+            })
+    }
+}
+
+/// Running totals of one market since it first traded, stored so public
+/// stats survive restarts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarketCounters {
+    pub base_token: TokenId,
+    pub quote_token: TokenId,
+    /// Order book fills and AMM swaps.
+    pub trade_count: u64,
+    /// Base traded.
+    pub base_volume: u128,
+    /// Quote traded.
+    pub quote_volume: u128,
+    pub last_price: Option<Price>,
+    /// Unix seconds of the latest trade.
+    pub last_trade_time: Option<u64>,
+}
+
+/// One market's bar for one minute, over order book fills and AMM swaps,
+/// with volume in the base token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CandleRecord {
+    pub base_token: TokenId,
+    pub quote_token: TokenId,
+    /// Unix seconds the minute starts at.
+    pub open_time: u64,
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume: Quantity,
+    pub trades: u64,
+}
+
+/// A concentrated-liquidity pool's ticks, stored as one compressed blob
+/// rather than a row per tick; see [`crate::tick_maps`] for the encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickMapBlob {
+    pub base_token: TokenId,
+    pub quote_token: TokenId,
+    /// Pool sequence the ticks were taken at.
+    pub sequence: u64,
+    pub tick_count: u64,
+    pub data: Vec<u8>,
+}
+
+/// An AMM pool's reserves, fee accounting and liquidity providers, as of
+/// one pool sequence. Ticks are stored separately, as a [`TickMapBlob`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolRecord {
+    pub base_token: TokenId,
+    pub quote_token: TokenId,
+    /// Pool sequence the state was taken at.
+    pub sequence: u64,
+    pub fee_bps: u32,
+    pub reserve_base: Quantity,
+    pub reserve_quote: Quantity,
+    /// LP tokens outstanding.
+    pub lp_supply: Quantity,
+    /// Swap fees earned per LP token, in each side, fixed-point.
+    pub fee_growth_base: u128,
+    pub fee_growth_quote: u128,
+    pub positions: Vec<PoolPosition>,
+}
+
+/// A liquidity provider's stake in a pool, with the amounts (base, quote)
+/// used to value it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PoolPosition {
+    pub trader_id: TraderId,
+    pub lp_tokens: Quantity,
+    /// Deposits still in the pool.
+    pub deposited: (u128, u128),
+    /// The pool's fee growth when fees were last credited.
+    pub fee_checkpoint: (u128, u128),
+    /// Credited fees on liquidity still in the pool.
+    pub fees: (u128, u128),
+    /// Fees paid out with liquidity already withdrawn.
+    pub fees_withdrawn: (u128, u128),
+}
+
+/// A custody wallet's balance in one token, as last reported from its
+/// chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustodyBalance {
+    pub wallet_id: String,
+    pub token: TokenId,
+    pub balance: u128,
+    /// Unix seconds the balance was reported at.
+    pub updated_at: u64,
+}
+
+/// A block of the internal chain, as kept for the explorer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainBlock {
+    pub shard_id: u64,
+    pub height: u64,
+    /// Hex.
+    pub hash: String,
+    /// Hex.
+    pub previous_hash: String,
+    pub timestamp: u64,
+    /// In block order.
+    pub transactions: Vec<ChainTransaction>,
+    /// Whether the shard has finalized the block.
+    pub finalized: bool,
+}
+
+/// A transaction of the internal chain and the block that included it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainTransaction {
+    /// Hex SHA-256 of the transaction.
+    pub hash: String,
+    pub shard_id: u64,
+    pub height: u64,
+    /// Position in the block.
+    pub index: u32,
+    pub from: String,
+    pub to: String,
+    pub amount: i64,
+    pub nonce: u64,
+    /// Whether the including block is finalized.
+    pub finalized: bool,
+}
+
+/// The engine's signed statement of where an accepted order was sequenced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequencingReceipt {
+    /// Position in the order of every order the engine accepted.
+    pub sequence: u64,
+    pub order_id: OrderId,
+    /// Hex SHA-256 of the order as sequenced.
+    pub command_hash: String,
+    /// Hex SHA-256 of the previous receipt's signed message.
+    pub previous_hash: String,
+    /// When the order was accepted, in Unix seconds.
+    pub timestamp: u64,
+    /// Region of the engine that sequenced the order.
+    pub region: String,
+    /// Hex Ed25519 signature of the receipt's signed message.
+    pub signature: String,
+}
+
+/// An event waiting in the outbox to be published to the event stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutboxEvent {
+    /// Assigned in insertion order when the event is enqueued.
+    pub id: u64,
+    pub topic: String,
+    /// Partitioning key, e.g. the trading pair.
+    pub key: String,
+    /// The event as JSON.
+    pub payload: String,
+    /// When the event happened, in Unix seconds.
+    pub created_at: u64,
+}
+
+/// A trader's HTTPS endpoint for order event notifications.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookRecord {
+    pub id: String,
+    pub trader_id: String,
+    pub url: String,
+    /// HMAC secret that deliveries are signed with. Stored in the clear for
+    /// the same reason as API key secrets.
+    pub secret: String,
+    /// Event types delivered, e.g. `fill`.
+    pub events: Vec<String>,
+    /// Unix seconds.
+    pub created_at: u64,
+    pub revoked: bool,
+}
+
+/// Where a webhook delivery stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Waiting for its next attempt.
+    Pending,
+    Delivered,
+    /// Given up on after the last attempt failed.
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for DeliveryStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(DeliveryStatus::Pending),
+            "delivered" => Ok(DeliveryStatus::Delivered),
+            "failed" => Ok(DeliveryStatus::Failed),
+            other => Err(format!("unknown delivery status: {}", other)),
+        }
+    }
+}
+
+/// One notification for one webhook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebhookDelivery {
+    /// Assigned when the delivery is queued.
+    pub id: u64,
+    pub webhook_id: String,
+    /// The JSON body that is posted.
+    pub payload: String,
+    pub status: DeliveryStatus,
+    /// Attempts made so far.
+    pub attempts: u32,
+    /// Unix seconds from which the next attempt may be made.
+    pub next_attempt_at: u64,
+    /// Why the last attempt failed.
+    pub last_error: Option<String>,
+}
+
+/// Where a stored order stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    /// Accepted and not yet traded.
+    Open,
+    PartiallyFilled,
+    Filled,
+    /// A market order's unfilled remainder, which never rests.
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Open => "open",
+            OrderStatus::PartiallyFilled => "partially_filled",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the order can still trade.
+    pub fn is_open(self) -> bool {
+        matches!(self, OrderStatus::Open | OrderStatus::PartiallyFilled)
+    }
+}
+
+impl std::str::FromStr for OrderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(OrderStatus::Open),
+            "partially_filled" => Ok(OrderStatus::PartiallyFilled),
+            "filled" => Ok(OrderStatus::Filled),
+            "cancelled" => Ok(OrderStatus::Cancelled),
+            other => Err(format!("unknown order status: {}", other)),
+        }
+    }
+}
+
+/// An order's state after a match; its filled quantity is the rest of its
+/// original quantity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderFill {
+    pub order_id: OrderId,
+    pub status: OrderStatus,
+    pub remaining_quantity: Quantity,
+}
+
+/// Whether funds came in or went out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FundingKind {
+    Deposit,
+    Withdrawal,
+}
+
+impl FundingKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FundingKind::Deposit => "deposit",
+            FundingKind::Withdrawal => "withdrawal",
+        }
+    }
+}
+
+impl std::str::FromStr for FundingKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(FundingKind::Deposit),
+            "withdrawal" => Ok(FundingKind::Withdrawal),
+            other => Err(format!("unknown funding kind: {}", other)),
+        }
+    }
+}
+
+/// Where a deposit or withdrawal stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FundingStatus {
+    /// Waiting for its transaction on the other chain.
+    Pending,
+    Confirmed,
+    /// Abandoned; a failed withdrawal's funds are returned.
+    Failed,
+}
+
+impl FundingStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FundingStatus::Pending => "pending",
+            FundingStatus::Confirmed => "confirmed",
+            FundingStatus::Failed => "failed",
+        }
+    }
+}
+
+impl std::str::FromStr for FundingStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(FundingStatus::Pending),
+            "confirmed" => Ok(FundingStatus::Confirmed),
+            "failed" => Ok(FundingStatus::Failed),
+            other => Err(format!("unknown funding status: {}", other)),
+        }
+    }
+}
+
+/// A deposit into or a withdrawal out of a trader's balance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FundingRecord {
+    /// Assigned when the record is stored.
+    pub id: u64,
+    pub trader_id: String,
+    pub kind: FundingKind,
+    /// Chain the funds came from or go to.
+    pub chain: String,
+    pub token: TokenId,
+    pub amount: u128,
+    /// Where a withdrawal is sent, on its chain.
+    pub address: Option<String>,
+    /// The event a deposit was credited for, unique per chain.
+    pub reference: Option<String>,
+    /// Transaction that moved the funds on the other chain, once known.
+    pub tx_hash: Option<String>,
+    pub status: FundingStatus,
+    /// Unix seconds.
+    pub created_at: u64,
+    /// Unix seconds of the last status change.
+    pub updated_at: u64,
+}
+
+/// Cursor and filters for funding history queries. Results are ordered by
+/// ID ascending, so the last ID of a page is the cursor for the next.
+#[derive(Debug, Clone, Default)]
+pub struct FundingFilter {
+    /// Only return records with an ID greater than this cursor.
+    pub after_id: Option<u64>,
+    /// Page size, capped at `MAX_FUNDING_PAGE`.
+    pub limit: u32,
+    pub trader_id: Option<String>,
+    pub kind: Option<FundingKind>,
+    pub status: Option<FundingStatus>,
+}
+
+impl FundingFilter {
+    /// Effective page size, always between 1 and `MAX_FUNDING_PAGE`.
+    pub fn page_size(&self) -> u32 {
+        self.limit.clamp(1, MAX_FUNDING_PAGE)
+    }
+
+    /// Whether a record passes the filters and cursor.
+    pub fn matches(&self, record: &FundingRecord) -> bool {
+        self.after_id.is_none_or(|after| record.id > after)
+            && self
+                .trader_id
+                .as_ref()
+                .is_none_or(|trader| record.trader_id == *trader)
+            && self.kind.is_none_or(|kind| record.kind == kind)
+            && self.status.is_none_or(|status| record.status == status)
+    }
+}
+
+/// Persistence of orders.
+#[async_trait]
+pub trait OrderRepo: Send + Sync {
+    /// Insert or replace an order.
+    async fn save_order(&self, order: &Order) -> Result<(), DatabaseError>;
+
+    /// Load an order by ID.
+    async fn load_order(&self, order_id: OrderId) -> Result<Option<Order>, DatabaseError>;
+
+    /// Soft-delete an order: it never rests in the book again, and an open
+    /// one is marked cancelled, but it stays loadable and in trade history.
+    /// Returns whether it existed and was not deleted already.
+    async fn delete_order(&self, order_id: OrderId) -> Result<bool, DatabaseError>;
+
+    /// Remove an order the book never accepted. Nothing of it is kept, since
+    /// it was never open and no trade refers to it.
+    async fn discard_order(&self, order_id: OrderId) -> Result<(), DatabaseError>;
+
+    /// Limit orders still open or partially filled, with their remaining
+    /// quantity as `quantity`, in ID order. Used to rebuild the book, so the
+    /// remaining quantity is never more than the order's stored trades leave.
+    async fn load_open_orders(&self) -> Result<Vec<Order>, DatabaseError>;
+
+    /// Highest order ID in use, including archived orders and deleted
+    /// orders that trades or sequencing receipts still refer to; zero when
+    /// there are none.
+    async fn last_order_id(&self) -> Result<OrderId, DatabaseError>;
+
+    /// Hand out an order ID never handed out before, by this or any other
+    /// instance sharing the store.
+    async fn next_order_id(&self) -> Result<OrderId, DatabaseError>;
+
+    /// Record the status and remaining quantity of orders a match changed.
+    /// Orders are stored open with nothing filled; fills count as executed,
+    /// so a later bust does not change them.
+    async fn update_order_fills(&self, fills: &[OrderFill]) -> Result<(), DatabaseError>;
+}
+
+/// Persistence of AMM swaps.
+#[async_trait]
+pub trait SwapRepo: Send + Sync {
+    /// Record a swap under the ID it was given.
+    async fn save_swap(&self, swap: &SwapRecord) -> Result<(), DatabaseError>;
+
+    /// One page of swaps matching the filter, in ascending ID order;
+    /// `after_id` is a swap ID.
+    async fn get_swaps(&self, filter: &TradeFilter) -> Result<Vec<SwapRecord>, DatabaseError>;
+
+    /// Highest swap ID recorded; zero when there are none.
+    async fn last_swap_id(&self) -> Result<u64, DatabaseError>;
+}
+
+/// Persistence of executed trades.
+#[async_trait]
+pub trait TradeRepo: Send + Sync {
+    /// Record an executed trade.
+    async fn save_trade(&self, trade: &Trade) -> Result<(), DatabaseError>;
+
+    /// Record the trades of one match together with the ledger `entries`
+    /// booking them and the `fills` of the orders they executed: all of them
+    /// are stored or none is, so the book rebuilt from storage never rests
+    /// quantity that already traded. Trades and entries already stored are
+    /// skipped and fills set absolute values, so a failed call may be
+    /// repeated.
+    async fn save_trades(
+        &self,
+        trades: &[Trade],
+        entries: &[JournalEntry],
+        fills: &[OrderFill],
+    ) -> Result<(), DatabaseError>;
+
+    /// Load a trade by ID.
+    async fn load_trade(&self, trade_id: TradeId) -> Result<Option<Trade>, DatabaseError>;
+
+    /// All trades where the order was maker or taker, oldest first.
+    async fn get_trades_for_order(&self, order_id: OrderId) -> Result<Vec<Trade>, DatabaseError>;
+
+    /// One page of trades involving the trader on either side, in ascending ID order.
+    async fn get_trades_for_trader(
+        &self,
+        trader_id: &TraderId,
+        filter: &TradeFilter,
+    ) -> Result<Vec<Trade>, DatabaseError>;
+
+    /// One page of all trades matching the filter, in ascending ID order.
+    async fn get_trades(&self, filter: &TradeFilter) -> Result<Vec<Trade>, DatabaseError>;
+
+    /// Bust or re-price a trade and append the adjustment to its history.
+    /// Applies only while the trade is not busted and still at
+    /// `adjustment.previous_price`, so concurrent corrections cannot
+    /// interleave. Returns whether it was applied.
+    ///
+    /// Busted trades are left out of every listing above; `load_trade`
+    /// still returns them.
+    async fn adjust_trade(&self, adjustment: &TradeAdjustment) -> Result<bool, DatabaseError>;
+
+    /// Highest trade ID recorded, busted and archived trades included; zero
+    /// when there are none.
+    async fn last_trade_id(&self) -> Result<TradeId, DatabaseError>;
+
+    /// Hand out `count` trade IDs never handed out before, in ascending
+    /// order.
+    async fn next_trade_ids(&self, count: usize) -> Result<Vec<TradeId>, DatabaseError>;
+
+    /// Adjustment history of a trade, oldest first.
+    async fn get_trade_adjustments(
+        &self,
+        trade_id: TradeId,
+    ) -> Result<Vec<TradeAdjustment>, DatabaseError>;
+}
+
+/// Persistence of API keys.
+#[async_trait]
+pub trait ApiKeyRepo: Send + Sync {
+    async fn save_api_key(&self, key: &ApiKeyRecord) -> Result<(), DatabaseError>;
+
+    async fn load_api_key(&self, key_id: &str) -> Result<Option<ApiKeyRecord>, DatabaseError>;
+
+    /// The subject's keys, revoked ones included, oldest first.
+    async fn list_api_keys(&self, subject: &str) -> Result<Vec<ApiKeyRecord>, DatabaseError>;
+
+    /// Revoke one of the subject's active keys; false when it has none by
+    /// that ID.
+    async fn revoke_api_key(&self, key_id: &str, subject: &str) -> Result<bool, DatabaseError>;
+
+    /// Replace the allowlist of one of the subject's active keys; false when
+    /// it has none by that ID.
+    async fn set_api_key_allowed_ips(
+        &self,
+        key_id: &str,
+        subject: &str,
+        allowed_ips: &[String],
+    ) -> Result<bool, DatabaseError>;
+}
+
+/// Daily API usage per trader and credential.
+#[async_trait]
+pub trait UsageRepo: Send + Sync {
+    /// Add the counts of each rollup to the stored ones for its day.
+    async fn add_usage(&self, rollups: &[UsageRollup]) -> Result<(), DatabaseError>;
+
+    /// The subject's rollups from `since_day` on, oldest day first.
+    async fn load_usage(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<UsageRollup>, DatabaseError>;
+
+    /// Every subject's rollups for one day.
+    async fn load_day_usage(&self, day: u64) -> Result<Vec<UsageRollup>, DatabaseError>;
+
+    /// Record a penalty; false when the subject already has one for that day.
+    async fn save_penalty(&self, penalty: &MessagingPenalty) -> Result<bool, DatabaseError>;
+
+    /// The subject's penalties from `since_day` on, oldest first.
+    async fn load_penalties(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError>;
+
+    /// Penalties whose rate limit reduction is still in force at `now`.
+    async fn load_active_penalties(&self, now: u64)
+        -> Result<Vec<MessagingPenalty>, DatabaseError>;
+}
+
+/// Persistence of refresh tokens, and of revoked access tokens so a sign-out
+/// holds on every API instance and across restarts.
+#[async_trait]
+pub trait RefreshTokenRepo: Send + Sync {
+    /// Record a newly issued token.
+    async fn save_refresh_token(&self, token: &RefreshTokenRecord) -> Result<(), DatabaseError>;
+
+    /// Revoke a token, returning it as it was before, so a token that was
+    /// already revoked can be told apart from a first use.
+    async fn revoke_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshTokenRecord>, DatabaseError>;
+
+    /// Revoke every token in a family, returning how many were still active.
+    async fn revoke_refresh_family(&self, family_id: &str) -> Result<u64, DatabaseError>;
+
+    /// `subject`'s tokens that are neither revoked nor expired at `now`: the
+    /// latest of each sign-in still in use, oldest sign-in first.
+    async fn list_refresh_sessions(
+        &self,
+        subject: &str,
+        now: u64,
+    ) -> Result<Vec<RefreshTokenRecord>, DatabaseError>;
+
+    /// Record an access token as revoked. Revoking it again is harmless.
+    async fn revoke_access_token(&self, token: &RevokedToken) -> Result<(), DatabaseError>;
+
+    /// Revoked access tokens that have not expired by `now`.
+    async fn load_revoked_tokens(&self, now: u64) -> Result<Vec<RevokedToken>, DatabaseError>;
+
+    /// Forget revocations of tokens expired by `now`, returning how many.
+    async fn purge_expired_revocations(&self, now: u64) -> Result<u64, DatabaseError>;
+}
+
+/// Persistence of wallet challenges, so a challenge issued by one API
+/// instance can be redeemed on another, or after a restart.
+#[async_trait]
+pub trait ChallengeRepo: Send + Sync {
+    /// Record a challenge, replacing any still pending for its subject.
+    async fn save_challenge(&self, challenge: &ChallengeRecord) -> Result<(), DatabaseError>;
+
+    /// Remove and return the subject's pending challenge, so it can be
+    /// redeemed only once.
+    async fn take_challenge(&self, subject: &str)
+        -> Result<Option<ChallengeRecord>, DatabaseError>;
+
+    /// Drop challenges expired as of `now`, returning how many.
+    async fn purge_expired_challenges(&self, now: u64) -> Result<u64, DatabaseError>;
+}
+
+/// Persistence of TOTP enrollments for shared-secret accounts.
+#[async_trait]
+pub trait TotpRepo: Send + Sync {
+    /// Record an enrollment, replacing an unconfirmed one; false when the
+    /// subject already has a confirmed enrollment.
+    async fn save_totp(&self, record: &TotpRecord) -> Result<bool, DatabaseError>;
+
+    async fn load_totp(&self, subject: &str) -> Result<Option<TotpRecord>, DatabaseError>;
+
+    /// Confirm a pending enrollment with the step of its first code; false
+    /// when there is none.
+    async fn confirm_totp(&self, subject: &str, step: u64) -> Result<bool, DatabaseError>;
+
+    /// Record a code accepted for `step`; false when a code for that step or
+    /// a later one was already accepted.
+    async fn use_totp_step(&self, subject: &str, step: u64) -> Result<bool, DatabaseError>;
+
+    /// Remove the subject's enrollment, returning whether there was one.
+    async fn delete_totp(&self, subject: &str) -> Result<bool, DatabaseError>;
+}
+
+/// The append-only log of authenticated actions, for compliance review.
+#[async_trait]
+pub trait AuditRepo: Send + Sync {
+    /// Append an entry, returning the ID it was recorded under. Entries are
+    /// never updated or removed.
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<u64, DatabaseError>;
+
+    /// Entries matching `filter`, oldest first.
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, DatabaseError>;
+}
+
+/// Netting sets of settlement batches, kept for audit.
+#[async_trait]
+pub trait SettlementRepo: Send + Sync {
+    /// Store a netting set with its positions and transfers, all or nothing,
+    /// and return its ID. Returns `None` when a set after the same trade was
+    /// stored first, so concurrent runs cannot net a trade twice.
+    async fn save_netting_set(&self, set: &NettingSet) -> Result<Option<u64>, DatabaseError>;
+
+    /// Load a netting set with its positions and transfers.
+    async fn load_netting_set(&self, id: u64) -> Result<Option<NettingSet>, DatabaseError>;
+
+    /// Highest trade ID covered by a netting set; zero when there are none.
+    async fn last_netted_trade_id(&self) -> Result<TradeId, DatabaseError>;
+}
+
+/// Cumulative market counters.
+#[async_trait]
+pub trait CounterRepo: Send + Sync {
+    /// Store each market's totals. A total below the stored one is ignored
+    /// and an older last trade does not replace a newer one, so counters
+    /// never go backwards, even when a stale snapshot is written late.
+    async fn save_counters(&self, counters: &[MarketCounters]) -> Result<(), DatabaseError>;
+
+    /// Every market's stored totals.
+    async fn load_counters(&self) -> Result<Vec<MarketCounters>, DatabaseError>;
+}
+
+/// One-minute bars aggregated from the trade history.
+#[async_trait]
+pub trait CandleRepo: Send + Sync {
+    /// Store bars, replacing those stored for the same market and minute.
+    async fn save_candles(&self, candles: &[CandleRecord]) -> Result<(), DatabaseError>;
+
+    /// Remove a market's bar for the minute starting at `open_time`, such as
+    /// after its only trade was busted.
+    async fn delete_candle(&self, pair: &TradingPair, open_time: u64) -> Result<(), DatabaseError>;
+
+    /// Up to `limit` of the market's bars opening in `[from, to)`, oldest
+    /// first.
+    async fn get_candles(
+        &self,
+        pair: &TradingPair,
+        from: u64,
+        to: u64,
+        limit: u32,
+    ) -> Result<Vec<CandleRecord>, DatabaseError>;
+
+    /// Unix seconds up to which every market's bars are stored; zero before
+    /// the first aggregation.
+    async fn candle_watermark(&self) -> Result<u64, DatabaseError>;
+
+    /// Record that bars are stored up to `through`. A lower value than the
+    /// stored one is ignored.
+    async fn advance_candle_watermark(&self, through: u64) -> Result<(), DatabaseError>;
+}
+
+/// Tick maps of concentrated-liquidity pools.
+#[async_trait]
+pub trait TickMapRepo: Send + Sync {
+    /// Store each pool's tick map. A map taken at a lower sequence than the
+    /// stored one is ignored, so a stale snapshot never replaces a newer one.
+    async fn save_tick_maps(&self, maps: &[TickMapBlob]) -> Result<(), DatabaseError>;
+
+    /// Every pool's stored tick map, still compressed.
+    async fn load_tick_maps(&self) -> Result<Vec<TickMapBlob>, DatabaseError>;
+}
+
+/// State of the AMM pools, so it survives a restart.
+#[async_trait]
+pub trait PoolRepo: Send + Sync {
+    /// Store each pool with its positions, replacing the stored ones. A pool
+    /// taken at a lower sequence than the stored one is ignored, so a stale
+    /// snapshot never replaces a newer one.
+    async fn save_pools(&self, pools: &[PoolRecord]) -> Result<(), DatabaseError>;
+
+    /// Every stored pool with its positions.
+    async fn load_pools(&self) -> Result<Vec<PoolRecord>, DatabaseError>;
+}
+
+/// Balances of the custody wallets backing wrapped assets.
+#[async_trait]
+pub trait CustodyRepo: Send + Sync {
+    /// Store a wallet's balance in a token. A balance reported before the
+    /// stored one is ignored, so a late report never replaces a newer one.
+    async fn save_custody_balance(&self, balance: &CustodyBalance) -> Result<(), DatabaseError>;
+
+    /// Every wallet's stored balance per token.
+    async fn load_custody_balances(&self) -> Result<Vec<CustodyBalance>, DatabaseError>;
+}
+
+/// Blocks and transactions of the internal chain, for the explorer.
+#[async_trait]
+pub trait ChainRepo: Send + Sync {
+    /// Store a block with its transactions, all or nothing. A block already
+    /// stored at the same shard and height is kept as it is.
+    async fn save_block(&self, block: &ChainBlock) -> Result<(), DatabaseError>;
+
+    /// Every shard's block at `height`, by shard.
+    async fn load_blocks_at(&self, height: u64) -> Result<Vec<ChainBlock>, DatabaseError>;
+
+    /// A transaction by its hex hash.
+    async fn load_transaction(&self, hash: &str)
+        -> Result<Option<ChainTransaction>, DatabaseError>;
+
+    /// Mark a shard's blocks up to `height` finalized, returning the ones
+    /// that were not already, lowest first.
+    async fn finalize_blocks(
+        &self,
+        shard_id: u64,
+        height: u64,
+    ) -> Result<Vec<ChainBlock>, DatabaseError>;
+}
+
+/// Sequencing receipts, one per accepted order.
+#[async_trait]
+pub trait ReceiptRepo: Send + Sync {
+    /// Store a receipt. A receipt already stored with the same sequence
+    /// number is kept as it is.
+    async fn save_receipt(&self, receipt: &SequencingReceipt) -> Result<(), DatabaseError>;
+
+    /// The receipt for an order.
+    async fn load_receipt(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<SequencingReceipt>, DatabaseError>;
+
+    /// The receipt with the highest sequence number, which the next one
+    /// follows.
+    async fn latest_receipt(&self) -> Result<Option<SequencingReceipt>, DatabaseError>;
+}
+
+/// Double-entry journal of every movement of value.
+#[async_trait]
+pub trait LedgerRepo: Send + Sync {
+    /// Store a balanced entry with its postings, all or nothing, and return
+    /// its ID. Returns `None` when an entry of the same kind and reference
+    /// is already stored, so an event is never booked twice.
+    async fn save_entry(&self, entry: &JournalEntry) -> Result<Option<u64>, DatabaseError>;
+
+    /// Entries matching `filter` with their postings, oldest first.
+    async fn query_ledger(&self, filter: &LedgerFilter)
+        -> Result<Vec<JournalEntry>, DatabaseError>;
+
+    /// Every account's non-zero balance per token.
+    async fn ledger_balances(&self) -> Result<Vec<AccountBalance>, DatabaseError>;
+
+    /// One account's non-zero balance per token, by token.
+    async fn account_balances(
+        &self,
+        account: &Account,
+    ) -> Result<Vec<AccountBalance>, DatabaseError>;
+}
+
+/// Events recorded alongside the state changes they describe, until a relay
+/// has published them.
+#[async_trait]
+pub trait OutboxRepo: Send + Sync {
+    /// Queue events in the given order. Their `id`s are ignored and assigned
+    /// on insert.
+    async fn enqueue_events(&self, events: &[OutboxEvent]) -> Result<(), DatabaseError>;
+
+    /// Up to `limit` events not yet published, oldest first.
+    async fn pending_events(&self, limit: u32) -> Result<Vec<OutboxEvent>, DatabaseError>;
+
+    /// Record that events were published at `published_at`.
+    async fn mark_published(&self, ids: &[u64], published_at: u64) -> Result<(), DatabaseError>;
+}
+
+/// Traders' webhooks and the notifications queued for them.
+#[async_trait]
+pub trait WebhookRepo: Send + Sync {
+    async fn save_webhook(&self, webhook: &WebhookRecord) -> Result<(), DatabaseError>;
+
+    /// The trader's webhooks that are not revoked, oldest first.
+    async fn list_webhooks(&self, trader_id: &str) -> Result<Vec<WebhookRecord>, DatabaseError>;
+
+    /// Every webhook that is not revoked.
+    async fn load_active_webhooks(&self) -> Result<Vec<WebhookRecord>, DatabaseError>;
+
+    /// Revoke one of the trader's webhooks; false when it has none by that
+    /// ID. Its pending deliveries are dropped.
+    async fn revoke_webhook(&self, id: &str, trader_id: &str) -> Result<bool, DatabaseError>;
+
+    /// Queue deliveries; their `id`s are ignored and assigned on insert.
+    async fn enqueue_deliveries(&self, deliveries: &[WebhookDelivery])
+        -> Result<(), DatabaseError>;
+
+    /// Up to `limit` pending deliveries due at `now`, oldest first.
+    async fn due_deliveries(
+        &self,
+        now: u64,
+        limit: u32,
+    ) -> Result<Vec<WebhookDelivery>, DatabaseError>;
+
+    /// Store the outcome of an attempt: status, attempts, next attempt and
+    /// last error.
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DatabaseError>;
+}
+
+/// Deposits and withdrawals of traders' funds.
+#[async_trait]
+pub trait FundingRepo: Send + Sync {
+    /// Store a record and return its ID; the record's `id` is ignored.
+    /// `None` when a deposit with the same chain and reference is already
+    /// stored.
+    async fn save_funding(&self, record: &FundingRecord) -> Result<Option<u64>, DatabaseError>;
+
+    /// Store a withdrawal together with the entry debiting it, which `debit`
+    /// builds from the new record's ID: both are stored or neither is.
+    /// Returns the ID; the record's `id` is ignored.
+    async fn save_withdrawal(
+        &self,
+        record: &FundingRecord,
+        debit: &(dyn Fn(u64) -> JournalEntry + Sync),
+    ) -> Result<u64, DatabaseError>;
+
+    async fn get_funding(&self, id: u64) -> Result<Option<FundingRecord>, DatabaseError>;
+
+    /// Move a pending record to `status`, with the transaction that settled
+    /// it if known, and book `refund` alongside it: both happen or neither
+    /// does. False when the record is missing or no longer pending.
+    async fn settle_funding(
+        &self,
+        id: u64,
+        status: FundingStatus,
+        tx_hash: Option<&str>,
+        at: u64,
+        refund: Option<&JournalEntry>,
+    ) -> Result<bool, DatabaseError>;
+
+    async fn query_funding(
+        &self,
+        filter: &FundingFilter,
+    ) -> Result<Vec<FundingRecord>, DatabaseError>;
+}
+
+/// Moving old trades and orders out of the tables the API queries.
+///
+/// Moved rows are kept in `trades_archive` and `orders_archive`; nothing
+/// the API serves reads them.
+#[async_trait]
+pub trait RetentionRepo: Send + Sync {
+    /// Move up to `limit` trades executed before `before`, and with IDs no
+    /// higher than `up_to_trade_id`, to the archive. Returns how many moved.
+    async fn archive_trades(
+        &self,
+        before: u64,
+        up_to_trade_id: TradeId,
+        limit: u32,
+    ) -> Result<u64, DatabaseError>;
+
+    /// Move up to `limit` filled or cancelled orders placed before `before`
+    /// to the archive, leaving those a trade still in the hot table refers
+    /// to. Returns how many moved.
+    async fn archive_orders(&self, before: u64, limit: u32) -> Result<u64, DatabaseError>;
+}
+
+/// A storage backend: every repository behind one value, so the API can be
+/// handed a backend and take each repository from it.
+pub trait Storage:
+    OrderRepo
+    + TradeRepo
+    + SwapRepo
+    + ApiKeyRepo
+    + UsageRepo
+    + RefreshTokenRepo
+    + ChallengeRepo
+    + TotpRepo
+    + AuditRepo
+    + SettlementRepo
+    + CounterRepo
+    + CandleRepo
+    + TickMapRepo
+    + PoolRepo
+    + CustodyRepo
+    + ChainRepo
+    + ReceiptRepo
+    + LedgerRepo
+    + OutboxRepo
+    + WebhookRepo
+    + FundingRepo
+    + RetentionRepo
+{
+    /// Whether writes can currently reach the backend. While they cannot,
+    /// the API serves reads and refuses orders.
+    fn is_available(&self) -> bool {
+        true
+    }
+}
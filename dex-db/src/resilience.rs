@@ -0,0 +1,306 @@
+//! Retry and circuit-breaking primitives for the database layer
+//!
+//! Transient Postgres failures (dropped connections, pool exhaustion,
+//! failovers) are retried with exponential backoff, while sustained failures
+//! trip a circuit breaker so callers can shed load instead of queueing
+//! requests behind a dead database.
+
+use sqlx_core::error::Error as SqlxError;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Backoff policy applied to idempotent database operations.
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(50),
+            max_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay to wait before the given retry (1-based).
+    pub fn delay_for(&self, retry: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(retry.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.base_delay
+            .checked_mul(factor)
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay)
+    }
+}
+
+/// Thresholds controlling when the circuit breaker opens and how long it stays open.
+#[derive(Debug, Clone, Copy)]
+pub struct BreakerConfig {
+    /// Consecutive failed operations before the breaker opens.
+    pub failure_threshold: u32,
+    /// Time the breaker stays open before letting a trial request through.
+    pub open_duration: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Combined retry and breaker settings for a `DatabaseManager`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResilienceConfig {
+    pub retry: RetryPolicy,
+    pub breaker: BreakerConfig,
+}
+
+/// Observable state of the circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Requests flow normally.
+    Closed,
+    /// The database is considered down; requests fail fast.
+    Open,
+    /// The open period elapsed and a trial request is allowed through.
+    HalfOpen,
+}
+
+impl BreakerState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BreakerState::Closed => "closed",
+            BreakerState::Open => "open",
+            BreakerState::HalfOpen => "half_open",
+        }
+    }
+}
+
+struct BreakerInner {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    trial_in_flight: bool,
+}
+
+/// Consecutive-failure circuit breaker guarding the connection pool.
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(BreakerInner {
+                consecutive_failures: 0,
+                opened_at: None,
+                trial_in_flight: false,
+            }),
+        }
+    }
+
+    /// Current breaker state, without reserving a trial slot.
+    pub fn state(&self) -> BreakerState {
+        let inner = self.inner.lock().expect("breaker lock poisoned");
+        match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(opened_at) if opened_at.elapsed() >= self.config.open_duration => {
+                BreakerState::HalfOpen
+            }
+            Some(_) => BreakerState::Open,
+        }
+    }
+
+    /// Ask permission to run an operation. While half-open only a single trial
+    /// request is admitted until its outcome is recorded.
+    pub fn try_acquire(&self) -> bool {
+        let mut inner = self.inner.lock().expect("breaker lock poisoned");
+        match inner.opened_at {
+            None => true,
+            Some(opened_at) if opened_at.elapsed() >= self.config.open_duration => {
+                if inner.trial_in_flight {
+                    false
+                } else {
+                    inner.trial_in_flight = true;
+                    true
+                }
+            }
+            Some(_) => false,
+        }
+    }
+
+    /// Record a successful operation, closing the breaker. Returns `true` if the
+    /// breaker transitioned from open/half-open to closed.
+    pub fn record_success(&self) -> bool {
+        let mut inner = self.inner.lock().expect("breaker lock poisoned");
+        let was_open = inner.opened_at.is_some();
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.trial_in_flight = false;
+        was_open
+    }
+
+    /// Record a failed operation. Returns `true` if this failure opened the breaker.
+    pub fn record_failure(&self) -> bool {
+        let mut inner = self.inner.lock().expect("breaker lock poisoned");
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        if inner.opened_at.is_some() {
+            // A failed trial restarts the open period.
+            inner.opened_at = Some(Instant::now());
+            inner.trial_in_flight = false;
+            return false;
+        }
+        if inner.consecutive_failures >= self.config.failure_threshold.max(1) {
+            inner.opened_at = Some(Instant::now());
+            inner.trial_in_flight = false;
+            return true;
+        }
+        false
+    }
+}
+
+/// Counters describing retry and breaker activity, exported via the API metrics endpoint.
+#[derive(Debug, Default)]
+pub struct DbMetrics {
+    pub operations: AtomicU64,
+    pub failures: AtomicU64,
+    pub retries: AtomicU64,
+    pub rejected: AtomicU64,
+    pub breaker_opened: AtomicU64,
+    pub breaker_closed: AtomicU64,
+    pub probes: AtomicU64,
+    pub probe_failures: AtomicU64,
+    pub timeouts: AtomicU64,
+    pub slow_queries: AtomicU64,
+    /// Attempts that gave up waiting for a pooled connection.
+    pub pool_timeouts: AtomicU64,
+    /// Reads sent back to the primary because the replica failed them.
+    pub replica_fallbacks: AtomicU64,
+}
+
+/// Point-in-time copy of `DbMetrics` together with the breaker state.
+#[derive(Debug, Clone, Copy)]
+pub struct DbMetricsSnapshot {
+    pub breaker_state: BreakerState,
+    pub operations: u64,
+    pub failures: u64,
+    pub retries: u64,
+    pub rejected: u64,
+    pub breaker_opened: u64,
+    pub breaker_closed: u64,
+    pub probes: u64,
+    pub probe_failures: u64,
+    pub timeouts: u64,
+    pub slow_queries: u64,
+    pub pool_timeouts: u64,
+    pub replica_fallbacks: u64,
+}
+
+impl DbMetrics {
+    pub fn snapshot(&self, breaker_state: BreakerState) -> DbMetricsSnapshot {
+        DbMetricsSnapshot {
+            breaker_state,
+            operations: self.operations.load(Ordering::Relaxed),
+            failures: self.failures.load(Ordering::Relaxed),
+            retries: self.retries.load(Ordering::Relaxed),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            breaker_opened: self.breaker_opened.load(Ordering::Relaxed),
+            breaker_closed: self.breaker_closed.load(Ordering::Relaxed),
+            probes: self.probes.load(Ordering::Relaxed),
+            probe_failures: self.probe_failures.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            slow_queries: self.slow_queries.load(Ordering::Relaxed),
+            pool_timeouts: self.pool_timeouts.load(Ordering::Relaxed),
+            replica_fallbacks: self.replica_fallbacks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Whether an error is likely to succeed on retry (connection loss, pool
+/// exhaustion, failover, serialization conflicts).
+pub fn is_transient(err: &SqlxError) -> bool {
+    match err {
+        SqlxError::Io(_) | SqlxError::PoolTimedOut | SqlxError::WorkerCrashed => true,
+        SqlxError::Protocol(_) | SqlxError::Tls(_) => true,
+        SqlxError::Database(db_err) => match db_err.code() {
+            Some(code) => {
+                code.starts_with("08")
+                    || matches!(
+                        code.as_ref(),
+                        "40001" | "40P01" | "53300" | "57P01" | "57P02" | "57P03"
+                    )
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+        };
+        assert_eq!(policy.delay_for(1), Duration::from_millis(100));
+        assert_eq!(policy.delay_for(2), Duration::from_millis(200));
+        assert_eq!(policy.delay_for(3), Duration::from_millis(350));
+        assert_eq!(policy.delay_for(40), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let breaker = CircuitBreaker::new(BreakerConfig {
+            failure_threshold: 2,
+            open_duration: Duration::from_secs(60),
+        });
+        assert!(!breaker.record_failure());
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.record_failure());
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn test_breaker_half_open_admits_single_trial() {
+        let breaker = CircuitBreaker::new(BreakerConfig {
+            failure_threshold: 1,
+            open_duration: Duration::ZERO,
+        });
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+        assert!(breaker.record_success());
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_transient_classification() {
+        assert!(is_transient(&SqlxError::PoolTimedOut));
+        assert!(!is_transient(&SqlxError::RowNotFound));
+        assert!(!is_transient(&SqlxError::PoolClosed));
+    }
+}
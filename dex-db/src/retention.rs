@@ -0,0 +1,82 @@
+//! Postgres implementation of `RetentionRepo`.
+//!
+//! Each batch is moved by one statement that deletes the rows from the hot
+//! table and inserts them into its archive, so a row is always in exactly
+//! one of the two. The archive tables copy the columns of `trades` and
+//! `orders` in order; a column added to either must be added to its archive
+//! too.
+
+use crate::{repository::RetentionRepo, rows::to_column, DatabaseError, DatabaseManager};
+use async_trait::async_trait;
+use dex_core::types::TradeId;
+use sqlx_core::query::query;
+
+#[async_trait]
+impl RetentionRepo for DatabaseManager {
+    async fn archive_trades(
+        &self,
+        before: u64,
+        up_to_trade_id: TradeId,
+        limit: u32,
+    ) -> Result<u64, DatabaseError> {
+        let before = to_column(before)?;
+        let up_to_trade_id = to_column(up_to_trade_id)?;
+        // A retry after a lost reply moves at most one batch more.
+        let result = self
+            .run("archive_trades", true, || {
+                query(
+                    r#"
+            WITH moved AS (
+                DELETE FROM trades
+                WHERE id IN (
+                    SELECT id FROM trades
+                    WHERE timestamp < $1 AND id <= $2
+                    ORDER BY id
+                    LIMIT $3
+                )
+                RETURNING *
+            )
+            INSERT INTO trades_archive SELECT * FROM moved
+            "#,
+                )
+                .bind(before)
+                .bind(up_to_trade_id)
+                .bind(i64::from(limit))
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn archive_orders(&self, before: u64, limit: u32) -> Result<u64, DatabaseError> {
+        let before = to_column(before)?;
+        let result = self
+            .run("archive_orders", true, || {
+                query(
+                    r#"
+            WITH moved AS (
+                DELETE FROM orders
+                WHERE id IN (
+                    SELECT o.id FROM orders o
+                    WHERE o.timestamp < $1
+                        AND o.status NOT IN ('open', 'partially_filled')
+                        AND NOT EXISTS (SELECT 1 FROM trades t WHERE t.maker_order_id = o.id)
+                        AND NOT EXISTS (SELECT 1 FROM trades t WHERE t.taker_order_id = o.id)
+                    ORDER BY o.id
+                    LIMIT $2
+                )
+                RETURNING *
+            )
+            INSERT INTO orders_archive SELECT * FROM moved
+            "#,
+                )
+                .bind(before)
+                .bind(i64::from(limit))
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,242 @@
+//! Typed rows of the `orders`, `trades` and `trade_adjustments` tables.
+//!
+//! Each row struct holds its table's columns in their Postgres types, is read
+//! with `query_as!`, which checks it against the schema at build time, and
+//! converts to and from the engine type here and nowhere else. Postgres has no unsigned integers, so IDs, prices and quantities are
+//! `BIGINT`; the conversions are checked, and a value that does not fit is a
+//! `DataIntegrityError` instead of a wrapped number.
+
+use crate::{repository::TradeAdjustment, DatabaseError};
+use dex_core::types::{Order, OrderSide, OrderType, Trade, TradingPair};
+
+/// An unsigned value as a `BIGINT` column.
+pub(crate) fn to_column(value: u64) -> Result<i64, DatabaseError> {
+    i64::try_from(value).map_err(|_| DatabaseError::DataIntegrityError)
+}
+
+/// A `BIGINT` column as an unsigned value.
+pub(crate) fn from_column(value: i64) -> Result<u64, DatabaseError> {
+    u64::try_from(value).map_err(|_| DatabaseError::DataIntegrityError)
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, DatabaseError> {
+    value.parse().map_err(|_| DatabaseError::DataIntegrityError)
+}
+
+pub(crate) fn side_to_str(side: OrderSide) -> &'static str {
+    match side {
+        OrderSide::Buy => "buy",
+        OrderSide::Sell => "sell",
+    }
+}
+
+fn side_from_str(side: &str) -> Result<OrderSide, DatabaseError> {
+    match side {
+        "buy" => Ok(OrderSide::Buy),
+        "sell" => Ok(OrderSide::Sell),
+        _ => Err(DatabaseError::DataIntegrityError),
+    }
+}
+
+fn order_type_to_str(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Limit => "limit",
+        OrderType::Market => "market",
+    }
+}
+
+fn order_type_from_str(order_type: &str) -> Result<OrderType, DatabaseError> {
+    match order_type {
+        "limit" => Ok(OrderType::Limit),
+        "market" => Ok(OrderType::Market),
+        _ => Err(DatabaseError::DataIntegrityError),
+    }
+}
+
+/// An `orders` row, without its fill state.
+pub(crate) struct OrderRow {
+    pub id: i64,
+    pub trader_id: String,
+    pub base_token: String,
+    pub quote_token: String,
+    pub side: String,
+    pub order_type: String,
+    pub price: Option<i64>,
+    pub quantity: i64,
+    pub timestamp: i64,
+}
+
+impl TryFrom<&Order> for OrderRow {
+    type Error = DatabaseError;
+
+    fn try_from(order: &Order) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            id: to_column(order.id)?,
+            trader_id: order.trader_id.to_string(),
+            base_token: order.pair.base().to_string(),
+            quote_token: order.pair.quote().to_string(),
+            side: side_to_str(order.side).to_string(),
+            order_type: order_type_to_str(order.order_type).to_string(),
+            price: order.price.map(to_column).transpose()?,
+            quantity: to_column(order.quantity)?,
+            timestamp: to_column(order.timestamp)?,
+        })
+    }
+}
+
+impl TryFrom<OrderRow> for Order {
+    type Error = DatabaseError;
+
+    fn try_from(row: OrderRow) -> Result<Self, DatabaseError> {
+        Ok(Order {
+            id: from_column(row.id)?,
+            trader_id: parse(&row.trader_id)?,
+            pair: TradingPair::new(parse(&row.base_token)?, parse(&row.quote_token)?)
+                .map_err(|_| DatabaseError::DataIntegrityError)?,
+            side: side_from_str(&row.side)?,
+            order_type: order_type_from_str(&row.order_type)?,
+            price: row.price.map(from_column).transpose()?,
+            quantity: from_column(row.quantity)?,
+            timestamp: from_column(row.timestamp)?,
+        })
+    }
+}
+
+/// A `trades` row.
+pub(crate) struct TradeRow {
+    pub id: i64,
+    pub maker_order_id: i64,
+    pub taker_order_id: i64,
+    pub base_token: String,
+    pub quote_token: String,
+    pub price: i64,
+    pub quantity: i64,
+    pub timestamp: i64,
+}
+
+impl TryFrom<&Trade> for TradeRow {
+    type Error = DatabaseError;
+
+    fn try_from(trade: &Trade) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            id: to_column(trade.id)?,
+            maker_order_id: to_column(trade.maker_order_id)?,
+            taker_order_id: to_column(trade.taker_order_id)?,
+            base_token: trade.base_token.to_string(),
+            quote_token: trade.quote_token.to_string(),
+            price: to_column(trade.price)?,
+            quantity: to_column(trade.quantity)?,
+            timestamp: to_column(trade.timestamp)?,
+        })
+    }
+}
+
+impl TryFrom<TradeRow> for Trade {
+    type Error = DatabaseError;
+
+    fn try_from(row: TradeRow) -> Result<Self, DatabaseError> {
+        Ok(Trade {
+            id: from_column(row.id)?,
+            maker_order_id: from_column(row.maker_order_id)?,
+            taker_order_id: from_column(row.taker_order_id)?,
+            base_token: parse(&row.base_token)?,
+            quote_token: parse(&row.quote_token)?,
+            price: from_column(row.price)?,
+            quantity: from_column(row.quantity)?,
+            timestamp: from_column(row.timestamp)?,
+        })
+    }
+}
+
+/// A `trade_adjustments` row.
+pub(crate) struct TradeAdjustmentRow {
+    pub trade_id: i64,
+    pub kind: String,
+    pub previous_price: i64,
+    pub new_price: Option<i64>,
+    pub reason: String,
+    pub adjusted_by: String,
+    pub adjusted_at: i64,
+}
+
+impl TryFrom<&TradeAdjustment> for TradeAdjustmentRow {
+    type Error = DatabaseError;
+
+    fn try_from(adjustment: &TradeAdjustment) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            trade_id: to_column(adjustment.trade_id)?,
+            kind: adjustment.kind.as_str().to_string(),
+            previous_price: to_column(adjustment.previous_price)?,
+            new_price: adjustment.new_price.map(to_column).transpose()?,
+            reason: adjustment.reason.clone(),
+            adjusted_by: adjustment.adjusted_by.clone(),
+            adjusted_at: to_column(adjustment.adjusted_at)?,
+        })
+    }
+}
+
+impl TryFrom<TradeAdjustmentRow> for TradeAdjustment {
+    type Error = DatabaseError;
+
+    fn try_from(row: TradeAdjustmentRow) -> Result<Self, DatabaseError> {
+        Ok(TradeAdjustment {
+            trade_id: from_column(row.trade_id)?,
+            kind: parse(&row.kind)?,
+            previous_price: from_column(row.previous_price)?,
+            new_price: row.new_price.map(from_column).transpose()?,
+            reason: row.reason,
+            adjusted_by: row.adjusted_by,
+            adjusted_at: from_column(row.adjusted_at)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order() -> Order {
+        Order {
+            id: 7,
+            trader_id: "alice".parse().unwrap(),
+            pair: TradingPair::new("ETH".parse().unwrap(), "USDC".parse().unwrap()).unwrap(),
+            side: OrderSide::Sell,
+            order_type: OrderType::Limit,
+            price: Some(2_000),
+            quantity: 3,
+            timestamp: 1_700_000_000,
+        }
+    }
+
+    #[test]
+    fn orders_round_trip_through_rows() {
+        let row = OrderRow::try_from(&order()).unwrap();
+        assert_eq!(
+            (row.side.as_str(), row.order_type.as_str()),
+            ("sell", "limit")
+        );
+        let back = Order::try_from(row).unwrap();
+        assert_eq!(back.id, 7);
+        assert_eq!(back.pair, order().pair);
+        assert_eq!((back.price, back.quantity), (Some(2_000), 3));
+    }
+
+    #[test]
+    fn values_that_do_not_fit_are_refused_both_ways() {
+        let huge = Order {
+            quantity: u64::MAX,
+            ..order()
+        };
+        assert!(matches!(
+            OrderRow::try_from(&huge),
+            Err(DatabaseError::DataIntegrityError)
+        ));
+
+        let mut row = OrderRow::try_from(&order()).unwrap();
+        row.price = Some(-1);
+        assert!(matches!(
+            Order::try_from(row),
+            Err(DatabaseError::DataIntegrityError)
+        ));
+    }
+}
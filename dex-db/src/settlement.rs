@@ -0,0 +1,181 @@
+//! Postgres implementation of `SettlementRepo`.
+
+use crate::{
+    parse_column,
+    repository::{NetPosition, NetTransfer, NettingSet, SettlementRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::types::TradeId;
+use sqlx_core::{query::query, row::Row};
+
+#[async_trait]
+impl SettlementRepo for DatabaseManager {
+    async fn save_netting_set(&self, set: &NettingSet) -> Result<Option<u64>, DatabaseError> {
+        let positions = |field: fn(&NetPosition) -> String| -> Vec<String> {
+            set.positions.iter().map(field).collect()
+        };
+        let transfers = |field: fn(&NetTransfer) -> String| -> Vec<String> {
+            set.transfers.iter().map(field).collect()
+        };
+        let traders = positions(|p| p.trader_id.clone());
+        let tokens = positions(|p| p.token.clone());
+        let delivered = positions(|p| p.delivered.to_string());
+        let received = positions(|p| p.received.to_string());
+        let transfer_tokens = transfers(|t| t.token.clone());
+        let senders = transfers(|t| t.from_trader.clone());
+        let recipients = transfers(|t| t.to_trader.clone());
+        let amounts = transfers(|t| t.amount.to_string());
+        let unattributed: Vec<i64> = set
+            .unattributed_trade_ids
+            .iter()
+            .map(|id| *id as i64)
+            .collect();
+
+        // One statement, so the set and its rows are stored together. Not
+        // retried: a retry after a lost reply would find the set already
+        // stored and report a conflict.
+        let row = self
+            .run("save_netting_set", false, || {
+                query(
+                    r#"
+            WITH netting AS (
+                INSERT INTO netting_sets (
+                    created_at, created_by, after_trade_id, last_trade_id, trade_count,
+                    gross_transfers, unattributed_trade_ids
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (after_trade_id) DO NOTHING
+                RETURNING id
+            ), positions AS (
+                INSERT INTO netting_positions (netting_set_id, trader_id, token, delivered, received)
+                SELECT netting.id, p.trader_id, p.token, p.delivered::NUMERIC, p.received::NUMERIC
+                FROM netting, UNNEST($8::TEXT[], $9::TEXT[], $10::TEXT[], $11::TEXT[])
+                    AS p (trader_id, token, delivered, received)
+            ), transfers AS (
+                INSERT INTO netting_transfers (netting_set_id, seq, token, from_trader, to_trader, amount)
+                SELECT netting.id, t.seq, t.token, t.from_trader, t.to_trader, t.amount::NUMERIC
+                FROM netting, UNNEST($12::TEXT[], $13::TEXT[], $14::TEXT[], $15::TEXT[])
+                    WITH ORDINALITY AS t (token, from_trader, to_trader, amount, seq)
+            )
+            SELECT id FROM netting
+            "#,
+                )
+                .bind(set.created_at as i64)
+                .bind(set.created_by.as_str())
+                .bind(set.after_trade_id as i64)
+                .bind(set.last_trade_id as i64)
+                .bind(set.trade_count as i64)
+                .bind(set.gross_transfers as i64)
+                .bind(unattributed.clone())
+                .bind(traders.clone())
+                .bind(tokens.clone())
+                .bind(delivered.clone())
+                .bind(received.clone())
+                .bind(transfer_tokens.clone())
+                .bind(senders.clone())
+                .bind(recipients.clone())
+                .bind(amounts.clone())
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        Ok(row.map(|row| row.get::<i64, _>("id") as u64))
+    }
+
+    async fn load_netting_set(&self, id: u64) -> Result<Option<NettingSet>, DatabaseError> {
+        let Some(row) = self
+            .run("load_netting_set", true, || {
+                query(
+                    r#"
+            SELECT id, created_at, created_by, after_trade_id, last_trade_id, trade_count,
+                gross_transfers, unattributed_trade_ids
+            FROM netting_sets
+            WHERE id = $1
+            "#,
+                )
+                .bind(id as i64)
+                .fetch_optional(&self.pool)
+            })
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let positions = self
+            .run("load_netting_positions", true, || {
+                query(
+                    r#"
+            SELECT trader_id, token, delivered::TEXT AS delivered, received::TEXT AS received
+            FROM netting_positions
+            WHERE netting_set_id = $1
+            ORDER BY token ASC, trader_id ASC
+            "#,
+                )
+                .bind(id as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+        let transfers = self
+            .run("load_netting_transfers", true, || {
+                query(
+                    r#"
+            SELECT token, from_trader, to_trader, amount::TEXT AS amount
+            FROM netting_transfers
+            WHERE netting_set_id = $1
+            ORDER BY seq ASC
+            "#,
+                )
+                .bind(id as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(Some(NettingSet {
+            id: row.get::<i64, _>("id") as u64,
+            created_at: row.get::<i64, _>("created_at") as u64,
+            created_by: row.get("created_by"),
+            after_trade_id: row.get::<i64, _>("after_trade_id") as TradeId,
+            last_trade_id: row.get::<i64, _>("last_trade_id") as TradeId,
+            trade_count: row.get::<i64, _>("trade_count") as u64,
+            gross_transfers: row.get::<i64, _>("gross_transfers") as u64,
+            unattributed_trade_ids: row
+                .get::<Vec<i64>, _>("unattributed_trade_ids")
+                .into_iter()
+                .map(|id| id as TradeId)
+                .collect(),
+            positions: positions
+                .iter()
+                .map(|row| {
+                    Ok(NetPosition {
+                        trader_id: row.get("trader_id"),
+                        token: row.get("token"),
+                        delivered: parse_column(row, "delivered")?,
+                        received: parse_column(row, "received")?,
+                    })
+                })
+                .collect::<Result<_, DatabaseError>>()?,
+            transfers: transfers
+                .iter()
+                .map(|row| {
+                    Ok(NetTransfer {
+                        token: row.get("token"),
+                        from_trader: row.get("from_trader"),
+                        to_trader: row.get("to_trader"),
+                        amount: parse_column(row, "amount")?,
+                    })
+                })
+                .collect::<Result<_, DatabaseError>>()?,
+        }))
+    }
+
+    async fn last_netted_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        let row = self
+            .run("last_netted_trade_id", true, || {
+                query("SELECT COALESCE(MAX(last_trade_id), 0) AS last_id FROM netting_sets")
+                    .fetch_one(&self.pool)
+            })
+            .await?;
+
+        Ok(row.get::<i64, _>("last_id") as TradeId)
+    }
+}
@@ -0,0 +1,713 @@
+//! SQLite implementation of the repositories keyed by account: API keys,
+//! refresh tokens, wallet challenges, TOTP, the audit log, usage and
+//! webhooks.
+
+use super::{int, json, opt_column, parsed, to_json, SqliteStorage};
+use crate::{
+    repository::{
+        ApiKeyRecord, ApiKeyRepo, AuditEntry, AuditFilter, AuditRepo, ChallengeRecord,
+        ChallengeRepo, MessagingPenalty, RefreshTokenRecord, RefreshTokenRepo, RevokedToken,
+        TotpRecord, TotpRepo, UsageRepo, UsageRollup, WebhookDelivery, WebhookRecord, WebhookRepo,
+    },
+    rows::to_column,
+    DatabaseError,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_sqlite::SqliteRow;
+
+fn api_key_from_row(row: &SqliteRow) -> Result<ApiKeyRecord, DatabaseError> {
+    Ok(ApiKeyRecord {
+        key_id: row.try_get("key_id")?,
+        sealed_secret: row.try_get("sealed_secret")?,
+        subject: row.try_get("subject")?,
+        scope: row.try_get("scope")?,
+        label: row.try_get("label")?,
+        created_at: int(row, "created_at")?,
+        revoked: row.try_get("revoked")?,
+        allowed_ips: json(row, "allowed_ips")?,
+    })
+}
+
+fn refresh_token_from_row(row: &SqliteRow) -> Result<RefreshTokenRecord, DatabaseError> {
+    Ok(RefreshTokenRecord {
+        token_hash: row.try_get("token_hash")?,
+        family_id: row.try_get("family_id")?,
+        subject: row.try_get("subject")?,
+        audience: row.try_get("audience")?,
+        scope: row.try_get("scope")?,
+        expires_at: int(row, "expires_at")?,
+        revoked: row.try_get("revoked")?,
+        signed_in_at: int(row, "signed_in_at")?,
+        issued_at: int(row, "issued_at")?,
+        access_jti: row.try_get("access_jti")?,
+        access_expires_at: int(row, "access_expires_at")?,
+    })
+}
+
+fn challenge_from_row(row: &SqliteRow) -> Result<ChallengeRecord, DatabaseError> {
+    Ok(ChallengeRecord {
+        subject: row.try_get("subject")?,
+        message: row.try_get("message")?,
+        typed_data: row.try_get("typed_data")?,
+        expires_at: int(row, "expires_at")?,
+    })
+}
+
+fn audit_from_row(row: &SqliteRow) -> Result<AuditEntry, DatabaseError> {
+    Ok(AuditEntry {
+        id: int(row, "id")?,
+        occurred_at: int(row, "occurred_at")?,
+        subject: row.try_get("subject")?,
+        ip: row.try_get("ip")?,
+        action: row.try_get("action")?,
+        target: row.try_get("target")?,
+        outcome: row.try_get("outcome")?,
+        status: row
+            .try_get::<Option<i64>, _>("status")?
+            .map(|status| u16::try_from(status).map_err(|_| DatabaseError::DataIntegrityError))
+            .transpose()?,
+    })
+}
+
+fn usage_from_row(row: &SqliteRow) -> Result<UsageRollup, DatabaseError> {
+    Ok(UsageRollup {
+        subject: row.try_get("subject")?,
+        day: int(row, "day")?,
+        credential: row.try_get("credential")?,
+        requests: int(row, "requests")?,
+        errors: int(row, "errors")?,
+        throttled: int(row, "throttled")?,
+        orders: int(row, "orders")?,
+        fills: int(row, "fills")?,
+        ws_messages: int(row, "ws_messages")?,
+        ws_bytes: int(row, "ws_bytes")?,
+    })
+}
+
+fn penalty_from_row(row: &SqliteRow) -> Result<MessagingPenalty, DatabaseError> {
+    Ok(MessagingPenalty {
+        subject: row.try_get("subject")?,
+        day: int(row, "day")?,
+        orders: int(row, "orders")?,
+        fills: int(row, "fills")?,
+        max_ratio: row.try_get("max_ratio")?,
+        fee: int(row, "fee")?,
+        rate_limit_percent: row.try_get("rate_limit_percent")?,
+        expires_at: int(row, "expires_at")?,
+    })
+}
+
+fn webhook_from_row(row: &SqliteRow) -> Result<WebhookRecord, DatabaseError> {
+    Ok(WebhookRecord {
+        id: row.try_get("id")?,
+        trader_id: row.try_get("trader_id")?,
+        url: row.try_get("url")?,
+        secret: row.try_get("secret")?,
+        events: json(row, "events")?,
+        created_at: int(row, "created_at")?,
+        revoked: row.try_get("revoked")?,
+    })
+}
+
+fn delivery_from_row(row: &SqliteRow) -> Result<WebhookDelivery, DatabaseError> {
+    Ok(WebhookDelivery {
+        id: int(row, "id")?,
+        webhook_id: row.try_get("webhook_id")?,
+        payload: row.try_get("payload")?,
+        status: parsed(row, "status")?,
+        attempts: row.try_get("attempts")?,
+        next_attempt_at: int(row, "next_attempt_at")?,
+        last_error: row.try_get("last_error")?,
+    })
+}
+
+#[async_trait]
+impl ApiKeyRepo for SqliteStorage {
+    async fn save_api_key(&self, key: &ApiKeyRecord) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO api_keys (key_id, sealed_secret, subject, scope, label, created_at, revoked, allowed_ips)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(key.key_id.as_str())
+        .bind(key.sealed_secret.as_str())
+        .bind(key.subject.as_str())
+        .bind(key.scope.as_str())
+        .bind(key.label.as_deref())
+        .bind(to_column(key.created_at)?)
+        .bind(key.revoked)
+        .bind(to_json(&key.allowed_ips))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load_api_key(&self, key_id: &str) -> Result<Option<ApiKeyRecord>, DatabaseError> {
+        let row = query("SELECT * FROM api_keys WHERE key_id = $1")
+            .bind(key_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(api_key_from_row).transpose()
+    }
+
+    async fn list_api_keys(&self, subject: &str) -> Result<Vec<ApiKeyRecord>, DatabaseError> {
+        let rows = query("SELECT * FROM api_keys WHERE subject = $1 ORDER BY created_at, key_id")
+            .bind(subject)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(api_key_from_row).collect()
+    }
+
+    async fn revoke_api_key(&self, key_id: &str, subject: &str) -> Result<bool, DatabaseError> {
+        let result = query(
+            "UPDATE api_keys SET revoked = TRUE WHERE key_id = $1 AND subject = $2 AND NOT revoked",
+        )
+        .bind(key_id)
+        .bind(subject)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_api_key_allowed_ips(
+        &self,
+        key_id: &str,
+        subject: &str,
+        allowed_ips: &[String],
+    ) -> Result<bool, DatabaseError> {
+        let result = query(
+            r#"
+            UPDATE api_keys SET allowed_ips = $3
+            WHERE key_id = $1 AND subject = $2 AND NOT revoked
+            "#,
+        )
+        .bind(key_id)
+        .bind(subject)
+        .bind(to_json(&allowed_ips))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl RefreshTokenRepo for SqliteStorage {
+    async fn save_refresh_token(&self, token: &RefreshTokenRecord) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO refresh_tokens (
+                token_hash, family_id, subject, audience, scope, expires_at, revoked,
+                signed_in_at, issued_at, access_jti, access_expires_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(token.token_hash.as_str())
+        .bind(token.family_id.as_str())
+        .bind(token.subject.as_str())
+        .bind(token.audience.as_deref())
+        .bind(token.scope.as_str())
+        .bind(to_column(token.expires_at)?)
+        .bind(token.revoked)
+        .bind(to_column(token.signed_in_at)?)
+        .bind(to_column(token.issued_at)?)
+        .bind(token.access_jti.as_deref())
+        .bind(to_column(token.access_expires_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn revoke_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshTokenRecord>, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let Some(row) = query("SELECT * FROM refresh_tokens WHERE token_hash = $1")
+            .bind(token_hash)
+            .fetch_optional(&mut *tx)
+            .await?
+        else {
+            return Ok(None);
+        };
+        query("UPDATE refresh_tokens SET revoked = TRUE WHERE token_hash = $1")
+            .bind(token_hash)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        refresh_token_from_row(&row).map(Some)
+    }
+
+    async fn revoke_refresh_family(&self, family_id: &str) -> Result<u64, DatabaseError> {
+        let result =
+            query("UPDATE refresh_tokens SET revoked = TRUE WHERE family_id = $1 AND NOT revoked")
+                .bind(family_id)
+                .execute(&self.pool)
+                .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn list_refresh_sessions(
+        &self,
+        subject: &str,
+        now: u64,
+    ) -> Result<Vec<RefreshTokenRecord>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT * FROM refresh_tokens
+            WHERE subject = $1 AND NOT revoked AND expires_at > $2
+            ORDER BY signed_in_at, token_hash
+            "#,
+        )
+        .bind(subject)
+        .bind(to_column(now)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(refresh_token_from_row).collect()
+    }
+
+    async fn revoke_access_token(&self, token: &RevokedToken) -> Result<(), DatabaseError> {
+        query(
+            "INSERT INTO revoked_tokens (jti, expires_at) VALUES ($1, $2) ON CONFLICT (jti) DO NOTHING",
+        )
+        .bind(token.jti.as_str())
+        .bind(to_column(token.expires_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load_revoked_tokens(&self, now: u64) -> Result<Vec<RevokedToken>, DatabaseError> {
+        let rows = query("SELECT jti, expires_at FROM revoked_tokens WHERE expires_at > $1")
+            .bind(to_column(now)?)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(RevokedToken {
+                    jti: row.try_get("jti")?,
+                    expires_at: int(row, "expires_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn purge_expired_revocations(&self, now: u64) -> Result<u64, DatabaseError> {
+        let result = query("DELETE FROM revoked_tokens WHERE expires_at <= $1")
+            .bind(to_column(now)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl ChallengeRepo for SqliteStorage {
+    async fn save_challenge(&self, challenge: &ChallengeRecord) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO wallet_challenges (subject, message, typed_data, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (subject) DO UPDATE SET
+                message = excluded.message,
+                typed_data = excluded.typed_data,
+                expires_at = excluded.expires_at
+            "#,
+        )
+        .bind(challenge.subject.as_str())
+        .bind(challenge.message.as_str())
+        .bind(challenge.typed_data.as_deref())
+        .bind(to_column(challenge.expires_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        subject: &str,
+    ) -> Result<Option<ChallengeRecord>, DatabaseError> {
+        let row = query("DELETE FROM wallet_challenges WHERE subject = $1 RETURNING *")
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(challenge_from_row).transpose()
+    }
+
+    async fn purge_expired_challenges(&self, now: u64) -> Result<u64, DatabaseError> {
+        let result = query("DELETE FROM wallet_challenges WHERE expires_at <= $1")
+            .bind(to_column(now)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[async_trait]
+impl TotpRepo for SqliteStorage {
+    async fn save_totp(&self, record: &TotpRecord) -> Result<bool, DatabaseError> {
+        let result = query(
+            r#"
+            INSERT INTO totp_secrets (subject, sealed_secret, confirmed, last_step, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (subject) DO UPDATE SET
+                sealed_secret = excluded.sealed_secret,
+                confirmed = excluded.confirmed,
+                last_step = excluded.last_step,
+                created_at = excluded.created_at
+            WHERE NOT totp_secrets.confirmed
+            "#,
+        )
+        .bind(record.subject.as_str())
+        .bind(record.sealed_secret.as_str())
+        .bind(record.confirmed)
+        .bind(to_column(record.last_step)?)
+        .bind(to_column(record.created_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn load_totp(&self, subject: &str) -> Result<Option<TotpRecord>, DatabaseError> {
+        let row = query("SELECT * FROM totp_secrets WHERE subject = $1")
+            .bind(subject)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.map(|row| {
+            Ok(TotpRecord {
+                subject: row.try_get("subject")?,
+                sealed_secret: row.try_get("sealed_secret")?,
+                confirmed: row.try_get("confirmed")?,
+                last_step: int(&row, "last_step")?,
+                created_at: int(&row, "created_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn confirm_totp(&self, subject: &str, step: u64) -> Result<bool, DatabaseError> {
+        let result = query(
+            r#"
+            UPDATE totp_secrets SET confirmed = TRUE, last_step = $2
+            WHERE subject = $1 AND NOT confirmed
+            "#,
+        )
+        .bind(subject)
+        .bind(to_column(step)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_totp_step(&self, subject: &str, step: u64) -> Result<bool, DatabaseError> {
+        let result = query(
+            r#"
+            UPDATE totp_secrets SET last_step = $2
+            WHERE subject = $1 AND confirmed AND last_step < $2
+            "#,
+        )
+        .bind(subject)
+        .bind(to_column(step)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_totp(&self, subject: &str) -> Result<bool, DatabaseError> {
+        let result = query("DELETE FROM totp_secrets WHERE subject = $1")
+            .bind(subject)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[async_trait]
+impl AuditRepo for SqliteStorage {
+    async fn append_audit(&self, entry: &AuditEntry) -> Result<u64, DatabaseError> {
+        let row = query(
+            r#"
+            INSERT INTO audit_log (occurred_at, subject, ip, action, target, outcome, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(to_column(entry.occurred_at)?)
+        .bind(entry.subject.as_str())
+        .bind(entry.ip.as_deref())
+        .bind(entry.action.as_str())
+        .bind(entry.target.as_str())
+        .bind(entry.outcome.as_str())
+        .bind(entry.status)
+        .fetch_one(&self.pool)
+        .await?;
+        int(&row, "id")
+    }
+
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT * FROM audit_log
+            WHERE ($1 IS NULL OR id > $1)
+                AND ($2 IS NULL OR subject = $2)
+                AND ($3 IS NULL OR action = $3)
+                AND ($4 IS NULL OR occurred_at >= $4)
+                AND ($5 IS NULL OR occurred_at < $5)
+            ORDER BY id ASC
+            LIMIT $6
+            "#,
+        )
+        .bind(opt_column(filter.after_id)?)
+        .bind(filter.subject.as_deref())
+        .bind(filter.action.as_deref())
+        .bind(opt_column(filter.from)?)
+        .bind(opt_column(filter.to)?)
+        .bind(i64::from(filter.page_size()))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(audit_from_row).collect()
+    }
+}
+
+#[async_trait]
+impl UsageRepo for SqliteStorage {
+    async fn add_usage(&self, rollups: &[UsageRollup]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for rollup in rollups {
+            query(
+                r#"
+                INSERT INTO api_usage_daily (
+                    subject, day, credential, requests, errors, throttled, orders, fills,
+                    ws_messages, ws_bytes
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                ON CONFLICT (subject, day, credential) DO UPDATE SET
+                    requests = requests + excluded.requests,
+                    errors = errors + excluded.errors,
+                    throttled = throttled + excluded.throttled,
+                    orders = orders + excluded.orders,
+                    fills = fills + excluded.fills,
+                    ws_messages = ws_messages + excluded.ws_messages,
+                    ws_bytes = ws_bytes + excluded.ws_bytes
+                "#,
+            )
+            .bind(rollup.subject.as_str())
+            .bind(to_column(rollup.day)?)
+            .bind(rollup.credential.as_str())
+            .bind(to_column(rollup.requests)?)
+            .bind(to_column(rollup.errors)?)
+            .bind(to_column(rollup.throttled)?)
+            .bind(to_column(rollup.orders)?)
+            .bind(to_column(rollup.fills)?)
+            .bind(to_column(rollup.ws_messages)?)
+            .bind(to_column(rollup.ws_bytes)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_usage(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<UsageRollup>, DatabaseError> {
+        let rows = query(
+            "SELECT * FROM api_usage_daily WHERE subject = $1 AND day >= $2 ORDER BY day, credential",
+        )
+        .bind(subject)
+        .bind(to_column(since_day)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(usage_from_row).collect()
+    }
+
+    async fn load_day_usage(&self, day: u64) -> Result<Vec<UsageRollup>, DatabaseError> {
+        let rows =
+            query("SELECT * FROM api_usage_daily WHERE day = $1 ORDER BY subject, credential")
+                .bind(to_column(day)?)
+                .fetch_all(&self.pool)
+                .await?;
+        rows.iter().map(usage_from_row).collect()
+    }
+
+    async fn save_penalty(&self, penalty: &MessagingPenalty) -> Result<bool, DatabaseError> {
+        let result = query(
+            r#"
+            INSERT INTO messaging_penalties (
+                subject, day, orders, fills, max_ratio, fee, rate_limit_percent, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (subject, day) DO NOTHING
+            "#,
+        )
+        .bind(penalty.subject.as_str())
+        .bind(to_column(penalty.day)?)
+        .bind(to_column(penalty.orders)?)
+        .bind(to_column(penalty.fills)?)
+        .bind(penalty.max_ratio)
+        .bind(to_column(penalty.fee)?)
+        .bind(penalty.rate_limit_percent)
+        .bind(to_column(penalty.expires_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn load_penalties(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+        let rows = query(
+            "SELECT * FROM messaging_penalties WHERE subject = $1 AND day >= $2 ORDER BY day",
+        )
+        .bind(subject)
+        .bind(to_column(since_day)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(penalty_from_row).collect()
+    }
+
+    async fn load_active_penalties(
+        &self,
+        now: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+        let rows = query("SELECT * FROM messaging_penalties WHERE expires_at > $1 ORDER BY day")
+            .bind(to_column(now)?)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(penalty_from_row).collect()
+    }
+}
+
+#[async_trait]
+impl WebhookRepo for SqliteStorage {
+    async fn save_webhook(&self, webhook: &WebhookRecord) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO webhooks (id, trader_id, url, secret, events, created_at, revoked)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(webhook.id.as_str())
+        .bind(webhook.trader_id.as_str())
+        .bind(webhook.url.as_str())
+        .bind(webhook.secret.as_str())
+        .bind(to_json(&webhook.events))
+        .bind(to_column(webhook.created_at)?)
+        .bind(webhook.revoked)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn list_webhooks(&self, trader_id: &str) -> Result<Vec<WebhookRecord>, DatabaseError> {
+        let rows = query(
+            "SELECT * FROM webhooks WHERE trader_id = $1 AND NOT revoked ORDER BY created_at, id",
+        )
+        .bind(trader_id)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(webhook_from_row).collect()
+    }
+
+    async fn load_active_webhooks(&self) -> Result<Vec<WebhookRecord>, DatabaseError> {
+        let rows = query("SELECT * FROM webhooks WHERE NOT revoked ORDER BY created_at, id")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(webhook_from_row).collect()
+    }
+
+    async fn revoke_webhook(&self, id: &str, trader_id: &str) -> Result<bool, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let revoked = query(
+            "UPDATE webhooks SET revoked = TRUE WHERE id = $1 AND trader_id = $2 AND NOT revoked",
+        )
+        .bind(id)
+        .bind(trader_id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if !revoked {
+            return Ok(false);
+        }
+        query(
+            r#"
+            UPDATE webhook_deliveries SET status = 'failed', last_error = 'webhook revoked'
+            WHERE webhook_id = $1 AND status = 'pending'
+            "#,
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn enqueue_deliveries(
+        &self,
+        deliveries: &[WebhookDelivery],
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for delivery in deliveries {
+            query(
+                r#"
+                INSERT INTO webhook_deliveries (
+                    webhook_id, payload, status, attempts, next_attempt_at, last_error
+                ) VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(delivery.webhook_id.as_str())
+            .bind(delivery.payload.as_str())
+            .bind(delivery.status.as_str())
+            .bind(delivery.attempts)
+            .bind(to_column(delivery.next_attempt_at)?)
+            .bind(delivery.last_error.as_deref())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn due_deliveries(
+        &self,
+        now: u64,
+        limit: u32,
+    ) -> Result<Vec<WebhookDelivery>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT * FROM webhook_deliveries
+            WHERE status = 'pending' AND next_attempt_at <= $1
+            ORDER BY id
+            LIMIT $2
+            "#,
+        )
+        .bind(to_column(now)?)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(delivery_from_row).collect()
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = $3, next_attempt_at = $4, last_error = $5
+            WHERE id = $1
+            "#,
+        )
+        .bind(to_column(delivery.id)?)
+        .bind(delivery.status.as_str())
+        .bind(delivery.attempts)
+        .bind(to_column(delivery.next_attempt_at)?)
+        .bind(delivery.last_error.as_deref())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+//! SQLite implementation of `LedgerRepo`, `FundingRepo` and `OutboxRepo`.
+//!
+//! Amounts are `u128`, stored as decimal text, so balances are summed here
+//! rather than in SQL.
+
+use super::{int, parsed, to_json, SqliteStorage};
+use crate::{
+    repository::{
+        FundingFilter, FundingRecord, FundingRepo, FundingStatus, LedgerFilter, LedgerRepo,
+        OutboxEvent, OutboxRepo,
+    },
+    rows::to_column,
+    DatabaseError,
+};
+use async_trait::async_trait;
+use dex_core::ledger::{Account, AccountBalance, JournalEntry, Posting};
+use sqlx_core::{query::query, row::Row};
+use sqlx_sqlite::{SqliteConnection, SqliteRow};
+use std::collections::{BTreeMap, HashMap};
+
+const FUNDING_COLUMNS: &str = "id, trader_id, kind, chain, token, amount, address, reference, \
+                               tx_hash, status, created_at, updated_at";
+
+fn posting_from_row(row: &SqliteRow) -> Result<Posting, DatabaseError> {
+    Ok(Posting {
+        account: parsed(row, "account")?,
+        token: parsed(row, "token")?,
+        direction: parsed(row, "direction")?,
+        amount: parsed(row, "amount")?,
+    })
+}
+
+fn funding_from_row(row: &SqliteRow) -> Result<FundingRecord, DatabaseError> {
+    Ok(FundingRecord {
+        id: int(row, "id")?,
+        trader_id: row.try_get("trader_id")?,
+        kind: parsed(row, "kind")?,
+        chain: row.try_get("chain")?,
+        token: parsed(row, "token")?,
+        amount: parsed(row, "amount")?,
+        address: row.try_get("address")?,
+        reference: row.try_get("reference")?,
+        tx_hash: row.try_get("tx_hash")?,
+        status: parsed(row, "status")?,
+        created_at: int(row, "created_at")?,
+        updated_at: int(row, "updated_at")?,
+    })
+}
+
+/// Store `entry` and its postings through `conn`. Returns the new entry's
+/// id, or `None` when an entry of the same kind and reference is already
+/// stored. Callers run it in a transaction so the entry is stored whole.
+pub(super) async fn insert_entry(
+    conn: &mut SqliteConnection,
+    entry: &JournalEntry,
+) -> Result<Option<u64>, DatabaseError> {
+    let row = query(
+        r#"
+        INSERT INTO ledger_entries (kind, reference, timestamp)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (kind, reference) DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(entry.kind.as_str())
+    .bind(entry.reference.as_str())
+    .bind(to_column(entry.timestamp)?)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let id: i64 = row.try_get("id")?;
+    for (line, posting) in entry.postings.iter().enumerate() {
+        query(
+            r#"
+            INSERT INTO ledger_postings (entry_id, line, account, token, direction, amount)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(id)
+        .bind(line as i64 + 1)
+        .bind(posting.account.to_string())
+        .bind(posting.token.to_string())
+        .bind(posting.direction.as_str())
+        .bind(posting.amount.to_string())
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(Some(id as u64))
+}
+
+/// Insert `record` through `conn`, returning its new ID, or `None` when a
+/// deposit with the same chain and reference is already stored.
+async fn insert_funding(
+    conn: &mut SqliteConnection,
+    record: &FundingRecord,
+) -> Result<Option<u64>, DatabaseError> {
+    let row = query(
+        r#"
+        INSERT INTO funding_transfers (
+            trader_id, kind, chain, token, amount, address, reference, tx_hash, status,
+            created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT (kind, chain, reference) DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(record.trader_id.as_str())
+    .bind(record.kind.as_str())
+    .bind(record.chain.as_str())
+    .bind(record.token.as_str())
+    .bind(record.amount.to_string())
+    .bind(record.address.as_deref())
+    .bind(record.reference.as_deref())
+    .bind(record.tx_hash.as_deref())
+    .bind(record.status.as_str())
+    .bind(to_column(record.created_at)?)
+    .bind(to_column(record.updated_at)?)
+    .fetch_optional(conn)
+    .await?;
+    row.as_ref().map(|row| int(row, "id")).transpose()
+}
+
+impl SqliteStorage {
+    /// Non-zero balances from every posting, or only `account`'s, by
+    /// account then token.
+    async fn balances(
+        &self,
+        account: Option<&Account>,
+    ) -> Result<Vec<AccountBalance>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT account, token, direction, amount
+            FROM ledger_postings
+            WHERE $1 IS NULL OR account = $1
+            "#,
+        )
+        .bind(account.map(ToString::to_string))
+        .fetch_all(&self.pool)
+        .await?;
+        let mut balances: BTreeMap<_, i128> = BTreeMap::new();
+        for row in &rows {
+            let posting = posting_from_row(row)?;
+            *balances
+                .entry((posting.account.clone(), posting.token.clone()))
+                .or_default() += posting.signed_amount();
+        }
+        Ok(balances
+            .into_iter()
+            .filter(|(_, balance)| *balance != 0)
+            .map(|((account, token), balance)| AccountBalance {
+                account,
+                token,
+                balance,
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl LedgerRepo for SqliteStorage {
+    async fn save_entry(&self, entry: &JournalEntry) -> Result<Option<u64>, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let id = insert_entry(&mut tx, entry).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn query_ledger(
+        &self,
+        filter: &LedgerFilter,
+    ) -> Result<Vec<JournalEntry>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT id, kind, reference, timestamp
+            FROM ledger_entries e
+            WHERE ($1 IS NULL OR id > $1)
+              AND ($2 IS NULL OR kind = $2)
+              AND ($3 IS NULL OR EXISTS (
+                  SELECT 1 FROM ledger_postings p WHERE p.entry_id = e.id AND p.account = $3
+              ))
+            ORDER BY id ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(filter.after_id.map(to_column).transpose()?)
+        .bind(filter.kind.map(|kind| kind.as_str()))
+        .bind(filter.account.as_ref().map(ToString::to_string))
+        .bind(i64::from(filter.page_size()))
+        .fetch_all(&self.pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ids = rows
+            .iter()
+            .map(|row| row.try_get("id"))
+            .collect::<Result<Vec<i64>, _>>()?;
+        let posting_rows = query(
+            r#"
+            SELECT entry_id, account, token, direction, amount
+            FROM ledger_postings
+            WHERE entry_id IN (SELECT value FROM json_each($1))
+            ORDER BY entry_id ASC, line ASC
+            "#,
+        )
+        .bind(to_json(&ids))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut postings: HashMap<u64, Vec<Posting>> = HashMap::new();
+        for row in &posting_rows {
+            postings
+                .entry(int(row, "entry_id")?)
+                .or_default()
+                .push(posting_from_row(row)?);
+        }
+
+        rows.iter()
+            .map(|row| {
+                let id = int(row, "id")?;
+                Ok(JournalEntry {
+                    id,
+                    kind: parsed(row, "kind")?,
+                    reference: row.try_get("reference")?,
+                    timestamp: int(row, "timestamp")?,
+                    postings: postings.remove(&id).unwrap_or_default(),
+                })
+            })
+            .collect()
+    }
+
+    async fn ledger_balances(&self) -> Result<Vec<AccountBalance>, DatabaseError> {
+        self.balances(None).await
+    }
+
+    async fn account_balances(
+        &self,
+        account: &Account,
+    ) -> Result<Vec<AccountBalance>, DatabaseError> {
+        self.balances(Some(account)).await
+    }
+}
+
+#[async_trait]
+impl FundingRepo for SqliteStorage {
+    async fn save_funding(&self, record: &FundingRecord) -> Result<Option<u64>, DatabaseError> {
+        let mut conn = self.pool.acquire().await?;
+        insert_funding(&mut conn, record).await
+    }
+
+    async fn save_withdrawal(
+        &self,
+        record: &FundingRecord,
+        debit: &(dyn Fn(u64) -> JournalEntry + Sync),
+    ) -> Result<u64, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let id = insert_funding(&mut tx, record)
+            .await?
+            .ok_or(DatabaseError::DataIntegrityError)?;
+        insert_entry(&mut tx, &debit(id)).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn get_funding(&self, id: u64) -> Result<Option<FundingRecord>, DatabaseError> {
+        let sql = format!(
+            "SELECT {} FROM funding_transfers WHERE id = $1",
+            FUNDING_COLUMNS
+        );
+        let row = query(&sql)
+            .bind(to_column(id)?)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(funding_from_row).transpose()
+    }
+
+    async fn settle_funding(
+        &self,
+        id: u64,
+        status: FundingStatus,
+        tx_hash: Option<&str>,
+        at: u64,
+        refund: Option<&JournalEntry>,
+    ) -> Result<bool, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let result = query(
+            r#"
+            UPDATE funding_transfers
+            SET status = $2, tx_hash = COALESCE($3, tx_hash), updated_at = $4
+            WHERE id = $1 AND status = 'pending'
+            "#,
+        )
+        .bind(to_column(id)?)
+        .bind(status.as_str())
+        .bind(tx_hash)
+        .bind(to_column(at)?)
+        .execute(&mut *tx)
+        .await?;
+        if let (1, Some(refund)) = (result.rows_affected(), refund) {
+            insert_entry(&mut tx, refund).await?;
+        }
+        tx.commit().await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn query_funding(
+        &self,
+        filter: &FundingFilter,
+    ) -> Result<Vec<FundingRecord>, DatabaseError> {
+        let sql = format!(
+            r#"
+            SELECT {}
+            FROM funding_transfers
+            WHERE ($1 IS NULL OR id > $1)
+              AND ($2 IS NULL OR trader_id = $2)
+              AND ($3 IS NULL OR kind = $3)
+              AND ($4 IS NULL OR status = $4)
+            ORDER BY id ASC
+            LIMIT $5
+            "#,
+            FUNDING_COLUMNS
+        );
+        let rows = query(&sql)
+            .bind(filter.after_id.map(to_column).transpose()?)
+            .bind(filter.trader_id.as_deref())
+            .bind(filter.kind.map(|kind| kind.as_str()))
+            .bind(filter.status.map(|status| status.as_str()))
+            .bind(i64::from(filter.page_size()))
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(funding_from_row).collect()
+    }
+}
+
+#[async_trait]
+impl OutboxRepo for SqliteStorage {
+    async fn enqueue_events(&self, events: &[OutboxEvent]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for event in events {
+            query(
+                r#"
+                INSERT INTO event_outbox (topic, key, payload, created_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(event.topic.as_str())
+            .bind(event.key.as_str())
+            .bind(event.payload.as_str())
+            .bind(to_column(event.created_at)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn pending_events(&self, limit: u32) -> Result<Vec<OutboxEvent>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT id, topic, key, payload, created_at
+            FROM event_outbox
+            WHERE published_at IS NULL
+            ORDER BY id
+            LIMIT $1
+            "#,
+        )
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(OutboxEvent {
+                    id: int(row, "id")?,
+                    topic: row.try_get("topic")?,
+                    key: row.try_get("key")?,
+                    payload: row.try_get("payload")?,
+                    created_at: int(row, "created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    async fn mark_published(&self, ids: &[u64], published_at: u64) -> Result<(), DatabaseError> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        query(
+            r#"
+            UPDATE event_outbox SET published_at = $2
+            WHERE id IN (SELECT value FROM json_each($1))
+            "#,
+        )
+        .bind(to_json(&ids))
+        .bind(to_column(published_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,770 @@
+//! SQLite implementation of the market and settlement repositories:
+//! netting sets, counters, candles, AMM pools and tick maps, custody
+//! balances, the internal chain and sequencing receipts.
+
+use super::{int, json, opt_column, opt_int, parsed, to_json, SqliteStorage};
+use crate::{
+    repository::{
+        CandleRecord, CandleRepo, ChainBlock, ChainRepo, ChainTransaction, CounterRepo,
+        CustodyBalance, CustodyRepo, MarketCounters, NetPosition, NetTransfer, NettingSet,
+        PoolPosition, PoolRecord, PoolRepo, ReceiptRepo, SequencingReceipt, SettlementRepo,
+        TickMapBlob, TickMapRepo,
+    },
+    rows::to_column,
+    DatabaseError,
+};
+use async_trait::async_trait;
+use dex_core::types::{OrderId, TradeId, TradingPair};
+use sqlx_core::{query::query, row::Row};
+use sqlx_sqlite::{SqliteConnection, SqliteRow};
+
+fn counters_from_row(row: &SqliteRow) -> Result<MarketCounters, DatabaseError> {
+    Ok(MarketCounters {
+        base_token: parsed(row, "base_token")?,
+        quote_token: parsed(row, "quote_token")?,
+        trade_count: int(row, "trade_count")?,
+        base_volume: parsed(row, "base_volume")?,
+        quote_volume: parsed(row, "quote_volume")?,
+        last_price: opt_int(row, "last_price")?,
+        last_trade_time: opt_int(row, "last_trade_time")?,
+    })
+}
+
+fn candle_from_row(row: &SqliteRow) -> Result<CandleRecord, DatabaseError> {
+    Ok(CandleRecord {
+        base_token: parsed(row, "base_token")?,
+        quote_token: parsed(row, "quote_token")?,
+        open_time: int(row, "open_time")?,
+        open: int(row, "open")?,
+        high: int(row, "high")?,
+        low: int(row, "low")?,
+        close: int(row, "close")?,
+        volume: int(row, "volume")?,
+        trades: int(row, "trades")?,
+    })
+}
+
+fn pool_position_from_row(row: &SqliteRow) -> Result<PoolPosition, DatabaseError> {
+    let pair = |base: &str, quote: &str| -> Result<(u128, u128), DatabaseError> {
+        Ok((parsed(row, base)?, parsed(row, quote)?))
+    };
+    Ok(PoolPosition {
+        trader_id: parsed(row, "trader_id")?,
+        lp_tokens: int(row, "lp_tokens")?,
+        deposited: pair("deposited_base", "deposited_quote")?,
+        fee_checkpoint: pair("fee_checkpoint_base", "fee_checkpoint_quote")?,
+        fees: pair("fees_base", "fees_quote")?,
+        fees_withdrawn: pair("fees_withdrawn_base", "fees_withdrawn_quote")?,
+    })
+}
+
+fn block_from_row(row: &SqliteRow) -> Result<ChainBlock, DatabaseError> {
+    Ok(ChainBlock {
+        shard_id: int(row, "shard_id")?,
+        height: int(row, "height")?,
+        hash: row.try_get("hash")?,
+        previous_hash: row.try_get("previous_hash")?,
+        timestamp: int(row, "timestamp")?,
+        transactions: Vec::new(),
+        finalized: row.try_get("finalized")?,
+    })
+}
+
+fn transaction_from_row(
+    row: &SqliteRow,
+    finalized: bool,
+) -> Result<ChainTransaction, DatabaseError> {
+    Ok(ChainTransaction {
+        hash: row.try_get("hash")?,
+        shard_id: int(row, "shard_id")?,
+        height: int(row, "height")?,
+        index: row.try_get("position")?,
+        from: row.try_get("sender")?,
+        to: row.try_get("recipient")?,
+        amount: row.try_get("amount")?,
+        nonce: int(row, "nonce")?,
+        finalized,
+    })
+}
+
+fn receipt_from_row(row: &SqliteRow) -> Result<SequencingReceipt, DatabaseError> {
+    Ok(SequencingReceipt {
+        sequence: int(row, "sequence")?,
+        order_id: int(row, "order_id")?,
+        command_hash: row.try_get("command_hash")?,
+        previous_hash: row.try_get("previous_hash")?,
+        timestamp: int(row, "timestamp")?,
+        region: row.try_get("region")?,
+        signature: row.try_get("signature")?,
+    })
+}
+
+#[async_trait]
+impl SettlementRepo for SqliteStorage {
+    async fn save_netting_set(&self, set: &NettingSet) -> Result<Option<u64>, DatabaseError> {
+        let unattributed = set
+            .unattributed_trade_ids
+            .iter()
+            .map(|id| to_column(*id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut tx = self.pool.begin().await?;
+        let Some(row) = query(
+            r#"
+            INSERT INTO netting_sets (
+                created_at, created_by, after_trade_id, last_trade_id, trade_count,
+                gross_transfers, unattributed_trade_ids
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (after_trade_id) DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(to_column(set.created_at)?)
+        .bind(set.created_by.as_str())
+        .bind(to_column(set.after_trade_id)?)
+        .bind(to_column(set.last_trade_id)?)
+        .bind(to_column(set.trade_count)?)
+        .bind(to_column(set.gross_transfers)?)
+        .bind(to_json(&unattributed))
+        .fetch_optional(&mut *tx)
+        .await?
+        else {
+            return Ok(None);
+        };
+        let id: i64 = row.try_get("id")?;
+        for position in &set.positions {
+            query(
+                r#"
+                INSERT INTO netting_positions (netting_set_id, trader_id, token, delivered, received)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+            )
+            .bind(id)
+            .bind(position.trader_id.as_str())
+            .bind(position.token.as_str())
+            .bind(position.delivered.to_string())
+            .bind(position.received.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+        for (seq, transfer) in set.transfers.iter().enumerate() {
+            query(
+                r#"
+                INSERT INTO netting_transfers (netting_set_id, seq, token, from_trader, to_trader, amount)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                "#,
+            )
+            .bind(id)
+            .bind(seq as i64 + 1)
+            .bind(transfer.token.as_str())
+            .bind(transfer.from_trader.as_str())
+            .bind(transfer.to_trader.as_str())
+            .bind(transfer.amount.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(Some(id as u64))
+    }
+
+    async fn load_netting_set(&self, id: u64) -> Result<Option<NettingSet>, DatabaseError> {
+        let id = to_column(id)?;
+        let Some(row) = query("SELECT * FROM netting_sets WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let positions = query(
+            r#"
+            SELECT trader_id, token, delivered, received
+            FROM netting_positions
+            WHERE netting_set_id = $1
+            ORDER BY token ASC, trader_id ASC
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        let transfers = query(
+            r#"
+            SELECT token, from_trader, to_trader, amount
+            FROM netting_transfers
+            WHERE netting_set_id = $1
+            ORDER BY seq ASC
+            "#,
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Some(NettingSet {
+            id: int(&row, "id")?,
+            created_at: int(&row, "created_at")?,
+            created_by: row.try_get("created_by")?,
+            after_trade_id: int(&row, "after_trade_id")?,
+            last_trade_id: int(&row, "last_trade_id")?,
+            trade_count: int(&row, "trade_count")?,
+            gross_transfers: int(&row, "gross_transfers")?,
+            unattributed_trade_ids: json::<Vec<i64>>(&row, "unattributed_trade_ids")?
+                .into_iter()
+                .map(|id| id as TradeId)
+                .collect(),
+            positions: positions
+                .iter()
+                .map(|row| {
+                    Ok(NetPosition {
+                        trader_id: row.try_get("trader_id")?,
+                        token: row.try_get("token")?,
+                        delivered: parsed(row, "delivered")?,
+                        received: parsed(row, "received")?,
+                    })
+                })
+                .collect::<Result<_, DatabaseError>>()?,
+            transfers: transfers
+                .iter()
+                .map(|row| {
+                    Ok(NetTransfer {
+                        token: row.try_get("token")?,
+                        from_trader: row.try_get("from_trader")?,
+                        to_trader: row.try_get("to_trader")?,
+                        amount: parsed(row, "amount")?,
+                    })
+                })
+                .collect::<Result<_, DatabaseError>>()?,
+        }))
+    }
+
+    async fn last_netted_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        let row = query("SELECT COALESCE(MAX(last_trade_id), 0) AS last_id FROM netting_sets")
+            .fetch_one(&self.pool)
+            .await?;
+        int(&row, "last_id")
+    }
+}
+
+#[async_trait]
+impl CounterRepo for SqliteStorage {
+    async fn save_counters(&self, counters: &[MarketCounters]) -> Result<(), DatabaseError> {
+        // Volumes are text, so the larger of each total is kept in Rust
+        // rather than by the upsert.
+        let mut tx = self.pool.begin().await?;
+        for counters in counters {
+            let stored =
+                query("SELECT * FROM market_counters WHERE base_token = $1 AND quote_token = $2")
+                    .bind(counters.base_token.as_str())
+                    .bind(counters.quote_token.as_str())
+                    .fetch_optional(&mut *tx)
+                    .await?
+                    .as_ref()
+                    .map(counters_from_row)
+                    .transpose()?;
+            let merged = match stored {
+                Some(stored) => {
+                    let newer = stored.last_trade_time <= counters.last_trade_time;
+                    MarketCounters {
+                        trade_count: stored.trade_count.max(counters.trade_count),
+                        base_volume: stored.base_volume.max(counters.base_volume),
+                        quote_volume: stored.quote_volume.max(counters.quote_volume),
+                        last_price: if newer {
+                            counters.last_price.or(stored.last_price)
+                        } else {
+                            stored.last_price
+                        },
+                        last_trade_time: stored.last_trade_time.max(counters.last_trade_time),
+                        ..stored
+                    }
+                }
+                None => counters.clone(),
+            };
+            query(
+                r#"
+                INSERT OR REPLACE INTO market_counters (
+                    base_token, quote_token, trade_count, base_volume, quote_volume,
+                    last_price, last_trade_time
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(merged.base_token.as_str())
+            .bind(merged.quote_token.as_str())
+            .bind(to_column(merged.trade_count)?)
+            .bind(merged.base_volume.to_string())
+            .bind(merged.quote_volume.to_string())
+            .bind(opt_column(merged.last_price)?)
+            .bind(opt_column(merged.last_trade_time)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_counters(&self) -> Result<Vec<MarketCounters>, DatabaseError> {
+        let rows = query("SELECT * FROM market_counters ORDER BY base_token, quote_token")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(counters_from_row).collect()
+    }
+}
+
+#[async_trait]
+impl CandleRepo for SqliteStorage {
+    async fn save_candles(&self, candles: &[CandleRecord]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for candle in candles {
+            query(
+                r#"
+                INSERT OR REPLACE INTO candles (
+                    base_token, quote_token, open_time, open, high, low, close, volume, trades
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                "#,
+            )
+            .bind(candle.base_token.as_str())
+            .bind(candle.quote_token.as_str())
+            .bind(to_column(candle.open_time)?)
+            .bind(to_column(candle.open)?)
+            .bind(to_column(candle.high)?)
+            .bind(to_column(candle.low)?)
+            .bind(to_column(candle.close)?)
+            .bind(to_column(candle.volume)?)
+            .bind(to_column(candle.trades)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn delete_candle(&self, pair: &TradingPair, open_time: u64) -> Result<(), DatabaseError> {
+        query("DELETE FROM candles WHERE base_token = $1 AND quote_token = $2 AND open_time = $3")
+            .bind(pair.base().as_str())
+            .bind(pair.quote().as_str())
+            .bind(to_column(open_time)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_candles(
+        &self,
+        pair: &TradingPair,
+        from: u64,
+        to: u64,
+        limit: u32,
+    ) -> Result<Vec<CandleRecord>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT * FROM candles
+            WHERE base_token = $1 AND quote_token = $2 AND open_time >= $3 AND open_time < $4
+            ORDER BY open_time ASC
+            LIMIT $5
+            "#,
+        )
+        .bind(pair.base().as_str())
+        .bind(pair.quote().as_str())
+        .bind(to_column(from)?)
+        .bind(to_column(to)?)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(candle_from_row).collect()
+    }
+
+    async fn candle_watermark(&self) -> Result<u64, DatabaseError> {
+        let row = query("SELECT COALESCE(MAX(through), 0) AS through FROM candle_watermark")
+            .fetch_one(&self.pool)
+            .await?;
+        int(&row, "through")
+    }
+
+    async fn advance_candle_watermark(&self, through: u64) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO candle_watermark (id, through) VALUES (1, $1)
+            ON CONFLICT (id) DO UPDATE SET through = MAX(through, excluded.through)
+            "#,
+        )
+        .bind(to_column(through)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TickMapRepo for SqliteStorage {
+    async fn save_tick_maps(&self, maps: &[TickMapBlob]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for map in maps {
+            query(
+                r#"
+                INSERT INTO amm_tick_maps (base_token, quote_token, sequence, tick_count, data)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (base_token, quote_token) DO UPDATE SET
+                    sequence = excluded.sequence,
+                    tick_count = excluded.tick_count,
+                    data = excluded.data
+                WHERE excluded.sequence > amm_tick_maps.sequence
+                "#,
+            )
+            .bind(map.base_token.as_str())
+            .bind(map.quote_token.as_str())
+            .bind(to_column(map.sequence)?)
+            .bind(to_column(map.tick_count)?)
+            .bind(map.data.as_slice())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_tick_maps(&self) -> Result<Vec<TickMapBlob>, DatabaseError> {
+        let rows = query("SELECT * FROM amm_tick_maps ORDER BY base_token, quote_token")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(TickMapBlob {
+                    base_token: parsed(row, "base_token")?,
+                    quote_token: parsed(row, "quote_token")?,
+                    sequence: int(row, "sequence")?,
+                    tick_count: int(row, "tick_count")?,
+                    data: row.try_get("data")?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PoolRepo for SqliteStorage {
+    async fn save_pools(&self, pools: &[PoolRecord]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for pool in pools {
+            let replaced = query(
+                r#"
+                INSERT INTO pools (
+                    base_token, quote_token, sequence, fee_bps, reserve_base, reserve_quote,
+                    lp_supply, fee_growth_base, fee_growth_quote
+                ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ON CONFLICT (base_token, quote_token) DO UPDATE SET
+                    sequence = excluded.sequence,
+                    fee_bps = excluded.fee_bps,
+                    reserve_base = excluded.reserve_base,
+                    reserve_quote = excluded.reserve_quote,
+                    lp_supply = excluded.lp_supply,
+                    fee_growth_base = excluded.fee_growth_base,
+                    fee_growth_quote = excluded.fee_growth_quote
+                WHERE excluded.sequence > pools.sequence
+                "#,
+            )
+            .bind(pool.base_token.as_str())
+            .bind(pool.quote_token.as_str())
+            .bind(to_column(pool.sequence)?)
+            .bind(pool.fee_bps)
+            .bind(to_column(pool.reserve_base)?)
+            .bind(to_column(pool.reserve_quote)?)
+            .bind(to_column(pool.lp_supply)?)
+            .bind(pool.fee_growth_base.to_string())
+            .bind(pool.fee_growth_quote.to_string())
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+                > 0;
+            if replaced {
+                replace_positions(&mut tx, pool).await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_pools(&self) -> Result<Vec<PoolRecord>, DatabaseError> {
+        let pools = query("SELECT * FROM pools ORDER BY base_token, quote_token")
+            .fetch_all(&self.pool)
+            .await?;
+        let positions =
+            query("SELECT * FROM pool_positions ORDER BY base_token, quote_token, trader_id")
+                .fetch_all(&self.pool)
+                .await?;
+        pools
+            .iter()
+            .map(|row| {
+                let base: &str = row.try_get("base_token")?;
+                let quote: &str = row.try_get("quote_token")?;
+                Ok(PoolRecord {
+                    base_token: parsed(row, "base_token")?,
+                    quote_token: parsed(row, "quote_token")?,
+                    sequence: int(row, "sequence")?,
+                    fee_bps: row.try_get("fee_bps")?,
+                    reserve_base: int(row, "reserve_base")?,
+                    reserve_quote: int(row, "reserve_quote")?,
+                    lp_supply: int(row, "lp_supply")?,
+                    fee_growth_base: parsed(row, "fee_growth_base")?,
+                    fee_growth_quote: parsed(row, "fee_growth_quote")?,
+                    positions: positions
+                        .iter()
+                        .filter(|position| {
+                            position.get::<&str, _>("base_token") == base
+                                && position.get::<&str, _>("quote_token") == quote
+                        })
+                        .map(pool_position_from_row)
+                        .collect::<Result<_, _>>()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Replace the stored positions of `pool` with its own, through `conn`.
+async fn replace_positions(
+    conn: &mut SqliteConnection,
+    pool: &PoolRecord,
+) -> Result<(), DatabaseError> {
+    query("DELETE FROM pool_positions WHERE base_token = $1 AND quote_token = $2")
+        .bind(pool.base_token.as_str())
+        .bind(pool.quote_token.as_str())
+        .execute(&mut *conn)
+        .await?;
+    for position in &pool.positions {
+        query(
+            r#"
+            INSERT INTO pool_positions (
+                base_token, quote_token, trader_id, lp_tokens, deposited_base, deposited_quote,
+                fee_checkpoint_base, fee_checkpoint_quote, fees_base, fees_quote,
+                fees_withdrawn_base, fees_withdrawn_quote
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+        )
+        .bind(pool.base_token.as_str())
+        .bind(pool.quote_token.as_str())
+        .bind(position.trader_id.to_string())
+        .bind(to_column(position.lp_tokens)?)
+        .bind(position.deposited.0.to_string())
+        .bind(position.deposited.1.to_string())
+        .bind(position.fee_checkpoint.0.to_string())
+        .bind(position.fee_checkpoint.1.to_string())
+        .bind(position.fees.0.to_string())
+        .bind(position.fees.1.to_string())
+        .bind(position.fees_withdrawn.0.to_string())
+        .bind(position.fees_withdrawn.1.to_string())
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl CustodyRepo for SqliteStorage {
+    async fn save_custody_balance(&self, balance: &CustodyBalance) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO custody_balances (wallet_id, token, balance, updated_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (wallet_id, token) DO UPDATE SET
+                balance = excluded.balance,
+                updated_at = excluded.updated_at
+            WHERE excluded.updated_at >= custody_balances.updated_at
+            "#,
+        )
+        .bind(balance.wallet_id.as_str())
+        .bind(balance.token.as_str())
+        .bind(balance.balance.to_string())
+        .bind(to_column(balance.updated_at)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load_custody_balances(&self) -> Result<Vec<CustodyBalance>, DatabaseError> {
+        let rows = query("SELECT * FROM custody_balances ORDER BY wallet_id ASC, token ASC")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(CustodyBalance {
+                    wallet_id: row.try_get("wallet_id")?,
+                    token: parsed(row, "token")?,
+                    balance: parsed(row, "balance")?,
+                    updated_at: int(row, "updated_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl SqliteStorage {
+    /// Fill in the transactions of `blocks`.
+    async fn attach_transactions(&self, blocks: &mut [ChainBlock]) -> Result<(), DatabaseError> {
+        for block in blocks {
+            let rows = query(
+                r#"
+                SELECT * FROM chain_transactions
+                WHERE shard_id = $1 AND height = $2
+                ORDER BY position ASC
+                "#,
+            )
+            .bind(to_column(block.shard_id)?)
+            .bind(to_column(block.height)?)
+            .fetch_all(&self.pool)
+            .await?;
+            block.transactions = rows
+                .iter()
+                .map(|row| transaction_from_row(row, block.finalized))
+                .collect::<Result<_, _>>()?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChainRepo for SqliteStorage {
+    async fn save_block(&self, block: &ChainBlock) -> Result<(), DatabaseError> {
+        let shard_id = to_column(block.shard_id)?;
+        let height = to_column(block.height)?;
+        let mut tx = self.pool.begin().await?;
+        let stored = query(
+            r#"
+            INSERT INTO chain_blocks (shard_id, height, hash, previous_hash, timestamp, finalized)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (shard_id, height) DO NOTHING
+            "#,
+        )
+        .bind(shard_id)
+        .bind(height)
+        .bind(block.hash.as_str())
+        .bind(block.previous_hash.as_str())
+        .bind(to_column(block.timestamp)?)
+        .bind(block.finalized)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if !stored {
+            return Ok(());
+        }
+        for (position, transaction) in block.transactions.iter().enumerate() {
+            query(
+                r#"
+                INSERT INTO chain_transactions
+                    (hash, shard_id, height, position, sender, recipient, amount, nonce)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                ON CONFLICT (hash) DO NOTHING
+                "#,
+            )
+            .bind(transaction.hash.as_str())
+            .bind(shard_id)
+            .bind(height)
+            .bind(position as i64)
+            .bind(transaction.from.as_str())
+            .bind(transaction.to.as_str())
+            .bind(transaction.amount)
+            .bind(to_column(transaction.nonce)?)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_blocks_at(&self, height: u64) -> Result<Vec<ChainBlock>, DatabaseError> {
+        let rows = query("SELECT * FROM chain_blocks WHERE height = $1 ORDER BY shard_id ASC")
+            .bind(to_column(height)?)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut blocks = rows
+            .iter()
+            .map(block_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.attach_transactions(&mut blocks).await?;
+        Ok(blocks)
+    }
+
+    async fn load_transaction(
+        &self,
+        hash: &str,
+    ) -> Result<Option<ChainTransaction>, DatabaseError> {
+        let row = query(
+            r#"
+            SELECT t.*, b.finalized
+            FROM chain_transactions t
+            JOIN chain_blocks b USING (shard_id, height)
+            WHERE t.hash = $1
+            "#,
+        )
+        .bind(hash)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.map(|row| transaction_from_row(&row, row.try_get("finalized")?))
+            .transpose()
+    }
+
+    async fn finalize_blocks(
+        &self,
+        shard_id: u64,
+        height: u64,
+    ) -> Result<Vec<ChainBlock>, DatabaseError> {
+        let rows = query(
+            r#"
+            UPDATE chain_blocks SET finalized = TRUE
+            WHERE shard_id = $1 AND height <= $2 AND NOT finalized
+            RETURNING *
+            "#,
+        )
+        .bind(to_column(shard_id)?)
+        .bind(to_column(height)?)
+        .fetch_all(&self.pool)
+        .await?;
+        let mut blocks = rows
+            .iter()
+            .map(block_from_row)
+            .collect::<Result<Vec<_>, _>>()?;
+        blocks.sort_by_key(|block| block.height);
+        self.attach_transactions(&mut blocks).await?;
+        Ok(blocks)
+    }
+}
+
+#[async_trait]
+impl ReceiptRepo for SqliteStorage {
+    async fn save_receipt(&self, receipt: &SequencingReceipt) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO sequencing_receipts (
+                sequence, order_id, command_hash, previous_hash, timestamp, region, signature
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (sequence) DO NOTHING
+            "#,
+        )
+        .bind(to_column(receipt.sequence)?)
+        .bind(to_column(receipt.order_id)?)
+        .bind(receipt.command_hash.as_str())
+        .bind(receipt.previous_hash.as_str())
+        .bind(to_column(receipt.timestamp)?)
+        .bind(receipt.region.as_str())
+        .bind(receipt.signature.as_str())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load_receipt(
+        &self,
+        order_id: OrderId,
+    ) -> Result<Option<SequencingReceipt>, DatabaseError> {
+        let row = query(
+            "SELECT * FROM sequencing_receipts WHERE order_id = $1 ORDER BY sequence LIMIT 1",
+        )
+        .bind(to_column(order_id)?)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(receipt_from_row).transpose()
+    }
+
+    async fn latest_receipt(&self) -> Result<Option<SequencingReceipt>, DatabaseError> {
+        let row = query("SELECT * FROM sequencing_receipts ORDER BY sequence DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(receipt_from_row).transpose()
+    }
+}
@@ -0,0 +1,455 @@
+//! Schema of the SQLite backend.
+//!
+//! Versioned and checksummed like the Postgres migrations, in a
+//! `migrations` table of their own, but numbered separately: the SQLite
+//! schema started from the Postgres one as it stood, not from its history.
+//!
+//! SQLite has no unsigned or 128-bit integers either. IDs, prices and
+//! quantities are `INTEGER` and converted like the Postgres `BIGINT`s;
+//! balances, volumes and other `u128` amounts are decimal `TEXT`, and are
+//! compared and summed in Rust rather than in SQL.
+
+use crate::migrations::{check_drift, Migration, MigrationError};
+use sqlx_core::{query::query, raw_sql::raw_sql, row::Row};
+use sqlx_sqlite::SqlitePool;
+use std::collections::BTreeMap;
+
+/// Get all migrations
+pub fn get_migrations() -> Vec<Migration> {
+    vec![Migration {
+        version: 1,
+        description: "Create the schema",
+        sql: r#"
+                CREATE TABLE orders (
+                    id INTEGER PRIMARY KEY,
+                    trader_id TEXT NOT NULL,
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    side TEXT NOT NULL,
+                    order_type TEXT NOT NULL,
+                    price INTEGER,
+                    quantity INTEGER NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    status TEXT NOT NULL DEFAULT 'open',
+                    remaining_quantity INTEGER NOT NULL,
+                    deleted_at INTEGER
+                );
+                CREATE INDEX idx_orders_trader ON orders (trader_id);
+                CREATE INDEX idx_orders_status ON orders (status);
+                CREATE TABLE orders_archive AS SELECT * FROM orders WHERE 0;
+
+                CREATE TABLE trades (
+                    id INTEGER PRIMARY KEY,
+                    maker_order_id INTEGER NOT NULL,
+                    taker_order_id INTEGER NOT NULL,
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    price INTEGER NOT NULL,
+                    quantity INTEGER NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    busted INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX idx_trades_maker ON trades (maker_order_id);
+                CREATE INDEX idx_trades_taker ON trades (taker_order_id);
+                CREATE INDEX idx_trades_pair ON trades (base_token, quote_token, id);
+                CREATE TABLE trades_archive AS SELECT * FROM trades WHERE 0;
+
+                CREATE TABLE trade_adjustments (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    trade_id INTEGER NOT NULL,
+                    kind TEXT NOT NULL,
+                    previous_price INTEGER NOT NULL,
+                    new_price INTEGER,
+                    reason TEXT NOT NULL,
+                    adjusted_by TEXT NOT NULL,
+                    adjusted_at INTEGER NOT NULL
+                );
+                CREATE INDEX idx_trade_adjustments_trade ON trade_adjustments (trade_id);
+
+                CREATE TABLE id_sequences (
+                    name TEXT PRIMARY KEY,
+                    last_value INTEGER NOT NULL
+                );
+
+                CREATE TABLE amm_swaps (
+                    id INTEGER PRIMARY KEY,
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    taker_side TEXT NOT NULL,
+                    price INTEGER NOT NULL,
+                    quantity INTEGER NOT NULL,
+                    quote_quantity INTEGER NOT NULL,
+                    timestamp INTEGER NOT NULL
+                );
+
+                CREATE TABLE api_keys (
+                    key_id TEXT PRIMARY KEY,
+                    sealed_secret TEXT NOT NULL,
+                    subject TEXT NOT NULL,
+                    scope TEXT NOT NULL,
+                    label TEXT,
+                    created_at INTEGER NOT NULL,
+                    revoked INTEGER NOT NULL DEFAULT 0,
+                    allowed_ips TEXT NOT NULL DEFAULT '[]'
+                );
+                CREATE INDEX idx_api_keys_subject ON api_keys (subject);
+
+                CREATE TABLE api_usage_daily (
+                    subject TEXT NOT NULL,
+                    day INTEGER NOT NULL,
+                    credential TEXT NOT NULL,
+                    requests INTEGER NOT NULL,
+                    errors INTEGER NOT NULL,
+                    throttled INTEGER NOT NULL,
+                    orders INTEGER NOT NULL,
+                    fills INTEGER NOT NULL,
+                    ws_messages INTEGER NOT NULL,
+                    ws_bytes INTEGER NOT NULL,
+                    PRIMARY KEY (subject, day, credential)
+                );
+                CREATE INDEX idx_api_usage_daily_day ON api_usage_daily (day);
+
+                CREATE TABLE messaging_penalties (
+                    subject TEXT NOT NULL,
+                    day INTEGER NOT NULL,
+                    orders INTEGER NOT NULL,
+                    fills INTEGER NOT NULL,
+                    max_ratio REAL NOT NULL,
+                    fee INTEGER NOT NULL,
+                    rate_limit_percent INTEGER NOT NULL,
+                    expires_at INTEGER NOT NULL,
+                    PRIMARY KEY (subject, day)
+                );
+
+                CREATE TABLE refresh_tokens (
+                    token_hash TEXT PRIMARY KEY,
+                    family_id TEXT NOT NULL,
+                    subject TEXT NOT NULL,
+                    audience TEXT,
+                    scope TEXT NOT NULL,
+                    expires_at INTEGER NOT NULL,
+                    revoked INTEGER NOT NULL DEFAULT 0,
+                    signed_in_at INTEGER NOT NULL,
+                    issued_at INTEGER NOT NULL,
+                    access_jti TEXT,
+                    access_expires_at INTEGER NOT NULL
+                );
+                CREATE INDEX idx_refresh_tokens_family ON refresh_tokens (family_id);
+                CREATE INDEX idx_refresh_tokens_subject ON refresh_tokens (subject);
+
+                CREATE TABLE revoked_tokens (
+                    jti TEXT PRIMARY KEY,
+                    expires_at INTEGER NOT NULL
+                );
+
+                CREATE TABLE wallet_challenges (
+                    subject TEXT PRIMARY KEY,
+                    message TEXT NOT NULL,
+                    typed_data TEXT,
+                    expires_at INTEGER NOT NULL
+                );
+
+                CREATE TABLE totp_secrets (
+                    subject TEXT PRIMARY KEY,
+                    sealed_secret TEXT NOT NULL,
+                    confirmed INTEGER NOT NULL DEFAULT 0,
+                    last_step INTEGER NOT NULL DEFAULT 0,
+                    created_at INTEGER NOT NULL
+                );
+
+                CREATE TABLE audit_log (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    occurred_at INTEGER NOT NULL,
+                    subject TEXT NOT NULL,
+                    ip TEXT,
+                    action TEXT NOT NULL,
+                    target TEXT NOT NULL,
+                    outcome TEXT NOT NULL,
+                    status INTEGER
+                );
+                CREATE INDEX idx_audit_log_subject ON audit_log (subject, id);
+
+                CREATE TABLE netting_sets (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    created_at INTEGER NOT NULL,
+                    created_by TEXT NOT NULL,
+                    after_trade_id INTEGER NOT NULL UNIQUE,
+                    last_trade_id INTEGER NOT NULL,
+                    trade_count INTEGER NOT NULL,
+                    gross_transfers INTEGER NOT NULL,
+                    unattributed_trade_ids TEXT NOT NULL
+                );
+                CREATE TABLE netting_positions (
+                    netting_set_id INTEGER NOT NULL REFERENCES netting_sets (id),
+                    trader_id TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    delivered TEXT NOT NULL,
+                    received TEXT NOT NULL,
+                    PRIMARY KEY (netting_set_id, trader_id, token)
+                );
+                CREATE TABLE netting_transfers (
+                    netting_set_id INTEGER NOT NULL REFERENCES netting_sets (id),
+                    seq INTEGER NOT NULL,
+                    token TEXT NOT NULL,
+                    from_trader TEXT NOT NULL,
+                    to_trader TEXT NOT NULL,
+                    amount TEXT NOT NULL,
+                    PRIMARY KEY (netting_set_id, seq)
+                );
+
+                CREATE TABLE market_counters (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    trade_count INTEGER NOT NULL,
+                    base_volume TEXT NOT NULL,
+                    quote_volume TEXT NOT NULL,
+                    last_price INTEGER,
+                    last_trade_time INTEGER,
+                    PRIMARY KEY (base_token, quote_token)
+                );
+
+                CREATE TABLE candles (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    open_time INTEGER NOT NULL,
+                    open INTEGER NOT NULL,
+                    high INTEGER NOT NULL,
+                    low INTEGER NOT NULL,
+                    close INTEGER NOT NULL,
+                    volume INTEGER NOT NULL,
+                    trades INTEGER NOT NULL,
+                    PRIMARY KEY (base_token, quote_token, open_time)
+                );
+                CREATE TABLE candle_watermark (
+                    id INTEGER PRIMARY KEY CHECK (id = 1),
+                    through INTEGER NOT NULL
+                );
+
+                CREATE TABLE amm_tick_maps (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    sequence INTEGER NOT NULL,
+                    tick_count INTEGER NOT NULL,
+                    data BLOB NOT NULL,
+                    PRIMARY KEY (base_token, quote_token)
+                );
+
+                CREATE TABLE pools (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    sequence INTEGER NOT NULL,
+                    fee_bps INTEGER NOT NULL,
+                    reserve_base INTEGER NOT NULL,
+                    reserve_quote INTEGER NOT NULL,
+                    lp_supply INTEGER NOT NULL,
+                    fee_growth_base TEXT NOT NULL,
+                    fee_growth_quote TEXT NOT NULL,
+                    PRIMARY KEY (base_token, quote_token)
+                );
+                CREATE TABLE pool_positions (
+                    base_token TEXT NOT NULL,
+                    quote_token TEXT NOT NULL,
+                    trader_id TEXT NOT NULL,
+                    lp_tokens INTEGER NOT NULL,
+                    deposited_base TEXT NOT NULL,
+                    deposited_quote TEXT NOT NULL,
+                    fee_checkpoint_base TEXT NOT NULL,
+                    fee_checkpoint_quote TEXT NOT NULL,
+                    fees_base TEXT NOT NULL,
+                    fees_quote TEXT NOT NULL,
+                    fees_withdrawn_base TEXT NOT NULL,
+                    fees_withdrawn_quote TEXT NOT NULL,
+                    PRIMARY KEY (base_token, quote_token, trader_id)
+                );
+
+                CREATE TABLE custody_balances (
+                    wallet_id TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    balance TEXT NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    PRIMARY KEY (wallet_id, token)
+                );
+
+                CREATE TABLE chain_blocks (
+                    shard_id INTEGER NOT NULL,
+                    height INTEGER NOT NULL,
+                    hash TEXT NOT NULL,
+                    previous_hash TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    finalized INTEGER NOT NULL,
+                    PRIMARY KEY (shard_id, height)
+                );
+                CREATE INDEX idx_chain_blocks_height ON chain_blocks (height);
+                CREATE TABLE chain_transactions (
+                    hash TEXT PRIMARY KEY,
+                    shard_id INTEGER NOT NULL,
+                    height INTEGER NOT NULL,
+                    position INTEGER NOT NULL,
+                    sender TEXT NOT NULL,
+                    recipient TEXT NOT NULL,
+                    amount INTEGER NOT NULL,
+                    nonce INTEGER NOT NULL
+                );
+                CREATE INDEX idx_chain_transactions_block
+                    ON chain_transactions (shard_id, height, position);
+
+                CREATE TABLE sequencing_receipts (
+                    sequence INTEGER PRIMARY KEY,
+                    order_id INTEGER NOT NULL,
+                    command_hash TEXT NOT NULL,
+                    previous_hash TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    region TEXT NOT NULL,
+                    signature TEXT NOT NULL
+                );
+                CREATE INDEX idx_sequencing_receipts_order ON sequencing_receipts (order_id);
+
+                CREATE TABLE ledger_entries (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    kind TEXT NOT NULL,
+                    reference TEXT NOT NULL,
+                    timestamp INTEGER NOT NULL,
+                    UNIQUE (kind, reference)
+                );
+                CREATE TABLE ledger_postings (
+                    entry_id INTEGER NOT NULL REFERENCES ledger_entries (id),
+                    line INTEGER NOT NULL,
+                    account TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    direction TEXT NOT NULL,
+                    amount TEXT NOT NULL,
+                    PRIMARY KEY (entry_id, line)
+                );
+                CREATE INDEX idx_ledger_postings_account ON ledger_postings (account, entry_id);
+
+                CREATE TABLE event_outbox (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    topic TEXT NOT NULL,
+                    key TEXT NOT NULL,
+                    payload TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    published_at INTEGER
+                );
+                CREATE INDEX idx_event_outbox_pending ON event_outbox (id) WHERE published_at IS NULL;
+
+                CREATE TABLE webhooks (
+                    id TEXT PRIMARY KEY,
+                    trader_id TEXT NOT NULL,
+                    url TEXT NOT NULL,
+                    secret TEXT NOT NULL,
+                    events TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    revoked INTEGER NOT NULL DEFAULT 0
+                );
+                CREATE INDEX idx_webhooks_trader ON webhooks (trader_id);
+                CREATE TABLE webhook_deliveries (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    webhook_id TEXT NOT NULL REFERENCES webhooks (id),
+                    payload TEXT NOT NULL,
+                    status TEXT NOT NULL,
+                    attempts INTEGER NOT NULL,
+                    next_attempt_at INTEGER NOT NULL,
+                    last_error TEXT
+                );
+                CREATE INDEX idx_webhook_deliveries_due
+                    ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
+
+                CREATE TABLE funding_transfers (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    trader_id TEXT NOT NULL,
+                    kind TEXT NOT NULL,
+                    chain TEXT NOT NULL,
+                    token TEXT NOT NULL,
+                    amount TEXT NOT NULL,
+                    address TEXT,
+                    reference TEXT,
+                    tx_hash TEXT,
+                    status TEXT NOT NULL,
+                    created_at INTEGER NOT NULL,
+                    updated_at INTEGER NOT NULL,
+                    UNIQUE (kind, chain, reference)
+                );
+                CREATE INDEX idx_funding_transfers_trader ON funding_transfers (trader_id, id)
+            "#,
+    }]
+}
+
+/// Applied versions with the checksums recorded for them; empty before the
+/// first run.
+async fn applied_migrations(
+    pool: &SqlitePool,
+) -> Result<BTreeMap<i32, Option<String>>, sqlx_core::error::Error> {
+    let exists = query("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'migrations'")
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    if !exists {
+        return Ok(BTreeMap::new());
+    }
+    let rows = query("SELECT version, checksum FROM migrations")
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| (row.get("version"), row.get("checksum")))
+        .collect())
+}
+
+/// Migrations not yet applied, in order, after checking the applied ones for
+/// drift. Changes nothing, so it serves as a dry run.
+pub async fn pending_migrations(pool: &SqlitePool) -> Result<Vec<Migration>, MigrationError> {
+    let applied = applied_migrations(pool).await?;
+    let migrations = get_migrations();
+    check_drift(&migrations, &applied)?;
+    Ok(migrations
+        .into_iter()
+        .filter(|migration| !applied.contains_key(&migration.version))
+        .collect())
+}
+
+/// Run all pending migrations, refusing to when an applied one has changed.
+/// Each runs in a transaction with the row recording it.
+pub async fn run_migrations(pool: &SqlitePool) -> Result<(), MigrationError> {
+    raw_sql(
+        r#"
+        CREATE TABLE IF NOT EXISTS migrations (
+            version INTEGER PRIMARY KEY,
+            description TEXT NOT NULL,
+            checksum TEXT NOT NULL,
+            applied_at INTEGER NOT NULL DEFAULT (unixepoch())
+        )
+        "#,
+    )
+    .execute(pool)
+    .await?;
+
+    for migration in pending_migrations(pool).await? {
+        tracing::info!(
+            version = migration.version,
+            description = migration.description,
+            "running migration"
+        );
+        let mut tx = pool.begin().await?;
+        raw_sql(migration.sql).execute(&mut *tx).await?;
+        query("INSERT INTO migrations (version, description, checksum) VALUES ($1, $2, $3)")
+            .bind(migration.version)
+            .bind(migration.description)
+            .bind(migration.checksum())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_unique_and_ascending() {
+        let versions: Vec<i32> = get_migrations().iter().map(|m| m.version).collect();
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,116 @@
+//! SQLite implementation of every repository.
+//!
+//! A single file holds everything, so small deployments and tests get
+//! storage that survives a restart without a Postgres server. It does not
+//! suit several instances: they cannot share the file, so leader election
+//! and read replicas need Postgres.
+//!
+//! SQLite lets one connection write at a time, and a transaction that
+//! starts reading and then writes fails rather than waits when another
+//! holds the lock. The pool therefore keeps a single connection: statements
+//! queue for it instead, and every multi-statement write runs in a
+//! transaction of its own.
+
+use crate::{
+    migrations::Migration,
+    rows::{from_column, to_column},
+    DatabaseError, Storage,
+};
+use serde::de::DeserializeOwned;
+use sqlx_core::row::Row;
+use sqlx_sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use std::{path::Path, str::FromStr, time::Duration};
+
+mod accounts;
+mod ledger;
+mod markets;
+pub mod migrations;
+mod trading;
+
+/// Every repository over one SQLite database.
+#[derive(Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    /// Open the database file at `path`, creating it if missing.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self, DatabaseError> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Self::connect_with(options).await
+    }
+
+    /// A private database in memory, gone when the value is dropped. The
+    /// schema is created, so it is ready to use.
+    pub async fn in_memory() -> Result<Self, DatabaseError> {
+        let storage =
+            Self::connect_with(SqliteConnectOptions::from_str("sqlite::memory:")?).await?;
+        storage.initialize().await?;
+        Ok(storage)
+    }
+
+    async fn connect_with(options: SqliteConnectOptions) -> Result<Self, DatabaseError> {
+        let options = options
+            .foreign_keys(true)
+            .busy_timeout(Duration::from_secs(5));
+        // Never retired: an in-memory database lives only as long as its
+        // connection.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await?;
+        Ok(Self { pool })
+    }
+
+    /// Initialize the database schema
+    pub async fn initialize(&self) -> Result<(), DatabaseError> {
+        migrations::run_migrations(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Migrations `initialize` would run, without running them.
+    pub async fn pending_migrations(&self) -> Result<Vec<Migration>, DatabaseError> {
+        Ok(migrations::pending_migrations(&self.pool).await?)
+    }
+}
+
+impl Storage for SqliteStorage {}
+
+/// An `INTEGER` column as an unsigned value.
+fn int(row: &SqliteRow, column: &str) -> Result<u64, DatabaseError> {
+    from_column(row.try_get(column)?)
+}
+
+/// A nullable `INTEGER` column as an unsigned value.
+fn opt_int(row: &SqliteRow, column: &str) -> Result<Option<u64>, DatabaseError> {
+    row.try_get::<Option<i64>, _>(column)?
+        .map(from_column)
+        .transpose()
+}
+
+/// An optional unsigned value as a nullable `INTEGER` column.
+fn opt_column(value: Option<u64>) -> Result<Option<i64>, DatabaseError> {
+    value.map(to_column).transpose()
+}
+
+/// A text column parsed into its type, such as a token or a `u128` amount.
+fn parsed<T: FromStr>(row: &SqliteRow, column: &str) -> Result<T, DatabaseError> {
+    row.try_get::<&str, _>(column)?
+        .parse()
+        .map_err(|_| DatabaseError::DataIntegrityError)
+}
+
+/// A text column holding JSON, such as a list.
+fn json<T: DeserializeOwned>(row: &SqliteRow, column: &str) -> Result<T, DatabaseError> {
+    serde_json::from_str(row.try_get(column)?).map_err(|_| DatabaseError::DataIntegrityError)
+}
+
+/// A list as the JSON text column `json` reads back.
+fn to_json<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_string(value).expect("lists serialize")
+}
@@ -0,0 +1,593 @@
+//! SQLite implementation of `OrderRepo`, `TradeRepo`, `SwapRepo` and
+//! `RetentionRepo`.
+
+use super::{int, parsed, to_json, SqliteStorage};
+use crate::{
+    repository::{
+        AdjustmentKind, OrderFill, OrderRepo, RetentionRepo, SwapRecord, SwapRepo, TradeAdjustment,
+        TradeFilter, TradeRepo,
+    },
+    rows::{from_column, side_to_str, to_column, OrderRow, TradeAdjustmentRow, TradeRow},
+    DatabaseError,
+};
+use async_trait::async_trait;
+use dex_core::{
+    ledger::JournalEntry,
+    types::{Order, OrderId, OrderSide, Trade, TradeId, TraderId},
+};
+use sqlx_core::{query::query, row::Row};
+use sqlx_sqlite::{SqliteConnection, SqliteRow};
+
+const ORDER_COLUMNS: &str =
+    "id, trader_id, base_token, quote_token, side, order_type, price, quantity, timestamp";
+const TRADE_COLUMNS: &str =
+    "id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp";
+
+/// The filters of a `TradeFilter`, as `$1` to `$6`; the pair is given as
+/// its base and quote.
+const TRADE_FILTER: &str = "($1 IS NULL OR id > $1)
+    AND ($2 IS NULL OR (base_token = $2 AND quote_token = $3))
+    AND ($4 IS NULL OR timestamp >= $4)
+    AND ($5 IS NULL OR timestamp < $5)";
+
+/// Highest order ID in use, as `last_order_id` documents it.
+const LAST_ORDER_ID: &str = "MAX(
+    COALESCE((SELECT MAX(id) FROM orders), 0),
+    COALESCE((SELECT MAX(id) FROM orders_archive), 0),
+    COALESCE((SELECT MAX(MAX(maker_order_id, taker_order_id)) FROM trades), 0),
+    COALESCE((SELECT MAX(MAX(maker_order_id, taker_order_id)) FROM trades_archive), 0),
+    COALESCE((SELECT MAX(order_id) FROM sequencing_receipts), 0)
+)";
+
+/// Highest trade ID recorded, archived ones included.
+const LAST_TRADE_ID: &str = "MAX(
+    COALESCE((SELECT MAX(id) FROM trades), 0),
+    COALESCE((SELECT MAX(id) FROM trades_archive), 0)
+)";
+
+fn order_from_row(row: &SqliteRow) -> Result<Order, DatabaseError> {
+    Order::try_from(OrderRow {
+        id: row.try_get("id")?,
+        trader_id: row.try_get("trader_id")?,
+        base_token: row.try_get("base_token")?,
+        quote_token: row.try_get("quote_token")?,
+        side: row.try_get("side")?,
+        order_type: row.try_get("order_type")?,
+        price: row.try_get("price")?,
+        quantity: row.try_get("quantity")?,
+        timestamp: row.try_get("timestamp")?,
+    })
+}
+
+fn trade_from_row(row: &SqliteRow) -> Result<Trade, DatabaseError> {
+    Trade::try_from(TradeRow {
+        id: row.try_get("id")?,
+        maker_order_id: row.try_get("maker_order_id")?,
+        taker_order_id: row.try_get("taker_order_id")?,
+        base_token: row.try_get("base_token")?,
+        quote_token: row.try_get("quote_token")?,
+        price: row.try_get("price")?,
+        quantity: row.try_get("quantity")?,
+        timestamp: row.try_get("timestamp")?,
+    })
+}
+
+fn swap_from_row(row: &SqliteRow) -> Result<SwapRecord, DatabaseError> {
+    Ok(SwapRecord {
+        id: int(row, "id")?,
+        base_token: parsed(row, "base_token")?,
+        quote_token: parsed(row, "quote_token")?,
+        taker_side: match row.try_get::<&str, _>("taker_side")? {
+            "buy" => OrderSide::Buy,
+            "sell" => OrderSide::Sell,
+            _ => return Err(DatabaseError::DataIntegrityError),
+        },
+        price: int(row, "price")?,
+        quantity: int(row, "quantity")?,
+        quote_quantity: int(row, "quote_quantity")?,
+        timestamp: int(row, "timestamp")?,
+    })
+}
+
+/// Bound values of a `TradeFilter`, in the order of `TRADE_FILTER`.
+struct FilterParams {
+    after_id: Option<i64>,
+    base: Option<String>,
+    quote: Option<String>,
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: i64,
+}
+
+impl FilterParams {
+    fn new(filter: &TradeFilter) -> Result<Self, DatabaseError> {
+        Ok(Self {
+            after_id: filter.after_id.map(to_column).transpose()?,
+            base: filter.pair.as_ref().map(|pair| pair.base().to_string()),
+            quote: filter.pair.as_ref().map(|pair| pair.quote().to_string()),
+            from: filter.from.map(to_column).transpose()?,
+            to: filter.to.map(to_column).transpose()?,
+            limit: i64::from(filter.page_size()),
+        })
+    }
+}
+
+/// Insert `trade` through `conn` unless one with its ID is stored.
+async fn insert_trade(conn: &mut SqliteConnection, trade: &Trade) -> Result<(), DatabaseError> {
+    let row = TradeRow::try_from(trade)?;
+    query(
+        r#"
+        INSERT INTO trades (
+            id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (id) DO NOTHING
+        "#,
+    )
+    .bind(row.id)
+    .bind(row.maker_order_id)
+    .bind(row.taker_order_id)
+    .bind(row.base_token)
+    .bind(row.quote_token)
+    .bind(row.price)
+    .bind(row.quantity)
+    .bind(row.timestamp)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Set the status and remaining quantity of the orders in `fills` through
+/// `conn`; fills of orders not stored are skipped.
+async fn update_fills(
+    conn: &mut SqliteConnection,
+    fills: &[OrderFill],
+) -> Result<(), DatabaseError> {
+    for fill in fills {
+        query("UPDATE orders SET status = $2, remaining_quantity = $3 WHERE id = $1")
+            .bind(to_column(fill.order_id)?)
+            .bind(fill.status.as_str())
+            .bind(to_column(fill.remaining_quantity)?)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl OrderRepo for SqliteStorage {
+    async fn save_order(&self, order: &Order) -> Result<(), DatabaseError> {
+        let row = OrderRow::try_from(order)?;
+        // Upsert by id, leaving the fill state of a stored order alone.
+        query(
+            r#"
+            INSERT INTO orders (
+                id, trader_id, base_token, quote_token, side, order_type, price, quantity, timestamp,
+                remaining_quantity
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $8)
+            ON CONFLICT (id) DO UPDATE SET
+                trader_id = excluded.trader_id,
+                base_token = excluded.base_token,
+                quote_token = excluded.quote_token,
+                side = excluded.side,
+                order_type = excluded.order_type,
+                price = excluded.price,
+                quantity = excluded.quantity,
+                timestamp = excluded.timestamp
+            "#,
+        )
+        .bind(row.id)
+        .bind(row.trader_id)
+        .bind(row.base_token)
+        .bind(row.quote_token)
+        .bind(row.side)
+        .bind(row.order_type)
+        .bind(row.price)
+        .bind(row.quantity)
+        .bind(row.timestamp)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn load_order(&self, order_id: OrderId) -> Result<Option<Order>, DatabaseError> {
+        let sql = format!("SELECT {} FROM orders WHERE id = $1", ORDER_COLUMNS);
+        let row = query(&sql)
+            .bind(to_column(order_id)?)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(order_from_row).transpose()
+    }
+
+    async fn delete_order(&self, order_id: OrderId) -> Result<bool, DatabaseError> {
+        let result = query(
+            r#"
+            UPDATE orders
+            SET deleted_at = unixepoch(),
+                status = CASE
+                    WHEN status IN ('open', 'partially_filled') THEN 'cancelled'
+                    ELSE status
+                END
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(to_column(order_id)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn discard_order(&self, order_id: OrderId) -> Result<(), DatabaseError> {
+        query("DELETE FROM orders WHERE id = $1")
+            .bind(to_column(order_id)?)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn load_open_orders(&self) -> Result<Vec<Order>, DatabaseError> {
+        // Bounded by the stored trades, like the Postgres query.
+        let rows = query(
+            r#"
+            SELECT * FROM (
+                SELECT
+                    o.id, o.trader_id, o.base_token, o.quote_token, o.side, o.order_type, o.price,
+                    MIN(o.remaining_quantity, o.quantity - (
+                        SELECT COALESCE(SUM(t.quantity), 0)
+                        FROM trades t
+                        WHERE t.maker_order_id = o.id OR t.taker_order_id = o.id
+                    )) AS quantity,
+                    o.timestamp
+                FROM orders o
+                WHERE o.status IN ('open', 'partially_filled')
+                    AND o.order_type = 'limit'
+                    AND o.deleted_at IS NULL
+            )
+            WHERE quantity > 0
+            ORDER BY id ASC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(order_from_row).collect()
+    }
+
+    async fn last_order_id(&self) -> Result<OrderId, DatabaseError> {
+        let sql = format!("SELECT {} AS last_id", LAST_ORDER_ID);
+        let row = query(&sql).fetch_one(&self.pool).await?;
+        int(&row, "last_id")
+    }
+
+    async fn next_order_id(&self) -> Result<OrderId, DatabaseError> {
+        // One statement, so two callers never get the same ID.
+        let sql = format!(
+            "INSERT INTO id_sequences (name, last_value) VALUES ('order_ids', {0} + 1)
+             ON CONFLICT (name) DO UPDATE SET last_value = MAX(last_value, {0}) + 1
+             RETURNING last_value",
+            LAST_ORDER_ID
+        );
+        let row = query(&sql).fetch_one(&self.pool).await?;
+        int(&row, "last_value")
+    }
+
+    async fn update_order_fills(&self, fills: &[OrderFill]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        update_fills(&mut tx, fills).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TradeRepo for SqliteStorage {
+    async fn save_trade(&self, trade: &Trade) -> Result<(), DatabaseError> {
+        let mut conn = self.pool.acquire().await?;
+        insert_trade(&mut conn, trade).await
+    }
+
+    async fn save_trades(
+        &self,
+        trades: &[Trade],
+        entries: &[JournalEntry],
+        fills: &[OrderFill],
+    ) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        for trade in trades {
+            insert_trade(&mut tx, trade).await?;
+        }
+        for entry in entries {
+            super::ledger::insert_entry(&mut tx, entry).await?;
+        }
+        update_fills(&mut tx, fills).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn load_trade(&self, trade_id: TradeId) -> Result<Option<Trade>, DatabaseError> {
+        let sql = format!("SELECT {} FROM trades WHERE id = $1", TRADE_COLUMNS);
+        let row = query(&sql)
+            .bind(to_column(trade_id)?)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(trade_from_row).transpose()
+    }
+
+    async fn get_trades_for_order(&self, order_id: OrderId) -> Result<Vec<Trade>, DatabaseError> {
+        let sql = format!(
+            "SELECT {} FROM trades \
+             WHERE (maker_order_id = $1 OR taker_order_id = $1) AND NOT busted \
+             ORDER BY timestamp ASC, id ASC",
+            TRADE_COLUMNS
+        );
+        let rows = query(&sql)
+            .bind(to_column(order_id)?)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(trade_from_row).collect()
+    }
+
+    async fn get_trades_for_trader(
+        &self,
+        trader_id: &TraderId,
+        filter: &TradeFilter,
+    ) -> Result<Vec<Trade>, DatabaseError> {
+        let params = FilterParams::new(filter)?;
+        let sql = format!(
+            "SELECT {} FROM trades \
+             WHERE NOT busted AND {} \
+                AND EXISTS ( \
+                    SELECT 1 FROM orders o \
+                    WHERE o.trader_id = $7 AND o.id IN (maker_order_id, taker_order_id) \
+                ) \
+             ORDER BY id ASC LIMIT $6",
+            TRADE_COLUMNS, TRADE_FILTER
+        );
+        let rows = query(&sql)
+            .bind(params.after_id)
+            .bind(params.base)
+            .bind(params.quote)
+            .bind(params.from)
+            .bind(params.to)
+            .bind(params.limit)
+            .bind(trader_id.to_string())
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(trade_from_row).collect()
+    }
+
+    async fn get_trades(&self, filter: &TradeFilter) -> Result<Vec<Trade>, DatabaseError> {
+        let params = FilterParams::new(filter)?;
+        let sql = format!(
+            "SELECT {} FROM trades WHERE NOT busted AND {} ORDER BY id ASC LIMIT $6",
+            TRADE_COLUMNS, TRADE_FILTER
+        );
+        let rows = query(&sql)
+            .bind(params.after_id)
+            .bind(params.base)
+            .bind(params.quote)
+            .bind(params.from)
+            .bind(params.to)
+            .bind(params.limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(trade_from_row).collect()
+    }
+
+    async fn adjust_trade(&self, adjustment: &TradeAdjustment) -> Result<bool, DatabaseError> {
+        let row = TradeAdjustmentRow::try_from(adjustment)?;
+        let mut tx = self.pool.begin().await?;
+        let changed = query(
+            r#"
+            UPDATE trades
+            SET busted = $2, price = COALESCE($4, price)
+            WHERE id = $1 AND NOT busted AND price = $3
+            "#,
+        )
+        .bind(row.trade_id)
+        .bind(adjustment.kind == AdjustmentKind::Bust)
+        .bind(row.previous_price)
+        .bind(row.new_price)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if !changed {
+            return Ok(false);
+        }
+        query(
+            r#"
+            INSERT INTO trade_adjustments (
+                trade_id, kind, previous_price, new_price, reason, adjusted_by, adjusted_at
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+        )
+        .bind(row.trade_id)
+        .bind(row.kind)
+        .bind(row.previous_price)
+        .bind(row.new_price)
+        .bind(row.reason)
+        .bind(row.adjusted_by)
+        .bind(row.adjusted_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn last_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        let sql = format!("SELECT {} AS last_id", LAST_TRADE_ID);
+        let row = query(&sql).fetch_one(&self.pool).await?;
+        int(&row, "last_id")
+    }
+
+    async fn next_trade_ids(&self, count: usize) -> Result<Vec<TradeId>, DatabaseError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let count = i64::try_from(count).map_err(|_| DatabaseError::DataIntegrityError)?;
+        let sql = format!(
+            "INSERT INTO id_sequences (name, last_value) VALUES ('trade_ids', {0} + $1)
+             ON CONFLICT (name) DO UPDATE SET last_value = MAX(last_value, {0}) + $1
+             RETURNING last_value",
+            LAST_TRADE_ID
+        );
+        let row = query(&sql).bind(count).fetch_one(&self.pool).await?;
+        let last: i64 = row.try_get("last_value")?;
+        (last - count + 1..=last).map(from_column).collect()
+    }
+
+    async fn get_trade_adjustments(
+        &self,
+        trade_id: TradeId,
+    ) -> Result<Vec<TradeAdjustment>, DatabaseError> {
+        let rows = query(
+            r#"
+            SELECT trade_id, kind, previous_price, new_price, reason, adjusted_by, adjusted_at
+            FROM trade_adjustments
+            WHERE trade_id = $1
+            ORDER BY id ASC
+            "#,
+        )
+        .bind(to_column(trade_id)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                TradeAdjustment::try_from(TradeAdjustmentRow {
+                    trade_id: row.try_get("trade_id")?,
+                    kind: row.try_get("kind")?,
+                    previous_price: row.try_get("previous_price")?,
+                    new_price: row.try_get("new_price")?,
+                    reason: row.try_get("reason")?,
+                    adjusted_by: row.try_get("adjusted_by")?,
+                    adjusted_at: row.try_get("adjusted_at")?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl SwapRepo for SqliteStorage {
+    async fn save_swap(&self, swap: &SwapRecord) -> Result<(), DatabaseError> {
+        query(
+            r#"
+            INSERT INTO amm_swaps (
+                id, base_token, quote_token, taker_side, price, quantity, quote_quantity, timestamp
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+        )
+        .bind(to_column(swap.id)?)
+        .bind(swap.base_token.as_str())
+        .bind(swap.quote_token.as_str())
+        .bind(side_to_str(swap.taker_side))
+        .bind(to_column(swap.price)?)
+        .bind(to_column(swap.quantity)?)
+        .bind(to_column(swap.quote_quantity)?)
+        .bind(to_column(swap.timestamp)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_swaps(&self, filter: &TradeFilter) -> Result<Vec<SwapRecord>, DatabaseError> {
+        let params = FilterParams::new(filter)?;
+        let sql = format!(
+            "SELECT * FROM amm_swaps WHERE {} ORDER BY id ASC LIMIT $6",
+            TRADE_FILTER
+        );
+        let rows = query(&sql)
+            .bind(params.after_id)
+            .bind(params.base)
+            .bind(params.quote)
+            .bind(params.from)
+            .bind(params.to)
+            .bind(params.limit)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(swap_from_row).collect()
+    }
+
+    async fn last_swap_id(&self) -> Result<u64, DatabaseError> {
+        let row = query("SELECT COALESCE(MAX(id), 0) AS last_id FROM amm_swaps")
+            .fetch_one(&self.pool)
+            .await?;
+        int(&row, "last_id")
+    }
+}
+
+/// Move the rows of `table` with the given IDs to `{table}_archive`
+/// through `conn`, returning how many moved.
+async fn archive_rows(
+    conn: &mut SqliteConnection,
+    table: &str,
+    ids: &[i64],
+) -> Result<u64, DatabaseError> {
+    let ids = to_json(&ids);
+    let moved = query(&format!(
+        "INSERT INTO {0}_archive SELECT * FROM {0} WHERE id IN (SELECT value FROM json_each($1))",
+        table
+    ))
+    .bind(&ids)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    query(&format!(
+        "DELETE FROM {} WHERE id IN (SELECT value FROM json_each($1))",
+        table
+    ))
+    .bind(&ids)
+    .execute(&mut *conn)
+    .await?;
+    Ok(moved)
+}
+
+#[async_trait]
+impl RetentionRepo for SqliteStorage {
+    async fn archive_trades(
+        &self,
+        before: u64,
+        up_to_trade_id: TradeId,
+        limit: u32,
+    ) -> Result<u64, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<i64> =
+            query("SELECT id FROM trades WHERE timestamp < $1 AND id <= $2 ORDER BY id LIMIT $3")
+                .bind(to_column(before)?)
+                .bind(to_column(up_to_trade_id)?)
+                .bind(i64::from(limit))
+                .fetch_all(&mut *tx)
+                .await?
+                .iter()
+                .map(|row| row.try_get("id"))
+                .collect::<Result<_, _>>()?;
+        let moved = archive_rows(&mut tx, "trades", &ids).await?;
+        tx.commit().await?;
+        Ok(moved)
+    }
+
+    async fn archive_orders(&self, before: u64, limit: u32) -> Result<u64, DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+        let ids: Vec<i64> = query(
+            r#"
+            SELECT o.id FROM orders o
+            WHERE o.timestamp < $1
+                AND o.status NOT IN ('open', 'partially_filled')
+                AND NOT EXISTS (SELECT 1 FROM trades t WHERE t.maker_order_id = o.id)
+                AND NOT EXISTS (SELECT 1 FROM trades t WHERE t.taker_order_id = o.id)
+            ORDER BY o.id
+            LIMIT $2
+            "#,
+        )
+        .bind(to_column(before)?)
+        .bind(i64::from(limit))
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(|row| row.try_get("id"))
+        .collect::<Result<_, _>>()?;
+        let moved = archive_rows(&mut tx, "orders", &ids).await?;
+        tx.commit().await?;
+        Ok(moved)
+    }
+}
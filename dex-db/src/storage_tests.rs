@@ -0,0 +1,813 @@
+//! Behaviour every `Storage` backend shares, checked against each backend
+//! that runs without a server: the in-memory one, which the API tests rely
+//! on, and SQLite.
+
+use crate::{
+    memory::MemoryStorage, sqlite::SqliteStorage, AdjustmentKind, ApiKeyRecord, AuditEntry,
+    AuditFilter, ChainBlock, ChainTransaction, ChallengeRecord, CustodyBalance, DeliveryStatus,
+    FundingFilter, FundingKind, FundingRecord, FundingStatus, LedgerFilter, MarketCounters,
+    NetPosition, NettingSet, OrderFill, OrderRepo, OrderStatus, OutboxEvent, PoolPosition,
+    PoolRecord, RefreshTokenRecord, SequencingReceipt, Storage, TickMapBlob, TotpRecord,
+    TradeAdjustment, TradeFilter, WebhookDelivery, WebhookRecord,
+};
+use dex_core::{
+    ledger::{Account, EntryKind, JournalEntry},
+    types::{Order, OrderSide, OrderType, Trade, TradingPair},
+};
+
+fn pair() -> TradingPair {
+    TradingPair::new("ETH".parse().unwrap(), "USDC".parse().unwrap()).unwrap()
+}
+
+fn order(id: u64, trader: &str, side: OrderSide, quantity: u64) -> Order {
+    Order {
+        id,
+        trader_id: trader.parse().unwrap(),
+        pair: pair(),
+        side,
+        order_type: OrderType::Limit,
+        price: Some(2_000),
+        quantity,
+        timestamp: 1_700_000_000 + id,
+    }
+}
+
+fn trade(id: u64, maker: u64, taker: u64, quantity: u64, timestamp: u64) -> Trade {
+    Trade {
+        id,
+        maker_order_id: maker,
+        taker_order_id: taker,
+        base_token: "ETH".parse().unwrap(),
+        quote_token: "USDC".parse().unwrap(),
+        price: 2_000,
+        quantity,
+        timestamp,
+    }
+}
+
+fn fill(order_id: u64, status: OrderStatus, remaining_quantity: u64) -> OrderFill {
+    OrderFill {
+        order_id,
+        status,
+        remaining_quantity,
+    }
+}
+
+fn deposit(trader: &str, reference: &str, amount: u128) -> JournalEntry {
+    JournalEntry::new(EntryKind::Deposit, reference, 1_700_000_000).transfer(
+        Account::Bridge("ethereum".to_string()),
+        Account::Trader(trader.to_string()),
+        &"USDC".parse().unwrap(),
+        amount,
+    )
+}
+
+fn withdrawal(trader: &str) -> FundingRecord {
+    FundingRecord {
+        id: 0,
+        trader_id: trader.to_string(),
+        kind: FundingKind::Withdrawal,
+        chain: "ethereum".to_string(),
+        token: "USDC".parse().unwrap(),
+        amount: u128::from(u64::MAX) * 3,
+        address: Some("0xabc".to_string()),
+        reference: None,
+        tx_hash: None,
+        status: FundingStatus::Pending,
+        created_at: 1_700_000_000,
+        updated_at: 1_700_000_000,
+    }
+}
+
+async fn orders_round_trip_and_soft_delete(storage: &impl Storage) {
+    let stored = order(1, "alice", OrderSide::Sell, 5);
+    storage.save_order(&stored).await.unwrap();
+    let loaded = storage.load_order(1).await.unwrap().unwrap();
+    assert_eq!((loaded.id, loaded.pair, loaded.quantity), (1, pair(), 5));
+    assert_eq!(loaded.trader_id, stored.trader_id);
+
+    assert!(storage.delete_order(1).await.unwrap());
+    assert!(!storage.delete_order(1).await.unwrap());
+    assert!(storage.load_open_orders().await.unwrap().is_empty());
+    assert!(storage.load_order(2).await.unwrap().is_none());
+}
+
+async fn open_orders_are_bounded_by_fills_and_trades(storage: &impl Storage) {
+    for stored in [
+        order(1, "alice", OrderSide::Sell, 10),
+        order(2, "bob", OrderSide::Buy, 4),
+        order(3, "carol", OrderSide::Sell, 6),
+    ] {
+        storage.save_order(&stored).await.unwrap();
+    }
+    storage
+        .save_trades(
+            &[trade(1, 1, 2, 4, 1_700_000_010)],
+            &[],
+            &[
+                fill(1, OrderStatus::PartiallyFilled, 6),
+                fill(2, OrderStatus::Filled, 0),
+            ],
+        )
+        .await
+        .unwrap();
+    // A fill left behind by an older write never rests more than the
+    // trades leave.
+    storage
+        .update_order_fills(&[fill(3, OrderStatus::Open, 6)])
+        .await
+        .unwrap();
+    storage
+        .save_trade(&trade(2, 3, 9, 2, 1_700_000_011))
+        .await
+        .unwrap();
+
+    let open: Vec<(u64, u64)> = storage
+        .load_open_orders()
+        .await
+        .unwrap()
+        .iter()
+        .map(|order| (order.id, order.quantity))
+        .collect();
+    assert_eq!(open, vec![(1, 6), (3, 4)]);
+
+    // Saving an order again keeps its fill state.
+    storage
+        .save_order(&order(1, "alice", OrderSide::Sell, 10))
+        .await
+        .unwrap();
+    assert_eq!(storage.load_open_orders().await.unwrap()[0].quantity, 6);
+}
+
+async fn saved_trades_are_filtered_and_skipped_when_repeated(storage: &impl Storage) {
+    storage
+        .save_order(&order(1, "alice", OrderSide::Sell, 10))
+        .await
+        .unwrap();
+    storage
+        .save_order(&order(2, "bob", OrderSide::Buy, 10))
+        .await
+        .unwrap();
+    let trades = [
+        trade(1, 1, 2, 1, 100),
+        trade(2, 1, 2, 2, 200),
+        trade(3, 1, 2, 3, 300),
+    ];
+    let entries: Vec<JournalEntry> = trades
+        .iter()
+        .map(|trade| JournalEntry::fill(trade, "bob", "alice"))
+        .collect();
+    storage.save_trades(&trades, &entries, &[]).await.unwrap();
+    storage.save_trades(&trades, &entries, &[]).await.unwrap();
+
+    let ids = |trades: Vec<Trade>| trades.iter().map(|trade| trade.id).collect::<Vec<_>>();
+    let filter = TradeFilter {
+        after_id: Some(1),
+        limit: 10,
+        pair: Some(pair()),
+        from: None,
+        to: Some(300),
+    };
+    assert_eq!(ids(storage.get_trades(&filter).await.unwrap()), vec![2]);
+    assert_eq!(
+        ids(storage.get_trades_for_order(1).await.unwrap()),
+        vec![1, 2, 3]
+    );
+    let alice = "alice".parse().unwrap();
+    let page = TradeFilter {
+        limit: 2,
+        ..TradeFilter::default()
+    };
+    assert_eq!(
+        ids(storage.get_trades_for_trader(&alice, &page).await.unwrap()),
+        vec![1, 2]
+    );
+    assert_eq!(
+        storage
+            .query_ledger(&LedgerFilter {
+                limit: 10,
+                ..LedgerFilter::default()
+            })
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+}
+
+async fn trades_are_adjusted_once_and_busts_hidden(storage: &impl Storage) {
+    storage.save_trade(&trade(1, 1, 2, 1, 100)).await.unwrap();
+    let reprice = TradeAdjustment {
+        trade_id: 1,
+        kind: AdjustmentKind::PriceAdjust,
+        previous_price: 2_000,
+        new_price: Some(1_990),
+        reason: "fat finger".to_string(),
+        adjusted_by: "admin".to_string(),
+        adjusted_at: 200,
+    };
+    assert!(storage.adjust_trade(&reprice).await.unwrap());
+    // The price moved, so the same correction no longer applies.
+    assert!(!storage.adjust_trade(&reprice).await.unwrap());
+    assert_eq!(storage.load_trade(1).await.unwrap().unwrap().price, 1_990);
+
+    let bust = TradeAdjustment {
+        kind: AdjustmentKind::Bust,
+        previous_price: 1_990,
+        new_price: None,
+        ..reprice.clone()
+    };
+    assert!(storage.adjust_trade(&bust).await.unwrap());
+    assert!(storage
+        .get_trades(&TradeFilter::default())
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage.load_trade(1).await.unwrap().is_some());
+    let history = storage.get_trade_adjustments(1).await.unwrap();
+    assert_eq!(history, vec![reprice, bust]);
+}
+
+async fn ids_are_never_handed_out_twice(storage: &impl Storage) {
+    storage
+        .save_order(&order(5, "alice", OrderSide::Sell, 1))
+        .await
+        .unwrap();
+    storage.save_trade(&trade(7, 5, 6, 1, 100)).await.unwrap();
+    assert_eq!(storage.last_order_id().await.unwrap(), 6);
+    assert_eq!(storage.last_trade_id().await.unwrap(), 7);
+
+    let first = storage.next_order_id().await.unwrap();
+    let second = storage.next_order_id().await.unwrap();
+    assert!(first > 6 && second > first);
+    let trade_ids = storage.next_trade_ids(3).await.unwrap();
+    assert_eq!(trade_ids.len(), 3);
+    assert!(trade_ids[0] > 7 && trade_ids.windows(2).all(|w| w[0] < w[1]));
+    assert!(storage.next_trade_ids(1).await.unwrap()[0] > trade_ids[2]);
+}
+
+async fn retention_moves_old_settled_rows(storage: &impl Storage) {
+    for id in 1..=3 {
+        storage
+            .save_order(&order(id, "alice", OrderSide::Sell, 1))
+            .await
+            .unwrap();
+    }
+    storage
+        .update_order_fills(&[
+            fill(1, OrderStatus::Filled, 0),
+            fill(2, OrderStatus::Filled, 0),
+        ])
+        .await
+        .unwrap();
+    storage.save_trade(&trade(1, 1, 9, 1, 100)).await.unwrap();
+    storage.save_trade(&trade(2, 1, 9, 1, 500)).await.unwrap();
+
+    // Order 1 is still referenced by a trade in the hot table and order 3
+    // is open.
+    assert_eq!(storage.archive_orders(2_000_000_000, 10).await.unwrap(), 1);
+    assert!(storage.load_order(2).await.unwrap().is_none());
+    assert_eq!(storage.archive_trades(200, 10, 10).await.unwrap(), 1);
+    assert_eq!(storage.archive_trades(1_000, 1, 10).await.unwrap(), 0);
+    assert_eq!(storage.archive_trades(1_000, 10, 10).await.unwrap(), 1);
+    assert_eq!(storage.archive_orders(2_000_000_000, 10).await.unwrap(), 1);
+    // Archived rows still count towards the IDs in use.
+    assert_eq!(storage.last_trade_id().await.unwrap(), 2);
+    assert!(storage.last_order_id().await.unwrap() >= 9);
+}
+
+async fn ledger_balances_sum_amounts_beyond_u64(storage: &impl Storage) {
+    let big = u128::from(u64::MAX) * 2;
+    assert!(storage
+        .save_entry(&deposit("alice", "tx:1", big))
+        .await
+        .unwrap()
+        .is_some());
+    assert!(storage
+        .save_entry(&deposit("alice", "tx:1", big))
+        .await
+        .unwrap()
+        .is_none());
+    storage
+        .save_entry(&deposit("alice", "tx:2", big))
+        .await
+        .unwrap();
+    storage
+        .save_entry(&deposit("bob", "tx:3", 5))
+        .await
+        .unwrap();
+
+    let alice = Account::Trader("alice".to_string());
+    let balances = storage.account_balances(&alice).await.unwrap();
+    assert_eq!(balances.len(), 1);
+    assert_eq!(balances[0].balance, (big * 2) as i128);
+    let all = storage.ledger_balances().await.unwrap();
+    assert_eq!(all.len(), 3);
+    assert_eq!(all.iter().map(|balance| balance.balance).sum::<i128>(), 0);
+
+    let entries = storage
+        .query_ledger(&LedgerFilter {
+            limit: 10,
+            account: Some(alice),
+            kind: Some(EntryKind::Deposit),
+            ..LedgerFilter::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].postings, deposit("alice", "tx:1", big).postings);
+    let after = storage
+        .query_ledger(&LedgerFilter {
+            after_id: Some(entries[0].id),
+            limit: 10,
+            ..LedgerFilter::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(after.len(), 2);
+}
+
+async fn withdrawals_settle_once_with_their_refund(storage: &impl Storage) {
+    let record = withdrawal("alice");
+    let debit = |id: u64| {
+        JournalEntry::new(EntryKind::Withdrawal, format!("withdrawal:{}", id), 1).transfer(
+            Account::Trader("alice".to_string()),
+            Account::Bridge("ethereum".to_string()),
+            &record.token,
+            record.amount,
+        )
+    };
+    let id = storage.save_withdrawal(&record, &debit).await.unwrap();
+    let stored = storage.get_funding(id).await.unwrap().unwrap();
+    assert_eq!(
+        (stored.amount, stored.status),
+        (record.amount, FundingStatus::Pending)
+    );
+
+    let refund = debit(id).reversal(EntryKind::Withdrawal, format!("refund:{}", id), 2);
+    assert!(storage
+        .settle_funding(id, FundingStatus::Failed, None, 2, Some(&refund))
+        .await
+        .unwrap());
+    assert!(!storage
+        .settle_funding(id, FundingStatus::Confirmed, Some("0xdef"), 3, None)
+        .await
+        .unwrap());
+    assert!(storage.ledger_balances().await.unwrap().is_empty());
+
+    let deposit = FundingRecord {
+        kind: FundingKind::Deposit,
+        reference: Some("0x1:0".to_string()),
+        status: FundingStatus::Confirmed,
+        ..withdrawal("bob")
+    };
+    assert!(storage.save_funding(&deposit).await.unwrap().is_some());
+    assert!(storage.save_funding(&deposit).await.unwrap().is_none());
+    let failed = storage
+        .query_funding(&FundingFilter {
+            limit: 10,
+            status: Some(FundingStatus::Failed),
+            ..FundingFilter::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].trader_id, "alice");
+}
+
+async fn outbox_events_are_published_in_order(storage: &impl Storage) {
+    let event = |key: &str| OutboxEvent {
+        id: 0,
+        topic: "trades".to_string(),
+        key: key.to_string(),
+        payload: "{}".to_string(),
+        created_at: 1,
+    };
+    storage
+        .enqueue_events(&[event("a"), event("b"), event("c")])
+        .await
+        .unwrap();
+    let pending = storage.pending_events(2).await.unwrap();
+    let keys: Vec<&str> = pending.iter().map(|event| event.key.as_str()).collect();
+    assert_eq!(keys, vec!["a", "b"]);
+    let ids: Vec<u64> = pending.iter().map(|event| event.id).collect();
+    storage.mark_published(&ids, 2).await.unwrap();
+    let pending = storage.pending_events(10).await.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].key, "c");
+}
+
+async fn credentials_are_revoked_once(storage: &impl Storage) {
+    let key = ApiKeyRecord {
+        key_id: "key-1".to_string(),
+        sealed_secret: "sealed".to_string(),
+        subject: "alice".to_string(),
+        scope: "trade".to_string(),
+        label: None,
+        created_at: 1,
+        revoked: false,
+        allowed_ips: vec!["10.0.0.0/8".to_string()],
+    };
+    storage.save_api_key(&key).await.unwrap();
+    assert_eq!(
+        storage.load_api_key("key-1").await.unwrap(),
+        Some(key.clone())
+    );
+    assert!(!storage.revoke_api_key("key-1", "bob").await.unwrap());
+    assert!(storage
+        .set_api_key_allowed_ips("key-1", "alice", &[])
+        .await
+        .unwrap());
+    assert!(storage.revoke_api_key("key-1", "alice").await.unwrap());
+    assert!(!storage.revoke_api_key("key-1", "alice").await.unwrap());
+    let listed = storage.list_api_keys("alice").await.unwrap();
+    assert!(listed[0].revoked && listed[0].allowed_ips.is_empty());
+
+    let token = RefreshTokenRecord {
+        token_hash: "hash-1".to_string(),
+        family_id: "family".to_string(),
+        subject: "alice".to_string(),
+        audience: None,
+        scope: "trade".to_string(),
+        expires_at: 1_000,
+        revoked: false,
+        signed_in_at: 1,
+        issued_at: 1,
+        access_jti: Some("jti-1".to_string()),
+        access_expires_at: 100,
+    };
+    storage.save_refresh_token(&token).await.unwrap();
+    storage
+        .save_refresh_token(&RefreshTokenRecord {
+            token_hash: "hash-2".to_string(),
+            ..token.clone()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.revoke_refresh_token("hash-1").await.unwrap(),
+        Some(token)
+    );
+    let again = storage
+        .revoke_refresh_token("hash-1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(again.revoked);
+    assert_eq!(storage.revoke_refresh_family("family").await.unwrap(), 1);
+    assert!(storage
+        .list_refresh_sessions("alice", 0)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let challenge = ChallengeRecord {
+        subject: "0xabc".to_string(),
+        message: "sign me".to_string(),
+        typed_data: None,
+        expires_at: 10,
+    };
+    storage.save_challenge(&challenge).await.unwrap();
+    assert_eq!(
+        storage.take_challenge("0xabc").await.unwrap(),
+        Some(challenge)
+    );
+    assert_eq!(storage.take_challenge("0xabc").await.unwrap(), None);
+
+    let totp = TotpRecord {
+        subject: "alice".to_string(),
+        sealed_secret: "sealed".to_string(),
+        confirmed: false,
+        last_step: 0,
+        created_at: 1,
+    };
+    assert!(storage.save_totp(&totp).await.unwrap());
+    assert!(storage.confirm_totp("alice", 5).await.unwrap());
+    assert!(!storage.save_totp(&totp).await.unwrap());
+    assert!(!storage.use_totp_step("alice", 5).await.unwrap());
+    assert!(storage.use_totp_step("alice", 6).await.unwrap());
+    assert!(storage.delete_totp("alice").await.unwrap());
+}
+
+async fn audit_entries_page_in_order(storage: &impl Storage) {
+    for (subject, at) in [("alice", 1), ("bob", 2), ("alice", 3)] {
+        storage
+            .append_audit(&AuditEntry {
+                id: 0,
+                occurred_at: at,
+                subject: subject.to_string(),
+                ip: None,
+                action: "order_create".to_string(),
+                target: "POST /orderbook/orders".to_string(),
+                outcome: "success".to_string(),
+                status: Some(201),
+            })
+            .await
+            .unwrap();
+    }
+    let entries = storage
+        .query_audit(&AuditFilter {
+            limit: 10,
+            subject: Some("alice".to_string()),
+            ..AuditFilter::default()
+        })
+        .await
+        .unwrap();
+    let times: Vec<u64> = entries.iter().map(|entry| entry.occurred_at).collect();
+    assert_eq!(times, vec![1, 3]);
+    assert!(entries[0].id < entries[1].id);
+}
+
+async fn revoked_webhooks_drop_their_deliveries(storage: &impl Storage) {
+    let webhook = WebhookRecord {
+        id: "hook-1".to_string(),
+        trader_id: "alice".to_string(),
+        url: "https://example.com/hook".to_string(),
+        secret: "secret".to_string(),
+        events: vec!["fill".to_string()],
+        created_at: 1,
+        revoked: false,
+    };
+    storage.save_webhook(&webhook).await.unwrap();
+    let delivery = WebhookDelivery {
+        id: 0,
+        webhook_id: "hook-1".to_string(),
+        payload: "{}".to_string(),
+        status: DeliveryStatus::Pending,
+        attempts: 0,
+        next_attempt_at: 10,
+        last_error: None,
+    };
+    storage
+        .enqueue_deliveries(&[delivery.clone(), delivery])
+        .await
+        .unwrap();
+    assert!(storage.due_deliveries(9, 10).await.unwrap().is_empty());
+    let mut due = storage.due_deliveries(10, 10).await.unwrap();
+    assert_eq!(due.len(), 2);
+    due[0].attempts = 1;
+    due[0].next_attempt_at = 20;
+    due[0].last_error = Some("timeout".to_string());
+    storage.update_delivery(&due[0]).await.unwrap();
+    assert_eq!(storage.due_deliveries(10, 10).await.unwrap().len(), 1);
+
+    assert_eq!(storage.list_webhooks("alice").await.unwrap(), vec![webhook]);
+    assert!(!storage.revoke_webhook("hook-1", "bob").await.unwrap());
+    assert!(storage.revoke_webhook("hook-1", "alice").await.unwrap());
+    assert!(storage.load_active_webhooks().await.unwrap().is_empty());
+    assert!(storage.due_deliveries(100, 10).await.unwrap().is_empty());
+}
+
+async fn market_snapshots_never_go_backwards(storage: &impl Storage) {
+    let counters = MarketCounters {
+        base_token: "ETH".parse().unwrap(),
+        quote_token: "USDC".parse().unwrap(),
+        trade_count: 10,
+        base_volume: u128::from(u64::MAX) * 4,
+        quote_volume: 1,
+        last_price: Some(2_000),
+        last_trade_time: Some(50),
+    };
+    storage
+        .save_counters(std::slice::from_ref(&counters))
+        .await
+        .unwrap();
+    let stale = MarketCounters {
+        trade_count: 3,
+        base_volume: 7,
+        last_trade_time: Some(20),
+        ..counters.clone()
+    };
+    storage.save_counters(&[stale]).await.unwrap();
+    assert_eq!(storage.load_counters().await.unwrap(), vec![counters]);
+
+    let map = TickMapBlob {
+        base_token: "ETH".parse().unwrap(),
+        quote_token: "USDC".parse().unwrap(),
+        sequence: 5,
+        tick_count: 2,
+        data: vec![1, 2, 3],
+    };
+    storage
+        .save_tick_maps(std::slice::from_ref(&map))
+        .await
+        .unwrap();
+    storage
+        .save_tick_maps(&[TickMapBlob {
+            sequence: 4,
+            data: vec![9],
+            ..map.clone()
+        }])
+        .await
+        .unwrap();
+    assert_eq!(storage.load_tick_maps().await.unwrap(), vec![map]);
+
+    let pool = PoolRecord {
+        base_token: "ETH".parse().unwrap(),
+        quote_token: "USDC".parse().unwrap(),
+        sequence: 5,
+        fee_bps: 30,
+        reserve_base: 1_000,
+        reserve_quote: 2_000_000,
+        lp_supply: 500,
+        fee_growth_base: u128::MAX,
+        fee_growth_quote: 3,
+        positions: vec![PoolPosition {
+            trader_id: "alice".parse().unwrap(),
+            lp_tokens: 500,
+            deposited: (1_000, 2_000_000),
+            fee_checkpoint: (u128::MAX, 3),
+            fees: (1, 2),
+            fees_withdrawn: (0, 0),
+        }],
+    };
+    storage
+        .save_pools(std::slice::from_ref(&pool))
+        .await
+        .unwrap();
+    storage
+        .save_pools(&[PoolRecord {
+            sequence: 4,
+            positions: Vec::new(),
+            ..pool.clone()
+        }])
+        .await
+        .unwrap();
+    assert_eq!(storage.load_pools().await.unwrap(), vec![pool]);
+
+    let balance = CustodyBalance {
+        wallet_id: "cold-1".to_string(),
+        token: "ETH".parse().unwrap(),
+        balance: u128::MAX,
+        updated_at: 10,
+    };
+    storage.save_custody_balance(&balance).await.unwrap();
+    storage
+        .save_custody_balance(&CustodyBalance {
+            balance: 1,
+            updated_at: 9,
+            ..balance.clone()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.load_custody_balances().await.unwrap(),
+        vec![balance]
+    );
+}
+
+async fn netting_sets_are_stored_once_per_batch(storage: &impl Storage) {
+    let set = NettingSet {
+        id: 0,
+        created_at: 1,
+        created_by: "admin".to_string(),
+        after_trade_id: 0,
+        last_trade_id: 4,
+        trade_count: 3,
+        gross_transfers: 6,
+        unattributed_trade_ids: vec![2],
+        positions: vec![NetPosition {
+            trader_id: "alice".to_string(),
+            token: "USDC".to_string(),
+            delivered: u128::from(u64::MAX) + 1,
+            received: 0,
+        }],
+        transfers: Vec::new(),
+    };
+    let id = storage.save_netting_set(&set).await.unwrap().unwrap();
+    assert!(storage.save_netting_set(&set).await.unwrap().is_none());
+    let loaded = storage.load_netting_set(id).await.unwrap().unwrap();
+    assert_eq!(loaded, NettingSet { id, ..set });
+    assert_eq!(storage.last_netted_trade_id().await.unwrap(), 4);
+}
+
+async fn chain_blocks_finalize_lowest_first(storage: &impl Storage) {
+    let block = |height: u64| ChainBlock {
+        shard_id: 1,
+        height,
+        hash: format!("{:02x}", height),
+        previous_hash: format!("{:02x}", height - 1),
+        timestamp: height,
+        transactions: vec![ChainTransaction {
+            hash: format!("tx{}", height),
+            shard_id: 1,
+            height,
+            index: 0,
+            from: "alice".to_string(),
+            to: "bob".to_string(),
+            amount: -5,
+            nonce: height,
+            finalized: false,
+        }],
+        finalized: false,
+    };
+    for height in 1..=3 {
+        storage.save_block(&block(height)).await.unwrap();
+    }
+    storage
+        .save_block(&ChainBlock {
+            hash: "other".to_string(),
+            ..block(1)
+        })
+        .await
+        .unwrap();
+    assert_eq!(storage.load_blocks_at(1).await.unwrap(), vec![block(1)]);
+
+    let finalized = storage.finalize_blocks(1, 2).await.unwrap();
+    let heights: Vec<u64> = finalized.iter().map(|block| block.height).collect();
+    assert_eq!(heights, vec![1, 2]);
+    assert!(storage.finalize_blocks(1, 2).await.unwrap().is_empty());
+    let transaction = storage.load_transaction("tx2").await.unwrap().unwrap();
+    assert!(transaction.finalized);
+    assert_eq!(transaction.amount, -5);
+
+    let receipt = SequencingReceipt {
+        sequence: 1,
+        order_id: 42,
+        command_hash: "aa".to_string(),
+        previous_hash: "00".to_string(),
+        timestamp: 1,
+        region: "eu".to_string(),
+        signature: "sig".to_string(),
+    };
+    storage.save_receipt(&receipt).await.unwrap();
+    storage
+        .save_receipt(&SequencingReceipt {
+            order_id: 43,
+            ..receipt.clone()
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.load_receipt(42).await.unwrap(),
+        Some(receipt.clone())
+    );
+    assert_eq!(storage.latest_receipt().await.unwrap(), Some(receipt));
+    assert!(storage.last_order_id().await.unwrap() >= 42);
+}
+
+/// Run each check against a fresh store of every backend.
+macro_rules! storage_tests {
+    ($($check:ident),* $(,)?) => {
+        mod memory {
+            $(
+                #[tokio::test]
+                async fn $check() {
+                    super::$check(&super::MemoryStorage::default()).await;
+                }
+            )*
+        }
+
+        mod sqlite {
+            $(
+                #[tokio::test]
+                async fn $check() {
+                    super::$check(&super::SqliteStorage::in_memory().await.unwrap()).await;
+                }
+            )*
+        }
+    };
+}
+
+storage_tests!(
+    orders_round_trip_and_soft_delete,
+    open_orders_are_bounded_by_fills_and_trades,
+    saved_trades_are_filtered_and_skipped_when_repeated,
+    trades_are_adjusted_once_and_busts_hidden,
+    ids_are_never_handed_out_twice,
+    retention_moves_old_settled_rows,
+    ledger_balances_sum_amounts_beyond_u64,
+    withdrawals_settle_once_with_their_refund,
+    outbox_events_are_published_in_order,
+    credentials_are_revoked_once,
+    audit_entries_page_in_order,
+    revoked_webhooks_drop_their_deliveries,
+    market_snapshots_never_go_backwards,
+    netting_sets_are_stored_once_per_batch,
+    chain_blocks_finalize_lowest_first,
+);
+
+#[tokio::test]
+async fn sqlite_storage_survives_reopening_its_file() {
+    let path = std::env::temp_dir().join(format!("dex-db-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    {
+        let storage = SqliteStorage::open(&path).await.unwrap();
+        storage.initialize().await.unwrap();
+        storage
+            .save_order(&order(1, "alice", OrderSide::Sell, 5))
+            .await
+            .unwrap();
+        storage
+            .update_order_fills(&[fill(1, OrderStatus::PartiallyFilled, 3)])
+            .await
+            .unwrap();
+    }
+
+    let storage = SqliteStorage::open(&path).await.unwrap();
+    assert!(storage.pending_migrations().await.unwrap().is_empty());
+    storage.initialize().await.unwrap();
+    let open = storage.load_open_orders().await.unwrap();
+    assert_eq!((open[0].id, open[0].quantity), (1, 3));
+    std::fs::remove_file(&path).unwrap();
+}
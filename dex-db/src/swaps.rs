@@ -0,0 +1,101 @@
+//! Postgres implementation of `SwapRepo`.
+
+use crate::{
+    parse_column,
+    repository::{SwapRecord, SwapRepo, TradeFilter},
+    rows::side_to_str,
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::types::OrderSide;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn swap_from_row(row: &PgRow) -> Result<SwapRecord, DatabaseError> {
+    Ok(SwapRecord {
+        id: row.get::<i64, _>("id") as u64,
+        base_token: parse_column(row, "base_token")?,
+        quote_token: parse_column(row, "quote_token")?,
+        taker_side: match row.get::<&str, _>("taker_side") {
+            "buy" => OrderSide::Buy,
+            "sell" => OrderSide::Sell,
+            _ => return Err(DatabaseError::DataIntegrityError),
+        },
+        price: row.get::<i64, _>("price") as u64,
+        quantity: row.get::<i64, _>("quantity") as u64,
+        quote_quantity: row.get::<i64, _>("quote_quantity") as u64,
+        timestamp: row.get::<i64, _>("timestamp") as u64,
+    })
+}
+
+#[async_trait]
+impl SwapRepo for DatabaseManager {
+    async fn save_swap(&self, swap: &SwapRecord) -> Result<(), DatabaseError> {
+        // The caller assigns the ID, so a retry that finds the row already
+        // stored is harmless.
+        self.run("save_swap", true, || {
+            query(
+                r#"
+            INSERT INTO amm_swaps (
+                id, base_token, quote_token, taker_side, price, quantity, quote_quantity, timestamp
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (id) DO NOTHING
+            "#,
+            )
+            .bind(swap.id as i64)
+            .bind(swap.base_token.as_str())
+            .bind(swap.quote_token.as_str())
+            .bind(side_to_str(swap.taker_side))
+            .bind(swap.price as i64)
+            .bind(swap.quantity as i64)
+            .bind(swap.quote_quantity as i64)
+            .bind(swap.timestamp as i64)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_swaps(&self, filter: &TradeFilter) -> Result<Vec<SwapRecord>, DatabaseError> {
+        let (base, quote) = match &filter.pair {
+            Some(pair) => (Some(pair.base().as_str()), Some(pair.quote().as_str())),
+            None => (None, None),
+        };
+        let rows = self
+            .run("get_swaps", true, || {
+                query(
+                    r#"
+            SELECT id, base_token, quote_token, taker_side, price, quantity, quote_quantity, timestamp
+            FROM amm_swaps
+            WHERE ($1::BIGINT IS NULL OR id > $1)
+              AND ($2::TEXT IS NULL OR (base_token = $2 AND quote_token = $3))
+              AND ($4::BIGINT IS NULL OR timestamp >= $4)
+              AND ($5::BIGINT IS NULL OR timestamp < $5)
+            ORDER BY id ASC
+            LIMIT $6
+            "#,
+                )
+                .bind(filter.after_id.map(|id| id as i64))
+                .bind(base)
+                .bind(quote)
+                .bind(filter.from.map(|ts| ts as i64))
+                .bind(filter.to.map(|ts| ts as i64))
+                .bind(i64::from(filter.page_size()))
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        rows.iter().map(swap_from_row).collect()
+    }
+
+    async fn last_swap_id(&self) -> Result<u64, DatabaseError> {
+        let row = self
+            .run("last_swap_id", true, || {
+                query("SELECT COALESCE(MAX(id), 0) AS last_id FROM amm_swaps").fetch_one(&self.pool)
+            })
+            .await?;
+
+        Ok(row.get::<i64, _>("last_id") as u64)
+    }
+}
@@ -0,0 +1,214 @@
+//! Compressed tick maps and the Postgres implementation of `TickMapRepo`.
+//!
+//! A concentrated-liquidity pool can hold thousands of ticks, and storing a
+//! row per tick makes saving and restoring a pool cost one row each. A tick
+//! map is instead stored as a single blob: gzip-compressed JSON listing each
+//! tick as `[gap, liquidity, liquidity_net]` in index order, where `gap` is
+//! the distance from the previous tick's index. Ticks in a range are
+//! usually adjacent and share their liquidity, so their entries repeat and
+//! the blob compresses well. It is only decoded when the pool's ticks are
+//! first needed.
+//! `benches/tick_maps.rs` compares both layouts.
+
+use crate::{
+    parse_column,
+    repository::{TickMapBlob, TickMapRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::{
+    amm::Tick,
+    types::{Quantity, TokenId},
+};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use sqlx_core::{query::query, row::Row};
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+/// A tick as it is encoded: gap from the previous index, liquidity and net
+/// liquidity.
+type EncodedTick = (i64, Quantity, i64);
+
+/// Compress `ticks`, taken at `sequence`, for the pool trading `base_token`
+/// against `quote_token`.
+pub fn encode(
+    base_token: &TokenId,
+    quote_token: &TokenId,
+    sequence: u64,
+    ticks: &HashMap<i32, Tick>,
+) -> TickMapBlob {
+    let mut sorted: Vec<&Tick> = ticks.values().collect();
+    sorted.sort_unstable_by_key(|tick| tick.index);
+    let mut previous = 0;
+    let encoded: Vec<EncodedTick> = sorted
+        .into_iter()
+        .map(|tick| {
+            let gap = i64::from(tick.index) - previous;
+            previous = i64::from(tick.index);
+            (gap, tick.liquidity, tick.liquidity_net)
+        })
+        .collect();
+    let json = serde_json::to_vec(&encoded).expect("ticks serialize");
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    let data = encoder
+        .write_all(&json)
+        .and_then(|()| encoder.finish())
+        .expect("compressing into memory does not fail");
+    TickMapBlob {
+        base_token: base_token.clone(),
+        quote_token: quote_token.clone(),
+        sequence,
+        tick_count: encoded.len() as u64,
+        data,
+    }
+}
+
+/// The ticks in `blob`, keyed by index.
+pub fn decode(blob: &TickMapBlob) -> Result<HashMap<i32, Tick>, DatabaseError> {
+    let mut json = Vec::new();
+    GzDecoder::new(blob.data.as_slice())
+        .read_to_end(&mut json)
+        .map_err(|_| DatabaseError::DataIntegrityError)?;
+    let encoded: Vec<EncodedTick> =
+        serde_json::from_slice(&json).map_err(|_| DatabaseError::DataIntegrityError)?;
+    if encoded.len() as u64 != blob.tick_count {
+        return Err(DatabaseError::DataIntegrityError);
+    }
+    let mut index = 0i64;
+    encoded
+        .into_iter()
+        .map(|(gap, liquidity, liquidity_net)| {
+            index = index
+                .checked_add(gap)
+                .ok_or(DatabaseError::DataIntegrityError)?;
+            let index = i32::try_from(index).map_err(|_| DatabaseError::DataIntegrityError)?;
+            let tick = Tick {
+                index,
+                liquidity,
+                liquidity_net,
+            };
+            Ok((index, tick))
+        })
+        .collect()
+}
+
+#[async_trait]
+impl TickMapRepo for DatabaseManager {
+    async fn save_tick_maps(&self, maps: &[TickMapBlob]) -> Result<(), DatabaseError> {
+        if maps.is_empty() {
+            return Ok(());
+        }
+        let bases: Vec<&str> = maps.iter().map(|m| m.base_token.as_str()).collect();
+        let quotes: Vec<&str> = maps.iter().map(|m| m.quote_token.as_str()).collect();
+        let sequences: Vec<i64> = maps.iter().map(|m| m.sequence as i64).collect();
+        let tick_counts: Vec<i64> = maps.iter().map(|m| m.tick_count as i64).collect();
+        let data: Vec<&[u8]> = maps.iter().map(|m| m.data.as_slice()).collect();
+
+        // Only a newer map replaces the stored one, so a retry is harmless.
+        self.run("save_tick_maps", true, || {
+            query(
+                r#"
+            INSERT INTO amm_tick_maps (base_token, quote_token, sequence, tick_count, data)
+            SELECT * FROM UNNEST($1::TEXT[], $2::TEXT[], $3::BIGINT[], $4::BIGINT[], $5::BYTEA[])
+            ON CONFLICT (base_token, quote_token) DO UPDATE SET
+                sequence = EXCLUDED.sequence,
+                tick_count = EXCLUDED.tick_count,
+                data = EXCLUDED.data
+            WHERE EXCLUDED.sequence > amm_tick_maps.sequence
+            "#,
+            )
+            .bind(bases.clone())
+            .bind(quotes.clone())
+            .bind(sequences.clone())
+            .bind(tick_counts.clone())
+            .bind(data.clone())
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_tick_maps(&self) -> Result<Vec<TickMapBlob>, DatabaseError> {
+        let rows = self
+            .run("load_tick_maps", true, || {
+                query(
+                    r#"
+            SELECT base_token, quote_token, sequence, tick_count, data
+            FROM amm_tick_maps
+            "#,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                Ok(TickMapBlob {
+                    base_token: parse_column(row, "base_token")?,
+                    quote_token: parse_column(row, "quote_token")?,
+                    sequence: row.get::<i64, _>("sequence") as u64,
+                    tick_count: row.get::<i64, _>("tick_count") as u64,
+                    data: row.get("data"),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(lower: i32, upper: i32, liquidity: Quantity) -> HashMap<i32, Tick> {
+        (lower..=upper)
+            .map(|index| {
+                let liquidity_net = match index {
+                    i if i == lower => liquidity as i64,
+                    i if i == upper => -(liquidity as i64),
+                    _ => 0,
+                };
+                let tick = Tick {
+                    index,
+                    liquidity,
+                    liquidity_net,
+                };
+                (index, tick)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tick_maps_round_trip_compressed() {
+        let (eth, usdc) = ("ETH".parse().unwrap(), "USDC".parse().unwrap());
+        let ticks = range(-5_000, 4_999, 1_000_000);
+        let blob = encode(&eth, &usdc, 7, &ticks);
+        assert_eq!(blob.tick_count, 10_000);
+        assert_eq!(blob.sequence, 7);
+        assert!(blob.data.len() < 1_000, "{} bytes", blob.data.len());
+
+        let decoded = decode(&blob).unwrap();
+        assert_eq!(decoded.len(), ticks.len());
+        for (index, tick) in &ticks {
+            let restored = &decoded[index];
+            assert_eq!(restored.index, tick.index);
+            assert_eq!(restored.liquidity, tick.liquidity);
+            assert_eq!(restored.liquidity_net, tick.liquidity_net);
+        }
+    }
+
+    #[test]
+    fn damaged_tick_maps_are_rejected() {
+        let (eth, usdc) = ("ETH".parse().unwrap(), "USDC".parse().unwrap());
+        let mut blob = encode(&eth, &usdc, 1, &range(0, 9, 10));
+        blob.tick_count += 1;
+        assert!(matches!(
+            decode(&blob),
+            Err(DatabaseError::DataIntegrityError)
+        ));
+        blob.data.truncate(blob.data.len() / 2);
+        assert!(decode(&blob).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Postgres implementation of `TotpRepo`.
+
+use crate::{
+    repository::{TotpRecord, TotpRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+
+#[async_trait]
+impl TotpRepo for DatabaseManager {
+    async fn save_totp(&self, record: &TotpRecord) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("save_totp", true, || {
+                query(
+                    r#"
+            INSERT INTO totp_secrets (subject, sealed_secret, confirmed, last_step, created_at)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (subject) DO UPDATE SET
+                sealed_secret = EXCLUDED.sealed_secret,
+                confirmed = EXCLUDED.confirmed,
+                last_step = EXCLUDED.last_step,
+                created_at = EXCLUDED.created_at
+            WHERE NOT totp_secrets.confirmed
+            "#,
+                )
+                .bind(record.subject.as_str())
+                .bind(record.sealed_secret.as_str())
+                .bind(record.confirmed)
+                .bind(record.last_step as i64)
+                .bind(record.created_at as i64)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn load_totp(&self, subject: &str) -> Result<Option<TotpRecord>, DatabaseError> {
+        let row = self
+            .run("load_totp", true, || {
+                query(
+                    r#"
+            SELECT subject, sealed_secret, confirmed, last_step, created_at
+            FROM totp_secrets WHERE subject = $1
+            "#,
+                )
+                .bind(subject)
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        Ok(row.map(|row| TotpRecord {
+            subject: row.get("subject"),
+            sealed_secret: row.get("sealed_secret"),
+            confirmed: row.get("confirmed"),
+            last_step: row.get::<i64, _>("last_step") as u64,
+            created_at: row.get::<i64, _>("created_at") as u64,
+        }))
+    }
+
+    async fn confirm_totp(&self, subject: &str, step: u64) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("confirm_totp", true, || {
+                query(
+                    r#"
+            UPDATE totp_secrets SET confirmed = TRUE, last_step = $2
+            WHERE subject = $1 AND NOT confirmed
+            "#,
+                )
+                .bind(subject)
+                .bind(step as i64)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn use_totp_step(&self, subject: &str, step: u64) -> Result<bool, DatabaseError> {
+        // Not retried: a retry after a lost reply would find the step already
+        // used and refuse a valid code as replayed.
+        let result = self
+            .run("use_totp_step", false, || {
+                query(
+                    r#"
+            UPDATE totp_secrets SET last_step = $2
+            WHERE subject = $1 AND confirmed AND last_step < $2
+            "#,
+                )
+                .bind(subject)
+                .bind(step as i64)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_totp(&self, subject: &str) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("delete_totp", true, || {
+                query("DELETE FROM totp_secrets WHERE subject = $1")
+                    .bind(subject)
+                    .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,80 @@
+//! Trades as they are stored, pushed over Postgres LISTEN/NOTIFY.
+//!
+//! A trigger on `trades` sends every inserted trade on the `trades` channel
+//! when its transaction commits, so services sharing the database can follow
+//! fills without polling. Notifications are not stored: those sent while a
+//! subscriber's connection is down are lost. The subscription reconnects by
+//! itself and reports the gap, after which the subscriber should catch up
+//! with `TradeRepo::get_trades` from the last trade it saw.
+
+use crate::{DatabaseError, DatabaseManager};
+use dex_core::types::Trade;
+use futures_util::stream::{self, Stream};
+use sqlx_postgres::PgListener;
+
+/// Channel the `notify_trade` trigger sends on.
+pub const TRADES_CHANNEL: &str = "trades";
+
+/// What a trade subscription delivers.
+#[derive(Debug, Clone)]
+pub enum TradeFeedEvent {
+    /// A trade whose insert was committed.
+    Trade(Trade),
+    /// The connection was lost and re-established; trades stored meanwhile
+    /// were not delivered.
+    Gap,
+}
+
+/// A trade as the trigger encodes it.
+fn parse_payload(payload: &str) -> Result<Trade, DatabaseError> {
+    serde_json::from_str(payload).map_err(|_| DatabaseError::DataIntegrityError)
+}
+
+impl DatabaseManager {
+    /// Follow trades as they are stored, on a connection of its own.
+    ///
+    /// The stream ends only if the pool is closed. A notification that does
+    /// not parse is yielded as a `DataIntegrityError` and the stream goes on.
+    pub async fn subscribe_trades(
+        &self,
+    ) -> Result<impl Stream<Item = Result<TradeFeedEvent, DatabaseError>> + Send, DatabaseError>
+    {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(TRADES_CHANNEL).await?;
+        Ok(stream::unfold(listener, |mut listener| async move {
+            let event = match listener.try_recv().await {
+                Ok(Some(notification)) => {
+                    parse_payload(notification.payload()).map(TradeFeedEvent::Trade)
+                }
+                Ok(None) => Ok(TradeFeedEvent::Gap),
+                Err(sqlx_core::error::Error::PoolClosed) => return None,
+                Err(err) => Err(err.into()),
+            };
+            Some((event, listener))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_payloads_parse_into_trades() {
+        let trade = parse_payload(
+            r#"{"id" : 42, "maker_order_id" : 7, "taker_order_id" : 9, "base_token" : "ETH", "quote_token" : "USDC", "price" : 2000, "quantity" : 3, "timestamp" : 1700000000}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            (trade.id, trade.maker_order_id, trade.taker_order_id),
+            (42, 7, 9)
+        );
+        assert_eq!(trade.base_token.as_str(), "ETH");
+        assert_eq!((trade.price, trade.quantity), (2000, 3));
+
+        assert!(matches!(
+            parse_payload(r#"{"id" : -1}"#),
+            Err(DatabaseError::DataIntegrityError)
+        ));
+    }
+}
@@ -0,0 +1,353 @@
+//! Postgres implementation of `TradeRepo`.
+
+use crate::{
+    ledger::insert_entry,
+    orders::{update_fills, FillColumns},
+    repository::{OrderFill, TradeAdjustment, TradeFilter, TradeRepo},
+    rows::{from_column, to_column, TradeAdjustmentRow, TradeRow},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use dex_core::{
+    ledger::JournalEntry,
+    types::{OrderId, Trade, TradeId, TraderId},
+};
+use sqlx::{query, query_as, query_scalar};
+
+/// Bound parameters of a trade history query.
+struct FilterParams<'a> {
+    after_id: Option<i64>,
+    base: Option<&'a str>,
+    quote: Option<&'a str>,
+    from: Option<i64>,
+    to: Option<i64>,
+    limit: i64,
+}
+
+impl<'a> FilterParams<'a> {
+    fn new(filter: &'a TradeFilter) -> Result<Self, DatabaseError> {
+        let (base, quote) = match &filter.pair {
+            Some(pair) => (Some(pair.base().as_str()), Some(pair.quote().as_str())),
+            None => (None, None),
+        };
+        Ok(Self {
+            after_id: filter.after_id.map(to_column).transpose()?,
+            base,
+            quote,
+            from: filter.from.map(to_column).transpose()?,
+            to: filter.to.map(to_column).transpose()?,
+            limit: i64::from(filter.page_size()),
+        })
+    }
+}
+
+#[async_trait]
+impl TradeRepo for DatabaseManager {
+    async fn save_trade(&self, trade: &Trade) -> Result<(), DatabaseError> {
+        let row = TradeRow::try_from(trade)?;
+        // Plain insert: a retry after an ambiguous failure could hit a duplicate key,
+        // so this goes through the breaker without retries.
+        self.run("save_trade", false, || {
+            query!(
+                r#"
+            INSERT INTO trades (
+                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+                row.id,
+                row.maker_order_id,
+                row.taker_order_id,
+                row.base_token,
+                row.quote_token,
+                row.price,
+                row.quantity,
+                row.timestamp,
+            )
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn save_trades(
+        &self,
+        trades: &[Trade],
+        entries: &[JournalEntry],
+        fills: &[OrderFill],
+    ) -> Result<(), DatabaseError> {
+        if trades.is_empty() && entries.is_empty() && fills.is_empty() {
+            return Ok(());
+        }
+        let rows = trades
+            .iter()
+            .map(TradeRow::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        let column = |field: fn(&TradeRow) -> i64| rows.iter().map(field).collect::<Vec<_>>();
+        let ids = &column(|row| row.id);
+        let makers = &column(|row| row.maker_order_id);
+        let takers = &column(|row| row.taker_order_id);
+        let prices = &column(|row| row.price);
+        let quantities = &column(|row| row.quantity);
+        let timestamps = &column(|row| row.timestamp);
+        let bases: &Vec<&str> = &rows.iter().map(|row| row.base_token.as_str()).collect();
+        let quotes: &Vec<&str> = &rows.iter().map(|row| row.quote_token.as_str()).collect();
+        let fills = &FillColumns::new(fills)?;
+
+        // One transaction, so the match is stored whole or not at all. Trade
+        // IDs are allocated up front, entries are keyed by their reference
+        // and fills set absolute values, so a retry after a lost reply stores
+        // nothing twice.
+        self.run("save_trades", true, move || async move {
+            let mut tx = self.pool.begin().await?;
+            let result = query!(
+                r#"
+            INSERT INTO trades (
+                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
+            )
+            SELECT * FROM UNNEST(
+                $1::BIGINT[], $2::BIGINT[], $3::BIGINT[], $4::TEXT[], $5::TEXT[],
+                $6::BIGINT[], $7::BIGINT[], $8::BIGINT[]
+            )
+            ON CONFLICT (id) DO NOTHING
+            "#,
+                ids,
+                makers,
+                takers,
+                bases as &[&str],
+                quotes as &[&str],
+                prices,
+                quantities,
+                timestamps,
+            )
+            .execute(&mut *tx)
+            .await?;
+            for entry in entries {
+                insert_entry(&mut *tx, entry).await?;
+            }
+            update_fills(&mut *tx, fills).await?;
+            tx.commit().await?;
+            Ok(result)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_trade(&self, trade_id: TradeId) -> Result<Option<Trade>, DatabaseError> {
+        let trade_id = to_column(trade_id)?;
+        let row = self
+            .run("load_trade", true, || {
+                query_as!(
+                    TradeRow,
+                    r#"
+            SELECT
+                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
+            FROM trades
+            WHERE id = $1
+            "#,
+                    trade_id,
+                )
+                .fetch_optional(&self.pool)
+            })
+            .await?;
+
+        row.map(Trade::try_from).transpose()
+    }
+
+    async fn get_trades_for_order(&self, order_id: OrderId) -> Result<Vec<Trade>, DatabaseError> {
+        let order_id = to_column(order_id)?;
+        let rows = self
+            .run_read("get_trades_for_order", |pool| {
+                query_as!(
+                    TradeRow,
+                    r#"
+            SELECT
+                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
+            FROM trades
+            WHERE (maker_order_id = $1 OR taker_order_id = $1) AND NOT busted
+            ORDER BY timestamp ASC
+            "#,
+                    order_id,
+                )
+                .fetch_all(pool)
+            })
+            .await?;
+
+        rows.into_iter().map(Trade::try_from).collect()
+    }
+
+    async fn get_trades_for_trader(
+        &self,
+        trader_id: &TraderId,
+        filter: &TradeFilter,
+    ) -> Result<Vec<Trade>, DatabaseError> {
+        let params = FilterParams::new(filter)?;
+        let rows = self
+            .run_read("get_trades_for_trader", |pool| {
+                query_as!(
+                    TradeRow,
+                    r#"
+            SELECT
+                t.id, t.maker_order_id, t.taker_order_id, t.base_token, t.quote_token, t.price, t.quantity, t.timestamp
+            FROM trades t
+            JOIN orders o1 ON t.maker_order_id = o1.id
+            JOIN orders o2 ON t.taker_order_id = o2.id
+            WHERE (o1.trader_id = $1 OR o2.trader_id = $1)
+              AND NOT t.busted
+              AND ($2::BIGINT IS NULL OR t.id > $2)
+              AND ($3::TEXT IS NULL OR (t.base_token = $3 AND t.quote_token = $4))
+              AND ($5::BIGINT IS NULL OR t.timestamp >= $5)
+              AND ($6::BIGINT IS NULL OR t.timestamp < $6)
+            ORDER BY t.id ASC
+            LIMIT $7
+            "#,
+                    trader_id.as_str(),
+                    params.after_id,
+                    params.base,
+                    params.quote,
+                    params.from,
+                    params.to,
+                    params.limit,
+                )
+                .fetch_all(pool)
+            })
+            .await?;
+
+        rows.into_iter().map(Trade::try_from).collect()
+    }
+
+    async fn get_trades(&self, filter: &TradeFilter) -> Result<Vec<Trade>, DatabaseError> {
+        let params = FilterParams::new(filter)?;
+        let rows = self
+            .run_read("get_trades", |pool| {
+                query_as!(
+                    TradeRow,
+                    r#"
+            SELECT
+                id, maker_order_id, taker_order_id, base_token, quote_token, price, quantity, timestamp
+            FROM trades
+            WHERE NOT busted
+              AND ($1::BIGINT IS NULL OR id > $1)
+              AND ($2::TEXT IS NULL OR (base_token = $2 AND quote_token = $3))
+              AND ($4::BIGINT IS NULL OR timestamp >= $4)
+              AND ($5::BIGINT IS NULL OR timestamp < $5)
+            ORDER BY id ASC
+            LIMIT $6
+            "#,
+                    params.after_id,
+                    params.base,
+                    params.quote,
+                    params.from,
+                    params.to,
+                    params.limit,
+                )
+                .fetch_all(pool)
+            })
+            .await?;
+
+        rows.into_iter().map(Trade::try_from).collect()
+    }
+
+    async fn last_trade_id(&self) -> Result<TradeId, DatabaseError> {
+        let last_id = self
+            .run("last_trade_id", true, || {
+                query_scalar!(
+                    r#"
+            SELECT GREATEST(
+                (SELECT MAX(id) FROM trades),
+                (SELECT MAX(id) FROM trades_archive),
+                0
+            ) AS "last_id!"
+            "#,
+                )
+                .fetch_one(&self.pool)
+            })
+            .await?;
+
+        from_column(last_id)
+    }
+
+    async fn next_trade_ids(&self, count: usize) -> Result<Vec<TradeId>, DatabaseError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        let count = i64::try_from(count).map_err(|_| DatabaseError::DataIntegrityError)?;
+        // A retry at worst skips IDs.
+        let ids = self
+            .run("next_trade_ids", true, || {
+                query_scalar!(
+                    r#"
+            SELECT nextval('trade_ids') AS "id!"
+            FROM generate_series(1, $1::BIGINT)
+            ORDER BY 1
+            "#,
+                    count,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        ids.into_iter().map(from_column).collect()
+    }
+
+    async fn adjust_trade(&self, adjustment: &TradeAdjustment) -> Result<bool, DatabaseError> {
+        let row = TradeAdjustmentRow::try_from(adjustment)?;
+        // Not retried: a retry after a lost reply would find the trade already
+        // changed and report a conflict for an adjustment that was applied.
+        let result = self
+            .run("adjust_trade", false, || {
+                query!(
+                    r#"
+            WITH updated AS (
+                UPDATE trades
+                SET busted = ($2 = 'bust'),
+                    price = COALESCE($4, price),
+                    updated_at = EXTRACT(EPOCH FROM NOW())::BIGINT
+                WHERE id = $1 AND NOT busted AND price = $3
+                RETURNING id
+            )
+            INSERT INTO trade_adjustments (
+                trade_id, kind, previous_price, new_price, reason, adjusted_by, adjusted_at
+            )
+            SELECT id, $2, $3, $4, $5, $6, $7 FROM updated
+            "#,
+                    row.trade_id,
+                    row.kind,
+                    row.previous_price,
+                    row.new_price,
+                    row.reason,
+                    row.adjusted_by,
+                    row.adjusted_at,
+                )
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn get_trade_adjustments(
+        &self,
+        trade_id: TradeId,
+    ) -> Result<Vec<TradeAdjustment>, DatabaseError> {
+        let trade_id = to_column(trade_id)?;
+        let rows = self
+            .run("get_trade_adjustments", true, || {
+                query_as!(
+                    TradeAdjustmentRow,
+                    r#"
+            SELECT trade_id, kind, previous_price, new_price, reason, adjusted_by, adjusted_at
+            FROM trade_adjustments
+            WHERE trade_id = $1
+            ORDER BY id ASC
+            "#,
+                    trade_id,
+                )
+                .fetch_all(&self.pool)
+            })
+            .await?;
+
+        rows.into_iter().map(TradeAdjustment::try_from).collect()
+    }
+}
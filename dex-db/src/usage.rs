@@ -0,0 +1,215 @@
+//! Postgres implementation of `UsageRepo`.
+
+use crate::{
+    repository::{MessagingPenalty, UsageRepo, UsageRollup},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+const USAGE_COLUMNS: &str =
+    "subject, day, credential, requests, errors, throttled, orders, fills, ws_messages, ws_bytes";
+
+const PENALTY_COLUMNS: &str =
+    "subject, day, orders, fills, max_ratio, fee, rate_limit_percent, expires_at";
+
+fn count(row: &PgRow, column: &str) -> u64 {
+    row.get::<i64, _>(column) as u64
+}
+
+fn usage_from_row(row: &PgRow) -> UsageRollup {
+    UsageRollup {
+        subject: row.get("subject"),
+        day: count(row, "day"),
+        credential: row.get("credential"),
+        requests: count(row, "requests"),
+        errors: count(row, "errors"),
+        throttled: count(row, "throttled"),
+        orders: count(row, "orders"),
+        fills: count(row, "fills"),
+        ws_messages: count(row, "ws_messages"),
+        ws_bytes: count(row, "ws_bytes"),
+    }
+}
+
+fn penalty_from_row(row: &PgRow) -> MessagingPenalty {
+    MessagingPenalty {
+        subject: row.get("subject"),
+        day: count(row, "day"),
+        orders: count(row, "orders"),
+        fills: count(row, "fills"),
+        max_ratio: row.get("max_ratio"),
+        fee: count(row, "fee"),
+        rate_limit_percent: row.get::<i32, _>("rate_limit_percent") as u32,
+        expires_at: count(row, "expires_at"),
+    }
+}
+
+#[async_trait]
+impl UsageRepo for DatabaseManager {
+    async fn add_usage(&self, rollups: &[UsageRollup]) -> Result<(), DatabaseError> {
+        if rollups.is_empty() {
+            return Ok(());
+        }
+        let column = |count: fn(&UsageRollup) -> u64| -> Vec<i64> {
+            rollups.iter().map(|rollup| count(rollup) as i64).collect()
+        };
+        let subjects: Vec<&str> = rollups
+            .iter()
+            .map(|rollup| rollup.subject.as_str())
+            .collect();
+        let credentials: Vec<&str> = rollups
+            .iter()
+            .map(|rollup| rollup.credential.as_str())
+            .collect();
+        let days = column(|rollup| rollup.day);
+        let requests = column(|rollup| rollup.requests);
+        let errors = column(|rollup| rollup.errors);
+        let throttled = column(|rollup| rollup.throttled);
+        let orders = column(|rollup| rollup.orders);
+        let fills = column(|rollup| rollup.fills);
+        let ws_messages = column(|rollup| rollup.ws_messages);
+        let ws_bytes = column(|rollup| rollup.ws_bytes);
+
+        // Not retried: the counts are added, so replaying a write whose reply
+        // was lost would count them twice.
+        self.run("add_usage", false, || {
+            query(
+                r#"
+            INSERT INTO api_usage_daily (
+                subject, day, credential, requests, errors, throttled, orders, fills,
+                ws_messages, ws_bytes
+            )
+            SELECT * FROM UNNEST(
+                $1::TEXT[], $2::BIGINT[], $3::TEXT[], $4::BIGINT[], $5::BIGINT[],
+                $6::BIGINT[], $7::BIGINT[], $8::BIGINT[], $9::BIGINT[], $10::BIGINT[]
+            )
+            ON CONFLICT (subject, day, credential) DO UPDATE SET
+                requests = api_usage_daily.requests + EXCLUDED.requests,
+                errors = api_usage_daily.errors + EXCLUDED.errors,
+                throttled = api_usage_daily.throttled + EXCLUDED.throttled,
+                orders = api_usage_daily.orders + EXCLUDED.orders,
+                fills = api_usage_daily.fills + EXCLUDED.fills,
+                ws_messages = api_usage_daily.ws_messages + EXCLUDED.ws_messages,
+                ws_bytes = api_usage_daily.ws_bytes + EXCLUDED.ws_bytes
+            "#,
+            )
+            .bind(&subjects)
+            .bind(&days)
+            .bind(&credentials)
+            .bind(&requests)
+            .bind(&errors)
+            .bind(&throttled)
+            .bind(&orders)
+            .bind(&fills)
+            .bind(&ws_messages)
+            .bind(&ws_bytes)
+            .execute(&self.pool)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn load_usage(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<UsageRollup>, DatabaseError> {
+        let sql = format!(
+            "SELECT {} FROM api_usage_daily WHERE subject = $1 AND day >= $2 \
+             ORDER BY day, credential",
+            USAGE_COLUMNS
+        );
+        let rows = self
+            .run("load_usage", true, || {
+                query(&sql)
+                    .bind(subject)
+                    .bind(since_day as i64)
+                    .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+
+    async fn load_day_usage(&self, day: u64) -> Result<Vec<UsageRollup>, DatabaseError> {
+        let sql = format!(
+            "SELECT {} FROM api_usage_daily WHERE day = $1 ORDER BY subject, credential",
+            USAGE_COLUMNS
+        );
+        let rows = self
+            .run("load_day_usage", true, || {
+                query(&sql).bind(day as i64).fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(usage_from_row).collect())
+    }
+
+    async fn save_penalty(&self, penalty: &MessagingPenalty) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("save_penalty", true, || {
+                query(
+                    r#"
+            INSERT INTO messaging_penalties (
+                subject, day, orders, fills, max_ratio, fee, rate_limit_percent, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (subject, day) DO NOTHING
+            "#,
+                )
+                .bind(penalty.subject.as_str())
+                .bind(penalty.day as i64)
+                .bind(penalty.orders as i64)
+                .bind(penalty.fills as i64)
+                .bind(penalty.max_ratio)
+                .bind(penalty.fee as i64)
+                .bind(penalty.rate_limit_percent as i32)
+                .bind(penalty.expires_at as i64)
+                .execute(&self.pool)
+            })
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn load_penalties(
+        &self,
+        subject: &str,
+        since_day: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+        let sql = format!(
+            "SELECT {} FROM messaging_penalties WHERE subject = $1 AND day >= $2 ORDER BY day",
+            PENALTY_COLUMNS
+        );
+        let rows = self
+            .run("load_penalties", true, || {
+                query(&sql)
+                    .bind(subject)
+                    .bind(since_day as i64)
+                    .fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(penalty_from_row).collect())
+    }
+
+    async fn load_active_penalties(
+        &self,
+        now: u64,
+    ) -> Result<Vec<MessagingPenalty>, DatabaseError> {
+        let sql = format!(
+            "SELECT {} FROM messaging_penalties WHERE expires_at > $1 ORDER BY day",
+            PENALTY_COLUMNS
+        );
+        let rows = self
+            .run("load_active_penalties", true, || {
+                query(&sql).bind(now as i64).fetch_all(&self.pool)
+            })
+            .await?;
+
+        Ok(rows.iter().map(penalty_from_row).collect())
+    }
+}
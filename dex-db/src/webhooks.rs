@@ -0,0 +1,195 @@
+//! Postgres implementation of `WebhookRepo`.
+
+use crate::{
+    parse_column,
+    repository::{WebhookDelivery, WebhookRecord, WebhookRepo},
+    DatabaseError, DatabaseManager,
+};
+use async_trait::async_trait;
+use sqlx_core::{query::query, row::Row};
+use sqlx_postgres::PgRow;
+
+fn webhook_from_row(row: &PgRow) -> WebhookRecord {
+    WebhookRecord {
+        id: row.get("id"),
+        trader_id: row.get("trader_id"),
+        url: row.get("url"),
+        secret: row.get("secret"),
+        events: row.get("events"),
+        created_at: row.get::<i64, _>("created_at") as u64,
+        revoked: row.get("revoked"),
+    }
+}
+
+fn delivery_from_row(row: &PgRow) -> Result<WebhookDelivery, DatabaseError> {
+    Ok(WebhookDelivery {
+        id: row.get::<i64, _>("id") as u64,
+        webhook_id: row.get("webhook_id"),
+        payload: row.get("payload"),
+        status: parse_column(row, "status")?,
+        attempts: row.get::<i32, _>("attempts") as u32,
+        next_attempt_at: row.get::<i64, _>("next_attempt_at") as u64,
+        last_error: row.get("last_error"),
+    })
+}
+
+#[async_trait]
+impl WebhookRepo for DatabaseManager {
+    async fn save_webhook(&self, webhook: &WebhookRecord) -> Result<(), DatabaseError> {
+        // Plain insert: webhook IDs are unique, so a retry could hit a duplicate key.
+        self.run("save_webhook", false, || {
+            query(
+                r#"
+            INSERT INTO webhooks (id, trader_id, url, secret, events, created_at, revoked)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            "#,
+            )
+            .bind(webhook.id.as_str())
+            .bind(webhook.trader_id.as_str())
+            .bind(webhook.url.as_str())
+            .bind(webhook.secret.as_str())
+            .bind(&webhook.events)
+            .bind(webhook.created_at as i64)
+            .bind(webhook.revoked)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn list_webhooks(&self, trader_id: &str) -> Result<Vec<WebhookRecord>, DatabaseError> {
+        let rows = self
+            .run("list_webhooks", true, || {
+                query(
+                    "SELECT * FROM webhooks WHERE trader_id = $1 AND NOT revoked \
+                     ORDER BY created_at, id",
+                )
+                .bind(trader_id)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+        Ok(rows.iter().map(webhook_from_row).collect())
+    }
+
+    async fn load_active_webhooks(&self) -> Result<Vec<WebhookRecord>, DatabaseError> {
+        let rows = self
+            .run("load_active_webhooks", true, || {
+                query("SELECT * FROM webhooks WHERE NOT revoked").fetch_all(&self.pool)
+            })
+            .await?;
+        Ok(rows.iter().map(webhook_from_row).collect())
+    }
+
+    async fn revoke_webhook(&self, id: &str, trader_id: &str) -> Result<bool, DatabaseError> {
+        let result = self
+            .run("revoke_webhook", true, || {
+                query(
+                    r#"
+            WITH revoked AS (
+                UPDATE webhooks SET revoked = TRUE
+                WHERE id = $1 AND trader_id = $2 AND NOT revoked
+                RETURNING id
+            ), dropped AS (
+                UPDATE webhook_deliveries SET status = 'failed', last_error = 'webhook revoked'
+                WHERE webhook_id IN (SELECT id FROM revoked) AND status = 'pending'
+            )
+            SELECT COUNT(*) AS revoked FROM revoked
+            "#,
+                )
+                .bind(id)
+                .bind(trader_id)
+                .fetch_one(&self.pool)
+            })
+            .await?;
+        Ok(result.get::<i64, _>("revoked") > 0)
+    }
+
+    async fn enqueue_deliveries(
+        &self,
+        deliveries: &[WebhookDelivery],
+    ) -> Result<(), DatabaseError> {
+        if deliveries.is_empty() {
+            return Ok(());
+        }
+        let webhook_ids: Vec<&str> = deliveries
+            .iter()
+            .map(|delivery| delivery.webhook_id.as_str())
+            .collect();
+        let payloads: Vec<&str> = deliveries
+            .iter()
+            .map(|delivery| delivery.payload.as_str())
+            .collect();
+        let statuses: Vec<&str> = deliveries
+            .iter()
+            .map(|delivery| delivery.status.as_str())
+            .collect();
+        let next_attempts: Vec<i64> = deliveries
+            .iter()
+            .map(|delivery| delivery.next_attempt_at as i64)
+            .collect();
+
+        // Not retried: a replayed insert would notify the trader twice.
+        self.run("enqueue_webhook_deliveries", false, || {
+            query(
+                r#"
+            INSERT INTO webhook_deliveries (webhook_id, payload, status, next_attempt_at)
+            SELECT webhook_id, payload, status, next_attempt_at
+            FROM UNNEST($1::TEXT[], $2::TEXT[], $3::TEXT[], $4::BIGINT[])
+                WITH ORDINALITY AS d (webhook_id, payload, status, next_attempt_at, position)
+            ORDER BY position
+            "#,
+            )
+            .bind(&webhook_ids)
+            .bind(&payloads)
+            .bind(&statuses)
+            .bind(&next_attempts)
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+
+    async fn due_deliveries(
+        &self,
+        now: u64,
+        limit: u32,
+    ) -> Result<Vec<WebhookDelivery>, DatabaseError> {
+        let rows = self
+            .run("due_webhook_deliveries", true, || {
+                query(
+                    r#"
+            SELECT id, webhook_id, payload, status, attempts, next_attempt_at, last_error
+            FROM webhook_deliveries
+            WHERE status = 'pending' AND next_attempt_at <= $1
+            ORDER BY id
+            LIMIT $2
+            "#,
+                )
+                .bind(now as i64)
+                .bind(limit as i64)
+                .fetch_all(&self.pool)
+            })
+            .await?;
+        rows.iter().map(delivery_from_row).collect()
+    }
+
+    async fn update_delivery(&self, delivery: &WebhookDelivery) -> Result<(), DatabaseError> {
+        self.run("update_webhook_delivery", true, || {
+            query(
+                r#"
+            UPDATE webhook_deliveries
+            SET status = $2, attempts = $3, next_attempt_at = $4, last_error = $5
+            WHERE id = $1
+            "#,
+            )
+            .bind(delivery.id as i64)
+            .bind(delivery.status.as_str())
+            .bind(delivery.attempts as i32)
+            .bind(delivery.next_attempt_at as i64)
+            .bind(delivery.last_error.as_deref())
+            .execute(&self.pool)
+        })
+        .await?;
+        Ok(())
+    }
+}
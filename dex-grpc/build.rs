@@ -0,0 +1,9 @@
+//! Generate the `dex.v1` messages, client and server from the protobuf
+//! contract, with a vendored `protoc` so the build needs none installed.
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path()?);
+    println!("cargo:rerun-if-changed=../proto");
+    tonic_build::configure().compile_protos(&["../proto/dex/v1/exchange.proto"], &["../proto"])?;
+    Ok(())
+}
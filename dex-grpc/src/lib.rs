@@ -0,0 +1,9 @@
+//! Generated bindings for the `dex.v1.Exchange` gRPC service.
+//!
+//! The contract lives in `proto/dex/v1/exchange.proto`. dex-api implements
+//! [`v1::exchange_server::Exchange`] on its shared state and serves it on
+//! `GRPC_PORT`; integrators can use [`v1::exchange_client::ExchangeClient`].
+
+pub mod v1 {
+    tonic::include_proto!("dex.v1");
+}
@@ -1,9 +1,20 @@
 export type OrderSide = "buy" | "sell";
 export type OrderType = "limit" | "market";
 
+export interface ApiTicker {
+  pair: string;
+  best_bid: number | null;
+  best_ask: number | null;
+  mid_price: number | null;
+  last_price: number | null;
+  last_trade_time: number | null;
+  timestamp: number;
+}
+
 export interface ApiPriceResponse {
   best_bid: number | null;
   best_ask: number | null;
+  pairs: ApiTicker[];
 }
 
 export interface ApiCreateOrderRequest {
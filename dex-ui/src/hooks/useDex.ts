@@ -39,6 +39,13 @@ interface Notification {
 
 type DepthConnection = "idle" | "connecting" | "live" | "error";
 
+// Frames pushed by the multiplexed `/ws` stream.
+type StreamMessage =
+  | { type: "subscribed" | "unsubscribed"; channel: string }
+  | { type: "update"; channel: string; data: unknown }
+  | { type: "lagged"; channel: string; skipped: number }
+  | { type: "error"; code: string; message: string };
+
 interface DepthState {
   snapshot: ApiDepthSnapshot | null;
   connection: DepthConnection;
@@ -223,7 +230,7 @@ export function useDex(): UseDexReturn {
       if (cancelled) {
         return;
       }
-      const url = buildDepthWsUrl(apiBaseUrl, depthLevels);
+      const url = buildStreamWsUrl(apiBaseUrl);
       if (!url) {
         setDepth((prev) => ({
           ...prev,
@@ -239,6 +246,7 @@ export function useDex(): UseDexReturn {
         if (cancelled) {
           return;
         }
+        socket?.send(JSON.stringify({ op: "subscribe", channel: "depth", levels: depthLevels }));
         setDepth((prev) => ({ ...prev, connection: "live", lastError: undefined }));
       };
 
@@ -247,10 +255,17 @@ export function useDex(): UseDexReturn {
           return;
         }
         try {
-          const payload = JSON.parse(event.data) as ApiDepthSnapshot;
+          const message = JSON.parse(event.data) as StreamMessage;
+          if (message.type === "error") {
+            setDepth((prev) => ({ ...prev, lastError: message.message }));
+            return;
+          }
+          if (message.type !== "update" || message.channel !== "depth") {
+            return;
+          }
           setDepth((prev) => ({
             ...prev,
-            snapshot: payload,
+            snapshot: message.data as ApiDepthSnapshot,
             connection: "live",
             lastError: undefined,
             isLoading: false,
@@ -524,12 +539,12 @@ function shorten(value: string) {
   return `${value.slice(0, 6)}…${value.slice(-4)}`;
 }
 
-function buildDepthWsUrl(baseUrl: string, levels: number): string | null {
+function buildStreamWsUrl(baseUrl: string): string | null {
   try {
     const url = new URL(baseUrl);
     url.protocol = url.protocol === "https:" ? "wss:" : "ws:";
-    url.pathname = "/ws/depth";
-    url.search = new URLSearchParams({ levels: String(levels) }).toString();
+    url.pathname = "/ws";
+    url.search = "";
     return url.toString();
   } catch {
     return null;
@@ -3,7 +3,13 @@
 //! This module provides the WASM bindings to allow the DEX-OS core engine
 //! to be used in web browsers and other WASM environments.
 
-use dex_core::{amm::ConstantProductAMM, orderbook::OrderBook, types::Order};
+use dex_core::{
+    amm::ConstantProductAMM,
+    amm_router::{AmmRouter, PoolSnapshot},
+    orderbook::OrderBook,
+    types::{Order, TokenId, Trade},
+};
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
 
 /// WASM wrapper for the OrderBook
@@ -95,6 +101,91 @@ impl WasmOrderBook {
     }
 }
 
+/// Result of one order in a replay: the trades it produced, or why it was rejected.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayOutcome {
+    Trades(Vec<Trade>),
+    Rejected(String),
+}
+
+/// Replay a JSON array of orders through a fresh orderbook and return one
+/// outcome per order as JSON.
+///
+/// This is plain Rust so the same bytes can be produced natively and inside
+/// the WASM build; frontends use it to preview what the server will execute.
+pub fn replay_orders(orders_json: &str) -> Result<String, String> {
+    let orders: Vec<Order> = serde_json::from_str(orders_json)
+        .map_err(|e| format!("Failed to deserialize orders: {}", e))?;
+    let mut book = OrderBook::new();
+    let outcomes: Vec<ReplayOutcome> = orders
+        .into_iter()
+        .map(|order| match book.add_order(order) {
+            Ok(trades) => ReplayOutcome::Trades(trades),
+            Err(e) => ReplayOutcome::Rejected(e.to_string()),
+        })
+        .collect();
+    serde_json::to_string(&outcomes).map_err(|e| format!("Failed to serialize trades: {}", e))
+}
+
+/// Replay a JSON array of orders and return the trades they produce as JSON
+#[wasm_bindgen]
+pub fn replay_orders_json(orders_json: &str) -> Result<String, JsValue> {
+    replay_orders(orders_json).map_err(|e| JsValue::from_str(&e))
+}
+
+/// `replay_orders` for hosts without the JavaScript glue, such as wasmtime.
+///
+/// The host writes the orders JSON into a buffer from `replay_alloc`, calls
+/// `replay_orders_raw` and reads the outcomes JSON from the returned buffer,
+/// packed as `pointer << 32 | length`; zero means the replay failed. Every
+/// buffer is given back with `replay_free`.
+#[cfg(target_arch = "wasm32")]
+mod raw {
+    use std::{ptr, slice};
+
+    /// Hand out the outcomes as a buffer the host frees.
+    fn leak(bytes: Vec<u8>) -> u64 {
+        let len = bytes.len();
+        let ptr = Box::into_raw(bytes.into_boxed_slice()) as *mut u8;
+        ((ptr as u64) << 32) | len as u64
+    }
+
+    #[no_mangle]
+    pub extern "C" fn replay_alloc(len: usize) -> *mut u8 {
+        Box::into_raw(vec![0u8; len].into_boxed_slice()) as *mut u8
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `len` must describe a buffer from `replay_alloc` or
+    /// `replay_orders_raw` that was not freed yet.
+    #[no_mangle]
+    pub unsafe extern "C" fn replay_free(ptr: *mut u8, len: usize) {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `len` must describe a live buffer from `replay_alloc`.
+    #[no_mangle]
+    pub unsafe extern "C" fn replay_orders_raw(ptr: *const u8, len: usize) -> u64 {
+        let Ok(orders_json) = std::str::from_utf8(slice::from_raw_parts(ptr, len)) else {
+            return 0;
+        };
+        super::replay_orders(orders_json).map_or(0, |outcomes| leak(outcomes.into_bytes()))
+    }
+}
+
+/// Parse a token symbol passed in from JavaScript.
+fn parse_token(raw: &str) -> Result<TokenId, JsValue> {
+    token(raw).map_err(|e| JsValue::from_str(&e))
+}
+
+fn token(raw: &str) -> Result<TokenId, String> {
+    TokenId::parse(raw).map_err(|e| format!("Invalid token {}: {}", raw, e))
+}
+
 /// WASM wrapper for the ConstantProductAMM
 #[wasm_bindgen]
 pub struct WasmAMM {
@@ -121,7 +212,12 @@ impl WasmAMM {
         amount_b: u64,
     ) -> Result<u64, JsValue> {
         self.inner
-            .add_liquidity(token_a, amount_a, token_b, amount_b)
+            .add_liquidity(
+                parse_token(&token_a)?,
+                amount_a,
+                parse_token(&token_b)?,
+                amount_b,
+            )
             .map_err(|e| JsValue::from_str(&format!("Failed to add liquidity: {}", e)))
     }
 
@@ -133,10 +229,11 @@ impl WasmAMM {
         token_b: String,
         liquidity_tokens: u64,
     ) -> Result<JsValue, JsValue> {
-        match self
-            .inner
-            .remove_liquidity(token_a, token_b, liquidity_tokens)
-        {
+        match self.inner.remove_liquidity(
+            parse_token(&token_a)?,
+            parse_token(&token_b)?,
+            liquidity_tokens,
+        ) {
             Ok((amount_a, amount_b)) => {
                 let result = serde_json::json!({
                     "amount_a": amount_a,
@@ -163,13 +260,18 @@ impl WasmAMM {
         amount_in: u64,
     ) -> Result<u64, JsValue> {
         self.inner
-            .swap(from_token, to_token, amount_in)
+            .swap(
+                parse_token(&from_token)?,
+                parse_token(&to_token)?,
+                amount_in,
+            )
             .map_err(|e| JsValue::from_str(&format!("Failed to swap: {}", e)))
     }
 
     /// Get the price of one token in terms of another
     #[wasm_bindgen]
     pub fn get_price(&self, from_token: String, to_token: String) -> Result<f64, JsValue> {
+        let (from_token, to_token) = (parse_token(&from_token)?, parse_token(&to_token)?);
         self.inner
             .get_price(&from_token, &to_token)
             .map_err(|e| JsValue::from_str(&format!("Failed to get price: {}", e)))
@@ -185,6 +287,7 @@ impl WasmAMM {
         max_price: f64,
         tolerance: f64,
     ) -> Result<f64, JsValue> {
+        let (from_token, to_token) = (parse_token(&from_token)?, parse_token(&to_token)?);
         self.inner
             .find_price_in_range(&from_token, &to_token, min_price, max_price, tolerance)
             .map_err(|e| JsValue::from_str(&format!("Failed to find price in range: {}", e)))
@@ -199,6 +302,7 @@ impl WasmAMM {
         proposed_price: f64,
         max_slippage: f64,
     ) -> Result<bool, JsValue> {
+        let (from_token, to_token) = (parse_token(&from_token)?, parse_token(&to_token)?);
         self.inner
             .is_price_within_slippage(&from_token, &to_token, proposed_price, max_slippage)
             .map_err(|e| {
@@ -207,4 +311,313 @@ impl WasmAMM {
     }
 }
 
+/// Route quoting over a snapshot of the server's pools (`GET /amm/pools`),
+/// so route previews, split routes included, need no round trip. Quotes are
+/// exact while `is_fresh` holds for the `sequence` the server last reported,
+/// e.g. on a `pool:PAIR` stream update.
+#[wasm_bindgen]
+pub struct WasmAmmRouter {
+    inner: AmmRouter,
+}
+
+impl WasmAmmRouter {
+    fn from_json(snapshot_json: &str) -> Result<Self, String> {
+        let snapshot: PoolSnapshot = serde_json::from_str(snapshot_json)
+            .map_err(|e| format!("Failed to deserialize pool snapshot: {}", e))?;
+        Ok(Self {
+            inner: AmmRouter::new(snapshot),
+        })
+    }
+
+    fn quote_json(
+        &self,
+        from_token: &str,
+        to_token: &str,
+        amount_in: u64,
+        max_hops: usize,
+    ) -> Result<String, String> {
+        let quote = self
+            .inner
+            .quote(&token(from_token)?, &token(to_token)?, amount_in, max_hops)
+            .map_err(|e| format!("Failed to quote route: {}", e))?;
+        serde_json::to_string(&quote).map_err(|e| format!("Failed to serialize quote: {}", e))
+    }
+
+    fn quote_split_json(
+        &self,
+        from_token: &str,
+        to_token: &str,
+        amount_in: u64,
+        max_hops: usize,
+        parts: u32,
+    ) -> Result<String, String> {
+        let quote = self
+            .inner
+            .quote_split(
+                &token(from_token)?,
+                &token(to_token)?,
+                amount_in,
+                max_hops,
+                parts,
+            )
+            .map_err(|e| format!("Failed to quote route: {}", e))?;
+        serde_json::to_string(&quote).map_err(|e| format!("Failed to serialize quote: {}", e))
+    }
+}
+
+#[wasm_bindgen]
+impl WasmAmmRouter {
+    /// Create a router from a pool snapshot in JSON
+    #[wasm_bindgen(constructor)]
+    pub fn new(snapshot_json: &str) -> Result<WasmAmmRouter, JsValue> {
+        Self::from_json(snapshot_json).map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Pool sequence of the snapshot
+    #[wasm_bindgen]
+    pub fn sequence(&self) -> u64 {
+        self.inner.sequence()
+    }
+
+    /// Whether the snapshot matches a server at `server_sequence`
+    #[wasm_bindgen]
+    pub fn is_fresh(&self, server_sequence: u64) -> bool {
+        self.inner.is_fresh(server_sequence)
+    }
+
+    /// Quote the best single path of at most `max_hops` pools, as JSON
+    #[wasm_bindgen]
+    pub fn quote(
+        &self,
+        from_token: String,
+        to_token: String,
+        amount_in: u64,
+        max_hops: usize,
+    ) -> Result<String, JsValue> {
+        self.quote_json(&from_token, &to_token, amount_in, max_hops)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+
+    /// Quote `amount_in` split into `parts` chunks across paths, as JSON
+    #[wasm_bindgen]
+    pub fn quote_split(
+        &self,
+        from_token: String,
+        to_token: String,
+        amount_in: u64,
+        max_hops: usize,
+        parts: u32,
+    ) -> Result<String, JsValue> {
+        self.quote_split_json(&from_token, &to_token, amount_in, max_hops, parts)
+            .map_err(|e| JsValue::from_str(&e))
+    }
+}
+
 // The default allocator is used for WASM builds to avoid unmaintained dependencies.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dex_core::types::{OrderSide, OrderType};
+    use proptest::prelude::*;
+    use std::{path::Path, process::Command, sync::OnceLock};
+    use wasmtime::{Engine, Linker, Module, Store};
+
+    const TRADERS: [&str; 3] = ["alice", "bob", "carol"];
+
+    fn order_strategy() -> impl Strategy<Value = (usize, bool, bool, u64, u64)> {
+        (
+            0..TRADERS.len(),
+            any::<bool>(),
+            prop::bool::weighted(0.8),
+            95u64..=105,
+            1u64..=50,
+        )
+    }
+
+    fn build_orders(specs: Vec<(usize, bool, bool, u64, u64)>) -> Vec<Order> {
+        specs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (trader, buy, limit, price, quantity))| Order {
+                id: i as u64 + 1,
+                trader_id: TRADERS[trader].parse().unwrap(),
+                pair: "ETH-USDC".parse().unwrap(),
+                side: if buy { OrderSide::Buy } else { OrderSide::Sell },
+                order_type: if limit {
+                    OrderType::Limit
+                } else {
+                    OrderType::Market
+                },
+                price: limit.then_some(price),
+                quantity,
+                timestamp: 1_700_000_000 + i as u64,
+            })
+            .collect()
+    }
+
+    /// Reference output: the orders applied directly to the native engine.
+    fn native_outcomes(orders: &[Order]) -> String {
+        let mut book = OrderBook::new();
+        let outcomes: Vec<ReplayOutcome> = orders
+            .iter()
+            .cloned()
+            .map(|order| match book.add_order(order) {
+                Ok(trades) => ReplayOutcome::Trades(trades),
+                Err(e) => ReplayOutcome::Rejected(e.to_string()),
+            })
+            .collect();
+        serde_json::to_string(&outcomes).unwrap()
+    }
+
+    /// This crate built for wasm32, compiled for wasmtime once per test run.
+    fn wasm_module() -> &'static (Engine, Module) {
+        static MODULE: OnceLock<(Engine, Module)> = OnceLock::new();
+        MODULE.get_or_init(|| {
+            // A target directory of its own, so the build does not wait on
+            // the lock of the one running the tests.
+            let target_dir =
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/wasm-differential");
+            let build = Command::new(env!("CARGO"))
+                .args([
+                    "build",
+                    "-p",
+                    "dex-wasm",
+                    "--release",
+                    "--target",
+                    "wasm32-unknown-unknown",
+                ])
+                .env("CARGO_TARGET_DIR", &target_dir)
+                .output()
+                .expect("run cargo");
+            assert!(
+                build.status.success(),
+                "building dex-wasm for wasm32 failed; is the target installed \
+                 (rustup target add wasm32-unknown-unknown)?\n{}",
+                String::from_utf8_lossy(&build.stderr)
+            );
+            let engine = Engine::default();
+            let module = Module::from_file(
+                &engine,
+                target_dir.join("wasm32-unknown-unknown/release/dex_wasm.wasm"),
+            )
+            .expect("compile the wasm module");
+            (engine, module)
+        })
+    }
+
+    /// `replay_orders` run inside the compiled module, through its raw ABI.
+    fn wasm_replay(input: &str) -> String {
+        let (engine, module) = wasm_module();
+        let mut store = Store::new(engine, ());
+        // The JavaScript glue's imports are never called on this path.
+        let mut linker = Linker::new(engine);
+        linker.define_unknown_imports_as_traps(module).unwrap();
+        let instance = linker.instantiate(&mut store, module).unwrap();
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        let alloc = instance
+            .get_typed_func::<u32, u32>(&mut store, "replay_alloc")
+            .unwrap();
+        let replay = instance
+            .get_typed_func::<(u32, u32), u64>(&mut store, "replay_orders_raw")
+            .unwrap();
+        let free = instance
+            .get_typed_func::<(u32, u32), ()>(&mut store, "replay_free")
+            .unwrap();
+
+        let len = input.len() as u32;
+        let ptr = alloc.call(&mut store, len).unwrap();
+        memory
+            .write(&mut store, ptr as usize, input.as_bytes())
+            .unwrap();
+        let packed = replay.call(&mut store, (ptr, len)).unwrap();
+        free.call(&mut store, (ptr, len)).unwrap();
+        assert_ne!(packed, 0, "replay failed inside the module");
+
+        let (out_ptr, out_len) = ((packed >> 32) as u32, packed as u32);
+        let mut output = vec![0; out_len as usize];
+        memory.read(&store, out_ptr as usize, &mut output).unwrap();
+        free.call(&mut store, (out_ptr, out_len)).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    proptest! {
+        #[test]
+        fn wasm_build_replays_the_same_bytes(specs in prop::collection::vec(order_strategy(), 1..40)) {
+            let orders = build_orders(specs);
+            let input = serde_json::to_string(&orders).unwrap();
+            prop_assert_eq!(wasm_replay(&input), native_outcomes(&orders));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn replay_matches_native_engine(specs in prop::collection::vec(order_strategy(), 1..40)) {
+            let orders = build_orders(specs);
+            let input = serde_json::to_string(&orders).unwrap();
+
+            let replayed = replay_orders(&input).unwrap();
+            prop_assert_eq!(&replayed, &native_outcomes(&orders));
+            // Same input, same bytes: previews must not depend on wall-clock time.
+            prop_assert_eq!(replayed, replay_orders(&input).unwrap());
+        }
+    }
+
+    fn snapshot_json(reserve_eth: u64, reserve_usdc: u64) -> String {
+        format!(
+            r#"{{"sequence":3,"pools":[
+                {{"pair":{{"base":"ETH","quote":"USDC"}},"reserve_base":{},"reserve_quote":{},"fee_bps":30}},
+                {{"pair":{{"base":"ETH","quote":"DAI"}},"reserve_base":{},"reserve_quote":{},"fee_bps":30}},
+                {{"pair":{{"base":"DAI","quote":"USDC"}},"reserve_base":50000000,"reserve_quote":50000000,"fee_bps":5}}
+            ]}}"#,
+            reserve_eth, reserve_usdc, reserve_eth, reserve_usdc
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn route_quotes_match_native_swaps(
+            reserve_eth in 1_000u64..1_000_000,
+            reserve_usdc in 1_000_000u64..1_000_000_000,
+            amount_in in 1u64..10_000,
+        ) {
+            let router = WasmAmmRouter::from_json(&snapshot_json(reserve_eth, reserve_usdc)).unwrap();
+            let quote: serde_json::Value =
+                serde_json::from_str(&router.quote_json("ETH", "USDC", amount_in, 1).unwrap()).unwrap();
+
+            let mut amm = ConstantProductAMM::new(30);
+            let (eth, usdc) = ("ETH".parse().unwrap(), "USDC".parse().unwrap());
+            amm.add_liquidity(eth, reserve_eth, usdc, reserve_usdc).unwrap();
+            let paid = amm
+                .swap("ETH".parse().unwrap(), "USDC".parse().unwrap(), amount_in)
+                .unwrap_or(0);
+            prop_assert_eq!(quote["amount_out"].as_u64().unwrap_or(0), paid);
+        }
+    }
+
+    #[test]
+    fn split_quotes_use_the_snapshot_sequence() {
+        let router = WasmAmmRouter::from_json(&snapshot_json(1_000, 2_000_000)).unwrap();
+        assert_eq!(router.sequence(), 3);
+        assert!(router.is_fresh(3));
+        assert!(!router.is_fresh(4));
+
+        let split: serde_json::Value =
+            serde_json::from_str(&router.quote_split_json("ETH", "USDC", 400, 2, 8).unwrap())
+                .unwrap();
+        assert_eq!(split["sequence"], 3);
+        assert_eq!(split["routes"].as_array().unwrap().len(), 2);
+        assert_eq!(split["routes"][1]["tokens"][1], "DAI");
+
+        assert!(WasmAmmRouter::from_json("not json").is_err());
+        assert!(router.quote_json("ETH", "WBTC", 10, 2).is_err());
+    }
+
+    #[test]
+    fn replay_reports_invalid_input() {
+        assert!(replay_orders("not json").is_err());
+        let bad_pair = r#"[{"id":1,"trader_id":"alice","pair":{"base":"ETH","quote":"ETH"},
+            "side":"Buy","order_type":"Limit","price":100,"quantity":1,"timestamp":1}]"#;
+        assert!(replay_orders(bad_pair).is_err());
+    }
+}